@@ -56,7 +56,7 @@ export const FIELD_CONFIGS = {
     formula: { label: 'Формула', type: 'string' },
     cas_number: { label: 'CAS номер', type: 'string' },
     manufacturer: { label: 'Производитель', type: 'string' },
-    physical_state: { label: 'Агрегатное состояние', type: 'enum', options: ['solid', 'liquid', 'gas'] },
+    physical_state: { label: 'Агрегатное состояние', type: 'enum', options: ['solid', 'liquid', 'gas', 'solution', 'other'] },
     status: { label: 'Статус', type: 'enum', options: ['active', 'inactive', 'discontinued'] },
   },
   experiments: {
@@ -0,0 +1,917 @@
+// src/access_control.rs
+//! Декларативная таблица прав доступа для /api/v1.
+//!
+//! Каждый маршрут, зарегистрированный в `api_v1_routes()` (main.rs), должен иметь
+//! запись в `ROUTE_PERMISSIONS`: ресурс, действие и минимальную роль. Middleware
+//! `RouteAuthorization` применяет таблицу к каждому запросу и отклоняет всё, чего
+//! в ней нет (deny-by-default). Тест в конце файла сверяет таблицу со списком маршрутов.
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::{web, HttpMessage};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::auth::{get_current_user, Claims, KioskPrincipal, UserRole};
+use crate::error::{ApiError, ApiResult};
+use crate::AppState;
+
+pub const API_PREFIX: &str = "/api/v1";
+
+// ==================== TABLE TYPES ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Reagent,
+    Batch,
+    Equipment,
+    Experiment,
+    Room,
+    Report,
+    Dashboard,
+    /// Собственный профиль текущего пользователя
+    Profile,
+    /// Управление учётными записями других пользователей
+    User,
+    /// Кэш, диагностика, настройки, ротация JWT
+    System,
+}
+
+impl Resource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resource::Reagent => "reagent",
+            Resource::Batch => "batch",
+            Resource::Equipment => "equipment",
+            Resource::Experiment => "experiment",
+            Resource::Room => "room",
+            Resource::Report => "report",
+            Resource::Dashboard => "dashboard",
+            Resource::Profile => "profile",
+            Resource::User => "user",
+            Resource::System => "system",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    View,
+    Create,
+    Edit,
+    Delete,
+    /// QC-решения по партии (продление срока годности)
+    Approve,
+    /// Расход партии (списание, выдача единиц)
+    Use,
+    Import,
+    Export,
+    Manage,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::View => "view",
+            Action::Create => "create",
+            Action::Edit => "edit",
+            Action::Delete => "delete",
+            Action::Approve => "approve",
+            Action::Use => "use",
+            Action::Import => "import",
+            Action::Export => "export",
+            Action::Manage => "manage",
+        }
+    }
+}
+
+/// Области действия киоск-токена помещения
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KioskScope {
+    /// Просмотр оборудования своего помещения
+    EquipmentRead,
+    /// Выдача и возврат оборудования
+    UsageWrite,
+    /// Отметка прихода/ухода в помещении
+    AttendanceWrite,
+}
+
+impl KioskScope {
+    pub const ALL: [KioskScope; 3] = [KioskScope::EquipmentRead, KioskScope::UsageWrite, KioskScope::AttendanceWrite];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KioskScope::EquipmentRead => "equipment:read",
+            KioskScope::UsageWrite => "usage:write",
+            KioskScope::AttendanceWrite => "attendance:write",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scope| scope.as_str() == value.trim())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    /// Доступно без токена (маршрут регистрируется вне auth middleware)
+    Public,
+    /// Только для киоск-токена с указанной областью; пользователям недоступно
+    Kiosk(KioskScope),
+    Require {
+        resource: Resource,
+        action: Action,
+        min_role: UserRole,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RoutePermission {
+    pub method: Method,
+    /// Шаблон пути относительно /api/v1, в точности как при регистрации маршрута
+    pub path: &'static str,
+    pub access: Access,
+}
+
+const fn public(method: Method, path: &'static str) -> RoutePermission {
+    RoutePermission { method, path, access: Access::Public }
+}
+
+const fn kiosk(method: Method, path: &'static str, scope: KioskScope) -> RoutePermission {
+    RoutePermission { method, path, access: Access::Kiosk(scope) }
+}
+
+const fn rule(
+    method: Method,
+    path: &'static str,
+    resource: Resource,
+    action: Action,
+    min_role: UserRole,
+) -> RoutePermission {
+    RoutePermission { method, path, access: Access::Require { resource, action, min_role } }
+}
+
+use Action::*;
+use Resource::*;
+use UserRole::{Admin, Researcher, Viewer};
+
+const GET: Method = Method::GET;
+const POST: Method = Method::POST;
+const PUT: Method = Method::PUT;
+const DELETE: Method = Method::DELETE;
+
+// ==================== ROUTE PERMISSIONS ====================
+// Единственное место, где описаны права на маршруты /api/v1.
+// Проверки владельца/участника (пресеты отчётов, отметка в эксперименте и т.п.)
+// остаются в хендлерах — таблица задаёт только верхнюю границу доступа.
+
+pub static ROUTE_PERMISSIONS: &[RoutePermission] = &[
+    // Public file access
+    public(GET, "/public/equipment/{id}/files/{file_id}"),
+    // Подписанные ссылки на экспорт: права автора ссылки проверяются в signed_downloads
+    public(GET, "/public/downloads/{token}"),
+
+    // API versions
+    rule(GET, "/version", Profile, View, Viewer),
+
+    // Unit conversion
+    rule(POST, "/units/convert", Batch, View, Viewer),
+
+    // Inline form validation: только чтение, ничего не записывает
+    rule(POST, "/validate/{entity}", Dashboard, View, Viewer),
+    // Подсказки полей форм: право просмотра сущности проверяется в хендлере по белому списку
+    rule(GET, "/suggest/{field}", Dashboard, View, Viewer),
+
+    // Auth management
+    rule(GET, "/auth/profile", Profile, View, Viewer),
+    rule(POST, "/auth/change-password", Profile, Edit, Viewer),
+    rule(POST, "/auth/logout", Profile, View, Viewer),
+    rule(GET, "/auth/roles", User, View, Admin),
+    rule(GET, "/auth/users", User, View, Admin),
+    rule(POST, "/auth/users", User, Create, Admin),
+    rule(GET, "/auth/users/inactive", User, View, Admin),
+    rule(GET, "/auth/users/{id}", User, View, Admin),
+    rule(PUT, "/auth/users/{id}", User, Edit, Admin),
+    rule(DELETE, "/auth/users/{id}", User, Delete, Admin),
+    rule(PUT, "/auth/users/{id}/reset-password", User, Edit, Admin),
+    rule(GET, "/auth/users/{id}/permissions", User, View, Admin),
+    rule(PUT, "/auth/users/{id}/permissions", User, Manage, Admin),
+    rule(GET, "/auth/users/{id}/activity", User, View, Admin),
+    rule(POST, "/auth/users/{id}/anonymize", User, Delete, Admin),
+    rule(GET, "/auth/jwt/status", System, View, Admin),
+    rule(POST, "/auth/jwt/rotate", System, Manage, Admin),
+
+    // Dashboard, search, scanning
+    rule(GET, "/dashboard/stats", Dashboard, View, Viewer),
+    rule(GET, "/dashboard/recent-activity", Dashboard, View, Viewer),
+    rule(GET, "/dashboard/trends", Dashboard, View, Viewer),
+    rule(GET, "/dashboard/equipment-summary", Dashboard, View, Viewer),
+    rule(GET, "/search", Dashboard, View, Viewer),
+    rule(GET, "/scan/{code}", Batch, View, Viewer),
+
+    // Admin
+    rule(POST, "/admin/cache/rebuild", System, Manage, Admin),
+    rule(GET, "/admin/slow-queries", System, View, Admin),
+    rule(GET, "/admin/kpis", System, View, Admin),
+    rule(GET, "/admin/anomalies", System, View, Admin),
+    rule(GET, "/admin/retention/dry-run", System, View, Admin),
+    rule(POST, "/admin/reconcile-reservations", System, Manage, Admin),
+    rule(GET, "/admin/storage/dedup", System, View, Admin),
+    rule(GET, "/admin/normalizations/manufacturer", System, View, Admin),
+    rule(PUT, "/admin/normalizations/manufacturer", System, Manage, Admin),
+    rule(GET, "/admin/normalizations/physical-state", System, View, Admin),
+    rule(PUT, "/admin/normalizations/physical-state", System, Manage, Admin),
+    rule(GET, "/admin/normalizations/user-identity", User, View, Admin),
+    rule(GET, "/admin/normalizations/equipment-serial", System, View, Admin),
+    rule(POST, "/admin/reference-cache/flush", System, Manage, Admin),
+    rule(GET, "/admin/settings", System, View, Admin),
+    rule(PUT, "/admin/settings", System, Manage, Admin),
+    // Режим обслуживания "только чтение" (см. maintenance_mode::ReadOnlyGuard)
+    rule(GET, "/admin/maintenance-mode", System, View, Admin),
+    rule(POST, "/admin/maintenance-mode", System, Manage, Admin),
+    rule(GET, "/admin/schema", System, View, Admin),
+    rule(POST, "/admin/migrate", System, Manage, Admin),
+    rule(POST, "/admin/seed-demo", System, Manage, Admin),
+    rule(POST, "/admin/seed-demo/clear", System, Manage, Admin),
+    rule(GET, "/admin/pending-deletions", System, View, Admin),
+    // Outbox событий: состояние очереди и разбор недоставленных (dead letters)
+    rule(GET, "/admin/outbox", System, View, Admin),
+    rule(GET, "/admin/outbox/dead-letters", System, View, Admin),
+    rule(POST, "/admin/outbox/{id}/requeue", System, Manage, Admin),
+    rule(POST, "/admin/outbox/{id}/discard", System, Manage, Admin),
+    // Перенос конфигурации между экземплярами (справочники, права, пресеты, настройки)
+    rule(GET, "/admin/config-export", System, View, Admin),
+    rule(POST, "/admin/config-import", System, Manage, Admin),
+    // Диагностический архив для поддержки: без строк таблиц, с ограничением частоты
+    rule(GET, "/admin/support-bundle", System, View, Admin),
+    // Отмена удаления: автор удаления или администратор (проверяется в хендлере)
+    rule(POST, "/undo/{token}", Profile, Edit, Viewer),
+    // Согласование крупного расхода: список фильтруется в хендлере (не согласующие видят только свои заявки)
+    rule(GET, "/approvals", Batch, View, Viewer),
+    rule(POST, "/approvals/{id}/approve", Batch, Approve, Admin),
+    rule(POST, "/approvals/{id}/reject", Batch, Approve, Admin),
+    // Предупреждения дашборда: подтверждение скрывает их из счётчиков и сводки
+    rule(GET, "/alerts", Dashboard, View, Viewer),
+    rule(POST, "/alerts/{id}/acknowledge", Dashboard, Edit, Researcher),
+    // Избранное - личное для каждого пользователя
+    rule(GET, "/favorites", Profile, View, Viewer),
+    rule(POST, "/favorites/{entity_type}/{id}", Profile, Edit, Viewer),
+    rule(DELETE, "/favorites/{entity_type}/{id}", Profile, Edit, Viewer),
+    // Команды: создаёт и удаляет администратор, составом управляют лиды (проверяется в хендлере)
+    rule(GET, "/teams", Profile, View, Viewer),
+    rule(POST, "/teams", User, Create, Admin),
+    rule(GET, "/teams/{id}", Profile, View, Viewer),
+    rule(PUT, "/teams/{id}", User, Edit, Admin),
+    rule(DELETE, "/teams/{id}", User, Delete, Admin),
+    rule(POST, "/teams/{id}/members", Profile, Edit, Viewer),
+    rule(DELETE, "/teams/{id}/members/{user_id}", Profile, Edit, Viewer),
+
+    // Batches
+    rule(GET, "/purchase-orders", Batch, View, Viewer),
+    rule(POST, "/purchase-orders", Batch, Create, Researcher),
+    rule(POST, "/purchase-orders/from-suggestions", Batch, Create, Researcher),
+    rule(GET, "/purchase-orders/{id}", Batch, View, Viewer),
+    rule(POST, "/purchase-orders/{id}/submit", Batch, Create, Researcher),
+    rule(POST, "/purchase-orders/{id}/cancel", Batch, Create, Researcher),
+    rule(GET, "/purchase-orders/{id}/receive", Batch, View, Viewer),
+    rule(POST, "/purchase-orders/{id}/receive", Batch, Create, Researcher),
+    rule(POST, "/batches/filter", Batch, View, Viewer),
+    rule(GET, "/batches/preset/{preset}", Batch, View, Viewer),
+    rule(GET, "/batches", Batch, View, Viewer),
+    rule(GET, "/batches/low-stock", Batch, View, Viewer),
+    rule(GET, "/batches/expiring", Batch, View, Viewer),
+    rule(GET, "/batches/export", Batch, Export, Researcher),
+    rule(POST, "/batches/import", Batch, Import, Admin),
+    rule(POST, "/batches/import/json", Batch, Import, Admin),
+    rule(POST, "/batches/import/excel", Batch, Import, Admin),
+    rule(POST, "/batches/import/usage", Batch, Edit, Researcher),
+    rule(GET, "/batches/{batch_id}/placements", Batch, View, Viewer),
+    rule(POST, "/batches/{batch_id}/placements", Batch, Edit, Researcher),
+    rule(POST, "/batches/{batch_id}/placements/move", Batch, Edit, Researcher),
+    rule(PUT, "/batches/{batch_id}/placements/{placement_id}", Batch, Edit, Researcher),
+    rule(DELETE, "/batches/{batch_id}/placements/{placement_id}", Batch, Delete, Admin),
+    rule(POST, "/batches/{batch_id}/adjust", Batch, Edit, Researcher),
+    rule(GET, "/batches/{batch_id}/genealogy", Batch, View, Viewer),
+    rule(GET, "/batches/{batch_id}/links", Batch, View, Viewer),
+    rule(POST, "/batches/{batch_id}/links", Batch, Edit, Researcher),
+    rule(DELETE, "/batches/{batch_id}/links/{link_id}", Batch, Edit, Researcher),
+
+    // Reagents
+    rule(POST, "/reagents", Reagent, Create, Researcher),
+    rule(GET, "/reagents", Reagent, View, Viewer),
+    rule(GET, "/reagents/search", Reagent, View, Viewer),
+    rule(GET, "/reagents/lookup", Reagent, View, Viewer),
+    rule(GET, "/reagents/forecast", Reagent, View, Viewer),
+    rule(GET, "/reagents/export", Reagent, Export, Researcher),
+    rule(GET, "/reagents/without-batches", Reagent, View, Viewer),
+    rule(POST, "/reagents/import", Reagent, Import, Admin),
+    rule(POST, "/reagents/import/json", Reagent, Import, Admin),
+    rule(POST, "/reagents/import/excel", Reagent, Import, Admin),
+    rule(GET, "/reagents/{id}", Reagent, View, Viewer),
+    rule(PUT, "/reagents/{id}", Reagent, Edit, Researcher),
+    rule(DELETE, "/reagents/{id}", Reagent, Delete, Admin),
+    rule(POST, "/reagents/{id}/deactivate", Reagent, Edit, Researcher),
+    rule(POST, "/reagents/{id}/reactivate", Reagent, Edit, Researcher),
+    rule(GET, "/reagents/{id}/details", Reagent, View, Viewer),
+    rule(GET, "/reagents/{id}/forecast", Reagent, View, Viewer),
+    rule(GET, "/reagents/{id}/adjustments/summary", Batch, View, Viewer),
+    rule(GET, "/reagents/{id}/image", Reagent, View, Viewer),
+    rule(POST, "/reagents/{id}/image", Reagent, Edit, Researcher),
+    rule(GET, "/reagents/{id}/batches", Batch, View, Viewer),
+    rule(POST, "/reagents/{id}/batches", Batch, Create, Researcher),
+    rule(GET, "/reagents/{id}/links", Reagent, View, Viewer),
+    rule(POST, "/reagents/{id}/links", Reagent, Edit, Researcher),
+    rule(DELETE, "/reagents/{id}/links/{link_id}", Reagent, Edit, Researcher),
+    rule(GET, "/reagents/{reagent_id}/batches/{batch_id}", Batch, View, Viewer),
+    rule(PUT, "/reagents/{reagent_id}/batches/{batch_id}", Batch, Edit, Researcher),
+    rule(DELETE, "/reagents/{reagent_id}/batches/{batch_id}", Batch, Delete, Admin),
+    rule(PUT, "/reagents/{reagent_id}/batches/{batch_id}/barcode", Batch, Edit, Researcher),
+    rule(POST, "/reagents/{reagent_id}/batches/{batch_id}/extend-expiry", Batch, Approve, Admin),
+    rule(POST, "/reagents/{reagent_id}/batches/{batch_id}/coa-received", Batch, Approve, Admin),
+    rule(POST, "/reagents/{reagent_id}/batches/{batch_id}/use", Batch, Use, Viewer),
+    rule(POST, "/reagents/{reagent_id}/consume", Batch, Use, Viewer),
+    rule(GET, "/reagents/{reagent_id}/batches/{batch_id}/usage", Batch, View, Viewer),
+    rule(POST, "/reagents/{reagent_id}/batches/{batch_id}/dispense-units", Batch, Use, Viewer),
+    rule(GET, "/reagents/{reagent_id}/batches/{batch_id}/units-info", Batch, View, Viewer),
+
+    // Equipment
+    rule(POST, "/equipment", Equipment, Create, Researcher),
+    rule(GET, "/equipment", Equipment, View, Viewer),
+    rule(GET, "/equipment/search", Equipment, View, Viewer),
+    rule(GET, "/equipment/manufacturers", Equipment, View, Viewer),
+    rule(GET, "/equipment/models", Equipment, View, Viewer),
+    rule(GET, "/equipment/catalog", Equipment, View, Viewer),
+    rule(POST, "/equipment/catalog", Equipment, Manage, Admin),
+    rule(GET, "/equipment/catalog/{id}", Equipment, View, Viewer),
+    rule(PUT, "/equipment/catalog/{id}", Equipment, Manage, Admin),
+    rule(DELETE, "/equipment/catalog/{id}", Equipment, Manage, Admin),
+    rule(GET, "/equipment/export", Equipment, Export, Researcher),
+    rule(POST, "/equipment/import", Equipment, Import, Admin),
+    rule(POST, "/equipment/import/json", Equipment, Import, Admin),
+    rule(POST, "/equipment/import/excel", Equipment, Import, Admin),
+    rule(GET, "/equipment/maintenance/upcoming", Equipment, View, Viewer),
+    rule(GET, "/equipment/maintenance/overdue", Equipment, View, Viewer),
+    rule(GET, "/equipment/parts/reorder-suggestions", Equipment, View, Viewer),
+    rule(GET, "/equipment/{id}", Equipment, View, Viewer),
+    rule(PUT, "/equipment/{id}", Equipment, Edit, Researcher),
+    rule(DELETE, "/equipment/{id}", Equipment, Delete, Admin),
+    rule(GET, "/equipment/{id}/components", Equipment, View, Viewer),
+    rule(GET, "/equipment/{id}/parts", Equipment, View, Viewer),
+    rule(POST, "/equipment/{id}/parts", Equipment, Edit, Researcher),
+    rule(PUT, "/equipment/{id}/parts/{part_id}", Equipment, Edit, Researcher),
+    rule(DELETE, "/equipment/{id}/parts/{part_id}", Equipment, Delete, Admin),
+    rule(GET, "/equipment/{id}/parts/{part_id}/files", Equipment, View, Viewer),
+    rule(GET, "/equipment/{id}/maintenance", Equipment, View, Viewer),
+    rule(POST, "/equipment/{id}/maintenance", Equipment, Edit, Researcher),
+    rule(PUT, "/equipment/{id}/maintenance/{maintenance_id}", Equipment, Edit, Researcher),
+    rule(POST, "/equipment/{id}/maintenance/{maintenance_id}/complete", Equipment, Edit, Researcher),
+    rule(DELETE, "/equipment/{id}/maintenance/{maintenance_id}", Equipment, Delete, Admin),
+    rule(GET, "/equipment/{id}/files", Equipment, View, Viewer),
+    rule(POST, "/equipment/{id}/files", Equipment, Edit, Researcher),
+    rule(GET, "/equipment/{id}/files/{file_id}", Equipment, View, Viewer),
+    rule(DELETE, "/equipment/{id}/files/{file_id}", Equipment, Delete, Admin),
+    rule(GET, "/equipment/{id}/links", Equipment, View, Viewer),
+    rule(POST, "/equipment/{id}/links", Equipment, Edit, Researcher),
+    rule(DELETE, "/equipment/{id}/links/{link_id}", Equipment, Edit, Researcher),
+
+    // Rooms
+    rule(GET, "/rooms", Room, View, Viewer),
+    rule(POST, "/rooms", Room, Create, Researcher),
+    rule(GET, "/rooms/available", Room, View, Viewer),
+    rule(GET, "/rooms/utilization", Room, View, Viewer),
+    rule(GET, "/rooms/{id}", Room, View, Viewer),
+    rule(PUT, "/rooms/{id}", Room, Edit, Researcher),
+    rule(DELETE, "/rooms/{id}", Room, Delete, Admin),
+    rule(GET, "/rooms/{id}/utilization", Room, View, Viewer),
+    rule(GET, "/rooms/{id}/inventory", Room, View, Viewer),
+    rule(GET, "/rooms/{id}/placements", Room, View, Viewer),
+    rule(POST, "/rooms/{id}/kiosk-token", Room, Manage, Admin),
+    rule(GET, "/rooms/{id}/kiosk-tokens", Room, Manage, Admin),
+    rule(DELETE, "/rooms/{id}/kiosk-tokens/{token_id}", Room, Manage, Admin),
+
+    // Storage locations
+    rule(GET, "/locations", Room, View, Viewer),
+    rule(POST, "/locations", Room, Create, Researcher),
+    rule(GET, "/locations/{id}", Room, View, Viewer),
+    rule(PUT, "/locations/{id}", Room, Edit, Researcher),
+    rule(DELETE, "/locations/{id}", Room, Delete, Admin),
+    rule(POST, "/locations/{id}/move", Room, Edit, Researcher),
+    rule(GET, "/locations/{id}/contents", Batch, View, Viewer),
+
+    // Kiosk (только киоск-токены помещения)
+    kiosk(GET, "/kiosk/equipment", KioskScope::EquipmentRead),
+    kiosk(POST, "/kiosk/equipment/{id}/checkout", KioskScope::UsageWrite),
+    kiosk(POST, "/kiosk/equipment/{id}/checkin", KioskScope::UsageWrite),
+    kiosk(POST, "/kiosk/attendance", KioskScope::AttendanceWrite),
+
+    // Experiments
+    rule(POST, "/experiments", Experiment, Create, Researcher),
+    rule(GET, "/experiments", Experiment, View, Viewer),
+    rule(GET, "/experiments/stats", Experiment, View, Viewer),
+    rule(POST, "/experiments/filter", Experiment, View, Viewer),
+    rule(GET, "/experiments/filter/fields", Experiment, View, Viewer),
+    rule(GET, "/experiments/preset/{preset}", Experiment, View, Viewer),
+    // Идемпотентный пересчёт статусов по времени, фронтенд вызывает его при открытии списка
+    rule(POST, "/experiments/auto-update-statuses", Experiment, View, Viewer),
+    rule(GET, "/experiments/diagnose-dates", Experiment, View, Viewer),
+    rule(GET, "/experiments/calendar", Experiment, View, Viewer),
+    rule(GET, "/experiments/dependency-graph", Experiment, View, Viewer),
+    // Общий календарь: эксперименты, обслуживание, бронирования помещений
+    rule(GET, "/calendar", Experiment, View, Viewer),
+    rule(GET, "/experiments/{id}", Experiment, View, Viewer),
+    rule(PUT, "/experiments/{id}", Experiment, Edit, Researcher),
+    rule(DELETE, "/experiments/{id}", Experiment, Delete, Admin),
+    rule(POST, "/experiments/{id}/publish", Experiment, Edit, Researcher),
+    // Копия создаётся черновиком вызывающего; чужой черновик копировать нельзя (404)
+    rule(POST, "/experiments/{id}/clone", Experiment, Create, Researcher),
+    rule(POST, "/experiments/{id}/start", Experiment, Edit, Researcher),
+    rule(POST, "/experiments/{id}/complete", Experiment, Edit, Researcher),
+    rule(POST, "/experiments/{id}/cancel", Experiment, Edit, Researcher),
+    rule(GET, "/experiments/{id}/reagents", Experiment, View, Viewer),
+    rule(POST, "/experiments/{id}/reagents", Experiment, Edit, Researcher),
+    rule(DELETE, "/experiments/{id}/reagents/{reagent_id}", Experiment, Edit, Researcher),
+    rule(POST, "/experiments/{id}/reagents/{reagent_id}/consume", Experiment, Edit, Researcher),
+    rule(GET, "/experiments/{id}/participants", Experiment, View, Viewer),
+    // Изменять эксперимент могут только автор, инструктор и админ (проверка в обёртках);
+    // остальным с правом Edit остаются комментарии
+    rule(GET, "/experiments/{id}/comments", Experiment, View, Viewer),
+    rule(POST, "/experiments/{id}/comments", Experiment, Edit, Researcher),
+    rule(GET, "/experiments/{id}/documents", Experiment, View, Viewer),
+    rule(GET, "/experiments/{id}/documents/{doc_id}", Experiment, View, Viewer),
+    rule(POST, "/experiments/{id}/participants", Experiment, Edit, Researcher),
+    rule(DELETE, "/experiments/{id}/participants/{participant_id}", Experiment, Edit, Researcher),
+    // Участник отмечает себя сам; отметка других требует Edit (проверка в хендлере)
+    rule(POST, "/experiments/{id}/sign-in", Experiment, View, Viewer),
+    rule(GET, "/experiments/{id}/signoff", Experiment, View, Viewer),
+    // Инструктор эксперимента или администратор (проверка в хендлере)
+    rule(POST, "/experiments/{id}/signoff", Experiment, View, Viewer),
+    rule(GET, "/experiments/{id}/dependencies", Experiment, View, Viewer),
+    rule(POST, "/experiments/{id}/dependencies", Experiment, Edit, Researcher),
+    rule(DELETE, "/experiments/{id}/dependencies/{depends_on_id}", Experiment, Edit, Researcher),
+    rule(GET, "/experiments/{id}/links", Experiment, View, Viewer),
+    rule(GET, "/experiments/{id}/protocol/rendered", Experiment, View, Viewer),
+    rule(GET, "/experiments/{id}/report.pdf", Experiment, View, Viewer),
+    rule(POST, "/experiments/{id}/links", Experiment, Edit, Researcher),
+    rule(DELETE, "/experiments/{id}/links/{link_id}", Experiment, Edit, Researcher),
+
+    // Reports (владение пресетами проверяется в хендлере)
+    rule(GET, "/reports/presets", Report, View, Viewer),
+    rule(POST, "/reports/presets", Report, Create, Viewer),
+    rule(PUT, "/reports/presets/{id}", Report, Edit, Viewer),
+    rule(DELETE, "/reports/presets/{id}", Report, Delete, Viewer),
+    rule(GET, "/reports/fields", Report, View, Viewer),
+    rule(POST, "/reports/generate", Report, View, Viewer),
+    rule(POST, "/reports/export", Report, Export, Viewer),
+    rule(GET, "/reports/consumption-variance", Report, View, Viewer),
+    // Рассылка по расписанию: владелец или администратор (проверка в хендлере)
+    rule(GET, "/reports/schedules", Report, View, Viewer),
+    rule(POST, "/reports/schedules", Report, Export, Researcher),
+    rule(GET, "/reports/schedules/{id}", Report, View, Viewer),
+    rule(PUT, "/reports/schedules/{id}", Report, Export, Researcher),
+    rule(DELETE, "/reports/schedules/{id}", Report, Export, Researcher),
+    rule(GET, "/reports/schedules/{id}/runs", Report, View, Viewer),
+    // Права на каждую сущность архива проверяются отдельно (недоступные попадают в manifest)
+    rule(GET, "/export/archive", Report, Export, Viewer),
+];
+
+// ==================== MATCHING ====================
+
+fn role_rank(role: &UserRole) -> u8 {
+    match role {
+        UserRole::Viewer => 0,
+        UserRole::Researcher => 1,
+        UserRole::Admin => 2,
+    }
+}
+
+fn is_param(segment: &str) -> bool {
+    segment.starts_with('{') && segment.ends_with('}')
+}
+
+fn segments(path: &str) -> Vec<&str> {
+    path.trim_matches('/').split('/').filter(|s| !s.is_empty()).collect()
+}
+
+/// Совпадение шаблона с путём; возвращает «специфичность» — маску литеральных
+/// сегментов, чтобы `/reagents/search` побеждал `/reagents/{id}`
+fn match_pattern(pattern: &str, path: &str) -> Option<Vec<bool>> {
+    let pattern_segments = segments(pattern);
+    let path_segments = segments(path);
+    if pattern_segments.len() != path_segments.len() {
+        return None;
+    }
+
+    let mut literal_mask = Vec::with_capacity(pattern_segments.len());
+    for (p, s) in pattern_segments.iter().zip(&path_segments) {
+        if is_param(p) {
+            literal_mask.push(false);
+        } else if p == s {
+            literal_mask.push(true);
+        } else {
+            return None;
+        }
+    }
+    Some(literal_mask)
+}
+
+/// Запись таблицы для метода и пути (относительно /api/v1); `None` — маршрут не описан
+pub fn find_rule(method: &Method, path: &str) -> Option<&'static RoutePermission> {
+    ROUTE_PERMISSIONS
+        .iter()
+        .filter(|r| r.method == *method)
+        .filter_map(|r| match_pattern(r.path, path).map(|mask| (mask, r)))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, r)| r)
+}
+
+pub fn is_public(method: &Method, path: &str) -> bool {
+    find_rule(method, path).is_some_and(|r| r.access == Access::Public)
+}
+
+// ==================== AUTHORIZATION ====================
+
+/// Ключ индивидуального права в user_permissions (например, `create_batch`).
+/// Индивидуальные права настраиваются только для CRUD и QC по основным сущностям.
+fn custom_permission_key(resource: Resource, action: Action) -> Option<String> {
+    let overridable_resource = matches!(resource, Reagent | Batch | Equipment | Experiment | Room);
+    let overridable_action = matches!(action, Create | Edit | Delete | Approve);
+    if overridable_resource && overridable_action {
+        Some(format!("{}_{}", action.as_str(), resource.as_str()))
+    } else {
+        None
+    }
+}
+
+/// Проверка права: ключ из индивидуальных прав пользователя (если он задан) имеет приоритет,
+/// иначе сравнивается роль с минимальной ролью из таблицы
+pub async fn authorize(
+    claims: &Claims,
+    resource: Resource,
+    action: Action,
+    min_role: &UserRole,
+    pool: &SqlitePool,
+) -> ApiResult<()> {
+    if let Some(permission_key) = custom_permission_key(resource, action) {
+        let result: Option<(String,)> = sqlx::query_as(
+            "SELECT permissions FROM user_permissions WHERE user_id = ?"
+        )
+        .bind(&claims.sub)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            log::error!("DB error checking permissions: {:?}", e);
+            ApiError::InternalServerError("Database error".to_string())
+        })?;
+
+        if let Some((perms_json,)) = result {
+            // Индивидуальные права решают только за перечисленные ключи, остальное - по роли
+            match serde_json::from_str::<HashMap<String, bool>>(&perms_json) {
+                Ok(perms) => match perms.get(&permission_key) {
+                    Some(true) => {
+                        log::debug!("User {} granted {} via custom permissions", claims.username, permission_key);
+                        return Ok(());
+                    }
+                    Some(false) => {
+                        log::info!("User {} denied {} via custom permissions", claims.username, permission_key);
+                        return Err(ApiError::Forbidden("Insufficient permissions".to_string()));
+                    }
+                    None => {}
+                },
+                Err(e) => {
+                    log::error!("Failed to parse permissions JSON for user {}: {:?}", claims.username, e);
+                    // Битый JSON - проверяем по роли
+                }
+            }
+        }
+    }
+
+    if role_rank(&claims.role) >= role_rank(min_role) {
+        return Ok(());
+    }
+
+    Err(ApiError::Forbidden("Insufficient permissions".to_string()))
+}
+
+/// Активные пользователи, которым `authorize` разрешит действие: (id, email).
+/// Используется для уведомлений (например, согласующих заявки на расход)
+pub async fn users_with_permission(
+    pool: &SqlitePool,
+    resource: Resource,
+    action: Action,
+    min_role: &UserRole,
+) -> ApiResult<Vec<(String, String)>> {
+    let users: Vec<(String, String, String, Option<String>)> = sqlx::query_as(
+        r#"SELECT u.id, u.email, u.role, p.permissions
+           FROM users u
+           LEFT JOIN user_permissions p ON p.user_id = u.id
+           WHERE u.is_active = 1
+           ORDER BY u.username"#
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let permission_key = custom_permission_key(resource, action);
+    Ok(users
+        .into_iter()
+        .filter(|(_, _, role, permissions)| {
+            // Те же правила, что в authorize: индивидуальные права важнее роли
+            let custom = permission_key.as_ref().zip(permissions.as_deref()).and_then(|(key, json)| {
+                serde_json::from_str::<HashMap<String, bool>>(json)
+                    .ok()
+                    .and_then(|perms| perms.get(key).copied())
+            });
+            custom.unwrap_or_else(|| {
+                UserRole::from_str(role).is_some_and(|role| role_rank(&role) >= role_rank(min_role))
+            })
+        })
+        .map(|(id, email, _, _)| (id, email))
+        .collect())
+}
+
+/// Проверка права пользователя на другой маршрут из таблицы (например, для составных
+/// операций, которые повторяют проверки отдельных эндпоинтов)
+pub async fn authorize_route(claims: &Claims, method: &Method, path: &str, pool: &SqlitePool) -> ApiResult<()> {
+    match find_rule(method, path).map(|r| &r.access) {
+        Some(Access::Public) => Ok(()),
+        Some(Access::Require { resource, action, min_role }) => {
+            authorize(claims, *resource, *action, min_role, pool).await
+        }
+        _ => Err(ApiError::Forbidden("Insufficient permissions".to_string())),
+    }
+}
+
+/// Проверка запроса по таблице; неописанный маршрут запрещён
+pub async fn authorize_request(method: &Method, path: &str, req: &actix_web::HttpRequest) -> ApiResult<()> {
+    let relative = path.strip_prefix(API_PREFIX).unwrap_or(path);
+    let Some(route) = find_rule(method, relative) else {
+        log::warn!("Denied {} {}: route has no access rule", method, path);
+        return Err(ApiError::Forbidden("Access to this endpoint is not configured".to_string()));
+    };
+
+    // Киоск-токен - отдельный принципал: только маршруты киоска и только в своих областях
+    let kiosk = req.extensions().get::<KioskPrincipal>().cloned();
+
+    match (&route.access, kiosk) {
+        (Access::Public, _) => Ok(()),
+        (Access::Kiosk(scope), Some(kiosk)) => {
+            if kiosk.has_scope(*scope) {
+                Ok(())
+            } else {
+                Err(ApiError::Forbidden(format!("Kiosk token has no '{}' scope", scope.as_str())))
+            }
+        }
+        (Access::Kiosk(_), None) => {
+            Err(ApiError::Forbidden("This endpoint is only available to kiosk tokens".to_string()))
+        }
+        (Access::Require { .. }, Some(kiosk)) => {
+            log::warn!("Denied {} {}: kiosk token {} is limited to kiosk endpoints", method, path, kiosk.token_id);
+            Err(ApiError::Forbidden("Kiosk tokens cannot access this endpoint".to_string()))
+        }
+        (Access::Require { resource, action, min_role }, None) => {
+            let claims = get_current_user(req)?;
+            let app_state = req
+                .app_data::<web::Data<Arc<AppState>>>()
+                .ok_or_else(|| ApiError::InternalServerError("Application state is not configured".to_string()))?;
+            authorize(&claims, *resource, *action, min_role, &app_state.db_pool).await
+        }
+    }
+}
+
+// ==================== MIDDLEWARE ====================
+
+/// Middleware для scope /api/v1; должен стоять внутри auth middleware,
+/// чтобы Claims уже были в extensions запроса
+pub struct RouteAuthorization;
+
+impl<S, B> Transform<S, ServiceRequest> for RouteAuthorization
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = RouteAuthorizationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RouteAuthorizationMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RouteAuthorizationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RouteAuthorizationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, ctx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let method = req.method().clone();
+            let path = req.path().to_string();
+            authorize_request(&method, &path, req.request()).await?;
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, App, HttpMessage, HttpResponse};
+    use std::collections::HashSet;
+
+    fn claims(user_id: &str, role: UserRole) -> Claims {
+        Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{}@lims.local", user_id),
+            role,
+            exp: i64::MAX,
+            iat: 0,
+        }
+    }
+
+    #[test]
+    fn test_every_registered_route_has_an_access_rule() {
+        let mut registered = HashSet::new();
+        for route in crate::api_v1_routes() {
+            let rule = find_rule(&route.method, route.path);
+            assert!(
+                rule.is_some_and(|r| r.path == route.path),
+                "{} {} has no entry in ROUTE_PERMISSIONS",
+                route.method, route.path
+            );
+            assert!(registered.insert((route.method.clone(), route.path)), "{} {} registered twice", route.method, route.path);
+        }
+
+        for rule in ROUTE_PERMISSIONS {
+            assert!(
+                registered.contains(&(rule.method.clone(), rule.path)),
+                "stale ROUTE_PERMISSIONS entry {} {}",
+                rule.method, rule.path
+            );
+        }
+    }
+
+    #[test]
+    fn test_most_specific_pattern_wins() {
+        let search = find_rule(&Method::GET, "/reagents/search").unwrap();
+        assert_eq!(search.path, "/reagents/search");
+        let by_id = find_rule(&Method::GET, "/reagents/abc").unwrap();
+        assert_eq!(by_id.path, "/reagents/{id}");
+        let usage = find_rule(&Method::POST, "/batches/import/usage").unwrap();
+        assert_eq!(usage.path, "/batches/import/usage");
+
+        assert!(find_rule(&Method::GET, "/reagents/abc/unknown").is_none());
+        assert!(find_rule(&Method::PATCH, "/reagents/abc").is_none());
+        assert!(is_public(&Method::GET, "/public/equipment/e1/files/f1"));
+        assert!(!is_public(&Method::GET, "/equipment/e1/files/f1"));
+    }
+
+    #[actix_web::test]
+    async fn test_authorize_roles_and_custom_permissions() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        let viewer = claims("viewer-1", UserRole::Viewer);
+        let researcher = claims("researcher-1", UserRole::Researcher);
+        let admin = claims("admin-1", UserRole::Admin);
+
+        assert!(authorize(&viewer, Reagent, Create, &Researcher, &pool).await.is_err());
+        assert!(authorize(&researcher, Reagent, Create, &Researcher, &pool).await.is_ok());
+        assert!(authorize(&researcher, Reagent, Delete, &Admin, &pool).await.is_err());
+        assert!(authorize(&admin, Reagent, Delete, &Admin, &pool).await.is_ok());
+
+        for user_id in ["viewer-1", "admin-1"] {
+            sqlx::query(
+                "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+                 VALUES (?, ?, ?, 'x', 'viewer', datetime('now'), datetime('now'))"
+            )
+            .bind(user_id).bind(user_id).bind(format!("{}@lims.local", user_id))
+            .execute(&pool).await.unwrap();
+        }
+        // Индивидуальные права важнее роли — в обе стороны
+        sqlx::query("INSERT INTO user_permissions (user_id, permissions) VALUES (?, ?)")
+            .bind("viewer-1").bind(r#"{"create_reagent": true}"#)
+            .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO user_permissions (user_id, permissions) VALUES (?, ?)")
+            .bind("admin-1").bind(r#"{"delete_reagent": false}"#)
+            .execute(&pool).await.unwrap();
+
+        assert!(authorize(&viewer, Reagent, Create, &Researcher, &pool).await.is_ok());
+        assert!(authorize(&admin, Reagent, Delete, &Admin, &pool).await.is_err());
+        // Ключей, которых нет в индивидуальных правах, решает роль
+        assert!(authorize(&viewer, Reagent, Delete, &Admin, &pool).await.is_err());
+        assert!(authorize(&admin, Equipment, Edit, &Researcher, &pool).await.is_ok());
+        assert!(authorize(&admin, Reagent, Create, &Researcher, &pool).await.is_ok());
+        // Системные права индивидуально не переопределяются
+        assert!(authorize(&viewer, System, Manage, &Admin, &pool).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_middleware_denies_by_default() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        let app_state = Arc::new(AppState { db_pool: pool, config: crate::config::Config::default(), read_replica: None, reference_cache: Default::default() });
+
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(app_state))
+                .service(
+                    web::scope(API_PREFIX)
+                        .wrap(RouteAuthorization)
+                        .wrap_fn(|req, srv| {
+                            // Заменяет JWT middleware: роль берётся из заголовка
+                            let role = req.headers().get("x-test-role")
+                                .and_then(|v| v.to_str().ok())
+                                .and_then(UserRole::from_str);
+                            if let Some(role) = role {
+                                req.extensions_mut().insert(claims("tester", role));
+                            }
+                            srv.call(req)
+                        })
+                        .route("/reagents/{id}", web::get().to(HttpResponse::Ok))
+                        .route("/reagents/{id}", web::delete().to(HttpResponse::Ok))
+                        .route("/not-in-table", web::get().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+
+        // Отказ middleware приходит как Err — превращаем его в статус, как это сделает сервер
+        let status = |method: Method, uri: &'static str, role: &'static str| {
+            let req = actix_test::TestRequest::default()
+                .method(method)
+                .uri(uri)
+                .insert_header(("x-test-role", role))
+                .to_request();
+            let fut = actix_test::try_call_service(&app, req);
+            async move {
+                match fut.await {
+                    Ok(resp) => resp.status().as_u16(),
+                    Err(e) => e.error_response().status().as_u16(),
+                }
+            }
+        };
+
+        assert_eq!(status(Method::GET, "/api/v1/reagents/r1", "viewer").await, 200);
+        assert_eq!(status(Method::DELETE, "/api/v1/reagents/r1", "researcher").await, 403);
+        assert_eq!(status(Method::DELETE, "/api/v1/reagents/r1", "admin").await, 200);
+        assert_eq!(status(Method::GET, "/api/v1/not-in-table", "admin").await, 403);
+        assert_eq!(status(Method::GET, "/api/v1/reagents/r1", "none").await, 401);
+    }
+
+    #[actix_web::test]
+    async fn test_kiosk_principal_is_limited_to_its_scopes() {
+        let kiosk_req = || {
+            let req = actix_test::TestRequest::default().to_http_request();
+            req.extensions_mut().insert(KioskPrincipal {
+                token_id: "k1".to_string(),
+                room_id: "room-1".to_string(),
+                room_name: "Lab 101".to_string(),
+                scopes: vec![KioskScope::EquipmentRead],
+            });
+            req
+        };
+        let forbidden = |result: ApiResult<()>| matches!(result, Err(ApiError::Forbidden(_)));
+
+        assert!(authorize_request(&Method::GET, "/api/v1/kiosk/equipment", &kiosk_req()).await.is_ok());
+        assert!(forbidden(authorize_request(&Method::POST, "/api/v1/kiosk/attendance", &kiosk_req()).await));
+        assert!(forbidden(authorize_request(&Method::GET, "/api/v1/reagents", &kiosk_req()).await));
+        assert!(forbidden(authorize_request(&Method::GET, "/api/v1/rooms/room-1/kiosk-tokens", &kiosk_req()).await));
+
+        // Обычный пользователь, даже администратор, не может действовать от имени киоска
+        let admin_req = actix_test::TestRequest::default().to_http_request();
+        admin_req.extensions_mut().insert(claims("admin", UserRole::Admin));
+        assert!(forbidden(authorize_request(&Method::GET, "/api/v1/kiosk/equipment", &admin_req).await));
+
+        assert_eq!(KioskScope::parse("usage:write"), Some(KioskScope::UsageWrite));
+        assert_eq!(KioskScope::parse("rooms:manage"), None);
+    }
+}
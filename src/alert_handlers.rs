@@ -0,0 +1,734 @@
+// src/alert_handlers.rs
+//! Предупреждения дашборда с подтверждением.
+//!
+//! Фоновая задача (`refresh_alerts`) создаёт по записи в `alerts` на каждый экземпляр условия:
+//! партия скоро истекает, мало остатка, просрочено ТО оборудования, подходит калибровка или
+//! плановое обслуживание. Калибровка и обслуживание напоминаются за `reminder_lead_days` дней
+//! до scheduled_date (по умолчанию - срок для типа из `[maintenance]`); срок и дата попадают
+//! в `context` предупреждения. Когда условие исчезает, предупреждение закрывается (`resolved`) автоматически.
+//! Аномалии расхода партий (`stock_anomalies`) держат предупреждение `stock_anomaly` открытым
+//! сутки после последнего срабатывания по партии; пользователь и правило - в `context`.
+//!
+//! `POST /alerts/{id}/acknowledge` помечает предупреждение как просмотренное (с заметкой и,
+//! при необходимости, `snooze_until`). Подтверждённые предупреждения не входят в счётчики
+//! дашборда и в ежедневную сводку; по истечении `snooze_until` предупреждение снова открыто.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::access_control::{self, Action, Resource};
+use crate::auth::{get_current_user, UserRole};
+use crate::config::{MaintenanceConfig, SmtpConfig};
+use crate::error::{ApiError, ApiResult};
+use crate::events::BusinessEvent;
+use crate::handlers::{ApiResponse, PaginatedResponse};
+use crate::pagination::PageGroup;
+use crate::mailer::{self, Email};
+use crate::AppState;
+
+/// Код ошибки 409 для уже закрытого предупреждения
+pub const ALERT_ALREADY_RESOLVED: &str = "ALERT_ALREADY_RESOLVED";
+
+pub const ALERT_STATUSES: &[&str] = &["open", "acknowledged", "resolved"];
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    BatchExpiring,
+    LowStock,
+    MaintenanceOverdue,
+    CalibrationDue,
+    MaintenanceDue,
+    MaintenanceEscalation,
+    StockAnomaly,
+}
+
+/// Сколько часов после последнего срабатывания аномалии по партии предупреждение остаётся открытым
+const STOCK_ANOMALY_ALERT_HOURS: i64 = 24;
+
+impl AlertKind {
+    pub const ALL: [AlertKind; 7] = [
+        AlertKind::BatchExpiring,
+        AlertKind::LowStock,
+        AlertKind::MaintenanceOverdue,
+        AlertKind::CalibrationDue,
+        AlertKind::MaintenanceDue,
+        AlertKind::MaintenanceEscalation,
+        AlertKind::StockAnomaly,
+    ];
+
+    /// Зависят от дат обслуживания - пересчитываются при их изменении
+    pub const MAINTENANCE: [AlertKind; 4] = [
+        AlertKind::MaintenanceOverdue,
+        AlertKind::CalibrationDue,
+        AlertKind::MaintenanceDue,
+        AlertKind::MaintenanceEscalation,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::BatchExpiring => "batch_expiring",
+            AlertKind::LowStock => "low_stock",
+            AlertKind::MaintenanceOverdue => "maintenance_overdue",
+            AlertKind::CalibrationDue => "calibration_due",
+            AlertKind::MaintenanceDue => "maintenance_due",
+            AlertKind::MaintenanceEscalation => "maintenance_escalation",
+            AlertKind::StockAnomaly => "stock_anomaly",
+        }
+    }
+
+    fn entity_type(&self) -> &'static str {
+        match self {
+            AlertKind::BatchExpiring | AlertKind::LowStock | AlertKind::StockAnomaly => "batch",
+            AlertKind::MaintenanceOverdue | AlertKind::CalibrationDue => "equipment",
+            AlertKind::MaintenanceDue | AlertKind::MaintenanceEscalation => "maintenance",
+        }
+    }
+
+    /// Текущие экземпляры условия: (id сущности, текст предупреждения, контекст JSON)
+    fn condition_sql(&self, maintenance: &MaintenanceConfig) -> String {
+        let lead_days = maintenance.lead_days_sql("m");
+        match self {
+            // Пороги - те же настройки, что у счётчиков дашборда;
+            // advisory-реагенты видны только в отчёте об истекающих партиях
+            AlertKind::BatchExpiring => r#"
+                SELECT b.id, 'Batch ' || b.batch_number || ' of ' || r.name || ' expires on ' || date(b.expiry_date), NULL
+                FROM batches b JOIN reagents r ON r.id = b.reagent_id AND r.deleted_at IS NULL
+                WHERE r.expiry_policy = 'strict' AND b.expiry_date IS NOT NULL AND b.expiry_date <= datetime('now', ?)
+                  AND b.status = 'available' AND b.deleted_at IS NULL"#.to_string(),
+            AlertKind::LowStock => r#"
+                SELECT b.id, 'Batch ' || b.batch_number || ' of ' || r.name || ' is low: '
+                       || b.quantity || ' ' || b.unit || ' of ' || b.original_quantity || ' left', NULL
+                FROM batches b JOIN reagents r ON r.id = b.reagent_id AND r.deleted_at IS NULL
+                WHERE b.original_quantity > 0 AND b.quantity * 100.0 / b.original_quantity <= CAST(? AS INTEGER)
+                  AND b.status = 'available' AND b.deleted_at IS NULL"#.to_string(),
+            AlertKind::MaintenanceOverdue => r#"
+                SELECT e.id, 'Maintenance of ' || e.name || ' is overdue', NULL
+                FROM equipment e
+                WHERE e.status != 'retired' AND e.pending_deletion_id IS NULL
+                  AND ((e.next_maintenance IS NOT NULL AND date(e.next_maintenance) < date('now'))
+                       OR EXISTS (SELECT 1 FROM equipment_maintenance m
+                                  WHERE m.equipment_id = e.id AND m.maintenance_type != 'calibration'
+                                    AND m.status IN ('scheduled', 'in_progress')
+                                    AND date(m.scheduled_date) < date('now')))"#.to_string(),
+            // Ближайшая калибровка; остальные столбцы SQLite берёт из строки с MIN
+            AlertKind::CalibrationDue => format!(r#"
+                SELECT e.id, 'Calibration of ' || e.name || ' is due on ' || MIN(date(m.scheduled_date))
+                       || ' (reminder ' || {lead} || ' day(s) ahead)',
+                       {context}
+                FROM equipment e
+                JOIN equipment_maintenance m ON m.equipment_id = e.id
+                WHERE e.status != 'retired' AND e.pending_deletion_id IS NULL
+                  AND m.maintenance_type = 'calibration' AND m.status IN ('scheduled', 'in_progress')
+                  AND date('now') >= date(m.scheduled_date, '-' || {lead} || ' days')
+                GROUP BY e.id, e.name"#,
+                lead = lead_days, context = reminder_context_sql(&lead_days)),
+            // Просроченное обслуживание - уже maintenance_overdue, калибровка - calibration_due
+            AlertKind::MaintenanceDue => format!(r#"
+                SELECT m.id, 'Maintenance (' || m.maintenance_type || ') of ' || e.name || ' is due on '
+                       || date(m.scheduled_date) || ' (reminder ' || {lead} || ' day(s) ahead)',
+                       {context}
+                FROM equipment_maintenance m
+                JOIN equipment e ON e.id = m.equipment_id
+                WHERE e.status != 'retired' AND e.pending_deletion_id IS NULL
+                  AND m.maintenance_type != 'calibration' AND m.status IN ('scheduled', 'in_progress')
+                  AND date(m.scheduled_date) >= date('now')
+                  AND date('now') >= date(m.scheduled_date, '-' || {lead} || ' days')"#,
+                lead = lead_days, context = reminder_context_sql(&lead_days)),
+            // Ступень ставит escalate_maintenance; открытое предупреждение - на всю цепочку эскалации
+            AlertKind::MaintenanceEscalation => format!(r#"
+                SELECT m.id, 'Maintenance (' || m.maintenance_type || ') of ' || e.name || ' is '
+                       || {days} || ' day(s) past its SLA'
+                       || CASE m.escalation_level WHEN 2 THEN ' (escalated to admins)' ELSE '' END,
+                       json_object('equipment_id', m.equipment_id, 'maintenance_id', m.id,
+                                   'maintenance_type', m.maintenance_type, 'scheduled_date', date(m.scheduled_date),
+                                   'sla_days', {sla}, 'days_overdue', {days},
+                                   'escalation_level', m.escalation_level, 'assigned_to', m.assigned_to)
+                FROM equipment_maintenance m
+                JOIN equipment e ON e.id = m.equipment_id
+                WHERE m.effective_status = 'overdue'"#,
+                sla = maintenance.sla_days_sql("m"), days = maintenance.days_overdue_sql("m")),
+            // Последнее срабатывание по партии в пределах STOCK_ANOMALY_ALERT_HOURS
+            AlertKind::StockAnomaly => r#"
+                SELECT s.batch_id, s.message,
+                       json_object('anomaly_id', s.id, 'rule', s.rule, 'operation', s.operation,
+                                   'user_id', s.user_id, 'username', u.username,
+                                   'observed', s.observed, 'threshold', s.threshold,
+                                   'window_minutes', s.window_minutes, 'detected_at', s.detected_at)
+                FROM stock_anomalies s
+                LEFT JOIN users u ON u.id = s.user_id
+                WHERE datetime(s.detected_at) >= datetime('now', ?)
+                  AND s.id = (SELECT l.id FROM stock_anomalies l WHERE l.batch_id = s.batch_id
+                              ORDER BY l.detected_at DESC, l.rowid DESC LIMIT 1)"#.to_string(),
+        }
+    }
+
+    /// Параметр запроса условия (строкой - число приводится в SQL)
+    fn condition_param(&self) -> Option<String> {
+        let runtime = crate::settings::settings();
+        match self {
+            AlertKind::BatchExpiring => Some(format!("+{} days", runtime.get_i64(crate::settings::EXPIRING_SOON_DAYS))),
+            AlertKind::LowStock => Some(runtime.get_i64(crate::settings::LOW_STOCK_THRESHOLD_PERCENT).to_string()),
+            AlertKind::StockAnomaly => Some(format!("-{} hours", STOCK_ANOMALY_ALERT_HOURS)),
+            AlertKind::MaintenanceOverdue | AlertKind::CalibrationDue | AlertKind::MaintenanceDue
+            | AlertKind::MaintenanceEscalation => None,
+        }
+    }
+}
+
+/// Контекст напоминания об обслуживании: запись, дата, срок и день, с которого напоминаем
+fn reminder_context_sql(lead_days: &str) -> String {
+    format!(
+        "json_object('equipment_id', m.equipment_id, 'maintenance_id', m.id, 'maintenance_type', m.maintenance_type, \
+         'scheduled_date', date(m.scheduled_date), 'reminder_lead_days', {lead}, \
+         'remind_from', date(m.scheduled_date, '-' || {lead} || ' days'))",
+        lead = lead_days
+    )
+}
+
+/// Контекст хранится текстом JSON, в ответе - объект
+fn serialize_context<S: serde::Serializer>(context: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    context.as_deref()
+        .and_then(|raw| serde_json::from_str::<serde_json::Value>(raw).ok())
+        .serialize(serializer)
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Alert {
+    pub id: String,
+    pub kind: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub message: String,
+    #[serde(serialize_with = "serialize_context")]
+    pub context: Option<String>,
+    pub status: String,
+    pub note: Option<String>,
+    pub snoozed_until: Option<DateTime<Utc>>,
+    pub acknowledged_by: Option<String>,
+    pub acknowledged_by_username: Option<String>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const ALERT_SELECT: &str = r#"SELECT a.id, a.kind, a.entity_type, a.entity_id, a.message, a.context, a.status, a.note,
+           a.snoozed_until, a.acknowledged_by, u.username AS acknowledged_by_username, a.acknowledged_at,
+           a.resolved_at, a.created_at, a.updated_at
+       FROM alerts a
+       LEFT JOIN users u ON u.id = a.acknowledged_by"#;
+
+#[derive(Debug, Deserialize)]
+pub struct AlertListQuery {
+    pub status: Option<String>,
+    pub kind: Option<String>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct AcknowledgeAlertRequest {
+    #[validate(length(max = 1000, message = "Note cannot exceed 1000 characters"))]
+    pub note: Option<String>,
+    /// YYYY-MM-DD (начало дня UTC) или RFC 3339
+    pub snooze_until: Option<String>,
+}
+
+/// Итог одного прохода фоновой задачи
+#[derive(Debug, Default, PartialEq)]
+pub struct AlertRefresh {
+    pub opened: u64,
+    pub resolved: u64,
+    pub reopened: u64,
+    /// Записи обслуживания, поднятые на следующую ступень эскалации
+    pub escalated: u64,
+}
+
+// ==================== REFRESH ====================
+
+/// Синхронизировать таблицу alerts с текущими условиями
+pub async fn refresh_alerts(pool: &SqlitePool, maintenance: &MaintenanceConfig) -> ApiResult<AlertRefresh> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    // Отложенные предупреждения, срок которых вышел, снова открыты
+    let reopened = sqlx::query(
+        r#"UPDATE alerts SET status = 'open', snoozed_until = NULL, updated_at = ?
+           WHERE status = 'acknowledged' AND snoozed_until IS NOT NULL AND snoozed_until <= ?"#
+    )
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    let escalated = escalate_maintenance(&mut tx, maintenance).await?;
+    let mut result = refresh_kinds(&mut tx, &AlertKind::ALL, maintenance).await?;
+    result.reopened = reopened;
+    result.escalated = escalated;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// Пересчёт напоминаний об обслуживании после переноса даты или смены срока
+pub async fn refresh_maintenance_alerts(pool: &SqlitePool, maintenance: &MaintenanceConfig) -> ApiResult<AlertRefresh> {
+    let mut tx = pool.begin().await?;
+    let escalated = escalate_maintenance(&mut tx, maintenance).await?;
+    let mut result = refresh_kinds(&mut tx, &AlertKind::MAINTENANCE, maintenance).await?;
+    result.escalated = escalated;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// Открыть предупреждения по только что записанным аномалиям расхода (stock_anomalies::track)
+pub async fn refresh_stock_anomaly_alerts(pool: &SqlitePool, maintenance: &MaintenanceConfig) -> ApiResult<AlertRefresh> {
+    let mut tx = pool.begin().await?;
+    let result = refresh_kinds(&mut tx, &[AlertKind::StockAnomaly], maintenance).await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+/// Ступени эскалации обслуживания, просроченного по SLA: спустя sla_days после scheduled_date
+/// запись получает ступень 1 (письмо ответственному, без него - автору), ещё через
+/// sla_days - ступень 2 (письмо и администраторам). Письма уходят через outbox событием
+/// `maintenance_escalated`. Завершённая, отменённая или перенесённая запись возвращается
+/// на ступень 0 - цепочка начнётся заново, если запись снова просрочат.
+/// Возвращает число записей, поднятых на новую ступень.
+async fn escalate_maintenance(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    maintenance: &MaintenanceConfig,
+) -> ApiResult<u64> {
+    let now = Utc::now();
+    let days = maintenance.days_overdue_sql("m");
+    let level = format!(
+        "CASE WHEN m.status IN ('scheduled', 'in_progress') AND e.status != 'retired' \
+              AND e.pending_deletion_id IS NULL AND {days} > 0 \
+         THEN CASE WHEN {days} > {sla} THEN 2 ELSE 1 END ELSE 0 END",
+        days = days, sla = maintenance.sla_days_sql("m")
+    );
+    let changed: Vec<(String, String, i64, i64, i64, Option<String>)> = sqlx::query_as(&format!(
+        r#"SELECT m.id, m.equipment_id, m.escalation_level, {level}, {days},
+                  (SELECT u.email FROM users u WHERE u.id = COALESCE(m.assigned_to, m.created_by) AND u.is_active = 1)
+           FROM equipment_maintenance m
+           JOIN equipment e ON e.id = m.equipment_id
+           WHERE m.escalation_level != {level}"#,
+        level = level, days = days
+    ))
+        .fetch_all(&mut **tx)
+        .await?;
+    if changed.is_empty() {
+        return Ok(0);
+    }
+
+    let admins: Vec<String> = sqlx::query_scalar("SELECT email FROM users WHERE role = 'admin' AND is_active = 1 ORDER BY username")
+        .fetch_all(&mut **tx)
+        .await?;
+    let mut escalated = 0;
+    for (maintenance_id, equipment_id, previous, level, days_overdue, assignee_email) in changed {
+        sqlx::query(
+            "UPDATE equipment_maintenance SET escalation_level = ?, escalated_at = ? WHERE id = ?"
+        )
+            .bind(level)
+            .bind(if level > 0 { Some(now) } else { None })
+            .bind(&maintenance_id)
+            .execute(&mut **tx)
+            .await?;
+        // Снижение ступени (увеличили SLA) повторно не уведомляет
+        if level <= previous {
+            continue;
+        }
+        let mut notify: Vec<String> = assignee_email.into_iter().collect();
+        if level >= 2 {
+            for email in &admins {
+                if !notify.contains(email) {
+                    notify.push(email.clone());
+                }
+            }
+        }
+        log::info!("Maintenance {} is {} day(s) past its SLA: escalation level {}", maintenance_id, days_overdue, level);
+        crate::outbox::enqueue(&mut **tx, &BusinessEvent::MaintenanceEscalated {
+            maintenance_id,
+            equipment_id,
+            level,
+            days_overdue,
+            notify,
+        }, None).await?;
+        escalated += 1;
+    }
+    Ok(escalated)
+}
+
+async fn refresh_kinds(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    kinds: &[AlertKind],
+    maintenance: &MaintenanceConfig,
+) -> ApiResult<AlertRefresh> {
+    let now = Utc::now();
+    let mut result = AlertRefresh::default();
+
+    for kind in kinds {
+        let sql = kind.condition_sql(maintenance);
+        let mut query = sqlx::query_as::<_, (String, String, Option<String>)>(&sql);
+        if let Some(param) = kind.condition_param() {
+            query = query.bind(param);
+        }
+        let current = query.fetch_all(&mut **tx).await?;
+
+        for (entity_id, message, context) in &current {
+            // Уже открытое (или подтверждённое) предупреждение по условию не дублируется
+            let inserted = sqlx::query(
+                r#"INSERT OR IGNORE INTO alerts (id, kind, entity_type, entity_id, message, context, status, created_at, updated_at)
+                   VALUES (?, ?, ?, ?, ?, ?, 'open', ?, ?)"#
+            )
+                .bind(Uuid::new_v4().to_string())
+                .bind(kind.as_str())
+                .bind(kind.entity_type())
+                .bind(entity_id)
+                .bind(message)
+                .bind(context)
+                .bind(now)
+                .bind(now)
+                .execute(&mut **tx)
+                .await?
+                .rows_affected();
+            result.opened += inserted;
+            if inserted == 0 {
+                // Дата или срок напоминания изменились - текст и контекст обновляются, статус нет
+                sqlx::query(
+                    r#"UPDATE alerts SET message = ?, context = ?, updated_at = ?
+                       WHERE kind = ? AND entity_id = ? AND status != 'resolved'
+                         AND (message != ? OR context IS NOT ?)"#
+                )
+                    .bind(message)
+                    .bind(context)
+                    .bind(now)
+                    .bind(kind.as_str())
+                    .bind(entity_id)
+                    .bind(message)
+                    .bind(context)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+
+        let active: HashSet<&str> = current.iter().map(|(id, _, _)| id.as_str()).collect();
+        let unresolved: Vec<(String, String)> = sqlx::query_as(
+            "SELECT id, entity_id FROM alerts WHERE kind = ? AND status != 'resolved'"
+        )
+            .bind(kind.as_str())
+            .fetch_all(&mut **tx)
+            .await?;
+        for (id, entity_id) in unresolved.iter().filter(|(_, entity_id)| !active.contains(entity_id.as_str())) {
+            log::debug!("Alert {} ({} {}) resolved: condition cleared", id, kind.as_str(), entity_id);
+            result.resolved += sqlx::query(
+                "UPDATE alerts SET status = 'resolved', resolved_at = ?, updated_at = ? WHERE id = ?"
+            )
+                .bind(now)
+                .bind(now)
+                .bind(id)
+                .execute(&mut **tx)
+                .await?
+                .rows_affected();
+        }
+    }
+
+    Ok(result)
+}
+
+// ==================== LIST ====================
+
+/// GET /alerts?status=open&kind=low_stock
+pub async fn get_alerts(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<AlertListQuery>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+
+    if let Some(status) = query.status.as_deref() {
+        if !ALERT_STATUSES.contains(&status) {
+            return Err(ApiError::bad_request(&format!(
+                "Invalid status '{}', allowed: {}", status, ALERT_STATUSES.join(", ")
+            )));
+        }
+    }
+    if let Some(kind) = query.kind.as_deref() {
+        if !AlertKind::ALL.iter().any(|k| k.as_str() == kind) {
+            let allowed: Vec<_> = AlertKind::ALL.iter().map(|k| k.as_str()).collect();
+            return Err(ApiError::bad_request(&format!(
+                "Invalid kind '{}', allowed: {}", kind, allowed.join(", ")
+            )));
+        }
+    }
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limits = app_state.page_limits(PageGroup::Other);
+    let per_page = limits.per_page(query.per_page);
+    let filter = "WHERE (?1 IS NULL OR a.status = ?1) AND (?2 IS NULL OR a.kind = ?2)";
+
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM alerts a {}", filter))
+        .bind(query.status.as_deref())
+        .bind(query.kind.as_deref())
+        .fetch_one(pool)
+        .await?;
+    let data: Vec<Alert> = sqlx::query_as(&format!(
+        "{} {} ORDER BY a.created_at DESC, a.id LIMIT ?3 OFFSET ?4", ALERT_SELECT, filter
+    ))
+        .bind(query.status.as_deref())
+        .bind(query.kind.as_deref())
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
+        data,
+        total,
+        page,
+        per_page,
+        total_pages: (total + per_page - 1) / per_page,
+        limits,
+        capped: limits.is_capped(query.per_page),
+    })))
+}
+
+// ==================== ACKNOWLEDGE ====================
+
+fn parse_snooze_until(value: &str) -> ApiResult<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(chrono::NaiveTime::MIN).and_utc());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| ApiError::bad_request(&format!("Invalid snooze_until '{}': use YYYY-MM-DD or RFC 3339", value)))
+}
+
+async fn fetch_alert(pool: &SqlitePool, id: &str) -> ApiResult<Option<Alert>> {
+    Ok(sqlx::query_as(&format!("{} WHERE a.id = ?", ALERT_SELECT))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?)
+}
+
+/// POST /alerts/{id}/acknowledge - повторное подтверждение обновляет заметку и срок
+pub async fn acknowledge_alert(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: Option<web::Json<AcknowledgeAlertRequest>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let body = body.map(|b| b.into_inner()).unwrap_or_default();
+    body.validate()?;
+    let id = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    let now = Utc::now();
+    let snooze_until = body.snooze_until.as_deref().map(parse_snooze_until).transpose()?;
+    if snooze_until.is_some_and(|until| until <= now) {
+        return Err(ApiError::bad_request("snooze_until must be in the future"));
+    }
+    let note = body.note.as_deref().map(str::trim).filter(|n| !n.is_empty());
+
+    let result = sqlx::query(
+        r#"UPDATE alerts
+           SET status = 'acknowledged', note = ?, snoozed_until = ?, acknowledged_by = ?,
+               acknowledged_at = ?, updated_at = ?
+           WHERE id = ? AND status != 'resolved'"#
+    )
+        .bind(note)
+        .bind(snooze_until)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .bind(&id)
+        .execute(pool)
+        .await?;
+
+    let alert = fetch_alert(pool, &id).await?.ok_or_else(|| ApiError::not_found("Alert"))?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::Conflict {
+            code: ALERT_ALREADY_RESOLVED,
+            message: "Alert is already resolved".to_string(),
+        });
+    }
+
+    let description = match snooze_until {
+        Some(until) => format!("Acknowledged alert \"{}\", snoozed until {}", alert.message, until.to_rfc3339()),
+        None => format!("Acknowledged alert \"{}\"", alert.message),
+    };
+    crate::audit::audit(pool, &claims.sub, "acknowledge_alert", "alert", &id, &description, &http_request).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(alert, "Alert acknowledged".to_string())))
+}
+
+// ==================== DIGEST ====================
+
+/// Открытые (не подтверждённые и не отложенные) предупреждения для сводки
+pub async fn digest_alerts(pool: &SqlitePool) -> ApiResult<Vec<Alert>> {
+    Ok(sqlx::query_as(&format!(
+        "{} WHERE a.status = 'open' AND (a.snoozed_until IS NULL OR a.snoozed_until <= ?) ORDER BY a.kind, a.created_at",
+        ALERT_SELECT
+    ))
+        .bind(Utc::now())
+        .fetch_all(pool)
+        .await?)
+}
+
+fn digest_body(alerts: &[Alert]) -> String {
+    let mut body = format!("{} open alert(s):\r\n", alerts.len());
+    for kind in AlertKind::ALL {
+        let lines: Vec<_> = alerts.iter().filter(|a| a.kind == kind.as_str()).collect();
+        if lines.is_empty() {
+            continue;
+        }
+        body.push_str(&format!("\r\n{} ({}):\r\n", kind.as_str(), lines.len()));
+        for alert in lines {
+            body.push_str(&format!("- {} [{}]\r\n", alert.message, alert.id));
+        }
+    }
+    body.push_str("\r\nAcknowledge an alert via POST /api/v1/alerts/{id}/acknowledge to leave it out of the digest.\r\n");
+    body
+}
+
+/// Ежедневная сводка пользователям, которые могут подтверждать предупреждения.
+/// Возвращает число предупреждений в письме (0 - письмо не отправлялось).
+pub async fn send_alert_digest(pool: &SqlitePool, smtp: &SmtpConfig, maintenance: &MaintenanceConfig) -> ApiResult<usize> {
+    refresh_alerts(pool, maintenance).await?;
+    let alerts = digest_alerts(pool).await?;
+    if alerts.is_empty() || !smtp.is_enabled() {
+        return Ok(0);
+    }
+
+    let recipients = access_control::users_with_permission(pool, Resource::Dashboard, Action::Edit, &UserRole::Researcher).await?;
+    if recipients.is_empty() {
+        log::warn!("Alert digest has no recipients: {} open alert(s)", alerts.len());
+        return Ok(0);
+    }
+    let email = Email {
+        to: recipients.into_iter().map(|(_, email)| email).collect(),
+        subject: format!("LIMS alerts: {} open", alerts.len()),
+        body: digest_body(&alerts),
+        attachments: Vec::new(),
+    };
+    mailer::send(smtp, email).await.map_err(ApiError::InternalServerError)?;
+    Ok(alerts.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixtures::RESEARCHER_ID;
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    /// Стенд с партиями на грани остатка и срока и оборудованием с просроченным обслуживанием
+    async fn setup() -> TestApp {
+        let app = TestApp::new().await;
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at)
+             VALUES ('r-1', 'Acetone', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, status, expiry_date, received_date, created_at, updated_at) VALUES
+             ('b-low', 'r-1', 'LOW-1', 5, 100, 'ml', 'available', datetime('now', '+300 days'), datetime('now'), datetime('now'), datetime('now')),
+             ('b-exp', 'r-1', 'EXP-1', 90, 100, 'ml', 'available', datetime('now', '+3 days'), datetime('now'), datetime('now'), datetime('now')),
+             ('b-ok', 'r-1', 'OK-1', 90, 100, 'ml', 'available', datetime('now', '+300 days'), datetime('now'), datetime('now'), datetime('now'))",
+            "INSERT INTO equipment (id, name, type_, quantity, status, next_maintenance, created_at, updated_at) VALUES
+             ('eq-1', 'Centrifuge', 'equipment', 1, 'available', date('now', '-2 days'), datetime('now'), datetime('now'))",
+            "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, status, scheduled_date, created_at, updated_at)
+             VALUES ('m-1', 'eq-1', 'calibration', 'scheduled', date('now', '+5 days'), datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&app.pool).await.unwrap();
+        }
+        app
+    }
+
+    async fn alert_id(pool: &SqlitePool, kind: &str, entity_id: &str) -> String {
+        sqlx::query_scalar("SELECT id FROM alerts WHERE kind = ? AND entity_id = ? AND status != 'resolved'")
+            .bind(kind)
+            .bind(entity_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_refresh_opens_one_alert_per_condition_and_resolves_cleared() {
+        let app = setup().await;
+        let pool = app.pool.clone();
+
+        let first = refresh_alerts(&pool, &MaintenanceConfig::default()).await.unwrap();
+        assert_eq!(first, AlertRefresh { opened: 4, resolved: 0, reopened: 0, escalated: 0 });
+        let mut kinds: Vec<(String, String)> = sqlx::query_as("SELECT kind, entity_id FROM alerts ORDER BY kind")
+            .fetch_all(&pool).await.unwrap();
+        kinds.sort();
+        assert_eq!(kinds, vec![
+            ("batch_expiring".to_string(), "b-exp".to_string()),
+            ("calibration_due".to_string(), "eq-1".to_string()),
+            ("low_stock".to_string(), "b-low".to_string()),
+            ("maintenance_overdue".to_string(), "eq-1".to_string()),
+        ]);
+
+        // Повторный проход ничего не дублирует
+        assert_eq!(refresh_alerts(&pool, &MaintenanceConfig::default()).await.unwrap(), AlertRefresh::default());
+
+        sqlx::query("UPDATE batches SET quantity = 80 WHERE id = 'b-low'").execute(&pool).await.unwrap();
+        sqlx::query("UPDATE equipment_maintenance SET status = 'completed' WHERE id = 'm-1'").execute(&pool).await.unwrap();
+        let cleared = refresh_alerts(&pool, &MaintenanceConfig::default()).await.unwrap();
+        assert_eq!(cleared.resolved, 2);
+        let open: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM alerts WHERE status = 'open'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(open, 2);
+
+        // Условие вернулось - новое предупреждение, закрытое остаётся в истории
+        sqlx::query("UPDATE batches SET quantity = 1 WHERE id = 'b-low'").execute(&pool).await.unwrap();
+        assert_eq!(refresh_alerts(&pool, &MaintenanceConfig::default()).await.unwrap().opened, 1);
+        let history: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM alerts WHERE kind = 'low_stock'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(history, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_acknowledged_alerts_leave_digest_until_snooze_expires() {
+        let app = setup().await;
+        let pool = app.pool.clone();
+        refresh_alerts(&pool, &MaintenanceConfig::default()).await.unwrap();
+        let low = alert_id(&pool, "low_stock", "b-low").await;
+        let expiring = alert_id(&pool, "batch_expiring", "b-exp").await;
+
+        let acknowledge = |id: &str| format!("/alerts/{}/acknowledge", id);
+
+        let (status, body) = app.post(UserRole::Researcher, &acknowledge(&low), json!({ "note": "Replacement ordered" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["acknowledged_by"], RESEARCHER_ID);
+        let (status, body) = app.post(UserRole::Researcher, &acknowledge(&expiring), json!({ "snooze_until": "2099-01-01" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let digest: Vec<String> = digest_alerts(&pool).await.unwrap().into_iter().map(|a| a.kind).collect();
+        assert_eq!(digest, vec!["calibration_due", "maintenance_overdue"]);
+        assert!(digest_body(&digest_alerts(&pool).await.unwrap()).contains("Calibration of Centrifuge is due on"));
+
+        // Срок откладывания вышел - предупреждение снова открыто
+        sqlx::query("UPDATE alerts SET snoozed_until = datetime('now', '-1 minute') WHERE id = ?")
+            .bind(&expiring).execute(&pool).await.unwrap();
+        assert_eq!(refresh_alerts(&pool, &MaintenanceConfig::default()).await.unwrap().reopened, 1);
+        assert_eq!(digest_alerts(&pool).await.unwrap().len(), 3);
+
+        let (status, _) = app.post(UserRole::Researcher, &acknowledge(&expiring), json!({ "snooze_until": "2001-01-01" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        sqlx::query("UPDATE batches SET quantity = 80 WHERE id = 'b-low'").execute(&pool).await.unwrap();
+        refresh_alerts(&pool, &MaintenanceConfig::default()).await.unwrap();
+        let (status, body) = app.post(UserRole::Researcher, &acknowledge(&low), json!({})).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+        assert_eq!(body["code"], ALERT_ALREADY_RESOLVED);
+    }
+}
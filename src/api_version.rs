@@ -0,0 +1,400 @@
+// src/api_version.rs
+//! Версии формы ответов API и их согласование.
+//!
+//! Клиент выбирает версию заголовком `X-API-Version: 1` (по умолчанию - последняя).
+//! Middleware `ApiVersionHeaders` проверяет заголовок, кладёт `ApiVersion` в extensions
+//! запроса и отвечает `X-API-Version`; для версии, назначенной к удалению, добавляет
+//! `Deprecation`, `Sunset` и `Link` на GET /api/v1/version.
+//!
+//! Путь /api/v1 от версии не зависит: версия меняет только форму отдельных ответов.
+//! Обработчики берут `ApiVersion` экстрактором и сериализуют партии и оборудование
+//! через `ApiVersion::render*` - сам список изменений живёт в `VERSIONS`.
+
+use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+
+pub const API_VERSION_HEADER: &str = "x-api-version";
+
+/// Устаревшие имена полей, использованные в запросе: `type_; use="type"`
+pub const DEPRECATED_FIELDS_HEADER: &str = "x-deprecated-fields";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+/// Запись реестра версий (он же пользовательский changelog)
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    /// Дата объявления устаревшей (заголовок `Deprecation`)
+    pub deprecated_on: Option<&'static str>,
+    /// Дата удаления (заголовок `Sunset`)
+    pub sunset_on: Option<&'static str>,
+    pub changes: &'static [&'static str],
+}
+
+/// Реестр версий по возрастанию; порядок совпадает с `ApiVersion::ALL`
+pub const VERSIONS: &[VersionInfo] = &[
+    VersionInfo {
+        version: "1",
+        deprecated_on: Some("2026-10-17"),
+        sunset_on: Some("2027-04-30"),
+        changes: &[
+            "Batch quantities (quantity, original_quantity, reserved_quantity, unit, pack_size, pack_count, converted_*, unplaced_quantity) are top-level fields",
+            "Equipment type is returned as `type_`, quantity and unit are top-level fields",
+        ],
+    },
+    VersionInfo {
+        version: "2",
+        deprecated_on: None,
+        sunset_on: None,
+        changes: &[
+            "Batch responses group stock fields under `stock`",
+            "Equipment responses rename `type_` to `equipment_type` and group quantity and unit under `stock`",
+        ],
+    },
+];
+
+/// Поля остатка партии, которые v2 переносит в `stock`
+const BATCH_STOCK_FIELDS: &[&str] = &[
+    "quantity", "original_quantity", "reserved_quantity", "unit", "pack_size", "pack_count",
+    "container_count", "open_containers",
+    "converted_quantity", "converted_unit", "original_unit", "unplaced_quantity",
+];
+
+/// Поля остатка оборудования, которые v2 переносит в `stock`
+const EQUIPMENT_STOCK_FIELDS: &[&str] = &["quantity", "unit"];
+
+/// Объекты, форма которых зависит от версии
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    Batch,
+    Equipment,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+    pub const LATEST: ApiVersion = ApiVersion::V2;
+
+    pub fn as_str(&self) -> &'static str {
+        self.info().version
+    }
+
+    pub fn info(&self) -> &'static VersionInfo {
+        &VERSIONS[*self as usize]
+    }
+
+    /// Значение заголовка: `2`, `v2` или `latest`; без заголовка - последняя версия
+    pub fn from_header(value: Option<&str>) -> Result<Self, String> {
+        let raw = match value.map(str::trim) {
+            None | Some("") => return Ok(Self::LATEST),
+            Some(raw) => raw,
+        };
+        if raw.eq_ignore_ascii_case("latest") {
+            return Ok(Self::LATEST);
+        }
+        let number = raw.strip_prefix(['v', 'V']).unwrap_or(raw);
+        Self::ALL
+            .into_iter()
+            .find(|v| v.as_str() == number)
+            .ok_or_else(|| format!(
+                "Unsupported API version '{}'. Supported versions: {}",
+                raw,
+                Self::ALL.map(|v| v.as_str()).join(", ")
+            ))
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        self.info().deprecated_on.is_some()
+    }
+
+    /// Сериализовать объект в форме этой версии
+    pub fn render<T: Serialize>(self, representation: Representation, item: &T) -> ApiResult<Value> {
+        let mut value = serde_json::to_value(item)
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to serialize response: {}", e)))?;
+        if let Some(object) = value.as_object_mut() {
+            self.reshape(representation, object);
+        }
+        Ok(value)
+    }
+
+    pub fn render_all<T: Serialize>(self, representation: Representation, items: &[T]) -> ApiResult<Vec<Value>> {
+        items.iter().map(|item| self.render(representation, item)).collect()
+    }
+
+    /// Привести уже сериализованный объект (например, строку `?fields=`) к форме версии;
+    /// отсутствующие поля пропускаются. Тип оборудования приходит как `type` (модель)
+    /// или `type_` (колонка в строке проекции).
+    pub fn reshape(self, representation: Representation, object: &mut Map<String, Value>) {
+        match (self, representation) {
+            (ApiVersion::V1, Representation::Batch) => {}
+            (ApiVersion::V1, Representation::Equipment) => rename_field(object, &["type"], "type_"),
+            (ApiVersion::V2, Representation::Batch) => nest_fields(object, BATCH_STOCK_FIELDS),
+            (ApiVersion::V2, Representation::Equipment) => {
+                rename_field(object, &["type", "type_"], "equipment_type");
+                nest_fields(object, EQUIPMENT_STOCK_FIELDS);
+            }
+        }
+    }
+}
+
+fn rename_field(object: &mut Map<String, Value>, from: &[&str], to: &str) {
+    for name in from {
+        if let Some(value) = object.remove(*name) {
+            object.insert(to.to_string(), value);
+        }
+    }
+}
+
+/// Отметить в ответе устаревшие имена полей из запроса (пары «старое, новое»)
+pub fn mark_deprecated_fields(response: &mut HttpResponse, renamed: &[(&str, &str)]) {
+    if renamed.is_empty() {
+        return;
+    }
+    let value = renamed
+        .iter()
+        .map(|(old, new)| format!("{}; use=\"{}\"", old, new))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        response.headers_mut().insert(HeaderName::from_static(DEPRECATED_FIELDS_HEADER), value);
+    }
+}
+
+fn nest_fields(object: &mut Map<String, Value>, fields: &[&str]) {
+    let stock: Map<String, Value> = fields
+        .iter()
+        .filter_map(|field| object.remove(*field).map(|v| (field.to_string(), v)))
+        .collect();
+    if !stock.is_empty() {
+        object.insert("stock".to_string(), Value::Object(stock));
+    }
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// Заголовки ответа для версии: сама версия и, для устаревшей, Deprecation/Sunset/Link
+pub fn response_headers(version: ApiVersion) -> Vec<(HeaderName, String)> {
+    let mut headers = vec![(HeaderName::from_static(API_VERSION_HEADER), version.as_str().to_string())];
+    let info = version.info();
+    // RFC 9745: `Deprecation: @<unix time>`
+    if let Some(deprecated) = info.deprecated_on.and_then(parse_date) {
+        let timestamp = deprecated.and_hms_opt(0, 0, 0).map(|d| d.and_utc().timestamp()).unwrap_or_default();
+        headers.push((HeaderName::from_static("deprecation"), format!("@{}", timestamp)));
+        headers.push((
+            HeaderName::from_static("link"),
+            format!("<{}/version>; rel=\"deprecation\"", crate::access_control::API_PREFIX),
+        ));
+    }
+    // RFC 8594: `Sunset: <HTTP-date>`
+    if let Some(sunset) = info.sunset_on.and_then(parse_date) {
+        headers.push((HeaderName::from_static("sunset"), sunset.format("%a, %d %b %Y 00:00:00 GMT").to_string()));
+    }
+    headers
+}
+
+// ==================== EXTRACTOR ====================
+
+impl FromRequest for ApiVersion {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        if let Some(version) = req.extensions().get::<ApiVersion>() {
+            return ready(Ok(*version));
+        }
+        let header = req.headers().get(API_VERSION_HEADER).and_then(|v| v.to_str().ok());
+        ready(ApiVersion::from_header(header).map_err(ApiError::BadRequest))
+    }
+}
+
+// ==================== MIDDLEWARE ====================
+
+pub struct ApiVersionHeaders;
+
+impl<S, B> Transform<S, ServiceRequest> for ApiVersionHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = ApiVersionHeadersMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiVersionHeadersMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct ApiVersionHeadersMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiVersionHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, ctx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let header = req.headers().get(API_VERSION_HEADER).and_then(|v| v.to_str().ok());
+            let version = ApiVersion::from_header(header).map_err(ApiError::BadRequest)?;
+            req.extensions_mut().insert(version);
+
+            let mut response = service.call(req).await?;
+            for (name, value) in response_headers(version) {
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    response.headers_mut().insert(name, value);
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+// ==================== ENDPOINT ====================
+
+#[derive(Debug, Serialize)]
+struct VersionsResponse {
+    /// Версия, применённая к этому запросу
+    requested: &'static str,
+    latest: &'static str,
+    header: &'static str,
+    versions: Vec<VersionStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct VersionStatus {
+    #[serde(flatten)]
+    info: &'static VersionInfo,
+    status: &'static str,
+}
+
+/// GET /api/v1/version - поддерживаемые версии, даты устаревания и изменения
+pub async fn get_versions(version: ApiVersion) -> ApiResult<HttpResponse> {
+    let versions = ApiVersion::ALL
+        .iter()
+        .map(|v| VersionStatus {
+            info: v.info(),
+            status: if v.is_deprecated() { "deprecated" } else if *v == ApiVersion::LATEST { "current" } else { "supported" },
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(VersionsResponse {
+        requested: version.as_str(),
+        latest: ApiVersion::LATEST.as_str(),
+        header: "X-API-Version",
+        versions,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, web, App};
+
+    #[test]
+    fn test_version_header_parsing() {
+        assert_eq!(ApiVersion::from_header(None), Ok(ApiVersion::LATEST));
+        assert_eq!(ApiVersion::from_header(Some(" ")), Ok(ApiVersion::LATEST));
+        assert_eq!(ApiVersion::from_header(Some("latest")), Ok(ApiVersion::LATEST));
+        assert_eq!(ApiVersion::from_header(Some("1")), Ok(ApiVersion::V1));
+        assert_eq!(ApiVersion::from_header(Some("v2")), Ok(ApiVersion::V2));
+        assert!(ApiVersion::from_header(Some("3")).unwrap_err().contains("Supported versions: 1, 2"));
+
+        assert_eq!(VERSIONS.len(), ApiVersion::ALL.len());
+        for version in ApiVersion::ALL {
+            let info = version.info();
+            assert!(info.deprecated_on.is_none_or(|d| parse_date(d).is_some()));
+            assert!(info.sunset_on.is_none_or(|d| parse_date(d).is_some()));
+        }
+        assert!(!ApiVersion::LATEST.is_deprecated());
+    }
+
+    #[test]
+    fn test_reshape_per_version() {
+        let mut batch = serde_json::json!({ "id": "b1", "quantity": 5.0, "unit": "mL", "status": "available" });
+        let object = batch.as_object_mut().unwrap();
+        ApiVersion::V1.reshape(Representation::Batch, object);
+        assert_eq!(batch["quantity"], 5.0);
+
+        let object = batch.as_object_mut().unwrap();
+        ApiVersion::V2.reshape(Representation::Batch, object);
+        assert_eq!(batch, serde_json::json!({ "id": "b1", "status": "available", "stock": { "quantity": 5.0, "unit": "mL" } }));
+
+        // Строка проекции без полей остатка остаётся без `stock`
+        let mut row = Map::new();
+        row.insert("type_".to_string(), Value::from("labware"));
+        ApiVersion::V2.reshape(Representation::Equipment, &mut row);
+        assert_eq!(Value::Object(row), serde_json::json!({ "equipment_type": "labware" }));
+
+        // Модель отдаёт `type`, v1 сохраняет прежнее `type_`
+        let mut equipment = serde_json::json!({ "type": "labware", "quantity": 2 });
+        ApiVersion::V1.reshape(Representation::Equipment, equipment.as_object_mut().unwrap());
+        assert_eq!(equipment, serde_json::json!({ "type_": "labware", "quantity": 2 }));
+    }
+
+    #[actix_web::test]
+    async fn test_middleware_negotiates_version_and_flags_deprecation() {
+        let app = actix_test::init_service(
+            App::new().service(
+                web::scope("/api/v1")
+                    .wrap(ApiVersionHeaders)
+                    .route("/version", web::get().to(get_versions)),
+            ),
+        ).await;
+
+        let response = actix_test::call_service(&app, actix_test::TestRequest::get().uri("/api/v1/version").to_request()).await;
+        assert_eq!(response.headers().get(API_VERSION_HEADER).unwrap(), "2");
+        assert!(response.headers().get("deprecation").is_none());
+        let json: Value = actix_test::read_body_json(response).await;
+        assert_eq!(json["data"]["requested"], "2");
+        assert_eq!(json["data"]["versions"][0]["status"], "deprecated");
+        assert_eq!(json["data"]["versions"][0]["sunset_on"], "2027-04-30");
+        assert_eq!(json["data"]["versions"][1]["status"], "current");
+
+        let response = actix_test::call_service(
+            &app,
+            actix_test::TestRequest::get().uri("/api/v1/version").insert_header(("X-API-Version", "1")).to_request(),
+        ).await;
+        assert_eq!(response.headers().get(API_VERSION_HEADER).unwrap(), "1");
+        assert_eq!(response.headers().get("deprecation").unwrap(), "@1792195200");
+        assert_eq!(response.headers().get("sunset").unwrap(), "Fri, 30 Apr 2027 00:00:00 GMT");
+        assert_eq!(response.headers().get("link").unwrap(), "</api/v1/version>; rel=\"deprecation\"");
+        let json: Value = actix_test::read_body_json(response).await;
+        assert_eq!(json["data"]["requested"], "1");
+
+        let request = actix_test::TestRequest::get().uri("/api/v1/version").insert_header(("X-API-Version", "9")).to_request();
+        let status = match actix_test::try_call_service(&app, request).await {
+            Ok(response) => response.status(),
+            Err(e) => e.error_response().status(),
+        };
+        assert_eq!(status, actix_web::http::StatusCode::BAD_REQUEST);
+    }
+}
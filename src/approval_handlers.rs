@@ -0,0 +1,739 @@
+// src/approval_handlers.rs
+//! Согласование крупного расхода реагентов.
+//!
+//! У реагента может быть задан порог `approval_threshold` в единице `approval_threshold_unit`.
+//! Списание из партии (`use_reagent`) и резерв для эксперимента (`add_reagent_to_experiment`)
+//! больше порога не выполняются сразу: создаётся заявка в `consumption_approvals` (ответ 202),
+//! а пользователи с правом Approve на партии получают уведомление (журнал аудита и email).
+//!
+//! `POST /approvals/{id}/approve` выполняет исходную операцию в одной транзакции со сменой
+//! статуса заявки: повторное или параллельное согласование получает 409 и ничего не списывает.
+//! `reject` заявку отбрасывает. Заявки, не обработанные за `approval_expiry_days` дней, истекают.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::access_control::{self, Action, Resource};
+use crate::auth::{get_current_user, UserRole};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::{ApiResponse, PaginatedResponse};
+use crate::pagination::PageGroup;
+use crate::mailer::{self, Email};
+use crate::models::{Batch, Reagent};
+use crate::validator::UnitConverter;
+use crate::AppState;
+
+/// Код ошибки 409 для уже обработанной заявки
+pub const APPROVAL_ALREADY_RESOLVED: &str = "APPROVAL_ALREADY_RESOLVED";
+
+pub const APPROVAL_STATUSES: &[&str] = &["pending", "approved", "rejected", "expired"];
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalOperation {
+    /// Списание из партии (use_reagent)
+    BatchUse,
+    /// Резерв партии для эксперимента (add_reagent_to_experiment)
+    ExperimentReagent,
+}
+
+impl ApprovalOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApprovalOperation::BatchUse => "batch_use",
+            ApprovalOperation::ExperimentReagent => "experiment_reagent",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "batch_use" => Some(ApprovalOperation::BatchUse),
+            "experiment_reagent" => Some(ApprovalOperation::ExperimentReagent),
+            _ => None,
+        }
+    }
+}
+
+/// Операция, отложенная до согласования
+#[derive(Debug, Clone, Copy)]
+pub struct NewApproval<'a> {
+    pub operation: ApprovalOperation,
+    pub reagent_id: &'a str,
+    pub batch_id: &'a str,
+    pub experiment_id: Option<&'a str>,
+    /// Количество в единице партии
+    pub quantity: f64,
+    pub unit: &'a str,
+    pub purpose: Option<&'a str>,
+    pub notes: Option<&'a str>,
+    pub requested_by: &'a str,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ConsumptionApproval {
+    pub id: String,
+    pub operation: String,
+    pub reagent_id: String,
+    pub reagent_name: Option<String>,
+    pub batch_id: String,
+    pub batch_number: Option<String>,
+    pub experiment_id: Option<String>,
+    pub quantity: f64,
+    pub unit: String,
+    pub purpose: Option<String>,
+    pub notes: Option<String>,
+    pub status: String,
+    pub requested_by: String,
+    pub requested_by_username: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub resolved_by: Option<String>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolution_note: Option<String>,
+    /// usage_logs.id (batch_use) или experiment_reagents.id (experiment_reagent) после согласования
+    pub result_id: Option<String>,
+}
+
+const APPROVAL_SELECT: &str = r#"SELECT a.id, a.operation, a.reagent_id, r.name AS reagent_name,
+           a.batch_id, b.batch_number, a.experiment_id, a.quantity, a.unit, a.purpose, a.notes,
+           a.status, a.requested_by, u.username AS requested_by_username, a.requested_at, a.expires_at,
+           a.resolved_by, a.resolved_at, a.resolution_note, a.result_id
+       FROM consumption_approvals a
+       LEFT JOIN reagents r ON r.id = a.reagent_id
+       LEFT JOIN batches b ON b.id = a.batch_id
+       LEFT JOIN users u ON u.id = a.requested_by"#;
+
+#[derive(Debug, Deserialize)]
+pub struct ApprovalListQuery {
+    pub status: Option<String>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct ResolveApprovalRequest {
+    #[validate(length(max = 1000, message = "Note cannot exceed 1000 characters"))]
+    pub note: Option<String>,
+}
+
+// ==================== THRESHOLD ====================
+
+/// Превышает ли расход порог согласования реагента. Количество, которое нельзя
+/// пересчитать в единицу порога (например, `pcs` против `g`), считается превышающим.
+pub fn exceeds_approval_threshold(reagent: &Reagent, quantity: f64, unit: &str) -> bool {
+    let (Some(threshold), Some(threshold_unit)) = (reagent.approval_threshold, reagent.approval_threshold_unit.as_deref()) else {
+        return false;
+    };
+    if unit == threshold_unit {
+        return quantity > threshold;
+    }
+    UnitConverter::new()
+        .convert(quantity, unit, threshold_unit)
+        .map_or(true, |converted| converted > threshold)
+}
+
+fn approval_expiry() -> Duration {
+    Duration::days(crate::settings::settings().get_i64(crate::settings::APPROVAL_EXPIRY_DAYS).max(1))
+}
+
+async fn fetch_approval(pool: &SqlitePool, id: &str) -> ApiResult<Option<ConsumptionApproval>> {
+    Ok(sqlx::query_as(&format!("{} WHERE a.id = ?", APPROVAL_SELECT))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?)
+}
+
+// ==================== REQUEST ====================
+
+/// Создать заявку вместо выполнения операции и уведомить согласующих (ответ 202).
+/// Повтор того же запроса, пока заявка ждёт решения, возвращает существующую заявку.
+pub async fn request_approval(
+    app_state: &AppState,
+    approval: NewApproval<'_>,
+    http_request: Option<&HttpRequest>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+
+    let existing: Option<String> = sqlx::query_scalar(
+        r#"SELECT id FROM consumption_approvals
+           WHERE status = 'pending' AND expires_at > ? AND operation = ? AND batch_id = ?
+             AND experiment_id IS ? AND quantity = ? AND requested_by = ?
+           LIMIT 1"#
+    )
+        .bind(Utc::now())
+        .bind(approval.operation.as_str())
+        .bind(approval.batch_id)
+        .bind(approval.experiment_id)
+        .bind(approval.quantity)
+        .bind(approval.requested_by)
+        .fetch_optional(pool)
+        .await?;
+
+    let id = match existing {
+        Some(id) => id,
+        None => {
+            let id = Uuid::new_v4().to_string();
+            let now = Utc::now();
+            sqlx::query(
+                r#"INSERT INTO consumption_approvals
+                   (id, operation, reagent_id, batch_id, experiment_id, quantity, unit, purpose, notes,
+                    status, requested_by, requested_at, expires_at)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, 'pending', ?, ?, ?)"#
+            )
+                .bind(&id)
+                .bind(approval.operation.as_str())
+                .bind(approval.reagent_id)
+                .bind(approval.batch_id)
+                .bind(approval.experiment_id)
+                .bind(approval.quantity)
+                .bind(approval.unit)
+                .bind(approval.purpose)
+                .bind(approval.notes)
+                .bind(approval.requested_by)
+                .bind(now)
+                .bind(now + approval_expiry())
+                .execute(pool)
+                .await?;
+
+            let created = fetch_approval(pool, &id).await?
+                .ok_or_else(|| ApiError::not_found("Approval"))?;
+            notify_approvers(app_state, &created, http_request).await;
+            id
+        }
+    };
+
+    let approval = fetch_approval(pool, &id).await?.ok_or_else(|| ApiError::not_found("Approval"))?;
+    let message = format!(
+        "Consumption exceeds the approval threshold; approval requested (expires {})",
+        approval.expires_at.to_rfc3339()
+    );
+    Ok(HttpResponse::Accepted().json(ApiResponse::success_with_message(approval, message)))
+}
+
+/// Запись в журнал аудита и письмо пользователям, которые могут согласовать заявку
+async fn notify_approvers(app_state: &AppState, approval: &ConsumptionApproval, http_request: Option<&HttpRequest>) {
+    let pool = &app_state.db_pool;
+    let approvers = match access_control::users_with_permission(pool, Resource::Batch, Action::Approve, &UserRole::Admin).await {
+        Ok(users) => users.into_iter().filter(|(id, _)| *id != approval.requested_by).collect::<Vec<_>>(),
+        Err(e) => {
+            log::error!("Failed to load approvers for approval {}: {}", approval.id, e);
+            Vec::new()
+        }
+    };
+
+    let summary = format!(
+        "{} {} of \"{}\" (batch {}) requested by {}",
+        approval.quantity,
+        approval.unit,
+        approval.reagent_name.as_deref().unwrap_or(&approval.reagent_id),
+        approval.batch_number.as_deref().unwrap_or(&approval.batch_id),
+        approval.requested_by_username.as_deref().unwrap_or(&approval.requested_by),
+    );
+    let description = format!("Approval requested: {}; {} approver(s) notified", summary, approvers.len());
+    if let Err(e) = crate::audit::log_activity(
+        pool, Some(&approval.requested_by), "approval_requested", "approval", Some(&approval.id),
+        Some(&description), None, http_request,
+    ).await {
+        log::error!("Failed to write audit log: {}", e);
+    }
+
+    if approvers.is_empty() {
+        log::warn!("Approval {} has no one to approve it: {}", approval.id, summary);
+        return;
+    }
+    if !app_state.config.smtp.is_enabled() {
+        return;
+    }
+    let email = Email {
+        to: approvers.into_iter().map(|(_, email)| email).collect(),
+        subject: format!("Approval requested: {} {} of {}", approval.quantity, approval.unit,
+            approval.reagent_name.as_deref().unwrap_or(&approval.reagent_id)),
+        body: format!(
+            "{}.\r\nApprove or reject it via POST /api/v1/approvals/{}/approve or /reject before {}.\r\n",
+            summary, approval.id, approval.expires_at.to_rfc3339()
+        ),
+        attachments: Vec::new(),
+    };
+    let smtp = app_state.config.smtp.clone();
+    let approval_id = approval.id.clone();
+    tokio::spawn(async move {
+        if let Err(e) = mailer::send(&smtp, email).await {
+            log::warn!("Failed to email approvers of approval {}: {}", approval_id, e);
+        }
+    });
+}
+
+// ==================== LIST ====================
+
+/// GET /approvals?status=pending - согласующие видят все заявки, остальные только свои
+pub async fn get_approvals(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ApprovalListQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+
+    if let Some(status) = query.status.as_deref() {
+        if !APPROVAL_STATUSES.contains(&status) {
+            return Err(ApiError::bad_request(&format!(
+                "Invalid status '{}', allowed: {}", status, APPROVAL_STATUSES.join(", ")
+            )));
+        }
+    }
+    expire_stale_approvals(pool).await?;
+
+    let can_approve = access_control::authorize(&claims, Resource::Batch, Action::Approve, &UserRole::Admin, pool)
+        .await
+        .is_ok();
+    let requested_by = (!can_approve).then_some(claims.sub.as_str());
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limits = app_state.page_limits(PageGroup::Other);
+    let per_page = limits.per_page(query.per_page);
+    let filter = "WHERE (?1 IS NULL OR a.status = ?1) AND (?2 IS NULL OR a.requested_by = ?2)";
+
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM consumption_approvals a {}", filter))
+        .bind(query.status.as_deref())
+        .bind(requested_by)
+        .fetch_one(pool)
+        .await?;
+    let data: Vec<ConsumptionApproval> = sqlx::query_as(&format!(
+        "{} {} ORDER BY a.requested_at DESC LIMIT ?3 OFFSET ?4", APPROVAL_SELECT, filter
+    ))
+        .bind(query.status.as_deref())
+        .bind(requested_by)
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
+        data,
+        total,
+        page,
+        per_page,
+        total_pages: (total + per_page - 1) / per_page,
+        limits,
+        capped: limits.is_capped(query.per_page),
+    })))
+}
+
+// ==================== RESOLVE ====================
+
+/// Заявка ещё ждёт решения; истёкшая по времени помечается expired
+async fn ensure_pending(pool: &SqlitePool, approval: &ConsumptionApproval) -> ApiResult<()> {
+    if approval.status == "pending" && approval.expires_at <= Utc::now() {
+        expire_stale_approvals(pool).await?;
+        return Err(already_resolved("expired"));
+    }
+    if approval.status != "pending" {
+        return Err(already_resolved(&approval.status));
+    }
+    Ok(())
+}
+
+fn already_resolved(status: &str) -> ApiError {
+    ApiError::Conflict {
+        code: APPROVAL_ALREADY_RESOLVED,
+        message: format!("Approval is already {}", status),
+    }
+}
+
+/// Атомарно перевести pending -> status; false - заявку уже обработал кто-то другой
+async fn claim_approval(
+    conn: &mut sqlx::SqliteConnection,
+    id: &str,
+    status: &str,
+    user_id: &str,
+    note: Option<&str>,
+) -> ApiResult<bool> {
+    let now = Utc::now();
+    let result = sqlx::query(
+        r#"UPDATE consumption_approvals
+           SET status = ?, resolved_by = ?, resolved_at = ?, resolution_note = ?
+           WHERE id = ? AND status = 'pending' AND expires_at > ?"#
+    )
+        .bind(status)
+        .bind(user_id)
+        .bind(now)
+        .bind(note)
+        .bind(id)
+        .bind(now)
+        .execute(&mut *conn)
+        .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// POST /approvals/{id}/approve - выполнить отложенную операцию ровно один раз
+pub async fn approve_consumption(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: Option<web::Json<ResolveApprovalRequest>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let body = body.map(|b| b.into_inner()).unwrap_or_default();
+    body.validate()?;
+    let id = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    let approval = fetch_approval(pool, &id).await?.ok_or_else(|| ApiError::not_found("Approval"))?;
+    ensure_pending(pool, &approval).await?;
+    if approval.requested_by == claims.sub {
+        return Err(ApiError::Forbidden("A consumption request cannot be approved by the user who made it".to_string()));
+    }
+    let operation = ApprovalOperation::parse(&approval.operation)
+        .ok_or_else(|| ApiError::InternalServerError(format!("Unknown approval operation '{}'", approval.operation)))?;
+
+    let mut tx = pool.begin().await?;
+    if !claim_approval(&mut tx, &id, "approved", &claims.sub, body.note.as_deref()).await? {
+        return Err(already_resolved("resolved"));
+    }
+
+    // Ошибка выполнения (например, партию уже израсходовали) откатывает и смену статуса
+    let (result_id, consumed) = match operation {
+        ApprovalOperation::BatchUse => {
+            let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ? AND reagent_id = ? AND deleted_at IS NULL")
+                .bind(&approval.batch_id)
+                .bind(&approval.reagent_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or_else(|| ApiError::batch_not_found(&approval.batch_id))?;
+            let usage = crate::handlers::record_batch_usage(
+                &mut tx,
+                &batch,
+                &approval.requested_by,
+                approval.quantity,
+                approval.purpose.as_deref(),
+                approval.notes.as_deref(),
+                None,
+            ).await?;
+            (usage.usage_id, Some(usage.remaining_quantity))
+        }
+        ApprovalOperation::ExperimentReagent => {
+            let experiment_id = approval.experiment_id.as_deref()
+                .ok_or_else(|| ApiError::InternalServerError("Experiment approval without experiment".to_string()))?;
+            let link_id = crate::experiment_handlers::reserve_experiment_reagent(
+                &mut tx,
+                experiment_id,
+                &approval.batch_id,
+                approval.quantity,
+                approval.notes.as_deref(),
+            ).await?;
+            (link_id, None)
+        }
+    };
+
+    sqlx::query("UPDATE consumption_approvals SET result_id = ? WHERE id = ?")
+        .bind(&result_id)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await?;
+    if let Some(remaining_quantity) = consumed {
+        crate::outbox::enqueue(
+            &mut *tx,
+            &crate::events::BusinessEvent::BatchConsumed {
+                reagent_id: approval.reagent_id.clone(),
+                batch_id: approval.batch_id.clone(),
+                quantity: approval.quantity,
+                unit: approval.unit.clone(),
+                remaining_quantity: remaining_quantity.max(0.0),
+            },
+            Some(&approval.requested_by),
+        ).await?;
+    }
+    tx.commit().await?;
+    // Расход записан на автора запроса - на него же и считается частота
+    if consumed.is_some() {
+        crate::stock_anomalies::track(&app_state, crate::stock_anomalies::StockOp {
+            operation: crate::stock_anomalies::StockOperation::Use,
+            batch_id: &approval.batch_id,
+            user_id: Some(&approval.requested_by),
+            quantity: approval.quantity,
+        }).await;
+    }
+    crate::audit::audit(
+        pool, &claims.sub, "approve", "approval", &id,
+        &format!(
+            "Approved {} {} of \"{}\" ({}) requested by {}",
+            approval.quantity, approval.unit,
+            approval.reagent_name.as_deref().unwrap_or(&approval.reagent_id),
+            operation.as_str(),
+            approval.requested_by_username.as_deref().unwrap_or(&approval.requested_by),
+        ),
+        &http_request,
+    ).await;
+
+    let approval = fetch_approval(pool, &id).await?.ok_or_else(|| ApiError::not_found("Approval"))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        approval,
+        "Approval granted and consumption recorded".to_string(),
+    )))
+}
+
+/// POST /approvals/{id}/reject - отбросить отложенную операцию
+pub async fn reject_consumption(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: Option<web::Json<ResolveApprovalRequest>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let body = body.map(|b| b.into_inner()).unwrap_or_default();
+    body.validate()?;
+    let id = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    let approval = fetch_approval(pool, &id).await?.ok_or_else(|| ApiError::not_found("Approval"))?;
+    ensure_pending(pool, &approval).await?;
+
+    let mut conn = pool.acquire().await?;
+    if !claim_approval(&mut conn, &id, "rejected", &claims.sub, body.note.as_deref()).await? {
+        return Err(already_resolved("resolved"));
+    }
+    drop(conn);
+
+    crate::audit::audit(
+        pool, &claims.sub, "reject", "approval", &id,
+        &format!(
+            "Rejected {} {} of \"{}\" requested by {}{}",
+            approval.quantity, approval.unit,
+            approval.reagent_name.as_deref().unwrap_or(&approval.reagent_id),
+            approval.requested_by_username.as_deref().unwrap_or(&approval.requested_by),
+            body.note.as_deref().map(|n| format!(": {}", n)).unwrap_or_default(),
+        ),
+        &http_request,
+    ).await;
+
+    let approval = fetch_approval(pool, &id).await?.ok_or_else(|| ApiError::not_found("Approval"))?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(approval, "Approval rejected".to_string())))
+}
+
+// ==================== EXPIRY ====================
+
+/// Пометить просроченные заявки expired (задача обслуживания и перед выдачей списка)
+pub async fn expire_stale_approvals(pool: &SqlitePool) -> ApiResult<u64> {
+    let now = Utc::now();
+    let result = sqlx::query(
+        "UPDATE consumption_approvals SET status = 'expired', resolved_at = ? WHERE status = 'pending' AND expires_at <= ?"
+    )
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Claims;
+    use actix_web::{test, HttpMessage};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        for (id, role) in [("u-admin", "admin"), ("u-res", "researcher")] {
+            sqlx::query(
+                "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+                 VALUES (?, ?, ?, 'x', ?, datetime('now'), datetime('now'))"
+            )
+                .bind(id).bind(id).bind(format!("{}@example.com", id)).bind(role)
+                .execute(&pool).await.unwrap();
+        }
+        for sql in [
+            "INSERT INTO reagents (id, name, status, approval_threshold, approval_threshold_unit, created_at, updated_at)
+             VALUES ('r-1', 'Acetonitrile', 'active', 1, 'l', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, status, received_date, created_at, updated_at)
+             VALUES ('b-1', 'r-1', 'B-001', 5000, 5000, 'ml', 'available', datetime('now'), datetime('now'), datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, created_by, created_at, updated_at)
+             VALUES ('e-1', 'HPLC run', datetime('now'), datetime('now'), 'planned', 'research', 'u-res', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    fn app_state(pool: &SqlitePool) -> web::Data<Arc<AppState>> {
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    fn request_as(user_id: &str, role: UserRole) -> HttpRequest {
+        let req = test::TestRequest::post().to_http_request();
+        req.extensions_mut().insert(Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{}@example.com", user_id),
+            role,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn reagent(pool: &SqlitePool) -> Reagent {
+        sqlx::query_as("SELECT * FROM reagents WHERE id = 'r-1'").fetch_one(pool).await.unwrap()
+    }
+
+    async fn request_use(state: &web::Data<Arc<AppState>>, quantity: f64) -> String {
+        let resp = request_approval(state, NewApproval {
+            operation: ApprovalOperation::BatchUse,
+            reagent_id: "r-1",
+            batch_id: "b-1",
+            experiment_id: None,
+            quantity,
+            unit: "ml",
+            purpose: Some("Mobile phase"),
+            notes: None,
+            requested_by: "u-res",
+        }, None).await.unwrap();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::ACCEPTED);
+        sqlx::query_scalar("SELECT id FROM consumption_approvals ORDER BY requested_at DESC LIMIT 1")
+            .fetch_one(&state.db_pool).await.unwrap()
+    }
+
+    async fn batch_quantity(pool: &SqlitePool) -> f64 {
+        sqlx::query_scalar("SELECT quantity FROM batches WHERE id = 'b-1'").fetch_one(pool).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_threshold_is_compared_in_threshold_unit() {
+        let pool = setup().await;
+        let reagent = reagent(&pool).await;
+
+        assert!(!exceeds_approval_threshold(&reagent, 1000.0, "ml"));
+        assert!(exceeds_approval_threshold(&reagent, 1500.0, "ml"));
+        assert!(exceeds_approval_threshold(&reagent, 2.0, "l"));
+        // Несопоставимые единицы - всегда через согласование
+        assert!(exceeds_approval_threshold(&reagent, 1.0, "pcs"));
+
+        let unrestricted = Reagent { approval_threshold: None, approval_threshold_unit: None, ..reagent };
+        assert!(!exceeds_approval_threshold(&unrestricted, 1e6, "ml"));
+    }
+
+    #[actix_web::test]
+    async fn test_approve_consumes_once_and_second_approve_conflicts() {
+        let pool = setup().await;
+        let state = app_state(&pool);
+
+        let id = request_use(&state, 2000.0).await;
+        // Повтор того же запроса не плодит заявки
+        assert_eq!(request_use(&state, 2000.0).await, id);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM consumption_approvals")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(batch_quantity(&pool).await, 5000.0);
+
+        let resp = approve_consumption(
+            state.clone(), web::Path::from(id.clone()), None, request_as("u-admin", UserRole::Admin),
+        ).await.unwrap();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(batch_quantity(&pool).await, 3000.0);
+
+        let approval = fetch_approval(&pool, &id).await.unwrap().unwrap();
+        assert_eq!(approval.status, "approved");
+        assert_eq!(approval.resolved_by.as_deref(), Some("u-admin"));
+        let logged_by: String = sqlx::query_scalar("SELECT user_id FROM usage_logs WHERE id = ?")
+            .bind(approval.result_id.as_deref().unwrap())
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(logged_by, "u-res");
+
+        let err = approve_consumption(
+            state.clone(), web::Path::from(id), None, request_as("u-admin", UserRole::Admin),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::Conflict { code: APPROVAL_ALREADY_RESOLVED, .. }));
+        assert_eq!(batch_quantity(&pool).await, 3000.0);
+    }
+
+    #[actix_web::test]
+    async fn test_requester_cannot_approve_own_request() {
+        let pool = setup().await;
+        let state = app_state(&pool);
+        let id = request_use(&state, 2000.0).await;
+
+        let err = approve_consumption(
+            state.clone(), web::Path::from(id.clone()), None, request_as("u-res", UserRole::Admin),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        assert_eq!(fetch_approval(&pool, &id).await.unwrap().unwrap().status, "pending");
+    }
+
+    #[actix_web::test]
+    async fn test_reject_and_expiry_leave_stock_untouched() {
+        let pool = setup().await;
+        let state = app_state(&pool);
+
+        let rejected = request_use(&state, 2000.0).await;
+        reject_consumption(
+            state.clone(),
+            web::Path::from(rejected.clone()),
+            Some(web::Json(ResolveApprovalRequest { note: Some("Use the opened bottle".to_string()) })),
+            request_as("u-admin", UserRole::Admin),
+        ).await.unwrap();
+        let approval = fetch_approval(&pool, &rejected).await.unwrap().unwrap();
+        assert_eq!(approval.status, "rejected");
+        assert_eq!(approval.resolution_note.as_deref(), Some("Use the opened bottle"));
+
+        let stale = request_use(&state, 3000.0).await;
+        sqlx::query("UPDATE consumption_approvals SET expires_at = ? WHERE id = ?")
+            .bind(Utc::now() - Duration::hours(1))
+            .bind(&stale)
+            .execute(&pool).await.unwrap();
+        let err = approve_consumption(
+            state.clone(), web::Path::from(stale.clone()), None, request_as("u-admin", UserRole::Admin),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::Conflict { .. }));
+        assert_eq!(fetch_approval(&pool, &stale).await.unwrap().unwrap().status, "expired");
+        assert_eq!(batch_quantity(&pool).await, 5000.0);
+    }
+
+    #[actix_web::test]
+    async fn test_approved_experiment_request_reserves_batch() {
+        let pool = setup().await;
+        let state = app_state(&pool);
+
+        request_approval(&state, NewApproval {
+            operation: ApprovalOperation::ExperimentReagent,
+            reagent_id: "r-1",
+            batch_id: "b-1",
+            experiment_id: Some("e-1"),
+            quantity: 1500.0,
+            unit: "ml",
+            purpose: None,
+            notes: None,
+            requested_by: "u-res",
+        }, None).await.unwrap();
+        let id: String = sqlx::query_scalar("SELECT id FROM consumption_approvals")
+            .fetch_one(&pool).await.unwrap();
+
+        approve_consumption(
+            state.clone(), web::Path::from(id.clone()), None, request_as("u-admin", UserRole::Admin),
+        ).await.unwrap();
+
+        let reserved: f64 = sqlx::query_scalar("SELECT reserved_quantity FROM batches WHERE id = 'b-1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(reserved, 1500.0);
+        let link: String = sqlx::query_scalar("SELECT id FROM experiment_reagents WHERE experiment_id = 'e-1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(fetch_approval(&pool, &id).await.unwrap().unwrap().result_id, Some(link));
+        assert_eq!(batch_quantity(&pool).await, 5000.0);
+    }
+}
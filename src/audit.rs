@@ -8,6 +8,8 @@ use chrono::Utc;
 use actix_web::HttpRequest;
 use serde::{Serialize, Deserialize};
 
+use crate::auth::KioskPrincipal;
+
 // ==================== CHANGE TRACKING ====================
 
 /// Single field change: old value -> new value
@@ -185,9 +187,10 @@ impl ChangeSet {
 
 // ==================== CORE AUDIT FUNCTIONS ====================
 
-/// Write an event to audit_logs (full version)
-pub async fn log_activity(
-    pool: &SqlitePool,
+/// Write an event to audit_logs (full version).
+/// Accepts a pool or a transaction, so the record can be committed together with the change.
+pub async fn log_activity<'e, E>(
+    executor: E,
     user_id: Option<&str>,
     action: &str,
     entity_type: &str,
@@ -195,22 +198,13 @@ pub async fn log_activity(
     description: Option<&str>,
     changes: Option<&str>,
     request: Option<&HttpRequest>,
-) -> Result<(), sqlx::Error> {
+) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
-
-    let ip_address = request.and_then(|req| {
-        req.connection_info()
-            .realip_remote_addr()
-            .map(|s| s.to_string())
-    });
-
-    let user_agent = request.and_then(|req| {
-        req.headers()
-            .get("User-Agent")
-            .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_string())
-    });
+    let (ip_address, user_agent) = request_origin(request);
 
     sqlx::query(
         r#"INSERT INTO audit_logs 
@@ -227,12 +221,65 @@ pub async fn log_activity(
     .bind(&ip_address)
     .bind(&user_agent)
     .bind(now)
-    .execute(pool)
+    .execute(executor)
     .await?;
 
     Ok(())
 }
 
+/// IP и User-Agent запроса для записи в журнал
+fn request_origin(request: Option<&HttpRequest>) -> (Option<String>, Option<String>) {
+    let ip_address = request.and_then(crate::client_ip::client_ip);
+
+    let user_agent = request.and_then(|req| {
+        req.headers()
+            .get("User-Agent")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    });
+
+    (ip_address, user_agent)
+}
+
+/// Action performed through a kiosk token: there is no user account, so user_id stays NULL
+/// and the typed or scanned identifier is stored in actor_identifier (via_kiosk = 1)
+#[allow(clippy::too_many_arguments)]
+pub async fn audit_kiosk(
+    pool: &SqlitePool,
+    kiosk: &KioskPrincipal,
+    actor_identifier: &str,
+    action: &str,
+    entity_type: &str,
+    entity_id: &str,
+    description: &str,
+    request: &HttpRequest,
+) {
+    let (ip_address, user_agent) = request_origin(Some(request));
+
+    let result = sqlx::query(
+        r#"INSERT INTO audit_logs
+           (id, user_id, action, entity_type, entity_id, description, ip_address, user_agent,
+            via_kiosk, kiosk_token_id, actor_identifier, created_at)
+           VALUES (?, NULL, ?, ?, ?, ?, ?, ?, 1, ?, ?, ?)"#
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(action)
+    .bind(entity_type)
+    .bind(entity_id)
+    .bind(description)
+    .bind(&ip_address)
+    .bind(&user_agent)
+    .bind(&kiosk.token_id)
+    .bind(actor_identifier)
+    .bind(Utc::now())
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        log::error!("Failed to write kiosk audit log: {}", e);
+    }
+}
+
 /// Short version for frequent calls (without changes)
 pub async fn audit(
     pool: &SqlitePool,
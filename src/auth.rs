@@ -9,6 +9,7 @@ use actix_web::HttpMessage;
 use validator::Validate;
 use actix_web::{HttpRequest, dev::ServiceRequest};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
+use crate::access_control::KioskScope;
 use crate::error::{ApiError, ApiResult};
 
 // ======== USER MODEL ========
@@ -27,6 +28,8 @@ pub struct User {
     pub updated_at: DateTime<Utc>,
     pub failed_login_attempts: u32,
     pub locked_until: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub last_activity_at: Option<DateTime<Utc>>,
 }
 
 // ======== USER ROLE ========
@@ -81,6 +84,12 @@ impl UserRole {
         matches!(self, UserRole::Admin | UserRole::Researcher)
     }
 
+    // ======== REPORT PERMISSIONS ========
+    /// Публиковать общие (shared) пресеты отчётов
+    pub fn can_share_report_presets(&self) -> bool {
+        matches!(self, UserRole::Admin | UserRole::Researcher)
+    }
+
     // ======== REAGENT PERMISSIONS ========
     pub fn can_create_reagents(&self) -> bool {
         matches!(self, UserRole::Admin | UserRole::Researcher)
@@ -111,6 +120,10 @@ impl UserRole {
         matches!(self, UserRole::Admin)
     }
 
+    pub fn can_approve_batches(&self) -> bool {
+        matches!(self, UserRole::Admin)
+    }
+
     pub fn can_view_batches(&self) -> bool {
         true // All roles can view
     }
@@ -267,6 +280,7 @@ pub struct UserInfo {
     pub role: UserRole,
     pub is_active: bool,
     pub last_login: Option<DateTime<Utc>>,
+    pub last_activity_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -280,6 +294,7 @@ impl From<User> for UserInfo {
             role: UserRole::from_str(&user.role).unwrap_or(UserRole::Viewer),
             is_active: user.is_active,
             last_login: user.last_login,
+            last_activity_at: user.last_activity_at,
             created_at: user.created_at,
         }
     }
@@ -295,6 +310,22 @@ pub struct Claims {
     pub iat: i64,
 }
 
+/// Принципал киоск-токена: планшет в помещении, а не пользователь.
+/// Claims для него не создаются, поэтому обычные маршруты ему недоступны.
+#[derive(Debug, Clone)]
+pub struct KioskPrincipal {
+    pub token_id: String,
+    pub room_id: String,
+    pub room_name: String,
+    pub scopes: Vec<KioskScope>,
+}
+
+impl KioskPrincipal {
+    pub fn has_scope(&self, scope: KioskScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
 // ======== AUTH SERVICE ========
 
 pub struct AuthService {
@@ -378,9 +409,13 @@ fn validate_password_strength(password: &str) -> Result<(), ApiError> {
 // ======== USER METHODS ========
 
 impl User {
+    /// Без учёта регистра и пробелов по краям; при совпадениях в старых данных
+    /// (см. user_identity) предпочитается точное написание
     pub async fn find_by_username(pool: &SqlitePool, username: &str) -> ApiResult<User> {
-        sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = ?")
-            .bind(username)
+        sqlx::query_as::<_, User>(
+            "SELECT * FROM users WHERE username = ?1 COLLATE NOCASE ORDER BY username = ?1 DESC, created_at LIMIT 1"
+        )
+            .bind(username.trim())
             .fetch_one(pool)
             .await
             .map_err(|_| ApiError::NotFound("User not found".to_string()))
@@ -395,8 +430,10 @@ impl User {
     }
 
     pub async fn find_by_email(pool: &SqlitePool, email: &str) -> ApiResult<User> {
-        sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = ?")
-            .bind(email)
+        sqlx::query_as::<_, User>(
+            "SELECT * FROM users WHERE email = ?1 COLLATE NOCASE ORDER BY email = ?1 DESC, created_at LIMIT 1"
+        )
+            .bind(email.trim())
             .fetch_one(pool)
             .await
             .map_err(|_| ApiError::NotFound("User not found".to_string()))
@@ -434,6 +471,7 @@ impl User {
             updated_at: now,
             failed_login_attempts: 0,
             locked_until: None,
+            last_activity_at: None,
         };
 
         sqlx::query(
@@ -459,7 +497,9 @@ impl User {
     }
 
     pub async fn update_last_login(&self, pool: &SqlitePool) -> ApiResult<()> {
-        sqlx::query("UPDATE users SET last_login = datetime('now') WHERE id = ?")
+        sqlx::query(
+            "UPDATE users SET last_login = datetime('now'), last_activity_at = datetime('now'), inactivity_notice_at = NULL WHERE id = ?"
+        )
             .bind(&self.id)
             .execute(pool)
             .await?;
@@ -553,6 +593,12 @@ pub fn get_current_user(req: &HttpRequest) -> ApiResult<Claims> {
         .ok_or_else(|| ApiError::Unauthorized("No user information found".to_string()))
 }
 
+pub fn get_kiosk_principal(req: &HttpRequest) -> ApiResult<KioskPrincipal> {
+    req.extensions()
+        .get::<KioskPrincipal>().cloned()
+        .ok_or_else(|| ApiError::Unauthorized("Kiosk token required".to_string()))
+}
+
 pub fn check_permission<F>(claims: &Claims, check: F) -> ApiResult<()>
 where
     F: Fn(&UserRole) -> bool,
@@ -571,6 +617,45 @@ pub fn require_permission(req: &HttpRequest, permission_check: fn(&UserRole) ->
     Ok(claims)
 }
 
+// ======== ACTIVITY TRACKING ========
+
+/// Не чаще одной записи last_activity_at на пользователя за этот интервал
+const ACTIVITY_WRITE_INTERVAL_MINUTES: i64 = 5;
+
+lazy_static::lazy_static! {
+    static ref LAST_ACTIVITY_WRITES: std::sync::Mutex<std::collections::HashMap<String, DateTime<Utc>>> =
+        std::sync::Mutex::new(std::collections::HashMap::new());
+}
+
+/// Решает, пора ли записать активность пользователя (in-memory троттлинг без обращения к БД)
+fn should_record_activity(user_id: &str, now: DateTime<Utc>) -> bool {
+    let mut writes = match LAST_ACTIVITY_WRITES.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    match writes.get(user_id) {
+        Some(last) if now - *last < Duration::minutes(ACTIVITY_WRITE_INTERVAL_MINUTES) => false,
+        _ => {
+            writes.insert(user_id.to_string(), now);
+            true
+        }
+    }
+}
+
+/// Обновляет last_activity_at; условие в WHERE защищает от лишних записей при нескольких воркерах
+pub async fn record_user_activity(pool: &SqlitePool, user_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"UPDATE users SET last_activity_at = datetime('now'), inactivity_notice_at = NULL
+           WHERE id = ?
+             AND (last_activity_at IS NULL OR last_activity_at < datetime('now', ?))"#
+    )
+        .bind(user_id)
+        .bind(format!("-{} minutes", ACTIVITY_WRITE_INTERVAL_MINUTES))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
 // ======== JWT MIDDLEWARE ========
 
 pub async fn jwt_middleware(
@@ -579,6 +664,28 @@ pub async fn jwt_middleware(
 ) -> Result<ServiceRequest, (actix_web::Error, ServiceRequest)> {
     let token = credentials.token();
 
+    // Киоск-токены - непрозрачные строки из БД, а не JWT
+    if token.starts_with(crate::kiosk_handlers::KIOSK_TOKEN_PREFIX) {
+        let Some(app_state) = req.app_data::<web::Data<std::sync::Arc<crate::AppState>>>() else {
+            log::error!("AppState not found in app data");
+            return Err((
+                ApiError::InternalServerError("Application state is not configured".to_string()).into(),
+                req,
+            ));
+        };
+        let pool = app_state.db_pool.clone();
+        return match crate::kiosk_handlers::resolve_kiosk_token(&pool, token).await {
+            Ok(principal) => {
+                req.extensions_mut().insert(principal);
+                Ok(req)
+            }
+            Err(err) => {
+                log::warn!("Kiosk token rejected: {}", err);
+                Err((err.into(), req))
+            }
+        };
+    }
+
     let auth_service = match req.app_data::<web::Data<std::sync::Arc<AuthService>>>() {
         Some(svc) => svc,
         None => {
@@ -592,6 +699,17 @@ pub async fn jwt_middleware(
 
     match auth_service.verify_token(token) {
         Ok(claims) => {
+            if should_record_activity(&claims.sub, Utc::now()) {
+                if let Some(app_state) = req.app_data::<web::Data<std::sync::Arc<crate::AppState>>>() {
+                    let pool = app_state.db_pool.clone();
+                    let user_id = claims.sub.clone();
+                    actix_web::rt::spawn(async move {
+                        if let Err(e) = record_user_activity(&pool, &user_id).await {
+                            log::warn!("Failed to record activity for user {}: {}", user_id, e);
+                        }
+                    });
+                }
+            }
             req.extensions_mut().insert(claims);
             Ok(req)
         }
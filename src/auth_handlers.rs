@@ -14,6 +14,7 @@ use crate::auth::{
     LoginResponse, UserInfo, UserRole, get_current_user, check_permission
 };
 use crate::error::{ApiError, ApiResult};
+use crate::user_identity::{ensure_available, normalize_email, normalize_username};
 use crate::AppState;
 
 // Re-export get_current_user as get_claims_from_request for backward compatibility
@@ -53,6 +54,15 @@ pub struct CreateUserRequest {
     #[validate(length(max = 100, message = "Name cannot exceed 100 characters"))]
     pub name: Option<String>,
 }
+
+/// Query for the inactive accounts list
+#[derive(Debug, Deserialize, Validate)]
+pub struct InactiveUsersQuery {
+    #[validate(range(min = 1, max = 3650, message = "days must be between 1 and 3650"))]
+    pub days: Option<i64>,
+}
+
+const DEFAULT_INACTIVE_DAYS: i64 = 90;
 // ✅ ADDED MISSING LOGOUT FUNCTION
 pub async fn logout() -> ApiResult<HttpResponse> {
     // In stateless JWT auth, the server doesn't need to do much.
@@ -89,6 +99,7 @@ pub enum Permission {
     DeleteBatch,
     ViewBatch,
     UseBatch,
+    ApproveBatch,
     
     // Equipment permissions
     CreateEquipment,
@@ -136,6 +147,7 @@ impl Permission {
             Permission::DeleteBatch => "delete_batch",
             Permission::ViewBatch => "view_batch",
             Permission::UseBatch => "use_batch",
+            Permission::ApproveBatch => "approve_batch",
             Permission::CreateEquipment => "create_equipment",
             Permission::EditEquipment => "edit_equipment",
             Permission::DeleteEquipment => "delete_equipment",
@@ -175,6 +187,7 @@ pub fn get_role_permissions(role: &UserRole) -> Vec<Permission> {
             Permission::DeleteBatch,
             Permission::ViewBatch,
             Permission::UseBatch,
+            Permission::ApproveBatch,
             Permission::CreateEquipment,
             Permission::EditEquipment,
             Permission::DeleteEquipment,
@@ -241,13 +254,18 @@ pub async fn login(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     request.validate()?;
+    let client_ip = crate::client_ip::client_ip(&http_request).unwrap_or_else(|| "unknown".to_string());
 
     // Find user by username
     let mut user = User::find_by_username(&app_state.db_pool, &request.username).await
-        .map_err(|_| ApiError::BadRequest("Invalid username or password".to_string()))?;
+        .map_err(|_| {
+            log::warn!("Failed login for unknown user '{}' from {}", request.username, client_ip);
+            ApiError::BadRequest("Invalid username or password".to_string())
+        })?;
 
     // Check if user is locked
     if user.is_locked() {
+        log::warn!("Login attempt for locked account {} from {}", user.username, client_ip);
         return Err(ApiError::AuthError("Account is temporarily locked. Try again later.".to_string()));
     }
 
@@ -257,10 +275,15 @@ pub async fn login(
 
         // Increment failed attempts
         user.increment_failed_attempts(&app_state.db_pool).await?;
+        log::warn!(
+            "Failed login for {} from {} (attempt {})",
+            user.username, client_ip, user.failed_login_attempts
+        );
 
         // Lock for 15 minutes after 5 failed attempts
         if user.failed_login_attempts >= 5 {
             user.lock_for_duration(&app_state.db_pool, Duration::minutes(15)).await?;
+            log::warn!("Account {} locked after failed logins from {}", user.username, client_ip);
             return Err(ApiError::AuthError(
                 "Account locked due to too many failed attempts. Try again in 15 minutes.".to_string()
             ));
@@ -309,6 +332,9 @@ pub async fn register(
     request: web::Json<RegisterRequest>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
+    let mut request = request.into_inner();
+    request.username = normalize_username(&request.username, &app_state.config.auth.username)?;
+    request.email = normalize_email(&request.email)?;
     request.validate()?;
 
     // Determine user role with transaction to prevent race condition
@@ -346,8 +372,12 @@ pub async fn register(
         role
     };
 
+    let mut conn = app_state.db_pool.acquire().await?;
+    ensure_available(&mut conn, Some(&request.username), Some(&request.email), None).await?;
+    drop(conn);
+
     // Create user (this will start its own transaction internally)
-    let user = User::create(&app_state.db_pool, request.into_inner(), role, &auth_service).await?;
+    let user = User::create(&app_state.db_pool, request, role, &auth_service).await?;
 
     // Save before into() consumes user
     let user_id = user.id.clone();
@@ -477,6 +507,33 @@ pub async fn get_users(
     Ok(HttpResponse::Ok().json(ApiResponse::success(user_infos)))
 }
 
+/// Active accounts without any activity (or login) for `days` days, oldest first.
+/// Accounts that never logged in are measured from their creation date.
+pub async fn get_inactive_users(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<InactiveUsersQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    check_permission(&claims, |role| role.can_manage_users())?;
+    query.validate()?;
+
+    let days = query.days.unwrap_or(DEFAULT_INACTIVE_DAYS);
+    let users: Vec<User> = sqlx::query_as(
+        r#"SELECT * FROM users
+           WHERE is_active = 1
+             AND datetime(COALESCE(last_activity_at, last_login, created_at)) < datetime('now', ?)
+           ORDER BY datetime(COALESCE(last_activity_at, last_login, created_at)) ASC"#
+    )
+    .bind(format!("-{} days", days))
+    .fetch_all(&app_state.db_pool)
+    .await?;
+
+    let user_infos: Vec<UserInfo> = users.into_iter().map(|u| u.into()).collect();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(user_infos)))
+}
+
 pub async fn get_user(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
@@ -502,6 +559,9 @@ pub async fn create_user(
     let claims = get_current_user(&http_request)?;
     check_permission(&claims, |role| role.can_manage_users())?;
 
+    let mut request = request.into_inner();
+    request.username = normalize_username(&request.username, &app_state.config.auth.username)?;
+    request.email = normalize_email(&request.email)?;
     request.validate()?;
 
     // Validate role
@@ -511,35 +571,10 @@ pub async fn create_user(
             request.role
         )))?;
 
-    // Check if username already exists
-    let existing_username: Option<(String,)> = sqlx::query_as(
-        "SELECT id FROM users WHERE username = ?"
-    )
-    .bind(&request.username)
-    .fetch_optional(&app_state.db_pool)
-    .await?;
-
-    if existing_username.is_some() {
-        return Err(ApiError::BadRequest(format!(
-            "Username '{}' already exists", 
-            request.username
-        )));
-    }
-
-    // Check if email already exists
-    let existing_email: Option<(String,)> = sqlx::query_as(
-        "SELECT id FROM users WHERE email = ?"
-    )
-    .bind(&request.email)
-    .fetch_optional(&app_state.db_pool)
-    .await?;
-
-    if existing_email.is_some() {
-        return Err(ApiError::BadRequest(format!(
-            "Email '{}' already exists", 
-            request.email
-        )));
-    }
+    // Username and email must be unique regardless of case
+    let mut conn = app_state.db_pool.acquire().await?;
+    ensure_available(&mut conn, Some(&request.username), Some(&request.email), None).await?;
+    drop(conn);
 
     // Hash password
     let password_hash = auth_service.hash_password(&request.password)
@@ -606,6 +641,13 @@ pub async fn update_user(
     let claims = get_current_user(&http_request)?;
     check_permission(&claims, |role| role.can_manage_users())?;
 
+    let mut request = request.into_inner();
+    if let Some(username) = request.username.take() {
+        request.username = Some(normalize_username(&username, &app_state.config.auth.username)?);
+    }
+    if let Some(email) = request.email.take() {
+        request.email = Some(normalize_email(&email)?);
+    }
     request.validate()?;
 
     let now = Utc::now();
@@ -642,43 +684,10 @@ pub async fn update_user(
     // Fetch existing user to check for conflicts
     let existing_user = User::find_by_id(&app_state.db_pool, &user_id).await?;
 
-    // Check username uniqueness if changing
-    if let Some(ref new_username) = request.username {
-        if new_username != &existing_user.username {
-            let conflict: Option<(String,)> = sqlx::query_as(
-                "SELECT id FROM users WHERE username = ? AND id != ?"
-            )
-            .bind(new_username)
-            .bind(&user_id)
-            .fetch_optional(&app_state.db_pool)
-            .await?;
-
-            if conflict.is_some() {
-                return Err(ApiError::BadRequest(format!(
-                    "Username '{}' already exists", new_username
-                )));
-            }
-        }
-    }
-
-    // Check email uniqueness if changing
-    if let Some(ref new_email) = request.email {
-        if new_email != &existing_user.email {
-            let conflict: Option<(String,)> = sqlx::query_as(
-                "SELECT id FROM users WHERE email = ? AND id != ?"
-            )
-            .bind(new_email)
-            .bind(&user_id)
-            .fetch_optional(&app_state.db_pool)
-            .await?;
-
-            if conflict.is_some() {
-                return Err(ApiError::BadRequest(format!(
-                    "Email '{}' already exists", new_email
-                )));
-            }
-        }
-    }
+    // Username and email must stay unique regardless of case
+    let mut conn = app_state.db_pool.acquire().await?;
+    ensure_available(&mut conn, request.username.as_deref(), request.email.as_deref(), Some(&user_id)).await?;
+    drop(conn);
 
     // Build dynamic update query
     let mut updates = vec!["updated_at = ?".to_string()];
@@ -689,6 +698,8 @@ pub async fn update_user(
     if request.name.is_some() { updates.push("name = ?".to_string()); has_changes = true; }
     if request.role.is_some() { updates.push("role = ?".to_string()); has_changes = true; }
     if request.is_active.is_some() { updates.push("is_active = ?".to_string()); has_changes = true; }
+    // Повторная активация начинает отсчёт неактивности заново
+    if request.is_active == Some(true) { updates.push("inactivity_notice_at = NULL".to_string()); }
 
     let sql = format!("UPDATE users SET {} WHERE id = ?", updates.join(", "));
 
@@ -823,6 +834,8 @@ pub async fn delete_user(
         .await?;
 
     if result.rows_affected() > 0 {
+        // Пресеты владельца удалены каскадом
+        app_state.reference_cache.invalidate(crate::reference_cache::ReferenceDataset::ReportPresets);
         let mut cs = ChangeSet::new();
         cs.deleted("username", &target_user.username);
         cs.deleted("email", &target_user.email);
@@ -843,8 +856,181 @@ pub async fn delete_user(
     }
 }
 
+// ======== USER ANONYMIZATION (GDPR) ========
+
+/// Колонки, ссылающиеся на users.id: при анонимизации переписываются на псевдоним,
+/// чтобы записи инвентаря и истории сохранились
+const USER_REFERENCE_COLUMNS: &[(&str, &str)] = &[
+    ("reagents", "created_by"),
+    ("reagents", "updated_by"),
+    ("batches", "created_by"),
+    ("batches", "updated_by"),
+    ("batch_placements", "placed_by"),
+    ("equipment", "created_by"),
+    ("equipment", "updated_by"),
+    ("equipment_parts", "created_by"),
+    ("equipment_maintenance", "created_by"),
+    ("equipment_files", "uploaded_by"),
+    ("reagent_images", "uploaded_by"),
+    ("kiosk_tokens", "created_by"),
+    ("kiosk_tokens", "revoked_by"),
+    ("rooms", "created_by"),
+    ("rooms", "updated_by"),
+    ("experiments", "researcher_id"),
+    ("experiments", "created_by"),
+    ("experiments", "updated_by"),
+    ("experiments", "instructor_user_id"),
+    ("experiment_comments", "user_id"),
+    ("pending_deletions", "requested_by"),
+    ("experiment_participants", "user_id"),
+    ("experiment_participants", "created_by"),
+    ("experiment_signoffs", "signed_by"),
+    ("experiment_dependencies", "created_by"),
+    ("settings", "updated_by"),
+    ("usage_logs", "user_id"),
+    ("audit_logs", "user_id"),
+    ("report_presets", "owner_id"),
+    ("batch_expiry_extensions", "approved_by"),
+    ("external_links", "created_by"),
+    ("teams", "created_by"),
+    ("team_members", "user_id"),
+    ("team_members", "added_by"),
+];
+
+const ANONYMIZED_USERNAME_PREFIX: &str = "anonymized-";
+
+#[derive(Debug, Serialize)]
+pub struct AnonymizeUserResponse {
+    /// Новый (псевдонимный) id пользователя; прежний id больше не существует
+    pub pseudonym_id: String,
+    pub username: String,
+    /// Сколько ссылок в таблицах переписано на псевдоним
+    pub references_updated: u64,
+}
+
+/// Заменяет учётную запись псевдонимной в одной транзакции: новая строка users без персональных данных,
+/// все ссылки переписываются на неё, исходная строка удаляется. Запись аудита - без удалённых данных.
+pub async fn anonymize_user_data(
+    pool: &sqlx::SqlitePool,
+    user_id: &str,
+    admin_id: &str,
+    request: Option<&HttpRequest>,
+) -> ApiResult<AnonymizeUserResponse> {
+    let target = User::find_by_id(pool, user_id).await?;
+    if target.username.starts_with(ANONYMIZED_USERNAME_PREFIX) {
+        return Err(ApiError::bad_request("User is already anonymized"));
+    }
+    if target.role == "admin" {
+        let admin_count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM users WHERE role = 'admin' AND is_active = 1"
+        )
+        .fetch_one(pool)
+        .await?;
+        if target.is_active && admin_count <= 1 {
+            return Err(ApiError::bad_request("Cannot anonymize the last admin user"));
+        }
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+    let pseudonym_id = format!("anon-{}", token);
+    let username = format!("{}{}", ANONYMIZED_USERNAME_PREFIX, &token[..12]);
+    let now = Utc::now();
+
+    let mut tx = pool.begin().await?;
+
+    // Пароль-заглушка не является bcrypt-хэшем - вход невозможен
+    sqlx::query(
+        r#"INSERT INTO users (id, username, email, password_hash, name, role, is_active,
+                              created_at, updated_at, failed_login_attempts)
+           VALUES (?, ?, ?, '!anonymized', NULL, 'viewer', 0, ?, ?, 0)"#
+    )
+    .bind(&pseudonym_id)
+    .bind(&username)
+    .bind(format!("{}@anonymized.invalid", username))
+    .bind(target.created_at)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    let mut references_updated = 0;
+    for (table, column) in USER_REFERENCE_COLUMNS {
+        let sql = format!("UPDATE {table} SET {column} = ? WHERE {column} = ?");
+        references_updated += sqlx::query(&sql)
+            .bind(&pseudonym_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+    }
+
+    // Контактные данные участника и сетевые следы в журнале
+    sqlx::query("UPDATE experiment_participants SET name = NULL, email = NULL WHERE user_id = ?")
+        .bind(&pseudonym_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE audit_logs SET ip_address = NULL, user_agent = NULL WHERE user_id = ?")
+        .bind(&pseudonym_id)
+        .execute(&mut *tx)
+        .await?;
+    // Записи об учётной записи (создание, изменения) содержат имя и email
+    sqlx::query(
+        r#"UPDATE audit_logs
+           SET entity_id = ?, description = 'User record anonymized', changes = NULL, old_value = NULL, new_value = NULL
+           WHERE entity_type = 'user' AND entity_id = ?"#
+    )
+    .bind(&pseudonym_id)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM users WHERE id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    crate::audit::log_activity(
+        &mut *tx,
+        Some(admin_id),
+        "anonymize_user",
+        "user",
+        Some(&pseudonym_id),
+        Some("User account anonymized (personal data removed)"),
+        None,
+        request,
+    ).await?;
+
+    tx.commit().await?;
+
+    Ok(AnonymizeUserResponse { pseudonym_id, username, references_updated })
+}
+
+/// POST /auth/users/{id}/anonymize (admin)
+pub async fn anonymize_user(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = path.into_inner();
+    let claims = get_current_user(&http_request)?;
+    check_permission(&claims, |role| role.can_manage_users())?;
+
+    if user_id == claims.sub {
+        return Err(ApiError::BadRequest("Cannot anonymize your own account".to_string()));
+    }
+
+    let response = anonymize_user_data(&app_state.db_pool, &user_id, &claims.sub, Some(&http_request)).await?;
+    app_state.reference_cache.invalidate(crate::reference_cache::ReferenceDataset::ReportPresets);
+    log::info!("Admin {} anonymized a user account as {}", claims.username, response.pseudonym_id);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        response,
+        "User anonymized successfully".to_string(),
+    )))
+}
+
 /// Get available roles
 pub async fn get_roles(
+    app_state: web::Data<Arc<AppState>>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = get_current_user(&http_request)?;
@@ -879,372 +1065,7 @@ pub async fn get_roles(
         },
     ];
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(roles)))
-}
-
-// ======== PERMISSION CHECK FUNCTIONS ========
-
-pub fn check_reagent_permission(
-    http_request: &HttpRequest,
-    action: ReagentAction,
-) -> ApiResult<()> {
-    let claims = get_current_user(http_request)?;
-
-    match action {
-        ReagentAction::Create => check_permission(&claims, |role| role.can_create_reagents()),
-        ReagentAction::Edit => check_permission(&claims, |role| role.can_edit_reagents()),
-        ReagentAction::Delete => check_permission(&claims, |role| role.can_delete_reagents()),
-        ReagentAction::View => Ok(()), // All can view
-    }
-}
-
-pub fn check_batch_permission(
-    http_request: &HttpRequest,
-    action: BatchAction,
-) -> ApiResult<()> {
-    let claims = get_current_user(http_request)?;
-
-    match action {
-        BatchAction::Create => check_permission(&claims, |role| role.can_create_batches()),
-        BatchAction::Edit => check_permission(&claims, |role| role.can_edit_batches()),
-        BatchAction::Delete => check_permission(&claims, |role| role.can_delete_batches()),
-        BatchAction::View => Ok(()), // All can view
-    }
-}
-
-/// Async version that checks custom permissions from user_permissions table
-/// Custom permissions override role-based defaults when present
-pub async fn check_batch_permission_async(
-    http_request: &HttpRequest,
-    action: BatchAction,
-    pool: &sqlx::SqlitePool,
-) -> ApiResult<()> {
-    let claims = get_current_user(http_request)?;
-
-    // View is always allowed
-    if matches!(action, BatchAction::View) {
-        return Ok(());
-    }
-
-    let permission_key = match action {
-        BatchAction::Create => "create_batch",
-        BatchAction::Edit => "edit_batch",
-        BatchAction::Delete => "delete_batch",
-        BatchAction::View => return Ok(()),
-    };
-
-    // First check if user has custom permissions — they take priority over role
-    let result: Option<(String,)> = sqlx::query_as(
-        "SELECT permissions FROM user_permissions WHERE user_id = ?"
-    )
-    .bind(&claims.sub)
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| {
-        log::error!("DB error checking permissions: {:?}", e);
-        ApiError::InternalServerError("Database error".to_string())
-    })?;
-
-    if let Some((perms_json,)) = result {
-        // Custom permissions exist — use them as source of truth
-        match serde_json::from_str::<std::collections::HashMap<String, bool>>(&perms_json) {
-            Ok(perms) => {
-                if perms.get(permission_key).copied().unwrap_or(false) {
-                    log::debug!("User {} granted {} via custom permissions", claims.username, permission_key);
-                    return Ok(());
-                } else {
-                    log::info!("User {} denied {} via custom permissions", claims.username, permission_key);
-                    return Err(ApiError::Forbidden("Insufficient permissions".to_string()));
-                }
-            }
-            Err(e) => {
-                log::error!("Failed to parse permissions JSON for user {}: {:?}", claims.username, e);
-                // Fall through to role-based check on parse error
-            }
-        }
-    }
-
-    // No custom permissions found — fall back to role-based defaults
-    let role_allowed = match action {
-        BatchAction::Create => claims.role.can_create_batches(),
-        BatchAction::Edit => claims.role.can_edit_batches(),
-        BatchAction::Delete => claims.role.can_delete_batches(),
-        BatchAction::View => true,
-    };
-
-    if role_allowed {
-        log::debug!("User {} allowed {:?} via role {:?}", claims.username, action, claims.role);
-        return Ok(());
-    }
-
-    Err(ApiError::Forbidden("Insufficient permissions".to_string()))
-}
-
-/// Async version for reagent permissions
-/// Custom permissions override role-based defaults when present
-pub async fn check_reagent_permission_async(
-    http_request: &HttpRequest,
-    action: ReagentAction,
-    pool: &sqlx::SqlitePool,
-) -> ApiResult<()> {
-    let claims = get_current_user(http_request)?;
-
-    // View is always allowed
-    if matches!(action, ReagentAction::View) {
-        return Ok(());
-    }
-
-    let permission_key = match action {
-        ReagentAction::Create => "create_reagent",
-        ReagentAction::Edit => "edit_reagent",
-        ReagentAction::Delete => "delete_reagent",
-        ReagentAction::View => return Ok(()),
-    };
-
-    // First check if user has custom permissions — they take priority over role
-    let result: Option<(String,)> = sqlx::query_as(
-        "SELECT permissions FROM user_permissions WHERE user_id = ?"
-    )
-    .bind(&claims.sub)
-    .fetch_optional(pool)
-    .await
-    .map_err(|e| {
-        log::error!("DB error checking permissions: {:?}", e);
-        ApiError::InternalServerError("Database error".to_string())
-    })?;
-
-    if let Some((perms_json,)) = result {
-        // Custom permissions exist — use them as source of truth
-        match serde_json::from_str::<std::collections::HashMap<String, bool>>(&perms_json) {
-            Ok(perms) => {
-                if perms.get(permission_key).copied().unwrap_or(false) {
-                    log::debug!("User {} granted {} via custom permissions", claims.username, permission_key);
-                    return Ok(());
-                } else {
-                    log::info!("User {} denied {} via custom permissions", claims.username, permission_key);
-                    return Err(ApiError::Forbidden("Insufficient permissions".to_string()));
-                }
-            }
-            Err(e) => {
-                log::error!("Failed to parse permissions JSON for user {}: {:?}", claims.username, e);
-                // Fall through to role-based check on parse error
-            }
-        }
-    }
-
-    // No custom permissions found — fall back to role-based defaults
-    let role_allowed = match action {
-        ReagentAction::Create => claims.role.can_create_reagents(),
-        ReagentAction::Edit => claims.role.can_edit_reagents(),
-        ReagentAction::Delete => claims.role.can_delete_reagents(),
-        ReagentAction::View => true,
-    };
-
-    if role_allowed {
-        log::debug!("User {} allowed {:?} via role {:?}", claims.username, action, claims.role);
-        return Ok(());
-    }
-
-    Err(ApiError::Forbidden("Insufficient permissions".to_string()))
-}
-
-pub async fn check_equipment_permission(
-    http_request: &HttpRequest,
-    action: EquipmentAction,
-    pool: &sqlx::SqlitePool,
-) -> ApiResult<()> {
-    let claims = get_current_user(http_request)?;
-
-    // View is always allowed
-    if matches!(action, EquipmentAction::View) {
-        return Ok(());
-    }
-
-    let permission_key = match action {
-        EquipmentAction::Create => "create_equipment",
-        EquipmentAction::Edit => "edit_equipment",
-        EquipmentAction::Delete => "delete_equipment",
-        EquipmentAction::View => return Ok(()),
-    };
-
-    // First check if user has custom permissions — they take priority over role
-    let result: Option<(String,)> = sqlx::query_as(
-        "SELECT permissions FROM user_permissions WHERE user_id = ?"
-    )
-    .bind(&claims.sub)
-    .fetch_optional(pool)
-    .await
-    .map_err(|_| ApiError::InternalServerError("Database error".to_string()))?;
-
-    if let Some((perms_json,)) = result {
-        if let Ok(perms) = serde_json::from_str::<std::collections::HashMap<String, bool>>(&perms_json) {
-            if perms.get(permission_key).copied().unwrap_or(false) {
-                return Ok(());
-            } else {
-                return Err(ApiError::Forbidden("Insufficient permissions".to_string()));
-            }
-        }
-    }
-
-    // No custom permissions — fall back to role-based defaults
-    let role_allowed = match action {
-        EquipmentAction::Create => claims.role.can_create_equipment(),
-        EquipmentAction::Edit => claims.role.can_edit_equipment(),
-        EquipmentAction::Delete => claims.role.can_delete_equipment(),
-        EquipmentAction::View => true,
-    };
-
-    if role_allowed {
-        return Ok(());
-    }
-
-    Err(ApiError::Forbidden("Insufficient permissions".to_string()))
-}
-
-pub async fn check_experiment_permission(
-    http_request: &HttpRequest,
-    action: ExperimentAction,
-    pool: &sqlx::SqlitePool,
-) -> ApiResult<()> {
-    let claims = get_current_user(http_request)?;
-
-    // View is always allowed
-    if matches!(action, ExperimentAction::View) {
-        return Ok(());
-    }
-
-    let permission_key = match action {
-        ExperimentAction::Create => "create_experiment",
-        ExperimentAction::Edit => "edit_experiment",
-        ExperimentAction::Delete => "delete_experiment",
-        ExperimentAction::View => return Ok(()),
-    };
-
-    // First check if user has custom permissions — they take priority over role
-    let result: Option<(String,)> = sqlx::query_as(
-        "SELECT permissions FROM user_permissions WHERE user_id = ?"
-    )
-    .bind(&claims.sub)
-    .fetch_optional(pool)
-    .await
-    .map_err(|_| ApiError::InternalServerError("Database error".to_string()))?;
-
-    if let Some((perms_json,)) = result {
-        if let Ok(perms) = serde_json::from_str::<std::collections::HashMap<String, bool>>(&perms_json) {
-            if perms.get(permission_key).copied().unwrap_or(false) {
-                return Ok(());
-            } else {
-                return Err(ApiError::Forbidden("Insufficient permissions".to_string()));
-            }
-        }
-    }
-
-    // No custom permissions — fall back to role-based defaults
-    let role_allowed = match action {
-        ExperimentAction::Create => claims.role.can_create_experiments(),
-        ExperimentAction::Edit => claims.role.can_edit_experiments(),
-        ExperimentAction::Delete => claims.role.can_delete_experiments(),
-        ExperimentAction::View => true,
-    };
-
-    if role_allowed {
-        return Ok(());
-    }
-
-    Err(ApiError::Forbidden("Insufficient permissions".to_string()))
-}
-
-pub async fn check_room_permission(
-    http_request: &HttpRequest,
-    action: RoomAction,
-    pool: &sqlx::SqlitePool,
-) -> ApiResult<()> {
-    let claims = get_current_user(http_request)?;
-
-    // View is always allowed
-    if matches!(action, RoomAction::View) {
-        return Ok(());
-    }
-
-    let permission_key = match action {
-        RoomAction::Create => "create_room",
-        RoomAction::Edit => "edit_room",
-        RoomAction::Delete => "delete_room",
-        RoomAction::View => return Ok(()),
-    };
-
-    // First check if user has custom permissions — they take priority over role
-    let result: Option<(String,)> = sqlx::query_as(
-        "SELECT permissions FROM user_permissions WHERE user_id = ?"
-    )
-    .bind(&claims.sub)
-    .fetch_optional(pool)
-    .await
-    .map_err(|_| ApiError::InternalServerError("Database error".to_string()))?;
-
-    if let Some((perms_json,)) = result {
-        if let Ok(perms) = serde_json::from_str::<std::collections::HashMap<String, bool>>(&perms_json) {
-            if perms.get(permission_key).copied().unwrap_or(false) {
-                return Ok(());
-            } else {
-                return Err(ApiError::Forbidden("Insufficient permissions".to_string()));
-            }
-        }
-    }
-
-    // No custom permissions — fall back to role-based defaults
-    let role_allowed = match action {
-        RoomAction::Create => claims.role.can_create_rooms(),
-        RoomAction::Edit => claims.role.can_edit_rooms(),
-        RoomAction::Delete => claims.role.can_delete_rooms(),
-        RoomAction::View => true,
-    };
-
-    if role_allowed {
-        return Ok(());
-    }
-
-    Err(ApiError::Forbidden("Insufficient permissions".to_string()))
-}
-
-// ======== ACTION ENUMS ========
-
-#[derive(Debug)]
-pub enum ReagentAction {
-    Create,
-    Edit,
-    Delete,
-    View,
-}
-
-#[derive(Debug)]
-pub enum BatchAction {
-    Create,
-    Edit,
-    Delete,
-    View,
-}
-
-#[derive(Debug)]
-pub enum EquipmentAction {
-    Create,
-    Edit,
-    Delete,
-    View,
-}
-
-pub enum ExperimentAction {
-    Create,
-    Edit,
-    Delete,
-    View,
-}
-
-pub enum RoomAction {
-    Create,
-    Edit,
-    Delete,
-    View,
+    Ok(crate::reference_cache::reference_response(&app_state, roles))
 }
 
 // ======== USER PERMISSIONS HANDLERS ========
@@ -1351,7 +1172,7 @@ fn get_default_permissions_for_role(role: &str) -> std::collections::HashMap<Str
         "admin" => {
             for perm in &[
                 "create_reagent", "edit_reagent", "delete_reagent", "view_reagent",
-                "create_batch", "edit_batch", "delete_batch", "view_batch", "use_batch",
+                "create_batch", "edit_batch", "delete_batch", "view_batch", "use_batch", "approve_batch",
                 "create_equipment", "edit_equipment", "delete_equipment", "view_equipment", "manage_maintenance",
                 "create_experiment", "edit_experiment", "delete_experiment", "view_experiment",
                 "create_room", "edit_room", "delete_room", "view_room",
@@ -1483,4 +1304,89 @@ pub async fn get_user_activity(
         .collect();
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(activities)))
-}
\ No newline at end of file
+}
+
+// ======== TESTS ========
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_anonymize_user_preserves_records() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, name, role, created_at, updated_at) VALUES \
+             ('admin', 'admin', 'admin@example.com', 'x', NULL, 'admin', datetime('now'), datetime('now')), \
+             ('student', 'jdoe', 'jdoe@example.com', 'x', 'John Doe', 'researcher', datetime('now'), datetime('now'))",
+            "INSERT INTO user_permissions (user_id, permissions, updated_at) VALUES ('student', '[]', datetime('now'))",
+            "INSERT INTO reagents (id, name, status, created_by, updated_by, created_at, updated_at) \
+             VALUES ('r1', 'Ethanol', 'active', 'student', 'student', datetime('now'), datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, status, experiment_type, created_by, created_at, updated_at) \
+             VALUES ('e1', 'Titration', datetime('now'), 'planned', 'educational', 'admin', datetime('now'), datetime('now'))",
+            "INSERT INTO experiment_participants (id, experiment_id, user_id, name, email, role, created_at) \
+             VALUES ('p1', 'e1', 'student', 'John Doe', 'jdoe@example.com', 'student', datetime('now'))",
+            "INSERT INTO audit_logs (id, user_id, action, entity_type, entity_id, description, changes, ip_address, created_at) VALUES \
+             ('a1', 'student', 'create', 'reagent', 'r1', 'Created reagent', NULL, '10.0.0.7', datetime('now')), \
+             ('a2', 'admin', 'create_user', 'user', 'student', 'Created user jdoe', '[{\"field\":\"email\"}]', '10.0.0.1', datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let result = anonymize_user_data(&pool, "student", "admin", None).await.unwrap();
+        assert!(result.username.starts_with(ANONYMIZED_USERNAME_PREFIX));
+        // reagents x2, participant, audit_logs
+        assert_eq!(result.references_updated, 4);
+
+        assert!(User::find_by_id(&pool, "student").await.is_err());
+        let pseudonym = User::find_by_id(&pool, &result.pseudonym_id).await.unwrap();
+        assert!(!pseudonym.is_active);
+        assert_eq!(pseudonym.name, None);
+        assert!(!pseudonym.email.contains("jdoe"));
+
+        let (created_by, updated_by): (String, String) = sqlx::query_as("SELECT created_by, updated_by FROM reagents WHERE id = 'r1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!((created_by.as_str(), updated_by.as_str()), (result.pseudonym_id.as_str(), result.pseudonym_id.as_str()));
+
+        let participant: (Option<String>, Option<String>, Option<String>) =
+            sqlx::query_as("SELECT user_id, name, email FROM experiment_participants WHERE id = 'p1'")
+                .fetch_one(&pool).await.unwrap();
+        assert_eq!(participant, (Some(result.pseudonym_id.clone()), None, None));
+
+        let (ip, description): (Option<String>, Option<String>) =
+            sqlx::query_as("SELECT ip_address, description FROM audit_logs WHERE id = 'a1'")
+                .fetch_one(&pool).await.unwrap();
+        assert_eq!((ip, description.as_deref()), (None, Some("Created reagent")));
+        let (entity_id, description, changes): (Option<String>, Option<String>, Option<String>) =
+            sqlx::query_as("SELECT entity_id, description, changes FROM audit_logs WHERE id = 'a2'")
+                .fetch_one(&pool).await.unwrap();
+        assert_eq!(entity_id.as_deref(), Some(result.pseudonym_id.as_str()));
+        assert!(!description.unwrap().contains("jdoe"));
+        assert_eq!(changes, None);
+
+        let logged: (String, Option<String>) = sqlx::query_as(
+            "SELECT user_id, description FROM audit_logs WHERE action = 'anonymize_user'"
+        ).fetch_one(&pool).await.unwrap();
+        assert_eq!(logged.0, "admin");
+        assert!(!logged.1.unwrap().contains("jdoe"));
+
+        let leftovers: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM audit_logs WHERE description LIKE '%jdoe%' OR changes LIKE '%jdoe%'"
+        ).fetch_one(&pool).await.unwrap();
+        assert_eq!(leftovers, 0);
+
+        assert!(matches!(
+            anonymize_user_data(&pool, &result.pseudonym_id, "admin", None).await,
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            anonymize_user_data(&pool, "admin", "admin", None).await,
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+}
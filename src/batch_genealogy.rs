@@ -0,0 +1,318 @@
+// src/batch_genealogy.rs
+//! Происхождение партии: GET /batches/{batch_id}/genealogy
+//!
+//! Граф в форме, пригодной для рендерера (nodes + edges с source/target), и плоский
+//! хронологический список для печати в аудит. Узлы - партия и эксперименты, списавшие
+//! её (терминальные узлы); рёбра - списания с количеством и временем. Связей партия-партия
+//! (деление, перенос, слияние) в схеме нет - партия всегда корень собственного графа.
+//! Трасса: поступление, расход (usage_logs), корректировки остатка и списания в эксперименты.
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct BatchGenealogy {
+    pub root: String,
+    pub nodes: Vec<GenealogyNode>,
+    pub edges: Vec<GenealogyEdge>,
+    /// Все события партии по времени (для печати)
+    pub trace: Vec<TraceEntry>,
+}
+
+/// Узел графа; id с префиксом типа ("batch:…", "experiment:…"), чтобы не пересекаться
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GenealogyNode {
+    Batch {
+        id: String,
+        label: String,
+        terminal: bool,
+        data: BatchNode,
+    },
+    Experiment {
+        id: String,
+        label: String,
+        terminal: bool,
+        data: ExperimentNode,
+    },
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct BatchNode {
+    pub batch_id: String,
+    pub reagent_id: String,
+    pub reagent_name: String,
+    pub batch_number: String,
+    pub lot_number: Option<String>,
+    pub quantity: f64,
+    pub original_quantity: f64,
+    pub unit: String,
+    pub status: String,
+    pub supplier: Option<String>,
+    pub received_date: DateTime<Utc>,
+    pub expiry_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExperimentNode {
+    pub experiment_id: String,
+    pub title: String,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GenealogyEdge {
+    pub id: String,
+    pub source: String,
+    pub target: String,
+    /// Пока только "consumption"
+    pub kind: &'static str,
+    pub quantity: f64,
+    pub unit: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TraceEntry {
+    pub at: DateTime<Utc>,
+    /// received | usage | adjustment | consumption
+    pub event: &'static str,
+    pub batch_id: String,
+    /// Изменение остатка: поступление положительно, расход и списание отрицательны
+    pub quantity: f64,
+    pub unit: String,
+    pub user_id: Option<String>,
+    pub experiment_id: Option<String>,
+    pub note: Option<String>,
+}
+
+/// Списание партии в эксперимент: отмеченная строка experiment_reagents или запись расхода
+/// (usage_logs) с experiment_id
+#[derive(Debug, sqlx::FromRow)]
+struct ConsumptionRow {
+    id: String,
+    experiment_id: String,
+    quantity: f64,
+    unit: String,
+    at: DateTime<Utc>,
+    user_id: Option<String>,
+    note: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct EventRow {
+    event: String,
+    quantity: f64,
+    unit: String,
+    at: DateTime<Utc>,
+    user_id: Option<String>,
+    note: Option<String>,
+}
+
+fn batch_node_id(batch_id: &str) -> String {
+    format!("batch:{}", batch_id)
+}
+
+fn experiment_node_id(experiment_id: &str) -> String {
+    format!("experiment:{}", experiment_id)
+}
+
+/// Порядок событий с одинаковым временем: поступление раньше любого расхода
+fn event_rank(event: &str) -> u8 {
+    match event {
+        "received" => 0,
+        "adjustment" => 1,
+        _ => 2,
+    }
+}
+
+pub async fn build_genealogy(pool: &SqlitePool, batch_id: &str) -> ApiResult<BatchGenealogy> {
+    let batch: BatchNode = sqlx::query_as(
+        r#"SELECT b.id AS batch_id, b.reagent_id, r.name AS reagent_name, b.batch_number, b.lot_number,
+                  b.quantity, b.original_quantity, b.unit, b.status, b.supplier, b.received_date, b.expiry_date
+           FROM batches b JOIN reagents r ON r.id = b.reagent_id
+           WHERE b.id = ? AND b.deleted_at IS NULL"#
+    )
+        .bind(batch_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Batch"))?;
+
+    let consumptions: Vec<ConsumptionRow> = sqlx::query_as(
+        r#"SELECT er.id, er.experiment_id, COALESCE(er.actual_quantity, er.planned_quantity) AS quantity,
+                  er.unit, COALESCE(er.consumed_at, er.updated_at) AS at, er.consumed_by AS user_id,
+                  er.consumption_notes AS note
+           FROM experiment_reagents er
+           WHERE er.batch_id = ?1 AND er.is_consumed = 1
+           UNION ALL
+           SELECT u.id, u.experiment_id, u.quantity_used, u.unit, u.created_at, u.user_id, u.notes
+           FROM usage_logs u
+           WHERE u.batch_id = ?1 AND u.experiment_id IS NOT NULL
+           ORDER BY at, id"#
+    )
+        .bind(batch_id)
+        .fetch_all(pool)
+        .await?;
+
+    let events: Vec<EventRow> = sqlx::query_as(
+        r#"SELECT 'usage' AS event, -u.quantity_used AS quantity, u.unit, u.created_at AS at, u.user_id,
+                  COALESCE(u.purpose, u.notes) AS note
+           FROM usage_logs u
+           WHERE u.batch_id = ?1 AND u.experiment_id IS NULL
+           UNION ALL
+           SELECT 'adjustment', a.delta, a.unit, a.created_at, a.user_id,
+                  a.reason || COALESCE(': ' || a.note, '')
+           FROM stock_adjustments a
+           WHERE a.batch_id = ?1"#
+    )
+        .bind(batch_id)
+        .fetch_all(pool)
+        .await?;
+
+    let root = batch_node_id(&batch.batch_id);
+    let mut trace = vec![TraceEntry {
+        at: batch.received_date,
+        event: "received",
+        batch_id: batch.batch_id.clone(),
+        quantity: batch.original_quantity,
+        unit: batch.unit.clone(),
+        user_id: None,
+        experiment_id: None,
+        note: batch.supplier.clone(),
+    }];
+    trace.extend(events.into_iter().map(|e| TraceEntry {
+        at: e.at,
+        event: if e.event == "adjustment" { "adjustment" } else { "usage" },
+        batch_id: batch.batch_id.clone(),
+        quantity: e.quantity,
+        unit: e.unit,
+        user_id: e.user_id,
+        experiment_id: None,
+        note: e.note,
+    }));
+
+    let mut experiment_ids: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut edges = Vec::with_capacity(consumptions.len());
+    for c in consumptions {
+        if seen.insert(c.experiment_id.clone()) {
+            experiment_ids.push(c.experiment_id.clone());
+        }
+        trace.push(TraceEntry {
+            at: c.at,
+            event: "consumption",
+            batch_id: batch.batch_id.clone(),
+            quantity: -c.quantity,
+            unit: c.unit.clone(),
+            user_id: c.user_id,
+            experiment_id: Some(c.experiment_id.clone()),
+            note: c.note,
+        });
+        edges.push(GenealogyEdge {
+            id: format!("consumption:{}", c.id),
+            source: root.clone(),
+            target: experiment_node_id(&c.experiment_id),
+            kind: "consumption",
+            quantity: c.quantity,
+            unit: c.unit,
+            at: c.at,
+        });
+    }
+    trace.sort_by(|a, b| a.at.cmp(&b.at).then_with(|| event_rank(a.event).cmp(&event_rank(b.event))));
+
+    let mut nodes = vec![GenealogyNode::Batch {
+        id: root.clone(),
+        label: format!("{} / {}", batch.reagent_name, batch.batch_number),
+        terminal: experiment_ids.is_empty(),
+        data: batch,
+    }];
+    for experiment_id in experiment_ids {
+        let experiment: ExperimentNode = sqlx::query_as(
+            "SELECT id AS experiment_id, title, status FROM experiments WHERE id = ?"
+        )
+            .bind(&experiment_id)
+            .fetch_one(pool)
+            .await?;
+        nodes.push(GenealogyNode::Experiment {
+            id: experiment_node_id(&experiment_id),
+            label: experiment.title.clone(),
+            terminal: true,
+            data: experiment,
+        });
+    }
+
+    Ok(BatchGenealogy { root, nodes, edges, trace })
+}
+
+/// GET /batches/{batch_id}/genealogy
+pub async fn get_batch_genealogy(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let genealogy = build_genealogy(app_state.read_pool(), &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(genealogy)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::UserRole;
+    use crate::test_support::{fixtures, TestApp};
+    use actix_web::http::StatusCode;
+
+    #[actix_web::test]
+    async fn test_genealogy_lists_consuming_experiments_and_trace() {
+        let app = TestApp::new().await;
+        for sql in [
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, \
+             is_consumed, consumed_at, consumed_by, created_at, updated_at) VALUES \
+             ('er-1', 'fx-experiment-titration', 'fx-reagent-ethanol', 'fx-batch-ethanol-1', 25.0, 'ml', 1, \
+              datetime('now', '+2 hours'), 'fx-researcher', datetime('now'), datetime('now'))",
+            "INSERT INTO usage_logs (id, reagent_id, batch_id, user_id, quantity_used, unit, purpose, created_at) VALUES \
+             ('u-1', 'fx-reagent-ethanol', 'fx-batch-ethanol-1', 'fx-researcher', 10.0, 'ml', 'rinse', datetime('now', '+1 hour'))",
+            "INSERT INTO stock_adjustments (id, reagent_id, batch_id, user_id, delta, unit, quantity_before, quantity_after, \
+             reason, created_at) VALUES \
+             ('a-1', 'fx-reagent-ethanol', 'fx-batch-ethanol-1', 'fx-admin', -5.0, 'ml', 100.0, 95.0, 'spillage', datetime('now', '+3 hours'))",
+        ] {
+            sqlx::query(sql).execute(&app.pool).await.unwrap();
+        }
+
+        let path = format!("/batches/{}/genealogy", fixtures::ETHANOL_BATCH_ID);
+        let (status, body) = app.get(UserRole::Viewer, &path).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let graph = &body["data"];
+        let root = format!("batch:{}", fixtures::ETHANOL_BATCH_ID);
+        assert_eq!(graph["root"], root.as_str());
+
+        let nodes = graph["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0]["type"], "batch");
+        assert_eq!(nodes[0]["terminal"], false);
+        assert_eq!(nodes[1]["type"], "experiment");
+        assert_eq!(nodes[1]["id"], format!("experiment:{}", fixtures::EXPERIMENT_ID));
+        assert_eq!(nodes[1]["label"], "Titration");
+        assert_eq!(nodes[1]["terminal"], true);
+
+        let edges = graph["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0]["source"], root.as_str());
+        assert_eq!(edges[0]["target"], nodes[1]["id"]);
+        assert_eq!(edges[0]["quantity"], 25.0);
+
+        let events: Vec<&str> = graph["trace"].as_array().unwrap().iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(events, ["received", "usage", "consumption", "adjustment"]);
+        assert_eq!(graph["trace"][1]["quantity"], -10.0);
+
+        let (status, _) = app.get(UserRole::Viewer, "/batches/missing/genealogy").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -5,12 +5,19 @@
 use actix_web::{web, HttpResponse, HttpRequest};
 use std::sync::Arc;
 use crate::AppState;
+use crate::api_version::{ApiVersion, Representation};
 use crate::models::*;
 use crate::error::{ApiError, ApiResult, validate_quantity, validate_unit};
 use crate::auth::get_current_user;
+use crate::events::BusinessEvent;
+use crate::outbox;
 use crate::handlers::{ApiResponse, PaginatedResponse};
-use crate::validator::{CustomValidate, UnitConverter};
+use crate::pagination::{PageGroup, PageLimits};
+use crate::location_handlers::{location_display_path, LOCATION_SUBTREE_SQL};
+use crate::validator::{validate_container_fill, CustomValidate, FieldValidator, UnitConverter, ValidationResult};
 use crate::query_builders::{SafeQueryBuilder, FieldWhitelist};
+use crate::query_builders::sql::UpdateBuilder;
+use crate::reagent_handlers::{ensure_reagent_active, ensure_reagent_active_by_id, INACTIVE_STATUS};
 use chrono::{Utc, DateTime};
 use uuid::Uuid;
 use validator::Validate;
@@ -32,12 +39,16 @@ pub struct BatchResponse {
     pub unit: String,
     pub pack_size: Option<f64>,
     pub pack_count: Option<i64>,
+    /// Запечатанные и вскрытые тары (см. models::batch::container_state)
+    pub container_count: Option<i64>,
+    pub open_containers: Option<i64>,
     pub expiry_date: Option<DateTime<Utc>>,
     pub supplier: Option<String>,
     pub manufacturer: Option<String>,
     pub received_date: DateTime<Utc>,
     pub status: String,
     pub location: Option<String>,
+    pub location_id: Option<String>,
     pub notes: Option<String>,
     pub created_by: Option<String>,
     pub updated_by: Option<String>,
@@ -55,6 +66,13 @@ pub struct BatchResponse {
     pub placements: Option<Vec<PlacementWithRoom>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub unplaced_quantity: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiry_extensions: Option<Vec<BatchExpiryExtension>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links: Option<Vec<ExternalLink>>,
+    /// Запчасти оборудования, чей остаток читается из этой партии
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_parts: Option<Vec<LinkedEquipmentPart>>,
 }
 
 /// Партия с именем реагента
@@ -76,6 +94,8 @@ pub struct BatchWithReagent {
     pub received_date: DateTime<Utc>,
     pub status: String,
     pub location: Option<String>,
+    #[sqlx(default)]
+    pub location_id: Option<String>,
     pub notes: Option<String>,
     pub created_by: Option<String>,
     pub updated_by: Option<String>,
@@ -83,6 +103,8 @@ pub struct BatchWithReagent {
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
     pub reagent_name: String,
+    #[sqlx(default)]
+    pub container_count: Option<i64>,
 }
 
 /// Расширенный ответ партии с реагентом
@@ -100,12 +122,16 @@ pub struct BatchWithReagentResponse {
     pub unit: String,
     pub pack_size: Option<f64>,
     pub pack_count: Option<i64>,
+    /// Запечатанные и вскрытые тары (см. models::batch::container_state)
+    pub container_count: Option<i64>,
+    pub open_containers: Option<i64>,
     pub expiry_date: Option<DateTime<Utc>>,
     pub supplier: Option<String>,
     pub manufacturer: Option<String>,
     pub received_date: DateTime<Utc>,
     pub status: String,
     pub location: Option<String>,
+    pub location_id: Option<String>,
     pub notes: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -147,7 +173,7 @@ fn calculate_expiration_status(expiry_date: Option<DateTime<Utc>>) -> (String, O
 
 // ==================== UNIT CONVERSION ====================
 
-fn convert_quantity(quantity: f64, from_unit: &str, to_unit: &str) -> Result<f64, String> {
+pub(crate) fn convert_quantity(quantity: f64, from_unit: &str, to_unit: &str) -> Result<f64, String> {
     if from_unit == to_unit {
         return Ok(quantity);
     }
@@ -155,6 +181,39 @@ fn convert_quantity(quantity: f64, from_unit: &str, to_unit: &str) -> Result<f64
     converter.convert(quantity, from_unit, to_unit)
 }
 
+/// Как `convert_quantity`, но mol/mmol/μmol пересчитываются в массу и обратно через
+/// молярную массу реагента (г/моль). Без молярной массы - MOLECULAR_WEIGHT_REQUIRED.
+pub(crate) fn convert_amount(
+    quantity: f64,
+    from_unit: &str,
+    to_unit: &str,
+    reagent_name: &str,
+    molecular_weight: Option<f64>,
+) -> ApiResult<f64> {
+    let (from_molar, to_molar) = (UnitConverter::molar_factor(from_unit), UnitConverter::molar_factor(to_unit));
+    let mass_unit = match (from_molar, to_molar) {
+        (None, None) => return convert_quantity(quantity, from_unit, to_unit).map_err(|e| ApiError::bad_request(&e)),
+        (Some(from), Some(to)) => return Ok(quantity * from / to),
+        (Some(_), None) => to_unit,
+        (None, Some(_)) => from_unit,
+    };
+    if UnitConverter::new().to_base(1.0, mass_unit).map(|(_, base)| base) != Some("g") {
+        return Err(ApiError::bad_request(&format!(
+            "Cannot convert {} to {}: molar amounts convert only to mass units", from_unit, to_unit
+        )));
+    }
+    let molecular_weight = molecular_weight
+        .filter(|mw| *mw > 0.0)
+        .ok_or_else(|| ApiError::molecular_weight_required(reagent_name))?;
+
+    match (from_molar, to_molar) {
+        (Some(factor), _) => convert_quantity(quantity * factor * molecular_weight, "g", to_unit),
+        (_, Some(factor)) => convert_quantity(quantity, from_unit, "g").map(|grams| grams / molecular_weight / factor),
+        (None, None) => convert_quantity(quantity, from_unit, to_unit),
+    }
+    .map_err(|e| ApiError::bad_request(&e))
+}
+
 // ==================== BATCH QUERY ====================
 
 #[derive(Debug, serde::Deserialize)]
@@ -162,14 +221,29 @@ pub struct BatchQuery {
     pub page: Option<i64>,
     pub per_page: Option<i64>,
     pub search: Option<String>,
+    /// Мультивыбор: `?status=available,in_use`
     pub status: Option<String>,
     pub unit: Option<String>,
+    /// Текстовое место хранения, мультивыбор: `?location=Fridge A,Shelf 2`
+    pub location: Option<String>,
+    /// Партии узла мест хранения и всех вложенных в него узлов
+    pub location_id: Option<String>,
+    /// `?fields=id,batch_number,reagent_name` - вернуть только перечисленные поля
+    pub fields: Option<String>,
+    /// Партии деактивированных реагентов (по умолчанию скрыты; доступны через /reagents/{id}/batches)
+    pub include_inactive: Option<bool>,
 }
 
+/// Статусы партии (CHECK таблицы batches) - допустимые значения `?status=`
+pub const BATCH_STATUSES: &[&str] = &["available", "in_use", "expired", "depleted", "awaiting_coa"];
+
+/// Колонки batches типа DateTime (для ответов с `?fields=`)
+const BATCH_DATETIME_COLUMNS: &[&str] = &["expiry_date", "received_date", "created_at", "updated_at"];
+
 impl BatchQuery {
-    pub fn normalize(&self) -> (i64, i64, i64) {
+    pub fn normalize(&self, limits: PageLimits) -> (i64, i64, i64) {
         let page = self.page.unwrap_or(1).max(1);
-        let per_page = self.per_page.unwrap_or(20).clamp(1, 100);
+        let per_page = limits.per_page(self.per_page);
         let offset = (page - 1) * per_page;
         (page, per_page, offset)
     }
@@ -183,11 +257,30 @@ fn get_batch_join_whitelist() -> FieldWhitelist {
         "b.id", "b.reagent_id", "b.batch_number", "b.lot_number", "b.cat_number",
         "b.quantity", "b.original_quantity", "b.reserved_quantity", "b.unit",
         "b.expiry_date", "b.supplier", "b.manufacturer", "b.received_date",
-        "b.status", "b.location", "b.notes", "b.created_at", "b.updated_at",
+        "b.status", "b.location", "b.location_id", "b.notes", "b.created_at", "b.updated_at",
         "r.name", "r.id", "r.formula", "r.cas_number",
     ])
 }
 
+/// Поля списка партий, доступные в `?fields=` (без префиксов JOIN-запроса)
+fn batch_list_fields_whitelist() -> FieldWhitelist {
+    FieldWhitelist::new("batches", &[
+        "id", "reagent_id", "batch_number", "lot_number", "cat_number",
+        "quantity", "original_quantity", "reserved_quantity", "unit",
+        "expiry_date", "supplier", "manufacturer", "received_date",
+        "status", "location", "location_id", "notes", "created_at", "updated_at",
+        "reagent_name",
+    ])
+}
+
+/// Поле списка -> колонка JOIN-запроса get_all_batches
+fn batch_list_column(field: &str) -> String {
+    match field {
+        "reagent_name" => "r.name AS reagent_name".to_string(),
+        other => format!("b.{}", other),
+    }
+}
+
 // ==================== BATCH CRUD ====================
 
 /// Получить все партии с пагинацией
@@ -195,10 +288,14 @@ fn get_batch_join_whitelist() -> FieldWhitelist {
 pub async fn get_all_batches(
     app_state: web::Data<Arc<AppState>>,
     query: web::Query<BatchQuery>,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
-    let (page, per_page, _offset) = query.normalize();
+    let limits = app_state.page_limits(PageGroup::Batches);
+    let capped = limits.is_capped(query.per_page);
+    let (page, per_page, _offset) = query.normalize(limits);
 
     let whitelist = get_batch_join_whitelist();
+    let fields = crate::handlers::parse_fields_param(query.fields.as_deref(), &batch_list_fields_whitelist())?;
     
     // Безопасное построение запроса через SafeQueryBuilder
     // Примечание: SafeQueryBuilder из mod.rs принимает base_query
@@ -209,6 +306,9 @@ pub async fn get_all_batches(
 
     // Исключаем удалённые батчи
     builder.add_condition("b.deleted_at IS NULL", vec![]);
+    if !query.include_inactive.unwrap_or(false) {
+        builder.add_condition("r.status != 'inactive'", vec![]);
+    }
 
     // Добавляем условия поиска
     if let Some(ref search) = query.search {
@@ -218,16 +318,21 @@ pub async fn get_all_batches(
             let pattern = format!("%{}%", trimmed);
             let or_condition = "(b.batch_number LIKE ? OR r.name LIKE ? OR b.cat_number LIKE ? OR b.supplier LIKE ?)";
             builder.add_condition(or_condition, vec![
-                pattern.clone(), 
-                pattern.clone(), 
-                pattern.clone(), 
-                pattern
+                pattern.clone().into(), 
+                pattern.clone().into(), 
+                pattern.clone().into(), 
+                pattern.into()
             ]);
         }
     }
 
-    if let Some(ref status) = query.status {
-        builder.add_exact_match("b.status", status);
+    let statuses = crate::handlers::parse_multi_filter(query.status.as_deref(), "status", Some(BATCH_STATUSES))?;
+    builder.add_in_clause("b.status", &statuses);
+    let locations = crate::handlers::parse_multi_filter(query.location.as_deref(), "location", None)?;
+    builder.add_in_clause("b.location", &locations);
+
+    if let Some(ref location_id) = query.location_id {
+        builder.add_condition(&format!("b.location_id IN ({})", LOCATION_SUBTREE_SQL), vec![location_id.into()]);
     }
 
     // Сортировка и пагинация
@@ -247,6 +352,28 @@ pub async fn get_all_batches(
     }
     let total: i64 = count_query.fetch_one(&app_state.db_pool).await?;
 
+    if let Some(fields) = fields {
+        let columns: Vec<String> = fields.iter().map(|f| batch_list_column(f)).collect();
+        builder.select_columns(&columns).map_err(ApiError::BadRequest)?;
+        let (select_sql, select_params) = builder.build();
+        let mut data = crate::handlers::fetch_json_rows(
+            &app_state.db_pool, &select_sql, &select_params, BATCH_DATETIME_COLUMNS,
+        ).await?;
+        for row in &mut data {
+            api_version.reshape(Representation::Batch, row);
+        }
+        let total_pages = (total + per_page - 1) / per_page;
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
+            data,
+            total,
+            page,
+            per_page,
+            total_pages,
+            limits,
+            capped,
+        })));
+    }
+
     // Выполнение SELECT запроса
     let mut select_query = sqlx::query_as::<_, BatchWithReagent>(&select_sql);
     for p in &select_params {
@@ -297,6 +424,7 @@ let response_batches: Vec<BatchResponse> = batches
     .map(|b| {
         let (expiration_status, days_until_expiration) = calculate_expiration_status(b.expiry_date);
         let pack_count = calculate_pack_count(b.quantity, b.pack_size);
+        let containers = container_state(b.quantity, b.pack_size, b.container_count);
         let batch_placements = placements_map.get(&b.id).cloned().unwrap_or_default();
         let placed_qty: f64 = batch_placements.iter().map(|p| p.quantity).sum();
         let unplaced = (b.quantity - placed_qty).max(0.0);
@@ -313,12 +441,15 @@ let response_batches: Vec<BatchResponse> = batches
             unit: b.unit,
             pack_size: b.pack_size,
             pack_count,
+            container_count: containers.map(|c| c.sealed),
+            open_containers: containers.map(|c| c.open),
             expiry_date: b.expiry_date,
             supplier: b.supplier,
             manufacturer: b.manufacturer,
             received_date: b.received_date,
             status: b.status,
             location: b.location,
+            location_id: b.location_id,
             notes: b.notes,
             created_by: b.created_by,
             updated_by: b.updated_by,
@@ -331,50 +462,57 @@ let response_batches: Vec<BatchResponse> = batches
             original_unit: None,
             placements: if batch_placements.is_empty() { None } else { Some(batch_placements) },
             unplaced_quantity: Some(unplaced),
+            expiry_extensions: None,
+            links: None,
+            linked_parts: None,
         }
     })
     .collect();
         let total_pages = (total + per_page - 1) / per_page;
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
-        data: response_batches,
+        data: api_version.render_all(Representation::Batch, &response_batches)?,
         total,
         page,
         per_page,
         total_pages,
+        limits,
+        capped,
     })))
 }
+/// Партия по вложенному пути `/reagents/{reagent_id}/batches/{batch_id}`.
+/// Принадлежность реагенту проверяется в самом запросе: нет партии, она удалена, принадлежит
+/// другому реагенту или нет самого реагента - всё это одинаковый 404 "Batch not found"
+pub(crate) async fn fetch_reagent_batch<'e, E>(executor: E, reagent_id: &str, batch_id: &str) -> ApiResult<Batch>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    sqlx::query_as(
+        "SELECT b.* FROM batches b JOIN reagents r ON r.id = b.reagent_id \
+         WHERE b.id = ? AND b.reagent_id = ? AND b.deleted_at IS NULL AND r.deleted_at IS NULL"
+    )
+        .bind(batch_id)
+        .bind(reagent_id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Batch"))
+}
+
 /// Получить одну партию по ID
 pub async fn get_batch(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
     let (reagent_id, batch_id) = path.into_inner();
-
-    let whitelist = FieldWhitelist::for_batches();
-    let mut builder = crate::query_builders::SafeQueryBuilder::new("SELECT * FROM batches")
-        .map_err(|e| ApiError::bad_request(&e))?
-        .with_whitelist(&whitelist);
-
-    builder
-        .add_exact_match("id", &batch_id)
-        .add_exact_match("reagent_id", &reagent_id)
-        .add_condition("deleted_at IS NULL", vec![]);
-
-    let (sql, params) = builder.build();
-    
-    let mut query = sqlx::query_as::<_, Batch>(&sql);
-    for p in &params {
-        query = query.bind(p);
-    }
-
-    let batch = query
-        .fetch_optional(&app_state.db_pool)
-        .await?
-        .ok_or_else(|| ApiError::not_found("Batch"))?;
+    let batch = fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
 
     let (expiration_status, days_until_expiration) = calculate_expiration_status(batch.expiry_date);
     let pack_count = calculate_pack_count(batch.quantity, batch.pack_size);
+    let containers = container_state(batch.quantity, batch.pack_size, batch.container_count);
+    let expiry_extensions = fetch_expiry_extensions(&app_state.db_pool, &batch.id).await?;
+    let links = crate::link_handlers::fetch_links(&app_state.db_pool, LinkEntityType::Batch, &batch.id).await?;
+    let linked_parts = fetch_linked_parts(&app_state.db_pool, &batch.id).await?;
     
     let response = BatchResponse {
         id: batch.id,
@@ -388,12 +526,15 @@ pub async fn get_batch(
         unit: batch.unit,
         pack_size: batch.pack_size,
         pack_count,
+        container_count: containers.map(|c| c.sealed),
+        open_containers: containers.map(|c| c.open),
         expiry_date: batch.expiry_date,
         supplier: batch.supplier,
         manufacturer: batch.manufacturer,
         received_date: batch.received_date,
         status: batch.status,
         location: batch.location,
+        location_id: batch.location_id,
         notes: batch.notes,
         created_by: batch.created_by,
         updated_by: batch.updated_by,
@@ -406,9 +547,92 @@ pub async fn get_batch(
         original_unit: None,
         placements: None,
         unplaced_quantity: None,
+        expiry_extensions: Some(expiry_extensions),
+        links: Some(links),
+        linked_parts: Some(linked_parts),
     };
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(api_version.render(Representation::Batch, &response)?)))
+}
+
+/// Запчасти оборудования, связанные с партией
+pub async fn fetch_linked_parts(pool: &sqlx::SqlitePool, batch_id: &str) -> ApiResult<Vec<LinkedEquipmentPart>> {
+    let parts: Vec<LinkedEquipmentPart> = sqlx::query_as(
+        r#"SELECT p.id, p.name, p.part_number, p.equipment_id, e.name AS equipment_name
+           FROM equipment_parts p
+           JOIN equipment e ON e.id = p.equipment_id
+           WHERE p.linked_batch_id = ?
+           ORDER BY e.name, p.name"#
+    )
+        .bind(batch_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(parts)
+}
+
+/// Проверка формы партии для create_batch и POST /validate/batch
+pub async fn validate_batch_request(
+    pool: &sqlx::SqlitePool,
+    reagent_id: &str,
+    batch: &CreateBatchRequest,
+    existing_id: Option<&str>,
+) -> ApiResult<ValidationResult> {
+    let reagent: Option<(String, String)> = sqlx::query_as("SELECT status, expiry_policy FROM reagents WHERE id = ?")
+        .bind(reagent_id)
+        .fetch_optional(pool)
+        .await?;
+    let check_expiry = reagent.as_ref().is_some_and(|(_, policy)| policy != EXPIRY_POLICY_NONE);
+    let mut result = validate_batch_fields(pool, batch, check_expiry).await?;
+
+    if let Some((status, _)) = reagent {
+        if existing_id.is_none() && status == INACTIVE_STATUS {
+            result.add_error("reagent_id", "Reagent is inactive; reactivate it before adding batches");
+        }
+
+        let duplicate: Option<(String,)> = sqlx::query_as(
+            "SELECT id FROM batches WHERE reagent_id = ? AND batch_number = ? AND id IS NOT ?"
+        )
+            .bind(reagent_id)
+            .bind(&batch.batch_number)
+            .bind(existing_id)
+            .fetch_optional(pool)
+            .await?;
+        if duplicate.is_some() {
+            result.add_error("batch_number", "Batch number already exists for this reagent");
+        }
+    } else {
+        result.add_error("reagent_id", "Reagent not found");
+    }
+
+    Ok(result)
+}
+
+/// Проверки партии, не зависящие от сохранённого реагента; нужны и для партии,
+/// создаваемой вместе с реагентом (CreateReagentRequest::initial_batch)
+pub async fn validate_batch_fields(
+    pool: &sqlx::SqlitePool,
+    batch: &CreateBatchRequest,
+    check_expiry: bool,
+) -> ApiResult<ValidationResult> {
+    let mut result = ValidationResult::from_validate(batch);
+    result.merge(batch.custom_validate());
+
+    if check_expiry {
+        result.merge(FieldValidator::expiry_date(batch.expiry_date.as_ref(), 30));
+    }
+
+    if let Some(location_id) = batch.location_id.as_deref().filter(|id| !id.is_empty()) {
+        let location: Option<(String,)> = sqlx::query_as("SELECT id FROM locations WHERE id = ?")
+            .bind(location_id)
+            .fetch_optional(pool)
+            .await?;
+        if location.is_none() {
+            result.add_error("location_id", "Location not found");
+        }
+    }
+
+    Ok(result)
 }
 
 /// Создать новую партию
@@ -417,66 +641,44 @@ pub async fn create_batch(
     path: web::Path<String>,
     batch_data: web::Json<CreateBatchRequest>,
     user_id: String,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
     let reagent_id = path.into_inner();
-    
-    // Валидация
-    batch_data.validate().map_err(|e| ApiError::ValidationError(e.to_string()))?;
-    
-    let custom_validation = batch_data.custom_validate();
-    if !custom_validation.is_valid() {
-        return Err(custom_validation.to_api_error());
-    }
 
     // Проверка существования реагента
-    let _: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
         .bind(&reagent_id)
         .fetch_one(&app_state.db_pool)
         .await
         .map_err(|_| ApiError::not_found("Reagent"))?;
+    ensure_reagent_active(&reagent)?;
 
-    let now = Utc::now();
-    let batch_id = Uuid::new_v4().to_string();
-    let received_date = batch_data.received_date.unwrap_or(now);
+    validate_batch_request(&app_state.db_pool, &reagent_id, &batch_data, None).await?.ensure_valid()?;
 
-    sqlx::query(
-        r#"INSERT INTO batches (
-            id, reagent_id, lot_number, batch_number, cat_number,
-            quantity, original_quantity, reserved_quantity, unit, pack_size,
-            expiry_date, supplier, manufacturer, received_date,
-            status, location, notes, created_by, updated_by,
-            created_at, updated_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, 0.0, ?, ?, ?, ?, ?, ?, 'available', ?, ?, ?, ?, ?, ?)"#,
-    )
-    .bind(&batch_id)
-    .bind(&reagent_id)
-    .bind(&batch_data.lot_number)
-    .bind(&batch_data.batch_number)
-    .bind(&batch_data.cat_number)
-    .bind(batch_data.quantity)
-    .bind(batch_data.quantity)  // original_quantity
-    .bind(&batch_data.unit)
-    .bind(&batch_data.pack_size)
-    .bind(&batch_data.expiry_date)
-    .bind(&batch_data.supplier)
-    .bind(&batch_data.manufacturer)
-    .bind(&received_date)
-    .bind(&batch_data.location)
-    .bind(&batch_data.notes)
-    .bind(&user_id)
-    .bind(&user_id)
-    .bind(&now)
-    .bind(&now)
-    .execute(&app_state.db_pool)
-    .await?;
+    let location = resolve_batch_location(&app_state.db_pool, &batch_data).await?;
+    let mut tx = app_state.db_pool.begin().await?;
+    let batch_id = insert_batch(&mut *tx, &reagent, &batch_data, location, &user_id).await?;
 
     let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ?")
         .bind(&batch_id)
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+    outbox::enqueue(
+        &mut *tx,
+        &BusinessEvent::BatchCreated {
+            reagent_id: batch.reagent_id.clone(),
+            batch_id: batch.id.clone(),
+            quantity: batch.quantity,
+            unit: batch.unit.clone(),
+        },
+        Some(&user_id),
+    ).await?;
+    tx.commit().await?;
+
     let (expiration_status, days_until_expiration) = calculate_expiration_status(batch.expiry_date);
     let pack_count = calculate_pack_count(batch.quantity, batch.pack_size);
+    let containers = container_state(batch.quantity, batch.pack_size, batch.container_count);
 
     let response = BatchResponse {
         id: batch.id,
@@ -490,12 +692,15 @@ pub async fn create_batch(
         unit: batch.unit,
         pack_size: batch.pack_size,
         pack_count,
+        container_count: containers.map(|c| c.sealed),
+        open_containers: containers.map(|c| c.open),
         expiry_date: batch.expiry_date,
         supplier: batch.supplier,
         manufacturer: batch.manufacturer,
         received_date: batch.received_date,
         status: batch.status,
         location: batch.location,
+        location_id: batch.location_id,
         notes: batch.notes,
         created_by: batch.created_by,
         updated_by: batch.updated_by,
@@ -508,69 +713,153 @@ pub async fn create_batch(
         original_unit: None,
         placements: None,
         unplaced_quantity: None,
+        expiry_extensions: None,
+        links: None,
+        linked_parts: None,
     };
 
-    Ok(HttpResponse::Created().json(ApiResponse::success(response)))
+    Ok(HttpResponse::Created().json(ApiResponse::success(api_version.render(Representation::Batch, &response)?)))
 }
 
-/// Обновить партию
-pub async fn update_batch(
-    app_state: web::Data<Arc<AppState>>,
-    path: web::Path<(String, String)>,
-    batch_data: web::Json<UpdateBatchRequest>,
-    user_id: String,
-) -> ApiResult<HttpResponse> {
-    let (reagent_id, batch_id) = path.into_inner();
-    
-    batch_data.validate().map_err(|e| ApiError::ValidationError(e.to_string()))?;
-
-    // Проверка существования
-    let existing: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ? AND reagent_id = ?")
-        .bind(&batch_id)
-        .bind(&reagent_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Batch"))?;
+/// Текст location новой партии: путь узла location_id, иначе переданный текст
+pub async fn resolve_batch_location(pool: &sqlx::SqlitePool, batch_data: &CreateBatchRequest) -> ApiResult<Option<String>> {
+    match batch_data.location_id.as_deref().filter(|id| !id.is_empty()) {
+        Some(id) => Ok(Some(location_display_path(pool, id).await?
+            .ok_or_else(|| ApiError::bad_request("Location not found"))?)),
+        None => Ok(batch_data.location.clone()),
+    }
+}
 
+/// Вставка проверенной партии; executor - пул или транзакция создания реагента.
+/// Статус awaiting_coa и итоги реагента выставляют триггеры
+pub async fn insert_batch<'e, E>(
+    executor: E,
+    reagent: &Reagent,
+    batch_data: &CreateBatchRequest,
+    location: Option<String>,
+    user_id: &str,
+) -> ApiResult<String>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
     let now = Utc::now();
+    let batch_id = Uuid::new_v4().to_string();
+    let received_date = batch_data.received_date.unwrap_or(now);
+    // Без явного срока годности - дата поступления + срок годности реагента по умолчанию
+    let expiry_date = batch_data.expiry_date.or_else(|| {
+        reagent.default_shelf_life_days.map(|days| received_date + chrono::Duration::days(days))
+    });
 
     sqlx::query(
-        r#"UPDATE batches SET
-            lot_number = COALESCE(?, lot_number),
-            batch_number = COALESCE(?, batch_number),
-            cat_number = COALESCE(?, cat_number),
-            quantity = COALESCE(?, quantity),
-            unit = COALESCE(?, unit),
-            pack_size = COALESCE(?, pack_size),
-            expiry_date = COALESCE(?, expiry_date),
-            supplier = COALESCE(?, supplier),
-            manufacturer = COALESCE(?, manufacturer),
-            status = COALESCE(?, status),
-            location = COALESCE(?, location),
-            notes = COALESCE(?, notes),
-            updated_by = ?,
-            updated_at = ?
-        WHERE id = ? AND reagent_id = ?"#,
+        r#"INSERT INTO batches (
+            id, reagent_id, lot_number, batch_number, cat_number,
+            quantity, original_quantity, reserved_quantity, unit, pack_size, container_count,
+            expiry_date, supplier, manufacturer, received_date,
+            status, location, location_id, notes, created_by, updated_by,
+            created_at, updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, 0.0, ?, ?, ?, ?, ?, ?, ?, 'available', ?, ?, ?, ?, ?, ?, ?)"#,
     )
+    .bind(&batch_id)
+    .bind(&reagent.id)
     .bind(&batch_data.lot_number)
     .bind(&batch_data.batch_number)
     .bind(&batch_data.cat_number)
-    .bind(&batch_data.quantity)
+    .bind(batch_data.quantity)
+    .bind(batch_data.quantity)  // original_quantity
     .bind(&batch_data.unit)
-    .bind(&batch_data.pack_size)
-    .bind(&batch_data.expiry_date)
+    .bind(batch_data.pack_size)
+    .bind(batch_data.container_count)
+    .bind(expiry_date)
     .bind(&batch_data.supplier)
     .bind(&batch_data.manufacturer)
-    .bind(&batch_data.status)
-    .bind(&batch_data.location)
+    .bind(received_date)
+    .bind(&location)
+    .bind(batch_data.location_id.as_deref().filter(|id| !id.is_empty()))
     .bind(&batch_data.notes)
-    .bind(&user_id)
-    .bind(&now)
-    .bind(&batch_id)
-    .bind(&reagent_id)
-    .execute(&app_state.db_pool)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(now)
+    .bind(now)
+    .execute(executor)
     .await?;
 
+    Ok(batch_id)
+}
+
+/// Обновить партию
+pub async fn update_batch(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    batch_data: web::Json<UpdateBatchRequest>,
+    user_id: String,
+    api_version: ApiVersion,
+) -> ApiResult<HttpResponse> {
+    let (reagent_id, batch_id) = path.into_inner();
+    
+    batch_data.validate().map_err(|e| ApiError::ValidationError(e.to_string()))?;
+
+    let existing = fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
+    ensure_reagent_active_by_id(&app_state.db_pool, &reagent_id).await?;
+
+    // Из awaiting_coa партия выходит только через coa-received
+    if existing.status == "awaiting_coa"
+        && batch_data.status.as_deref().is_some_and(|s| s != "awaiting_coa")
+    {
+        return Err(ApiError::batch_awaiting_coa(&existing.batch_number));
+    }
+
+    // location_id: узел задаёт и текст location; null/"" отвязывает партию (текст остаётся)
+    let location_assignment = match batch_data.location_id.as_ref().map(|id| id.as_deref().filter(|id| !id.is_empty())) {
+        None => None,
+        Some(None) => Some((None, None)),
+        Some(Some(id)) => {
+            let path = location_display_path(&app_state.db_pool, id).await?
+                .ok_or_else(|| ApiError::bad_request("Location not found"))?;
+            Some((Some(id), Some(path)))
+        }
+    };
+
+    // Пересчёт тар сверяется с итоговым остатком; без пересчёта запечатанных остаётся
+    // не больше, чем помещается в новый остаток
+    let quantity = batch_data.quantity.unwrap_or(existing.quantity);
+    let pack_size = batch_data.pack_size.unwrap_or(existing.pack_size);
+    let container_count = match batch_data.container_count {
+        Some(count) => {
+            validate_container_fill(quantity, pack_size, Some(count)).ensure_valid()?;
+            Some(count)
+        }
+        None => container_state(quantity, pack_size, existing.container_count).map(|c| c.sealed),
+    };
+
+    let mut builder = UpdateBuilder::new(FieldWhitelist::for_batch_update());
+    builder
+        .patch_text("lot_number", &batch_data.lot_number)
+        .set_opt("batch_number", &batch_data.batch_number)
+        .patch_text("cat_number", &batch_data.cat_number)
+        .set_opt("quantity", &batch_data.quantity)
+        .set_opt("unit", &batch_data.unit)
+        .patch("pack_size", &batch_data.pack_size)
+        .set("container_count", container_count)
+        .set_opt("expiry_date", &batch_data.expiry_date)
+        .patch_text("supplier", &batch_data.supplier)
+        .patch_text("manufacturer", &batch_data.manufacturer)
+        .set_opt("status", &batch_data.status)
+        .patch_text("location", &batch_data.location)
+        .patch_text("notes", &batch_data.notes);
+    if let Some((location_id, path)) = location_assignment {
+        // Путь узла заменяет переданный текст location
+        builder.set("location_id", location_id);
+        if let Some(path) = path {
+            builder.set("location", path);
+        }
+    }
+    builder
+        .set("updated_by", user_id.as_str())
+        .set("updated_at", Utc::now())
+        .where_eq("id", batch_id.as_str())
+        .where_eq("reagent_id", reagent_id.as_str());
+    builder.execute(&app_state.db_pool).await?;
+
     let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ?")
         .bind(&batch_id)
         .fetch_one(&app_state.db_pool)
@@ -578,6 +867,7 @@ pub async fn update_batch(
 
     let (expiration_status, days_until_expiration) = calculate_expiration_status(batch.expiry_date);
     let pack_count = calculate_pack_count(batch.quantity, batch.pack_size);
+    let containers = container_state(batch.quantity, batch.pack_size, batch.container_count);
 
     let response = BatchResponse {
         id: batch.id,
@@ -591,12 +881,15 @@ pub async fn update_batch(
         unit: batch.unit,
         pack_size: batch.pack_size,
         pack_count,
+        container_count: containers.map(|c| c.sealed),
+        open_containers: containers.map(|c| c.open),
         expiry_date: batch.expiry_date,
         supplier: batch.supplier,
         manufacturer: batch.manufacturer,
         received_date: batch.received_date,
         status: batch.status,
         location: batch.location,
+        location_id: batch.location_id,
         notes: batch.notes,
         created_by: batch.created_by,
         updated_by: batch.updated_by,
@@ -609,9 +902,12 @@ pub async fn update_batch(
         original_unit: None,
         placements: None,
         unplaced_quantity: None,
+        expiry_extensions: None,
+        links: None,
+        linked_parts: None,
     };
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(api_version.render(Representation::Batch, &response)?)))
 }
 
 /// Удалить партию (soft delete)
@@ -622,13 +918,7 @@ pub async fn delete_batch(
 ) -> ApiResult<HttpResponse> {
     let (reagent_id, batch_id) = path.into_inner();
 
-    // Проверка существования (только не удалённые)
-    let _: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ? AND reagent_id = ? AND deleted_at IS NULL")
-        .bind(&batch_id)
-        .bind(&reagent_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Batch"))?;
+    fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
 
     // Soft delete - устанавливаем deleted_at
     let result = sqlx::query("UPDATE batches SET deleted_at = datetime('now'), updated_by = ? WHERE id = ? AND reagent_id = ?")
@@ -647,49 +937,290 @@ pub async fn delete_batch(
     Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), "Batch deleted successfully".to_string())))
 }
 
+// ==================== BARCODE ====================
+
+/// Назначить, заменить или снять штрихкод партии
+pub async fn set_batch_barcode(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<SetBatchBarcodeRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let (reagent_id, batch_id) = path.into_inner();
+    body.validate()?;
+
+    let barcode = body.barcode.as_deref().map(str::trim).filter(|b| !b.is_empty());
+    if let Some(code) = barcode {
+        if code.chars().any(|c| c.is_control()) {
+            return Err(ApiError::bad_request("Barcode contains control characters"));
+        }
+    }
+
+    fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
+    ensure_reagent_active_by_id(&app_state.db_pool, &reagent_id).await?;
+
+    if let Some(code) = barcode {
+        let duplicate: Option<(String, String)> = sqlx::query_as(
+            "SELECT id, batch_number FROM batches WHERE barcode = ? AND id != ?"
+        )
+            .bind(code)
+            .bind(&batch_id)
+            .fetch_optional(&app_state.db_pool)
+            .await?;
+        if let Some((other_id, other_number)) = duplicate {
+            return Err(ApiError::BadRequest(format!(
+                "Barcode '{}' is already assigned to batch '{}' ({})",
+                code, other_number, other_id
+            )));
+        }
+    }
+
+    sqlx::query("UPDATE batches SET barcode = ?, updated_by = ?, updated_at = datetime('now') WHERE id = ?")
+        .bind(barcode)
+        .bind(&user_id)
+        .bind(&batch_id)
+        .execute(&app_state.db_pool)
+        .await
+        .map_err(|e| match e {
+            // Параллельное назначение того же кода ловит уникальный индекс
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                ApiError::bad_request("Barcode is already assigned to another batch")
+            }
+            other => ApiError::from(other),
+        })?;
+
+    let updated: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ?")
+        .bind(&batch_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+}
+
+// ==================== EXPIRY EXTENSION ====================
+
+/// История продлений срока годности партии (от старых к новым)
+pub async fn fetch_expiry_extensions(
+    pool: &sqlx::SqlitePool,
+    batch_id: &str,
+) -> ApiResult<Vec<BatchExpiryExtension>> {
+    let extensions = sqlx::query_as::<_, BatchExpiryExtension>(
+        r#"SELECT e.id, e.batch_id, e.reagent_id, e.old_expiry_date, e.new_expiry_date,
+                  e.justification, e.document_reference, e.approved_by,
+                  u.username as approved_by_username, e.created_at
+           FROM batch_expiry_extensions e
+           LEFT JOIN users u ON u.id = e.approved_by
+           WHERE e.batch_id = ?
+           ORDER BY e.created_at, e.rowid"#,
+    )
+        .bind(batch_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(extensions)
+}
+
+/// Продлить срок годности партии после повторного QC.
+/// Новая дата должна быть позже текущей; старая/новая дата и утвердивший пользователь
+/// сохраняются в `batch_expiry_extensions`.
+pub async fn extend_batch_expiry(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<ExtendBatchExpiryRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let (reagent_id, batch_id) = path.into_inner();
+    body.validate()?;
+
+    let justification = body.justification.trim();
+    if justification.is_empty() {
+        return Err(ApiError::bad_request("Justification is required"));
+    }
+    let document_reference = body.document_reference.as_deref()
+        .map(str::trim)
+        .filter(|d| !d.is_empty());
+
+    let mut tx = app_state.db_pool.begin().await?;
+
+    let batch = fetch_reagent_batch(&mut *tx, &reagent_id, &batch_id).await?;
+    ensure_reagent_active_by_id(&mut *tx, &reagent_id).await?;
+
+    let old_expiry = batch.expiry_date
+        .ok_or_else(|| ApiError::bad_request("Batch has no expiry date to extend"))?;
+    if body.new_expiry_date <= old_expiry {
+        return Err(ApiError::BadRequest(format!(
+            "New expiry date must be later than the current one ({})",
+            old_expiry.format("%Y-%m-%d")
+        )));
+    }
+
+    let now = Utc::now();
+    // Просроченная партия после успешного продления снова доступна
+    let new_status = if batch.status == "expired" && body.new_expiry_date > now {
+        "available"
+    } else {
+        batch.status.as_str()
+    };
+
+    let extension_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"INSERT INTO batch_expiry_extensions (
+            id, batch_id, reagent_id, old_expiry_date, new_expiry_date,
+            justification, document_reference, approved_by, created_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+    )
+        .bind(&extension_id)
+        .bind(&batch_id)
+        .bind(&reagent_id)
+        .bind(old_expiry)
+        .bind(body.new_expiry_date)
+        .bind(justification)
+        .bind(document_reference)
+        .bind(&user_id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE batches SET expiry_date = ?, status = ?, updated_by = ?, updated_at = ? WHERE id = ?")
+        .bind(body.new_expiry_date)
+        .bind(new_status)
+        .bind(&user_id)
+        .bind(now)
+        .bind(&batch_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    log::info!("Batch {} expiry extended {} -> {} by {}",
+        batch_id, old_expiry.format("%Y-%m-%d"), body.new_expiry_date.format("%Y-%m-%d"), user_id);
+
+    let extension = fetch_expiry_extensions(&app_state.db_pool, &batch_id).await?
+        .into_iter()
+        .find(|e| e.id == extension_id)
+        .ok_or_else(|| ApiError::internal_error("Expiry extension was not saved"))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        extension,
+        "Batch expiry extended".to_string(),
+    )))
+}
+
+// ==================== CERTIFICATE OF ANALYSIS ====================
+
+/// Отметить получение сертификата анализа (COA): партия из `awaiting_coa`
+/// становится доступной (или сразу `expired`, если срок годности уже истёк)
+pub async fn mark_coa_received(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<CoaReceivedRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let (reagent_id, batch_id) = path.into_inner();
+    body.validate()?;
+
+    let reference = body.reference_number.trim();
+    if reference.is_empty() {
+        return Err(ApiError::bad_request("COA reference number is required"));
+    }
+
+    let batch = fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
+    ensure_reagent_active_by_id(&app_state.db_pool, &reagent_id).await?;
+
+    if batch.status != "awaiting_coa" {
+        return Err(ApiError::BadRequest(format!(
+            "Batch is not awaiting a COA (status: '{}')", batch.status
+        )));
+    }
+
+    let now = Utc::now();
+    let expiry_policy: String = sqlx::query_scalar("SELECT expiry_policy FROM reagents WHERE id = ?")
+        .bind(&reagent_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    let new_status = if expiry_policy == EXPIRY_POLICY_STRICT && batch.expiry_date.is_some_and(|expiry| expiry <= now) {
+        "expired"
+    } else {
+        "available"
+    };
+
+    sqlx::query(
+        r#"UPDATE batches SET
+            status = ?, coa_reference = ?, coa_received_at = ?, coa_received_by = ?,
+            updated_by = ?, updated_at = ?
+        WHERE id = ? AND status = 'awaiting_coa'"#,
+    )
+        .bind(new_status)
+        .bind(reference)
+        .bind(now)
+        .bind(&user_id)
+        .bind(&user_id)
+        .bind(now)
+        .bind(&batch_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    log::info!("COA {} received for batch {} by {}", reference, batch_id, user_id);
+
+    let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ?")
+        .bind(&batch_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        batch,
+        "Certificate of Analysis recorded".to_string(),
+    )))
+}
+
 // ==================== EXPIRING BATCHES ====================
 
+/// Истекающие партии: порог - готовая метка времени без вычислений над строками,
+/// поэтому запрос идёт диапазоном по idx_batches_status_expiry и уже отсортирован
+pub const EXPIRING_BATCHES_SQL: &str = r#"
+    SELECT b.*, r.name as reagent_name
+    FROM batches b
+    JOIN reagents r ON b.reagent_id = r.id
+    WHERE b.status = 'available'
+      AND b.deleted_at IS NULL
+      AND b.expiry_date IS NOT NULL
+      AND b.expiry_date <= ?
+      AND r.expiry_policy = ?
+    ORDER BY b.expiry_date ASC
+"#;
+
 #[derive(Debug, serde::Deserialize)]
 pub struct ExpiringQuery {
     pub days: Option<i64>,
+    /// `strict` (по умолчанию) или `advisory` - раздел рекомендательных сроков
+    pub expiry_policy: Option<String>,
 }
 
 /// Получить партии с истекающим сроком годности
 pub async fn get_expiring_batches(
     app_state: web::Data<Arc<AppState>>,
     query: web::Query<ExpiringQuery>,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
-    let days = query.days.unwrap_or(30);
+    let days = query.days.unwrap_or_else(|| crate::settings::settings().get_i64(crate::settings::EXPIRING_SOON_DAYS));
     let expiry_threshold = Utc::now() + chrono::Duration::days(days);
-
-    let whitelist = get_batch_join_whitelist();
-    let base_query = "SELECT b.*, r.name as reagent_name FROM batches b JOIN reagents r ON b.reagent_id = r.id";
-    let mut builder = crate::query_builders::SafeQueryBuilder::new(base_query)
-        .map_err(|e| ApiError::bad_request(&e))?
-        .with_whitelist(&whitelist);
-
-    // Исключаем удалённые батчи
-    builder.add_condition("b.deleted_at IS NULL", vec![]);
-
-    builder
-        .add_is_not_null("b.expiry_date")
-        .add_comparison("b.expiry_date", "<=", expiry_threshold.to_rfc3339())
-        .add_exact_match("b.status", "available")
-        .order_by("b.expiry_date", "ASC");
-
-    let (sql, params) = builder.build();
-
-    let mut select_query = sqlx::query_as::<_, BatchWithReagent>(&sql);
-    for p in &params {
-        select_query = select_query.bind(p);
+    // Реагенты с политикой none сроки не отслеживают
+    let expiry_policy = query.expiry_policy.as_deref().unwrap_or(EXPIRY_POLICY_STRICT);
+    if ![EXPIRY_POLICY_STRICT, EXPIRY_POLICY_ADVISORY].contains(&expiry_policy) {
+        return Err(ApiError::bad_request("expiry_policy must be 'strict' or 'advisory'"));
     }
-    let batches: Vec<BatchWithReagent> = select_query.fetch_all(&app_state.db_pool).await?;
+
+    let batches: Vec<BatchWithReagent> = sqlx::query_as(EXPIRING_BATCHES_SQL)
+        .bind(expiry_threshold.to_rfc3339())
+        .bind(expiry_policy)
+        .fetch_all(&app_state.db_pool)
+        .await?;
 
     let response: Vec<BatchWithReagentResponse> = batches
         .into_iter()
         .map(|b| {
             let (expiration_status, days_until_expiration) = calculate_expiration_status(b.expiry_date);
             let pack_count = calculate_pack_count(b.quantity, b.pack_size);
+            let containers = container_state(b.quantity, b.pack_size, b.container_count);
             BatchWithReagentResponse {
                 id: b.id,
                 reagent_id: b.reagent_id,
@@ -703,12 +1234,15 @@ pub async fn get_expiring_batches(
                 unit: b.unit,
                 pack_size: b.pack_size,
                 pack_count,
+                container_count: containers.map(|c| c.sealed),
+                open_containers: containers.map(|c| c.open),
                 expiry_date: b.expiry_date,
                 supplier: b.supplier,
                 manufacturer: b.manufacturer,
                 received_date: b.received_date,
                 status: b.status,
                 location: b.location,
+                location_id: b.location_id,
                 notes: b.notes,
                 created_at: b.created_at,
                 updated_at: b.updated_at,
@@ -718,11 +1252,24 @@ pub async fn get_expiring_batches(
         })
         .collect();
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(api_version.render_all(Representation::Batch, &response)?)))
 }
 
 // ==================== LOW STOCK BATCHES ====================
 
+/// Партии с низким остатком. Доля остатка записана тем же выражением, что и в
+/// idx_batches_low_stock, а порог передаётся долей: так условие и сортировка идут по индексу
+pub const LOW_STOCK_BATCHES_SQL: &str = r#"
+    SELECT b.*, r.name as reagent_name
+    FROM batches b
+    JOIN reagents r ON b.reagent_id = r.id
+    WHERE b.status = 'available'
+      AND b.deleted_at IS NULL
+      AND b.original_quantity > 0
+      AND b.quantity / b.original_quantity <= ?
+    ORDER BY b.quantity / b.original_quantity ASC
+"#;
+
 #[derive(Debug, serde::Deserialize)]
 pub struct LowStockQuery {
     pub threshold: Option<f64>,
@@ -732,21 +1279,14 @@ pub struct LowStockQuery {
 pub async fn get_low_stock_batches(
     app_state: web::Data<Arc<AppState>>,
     query: web::Query<LowStockQuery>,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
-    let threshold_percentage = query.threshold.unwrap_or(20.0);
-
-    // Для сложного условия используем raw SQL, но безопасно
-    let batches: Vec<BatchWithReagent> = sqlx::query_as(r#"
-        SELECT b.*, r.name as reagent_name
-        FROM batches b
-        JOIN reagents r ON b.reagent_id = r.id
-        WHERE b.status = 'available'
-          AND b.deleted_at IS NULL
-          AND b.original_quantity > 0
-          AND (b.quantity / b.original_quantity * 100) <= ?
-        ORDER BY (b.quantity / b.original_quantity) ASC
-    "#)
-        .bind(threshold_percentage)
+    let threshold_percentage = query.threshold.unwrap_or_else(|| {
+        crate::settings::settings().get_i64(crate::settings::LOW_STOCK_THRESHOLD_PERCENT) as f64
+    });
+
+    let batches: Vec<BatchWithReagent> = sqlx::query_as(LOW_STOCK_BATCHES_SQL)
+        .bind(threshold_percentage / 100.0)
         .fetch_all(&app_state.db_pool)
         .await?;
 
@@ -755,6 +1295,7 @@ pub async fn get_low_stock_batches(
         .map(|b| {
             let (expiration_status, days_until_expiration) = calculate_expiration_status(b.expiry_date);
             let pack_count = calculate_pack_count(b.quantity, b.pack_size);
+            let containers = container_state(b.quantity, b.pack_size, b.container_count);
             BatchWithReagentResponse {
                 id: b.id,
                 reagent_id: b.reagent_id,
@@ -768,12 +1309,15 @@ pub async fn get_low_stock_batches(
                 unit: b.unit,
                 pack_size: b.pack_size,
                 pack_count,
+                container_count: containers.map(|c| c.sealed),
+                open_containers: containers.map(|c| c.open),
                 expiry_date: b.expiry_date,
                 supplier: b.supplier,
                 manufacturer: b.manufacturer,
                 received_date: b.received_date,
                 status: b.status,
                 location: b.location,
+                location_id: b.location_id,
                 notes: b.notes,
                 created_at: b.created_at,
                 updated_at: b.updated_at,
@@ -783,7 +1327,7 @@ pub async fn get_low_stock_batches(
         })
         .collect();
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(api_version.render_all(Representation::Batch, &response)?)))
 }
 
 // ==================== UNIT CONVERSION ENDPOINT ====================
@@ -793,6 +1337,8 @@ pub struct ConvertUnitRequest {
     pub quantity: f64,
     pub from_unit: String,
     pub to_unit: String,
+    /// Реагент, чья молярная масса нужна для пересчёта mol/mmol в массу
+    pub reagent_id: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -801,22 +1347,41 @@ pub struct ConvertUnitResponse {
     pub original_unit: String,
     pub converted_quantity: f64,
     pub converted_unit: String,
+    /// Молярная масса, использованная при пересчёте mol/mmol
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub molecular_weight: Option<f64>,
 }
 
 pub async fn convert_units(
+    app_state: web::Data<Arc<AppState>>,
     request: web::Json<ConvertUnitRequest>,
 ) -> ApiResult<HttpResponse> {
-    let converter = UnitConverter::new();
-    
-    let converted = converter
-        .convert(request.quantity, &request.from_unit, &request.to_unit)
-        .map_err(|e| ApiError::bad_request(&e))?;
+    // Пересчёт между мольными и массовыми единицами требует молярной массы реагента
+    let molar_to_mass = UnitConverter::molar_factor(&request.from_unit).is_some()
+        != UnitConverter::molar_factor(&request.to_unit).is_some();
+
+    let (converted, molecular_weight) = if molar_to_mass {
+        let reagent_id = request.reagent_id.as_deref()
+            .ok_or_else(|| ApiError::bad_request("reagent_id is required to convert between molar and mass units"))?;
+        let (name, molecular_weight): (String, Option<f64>) = sqlx::query_as(
+            "SELECT name, molecular_weight FROM reagents WHERE id = ? AND deleted_at IS NULL"
+        )
+            .bind(reagent_id)
+            .fetch_optional(&app_state.db_pool)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Reagent"))?;
+        let converted = convert_amount(request.quantity, &request.from_unit, &request.to_unit, &name, molecular_weight)?;
+        (converted, molecular_weight)
+    } else {
+        (convert_amount(request.quantity, &request.from_unit, &request.to_unit, "", None)?, None)
+    };
 
     let response = ConvertUnitResponse {
         original_quantity: request.quantity,
         original_unit: request.from_unit.clone(),
         converted_quantity: converted,
         converted_unit: request.to_unit.clone(),
+        molecular_weight,
     };
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
@@ -824,13 +1389,56 @@ pub async fn convert_units(
 
 // ==================== BATCHES FOR REAGENT ====================
 
+/// Запрос списка партий реагента
+#[derive(Debug, serde::Deserialize)]
+pub struct ReagentBatchesQuery {
+    pub page: Option<i64>,
+    /// Число или `all` - все партии одной страницей (не больше настройки reagent_batches_all_limit)
+    pub per_page: Option<String>,
+    /// Мультивыбор: `?status=available,in_use`
+    pub status: Option<String>,
+    /// Текстовое место хранения, мультивыбор: `?location=Fridge A,Shelf 2`
+    pub location: Option<String>,
+    /// Партии узла мест хранения и всех вложенных в него узлов
+    pub location_id: Option<String>,
+    /// `?include_empty=false` - скрыть партии с нулевым остатком
+    pub include_empty: Option<bool>,
+}
+
+/// Остатки по всем партиям реагента - без учёта страницы и фильтров,
+/// чтобы цифры в шапке карточки не менялись при листании
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ReagentBatchSummary {
+    pub batch_count: i64,
+    pub total_quantity: f64,
+    pub reserved_quantity: f64,
+    pub available_quantity: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReagentBatchesResponse {
+    #[serde(flatten)]
+    pub page: PaginatedResponse<serde_json::Value>,
+    pub summary: ReagentBatchSummary,
+}
+
+/// Партии реагента: по сроку годности (ближайший сначала, без срока - в конце)
 pub async fn get_batches_for_reagent(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
-    query: web::Query<BatchQuery>,
+    query: web::Query<ReagentBatchesQuery>,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
     let reagent_id = path.into_inner();
-    let (page, per_page, _offset) = query.normalize();
+    let per_page_param = query.per_page.as_deref().map(str::trim);
+    let all = per_page_param == Some("all");
+    let requested_per_page = match per_page_param {
+        None | Some("all") => None,
+        Some(raw) => Some(raw.parse::<i64>().map_err(|_| ApiError::bad_request("per_page must be a number or 'all'"))?),
+    };
+    let limits = app_state.page_limits(PageGroup::Batches);
+    let page = if all { 1 } else { query.page.unwrap_or(1).max(1) };
+    let mut per_page = limits.per_page(requested_per_page);
 
     // Проверка существования реагента
     let _: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
@@ -849,14 +1457,22 @@ pub async fn get_batches_for_reagent(
 
     builder.add_exact_match("reagent_id", &reagent_id);
 
-    if let Some(ref status) = query.status {
-        builder.add_exact_match("status", status);
+    let statuses = crate::handlers::parse_multi_filter(query.status.as_deref(), "status", Some(BATCH_STATUSES))?;
+    builder.add_in_clause("status", &statuses);
+    let locations = crate::handlers::parse_multi_filter(query.location.as_deref(), "location", None)?;
+    builder.add_in_clause("location", &locations);
+
+    if let Some(ref location_id) = query.location_id {
+        builder.add_condition(&format!("location_id IN ({})", LOCATION_SUBTREE_SQL), vec![location_id.into()]);
+    }
+
+    if query.include_empty == Some(false) {
+        builder.add_condition("quantity > 0", vec![]);
     }
 
     builder
-        .order_by("received_date", "DESC")
-        .limit(per_page)
-        .offset((page - 1) * per_page);
+        .order_first_by("expiry_date IS NULL, expiry_date ASC")
+        .order_by("received_date", "DESC");
 
     // Count
     let (count_sql, count_params) = builder.build_count();
@@ -866,6 +1482,19 @@ pub async fn get_batches_for_reagent(
     }
     let total: i64 = count_query.fetch_one(&app_state.db_pool).await?;
 
+    // per_page=all - одной страницей, но не больше предела из настроек
+    if all {
+        let limit = crate::settings::settings().get_i64(crate::settings::REAGENT_BATCHES_ALL_LIMIT).max(1);
+        if total > limit {
+            return Err(ApiError::BadRequest(format!(
+                "Reagent has {} matching batches; per_page=all is limited to {}. Use page and per_page instead",
+                total, limit
+            )));
+        }
+        per_page = total.max(1);
+    }
+    builder.limit(per_page).offset((page - 1) * per_page);
+
     // Select
     let (sql, params) = builder.build();
     let mut select_query = sqlx::query_as::<_, Batch>(&sql);
@@ -874,12 +1503,24 @@ pub async fn get_batches_for_reagent(
     }
     let batches: Vec<Batch> = select_query.fetch_all(&app_state.db_pool).await?;
 
+    let summary: ReagentBatchSummary = sqlx::query_as(
+        r#"SELECT COUNT(*) AS batch_count,
+                  COALESCE(SUM(quantity), 0.0) AS total_quantity,
+                  COALESCE(SUM(reserved_quantity), 0.0) AS reserved_quantity,
+                  COALESCE(SUM(MAX(quantity - reserved_quantity, 0.0)), 0.0) AS available_quantity
+           FROM batches WHERE reagent_id = ? AND deleted_at IS NULL"#
+    )
+        .bind(&reagent_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
     // Transform
     let response_batches: Vec<BatchResponse> = batches
         .into_iter()
         .map(|b| {
             let (expiration_status, days_until_expiration) = calculate_expiration_status(b.expiry_date);
             let pack_count = calculate_pack_count(b.quantity, b.pack_size);
+            let containers = container_state(b.quantity, b.pack_size, b.container_count);
             BatchResponse {
                 id: b.id,
                 reagent_id: b.reagent_id,
@@ -892,12 +1533,15 @@ pub async fn get_batches_for_reagent(
                 unit: b.unit,
                 pack_size: b.pack_size,
                 pack_count,
+                container_count: containers.map(|c| c.sealed),
+                open_containers: containers.map(|c| c.open),
                 expiry_date: b.expiry_date,
                 supplier: b.supplier,
                 manufacturer: b.manufacturer,
                 received_date: b.received_date,
                 status: b.status,
                 location: b.location,
+                location_id: b.location_id,
                 notes: b.notes,
                 created_by: b.created_by,
                 updated_by: b.updated_by,
@@ -910,18 +1554,26 @@ pub async fn get_batches_for_reagent(
                 original_unit: None,
                 placements: None,
                 unplaced_quantity: None,
+                expiry_extensions: None,
+                links: None,
+            linked_parts: None,
             }
         })
         .collect();
 
     let total_pages = (total + per_page - 1) / per_page;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
-        data: response_batches,
-        total,
-        page,
-        per_page,
-        total_pages,
+    Ok(HttpResponse::Ok().json(ApiResponse::success(ReagentBatchesResponse {
+        page: PaginatedResponse {
+            data: api_version.render_all(Representation::Batch, &response_batches)?,
+            total,
+            page,
+            per_page,
+            total_pages,
+            limits,
+            capped: limits.is_capped(requested_per_page),
+        },
+        summary,
     })))
 }
 
@@ -984,24 +1636,18 @@ pub async fn dispense_units(
     // Получаем текущего пользователя
     let claims = get_current_user(&http_request)?;
     
-    // Проверяем существование реагента
-    let _reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+    // Батч вместе с проверкой реагента из пути
+    let batch = fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
         .bind(&reagent_id)
         .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::reagent_not_found(&reagent_id))?;
-
-    // Получаем батч
-    let batch: Batch = sqlx::query_as(
-        "SELECT * FROM batches WHERE id = ? AND reagent_id = ? AND deleted_at IS NULL"
-    )
-        .bind(&batch_id)
-        .bind(&reagent_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::batch_not_found(&batch_id))?;
+        .await?;
+    ensure_reagent_active(&reagent)?;
 
     // Проверяем статус батча
+    if batch.status == "awaiting_coa" {
+        return Err(ApiError::batch_awaiting_coa(&batch.batch_number));
+    }
     if batch.status != "available" {
         return Err(ApiError::BadRequest(format!(
             "Batch is not available for dispensing. Current status: '{}'", 
@@ -1076,12 +1722,20 @@ pub async fn dispense_units(
         "available" 
     };
 
+    // Списанные единицы - целые тары; при учёте тар уменьшаем запечатанные
+    let container_count = container_state(
+        new_quantity.max(0.0),
+        batch.pack_size,
+        batch.container_count.map(|count| count - request.units_to_dispense),
+    ).map(|c| c.sealed);
+
     // Обновляем батч
     sqlx::query(
-        "UPDATE batches SET quantity = ?, status = ?, updated_at = ?, updated_by = ? WHERE id = ?"
+        "UPDATE batches SET quantity = ?, status = ?, container_count = ?, updated_at = ?, updated_by = ? WHERE id = ?"
     )
     .bind(new_quantity.max(0.0))
     .bind(new_status)
+    .bind(container_count)
     .bind(&now)
     .bind(&claims.sub)
     .bind(&batch_id)
@@ -1131,15 +1785,7 @@ pub async fn get_batch_units_info(
     path: web::Path<(String, String)>,
 ) -> ApiResult<HttpResponse> {
     let (reagent_id, batch_id) = path.into_inner();
-
-    let batch: Batch = sqlx::query_as(
-        "SELECT * FROM batches WHERE id = ? AND reagent_id = ? AND deleted_at IS NULL"
-    )
-        .bind(&batch_id)
-        .bind(&reagent_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::batch_not_found(&batch_id))?;
+    let batch = fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
 
     #[derive(Debug, Serialize)]
     struct UnitsInfo {
@@ -1189,4 +1835,763 @@ pub async fn get_batch_units_info(
     };
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(info)))
-}
\ No newline at end of file
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::fixtures::*;
+    use crate::test_support::TestApp;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::ResponseError;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_convert_amount_molar_units() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(convert_amount(5.0, "mmol", "g", "NaCl", Some(58.44)).unwrap(), 0.2922));
+        assert!(close(convert_amount(2.0, "mol", "kg", "NaCl", Some(58.44)).unwrap(), 0.11688));
+        assert!(close(convert_amount(292.2, "mg", "mmol", "NaCl", Some(58.44)).unwrap(), 5.0));
+        // mol <-> mmol и масса <-> масса не требуют молярной массы
+        assert!(close(convert_amount(1.5, "mol", "mmol", "NaCl", None).unwrap(), 1500.0));
+        assert!(close(convert_amount(1.0, "kg", "g", "NaCl", None).unwrap(), 1000.0));
+
+        let missing = convert_amount(5.0, "mmol", "g", "NaCl", None).unwrap_err();
+        assert!(matches!(missing, ApiError::Conflict { code: crate::error::MOLECULAR_WEIGHT_REQUIRED, .. }));
+        assert!(matches!(convert_amount(5.0, "mmol", "g", "NaCl", Some(0.0)), Err(ApiError::Conflict { .. })));
+        assert!(matches!(convert_amount(5.0, "mmol", "mL", "NaCl", Some(58.44)), Err(ApiError::BadRequest(_))));
+    }
+
+    async fn test_app_state() -> web::Data<Arc<AppState>> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('qc', 'qc_admin', 'qc@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Acetonitrile', 'active', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             expiry_date, received_date, status, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'LOT-1', 500, 500, 'mL', '2024-01-31T00:00:00+00:00', \
+             datetime('now'), 'expired', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    fn extend_request(value: serde_json::Value) -> web::Json<ExtendBatchExpiryRequest> {
+        web::Json(serde_json::from_value(value).unwrap())
+    }
+
+    fn batch_path() -> web::Path<(String, String)> {
+        web::Path::from(("r1".to_string(), "b1".to_string()))
+    }
+
+    #[actix_web::test]
+    async fn test_extend_batch_expiry() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+
+        // Новая дата не позже текущей
+        let err = extend_batch_expiry(
+            app_state.clone(),
+            batch_path(),
+            extend_request(serde_json::json!({
+                "new_expiry_date": "2024-01-31T00:00:00Z",
+                "justification": "Re-test passed",
+            })),
+            "qc".to_string(),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        // Пустое обоснование
+        let err = extend_batch_expiry(
+            app_state.clone(),
+            batch_path(),
+            extend_request(serde_json::json!({
+                "new_expiry_date": "2099-01-31T00:00:00Z",
+                "justification": "   ",
+            })),
+            "qc".to_string(),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        extend_batch_expiry(
+            app_state.clone(),
+            batch_path(),
+            extend_request(serde_json::json!({
+                "new_expiry_date": "2099-01-31T00:00:00Z",
+                "justification": "HPLC purity re-test 99.9%",
+                "document_reference": "QC-2024-017",
+            })),
+            "qc".to_string(),
+        ).await.unwrap();
+
+        let (expiry, status): (DateTime<Utc>, String) =
+            sqlx::query_as("SELECT expiry_date, status FROM batches WHERE id = 'b1'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(expiry.format("%Y-%m-%d").to_string(), "2099-01-31");
+        assert_eq!(status, "available");
+
+        let response = get_batch(app_state.clone(), batch_path(), ApiVersion::LATEST).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let extensions = json["data"]["expiry_extensions"].as_array().unwrap();
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions[0]["approved_by_username"], "qc_admin");
+        assert_eq!(extensions[0]["document_reference"], "QC-2024-017");
+        assert!(extensions[0]["old_expiry_date"].as_str().unwrap().starts_with("2024-01-31"));
+
+        // Повторное продление на ту же дату отклоняется
+        let err = extend_batch_expiry(
+            app_state.clone(),
+            batch_path(),
+            extend_request(serde_json::json!({
+                "new_expiry_date": "2099-01-31T00:00:00Z",
+                "justification": "Duplicate",
+            })),
+            "qc".to_string(),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+        assert_eq!(fetch_expiry_extensions(&pool, "b1").await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_coa_required_batches_wait_for_certificate() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        sqlx::query("UPDATE reagents SET coa_required = 1 WHERE id = 'r1'").execute(&pool).await.unwrap();
+
+        let create: CreateBatchRequest = serde_json::from_value(serde_json::json!({
+            "batch_number": "LOT-2",
+            "quantity": 250.0,
+            "unit": "mL",
+        })).unwrap();
+        create_batch(
+            app_state.clone(), web::Path::from("r1".to_string()), web::Json(create), "qc".to_string(), ApiVersion::LATEST,
+        ).await.unwrap();
+
+        let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE batch_number = 'LOT-2'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(batch.status, "awaiting_coa");
+        let (total,): (f64,) = sqlx::query_as("SELECT total_quantity FROM reagents WHERE id = 'r1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(total, 0.0);
+
+        // Расход и ручной перевод статуса отклоняются с кодом для UI
+        let mut conn = pool.acquire().await.unwrap();
+        let err = crate::handlers::record_batch_usage(&mut conn, &batch, "qc", 10.0, None, None, None)
+            .await.unwrap_err();
+        drop(conn);
+        let response = err.error_response();
+        assert_eq!(response.status(), 409);
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["code"], crate::error::BATCH_AWAITING_COA);
+
+        let path = || web::Path::from(("r1".to_string(), batch.id.clone()));
+        let update: UpdateBatchRequest = serde_json::from_value(serde_json::json!({ "status": "available" })).unwrap();
+        let err = update_batch(app_state.clone(), path(), web::Json(update), "qc".to_string(), ApiVersion::LATEST).await.unwrap_err();
+        assert!(matches!(err, ApiError::Conflict { code: crate::error::BATCH_AWAITING_COA, .. }));
+
+        let coa = |reference: &str| web::Json(CoaReceivedRequest { reference_number: reference.to_string() });
+        mark_coa_received(app_state.clone(), path(), coa("COA-2024-118"), "qc".to_string()).await.unwrap();
+
+        let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ?")
+            .bind(&batch.id).fetch_one(&pool).await.unwrap();
+        assert_eq!(batch.status, "available");
+        assert_eq!(batch.coa_reference.as_deref(), Some("COA-2024-118"));
+        assert_eq!(batch.coa_received_by.as_deref(), Some("qc"));
+        let (total,): (f64,) = sqlx::query_as("SELECT total_quantity FROM reagents WHERE id = 'r1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(total, 250.0);
+
+        // Повторно отметить нельзя
+        let err = mark_coa_received(app_state.clone(), path(), coa("COA-2024-119"), "qc".to_string()).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+    }
+
+    #[actix_web::test]
+    async fn test_get_all_batches_with_fields() {
+        let app_state = test_app_state().await;
+
+        let query = web::Query::<BatchQuery>::from_query("fields=id,batch_number,reagent_name,expiry_date").unwrap();
+        let resp = get_all_batches(app_state.clone(), query, ApiVersion::LATEST).await.unwrap();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let row = &json["data"]["data"][0];
+        assert_eq!(row.as_object().unwrap().len(), 4);
+        assert_eq!(row["reagent_name"], "Acetonitrile");
+        assert_eq!(row["batch_number"], "LOT-1");
+        assert_eq!(row["expiry_date"], "2024-01-31T00:00:00Z");
+        assert_eq!(json["data"]["total"], 1);
+
+        let query = web::Query::<BatchQuery>::from_query("fields=id,secret").unwrap();
+        let err = get_all_batches(app_state, query, ApiVersion::LATEST).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref msg) if msg.contains("Valid fields:")));
+    }
+
+    #[actix_web::test]
+    async fn test_get_all_batches_caps_per_page_at_group_max() {
+        let app_state = test_app_state().await;
+        let query = web::Query::<BatchQuery>::from_query("per_page=5000").unwrap();
+        let resp = get_all_batches(app_state.clone(), query, ApiVersion::LATEST).await.unwrap();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"]["per_page"], 100);
+        assert_eq!(json["data"]["capped"], true);
+        assert_eq!(json["data"]["limits"]["max_per_page"], 100);
+
+        let mut config = app_state.config.clone();
+        config.pagination.batches.max = Some(1000);
+        let app_state = web::Data::new(Arc::new(AppState {
+            db_pool: app_state.db_pool.clone(),
+            config,
+            read_replica: None,
+            reference_cache: Default::default(),
+        }));
+        let query = web::Query::<BatchQuery>::from_query("per_page=1000").unwrap();
+        let resp = get_all_batches(app_state, query, ApiVersion::LATEST).await.unwrap();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"]["per_page"], 1000);
+        assert_eq!(json["data"]["capped"], false);
+        assert_eq!(json["data"]["limits"], serde_json::json!({ "default_per_page": 20, "max_per_page": 1000 }));
+    }
+
+    #[actix_web::test]
+    async fn test_get_all_batches_multi_select_filters() {
+        let app_state = test_app_state().await;
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, location, created_at, updated_at) \
+             VALUES ('b2', 'r1', 'LOT-2', 100, 100, 'mL', datetime('now'), 'in_use', 'Fridge A', datetime('now'), datetime('now')), \
+                    ('b3', 'r1', 'LOT-3', 100, 100, 'mL', datetime('now'), 'available', 'Shelf 2', datetime('now'), datetime('now'))"
+        ).execute(&app_state.db_pool).await.unwrap();
+
+        let numbers = |params: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let query = web::Query::<BatchQuery>::from_query(params).unwrap();
+                let resp = get_all_batches(app_state, query, ApiVersion::LATEST).await?;
+                let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+                let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let mut numbers: Vec<String> = json["data"]["data"].as_array().unwrap()
+                    .iter()
+                    .map(|b| b["batch_number"].as_str().unwrap().to_string())
+                    .collect();
+                numbers.sort();
+                Ok::<_, ApiError>(numbers)
+            }
+        };
+        assert_eq!(numbers("status=in_use,expired").await.unwrap(), vec!["LOT-1", "LOT-2"]);
+        assert_eq!(numbers("location=Fridge%20A,Shelf%202").await.unwrap(), vec!["LOT-2", "LOT-3"]);
+        assert_eq!(numbers("status=available&location=Fridge%20A,Shelf%202").await.unwrap(), vec!["LOT-3"]);
+
+        let err = numbers("status=available,lost").await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref msg) if msg.contains("lost") && msg.contains("awaiting_coa")), "{:?}", err);
+    }
+
+    #[actix_web::test]
+    async fn test_batches_for_reagent_paginated_by_expiry_with_summary() {
+        let app_state = test_app_state().await;
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity, unit, \
+             expiry_date, received_date, status, location, created_at, updated_at) \
+             VALUES ('b2', 'r1', 'LOT-2', 0, 100, 0, 'mL', '2030-06-01T00:00:00+00:00', datetime('now'), 'depleted', NULL, datetime('now'), datetime('now')), \
+                    ('b3', 'r1', 'LOT-3', 10, 10, 4, 'mL', NULL, datetime('now'), 'available', 'Fridge A', datetime('now'), datetime('now')), \
+                    ('b4', 'r1', 'LOT-4', 5, 5, 0, 'mL', '2023-01-01T00:00:00+00:00', datetime('now'), 'in_use', 'Fridge A', datetime('now'), datetime('now'))"
+        ).execute(&app_state.db_pool).await.unwrap();
+
+        let list = |params: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let query = web::Query::<ReagentBatchesQuery>::from_query(params).unwrap();
+                let resp = get_batches_for_reagent(app_state, web::Path::from("r1".to_string()), query, ApiVersion::V1).await?;
+                let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+                let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                Ok::<_, ApiError>(json["data"].clone())
+            }
+        };
+        let numbers = |data: &serde_json::Value| -> Vec<String> {
+            data["data"].as_array().unwrap().iter().map(|b| b["batch_number"].as_str().unwrap().to_string()).collect()
+        };
+
+        // Ближайший срок сначала, партии без срока - в конце; шапка не зависит от страницы
+        let first = list("per_page=2").await.unwrap();
+        assert_eq!(numbers(&first), vec!["LOT-4", "LOT-1"]);
+        assert_eq!((first["total"].as_i64(), first["total_pages"].as_i64()), (Some(4), Some(2)));
+        let second = list("per_page=2&page=2").await.unwrap();
+        assert_eq!(numbers(&second), vec!["LOT-2", "LOT-3"]);
+        for data in [&first, &second] {
+            assert_eq!(data["summary"]["batch_count"], 4);
+            assert_eq!(data["summary"]["total_quantity"], 515.0);
+            assert_eq!(data["summary"]["reserved_quantity"], 4.0);
+            assert_eq!(data["summary"]["available_quantity"], 511.0);
+        }
+
+        assert_eq!(numbers(&list("include_empty=false").await.unwrap()), vec!["LOT-4", "LOT-1", "LOT-3"]);
+        assert_eq!(numbers(&list("status=in_use,available&location=Fridge%20A").await.unwrap()), vec!["LOT-4", "LOT-3"]);
+
+        let all = list("per_page=all&page=3").await.unwrap();
+        assert_eq!(numbers(&all).len(), 4);
+        assert_eq!((all["page"].as_i64(), all["per_page"].as_i64()), (Some(1), Some(4)));
+
+        assert!(matches!(list("per_page=lots").await.unwrap_err(), ApiError::BadRequest(_)));
+    }
+
+    #[actix_web::test]
+    async fn test_inactive_reagent_batches_are_read_only() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) \
+             VALUES ('b2', 'r1', 'LOT-2', 100, 100, 'mL', datetime('now'), 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query("UPDATE reagents SET status = 'inactive' WHERE id = 'r1'").execute(&pool).await.unwrap();
+        let is_inactive = |err: &ApiError| matches!(err, ApiError::Conflict { code: crate::error::REAGENT_INACTIVE, .. });
+
+        let create: CreateBatchRequest = serde_json::from_value(serde_json::json!({
+            "batch_number": "LOT-3",
+            "quantity": 250.0,
+            "unit": "mL",
+        })).unwrap();
+        let checks = validate_batch_request(&pool, "r1", &create, None).await.unwrap();
+        assert!(checks.errors["reagent_id"][0].contains("inactive"));
+        let err = create_batch(
+            app_state.clone(), web::Path::from("r1".to_string()), web::Json(create), "qc".to_string(), ApiVersion::LATEST,
+        ).await.unwrap_err();
+        assert!(is_inactive(&err), "{:?}", err);
+
+        // Общий список скрывает партии, карточка реагента - нет
+        let total = |params: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let query = web::Query::<BatchQuery>::from_query(params).unwrap();
+                let resp = get_all_batches(app_state, query, ApiVersion::LATEST).await.unwrap();
+                let json: serde_json::Value =
+                    serde_json::from_slice(&actix_web::body::to_bytes(resp.into_body()).await.unwrap()).unwrap();
+                json["data"]["total"].as_i64().unwrap()
+            }
+        };
+        assert_eq!(total("").await, 0);
+        assert_eq!(total("include_inactive=true").await, 2);
+        let query = web::Query::<ReagentBatchesQuery>::from_query("").unwrap();
+        get_batches_for_reagent(app_state.clone(), web::Path::from("r1".to_string()), query, ApiVersion::V1).await.unwrap();
+
+        let path = || web::Path::from(("r1".to_string(), "b2".to_string()));
+        let update: UpdateBatchRequest = serde_json::from_value(serde_json::json!({ "notes": "moved" })).unwrap();
+        let err = update_batch(app_state.clone(), path(), web::Json(update), "qc".to_string(), ApiVersion::LATEST).await.unwrap_err();
+        assert!(is_inactive(&err), "{:?}", err);
+        let err = extend_batch_expiry(
+            app_state.clone(),
+            batch_path(),
+            extend_request(serde_json::json!({ "new_expiry_date": "2099-01-31T00:00:00Z", "justification": "Re-test passed" })),
+            "qc".to_string(),
+        ).await.unwrap_err();
+        assert!(is_inactive(&err), "{:?}", err);
+
+        let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = 'b2'").fetch_one(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        let err = crate::handlers::record_batch_usage(&mut conn, &batch, "qc", 10.0, None, None, None)
+            .await.unwrap_err();
+        drop(conn);
+        assert!(is_inactive(&err), "{:?}", err);
+        assert_eq!(err.error_response().status(), 409);
+    }
+    #[actix_web::test]
+    async fn test_batch_shape_follows_api_version() {
+        let app_state = test_app_state().await;
+        let batch_json = |version: ApiVersion| {
+            let app_state = app_state.clone();
+            async move {
+                let response = get_batch(app_state, batch_path(), version).await.unwrap();
+                let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()["data"].clone()
+            }
+        };
+
+        let v1 = batch_json(ApiVersion::V1).await;
+        assert_eq!(v1["quantity"], 500.0);
+        assert_eq!(v1["unit"], "mL");
+        assert!(v1.get("stock").is_none());
+
+        let v2 = batch_json(ApiVersion::V2).await;
+        assert_eq!(v2["stock"]["quantity"], 500.0);
+        assert_eq!(v2["stock"]["original_quantity"], 500.0);
+        assert_eq!(v2["stock"]["unit"], "mL");
+        assert!(v2.get("quantity").is_none());
+        assert_eq!(v2["batch_number"], "LOT-1");
+
+        // Проекция `?fields=` тоже следует версии
+        let query = web::Query::<BatchQuery>::from_query("fields=id,quantity,unit").unwrap();
+        let response = get_all_batches(app_state.clone(), query, ApiVersion::V2).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"]["data"][0]["stock"]["quantity"], 500.0);
+    }
+
+    #[actix_web::test]
+    async fn test_update_batch_clears_fields_with_null() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+
+        let update: UpdateBatchRequest = serde_json::from_value(serde_json::json!({
+            "notes": "Opened 2024-02-01", "supplier": "Sigma", "pack_size": 250.0,
+        })).unwrap();
+        update_batch(app_state.clone(), batch_path(), web::Json(update), "qc".to_string(), ApiVersion::LATEST).await.unwrap();
+
+        // null очищает notes и pack_size; supplier не передан и остаётся
+        let update: UpdateBatchRequest = serde_json::from_value(serde_json::json!({
+            "notes": null, "pack_size": null, "quantity": 480,
+        })).unwrap();
+        update_batch(app_state.clone(), batch_path(), web::Json(update), "qc".to_string(), ApiVersion::LATEST).await.unwrap();
+
+        let row: (Option<String>, Option<String>, Option<f64>, String) = sqlx::query_as(
+            "SELECT notes, supplier, pack_size, typeof(quantity) FROM batches WHERE id = 'b1'"
+        ).fetch_one(&pool).await.unwrap();
+        assert_eq!(row, (None, Some("Sigma".to_string()), None, "real".to_string()));
+    }
+
+    async fn query_plan(pool: &sqlx::SqlitePool, sql: &str, params: &[&str]) -> String {
+        let explain = format!("EXPLAIN QUERY PLAN {}", sql);
+        let mut query = sqlx::query_as::<_, (i64, i64, i64, String)>(&explain);
+        for param in params {
+            query = query.bind(*param);
+        }
+        query.fetch_all(pool).await.unwrap()
+            .into_iter()
+            .map(|(_, _, _, detail)| detail)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    async fn response_ids(response: HttpResponse) -> Vec<String> {
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        json["data"].as_array().unwrap().iter().map(|b| b["id"].as_str().unwrap().to_string()).collect()
+    }
+
+    /// 100 000 партий: каждая тысячная истекает через 10 дней, каждая тысячная со сдвигом почти пуста,
+    /// треть израсходована и в выборки не попадает
+    async fn seed_large_batch_table(pool: &sqlx::SqlitePool) {
+        let soon = (Utc::now() + chrono::Duration::days(10)).to_rfc3339();
+        sqlx::query(
+            "WITH RECURSIVE seq(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM seq WHERE n < 100000) \
+             INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             expiry_date, received_date, status, created_at, updated_at) \
+             SELECT 'p' || n, 'r1', 'P-' || n, CASE WHEN n % 1000 = 500 THEN 5 ELSE 500 END, 500, 'mL', \
+             CASE WHEN n % 1000 = 0 THEN ? ELSE '2099-01-01T00:00:00+00:00' END, datetime('now'), \
+             CASE WHEN n % 3 = 1 THEN 'depleted' ELSE 'available' END, datetime('now'), datetime('now') \
+             FROM seq"
+        ).bind(&soon).execute(pool).await.unwrap();
+        sqlx::query("ANALYZE").execute(pool).await.unwrap();
+    }
+
+    async fn dashboard_batch_ids(app_state: &web::Data<Arc<AppState>>) -> (Vec<String>, Vec<String>) {
+        let query = web::Query(ExpiringQuery { days: Some(30), expiry_policy: None });
+        let expiring = response_ids(get_expiring_batches(app_state.clone(), query, ApiVersion::LATEST).await.unwrap()).await;
+        let query = web::Query(LowStockQuery { threshold: Some(20.0) });
+        let low_stock = response_ids(get_low_stock_batches(app_state.clone(), query, ApiVersion::LATEST).await.unwrap()).await;
+        (expiring, low_stock)
+    }
+
+    #[actix_web::test]
+    async fn test_expiring_and_low_stock_use_indexes_on_large_table() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        seed_large_batch_table(&pool).await;
+
+        let cutoff = (Utc::now() + chrono::Duration::days(30)).to_rfc3339();
+        let plan = query_plan(&pool, EXPIRING_BATCHES_SQL, &[&cutoff, "strict"]).await;
+        assert!(plan.contains("SEARCH b USING INDEX idx_batches_status_expiry (status=? AND expiry_date>? AND expiry_date<?)"), "{}", plan);
+        assert!(!plan.contains("TEMP B-TREE"), "{}", plan);
+        let plan = query_plan(&pool, LOW_STOCK_BATCHES_SQL, &["0.2"]).await;
+        assert!(plan.contains("SEARCH b USING INDEX idx_batches_low_stock"), "{}", plan);
+        assert!(!plan.contains("TEMP B-TREE"), "{}", plan);
+
+        // Доступные среди n % 1000 = 0 (66 из 100) и n % 1000 = 500 (67 из 100)
+        let (expiring, low_stock) = dashboard_batch_ids(&app_state).await;
+        assert_eq!(expiring.len(), 66);
+        assert!(expiring.iter().all(|id| id.ends_with("000")));
+        assert_eq!(low_stock.len(), 67);
+        assert!(low_stock.iter().all(|id| id.ends_with("500")));
+    }
+
+    /// Время зависит от машины, поэтому только вручную: `cargo test -- --ignored bench_dashboard`
+    #[actix_web::test]
+    #[ignore]
+    async fn bench_dashboard_queries_on_large_table() {
+        let app_state = test_app_state().await;
+        seed_large_batch_table(&app_state.db_pool).await;
+
+        let started = std::time::Instant::now();
+        dashboard_batch_ids(&app_state).await;
+        let elapsed = started.elapsed();
+        assert!(elapsed < std::time::Duration::from_millis(500), "dashboard queries took {:?}", elapsed);
+    }
+
+    #[actix_web::test]
+    async fn test_batch_containers_are_validated_and_consumed() {
+        let app = TestApp::new().await;
+        let batches_path = format!("/reagents/{}/batches", NACL_ID);
+
+        // 3 x 100 g не сходится с 250 g
+        let (status, body) = app.post(UserRole::Researcher, &batches_path, json!({
+            "batch_number": "NACL-BAD", "quantity": 250.0, "unit": "g",
+            "container_size": 100.0, "container_count": 3,
+        })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+
+        let (status, body) = app.post(UserRole::Researcher, &batches_path, json!({
+            "batch_number": "NACL-BOTTLES", "quantity": 300.0, "unit": "g",
+            "container_size": 100.0, "container_count": 3,
+        })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        let batch_id = body["data"]["id"].as_str().unwrap().to_string();
+        let use_path = format!("{}/{}/use", batches_path, batch_id);
+
+        let (status, body) = app.post(UserRole::Researcher, &use_path, json!({ "quantity_used": 30.0 })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        let (status, body) = app.post(UserRole::Researcher, &use_path, json!({ "containers_used": 1 })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        assert_eq!(app.batch_stock(&batch_id).await.0, 170.0);
+
+        let (_, body) = app.get(UserRole::Viewer, &format!("{}/{}", batches_path, batch_id)).await;
+        assert_eq!(body["data"]["stock"]["container_count"], 1, "{}", body);
+        assert_eq!(body["data"]["stock"]["open_containers"], 1, "{}", body);
+
+        // Запечатанная упаковка осталась одна
+        let (status, _) = app.post(UserRole::Researcher, &use_path, json!({ "containers_used": 2 })).await;
+        assert!(status.is_client_error());
+        assert_eq!(app.batch_stock(&batch_id).await.0, 170.0);
+    }
+
+    /// Дочерние записи под фикстурными родителями и «чужие» родители для подмены пути
+    async fn seed_nested_children(app: &TestApp) {
+        for sql in [
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at) VALUES \
+             ('eq-other', 'Balance', 'equipment', 1, 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO equipment_parts (id, equipment_id, name, created_at, updated_at) VALUES \
+             ('part-1', 'fx-equipment-centrifuge', 'Rotor', datetime('now'), datetime('now'))",
+            "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, scheduled_date, created_at, updated_at) VALUES \
+             ('mnt-1', 'fx-equipment-centrifuge', 'calibration', '2030-01-01', datetime('now'), datetime('now'))",
+            "INSERT INTO equipment_files (id, equipment_id, original_filename, stored_filename, file_path, file_size, \
+             mime_type, created_at) VALUES \
+             ('file-1', 'fx-equipment-centrifuge', 'manual.pdf', 'manual.pdf', '/nonexistent/manual.pdf', 10, \
+              'application/pdf', datetime('now'))",
+            "INSERT INTO batch_placements (id, batch_id, room_id, quantity, created_at, updated_at) VALUES \
+             ('pl-1', 'fx-batch-ethanol-1', 'fx-room-lab-1', 10.0, datetime('now'), datetime('now'))",
+            "INSERT INTO rooms (id, name, capacity, status, created_at, updated_at) VALUES \
+             ('room-other', 'Lab 2', 10, 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO kiosk_tokens (id, room_id, label, token_hash, token_hint, scopes, created_at) VALUES \
+             ('kt-1', 'fx-room-lab-1', 'Door', 'hash-1', 'abcd', 'equipment', datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, created_by, created_at, updated_at) VALUES \
+             ('exp-other', 'Extraction', datetime('now'), datetime('now'), 'in_progress', 'fx-researcher', datetime('now'), datetime('now'))",
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at) VALUES \
+             ('er-1', 'fx-experiment-titration', 'fx-reagent-ethanol', 'fx-batch-ethanol-1', 5.0, 'ml', datetime('now'), datetime('now'))",
+            "INSERT INTO experiment_participants (id, experiment_id, name, role, created_at) VALUES \
+             ('pt-1', 'fx-experiment-titration', 'Student A', 'student', datetime('now'))",
+            "INSERT INTO external_links (id, entity_type, entity_id, title, url, created_at) VALUES \
+             ('lnk-exp', 'experiment', 'fx-experiment-titration', 'Protocol', 'https://example.com/p', datetime('now')), \
+             ('lnk-eq', 'equipment', 'fx-equipment-centrifuge', 'Manual', 'https://example.com/m', datetime('now')), \
+             ('lnk-batch', 'batch', 'fx-batch-ethanol-1', 'COA', 'https://example.com/c', datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&app.pool).await.unwrap();
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_nested_paths_reject_foreign_parent_with_child_404() {
+        let app = TestApp::new().await;
+        seed_nested_children(&app).await;
+
+        let batch = |reagent: &str, suffix: &str| format!("/reagents/{}/batches/{}{}", reagent, ETHANOL_BATCH_ID, suffix);
+        let cases: Vec<(Method, String, Option<Value>, &str)> = vec![
+            (Method::GET, batch(NACL_ID, ""), None, "Batch"),
+            (Method::GET, batch("missing", ""), None, "Batch"),
+            (Method::PUT, batch(NACL_ID, ""), Some(json!({ "notes": "x" })), "Batch"),
+            (Method::DELETE, batch(NACL_ID, ""), None, "Batch"),
+            (Method::PUT, batch(NACL_ID, "/barcode"), Some(json!({ "barcode": "X-1" })), "Batch"),
+            (Method::POST, batch(NACL_ID, "/extend-expiry"),
+             Some(json!({ "new_expiry_date": "2031-01-01T00:00:00Z", "justification": "QC" })), "Batch"),
+            (Method::POST, batch(NACL_ID, "/coa-received"), Some(json!({ "reference_number": "COA-1" })), "Batch"),
+            (Method::POST, batch(NACL_ID, "/use"), Some(json!({ "quantity_used": 1.0 })), "Batch"),
+            (Method::GET, batch(NACL_ID, "/usage"), None, "Batch"),
+            (Method::POST, batch(NACL_ID, "/dispense-units"), Some(json!({ "units_to_dispense": 1 })), "Batch"),
+            (Method::GET, batch(NACL_ID, "/units-info"), None, "Batch"),
+            (Method::PUT, format!("/batches/{}/placements/pl-1", NACL_BATCH_ID), Some(json!({ "notes": "x" })), "Placement"),
+            (Method::DELETE, format!("/batches/{}/placements/pl-1", NACL_BATCH_ID), None, "Placement"),
+            (Method::DELETE, format!("/batches/{}/links/lnk-batch", NACL_BATCH_ID), None, "Link"),
+            (Method::DELETE, format!("/reagents/{}/links/lnk-batch", ETHANOL_ID), None, "Link"),
+            (Method::PUT, "/equipment/eq-other/parts/part-1".to_string(), Some(json!({ "notes": "x" })), "Equipment part"),
+            (Method::PUT, "/equipment/missing/parts/part-1".to_string(), Some(json!({ "notes": "x" })), "Equipment part"),
+            (Method::DELETE, "/equipment/eq-other/parts/part-1".to_string(), None, "Equipment part"),
+            (Method::GET, "/equipment/eq-other/parts/part-1/files".to_string(), None, "Equipment part"),
+            (Method::GET, format!("/equipment/{}/parts/missing/files", CENTRIFUGE_ID), None, "Equipment part"),
+            (Method::PUT, "/equipment/eq-other/maintenance/mnt-1".to_string(), Some(json!({ "notes": "x" })), "Maintenance record"),
+            (Method::POST, "/equipment/eq-other/maintenance/mnt-1/complete".to_string(), Some(json!({})), "Maintenance record"),
+            (Method::DELETE, "/equipment/eq-other/maintenance/mnt-1".to_string(), None, "Maintenance record"),
+            (Method::GET, "/equipment/eq-other/files/file-1".to_string(), None, "File"),
+            (Method::GET, "/equipment/missing/files/file-1".to_string(), None, "File"),
+            (Method::DELETE, "/equipment/eq-other/files/file-1".to_string(), None, "File"),
+            (Method::DELETE, "/equipment/eq-other/links/lnk-eq".to_string(), None, "Link"),
+            (Method::DELETE, "/rooms/room-other/kiosk-tokens/kt-1".to_string(), None, "Kiosk token"),
+            (Method::DELETE, "/experiments/exp-other/reagents/er-1".to_string(), None, "Experiment reagent"),
+            (Method::POST, "/experiments/exp-other/reagents/er-1/consume".to_string(), Some(json!({})), "Experiment reagent"),
+            (Method::DELETE, "/experiments/exp-other/participants/pt-1".to_string(), None, "Experiment participant"),
+            (Method::DELETE, "/experiments/exp-other/links/lnk-exp".to_string(), None, "Link"),
+        ];
+        for (method, path, body, child) in cases {
+            let (status, response) = app.request(UserRole::Admin, method.clone(), &path, body).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{} {}: {}", method, path, response);
+            assert!(response.to_string().contains(&format!("{} not found", child)), "{} {}: {}", method, path, response);
+        }
+
+        // Подмена пути ничего не изменила
+        assert_eq!(app.batch_stock(ETHANOL_BATCH_ID).await, (1000.0, 0.0));
+        let remaining: i64 = sqlx::query_scalar(
+            "SELECT (SELECT COUNT(*) FROM equipment_parts) + (SELECT COUNT(*) FROM equipment_maintenance) \
+             + (SELECT COUNT(*) FROM equipment_files) + (SELECT COUNT(*) FROM batch_placements) \
+             + (SELECT COUNT(*) FROM experiment_reagents) + (SELECT COUNT(*) FROM experiment_participants) \
+             + (SELECT COUNT(*) FROM external_links) + (SELECT COUNT(*) FROM kiosk_tokens WHERE revoked_at IS NULL)"
+        ).fetch_one(&app.pool).await.unwrap();
+        assert_eq!(remaining, 10);
+
+        // Правильный родитель по-прежнему работает
+        let (status, body) = app.get(UserRole::Viewer, &batch(ETHANOL_ID, "")).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    #[actix_web::test]
+    async fn test_expiry_policy_shapes_shelf_life_alerts_and_reports() {
+        let app = TestApp::new().await;
+
+        let (status, _) = app.post(UserRole::Admin, "/reagents", json!({ "name": "Buffer X", "expiry_policy": "sometimes" })).await;
+        assert!(status.is_client_error());
+
+        let mut batch_ids = Vec::new();
+        for (name, policy) in [("Buffer A", "advisory"), ("Sodium sulfate", "none")] {
+            let (status, body) = app.post(UserRole::Admin, "/reagents", json!({
+                "name": name, "default_shelf_life_days": 30, "expiry_policy": policy,
+            })).await;
+            assert!(status.is_success(), "{}: {}", status, body);
+            let reagent_id = body["data"]["id"].as_str().unwrap().to_string();
+
+            // Срок годности не передан - received_date + 30 дней
+            let (status, body) = app.post(UserRole::Researcher, &format!("/reagents/{}/batches", reagent_id), json!({
+                "batch_number": format!("{}-1", policy), "quantity": 100.0, "unit": "g",
+                "received_date": "2030-01-01T00:00:00Z",
+            })).await;
+            assert!(status.is_success(), "{}: {}", status, body);
+            assert!(body["data"]["expiry_date"].as_str().unwrap().starts_with("2030-01-31"), "{}", body);
+            batch_ids.push(body["data"]["id"].as_str().unwrap().to_string());
+        }
+        let (advisory_batch, untracked_batch) = (batch_ids[0].clone(), batch_ids[1].clone());
+
+        sqlx::query("UPDATE batches SET expiry_date = datetime('now', '+3 days')")
+            .execute(&app.pool).await.unwrap();
+        crate::alert_handlers::refresh_alerts(&app.pool, &Default::default()).await.unwrap();
+        let alerted: Vec<String> = sqlx::query_scalar("SELECT entity_id FROM alerts WHERE kind = 'batch_expiring'")
+            .fetch_all(&app.pool).await.unwrap();
+        assert!(alerted.iter().any(|id| id == ETHANOL_BATCH_ID));
+        assert!(!alerted.contains(&advisory_batch) && !alerted.contains(&untracked_batch));
+
+        let ids = |rows: &Value| -> Vec<String> {
+            rows.as_array().unwrap().iter().map(|r| r["id"].as_str().unwrap().to_string()).collect()
+        };
+        let (_, body) = app.get(UserRole::Viewer, "/batches/expiring").await;
+        assert!(ids(&body["data"]).contains(&ETHANOL_BATCH_ID.to_string()));
+        assert!(!ids(&body["data"]).contains(&advisory_batch) && !ids(&body["data"]).contains(&untracked_batch));
+        let (_, body) = app.get(UserRole::Viewer, "/batches/expiring?expiry_policy=advisory").await;
+        assert_eq!(ids(&body["data"]), vec![advisory_batch.clone()]);
+        let (status, _) = app.get(UserRole::Viewer, "/batches/expiring?expiry_policy=none").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = app.post(UserRole::Admin, "/reports/generate", json!({ "preset": "expiring_soon" })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        let main = ids(&body["data"]["data"]);
+        assert!(main.contains(&ETHANOL_BATCH_ID.to_string()) && !main.contains(&advisory_batch) && !main.contains(&untracked_batch));
+        assert_eq!(ids(&body["data"]["advisory"]), vec![advisory_batch]);
+    }
+
+    #[actix_web::test]
+    async fn test_molar_amounts_convert_to_mass() {
+        let app = TestApp::new().await;
+        let add_path = format!("/experiments/{}/reagents", EXPERIMENT_ID);
+
+        // Без молярной массы пересчёт mmol -> g невозможен
+        let (status, body) = app.post(UserRole::Researcher, &add_path, json!({
+            "batch_id": NACL_BATCH_ID, "quantity_used": 100, "unit": "mmol",
+        })).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+        assert_eq!(body["code"], crate::error::MOLECULAR_WEIGHT_REQUIRED);
+
+        let (status, body) = app.put(UserRole::Researcher, &format!("/reagents/{}", NACL_ID), json!({
+            "molecular_weight": 58.44,
+        })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+
+        let (status, body) = app.post(UserRole::Viewer, "/units/convert", json!({
+            "quantity": 100, "from_unit": "mmol", "to_unit": "mg", "reagent_id": NACL_ID,
+        })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!((body["data"]["converted_quantity"].as_f64().unwrap() - 5844.0).abs() < 1e-6);
+        let (status, _) = app.post(UserRole::Viewer, "/units/convert", json!({
+            "quantity": 100, "from_unit": "mmol", "to_unit": "g",
+        })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Резерв в граммах партии, введённое количество сохраняется
+        let (status, body) = app.post(UserRole::Researcher, &add_path, json!({
+            "batch_id": NACL_BATCH_ID, "quantity_used": 100, "unit": "mmol",
+        })).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        let (_, reserved) = app.batch_stock(NACL_BATCH_ID).await;
+        assert!((reserved - 5.844).abs() < 1e-9, "{}", reserved);
+
+        let (status, body) = app.get(UserRole::Viewer, &add_path).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let line = &body["data"][0];
+        assert_eq!((line["entered_quantity"].as_f64(), line["entered_unit"].as_str()), (Some(100.0), Some("mmol")));
+        assert_eq!(line["unit"], "g");
+
+        // Мольная единица не пересчитывается в объём
+        let (status, _) = app.post(UserRole::Researcher, &add_path, json!({
+            "batch_id": ETHANOL_BATCH_ID, "quantity_used": 10, "unit": "mmol",
+        })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Расход в молях: списание в граммах, в истории - исходная единица
+        let use_path = format!("/reagents/{}/batches/{}", NACL_ID, NACL_BATCH_ID);
+        let (status, body) = app.post(UserRole::Researcher, &format!("{}/use", use_path), json!({
+            "quantity_used": 0.5, "unit": "mol",
+        })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!((app.batch_stock(NACL_BATCH_ID).await.0 - (500.0 - 29.22)).abs() < 1e-9);
+
+        let (status, body) = app.get(UserRole::Viewer, &format!("{}/usage", use_path)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let entry = &body["data"]["data"][0];
+        assert!((entry["quantity_used"].as_f64().unwrap() - 29.22).abs() < 1e-9);
+        assert_eq!((entry["entered_quantity"].as_f64(), entry["entered_unit"].as_str()), (Some(0.5), Some("mol")));
+    }
+}
@@ -0,0 +1,516 @@
+// src/calendar_handlers.rs
+//! Общий календарь: эксперименты, окна обслуживания оборудования и бронирования помещений
+//! одним списком с полем `kind`.
+//!
+//! Отдельной таблицы бронирований нет (см. загрузку помещений в room_handlers), поэтому
+//! бронирование - это занятость помещения экспериментом: событие `reservation` с именем
+//! и цветом помещения для слоя помещений в календаре. Окна обслуживания привязываются
+//! к помещению по расположению оборудования (equipment.location = имя помещения).
+//! Нерабочее время помещений (`closed`) отдаётся фоновыми событиями (`display: "background"`)
+//! и строится только для периодов до CLOSED_PERIODS_MAX_DAYS дней.
+//! Каждый вид ограничен CALENDAR_KIND_LIMIT событиями; обрезанные виды перечисляются в `truncated`.
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+use crate::equipment_handlers::whole_day_window;
+use crate::error::{ApiError, ApiResult};
+use crate::experiment_handlers::{
+    calendar_event_end, fetch_calendar_experiments, CalendarEvent, CalendarQuery, CALENDAR_KIND_LIMIT,
+};
+use crate::handlers::ApiResponse;
+use crate::models::Room;
+use crate::room_handlers::OpeningHours;
+use crate::AppState;
+
+/// Дольше этого периода нерабочее время не строится, а вид `closed` попадает в `truncated`
+const CLOSED_PERIODS_MAX_DAYS: i64 = 93;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CalendarKind {
+    Experiments,
+    Maintenance,
+    Reservations,
+    Closed,
+}
+
+impl CalendarKind {
+    const ALL: [CalendarKind; 4] = [
+        CalendarKind::Experiments,
+        CalendarKind::Maintenance,
+        CalendarKind::Reservations,
+        CalendarKind::Closed,
+    ];
+
+    fn parse(value: &str) -> ApiResult<Self> {
+        match value.trim() {
+            "experiments" => Ok(CalendarKind::Experiments),
+            "maintenance" => Ok(CalendarKind::Maintenance),
+            "reservations" => Ok(CalendarKind::Reservations),
+            "closed" => Ok(CalendarKind::Closed),
+            other => Err(ApiError::bad_request(&format!(
+                "Unknown calendar kind '{}'. Valid kinds: experiments, maintenance, reservations, closed", other
+            ))),
+        }
+    }
+
+    /// `?include=experiments,maintenance`; по умолчанию - все виды
+    fn parse_list(include: Option<&str>) -> ApiResult<Vec<Self>> {
+        let Some(include) = include.filter(|s| !s.trim().is_empty()) else {
+            return Ok(Self::ALL.to_vec());
+        };
+        let mut kinds = Vec::new();
+        for kind in include.split(',').map(Self::parse) {
+            let kind = kind?;
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        Ok(kinds)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceCalendarEvent {
+    pub id: String,
+    pub equipment_id: String,
+    pub title: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub all_day: bool,
+    pub status: String,
+    pub maintenance_type: String,
+    pub room_id: Option<String>,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReservationCalendarEvent {
+    /// id эксперимента, занимающего помещение
+    pub id: String,
+    pub room_id: String,
+    /// Имя помещения
+    pub title: String,
+    pub experiment_title: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub all_day: bool,
+    pub status: String,
+    pub color: Option<String>,
+}
+
+/// Нерабочее время помещения (ночи и выходные объединены)
+#[derive(Debug, Serialize)]
+pub struct ClosedCalendarEvent {
+    pub room_id: String,
+    /// "Closed: <имя помещения>"
+    pub title: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Всегда "background": календарь рисует период фоном, а не отдельным событием
+    pub display: &'static str,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CalendarItem {
+    Experiment(CalendarEvent),
+    Maintenance(MaintenanceCalendarEvent),
+    Reservation(ReservationCalendarEvent),
+    Closed(ClosedCalendarEvent),
+}
+
+impl CalendarItem {
+    fn start(&self) -> DateTime<Utc> {
+        match self {
+            CalendarItem::Experiment(e) => e.start,
+            CalendarItem::Maintenance(m) => m.start,
+            CalendarItem::Reservation(r) => r.start,
+            CalendarItem::Closed(c) => c.start,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CalendarFeed {
+    pub events: Vec<CalendarItem>,
+    /// Виды, у которых событий в периоде больше предела
+    pub truncated: Vec<CalendarKind>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CombinedCalendarQuery {
+    pub include: Option<String>,
+    #[serde(flatten)]
+    pub calendar: CalendarQuery,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct MaintenanceRow {
+    id: String,
+    equipment_id: String,
+    equipment_name: String,
+    maintenance_type: String,
+    status: String,
+    scheduled_date: String,
+    scheduled_start: Option<DateTime<Utc>>,
+    scheduled_end: Option<DateTime<Utc>>,
+    room_id: Option<String>,
+    color: Option<String>,
+}
+
+/// Окна обслуживания, пересекающиеся с периодом (отменённые не показываются)
+async fn fetch_maintenance_events(
+    pool: &SqlitePool,
+    query: &CalendarQuery,
+    limit: i64,
+) -> ApiResult<Vec<MaintenanceCalendarEvent>> {
+    let (range_start, range_end) = query.range()?;
+
+    let rows: Vec<MaintenanceRow> = sqlx::query_as(
+        r#"SELECT m.id, m.equipment_id, e.name AS equipment_name, m.maintenance_type, m.status,
+                  m.scheduled_date, m.scheduled_start, m.scheduled_end, r.id AS room_id, r.color
+           FROM equipment_maintenance m
+           JOIN equipment e ON e.id = m.equipment_id
+           LEFT JOIN rooms r ON r.name = e.location COLLATE NOCASE
+           WHERE m.status != 'cancelled'
+             AND e.pending_deletion_id IS NULL
+             AND datetime(COALESCE(m.scheduled_start, m.scheduled_date)) < datetime(?)
+             AND datetime(COALESCE(m.scheduled_end, datetime(m.scheduled_date, '+1 day'))) > datetime(?)
+             AND (? IS NULL OR r.id = ?)
+           ORDER BY datetime(COALESCE(m.scheduled_start, m.scheduled_date)) ASC
+           LIMIT ?"#
+    )
+        .bind(range_end)
+        .bind(range_start)
+        .bind(&query.room_id)
+        .bind(&query.room_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let (start, end) = match (row.scheduled_start, row.scheduled_end) {
+                (Some(start), Some(end)) => (start, end),
+                _ => whole_day_window(&row.scheduled_date)?,
+            };
+            let (end, all_day) = calendar_event_end(start, Some(end));
+            Some(MaintenanceCalendarEvent {
+                title: format!("{}: {}", row.maintenance_type, row.equipment_name),
+                id: row.id,
+                equipment_id: row.equipment_id,
+                start,
+                end,
+                all_day,
+                status: row.status,
+                maintenance_type: row.maintenance_type,
+                room_id: row.room_id,
+                color: row.color,
+            })
+        })
+        .collect())
+}
+
+/// Нерабочее время помещений в периоде по их часам работы, по возрастанию начала
+async fn fetch_closed_periods(
+    pool: &SqlitePool,
+    query: &CalendarQuery,
+    range_start: DateTime<Utc>,
+    range_end: DateTime<Utc>,
+) -> ApiResult<Vec<ClosedCalendarEvent>> {
+    let rooms: Vec<Room> = sqlx::query_as(
+        "SELECT * FROM rooms WHERE (? IS NULL OR id = ?) ORDER BY name"
+    )
+        .bind(&query.room_id)
+        .bind(&query.room_id)
+        .fetch_all(pool)
+        .await?;
+
+    let mut events: Vec<ClosedCalendarEvent> = rooms
+        .iter()
+        .flat_map(|room| {
+            OpeningHours::from_room(room)
+                .closed_periods(range_start, range_end)
+                .into_iter()
+                .map(move |(start, end)| ClosedCalendarEvent {
+                    room_id: room.id.clone(),
+                    title: format!("Closed: {}", room.name),
+                    start,
+                    end,
+                    display: "background",
+                    color: room.color.clone(),
+                })
+        })
+        .collect();
+    events.sort_by_key(|event| event.start);
+    Ok(events)
+}
+
+/// Отбросить лишнее сверх предела и отметить вид как обрезанный
+fn cap<T>(mut items: Vec<T>, limit: i64, kind: CalendarKind, truncated: &mut Vec<CalendarKind>) -> Vec<T> {
+    if items.len() as i64 > limit {
+        items.truncate(limit as usize);
+        truncated.push(kind);
+    }
+    items
+}
+
+async fn build_calendar(
+    pool: &SqlitePool,
+    query: &CalendarQuery,
+    kinds: &[CalendarKind],
+    limit: i64,
+) -> ApiResult<CalendarFeed> {
+    // Проверка параметров до запросов, чтобы ошибка не зависела от набора видов
+    query.range()?;
+    query.experiment_type()?;
+
+    let mut events = Vec::new();
+    let mut truncated = Vec::new();
+    // Запрашиваем на одну строку больше предела, чтобы узнать об обрезке
+    for &kind in kinds {
+        match kind {
+            CalendarKind::Experiments => {
+                let rows = fetch_calendar_experiments(pool, query, false, limit + 1).await?;
+                events.extend(
+                    cap(rows, limit, kind, &mut truncated)
+                        .into_iter()
+                        .map(|row| CalendarItem::Experiment(row.into())),
+                );
+            }
+            CalendarKind::Maintenance => {
+                let items = fetch_maintenance_events(pool, query, limit + 1).await?;
+                events.extend(cap(items, limit, kind, &mut truncated).into_iter().map(CalendarItem::Maintenance));
+            }
+            CalendarKind::Reservations => {
+                let rows = fetch_calendar_experiments(pool, query, true, limit + 1).await?;
+                events.extend(cap(rows, limit, kind, &mut truncated).into_iter().filter_map(|row| {
+                    let (end, all_day) = calendar_event_end(row.start, row.end_date);
+                    Some(CalendarItem::Reservation(ReservationCalendarEvent {
+                        room_id: row.room_id?,
+                        title: row.room_name.unwrap_or_default(),
+                        id: row.id,
+                        experiment_title: row.title,
+                        start: row.start,
+                        end,
+                        all_day,
+                        status: row.status,
+                        color: row.color,
+                    }))
+                }));
+            }
+            CalendarKind::Closed => {
+                let (range_start, range_end) = query.range()?;
+                if (range_end - range_start).num_days() > CLOSED_PERIODS_MAX_DAYS {
+                    truncated.push(kind);
+                    continue;
+                }
+                let items = fetch_closed_periods(pool, query, range_start, range_end).await?;
+                events.extend(cap(items, limit, kind, &mut truncated).into_iter().map(CalendarItem::Closed));
+            }
+        }
+    }
+
+    events.sort_by_key(CalendarItem::start);
+    Ok(CalendarFeed { events, truncated })
+}
+
+/// GET /calendar?include=experiments,maintenance,reservations,closed&start=&end=&room_id=&experiment_type=
+pub async fn get_calendar(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<CombinedCalendarQuery>,
+) -> ApiResult<HttpResponse> {
+    let kinds = CalendarKind::parse_list(query.include.as_deref())?;
+    let feed = build_calendar(&app_state.db_pool, &query.calendar, &kinds, CALENDAR_KIND_LIMIT).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(feed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+             VALUES ('u-1', 'u-1', 'u-1@example.com', 'x', 'researcher', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO rooms (id, name, color, status, created_at, updated_at) VALUES
+             ('room-a', 'Lab A', '#ff0000', 'available', datetime('now'), datetime('now')),
+             ('room-b', 'Lab B', '#00ff00', 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        // (id, start, end, status, type, room_id, location)
+        let experiments = [
+            ("exp-1", "2026-03-02T10:00:00Z", Some("2026-03-02T12:00:00Z"), "planned", "educational", Some("room-a"), None),
+            ("exp-2", "2026-03-03T00:00:00Z", None, "planned", "research", None, Some("Lab B")),
+            ("exp-3", "2026-03-04T09:00:00Z", None, "planned", "research", None, None),
+            ("exp-4", "2026-04-01T09:00:00Z", None, "planned", "research", Some("room-a"), None),
+            // Черновики в календарь не попадают
+            ("exp-5", "2026-03-05T09:00:00Z", None, "draft", "research", Some("room-a"), None),
+        ];
+        for (id, start, end, status, experiment_type, room_id, location) in experiments {
+            sqlx::query(
+                "INSERT INTO experiments (id, title, experiment_date, start_date, end_date, status, experiment_type,
+                                          room_id, location, created_by, created_at, updated_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, 'u-1', datetime('now'), datetime('now'))"
+            )
+                .bind(id).bind(format!("Experiment {}", id)).bind(start).bind(start).bind(end)
+                .bind(status).bind(experiment_type).bind(room_id).bind(location)
+                .execute(&pool).await.unwrap();
+        }
+
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, location, created_at, updated_at)
+             VALUES ('eq-1', 'Centrifuge', 'equipment', 1, 'available', 'lab a', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, status, scheduled_date, created_at, updated_at) VALUES
+             ('m-1', 'eq-1', 'calibration', 'scheduled', '2026-03-05', datetime('now'), datetime('now')),
+             ('m-2', 'eq-1', 'repair', 'cancelled', '2026-03-06', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        pool
+    }
+
+    fn march(room_id: Option<&str>, experiment_type: Option<&str>) -> CalendarQuery {
+        CalendarQuery {
+            start: Some("2026-03-01".to_string()),
+            end: Some("2026-03-31".to_string()),
+            room_id: room_id.map(str::to_string),
+            experiment_type: experiment_type.map(str::to_string),
+        }
+    }
+
+    /// Все виды, кроме нерабочего времени
+    const BOOKINGS: [CalendarKind; 3] = [CalendarKind::Experiments, CalendarKind::Maintenance, CalendarKind::Reservations];
+
+    fn summary(feed: &CalendarFeed) -> Vec<String> {
+        feed.events
+            .iter()
+            .map(|item| match item {
+                CalendarItem::Experiment(e) => format!("experiment:{}", e.id),
+                CalendarItem::Maintenance(m) => format!("maintenance:{}", m.id),
+                CalendarItem::Reservation(r) => format!("reservation:{}:{}", r.id, r.title),
+                CalendarItem::Closed(c) => format!("closed:{}:{}..{}", c.room_id, c.start.format("%d %H:%M"), c.end.format("%d %H:%M")),
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_combined_calendar_merges_kinds_with_room_colors() {
+        let pool = setup().await;
+
+        let feed = build_calendar(&pool, &march(None, None), &BOOKINGS, CALENDAR_KIND_LIMIT).await.unwrap();
+        assert_eq!(summary(&feed), vec![
+            "experiment:exp-1", "reservation:exp-1:Lab A",
+            "experiment:exp-2", "reservation:exp-2:Lab B",
+            "experiment:exp-3",
+            "maintenance:m-1",
+        ]);
+        assert!(feed.truncated.is_empty());
+
+        let json = serde_json::to_value(&feed).unwrap();
+        let exp1 = &json["events"][0];
+        assert_eq!(exp1["kind"], "experiment");
+        assert_eq!(exp1["color"], "#ff0000");
+        assert_eq!(exp1["all_day"], false);
+        assert_eq!(exp1["end"], "2026-03-02T12:00:00Z");
+        // Без end_date: с полуночи - весь день
+        let exp2 = &json["events"][2];
+        assert_eq!(exp2["room_id"], "room-b");
+        assert_eq!(exp2["all_day"], true);
+        assert_eq!(exp2["end"], "2026-03-04T00:00:00Z");
+        // Без end_date и не с полуночи - длительность по умолчанию
+        let exp3 = &json["events"][4];
+        assert_eq!(exp3["all_day"], false);
+        assert_eq!(exp3["end"], "2026-03-04T11:00:00Z");
+        let maintenance = &json["events"][5];
+        assert_eq!(maintenance["kind"], "maintenance");
+        assert_eq!(maintenance["room_id"], "room-a");
+        assert_eq!(maintenance["all_day"], true);
+
+        let room_b = build_calendar(&pool, &march(Some("room-b"), None), &BOOKINGS, CALENDAR_KIND_LIMIT)
+            .await
+            .unwrap();
+        assert_eq!(summary(&room_b), vec!["experiment:exp-2", "reservation:exp-2:Lab B"]);
+
+        let educational = build_calendar(&pool, &march(None, Some("educational")), &[CalendarKind::Experiments], CALENDAR_KIND_LIMIT)
+            .await
+            .unwrap();
+        assert_eq!(summary(&educational), vec!["experiment:exp-1"]);
+
+        let capped = build_calendar(&pool, &march(None, None), &[CalendarKind::Experiments, CalendarKind::Maintenance], 2)
+            .await
+            .unwrap();
+        assert_eq!(summary(&capped), vec!["experiment:exp-1", "experiment:exp-2", "maintenance:m-1"]);
+        assert_eq!(capped.truncated, vec![CalendarKind::Experiments]);
+    }
+
+    #[actix_web::test]
+    async fn test_closed_periods_are_background_events() {
+        let pool = setup().await;
+        // Lab B работает только по субботам 10-14
+        sqlx::query("UPDATE rooms SET weekly_hours = '{\"6\":{\"open\":\"10:00\",\"close\":\"14:00\"}}' WHERE id = 'room-b'")
+            .execute(&pool).await.unwrap();
+
+        // 2026-03-01 - воскресенье; ночь и выходной объединяются в один интервал
+        let mut query = march(Some("room-a"), None);
+        query.end = Some("2026-03-03".to_string());
+        let feed = build_calendar(&pool, &query, &[CalendarKind::Closed], CALENDAR_KIND_LIMIT).await.unwrap();
+        assert_eq!(summary(&feed), vec![
+            "closed:room-a:01 00:00..02 08:00",
+            "closed:room-a:02 18:00..03 08:00",
+            "closed:room-a:03 18:00..04 00:00",
+        ]);
+        let json = serde_json::to_value(&feed).unwrap();
+        assert_eq!(json["events"][0]["kind"], "closed");
+        assert_eq!(json["events"][0]["display"], "background");
+        assert_eq!(json["events"][0]["title"], "Closed: Lab A");
+        assert_eq!(json["events"][0]["color"], "#ff0000");
+
+        let mut query = march(Some("room-b"), None);
+        query.start = Some("2026-03-06".to_string());
+        query.end = Some("2026-03-08".to_string());
+        let feed = build_calendar(&pool, &query, &[CalendarKind::Closed], CALENDAR_KIND_LIMIT).await.unwrap();
+        assert_eq!(summary(&feed), vec!["closed:room-b:06 00:00..07 10:00", "closed:room-b:07 14:00..09 00:00"]);
+
+        // Нерабочее время входит в календарь по умолчанию, но не строится для слишком длинного периода
+        let feed = build_calendar(&pool, &CalendarQuery::default(), &CalendarKind::ALL, CALENDAR_KIND_LIMIT).await.unwrap();
+        assert!(feed.events.iter().all(|e| !matches!(e, CalendarItem::Closed(_))));
+        assert_eq!(feed.truncated, vec![CalendarKind::Closed]);
+    }
+
+    #[actix_web::test]
+    async fn test_calendar_rejects_bad_parameters() {
+        let pool = setup().await;
+
+        assert!(matches!(CalendarKind::parse_list(Some("experiments,holidays")), Err(ApiError::BadRequest(_))));
+        assert_eq!(
+            CalendarKind::parse_list(Some("maintenance,maintenance")).unwrap(),
+            vec![CalendarKind::Maintenance]
+        );
+
+        let mut query = march(None, Some("party"));
+        assert!(matches!(build_calendar(&pool, &query, &CalendarKind::ALL, 10).await, Err(ApiError::BadRequest(_))));
+        query.experiment_type = None;
+        query.end = Some("yesterday".to_string());
+        assert!(matches!(build_calendar(&pool, &query, &CalendarKind::ALL, 10).await, Err(ApiError::BadRequest(_))));
+        query.end = Some("2026-02-01".to_string());
+        assert!(matches!(build_calendar(&pool, &query, &CalendarKind::ALL, 10).await, Err(ApiError::BadRequest(_))));
+    }
+}
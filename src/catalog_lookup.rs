@@ -0,0 +1,377 @@
+// src/catalog_lookup.rs
+//! Подсказки метаданных реагента из внешнего справочника (PubChem).
+//!
+//! `GET /reagents/lookup?cas=` или `?name=` возвращает предлагаемые название,
+//! формулу, молярную массу и синонимы для предзаполнения CreateReagentRequest.
+//! Ответы кэшируются в таблице catalog_lookup_cache с TTL; любые сетевые
+//! ошибки превращаются в пустой список подсказок — создание реагента от
+//! справочника не зависит.
+
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::CatalogConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::http_client::{self, percent_encode};
+use crate::monitoring::Metrics;
+use crate::AppState;
+
+const SOURCE_PUBCHEM: &str = "pubchem";
+const MAX_SYNONYMS: usize = 15;
+
+// ==================== СТРУКТУРЫ ====================
+
+#[derive(Debug, Deserialize)]
+pub struct CatalogLookupQuery {
+    pub cas: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CatalogSuggestion {
+    pub cid: Option<i64>,
+    pub name: String,
+    pub formula: Option<String>,
+    pub molecular_weight: Option<f64>,
+    pub cas_number: Option<String>,
+    pub iupac_name: Option<String>,
+    pub synonyms: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CatalogLookupResponse {
+    pub query: String,
+    /// "pubchem" | "disabled"
+    pub source: String,
+    pub cached: bool,
+    pub suggestions: Vec<CatalogSuggestion>,
+}
+
+// ==================== HANDLER ====================
+
+/// GET /api/v1/reagents/lookup?cas=...|name=...
+pub async fn lookup_reagent(
+    app_state: web::Data<Arc<AppState>>,
+    metrics: web::Data<Metrics>,
+    query: web::Query<CatalogLookupQuery>,
+) -> ApiResult<HttpResponse> {
+    let term = match (query.cas.as_deref(), query.name.as_deref()) {
+        (Some(cas), _) if !cas.trim().is_empty() => cas.trim(),
+        (_, Some(name)) if !name.trim().is_empty() => name.trim(),
+        _ => return Err(ApiError::bad_request("Either 'cas' or 'name' query parameter is required")),
+    };
+    if term.chars().count() > 255 {
+        return Err(ApiError::bad_request("Lookup term cannot exceed 255 characters"));
+    }
+
+    let response = lookup(&app_state.db_pool, &app_state.config.catalog, &metrics, term).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+}
+
+/// Поиск с кэшем; никогда не возвращает ошибку наружу
+pub async fn lookup(
+    pool: &SqlitePool,
+    config: &CatalogConfig,
+    metrics: &Metrics,
+    term: &str,
+) -> CatalogLookupResponse {
+    if !config.pubchem_enabled {
+        return CatalogLookupResponse {
+            query: term.to_string(),
+            source: "disabled".to_string(),
+            cached: false,
+            suggestions: Vec::new(),
+        };
+    }
+
+    let key = term.to_lowercase();
+
+    if let Some(suggestions) = read_cache(pool, &key, config.cache_ttl_hours).await {
+        metrics.record_lookup(true);
+        return CatalogLookupResponse {
+            query: term.to_string(),
+            source: SOURCE_PUBCHEM.to_string(),
+            cached: true,
+            suggestions,
+        };
+    }
+
+    metrics.record_lookup(false);
+    let suggestions = match fetch_pubchem(config, term).await {
+        Ok(suggestions) => {
+            write_cache(pool, &key, &suggestions).await;
+            suggestions
+        }
+        Err(e) => {
+            metrics.record_lookup_failure();
+            log::warn!("PubChem lookup for '{}' failed: {}", term, e);
+            Vec::new()
+        }
+    };
+
+    CatalogLookupResponse {
+        query: term.to_string(),
+        source: SOURCE_PUBCHEM.to_string(),
+        cached: false,
+        suggestions,
+    }
+}
+
+// ==================== CACHE ====================
+
+async fn read_cache(pool: &SqlitePool, key: &str, ttl_hours: i64) -> Option<Vec<CatalogSuggestion>> {
+    let row: Option<(String,)> = sqlx::query_as(
+        r#"SELECT response FROM catalog_lookup_cache
+           WHERE source = ? AND query_key = ?
+             AND fetched_at > datetime('now', ?)"#,
+    )
+        .bind(SOURCE_PUBCHEM)
+        .bind(key)
+        .bind(format!("-{} hours", ttl_hours.max(0)))
+        .fetch_optional(pool)
+        .await
+        .ok()?;
+
+    row.and_then(|(json,)| serde_json::from_str(&json).ok())
+}
+
+async fn write_cache(pool: &SqlitePool, key: &str, suggestions: &[CatalogSuggestion]) {
+    let json = match serde_json::to_string(suggestions) {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+
+    if let Err(e) = sqlx::query(
+        r#"INSERT OR REPLACE INTO catalog_lookup_cache (source, query_key, response, fetched_at)
+           VALUES (?, ?, ?, datetime('now'))"#,
+    )
+        .bind(SOURCE_PUBCHEM)
+        .bind(key)
+        .bind(json)
+        .execute(pool)
+        .await
+    {
+        log::warn!("Failed to cache catalog lookup '{}': {}", key, e);
+    }
+}
+
+// ==================== PUBCHEM ====================
+
+async fn fetch_pubchem(config: &CatalogConfig, term: &str) -> Result<Vec<CatalogSuggestion>, String> {
+    let base = config.pubchem_base_url.trim_end_matches('/');
+    let encoded = percent_encode(term);
+    let properties_url = format!(
+        "{}/compound/name/{}/property/Title,MolecularFormula,MolecularWeight,IUPACName/JSON",
+        base, encoded
+    );
+    let synonyms_url = format!("{}/compound/name/{}/synonyms/JSON", base, encoded);
+    let timeout = Duration::from_secs(config.timeout_seconds.max(1));
+
+    let properties = match http_get_json(properties_url, timeout).await? {
+        Some(json) => json,
+        // 404 — вещество не найдено, это валидный (пустой) ответ
+        None => return Ok(Vec::new()),
+    };
+    let mut suggestions = parse_properties(&properties);
+
+    // Синонимы — необязательная часть: ошибка не отменяет подсказки
+    match http_get_json(synonyms_url, timeout).await {
+        Ok(Some(json)) => apply_synonyms(&mut suggestions, &json),
+        Ok(None) => {}
+        Err(e) => log::debug!("PubChem synonyms lookup failed: {}", e),
+    }
+
+    Ok(suggestions)
+}
+
+fn parse_properties(json: &serde_json::Value) -> Vec<CatalogSuggestion> {
+    let properties = match json["PropertyTable"]["Properties"].as_array() {
+        Some(p) => p,
+        None => return Vec::new(),
+    };
+
+    properties
+        .iter()
+        .filter_map(|p| {
+            let name = p["Title"].as_str().or_else(|| p["IUPACName"].as_str())?.to_string();
+            // PubChem отдаёт MolecularWeight то строкой, то числом
+            let molecular_weight = p["MolecularWeight"]
+                .as_f64()
+                .or_else(|| p["MolecularWeight"].as_str().and_then(|s| s.parse().ok()));
+            Some(CatalogSuggestion {
+                cid: p["CID"].as_i64(),
+                name,
+                formula: p["MolecularFormula"].as_str().map(String::from),
+                molecular_weight,
+                cas_number: None,
+                iupac_name: p["IUPACName"].as_str().map(String::from),
+                synonyms: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+fn apply_synonyms(suggestions: &mut [CatalogSuggestion], json: &serde_json::Value) {
+    let information = match json["InformationList"]["Information"].as_array() {
+        Some(i) => i,
+        None => return,
+    };
+
+    for info in information {
+        let cid = info["CID"].as_i64();
+        let synonyms: Vec<String> = info["Synonym"]
+            .as_array()
+            .map(|list| list.iter().filter_map(|s| s.as_str().map(String::from)).collect())
+            .unwrap_or_default();
+
+        if let Some(suggestion) = suggestions.iter_mut().find(|s| s.cid == cid) {
+            suggestion.cas_number = synonyms.iter().find(|s| looks_like_cas(s)).cloned();
+            suggestion.synonyms = synonyms.into_iter().take(MAX_SYNONYMS).collect();
+        }
+    }
+}
+
+/// CAS: 2-7 цифр, 2 цифры, 1 контрольная цифра
+fn looks_like_cas(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('-').collect();
+    parts.len() == 3
+        && (2..=7).contains(&parts[0].len())
+        && parts[1].len() == 2
+        && parts[2].len() == 1
+        && parts.iter().all(|p| p.chars().all(|c| c.is_ascii_digit()))
+}
+
+// ==================== HTTP ====================
+
+/// GET с таймаутом. Ok(None) — 404 от сервера.
+async fn http_get_json(url: String, timeout: Duration) -> Result<Option<serde_json::Value>, String> {
+    let (status, body) = http_client::request(
+        http_client::Method::GET,
+        &url,
+        &[("Accept", "application/json")],
+        Vec::new(),
+        timeout,
+    )
+    .await?;
+
+    match status {
+        200 => serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(|e| format!("Invalid JSON from catalog: {}", e)),
+        404 => Ok(None),
+        other => Err(format!("Catalog returned HTTP {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_pool() -> SqlitePool {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    fn enabled_config(base_url: &str) -> CatalogConfig {
+        CatalogConfig {
+            pubchem_enabled: true,
+            pubchem_base_url: base_url.to_string(),
+            timeout_seconds: 1,
+            cache_ttl_hours: 24,
+        }
+    }
+
+    #[test]
+    fn test_parse_pubchem_payloads() {
+        let properties = serde_json::json!({
+            "PropertyTable": {"Properties": [{
+                "CID": 962, "MolecularFormula": "H2O", "MolecularWeight": "18.015",
+                "Title": "Water", "IUPACName": "oxidane"
+            }]}
+        });
+        let mut suggestions = parse_properties(&properties);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].name, "Water");
+        assert_eq!(suggestions[0].molecular_weight, Some(18.015));
+
+        let synonyms = serde_json::json!({
+            "InformationList": {"Information": [{
+                "CID": 962, "Synonym": ["water", "7732-18-5", "dihydrogen oxide"]
+            }]}
+        });
+        apply_synonyms(&mut suggestions, &synonyms);
+        assert_eq!(suggestions[0].cas_number.as_deref(), Some("7732-18-5"));
+        assert_eq!(suggestions[0].synonyms.len(), 3);
+    }
+
+    #[test]
+    fn test_helpers() {
+        assert!(looks_like_cas("64-17-5"));
+        assert!(!looks_like_cas("ethanol"));
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_returns_empty() {
+        let pool = test_pool().await;
+        let metrics = Metrics::new();
+        let res = lookup(&pool, &CatalogConfig::default(), &metrics, "ethanol").await;
+        assert_eq!(res.source, "disabled");
+        assert!(res.suggestions.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_network_failure_degrades_to_empty() {
+        let pool = test_pool().await;
+        let metrics = Metrics::new();
+        let res = lookup(&pool, &enabled_config("http://127.0.0.1:9"), &metrics, "ethanol").await;
+        assert!(res.suggestions.is_empty());
+        assert!(!res.cached);
+        assert_eq!(metrics.lookup_failures.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        // Ошибки не кэшируются
+        let cached: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM catalog_lookup_cache")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(cached, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_cache_respects_ttl() {
+        let pool = test_pool().await;
+        let metrics = Metrics::new();
+        let config = enabled_config("http://127.0.0.1:9");
+        let suggestion = CatalogSuggestion {
+            cid: Some(702),
+            name: "Ethanol".to_string(),
+            formula: Some("C2H6O".to_string()),
+            molecular_weight: Some(46.07),
+            cas_number: Some("64-17-5".to_string()),
+            iupac_name: Some("ethanol".to_string()),
+            synonyms: vec![],
+        };
+        write_cache(&pool, "ethanol", std::slice::from_ref(&suggestion)).await;
+
+        let res = lookup(&pool, &config, &metrics, "Ethanol").await;
+        assert!(res.cached);
+        assert_eq!(res.suggestions, vec![suggestion]);
+        assert_eq!(metrics.lookup_cache_hits.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        sqlx::query("UPDATE catalog_lookup_cache SET fetched_at = datetime('now', '-48 hours')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let res = lookup(&pool, &config, &metrics, "ethanol").await;
+        assert!(!res.cached);
+        assert!(res.suggestions.is_empty());
+    }
+}
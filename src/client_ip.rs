@@ -0,0 +1,253 @@
+// src/client_ip.rs
+//! IP клиента за обратным прокси.
+//!
+//! За nginx адрес соединения - адрес прокси (127.0.0.1), поэтому журнал аудита, журнал
+//! запросов, блокировка входа и лимиты видели всех клиентов как одного. Заголовки
+//! Forwarded / X-Forwarded-For учитываются только если соединение пришло с доверенного
+//! прокси (`security.trusted_proxies`, список CIDR). Цепочка читается справа налево:
+//! доверенные звенья пропускаются, первое недоверенное - клиент. Всё, что левее, мог
+//! дописать сам клиент, поэтому дальше не читается. От недоверенных адресов заголовки
+//! игнорируются полностью.
+//!
+//! RequestLogger определяет адрес один раз и кладёт его в extensions запроса (`ClientIp`);
+//! остальные берут его через `client_ip(&req)`.
+
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::HeaderMap;
+use actix_web::{HttpMessage, HttpRequest};
+use std::net::IpAddr;
+
+/// IP клиента, определённый RequestLogger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Сеть в нотации CIDR; адрес без маски - один хост
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("invalid address in '{}'", value))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().ok().filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix length in '{}'", value))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, normalize(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// IPv4, пришедший как ::ffff:a.b.c.d, сравнивается как IPv4
+pub fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    }
+}
+
+/// Доверенные прокси из `security.trusted_proxies`; пустой список - заголовкам не верим никогда
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    nets: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn parse(values: &[String]) -> Result<Self, String> {
+        let nets = values.iter()
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| IpNet::parse(v))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { nets })
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|net| net.contains(ip))
+    }
+
+    /// IP клиента по адресу соединения и заголовкам прокси
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = normalize(peer);
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+
+        // Forwarded (RFC 7239) важнее X-Forwarded-For; несколько заголовков - одна цепочка
+        let forwarded: Vec<&str> = headers.get_all("forwarded").filter_map(|v| v.to_str().ok()).collect();
+        let chain = if !forwarded.is_empty() {
+            forwarded_chain(&forwarded.join(","))
+        } else {
+            let xff: Vec<&str> = headers.get_all("x-forwarded-for").filter_map(|v| v.to_str().ok()).collect();
+            xff.join(",").split(',').map(parse_hop).collect()
+        };
+
+        let mut client = peer;
+        for hop in chain.into_iter().rev() {
+            // Неразборчивое звено (unknown, обфусцированное имя) - дальше цепочке верить нельзя
+            let Some(ip) = hop else { break };
+            client = normalize(ip);
+            if !self.is_trusted(client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// Значения for= из Forwarded: `for=192.0.2.60;proto=http, for="[2001:db8::1]:4711"`
+fn forwarded_chain(value: &str) -> Vec<Option<IpAddr>> {
+    value.split(',')
+        .filter_map(|element| {
+            element.split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                .map(|(_, node)| parse_hop(node))
+        })
+        .collect()
+}
+
+/// Звено цепочки: адрес, возможно в кавычках, в [] и с портом
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+    if let Ok(ip) = hop.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Some(rest) = hop.strip_prefix('[') {
+        return rest.split_once(']').and_then(|(ip, _)| ip.parse().ok());
+    }
+    hop.parse::<std::net::SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+/// Определить IP клиента и сохранить в extensions (вызывается из RequestLogger)
+pub fn assign(req: &ServiceRequest, trusted: &TrustedProxies) {
+    if let Some(peer) = req.peer_addr() {
+        let ip = trusted.resolve(peer.ip(), req.headers());
+        req.extensions_mut().insert(ClientIp(ip));
+    }
+}
+
+/// IP клиента запроса; без RequestLogger - адрес соединения, заголовки не читаются
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<ClientIp>().map(|ip| ip.0)
+        .or_else(|| req.peer_addr().map(|addr| normalize(addr.ip())))
+        .map(|ip| ip.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn proxies(values: &[&str]) -> TrustedProxies {
+        TrustedProxies::parse(&values.iter().map(|v| v.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.append(HeaderName::from_static(name), HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_parsing_and_matching() {
+        let net = IpNet::parse("10.0.0.0/8").unwrap();
+        assert!(net.contains(ip("10.20.30.40")));
+        assert!(!net.contains(ip("11.0.0.1")));
+        assert!(net.contains(ip("::ffff:10.1.2.3")));
+        assert!(IpNet::parse("::1").unwrap().contains(ip("::1")));
+        assert!(IpNet::parse("fd00::/8").unwrap().contains(ip("fd12::5")));
+        assert!(IpNet::parse("0.0.0.0/0").unwrap().contains(ip("203.0.113.9")));
+        assert!(IpNet::parse("10.0.0.0/33").is_err());
+        assert!(IpNet::parse("nginx").is_err());
+    }
+
+    #[test]
+    fn test_multi_hop_chain_takes_rightmost_untrusted() {
+        let trusted = proxies(&["127.0.0.1", "10.0.0.0/8"]);
+        // client -> CDN-узел 10.1.1.1 -> nginx 127.0.0.1
+        let h = headers(&[("x-forwarded-for", "203.0.113.7, 10.1.1.1")]);
+        assert_eq!(trusted.resolve(ip("127.0.0.1"), &h), ip("203.0.113.7"));
+
+        // Цепочка из нескольких заголовков склеивается по порядку
+        let h = headers(&[("x-forwarded-for", "203.0.113.7"), ("x-forwarded-for", "10.1.1.1")]);
+        assert_eq!(trusted.resolve(ip("127.0.0.1"), &h), ip("203.0.113.7"));
+
+        let h = headers(&[("forwarded", r#"for=198.51.100.2;proto=https, for="[2001:db8::1]:4711", for=10.2.2.2"#)]);
+        assert_eq!(trusted.resolve(ip("127.0.0.1"), &h), ip("2001:db8::1"));
+
+        // Только доверенные звенья - клиент самый левый из них
+        let h = headers(&[("x-forwarded-for", "10.9.9.9, 10.1.1.1")]);
+        assert_eq!(trusted.resolve(ip("127.0.0.1"), &h), ip("10.9.9.9"));
+
+        // Без заголовков за прокси остаётся адрес прокси
+        assert_eq!(trusted.resolve(ip("127.0.0.1"), &HeaderMap::new()), ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn test_spoofed_headers_are_ignored() {
+        let trusted = proxies(&["127.0.0.1"]);
+        let spoofed = headers(&[("x-forwarded-for", "1.2.3.4"), ("forwarded", "for=1.2.3.4")]);
+        // Прямое соединение с недоверенного адреса: заголовки не читаются
+        assert_eq!(trusted.resolve(ip("198.51.100.9"), &spoofed), ip("198.51.100.9"));
+        // Без настроенных прокси заголовкам не верим даже от localhost
+        assert_eq!(proxies(&[]).resolve(ip("127.0.0.1"), &spoofed), ip("127.0.0.1"));
+
+        // Клиент дописал поддельное звено слева: nginx добавил настоящий адрес справа
+        let h = headers(&[("x-forwarded-for", "1.2.3.4, 203.0.113.7")]);
+        assert_eq!(trusted.resolve(ip("127.0.0.1"), &h), ip("203.0.113.7"));
+
+        // Неразборчивое звено обрывает цепочку, левее него ничего не читается
+        let h = headers(&[("x-forwarded-for", "1.2.3.4, garbage")]);
+        assert_eq!(trusted.resolve(ip("127.0.0.1"), &h), ip("127.0.0.1"));
+        let h = headers(&[("forwarded", "for=1.2.3.4, for=unknown")]);
+        assert_eq!(trusted.resolve(ip("127.0.0.1"), &h), ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn test_request_helper_uses_extension_or_peer() {
+        use actix_web::test::TestRequest;
+
+        let trusted = proxies(&["127.0.0.1"]);
+        let req = TestRequest::default()
+            .peer_addr("127.0.0.1:5000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .to_srv_request();
+        assign(&req, &trusted);
+        assert_eq!(client_ip(req.request()).as_deref(), Some("203.0.113.7"));
+
+        // Без RequestLogger заголовок не читается вовсе
+        let req = TestRequest::default()
+            .peer_addr("198.51.100.9:5000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "1.2.3.4"))
+            .to_http_request();
+        assert_eq!(client_ip(&req).as_deref(), Some("198.51.100.9"));
+    }
+}
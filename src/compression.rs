@@ -0,0 +1,375 @@
+// src/compression.rs
+//! Сжатие ответов с настраиваемым уровнем и списком алгоритмов.
+//!
+//! Заменяет `Compress::default()`: уже сжатые форматы (xlsx, zip, pdf,
+//! изображения) и потоковые файлы отдаются без повторного сжатия —
+//! двойное сжатие ломало xlsx у части клиентов.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+
+use crate::config::CompressionConfig;
+
+/// Ответы больше этого размера не буферизуются для сжатия
+const MAX_BUFFERED_BODY: u64 = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Gzip,
+    Deflate,
+}
+
+impl Algorithm {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "gzip" => Some(Algorithm::Gzip),
+            "deflate" => Some(Algorithm::Deflate),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Gzip => "gzip",
+            Algorithm::Deflate => "deflate",
+        }
+    }
+
+    fn encode(&self, data: &[u8], level: u32) -> std::io::Result<Vec<u8>> {
+        let level = Compression::new(level.min(9));
+        match self {
+            Algorithm::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Algorithm::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len() / 2), level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Выбирает первый разрешённый алгоритм, который принимает клиент (q > 0)
+fn negotiate(accept_encoding: &str, allowed: &[Algorithm]) -> Option<Algorithm> {
+    let accepted: Vec<(String, f32)> = accept_encoding
+        .split(',')
+        .filter_map(|part| {
+            let mut pieces = part.split(';');
+            let name = pieces.next()?.trim().to_lowercase();
+            if name.is_empty() {
+                return None;
+            }
+            let q = pieces
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .filter_map(|v| v.trim().parse::<f32>().ok())
+                .next()
+                .unwrap_or(1.0);
+            Some((name, q))
+        })
+        .collect();
+
+    allowed.iter().copied().find(|alg| {
+        accepted
+            .iter()
+            .any(|(name, q)| *q > 0.0 && (name == alg.as_str() || name == "*"))
+    })
+}
+
+fn is_excluded_content_type(content_type: &str, excluded: &[String]) -> bool {
+    let content_type = content_type.to_lowercase();
+    excluded
+        .iter()
+        .any(|prefix| content_type.starts_with(&prefix.to_lowercase()))
+}
+
+// ==================== MIDDLEWARE ====================
+
+pub struct ResponseCompression {
+    config: Arc<CompressionConfig>,
+}
+
+impl ResponseCompression {
+    pub fn new(config: &CompressionConfig) -> Self {
+        Self { config: Arc::new(config.clone()) }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ResponseCompression
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = ResponseCompressionMiddleware<S>;
+    type Future = std::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let algorithms = self
+            .config
+            .algorithms
+            .iter()
+            .filter_map(|a| Algorithm::from_str(a))
+            .collect();
+
+        std::future::ready(Ok(ResponseCompressionMiddleware {
+            service,
+            config: self.config.clone(),
+            algorithms: Arc::new(algorithms),
+        }))
+    }
+}
+
+pub struct ResponseCompressionMiddleware<S> {
+    service: S,
+    config: Arc<CompressionConfig>,
+    algorithms: Arc<Vec<Algorithm>>,
+}
+
+impl<S, B> Service<ServiceRequest> for ResponseCompressionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Future = std::pin::Pin<Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, ctx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(ctx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let algorithm = if self.config.enabled && req.method() != Method::HEAD {
+            req.headers()
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| negotiate(v, &self.algorithms))
+        } else {
+            None
+        };
+        let config = self.config.clone();
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await?;
+
+            let algorithm = match algorithm {
+                Some(alg) => alg,
+                None => return Ok(res.map_into_boxed_body()),
+            };
+
+            let status = res.status();
+            if status == StatusCode::NO_CONTENT
+                || status == StatusCode::NOT_MODIFIED
+                || status.is_informational()
+                || res.headers().contains_key(header::CONTENT_ENCODING)
+            {
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let excluded = res
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(|ct| is_excluded_content_type(ct, &config.excluded_content_types))
+                .unwrap_or(false);
+            if excluded {
+                return Ok(res.map_into_boxed_body());
+            }
+
+            // Потоковые и слишком большие тела отдаём как есть
+            match res.response().body().size() {
+                BodySize::Sized(n) if n >= config.min_size as u64 && n <= MAX_BUFFERED_BODY => {}
+                _ => return Ok(res.map_into_boxed_body()),
+            }
+
+            let (req, response) = res.into_parts();
+            let (mut response, body) = response.into_parts();
+
+            let bytes = match actix_web::body::to_bytes(body).await {
+                Ok(bytes) => bytes,
+                Err(_) => {
+                    return Err(actix_web::error::ErrorInternalServerError(
+                        "Failed to read response body for compression",
+                    ))
+                }
+            };
+
+            let compressed = match algorithm.encode(&bytes, config.level) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("Response compression failed, sending uncompressed: {}", e);
+                    let response = response.set_body(BoxBody::new(bytes));
+                    return Ok(ServiceResponse::new(req, response));
+                }
+            };
+
+            let headers = response.headers_mut();
+            headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(algorithm.as_str()));
+            headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+            headers.remove(header::CONTENT_LENGTH);
+
+            let response = response.set_body(BoxBody::new(compressed));
+            Ok(ServiceResponse::new(req, response))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, web, App, HttpResponse};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn gunzip(data: &[u8]) -> Vec<u8> {
+        let mut decoder = GzDecoder::new(data);
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let all = [Algorithm::Gzip, Algorithm::Deflate];
+        assert_eq!(negotiate("gzip, deflate, br", &all), Some(Algorithm::Gzip));
+        assert_eq!(negotiate("deflate", &all), Some(Algorithm::Deflate));
+        assert_eq!(negotiate("gzip;q=0, deflate", &all), Some(Algorithm::Deflate));
+        assert_eq!(negotiate("br", &all), None);
+        assert_eq!(negotiate("*", &[Algorithm::Deflate]), Some(Algorithm::Deflate));
+        assert_eq!(negotiate("gzip", &[Algorithm::Deflate]), None);
+    }
+
+    #[test]
+    fn test_excluded_content_types() {
+        let excluded = CompressionConfig::default().excluded_content_types;
+        assert!(is_excluded_content_type(
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            &excluded
+        ));
+        assert!(is_excluded_content_type("image/png", &excluded));
+        assert!(is_excluded_content_type("application/pdf", &excluded));
+        assert!(!is_excluded_content_type("application/json", &excluded));
+        assert!(!is_excluded_content_type("text/csv; charset=utf-8", &excluded));
+    }
+
+    #[actix_web::test]
+    async fn test_export_download_gzip_integrity() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        for i in 0..50 {
+            sqlx::query(
+                "INSERT INTO reagents (id, name, formula, status, created_at, updated_at)
+                 VALUES (?, ?, 'H2O', 'active', datetime('now'), datetime('now'))",
+            )
+            .bind(format!("reagent-{}", i))
+            .bind(format!("Reagent number {}", i))
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let app_state = Arc::new(crate::AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        });
+
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(ResponseCompression::new(&CompressionConfig::default()))
+                .app_data(web::Data::new(app_state))
+                .route("/export", web::get().to(crate::import_export::export_reagents)),
+        )
+        .await;
+
+        let plain_req = actix_test::TestRequest::get().uri("/export").to_request();
+        let plain_res = actix_test::call_service(&app, plain_req).await;
+        assert!(plain_res.headers().get(header::CONTENT_ENCODING).is_none());
+        let plain_body = actix_test::read_body(plain_res).await;
+
+        let gz_req = actix_test::TestRequest::get()
+            .uri("/export")
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        let gz_res = actix_test::call_service(&app, gz_req).await;
+        assert_eq!(gz_res.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+        let gz_body = actix_test::read_body(gz_res).await;
+
+        assert!(gz_body.len() < plain_body.len());
+        assert_eq!(gunzip(&gz_body), plain_body.to_vec());
+
+        let reagents: Vec<serde_json::Value> = serde_json::from_slice(&plain_body).unwrap();
+        assert_eq!(reagents.len(), 50);
+    }
+
+    #[actix_web::test]
+    async fn test_xlsx_not_double_compressed() {
+        let payload: Vec<u8> = (0..8192u32).map(|i| (i % 7) as u8).collect();
+        let expected = payload.clone();
+
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(ResponseCompression::new(&CompressionConfig::default()))
+                .route(
+                    "/file.xlsx",
+                    web::get().to(move || {
+                        let payload = payload.clone();
+                        async move {
+                            HttpResponse::Ok()
+                                .content_type("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+                                .body(payload)
+                        }
+                    }),
+                ),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/file.xlsx")
+            .insert_header((header::ACCEPT_ENCODING, "gzip, deflate"))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        let body = actix_test::read_body(res).await;
+        assert_eq!(body.to_vec(), expected);
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_compression_passthrough() {
+        let config = CompressionConfig { enabled: false, ..CompressionConfig::default() };
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(ResponseCompression::new(&config))
+                .route("/", web::get().to(|| async { HttpResponse::Ok().body("x".repeat(4096)) })),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        let res = actix_test::call_service(&app, req).await;
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    }
+}
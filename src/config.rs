@@ -1,5 +1,6 @@
 // src/config.rs - Configuration management with hot reload support
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::sync::{Arc, RwLock};
 use std::thread;
@@ -12,7 +13,7 @@ use rand::{thread_rng, Rng, distributions::Alphanumeric};
 use std::path::Path;
 use std::fs;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub server: ServerConfig,
     pub database: DatabaseConfig,
@@ -20,16 +21,56 @@ pub struct Config {
     pub security: SecurityConfig,
     pub logging: LoggingConfig,
     pub hot_reload: HotReloadConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub catalog: CatalogConfig,
+    #[serde(default)]
+    pub inactivity: InactivityConfig,
+    #[serde(default)]
+    pub query_log: QueryLogConfig,
+    #[serde(default)]
+    pub safety: SafetyConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    #[serde(default)]
+    pub settings: RuntimeSettingsConfig,
+    #[serde(default)]
+    pub events: EventLogConfig,
+    #[serde(default)]
+    pub smtp: SmtpConfig,
+    #[serde(default)]
+    pub deployment: DeploymentConfig,
+    #[serde(default)]
+    pub work_queues: WorkQueueConfig,
+    #[serde(default)]
+    pub public_catalogue: PublicCatalogueConfig,
+    #[serde(default)]
+    pub timeouts: RequestTimeoutConfig,
+    #[serde(default)]
+    pub outbox: OutboxConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub downloads: DownloadLinkConfig,
+    #[serde(default)]
+    pub pagination: PaginationConfig,
+    #[serde(default)]
+    pub reference_cache: ReferenceCacheConfig,
+    #[serde(default)]
+    pub experiment_report: ExperimentReportConfig,
+    #[serde(default)]
+    pub stock_anomalies: StockAnomalyConfig,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct HotReloadConfig {
     pub enabled: bool,
     pub interval_seconds: u64,
     pub watch_paths: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
@@ -39,7 +80,8 @@ pub struct ServerConfig {
     pub client_shutdown: u64,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct DatabaseConfig {
     pub url: String,
     pub max_connections: u32,
@@ -48,9 +90,25 @@ pub struct DatabaseConfig {
     pub idle_timeout: u64,
     pub backup_enabled: bool,
     pub backup_interval_hours: u64,
+    /// Сколько ждать снятия блокировки SQLite, прежде чем вернуть SQLITE_BUSY (мс)
+    pub busy_timeout_ms: u64,
+    /// PRAGMA journal_mode: DELETE, TRUNCATE, PERSIST, MEMORY, WAL, OFF
+    pub journal_mode: String,
+    /// PRAGMA synchronous: OFF, NORMAL, FULL, EXTRA
+    pub synchronous: String,
+    pub foreign_keys: bool,
+    /// Применять схему при запуске; иначе см. migration_guard
+    pub auto_migrate: bool,
+    /// Незапущенные миграции при auto_migrate = false: "fail" - не запускаться, "warn" - только лог
+    pub migration_guard: String,
+    /// POST /admin/migrate; по умолчанию разрешён только вне production
+    pub allow_runtime_migrate: Option<bool>,
+    /// Размер отдельного read-only пула для отчётов, экспорта, аналитики и дашборда;
+    /// 0 - один общий пул
+    pub read_pool_size: u32,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AuthConfig {
     pub jwt_secret: String,
     pub token_expiration_hours: i64,
@@ -58,18 +116,470 @@ pub struct AuthConfig {
     pub max_login_attempts: u32,
     pub lockout_duration_minutes: u64,
     pub allow_self_registration: bool,
+    #[serde(default)]
+    pub username: UsernamePolicyConfig,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Допустимые имена пользователей (см. user_identity::normalize_username). Длина -
+/// в пределах CHECK таблицы users (3..=50)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct UsernamePolicyConfig {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Символы, разрешённые помимо латинских букв и цифр (не в начале имени)
+    pub allowed_symbols: String,
+}
+
+impl Default for UsernamePolicyConfig {
+    fn default() -> Self {
+        Self { min_length: 3, max_length: 50, allowed_symbols: "._-".to_string() }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SecurityConfig {
     pub allowed_origins: Vec<String>,
     pub rate_limit_requests: u32,
     pub rate_limit_window_seconds: u64,
     pub max_request_size: usize,
     pub require_https: bool,
+    /// Обратные прокси (CIDR), от которых принимаются Forwarded / X-Forwarded-For (см. client_ip)
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default)]
+    pub headers: SecurityHeadersConfig,
+}
+
+/// Заголовки безопасности ответов (см. security_headers); HSTS включается через require_https
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SecurityHeadersConfig {
+    pub content_type_options: bool,
+    pub frame_options: bool,
+    pub xss_protection: bool,
+    pub referrer_policy: bool,
+    /// Только для HTML-ответов; None или пустая строка - без CSP
+    pub content_security_policy: Option<String>,
+    /// Content-Security-Policy-Report-Only: нарушения только присылаются на /csp-report
+    pub csp_report_only: bool,
+    /// Только для HTML-ответов; None или пустая строка - без заголовка
+    pub permissions_policy: Option<String>,
+}
+
+/// Настройки сжатия ответов (gzip/deflate)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Уровень сжатия 0-9 (1 = быстро, 9 = максимально)
+    pub level: u32,
+    /// Разрешённые алгоритмы в порядке предпочтения: "gzip", "deflate"
+    pub algorithms: Vec<String>,
+    /// Ответы меньше этого размера не сжимаются
+    pub min_size: usize,
+    /// Уже сжатые форматы (xlsx, zip, изображения, pdf) отдаются как есть
+    pub excluded_content_types: Vec<String>,
+}
+
+/// Внешний справочник веществ (PubChem) для подсказок при создании реагента
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CatalogConfig {
+    pub pubchem_enabled: bool,
+    pub pubchem_base_url: String,
+    pub timeout_seconds: u64,
+    /// Время жизни закэшированного ответа
+    pub cache_ttl_hours: i64,
+}
+
+/// Политика автоматической деактивации неактивных учётных записей (кроме админов)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InactivityConfig {
+    /// Деактивировать после N дней без активности; 0 - политика выключена
+    pub deactivate_after_days: i64,
+    /// За сколько дней до деактивации администраторы получают уведомление
+    pub notice_days: i64,
+}
+
+impl InactivityConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.deactivate_after_days > 0
+    }
+}
+
+/// Профилирование SQL-запросов: гистограмма латентности и журнал медленных запросов
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueryLogConfig {
+    pub enabled: bool,
+    /// Запросы дольше порога пишутся в лог и в кольцевой буфер
+    pub slow_threshold_ms: u64,
+    /// Сколько последних медленных запросов держать в памяти
+    pub ring_buffer_size: usize,
+}
+
+/// Техника безопасности: допуск к экспериментам с опасными реагентами
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SafetyConfig {
+    /// Допуск инструктора обязателен, если тяжесть опасности реагента (GHS, 0-3) выше порога;
+    /// 3 - требование отключено
+    pub signoff_hazard_threshold: u8,
+}
+
+/// Сроки хранения журналов; записи старше срока удаляются фоновой задачей обслуживания
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetentionConfig {
+    /// audit_logs старше N лет; 0 - хранить бессрочно
+    pub audit_log_years: u32,
+    /// usage_logs (история расхода) старше N лет; 0 - хранить бессрочно
+    pub usage_history_years: u32,
+}
+
+/// Значения по умолчанию для настроек, изменяемых на лету через /admin/settings
+/// (используются, пока администратор не задал значение в таблице settings)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RuntimeSettingsConfig {
+    /// Партия считается заканчивающейся при остатке <= N% от исходного количества
+    pub low_stock_threshold_percent: i64,
+    /// Окно «скоро истекает» для партий, дней
+    pub expiring_soon_days: i64,
+    /// Час (UTC), в который рассылаются ежедневные уведомления
+    pub notification_hour: i64,
+    /// Предел размера загружаемого файла, МБ
+    pub max_upload_size_mb: i64,
+    /// Верхняя граница per_page для списков
+    pub max_per_page: i64,
+    /// Окно (дней) истории расхода для прогноза исчерпания реагентов
+    pub forecast_window_days: i64,
+    /// Сколько минут удалённый объект можно восстановить через POST /undo/{token}
+    pub undo_window_minutes: i64,
+    /// Сколько партий реагента можно получить одним ответом `?per_page=all`
+    pub reagent_batches_all_limit: i64,
+    /// Через сколько дней несогласованная заявка на расход истекает
+    pub approval_expiry_days: i64,
+    /// Коды причин корректировки остатка партии
+    pub stock_adjustment_reasons: Vec<String>,
+    /// Списки отклоняют неизвестные поля фильтров и сортировки (400), а не игнорируют их
+    #[serde(default)]
+    pub strict_field_filters: bool,
+}
+
+/// Поток бизнес-событий (target `lims::events`, одна JSON-строка на событие), отдельно от журнала доступа
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EventLogConfig {
+    pub output: EventLogOutput,
+    /// Файл событий для output = file|both; ротация по суткам (к имени добавляется дата)
+    pub file_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventLogOutput {
+    Off,
+    Stdout,
+    File,
+    Both,
+}
+
+impl EventLogOutput {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" | "none" => Some(EventLogOutput::Off),
+            "stdout" => Some(EventLogOutput::Stdout),
+            "file" => Some(EventLogOutput::File),
+            "both" => Some(EventLogOutput::Both),
+            _ => None,
+        }
+    }
+
+    pub fn writes_stdout(&self) -> bool {
+        matches!(self, EventLogOutput::Stdout | EventLogOutput::Both)
+    }
+
+    pub fn writes_file(&self) -> bool {
+        matches!(self, EventLogOutput::File | EventLogOutput::Both)
+    }
+}
+
+/// Исходящая почта (рассылка отчётов по расписанию)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmtpConfig {
+    /// Пустой host - отправка почты выключена
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Адрес отправителя (From)
+    pub from: String,
+    pub timeout_seconds: u64,
+}
+
+impl SmtpConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.host.trim().is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Без шифрования (локальный relay)
+    None,
+    /// STARTTLS после EHLO (обычно порт 587)
+    Starttls,
+    /// Неявный TLS с момента подключения (обычно порт 465)
+    Tls,
+}
+
+impl SmtpSecurity {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "none" | "plain" => Some(SmtpSecurity::None),
+            "starttls" => Some(SmtpSecurity::Starttls),
+            "tls" | "ssl" => Some(SmtpSecurity::Tls),
+            _ => None,
+        }
+    }
+}
+
+/// Параметры конкретной установки
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeploymentConfig {
+    /// Часовой пояс IANA (например, Europe/Moscow) - в нём задаются расписания отчётов
+    pub timezone: String,
+}
+
+/// Очереди тяжёлых операций импорта/экспорта (см. work_queue)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct WorkQueueConfig {
+    /// Сколько Excel/CSV-файлов импорта разбирается одновременно
+    pub xlsx_parse_concurrency: usize,
+    /// Сколько экспортов выполняется одновременно
+    pub export_concurrency: usize,
+    /// Сколько запросов может ждать в каждой очереди; сверх этого - 429
+    pub max_queued: usize,
+    /// Retry-After для ответа 429, секунд
+    pub retry_after_secs: u64,
+}
+
+/// Таймауты обработки запросов API (см. request_timeout)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RequestTimeoutConfig {
+    /// Таймаут по умолчанию, секунд
+    pub default_secs: u64,
+    /// Таймаут для маршрутов экспорта, секунд
+    pub export_secs: u64,
+    /// Переопределения для отдельных маршрутов: ключ "POST /reports/generate" (шаблон из таблицы доступа)
+    pub routes: HashMap<String, u64>,
+}
+
+/// Доставка бизнес-событий из таблицы outbox (см. outbox)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct OutboxConfig {
+    /// Пауза между опросами таблицы, секунд
+    pub poll_interval_secs: u64,
+    /// Сколько событий берётся за один запрос
+    pub batch_size: i64,
+    /// После стольких неудачных попыток событие уходит в dead letter
+    pub max_attempts: i64,
+    /// Пауза перед повтором: retry_base_secs * 2^(попытка - 1), не больше часа
+    pub retry_base_secs: u64,
+    /// Доставленные события хранятся столько дней
+    pub delivered_retention_days: i64,
+    pub webhook_timeout_secs: u64,
+    pub webhooks: Vec<WebhookConfig>,
+    /// Получатели уведомлений о событиях из email_events; пусто - почта не отправляется
+    pub email_to: Vec<String>,
+    pub email_events: Vec<String>,
+}
+
+/// Напоминания о плановом обслуживании оборудования
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// За сколько дней до scheduled_date напоминать, по типу обслуживания
+    /// (calibration, repair, inspection, cleaning, replacement, other); значение
+    /// копируется в reminder_lead_days записи, если его не передали при создании
+    pub reminder_lead_days: HashMap<String, i64>,
+    /// Для типов, не перечисленных в reminder_lead_days
+    pub default_reminder_lead_days: i64,
+    /// Сколько дней после scheduled_date обслуживание может не выполняться, по типу;
+    /// дальше запись просрочена и эскалируется (см. alert_handlers). Копируется
+    /// в sla_days записи, если его не передали при создании
+    pub sla_days: HashMap<String, i64>,
+    /// Для типов, не перечисленных в sla_days
+    pub default_sla_days: i64,
+}
+
+impl MaintenanceConfig {
+    pub fn reminder_lead_days_for(&self, maintenance_type: &str) -> i64 {
+        self.reminder_lead_days.get(maintenance_type).copied().unwrap_or(self.default_reminder_lead_days)
+    }
+
+    /// SQL-выражение срока напоминания для записи обслуживания с псевдонимом `alias`:
+    /// reminder_lead_days записи, а если он не задан - значение для её типа
+    pub fn lead_days_sql(&self, alias: &str) -> String {
+        let mut sql = format!("COALESCE({}.reminder_lead_days, CASE {}.maintenance_type", alias, alias);
+        for maintenance_type in <crate::query_builders::MaintenanceType as strum::VariantNames>::VARIANTS {
+            sql.push_str(&format!(" WHEN '{}' THEN {}", maintenance_type, self.reminder_lead_days_for(maintenance_type)));
+        }
+        sql.push_str(&format!(" ELSE {} END)", self.default_reminder_lead_days));
+        sql
+    }
+
+    pub fn sla_days_for(&self, maintenance_type: &str) -> i64 {
+        self.sla_days.get(maintenance_type).copied().unwrap_or(self.default_sla_days)
+    }
+
+    /// SQL-выражение SLA записи обслуживания с псевдонимом `alias`:
+    /// sla_days записи, а если он не задан - значение для её типа
+    pub fn sla_days_sql(&self, alias: &str) -> String {
+        let mut sql = format!("COALESCE({}.sla_days, CASE {}.maintenance_type", alias, alias);
+        for maintenance_type in <crate::query_builders::MaintenanceType as strum::VariantNames>::VARIANTS {
+            sql.push_str(&format!(" WHEN '{}' THEN {}", maintenance_type, self.sla_days_for(maintenance_type)));
+        }
+        sql.push_str(&format!(" ELSE {} END)", self.default_sla_days));
+        sql
+    }
+
+    /// Дней просрочки сверх SLA на сегодня (0 и меньше - в срок)
+    pub fn days_overdue_sql(&self, alias: &str) -> String {
+        format!(
+            "CAST(julianday(date('now')) - julianday(date({}.scheduled_date, '+' || {} || ' days')) AS INTEGER)",
+            alias, self.sla_days_sql(alias)
+        )
+    }
+}
+
+/// Подписанные ссылки на скачивание экспортов (см. signed_downloads)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DownloadLinkConfig {
+    /// Срок жизни ссылки, секунд
+    pub ttl_seconds: u64,
+    /// Ссылка срабатывает только один раз
+    pub single_use: bool,
+    /// Внешний адрес сервера для ссылок (`https://lims.example.org`); не задан - адрес запроса
+    pub public_base_url: Option<String>,
+}
+
+/// Кэш справочников в памяти (см. reference_cache)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReferenceCacheConfig {
+    /// Срок жизни записи и max-age ответа, секунд; 0 - кэш выключен
+    pub ttl_seconds: u64,
+}
+
+impl Default for ReferenceCacheConfig {
+    fn default() -> Self {
+        Self { ttl_seconds: crate::reference_cache::DEFAULT_TTL_SECONDS }
+    }
+}
+
+/// PDF-отчёт по эксперименту (см. experiment_report)
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ExperimentReportConfig {
+    /// TTF-шрифт с кириллицей; не задан - первый найденный из системных (DejaVu Sans,
+    /// Liberation Sans), без них - встроенный Helvetica (только латиница)
+    pub font_path: Option<String>,
+}
+
+/// Пороги аномалий расхода и корректировок партий (см. stock_anomalies); 0 отключает правило
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct StockAnomalyConfig {
+    /// Скользящее окно счётчиков частоты, минут
+    pub window_minutes: i64,
+    /// Операций с одной партией за окно, после которых срабатывает аномалия
+    pub batch_ops_per_window: u64,
+    /// Операций одного пользователя (по всем партиям) за окно
+    pub user_ops_per_window: u64,
+    /// Одно списание больше этой доли original_quantity партии, %
+    pub single_consumption_percent: f64,
+}
+
+/// Пределы per_page списков (см. pagination::page_limits). Общий максимум - настройка
+/// max_per_page; группа может задать свои значения: `[pagination.batches] max = 1000`
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PaginationConfig {
+    /// per_page без параметра в запросе; не задан - 20 (у списка реагентов 50)
+    pub default_per_page: Option<i64>,
+    pub reagents: PageLimitsConfig,
+    pub batches: PageLimitsConfig,
+    pub experiments: PageLimitsConfig,
+    pub equipment: PageLimitsConfig,
+}
+
+/// Переопределение пределов группы списков; незаданное значение берётся из общих
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(default)]
+pub struct PageLimitsConfig {
+    pub default: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl PaginationConfig {
+    pub fn group(&self, group: crate::pagination::PageGroup) -> Option<&PageLimitsConfig> {
+        use crate::pagination::PageGroup;
+        match group {
+            PageGroup::Reagents => Some(&self.reagents),
+            PageGroup::Batches => Some(&self.batches),
+            PageGroup::Experiments => Some(&self.experiments),
+            PageGroup::Equipment => Some(&self.equipment),
+            PageGroup::Other => None,
+        }
+    }
+
+    fn group_mut(&mut self, group: crate::pagination::PageGroup) -> Option<&mut PageLimitsConfig> {
+        use crate::pagination::PageGroup;
+        match group {
+            PageGroup::Reagents => Some(&mut self.reagents),
+            PageGroup::Batches => Some(&mut self.batches),
+            PageGroup::Experiments => Some(&mut self.experiments),
+            PageGroup::Equipment => Some(&mut self.equipment),
+            PageGroup::Other => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// http:// или https://
+    pub url: String,
+    /// Ключ подписи HMAC-SHA256 (заголовок X-LIMS-Signature); не задан - без подписи
+    pub secret: Option<String>,
+    /// Типы событий (BusinessEvent::TYPES); пусто - все
+    pub events: Vec<String>,
+}
+
+/// Публичный каталог реагентов только для чтения (см. public_catalogue)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PublicCatalogueConfig {
+    pub enabled: bool,
+    /// Общий токен доступа для других подразделений; не задан - каталог открыт без токена
+    pub access_token: Option<String>,
+    /// Лимит запросов с одного IP за окно, отдельный от основного API
+    pub rate_limit_requests: u32,
+    pub rate_limit_window_seconds: u64,
+    /// Поля реагента в ответе; допустимы только поля из public_catalogue::CATALOGUE_FIELDS
+    pub fields: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl DeploymentConfig {
+    /// Некорректное значение отсекается в Config::validate; здесь - UTC как запасной вариант
+    pub fn tz(&self) -> chrono_tz::Tz {
+        self.timezone.parse().unwrap_or(chrono_tz::UTC)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LoggingConfig {
     pub level: String,
     pub format: String,
@@ -89,6 +599,7 @@ impl Default for AuthConfig {
             max_login_attempts: 5,
             lockout_duration_minutes: 15,
             allow_self_registration: false,
+            username: UsernamePolicyConfig::default(),
         }
     }
 }
@@ -117,6 +628,14 @@ impl Default for DatabaseConfig {
             idle_timeout: 600,
             backup_enabled: true,
             backup_interval_hours: 24,
+            busy_timeout_ms: 5000,
+            journal_mode: "WAL".to_string(),
+            synchronous: "NORMAL".to_string(),
+            foreign_keys: true,
+            auto_migrate: true,
+            migration_guard: crate::schema::GUARD_FAIL.to_string(),
+            allow_runtime_migrate: None,
+            read_pool_size: 4,
         }
     }
 }
@@ -135,6 +654,8 @@ impl Default for SecurityConfig {
             rate_limit_window_seconds: 60,
             max_request_size: 1024 * 1024,
             require_https: false,
+            trusted_proxies: Vec::new(),
+            headers: SecurityHeadersConfig::default(),
         }
     }
 }
@@ -151,6 +672,226 @@ impl Default for LoggingConfig {
     }
 }
 
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            level: 6,
+            algorithms: vec!["gzip".to_string(), "deflate".to_string()],
+            min_size: 1024,
+            excluded_content_types: vec![
+                "application/vnd.openxmlformats-officedocument".to_string(),
+                "application/vnd.ms-excel".to_string(),
+                "application/zip".to_string(),
+                "application/gzip".to_string(),
+                "application/x-7z-compressed".to_string(),
+                "application/pdf".to_string(),
+                "application/octet-stream".to_string(),
+                "image/".to_string(),
+                "video/".to_string(),
+                "audio/".to_string(),
+            ],
+        }
+    }
+}
+
+impl Default for CatalogConfig {
+    fn default() -> Self {
+        Self {
+            pubchem_enabled: false,
+            pubchem_base_url: "https://pubchem.ncbi.nlm.nih.gov/rest/pug".to_string(),
+            timeout_seconds: 5,
+            cache_ttl_hours: 24 * 7,
+        }
+    }
+}
+
+impl Default for InactivityConfig {
+    fn default() -> Self {
+        Self {
+            deactivate_after_days: 0,
+            notice_days: 7,
+        }
+    }
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            slow_threshold_ms: 250,
+            ring_buffer_size: 500,
+        }
+    }
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            signoff_hazard_threshold: 2,
+        }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            audit_log_years: 1,
+            usage_history_years: 0,
+        }
+    }
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            output: EventLogOutput::Stdout,
+            file_path: "logs/events.log".to_string(),
+        }
+    }
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 587,
+            security: SmtpSecurity::Starttls,
+            username: None,
+            password: None,
+            from: "lims@localhost".to_string(),
+            timeout_seconds: 30,
+        }
+    }
+}
+
+impl Default for WorkQueueConfig {
+    fn default() -> Self {
+        Self {
+            xlsx_parse_concurrency: 1,
+            export_concurrency: 2,
+            max_queued: 8,
+            retry_after_secs: 30,
+        }
+    }
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            content_type_options: true,
+            frame_options: true,
+            xss_protection: true,
+            referrer_policy: true,
+            content_security_policy: Some(crate::security_headers::DEFAULT_CONTENT_SECURITY_POLICY.to_string()),
+            csp_report_only: false,
+            permissions_policy: Some(crate::security_headers::DEFAULT_PERMISSIONS_POLICY.to_string()),
+        }
+    }
+}
+
+impl Default for RequestTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            default_secs: 30,
+            export_secs: 120,
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 5,
+            batch_size: 100,
+            max_attempts: 8,
+            retry_base_secs: 30,
+            delivered_retention_days: 7,
+            webhook_timeout_secs: 10,
+            webhooks: Vec::new(),
+            email_to: Vec::new(),
+            email_events: Vec::new(),
+        }
+    }
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        // Калибровку заказывают у внешнего подрядчика заранее, уборка - на следующий день
+        let lead_days = [("calibration", 14), ("repair", 3), ("inspection", 7), ("cleaning", 1), ("replacement", 7)];
+        Self {
+            reminder_lead_days: lead_days.iter().map(|(t, days)| (t.to_string(), *days)).collect(),
+            default_reminder_lead_days: 3,
+            // Ремонт и уборка не ждут, плановая проверка может сдвинуться на неделю
+            sla_days: [("repair", 2), ("cleaning", 2)].iter().map(|(t, days)| (t.to_string(), *days)).collect(),
+            default_sla_days: 7,
+        }
+    }
+}
+
+impl Default for StockAnomalyConfig {
+    fn default() -> Self {
+        Self {
+            window_minutes: 60,
+            batch_ops_per_window: 50,
+            user_ops_per_window: 200,
+            single_consumption_percent: 50.0,
+        }
+    }
+}
+
+impl Default for DownloadLinkConfig {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: 300,
+            single_use: false,
+            public_base_url: None,
+        }
+    }
+}
+
+impl Default for PublicCatalogueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            access_token: None,
+            rate_limit_requests: 30,
+            rate_limit_window_seconds: 60,
+            fields: crate::public_catalogue::CATALOGUE_FIELDS.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+}
+
+impl Default for DeploymentConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+        }
+    }
+}
+
+impl Default for RuntimeSettingsConfig {
+    fn default() -> Self {
+        Self {
+            low_stock_threshold_percent: 20,
+            expiring_soon_days: 30,
+            notification_hour: 8,
+            max_upload_size_mb: 10,
+            max_per_page: crate::handlers::MAX_PER_PAGE,
+            forecast_window_days: 90,
+            undo_window_minutes: 15,
+            reagent_batches_all_limit: 1000,
+            approval_expiry_days: 7,
+            stock_adjustment_reasons: ["spillage", "evaporation", "recount", "damaged", "other"]
+                .iter()
+                .map(|reason| reason.to_string())
+                .collect(),
+            strict_field_filters: false,
+        }
+    }
+}
+
 impl Default for HotReloadConfig {
     fn default() -> Self {
         Self {
@@ -170,6 +911,26 @@ impl Default for Config {
             security: SecurityConfig::default(),
             logging: LoggingConfig::default(),
             hot_reload: HotReloadConfig::default(),
+            compression: CompressionConfig::default(),
+            catalog: CatalogConfig::default(),
+            inactivity: InactivityConfig::default(),
+            query_log: QueryLogConfig::default(),
+            safety: SafetyConfig::default(),
+            retention: RetentionConfig::default(),
+            settings: RuntimeSettingsConfig::default(),
+            events: EventLogConfig::default(),
+            smtp: SmtpConfig::default(),
+            deployment: DeploymentConfig::default(),
+            work_queues: WorkQueueConfig::default(),
+            public_catalogue: PublicCatalogueConfig::default(),
+            timeouts: RequestTimeoutConfig::default(),
+            outbox: OutboxConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            downloads: DownloadLinkConfig::default(),
+            pagination: PaginationConfig::default(),
+            reference_cache: ReferenceCacheConfig::default(),
+            experiment_report: ExperimentReportConfig::default(),
+            stock_anomalies: StockAnomalyConfig::default(),
         }
     }
 }
@@ -334,6 +1095,19 @@ fn override_with_env(config: &mut Config) -> Result<()> {
             config.auth.lockout_duration_minutes = lockout;
         }
     }
+    if let Ok(min_str) = env::var("AUTH_USERNAME_MIN_LENGTH") {
+        if let Ok(min) = min_str.parse::<usize>() {
+            config.auth.username.min_length = min;
+        }
+    }
+    if let Ok(max_str) = env::var("AUTH_USERNAME_MAX_LENGTH") {
+        if let Ok(max) = max_str.parse::<usize>() {
+            config.auth.username.max_length = max;
+        }
+    }
+    if let Ok(symbols) = env::var("AUTH_USERNAME_ALLOWED_SYMBOLS") {
+        config.auth.username.allowed_symbols = symbols;
+    }
     if let Ok(url) = env::var("DATABASE_URL") {
         config.database.url = url;
 		
@@ -348,6 +1122,40 @@ fn override_with_env(config: &mut Config) -> Result<()> {
             config.database.min_connections = min_conn;
         }
     }
+    if let Ok(read_str) = env::var("DATABASE_READ_POOL_SIZE") {
+        if let Ok(read_size) = read_str.parse::<u32>() {
+            config.database.read_pool_size = read_size;
+        }
+    }
+    if let Ok(busy_str) = env::var("DATABASE_BUSY_TIMEOUT_MS") {
+        if let Ok(busy) = busy_str.parse::<u64>() {
+            config.database.busy_timeout_ms = busy;
+        }
+    }
+    if let Ok(mode) = env::var("DATABASE_JOURNAL_MODE") {
+        config.database.journal_mode = mode.trim().to_uppercase();
+    }
+    if let Ok(sync) = env::var("DATABASE_SYNCHRONOUS") {
+        config.database.synchronous = sync.trim().to_uppercase();
+    }
+    if let Ok(fk_str) = env::var("DATABASE_FOREIGN_KEYS") {
+        if let Ok(fk) = fk_str.parse::<bool>() {
+            config.database.foreign_keys = fk;
+        }
+    }
+    if let Ok(auto_str) = env::var("DATABASE_AUTO_MIGRATE") {
+        if let Ok(auto) = auto_str.parse::<bool>() {
+            config.database.auto_migrate = auto;
+        }
+    }
+    if let Ok(guard) = env::var("DATABASE_MIGRATION_GUARD") {
+        config.database.migration_guard = guard.trim().to_lowercase();
+    }
+    if let Ok(allow_str) = env::var("DATABASE_ALLOW_RUNTIME_MIGRATE") {
+        if let Ok(allow) = allow_str.parse::<bool>() {
+            config.database.allow_runtime_migrate = Some(allow);
+        }
+    }
     if let Ok(origins_str) = env::var("ALLOWED_ORIGINS") {
         config.security.allowed_origins = origins_str
             .split(',')
@@ -355,9 +1163,265 @@ fn override_with_env(config: &mut Config) -> Result<()> {
             .filter(|s| !s.is_empty())
             .collect();
     }
+    if let Ok(proxies_str) = env::var("TRUSTED_PROXIES") {
+        config.security.trusted_proxies = proxies_str
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+    }
+    if let Ok(policy) = env::var("CONTENT_SECURITY_POLICY") {
+        config.security.headers.content_security_policy = Some(policy).filter(|p| !p.trim().is_empty());
+    }
+    if let Ok(report_only_str) = env::var("CSP_REPORT_ONLY") {
+        if let Ok(report_only) = report_only_str.parse::<bool>() {
+            config.security.headers.csp_report_only = report_only;
+        }
+    }
     if let Ok(level) = env::var("RUST_LOG") {
         config.logging.level = level;
     }
+    if let Ok(enabled_str) = env::var("COMPRESSION_ENABLED") {
+        if let Ok(enabled) = enabled_str.parse::<bool>() {
+            config.compression.enabled = enabled;
+        }
+    }
+    if let Ok(level_str) = env::var("COMPRESSION_LEVEL") {
+        if let Ok(level) = level_str.parse::<u32>() {
+            config.compression.level = level;
+        }
+    }
+    if let Ok(algorithms_str) = env::var("COMPRESSION_ALGORITHMS") {
+        config.compression.algorithms = algorithms_str
+            .split(',')
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+    }
+    if let Ok(min_size_str) = env::var("COMPRESSION_MIN_SIZE") {
+        if let Ok(min_size) = min_size_str.parse::<usize>() {
+            config.compression.min_size = min_size;
+        }
+    }
+    if let Ok(enabled_str) = env::var("PUBCHEM_ENABLED") {
+        if let Ok(enabled) = enabled_str.parse::<bool>() {
+            config.catalog.pubchem_enabled = enabled;
+        }
+    }
+    if let Ok(url) = env::var("PUBCHEM_BASE_URL") {
+        config.catalog.pubchem_base_url = url;
+    }
+    if let Ok(timeout_str) = env::var("PUBCHEM_TIMEOUT_SECONDS") {
+        if let Ok(timeout) = timeout_str.parse::<u64>() {
+            config.catalog.timeout_seconds = timeout;
+        }
+    }
+    if let Ok(ttl_str) = env::var("PUBCHEM_CACHE_TTL_HOURS") {
+        if let Ok(ttl) = ttl_str.parse::<i64>() {
+            config.catalog.cache_ttl_hours = ttl;
+        }
+    }
+    if let Ok(days_str) = env::var("INACTIVITY_DEACTIVATE_DAYS") {
+        if let Ok(days) = days_str.parse::<i64>() {
+            config.inactivity.deactivate_after_days = days;
+        }
+    }
+    if let Ok(days_str) = env::var("INACTIVITY_NOTICE_DAYS") {
+        if let Ok(days) = days_str.parse::<i64>() {
+            config.inactivity.notice_days = days;
+        }
+    }
+    if let Ok(enabled_str) = env::var("QUERY_LOG_ENABLED") {
+        if let Ok(enabled) = enabled_str.parse::<bool>() {
+            config.query_log.enabled = enabled;
+        }
+    }
+    if let Ok(ms_str) = env::var("SLOW_QUERY_THRESHOLD_MS") {
+        if let Ok(ms) = ms_str.parse::<u64>() {
+            config.query_log.slow_threshold_ms = ms;
+        }
+    }
+    if let Ok(size_str) = env::var("SLOW_QUERY_BUFFER_SIZE") {
+        if let Ok(size) = size_str.parse::<usize>() {
+            config.query_log.ring_buffer_size = size;
+        }
+    }
+    if let Ok(threshold_str) = env::var("SIGNOFF_HAZARD_THRESHOLD") {
+        if let Ok(threshold) = threshold_str.parse::<u8>() {
+            config.safety.signoff_hazard_threshold = threshold;
+        }
+    }
+    if let Ok(years_str) = env::var("RETENTION_AUDIT_LOG_YEARS") {
+        if let Ok(years) = years_str.parse::<u32>() {
+            config.retention.audit_log_years = years;
+        }
+    }
+    if let Ok(years_str) = env::var("RETENTION_USAGE_HISTORY_YEARS") {
+        if let Ok(years) = years_str.parse::<u32>() {
+            config.retention.usage_history_years = years;
+        }
+    }
+    if let Ok(output_str) = env::var("EVENT_LOG_OUTPUT") {
+        if let Some(output) = EventLogOutput::parse(&output_str) {
+            config.events.output = output;
+        }
+    }
+    if let Ok(path) = env::var("EVENT_LOG_FILE") {
+        config.events.file_path = path;
+    }
+    if let Ok(host) = env::var("SMTP_HOST") {
+        config.smtp.host = host;
+    }
+    if let Ok(port_str) = env::var("SMTP_PORT") {
+        if let Ok(port) = port_str.parse::<u16>() {
+            config.smtp.port = port;
+        }
+    }
+    if let Ok(security_str) = env::var("SMTP_SECURITY") {
+        if let Some(security) = SmtpSecurity::parse(&security_str) {
+            config.smtp.security = security;
+        }
+    }
+    if let Ok(username) = env::var("SMTP_USERNAME") {
+        config.smtp.username = Some(username).filter(|u| !u.is_empty());
+    }
+    if let Ok(password) = env::var("SMTP_PASSWORD") {
+        config.smtp.password = Some(password).filter(|p| !p.is_empty());
+    }
+    if let Ok(from) = env::var("SMTP_FROM") {
+        config.smtp.from = from;
+    }
+    if let Ok(timezone) = env::var("LIMS_TIMEZONE") {
+        config.deployment.timezone = timezone;
+    }
+    if let Ok(enabled_str) = env::var("PUBLIC_CATALOGUE_ENABLED") {
+        if let Ok(enabled) = enabled_str.parse::<bool>() {
+            config.public_catalogue.enabled = enabled;
+        }
+    }
+    if let Ok(token) = env::var("PUBLIC_CATALOGUE_TOKEN") {
+        config.public_catalogue.access_token = Some(token).filter(|t| !t.is_empty());
+    }
+    let work_queue_limits = [
+        ("IMPORT_PARSE_CONCURRENCY", &mut config.work_queues.xlsx_parse_concurrency),
+        ("EXPORT_CONCURRENCY", &mut config.work_queues.export_concurrency),
+        ("WORK_QUEUE_MAX_QUEUED", &mut config.work_queues.max_queued),
+    ];
+    for (var, target) in work_queue_limits {
+        if let Some(value) = env::var(var).ok().and_then(|v| v.parse::<usize>().ok()) {
+            *target = value;
+        }
+    }
+    // Один вебхук из окружения добавляется к перечисленным в файле конфигурации
+    if let Ok(url) = env::var("OUTBOX_WEBHOOK_URL") {
+        if !url.trim().is_empty() {
+            config.outbox.webhooks.push(WebhookConfig {
+                url: url.trim().to_string(),
+                secret: env::var("OUTBOX_WEBHOOK_SECRET").ok().filter(|s| !s.is_empty()),
+                events: Vec::new(),
+            });
+        }
+    }
+    // MAINTENANCE_REMINDER_LEAD_DAYS_CALIBRATION=14 и т.п. для каждого типа обслуживания
+    for maintenance_type in <crate::query_builders::MaintenanceType as strum::VariantNames>::VARIANTS {
+        let var = format!("MAINTENANCE_REMINDER_LEAD_DAYS_{}", maintenance_type.to_uppercase());
+        if let Some(days) = env::var(&var).ok().and_then(|v| v.parse::<i64>().ok()) {
+            config.maintenance.reminder_lead_days.insert(maintenance_type.to_string(), days);
+        }
+        // MAINTENANCE_SLA_DAYS_REPAIR=1 и т.п.
+        let var = format!("MAINTENANCE_SLA_DAYS_{}", maintenance_type.to_uppercase());
+        if let Some(days) = env::var(&var).ok().and_then(|v| v.parse::<i64>().ok()) {
+            config.maintenance.sla_days.insert(maintenance_type.to_string(), days);
+        }
+    }
+    if let Some(days) = env::var("MAINTENANCE_DEFAULT_SLA_DAYS").ok().and_then(|v| v.parse::<i64>().ok()) {
+        config.maintenance.default_sla_days = days;
+    }
+    if let Some(ttl) = env::var("DOWNLOAD_LINK_TTL_SECONDS").ok().and_then(|v| v.parse::<u64>().ok()) {
+        config.downloads.ttl_seconds = ttl;
+    }
+    if let Ok(single_use_str) = env::var("DOWNLOAD_LINK_SINGLE_USE") {
+        if let Ok(single_use) = single_use_str.parse::<bool>() {
+            config.downloads.single_use = single_use;
+        }
+    }
+    if let Ok(url) = env::var("DOWNLOAD_LINK_PUBLIC_BASE_URL") {
+        config.downloads.public_base_url = Some(url).filter(|u| !u.trim().is_empty());
+    }
+    if let Some(ttl) = env::var("REFERENCE_CACHE_TTL_SECONDS").ok().and_then(|v| v.parse::<u64>().ok()) {
+        config.reference_cache.ttl_seconds = ttl;
+    }
+    if let Ok(font_path) = env::var("EXPERIMENT_REPORT_FONT_PATH") {
+        config.experiment_report.font_path = Some(font_path).filter(|p| !p.trim().is_empty());
+    }
+    if let Some(minutes) = env::var("STOCK_ANOMALY_WINDOW_MINUTES").ok().and_then(|v| v.parse::<i64>().ok()) {
+        config.stock_anomalies.window_minutes = minutes;
+    }
+    let anomaly_limits = [
+        ("STOCK_ANOMALY_BATCH_OPS", &mut config.stock_anomalies.batch_ops_per_window),
+        ("STOCK_ANOMALY_USER_OPS", &mut config.stock_anomalies.user_ops_per_window),
+    ];
+    for (var, target) in anomaly_limits {
+        if let Some(value) = env::var(var).ok().and_then(|v| v.parse::<u64>().ok()) {
+            *target = value;
+        }
+    }
+    if let Some(percent) = env::var("STOCK_ANOMALY_SINGLE_CONSUMPTION_PERCENT").ok().and_then(|v| v.parse::<f64>().ok()) {
+        config.stock_anomalies.single_consumption_percent = percent;
+    }
+    if let Some(per_page) = env::var("PAGINATION_DEFAULT_PER_PAGE").ok().and_then(|v| v.parse::<i64>().ok()) {
+        config.pagination.default_per_page = Some(per_page);
+    }
+    // PAGINATION_BATCHES_MAX=1000, PAGINATION_EXPERIMENTS_DEFAULT=10 и т.п.
+    for group in crate::pagination::PageGroup::CONFIGURABLE {
+        let Some(limits) = config.pagination.group_mut(group) else { continue };
+        let prefix = format!("PAGINATION_{}", group.as_str().to_uppercase());
+        if let Some(value) = env::var(format!("{}_DEFAULT", prefix)).ok().and_then(|v| v.parse::<i64>().ok()) {
+            limits.default = Some(value);
+        }
+        if let Some(value) = env::var(format!("{}_MAX", prefix)).ok().and_then(|v| v.parse::<i64>().ok()) {
+            limits.max = Some(value);
+        }
+    }
+    let timeouts = [
+        ("REQUEST_TIMEOUT_SECS", &mut config.timeouts.default_secs),
+        ("EXPORT_TIMEOUT_SECS", &mut config.timeouts.export_secs),
+    ];
+    for (var, target) in timeouts {
+        if let Some(value) = env::var(var).ok().and_then(|v| v.parse::<u64>().ok()) {
+            *target = value;
+        }
+    }
+    let runtime_defaults = [
+        ("LOW_STOCK_THRESHOLD_PERCENT", &mut config.settings.low_stock_threshold_percent),
+        ("EXPIRING_SOON_DAYS", &mut config.settings.expiring_soon_days),
+        ("NOTIFICATION_HOUR", &mut config.settings.notification_hour),
+        ("MAX_UPLOAD_SIZE_MB", &mut config.settings.max_upload_size_mb),
+        ("MAX_PER_PAGE", &mut config.settings.max_per_page),
+        ("FORECAST_WINDOW_DAYS", &mut config.settings.forecast_window_days),
+        ("UNDO_WINDOW_MINUTES", &mut config.settings.undo_window_minutes),
+        ("REAGENT_BATCHES_ALL_LIMIT", &mut config.settings.reagent_batches_all_limit),
+        ("APPROVAL_EXPIRY_DAYS", &mut config.settings.approval_expiry_days),
+    ];
+    for (var, target) in runtime_defaults {
+        if let Some(value) = env::var(var).ok().and_then(|v| v.parse::<i64>().ok()) {
+            *target = value;
+        }
+    }
+    if let Ok(strict_str) = env::var("STRICT_FIELD_FILTERS") {
+        if let Ok(strict) = strict_str.parse::<bool>() {
+            config.settings.strict_field_filters = strict;
+        }
+    }
+    if let Ok(reasons) = env::var("STOCK_ADJUSTMENT_REASONS") {
+        let reasons: Vec<String> = reasons.split(',')
+            .map(|reason| reason.trim().to_string())
+            .filter(|reason| !reason.is_empty())
+            .collect();
+        if !reasons.is_empty() {
+            config.settings.stock_adjustment_reasons = reasons;
+        }
+    }
 
     Ok(())
 }
@@ -371,6 +1435,19 @@ impl Config {
             ));
         }
 
+        let username = &self.auth.username;
+        if username.min_length < 3 || username.max_length > 50 || username.min_length > username.max_length {
+            return Err(anyhow::anyhow!(
+                "auth.username length limits must satisfy 3 <= min_length <= max_length <= 50 (current: {}..{})",
+                username.min_length, username.max_length
+            ));
+        }
+        if let Some(c) = username.allowed_symbols.chars().find(|c| !c.is_ascii_punctuation() || *c == '@') {
+            return Err(anyhow::anyhow!(
+                "auth.username allowed_symbols may only contain ASCII punctuation other than '@' (found {:?})", c
+            ));
+        }
+
         if self.database.max_connections < self.database.min_connections {
             return Err(anyhow::anyhow!(
                 "max_connections ({}) must be >= min_connections ({})",
@@ -379,6 +1456,208 @@ impl Config {
             ));
         }
 
+        if self.database.max_connections == 0 {
+            return Err(anyhow::anyhow!("database max_connections must be greater than 0"));
+        }
+
+        if self.database.journal_mode.parse::<sqlx::sqlite::SqliteJournalMode>().is_err() {
+            return Err(anyhow::anyhow!(
+                "Unsupported database journal_mode '{}' (supported: DELETE, TRUNCATE, PERSIST, MEMORY, WAL, OFF)",
+                self.database.journal_mode
+            ));
+        }
+
+        if ![crate::schema::GUARD_FAIL, crate::schema::GUARD_WARN].contains(&self.database.migration_guard.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Unsupported database migration_guard '{}' (supported: fail, warn)",
+                self.database.migration_guard
+            ));
+        }
+
+        if self.database.synchronous.parse::<sqlx::sqlite::SqliteSynchronous>().is_err() {
+            return Err(anyhow::anyhow!(
+                "Unsupported database synchronous '{}' (supported: OFF, NORMAL, FULL, EXTRA)",
+                self.database.synchronous
+            ));
+        }
+
+        if let Err(e) = crate::client_ip::TrustedProxies::parse(&self.security.trusted_proxies) {
+            return Err(anyhow::anyhow!("security trusted_proxies: {}", e));
+        }
+
+        if self.compression.level > 9 {
+            return Err(anyhow::anyhow!(
+                "compression level must be between 0 and 9 (current: {})",
+                self.compression.level
+            ));
+        }
+
+        for algorithm in &self.compression.algorithms {
+            if algorithm != "gzip" && algorithm != "deflate" {
+                return Err(anyhow::anyhow!(
+                    "Unsupported compression algorithm '{}' (supported: gzip, deflate)",
+                    algorithm
+                ));
+            }
+        }
+
+        if self.inactivity.deactivate_after_days < 0 || self.inactivity.notice_days < 0 {
+            return Err(anyhow::anyhow!("inactivity days must not be negative"));
+        }
+        if self.events.output.writes_file() && self.events.file_path.trim().is_empty() {
+            return Err(anyhow::anyhow!("events file_path must be set when event output includes a file"));
+        }
+        if self.query_log.enabled && self.query_log.ring_buffer_size == 0 {
+            return Err(anyhow::anyhow!("query_log ring_buffer_size must be greater than 0"));
+        }
+        if self.safety.signoff_hazard_threshold > crate::models::MAX_HAZARD_SEVERITY {
+            return Err(anyhow::anyhow!(
+                "safety signoff_hazard_threshold must be between 0 and {} (current: {})",
+                crate::models::MAX_HAZARD_SEVERITY,
+                self.safety.signoff_hazard_threshold
+            ));
+        }
+        if self.deployment.timezone.parse::<chrono_tz::Tz>().is_err() {
+            return Err(anyhow::anyhow!(
+                "Unknown deployment timezone '{}' (expected an IANA name such as Europe/Moscow)",
+                self.deployment.timezone
+            ));
+        }
+        if self.work_queues.xlsx_parse_concurrency == 0 || self.work_queues.export_concurrency == 0 {
+            return Err(anyhow::anyhow!("work_queues concurrency must be greater than 0"));
+        }
+        if self.timeouts.default_secs == 0
+            || self.timeouts.export_secs == 0
+            || self.timeouts.routes.values().any(|secs| *secs == 0)
+        {
+            return Err(anyhow::anyhow!("request timeouts must be greater than 0 seconds"));
+        }
+        if self.outbox.poll_interval_secs == 0 || self.outbox.batch_size <= 0 || self.outbox.max_attempts <= 0 {
+            return Err(anyhow::anyhow!("outbox poll_interval_secs, batch_size and max_attempts must be greater than 0"));
+        }
+        let outbox_events = self.outbox.webhooks.iter()
+            .flat_map(|hook| hook.events.iter())
+            .chain(self.outbox.email_events.iter());
+        for event_type in outbox_events {
+            if !crate::events::BusinessEvent::TYPES.contains(&event_type.as_str()) {
+                return Err(anyhow::anyhow!(
+                    "unknown outbox event type '{}' (known: {})",
+                    event_type,
+                    crate::events::BusinessEvent::TYPES.join(", ")
+                ));
+            }
+        }
+        let maintenance_types = <crate::query_builders::MaintenanceType as strum::VariantNames>::VARIANTS;
+        if let Some(t) = self.maintenance.reminder_lead_days.keys().find(|t| !maintenance_types.contains(&t.as_str())) {
+            return Err(anyhow::anyhow!(
+                "unknown maintenance type '{}' in reminder_lead_days (known: {})", t, maintenance_types.join(", ")
+            ));
+        }
+        let lead_days = self.maintenance.reminder_lead_days.values().chain([&self.maintenance.default_reminder_lead_days]);
+        if let Some(days) = lead_days.into_iter().find(|d| !(0..=crate::models::MAX_REMINDER_LEAD_DAYS).contains(*d)) {
+            return Err(anyhow::anyhow!(
+                "maintenance reminder lead days must be between 0 and {} (current: {})",
+                crate::models::MAX_REMINDER_LEAD_DAYS, days
+            ));
+        }
+        if let Some(t) = self.maintenance.sla_days.keys().find(|t| !maintenance_types.contains(&t.as_str())) {
+            return Err(anyhow::anyhow!(
+                "unknown maintenance type '{}' in sla_days (known: {})", t, maintenance_types.join(", ")
+            ));
+        }
+        let sla_days = self.maintenance.sla_days.values().chain([&self.maintenance.default_sla_days]);
+        if let Some(days) = sla_days.into_iter().find(|d| !(0..=crate::models::MAX_SLA_DAYS).contains(*d)) {
+            return Err(anyhow::anyhow!(
+                "maintenance SLA days must be between 0 and {} (current: {})",
+                crate::models::MAX_SLA_DAYS, days
+            ));
+        }
+        let page_groups = crate::pagination::PageGroup::CONFIGURABLE.iter()
+            .filter_map(|g| self.pagination.group(*g).map(|limits| (g.as_str(), limits)));
+        let page_limits = page_groups.clone()
+            .flat_map(|(_, limits)| [limits.default, limits.max])
+            .chain([self.pagination.default_per_page]);
+        if let Some(value) = page_limits.flatten().find(|v| !(1..=crate::pagination::MAX_PAGE_SIZE).contains(v)) {
+            return Err(anyhow::anyhow!(
+                "pagination per_page limits must be between 1 and {} (current: {})",
+                crate::pagination::MAX_PAGE_SIZE, value
+            ));
+        }
+        for (group, limits) in page_groups {
+            if let (Some(default), Some(max)) = (limits.default, limits.max) {
+                if default > max {
+                    return Err(anyhow::anyhow!(
+                        "pagination.{} default ({}) must not exceed max ({})", group, default, max
+                    ));
+                }
+            }
+        }
+        if !(1..=crate::signed_downloads::MAX_LINK_TTL_SECONDS).contains(&self.downloads.ttl_seconds) {
+            return Err(anyhow::anyhow!(
+                "downloads ttl_seconds must be between 1 and {} (current: {})",
+                crate::signed_downloads::MAX_LINK_TTL_SECONDS, self.downloads.ttl_seconds
+            ));
+        }
+        if let Some(url) = &self.downloads.public_base_url {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(anyhow::anyhow!("downloads public_base_url must start with http:// or https:// (current: {})", url));
+            }
+        }
+        if self.reference_cache.ttl_seconds > crate::reference_cache::MAX_TTL_SECONDS {
+            return Err(anyhow::anyhow!(
+                "reference_cache ttl_seconds must be at most {} (current: {})",
+                crate::reference_cache::MAX_TTL_SECONDS, self.reference_cache.ttl_seconds
+            ));
+        }
+        if let Some(font_path) = &self.experiment_report.font_path {
+            if !Path::new(font_path).is_file() {
+                return Err(anyhow::anyhow!("experiment_report font_path '{}' is not a file", font_path));
+            }
+        }
+        if !(1..=crate::stock_anomalies::MAX_WINDOW_MINUTES).contains(&self.stock_anomalies.window_minutes) {
+            return Err(anyhow::anyhow!(
+                "stock_anomalies window_minutes must be between 1 and {} (current: {})",
+                crate::stock_anomalies::MAX_WINDOW_MINUTES, self.stock_anomalies.window_minutes
+            ));
+        }
+        let percent = self.stock_anomalies.single_consumption_percent;
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(anyhow::anyhow!(
+                "stock_anomalies single_consumption_percent must be between 0 and 100 (current: {})", percent
+            ));
+        }
+        if let Some(hook) = self.outbox.webhooks.iter()
+            .find(|hook| !(hook.url.starts_with("http://") || hook.url.starts_with("https://")))
+        {
+            return Err(anyhow::anyhow!("outbox webhook url must start with http:// or https:// (current: '{}')", hook.url));
+        }
+        if let Some(field) = self.public_catalogue.fields.iter()
+            .find(|f| !crate::public_catalogue::CATALOGUE_FIELDS.contains(&f.as_str()))
+        {
+            return Err(anyhow::anyhow!(
+                "public_catalogue field '{}' is not allowed (allowed: {})",
+                field,
+                crate::public_catalogue::CATALOGUE_FIELDS.join(", ")
+            ));
+        }
+        if self.public_catalogue.access_token.as_ref().is_some_and(|t| t.len() < 16) {
+            return Err(anyhow::anyhow!("public_catalogue access_token must be at least 16 characters long"));
+        }
+        if self.public_catalogue.rate_limit_requests == 0 || self.public_catalogue.rate_limit_window_seconds == 0 {
+            return Err(anyhow::anyhow!("public_catalogue rate limit must be greater than 0"));
+        }
+        if self.smtp.is_enabled() && !self.smtp.from.contains('@') {
+            return Err(anyhow::anyhow!("smtp from must be an email address (current: '{}')", self.smtp.from));
+        }
+        crate::settings::validate_defaults(&self.settings).map_err(|e| anyhow::anyhow!("settings: {}", e))?;
+        if self.inactivity.is_enabled() && self.inactivity.notice_days >= self.inactivity.deactivate_after_days {
+            return Err(anyhow::anyhow!(
+                "inactivity notice_days ({}) must be less than deactivate_after_days ({})",
+                self.inactivity.notice_days,
+                self.inactivity.deactivate_after_days
+            ));
+        }
+
         Ok(())
     }
 
@@ -0,0 +1,1271 @@
+// src/config_transfer.rs
+//! Перенос конфигурации между экземплярами:
+//!   GET  /admin/config-export                 — один JSON-документ с конфигурацией
+//!   POST /admin/config-import?dry_run=true    — применение документа (dry_run - без записи)
+//!
+//! В документ входят помещения, дерево мест хранения, команды, роли и индивидуальные
+//! права пользователей, пресеты отчётов, карта нормализации производителей и
+//! переопределённые настройки. Инвентарных данных (реагенты, партии, оборудование,
+//! эксперименты) и учётных данных пользователей в нём нет: пользователи сопоставляются
+//! по username и при импорте не создаются.
+//!
+//! Импорт идемпотентен: записи сопоставляются по естественным ключам (имя помещения,
+//! путь места хранения, имя команды, username, владелец + имя пресета, написание
+//! производителя, ключ настройки), недостающие создаются, отличающиеся обновляются,
+//! ничего не удаляется. Запись, которую нельзя применить, попадает в `conflicts` и не
+//! мешает остальным. dry_run выполняет тот же импорт в транзакции и откатывает её,
+//! поэтому отчёт пробного прогона совпадает с отчётом настоящего.
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::{get_current_user, UserRole};
+use crate::equipment_catalog::{collapse_whitespace, normalization_groups, variant_key, NormalizationGroup};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{
+    parse_weekly_hours, CreateRoomRequest, CreateTeamRequest, LocationType, Room, WeeklyHours, TEAM_ROLES,
+};
+use crate::report_handlers::{validate_preset_request, SaveReportPresetRequest};
+use crate::room_handlers::{DEFAULT_CLOSING_TIME, DEFAULT_OPENING_TIME, DEFAULT_OPEN_DAYS};
+use crate::settings::{definition, settings, validate_value, SettingsStore};
+use crate::AppState;
+
+/// Версия формата документа; увеличивается при несовместимых изменениях структуры
+pub const CONFIG_FORMAT_VERSION: u64 = 1;
+
+/// Документ создан более новой версией приложения, чем эта
+pub const CONFIG_FORMAT_TOO_NEW: &str = "CONFIG_FORMAT_TOO_NEW";
+
+/// Цвет нового помещения, как в POST /rooms
+const DEFAULT_ROOM_COLOR: &str = "#667eea";
+
+const ROOMS: &str = "rooms";
+const LOCATIONS: &str = "locations";
+const TEAMS: &str = "teams";
+const TEAM_MEMBERS: &str = "team_members";
+const USERS: &str = "users";
+const REPORT_PRESETS: &str = "report_presets";
+const MANUFACTURER_NORMALIZATIONS: &str = "manufacturer_normalizations";
+const SETTINGS: &str = "settings";
+
+// ==================== DOCUMENT ====================
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigDocument {
+    pub format_version: u64,
+    #[serde(default)]
+    pub exported_at: Option<DateTime<Utc>>,
+    /// Версия приложения, создавшего документ (справочно)
+    #[serde(default)]
+    pub app_version: Option<String>,
+    #[serde(default)]
+    pub rooms: Vec<ConfigRoom>,
+    /// Родитель всегда идёт раньше вложенных узлов
+    #[serde(default)]
+    pub locations: Vec<ConfigLocation>,
+    #[serde(default)]
+    pub teams: Vec<ConfigTeam>,
+    #[serde(default)]
+    pub users: Vec<ConfigUserAccess>,
+    #[serde(default)]
+    pub report_presets: Vec<ConfigReportPreset>,
+    #[serde(default)]
+    pub manufacturer_normalizations: Vec<NormalizationGroup>,
+    /// Только переопределённые администратором значения; `null` снимает переопределение
+    #[serde(default)]
+    pub settings: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigRoom {
+    pub name: String,
+    pub description: Option<String>,
+    pub capacity: Option<i32>,
+    pub color: Option<String>,
+    pub opening_time: Option<String>,
+    pub closing_time: Option<String>,
+    pub open_days: Option<String>,
+    pub weekly_hours: Option<WeeklyHours>,
+}
+
+impl From<Room> for ConfigRoom {
+    fn from(room: Room) -> Self {
+        Self {
+            weekly_hours: room.weekly_hours.as_deref().and_then(parse_weekly_hours),
+            name: room.name,
+            description: room.description,
+            capacity: room.capacity,
+            color: room.color,
+            opening_time: room.opening_time,
+            closing_time: room.closing_time,
+            open_days: room.open_days,
+        }
+    }
+}
+
+impl ConfigRoom {
+    /// Незаданные поля получают те же значения по умолчанию, что и при POST /rooms
+    fn normalized(&self) -> Self {
+        Self {
+            name: self.name.trim().to_string(),
+            color: Some(self.color.clone().unwrap_or_else(|| DEFAULT_ROOM_COLOR.to_string())),
+            opening_time: Some(self.opening_time.clone().unwrap_or_else(|| DEFAULT_OPENING_TIME.to_string())),
+            closing_time: Some(self.closing_time.clone().unwrap_or_else(|| DEFAULT_CLOSING_TIME.to_string())),
+            open_days: Some(self.open_days.clone().unwrap_or_else(|| DEFAULT_OPEN_DAYS.to_string())),
+            weekly_hours: self.weekly_hours.clone().filter(|hours| !hours.is_empty()),
+            ..self.clone()
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let request = CreateRoomRequest {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            capacity: self.capacity,
+            color: self.color.clone(),
+            opening_time: self.opening_time.clone(),
+            closing_time: self.closing_time.clone(),
+            open_days: self.open_days.clone(),
+            weekly_hours: self.weekly_hours.clone(),
+        };
+        request.validate().map_err(|e| e.to_string())?;
+        // HH:MM уже проверен, строки сравниваются как время
+        if self.opening_time >= self.closing_time {
+            return Err("Closing time must be later than opening time".to_string());
+        }
+        Ok(())
+    }
+
+    fn weekly_hours_column(&self) -> Option<String> {
+        self.weekly_hours.as_ref().and_then(|hours| serde_json::to_string(hours).ok())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigLocation {
+    /// Имена узлов от корня: ["Building A", "Room 101", "Fridge 2"]
+    pub path: Vec<String>,
+    #[serde(rename = "type")]
+    pub location_type: LocationType,
+    /// Имя помещения из справочника rooms
+    pub room: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigTeam {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub members: Vec<ConfigTeamMember>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigTeamMember {
+    pub username: String,
+    pub role: String,
+}
+
+/// Роль и индивидуальные права пользователя (без учётных данных)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigUserAccess {
+    pub username: String,
+    pub role: String,
+    /// None - индивидуальные права не заданы (действуют права роли), при импорте не меняются
+    pub permissions: Option<BTreeMap<String, bool>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigReportPreset {
+    /// username владельца
+    pub owner: String,
+    #[serde(flatten)]
+    pub preset: SaveReportPresetRequest,
+}
+
+// ==================== EXPORT ====================
+
+#[derive(Debug, sqlx::FromRow)]
+struct LocationExportRow {
+    id: String,
+    parent_id: Option<String>,
+    name: String,
+    location_type: String,
+    room_name: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct PresetExportRow {
+    owner: String,
+    name: String,
+    description: Option<String>,
+    filters: String,
+    columns: String,
+    default_sort: Option<String>,
+    default_sort_order: Option<String>,
+    is_shared: bool,
+}
+
+async fn export_locations(pool: &SqlitePool) -> ApiResult<Vec<ConfigLocation>> {
+    let rows: Vec<LocationExportRow> = sqlx::query_as(
+        "SELECT l.id, l.parent_id, l.name, l.location_type, r.name AS room_name, l.description \
+         FROM locations l LEFT JOIN rooms r ON r.id = l.room_id"
+    )
+        .fetch_all(pool)
+        .await?;
+    let by_id: HashMap<&str, &LocationExportRow> = rows.iter().map(|row| (row.id.as_str(), row)).collect();
+
+    let mut locations = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let Some(location_type) = LocationType::parse(&row.location_type) else {
+            log::warn!("Config export: skipping location {} with unknown type '{}'", row.id, row.location_type);
+            continue;
+        };
+        let mut path = vec![row.name.clone()];
+        let mut parent = row.parent_id.as_deref();
+        while let Some(parent_row) = parent.and_then(|id| by_id.get(id)) {
+            // Защита от цикла в повреждённых данных
+            if path.len() > rows.len() {
+                break;
+            }
+            path.insert(0, parent_row.name.clone());
+            parent = parent_row.parent_id.as_deref();
+        }
+        locations.push(ConfigLocation {
+            path,
+            location_type,
+            room: row.room_name.clone(),
+            description: row.description.clone(),
+        });
+    }
+    locations.sort_by(|a, b| a.path.len().cmp(&b.path.len()).then_with(|| a.path.cmp(&b.path)));
+    Ok(locations)
+}
+
+async fn export_teams(pool: &SqlitePool) -> ApiResult<Vec<ConfigTeam>> {
+    let teams: Vec<(String, String, Option<String>)> =
+        sqlx::query_as("SELECT id, name, description FROM teams ORDER BY name")
+            .fetch_all(pool)
+            .await?;
+    let members: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT tm.team_id, u.username, tm.role FROM team_members tm \
+         JOIN users u ON u.id = tm.user_id ORDER BY u.username"
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let mut by_team: HashMap<String, Vec<ConfigTeamMember>> = HashMap::new();
+    for (team_id, username, role) in members {
+        by_team.entry(team_id).or_default().push(ConfigTeamMember { username, role });
+    }
+    Ok(teams
+        .into_iter()
+        .map(|(id, name, description)| ConfigTeam {
+            name,
+            description,
+            members: by_team.remove(&id).unwrap_or_default(),
+        })
+        .collect())
+}
+
+async fn export_users(pool: &SqlitePool) -> ApiResult<Vec<ConfigUserAccess>> {
+    let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+        "SELECT u.username, u.role, p.permissions FROM users u \
+         LEFT JOIN user_permissions p ON p.user_id = u.id ORDER BY u.username"
+    )
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(username, role, permissions)| ConfigUserAccess {
+            username,
+            role,
+            permissions: permissions.and_then(|raw| serde_json::from_str(&raw).ok()),
+        })
+        .collect())
+}
+
+async fn export_report_presets(pool: &SqlitePool) -> ApiResult<Vec<ConfigReportPreset>> {
+    let rows: Vec<PresetExportRow> = sqlx::query_as(
+        "SELECT u.username AS owner, p.name, p.description, p.filters, p.columns, p.default_sort, \
+                p.default_sort_order, p.is_shared \
+         FROM report_presets p JOIN users u ON u.id = p.owner_id ORDER BY u.username, p.name"
+    )
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ConfigReportPreset {
+            owner: row.owner,
+            preset: SaveReportPresetRequest {
+                name: row.name,
+                description: row.description,
+                filters: serde_json::from_str(&row.filters).unwrap_or_default(),
+                columns: serde_json::from_str(&row.columns).unwrap_or_default(),
+                default_sort: row.default_sort,
+                default_sort_order: row.default_sort_order,
+                is_shared: row.is_shared,
+            },
+        })
+        .collect())
+}
+
+async fn export_settings(pool: &SqlitePool) -> ApiResult<BTreeMap<String, Value>> {
+    let rows: Vec<(String, String)> =
+        sqlx::query_as("SELECT key, value FROM settings WHERE value IS NOT NULL ORDER BY key")
+            .fetch_all(pool)
+            .await?;
+    Ok(rows
+        .into_iter()
+        .filter(|(key, _)| definition(key).is_some())
+        .filter_map(|(key, raw)| serde_json::from_str(&raw).ok().map(|value| (key, value)))
+        .collect())
+}
+
+pub async fn build_config_document(pool: &SqlitePool) -> ApiResult<ConfigDocument> {
+    let rooms: Vec<Room> = sqlx::query_as("SELECT * FROM rooms ORDER BY name")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(ConfigDocument {
+        format_version: CONFIG_FORMAT_VERSION,
+        exported_at: Some(Utc::now()),
+        app_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        rooms: rooms.into_iter().map(ConfigRoom::from).collect(),
+        locations: export_locations(pool).await?,
+        teams: export_teams(pool).await?,
+        users: export_users(pool).await?,
+        report_presets: export_report_presets(pool).await?,
+        manufacturer_normalizations: normalization_groups(pool).await?,
+        settings: export_settings(pool).await?,
+    })
+}
+
+// ==================== IMPORT ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportAction {
+    Created,
+    Updated,
+    Unchanged,
+    Conflict,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SectionSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub conflicts: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportItem {
+    pub section: &'static str,
+    /// Естественный ключ записи (имя, путь, username, "владелец/имя" и т.п.)
+    pub key: String,
+    pub action: ImportAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigImportReport {
+    pub format_version: u64,
+    pub dry_run: bool,
+    pub summary: BTreeMap<&'static str, SectionSummary>,
+    /// Созданные и обновлённые записи
+    pub changes: Vec<ImportItem>,
+    pub conflicts: Vec<ImportItem>,
+}
+
+impl ConfigImportReport {
+    fn new(format_version: u64, dry_run: bool) -> Self {
+        Self { format_version, dry_run, summary: BTreeMap::new(), changes: Vec::new(), conflicts: Vec::new() }
+    }
+
+    fn record(&mut self, section: &'static str, key: &str, action: ImportAction) {
+        let summary = self.summary.entry(section).or_default();
+        match action {
+            ImportAction::Created => summary.created += 1,
+            ImportAction::Updated => summary.updated += 1,
+            ImportAction::Unchanged => summary.unchanged += 1,
+            ImportAction::Conflict => summary.conflicts += 1,
+        }
+        if matches!(action, ImportAction::Created | ImportAction::Updated) {
+            self.changes.push(ImportItem { section, key: key.to_string(), action, reason: None });
+        }
+    }
+
+    fn conflict(&mut self, section: &'static str, key: &str, reason: impl Into<String>) {
+        self.record(section, key, ImportAction::Conflict);
+        self.conflicts.push(ImportItem {
+            section,
+            key: key.to_string(),
+            action: ImportAction::Conflict,
+            reason: Some(reason.into()),
+        });
+    }
+
+    fn total(&self, pick: fn(&SectionSummary) -> usize) -> usize {
+        self.summary.values().map(pick).sum()
+    }
+
+    fn settings_changed(&self) -> bool {
+        self.summary.get(SETTINGS).is_some_and(|s| s.created + s.updated > 0)
+    }
+}
+
+/// Ошибка проверки записи становится конфликтом, остальные ошибки прерывают импорт
+fn conflict_reason(err: ApiError) -> ApiResult<String> {
+    match err {
+        ApiError::BadRequest(message) | ApiError::ValidationError(message) => Ok(message),
+        other => Err(other),
+    }
+}
+
+/// Версия проверяется до разбора структуры: документ новой версии может не разобраться
+pub fn parse_config_document(raw: Value) -> ApiResult<ConfigDocument> {
+    let version = raw
+        .get("format_version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| ApiError::bad_request("format_version is required"))?;
+    if version > CONFIG_FORMAT_VERSION {
+        return Err(ApiError::Conflict {
+            code: CONFIG_FORMAT_TOO_NEW,
+            message: format!(
+                "Config document format version {} is newer than the supported version {}; \
+                 upgrade this instance before importing",
+                version, CONFIG_FORMAT_VERSION
+            ),
+        });
+    }
+    if version == 0 {
+        return Err(ApiError::bad_request("format_version must be at least 1"));
+    }
+    serde_json::from_value(raw).map_err(|e| ApiError::BadRequest(format!("Invalid config document: {}", e)))
+}
+
+struct ImportContext<'a> {
+    user_id: &'a str,
+    now: DateTime<Utc>,
+}
+
+/// Имена пользователей без учёта регистра (см. user_identity)
+async fn user_id_by_username(conn: &mut SqliteConnection, username: &str) -> ApiResult<Option<String>> {
+    Ok(sqlx::query_scalar(
+        "SELECT id FROM users WHERE username = ?1 COLLATE NOCASE ORDER BY username = ?1 DESC, created_at LIMIT 1"
+    )
+        .bind(username.trim())
+        .fetch_optional(conn)
+        .await?)
+}
+
+async fn room_id_by_name(conn: &mut SqliteConnection, name: &str) -> ApiResult<Option<String>> {
+    Ok(sqlx::query_scalar("SELECT id FROM rooms WHERE LOWER(name) = LOWER(?)")
+        .bind(name.trim())
+        .fetch_optional(conn)
+        .await?)
+}
+
+async fn import_rooms(
+    conn: &mut SqliteConnection,
+    ctx: &ImportContext<'_>,
+    rooms: &[ConfigRoom],
+    report: &mut ConfigImportReport,
+) -> ApiResult<()> {
+    for incoming in rooms {
+        let room = incoming.normalized();
+        if let Err(reason) = room.check() {
+            report.conflict(ROOMS, &room.name, reason);
+            continue;
+        }
+
+        let existing: Option<Room> = sqlx::query_as("SELECT * FROM rooms WHERE LOWER(name) = LOWER(?)")
+            .bind(&room.name)
+            .fetch_optional(&mut *conn)
+            .await?;
+        match existing {
+            None => {
+                sqlx::query(
+                    r#"INSERT INTO rooms (id, name, description, capacity, color, status, opening_time, closing_time,
+                                          open_days, weekly_hours, created_by, updated_by, created_at, updated_at)
+                       VALUES (?, ?, ?, ?, ?, 'available', ?, ?, ?, ?, ?, ?, ?, ?)"#
+                )
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(&room.name)
+                    .bind(&room.description)
+                    .bind(room.capacity)
+                    .bind(&room.color)
+                    .bind(&room.opening_time)
+                    .bind(&room.closing_time)
+                    .bind(&room.open_days)
+                    .bind(room.weekly_hours_column())
+                    .bind(ctx.user_id)
+                    .bind(ctx.user_id)
+                    .bind(ctx.now)
+                    .bind(ctx.now)
+                    .execute(&mut *conn)
+                    .await?;
+                report.record(ROOMS, &room.name, ImportAction::Created);
+            }
+            Some(existing) => {
+                let id = existing.id.clone();
+                if ConfigRoom::from(existing).normalized() == room {
+                    report.record(ROOMS, &room.name, ImportAction::Unchanged);
+                    continue;
+                }
+                sqlx::query(
+                    r#"UPDATE rooms SET name = ?, description = ?, capacity = ?, color = ?, opening_time = ?,
+                                        closing_time = ?, open_days = ?, weekly_hours = ?, updated_by = ?, updated_at = ?
+                       WHERE id = ?"#
+                )
+                    .bind(&room.name)
+                    .bind(&room.description)
+                    .bind(room.capacity)
+                    .bind(&room.color)
+                    .bind(&room.opening_time)
+                    .bind(&room.closing_time)
+                    .bind(&room.open_days)
+                    .bind(room.weekly_hours_column())
+                    .bind(ctx.user_id)
+                    .bind(ctx.now)
+                    .bind(&id)
+                    .execute(&mut *conn)
+                    .await?;
+                report.record(ROOMS, &room.name, ImportAction::Updated);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ExistingLocation {
+    id: String,
+    location_type: String,
+    room_id: Option<String>,
+    description: Option<String>,
+}
+
+/// Узел по пути имён от корня; имена соседних узлов уникальны без учёта регистра
+async fn find_location_by_path(conn: &mut SqliteConnection, path: &[String]) -> ApiResult<Option<ExistingLocation>> {
+    let mut node: Option<ExistingLocation> = None;
+    for name in path {
+        let parent_id = node.as_ref().map(|n| n.id.clone());
+        node = sqlx::query_as(
+            "SELECT id, location_type, room_id, description FROM locations \
+             WHERE parent_id IS ? AND LOWER(name) = LOWER(?)"
+        )
+            .bind(parent_id)
+            .bind(name.trim())
+            .fetch_optional(&mut *conn)
+            .await?;
+        if node.is_none() {
+            return Ok(None);
+        }
+    }
+    Ok(node)
+}
+
+fn check_location(location: &ConfigLocation) -> Result<(), String> {
+    if location.path.is_empty() {
+        return Err("Location path cannot be empty".to_string());
+    }
+    if location.path.iter().any(|name| name.trim().is_empty() || name.trim().chars().count() > 100) {
+        return Err("Location names must be between 1 and 100 characters".to_string());
+    }
+    if location.description.as_ref().is_some_and(|d| d.chars().count() > 500) {
+        return Err("Description cannot exceed 500 characters".to_string());
+    }
+    Ok(())
+}
+
+async fn import_locations(
+    conn: &mut SqliteConnection,
+    ctx: &ImportContext<'_>,
+    locations: &[ConfigLocation],
+    report: &mut ConfigImportReport,
+) -> ApiResult<()> {
+    // Родители раньше вложенных узлов, даже если документ собран вручную
+    let mut ordered: Vec<&ConfigLocation> = locations.iter().collect();
+    ordered.sort_by_key(|location| location.path.len());
+
+    for location in ordered {
+        let key = location.path.iter().map(|name| name.trim()).collect::<Vec<_>>().join(", ");
+        if let Err(reason) = check_location(location) {
+            report.conflict(LOCATIONS, &key, reason);
+            continue;
+        }
+        let (name, parent_path) = location.path.split_last().expect("path is not empty");
+
+        let parent = if parent_path.is_empty() {
+            None
+        } else {
+            match find_location_by_path(conn, parent_path).await? {
+                Some(parent) => Some(parent),
+                None => {
+                    report.conflict(LOCATIONS, &key, format!("Parent location '{}' does not exist", parent_path.join(", ")));
+                    continue;
+                }
+            }
+        };
+        if let Some(parent_type) = parent.as_ref().and_then(|p| LocationType::parse(&p.location_type)) {
+            if !parent_type.can_contain(location.location_type) {
+                report.conflict(LOCATIONS, &key, format!(
+                    "A {} cannot be placed inside a {}", location.location_type.as_str(), parent_type.as_str()
+                ));
+                continue;
+            }
+        }
+
+        let room_id = match location.room.as_deref() {
+            None => None,
+            Some(room) => match room_id_by_name(conn, room).await? {
+                Some(id) => Some(id),
+                None => {
+                    report.conflict(LOCATIONS, &key, format!("Room '{}' does not exist", room));
+                    continue;
+                }
+            },
+        };
+
+        match find_location_by_path(conn, &location.path).await? {
+            None => {
+                sqlx::query(
+                    r#"INSERT INTO locations (id, parent_id, name, location_type, room_id, description,
+                                              created_by, updated_by, created_at, updated_at)
+                       VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+                )
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(parent.as_ref().map(|p| p.id.clone()))
+                    .bind(name.trim())
+                    .bind(location.location_type.as_str())
+                    .bind(&room_id)
+                    .bind(&location.description)
+                    .bind(ctx.user_id)
+                    .bind(ctx.user_id)
+                    .bind(ctx.now)
+                    .bind(ctx.now)
+                    .execute(&mut *conn)
+                    .await?;
+                report.record(LOCATIONS, &key, ImportAction::Created);
+            }
+            Some(existing) if existing.location_type != location.location_type.as_str() => {
+                report.conflict(LOCATIONS, &key, format!(
+                    "Location already exists as a {}; change its type manually", existing.location_type
+                ));
+            }
+            Some(existing) if existing.room_id == room_id && existing.description == location.description => {
+                report.record(LOCATIONS, &key, ImportAction::Unchanged);
+            }
+            Some(existing) => {
+                sqlx::query("UPDATE locations SET room_id = ?, description = ?, updated_by = ?, updated_at = ? WHERE id = ?")
+                    .bind(&room_id)
+                    .bind(&location.description)
+                    .bind(ctx.user_id)
+                    .bind(ctx.now)
+                    .bind(&existing.id)
+                    .execute(&mut *conn)
+                    .await?;
+                report.record(LOCATIONS, &key, ImportAction::Updated);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn import_teams(
+    conn: &mut SqliteConnection,
+    ctx: &ImportContext<'_>,
+    teams: &[ConfigTeam],
+    report: &mut ConfigImportReport,
+) -> ApiResult<()> {
+    for team in teams {
+        let name = team.name.trim();
+        let request = CreateTeamRequest { name: name.to_string(), description: team.description.clone() };
+        if let Err(e) = request.validate() {
+            report.conflict(TEAMS, name, e.to_string());
+            continue;
+        }
+
+        let existing: Option<(String, Option<String>)> =
+            sqlx::query_as("SELECT id, description FROM teams WHERE name = ?")
+                .bind(name)
+                .fetch_optional(&mut *conn)
+                .await?;
+        let team_id = match existing {
+            None => {
+                let id = Uuid::new_v4().to_string();
+                sqlx::query(
+                    "INSERT INTO teams (id, name, description, created_by, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)"
+                )
+                    .bind(&id)
+                    .bind(name)
+                    .bind(&team.description)
+                    .bind(ctx.user_id)
+                    .bind(ctx.now)
+                    .bind(ctx.now)
+                    .execute(&mut *conn)
+                    .await?;
+                report.record(TEAMS, name, ImportAction::Created);
+                id
+            }
+            Some((id, description)) if description == team.description => {
+                report.record(TEAMS, name, ImportAction::Unchanged);
+                id
+            }
+            Some((id, _)) => {
+                sqlx::query("UPDATE teams SET description = ?, updated_at = ? WHERE id = ?")
+                    .bind(&team.description)
+                    .bind(ctx.now)
+                    .bind(&id)
+                    .execute(&mut *conn)
+                    .await?;
+                report.record(TEAMS, name, ImportAction::Updated);
+                id
+            }
+        };
+
+        // Участники только добавляются или меняют роль; лишние участники не удаляются
+        for member in &team.members {
+            let key = format!("{}/{}", name, member.username);
+            if !TEAM_ROLES.contains(&member.role.as_str()) {
+                report.conflict(TEAM_MEMBERS, &key, "Team role must be 'lead' or 'member'");
+                continue;
+            }
+            let Some(user_id) = user_id_by_username(conn, &member.username).await? else {
+                report.conflict(TEAM_MEMBERS, &key, format!("User '{}' does not exist", member.username));
+                continue;
+            };
+            let role: Option<String> = sqlx::query_scalar("SELECT role FROM team_members WHERE team_id = ? AND user_id = ?")
+                .bind(&team_id)
+                .bind(&user_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+            let action = match role {
+                Some(role) if role == member.role => {
+                    report.record(TEAM_MEMBERS, &key, ImportAction::Unchanged);
+                    continue;
+                }
+                Some(_) => ImportAction::Updated,
+                None => ImportAction::Created,
+            };
+            sqlx::query(
+                r#"INSERT INTO team_members (team_id, user_id, role, added_by, created_at) VALUES (?, ?, ?, ?, ?)
+                   ON CONFLICT(team_id, user_id) DO UPDATE SET role = excluded.role"#
+            )
+                .bind(&team_id)
+                .bind(&user_id)
+                .bind(&member.role)
+                .bind(ctx.user_id)
+                .bind(ctx.now)
+                .execute(&mut *conn)
+                .await?;
+            report.record(TEAM_MEMBERS, &key, action);
+        }
+    }
+    Ok(())
+}
+
+async fn import_users(
+    conn: &mut SqliteConnection,
+    ctx: &ImportContext<'_>,
+    users: &[ConfigUserAccess],
+    report: &mut ConfigImportReport,
+) -> ApiResult<()> {
+    let mut seen = HashSet::new();
+    for access in users {
+        let key = access.username.trim();
+        if !seen.insert(key.to_lowercase()) {
+            report.conflict(USERS, key, "Duplicate of an earlier row (usernames are case-insensitive)");
+            continue;
+        }
+        let Some(role) = UserRole::from_str(&access.role) else {
+            report.conflict(USERS, key, format!("Unknown role '{}'", access.role));
+            continue;
+        };
+        let matches: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT u.id, u.role, p.permissions FROM users u \
+             LEFT JOIN user_permissions p ON p.user_id = u.id WHERE u.username = ? COLLATE NOCASE"
+        )
+            .bind(key)
+            .fetch_all(&mut *conn)
+            .await?;
+        if matches.len() > 1 {
+            report.conflict(USERS, key, format!(
+                "Matches {} accounts that differ only by case; resolve them via /admin/normalizations/user-identity",
+                matches.len()
+            ));
+            continue;
+        }
+        let Some((user_id, current_role, current_permissions)) = matches.into_iter().next() else {
+            report.conflict(USERS, key, "User does not exist; users are not created by an import");
+            continue;
+        };
+
+        let role_changed = current_role != role.as_str();
+        if role_changed && user_id == ctx.user_id {
+            report.conflict(USERS, key, "An import cannot change your own role");
+            continue;
+        }
+        let current_permissions: Option<BTreeMap<String, bool>> =
+            current_permissions.and_then(|raw| serde_json::from_str(&raw).ok());
+        let permissions_changed = access.permissions.is_some() && access.permissions != current_permissions;
+        if !role_changed && !permissions_changed {
+            report.record(USERS, key, ImportAction::Unchanged);
+            continue;
+        }
+
+        if role_changed {
+            sqlx::query("UPDATE users SET role = ?, updated_at = ? WHERE id = ?")
+                .bind(role.as_str())
+                .bind(ctx.now)
+                .bind(&user_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        if let Some(permissions) = access.permissions.as_ref().filter(|_| permissions_changed) {
+            let permissions_json = serde_json::to_string(permissions)
+                .map_err(|e| ApiError::internal_error(e.to_string()))?;
+            sqlx::query(
+                r#"INSERT INTO user_permissions (user_id, permissions, created_at, updated_at) VALUES (?, ?, ?, ?)
+                   ON CONFLICT(user_id) DO UPDATE SET permissions = excluded.permissions, updated_at = excluded.updated_at"#
+            )
+                .bind(&user_id)
+                .bind(permissions_json)
+                .bind(ctx.now)
+                .bind(ctx.now)
+                .execute(&mut *conn)
+                .await?;
+        }
+        report.record(USERS, key, ImportAction::Updated);
+    }
+    Ok(())
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ExistingPreset {
+    id: String,
+    description: Option<String>,
+    filters: String,
+    columns: String,
+    default_sort: Option<String>,
+    default_sort_order: Option<String>,
+    is_shared: bool,
+}
+
+async fn import_report_presets(
+    conn: &mut SqliteConnection,
+    ctx: &ImportContext<'_>,
+    presets: &[ConfigReportPreset],
+    report: &mut ConfigImportReport,
+) -> ApiResult<()> {
+    for entry in presets {
+        let preset = &entry.preset;
+        let name = preset.name.trim();
+        let key = format!("{}/{}", entry.owner, name);
+        if let Err(e) = validate_preset_request(preset) {
+            report.conflict(REPORT_PRESETS, &key, conflict_reason(e)?);
+            continue;
+        }
+        let Some(owner_id) = user_id_by_username(conn, &entry.owner).await? else {
+            report.conflict(REPORT_PRESETS, &key, format!("Owner '{}' does not exist", entry.owner));
+            continue;
+        };
+
+        let filters = serde_json::to_string(&preset.filters).map_err(|e| ApiError::internal_error(e.to_string()))?;
+        let columns = serde_json::to_string(&preset.columns).map_err(|e| ApiError::internal_error(e.to_string()))?;
+        let sort_order = preset.default_sort_order.as_ref().map(|o| o.to_uppercase());
+
+        let existing: Option<ExistingPreset> = sqlx::query_as(
+            "SELECT id, description, filters, columns, default_sort, default_sort_order, is_shared \
+             FROM report_presets WHERE owner_id = ? AND name = ? COLLATE NOCASE"
+        )
+            .bind(&owner_id)
+            .bind(name)
+            .fetch_optional(&mut *conn)
+            .await?;
+        match existing {
+            None => {
+                sqlx::query(
+                    r#"INSERT INTO report_presets (id, name, description, filters, columns, default_sort,
+                                                   default_sort_order, owner_id, is_shared, created_at, updated_at)
+                       VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+                )
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(name)
+                    .bind(&preset.description)
+                    .bind(&filters)
+                    .bind(&columns)
+                    .bind(&preset.default_sort)
+                    .bind(&sort_order)
+                    .bind(&owner_id)
+                    .bind(preset.is_shared)
+                    .bind(ctx.now)
+                    .bind(ctx.now)
+                    .execute(&mut *conn)
+                    .await?;
+                report.record(REPORT_PRESETS, &key, ImportAction::Created);
+            }
+            Some(existing) if existing.description == preset.description
+                && existing.filters == filters
+                && existing.columns == columns
+                && existing.default_sort == preset.default_sort
+                && existing.default_sort_order == sort_order
+                && existing.is_shared == preset.is_shared =>
+            {
+                report.record(REPORT_PRESETS, &key, ImportAction::Unchanged);
+            }
+            Some(existing) => {
+                sqlx::query(
+                    r#"UPDATE report_presets SET description = ?, filters = ?, columns = ?, default_sort = ?,
+                                                 default_sort_order = ?, is_shared = ?, updated_at = ?
+                       WHERE id = ?"#
+                )
+                    .bind(&preset.description)
+                    .bind(&filters)
+                    .bind(&columns)
+                    .bind(&preset.default_sort)
+                    .bind(&sort_order)
+                    .bind(preset.is_shared)
+                    .bind(ctx.now)
+                    .bind(&existing.id)
+                    .execute(&mut *conn)
+                    .await?;
+                report.record(REPORT_PRESETS, &key, ImportAction::Updated);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Записи карты добавляются и переназначаются; уже сохранённое оборудование не переписывается
+async fn import_normalizations(
+    conn: &mut SqliteConnection,
+    ctx: &ImportContext<'_>,
+    groups: &[NormalizationGroup],
+    report: &mut ConfigImportReport,
+) -> ApiResult<()> {
+    for group in groups {
+        let canonical = collapse_whitespace(&group.canonical);
+        if canonical.is_empty() || canonical.chars().count() > 255 {
+            report.conflict(MANUFACTURER_NORMALIZATIONS, &group.canonical, "Canonical name must be between 1 and 255 characters");
+            continue;
+        }
+        for variant in &group.variants {
+            let key = variant_key(variant);
+            if key.is_empty() || key.chars().count() > 255 {
+                report.conflict(MANUFACTURER_NORMALIZATIONS, variant, "Each variant must be between 1 and 255 characters");
+                continue;
+            }
+            let current: Option<String> =
+                sqlx::query_scalar("SELECT canonical FROM manufacturer_normalizations WHERE variant = ?")
+                    .bind(&key)
+                    .fetch_optional(&mut *conn)
+                    .await?;
+            let action = match current {
+                Some(current) if current == canonical => {
+                    report.record(MANUFACTURER_NORMALIZATIONS, &key, ImportAction::Unchanged);
+                    continue;
+                }
+                Some(_) => ImportAction::Updated,
+                None => ImportAction::Created,
+            };
+            sqlx::query(
+                r#"INSERT INTO manufacturer_normalizations (variant, canonical, updated_by, updated_at) VALUES (?, ?, ?, ?)
+                   ON CONFLICT(variant) DO UPDATE SET canonical = excluded.canonical,
+                                                      updated_by = excluded.updated_by, updated_at = excluded.updated_at"#
+            )
+                .bind(&key)
+                .bind(&canonical)
+                .bind(ctx.user_id)
+                .bind(ctx.now)
+                .execute(&mut *conn)
+                .await?;
+            report.record(MANUFACTURER_NORMALIZATIONS, &key, action);
+        }
+    }
+    Ok(())
+}
+
+/// Кэш настроек обновляется после фиксации транзакции (`SettingsStore::load`)
+async fn import_settings(
+    conn: &mut SqliteConnection,
+    ctx: &ImportContext<'_>,
+    store: &SettingsStore,
+    values: &BTreeMap<String, Value>,
+    report: &mut ConfigImportReport,
+) -> ApiResult<()> {
+    for (key, value) in values {
+        let Some(def) = definition(key) else {
+            report.conflict(SETTINGS, key, "Unknown setting");
+            continue;
+        };
+        if !value.is_null() {
+            if let Err(e) = validate_value(def, value) {
+                report.conflict(SETTINGS, key, e);
+                continue;
+            }
+        }
+
+        let current: Option<Option<String>> = sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&mut *conn)
+            .await?;
+        let current = current.flatten().and_then(|raw| serde_json::from_str::<Value>(&raw).ok());
+        let action = match current {
+            Some(ref current) if current == value => ImportAction::Unchanged,
+            None if value.is_null() => ImportAction::Unchanged,
+            Some(_) => ImportAction::Updated,
+            None => ImportAction::Created,
+        };
+        if action != ImportAction::Unchanged {
+            sqlx::query(
+                r#"INSERT INTO settings (key, value, default_value, updated_by, updated_at) VALUES (?, ?, ?, ?, ?)
+                   ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_by = excluded.updated_by,
+                                                  updated_at = excluded.updated_at"#
+            )
+                .bind(key)
+                .bind((!value.is_null()).then(|| value.to_string()))
+                .bind(store.default_value(key).unwrap_or(Value::Null).to_string())
+                .bind(ctx.user_id)
+                .bind(ctx.now)
+                .execute(&mut *conn)
+                .await?;
+        }
+        report.record(SETTINGS, key, action);
+    }
+    Ok(())
+}
+
+/// Применяет документ в одной транзакции; при dry_run транзакция откатывается
+pub async fn import_config_document(
+    pool: &SqlitePool,
+    store: &SettingsStore,
+    user_id: &str,
+    raw: Value,
+    dry_run: bool,
+) -> ApiResult<ConfigImportReport> {
+    let document = parse_config_document(raw)?;
+    let mut report = ConfigImportReport::new(document.format_version, dry_run);
+    let ctx = ImportContext { user_id, now: Utc::now() };
+
+    // Порядок важен: места хранения ссылаются на помещения
+    let mut tx = pool.begin().await?;
+    import_rooms(&mut tx, &ctx, &document.rooms, &mut report).await?;
+    import_locations(&mut tx, &ctx, &document.locations, &mut report).await?;
+    import_teams(&mut tx, &ctx, &document.teams, &mut report).await?;
+    import_users(&mut tx, &ctx, &document.users, &mut report).await?;
+    import_report_presets(&mut tx, &ctx, &document.report_presets, &mut report).await?;
+    import_normalizations(&mut tx, &ctx, &document.manufacturer_normalizations, &mut report).await?;
+    import_settings(&mut tx, &ctx, store, &document.settings, &mut report).await?;
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+        if report.settings_changed() {
+            store.load(pool).await?;
+        }
+    }
+    Ok(report)
+}
+
+// ==================== HANDLERS ====================
+
+/// GET /admin/config-export - документ отдаётся файлом и без обёртки ApiResponse,
+/// чтобы его можно было сразу отправить в POST /admin/config-import
+pub async fn export_config(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let document = build_config_document(&app_state.db_pool).await?;
+    let filename = format!("lims-config-{}.json", Utc::now().format("%Y%m%d"));
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)))
+        .json(document))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfigImportQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// POST /admin/config-import?dry_run=true - тело запроса - документ из GET /admin/config-export
+pub async fn import_config(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ConfigImportQuery>,
+    body: web::Json<Value>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = get_current_user(&http_request)?.sub;
+    let report = import_config_document(
+        &app_state.db_pool, settings(), &user_id, body.into_inner(), query.dry_run,
+    ).await?;
+
+    let counts = format!(
+        "{} created, {} updated, {} unchanged, {} conflict(s)",
+        report.total(|s| s.created), report.total(|s| s.updated),
+        report.total(|s| s.unchanged), report.total(|s| s.conflicts),
+    );
+    let message = if report.dry_run {
+        format!("Dry run, nothing was changed: {}", counts)
+    } else {
+        // Импорт меняет помещения, пресеты отчётов и настройки
+        app_state.reference_cache.flush();
+        crate::audit::audit(
+            &app_state.db_pool, &user_id, "config_import", "system", "config",
+            &format!("Configuration imported (format v{}): {}", report.format_version, counts),
+            &http_request,
+        ).await;
+        format!("Configuration imported: {}", counts)
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(report, message)))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RuntimeSettingsConfig;
+    use crate::settings::EXPIRING_SOON_DAYS;
+    use crate::test_support::{fixtures, TestApp};
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    async fn count(pool: &SqlitePool, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table)).fetch_one(pool).await.unwrap()
+    }
+
+    #[test]
+    fn test_newer_format_version_is_refused() {
+        let err = parse_config_document(json!({ "format_version": CONFIG_FORMAT_VERSION + 1, "rooms": "anything" }))
+            .unwrap_err();
+        let ApiError::Conflict { code, message } = err else { panic!("expected conflict") };
+        assert_eq!(code, CONFIG_FORMAT_TOO_NEW);
+        assert!(message.contains("upgrade this instance"), "{}", message);
+
+        assert!(matches!(parse_config_document(json!({ "rooms": [] })), Err(ApiError::BadRequest(_))));
+        let document = parse_config_document(json!({ "format_version": 1 })).unwrap();
+        assert!(document.rooms.is_empty() && document.settings.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_export_import_round_trip_is_idempotent() {
+        let source = TestApp::new().await;
+        for sql in [
+            "UPDATE rooms SET capacity = 30 WHERE id = 'fx-room-lab-1'",
+            "INSERT INTO locations (id, parent_id, name, location_type, room_id, created_at, updated_at) VALUES \
+             ('loc-b', NULL, 'Building A', 'building', NULL, datetime('now'), datetime('now')), \
+             ('loc-c', 'loc-b', 'Cold room', 'room', 'fx-room-lab-1', datetime('now'), datetime('now')), \
+             ('loc-f', 'loc-c', 'Fridge 2', 'cabinet', NULL, datetime('now'), datetime('now'))",
+            "INSERT INTO teams (id, name, created_by, created_at, updated_at) \
+             VALUES ('team-1', 'Synthesis', 'fx-admin', datetime('now'), datetime('now'))",
+            "INSERT INTO team_members (team_id, user_id, role, created_at) \
+             VALUES ('team-1', 'fx-researcher', 'lead', datetime('now'))",
+            "INSERT INTO user_permissions (user_id, permissions) VALUES ('fx-viewer', '{\"create_batch\":true}')",
+            "INSERT INTO settings (key, value, default_value, updated_at) VALUES ('expiring_soon_days', '14', '30', datetime('now'))",
+            "INSERT INTO manufacturer_normalizations (variant, canonical, updated_at) \
+             VALUES ('thermo', 'Thermo Fisher Scientific', datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&source.pool).await.unwrap();
+        }
+        let (status, _) = source.post(UserRole::Admin, "/reports/presets", json!({
+            "name": "Solvents", "columns": ["reagent_name", "quantity"], "is_shared": true,
+        })).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, document) = source.get(UserRole::Admin, "/admin/config-export").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(document["format_version"], 1);
+        assert_eq!(document["locations"][2]["path"], json!(["Building A", "Cold room", "Fridge 2"]));
+        assert_eq!(document["locations"][1]["room"], "Lab 1");
+        assert!(document.get("reagents").is_none() && document["users"][0].get("password_hash").is_none());
+        let (status, _) = source.get(UserRole::Researcher, "/admin/config-export").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Второй экземпляр: те же пользователи, помещение Lab 1 с другой вместимостью
+        let target = TestApp::new().await;
+        let store = SettingsStore::new(&RuntimeSettingsConfig::default());
+        let dry = import_config_document(&target.pool, &store, fixtures::ADMIN_ID, document.clone(), true).await.unwrap();
+        assert_eq!(dry.summary[ROOMS].updated, 1);
+        assert_eq!(dry.summary[LOCATIONS].created, 3);
+        assert_eq!(count(&target.pool, "locations").await, 0);
+
+        let report = import_config_document(&target.pool, &store, fixtures::ADMIN_ID, document.clone(), false).await.unwrap();
+        assert!(report.conflicts.is_empty(), "{:?}", report.conflicts);
+        assert_eq!(report.summary[LOCATIONS].created, 3);
+        assert_eq!((report.summary[TEAMS].created, report.summary[TEAM_MEMBERS].created), (1, 1));
+        assert_eq!(report.summary[USERS].updated, 1);
+        assert_eq!(report.summary[REPORT_PRESETS].created, 1);
+        assert_eq!(report.summary[SETTINGS].created, 1);
+        assert_eq!(store.get_i64(EXPIRING_SOON_DAYS), 14);
+        let fridge_room: Option<String> = sqlx::query_scalar(
+            "SELECT p.room_id FROM locations l JOIN locations p ON p.id = l.parent_id WHERE l.name = 'Fridge 2'"
+        ).fetch_one(&target.pool).await.unwrap();
+        assert_eq!(fridge_room.as_deref(), Some(fixtures::ROOM_ID));
+
+        // Повторный импорт ничего не меняет
+        let again = import_config_document(&target.pool, &store, fixtures::ADMIN_ID, document, false).await.unwrap();
+        assert!(again.changes.is_empty(), "{:?}", again.changes);
+        assert_eq!(again.summary[LOCATIONS].unchanged, 3);
+    }
+
+    #[actix_web::test]
+    async fn test_import_reports_conflicts_without_blocking_other_records() {
+        let app = TestApp::new().await;
+        let store = SettingsStore::new(&RuntimeSettingsConfig::default());
+        let document = json!({
+            "format_version": 1,
+            "rooms": [{ "name": "Lab 2", "opening_time": "18:00", "closing_time": "08:00" }, { "name": "Lab 3" }],
+            "locations": [{ "path": ["Nowhere", "Shelf"], "type": "shelf" }],
+            "users": [
+                { "username": "ghost", "role": "admin", "permissions": null },
+                { "username": "fx_admin", "role": "viewer", "permissions": null },
+            ],
+            "settings": { "no_such_setting": 1, "max_per_page": 5 },
+        });
+        let report = import_config_document(&app.pool, &store, fixtures::ADMIN_ID, document, false).await.unwrap();
+        let mut conflicts: Vec<(&str, &str)> = report.conflicts.iter().map(|c| (c.section, c.key.as_str())).collect();
+        conflicts.sort();
+        assert_eq!(conflicts, [
+            (LOCATIONS, "Nowhere, Shelf"), (ROOMS, "Lab 2"), (SETTINGS, "max_per_page"),
+            (SETTINGS, "no_such_setting"), (USERS, "fx_admin"), (USERS, "ghost"),
+        ]);
+        assert_eq!(report.summary[ROOMS].created, 1);
+        let role: String = sqlx::query_scalar("SELECT role FROM users WHERE id = ?")
+            .bind(fixtures::ADMIN_ID).fetch_one(&app.pool).await.unwrap();
+        assert_eq!(role, "admin");
+    }
+
+    #[actix_web::test]
+    async fn test_import_matches_usernames_regardless_of_case() {
+        let app = TestApp::new().await;
+        let store = SettingsStore::new(&RuntimeSettingsConfig::default());
+        let document = json!({
+            "format_version": 1,
+            "users": [
+                { "username": " FX_Researcher ", "role": "admin", "permissions": null },
+                { "username": "fx_researcher", "role": "viewer", "permissions": null },
+            ],
+        });
+        let report = import_config_document(&app.pool, &store, fixtures::ADMIN_ID, document, false).await.unwrap();
+        assert_eq!(report.summary[USERS].updated, 1);
+        let conflicts: Vec<(&str, &str)> = report.conflicts.iter().map(|c| (c.section, c.key.as_str())).collect();
+        assert_eq!(conflicts, [(USERS, "fx_researcher")]);
+        let role: String = sqlx::query_scalar("SELECT role FROM users WHERE id = ?")
+            .bind(fixtures::RESEARCHER_ID).fetch_one(&app.pool).await.unwrap();
+        assert_eq!(role, "admin");
+    }
+}
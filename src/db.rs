@@ -2,8 +2,124 @@
 // Optimized for 270,000+ records with hybrid pagination
 
 use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
 use anyhow::Result;
 use log::info;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::config::DatabaseConfig;
+
+// ==================== CONNECTION POOL ====================
+
+/// Параметры соединения из конфигурации: busy_timeout, journal_mode, synchronous, foreign_keys.
+/// Прагмы применяются к каждому новому соединению пула, а не только к первому.
+pub fn connect_options(config: &DatabaseConfig) -> Result<SqliteConnectOptions> {
+    let journal_mode: SqliteJournalMode = config
+        .journal_mode
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Unsupported journal_mode '{}'", config.journal_mode))?;
+    let synchronous: SqliteSynchronous = config
+        .synchronous
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Unsupported synchronous '{}'", config.synchronous))?;
+
+    Ok(SqliteConnectOptions::new()
+        .filename(&config.url)
+        .create_if_missing(true)
+        .busy_timeout(Duration::from_millis(config.busy_timeout_ms))
+        .journal_mode(journal_mode)
+        .synchronous(synchronous)
+        .foreign_keys(config.foreign_keys))
+}
+
+/// Единая точка создания пула: размеры, таймауты и trace-хук профилирования запросов
+pub async fn create_pool(config: &DatabaseConfig) -> Result<SqlitePool> {
+    let options = connect_options(config)?;
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_secs(config.connect_timeout))
+        .idle_timeout((config.idle_timeout > 0).then(|| Duration::from_secs(config.idle_timeout)))
+        // Trace-хук на каждое соединение: гистограмма латентности и журнал медленных запросов
+        .after_connect(|conn, _meta| Box::pin(crate::query_log::install_query_tracing(conn)))
+        .connect_with(options)
+        .await?;
+
+    info!(
+        "Database pool ready: max={}, min={}, busy_timeout={}ms, journal_mode={}, synchronous={}, foreign_keys={}",
+        config.max_connections,
+        config.min_connections,
+        config.busy_timeout_ms,
+        config.journal_mode,
+        config.synchronous,
+        config.foreign_keys
+    );
+    Ok(pool)
+}
+
+/// Отдельный пул только для чтения (SQLITE_OPEN_READONLY + query_only) для тяжёлых
+/// отчётов, экспорта и аналитики, чтобы они не занимали соединения записи.
+/// None при `read_pool_size = 0` или базе в памяти: тогда всё идёт через основной пул.
+/// Создавать после миграций - файл базы должен уже существовать.
+pub async fn create_read_pool(config: &DatabaseConfig) -> Result<Option<SqlitePool>> {
+    if config.read_pool_size == 0 || config.url.is_empty() || config.url.contains(":memory:") {
+        return Ok(None);
+    }
+
+    // journal_mode не задаём: на read-only соединении его смена завершится ошибкой
+    let options = SqliteConnectOptions::new()
+        .filename(&config.url)
+        .read_only(true)
+        .busy_timeout(Duration::from_millis(config.busy_timeout_ms))
+        .pragma("query_only", "ON");
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.read_pool_size)
+        .min_connections(config.min_connections.min(config.read_pool_size))
+        .acquire_timeout(Duration::from_secs(config.connect_timeout))
+        .idle_timeout((config.idle_timeout > 0).then(|| Duration::from_secs(config.idle_timeout)))
+        .after_connect(|conn, _meta| Box::pin(crate::query_log::install_query_tracing(conn)))
+        .connect_with(options)
+        .await?;
+
+    info!("Read-only database pool ready: max={}", config.read_pool_size);
+    Ok(Some(pool))
+}
+
+static READ_POOL_SELECTIONS: AtomicU64 = AtomicU64::new(0);
+static PRIMARY_POOL_SELECTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Сколько раз запросы на чтение получили read-only пул и сколько - основной
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PoolSelectionStats {
+    pub read_pool: u64,
+    pub primary: u64,
+}
+
+/// Пул для запроса только на чтение: read-only, если он есть, иначе основной.
+/// Выбор учитывается в метриках.
+pub fn select_read_pool<'a>(primary: &'a SqlitePool, read_pool: Option<&'a SqlitePool>) -> &'a SqlitePool {
+    match read_pool {
+        Some(pool) => {
+            READ_POOL_SELECTIONS.fetch_add(1, Ordering::Relaxed);
+            pool
+        }
+        None => {
+            PRIMARY_POOL_SELECTIONS.fetch_add(1, Ordering::Relaxed);
+            primary
+        }
+    }
+}
+
+pub fn pool_selection_stats() -> PoolSelectionStats {
+    PoolSelectionStats {
+        read_pool: READ_POOL_SELECTIONS.load(Ordering::Relaxed),
+        primary: PRIMARY_POOL_SELECTIONS.load(Ordering::Relaxed),
+    }
+}
 
 pub async fn ensure_performance_indexes(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     info!("Checking and applying performance indexes...");
@@ -18,6 +134,8 @@ pub async fn ensure_performance_indexes(pool: &SqlitePool) -> Result<(), sqlx::E
         r#"CREATE INDEX IF NOT EXISTS idx_reagents_status_name ON reagents(status, name);"#,
         r#"CREATE INDEX IF NOT EXISTS idx_reagents_name ON reagents(name);"#,
         r#"CREATE INDEX IF NOT EXISTS idx_reagents_created_at ON reagents(created_at DESC, id ASC);"#,
+        // Фильтр ?physical_state= и сводка по агрегатным состояниям
+        r#"CREATE INDEX IF NOT EXISTS idx_reagents_physical_state ON reagents(physical_state);"#,
 
         // Critical index for keyset pagination by total_quantity
         r#"CREATE INDEX IF NOT EXISTS idx_reagents_total_qty ON reagents(total_quantity DESC, id ASC);"#,
@@ -31,6 +149,13 @@ pub async fn ensure_performance_indexes(pool: &SqlitePool) -> Result<(), sqlx::E
 
         // User permissions
         r#"CREATE INDEX IF NOT EXISTS idx_user_permissions_user_id ON user_permissions(user_id);"#,
+
+        // Form field autosuggest (suggest_handlers::SUGGEST_FIELDS)
+        r#"CREATE INDEX IF NOT EXISTS idx_suggest_batches_supplier ON batches(supplier);"#,
+        r#"CREATE INDEX IF NOT EXISTS idx_suggest_batches_location ON batches(location);"#,
+        r#"CREATE INDEX IF NOT EXISTS idx_suggest_batches_unit ON batches(unit);"#,
+        r#"CREATE INDEX IF NOT EXISTS idx_suggest_equipment_manufacturer ON equipment(manufacturer);"#,
+        r#"CREATE INDEX IF NOT EXISTS idx_suggest_experiments_instructor ON experiments(instructor);"#,
     ];
 
     for query in queries {
@@ -44,15 +169,12 @@ pub async fn ensure_performance_indexes(pool: &SqlitePool) -> Result<(), sqlx::E
 }
 
 pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
-    // Enable foreign keys and WAL mode
+    // journal_mode, synchronous и busy_timeout задаются в connect_options();
+    // foreign_keys включаем явно и здесь — для пулов, созданных в обход create_pool (тесты)
     sqlx::query("PRAGMA foreign_keys = ON")
         .execute(pool)
         .await?;
 
-    sqlx::query("PRAGMA journal_mode = WAL")
-        .execute(pool)
-        .await?;
-
     // Create users table
     sqlx::query(
         r#"
@@ -70,7 +192,9 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             created_at DATETIME NOT NULL,
             updated_at DATETIME NOT NULL,
             failed_login_attempts INTEGER NOT NULL DEFAULT 0,
-            locked_until DATETIME
+            locked_until DATETIME,
+            last_activity_at DATETIME,
+            inactivity_notice_at DATETIME
         )
         "#,
     )
@@ -131,7 +255,7 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             manufacturer TEXT CHECK(manufacturer IS NULL OR length(manufacturer) <= 255),
             received_date DATETIME NOT NULL,
             status TEXT NOT NULL DEFAULT 'available' CHECK(
-                status IN ('available', 'in_use', 'expired', 'depleted')
+                status IN ('available', 'in_use', 'expired', 'depleted', 'awaiting_coa')
             ),
             location TEXT CHECK(location IS NULL OR length(location) <= 255),
             notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
@@ -140,6 +264,10 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             created_at DATETIME NOT NULL,
             updated_at DATETIME NOT NULL,
             deleted_at DATETIME,
+            barcode TEXT CHECK(barcode IS NULL OR length(barcode) <= 128),
+            coa_reference TEXT CHECK(coa_reference IS NULL OR length(coa_reference) <= 100),
+            coa_received_at DATETIME,
+            coa_received_by TEXT,
             FOREIGN KEY (reagent_id) REFERENCES reagents (id) ON DELETE CASCADE,
             FOREIGN KEY (created_by) REFERENCES users (id),
             FOREIGN KEY (updated_by) REFERENCES users (id),
@@ -198,10 +326,12 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             last_maintenance TEXT,
             next_maintenance TEXT,
             maintenance_interval_days INTEGER DEFAULT 90,
+            parent_equipment_id TEXT,
             created_by TEXT,
             updated_by TEXT,
             created_at DATETIME NOT NULL,
             updated_at DATETIME NOT NULL,
+            FOREIGN KEY (parent_equipment_id) REFERENCES equipment (id) ON DELETE SET NULL,
             FOREIGN KEY (created_by) REFERENCES users (id),
             FOREIGN KEY (updated_by) REFERENCES users (id)
         )
@@ -259,7 +389,7 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             created_at DATETIME NOT NULL,
             updated_at DATETIME NOT NULL,
             FOREIGN KEY (researcher_id) REFERENCES users (id),
-            FOREIGN KEY (room_id) REFERENCES rooms (id),
+            FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE SET NULL,
             FOREIGN KEY (created_by) REFERENCES users (id),
             FOREIGN KEY (updated_by) REFERENCES users (id)
         )
@@ -306,6 +436,7 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             unit TEXT NOT NULL,
             purpose TEXT CHECK(purpose IS NULL OR length(purpose) <= 500),
             notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            imported INTEGER NOT NULL DEFAULT 0 CHECK(imported IN (0, 1)),
             created_at DATETIME NOT NULL,
             FOREIGN KEY (reagent_id) REFERENCES reagents (id),
             FOREIGN KEY (batch_id) REFERENCES batches (id),
@@ -440,193 +571,1219 @@ pub async fn run_migrations(pool: &SqlitePool) -> Result<()> {
         .execute(pool)
         .await?;
 
-    // ==================== RUN ADDITIONAL MIGRATIONS ====================
-    run_additional_migrations(pool).await?;
-
-    // ==================== CREATE BATCH TRIGGERS ====================
-    create_batch_triggers(pool).await?;
-
-    // ==================== CREATE FTS TABLES ====================
-    create_fts_tables(pool).await?;
-
-    // ==================== INITIALIZE CACHED FIELDS ====================
-    initialize_reagent_cache(pool).await?;
+    // ==================== FILE BLOBS TABLE ====================
+    // Содержимое вложений по SHA-256: один файл на диске на все одинаковые загрузки
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_blobs (
+            sha256 TEXT PRIMARY KEY CHECK(length(sha256) = 64),
+            file_path TEXT NOT NULL,
+            file_size INTEGER NOT NULL CHECK(file_size >= 0),
+            ref_count INTEGER NOT NULL CHECK(ref_count >= 0),
+            created_at DATETIME NOT NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-    // ==================== PERFORMANCE INDEXES ====================
-    ensure_performance_indexes(pool).await?;
+    // ==================== MANUFACTURER NORMALIZATIONS TABLE ====================
+    // Написание производителя (lower/trim) -> каноническое имя; применяется при записи оборудования
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS manufacturer_normalizations (
+            variant TEXT PRIMARY KEY CHECK(length(variant) > 0 AND length(variant) <= 255),
+            canonical TEXT NOT NULL CHECK(length(canonical) > 0 AND length(canonical) <= 255),
+            updated_by TEXT,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (updated_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-    Ok(())
-}
+    // ==================== PHYSICAL STATE MAPPINGS TABLE ====================
+    // Написание агрегатного состояния (lower/trim) -> значение PhysicalState, сверх встроенных синонимов
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS physical_state_mappings (
+            variant TEXT PRIMARY KEY CHECK(length(variant) > 0 AND length(variant) <= 255),
+            physical_state TEXT NOT NULL CHECK(physical_state IN ('solid', 'liquid', 'gas', 'solution', 'other')),
+            updated_by TEXT,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (updated_by) REFERENCES users (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-// ==================== BATCH TRIGGERS ====================
-// Automatically update total_quantity and batches_count in reagents
+    // ==================== EQUIPMENT CATALOG TABLE ====================
+    // Типовые модели оборудования: значения по умолчанию для создания экземпляров
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS equipment_catalog (
+            id TEXT PRIMARY KEY,
+            manufacturer TEXT NOT NULL CHECK(length(manufacturer) > 0 AND length(manufacturer) <= 255),
+            model TEXT NOT NULL CHECK(length(model) > 0 AND length(model) <= 255),
+            type_ TEXT NOT NULL CHECK(type_ IN (
+                'equipment', 'labware', 'instrument', 'glassware',
+                'safety', 'storage', 'consumable', 'other'
+            )),
+            maintenance_interval_days INTEGER CHECK(maintenance_interval_days IS NULL OR maintenance_interval_days > 0),
+            manual_file_id TEXT,
+            description TEXT CHECK(description IS NULL OR length(description) <= 1000),
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            UNIQUE(manufacturer, model),
+            FOREIGN KEY (manual_file_id) REFERENCES equipment_files (id) ON DELETE SET NULL,
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-async fn create_batch_triggers(pool: &SqlitePool) -> Result<()> {
-    info!("Creating batch triggers for reagent cache...");
+    // ==================== REAGENT IMAGES TABLE ====================
+    // Фото флакона или структура; актуальное изображение - reagents.image_id
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS reagent_images (
+            id TEXT PRIMARY KEY,
+            reagent_id TEXT NOT NULL,
+            original_filename TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            thumbnail_path TEXT NOT NULL,
+            mime_type TEXT NOT NULL,
+            thumbnail_mime_type TEXT NOT NULL,
+            file_size INTEGER NOT NULL CHECK(file_size > 0),
+            width INTEGER NOT NULL,
+            height INTEGER NOT NULL,
+            uploaded_by TEXT,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (reagent_id) REFERENCES reagents (id) ON DELETE CASCADE,
+            FOREIGN KEY (uploaded_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-    // Drop old triggers if exist
-    let drop_triggers = [
-        "DROP TRIGGER IF EXISTS trg_batches_insert",
-        "DROP TRIGGER IF EXISTS trg_batches_update",
-        "DROP TRIGGER IF EXISTS trg_batches_delete",
-    ];
+    // ==================== KIOSK TABLES ====================
+    // Киоск-токены помещений: храним только SHA-256, сам токен показывается один раз
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS kiosk_tokens (
+            id TEXT PRIMARY KEY,
+            room_id TEXT NOT NULL,
+            label TEXT NOT NULL CHECK(length(label) > 0 AND length(label) <= 100),
+            token_hash TEXT NOT NULL UNIQUE,
+            token_hint TEXT NOT NULL,
+            scopes TEXT NOT NULL,
+            expires_at DATETIME,
+            last_used_at DATETIME,
+            revoked_at DATETIME,
+            revoked_by TEXT,
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE,
+            FOREIGN KEY (revoked_by) REFERENCES users (id),
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-    for query in drop_triggers {
-        let _ = sqlx::query(query).execute(pool).await;
-    }
+    // Сеансы использования оборудования (выдача/возврат через киоск)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS equipment_usage_sessions (
+            id TEXT PRIMARY KEY,
+            equipment_id TEXT NOT NULL,
+            room_id TEXT,
+            user_identifier TEXT NOT NULL CHECK(length(user_identifier) > 0 AND length(user_identifier) <= 255),
+            started_at DATETIME NOT NULL,
+            ended_at DATETIME,
+            notes TEXT,
+            kiosk_token_id TEXT,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE,
+            FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE SET NULL,
+            FOREIGN KEY (kiosk_token_id) REFERENCES kiosk_tokens (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-    // INSERT trigger - when adding a batch with status='available' and not deleted
-    sqlx::query(r#"
-        CREATE TRIGGER IF NOT EXISTS trg_batches_insert
-        AFTER INSERT ON batches
-        WHEN NEW.status = 'available' AND NEW.deleted_at IS NULL
-        BEGIN
-            UPDATE reagents SET
-                total_quantity = total_quantity + NEW.quantity,
-                batches_count = batches_count + 1,
-                primary_unit = COALESCE(primary_unit, NEW.unit),
-                updated_at = datetime('now')
-            WHERE id = NEW.reagent_id;
-        END
-    "#)
+    // Отметки прихода/ухода в помещении
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS room_attendance (
+            id TEXT PRIMARY KEY,
+            room_id TEXT NOT NULL,
+            user_identifier TEXT NOT NULL CHECK(length(user_identifier) > 0 AND length(user_identifier) <= 255),
+            event TEXT NOT NULL CHECK(event IN ('check_in', 'check_out')),
+            kiosk_token_id TEXT,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE CASCADE,
+            FOREIGN KEY (kiosk_token_id) REFERENCES kiosk_tokens (id) ON DELETE SET NULL
+        )
+        "#,
+    )
         .execute(pool)
         .await?;
 
-    // DELETE trigger - when hard deleting a batch with status='available' (not soft-deleted)
-    sqlx::query(r#"
-        CREATE TRIGGER IF NOT EXISTS trg_batches_delete
-        AFTER DELETE ON batches
-        WHEN OLD.status = 'available' AND OLD.deleted_at IS NULL
-        BEGIN
-            UPDATE reagents SET
-                total_quantity = MAX(0, total_quantity - OLD.quantity),
-                batches_count = MAX(0, batches_count - 1),
-                updated_at = datetime('now')
-            WHERE id = OLD.reagent_id;
-        END
-    "#)
+    // ==================== EXPERIMENT COMMENTS TABLE ====================
+    // Канал для коллег без права изменять чужой эксперимент
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_comments (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            body TEXT NOT NULL CHECK(length(body) > 0 AND length(body) <= 2000),
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (user_id) REFERENCES users (id)
+        )
+        "#,
+    )
         .execute(pool)
         .await?;
 
-    // UPDATE trigger - full recalculation on change (including soft delete)
-    sqlx::query(r#"
-        CREATE TRIGGER IF NOT EXISTS trg_batches_update
-        AFTER UPDATE ON batches
-        BEGIN
-            UPDATE reagents SET
-                total_quantity = (
-                    SELECT COALESCE(SUM(quantity), 0)
-                    FROM batches
-                    WHERE reagent_id = NEW.reagent_id AND status = 'available' AND deleted_at IS NULL
-                ),
-                batches_count = (
-                    SELECT COUNT(*)
-                    FROM batches
-                    WHERE reagent_id = NEW.reagent_id AND status = 'available' AND deleted_at IS NULL
-                ),
-                primary_unit = (
-                    SELECT unit
-                    FROM batches
-                    WHERE reagent_id = NEW.reagent_id AND status = 'available' AND deleted_at IS NULL
-                    LIMIT 1
-                ),
-                updated_at = datetime('now')
-            WHERE id = NEW.reagent_id;
+    // ==================== PENDING DELETIONS TABLE ====================
+    // Отложенное удаление реагентов, партий и оборудования: до finalize_after
+    // объект скрыт из списков и восстанавливается по undo-токену
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS pending_deletions (
+            id TEXT PRIMARY KEY,
+            entity_type TEXT NOT NULL CHECK(entity_type IN ('reagent', 'batch', 'equipment')),
+            entity_id TEXT NOT NULL,
+            parent_id TEXT,
+            entity_name TEXT,
+            previous_status TEXT,
+            undo_token_hash TEXT NOT NULL UNIQUE,
+            requested_by TEXT,
+            requested_at DATETIME NOT NULL,
+            finalize_after DATETIME NOT NULL,
+            FOREIGN KEY (requested_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-            -- If reagent_id changed, update the old reagent as well
-            UPDATE reagents SET
-                total_quantity = (
-                    SELECT COALESCE(SUM(quantity), 0)
-                    FROM batches
-                    WHERE reagent_id = OLD.reagent_id AND status = 'available' AND deleted_at IS NULL
-                ),
-                batches_count = (
-                    SELECT COUNT(*)
-                    FROM batches
-                    WHERE reagent_id = OLD.reagent_id AND status = 'available' AND deleted_at IS NULL
-                ),
-                primary_unit = (
-                    SELECT unit
-                    FROM batches
-                    WHERE reagent_id = OLD.reagent_id AND status = 'available' AND deleted_at IS NULL
-                    LIMIT 1
-                ),
-                updated_at = datetime('now')
-            WHERE id = OLD.reagent_id AND OLD.reagent_id != NEW.reagent_id;
-        END
-    "#)
+    // ==================== USER FAVORITES TABLE ====================
+    // Закреплённые пользователем реагенты и оборудование (?favorites_first=true в списках).
+    // WITHOUT ROWID: в списках таблица присоединяется LEFT JOIN, и неквалифицированный
+    // rowid в условиях FTS должен однозначно указывать на основную таблицу
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS user_favorites (
+            user_id TEXT NOT NULL,
+            entity_type TEXT NOT NULL CHECK(entity_type IN ('reagent', 'equipment')),
+            entity_id TEXT NOT NULL,
+            pinned_at DATETIME NOT NULL,
+            PRIMARY KEY (user_id, entity_type, entity_id),
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+        ) WITHOUT ROWID
+        "#,
+    )
         .execute(pool)
         .await?;
 
-    info!("Batch triggers created successfully.");
-    Ok(())
-}
+    // ==================== LOCATIONS TABLE ====================
+    // Иерархия мест хранения (здание > помещение > шкаф > полка > коробка).
+    // batches.location_id ссылается на узел, batches.location хранит его путь текстом
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS locations (
+            id TEXT PRIMARY KEY,
+            parent_id TEXT,
+            name TEXT NOT NULL CHECK(length(name) >= 1 AND length(name) <= 100),
+            location_type TEXT NOT NULL CHECK(location_type IN ('building', 'room', 'cabinet', 'shelf', 'box')),
+            room_id TEXT,
+            description TEXT CHECK(description IS NULL OR length(description) <= 500),
+            created_by TEXT,
+            updated_by TEXT,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (parent_id) REFERENCES locations (id),
+            FOREIGN KEY (room_id) REFERENCES rooms (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
 
-// ==================== FTS TABLES ====================
-// Full-text search for fast searching across 100k+ records
-// Search fields: name, cas_number, formula
+    // ==================== EXPERIMENT PARTICIPANTS TABLE ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_participants (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            user_id TEXT,
+            name TEXT CHECK(name IS NULL OR length(name) <= 255),
+            email TEXT CHECK(email IS NULL OR length(email) <= 255),
+            role TEXT NOT NULL DEFAULT 'student' CHECK(
+                role IN ('student', 'instructor', 'assistant')
+            ),
+            signed_in_at DATETIME,
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            CHECK(user_id IS NOT NULL OR name IS NOT NULL),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE SET NULL,
+            FOREIGN KEY (created_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== CATALOG LOOKUP CACHE ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS catalog_lookup_cache (
+            source TEXT NOT NULL,
+            query_key TEXT NOT NULL,
+            response TEXT NOT NULL,
+            fetched_at DATETIME NOT NULL,
+            PRIMARY KEY (source, query_key)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== REPORT PRESETS TABLE ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS report_presets (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL CHECK(length(name) > 0 AND length(name) <= 100),
+            description TEXT CHECK(description IS NULL OR length(description) <= 500),
+            filters TEXT NOT NULL DEFAULT '[]',
+            columns TEXT NOT NULL DEFAULT '[]',
+            default_sort TEXT,
+            default_sort_order TEXT CHECK(default_sort_order IS NULL OR default_sort_order IN ('ASC', 'DESC')),
+            owner_id TEXT NOT NULL,
+            is_shared INTEGER NOT NULL DEFAULT 0 CHECK(is_shared IN (0, 1)),
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (owner_id) REFERENCES users (id) ON DELETE CASCADE
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== REPORT SCHEDULES ====================
+    // Рассылка отчётов по расписанию: день недели ISO 1=Пн..7=Вс (NULL - ежедневно) и час
+    // в часовом поясе развёртывания; preset - встроенный пресет или id из report_presets
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS report_schedules (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL CHECK(length(name) > 0 AND length(name) <= 100),
+            preset TEXT NOT NULL,
+            preset_params TEXT,
+            weekday INTEGER CHECK(weekday IS NULL OR weekday BETWEEN 1 AND 7),
+            hour INTEGER NOT NULL CHECK(hour BETWEEN 0 AND 23),
+            recipients TEXT NOT NULL DEFAULT '[]',
+            format TEXT NOT NULL DEFAULT 'csv' CHECK(format IN ('csv', 'json')),
+            enabled INTEGER NOT NULL DEFAULT 1 CHECK(enabled IN (0, 1)),
+            owner_id TEXT NOT NULL,
+            next_run_at DATETIME,
+            last_run_at DATETIME,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (owner_id) REFERENCES users (id) ON DELETE CASCADE
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // Журнал запусков: статус, число строк отчёта и попытки (повтор один раз)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS report_schedule_runs (
+            id TEXT PRIMARY KEY,
+            schedule_id TEXT NOT NULL,
+            status TEXT NOT NULL CHECK(status IN ('success', 'failed')),
+            attempts INTEGER NOT NULL DEFAULT 1,
+            row_count INTEGER,
+            error TEXT,
+            started_at DATETIME NOT NULL,
+            finished_at DATETIME NOT NULL,
+            FOREIGN KEY (schedule_id) REFERENCES report_schedules (id) ON DELETE CASCADE
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== BATCH EXPIRY EXTENSIONS ====================
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS batch_expiry_extensions (
+            id TEXT PRIMARY KEY,
+            batch_id TEXT NOT NULL,
+            reagent_id TEXT NOT NULL,
+            old_expiry_date DATETIME NOT NULL,
+            new_expiry_date DATETIME NOT NULL,
+            justification TEXT NOT NULL CHECK(length(justification) > 0 AND length(justification) <= 2000),
+            document_reference TEXT CHECK(document_reference IS NULL OR length(document_reference) <= 500),
+            approved_by TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (batch_id) REFERENCES batches (id) ON DELETE CASCADE,
+            FOREIGN KEY (approved_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EXTERNAL LINKS ====================
+    // Полиморфная привязка (entity_type, entity_id) - FK невозможен,
+    // удаление вместе с родителем обеспечивают триггеры create_external_link_triggers
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS external_links (
+            id TEXT PRIMARY KEY,
+            entity_type TEXT NOT NULL CHECK(entity_type IN ('reagent', 'batch', 'equipment', 'experiment')),
+            entity_id TEXT NOT NULL,
+            title TEXT NOT NULL CHECK(length(title) > 0 AND length(title) <= 255),
+            url TEXT NOT NULL CHECK(length(url) <= 2048),
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EXPERIMENT SAFETY SIGN-OFFS ====================
+    // Один допуск на эксперимент: без него эксперимент с опасными реагентами не запускается
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_signoffs (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL UNIQUE,
+            signed_by TEXT NOT NULL,
+            signed_at DATETIME NOT NULL,
+            checklist TEXT NOT NULL CHECK(json_valid(checklist)),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (signed_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EXPERIMENT DEPENDENCIES ====================
+    // Эксперимент не запускается, пока не завершены все его предшественники (depends_on_id);
+    // циклы отсекаются при добавлении в experiment_handlers::add_experiment_dependency
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_dependencies (
+            experiment_id TEXT NOT NULL,
+            depends_on_id TEXT NOT NULL,
+            created_by TEXT,
+            created_at DATETIME NOT NULL,
+            PRIMARY KEY (experiment_id, depends_on_id),
+            CHECK(experiment_id != depends_on_id),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (depends_on_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (created_by) REFERENCES users (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_experiment_dependencies_depends_on ON experiment_dependencies(depends_on_id)")
+        .execute(pool)
+        .await?;
+
+    // ==================== EXPERIMENT DOCUMENTS / EQUIPMENT ====================
+    // Счётчики в списке экспериментов считаются по experiment_id этих таблиц
+    // (для experiment_reagents достаточно UNIQUE(experiment_id, ...))
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_documents (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            original_name TEXT NOT NULL,
+            mime_type TEXT NOT NULL DEFAULT 'application/octet-stream',
+            size INTEGER NOT NULL DEFAULT 0 CHECK(size >= 0),
+            uploaded_by TEXT,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (uploaded_by) REFERENCES users (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_equipment (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            equipment_id TEXT NOT NULL,
+            quantity_used INTEGER NOT NULL DEFAULT 1 CHECK(quantity_used > 0),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 500),
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (equipment_id) REFERENCES equipment (id) ON DELETE CASCADE
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EXPERIMENT STATUS HISTORY ====================
+    // Заполняется триггерами на experiments: каждый путь смены статуса (хендлеры, автопереходы,
+    // импорт) попадает в историю. Автор - updated_by строки; автопереходы сбрасывают его в NULL
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS experiment_status_history (
+            id TEXT PRIMARY KEY,
+            experiment_id TEXT NOT NULL,
+            from_status TEXT,
+            to_status TEXT NOT NULL,
+            changed_by TEXT,
+            changed_at DATETIME NOT NULL,
+            -- 1: запись создана при миграции по текущему статусу, прежние переходы неизвестны
+            backfilled INTEGER NOT NULL DEFAULT 0 CHECK(backfilled IN (0, 1)),
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (changed_by) REFERENCES users (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_experiment_status_history_experiment \
+         ON experiment_status_history (experiment_id, changed_at)"
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO experiment_status_history (id, experiment_id, from_status, to_status, changed_by, changed_at, backfilled)
+        SELECT lower(hex(randomblob(16))), e.id, NULL, e.status, e.updated_by, e.updated_at, 1
+        FROM experiments e
+        WHERE NOT EXISTS (SELECT 1 FROM experiment_status_history h WHERE h.experiment_id = e.id)
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_experiments_status_insert
+        AFTER INSERT ON experiments
+        BEGIN
+            INSERT INTO experiment_status_history (id, experiment_id, from_status, to_status, changed_by, changed_at)
+            VALUES (lower(hex(randomblob(16))), NEW.id, NULL, NEW.status, COALESCE(NEW.created_by, NEW.updated_by), NEW.created_at);
+        END
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_experiments_status_update
+        AFTER UPDATE OF status ON experiments
+        WHEN OLD.status IS NOT NEW.status
+        BEGIN
+            INSERT INTO experiment_status_history (id, experiment_id, from_status, to_status, changed_by, changed_at)
+            VALUES (lower(hex(randomblob(16))), NEW.id, OLD.status, NEW.status, NEW.updated_by, NEW.updated_at);
+        END
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== CONSUMPTION APPROVALS ====================
+    // Расход сверх порога реагента (reagents.approval_threshold) ждёт согласования;
+    // операция выполняется при approve в одной транзакции со сменой статуса
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS consumption_approvals (
+            id TEXT PRIMARY KEY,
+            operation TEXT NOT NULL CHECK(operation IN ('batch_use', 'experiment_reagent')),
+            reagent_id TEXT NOT NULL,
+            batch_id TEXT NOT NULL,
+            experiment_id TEXT,
+            quantity REAL NOT NULL CHECK(quantity > 0),
+            unit TEXT NOT NULL,
+            purpose TEXT CHECK(purpose IS NULL OR length(purpose) <= 500),
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending', 'approved', 'rejected', 'expired')),
+            requested_by TEXT NOT NULL,
+            requested_at DATETIME NOT NULL,
+            expires_at DATETIME NOT NULL,
+            resolved_by TEXT,
+            resolved_at DATETIME,
+            resolution_note TEXT CHECK(resolution_note IS NULL OR length(resolution_note) <= 1000),
+            result_id TEXT,
+            FOREIGN KEY (reagent_id) REFERENCES reagents (id) ON DELETE CASCADE,
+            FOREIGN KEY (batch_id) REFERENCES batches (id) ON DELETE CASCADE,
+            FOREIGN KEY (experiment_id) REFERENCES experiments (id) ON DELETE CASCADE,
+            FOREIGN KEY (requested_by) REFERENCES users (id),
+            FOREIGN KEY (resolved_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== ALERTS ====================
+    // Экземпляры предупреждений дашборда (партия истекает, мало остатка, просрочено ТО,
+    // подходит калибровка или плановое обслуживание); создаются и закрываются фоновой задачей, подтверждаются вручную
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS alerts (
+            id TEXT PRIMARY KEY,
+            kind TEXT NOT NULL CHECK(kind IN ('batch_expiring', 'low_stock', 'maintenance_overdue', 'calibration_due', 'maintenance_due', 'maintenance_escalation', 'stock_anomaly')),
+            entity_type TEXT NOT NULL CHECK(entity_type IN ('batch', 'equipment', 'maintenance')),
+            entity_id TEXT NOT NULL,
+            message TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'open' CHECK(status IN ('open', 'acknowledged', 'resolved')),
+            note TEXT CHECK(note IS NULL OR length(note) <= 1000),
+            snoozed_until DATETIME,
+            acknowledged_by TEXT,
+            acknowledged_at DATETIME,
+            resolved_at DATETIME,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (acknowledged_by) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== STOCK ADJUSTMENTS ====================
+    // Корректировки остатка партий с кодом причины; отдельно от usage_logs (это не расход)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS stock_adjustments (
+            id TEXT PRIMARY KEY,
+            reagent_id TEXT NOT NULL,
+            batch_id TEXT NOT NULL,
+            user_id TEXT,
+            delta REAL NOT NULL CHECK(delta != 0),
+            unit TEXT NOT NULL,
+            quantity_before REAL NOT NULL,
+            quantity_after REAL NOT NULL CHECK(quantity_after >= 0),
+            reason TEXT NOT NULL CHECK(length(reason) BETWEEN 1 AND 50),
+            note TEXT CHECK(note IS NULL OR length(note) <= 1000),
+            created_at DATETIME NOT NULL,
+            FOREIGN KEY (reagent_id) REFERENCES reagents (id),
+            FOREIGN KEY (batch_id) REFERENCES batches (id),
+            FOREIGN KEY (user_id) REFERENCES users (id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== KPI DAILY ROLLUPS ====================
+    // Дневные значения бизнес-показателей (kpi::flush); label - '' для метрик без меток
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS metrics_daily (
+            day TEXT NOT NULL,
+            metric TEXT NOT NULL,
+            label TEXT NOT NULL DEFAULT '',
+            value INTEGER NOT NULL DEFAULT 0,
+            updated_at DATETIME NOT NULL,
+            PRIMARY KEY (day, metric, label)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // Пользователи, активные за сутки: metrics_daily.active_users = COUNT(*) по дню
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS metrics_daily_users (
+            day TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            PRIMARY KEY (day, user_id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== STOCK ANOMALIES ====================
+    // Срабатывания правил stock_anomalies: частота операций с партией или пользователем,
+    // крупное разовое списание. Без внешних ключей - история переживает удаление партии
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS stock_anomalies (
+            id TEXT PRIMARY KEY,
+            rule TEXT NOT NULL CHECK(rule IN ('batch_rate', 'user_rate', 'large_consumption')),
+            operation TEXT NOT NULL CHECK(operation IN ('use', 'adjustment', 'experiment_consumption')),
+            batch_id TEXT NOT NULL,
+            user_id TEXT,
+            observed REAL NOT NULL,
+            threshold REAL NOT NULL,
+            window_minutes INTEGER,
+            message TEXT NOT NULL,
+            detected_at DATETIME NOT NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_stock_anomalies_detected ON stock_anomalies (detected_at)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_stock_anomalies_batch ON stock_anomalies (batch_id, detected_at)")
+        .execute(pool)
+        .await?;
+
+    // ==================== DEMO SEED REGISTRY ====================
+    // Всё, что создал demo_seed: по этому реестру /admin/seed-demo/clear удаляет ровно свои записи
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS demo_seed_records (
+            entity_type TEXT NOT NULL,
+            entity_id TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            PRIMARY KEY (entity_type, entity_id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== EVENT OUTBOX ====================
+    // Бизнес-события пишутся в транзакции обработчика и доставляются диспетчером outbox;
+    // id задаёт порядок, в пределах (entity_type, entity_id) доставка строго по порядку
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS outbox (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            event_type TEXT NOT NULL,
+            entity_type TEXT NOT NULL,
+            entity_id TEXT NOT NULL,
+            payload TEXT NOT NULL CHECK(json_valid(payload)),
+            status TEXT NOT NULL DEFAULT 'pending'
+                CHECK(status IN ('pending', 'delivered', 'dead', 'discarded')),
+            attempts INTEGER NOT NULL DEFAULT 0,
+            last_error TEXT,
+            created_at DATETIME NOT NULL,
+            next_attempt_at DATETIME NOT NULL,
+            delivered_at DATETIME
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    for index in [
+        "CREATE INDEX IF NOT EXISTS idx_outbox_status_next ON outbox(status, next_attempt_at)",
+        "CREATE INDEX IF NOT EXISTS idx_outbox_entity ON outbox(entity_type, entity_id, id)",
+    ] {
+        sqlx::query(index).execute(pool).await?;
+    }
+
+    // ==================== TEAMS ====================
+    // Команды совместно владеют реагентами, оборудованием и экспериментами;
+    // lead управляет составом, member получает права на изменение объектов команды
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS teams (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE CHECK(length(name) BETWEEN 1 AND 100),
+            description TEXT CHECK(description IS NULL OR length(description) <= 1000),
+            created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS team_members (
+            team_id TEXT NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+            user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            role TEXT NOT NULL DEFAULT 'member' CHECK(role IN ('lead', 'member')),
+            added_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+            created_at DATETIME NOT NULL,
+            PRIMARY KEY (team_id, user_id)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_team_members_user ON team_members(user_id, team_id)")
+        .execute(pool)
+        .await?;
+
+    // ==================== PURCHASE ORDERS ====================
+    // Заказы поставщику; строки приёмки превращаются в партии (batches.purchase_order_line_id)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS purchase_orders (
+            id TEXT PRIMARY KEY,
+            po_number TEXT NOT NULL UNIQUE COLLATE NOCASE CHECK(length(po_number) BETWEEN 1 AND 50),
+            supplier TEXT NOT NULL CHECK(length(supplier) BETWEEN 1 AND 255),
+            status TEXT NOT NULL DEFAULT 'open'
+                CHECK(status IN ('draft', 'open', 'partially_received', 'received', 'cancelled')),
+            expected_date DATETIME,
+            notes TEXT CHECK(notes IS NULL OR length(notes) <= 1000),
+            created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME NOT NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // Строка - реагент из справочника или произвольное описание (например, запчасть)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS purchase_order_lines (
+            id TEXT PRIMARY KEY,
+            purchase_order_id TEXT NOT NULL REFERENCES purchase_orders(id) ON DELETE CASCADE,
+            line_no INTEGER NOT NULL CHECK(line_no > 0),
+            reagent_id TEXT REFERENCES reagents(id),
+            description TEXT CHECK(description IS NULL OR length(description) <= 500),
+            cat_number TEXT CHECK(cat_number IS NULL OR length(cat_number) <= 100),
+            quantity REAL NOT NULL CHECK(quantity > 0),
+            received_quantity REAL NOT NULL DEFAULT 0 CHECK(received_quantity >= 0),
+            unit TEXT NOT NULL CHECK(length(unit) BETWEEN 1 AND 20),
+            expected_date DATETIME,
+            created_at DATETIME NOT NULL,
+            CHECK(reagent_id IS NOT NULL OR description IS NOT NULL),
+            UNIQUE(purchase_order_id, line_no)
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== DOWNLOAD REDEMPTIONS ====================
+    // Использованные одноразовые ссылки на экспорт (signed_downloads); хранятся до истечения ссылки
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS download_redemptions (
+            jti TEXT PRIMARY KEY,
+            user_id TEXT,
+            kind TEXT NOT NULL,
+            expires_at DATETIME NOT NULL,
+            redeemed_at DATETIME NOT NULL,
+            FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== RUNTIME SETTINGS TABLE ====================
+    // Переопределения администратора; value = NULL - действует default_value из Config
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT CHECK(value IS NULL OR json_valid(value)),
+            default_value TEXT NOT NULL CHECK(json_valid(default_value)),
+            updated_by TEXT,
+            updated_at DATETIME NOT NULL,
+            FOREIGN KEY (updated_by) REFERENCES users (id) ON DELETE SET NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // ==================== RUN ADDITIONAL MIGRATIONS ====================
+    run_additional_migrations(pool).await?;
+
+    // ==================== CONTENT HASH BACKFILL ====================
+    crate::file_blobs::backfill_equipment_files(pool).await?;
+
+    // ==================== PHYSICAL STATE NORMALIZATION ====================
+    crate::physical_state::normalize_stored_states(pool).await?;
+
+    // ==================== USERNAME / EMAIL NORMALIZATION ====================
+    crate::user_identity::normalize_stored_identities(pool).await?;
+
+    // ==================== EQUIPMENT SERIAL NUMBERS ====================
+    crate::equipment_catalog::ensure_serial_index(pool).await?;
+
+    // ==================== CREATE BATCH TRIGGERS ====================
+    create_batch_triggers(pool).await?;
+
+    // ==================== CREATE EXTERNAL LINK TRIGGERS ====================
+    create_external_link_triggers(pool).await?;
+
+    // ==================== CREATE FAVORITE TRIGGERS ====================
+    create_favorite_triggers(pool).await?;
+
+    // ==================== CREATE FTS TABLES ====================
+    create_fts_tables(pool).await?;
 
-async fn create_fts_tables(pool: &SqlitePool) -> Result<()> {
-    info!("Creating FTS5 tables for full-text search...");
+    // ==================== INITIALIZE CACHED FIELDS ====================
+    initialize_reagent_cache(pool).await?;
 
-    // Check if FTS table already exists
-    let exists: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='reagents_fts'"
-    ).fetch_one(pool).await?;
+    // ==================== PERFORMANCE INDEXES ====================
+    ensure_performance_indexes(pool).await?;
 
-    if exists.0 > 0 {
-        info!("FTS table already exists, skipping creation.");
-        return Ok(());
+    Ok(())
+}
+
+// ==================== EXTERNAL LINK TRIGGERS ====================
+// Remove external links when the parent entity is deleted (hard or soft delete)
+
+async fn create_external_link_triggers(pool: &SqlitePool) -> Result<()> {
+    // (entity_type, table, soft-deletable)
+    let parents = [
+        ("reagent", "reagents", true),
+        ("batch", "batches", true),
+        ("equipment", "equipment", false),
+        ("experiment", "experiments", false),
+    ];
+
+    // Все триггеры создаются через одно соединение, чтобы каждое следующее
+    // выражение видело схему, изменённую предыдущим
+    let mut conn = pool.acquire().await?;
+
+    for (entity_type, table, soft_deletable) in parents {
+        sqlx::query(&format!(
+            r#"
+            CREATE TRIGGER IF NOT EXISTS trg_{table}_external_links_delete
+            AFTER DELETE ON {table}
+            BEGIN
+                DELETE FROM external_links WHERE entity_type = '{entity_type}' AND entity_id = OLD.id;
+            END
+            "#
+        ))
+            .execute(&mut *conn)
+            .await?;
+
+        if soft_deletable {
+            // Пока удаление можно отменить (pending_deletion_id задан), ссылки сохраняются;
+            // удаляются при мягком удалении без окна отмены или при его завершении
+            sqlx::query(&format!("DROP TRIGGER IF EXISTS trg_{table}_external_links_soft_delete"))
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query(&format!(
+                r#"
+                CREATE TRIGGER IF NOT EXISTS trg_{table}_external_links_soft_delete
+                AFTER UPDATE OF deleted_at, pending_deletion_id ON {table}
+                WHEN NEW.deleted_at IS NOT NULL AND NEW.pending_deletion_id IS NULL
+                    AND (OLD.deleted_at IS NULL OR OLD.pending_deletion_id IS NOT NULL)
+                BEGIN
+                    DELETE FROM external_links WHERE entity_type = '{entity_type}' AND entity_id = NEW.id;
+                END
+                "#
+            ))
+                .execute(&mut *conn)
+                .await?;
+        }
     }
 
-    // Create FTS5 virtual table for reagents
-    sqlx::query(r#"
-        CREATE VIRTUAL TABLE reagents_fts USING fts5(
-            name,
-            cas_number,
-            formula,
-            content='reagents',
-            content_rowid='rowid',
-            tokenize='unicode61 remove_diacritics 1'
-        )
-    "#).execute(pool).await?;
+    Ok(())
+}
+
+// ==================== FAVORITE TRIGGERS ====================
+// Избранное удалённого объекта удаляется вместе с ним (как внешние ссылки)
+
+async fn create_favorite_triggers(pool: &SqlitePool) -> Result<()> {
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_equipment_favorites_delete
+        AFTER DELETE ON equipment
+        BEGIN
+            DELETE FROM user_favorites WHERE entity_type = 'equipment' AND entity_id = OLD.id;
+        END
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_reagents_favorites_delete
+        AFTER DELETE ON reagents
+        BEGIN
+            DELETE FROM user_favorites WHERE entity_type = 'reagent' AND entity_id = OLD.id;
+        END
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    // Мягкое удаление: как только удаление нельзя отменить
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_reagents_favorites_soft_delete
+        AFTER UPDATE OF deleted_at, pending_deletion_id ON reagents
+        WHEN NEW.deleted_at IS NOT NULL AND NEW.pending_deletion_id IS NULL
+            AND (OLD.deleted_at IS NULL OR OLD.pending_deletion_id IS NOT NULL)
+        BEGIN
+            DELETE FROM user_favorites WHERE entity_type = 'reagent' AND entity_id = NEW.id;
+        END
+        "#,
+    )
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+// ==================== BATCH TRIGGERS ====================
+// Automatically update total_quantity and batches_count in reagents
+
+async fn create_batch_triggers(pool: &SqlitePool) -> Result<()> {
+    info!("Creating batch triggers for reagent cache...");
+
+    // Drop old triggers if exist
+    let drop_triggers = [
+        "DROP TRIGGER IF EXISTS trg_batches_insert",
+        "DROP TRIGGER IF EXISTS trg_batches_update",
+        "DROP TRIGGER IF EXISTS trg_batches_delete",
+        "DROP TRIGGER IF EXISTS trg_batches_coa_required",
+    ];
+
+    for query in drop_triggers {
+        let _ = sqlx::query(query).execute(pool).await;
+    }
 
-    // INSERT trigger - sync new reagents to FTS
+    // COA trigger - batches of coa_required reagents start as 'awaiting_coa'
+    // (covers every creation path: API, scan receiving, imports)
     sqlx::query(r#"
-        CREATE TRIGGER reagents_fts_insert AFTER INSERT ON reagents BEGIN
-            INSERT INTO reagents_fts(rowid, name, cas_number, formula)
-            VALUES (NEW.rowid, NEW.name, NEW.cas_number, NEW.formula);
+        CREATE TRIGGER IF NOT EXISTS trg_batches_coa_required
+        AFTER INSERT ON batches
+        WHEN NEW.status = 'available'
+            AND (SELECT coa_required FROM reagents WHERE id = NEW.reagent_id) = 1
+        BEGIN
+            UPDATE batches SET status = 'awaiting_coa' WHERE id = NEW.id;
         END
-    "#).execute(pool).await?;
+    "#)
+        .execute(pool)
+        .await?;
 
-    // DELETE trigger - remove from FTS
+    // INSERT trigger - when adding a batch with status='available' and not deleted
     sqlx::query(r#"
-        CREATE TRIGGER reagents_fts_delete AFTER DELETE ON reagents BEGIN
-            INSERT INTO reagents_fts(reagents_fts, rowid, name, cas_number, formula)
-            VALUES ('delete', OLD.rowid, OLD.name, OLD.cas_number, OLD.formula);
+        CREATE TRIGGER IF NOT EXISTS trg_batches_insert
+        AFTER INSERT ON batches
+        WHEN NEW.status = 'available' AND NEW.deleted_at IS NULL
+            AND COALESCE((SELECT coa_required FROM reagents WHERE id = NEW.reagent_id), 0) = 0
+        BEGIN
+            UPDATE reagents SET
+                total_quantity = total_quantity + NEW.quantity,
+                batches_count = batches_count + 1,
+                primary_unit = COALESCE(primary_unit, NEW.unit),
+                updated_at = datetime('now')
+            WHERE id = NEW.reagent_id;
         END
-    "#).execute(pool).await?;
+    "#)
+        .execute(pool)
+        .await?;
 
-    // UPDATE trigger - update FTS index
+    // DELETE trigger - when hard deleting a batch with status='available' (not soft-deleted)
     sqlx::query(r#"
-        CREATE TRIGGER reagents_fts_update AFTER UPDATE ON reagents BEGIN
-            INSERT INTO reagents_fts(reagents_fts, rowid, name, cas_number, formula)
-            VALUES ('delete', OLD.rowid, OLD.name, OLD.cas_number, OLD.formula);
-            INSERT INTO reagents_fts(rowid, name, cas_number, formula)
-            VALUES (NEW.rowid, NEW.name, NEW.cas_number, NEW.formula);
+        CREATE TRIGGER IF NOT EXISTS trg_batches_delete
+        AFTER DELETE ON batches
+        WHEN OLD.status = 'available' AND OLD.deleted_at IS NULL
+        BEGIN
+            UPDATE reagents SET
+                total_quantity = MAX(0, total_quantity - OLD.quantity),
+                batches_count = MAX(0, batches_count - 1),
+                updated_at = datetime('now')
+            WHERE id = OLD.reagent_id;
         END
-    "#).execute(pool).await?;
+    "#)
+        .execute(pool)
+        .await?;
 
-    // Populate FTS with existing data
+    // UPDATE trigger - full recalculation on change (including soft delete)
     sqlx::query(r#"
-        INSERT INTO reagents_fts(rowid, name, cas_number, formula)
-        SELECT rowid, name, cas_number, formula FROM reagents
-    "#).execute(pool).await?;
+        CREATE TRIGGER IF NOT EXISTS trg_batches_update
+        AFTER UPDATE ON batches
+        BEGIN
+            UPDATE reagents SET
+                total_quantity = (
+                    SELECT COALESCE(SUM(quantity), 0)
+                    FROM batches
+                    WHERE reagent_id = NEW.reagent_id AND status = 'available' AND deleted_at IS NULL
+                ),
+                batches_count = (
+                    SELECT COUNT(*)
+                    FROM batches
+                    WHERE reagent_id = NEW.reagent_id AND status = 'available' AND deleted_at IS NULL
+                ),
+                primary_unit = (
+                    SELECT unit
+                    FROM batches
+                    WHERE reagent_id = NEW.reagent_id AND status = 'available' AND deleted_at IS NULL
+                    LIMIT 1
+                ),
+                updated_at = datetime('now')
+            WHERE id = NEW.reagent_id;
 
-    // Optimize the FTS index
-    let _ = sqlx::query("INSERT INTO reagents_fts(reagents_fts) VALUES('optimize')").execute(pool).await;
+            -- If reagent_id changed, update the old reagent as well
+            UPDATE reagents SET
+                total_quantity = (
+                    SELECT COALESCE(SUM(quantity), 0)
+                    FROM batches
+                    WHERE reagent_id = OLD.reagent_id AND status = 'available' AND deleted_at IS NULL
+                ),
+                batches_count = (
+                    SELECT COUNT(*)
+                    FROM batches
+                    WHERE reagent_id = OLD.reagent_id AND status = 'available' AND deleted_at IS NULL
+                ),
+                primary_unit = (
+                    SELECT unit
+                    FROM batches
+                    WHERE reagent_id = OLD.reagent_id AND status = 'available' AND deleted_at IS NULL
+                    LIMIT 1
+                ),
+                updated_at = datetime('now')
+            WHERE id = OLD.reagent_id AND OLD.reagent_id != NEW.reagent_id;
+        END
+    "#)
+        .execute(pool)
+        .await?;
+
+    info!("Batch triggers created successfully.");
+    Ok(())
+}
+
+// ==================== FTS TABLES ====================
+// Full-text search for fast searching across 100k+ records
+// reagents_fts: name, cas_number, formula
+// equipment_fts: name, model, serial_number, manufacturer, description, location
+// experiments_fts: title, description, protocol, results, notes
+//
+// Индексы синхронизируются только триггерами (AFTER INSERT/UPDATE/DELETE),
+// поэтому любые пути записи (handlers, импорт, массовые UPDATE) обновляют FTS сразу.
+
+const REAGENTS_FTS_COLUMNS: &[&str] = &["name", "cas_number", "formula"];
+const EQUIPMENT_FTS_COLUMNS: &[&str] = &["name", "model", "serial_number", "manufacturer", "description", "location"];
+const EXPERIMENTS_FTS_COLUMNS: &[&str] = &["title", "description", "protocol", "results", "notes"];
+
+async fn create_fts_tables(pool: &SqlitePool) -> Result<()> {
+    info!("Creating FTS5 tables for full-text search...");
+
+    ensure_fts_index(pool, "reagents_fts", "reagents", REAGENTS_FTS_COLUMNS).await?;
+    ensure_fts_index(pool, "equipment_fts", "equipment", EQUIPMENT_FTS_COLUMNS).await?;
+    ensure_fts_index(pool, "experiments_fts", "experiments", EXPERIMENTS_FTS_COLUMNS).await?;
+
+    info!("FTS5 tables and triggers are up to date.");
+    Ok(())
+}
+
+/// Создаёт FTS5 таблицу (external content) и пересоздаёт триггеры синхронизации.
+/// UPDATE-триггер срабатывает только при изменении индексируемых колонок,
+/// чтобы обновления кэшей (total_quantity, updated_at и т.п.) не трогали индекс.
+async fn ensure_fts_index(
+    pool: &SqlitePool,
+    fts_table: &str,
+    content_table: &str,
+    columns: &[&str],
+) -> Result<()> {
+    // Проверка, DROP и CREATE триггеров идут через одно соединение: соединение пула
+    // с устаревшим кэшем схемы не видит удаление, и CREATE TRIGGER падает с "already exists"
+    let mut conn = pool.acquire().await?;
+
+    let exists: (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name = ?"
+    )
+        .bind(fts_table)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let column_list = columns.join(", ");
+    let new_values = columns.iter().map(|c| format!("NEW.{}", c)).collect::<Vec<_>>().join(", ");
+    let old_values = columns.iter().map(|c| format!("OLD.{}", c)).collect::<Vec<_>>().join(", ");
+
+    if exists.0 == 0 {
+        sqlx::query(&format!(
+            r#"
+            CREATE VIRTUAL TABLE {fts} USING fts5(
+                {cols},
+                content='{content}',
+                content_rowid='rowid',
+                tokenize='unicode61 remove_diacritics 1'
+            )
+            "#,
+            fts = fts_table, cols = column_list, content = content_table
+        )).execute(&mut *conn).await?;
+
+        // Populate FTS with existing data
+        sqlx::query(&format!("INSERT INTO {fts}({fts}) VALUES('rebuild')", fts = fts_table))
+            .execute(&mut *conn)
+            .await?;
+
+        info!("FTS5 table {} created and populated.", fts_table);
+    }
+
+    // Триггеры пересоздаются при каждом запуске - старые версии заменяются
+    for suffix in ["insert", "delete", "update"] {
+        sqlx::query(&format!("DROP TRIGGER IF EXISTS {}_{}", fts_table, suffix))
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    sqlx::query(&format!(
+        r#"
+        CREATE TRIGGER {fts}_insert AFTER INSERT ON {content} BEGIN
+            INSERT INTO {fts}(rowid, {cols}) VALUES (NEW.rowid, {new});
+        END
+        "#,
+        fts = fts_table, content = content_table, cols = column_list, new = new_values
+    )).execute(&mut *conn).await?;
+
+    sqlx::query(&format!(
+        r#"
+        CREATE TRIGGER {fts}_delete AFTER DELETE ON {content} BEGIN
+            INSERT INTO {fts}({fts}, rowid, {cols}) VALUES ('delete', OLD.rowid, {old});
+        END
+        "#,
+        fts = fts_table, content = content_table, cols = column_list, old = old_values
+    )).execute(&mut *conn).await?;
+
+    sqlx::query(&format!(
+        r#"
+        CREATE TRIGGER {fts}_update AFTER UPDATE OF {cols} ON {content} BEGIN
+            INSERT INTO {fts}({fts}, rowid, {cols}) VALUES ('delete', OLD.rowid, {old});
+            INSERT INTO {fts}(rowid, {cols}) VALUES (NEW.rowid, {new});
+        END
+        "#,
+        fts = fts_table, content = content_table, cols = column_list, old = old_values, new = new_values
+    )).execute(&mut *conn).await?;
 
-    info!("FTS5 table created and populated.");
     Ok(())
 }
 
@@ -671,11 +1828,43 @@ async fn initialize_reagent_cache(pool: &SqlitePool) -> Result<()> {
 async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
     info!("Running additional migrations...");
 
+    // До ALTER/индексов: пересоздание таблицы удаляет её индексы и триггеры
+    migrate_batch_status_check(pool).await?;
+    migrate_alert_kind_check(pool).await?;
+    migrate_foreign_keys(pool).await?;
+
     let migration_queries = [
         // ==================== REAGENTS ====================
         "ALTER TABLE reagents ADD COLUMN total_quantity REAL NOT NULL DEFAULT 0.0",
         "ALTER TABLE reagents ADD COLUMN batches_count INTEGER NOT NULL DEFAULT 0",
         "ALTER TABLE reagents ADD COLUMN primary_unit TEXT",
+        // Срок поставки (дней) для прогноза исчерпания запаса
+        "ALTER TABLE reagents ADD COLUMN procurement_lead_time_days INTEGER CHECK(procurement_lead_time_days IS NULL OR procurement_lead_time_days >= 0)",
+        // Расход за окно прогноза
+        "CREATE INDEX IF NOT EXISTS idx_usage_logs_reagent_created ON usage_logs(reagent_id, created_at)",
+        // Партии не используются до получения COA
+        "ALTER TABLE reagents ADD COLUMN coa_required INTEGER NOT NULL DEFAULT 0 CHECK(coa_required IN (0, 1))",
+        // Текущее изображение реагента
+        "ALTER TABLE reagents ADD COLUMN image_id TEXT REFERENCES reagent_images(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_reagent_images_reagent ON reagent_images(reagent_id)",
+        // Реагент показывается в публичном каталоге (отказ - publicly_visible = 0)
+        "ALTER TABLE reagents ADD COLUMN publicly_visible INTEGER NOT NULL DEFAULT 1 CHECK(publicly_visible IN (0, 1))",
+        // Расход больше порога требует согласования (consumption_approvals)
+        "ALTER TABLE reagents ADD COLUMN approval_threshold REAL CHECK(approval_threshold IS NULL OR approval_threshold > 0)",
+        "ALTER TABLE reagents ADD COLUMN approval_threshold_unit TEXT CHECK(approval_threshold_unit IS NULL OR length(approval_threshold_unit) <= 20)",
+        "CREATE INDEX IF NOT EXISTS idx_consumption_approvals_status ON consumption_approvals(status, expires_at)",
+        // Не больше одного незакрытого предупреждения на условие
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_alerts_active ON alerts(kind, entity_id) WHERE status != 'resolved'",
+        // Контекст предупреждения (JSON), например срок напоминания об обслуживании
+        "ALTER TABLE alerts ADD COLUMN context TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_stock_adjustments_reagent_created ON stock_adjustments(reagent_id, created_at)",
+        "CREATE INDEX IF NOT EXISTS idx_stock_adjustments_batch ON stock_adjustments(batch_id, created_at)",
+        // Срок годности по умолчанию и политика контроля сроков (strict/advisory/none)
+        "ALTER TABLE reagents ADD COLUMN default_shelf_life_days INTEGER CHECK(default_shelf_life_days IS NULL OR default_shelf_life_days > 0)",
+        "ALTER TABLE reagents ADD COLUMN expiry_policy TEXT NOT NULL DEFAULT 'strict' CHECK(expiry_policy IN ('strict', 'advisory', 'none'))",
+        // Команда-владелец: её участники изменяют реагент наравне с автором
+        "ALTER TABLE reagents ADD COLUMN team_id TEXT REFERENCES teams(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_reagents_team ON reagents(team_id) WHERE team_id IS NOT NULL",
         
 
         // ==================== EQUIPMENT ====================
@@ -687,11 +1876,44 @@ async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
         "ALTER TABLE equipment ADD COLUMN last_maintenance TEXT",
         "ALTER TABLE equipment ADD COLUMN next_maintenance TEXT",
         "ALTER TABLE equipment ADD COLUMN maintenance_interval_days INTEGER DEFAULT 90",
+        "ALTER TABLE equipment ADD COLUMN parent_equipment_id TEXT REFERENCES equipment(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_parent ON equipment(parent_equipment_id)",
+        // Расходники (колонки ВЭЖХ, фильтры), учитываемые как партии реагентов
+        "ALTER TABLE equipment_parts ADD COLUMN linked_batch_id TEXT REFERENCES batches(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_parts_linked_batch ON equipment_parts(linked_batch_id)",
+        // Окно обслуживания (оборудование недоступно с scheduled_start до scheduled_end)
+        "ALTER TABLE equipment_maintenance ADD COLUMN scheduled_start DATETIME",
+        "ALTER TABLE equipment_maintenance ADD COLUMN scheduled_end DATETIME",
+        // За сколько дней до scheduled_date напоминать; NULL - значение по умолчанию для типа из конфигурации
+        "ALTER TABLE equipment_maintenance ADD COLUMN reminder_lead_days INTEGER CHECK(reminder_lead_days IS NULL OR reminder_lead_days BETWEEN 0 AND 365)",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_maintenance_window ON equipment_maintenance(equipment_id, scheduled_start, scheduled_end)",
+        // SLA обслуживания: NULL - значение по умолчанию для типа из конфигурации
+        "ALTER TABLE equipment_maintenance ADD COLUMN sla_days INTEGER CHECK(sla_days IS NULL OR sla_days BETWEEN 0 AND 365)",
+        "ALTER TABLE equipment_maintenance ADD COLUMN assigned_to TEXT REFERENCES users(id) ON DELETE SET NULL",
+        // Ступень эскалации просрочки (0 - нет, 1 - ответственный, 2 - администраторы), ставит фоновая задача
+        "ALTER TABLE equipment_maintenance ADD COLUMN escalation_level INTEGER NOT NULL DEFAULT 0 CHECK(escalation_level BETWEEN 0 AND 2)",
+        "ALTER TABLE equipment_maintenance ADD COLUMN escalated_at DATETIME",
+        // Вычисляемый статус: хранимый status не перезаписывается
+        "ALTER TABLE equipment_maintenance ADD COLUMN effective_status TEXT GENERATED ALWAYS AS \
+         (CASE WHEN escalation_level > 0 AND status IN ('scheduled', 'in_progress') THEN 'overdue' ELSE status END) VIRTUAL",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_maintenance_escalation ON equipment_maintenance(escalation_level) WHERE escalation_level > 0",
+        // Оборудование в окне отмены удаления скрыто из списков
+        "ALTER TABLE equipment ADD COLUMN pending_deletion_id TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_pending_deletion ON equipment(pending_deletion_id) WHERE pending_deletion_id IS NOT NULL",
+        // Роли, которым разрешено брать оборудование в работу (через запятую; NULL - всем)
+        "ALTER TABLE equipment ADD COLUMN restricted_to_roles TEXT",
+        "ALTER TABLE equipment ADD COLUMN team_id TEXT REFERENCES teams(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_team ON equipment(team_id) WHERE team_id IS NOT NULL",
+        // Дедупликация вложений: ссылка на file_blobs (NULL - файл загружен до дедупликации)
+        "ALTER TABLE equipment_files ADD COLUMN content_hash TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_equipment_files_content_hash ON equipment_files(content_hash)",
 
         // ==================== USERS ====================
         "ALTER TABLE users ADD COLUMN failed_login_attempts INTEGER NOT NULL DEFAULT 0",
         "ALTER TABLE users ADD COLUMN locked_until DATETIME",
         "ALTER TABLE users ADD COLUMN name TEXT CHECK(name IS NULL OR length(name) <= 100)",
+        "ALTER TABLE users ADD COLUMN last_activity_at DATETIME",
+        "ALTER TABLE users ADD COLUMN inactivity_notice_at DATETIME",
 
         // ==================== BATCHES ====================
         "ALTER TABLE batches ADD COLUMN lot_number TEXT CHECK(lot_number IS NULL OR length(lot_number) <= 100)",
@@ -706,14 +1928,45 @@ async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
         "ALTER TABLE batches ADD COLUMN reserved_quantity REAL NOT NULL DEFAULT 0.0 CHECK(reserved_quantity >= 0)",
         "ALTER TABLE batches ADD COLUMN pack_size REAL CHECK(pack_size IS NULL OR pack_size > 0)",
         "ALTER TABLE batches ADD COLUMN deleted_at DATETIME",
+        "ALTER TABLE batches ADD COLUMN barcode TEXT CHECK(barcode IS NULL OR length(barcode) <= 128)",
+        // Сертификат анализа (COA) для реагентов с coa_required
+        "ALTER TABLE batches ADD COLUMN coa_reference TEXT CHECK(coa_reference IS NULL OR length(coa_reference) <= 100)",
+        "ALTER TABLE batches ADD COLUMN coa_received_at DATETIME",
+        "ALTER TABLE batches ADD COLUMN coa_received_by TEXT",
+        // Индексы для сканирования штрихкодов (GET /scan/{code})
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_batches_barcode ON batches(barcode) WHERE barcode IS NOT NULL",
+        "CREATE INDEX IF NOT EXISTS idx_batches_cat_number ON batches(cat_number)",
+        // Мягкое удаление, которое ещё можно отменить (см. pending_deletions)
+        "ALTER TABLE batches ADD COLUMN pending_deletion_id TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_batches_pending_deletion ON batches(pending_deletion_id) WHERE pending_deletion_id IS NOT NULL",
+        // Узел иерархии мест хранения (locations); текст location остаётся для отображения
+        "ALTER TABLE batches ADD COLUMN location_id TEXT REFERENCES locations(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_batches_location_id ON batches(location_id) WHERE location_id IS NOT NULL",
+        // Дашборд: заканчивающиеся партии (batch_handlers::LOW_STOCK_BATCHES_SQL). Выражение и условия WHERE
+        // должны совпадать с запросом буквально, иначе индекс не используется
+        "CREATE INDEX IF NOT EXISTS idx_batches_low_stock ON batches(quantity / original_quantity) \
+         WHERE status = 'available' AND deleted_at IS NULL AND original_quantity > 0",
+        "CREATE INDEX IF NOT EXISTS idx_locations_parent ON locations(parent_id)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_locations_sibling_name ON locations(COALESCE(parent_id, ''), LOWER(name))",
+        // Учёт тар: запечатанные флаконы размером pack_size (вскрытые выводятся из остатка)
+        "ALTER TABLE batches ADD COLUMN container_count INTEGER CHECK(container_count IS NULL OR container_count >= 0)",
+        // Партия, принятая по строке заказа поставщику
+        "ALTER TABLE batches ADD COLUMN purchase_order_line_id TEXT REFERENCES purchase_order_lines(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_batches_po_line ON batches(purchase_order_line_id) WHERE purchase_order_line_id IS NOT NULL",
+        "CREATE INDEX IF NOT EXISTS idx_purchase_orders_status ON purchase_orders(status, created_at)",
+        "CREATE INDEX IF NOT EXISTS idx_purchase_order_lines_order ON purchase_order_lines(purchase_order_id, line_no)",
         
         // ==================== REAGENTS SOFT DELETE ====================
         "ALTER TABLE reagents ADD COLUMN deleted_at DATETIME",
+        "ALTER TABLE reagents ADD COLUMN pending_deletion_id TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_reagents_pending_deletion ON reagents(pending_deletion_id) WHERE pending_deletion_id IS NOT NULL",
+        "CREATE INDEX IF NOT EXISTS idx_pending_deletions_finalize ON pending_deletions(finalize_after)",
+        "CREATE INDEX IF NOT EXISTS idx_user_favorites_entity ON user_favorites(entity_type, entity_id)",
         
         // ==================== EXPERIMENTS ====================
         "ALTER TABLE experiment_reagents ADD COLUMN is_consumed INTEGER NOT NULL DEFAULT 0 CHECK(is_consumed IN (0, 1))",
         "ALTER TABLE experiments ADD COLUMN location TEXT CHECK(location IS NULL OR length(location) <= 255)",
-        "ALTER TABLE experiments ADD COLUMN room_id TEXT REFERENCES rooms(id)",
+        "ALTER TABLE experiments ADD COLUMN room_id TEXT REFERENCES rooms(id) ON DELETE SET NULL",
         "ALTER TABLE experiments ADD COLUMN experiment_type TEXT NOT NULL DEFAULT 'research' CHECK(experiment_type IN ('educational', 'research'))",
         "ALTER TABLE experiments ADD COLUMN experiment_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP",
         "ALTER TABLE experiments ADD COLUMN instructor TEXT CHECK(length(instructor) <= 255)",
@@ -721,15 +1974,71 @@ async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
         "ALTER TABLE experiments ADD COLUMN protocol TEXT CHECK(length(protocol) <= 2000)",
         "ALTER TABLE experiments ADD COLUMN results TEXT CHECK(length(results) <= 5000)",
         "ALTER TABLE experiments ADD COLUMN notes TEXT CHECK(length(notes) <= 1000)",
+        // Итог эксперимента задаётся только при завершении
+        "ALTER TABLE experiments ADD COLUMN outcome TEXT CHECK(outcome IS NULL OR outcome IN ('successful', 'partial', 'failed', 'inconclusive'))",
+        "CREATE INDEX IF NOT EXISTS idx_experiments_outcome ON experiments(outcome)",
+        // Инструктор как учётная запись: вместе с автором и админами может изменять эксперимент
+        "ALTER TABLE experiments ADD COLUMN instructor_user_id TEXT REFERENCES users(id)",
+        "CREATE INDEX IF NOT EXISTS idx_experiments_instructor_user ON experiments(instructor_user_id)",
+        // Источник копии: без внешнего ключа, чтобы удаление источника не мешало копиям
+        "ALTER TABLE experiments ADD COLUMN cloned_from TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_experiments_cloned_from ON experiments(cloned_from) WHERE cloned_from IS NOT NULL",
+        "ALTER TABLE experiments ADD COLUMN team_id TEXT REFERENCES teams(id) ON DELETE SET NULL",
+        "CREATE INDEX IF NOT EXISTS idx_experiments_team ON experiments(team_id) WHERE team_id IS NOT NULL",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_comments_experiment ON experiment_comments(experiment_id, created_at)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_documents_experiment ON experiment_documents(experiment_id)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_equipment_experiment ON experiment_equipment(experiment_id)",
+        "CREATE INDEX IF NOT EXISTS idx_experiment_equipment_equipment ON experiment_equipment(equipment_id)",
         // ==================== AUDIT_LOGS ====================
         "ALTER TABLE audit_logs ADD COLUMN description TEXT",
         "ALTER TABLE audit_logs ADD COLUMN changes TEXT",
+        // Действия через киоск: пользователя нет, есть введённый/отсканированный идентификатор
+        "ALTER TABLE audit_logs ADD COLUMN via_kiosk INTEGER NOT NULL DEFAULT 0",
+        "ALTER TABLE audit_logs ADD COLUMN kiosk_token_id TEXT",
+        "ALTER TABLE audit_logs ADD COLUMN actor_identifier TEXT",
+        "CREATE INDEX IF NOT EXISTS idx_kiosk_tokens_room ON kiosk_tokens(room_id)",
+        "CREATE INDEX IF NOT EXISTS idx_usage_sessions_open ON equipment_usage_sessions(equipment_id, ended_at)",
+        "CREATE INDEX IF NOT EXISTS idx_room_attendance_room ON room_attendance(room_id, created_at)",
         "ALTER TABLE usage_logs ADD COLUMN placement_id TEXT REFERENCES batch_placements(id)",
+        // Исторический расход, перенесённый импортом (исключается из ленты недавней активности)
+        "ALTER TABLE usage_logs ADD COLUMN imported INTEGER NOT NULL DEFAULT 0 CHECK(imported IN (0, 1))",
+        // Количество в единице ввода (например, mmol), если она отличается от единицы партии
+        "ALTER TABLE usage_logs ADD COLUMN entered_quantity REAL",
+        "ALTER TABLE usage_logs ADD COLUMN entered_unit TEXT CHECK(entered_unit IS NULL OR length(entered_unit) <= 20)",
+        "ALTER TABLE experiment_reagents ADD COLUMN entered_quantity REAL",
+        "ALTER TABLE experiment_reagents ADD COLUMN entered_unit TEXT CHECK(entered_unit IS NULL OR length(entered_unit) <= 20)",
+        // Запись о списании: кто, когда и с какой пометкой; реквизиты партии фиксируются на момент
+        // списания, чтобы последующие правки партии не меняли историю эксперимента
+        "ALTER TABLE experiment_reagents ADD COLUMN consumed_at DATETIME",
+        "ALTER TABLE experiment_reagents ADD COLUMN consumed_by TEXT",
+        "ALTER TABLE experiment_reagents ADD COLUMN consumption_notes TEXT CHECK(consumption_notes IS NULL OR length(consumption_notes) <= 1000)",
+        "ALTER TABLE experiment_reagents ADD COLUMN lot_batch_number TEXT",
+        "ALTER TABLE experiment_reagents ADD COLUMN lot_supplier TEXT",
+        "ALTER TABLE experiment_reagents ADD COLUMN lot_expiry_date DATETIME",
 
         // ==================== ROOMS ====================
         "ALTER TABLE rooms ADD COLUMN color TEXT CHECK(color IS NULL OR length(color) <= 20)",
         "ALTER TABLE rooms ADD COLUMN created_by TEXT REFERENCES users(id)",
         "ALTER TABLE rooms ADD COLUMN updated_by TEXT REFERENCES users(id)",
+        // Часы работы помещения (UTC, HH:MM) и рабочие дни ISO 1=Пн..7=Вс - для расчёта загрузки
+        "ALTER TABLE rooms ADD COLUMN opening_time TEXT NOT NULL DEFAULT '08:00'",
+        "ALTER TABLE rooms ADD COLUMN closing_time TEXT NOT NULL DEFAULT '18:00'",
+        "ALTER TABLE rooms ADD COLUMN open_days TEXT NOT NULL DEFAULT '1,2,3,4,5'",
+        // Недельное расписание по дням (JSON); если задано, заменяет opening_time/closing_time/open_days
+        "ALTER TABLE rooms ADD COLUMN weekly_hours TEXT",
+        // ==================== REPORT PRESETS ====================
+        "CREATE INDEX IF NOT EXISTS idx_report_presets_owner ON report_presets(owner_id)",
+        "CREATE INDEX IF NOT EXISTS idx_report_presets_shared ON report_presets(is_shared) WHERE is_shared = 1",
+        "CREATE INDEX IF NOT EXISTS idx_report_schedules_owner ON report_schedules(owner_id)",
+        "CREATE INDEX IF NOT EXISTS idx_report_schedules_due ON report_schedules(next_run_at) WHERE enabled = 1",
+        "CREATE INDEX IF NOT EXISTS idx_report_schedule_runs_schedule ON report_schedule_runs(schedule_id, started_at)",
+        // ==================== BATCH EXPIRY EXTENSIONS ====================
+        "CREATE INDEX IF NOT EXISTS idx_expiry_extensions_batch ON batch_expiry_extensions(batch_id, created_at)",
+        "CREATE INDEX IF NOT EXISTS idx_external_links_entity ON external_links(entity_type, entity_id)",
+        "CREATE INDEX IF NOT EXISTS idx_external_links_title ON external_links(title COLLATE NOCASE)",
+        // ==================== EXPERIMENT PARTICIPANTS ====================
+        "CREATE INDEX IF NOT EXISTS idx_participants_experiment ON experiment_participants(experiment_id)",
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_participants_experiment_user ON experiment_participants(experiment_id, user_id) WHERE user_id IS NOT NULL",
         // ==================== BATCH PLACEMENTS IDEXES ====================
         "CREATE INDEX IF NOT EXISTS idx_placements_batch ON batch_placements(batch_id)",
         "CREATE INDEX IF NOT EXISTS idx_placements_room ON batch_placements(room_id)",
@@ -746,6 +2055,16 @@ async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
         .execute(pool)
         .await;
 
+    // Старые записи обслуживания: окно на весь день scheduled_date
+    let _ = sqlx::query(
+        r#"UPDATE equipment_maintenance
+           SET scheduled_start = strftime('%Y-%m-%dT%H:%M:%SZ', date(substr(scheduled_date, 1, 10))),
+               scheduled_end = strftime('%Y-%m-%dT%H:%M:%SZ', date(substr(scheduled_date, 1, 10), '+1 day'))
+           WHERE scheduled_start IS NULL AND date(substr(scheduled_date, 1, 10)) IS NOT NULL"#
+    )
+        .execute(pool)
+        .await;
+
     // ==================== CLEANUP OLD CACHE TABLES ====================
     let _ = sqlx::query("DROP TABLE IF EXISTS reagent_stock_cache").execute(pool).await;
     let _ = sqlx::query("DROP TABLE IF EXISTS reagent_count_cache").execute(pool).await;
@@ -754,6 +2073,245 @@ async fn run_additional_migrations(pool: &SqlitePool) -> Result<()> {
     Ok(())
 }
 
+// ==================== BATCH STATUS CHECK ====================
+
+const BATCH_STATUS_CHECK_OLD: &str = "status IN ('available', 'in_use', 'expired', 'depleted')";
+const BATCH_STATUS_CHECK: &str = "status IN ('available', 'in_use', 'expired', 'depleted', 'awaiting_coa')";
+
+/// Статус 'awaiting_coa' появился позже: CHECK в SQLite через ALTER не меняется,
+/// поэтому в старых базах таблица batches пересоздаётся с новым ограничением
+async fn migrate_batch_status_check(pool: &SqlitePool) -> Result<()> {
+    let (sql,): (String,) = sqlx::query_as(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'batches'"
+    )
+        .fetch_one(pool)
+        .await?;
+
+    if sql.contains(BATCH_STATUS_CHECK) {
+        return Ok(());
+    }
+    if !sql.contains(BATCH_STATUS_CHECK_OLD) {
+        log::warn!("Unexpected batches.status constraint, 'awaiting_coa' migration skipped");
+        return Ok(());
+    }
+
+    info!("Rebuilding batches table to allow 'awaiting_coa' status...");
+    rebuild_table(pool, "batches", &sql.replacen(BATCH_STATUS_CHECK_OLD, BATCH_STATUS_CHECK, 1)).await?;
+    info!("Batches table rebuilt.");
+    Ok(())
+}
+
+// ==================== ALERT KIND CHECK ====================
+
+const ALERT_KIND_CHECK_OLD: &str = "kind IN ('batch_expiring', 'low_stock', 'maintenance_overdue', 'calibration_due')";
+const ALERT_KIND_CHECK_DUE: &str = "kind IN ('batch_expiring', 'low_stock', 'maintenance_overdue', 'calibration_due', 'maintenance_due')";
+const ALERT_KIND_CHECK_ESCALATION: &str = "kind IN ('batch_expiring', 'low_stock', 'maintenance_overdue', 'calibration_due', 'maintenance_due', 'maintenance_escalation')";
+const ALERT_KIND_CHECK: &str = "kind IN ('batch_expiring', 'low_stock', 'maintenance_overdue', 'calibration_due', 'maintenance_due', 'maintenance_escalation', 'stock_anomaly')";
+const ALERT_ENTITY_CHECK_OLD: &str = "entity_type IN ('batch', 'equipment')";
+const ALERT_ENTITY_CHECK: &str = "entity_type IN ('batch', 'equipment', 'maintenance')";
+
+/// Напоминания о плановом обслуживании (kind 'maintenance_due', сущность - запись обслуживания)
+/// эскалация просрочки по SLA ('maintenance_escalation') и аномалии расхода партий
+/// ('stock_anomaly') появились позже:
+/// таблица alerts пересоздаётся с новыми ограничениями
+async fn migrate_alert_kind_check(pool: &SqlitePool) -> Result<()> {
+    let (sql,): (String,) = sqlx::query_as(
+        "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'alerts'"
+    )
+        .fetch_one(pool)
+        .await?;
+
+    if sql.contains(ALERT_KIND_CHECK) {
+        return Ok(());
+    }
+    let create_sql = if sql.contains(ALERT_KIND_CHECK_ESCALATION) && sql.contains(ALERT_ENTITY_CHECK) {
+        sql.replacen(ALERT_KIND_CHECK_ESCALATION, ALERT_KIND_CHECK, 1)
+    } else if sql.contains(ALERT_KIND_CHECK_DUE) && sql.contains(ALERT_ENTITY_CHECK) {
+        sql.replacen(ALERT_KIND_CHECK_DUE, ALERT_KIND_CHECK, 1)
+    } else if sql.contains(ALERT_KIND_CHECK_OLD) && sql.contains(ALERT_ENTITY_CHECK_OLD) {
+        sql.replacen(ALERT_KIND_CHECK_OLD, ALERT_KIND_CHECK, 1)
+            .replacen(ALERT_ENTITY_CHECK_OLD, ALERT_ENTITY_CHECK, 1)
+    } else {
+        log::warn!("Unexpected alerts constraints, alert kind migration skipped");
+        return Ok(());
+    };
+
+    info!("Rebuilding alerts table to allow new alert kinds...");
+    rebuild_table(pool, "alerts", &create_sql).await?;
+    info!("Alerts table rebuilt.");
+    Ok(())
+}
+
+// ==================== FOREIGN KEYS ====================
+
+/// Внешний ключ, который обязан быть в схеме: (таблица, колонка, родитель, ON DELETE)
+const REQUIRED_FOREIGN_KEYS: &[(&str, &str, &str, &str)] = &[
+    ("batches", "reagent_id", "reagents", "CASCADE"),
+    ("experiment_reagents", "experiment_id", "experiments", "CASCADE"),
+    // Партию с расходом в эксперименте удалить нельзя - только списать
+    ("experiment_reagents", "batch_id", "batches", "NO ACTION"),
+    ("equipment_parts", "equipment_id", "equipment", "CASCADE"),
+    ("equipment_maintenance", "equipment_id", "equipment", "CASCADE"),
+    ("equipment_files", "equipment_id", "equipment", "CASCADE"),
+    ("experiments", "room_id", "rooms", "SET NULL"),
+];
+
+/// Базы, созданные ранними версиями, могли остаться без внешних ключей (или с другим
+/// ON DELETE): такие таблицы пересоздаются с ограничениями из REQUIRED_FOREIGN_KEYS.
+/// Висячие ссылки с ON DELETE SET NULL обнуляются, остальные попадают в foreign_key_check.
+async fn migrate_foreign_keys(pool: &SqlitePool) -> Result<()> {
+    let mut tables: Vec<&str> = REQUIRED_FOREIGN_KEYS.iter().map(|(table, ..)| *table).collect();
+    tables.dedup();
+
+    for table in tables {
+        let Some((mut sql,)) = sqlx::query_as::<_, (String,)>(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?"
+        )
+            .bind(table)
+            .fetch_optional(pool)
+            .await? else { continue };
+        let existing: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT \"from\", \"table\", on_delete FROM pragma_foreign_key_list(?)"
+        )
+            .bind(table)
+            .fetch_all(pool)
+            .await?;
+        let columns: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info(?)")
+            .bind(table)
+            .fetch_all(pool)
+            .await?;
+
+        let mut changed = Vec::new();
+        for (_, column, parent, on_delete) in REQUIRED_FOREIGN_KEYS.iter().filter(|(t, ..)| *t == table) {
+            // Колонка, которой ещё нет, появится через ALTER уже с ограничением
+            if !columns.iter().any(|(name,)| name == column) {
+                continue;
+            }
+            let declared = existing.iter().filter(|(from, to, _)| from == column && to.eq_ignore_ascii_case(parent));
+            if declared.clone().any(|(_, _, action)| action.eq_ignore_ascii_case(on_delete)) {
+                continue;
+            }
+            match with_foreign_key(&sql, column, parent, on_delete, declared.count() > 0) {
+                Some(patched) => {
+                    sql = patched;
+                    changed.push((*column, *parent, *on_delete));
+                }
+                None => log::warn!("Unexpected {}.{} definition, foreign key migration skipped", table, column),
+            }
+        }
+        if changed.is_empty() {
+            continue;
+        }
+
+        info!("Rebuilding {} table to enforce foreign keys...", table);
+        for (column, parent, on_delete) in &changed {
+            if *on_delete == "SET NULL" {
+                sqlx::query(&format!(
+                    "UPDATE {table} SET {column} = NULL WHERE {column} IS NOT NULL AND {column} NOT IN (SELECT id FROM {parent})"
+                ))
+                    .execute(pool)
+                    .await?;
+            }
+        }
+        // Пересоздание удаляет индексы и триггеры таблицы - возвращаем их в прежнем виде
+        let dependents: Vec<(String,)> = sqlx::query_as(
+            "SELECT sql FROM sqlite_master WHERE tbl_name = ? AND type IN ('index', 'trigger') AND sql IS NOT NULL"
+        )
+            .bind(table)
+            .fetch_all(pool)
+            .await?;
+        rebuild_table(pool, table, &sql).await?;
+        for (statement,) in dependents {
+            sqlx::query(&statement).execute(pool).await?;
+        }
+        info!("{} table rebuilt.", table);
+    }
+    Ok(())
+}
+
+/// Определение таблицы с внешним ключом `column -> parent(id) ON DELETE on_delete`:
+/// существующее ограничение колонки (табличное или в описании колонки) получает новое
+/// действие, отсутствующее добавляется в конец. None - объявленное (`declared`) ограничение
+/// не найдено в тексте: второй ключ на той же колонке оставил бы в силе прежний.
+fn with_foreign_key(create_sql: &str, column: &str, parent: &str, on_delete: &str, declared: bool) -> Option<String> {
+    let action = if on_delete == "NO ACTION" { String::new() } else { format!(" ON DELETE {}", on_delete) };
+    let existing_action = r"(?:\s+ON\s+DELETE\s+(?:SET\s+NULL|SET\s+DEFAULT|CASCADE|RESTRICT|NO\s+ACTION))?";
+    let references = format!(r"REFERENCES\s+{}\s*\(\s*id\s*\)", regex::escape(parent));
+    let patterns = [
+        format!(r"(?i)(FOREIGN\s+KEY\s*\(\s*{}\s*\)\s*{}){}", regex::escape(column), references, existing_action),
+        format!(r"(?i)((?:^|[(,])\s*{}\s[^,]*?{}){}", regex::escape(column), references, existing_action),
+    ];
+    for pattern in &patterns {
+        let re = regex::Regex::new(pattern).ok()?;
+        if re.is_match(create_sql) {
+            return Some(re.replace(create_sql, |caps: &regex::Captures| format!("{}{}", &caps[1], action)).into_owned());
+        }
+    }
+    if declared {
+        return None;
+    }
+
+    let end = create_sql.rfind(')')?;
+    Some(format!(
+        "{},\n    FOREIGN KEY ({}) REFERENCES {} (id){}\n{}",
+        create_sql[..end].trim_end(), column, parent, action, &create_sql[end..]
+    ))
+}
+
+/// Пересоздание таблицы с новым определением (порядок столбцов тот же) по схеме
+/// https://www.sqlite.org/lang_altertable.html#otheralter: внешние ключи на время
+/// отключаются, чтобы DROP не вызвал каскадное удаление в связанных таблицах.
+/// Индексы и триггеры таблицы создаются заново вызывающим кодом.
+async fn rebuild_table(pool: &SqlitePool, table: &str, create_sql: &str) -> Result<()> {
+    let body = create_sql
+        .find('(')
+        .map(|pos| &create_sql[pos..])
+        .ok_or_else(|| anyhow::anyhow!("Invalid CREATE TABLE statement for {}", table))?;
+    let tmp_table = format!("{}_rebuild", table);
+    // rowid переносится явно: на него ссылаются внешние FTS-индексы (content_rowid)
+    let columns: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(pool)
+        .await?;
+    let column_list = columns.iter().map(|(name,)| format!("\"{}\"", name)).collect::<Vec<_>>().join(", ");
+
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+    // Ссылки других таблиц на {table} не переписываются при RENAME
+    sqlx::query("PRAGMA legacy_alter_table = ON").execute(&mut *conn).await?;
+
+    let statements = [
+        "BEGIN IMMEDIATE".to_string(),
+        format!("DROP TABLE IF EXISTS {}", tmp_table),
+        format!("CREATE TABLE {} {}", tmp_table, body),
+        format!("INSERT INTO {} (rowid, {cols}) SELECT rowid, {cols} FROM {}", tmp_table, table, cols = column_list),
+        format!("DROP TABLE {}", table),
+        format!("ALTER TABLE {} RENAME TO {}", tmp_table, table),
+        "COMMIT".to_string(),
+    ];
+
+    let mut result = Ok(());
+    for statement in &statements {
+        if let Err(e) = sqlx::query(statement).execute(&mut *conn).await {
+            let _ = sqlx::query("ROLLBACK").execute(&mut *conn).await;
+            result = Err(e);
+            break;
+        }
+    }
+
+    if result.is_ok() {
+        let violations = sqlx::query("PRAGMA foreign_key_check").fetch_all(&mut *conn).await?;
+        if !violations.is_empty() {
+            log::warn!("{} foreign key violations after rebuilding {}", violations.len(), table);
+        }
+    }
+
+    sqlx::query("PRAGMA legacy_alter_table = OFF").execute(&mut *conn).await?;
+    sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await?;
+    result?;
+    Ok(())
+}
+
 // ==================== DATABASE RESET (DEVELOPMENT ONLY) ====================
 
 pub async fn reset_database(pool: &SqlitePool) -> Result<()> {
@@ -763,12 +2321,28 @@ pub async fn reset_database(pool: &SqlitePool) -> Result<()> {
         "DROP TRIGGER IF EXISTS reagents_fts_insert",
         "DROP TRIGGER IF EXISTS reagents_fts_update",
         "DROP TRIGGER IF EXISTS reagents_fts_delete",
+        "DROP TRIGGER IF EXISTS equipment_fts_insert",
+        "DROP TRIGGER IF EXISTS equipment_fts_update",
+        "DROP TRIGGER IF EXISTS equipment_fts_delete",
+        "DROP TRIGGER IF EXISTS experiments_fts_insert",
+        "DROP TRIGGER IF EXISTS experiments_fts_update",
+        "DROP TRIGGER IF EXISTS experiments_fts_delete",
+        "DROP TABLE IF EXISTS experiments_fts",
         "DROP TABLE IF EXISTS equipment_fts",
         "DROP TABLE IF EXISTS reagents_fts",
+        "DROP TABLE IF EXISTS equipment_catalog",
+        "DROP TABLE IF EXISTS manufacturer_normalizations",
+        "DROP TABLE IF EXISTS physical_state_mappings",
         "DROP TABLE IF EXISTS equipment_files",
+        "DROP TABLE IF EXISTS file_blobs",
+        "DROP TABLE IF EXISTS reagent_images",
+        "DROP TABLE IF EXISTS room_attendance",
+        "DROP TABLE IF EXISTS equipment_usage_sessions",
+        "DROP TABLE IF EXISTS kiosk_tokens",
         "DROP TABLE IF EXISTS equipment_maintenance",
         "DROP TABLE IF EXISTS equipment_parts",
         "DROP TABLE IF EXISTS experiment_equipment",
+        "DROP TABLE IF EXISTS experiment_participants",
         "DROP TABLE IF EXISTS experiment_reagents",
         "DROP TABLE IF EXISTS experiment_documents",
         "DROP TABLE IF EXISTS experiments",
@@ -783,6 +2357,32 @@ pub async fn reset_database(pool: &SqlitePool) -> Result<()> {
         "DROP TABLE IF EXISTS reagent_stock_cache",
         "DROP TABLE IF EXISTS reagent_count_cache",
         "DROP TABLE IF EXISTS batch_placements",
+        "DROP TABLE IF EXISTS catalog_lookup_cache",
+        "DROP TABLE IF EXISTS report_schedule_runs",
+        "DROP TABLE IF EXISTS report_schedules",
+        "DROP TABLE IF EXISTS report_presets",
+        "DROP TABLE IF EXISTS batch_expiry_extensions",
+        "DROP TABLE IF EXISTS external_links",
+        "DROP TABLE IF EXISTS experiment_dependencies",
+        "DROP TABLE IF EXISTS experiment_signoffs",
+        "DROP TABLE IF EXISTS experiment_comments",
+        "DROP TABLE IF EXISTS pending_deletions",
+        "DROP TABLE IF EXISTS consumption_approvals",
+        "DROP TABLE IF EXISTS alerts",
+        "DROP TABLE IF EXISTS stock_adjustments",
+        "DROP TABLE IF EXISTS stock_anomalies",
+        "DROP TABLE IF EXISTS metrics_daily_users",
+        "DROP TABLE IF EXISTS metrics_daily",
+        "DROP TABLE IF EXISTS demo_seed_records",
+        "DROP TABLE IF EXISTS outbox",
+        "DROP TABLE IF EXISTS download_redemptions",
+        "DROP TABLE IF EXISTS purchase_order_lines",
+        "DROP TABLE IF EXISTS purchase_orders",
+        "DROP TABLE IF EXISTS team_members",
+        "DROP TABLE IF EXISTS teams",
+        "DROP TABLE IF EXISTS user_favorites",
+        "DROP TABLE IF EXISTS locations",
+        "DROP TABLE IF EXISTS settings",
     ];
 
     for query in drop_queries.iter() {
@@ -846,21 +2446,236 @@ pub async fn rebuild_reagent_cache(pool: &SqlitePool) -> Result<u64> {
     Ok(result.rows_affected())
 }
 
-/// Rebuild FTS index (for maintenance after bulk imports)
+/// Rebuild reagent FTS index from the content table (maintenance only - triggers keep it in sync)
 pub async fn rebuild_fts_index(pool: &SqlitePool) -> Result<u64> {
-    info!("Rebuilding FTS index...");
+    info!("Rebuilding reagents FTS index...");
 
-    // Clear and repopulate
-    let _ = sqlx::query("DELETE FROM reagents_fts").execute(pool).await;
-
-    let result = sqlx::query(r#"
-        INSERT INTO reagents_fts(rowid, name, cas_number, formula)
-        SELECT rowid, name, cas_number, formula FROM reagents
-    "#).execute(pool).await?;
+    sqlx::query("INSERT INTO reagents_fts(reagents_fts) VALUES('rebuild')").execute(pool).await?;
 
     // Optimize
     let _ = sqlx::query("INSERT INTO reagents_fts(reagents_fts) VALUES('optimize')").execute(pool).await;
 
-    info!("FTS index rebuilt: {} rows", result.rows_affected());
-    Ok(result.rows_affected())
-}
\ No newline at end of file
+    let (rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM reagents").fetch_one(pool).await?;
+    info!("FTS index rebuilt: {} rows", rows);
+    Ok(rows as u64)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_batch_status_check_migration_keeps_rows_and_references() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+
+        let count_triggers = || sqlx::query_as::<_, (i64,)>(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'trigger' AND tbl_name = 'batches'"
+        ).fetch_one(&pool);
+        let (triggers_before,) = count_triggers().await.unwrap();
+
+        // База до появления 'awaiting_coa'
+        let (sql,): (String,) = sqlx::query_as("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'batches'")
+            .fetch_one(&pool).await.unwrap();
+        rebuild_table(&pool, "batches", &sql.replace(BATCH_STATUS_CHECK, BATCH_STATUS_CHECK_OLD)).await.unwrap();
+
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Ethanol', 'active', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'LOT-1', 5, 5, 'L', datetime('now'), 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO usage_logs (id, reagent_id, batch_id, quantity_used, unit, created_at) \
+             VALUES ('u1', 'r1', 'b1', 1, 'L', datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        assert!(sqlx::query("UPDATE batches SET status = 'awaiting_coa'").execute(&pool).await.is_err());
+
+        run_migrations(&pool).await.unwrap();
+
+        sqlx::query("UPDATE batches SET status = 'awaiting_coa'").execute(&pool).await.unwrap();
+        let (usage_rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM usage_logs WHERE batch_id = 'b1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(usage_rows, 1);
+        let (triggers_after,) = count_triggers().await.unwrap();
+        assert_eq!(triggers_after, triggers_before);
+        let (fk_enabled,): (i64,) = sqlx::query_as("PRAGMA foreign_keys").fetch_one(&pool).await.unwrap();
+        assert_eq!(fk_enabled, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_alert_kind_check_migration_keeps_alerts() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+
+        // База до появления 'maintenance_due': ограничения прежние, context ещё нет
+        let (sql,): (String,) = sqlx::query_as("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'alerts'")
+            .fetch_one(&pool).await.unwrap();
+        let old_sql = sql
+            .replace(ALERT_KIND_CHECK, ALERT_KIND_CHECK_OLD)
+            .replace(ALERT_ENTITY_CHECK, ALERT_ENTITY_CHECK_OLD)
+            .replace(", context TEXT", "");
+        sqlx::query("DROP TABLE alerts").execute(&pool).await.unwrap();
+        sqlx::query(&old_sql).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO alerts (id, kind, entity_type, entity_id, message, created_at, updated_at) \
+             VALUES ('a1', 'low_stock', 'batch', 'b1', 'Batch is low', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO alerts (id, kind, entity_type, entity_id, message, context, created_at, updated_at) \
+             VALUES ('a2', 'maintenance_due', 'maintenance', 'm1', 'Cleaning is due', '{}', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO alerts (id, kind, entity_type, entity_id, message, context, created_at, updated_at) \
+             VALUES ('a3', 'maintenance_escalation', 'maintenance', 'm1', 'Cleaning is overdue', '{}', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO alerts (id, kind, entity_type, entity_id, message, context, created_at, updated_at) \
+             VALUES ('a4', 'stock_anomaly', 'batch', 'b1', 'Batch consumed 51 times', '{}', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM alerts").fetch_one(&pool).await.unwrap();
+        assert_eq!(count, 4);
+    }
+
+    async fn fk_test_pool() -> SqlitePool {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u1', 'alice', 'alice@example.com', 'x', 'researcher', datetime('now'), datetime('now'))",
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Ethanol', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'LOT-1', 5, 5, 'L', datetime('now'), 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO rooms (id, name, capacity, status, created_at, updated_at) \
+             VALUES ('room1', 'Lab 1', 10, 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, room_id, \
+             created_by, created_at, updated_at) \
+             VALUES ('e1', 'Titration', datetime('now'), datetime('now'), 'planned', 'research', 'room1', 'u1', \
+             datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    async fn foreign_key_action(pool: &SqlitePool, table: &str, column: &str) -> Option<String> {
+        sqlx::query_scalar("SELECT on_delete FROM pragma_foreign_key_list(?) WHERE \"from\" = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_optional(pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_foreign_keys_reject_orphans() {
+        let pool = fk_test_pool().await;
+        let is_fk_violation = |result: Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error>| {
+            matches!(result, Err(sqlx::Error::Database(ref e)) if e.is_foreign_key_violation())
+        };
+
+        let orphan_batch = sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) \
+             VALUES ('b2', 'missing', 'LOT-2', 5, 5, 'L', datetime('now'), 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await;
+        assert!(is_fk_violation(orphan_batch));
+        let orphan_line = sqlx::query(
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at) \
+             VALUES ('l0', 'e1', 'r1', 'missing', 1, 'L', datetime('now'), datetime('now'))"
+        ).execute(&pool).await;
+        assert!(is_fk_violation(orphan_line));
+        let orphan_maintenance = sqlx::query(
+            "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, scheduled_date, status, created_at, updated_at) \
+             VALUES ('m0', 'missing', 'cleaning', datetime('now'), 'scheduled', datetime('now'), datetime('now'))"
+        ).execute(&pool).await;
+        assert!(is_fk_violation(orphan_maintenance));
+
+        // Партию (и реагент) с расходом в эксперименте удалить нельзя
+        sqlx::query(
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at) \
+             VALUES ('l1', 'e1', 'r1', 'b1', 1, 'L', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        assert!(is_fk_violation(sqlx::query("DELETE FROM batches WHERE id = 'b1'").execute(&pool).await));
+        assert!(is_fk_violation(sqlx::query("DELETE FROM reagents WHERE id = 'r1'").execute(&pool).await));
+
+        // Удаление эксперимента уносит его строки, удаление помещения отвязывает эксперименты
+        sqlx::query("DELETE FROM rooms WHERE id = 'room1'").execute(&pool).await.unwrap();
+        let room: Option<String> = sqlx::query_scalar("SELECT room_id FROM experiments WHERE id = 'e1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(room, None);
+        sqlx::query("DELETE FROM experiments WHERE id = 'e1'").execute(&pool).await.unwrap();
+        sqlx::query("DELETE FROM reagents WHERE id = 'r1'").execute(&pool).await.unwrap();
+        let (batches,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM batches").fetch_one(&pool).await.unwrap();
+        assert_eq!(batches, 0);
+
+        for (table, column) in [("equipment_parts", "equipment_id"), ("equipment_files", "equipment_id"), ("experiment_reagents", "experiment_id")] {
+            assert_eq!(foreign_key_action(&pool, table, column).await.as_deref(), Some("CASCADE"), "{}", table);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_foreign_key_migration_rebuilds_legacy_tables() {
+        let pool = fk_test_pool().await;
+        let count_dependents = |table: &'static str| sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM sqlite_master WHERE tbl_name = ? AND type IN ('index', 'trigger')"
+        ).bind(table).fetch_one(&pool);
+        let experiment_dependents = count_dependents("experiments").await.unwrap();
+
+        // Старая схема: помещение без ON DELETE SET NULL, партии без ключа на реагент
+        let table_sql = |table: &'static str| sqlx::query_scalar::<_, String>(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?"
+        ).bind(table).fetch_one(&pool);
+        let experiments_sql = table_sql("experiments").await.unwrap();
+        rebuild_table(&pool, "experiments", &experiments_sql.replace("REFERENCES rooms (id) ON DELETE SET NULL", "REFERENCES rooms (id)"))
+            .await.unwrap();
+        let batches_sql = table_sql("batches").await.unwrap();
+        rebuild_table(&pool, "batches", &batches_sql.replace("FOREIGN KEY (reagent_id) REFERENCES reagents (id) ON DELETE CASCADE,", ""))
+            .await.unwrap();
+        assert_eq!(foreign_key_action(&pool, "experiments", "room_id").await.as_deref(), Some("NO ACTION"));
+        assert_eq!(foreign_key_action(&pool, "batches", "reagent_id").await, None);
+        sqlx::query("PRAGMA foreign_keys = OFF").execute(&pool).await.unwrap();
+        sqlx::query("UPDATE experiments SET room_id = 'demolished' WHERE id = 'e1'").execute(&pool).await.unwrap();
+        sqlx::query("PRAGMA foreign_keys = ON").execute(&pool).await.unwrap();
+
+        run_migrations(&pool).await.unwrap();
+
+        assert_eq!(foreign_key_action(&pool, "experiments", "room_id").await.as_deref(), Some("SET NULL"));
+        assert_eq!(foreign_key_action(&pool, "batches", "reagent_id").await.as_deref(), Some("CASCADE"));
+        let room: Option<String> = sqlx::query_scalar("SELECT room_id FROM experiments WHERE id = 'e1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(room, None);
+        assert_eq!(count_dependents("experiments").await.unwrap(), experiment_dependents);
+        // rowid сохранён - полнотекстовый индекс по-прежнему указывает на ту же строку
+        let found: String = sqlx::query_scalar(
+            "SELECT e.id FROM experiments e JOIN experiments_fts f ON f.rowid = e.rowid WHERE experiments_fts MATCH 'Titration'"
+        ).fetch_one(&pool).await.unwrap();
+        assert_eq!(found, "e1");
+
+        // Ключ в описании колонки (ALTER TABLE ... ADD COLUMN) получает новое действие, а не дубликат
+        let inline = "CREATE TABLE t (id TEXT PRIMARY KEY, notes TEXT, room_id TEXT REFERENCES rooms(id))";
+        assert_eq!(
+            with_foreign_key(inline, "room_id", "rooms", "SET NULL", true).unwrap(),
+            "CREATE TABLE t (id TEXT PRIMARY KEY, notes TEXT, room_id TEXT REFERENCES rooms(id) ON DELETE SET NULL)"
+        );
+        assert_eq!(with_foreign_key("CREATE TABLE t (id TEXT, room_id TEXT CHECK(room_id != ','))", "room_id", "rooms", "CASCADE", true), None);
+    }
+}
@@ -0,0 +1,849 @@
+// src/demo_seed.rs
+//! Демо-данные для холодного старта: `--seed-demo` при запуске и `POST /admin/seed-demo`
+//! (только вне production).
+//!
+//! Набор создаётся через обычные обработчики (create_reagent, create_batch, create_equipment,
+//! store_equipment_upload, create_experiment, ...), поэтому наполнение заодно служит
+//! smoke-тестом основных путей записи. Каждая созданная запись регистрируется в
+//! `demo_seed_records`; `POST /admin/seed-demo/clear` удаляет ровно их. Записи, на которые
+//! уже ссылаются не демо-данные, остаются и перечисляются в ответе.
+
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc, Weekday};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::api_version::ApiVersion;
+use crate::auth::{AuthService, RegisterRequest, UserRole};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Флаг командной строки для наполнения при запуске
+pub const SEED_DEMO_FLAG: &str = "--seed-demo";
+
+pub const DEMO_ALREADY_SEEDED: &str = "DEMO_ALREADY_SEEDED";
+
+/// Тип записи в реестре и её таблица - в порядке удаления (зависимые раньше)
+const DEMO_ENTITIES: &[(&str, &str)] = &[
+    ("experiment", "experiments"),
+    ("equipment_file", "equipment_files"),
+    ("maintenance", "equipment_maintenance"),
+    ("equipment_part", "equipment_parts"),
+    ("equipment", "equipment"),
+    ("batch", "batches"),
+    ("reagent", "reagents"),
+    ("room", "rooms"),
+    ("user", "users"),
+];
+
+/// Демо-пользователи: (логин, роль)
+const DEMO_USERS: &[(&str, UserRole)] = &[
+    ("demo_admin", UserRole::Admin),
+    ("demo_researcher", UserRole::Researcher),
+    ("demo_viewer", UserRole::Viewer),
+];
+
+/// Реагент демо-набора
+struct DemoReagent {
+    name: &'static str,
+    formula: &'static str,
+    cas: &'static str,
+    liquid: bool,
+    hazards: Option<&'static str>,
+    policy: &'static str,
+    shelf_life_days: Option<i64>,
+}
+
+const fn reagent(
+    name: &'static str,
+    formula: &'static str,
+    cas: &'static str,
+    liquid: bool,
+    hazards: Option<&'static str>,
+) -> DemoReagent {
+    DemoReagent { name, formula, cas, liquid, hazards, policy: "strict", shelf_life_days: None }
+}
+
+const fn with_policy(r: DemoReagent, policy: &'static str) -> DemoReagent {
+    DemoReagent { policy, ..r }
+}
+
+const fn with_shelf_life(r: DemoReagent, days: i64) -> DemoReagent {
+    DemoReagent { shelf_life_days: Some(days), ..r }
+}
+
+const DEMO_REAGENTS: &[DemoReagent] = &[
+    reagent("Ethanol absolute", "C2H6O", "64-17-5", true, Some("GHS02, GHS07")),
+    reagent("Methanol", "CH4O", "67-56-1", true, Some("GHS02, GHS06, GHS08")),
+    reagent("Acetone", "C3H6O", "67-64-1", true, Some("GHS02, GHS07")),
+    reagent("Acetonitrile", "C2H3N", "75-05-8", true, Some("GHS02, GHS07")),
+    reagent("2-Propanol", "C3H8O", "67-63-0", true, Some("GHS02, GHS07")),
+    reagent("n-Hexane", "C6H14", "110-54-3", true, Some("GHS02, GHS07, GHS08, GHS09")),
+    reagent("Toluene", "C7H8", "108-88-3", true, Some("GHS02, GHS07, GHS08")),
+    reagent("Dichloromethane", "CH2Cl2", "75-09-2", true, Some("GHS07, GHS08")),
+    reagent("Chloroform", "CHCl3", "67-66-3", true, Some("GHS06, GHS08")),
+    reagent("Ethyl acetate", "C4H8O2", "141-78-6", true, Some("GHS02, GHS07")),
+    reagent("Diethyl ether", "C4H10O", "60-29-7", true, Some("GHS02, GHS07")),
+    reagent("Tetrahydrofuran", "C4H8O", "109-99-9", true, Some("GHS02, GHS07, GHS08")),
+    reagent("Dimethyl sulfoxide", "C2H6OS", "67-68-5", true, None),
+    reagent("N,N-Dimethylformamide", "C3H7NO", "68-12-2", true, Some("GHS02, GHS07, GHS08")),
+    reagent("Acetic acid glacial", "C2H4O2", "64-19-7", true, Some("GHS02, GHS05")),
+    reagent("Hydrochloric acid 37%", "HCl", "7647-01-0", true, Some("GHS05, GHS07")),
+    reagent("Sulfuric acid 96%", "H2SO4", "7664-93-9", true, Some("GHS05")),
+    reagent("Nitric acid 65%", "HNO3", "7697-37-2", true, Some("GHS03, GHS05")),
+    reagent("Phosphoric acid 85%", "H3PO4", "7664-38-2", true, Some("GHS05")),
+    reagent("Sodium hydroxide", "NaOH", "1310-73-2", false, Some("GHS05")),
+    reagent("Potassium hydroxide", "KOH", "1310-58-3", false, Some("GHS05, GHS07")),
+    reagent("Ammonium hydroxide 25%", "NH4OH", "1336-21-6", true, Some("GHS05, GHS07, GHS09")),
+    with_policy(reagent("Sodium chloride", "NaCl", "7647-14-5", false, None), "none"),
+    with_policy(reagent("Potassium chloride", "KCl", "7447-40-7", false, None), "none"),
+    with_policy(reagent("Calcium chloride", "CaCl2", "10043-52-4", false, Some("GHS07")), "advisory"),
+    with_policy(reagent("Magnesium sulfate", "MgSO4", "7487-88-9", false, None), "advisory"),
+    with_policy(reagent("Sodium bicarbonate", "NaHCO3", "144-55-8", false, None), "advisory"),
+    with_policy(reagent("Sodium carbonate", "Na2CO3", "497-19-8", false, Some("GHS07")), "advisory"),
+    reagent("Potassium permanganate", "KMnO4", "7722-64-7", false, Some("GHS03, GHS07, GHS09")),
+    reagent("Potassium dichromate", "K2Cr2O7", "7778-50-9", false, Some("GHS03, GHS05, GHS06, GHS08, GHS09")),
+    reagent("Copper(II) sulfate", "CuSO4", "7758-98-7", false, Some("GHS07, GHS09")),
+    reagent("Silver nitrate", "AgNO3", "7761-88-8", false, Some("GHS03, GHS05, GHS09")),
+    reagent("Iron(III) chloride", "FeCl3", "7705-08-0", false, Some("GHS05, GHS07")),
+    reagent("Zinc sulfate", "ZnSO4", "7733-02-0", false, Some("GHS05, GHS07, GHS09")),
+    with_policy(reagent("Sodium thiosulfate", "Na2S2O3", "7772-98-7", false, None), "advisory"),
+    with_policy(reagent("Potassium iodide", "KI", "7681-11-0", false, None), "advisory"),
+    reagent("Iodine", "I2", "7553-56-2", false, Some("GHS07, GHS09")),
+    with_shelf_life(reagent("Hydrogen peroxide 30%", "H2O2", "7722-84-1", true, Some("GHS05, GHS07")), 365),
+    with_shelf_life(reagent("D-Glucose", "C6H12O6", "50-99-7", false, None), 1095),
+    with_policy(reagent("Sucrose", "C12H22O11", "57-50-1", false, None), "advisory"),
+    with_policy(reagent("Urea", "CH4N2O", "57-13-6", false, None), "advisory"),
+    with_shelf_life(reagent("Glycine", "C2H5NO2", "56-40-6", false, None), 1095),
+    with_shelf_life(reagent("Tris base", "C4H11NO3", "77-86-1", false, Some("GHS07")), 1825),
+    reagent("EDTA disodium salt", "C10H14N2Na2O8", "139-33-3", false, Some("GHS07")),
+    reagent("Sodium dodecyl sulfate", "C12H25NaO4S", "151-21-3", false, Some("GHS02, GHS05, GHS07")),
+    reagent("Phenolphthalein", "C20H14O4", "77-09-8", false, Some("GHS08")),
+    reagent("Methyl orange", "C14H14N3NaO3S", "547-58-0", false, Some("GHS06")),
+    reagent("Bromothymol blue", "C27H28Br2O5S", "76-59-5", false, None),
+    reagent("Benzoic acid", "C7H6O2", "65-85-0", false, Some("GHS05, GHS07, GHS08")),
+    reagent("Citric acid", "C6H8O7", "77-92-9", false, Some("GHS07")),
+    reagent("Ammonium chloride", "NH4Cl", "12125-02-9", false, Some("GHS07")),
+    reagent("Potassium nitrate", "KNO3", "7757-79-1", false, Some("GHS03")),
+];
+
+const DEMO_MANUFACTURERS: &[&str] = &["Sigma-Aldrich", "Merck", "Thermo Fisher Scientific", "Fluka"];
+const DEMO_LOCATIONS: &[&str] = &["Cabinet A1", "Cabinet A2", "Flammables cabinet", "Cold room", "Acids cabinet"];
+
+/// Сроки годности партий относительно сегодняшнего дня: от просроченных до долгих
+const DEMO_EXPIRY_OFFSETS_DAYS: &[i64] = &[-20, 5, 12, 28, 45, 90, 180, 365, 540, 730];
+
+/// Оборудование: (название, тип, производитель, модель, количество, запчасти (название, артикул))
+type DemoEquipment = (&'static str, &'static str, &'static str, &'static str, i32, &'static [(&'static str, &'static str)]);
+
+const DEMO_EQUIPMENT: &[DemoEquipment] = &[
+    ("Refrigerated centrifuge", "instrument", "Eppendorf", "5810 R", 1,
+     &[("Swing-bucket rotor A-4-62", "5810709008"), ("Adapter for 50 mL conical tubes", "5810711002")]),
+    ("HPLC system", "instrument", "Agilent", "1260 Infinity II", 1,
+     &[("C18 column 4.6x150 mm", "959963-902"), ("Deuterium lamp", "5190-0917")]),
+    ("Analytical balance", "instrument", "Sartorius", "Quintix 125D-1S", 1,
+     &[("Calibration weight 100 g", "YCW512-AC-02")]),
+    ("pH meter", "instrument", "Mettler Toledo", "SevenCompact S220", 1,
+     &[("pH electrode InLab Expert Pro", "51343101")]),
+    ("UV-Vis spectrophotometer", "instrument", "Shimadzu", "UV-1900i", 1,
+     &[("Quartz cuvette 10 mm", "200-34442")]),
+    ("Magnetic stirrer with heating", "instrument", "IKA", "RCT basic", 3, &[]),
+    ("Volumetric flask 100 mL", "glassware", "DURAN", "Class A", 24, &[]),
+    ("Burette 50 mL", "glassware", "BRAND", "Blaubrand", 12, &[]),
+];
+
+/// Помещения: (название, описание, вместимость, цвет)
+const DEMO_ROOMS: &[(&str, &str, i32, &str)] = &[
+    ("Demo Chemistry Lab 101", "General chemistry laboratory", 16, "#4CAF50"),
+    ("Demo Biochemistry Lab 102", "Biochemistry and buffers", 12, "#2196F3"),
+    ("Demo Instrument Room", "HPLC, UV-Vis and balances", 6, "#FF9800"),
+    ("Demo Teaching Lab", "Student practicums", 24, "#9C27B0"),
+];
+
+/// Учётные данные демо-пользователя; пароль показывается только в ответе наполнения
+#[derive(Debug, Serialize)]
+pub struct DemoCredential {
+    pub username: String,
+    pub role: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DemoSeedReport {
+    /// Число созданных записей по типам
+    pub created: BTreeMap<String, i64>,
+    pub users: Vec<DemoCredential>,
+    /// Реагенты, пропущенные из-за уже существующего имени
+    pub skipped_reagents: Vec<String>,
+}
+
+/// Запись, которая не удалена, потому что на неё ссылаются не демо-данные
+#[derive(Debug, Serialize)]
+pub struct RetainedDemoRecord {
+    pub entity_type: String,
+    pub entity_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DemoClearReport {
+    pub removed: BTreeMap<String, i64>,
+    pub retained: Vec<RetainedDemoRecord>,
+}
+
+// ==================== REGISTRY ====================
+
+async fn record(pool: &SqlitePool, entity_type: &str, entity_id: &str) -> ApiResult<()> {
+    sqlx::query("INSERT OR IGNORE INTO demo_seed_records (entity_type, entity_id, created_at) VALUES (?, ?, ?)")
+        .bind(entity_type)
+        .bind(entity_id)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn registry_counts(pool: &SqlitePool) -> ApiResult<BTreeMap<String, i64>> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT entity_type, COUNT(*) FROM demo_seed_records GROUP BY entity_type"
+    )
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Демо-данные уже есть (в том числе оставшиеся после неполной очистки)
+pub async fn is_seeded(pool: &SqlitePool) -> ApiResult<bool> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM demo_seed_records)")
+        .fetch_one(pool)
+        .await?;
+    Ok(exists)
+}
+
+// ==================== HELPERS ====================
+
+/// id созданной записи из ответа обработчика (`data.id`)
+async fn created_id(step: &str, resp: HttpResponse) -> ApiResult<String> {
+    let status = resp.status();
+    let bytes = actix_web::body::to_bytes(resp.into_body())
+        .await
+        .map_err(|_| ApiError::internal_error(format!("Demo seed: unreadable response from {}", step)))?;
+    let body: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    match body.pointer("/data/id").and_then(Value::as_str) {
+        Some(id) if status.is_success() => Ok(id.to_string()),
+        _ => Err(ApiError::internal_error(format!("Demo seed: {} returned {} without an id", step, status))),
+    }
+}
+
+fn request<T: DeserializeOwned>(value: Value) -> ApiResult<web::Json<T>> {
+    serde_json::from_value(value)
+        .map(web::Json)
+        .map_err(|e| ApiError::internal_error(format!("Demo seed: invalid request: {}", e)))
+}
+
+fn demo_password() -> String {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .map(char::from)
+        .collect();
+    format!("Demo-{}-7x", random)
+}
+
+/// Ближайший будний день через `days_ahead` суток (может быть отрицательным) в `hour`:00 UTC
+fn weekday_at(days_ahead: i64, hour: u32) -> DateTime<Utc> {
+    let mut day = (Utc::now() + Duration::days(days_ahead)).date_naive();
+    while matches!(day.weekday(), Weekday::Sat | Weekday::Sun) {
+        day = day.succ_opt().unwrap_or(day);
+    }
+    Utc.from_utc_datetime(&day.and_hms_opt(hour, 0, 0).unwrap_or_default())
+}
+
+/// Multipart с текстовым руководством - как форма загрузки файла оборудования
+fn manual_upload(filename: &str, text: &str) -> Multipart {
+    const BOUNDARY: &str = "LIMSDEMOSEEDBOUNDARY";
+    let mut headers = actix_web::http::header::HeaderMap::new();
+    headers.insert(
+        actix_web::http::header::CONTENT_TYPE,
+        actix_web::http::header::HeaderValue::from_static("multipart/form-data; boundary=LIMSDEMOSEEDBOUNDARY"),
+    );
+    let body = format!(
+        "--{b}\r\nContent-Disposition: form-data; name=\"file_type\"\r\n\r\nmanual\r\n\
+         --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{f}\"\r\n\
+         Content-Type: text/plain\r\n\r\n{t}\r\n--{b}--\r\n",
+        b = BOUNDARY,
+        f = filename,
+        t = text
+    );
+    let chunks: Vec<Result<web::Bytes, actix_web::error::PayloadError>> = vec![Ok(web::Bytes::from(body))];
+    Multipart::new(&headers, futures_util::stream::iter(chunks))
+}
+
+// ==================== SEED ====================
+
+/// Партия реагента, пригодная для резерва в экспериментах
+struct SeededBatch {
+    batch_id: String,
+    hazardous: bool,
+}
+
+/// Создать демо-набор от имени демо-пользователей. Пароли возвращаются один раз в отчёте.
+pub async fn seed_demo(
+    app_state: &web::Data<Arc<AppState>>,
+    auth_service: &AuthService,
+    files_dir: &Path,
+) -> ApiResult<DemoSeedReport> {
+    let pool = &app_state.db_pool;
+    if is_seeded(pool).await? {
+        return Err(ApiError::Conflict {
+            code: DEMO_ALREADY_SEEDED,
+            message: "Demo data is already present; clear it via POST /admin/seed-demo/clear first".to_string(),
+        });
+    }
+
+    let mut users = Vec::new();
+    let mut user_ids = HashMap::new();
+    for &(username, ref role) in DEMO_USERS {
+        let password = demo_password();
+        let user = crate::auth::User::create(
+            pool,
+            RegisterRequest {
+                username: username.to_string(),
+                email: format!("{}@demo.lims.local", username),
+                password: password.clone(),
+                role: None,
+            },
+            UserRole::Viewer,
+            auth_service,
+        ).await?;
+        record(pool, "user", &user.id).await?;
+        if *role != UserRole::Viewer {
+            sqlx::query("UPDATE users SET role = ?, updated_at = datetime('now') WHERE id = ?")
+                .bind(role.as_str())
+                .bind(&user.id)
+                .execute(pool)
+                .await?;
+        }
+        user_ids.insert(username, user.id);
+        users.push(DemoCredential { username: username.to_string(), role: role.as_str().to_string(), password });
+    }
+    let admin = user_ids["demo_admin"].clone();
+    let researcher = user_ids["demo_researcher"].clone();
+
+    let rooms = seed_rooms(app_state, &admin).await?;
+    let (batches, skipped_reagents) = seed_reagents(app_state, &admin).await?;
+    seed_equipment(app_state, &admin, files_dir).await?;
+    seed_experiments(app_state, &researcher, &rooms, &batches).await?;
+
+    Ok(DemoSeedReport { created: registry_counts(pool).await?, users, skipped_reagents })
+}
+
+async fn seed_rooms(app_state: &web::Data<Arc<AppState>>, user_id: &str) -> ApiResult<Vec<String>> {
+    let mut ids = Vec::new();
+    for &(name, description, capacity, color) in DEMO_ROOMS {
+        let resp = crate::room_handlers::create_room(
+            app_state.clone(),
+            request(json!({ "name": name, "description": description, "capacity": capacity, "color": color }))?,
+            user_id.to_string(),
+        ).await?;
+        let id = created_id("create_room", resp).await?;
+        record(&app_state.db_pool, "room", &id).await?;
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+/// Реагенты с 1-3 партиями; возвращает непросроченные партии по имени реагента
+async fn seed_reagents(
+    app_state: &web::Data<Arc<AppState>>,
+    user_id: &str,
+) -> ApiResult<(HashMap<&'static str, SeededBatch>, Vec<String>)> {
+    let pool = &app_state.db_pool;
+    let now = Utc::now();
+    let mut fresh = HashMap::new();
+    let mut skipped = Vec::new();
+
+    for (i, r) in DEMO_REAGENTS.iter().enumerate() {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM reagents WHERE name = ?)")
+            .bind(r.name)
+            .fetch_one(pool)
+            .await?;
+        if exists {
+            skipped.push(r.name.to_string());
+            continue;
+        }
+
+        let resp = crate::reagent_handlers::create_reagent(
+            app_state.clone(),
+            request(json!({
+                "name": r.name,
+                "formula": r.formula,
+                "cas_number": r.cas,
+                "manufacturer": DEMO_MANUFACTURERS[i % DEMO_MANUFACTURERS.len()],
+                "physical_state": if r.liquid { "liquid" } else { "solid" },
+                "storage_conditions": if r.liquid { "Tightly closed, 15-25 °C" } else { "Dry place, 15-25 °C" },
+                "hazard_pictograms": r.hazards,
+                "procurement_lead_time_days": 7 + (i as i64 % 4) * 7,
+                "default_shelf_life_days": r.shelf_life_days,
+                "expiry_policy": r.policy,
+            }))?,
+            user_id.to_string(),
+        ).await?;
+        let reagent_id = created_id("create_reagent", resp).await?;
+        record(pool, "reagent", &reagent_id).await?;
+
+        for k in 0..(1 + i % 3) {
+            let offset = DEMO_EXPIRY_OFFSETS_DAYS[(i + 4 * k) % DEMO_EXPIRY_OFFSETS_DAYS.len()];
+            // Первая партия реагента со сроком хранения получает срок годности автоматически
+            let expiry = match (r.policy, r.shelf_life_days) {
+                ("none", _) => None,
+                (_, Some(_)) if k == 0 => None,
+                _ => Some(now + Duration::days(offset.max(1))),
+            };
+            let unit = if r.liquid { "mL" } else { "g" };
+            let quantity = if r.liquid { 500.0 } else { 250.0 } * (k + 1) as f64;
+            let resp = crate::batch_handlers::create_batch(
+                app_state.clone(),
+                web::Path::from(reagent_id.clone()),
+                request(json!({
+                    "batch_number": format!("DEMO-{:03}-{}", i + 1, k + 1),
+                    "lot_number": format!("L{}{:03}", 24 + k, i + 1),
+                    "quantity": quantity,
+                    "unit": unit,
+                    "expiry_date": expiry,
+                    "supplier": DEMO_MANUFACTURERS[(i + k) % DEMO_MANUFACTURERS.len()],
+                    "location": DEMO_LOCATIONS[(i + k) % DEMO_LOCATIONS.len()],
+                    "received_date": now - Duration::days(30 + 15 * k as i64),
+                }))?,
+                user_id.to_string(),
+                ApiVersion::LATEST,
+            ).await?;
+            let batch_id = created_id("create_batch", resp).await?;
+            record(pool, "batch", &batch_id).await?;
+
+            if expiry.is_some() && offset < 0 {
+                // Просроченную партию API не примет - срок сдвигаем в прошлое после создания
+                sqlx::query("UPDATE batches SET expiry_date = ? WHERE id = ?")
+                    .bind(now + Duration::days(offset))
+                    .bind(&batch_id)
+                    .execute(pool)
+                    .await?;
+            } else {
+                fresh.entry(r.name).or_insert(SeededBatch { batch_id, hazardous: r.hazards.is_some() });
+            }
+        }
+    }
+    Ok((fresh, skipped))
+}
+
+async fn seed_equipment(app_state: &web::Data<Arc<AppState>>, user_id: &str, files_dir: &Path) -> ApiResult<()> {
+    let pool = &app_state.db_pool;
+    let today = Utc::now().date_naive();
+
+    for (i, &(name, type_, manufacturer, model, quantity, parts)) in DEMO_EQUIPMENT.iter().enumerate() {
+        let serial = format!("DEMO-EQ-{:03}", i + 1);
+        let resp = crate::equipment_handlers::create_equipment(
+            app_state.clone(),
+            request(json!({
+                "name": name,
+                "type": type_,
+                "quantity": quantity,
+                "manufacturer": manufacturer,
+                "model": model,
+                "serial_number": serial,
+                "location": DEMO_ROOMS[i % DEMO_ROOMS.len()].0,
+                "purchase_date": (today - Duration::days(400 + 90 * i as i64)).to_string(),
+            }))?,
+            user_id.to_string(),
+            ApiVersion::LATEST,
+        ).await?;
+        let equipment_id = created_id("create_equipment", resp).await?;
+        record(pool, "equipment", &equipment_id).await?;
+
+        for &(part_name, part_number) in parts {
+            let resp = crate::equipment_handlers::add_equipment_part(
+                app_state.clone(),
+                web::Path::from(equipment_id.clone()),
+                request(json!({
+                    "name": part_name,
+                    "part_number": part_number,
+                    "manufacturer": manufacturer,
+                    "quantity": 2,
+                    "min_quantity": 1,
+                }))?,
+                user_id.to_string(),
+            ).await?;
+            record(pool, "equipment_part", &created_id("add_equipment_part", resp).await?).await?;
+        }
+
+        if type_ != "instrument" {
+            continue;
+        }
+
+        // Прошлая калибровка (завершена) и ближайший плановый осмотр
+        let past = today - Duration::days(60 + 7 * i as i64);
+        let resp = crate::equipment_handlers::create_maintenance(
+            app_state.clone(),
+            web::Path::from(equipment_id.clone()),
+            request(json!({ "maintenance_type": "calibration", "scheduled_date": past.to_string() }))?,
+            user_id.to_string(),
+        ).await?;
+        let maintenance_id = created_id("create_maintenance", resp).await?;
+        record(pool, "maintenance", &maintenance_id).await?;
+        crate::equipment_handlers::complete_maintenance(
+            app_state.clone(),
+            web::Path::from((equipment_id.clone(), maintenance_id)),
+            request(json!({ "completed_date": past.to_string(), "performed_by": "Service engineer" }))?,
+            user_id.to_string(),
+        ).await?;
+
+        let upcoming = today + Duration::days(14 + 7 * i as i64);
+        let resp = crate::equipment_handlers::create_maintenance(
+            app_state.clone(),
+            web::Path::from(equipment_id.clone()),
+            request(json!({ "maintenance_type": "inspection", "scheduled_date": upcoming.to_string() }))?,
+            user_id.to_string(),
+        ).await?;
+        record(pool, "maintenance", &created_id("create_maintenance", resp).await?).await?;
+
+        let manual = format!("{} {} ({})\nOperating manual - demo data.\n", manufacturer, model, name);
+        let file = crate::equipment_handlers::store_equipment_upload(
+            pool, files_dir, &equipment_id, manual_upload(&format!("{}-manual.txt", serial), &manual), user_id,
+        ).await?;
+        record(pool, "equipment_file", &file.id).await?;
+    }
+    Ok(())
+}
+
+/// Эксперименты во всех статусах. В переводимые в работу попадают только неопасные реагенты -
+/// иначе понадобился бы допуск инструктора.
+async fn seed_experiments(
+    app_state: &web::Data<Arc<AppState>>,
+    user_id: &str,
+    rooms: &[String],
+    batches: &HashMap<&'static str, SeededBatch>,
+) -> ApiResult<()> {
+    let now = Utc::now();
+    let teaching_lab = rooms.get(3).cloned();
+    let experiments = [
+        // (название, тип, начало, длительность в часах, помещение, реагенты, начальный статус, переходы)
+        ("Calibration curve for Cu(II) by UV-Vis", "research", weekday_at(-21, 10), 4, rooms.get(2).cloned(),
+         vec!["D-Glucose"], "planned", vec!["in_progress", "completed"]),
+        ("Iodometric titration of vitamin C", "educational", weekday_at(-10, 10), 2, teaching_lab.clone(),
+         vec!["Potassium iodide", "Sodium thiosulfate"], "planned", vec!["in_progress", "completed"]),
+        ("Protein buffer stability study", "research", now - Duration::days(1), 24 * 6, None,
+         vec!["Glycine", "Sodium chloride", "Magnesium sulfate"], "planned", vec!["in_progress"]),
+        ("Acid-base titration practicum", "educational", weekday_at(3, 10), 2, teaching_lab.clone(),
+         vec!["Hydrochloric acid 37%", "Sodium hydroxide", "Phenolphthalein"], "planned", vec![]),
+        ("Recrystallization of benzoic acid", "educational", weekday_at(5, 13), 3, teaching_lab,
+         vec!["Benzoic acid", "Ethanol absolute"], "planned", vec![]),
+        ("Kinetics of permanganate reduction", "research", weekday_at(10, 9), 6, rooms.first().cloned(),
+         vec!["Potassium permanganate", "Sulfuric acid 96%"], "planned", vec!["cancelled"]),
+        ("Solvent screening for HPLC method", "research", weekday_at(20, 9), 8, None,
+         vec!["Acetonitrile", "Methanol"], "draft", vec![]),
+    ];
+
+    for (title, experiment_type, start, hours, room_id, reagents, initial, transitions) in experiments {
+        let resp = crate::experiment_handlers::create_experiment(
+            app_state.clone(),
+            request(json!({
+                "title": title,
+                "description": "Demo experiment",
+                "experiment_type": experiment_type,
+                "experiment_date": start,
+                "start_date": start,
+                "end_date": start + Duration::hours(hours),
+                "room_id": room_id,
+                "instructor": if experiment_type == "educational" { Some("Demo Instructor") } else { None },
+                "student_group": if experiment_type == "educational" { Some("CHEM-201") } else { None },
+                "status": initial,
+            }))?,
+            user_id.to_string(),
+        ).await?;
+        let experiment_id = created_id("create_experiment", resp).await?;
+        record(&app_state.db_pool, "experiment", &experiment_id).await?;
+
+        let starts = transitions.contains(&"in_progress");
+        for name in reagents {
+            let Some(batch) = batches.get(name).filter(|b| !(starts && b.hazardous)) else {
+                continue;
+            };
+            crate::experiment_handlers::add_reagent_to_experiment(
+                app_state.clone(),
+                web::Path::from(experiment_id.clone()),
+                request(json!({ "batch_id": batch.batch_id, "quantity_used": 10.0, "notes": "Demo reservation" }))?,
+                user_id.to_string(),
+            ).await?;
+        }
+
+        for status in transitions {
+            crate::experiment_handlers::update_experiment_status(
+                app_state.clone(),
+                web::Path::from(experiment_id.clone()),
+                request(json!({ "status": status }))?,
+                user_id.to_string(),
+            ).await?;
+        }
+    }
+    Ok(())
+}
+
+// ==================== CLEAR ====================
+
+/// Удалить записи, созданные наполнением. Дочерние записи демо-объектов уходят вместе с ними;
+/// объект с чужими (не демо) зависимостями остаётся в реестре и попадает в `retained`.
+pub async fn clear_demo(pool: &SqlitePool) -> ApiResult<DemoClearReport> {
+    let before = registry_counts(pool).await?;
+    let mut retained = Vec::new();
+
+    for &(entity_type, table) in DEMO_ENTITIES {
+        let ids: Vec<String> = sqlx::query_scalar(
+            "SELECT entity_id FROM demo_seed_records WHERE entity_type = ? ORDER BY created_at DESC"
+        )
+            .bind(entity_type)
+            .fetch_all(pool)
+            .await?;
+        for id in ids {
+            if let Some(reason) = remove_entity(pool, entity_type, table, &id).await? {
+                retained.push(RetainedDemoRecord {
+                    entity_type: entity_type.to_string(),
+                    entity_id: id,
+                    reason,
+                });
+            }
+        }
+    }
+
+    // Из реестра уходит всё, чего больше нет в таблице (в том числе удалённое каскадом)
+    for &(entity_type, table) in DEMO_ENTITIES {
+        sqlx::query(&format!(
+            "DELETE FROM demo_seed_records WHERE entity_type = ? AND entity_id NOT IN (SELECT id FROM {})",
+            table
+        ))
+            .bind(entity_type)
+            .execute(pool)
+            .await?;
+    }
+
+    let after = registry_counts(pool).await?;
+    let removed = before.into_iter()
+        .map(|(entity_type, count)| {
+            let left = after.get(&entity_type).copied().unwrap_or(0);
+            (entity_type, count - left)
+        })
+        .collect();
+    Ok(DemoClearReport { removed, retained })
+}
+
+/// Есть ли у объекта дочерние записи, которых нет в реестре демо-данных
+async fn has_foreign_children(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    parent_id: &str,
+    child_type: &str,
+) -> ApiResult<bool> {
+    let exists: bool = sqlx::query_scalar(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {} WHERE {} = ? AND id NOT IN \
+         (SELECT entity_id FROM demo_seed_records WHERE entity_type = ?))",
+        table, column
+    ))
+        .bind(parent_id)
+        .bind(child_type)
+        .fetch_one(pool)
+        .await?;
+    Ok(exists)
+}
+
+/// Удалить одну демо-запись; Some(причина), если она остаётся
+async fn remove_entity(pool: &SqlitePool, entity_type: &str, table: &str, id: &str) -> ApiResult<Option<String>> {
+    match entity_type {
+        // Удаляются вместе с оборудованием
+        "equipment_file" | "maintenance" | "equipment_part" => return Ok(None),
+        "equipment" => {
+            for (child_table, child_type) in [
+                ("equipment_parts", "equipment_part"),
+                ("equipment_maintenance", "maintenance"),
+                ("equipment_files", "equipment_file"),
+            ] {
+                if has_foreign_children(pool, child_table, "equipment_id", id, child_type).await? {
+                    return Ok(Some(format!("has non-demo records in {}", child_table)));
+                }
+            }
+            crate::equipment_handlers::purge_equipment(pool, id).await?;
+            return Ok(None);
+        }
+        "reagent" if has_foreign_children(pool, "batches", "reagent_id", id, "batch").await? => {
+            return Ok(Some("has non-demo batches".to_string()));
+        }
+        "experiment" => {
+            // Списания, сделанные при завершении демо-эксперимента
+            sqlx::query("DELETE FROM usage_logs WHERE experiment_id = ?")
+                .bind(id)
+                .execute(pool)
+                .await?;
+        }
+        _ => {}
+    }
+
+    match sqlx::query(&format!("DELETE FROM {} WHERE id = ?", table)).bind(id).execute(pool).await {
+        Ok(_) => Ok(None),
+        Err(sqlx::Error::Database(ref db)) if db.is_foreign_key_violation() => {
+            Ok(Some("referenced by non-demo records".to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// ==================== STARTUP / HANDLERS ====================
+
+/// `--seed-demo`: наполнить при запуске, если демо-данных ещё нет; пароли выводятся в лог один раз
+pub async fn seed_on_startup(app_state: &web::Data<Arc<AppState>>, auth_service: &AuthService) -> anyhow::Result<()> {
+    if is_seeded(&app_state.db_pool).await.map_err(|e| anyhow::anyhow!("{}", e))? {
+        log::info!("Demo data already present, skipping {}", SEED_DEMO_FLAG);
+        return Ok(());
+    }
+    if app_state.config.is_production() {
+        log::warn!("Seeding demo data into a production database ({})", SEED_DEMO_FLAG);
+    }
+
+    let report = seed_demo(app_state, auth_service, &crate::equipment_handlers::get_equipment_files_dir())
+        .await
+        .map_err(|e| anyhow::anyhow!("Demo seed failed: {}", e))?;
+    log::info!("Demo data created: {:?}", report.created);
+    for user in &report.users {
+        log::warn!("Demo user {} ({}): password {}", user.username, user.role, user.password);
+    }
+    Ok(())
+}
+
+/// POST /admin/seed-demo - наполнить демо-данными (вне production)
+pub async fn seed_demo_handler(
+    app_state: web::Data<Arc<AppState>>,
+    auth_service: web::Data<Arc<AuthService>>,
+) -> ApiResult<HttpResponse> {
+    if app_state.config.is_production() {
+        return Err(ApiError::Forbidden("Demo seeding is disabled in production".to_string()));
+    }
+
+    let report = seed_demo(&app_state, &auth_service, &crate::equipment_handlers::get_equipment_files_dir()).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success_with_message(
+        report,
+        "Demo data created; passwords are shown only once".to_string(),
+    )))
+}
+
+/// POST /admin/seed-demo/clear - удалить всё, что создало наполнение
+pub async fn clear_demo_handler(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let report = clear_demo(&app_state.db_pool).await?;
+    let message = if report.retained.is_empty() {
+        "Demo data removed".to_string()
+    } else {
+        format!("Demo data removed; {} record(s) retained", report.retained.len())
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(report, message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestApp;
+
+    async fn count(pool: &SqlitePool, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    const COUNTED: &[&str] = &[
+        "users", "reagents", "batches", "equipment", "equipment_parts", "equipment_maintenance",
+        "equipment_files", "rooms", "experiments", "experiment_reagents",
+    ];
+
+    #[actix_web::test]
+    async fn test_seed_demo_then_clear_removes_exactly_seeded_rows() {
+        let app = TestApp::new().await;
+        let dir = tempfile::tempdir().unwrap();
+        let mut baseline = Vec::new();
+        for table in COUNTED {
+            baseline.push(count(&app.pool, table).await);
+        }
+
+        let report = seed_demo(&app.state(), &app.auth_service(), dir.path()).await.unwrap();
+        assert_eq!(report.users.len(), 3);
+        // "Sodium chloride" уже есть в фикстурах - пропускается
+        assert_eq!(report.skipped_reagents, ["Sodium chloride"]);
+        assert_eq!(report.created["reagent"], DEMO_REAGENTS.len() as i64 - 1);
+        assert_eq!(report.created["room"], DEMO_ROOMS.len() as i64);
+        assert_eq!(report.created["equipment_file"], 6);
+        assert!(report.created["batch"] > 90);
+
+        let statuses: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT e.status FROM experiments e \
+             JOIN demo_seed_records d ON d.entity_type = 'experiment' AND d.entity_id = e.id ORDER BY e.status"
+        ).fetch_all(&app.pool).await.unwrap();
+        assert_eq!(statuses, ["cancelled", "completed", "draft", "in_progress", "planned"]);
+        let expired: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM batches WHERE expiry_date < datetime('now')")
+            .fetch_one(&app.pool).await.unwrap();
+        assert!(expired > 0);
+        let researcher: String = sqlx::query_scalar("SELECT role FROM users WHERE username = 'demo_researcher'")
+            .fetch_one(&app.pool).await.unwrap();
+        assert_eq!(researcher, "researcher");
+
+        // Повторное наполнение без очистки отклоняется
+        let again = seed_demo(&app.state(), &app.auth_service(), dir.path()).await.unwrap_err();
+        assert!(matches!(again, ApiError::Conflict { code: DEMO_ALREADY_SEEDED, .. }));
+
+        // Не демо-партия у демо-реагента удерживает реагент при очистке
+        let demo_reagent: String = sqlx::query_scalar("SELECT id FROM reagents WHERE name = 'Sucrose'")
+            .fetch_one(&app.pool).await.unwrap();
+        let (status, _) = app.post(UserRole::Admin, &format!("/reagents/{}/batches", demo_reagent), json!({
+            "batch_number": "REAL-1", "quantity": 10.0, "unit": "g",
+        })).await;
+        assert_eq!(status, actix_web::http::StatusCode::CREATED);
+
+        let (status, body) = app.post(UserRole::Admin, "/admin/seed-demo/clear", json!({})).await;
+        assert_eq!(status, actix_web::http::StatusCode::OK);
+        // Остаются реагент и его автор demo_admin (на него ссылается reagents.created_by)
+        let retained: Vec<(&str, &str)> = body["data"]["retained"].as_array().unwrap().iter()
+            .map(|r| (r["entity_type"].as_str().unwrap(), r["entity_id"].as_str().unwrap()))
+            .collect();
+        assert_eq!(retained.len(), 2, "{:?}", retained);
+        assert_eq!(retained[0], ("reagent", demo_reagent.as_str()));
+        assert_eq!(retained[1].0, "user");
+        assert_eq!(body["data"]["removed"]["experiment"], 7);
+
+        for (table, before) in COUNTED.iter().zip(&baseline) {
+            let expected = match *table {
+                "reagents" | "batches" | "users" => before + 1,
+                _ => *before,
+            };
+            assert_eq!(count(&app.pool, table).await, expected, "{}", table);
+        }
+        assert_eq!(count(&app.pool, "demo_seed_records").await, 2);
+        assert!(files_under(dir.path()).is_empty());
+    }
+
+    fn files_under(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                files.extend(files_under(&path));
+            } else {
+                files.push(path);
+            }
+        }
+        files
+    }
+}
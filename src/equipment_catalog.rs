@@ -0,0 +1,963 @@
+// src/equipment_catalog.rs
+//! Справочник производителей и моделей оборудования.
+//!
+//! - GET /equipment/manufacturers, GET /equipment/models?manufacturer= - различающиеся
+//!   значения с количеством экземпляров для автодополнения (вместе с моделями каталога);
+//! - GET/PUT /admin/normalizations/manufacturer - карта написаний производителя
+//!   ("Thermo", "ThermoFisher" -> "Thermo Fisher Scientific"). PUT переписывает уже
+//!   сохранённые строки; при создании, изменении и импорте оборудования карта
+//!   применяется через `ManufacturerNormalizer`;
+//! - серийный номер уникален (idx_equipment_serial_unique, на нём держится upsert импорта).
+//!   Индекс создаётся при запуске (`ensure_serial_index`), если повторов нет; повторы
+//!   перечисляются в GET /admin/normalizations/equipment-serial, импорт до их разбора отклоняется;
+//! - /equipment/catalog - типовые модели (тип, интервал калибровки/обслуживания,
+//!   руководство). `catalog_id` в CreateEquipmentRequest заполняет незаданные поля
+//!   из каталога, а руководство прикладывается к новому экземпляру общим blob'ом
+//!   (см. file_blobs) без копирования файла.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::audit::ChangeSet;
+use crate::auth::get_current_user;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::CreateEquipmentRequest;
+use crate::query_builders::EquipmentType;
+use crate::AppState;
+
+/// Предел подсказок автодополнения по умолчанию
+const DEFAULT_SUGGESTIONS: usize = 20;
+const MAX_SUGGESTIONS: usize = 100;
+
+// ==================== NORMALIZATION ====================
+
+/// Схлопывание пробелов: " Thermo   Fisher " -> "Thermo Fisher"
+pub(crate) fn collapse_whitespace(raw: &str) -> String {
+    raw.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Ключ карты нормализации: без учёта регистра и лишних пробелов
+pub fn variant_key(raw: &str) -> String {
+    collapse_whitespace(raw).to_lowercase()
+}
+
+/// Карта написаний производителя, загруженная для серии записей
+pub struct ManufacturerNormalizer {
+    canonical_by_variant: HashMap<String, String>,
+}
+
+impl ManufacturerNormalizer {
+    pub async fn load(pool: &SqlitePool) -> Result<Self, sqlx::Error> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT variant, canonical FROM manufacturer_normalizations")
+            .fetch_all(pool)
+            .await?;
+        Ok(Self { canonical_by_variant: rows.into_iter().collect() })
+    }
+
+    /// Каноническое имя или исходное значение без лишних пробелов
+    pub fn apply(&self, raw: &str) -> String {
+        self.canonical_by_variant
+            .get(&variant_key(raw))
+            .cloned()
+            .unwrap_or_else(|| collapse_whitespace(raw))
+    }
+
+    /// Нормализация необязательного поля; пустое значение -> None
+    pub fn apply_opt(&self, raw: Option<&str>) -> Option<String> {
+        raw.map(|m| self.apply(m)).filter(|m| !m.is_empty())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct NormalizationGroup {
+    pub canonical: String,
+    pub variants: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ManufacturerNormalizationRequest {
+    #[validate(length(min = 1, max = 255, message = "Canonical name must be between 1 and 255 characters"))]
+    pub canonical: String,
+    /// Написания, которые заменяются на `canonical`; пустой список удаляет группу
+    #[serde(default)]
+    pub variants: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NormalizationResult {
+    #[serde(flatten)]
+    pub group: NormalizationGroup,
+    /// Сколько строк оборудования и каталога переписано на каноническое имя
+    pub equipment_rewritten: u64,
+    pub catalog_rewritten: u64,
+}
+
+pub(crate) async fn normalization_groups(pool: &SqlitePool) -> ApiResult<Vec<NormalizationGroup>> {
+    let rows: Vec<(String, String)> = sqlx::query_as(
+        "SELECT canonical, variant FROM manufacturer_normalizations ORDER BY canonical, variant"
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let mut groups: Vec<NormalizationGroup> = Vec::new();
+    for (canonical, variant) in rows {
+        match groups.last_mut() {
+            Some(group) if group.canonical == canonical => group.variants.push(variant),
+            _ => groups.push(NormalizationGroup { canonical, variants: vec![variant] }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Замена группы написаний для `canonical` и перезапись существующих строк.
+/// Группа, каноническое имя которой вошло в `variants`, сливается в новую.
+pub async fn set_manufacturer_normalization(
+    pool: &SqlitePool,
+    request: &ManufacturerNormalizationRequest,
+    user_id: &str,
+) -> ApiResult<NormalizationResult> {
+    request.validate()?;
+    let canonical = collapse_whitespace(&request.canonical);
+    if canonical.is_empty() {
+        return Err(ApiError::bad_request("Canonical name cannot be empty"));
+    }
+    let mut keys: BTreeSet<String> = BTreeSet::new();
+    for variant in &request.variants {
+        let key = variant_key(variant);
+        if key.is_empty() || key.chars().count() > 255 {
+            return Err(ApiError::bad_request("Each variant must be between 1 and 255 characters"));
+        }
+        keys.insert(key);
+    }
+    // Другой регистр самого канонического имени тоже приводится к нему
+    if !keys.is_empty() {
+        keys.insert(variant_key(&canonical));
+    }
+
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    // Группа заменяется целиком (в т.ч. записанная ранее в другом регистре)
+    let existing: Vec<(String, String)> = sqlx::query_as("SELECT variant, canonical FROM manufacturer_normalizations")
+        .fetch_all(&mut *tx)
+        .await?;
+    for (variant, old_canonical) in &existing {
+        let same_group = variant_key(old_canonical) == variant_key(&canonical);
+        let merged_group = keys.contains(&variant_key(old_canonical));
+        if same_group {
+            sqlx::query("DELETE FROM manufacturer_normalizations WHERE variant = ?")
+                .bind(variant)
+                .execute(&mut *tx)
+                .await?;
+        } else if merged_group {
+            sqlx::query("UPDATE manufacturer_normalizations SET canonical = ?, updated_by = ?, updated_at = ? WHERE variant = ?")
+                .bind(&canonical)
+                .bind(user_id)
+                .bind(now)
+                .bind(variant)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    for key in &keys {
+        sqlx::query(
+            r#"INSERT INTO manufacturer_normalizations (variant, canonical, updated_by, updated_at) VALUES (?, ?, ?, ?)
+               ON CONFLICT(variant) DO UPDATE SET canonical = excluded.canonical,
+                                                  updated_by = excluded.updated_by, updated_at = excluded.updated_at"#
+        )
+            .bind(key)
+            .bind(&canonical)
+            .bind(user_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // Перезапись существующих строк: все написания, которые теперь сводятся к canonical
+    let mut rewritten = [0u64; 2];
+    for (slot, table) in ["equipment", "equipment_catalog"].into_iter().enumerate() {
+        let spellings: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT manufacturer FROM {} WHERE manufacturer IS NOT NULL", table
+        ))
+            .fetch_all(&mut *tx)
+            .await?;
+        for spelling in spellings.into_iter().filter(|s| *s != canonical && keys.contains(&variant_key(s))) {
+            let result = sqlx::query(&format!(
+                "UPDATE {} SET manufacturer = ?, updated_at = ? WHERE manufacturer = ?", table
+            ))
+                .bind(&canonical)
+                .bind(now)
+                .bind(&spelling)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::Database(ref db) if db.is_unique_violation() => ApiError::bad_request(&format!(
+                        "Catalog already has the same model under both '{}' and '{}'; remove one of them first",
+                        spelling, canonical
+                    )),
+                    other => ApiError::from(other),
+                })?;
+            rewritten[slot] += result.rows_affected();
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(NormalizationResult {
+        group: NormalizationGroup { canonical, variants: keys.into_iter().collect() },
+        equipment_rewritten: rewritten[0],
+        catalog_rewritten: rewritten[1],
+    })
+}
+
+/// GET /admin/normalizations/manufacturer
+pub async fn get_manufacturer_normalizations(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let groups = normalization_groups(&app_state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(groups)))
+}
+
+/// PUT /admin/normalizations/manufacturer - `{ "canonical": "...", "variants": [...] }`
+pub async fn put_manufacturer_normalization(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<ManufacturerNormalizationRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = get_current_user(&http_request)?.sub;
+    let result = set_manufacturer_normalization(&app_state.db_pool, &body, &user_id).await?;
+
+    let mut cs = ChangeSet::new();
+    cs.add("variants", "", &result.group.variants.join(", "));
+    crate::audit::audit_with_changes(
+        &app_state.db_pool, &user_id, "update_normalization", "manufacturer", &result.group.canonical,
+        &format!(
+            "Manufacturer normalization '{}': {} variant(s), {} equipment row(s) rewritten",
+            result.group.canonical, result.group.variants.len(), result.equipment_rewritten
+        ),
+        &cs, &http_request,
+    ).await;
+
+    let message = format!("{} equipment record(s) rewritten", result.equipment_rewritten);
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(result, message)))
+}
+
+// ==================== SERIAL NUMBERS ====================
+
+const SERIAL_INDEX: &str = "idx_equipment_serial_unique";
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SerialCollision {
+    pub serial_number: String,
+    pub equipment: Vec<CollidingEquipment>,
+}
+
+#[derive(Debug, Serialize, PartialEq, sqlx::FromRow)]
+pub struct CollidingEquipment {
+    pub id: String,
+    pub name: String,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SerialNumberNormalizations {
+    pub index_created: bool,
+    /// Повторы, из-за которых индекс не создан: исправьте или очистите лишние серийные номера
+    pub collisions: Vec<SerialCollision>,
+}
+
+/// Оборудование с одинаковым серийным номером
+pub async fn find_serial_collisions(conn: &mut SqliteConnection) -> Result<Vec<SerialCollision>, sqlx::Error> {
+    let serials: Vec<String> = sqlx::query_scalar(
+        "SELECT serial_number FROM equipment WHERE serial_number IS NOT NULL \
+         GROUP BY serial_number HAVING COUNT(*) > 1 ORDER BY 1"
+    )
+        .fetch_all(&mut *conn)
+        .await?;
+    let mut collisions = Vec::with_capacity(serials.len());
+    for serial_number in serials {
+        let equipment = sqlx::query_as(
+            "SELECT id, name, status FROM equipment WHERE serial_number = ? ORDER BY created_at, id"
+        )
+            .bind(&serial_number)
+            .fetch_all(&mut *conn)
+            .await?;
+        collisions.push(SerialCollision { serial_number, equipment });
+    }
+    Ok(collisions)
+}
+
+pub async fn serial_index_exists(pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = ?)")
+        .bind(SERIAL_INDEX)
+        .fetch_one(pool)
+        .await
+}
+
+/// Миграция (вызывается из run_migrations): индекс создаётся, когда повторов нет,
+/// иначе повторы выводятся в лог
+pub async fn ensure_serial_index(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let collisions = find_serial_collisions(&mut conn).await?;
+    if collisions.is_empty() {
+        sqlx::query(&format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {SERIAL_INDEX} ON equipment(serial_number) WHERE serial_number IS NOT NULL"
+        ))
+            .execute(&mut *conn)
+            .await?;
+        return Ok(());
+    }
+    for collision in &collisions {
+        let ids: Vec<&str> = collision.equipment.iter().map(|e| e.id.as_str()).collect();
+        log::warn!("Equipment {} share the serial number '{}'", ids.join(", "), collision.serial_number);
+    }
+    log::warn!(
+        "{} duplicated serial number(s): {} not created, equipment import is disabled; \
+         resolve them via /admin/normalizations/equipment-serial",
+        collisions.len(), SERIAL_INDEX
+    );
+    Ok(())
+}
+
+/// GET /admin/normalizations/equipment-serial
+pub async fn get_serial_normalizations(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let index_created = serial_index_exists(&app_state.db_pool).await?;
+    let mut conn = app_state.db_pool.acquire().await?;
+    let collisions = find_serial_collisions(&mut conn).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(SerialNumberNormalizations { index_created, collisions })))
+}
+
+// ==================== AUTOCOMPLETE ====================
+
+#[derive(Debug, Deserialize)]
+pub struct AutocompleteQuery {
+    /// Подстрока без учёта регистра
+    pub search: Option<String>,
+    /// Для /equipment/models - только модели этого производителя (с учётом нормализации)
+    pub manufacturer: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl AutocompleteQuery {
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_SUGGESTIONS).clamp(1, MAX_SUGGESTIONS)
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        self.search
+            .as_deref()
+            .map(variant_key)
+            .filter(|s| !s.is_empty())
+            .is_none_or(|s| value.to_lowercase().contains(&s))
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ManufacturerSuggestion {
+    pub manufacturer: String,
+    pub equipment_count: i64,
+    pub catalog_models: i64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ModelSuggestion {
+    pub manufacturer: String,
+    pub model: String,
+    pub equipment_count: i64,
+    /// Запись каталога для предзаполнения формы
+    pub catalog_id: Option<String>,
+}
+
+/// Написание с наибольшим числом экземпляров (для значений без канонического имени)
+fn prefer_spelling(current: &mut (String, i64), spelling: &str, count: i64) {
+    if count > current.1 {
+        *current = (spelling.to_string(), count);
+    }
+}
+
+pub async fn manufacturer_suggestions(
+    pool: &SqlitePool,
+    query: &AutocompleteQuery,
+) -> ApiResult<Vec<ManufacturerSuggestion>> {
+    let normalizer = ManufacturerNormalizer::load(pool).await?;
+    let equipment: Vec<(String, i64)> = sqlx::query_as(
+        r#"SELECT manufacturer, COUNT(*) FROM equipment
+           WHERE manufacturer IS NOT NULL AND TRIM(manufacturer) != '' AND pending_deletion_id IS NULL
+           GROUP BY manufacturer"#
+    )
+        .fetch_all(pool)
+        .await?;
+    let catalog: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT manufacturer, COUNT(*) FROM equipment_catalog GROUP BY manufacturer"
+    )
+        .fetch_all(pool)
+        .await?;
+
+    // ключ -> (отображаемое написание, его вес), экземпляры, модели каталога
+    let mut merged: HashMap<String, ((String, i64), i64, i64)> = HashMap::new();
+    for (spelling, count, is_catalog) in equipment.into_iter().map(|(s, c)| (s, c, false))
+        .chain(catalog.into_iter().map(|(s, c)| (s, c, true)))
+    {
+        let normalized = normalizer.apply(&spelling);
+        let entry = merged.entry(variant_key(&normalized)).or_insert(((normalized.clone(), 0), 0, 0));
+        prefer_spelling(&mut entry.0, &normalized, if is_catalog { i64::MAX } else { count });
+        if is_catalog { entry.2 += count } else { entry.1 += count }
+    }
+
+    let mut suggestions: Vec<ManufacturerSuggestion> = merged
+        .into_values()
+        .map(|((manufacturer, _), equipment_count, catalog_models)| ManufacturerSuggestion {
+            manufacturer,
+            equipment_count,
+            catalog_models,
+        })
+        .filter(|s| query.matches(&s.manufacturer))
+        .collect();
+    suggestions.sort_by(|a, b| b.equipment_count.cmp(&a.equipment_count).then_with(|| a.manufacturer.cmp(&b.manufacturer)));
+    suggestions.truncate(query.limit());
+    Ok(suggestions)
+}
+
+pub async fn model_suggestions(
+    pool: &SqlitePool,
+    query: &AutocompleteQuery,
+) -> ApiResult<Vec<ModelSuggestion>> {
+    let normalizer = ManufacturerNormalizer::load(pool).await?;
+    let manufacturer_filter = query.manufacturer.as_deref()
+        .map(|m| variant_key(&normalizer.apply(m)))
+        .filter(|m| !m.is_empty());
+
+    let equipment: Vec<(Option<String>, String, i64)> = sqlx::query_as(
+        r#"SELECT manufacturer, model, COUNT(*) FROM equipment
+           WHERE model IS NOT NULL AND TRIM(model) != '' AND pending_deletion_id IS NULL
+           GROUP BY manufacturer, model"#
+    )
+        .fetch_all(pool)
+        .await?;
+    let catalog: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT manufacturer, model, id FROM equipment_catalog"
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let mut merged: HashMap<(String, String), ModelSuggestion> = HashMap::new();
+    let mut weights: HashMap<(String, String), i64> = HashMap::new();
+    for (manufacturer, model, count) in equipment {
+        let manufacturer = normalizer.apply_opt(manufacturer.as_deref()).unwrap_or_default();
+        let model = collapse_whitespace(&model);
+        let key = (variant_key(&manufacturer), model.to_lowercase());
+        let weight = weights.entry(key.clone()).or_insert(0);
+        let entry = merged.entry(key).or_insert_with(|| ModelSuggestion {
+            manufacturer: manufacturer.clone(),
+            model: model.clone(),
+            equipment_count: 0,
+            catalog_id: None,
+        });
+        entry.equipment_count += count;
+        if count > *weight && entry.catalog_id.is_none() {
+            *weight = count;
+            entry.model = model;
+        }
+    }
+    for (manufacturer, model, id) in catalog {
+        let manufacturer = normalizer.apply(&manufacturer);
+        let key = (variant_key(&manufacturer), model.to_lowercase());
+        let entry = merged.entry(key).or_insert_with(|| ModelSuggestion {
+            manufacturer: manufacturer.clone(),
+            model: model.clone(),
+            equipment_count: 0,
+            catalog_id: None,
+        });
+        // Написание каталога - эталонное
+        entry.manufacturer = manufacturer;
+        entry.model = model;
+        entry.catalog_id = Some(id);
+    }
+
+    let mut suggestions: Vec<ModelSuggestion> = merged
+        .into_iter()
+        .filter(|((manufacturer, _), _)| manufacturer_filter.as_ref().is_none_or(|m| m == manufacturer))
+        .map(|(_, suggestion)| suggestion)
+        .filter(|s| query.matches(&s.model))
+        .collect();
+    suggestions.sort_by(|a, b| {
+        b.equipment_count.cmp(&a.equipment_count)
+            .then_with(|| a.manufacturer.cmp(&b.manufacturer))
+            .then_with(|| a.model.cmp(&b.model))
+    });
+    suggestions.truncate(query.limit());
+    Ok(suggestions)
+}
+
+/// GET /equipment/manufacturers?search=
+pub async fn get_manufacturers(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<AutocompleteQuery>,
+) -> ApiResult<HttpResponse> {
+    let suggestions = manufacturer_suggestions(&app_state.db_pool, &query).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(suggestions)))
+}
+
+/// GET /equipment/models?manufacturer=&search=
+pub async fn get_models(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<AutocompleteQuery>,
+) -> ApiResult<HttpResponse> {
+    let suggestions = model_suggestions(&app_state.db_pool, &query).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(suggestions)))
+}
+
+// ==================== CATALOG ====================
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CatalogEntry {
+    pub id: String,
+    pub manufacturer: String,
+    pub model: String,
+    pub type_: String,
+    /// Ожидаемый интервал калибровки/обслуживания, дней
+    pub maintenance_interval_days: Option<i64>,
+    /// Руководство - вложение оборудования (equipment_files), прикладываемое к новым экземплярам
+    pub manual_file_id: Option<String>,
+    pub description: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CatalogEntryRequest {
+    #[validate(length(min = 1, max = 255, message = "Manufacturer must be between 1 and 255 characters"))]
+    pub manufacturer: String,
+
+    #[validate(length(min = 1, max = 255, message = "Model must be between 1 and 255 characters"))]
+    pub model: String,
+
+    #[serde(rename = "type_", alias = "equipment_type")]
+    pub type_: String,
+
+    #[validate(range(min = 1, max = 3650, message = "Maintenance interval must be between 1 and 3650 days"))]
+    pub maintenance_interval_days: Option<i64>,
+
+    pub manual_file_id: Option<String>,
+
+    #[validate(length(max = 1000, message = "Description cannot exceed 1000 characters"))]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CatalogQuery {
+    pub search: Option<String>,
+    pub manufacturer: Option<String>,
+}
+
+async fn fetch_catalog_entry(pool: &SqlitePool, id: &str) -> ApiResult<CatalogEntry> {
+    sqlx::query_as("SELECT * FROM equipment_catalog WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Catalog entry"))
+}
+
+/// Проверка и нормализация полей записи каталога
+async fn prepare_catalog_entry(pool: &SqlitePool, request: &CatalogEntryRequest) -> ApiResult<(String, String, Option<String>)> {
+    request.validate()?;
+    if EquipmentType::from_str(&request.type_).is_err() {
+        return Err(ApiError::bad_request(&format!("Invalid type: {}", request.type_)));
+    }
+    let manufacturer = ManufacturerNormalizer::load(pool).await?.apply(&request.manufacturer);
+    let model = collapse_whitespace(&request.model);
+    if manufacturer.is_empty() || model.is_empty() {
+        return Err(ApiError::bad_request("Manufacturer and model cannot be empty"));
+    }
+
+    let manual_file_id = request.manual_file_id.as_deref().map(str::trim).filter(|id| !id.is_empty());
+    if let Some(file_id) = manual_file_id {
+        let exists: Option<String> = sqlx::query_scalar("SELECT id FROM equipment_files WHERE id = ?")
+            .bind(file_id)
+            .fetch_optional(pool)
+            .await?;
+        if exists.is_none() {
+            return Err(ApiError::file_not_found(file_id));
+        }
+    }
+    Ok((manufacturer, model, manual_file_id.map(str::to_string)))
+}
+
+fn duplicate_model(e: sqlx::Error) -> ApiError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            ApiError::bad_request("Catalog already contains this manufacturer and model")
+        }
+        other => ApiError::from(other),
+    }
+}
+
+pub async fn list_catalog(pool: &SqlitePool, query: &CatalogQuery) -> ApiResult<Vec<CatalogEntry>> {
+    let entries: Vec<CatalogEntry> = sqlx::query_as("SELECT * FROM equipment_catalog ORDER BY manufacturer, model")
+        .fetch_all(pool)
+        .await?;
+    let normalizer = ManufacturerNormalizer::load(pool).await?;
+    let manufacturer = query.manufacturer.as_deref().map(|m| variant_key(&normalizer.apply(m)));
+    let search = query.search.as_deref().map(variant_key).filter(|s| !s.is_empty());
+
+    Ok(entries
+        .into_iter()
+        .filter(|e| manufacturer.as_ref().is_none_or(|m| *m == variant_key(&e.manufacturer)))
+        .filter(|e| search.as_ref().is_none_or(|s| {
+            format!("{} {}", e.manufacturer, e.model).to_lowercase().contains(s)
+        }))
+        .collect())
+}
+
+/// GET /equipment/catalog?search=&manufacturer=
+pub async fn get_catalog(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<CatalogQuery>,
+) -> ApiResult<HttpResponse> {
+    let entries = list_catalog(&app_state.db_pool, &query).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(entries)))
+}
+
+/// GET /equipment/catalog/{id}
+pub async fn get_catalog_entry(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let entry = fetch_catalog_entry(&app_state.db_pool, &path).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(entry)))
+}
+
+/// POST /equipment/catalog
+pub async fn create_catalog_entry(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CatalogEntryRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = get_current_user(&http_request)?.sub;
+    let pool = &app_state.db_pool;
+    let (manufacturer, model, manual_file_id) = prepare_catalog_entry(pool, &body).await?;
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO equipment_catalog
+           (id, manufacturer, model, type_, maintenance_interval_days, manual_file_id, description,
+            created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(&manufacturer)
+        .bind(&model)
+        .bind(&body.type_)
+        .bind(body.maintenance_interval_days)
+        .bind(&manual_file_id)
+        .bind(&body.description)
+        .bind(&user_id)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(duplicate_model)?;
+
+    crate::audit::audit(
+        pool, &user_id, "create", "equipment_catalog", &id,
+        &format!("Created catalog model: {} {}", manufacturer, model), &http_request,
+    ).await;
+
+    let entry = fetch_catalog_entry(pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(entry)))
+}
+
+/// PUT /equipment/catalog/{id} - полная замена полей записи
+pub async fn update_catalog_entry(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<CatalogEntryRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = get_current_user(&http_request)?.sub;
+    let pool = &app_state.db_pool;
+    let id = path.into_inner();
+    let existing = fetch_catalog_entry(pool, &id).await?;
+    let (manufacturer, model, manual_file_id) = prepare_catalog_entry(pool, &body).await?;
+
+    sqlx::query(
+        r#"UPDATE equipment_catalog SET manufacturer = ?, model = ?, type_ = ?, maintenance_interval_days = ?,
+           manual_file_id = ?, description = ?, updated_at = ? WHERE id = ?"#
+    )
+        .bind(&manufacturer)
+        .bind(&model)
+        .bind(&body.type_)
+        .bind(body.maintenance_interval_days)
+        .bind(&manual_file_id)
+        .bind(&body.description)
+        .bind(Utc::now())
+        .bind(&id)
+        .execute(pool)
+        .await
+        .map_err(duplicate_model)?;
+
+    let mut cs = ChangeSet::new();
+    cs.add("manufacturer", &existing.manufacturer, &manufacturer);
+    cs.add("model", &existing.model, &model);
+    cs.add("type", &existing.type_, &body.type_);
+    cs.add_opt("manual_file_id", &existing.manual_file_id, &manual_file_id);
+    crate::audit::audit_with_changes(
+        pool, &user_id, "update", "equipment_catalog", &id,
+        &format!("Updated catalog model: {} {}", manufacturer, model), &cs, &http_request,
+    ).await;
+
+    let entry = fetch_catalog_entry(pool, &id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(entry)))
+}
+
+/// DELETE /equipment/catalog/{id} - созданное по записи оборудование не затрагивается
+pub async fn delete_catalog_entry(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = get_current_user(&http_request)?.sub;
+    let pool = &app_state.db_pool;
+    let id = path.into_inner();
+    let existing = fetch_catalog_entry(pool, &id).await?;
+
+    sqlx::query("DELETE FROM equipment_catalog WHERE id = ?")
+        .bind(&id)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(
+        pool, &user_id, "delete", "equipment_catalog", &id,
+        &format!("Deleted catalog model: {} {}", existing.manufacturer, existing.model), &http_request,
+    ).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "message": "Catalog entry deleted successfully"
+    }))))
+}
+
+// ==================== PREFILL ====================
+
+/// Заполнение незаданных полей запроса из записи каталога (`catalog_id`).
+/// Явно переданные значения имеют приоритет.
+pub async fn apply_catalog_defaults(
+    pool: &SqlitePool,
+    request: &mut CreateEquipmentRequest,
+) -> ApiResult<Option<CatalogEntry>> {
+    let Some(catalog_id) = request.catalog_id.as_deref().map(str::trim).filter(|id| !id.is_empty()) else {
+        return Ok(None);
+    };
+    let entry = fetch_catalog_entry(pool, catalog_id).await?;
+
+    if request.type_.trim().is_empty() {
+        request.type_ = entry.type_.clone();
+    }
+    if request.manufacturer.as_deref().is_none_or(|m| m.trim().is_empty()) {
+        request.manufacturer = Some(entry.manufacturer.clone());
+    }
+    if request.model.as_deref().is_none_or(|m| m.trim().is_empty()) {
+        request.model = Some(entry.model.clone());
+    }
+    if request.description.is_none() {
+        request.description = entry.description.clone();
+    }
+    Ok(Some(entry))
+}
+
+/// Руководство из каталога прикладывается к новому экземпляру: новая строка equipment_files
+/// ссылается на тот же blob. Руководство без хэша (файл потерян до дедупликации) пропускается.
+pub async fn attach_catalog_manual(
+    pool: &SqlitePool,
+    entry: &CatalogEntry,
+    equipment_id: &str,
+    user_id: &str,
+) -> ApiResult<Option<String>> {
+    let Some(ref manual_file_id) = entry.manual_file_id else {
+        return Ok(None);
+    };
+    let source: Option<(Option<String>, String, i64)> = sqlx::query_as(
+        "SELECT content_hash, file_path, file_size FROM equipment_files WHERE id = ?"
+    )
+        .bind(manual_file_id)
+        .fetch_optional(pool)
+        .await?;
+    let Some((Some(hash), file_path, file_size)) = source else {
+        log::warn!("Catalog {} manual {} is missing or unhashed; not attached to {}", entry.id, manual_file_id, equipment_id);
+        return Ok(None);
+    };
+
+    let file_id = Uuid::new_v4().to_string();
+    let mut tx = pool.begin().await?;
+    crate::file_blobs::acquire(&mut tx, &hash, &file_path, file_size).await?;
+    sqlx::query(
+        r#"INSERT INTO equipment_files
+           (id, equipment_id, part_id, file_type, original_filename, stored_filename, file_path,
+            file_size, mime_type, description, uploaded_by, created_at, content_hash)
+           SELECT ?, ?, NULL, file_type, original_filename, stored_filename, file_path,
+                  file_size, mime_type, description, ?, ?, content_hash
+           FROM equipment_files WHERE id = ?"#
+    )
+        .bind(&file_id)
+        .bind(equipment_id)
+        .bind(user_id)
+        .bind(Utc::now())
+        .bind(manual_file_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(Some(file_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+             VALUES ('admin', 'admin', 'admin@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, manufacturer, model, created_at, updated_at) VALUES
+             ('e1', 'HPLC 1', 'instrument', 1, 'available', 'Thermo', 'Vanquish', datetime('now'), datetime('now')),
+             ('e2', 'HPLC 2', 'instrument', 1, 'available', 'ThermoFisher', 'Vanquish', datetime('now'), datetime('now')),
+             ('e3', 'HPLC 3', 'instrument', 1, 'available', 'Thermo Fisher Scientific', 'vanquish', datetime('now'), datetime('now')),
+             ('e4', 'Balance', 'instrument', 1, 'available', ' Mettler  Toledo', 'XPR', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        pool
+    }
+
+    fn query(search: Option<&str>, manufacturer: Option<&str>) -> AutocompleteQuery {
+        AutocompleteQuery {
+            search: search.map(str::to_string),
+            manufacturer: manufacturer.map(str::to_string),
+            limit: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_normalization_rewrites_rows_and_merges_suggestions() {
+        let pool = setup().await;
+        let before = manufacturer_suggestions(&pool, &query(Some("thermo"), None)).await.unwrap();
+        assert_eq!(before.len(), 3);
+
+        let result = set_manufacturer_normalization(&pool, &ManufacturerNormalizationRequest {
+            canonical: "Thermo Fisher Scientific".to_string(),
+            variants: vec!["Thermo".to_string(), " thermofisher ".to_string()],
+        }, "admin").await.unwrap();
+        assert_eq!(result.equipment_rewritten, 2);
+        assert_eq!(result.group.variants, vec!["thermo", "thermo fisher scientific", "thermofisher"]);
+
+        let stored: Vec<String> = sqlx::query_scalar("SELECT DISTINCT manufacturer FROM equipment WHERE id IN ('e1', 'e2', 'e3')")
+            .fetch_all(&pool).await.unwrap();
+        assert_eq!(stored, vec!["Thermo Fisher Scientific"]);
+
+        let manufacturers = manufacturer_suggestions(&pool, &query(None, None)).await.unwrap();
+        assert_eq!(manufacturers, vec![
+            ManufacturerSuggestion { manufacturer: "Thermo Fisher Scientific".into(), equipment_count: 3, catalog_models: 0 },
+            ManufacturerSuggestion { manufacturer: "Mettler Toledo".into(), equipment_count: 1, catalog_models: 0 },
+        ]);
+
+        // Модели одного производителя в разных регистрах сводятся в одну подсказку
+        let models = model_suggestions(&pool, &query(None, Some("THERMO"))).await.unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!((models[0].model.as_str(), models[0].equipment_count), ("Vanquish", 3));
+
+        // Пустой список вариантов удаляет группу
+        set_manufacturer_normalization(&pool, &ManufacturerNormalizationRequest {
+            canonical: "thermo fisher scientific".to_string(),
+            variants: vec![],
+        }, "admin").await.unwrap();
+        assert!(normalization_groups(&pool).await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_catalog_prefills_request_and_shares_manual_blob() {
+        let pool = setup().await;
+        let hash = "a".repeat(64);
+        sqlx::query(
+            "INSERT INTO file_blobs (sha256, file_path, file_size, ref_count, created_at)
+             VALUES (?, '/files/manual.pdf', 2048, 1, datetime('now'))"
+        )
+            .bind(&hash)
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO equipment_files (id, equipment_id, file_type, original_filename, stored_filename,
+                                          file_path, file_size, mime_type, created_at, content_hash)
+             VALUES ('m1', 'e1', 'manual', 'Vanquish manual.pdf', 'manual.pdf', '/files/manual.pdf', 2048,
+                     'application/pdf', datetime('now'), ?)"
+        )
+            .bind(&hash)
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO equipment_catalog (id, manufacturer, model, type_, maintenance_interval_days, manual_file_id,
+                                            created_at, updated_at)
+             VALUES ('c1', 'Thermo Fisher Scientific', 'Vanquish Core', 'instrument', 180, 'm1', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+
+        let mut request: CreateEquipmentRequest = serde_json::from_value(serde_json::json!({
+            "name": "HPLC 4", "quantity": 1, "catalog_id": "c1", "model": "Vanquish Core+"
+        })).unwrap();
+        let entry = apply_catalog_defaults(&pool, &mut request).await.unwrap().unwrap();
+        assert_eq!(request.type_, "instrument");
+        assert_eq!(request.manufacturer.as_deref(), Some("Thermo Fisher Scientific"));
+        // Явно заданное значение не перезаписывается
+        assert_eq!(request.model.as_deref(), Some("Vanquish Core+"));
+        assert_eq!(entry.maintenance_interval_days, Some(180));
+
+        let file_id = attach_catalog_manual(&pool, &entry, "e2", "admin").await.unwrap().unwrap();
+        let (path, refs): (String, i64) = sqlx::query_as(
+            "SELECT f.file_path, b.ref_count FROM equipment_files f JOIN file_blobs b ON b.sha256 = f.content_hash WHERE f.id = ?"
+        )
+            .bind(&file_id)
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!((path.as_str(), refs), ("/files/manual.pdf", 2));
+
+        let models = model_suggestions(&pool, &query(Some("core"), None)).await.unwrap();
+        assert_eq!(models[0].catalog_id.as_deref(), Some("c1"));
+
+        request.catalog_id = Some("missing".to_string());
+        assert!(matches!(apply_catalog_defaults(&pool, &mut request).await, Err(ApiError::NotFound(_))));
+    }
+    #[actix_web::test]
+    async fn test_serial_index_waits_for_duplicates_to_be_resolved() {
+        let pool = setup().await;
+        assert!(serial_index_exists(&pool).await.unwrap());
+
+        // База, где индекс не мог появиться
+        sqlx::query("DROP INDEX idx_equipment_serial_unique").execute(&pool).await.unwrap();
+        sqlx::query("UPDATE equipment SET serial_number = 'SN-1' WHERE id IN ('e1', 'e3')").execute(&pool).await.unwrap();
+        sqlx::query("UPDATE equipment SET serial_number = 'SN-2' WHERE id = 'e2'").execute(&pool).await.unwrap();
+
+        crate::db::run_migrations(&pool).await.unwrap();
+        assert!(!serial_index_exists(&pool).await.unwrap());
+        let collisions = find_serial_collisions(&mut pool.acquire().await.unwrap()).await.unwrap();
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].serial_number, "SN-1");
+        let ids: Vec<&str> = collisions[0].equipment.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["e1", "e3"]);
+
+        sqlx::query("UPDATE equipment SET serial_number = 'SN-3' WHERE id = 'e3'").execute(&pool).await.unwrap();
+        ensure_serial_index(&pool).await.unwrap();
+        assert!(serial_index_exists(&pool).await.unwrap());
+        assert!(find_serial_collisions(&mut pool.acquire().await.unwrap()).await.unwrap().is_empty());
+    }
+}
@@ -14,25 +14,41 @@ use sqlx::SqlitePool;
 use std::sync::Arc;
 use std::io::Write;
 use std::str::FromStr;
-use chrono::Utc;
+use strum::VariantNames;
+use sha2::{Digest, Sha256};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::AppState;
+use crate::api_version::{ApiVersion, Representation};
 use crate::models::{
     Equipment, CreateEquipmentRequest, UpdateEquipmentRequest,
-    EquipmentPart, CreateEquipmentPartRequest, UpdateEquipmentPartRequest,
-    EquipmentMaintenance, EquipmentMaintenanceWithEquipment,
+    EquipmentPart, CreateEquipmentPartRequest, UpdateEquipmentPartRequest, PartBatchLink,
+    EquipmentMaintenance, ConsumedPartResult, MaintenanceCompletionResponse, Batch, EquipmentMaintenanceWithEquipment,
     CreateMaintenanceRequest, UpdateMaintenanceRequest, CompleteMaintenanceRequest,
-    EquipmentFile, UploadFileRequest, EquipmentDetailResponse
+    UpcomingMaintenanceQuery, UpcomingMaintenance, OverdueMaintenanceQuery, OverdueMaintenance, EquipmentFleetSummary, BrokenEquipment,
+    EquipmentFile, UploadFileRequest, EquipmentDetailResponse,
+    EquipmentComponent, AssemblyMaintenanceSummary, LEGACY_TYPE_FIELD, parse_role_list,
+    PartReorderQuery, PartReorderSuggestion, PartReorderUsage,
 };
 use crate::error::{ApiError, ApiResult};
-use crate::handlers::{ApiResponse, PaginatedResponse};
+use crate::validator::{CustomValidate, ValidationResult};
+use crate::events::BusinessEvent;
+use crate::outbox;
+use crate::equipment_catalog::{self, ManufacturerNormalizer};
+use crate::file_blobs;
+use crate::report_handlers::escape_csv_field;
+use crate::favorites_handlers::{favorites_join, FavoriteEntity, FAVORITES_FIRST_ORDER, FAVORITE_FLAG_COLUMN};
+use crate::handlers::{ApiResponse, NestedList, PaginatedResponse, MAX_NESTED_LIST_ROWS};
+use crate::pagination::{PageGroup, PageLimits};
 use crate::query_builders::{
-    SafeQueryBuilder, CountQueryBuilder, FieldWhitelist,
-    EquipmentType, MaintenanceType, MaintenanceStatus,
+    SafeQueryBuilder, CountQueryBuilder, FieldWhitelist, FtsQueryBuilder, SqlParam,
+    EquipmentStatus, EquipmentType, MaintenanceType, MaintenanceStatus,
     MaintenanceValidator, generate_unique_filename, validate_file_size, validate_mime_type,
+    content_disposition, sanitize_original_filename,
 };
+use crate::query_builders::sql::UpdateBuilder;
 
 // ==================== КОНСТАНТЫ ====================
 
@@ -47,15 +63,59 @@ pub struct EquipmentPaginationQuery {
     pub status: Option<String>,
     #[serde(rename = "type")]
     pub type_: Option<String>,
+    /// Устаревшее написание фильтра `type`
+    #[serde(rename = "type_")]
+    pub legacy_type: Option<String>,
     pub location: Option<String>,
+    /// Только оборудование команды
+    pub team_id: Option<String>,
+    /// Созданное текущим пользователем или его командами
+    pub mine: Option<bool>,
     pub sort_by: Option<String>,
     pub sort_order: Option<String>,
+    /// `?fields=id,name,status` - вернуть только перечисленные поля
+    pub fields: Option<String>,
+    /// Закреплённое пользователем оборудование первым (+ is_favorite в строках)
+    pub favorites_first: Option<bool>,
+    /// Неизвестное поле сортировки - 400 вместо сортировки по умолчанию
+    pub strict: Option<bool>,
 }
 
 impl EquipmentPaginationQuery {
-    pub fn normalize(&self) -> (i64, i64, i64) {
+    /// Устаревшее `type_` в фильтре, сортировке или `?fields=`
+    fn uses_legacy_type(&self) -> bool {
+        self.legacy_type.is_some()
+            || self.sort_by.as_deref() == Some(LEGACY_TYPE_FIELD)
+            || self.fields.as_deref().is_some_and(|f| f.split(',').any(|field| field.trim() == LEGACY_TYPE_FIELD))
+    }
+}
+
+/// Ответ с заголовком о устаревшем `type_`, если оно было в запросе
+fn with_type_deprecation(mut response: HttpResponse, legacy_used: bool) -> HttpResponse {
+    if legacy_used {
+        crate::api_version::mark_deprecated_fields(&mut response, &[(LEGACY_TYPE_FIELD, "type")]);
+    }
+    response
+}
+
+/// Строка списка оборудования; `is_favorite` - только с `?favorites_first=true`
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct EquipmentListItem {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub equipment: Equipment,
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_favorite: Option<bool>,
+}
+
+/// Колонки equipment типа DateTime (для ответов с `?fields=`)
+const EQUIPMENT_DATETIME_COLUMNS: &[&str] = &["created_at", "updated_at"];
+
+impl EquipmentPaginationQuery {
+    pub fn normalize(&self, limits: PageLimits) -> (i64, i64, i64) {
         let page = self.page.unwrap_or(1).max(1);
-        let per_page = self.per_page.unwrap_or(20).clamp(1, 100);
+        let per_page = limits.per_page(self.per_page);
         let offset = (page - 1) * per_page;
         (page, per_page, offset)
     }
@@ -68,10 +128,31 @@ pub struct SearchQuery {
     pub limit: Option<i64>,
 }
 
+/// `?recursive=true` - все уровни сборки, иначе только прямые компоненты
+#[derive(Debug, serde::Deserialize)]
+pub struct ComponentsQuery {
+    pub recursive: Option<bool>,
+}
+
+/// `?cascade=true` - распространить смену статуса на все компоненты сборки
+#[derive(Debug, serde::Deserialize)]
+pub struct CascadeQuery {
+    pub cascade: Option<bool>,
+}
+
 // ==================== КОНСТАНТЫ (продолжение) ====================
 
-/// Максимальный размер файла (10 МБ)
-const MAX_FILE_SIZE: usize = 10 * 1024 * 1024;
+/// Максимальная глубина вложенности сборок (защита рекурсивных запросов)
+const MAX_ASSEMBLY_DEPTH: i64 = 10;
+
+/// Горизонт предстоящего обслуживания по умолчанию (дней)
+const DEFAULT_UPCOMING_MAINTENANCE_DAYS: i32 = 30;
+
+/// Максимальный размер файла - настройка max_upload_size_mb (по умолчанию 10 МБ)
+fn max_file_size() -> usize {
+    let mb = crate::settings::settings().get_i64(crate::settings::MAX_UPLOAD_SIZE_MB).max(1) as usize;
+    mb * 1024 * 1024
+}
 
 /// Разрешенные MIME типы для изображений
 const ALLOWED_IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
@@ -85,7 +166,7 @@ const ALLOWED_DOC_TYPES: &[&str] = &[
 ];
 
 /// Возвращает путь к директории файлов оборудования (кроссплатформенно)
-fn get_equipment_files_dir() -> std::path::PathBuf {
+pub(crate) fn get_equipment_files_dir() -> std::path::PathBuf {
     std::env::var("EQUIPMENT_FILES_DIR")
         .map(std::path::PathBuf::from)
         .unwrap_or_else(|_| {
@@ -101,86 +182,162 @@ fn get_equipment_files_dir() -> std::path::PathBuf {
 pub async fn get_equipment(
     app_state: web::Data<Arc<AppState>>,
     query: web::Query<EquipmentPaginationQuery>,
+    user_id: String,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
-    let (page, per_page, offset) = query.normalize();
+    let limits = app_state.page_limits(PageGroup::Equipment);
+    let capped = limits.is_capped(query.per_page);
+    let (page, per_page, offset) = query.normalize(limits);
     let whitelist = FieldWhitelist::for_equipment();
+    let fields = crate::handlers::parse_fields_param(query.fields.as_deref(), &whitelist)?;
+
+    let strict = crate::handlers::strict_fields(query.strict);
 
     // Подсчет общего количества
     let mut count_builder = CountQueryBuilder::new("equipment")
-        .map_err(|e| ApiError::InternalServerError(e))?;
-    apply_equipment_filters(&mut count_builder, &query, &whitelist)?;
-
-    let (count_sql, count_params) = count_builder.build();
-    let mut count_query = sqlx::query_scalar::<_, i64>(&count_sql);
-    for param in &count_params {
-        count_query = count_query.bind(param);
-    }
-    let total: i64 = count_query.fetch_one(&app_state.db_pool).await?;
+        .map_err(ApiError::InternalServerError)?
+        .with_whitelist(&whitelist)
+        .strict(strict);
+    apply_equipment_filters(&mut count_builder, &query, &whitelist, &user_id)?;
 
     // Выборка данных
-    let base_sql = "SELECT * FROM equipment";
+    let favorites_first = query.favorites_first.unwrap_or(false);
+    let base_sql = if favorites_first { "SELECT equipment.* FROM equipment" } else { "SELECT * FROM equipment" };
     let mut select_builder = SafeQueryBuilder::new(base_sql)
         .map_err(|e| ApiError::InternalServerError(e))?
-        .with_whitelist(&whitelist);
+        .with_whitelist(&whitelist)
+        .strict(strict);
 
-    apply_equipment_filters_safe(&mut select_builder, &query)?;
+    apply_equipment_filters_safe(&mut select_builder, &query, &user_id)?;
+
+    // Избранное: LEFT JOIN и сортировка закреплённого в начало
+    if favorites_first {
+        let (join, params) = favorites_join(FavoriteEntity::Equipment, "equipment", &user_id);
+        select_builder.left_join(&join, params.into_iter().map(SqlParam::from).collect());
+        select_builder.select_extra(FAVORITE_FLAG_COLUMN);
+        select_builder.order_first_by(FAVORITES_FIRST_ORDER);
+    }
 
     // ИСПРАВЛЕНО: Теперь используем параметры из запроса, а не хардкод
-    let sort_field = query.sort_by.as_deref().unwrap_or("created_at");
+    let sort_field = whitelist.column(query.sort_by.as_deref().unwrap_or("created_at"));
     let sort_order = query.sort_order.as_deref().unwrap_or("desc");
     select_builder.order_by(sort_field, sort_order);
+    let legacy_type = query.uses_legacy_type();
+
+    // В строгом режиме неизвестное поле сортировки - 400 до выполнения запросов
+    count_builder.check_rejected().map_err(ApiError::BadRequest)?;
+    select_builder.check_rejected().map_err(ApiError::BadRequest)?;
+
+    let (count_sql, count_params) = count_builder.build();
+    let mut count_query = sqlx::query_scalar::<_, i64>(&count_sql);
+    for param in &count_params {
+        count_query = count_query.bind(param);
+    }
+    let total: i64 = count_query.fetch_one(&app_state.db_pool).await?;
 
     // В вашем query_builders/mod.rs limit принимает i64, приведение к u32 не нужно
     select_builder.limit(per_page);
     select_builder.offset(offset);
+    let total_pages = (total + per_page - 1) / per_page;
+
+    if let Some(fields) = fields {
+        select_builder.select_columns(&fields).map_err(ApiError::BadRequest)?;
+        let (select_sql, select_params) = select_builder.build();
+        let mut data = crate::handlers::fetch_json_rows(
+            &app_state.db_pool, &select_sql, &select_params, EQUIPMENT_DATETIME_COLUMNS,
+        ).await?;
+        for row in &mut data {
+            if let Some(flag) = row.get_mut("is_favorite") {
+                *flag = serde_json::Value::Bool(flag.as_i64().unwrap_or(0) != 0);
+            }
+            api_version.reshape(Representation::Equipment, row);
+        }
+        return Ok(with_type_deprecation(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
+            data,
+            total,
+            page,
+            per_page,
+            total_pages,
+            limits,
+            capped,
+        })), legacy_type));
+    }
 
     let (select_sql, select_params) = select_builder.build();
-    let mut select_query = sqlx::query_as::<_, Equipment>(&select_sql);
+    let mut select_query = sqlx::query_as::<_, EquipmentListItem>(&select_sql);
     for param in &select_params {
         select_query = select_query.bind(param);
     }
-    let equipment = select_query.fetch_all(&app_state.db_pool).await?;
-
-    let total_pages = (total + per_page - 1) / per_page;
+    let mut equipment = select_query.fetch_all(&app_state.db_pool).await?;
+    crate::team_handlers::expand_team_names(&app_state.db_pool, &mut equipment).await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
-        data: equipment,
+    Ok(with_type_deprecation(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
+        data: api_version.render_all(Representation::Equipment, &equipment)?,
         total,
         page,
         per_page,
         total_pages,
-    })))
+        limits,
+        capped,
+    })), legacy_type))
 }
 
 /// Получение оборудования по ID с деталями (части, обслуживание, файлы)
 pub async fn get_equipment_by_id(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
     let equipment_id = path.into_inner();
 
     let equipment: Option<Equipment> = sqlx::query_as(
-        "SELECT * FROM equipment WHERE id = ?"
+        "SELECT * FROM equipment WHERE id = ? AND pending_deletion_id IS NULL"
     )
         .bind(&equipment_id)
         .fetch_optional(&app_state.db_pool)
         .await?;
 
     match equipment {
-        Some(e) => {
+        Some(mut e) => {
+            crate::team_handlers::expand_team_names(&app_state.db_pool, std::slice::from_mut(&mut e)).await?;
             // Загружаем связанные данные
             let parts = get_equipment_parts_internal(&app_state.db_pool, &equipment_id).await?;
             let maintenance = get_recent_maintenance_internal(&app_state.db_pool, &equipment_id, 5).await?;
             let files = get_equipment_files_internal(&app_state.db_pool, &equipment_id).await?;
+            let components = get_direct_components_internal(&app_state.db_pool, &equipment_id).await?;
+            let active_maintenance = find_active_maintenance(&app_state.db_pool, &equipment_id).await?;
+            let links = crate::link_handlers::fetch_links(
+                &app_state.db_pool, crate::models::LinkEntityType::Equipment, &equipment_id,
+            ).await?;
+            let assembly_maintenance = if components.is_empty() {
+                None
+            } else {
+                Some(get_assembly_maintenance_internal(&app_state.db_pool, &equipment_id).await?)
+            };
 
+            let truncated = [("parts", parts.truncated), ("files", files.truncated)]
+                .into_iter()
+                .filter_map(|(name, cut)| cut.then_some(name))
+                .collect();
             let response = EquipmentDetailResponse {
                 equipment: e,
-                parts,
+                parts: parts.rows,
                 recent_maintenance: maintenance,
-                files,
+                files: files.rows,
+                truncated,
+                components,
+                assembly_maintenance,
+                active_maintenance,
+                links,
             };
 
-            Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+            let mut data = api_version.render(Representation::Equipment, &response)?;
+            if let Some(components) = data.get_mut("components").and_then(|c| c.as_array_mut()) {
+                for component in components.iter_mut().filter_map(|c| c.as_object_mut()) {
+                    api_version.reshape(Representation::Equipment, component);
+                }
+            }
+            Ok(HttpResponse::Ok().json(ApiResponse::success(data)))
         },
         None => Err(ApiError::not_found("Equipment")),
     }
@@ -191,19 +348,29 @@ pub async fn create_equipment(
     app_state: web::Data<Arc<AppState>>,
     equipment: web::Json<CreateEquipmentRequest>,
     _user_id: String,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
-    equipment.validate()?;
-    validate_equipment_data(&equipment)?;
+    let mut equipment = equipment.into_inner();
+    let legacy_type = equipment.take_legacy_type();
+    let catalog_entry = equipment_catalog::apply_catalog_defaults(&app_state.db_pool, &mut equipment).await?;
+    equipment.manufacturer = ManufacturerNormalizer::load(&app_state.db_pool).await?
+        .apply_opt(equipment.manufacturer.as_deref());
+    validate_equipment_request(&app_state.db_pool, &equipment, None).await?.ensure_valid()?;
+
+    let parent_id = equipment.parent_equipment_id.as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
 
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
 
+    let mut tx = app_state.db_pool.begin().await?;
     sqlx::query(
         r#"INSERT INTO equipment
            (id, name, type_, quantity, unit, status, location, description, 
-            serial_number, manufacturer, model, purchase_date, warranty_until,
-            created_by, updated_by, created_at, updated_at)
-           VALUES (?, ?, ?, ?, ?, 'available', ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+            serial_number, manufacturer, model, purchase_date, warranty_until, maintenance_interval_days,
+            parent_equipment_id, restricted_to_roles, team_id, created_by, updated_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, 'available', ?, ?, ?, ?, ?, ?, ?, COALESCE(?, 90), ?, ?, ?, ?, ?, ?, ?)"#
     )
         .bind(&id)
         .bind(&equipment.name)
@@ -217,33 +384,60 @@ pub async fn create_equipment(
         .bind(&equipment.model)
         .bind(&equipment.purchase_date)
         .bind(&equipment.warranty_until)
+        .bind(catalog_entry.as_ref().and_then(|entry| entry.maintenance_interval_days))
+        .bind(parent_id)
+        .bind(equipment.restricted_to_roles.as_deref().and_then(role_list_column))
+        .bind(crate::team_handlers::normalize_team_id(equipment.team_id.as_deref()))
         .bind(&_user_id)
         .bind(&_user_id)
         .bind(&now)
         .bind(&now)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
+    outbox::enqueue(
+        &mut *tx,
+        &BusinessEvent::EquipmentCreated { equipment_id: id.clone(), name: equipment.name.clone() },
+        Some(&_user_id),
+    ).await?;
+    tx.commit().await?;
+
+    if let Some(ref entry) = catalog_entry {
+        equipment_catalog::attach_catalog_manual(&app_state.db_pool, entry, &id, &_user_id).await?;
+    }
 
-    // Обновляем FTS индекс
-    update_equipment_fts(&app_state.db_pool, &id).await?;
-
-    let created: Equipment = sqlx::query_as("SELECT * FROM equipment WHERE id = ?")
+    let mut created: Equipment = sqlx::query_as("SELECT * FROM equipment WHERE id = ?")
         .bind(&id)
         .fetch_one(&app_state.db_pool)
         .await?;
+    crate::team_handlers::expand_team_names(&app_state.db_pool, std::slice::from_mut(&mut created)).await?;
 
-    Ok(HttpResponse::Created().json(ApiResponse::success(created)))
+    Ok(with_type_deprecation(HttpResponse::Created().json(ApiResponse::success(
+        api_version.render(Representation::Equipment, &created)?,
+    )), legacy_type))
 }
 
-/// Обновление оборудования
+/// Обновление оборудования.
+/// При `cascade = true` новый статус распространяется на все компоненты сборки.
 pub async fn update_equipment(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
     update: web::Json<UpdateEquipmentRequest>,
     user_id: String,
+    cascade: bool,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
     update.validate()?;
     let equipment_id = path.into_inner();
+    let mut update = update.into_inner();
+    let legacy_type = update.take_legacy_type();
+    if let Some(type_) = update.type_.as_deref() {
+        if EquipmentType::from_str(type_).is_err() {
+            return Err(ApiError::bad_request(&format!("Invalid equipment type: {}", type_)));
+        }
+    }
+    if let Some(Some(ref manufacturer)) = update.manufacturer {
+        update.manufacturer = Some(Some(ManufacturerNormalizer::load(&app_state.db_pool).await?.apply(manufacturer)));
+    }
 
     // Проверяем существование
     let existing: Option<Equipment> = sqlx::query_as(
@@ -257,61 +451,148 @@ pub async fn update_equipment(
         return Err(ApiError::not_found("Equipment"));
     }
 
-    // Строим динамический UPDATE
-    let mut updates = Vec::new();
-    let mut values: Vec<String> = Vec::new();
+    // Во время окна обслуживания оборудование нельзя перевести в работу
+    if update.status.as_deref() == Some("in_use") {
+        if let Some(ref equipment) = existing {
+            let role = user_role(&app_state.db_pool, &user_id).await?;
+            ensure_role_allowed(equipment, role.as_deref())?;
+        }
+        if let Some(window) = find_active_maintenance(&app_state.db_pool, &equipment_id).await? {
+            return Err(ApiError::bad_request(&format!(
+                "Equipment is blocked by {} maintenance until {}",
+                window.maintenance_type,
+                window.scheduled_end.map(|end| end.to_rfc3339()).unwrap_or_default()
+            )));
+        }
+    }
 
-    macro_rules! add_field {
-        ($field:ident, $name:expr) => {
-            if let Some(ref val) = update.$field {
-                updates.push(concat!($name, " = ?"));
-                values.push(val.clone());
+    let mut builder = UpdateBuilder::new(FieldWhitelist::for_equipment_update());
+    builder
+        .set_opt("name", &update.name)
+        .set_opt("type", &update.type_)
+        .patch_text("unit", &update.unit)
+        .patch_text("location", &update.location)
+        .patch_text("description", &update.description)
+        .set_opt("status", &update.status)
+        .patch_text("serial_number", &update.serial_number)
+        .patch_text("manufacturer", &update.manufacturer)
+        .patch_text("model", &update.model)
+        .patch_text("purchase_date", &update.purchase_date)
+        .patch_text("warranty_until", &update.warranty_until)
+        .set_opt("quantity", &update.quantity);
+
+    if let Some(ref parent) = update.parent_equipment_id {
+        match parent.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+            Some(parent) => {
+                validate_parent_equipment(&app_state.db_pool, Some(&equipment_id), parent).await?;
+                builder.set("parent_equipment_id", parent);
             }
-        };
+            None => {
+                builder.set_null("parent_equipment_id");
+            }
+        }
     }
 
-    add_field!(name, "name");
-    add_field!(unit, "unit");
-    add_field!(location, "location");
-    add_field!(description, "description");
-    add_field!(status, "status");
-    add_field!(serial_number, "serial_number");
-    add_field!(manufacturer, "manufacturer");
-    add_field!(model, "model");
+    if let Some(ref roles) = update.restricted_to_roles {
+        builder.set("restricted_to_roles", roles.as_deref().and_then(role_list_column));
+    }
 
-    if let Some(quantity) = update.quantity {
-        updates.push("quantity = ?");
-        values.push(quantity.to_string());
+    if let Some(ref team_id) = update.team_id {
+        crate::team_handlers::ensure_team_exists(&app_state.db_pool, crate::team_handlers::normalize_team_id(team_id.as_deref())).await?;
+        builder.patch_text("team_id", &update.team_id);
     }
 
-    if updates.is_empty() {
+    if builder.is_empty() {
         return Err(ApiError::bad_request("No fields to update"));
     }
 
-    updates.push("updated_by = ?");
-    updates.push("updated_at = ?");
-    values.push(user_id);
-    values.push(Utc::now().to_rfc3339());
+    let now = Utc::now().to_rfc3339();
+    builder
+        .set("updated_by", user_id.as_str())
+        .set("updated_at", now.as_str())
+        .where_eq("id", equipment_id.as_str());
+
+    let mut tx = app_state.db_pool.begin().await?;
+    builder.execute(&mut *tx).await?;
+
+    // Каскадная смена статуса на компоненты сборки
+    let mut cascaded = 0;
+    if let (true, Some(status)) = (cascade, update.status.as_ref()) {
+        let result = sqlx::query(
+            r#"WITH RECURSIVE tree(id, depth) AS (
+                   SELECT id, 1 FROM equipment WHERE parent_equipment_id = ?
+                   UNION
+                   SELECT e.id, t.depth + 1 FROM equipment e
+                   JOIN tree t ON e.parent_equipment_id = t.id
+                   WHERE t.depth < ?
+               )
+               UPDATE equipment SET status = ?, updated_by = ?, updated_at = ?
+               WHERE id IN (SELECT id FROM tree) AND id != ?"#
+        )
+            .bind(&equipment_id)
+            .bind(MAX_ASSEMBLY_DEPTH)
+            .bind(status)
+            .bind(&user_id)
+            .bind(&now)
+            .bind(&equipment_id)
+            .execute(&mut *tx)
+            .await?;
+        cascaded = result.rows_affected();
+    }
 
-    let sql = format!("UPDATE equipment SET {} WHERE id = ?", updates.join(", "));
+    tx.commit().await?;
 
-    let mut query = sqlx::query(&sql);
-    for value in &values {
-        query = query.bind(value);
+    let mut updated: Equipment = sqlx::query_as("SELECT * FROM equipment WHERE id = ?")
+        .bind(&equipment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    crate::team_handlers::expand_team_names(&app_state.db_pool, std::slice::from_mut(&mut updated)).await?;
+    let updated = api_version.render(Representation::Equipment, &updated)?;
+
+    if cascaded > 0 {
+        return Ok(with_type_deprecation(HttpResponse::Ok().json(ApiResponse::success_with_message(
+            updated,
+            format!("Status propagated to {} components", cascaded),
+        )), legacy_type));
     }
-    query = query.bind(&equipment_id);
 
-    query.execute(&app_state.db_pool).await?;
+    Ok(with_type_deprecation(HttpResponse::Ok().json(ApiResponse::success(updated)), legacy_type))
+}
 
-    // Обновляем FTS индекс
-    update_equipment_fts(&app_state.db_pool, &equipment_id).await?;
+/// Компоненты сборки: прямые или (с `recursive=true`) все уровни
+pub async fn get_equipment_components(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<ComponentsQuery>,
+) -> ApiResult<HttpResponse> {
+    let equipment_id = path.into_inner();
+    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
 
-    let updated: Equipment = sqlx::query_as("SELECT * FROM equipment WHERE id = ?")
+    let max_depth = if query.recursive.unwrap_or(false) { MAX_ASSEMBLY_DEPTH } else { 1 };
+
+    let components: Vec<EquipmentComponent> = sqlx::query_as(
+        r#"WITH RECURSIVE tree(id, depth) AS (
+               SELECT id, 1 FROM equipment WHERE parent_equipment_id = ?
+               UNION
+               SELECT e.id, t.depth + 1 FROM equipment e
+               JOIN tree t ON e.parent_equipment_id = t.id
+               WHERE t.depth < ?
+           )
+           SELECT e.*, MIN(t.depth) AS depth
+           FROM tree t JOIN equipment e ON e.id = t.id
+           WHERE e.id != ?
+           GROUP BY e.id
+           ORDER BY depth, e.name
+           LIMIT ?"#
+    )
         .bind(&equipment_id)
-        .fetch_one(&app_state.db_pool)
+        .bind(max_depth)
+        .bind(&equipment_id)
+        .bind(MAX_NESTED_LIST_ROWS)
+        .fetch_all(&app_state.db_pool)
         .await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(components)))
 }
 
 /// Удаление оборудования
@@ -321,55 +602,64 @@ pub async fn delete_equipment(
 ) -> ApiResult<HttpResponse> {
     let equipment_id = path.into_inner();
 
+    if !purge_equipment(&app_state.db_pool, &equipment_id).await? {
+        return Err(ApiError::not_found("Equipment"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        (),
+        "Equipment deleted successfully".to_string(),
+    )))
+}
+
+/// Окончательное удаление оборудования со связанными частями, обслуживанием и файлами
+/// (также завершает отложенное удаление); false - оборудования уже нет
+pub(crate) async fn purge_equipment(pool: &SqlitePool, equipment_id: &str) -> ApiResult<bool> {
+    // Компоненты сборки остаются, но отвязываются от удаляемого родителя
+    sqlx::query("UPDATE equipment SET parent_equipment_id = NULL WHERE parent_equipment_id = ?")
+        .bind(equipment_id)
+        .execute(pool)
+        .await?;
+
     // Удаляем связанные данные
     sqlx::query("DELETE FROM equipment_parts WHERE equipment_id = ?")
-        .bind(&equipment_id)
-        .execute(&app_state.db_pool)
+        .bind(equipment_id)
+        .execute(pool)
         .await?;
 
     sqlx::query("DELETE FROM equipment_maintenance WHERE equipment_id = ?")
-        .bind(&equipment_id)
-        .execute(&app_state.db_pool)
+        .bind(equipment_id)
+        .execute(pool)
         .await?;
 
-    // Удаляем файлы с диска
+    // Удаляем файлы; с диска - только те, на которые не ссылается другое оборудование
+    let mut tx = pool.begin().await?;
     let files: Vec<EquipmentFile> = sqlx::query_as(
         "SELECT * FROM equipment_files WHERE equipment_id = ?"
     )
-        .bind(&equipment_id)
-        .fetch_all(&app_state.db_pool)
+        .bind(equipment_id)
+        .fetch_all(&mut *tx)
         .await?;
 
-    for file in files {
-        let _ = std::fs::remove_file(&file.file_path);
-    }
-
     sqlx::query("DELETE FROM equipment_files WHERE equipment_id = ?")
-        .bind(&equipment_id)
-        .execute(&app_state.db_pool)
+        .bind(equipment_id)
+        .execute(&mut *tx)
         .await?;
 
-    // Удаляем из FTS
-    sqlx::query("DELETE FROM equipment_fts WHERE equipment_id = ?")
-        .bind(&equipment_id)
-        .execute(&app_state.db_pool)
-        .await
-        .ok(); // Игнорируем ошибку если FTS таблица не существует
+    let mut unlinked = Vec::new();
+    for file in &files {
+        unlinked.extend(file_blobs::release(&mut tx, file.content_hash.as_deref(), &file.file_path).await?);
+    }
+    tx.commit().await?;
+    file_blobs::remove_files(unlinked);
 
     // Удаляем само оборудование
     let result = sqlx::query("DELETE FROM equipment WHERE id = ?")
-        .bind(&equipment_id)
-        .execute(&app_state.db_pool)
+        .bind(equipment_id)
+        .execute(pool)
         .await?;
 
-    if result.rows_affected() == 0 {
-        return Err(ApiError::not_found("Equipment"));
-    }
-
-    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
-        (),
-        "Equipment deleted successfully".to_string(),
-    )))
+    Ok(result.rows_affected() > 0)
 }
 
 // ==================== ЗАПАСНЫЕ ЧАСТИ ====================
@@ -386,7 +676,7 @@ pub async fn get_equipment_parts(
 
     let parts = get_equipment_parts_internal(&app_state.db_pool, &equipment_id).await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(parts)))
+    Ok(parts.into_response())
 }
 
 /// Добавление части к оборудованию
@@ -401,6 +691,16 @@ pub async fn add_equipment_part(
 
     check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
 
+    let linked_batch_id = part.linked_batch_id.as_deref().filter(|id| !id.is_empty());
+    if let Some(batch_id) = linked_batch_id {
+        if part.quantity.is_some() {
+            return Err(ApiError::bad_request(
+                "quantity cannot be set for a part linked to a batch: it is read from the batch"
+            ));
+        }
+        ensure_linkable_batch(&app_state.db_pool, batch_id).await?;
+    }
+
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
     let status = part.status.as_deref().unwrap_or("good");  // FIXED: "good" matches DB default
@@ -418,15 +718,15 @@ pub async fn add_equipment_part(
         r#"INSERT INTO equipment_parts
            (id, equipment_id, name, part_number, manufacturer, quantity, 
             min_quantity, status, last_replaced, next_replacement, notes,
-            created_by, created_at, updated_at)
-           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+            created_by, created_at, updated_at, linked_batch_id)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
     )
         .bind(&id)
         .bind(&equipment_id)
         .bind(&part.name)
         .bind(&part.part_number)
         .bind(&part.manufacturer)
-        .bind(part.quantity.unwrap_or(1))
+        .bind(if linked_batch_id.is_some() { 0 } else { part.quantity.unwrap_or(1) })
         .bind(part.min_quantity.unwrap_or(0))
         .bind(status)
         .bind(&part.last_replaced)
@@ -435,15 +735,17 @@ pub async fn add_equipment_part(
         .bind(&user_id)
         .bind(&now)
         .bind(&now)
+        .bind(linked_batch_id)
         .execute(&app_state.db_pool)
         .await?;
 
-    let created: EquipmentPart = sqlx::query_as(
+    let mut created: EquipmentPart = sqlx::query_as(
         "SELECT * FROM equipment_parts WHERE id = ?"
     )
         .bind(&id)
         .fetch_one(&app_state.db_pool)
         .await?;
+    resolve_part_links(&app_state.db_pool, std::slice::from_mut(&mut created)).await?;
 
     Ok(HttpResponse::Created().json(ApiResponse::success(created)))
 }
@@ -458,7 +760,7 @@ pub async fn update_equipment_part(
     update.validate()?;
     let (equipment_id, part_id) = path.into_inner();
 
-    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+    check_nested_equipment(&app_state.db_pool, &equipment_id, "Equipment part").await?;
 
     // Проверяем существование части
     let existing: Option<EquipmentPart> = sqlx::query_as(
@@ -469,33 +771,40 @@ pub async fn update_equipment_part(
         .fetch_optional(&app_state.db_pool)
         .await?;
 
-    if existing.is_none() {
-        return Err(ApiError::not_found("Equipment part"));
+    let existing = existing.ok_or_else(|| ApiError::not_found("Equipment part"))?;
+
+    let mut builder = UpdateBuilder::new(FieldWhitelist::for_equipment_part_update());
+
+    // Связь с партией: null/"" - отвязать, id - связать, поле не передано - без изменений
+    let link = update.linked_batch_id.as_ref()
+        .map(|id| id.as_deref().map(str::trim).filter(|id| !id.is_empty()));
+    let stays_linked = match link {
+        Some(None) => {
+            if update.quantity.is_none() {
+                if let Some(ref batch_id) = existing.linked_batch_id {
+                    // Фиксируем последний остаток партии как собственный остаток запчасти
+                    let remaining = fetch_part_batch_link(&app_state.db_pool, batch_id).await?
+                        .map(|link| link.quantity.floor() as i32)
+                        .unwrap_or(0);
+                    builder.set("quantity", remaining);
+                }
+            }
+            builder.set_null("linked_batch_id");
+            false
+        }
+        Some(Some(batch_id)) => {
+            ensure_linkable_batch(&app_state.db_pool, batch_id).await?;
+            builder.set("linked_batch_id", batch_id);
+            true
+        }
+        None => existing.linked_batch_id.is_some(),
+    };
+    if stays_linked && update.quantity.is_some() {
+        return Err(ApiError::bad_request(
+            "quantity cannot be edited for a part linked to a batch: it is read from the batch"
+        ));
     }
 
-    let mut updates = Vec::new();
-    let mut values: Vec<String> = Vec::new();
-
-    if let Some(ref name) = update.name {
-        updates.push("name = ?");
-        values.push(name.clone());
-    }
-    if let Some(ref part_number) = update.part_number {
-        updates.push("part_number = ?");
-        values.push(part_number.clone());
-    }
-    if let Some(ref manufacturer) = update.manufacturer {
-        updates.push("manufacturer = ?");
-        values.push(manufacturer.clone());
-    }
-    if let Some(quantity) = update.quantity {
-        updates.push("quantity = ?");
-        values.push(quantity.to_string());
-    }
-    if let Some(min_quantity) = update.min_quantity {
-        updates.push("min_quantity = ?");
-        values.push(min_quantity.to_string());
-    }
     if let Some(ref status) = update.status {
         // Validate part status against DB constraint
         let valid_statuses = ["good", "needs_attention", "needs_replacement", "replaced", "missing"];
@@ -505,36 +814,35 @@ pub async fn update_equipment_part(
                 status
             )));
         }
-        updates.push("status = ?");
-        values.push(status.clone());
-    }
-    if let Some(ref notes) = update.notes {
-        updates.push("notes = ?");
-        values.push(notes.clone());
     }
 
-    if updates.is_empty() {
+    builder
+        .set_opt("name", &update.name)
+        .patch_text("part_number", &update.part_number)
+        .patch_text("manufacturer", &update.manufacturer)
+        .set_opt("quantity", &update.quantity)
+        .set_opt("min_quantity", &update.min_quantity)
+        .set_opt("status", &update.status)
+        .patch_text("last_replaced", &update.last_replaced)
+        .patch_text("next_replacement", &update.next_replacement)
+        .patch_text("notes", &update.notes);
+
+    if builder.is_empty() {
         return Err(ApiError::bad_request("No fields to update"));
     }
 
-    updates.push("updated_at = ?");
-    values.push(Utc::now().to_rfc3339());
-
-    let sql = format!("UPDATE equipment_parts SET {} WHERE id = ?", updates.join(", "));
-
-    let mut query = sqlx::query(&sql);
-    for value in &values {
-        query = query.bind(value);
-    }
-    query = query.bind(&part_id);
-    query.execute(&app_state.db_pool).await?;
+    builder
+        .set("updated_at", Utc::now())
+        .where_eq("id", part_id.as_str());
+    builder.execute(&app_state.db_pool).await?;
 
-    let updated: EquipmentPart = sqlx::query_as(
+    let mut updated: EquipmentPart = sqlx::query_as(
         "SELECT * FROM equipment_parts WHERE id = ?"
     )
         .bind(&part_id)
         .fetch_one(&app_state.db_pool)
         .await?;
+    resolve_part_links(&app_state.db_pool, std::slice::from_mut(&mut updated)).await?;
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
 }
@@ -546,7 +854,7 @@ pub async fn delete_equipment_part(
 ) -> ApiResult<HttpResponse> {
     let (equipment_id, part_id) = path.into_inner();
 
-    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+    check_nested_equipment(&app_state.db_pool, &equipment_id, "Equipment part").await?;
 
     let result = sqlx::query(
         "DELETE FROM equipment_parts WHERE id = ? AND equipment_id = ?"
@@ -580,13 +888,15 @@ pub async fn get_equipment_maintenance(
     let maintenance: Vec<EquipmentMaintenance> = sqlx::query_as(
         r#"SELECT * FROM equipment_maintenance 
            WHERE equipment_id = ? 
-           ORDER BY scheduled_date DESC"#
+           ORDER BY scheduled_date DESC
+           LIMIT ?"#
     )
         .bind(&equipment_id)
+        .bind(MAX_NESTED_LIST_ROWS + 1)
         .fetch_all(&app_state.db_pool)
         .await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(maintenance)))
+    Ok(NestedList::new(maintenance).into_response())
 }
 
 /// Создание записи об обслуживании
@@ -609,9 +919,15 @@ pub async fn create_maintenance(
         )));
     }
 
+    let (scheduled_date, window) = resolve_maintenance_window(
+        &maintenance.scheduled_date,
+        maintenance.scheduled_start,
+        maintenance.scheduled_end,
+    )?;
+
     // Валидация временных интервалов
     if let Some(ref end) = maintenance.completed_date {
-        if MaintenanceValidator::validate_time_range(&maintenance.scheduled_date, end).is_err() {
+        if MaintenanceValidator::validate_time_range(&scheduled_date, end).is_err() {
             return Err(ApiError::bad_request("Completed date cannot be before scheduled date"));
         }
     }
@@ -619,19 +935,31 @@ pub async fn create_maintenance(
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
     let status = maintenance.status.as_deref().unwrap_or("scheduled");
+    let reminder_lead_days = maintenance.reminder_lead_days
+        .unwrap_or_else(|| app_state.config.maintenance.reminder_lead_days_for(&maintenance.maintenance_type));
+    let sla_days = maintenance.sla_days
+        .unwrap_or_else(|| app_state.config.maintenance.sla_days_for(&maintenance.maintenance_type));
+    if let Some(ref assignee) = maintenance.assigned_to {
+        check_maintenance_assignee(&app_state.db_pool, assignee).await?;
+    }
 
     sqlx::query(
         r#"INSERT INTO equipment_maintenance
-           (id, equipment_id, maintenance_type, status, scheduled_date, completed_date,
-            performed_by, description, cost, parts_replaced, notes,
-            created_by, created_at, updated_at)
-           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+           (id, equipment_id, maintenance_type, status, scheduled_date, scheduled_start, scheduled_end,
+            reminder_lead_days, sla_days, assigned_to, completed_date, performed_by, description, cost,
+            parts_replaced, notes, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
     )
         .bind(&id)
         .bind(&equipment_id)
         .bind(&maintenance.maintenance_type)
         .bind(status)
-        .bind(&maintenance.scheduled_date)
+        .bind(&scheduled_date)
+        .bind(window.map(|(start, _)| start))
+        .bind(window.map(|(_, end)| end))
+        .bind(reminder_lead_days)
+        .bind(sla_days)
+        .bind(&maintenance.assigned_to)
         .bind(&maintenance.completed_date)
         .bind(&maintenance.performed_by)
         .bind(&maintenance.description)
@@ -664,75 +992,92 @@ pub async fn update_maintenance(
     update.validate()?;
     let (equipment_id, maintenance_id) = path.into_inner();
 
-    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+    check_nested_equipment(&app_state.db_pool, &equipment_id, "Maintenance record").await?;
 
-    let existing: Option<EquipmentMaintenance> = sqlx::query_as(
+    let existing: EquipmentMaintenance = sqlx::query_as(
         "SELECT * FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
     )
         .bind(&maintenance_id)
         .bind(&equipment_id)
         .fetch_optional(&app_state.db_pool)
-        .await?;
-
-    if existing.is_none() {
-        return Err(ApiError::not_found("Maintenance record"));
-    }
+        .await?
+        .ok_or_else(|| ApiError::not_found("Maintenance record"))?;
 
-    let mut updates = Vec::new();
-    let mut values: Vec<String> = Vec::new();
+    let mut builder = UpdateBuilder::new(FieldWhitelist::for_maintenance_update());
 
     if let Some(ref status) = update.status {
-        // FIXED: FromStr trait is now in scope
         if MaintenanceStatus::from_str(status).is_err() {
             return Err(ApiError::bad_request(&format!("Invalid status: {}", status)));
         }
-        updates.push("status = ?");
-        values.push(status.clone());
-    }
-    if let Some(ref completed_date) = update.completed_date {
-        updates.push("completed_date = ?");
-        values.push(completed_date.clone());
-    }
-    if let Some(ref performed_by) = update.performed_by {
-        updates.push("performed_by = ?");
-        values.push(performed_by.clone());
-    }
-    if let Some(ref description) = update.description {
-        updates.push("description = ?");
-        values.push(description.clone());
+        builder.set("status", status.as_str());
     }
-    if let Some(cost) = update.cost {
-        updates.push("cost = ?");
-        values.push(cost.to_string());
+    if let Some(ref date) = update.scheduled_date {
+        if update.scheduled_start.is_some() {
+            return Err(ApiError::bad_request("Use either scheduled_date or scheduled_start, not both"));
+        }
+        let date = date.trim();
+        if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+            return Err(ApiError::bad_request("scheduled_date must be YYYY-MM-DD"));
+        }
+        builder.set("scheduled_date", date.to_string());
+        // Окно на весь прежний день переносится вместе с датой; заданное вручную окно остаётся
+        if update.scheduled_end.is_none() && existing.scheduled_start == whole_day_window(&existing.scheduled_date).map(|(start, _)| start) {
+            if let Some((start, end)) = whole_day_window(date) {
+                builder.set("scheduled_start", start).set("scheduled_end", end);
+            }
+        }
     }
-    if let Some(ref parts_replaced) = update.parts_replaced {
-        updates.push("parts_replaced = ?");
-        values.push(parts_replaced.clone());
+    if update.scheduled_start.is_some() || update.scheduled_end.is_some() {
+        let start = update.scheduled_start.or(existing.scheduled_start);
+        let end = update.scheduled_end.or(existing.scheduled_end);
+        let (Some(start), Some(end)) = (start, end) else {
+            return Err(ApiError::bad_request("scheduled_start and scheduled_end must be set together"));
+        };
+        check_window_order(start, end)?;
+        builder.set("scheduled_start", start).set("scheduled_end", end);
+        // Дата обслуживания следует за началом окна
+        if update.scheduled_start.is_some() {
+            builder.set("scheduled_date", start.format("%Y-%m-%d").to_string());
+        }
     }
-    if let Some(ref notes) = update.notes {
-        updates.push("notes = ?");
-        values.push(notes.clone());
+    if let Some(Some(ref assignee)) = update.assigned_to {
+        check_maintenance_assignee(&app_state.db_pool, assignee).await?;
     }
-
-    if updates.is_empty() {
+    builder
+        .patch("reminder_lead_days", &update.reminder_lead_days)
+        .patch("sla_days", &update.sla_days)
+        .patch_text("assigned_to", &update.assigned_to)
+        .patch_text("completed_date", &update.completed_date)
+        .patch_text("performed_by", &update.performed_by)
+        .patch_text("description", &update.description)
+        .patch("cost", &update.cost)
+        .patch_text("parts_replaced", &update.parts_replaced)
+        .patch_text("notes", &update.notes);
+
+    if builder.is_empty() {
         return Err(ApiError::bad_request("No fields to update"));
     }
 
-    updates.push("updated_at = ?");
-    values.push(Utc::now().to_rfc3339());
+    builder
+        .set("updated_at", Utc::now())
+        .where_eq("id", maintenance_id.as_str());
+    builder.execute(&app_state.db_pool).await?;
 
-    let sql = format!(
-        "UPDATE equipment_maintenance SET {} WHERE id = ?",
-        updates.join(", ")
-    );
+    let updated: EquipmentMaintenance = sqlx::query_as(
+        "SELECT * FROM equipment_maintenance WHERE id = ?"
+    )
+        .bind(&maintenance_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
 
-    let mut query = sqlx::query(&sql);
-    for value in &values {
-        query = query.bind(value);
+    // Перенос даты, смена срока, SLA или статуса меняют ожидающие напоминания и эскалацию -
+    // пересчитываем сразу, не дожидаясь ежечасной задачи
+    let reschedules = update.scheduled_date.is_some() || update.scheduled_start.is_some()
+        || update.reminder_lead_days.is_some() || update.sla_days.is_some() || update.status.is_some();
+    if !reschedules {
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(updated)));
     }
-    query = query.bind(&maintenance_id);
-    query.execute(&app_state.db_pool).await?;
-
+    crate::alert_handlers::refresh_maintenance_alerts(&app_state.db_pool, &app_state.config.maintenance).await?;
     let updated: EquipmentMaintenance = sqlx::query_as(
         "SELECT * FROM equipment_maintenance WHERE id = ?"
     )
@@ -743,80 +1088,514 @@ pub async fn update_maintenance(
     Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
 }
 
-/// Завершение обслуживания
-pub async fn complete_maintenance(
+/// Предстоящие и идущие окна обслуживания с временем до начала.
+/// Фильтры: `type` оборудования, `room_id` (по названию помещения в location) или `location`
+pub async fn get_upcoming_maintenance(
     app_state: web::Data<Arc<AppState>>,
-    path: web::Path<(String, String)>,
-    body: web::Json<CompleteMaintenanceRequest>,
-    _user_id: String,
+    query: web::Query<UpcomingMaintenanceQuery>,
 ) -> ApiResult<HttpResponse> {
-    let (equipment_id, maintenance_id) = path.into_inner();
+    if query.use_lead_times && query.days.is_some() {
+        return Err(ApiError::bad_request("Use either days or use_lead_times, not both"));
+    }
+    let days = query.days.unwrap_or(DEFAULT_UPCOMING_MAINTENANCE_DAYS).clamp(1, 365);
+    let limit = query.limit.map_or(MAX_NESTED_LIST_ROWS, i64::from).clamp(1, MAX_NESTED_LIST_ROWS);
 
-    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+    if let Some(ref type_) = query.type_ {
+        if EquipmentType::from_str(type_).is_err() {
+            return Err(ApiError::bad_request(&format!("Invalid equipment type: {}", type_)));
+        }
+    }
 
-    let existing: Option<EquipmentMaintenance> = sqlx::query_as(
-        "SELECT * FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
-    )
-        .bind(&maintenance_id)
-        .bind(&equipment_id)
-        .fetch_optional(&app_state.db_pool)
+    let location = match (&query.room_id, &query.location) {
+        (Some(_), Some(_)) => {
+            return Err(ApiError::bad_request("Use either room_id or location, not both"));
+        }
+        (Some(room_id), None) => Some(
+            sqlx::query_scalar::<_, String>("SELECT name FROM rooms WHERE id = ?")
+                .bind(room_id)
+                .fetch_optional(&app_state.db_pool)
+                .await?
+                .ok_or_else(|| ApiError::not_found("Room"))?,
+        ),
+        (None, location) => location.clone(),
+    };
+
+    let now = Utc::now();
+    let lead_days = app_state.config.maintenance.lead_days_sql("m");
+    // С use_lead_times окно у каждой записи своё: начало не позже чем через reminder_lead_days
+    let horizon = if query.use_lead_times {
+        format!("datetime(?, '+' || {} || ' days')", lead_days)
+    } else {
+        format!("datetime(?, '+{} days')", days)
+    };
+    let rows: Vec<EquipmentMaintenanceWithEquipment> = sqlx::query_as(&format!(
+        r#"SELECT m.*, e.name AS equipment_name, e.location AS equipment_location
+           FROM equipment_maintenance m
+           JOIN equipment e ON e.id = m.equipment_id
+           WHERE m.status IN ('scheduled', 'in_progress')
+             AND m.scheduled_start IS NOT NULL
+             AND datetime(m.scheduled_end) > datetime(?)
+             AND datetime(m.scheduled_start) < {}
+             AND (? IS NULL OR e.type_ = ?)
+             AND (? IS NULL OR e.location = ? COLLATE NOCASE)
+           ORDER BY datetime(m.scheduled_start)
+           LIMIT ?"#,
+        horizon
+    ))
+        .bind(now)
+        .bind(now)
+        .bind(&query.type_)
+        .bind(&query.type_)
+        .bind(&location)
+        .bind(&location)
+        .bind(limit)
+        .fetch_all(&app_state.db_pool)
         .await?;
 
-    if existing.is_none() {
-        return Err(ApiError::not_found("Maintenance record"));
-    }
+    let upcoming: Vec<UpcomingMaintenance> = rows
+        .into_iter()
+        .map(|maintenance| {
+            let start = maintenance.scheduled_start.unwrap_or(now);
+            let effective_reminder_lead_days = maintenance.reminder_lead_days
+                .unwrap_or_else(|| app_state.config.maintenance.reminder_lead_days_for(&maintenance.maintenance_type));
+            UpcomingMaintenance {
+                seconds_until_start: (start - now).num_seconds().max(0),
+                is_active: start <= now,
+                effective_reminder_lead_days,
+                maintenance,
+            }
+        })
+        .collect();
 
-    let completed_date = body.completed_date.clone()
-        .unwrap_or_else(|| Utc::now().format("%Y-%m-%d").to_string());
+    Ok(HttpResponse::Ok().json(ApiResponse::success(upcoming)))
+}
 
-    sqlx::query(
-        r#"UPDATE equipment_maintenance 
-           SET status = 'completed', completed_date = ?, performed_by = ?, 
-               notes = COALESCE(?, notes), updated_at = ?
-           WHERE id = ?"#
+/// Окно (в днях) для калибровок и истекающих гарантий в сводке парка
+const FLEET_SUMMARY_WINDOW_DAYS: i64 = 30;
+/// Сколько последних повреждённых единиц показывает сводка
+const FLEET_RECENTLY_BROKEN_LIMIT: i64 = 5;
+
+/// Сводка по парку оборудования (дашборд): статусы, просрочки, калибровки, гарантии
+pub(crate) async fn equipment_fleet_summary(pool: &SqlitePool) -> ApiResult<EquipmentFleetSummary> {
+    let status_rows: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT status, COUNT(*) FROM equipment WHERE pending_deletion_id IS NULL GROUP BY status"
     )
-        .bind(&completed_date)
-        .bind(&body.performed_by)
-        .bind(&body.notes)
-        .bind(Utc::now())
-        .bind(&maintenance_id)
-        .execute(&app_state.db_pool)
+        .fetch_all(pool)
         .await?;
-
-    let updated: EquipmentMaintenance = sqlx::query_as(
-        "SELECT * FROM equipment_maintenance WHERE id = ?"
+    let mut by_status: std::collections::BTreeMap<String, i64> = EquipmentStatus::VARIANTS
+        .iter()
+        .map(|status| (status.to_string(), 0))
+        .collect();
+    by_status.extend(status_rows);
+
+    let window = format!("+{} days", FLEET_SUMMARY_WINDOW_DAYS);
+    let (maintenance_overdue, calibration_due, warranty_expiring): (i64, i64, i64) = sqlx::query_as(
+        r#"SELECT
+               COALESCE(SUM(
+                   (e.next_maintenance IS NOT NULL AND date(e.next_maintenance) < date('now'))
+                   OR EXISTS (SELECT 1 FROM equipment_maintenance m
+                              WHERE m.equipment_id = e.id
+                                AND m.status IN ('scheduled', 'in_progress')
+                                AND date(m.scheduled_date) < date('now'))
+               ), 0),
+               COALESCE(SUM(EXISTS (
+                   SELECT 1 FROM equipment_maintenance m
+                   WHERE m.equipment_id = e.id
+                     AND m.maintenance_type = 'calibration'
+                     AND m.status IN ('scheduled', 'in_progress')
+                     AND date(m.scheduled_date) <= date('now', ?1)
+               )), 0),
+               COALESCE(SUM(
+                   e.warranty_until IS NOT NULL
+                   AND date(e.warranty_until) >= date('now')
+                   AND date(e.warranty_until) <= date('now', ?1)
+               ), 0)
+           FROM equipment e
+           WHERE e.status != 'retired' AND e.pending_deletion_id IS NULL"#
     )
-        .bind(&maintenance_id)
-        .fetch_one(&app_state.db_pool)
+        .bind(&window)
+        .fetch_one(pool)
         .await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
-}
-
-/// Удаление записи об обслуживании
-pub async fn delete_maintenance(
-    app_state: web::Data<Arc<AppState>>,
-    path: web::Path<(String, String)>,
-) -> ApiResult<HttpResponse> {
-    let (equipment_id, maintenance_id) = path.into_inner();
-
-    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+    let maintenance_sla_overdue: i64 = sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM equipment_maintenance m
+           JOIN equipment e ON e.id = m.equipment_id
+           WHERE m.effective_status = 'overdue' AND e.status != 'retired' AND e.pending_deletion_id IS NULL"#
+    )
+        .fetch_one(pool)
+        .await?;
 
-    let result = sqlx::query(
-        "DELETE FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
+    let recently_broken: Vec<BrokenEquipment> = sqlx::query_as(
+        "SELECT id, name, location, serial_number, updated_at FROM equipment \
+         WHERE status = 'damaged' AND pending_deletion_id IS NULL \
+         ORDER BY updated_at DESC LIMIT ?"
     )
-        .bind(&maintenance_id)
-        .bind(&equipment_id)
-        .execute(&app_state.db_pool)
+        .bind(FLEET_RECENTLY_BROKEN_LIMIT)
+        .fetch_all(pool)
         .await?;
 
-    if result.rows_affected() == 0 {
-        return Err(ApiError::not_found("Maintenance record"));
-    }
+    Ok(EquipmentFleetSummary {
+        by_status,
+        maintenance_overdue,
+        calibration_due,
+        warranty_expiring,
+        maintenance_sla_overdue,
+        recently_broken,
+    })
+}
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
-        (),
-        "Maintenance record deleted successfully".to_string(),
+// ==================== PART REORDER SUGGESTIONS ====================
+
+#[derive(Debug, sqlx::FromRow)]
+struct PartDeficitRow {
+    part_id: String,
+    name: String,
+    part_number: Option<String>,
+    manufacturer: Option<String>,
+    equipment_id: String,
+    equipment_name: String,
+    quantity: i64,
+    min_quantity: i64,
+    replacement_maintenance_id: Option<String>,
+}
+
+/// Запчасти ниже min_quantity; списанные и ожидающие удаления приборы не учитываются.
+/// Замена считается запланированной, если в parts_replaced открытого обслуживания
+/// типа replacement того же прибора упомянуто название или part_number
+const PART_DEFICIT_SQL: &str = r#"
+    SELECT p.id AS part_id, p.name, p.part_number, p.manufacturer,
+           p.equipment_id, e.name AS equipment_name, p.quantity, p.min_quantity,
+           (SELECT m.id FROM equipment_maintenance m
+            WHERE m.equipment_id = p.equipment_id
+              AND m.maintenance_type = 'replacement'
+              AND m.status IN ('scheduled', 'in_progress')
+              AND m.parts_replaced IS NOT NULL
+              AND (instr(lower(m.parts_replaced), lower(p.name)) > 0
+                   OR (p.part_number IS NOT NULL AND p.part_number != ''
+                       AND instr(lower(m.parts_replaced), lower(p.part_number)) > 0))
+            ORDER BY m.scheduled_date LIMIT 1) AS replacement_maintenance_id
+    FROM equipment_parts p
+    JOIN equipment e ON e.id = p.equipment_id
+    WHERE p.quantity < p.min_quantity
+      AND e.status != 'retired' AND e.pending_deletion_id IS NULL
+    ORDER BY p.name, e.name
+"#;
+
+/// Объединение строк по part_number (без учёта регистра и пробелов по краям);
+/// запчасти без part_number остаются отдельными строками
+fn group_part_deficits(rows: Vec<PartDeficitRow>) -> Vec<PartReorderSuggestion> {
+    let mut suggestions: Vec<PartReorderSuggestion> = Vec::new();
+    let mut index: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+
+    for row in rows {
+        let part_number = row.part_number.as_deref().map(str::trim).filter(|n| !n.is_empty()).map(str::to_string);
+        let usage = PartReorderUsage {
+            deficit: row.min_quantity - row.quantity,
+            part_id: row.part_id,
+            equipment_id: row.equipment_id,
+            equipment_name: row.equipment_name,
+            quantity: row.quantity,
+            min_quantity: row.min_quantity,
+            replacement_maintenance_id: row.replacement_maintenance_id,
+        };
+        let key = part_number.as_ref()
+            .map(|n| n.to_lowercase())
+            .unwrap_or_else(|| format!("part:{}", usage.part_id));
+        let position = *index.entry(key).or_insert_with(|| {
+            suggestions.push(PartReorderSuggestion {
+                part_number,
+                name: row.name,
+                manufacturer: None,
+                total_deficit: 0,
+                deficit_to_order: 0,
+                replacement_scheduled: false,
+                equipment: Vec::new(),
+            });
+            suggestions.len() - 1
+        });
+
+        let suggestion = &mut suggestions[position];
+        if suggestion.manufacturer.is_none() {
+            suggestion.manufacturer = row.manufacturer;
+        }
+        suggestion.total_deficit += usage.deficit;
+        if usage.replacement_maintenance_id.is_some() {
+            suggestion.replacement_scheduled = true;
+        } else {
+            suggestion.deficit_to_order += usage.deficit;
+        }
+        suggestion.equipment.push(usage);
+    }
+
+    suggestions.sort_by(|a, b| b.deficit_to_order.cmp(&a.deficit_to_order).then_with(|| a.name.cmp(&b.name)));
+    suggestions
+}
+
+/// Предложения по дозаказу; используются также при создании черновика заказа поставщику
+pub(crate) async fn part_reorder_suggestions(pool: &sqlx::SqlitePool) -> ApiResult<Vec<PartReorderSuggestion>> {
+    let rows: Vec<PartDeficitRow> = sqlx::query_as(PART_DEFICIT_SQL)
+        .fetch_all(pool)
+        .await?;
+    Ok(group_part_deficits(rows))
+}
+
+const PART_REORDER_CSV_HEADER: &str =
+    "Part Number,Name,Manufacturer,Total Deficit,To Order,Replacement Scheduled,Equipment\n";
+
+fn part_reorder_csv(suggestions: &[PartReorderSuggestion]) -> String {
+    let mut csv = String::from(PART_REORDER_CSV_HEADER);
+    for s in suggestions {
+        let equipment = s.equipment.iter()
+            .map(|u| format!("{} ({})", u.equipment_name, u.deficit))
+            .collect::<Vec<_>>()
+            .join("; ");
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            escape_csv_field(s.part_number.as_deref().unwrap_or("")),
+            escape_csv_field(&s.name),
+            escape_csv_field(s.manufacturer.as_deref().unwrap_or("")),
+            s.total_deficit,
+            s.deficit_to_order,
+            if s.replacement_scheduled { "yes" } else { "no" },
+            escape_csv_field(&equipment),
+        ));
+    }
+    csv
+}
+
+/// GET /equipment/parts/reorder-suggestions - что дозаказать из запчастей (format=csv - файлом)
+pub async fn get_part_reorder_suggestions(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<PartReorderQuery>,
+) -> ApiResult<HttpResponse> {
+    let suggestions = part_reorder_suggestions(app_state.read_pool()).await?;
+
+    match query.format.as_deref().unwrap_or("json") {
+        "json" => Ok(HttpResponse::Ok().json(ApiResponse::success(suggestions))),
+        "csv" => Ok(HttpResponse::Ok()
+            .insert_header(("Content-Type", "text/csv; charset=utf-8"))
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"part_reorder_{}.csv\"", Utc::now().format("%Y%m%d")),
+            ))
+            .body(part_reorder_csv(&suggestions))),
+        other => Err(ApiError::bad_request(&format!("Unsupported format '{}' (use json or csv)", other))),
+    }
+}
+
+/// GET /dashboard/equipment-summary
+pub async fn get_equipment_summary(
+    app_state: web::Data<Arc<AppState>>,
+) -> ApiResult<HttpResponse> {
+    let summary = equipment_fleet_summary(app_state.read_pool()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(summary)))
+}
+
+/// Обслуживание, просроченное по SLA (effective_status = 'overdue'): дни просрочки,
+/// ступень эскалации и текущий статус оборудования; сначала самые просроченные
+pub async fn get_overdue_maintenance(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<OverdueMaintenanceQuery>,
+) -> ApiResult<HttpResponse> {
+    if let Some(level) = query.escalation_level {
+        if !(1..=2).contains(&level) {
+            return Err(ApiError::bad_request("escalation_level must be 1 or 2"));
+        }
+    }
+    let limit = query.limit.map_or(MAX_NESTED_LIST_ROWS, i64::from).clamp(1, MAX_NESTED_LIST_ROWS);
+    let maintenance = &app_state.config.maintenance;
+
+    let overdue: Vec<OverdueMaintenance> = sqlx::query_as(&format!(
+        r#"SELECT m.*, e.name AS equipment_name, e.location AS equipment_location,
+                  e.status AS equipment_status, {sla} AS effective_sla_days, {days} AS days_overdue,
+                  u.username AS assigned_to_username
+           FROM equipment_maintenance m
+           JOIN equipment e ON e.id = m.equipment_id
+           LEFT JOIN users u ON u.id = m.assigned_to
+           WHERE m.effective_status = 'overdue'
+             AND e.status != 'retired' AND e.pending_deletion_id IS NULL
+             AND (? IS NULL OR m.escalation_level = ?)
+           ORDER BY days_overdue DESC, m.scheduled_date
+           LIMIT ?"#,
+        sla = maintenance.sla_days_sql("m"), days = maintenance.days_overdue_sql("m")
+    ))
+        .bind(query.escalation_level)
+        .bind(query.escalation_level)
+        .bind(limit)
+        .fetch_all(app_state.read_pool())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(overdue)))
+}
+
+/// Завершение обслуживания
+pub async fn complete_maintenance(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<CompleteMaintenanceRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let (equipment_id, maintenance_id) = path.into_inner();
+
+    check_nested_equipment(&app_state.db_pool, &equipment_id, "Maintenance record").await?;
+
+    let existing: Option<EquipmentMaintenance> = sqlx::query_as(
+        "SELECT * FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
+    )
+        .bind(&maintenance_id)
+        .bind(&equipment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?;
+
+    let existing = existing.ok_or_else(|| ApiError::not_found("Maintenance record"))?;
+
+    if !body.consumed_parts.is_empty() && existing.status == "completed" {
+        // Повторное завершение не должно списывать расходники второй раз
+        return Err(ApiError::bad_request("Maintenance is already completed; consumed parts were not deducted"));
+    }
+    if body.consumed_parts.iter().any(|c| c.quantity <= 0) {
+        return Err(ApiError::bad_request("Consumed part quantity must be positive"));
+    }
+
+    let completed_date = body.completed_date.clone()
+        .unwrap_or_else(|| Utc::now().format("%Y-%m-%d").to_string());
+
+    let mut tx = app_state.db_pool.begin().await?;
+    let mut consumed_parts = Vec::with_capacity(body.consumed_parts.len());
+
+    // Завершение обрывает цепочку эскалации
+    sqlx::query(
+        r#"UPDATE equipment_maintenance 
+           SET status = 'completed', completed_date = ?, performed_by = ?, 
+               notes = COALESCE(?, notes), escalation_level = 0, escalated_at = NULL, updated_at = ?
+           WHERE id = ?"#
+    )
+        .bind(&completed_date)
+        .bind(&body.performed_by)
+        .bind(&body.notes)
+        .bind(Utc::now())
+        .bind(&maintenance_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for consumed in &body.consumed_parts {
+        let part: EquipmentPart = sqlx::query_as(
+            "SELECT * FROM equipment_parts WHERE id = ? AND equipment_id = ?"
+        )
+            .bind(&consumed.part_id)
+            .bind(&equipment_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Equipment part"))?;
+
+        let result = match part.linked_batch_id {
+            Some(ref batch_id) => {
+                // Связанный расходник списывается из партии общим путём расхода
+                let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ? AND deleted_at IS NULL")
+                    .bind(batch_id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .ok_or_else(|| ApiError::batch_not_found(batch_id))?;
+                let usage = crate::handlers::record_batch_usage(
+                    &mut tx,
+                    &batch,
+                    &user_id,
+                    consumed.quantity as f64,
+                    Some(&format!("Maintenance: {}", existing.maintenance_type)),
+                    Some(&format!("Equipment part \"{}\" (maintenance {})", part.name, maintenance_id)),
+                    None,
+                ).await?;
+                outbox::enqueue(&mut *tx, &BusinessEvent::BatchConsumed {
+                    reagent_id: batch.reagent_id.clone(),
+                    batch_id: batch.id.clone(),
+                    quantity: consumed.quantity as f64,
+                    unit: batch.unit.clone(),
+                    remaining_quantity: usage.remaining_quantity,
+                }, Some(&user_id)).await?;
+                ConsumedPartResult {
+                    part_id: part.id,
+                    part_name: part.name,
+                    quantity: consumed.quantity,
+                    batch_id: Some(batch.id),
+                    usage_id: Some(usage.usage_id),
+                    remaining_quantity: usage.remaining_quantity,
+                }
+            }
+            None => {
+                if consumed.quantity > part.quantity {
+                    return Err(ApiError::insufficient_quantity(part.quantity as f64, consumed.quantity as f64));
+                }
+                sqlx::query("UPDATE equipment_parts SET quantity = quantity - ?, updated_at = ? WHERE id = ?")
+                    .bind(consumed.quantity)
+                    .bind(Utc::now())
+                    .bind(&part.id)
+                    .execute(&mut *tx)
+                    .await?;
+                ConsumedPartResult {
+                    remaining_quantity: (part.quantity - consumed.quantity) as f64,
+                    part_id: part.id,
+                    part_name: part.name,
+                    quantity: consumed.quantity,
+                    batch_id: None,
+                    usage_id: None,
+                }
+            }
+        };
+        consumed_parts.push(result);
+    }
+
+    tx.commit().await?;
+    for part in consumed_parts.iter().filter(|p| p.usage_id.is_some()) {
+        let Some(batch_id) = part.batch_id.as_deref() else { continue };
+        crate::stock_anomalies::track(&app_state, crate::stock_anomalies::StockOp {
+            operation: crate::stock_anomalies::StockOperation::Use,
+            batch_id,
+            user_id: Some(&user_id),
+            quantity: part.quantity as f64,
+        }).await;
+    }
+
+    if existing.escalation_level > 0 {
+        crate::alert_handlers::refresh_maintenance_alerts(&app_state.db_pool, &app_state.config.maintenance).await?;
+    }
+
+    let updated: EquipmentMaintenance = sqlx::query_as(
+        "SELECT * FROM equipment_maintenance WHERE id = ?"
+    )
+        .bind(&maintenance_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(MaintenanceCompletionResponse {
+        maintenance: updated,
+        consumed_parts,
+    })))
+}
+
+/// Удаление записи об обслуживании
+pub async fn delete_maintenance(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+) -> ApiResult<HttpResponse> {
+    let (equipment_id, maintenance_id) = path.into_inner();
+
+    check_nested_equipment(&app_state.db_pool, &equipment_id, "Maintenance record").await?;
+
+    let result = sqlx::query(
+        "DELETE FROM equipment_maintenance WHERE id = ? AND equipment_id = ?"
+    )
+        .bind(&maintenance_id)
+        .bind(&equipment_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Maintenance record"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        (),
+        "Maintenance record deleted successfully".to_string(),
     )))
 }
 
@@ -833,7 +1612,7 @@ pub async fn get_equipment_files(
 
     let files = get_equipment_files_internal(&app_state.db_pool, &equipment_id).await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(files)))
+    Ok(files.into_response())
 }
 
 /// Загрузка файла для оборудования с древовидной структурой папок
@@ -844,21 +1623,106 @@ pub async fn get_equipment_files(
 pub async fn upload_equipment_file(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
-    mut payload: Multipart,
+    payload: Multipart,
     user_id: String,
 ) -> ApiResult<HttpResponse> {
     let equipment_id = path.into_inner();
 
+    let created = store_equipment_upload(
+        &app_state.db_pool, &get_equipment_files_dir(), &equipment_id, payload, &user_id,
+    ).await?;
+    crate::kpi::record(crate::kpi::Kpi::FilesUploaded, "equipment_file");
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(created)))
+}
+
+/// Временный файл загрузки. Пишется в `{base_dir}/.staging` и переносится на место
+/// только вместе с записью в БД; пока не вызван `keep`, файл удаляется при любом
+/// выходе - ошибке, обрыве соединения клиентом или отмене запроса.
+struct StagedUpload {
+    path: std::path::PathBuf,
+    file: Option<std::fs::File>,
+    size: usize,
+    /// SHA-256 содержимого считается по мере записи (дедупликация, см. file_blobs)
+    hasher: Sha256,
+    kept: bool,
+}
+
+impl StagedUpload {
+    fn create(base_dir: &std::path::Path) -> ApiResult<Self> {
+        let staging_dir = base_dir.join(".staging");
+        std::fs::create_dir_all(&staging_dir)
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to create directory: {}", e)))?;
+        let path = staging_dir.join(format!("{}.part", Uuid::new_v4()));
+        let file = std::fs::File::create(&path)
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to create file: {}", e)))?;
+        Ok(Self { path, file: Some(file), size: 0, hasher: Sha256::new(), kept: false })
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8]) -> ApiResult<()> {
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(chunk)
+                .map_err(|e| ApiError::InternalServerError(format!("Failed to write file: {}", e)))?;
+        }
+        self.hasher.update(chunk);
+        self.size += chunk.len();
+        Ok(())
+    }
+
+    fn content_hash(&self) -> String {
+        file_blobs::hex_digest(self.hasher.clone())
+    }
+
+    /// fsync и закрытие перед записью в БД
+    fn sync(&mut self) -> ApiResult<()> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()
+                .map_err(|e| ApiError::InternalServerError(format!("Failed to write file: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    fn move_to(&mut self, dest: &std::path::Path) -> ApiResult<()> {
+        self.sync()?;
+        std::fs::rename(&self.path, dest)
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to store file: {}", e)))?;
+        self.path = dest.to_path_buf();
+        Ok(())
+    }
+
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for StagedUpload {
+    fn drop(&mut self) {
+        if !self.kept {
+            self.file.take();
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Чтение multipart-формы и сохранение файла. Обрыв загрузки клиентом - 408,
+/// ошибки формы и валидации - 400; ни в одном из случаев не остаётся ни файла, ни строки в БД.
+pub(crate) async fn store_equipment_upload(
+    pool: &SqlitePool,
+    base_dir: &std::path::Path,
+    equipment_id: &str,
+    mut payload: Multipart,
+    user_id: &str,
+) -> ApiResult<EquipmentFile> {
     // Получаем информацию об оборудовании
     let equipment: Equipment = sqlx::query_as(
-        "SELECT * FROM equipment WHERE id = ?"
+        "SELECT * FROM equipment WHERE id = ? AND pending_deletion_id IS NULL"
     )
-        .bind(&equipment_id)
-        .fetch_optional(&app_state.db_pool)
+        .bind(equipment_id)
+        .fetch_optional(pool)
         .await?
         .ok_or_else(|| ApiError::not_found("Equipment"))?;
 
-    let mut file_bytes: Option<Vec<u8>> = None;
+    let mut staged: Option<StagedUpload> = None;
     let mut original_filename: Option<String> = None;
     let mut content_type: Option<String> = None;
     let mut form_file_type: Option<String> = None;
@@ -867,7 +1731,7 @@ pub async fn upload_equipment_file(
 
     // Читаем все поля формы
     while let Some(item) = payload.next().await {
-        let mut field = item.map_err(|e| ApiError::bad_request(&format!("Multipart error: {}", e)))?;
+        let mut field = item.map_err(ApiError::from)?;
 
         let content_disposition = field.content_disposition();
         let field_name = content_disposition.get_name().unwrap_or("");
@@ -876,8 +1740,8 @@ pub async fn upload_equipment_file(
             "file" => {
                 let filename = content_disposition
                     .get_filename()
-                    .ok_or_else(|| ApiError::bad_request("Filename not provided"))?
-                    .to_string();
+                    .map(sanitize_original_filename)
+                    .ok_or_else(|| ApiError::bad_request("Filename not provided"))?;
 
                 let mime = field.content_type()
                     .map(|m| m.to_string())
@@ -890,21 +1754,22 @@ pub async fn upload_equipment_file(
 
                 validate_mime_type(&mime, &all_allowed)?;
 
-                let mut bytes = Vec::new();
+                let max_size = max_file_size();
+                let mut upload = StagedUpload::create(base_dir)?;
                 while let Some(chunk) = field.next().await {
-                    let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
-                    bytes.extend_from_slice(&chunk);
-                    validate_file_size(bytes.len(), MAX_FILE_SIZE)?;
+                    let chunk = chunk.map_err(ApiError::from)?;
+                    validate_file_size(upload.size + chunk.len(), max_size)?;
+                    upload.write_chunk(&chunk)?;
                 }
 
-                file_bytes = Some(bytes);
+                staged = Some(upload);
                 original_filename = Some(filename);
                 content_type = Some(mime);
             }
             "file_type" => {
                 let mut bytes = Vec::new();
                 while let Some(chunk) = field.next().await {
-                    let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+                    let chunk = chunk.map_err(ApiError::from)?;
                     bytes.extend_from_slice(&chunk);
                 }
                 if let Ok(value) = String::from_utf8(bytes) {
@@ -918,7 +1783,7 @@ pub async fn upload_equipment_file(
             "description" => {
                 let mut bytes = Vec::new();
                 while let Some(chunk) = field.next().await {
-                    let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+                    let chunk = chunk.map_err(ApiError::from)?;
                     bytes.extend_from_slice(&chunk);
                 }
                 if let Ok(value) = String::from_utf8(bytes) {
@@ -931,7 +1796,7 @@ pub async fn upload_equipment_file(
             "part_id" => {
                 let mut bytes = Vec::new();
                 while let Some(chunk) = field.next().await {
-                    let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+                    let chunk = chunk.map_err(ApiError::from)?;
                     bytes.extend_from_slice(&chunk);
                 }
                 if let Ok(value) = String::from_utf8(bytes) {
@@ -945,7 +1810,7 @@ pub async fn upload_equipment_file(
         }
     }
 
-    let file_bytes = file_bytes.ok_or_else(|| ApiError::bad_request("No file provided"))?;
+    let mut staged = staged.ok_or_else(|| ApiError::bad_request("No file provided"))?;
     let original_filename = original_filename.ok_or_else(|| ApiError::bad_request("No filename"))?;
     let content_type = content_type.unwrap_or_else(|| "application/octet-stream".to_string());
 
@@ -961,90 +1826,92 @@ pub async fn upload_equipment_file(
     let sanitized_equip_name = sanitize_folder_name(&equipment.name);
     let type_folder = get_type_folder(&file_type);
 
-    let file_path = if let Some(ref part_id) = form_part_id {
+    let type_dir = if let Some(ref part_id) = form_part_id {
         // Получаем имя запчасти
         let part: EquipmentPart = sqlx::query_as(
             "SELECT * FROM equipment_parts WHERE id = ? AND equipment_id = ?"
         )
             .bind(part_id)
-            .bind(&equipment_id)
-            .fetch_optional(&app_state.db_pool)
+            .bind(equipment_id)
+            .fetch_optional(pool)
             .await?
             .ok_or_else(|| ApiError::not_found("Part"))?;
 
-        let sanitized_part_name = sanitize_folder_name(&part.name);
-
         // Структура: equipment/{equip_name}/parts/{part_name}/{type}/
-        let type_dir = get_equipment_files_dir()
+        base_dir
             .join(&sanitized_equip_name)
             .join("parts")
-            .join(&sanitized_part_name)
-            .join(type_folder);
-
-        std::fs::create_dir_all(&type_dir)
-            .map_err(|e| ApiError::InternalServerError(format!("Failed to create directory: {}", e)))?;
-
-        let unique_filename = generate_unique_filename(&original_filename);
-        type_dir.join(&unique_filename).to_string_lossy().to_string()
+            .join(sanitize_folder_name(&part.name))
+            .join(type_folder)
     } else {
         // Структура: equipment/{equip_name}/{type}/
-        let type_dir = get_equipment_files_dir()
+        base_dir
             .join(&sanitized_equip_name)
-            .join(type_folder);
-
-        std::fs::create_dir_all(&type_dir)
-            .map_err(|e| ApiError::InternalServerError(format!("Failed to create directory: {}", e)))?;
-
-        let unique_filename = generate_unique_filename(&original_filename);
-        type_dir.join(&unique_filename).to_string_lossy().to_string()
+            .join(type_folder)
     };
 
-    // Извлекаем stored_filename из полного пути
-    let stored_filename = std::path::Path::new(&file_path)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or(&original_filename)
-        .to_string();
+    let mut stored_filename = generate_unique_filename(&original_filename);
+    let dest = type_dir.join(&stored_filename);
+    let mut file_path = dest.to_string_lossy().to_string();
 
-    // Сохраняем файл
-    let mut f = std::fs::File::create(&file_path)
-        .map_err(|e| ApiError::InternalServerError(format!("Failed to create file: {}", e)))?;
-    f.write_all(&file_bytes)
-        .map_err(|e| ApiError::InternalServerError(format!("Failed to write file: {}", e)))?;
+    // Файл на диске до записи в БД, затем строка и перенос на место в одной транзакции:
+    // при ошибке транзакция откатывается, а StagedUpload удаляет файл
+    staged.sync()?;
+    let content_hash = staged.content_hash();
 
-    // Сохраняем в БД
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
 
+    let mut tx = pool.begin().await?;
+    // Такое содержимое уже хранится - строка ссылается на существующий файл, загрузка удаляется
+    let existing = file_blobs::acquire(&mut tx, &content_hash, &file_path, staged.size as i64).await?;
+    if let Some(ref blob_path) = existing {
+        file_path = blob_path.clone();
+        if let Some(name) = std::path::Path::new(blob_path).file_name() {
+            stored_filename = name.to_string_lossy().to_string();
+        }
+    }
+
     sqlx::query(
         r#"INSERT INTO equipment_files
            (id, equipment_id, part_id, file_type, original_filename, stored_filename,
-            file_path, file_size, mime_type, description, uploaded_by, created_at)
-           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+            file_path, file_size, mime_type, description, uploaded_by, created_at, content_hash)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
     )
         .bind(&id)
-        .bind(&equipment_id)
+        .bind(equipment_id)
         .bind(&form_part_id)
         .bind(&file_type)
         .bind(&original_filename)
         .bind(&stored_filename)
         .bind(&file_path)
-        .bind(file_bytes.len() as i64)
+        .bind(staged.size as i64)
         .bind(&content_type)
         .bind(&form_description)
-        .bind(&user_id)
+        .bind(user_id)
         .bind(&now)
-        .execute(&app_state.db_pool)
+        .bind(&content_hash)
+        .execute(&mut *tx)
         .await?;
 
+    if existing.is_none() {
+        std::fs::create_dir_all(&type_dir)
+            .map_err(|e| ApiError::InternalServerError(format!("Failed to create directory: {}", e)))?;
+        staged.move_to(&dest)?;
+    }
+    tx.commit().await?;
+    if existing.is_none() {
+        staged.keep();
+    }
+
     let created: EquipmentFile = sqlx::query_as(
         "SELECT * FROM equipment_files WHERE id = ?"
     )
         .bind(&id)
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(pool)
         .await?;
 
-    Ok(HttpResponse::Created().json(ApiResponse::success(created)))
+    Ok(created)
 }
 
 /// Очистка имени папки от спецсимволов
@@ -1081,6 +1948,7 @@ pub async fn download_equipment_file(
     path: web::Path<(String, String)>,
 ) -> ApiResult<HttpResponse> {
     let (equipment_id, file_id) = path.into_inner();
+    check_nested_equipment(&app_state.db_pool, &equipment_id, "File").await?;
 
     let file: Option<EquipmentFile> = sqlx::query_as(
         "SELECT * FROM equipment_files WHERE id = ? AND equipment_id = ?"
@@ -1098,9 +1966,9 @@ pub async fn download_equipment_file(
 
     // Определяем Content-Disposition: inline для изображений, attachment для остальных
     let disposition = if file.mime_type.starts_with("image/") {
-        format!("inline; filename=\"{}\"", file.original_filename)
+        content_disposition("inline", &file.original_filename)
     } else {
-        format!("attachment; filename=\"{}\"", file.original_filename)
+        content_disposition("attachment", &file.original_filename)
     };
 
     Ok(HttpResponse::Ok()
@@ -1116,6 +1984,7 @@ pub async fn delete_equipment_file(
     path: web::Path<(String, String)>,
 ) -> ApiResult<HttpResponse> {
     let (equipment_id, file_id) = path.into_inner();
+    check_nested_equipment(&app_state.db_pool, &equipment_id, "File").await?;
 
     // Получаем информацию о файле
     let file: Option<EquipmentFile> = sqlx::query_as(
@@ -1128,14 +1997,15 @@ pub async fn delete_equipment_file(
 
     let file = file.ok_or_else(|| ApiError::not_found("File"))?;
 
-    // Удаляем файл с диска
-    let _ = std::fs::remove_file(&file.file_path);
-
-    // Удаляем из БД
+    // Удаляем из БД; файл с диска - только если на него больше никто не ссылается
+    let mut tx = app_state.db_pool.begin().await?;
     sqlx::query("DELETE FROM equipment_files WHERE id = ?")
         .bind(&file_id)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
+    let unlinked = file_blobs::release(&mut tx, file.content_hash.as_deref(), &file.file_path).await?;
+    tx.commit().await?;
+    file_blobs::remove_files(unlinked);
 
     Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
         (),
@@ -1149,6 +2019,7 @@ pub async fn delete_equipment_file(
 pub async fn search_equipment(
     app_state: web::Data<Arc<AppState>>,
     query: web::Query<SearchQuery>,
+    api_version: ApiVersion,
 ) -> ApiResult<HttpResponse> {
     let search_term = query.q.as_deref().unwrap_or("").trim();
 
@@ -1167,18 +2038,20 @@ pub async fn search_equipment(
         .unwrap_or(false);
 
     let equipment: Vec<Equipment> = if fts_available {
-        // FTS поиск
-        let escaped_term = search_term.replace("\"", "\"\"");
-        let sql = format!(
-            r#"SELECT e.* FROM equipment e
-               JOIN equipment_fts f ON e.id = f.equipment_id
-               WHERE equipment_fts MATCH '"{}"'
-               ORDER BY rank
-               LIMIT ?"#,
-            escaped_term
-        );
+        // FTS поиск (equipment_fts связана с equipment по rowid)
+        let fts_query = FtsQueryBuilder::build_fts_query(search_term);
+        if fts_query.is_empty() {
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(Vec::<Equipment>::new())));
+        }
 
-        sqlx::query_as::<_, Equipment>(&sql)
+        sqlx::query_as::<_, Equipment>(
+            r#"SELECT e.* FROM equipment_fts
+               JOIN equipment e ON e.rowid = equipment_fts.rowid
+               WHERE equipment_fts MATCH ? AND e.pending_deletion_id IS NULL
+               ORDER BY equipment_fts.rank
+               LIMIT ?"#
+        )
+            .bind(&fts_query)
             .bind(limit)
             .fetch_all(&app_state.db_pool)
             .await?
@@ -1186,7 +2059,7 @@ pub async fn search_equipment(
         // Fallback на LIKE
         let pattern = format!("%{}%", search_term);
         sqlx::query_as::<_, Equipment>(
-            "SELECT * FROM equipment WHERE name LIKE ? OR description LIKE ? OR location LIKE ? ORDER BY name LIMIT ?"
+            "SELECT * FROM equipment WHERE (name LIKE ? OR description LIKE ? OR location LIKE ?) AND pending_deletion_id IS NULL ORDER BY name LIMIT ?"
         )
             .bind(&pattern)
             .bind(&pattern)
@@ -1196,15 +2069,39 @@ pub async fn search_equipment(
             .await?
     };
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(equipment)))
+    Ok(HttpResponse::Ok().json(ApiResponse::success(
+        api_version.render_all(Representation::Equipment, &equipment)?,
+    )))
 }
 
 // ==================== ВСПОМОГАТЕЛЬНЫЕ ФУНКЦИИ ====================
 
 /// Проверка существования оборудования
+/// Родитель вложенного пути `/equipment/{id}/<child>/{child_id}`: отсутствие оборудования
+/// неотличимо от отсутствия самой записи - 404 с именем дочерней сущности
+async fn check_nested_equipment(pool: &SqlitePool, equipment_id: &str, child: &str) -> ApiResult<()> {
+    check_equipment_exists(pool, equipment_id).await.map_err(|e| match e {
+        ApiError::NotFound(_) => ApiError::not_found(child),
+        other => other,
+    })
+}
+
+/// Ответственным за обслуживание может быть только активный пользователь
+async fn check_maintenance_assignee(pool: &SqlitePool, user_id: &str) -> ApiResult<()> {
+    let active: Option<bool> = sqlx::query_scalar("SELECT is_active FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    match active {
+        None => Err(ApiError::not_found("User")),
+        Some(false) => Err(ApiError::bad_request("Maintenance cannot be assigned to an inactive user")),
+        Some(true) => Ok(()),
+    }
+}
+
 async fn check_equipment_exists(pool: &SqlitePool, equipment_id: &str) -> ApiResult<()> {
     let exists: bool = sqlx::query_scalar(
-        "SELECT EXISTS(SELECT 1 FROM equipment WHERE id = ?)"
+        "SELECT EXISTS(SELECT 1 FROM equipment WHERE id = ? AND pending_deletion_id IS NULL)"
     )
         .bind(equipment_id)
         .fetch_one(pool)
@@ -1220,17 +2117,54 @@ async fn check_equipment_exists(pool: &SqlitePool, equipment_id: &str) -> ApiRes
 async fn get_equipment_parts_internal(
     pool: &SqlitePool,
     equipment_id: &str,
-) -> ApiResult<Vec<EquipmentPart>> {
+) -> ApiResult<NestedList<EquipmentPart>> {
     let parts: Vec<EquipmentPart> = sqlx::query_as(
-        "SELECT * FROM equipment_parts WHERE equipment_id = ? ORDER BY name"
+        "SELECT * FROM equipment_parts WHERE equipment_id = ? ORDER BY name LIMIT ?"
     )
         .bind(equipment_id)
+        .bind(MAX_NESTED_LIST_ROWS + 1)
         .fetch_all(pool)
         .await?;
+    let mut parts = NestedList::new(parts);
+    resolve_part_links(pool, &mut parts.rows).await?;
 
     Ok(parts)
 }
 
+/// Сведения о партии для связанной запчасти (удалённые партии не учитываются)
+async fn fetch_part_batch_link(pool: &SqlitePool, batch_id: &str) -> ApiResult<Option<PartBatchLink>> {
+    let link: Option<PartBatchLink> = sqlx::query_as(
+        r#"SELECT b.id AS batch_id, b.reagent_id, r.name AS reagent_name, b.batch_number,
+                  b.quantity, b.unit, b.status
+           FROM batches b
+           JOIN reagents r ON r.id = b.reagent_id
+           WHERE b.id = ? AND b.deleted_at IS NULL"#
+    )
+        .bind(batch_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(link)
+}
+
+async fn ensure_linkable_batch(pool: &SqlitePool, batch_id: &str) -> ApiResult<PartBatchLink> {
+    fetch_part_batch_link(pool, batch_id)
+        .await?
+        .ok_or_else(|| ApiError::bad_request(&format!("Linked batch '{}' not found", batch_id)))
+}
+
+/// Остаток связанной запчасти - read-through из партии, собственный quantity не используется
+async fn resolve_part_links(pool: &SqlitePool, parts: &mut [EquipmentPart]) -> ApiResult<()> {
+    for part in parts.iter_mut() {
+        if let Some(ref batch_id) = part.linked_batch_id {
+            let link = fetch_part_batch_link(pool, batch_id).await?;
+            part.quantity = link.as_ref().map(|l| l.quantity.floor() as i32).unwrap_or(0);
+            part.linked_batch = link;
+        }
+    }
+    Ok(())
+}
+
 /// Получение недавнего обслуживания (внутренняя функция)
 async fn get_recent_maintenance_internal(
     pool: &SqlitePool,
@@ -1251,19 +2185,120 @@ async fn get_recent_maintenance_internal(
     Ok(maintenance)
 }
 
+/// Значение колонки restricted_to_roles (нормализованный список; пустой - без ограничений)
+fn role_list_column(value: &str) -> Option<String> {
+    parse_role_list(value)
+        .filter(|roles| !roles.is_empty())
+        .map(|roles| roles.join(","))
+}
+
+/// Роль активного пользователя (None - не найден или отключён)
+pub(crate) async fn user_role(pool: &SqlitePool, user_id: &str) -> ApiResult<Option<String>> {
+    let role: Option<(String,)> = sqlx::query_as("SELECT role FROM users WHERE id = ? AND is_active = 1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(role.map(|(role,)| role))
+}
+
+/// Оборудование с ограничением по ролям берут в работу только эти роли; администратору можно всегда
+pub(crate) fn ensure_role_allowed(equipment: &Equipment, role: Option<&str>) -> ApiResult<()> {
+    let Some(allowed) = equipment.restricted_to_roles.as_deref().and_then(parse_role_list) else {
+        return Ok(());
+    };
+    if allowed.is_empty() || role.is_some_and(|r| r == "admin" || allowed.iter().any(|a| a == r)) {
+        return Ok(());
+    }
+    Err(ApiError::Forbidden(format!(
+        "Equipment '{}' is restricted to roles: {} (current role: {})",
+        equipment.name,
+        allowed.join(", "),
+        role.unwrap_or("unknown user"),
+    )))
+}
+
+/// Окно обслуживания, идущее прямо сейчас (scheduled/in_progress) - оборудование недоступно
+pub(crate) async fn find_active_maintenance(
+    pool: &SqlitePool,
+    equipment_id: &str,
+) -> ApiResult<Option<EquipmentMaintenance>> {
+    let now = Utc::now();
+    let window = sqlx::query_as(
+        r#"SELECT * FROM equipment_maintenance
+           WHERE equipment_id = ?
+             AND status IN ('scheduled', 'in_progress')
+             AND datetime(scheduled_start) <= datetime(?)
+             AND datetime(scheduled_end) > datetime(?)
+           ORDER BY datetime(scheduled_end) DESC
+           LIMIT 1"#
+    )
+        .bind(equipment_id)
+        .bind(now)
+        .bind(now)
+        .fetch_optional(pool)
+        .await?;
+    Ok(window)
+}
+
+/// Окно обслуживания (начало, конец)
+pub(crate) type MaintenanceWindow = (DateTime<Utc>, DateTime<Utc>);
+
+fn check_window_order(start: DateTime<Utc>, end: DateTime<Utc>) -> ApiResult<()> {
+    if end <= start {
+        return Err(ApiError::bad_request("scheduled_end must be after scheduled_start"));
+    }
+    Ok(())
+}
+
+/// Окно обслуживания из запроса: явные scheduled_start/scheduled_end
+/// или весь день scheduled_date. Возвращает (scheduled_date, (start, end))
+fn resolve_maintenance_window(
+    scheduled_date: &str,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> ApiResult<(String, Option<MaintenanceWindow>)> {
+    let scheduled_date = scheduled_date.trim();
+    match (start, end) {
+        (Some(start), Some(end)) => {
+            check_window_order(start, end)?;
+            let date = if scheduled_date.is_empty() {
+                start.format("%Y-%m-%d").to_string()
+            } else {
+                scheduled_date.to_string()
+            };
+            Ok((date, Some((start, end))))
+        }
+        (None, None) => {
+            if scheduled_date.is_empty() {
+                return Err(ApiError::bad_request("scheduled_date or scheduled_start/scheduled_end is required"));
+            }
+            Ok((scheduled_date.to_string(), whole_day_window(scheduled_date)))
+        }
+        _ => Err(ApiError::bad_request("scheduled_start and scheduled_end must be set together")),
+    }
+}
+
+/// Окно на весь день `YYYY-MM-DD` (UTC) - так же миграция заполняет старые записи
+pub(crate) fn whole_day_window(date: &str) -> Option<MaintenanceWindow> {
+    let day = NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok()?;
+    let start = day.and_hms_opt(0, 0, 0)?.and_utc();
+    Some((start, start + Duration::days(1)))
+}
+
 /// Получение файлов оборудования (внутренняя функция)
 async fn get_equipment_files_internal(
     pool: &SqlitePool,
     equipment_id: &str,
-) -> ApiResult<Vec<EquipmentFile>> {
+) -> ApiResult<NestedList<EquipmentFile>> {
     let files: Vec<EquipmentFile> = sqlx::query_as(
-        "SELECT * FROM equipment_files WHERE equipment_id = ? ORDER BY created_at DESC"
+        "SELECT * FROM equipment_files WHERE equipment_id = ? ORDER BY created_at DESC LIMIT ?"
     )
         .bind(equipment_id)
+        .bind(MAX_NESTED_LIST_ROWS + 1)
         .fetch_all(pool)
         .await?;
 
-    Ok(files)
+    Ok(NestedList::new(files))
 }
 
 /// Получение файлов запчасти
@@ -1273,26 +2308,141 @@ pub async fn get_part_files(
 ) -> ApiResult<HttpResponse> {
     let (equipment_id, part_id) = path.into_inner();
 
-    check_equipment_exists(&app_state.db_pool, &equipment_id).await?;
+    check_nested_equipment(&app_state.db_pool, &equipment_id, "Equipment part").await?;
+    let part_exists: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM equipment_parts WHERE id = ? AND equipment_id = ?)"
+    )
+        .bind(&part_id)
+        .bind(&equipment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+    if !part_exists {
+        return Err(ApiError::not_found("Equipment part"));
+    }
 
     let files: Vec<EquipmentFile> = sqlx::query_as(
-        "SELECT * FROM equipment_files WHERE equipment_id = ? AND part_id = ? ORDER BY created_at DESC"
+        "SELECT * FROM equipment_files WHERE equipment_id = ? AND part_id = ? ORDER BY created_at DESC LIMIT ?"
     )
         .bind(&equipment_id)
         .bind(&part_id)
+        .bind(MAX_NESTED_LIST_ROWS + 1)
         .fetch_all(&app_state.db_pool)
         .await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(files)))
+    Ok(NestedList::new(files).into_response())
 }
 
-/// Обновление FTS индекса для оборудования
-/// ПРИМЕЧАНИЕ: FTS5 с content table синхронизируется автоматически через триггеры.
-/// Эта функция оставлена для совместимости, но фактически ничего не делает,
-/// так как триггеры equipment_ai/equipment_au/equipment_ad обрабатывают синхронизацию.
-async fn update_equipment_fts(_pool: &SqlitePool, _equipment_id: &str) -> ApiResult<()> {
-    // FTS5 с content='equipment' синхронизируется автоматически через триггеры
-    // Ручное обновление не требуется и может вызвать ошибки
+/// Прямые компоненты сборки (для detail response)
+async fn get_direct_components_internal(
+    pool: &SqlitePool,
+    equipment_id: &str,
+) -> ApiResult<Vec<Equipment>> {
+    let components = sqlx::query_as(
+        "SELECT * FROM equipment WHERE parent_equipment_id = ? ORDER BY name LIMIT ?"
+    )
+        .bind(equipment_id)
+        .bind(MAX_NESTED_LIST_ROWS)
+        .fetch_all(pool)
+        .await?;
+    Ok(components)
+}
+
+/// Сводка обслуживания по сборке: ближайшая дата, просрочки и затраты
+/// по самому оборудованию и всем его компонентам
+async fn get_assembly_maintenance_internal(
+    pool: &SqlitePool,
+    equipment_id: &str,
+) -> ApiResult<AssemblyMaintenanceSummary> {
+    let summary = sqlx::query_as(
+        r#"WITH RECURSIVE tree(id, depth) AS (
+               SELECT ?, 0
+               UNION
+               SELECT e.id, t.depth + 1 FROM equipment e
+               JOIN tree t ON e.parent_equipment_id = t.id
+               WHERE t.depth < ?
+           ),
+           ids AS (SELECT DISTINCT id FROM tree)
+           SELECT
+               (SELECT COUNT(*) FROM ids) - 1 AS components_count,
+               (SELECT MIN(due) FROM (
+                   SELECT next_maintenance AS due FROM equipment
+                   WHERE id IN (SELECT id FROM ids) AND next_maintenance IS NOT NULL
+                   UNION ALL
+                   SELECT scheduled_date FROM equipment_maintenance
+                   WHERE equipment_id IN (SELECT id FROM ids) AND status IN ('scheduled', 'in_progress')
+               )) AS next_maintenance_date,
+               (SELECT COUNT(*) FROM equipment_maintenance
+                WHERE equipment_id IN (SELECT id FROM ids) AND status = 'scheduled') AS scheduled_count,
+               (SELECT COUNT(*) FROM equipment_maintenance
+                WHERE equipment_id IN (SELECT id FROM ids)
+                  AND status IN ('scheduled', 'in_progress')
+                  AND date(scheduled_date) < date('now')) AS overdue_count,
+               (SELECT COALESCE(SUM(cost), 0.0) FROM equipment_maintenance
+                WHERE equipment_id IN (SELECT id FROM ids) AND status != 'cancelled') AS total_maintenance_cost"#
+    )
+        .bind(equipment_id)
+        .bind(MAX_ASSEMBLY_DEPTH)
+        .fetch_one(pool)
+        .await?;
+    Ok(summary)
+}
+
+/// Проверка родителя: существует, не сам объект, не создаёт цикл и не превышает глубину
+async fn validate_parent_equipment(
+    pool: &SqlitePool,
+    equipment_id: Option<&str>,
+    parent_id: &str,
+) -> ApiResult<()> {
+    if equipment_id == Some(parent_id) {
+        return Err(ApiError::bad_request("Equipment cannot be its own parent"));
+    }
+
+    // Цепочка предков родителя (включая его самого)
+    let ancestors: Vec<(String,)> = sqlx::query_as(
+        r#"WITH RECURSIVE ancestors(id, parent_id, depth) AS (
+               SELECT id, parent_equipment_id, 1 FROM equipment WHERE id = ?
+               UNION
+               SELECT e.id, e.parent_equipment_id, a.depth + 1 FROM equipment e
+               JOIN ancestors a ON e.id = a.parent_id
+               WHERE a.depth <= ?
+           )
+           SELECT id FROM ancestors"#
+    )
+        .bind(parent_id)
+        .bind(MAX_ASSEMBLY_DEPTH)
+        .fetch_all(pool)
+        .await?;
+
+    if ancestors.is_empty() {
+        return Err(ApiError::NotFound(format!("Parent equipment with ID '{}' not found", parent_id)));
+    }
+
+    if let Some(id) = equipment_id {
+        if ancestors.iter().any(|(a,)| a == id) {
+            return Err(ApiError::bad_request(
+                "Cannot set parent: equipment would become a component of itself",
+            ));
+        }
+    }
+
+    if ancestors.len() as i64 >= MAX_ASSEMBLY_DEPTH {
+        return Err(ApiError::bad_request(&format!(
+            "Assembly nesting cannot exceed {} levels", MAX_ASSEMBLY_DEPTH
+        )));
+    }
+
+    Ok(())
+}
+
+/// Полная перестройка FTS индекса оборудования (только для admin rebuild).
+/// В обычной работе equipment_fts синхронизируется триггерами equipment_fts_insert/update/delete.
+pub async fn update_equipment_fts(pool: &SqlitePool) -> ApiResult<()> {
+    sqlx::query("INSERT INTO equipment_fts(equipment_fts) VALUES('rebuild')")
+        .execute(pool)
+        .await?;
+    let _ = sqlx::query("INSERT INTO equipment_fts(equipment_fts) VALUES('optimize')")
+        .execute(pool)
+        .await;
     Ok(())
 }
 
@@ -1301,23 +2451,23 @@ fn apply_equipment_filters(
     builder: &mut CountQueryBuilder,
     query: &EquipmentPaginationQuery,
     _whitelist: &FieldWhitelist,
+    viewer: &str,
 ) -> Result<(), ApiError> {
+    // Ожидающее окончательного удаления оборудование скрыто
+    builder.add_condition("pending_deletion_id IS NULL", vec![]);
+
     if let Some(ref search) = query.search {
         if !search.trim().is_empty() {
             builder.add_like("name", search);
         }
     }
 
-    if let Some(ref status) = query.status {
-        builder.add_exact_match("status", status);
+    for (field, values) in equipment_multi_filters(query)? {
+        builder.add_in_clause(field, &values);
     }
 
-    if let Some(ref type_) = query.type_ {
-        builder.add_exact_match("type_", type_);
-    }
-
-    if let Some(ref location) = query.location {
-        builder.add_exact_match("location", location);
+    for (condition, params) in equipment_team_filters(query, viewer) {
+        builder.add_condition(condition, params);
     }
 
     Ok(())
@@ -1326,46 +2476,91 @@ fn apply_equipment_filters(
 fn apply_equipment_filters_safe(
     builder: &mut SafeQueryBuilder,
     query: &EquipmentPaginationQuery,
+    viewer: &str,
 ) -> Result<(), ApiError> {
+    builder.add_condition("pending_deletion_id IS NULL", vec![]);
+
     if let Some(ref search) = query.search {
         if !search.trim().is_empty() {
             builder.add_like("name", search);
         }
     }
 
-    if let Some(ref status) = query.status {
-        builder.add_exact_match("status", status);
+    for (field, values) in equipment_multi_filters(query)? {
+        builder.add_in_clause(field, &values);
     }
 
-    if let Some(ref type_) = query.type_ {
-        builder.add_exact_match("type_", type_);
+    for (condition, params) in equipment_team_filters(query, viewer) {
+        builder.add_condition(condition, params);
     }
 
-    if let Some(ref location) = query.location {
-        builder.add_exact_match("location", location);
+    Ok(())
+}
+
+/// `?team_id=` и `?mine=true` (создано мной или моими командами)
+fn equipment_team_filters(query: &EquipmentPaginationQuery, viewer: &str) -> Vec<(&'static str, Vec<SqlParam>)> {
+    let mut conditions = Vec::new();
+    if let Some(team_id) = crate::team_handlers::normalize_team_id(query.team_id.as_deref()) {
+        conditions.push(("team_id = ?", vec![team_id.into()]));
+    }
+    if query.mine.unwrap_or(false) {
+        conditions.push((crate::team_handlers::MINE_CONDITION, vec![viewer.into(), viewer.into()]));
     }
+    conditions
+}
 
-    Ok(())
+/// Мультивыбор `?status=available,in_use&type=instrument,glassware&location=...` -> (колонка, значения).
+/// status и type проверяются по EquipmentStatus/EquipmentType.
+fn equipment_multi_filters(query: &EquipmentPaginationQuery) -> ApiResult<[(&'static str, Vec<String>); 3]> {
+    use crate::handlers::parse_multi_filter;
+    Ok([
+        ("status", parse_multi_filter(query.status.as_deref(), "status", Some(EquipmentStatus::VARIANTS))?),
+        ("type_", parse_multi_filter(query.type_.as_deref().or(query.legacy_type.as_deref()), "type", Some(EquipmentType::VARIANTS))?),
+        ("location", parse_multi_filter(query.location.as_deref(), "location", None)?),
+    ])
 }
-/// Валидация данных оборудования
-fn validate_equipment_data(equipment: &CreateEquipmentRequest) -> Result<(), ApiError> {
-    if equipment.name.trim().is_empty() {
-        return Err(ApiError::bad_request("Name cannot be empty"));
+/// Проверка формы оборудования для create_equipment и POST /validate/equipment
+pub async fn validate_equipment_request(
+    pool: &SqlitePool,
+    equipment: &CreateEquipmentRequest,
+    existing_id: Option<&str>,
+) -> ApiResult<ValidationResult> {
+    let mut result = ValidationResult::from_validate(equipment);
+    result.merge(equipment.custom_validate());
+
+    if let Some(serial) = equipment.serial_number.as_deref().filter(|s| !s.trim().is_empty()) {
+        let duplicate: Option<(String,)> = sqlx::query_as(
+            "SELECT id FROM equipment WHERE serial_number = ? AND id IS NOT ?"
+        )
+            .bind(serial)
+            .bind(existing_id)
+            .fetch_optional(pool)
+            .await?;
+        if duplicate.is_some() {
+            result.add_error("serial_number", "Equipment with this serial number already exists");
+        }
     }
 
-    if equipment.quantity < 1 {
-        return Err(ApiError::bad_request("Quantity must be at least 1"));
+    let parent_id = equipment.parent_equipment_id.as_deref()
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
+    if let Some(parent_id) = parent_id {
+        match validate_parent_equipment(pool, existing_id, parent_id).await {
+            Ok(()) => {}
+            Err(ApiError::NotFound(message)) | Err(ApiError::BadRequest(message)) => {
+                result.add_error("parent_equipment_id", message);
+            }
+            Err(e) => return Err(e),
+        }
     }
 
-    // FIXED: FromStr trait is now in scope
-    if EquipmentType::from_str(&equipment.type_).is_err() {
-        return Err(ApiError::bad_request(&format!(
-            "Invalid type: {}. Valid: instrument, glassware, safety, storage, consumable, other",
-            equipment.type_
-        )));
+    match crate::team_handlers::ensure_team_exists(pool, crate::team_handlers::normalize_team_id(equipment.team_id.as_deref())).await {
+        Ok(()) => {}
+        Err(ApiError::NotFound(message)) => result.add_error("team_id", message),
+        Err(e) => return Err(e),
     }
 
-    Ok(())
+    Ok(result)
 }
 
 // ==================== ВСПОМОГАТЕЛЬНЫЕ СТРУКТУРЫ ====================
@@ -1408,4 +2603,1342 @@ mod tests {
         assert!(!valid_statuses.contains(&"invalid"));
         assert!(!valid_statuses.contains(&"available")); // Old value - should fail
     }
+
+    async fn fts_app_state() -> web::Data<Arc<AppState>> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('tester', 'tester', 'tester@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    async fn fts_names(pool: &SqlitePool, term: &str) -> Vec<String> {
+        sqlx::query_scalar(
+            "SELECT e.name FROM equipment_fts JOIN equipment e ON e.rowid = equipment_fts.rowid \
+             WHERE equipment_fts MATCH ? ORDER BY e.name"
+        )
+            .bind(FtsQueryBuilder::build_fts_query(term))
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_equipment_fts_follows_handler_mutations() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+
+        let request: CreateEquipmentRequest = serde_json::from_value(serde_json::json!({
+            "name": "Centrifuge Eppendorf",
+            "type_": "instrument",
+            "quantity": 1,
+            "model": "5810R",
+        })).unwrap();
+        create_equipment(app_state.clone(), web::Json(request), "tester".to_string(), ApiVersion::LATEST).await.unwrap();
+        assert_eq!(fts_names(&pool, "centrifuge").await, vec!["Centrifuge Eppendorf"]);
+        assert_eq!(fts_names(&pool, "5810R").await, vec!["Centrifuge Eppendorf"]);
+
+        let id: String = sqlx::query_scalar("SELECT id FROM equipment WHERE name = 'Centrifuge Eppendorf'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let update: UpdateEquipmentRequest = serde_json::from_value(serde_json::json!({
+            "name": "Microcentrifuge",
+        })).unwrap();
+        update_equipment(app_state.clone(), web::Path::from(id.clone()), web::Json(update), "tester".to_string(), false, ApiVersion::LATEST)
+            .await
+            .unwrap();
+        assert!(fts_names(&pool, "centrifuge").await.is_empty());
+        assert_eq!(fts_names(&pool, "microcentrifuge").await, vec!["Microcentrifuge"]);
+
+        delete_equipment(app_state.clone(), web::Path::from(id)).await.unwrap();
+        assert!(fts_names(&pool, "microcentrifuge").await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_equipment_fts_follows_import_and_bulk_updates() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+
+        let items: Vec<crate::import_export::EquipmentImportDto> = serde_json::from_value(serde_json::json!([
+            { "name": "Spectrophotometer", "type": "instrument", "serial_number": "SP-001", "location": "Room 12" },
+            { "name": "Fume hood", "type": "safety", "serial_number": "FH-002", "location": "Room 12" },
+        ])).unwrap();
+        crate::import_export::import_equipment(app_state.clone(), web::Json(items)).await.unwrap();
+        assert_eq!(fts_names(&pool, "spectrophotometer").await, vec!["Spectrophotometer"]);
+        assert_eq!(fts_names(&pool, "room").await.len(), 2);
+
+        // Повторный импорт с тем же serial_number идёт через ON CONFLICT DO UPDATE
+        let items: Vec<crate::import_export::EquipmentImportDto> = serde_json::from_value(serde_json::json!([
+            { "name": "UV-Vis spectrometer", "type": "instrument", "serial_number": "SP-001" },
+        ])).unwrap();
+        crate::import_export::import_equipment(app_state.clone(), web::Json(items)).await.unwrap();
+        assert!(fts_names(&pool, "spectrophotometer").await.is_empty());
+        assert_eq!(fts_names(&pool, "spectrometer").await, vec!["UV-Vis spectrometer"]);
+
+        // Массовые операции напрямую через SQL
+        sqlx::query("UPDATE equipment SET location = 'Storage B' WHERE location = 'Room 12'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(fts_names(&pool, "room").await.is_empty());
+        assert_eq!(fts_names(&pool, "storage").await.len(), 2);
+
+        // Изменение неиндексируемых колонок не должно ломать индекс
+        sqlx::query("UPDATE equipment SET status = 'maintenance', updated_at = datetime('now')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(fts_names(&pool, "storage").await.len(), 2);
+
+        sqlx::query("DELETE FROM equipment WHERE type_ = 'safety'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(fts_names(&pool, "storage").await, vec!["UV-Vis spectrometer"]);
+
+        // Admin rebuild даёт тот же результат
+        update_equipment_fts(&pool).await.unwrap();
+        assert_eq!(fts_names(&pool, "storage").await, vec!["UV-Vis spectrometer"]);
+    }
+
+    #[actix_web::test]
+    async fn test_reagent_fts_follows_mutations() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+
+        let reagent_names = |term: &'static str| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, String>(
+                    "SELECT name FROM reagents WHERE rowid IN \
+                     (SELECT rowid FROM reagents_fts WHERE reagents_fts MATCH ?) ORDER BY name"
+                )
+                    .bind(FtsQueryBuilder::build_fts_query(term))
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        sqlx::query(
+            "INSERT INTO reagents (id, name, formula, cas_number, status, created_at, updated_at) \
+             VALUES ('r1', 'Sodium chloride', 'NaCl', '7647-14-5', 'active', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        assert_eq!(reagent_names("sodium").await, vec!["Sodium chloride"]);
+
+        // Обновление кэш-полей не затрагивает индекс
+        sqlx::query("UPDATE reagents SET total_quantity = 5.0, updated_at = datetime('now')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(reagent_names("NaCl").await, vec!["Sodium chloride"]);
+
+        sqlx::query("UPDATE reagents SET name = 'Potassium chloride', formula = 'KCl' WHERE id = 'r1'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(reagent_names("sodium").await.is_empty());
+        assert_eq!(reagent_names("KCl").await, vec!["Potassium chloride"]);
+
+        sqlx::query("DELETE FROM reagents WHERE id = 'r1'").execute(&pool).await.unwrap();
+        assert!(reagent_names("potassium").await.is_empty());
+    }
+
+    async fn create_test_equipment(app_state: &web::Data<Arc<AppState>>, name: &str, parent: Option<&str>) -> String {
+        let request: CreateEquipmentRequest = serde_json::from_value(serde_json::json!({
+            "name": name,
+            "type": "instrument",
+            "quantity": 1,
+            "parent_equipment_id": parent,
+        })).unwrap();
+        create_equipment(app_state.clone(), web::Json(request), "tester".to_string(), ApiVersion::LATEST).await.unwrap();
+        sqlx::query_scalar("SELECT id FROM equipment WHERE name = ?")
+            .bind(name)
+            .fetch_one(&app_state.db_pool)
+            .await
+            .unwrap()
+    }
+
+    async fn response_json(response: HttpResponse) -> serde_json::Value {
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_equipment_listing_ignores_hostile_sort_params() {
+        let app_state = fts_app_state().await;
+        create_test_equipment(&app_state, "Balance", None).await;
+        create_test_equipment(&app_state, "Centrifuge", None).await;
+
+        let list_names = |sort_by: &'static str, sort_order: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let query = EquipmentPaginationQuery {
+                    page: None,
+                    per_page: None,
+                    search: None,
+                    status: None,
+                    type_: None,
+                    legacy_type: None,
+                    location: None,
+                    team_id: None,
+                    mine: None,
+                    sort_by: Some(sort_by.to_string()),
+                    sort_order: Some(sort_order.to_string()),
+                    fields: None,
+                    favorites_first: None,
+                    strict: None,
+                };
+                let response = get_equipment(app_state, web::Query(query), "tester".to_string(), ApiVersion::LATEST).await.unwrap();
+                let json = response_json(response).await;
+                json["data"]["data"].as_array().unwrap()
+                    .iter()
+                    .map(|e| e["name"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(list_names("name", "asc").await, vec!["Balance", "Centrifuge"]);
+        assert_eq!(list_names("name", "ASC; DROP TABLE equipment").await, vec!["Centrifuge", "Balance"]);
+
+        for field in [
+            "name; DROP TABLE equipment",
+            "x; DROP TABLE equipment.name",
+            "(SELECT password_hash FROM users)",
+            "users.password_hash",
+        ] {
+            assert_eq!(list_names(field, "ASC").await.len(), 2);
+        }
+
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM equipment")
+            .fetch_one(&app_state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_equipment_listing_multi_select_filters() {
+        let app_state = fts_app_state().await;
+        for name in ["Balance", "Centrifuge", "Oven"] {
+            create_test_equipment(&app_state, name, None).await;
+        }
+        sqlx::query("UPDATE equipment SET status = 'in_use', location = 'Lab 2' WHERE name = 'Centrifuge'")
+            .execute(&app_state.db_pool).await.unwrap();
+        sqlx::query("UPDATE equipment SET status = 'damaged', location = 'Lab 3' WHERE name = 'Oven'")
+            .execute(&app_state.db_pool).await.unwrap();
+
+        let list = |filters: serde_json::Value| {
+            let app_state = app_state.clone();
+            async move {
+                let query: EquipmentPaginationQuery = serde_json::from_value(filters).unwrap();
+                get_equipment(app_state, web::Query(query), "tester".to_string(), ApiVersion::LATEST).await
+            }
+        };
+        let names = |response: HttpResponse| async move {
+            let json = response_json(response).await;
+            assert_eq!(json["data"]["total"], json["data"]["data"].as_array().unwrap().len());
+            let mut names: Vec<String> = json["data"]["data"].as_array().unwrap()
+                .iter()
+                .map(|e| e["name"].as_str().unwrap().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let response = list(serde_json::json!({ "status": "available, in_use" })).await.unwrap();
+        assert_eq!(names(response).await, vec!["Balance", "Centrifuge"]);
+        let response = list(serde_json::json!({ "status": "in_use,damaged", "location": "Lab 3" })).await.unwrap();
+        assert_eq!(names(response).await, vec!["Oven"]);
+        let response = list(serde_json::json!({ "type": "instrument,glassware", "status": "" })).await.unwrap();
+        assert_eq!(names(response).await.len(), 3);
+
+        // Неизвестное значение - 400 со списком допустимых, а не полная выборка
+        let err = list(serde_json::json!({ "status": "available,broken" })).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref m) if m.contains("broken") && m.contains("calibration")), "{:?}", err);
+        let err = list(serde_json::json!({ "type": "spaceship" })).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref m) if m.contains("glassware")), "{:?}", err);
+    }
+
+    #[actix_web::test]
+    async fn test_equipment_type_accepts_legacy_spelling_with_deprecation_header() {
+        let app_state = fts_app_state().await;
+        let deprecated = |response: &HttpResponse| {
+            response.headers().get(crate::api_version::DEPRECATED_FIELDS_HEADER)
+                .map(|v| v.to_str().unwrap().to_string())
+        };
+
+        let create = |body: serde_json::Value| {
+            let app_state = app_state.clone();
+            async move {
+                let request: CreateEquipmentRequest = serde_json::from_value(body).unwrap();
+                create_equipment(app_state, web::Json(request), "tester".to_string(), ApiVersion::LATEST).await.unwrap()
+            }
+        };
+        let response = create(serde_json::json!({ "name": "Balance", "type": "instrument", "quantity": 1 })).await;
+        assert_eq!(deprecated(&response), None);
+        let response = create(serde_json::json!({ "name": "Flask", "type_": "glassware", "quantity": 1 })).await;
+        assert_eq!(deprecated(&response).as_deref(), Some(r#"type_; use="type""#));
+
+        let flask_id: String = sqlx::query_scalar("SELECT id FROM equipment WHERE name = 'Flask'")
+            .fetch_one(&app_state.db_pool).await.unwrap();
+        let update = |body: serde_json::Value| {
+            let app_state = app_state.clone();
+            let id = flask_id.clone();
+            async move {
+                let request: UpdateEquipmentRequest = serde_json::from_value(body).unwrap();
+                update_equipment(app_state, web::Path::from(id), web::Json(request), "tester".to_string(), false, ApiVersion::LATEST).await
+            }
+        };
+        let response = update(serde_json::json!({ "type": "storage" })).await.unwrap();
+        assert_eq!(deprecated(&response), None);
+        let response = update(serde_json::json!({ "type_": "consumable" })).await.unwrap();
+        assert_eq!(deprecated(&response).as_deref(), Some(r#"type_; use="type""#));
+        let stored: String = sqlx::query_scalar("SELECT type_ FROM equipment WHERE id = ?")
+            .bind(&flask_id).fetch_one(&app_state.db_pool).await.unwrap();
+        assert_eq!(stored, "consumable");
+        assert!(matches!(update(serde_json::json!({ "type": "spaceship" })).await, Err(ApiError::BadRequest(_))));
+
+        let list = |filters: serde_json::Value| {
+            let app_state = app_state.clone();
+            async move {
+                let query: EquipmentPaginationQuery = serde_json::from_value(filters).unwrap();
+                get_equipment(app_state, web::Query(query), "tester".to_string(), ApiVersion::V1).await.unwrap()
+            }
+        };
+        for (filters, legacy) in [
+            (serde_json::json!({ "type": "instrument" }), false),
+            (serde_json::json!({ "type_": "instrument" }), true),
+        ] {
+            let response = list(filters).await;
+            assert_eq!(deprecated(&response).is_some(), legacy);
+            let json = response_json(response).await;
+            assert_eq!(json["data"]["total"], 1);
+            assert_eq!(json["data"]["data"][0]["name"], "Balance");
+        }
+
+        // Сортировка и ?fields= понимают оба имени; v1 по-прежнему отдаёт `type_`
+        for (sort_by, legacy) in [("type", false), ("type_", true)] {
+            let response = list(serde_json::json!({ "sort_by": sort_by, "sort_order": "asc", "fields": "name,type" })).await;
+            assert_eq!(deprecated(&response).is_some(), legacy);
+            let json = response_json(response).await;
+            let rows = json["data"]["data"].as_array().unwrap();
+            assert_eq!(rows[0]["name"], "Flask");
+            assert_eq!(rows[0]["type_"], "consumable");
+            assert!(rows[0].get("type").is_none());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_restricted_equipment_in_use_requires_role() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) VALUES \
+             ('viewer', 'viewer', 'viewer@example.com', 'x', 'viewer', datetime('now'), datetime('now')), \
+             ('chemist', 'chemist', 'chemist@example.com', 'x', 'researcher', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        let request: CreateEquipmentRequest = serde_json::from_value(serde_json::json!({
+            "name": "Glovebox", "type": "instrument", "quantity": 1, "restricted_to_roles": "Researcher, researcher"
+        })).unwrap();
+        create_equipment(app_state.clone(), web::Json(request), "tester".to_string(), ApiVersion::LATEST).await.unwrap();
+        let (id, roles): (String, Option<String>) = sqlx::query_as("SELECT id, restricted_to_roles FROM equipment")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(roles.as_deref(), Some("researcher"));
+
+        let update = |body: serde_json::Value, user: &str| {
+            let app_state = app_state.clone();
+            let id = id.clone();
+            let user = user.to_string();
+            async move {
+                let request: UpdateEquipmentRequest = serde_json::from_value(body).unwrap();
+                update_equipment(app_state, web::Path::from(id), web::Json(request), user, false, ApiVersion::LATEST).await
+            }
+        };
+        let err = update(serde_json::json!({ "status": "in_use" }), "viewer").await.unwrap_err();
+        assert!(
+            matches!(err, ApiError::Forbidden(ref m) if m.contains("restricted to roles: researcher (current role: viewer)")),
+            "{:?}", err
+        );
+        // Прочие изменения ограничение не затрагивает
+        update(serde_json::json!({ "location": "Lab 2" }), "viewer").await.unwrap();
+        update(serde_json::json!({ "status": "in_use" }), "chemist").await.unwrap();
+        assert!(matches!(
+            update(serde_json::json!({ "restricted_to_roles": "janitor" }), "tester").await,
+            Err(ApiError::ValidationError(_))
+        ));
+
+        // Пустая строка снимает ограничение
+        update(serde_json::json!({ "restricted_to_roles": "", "status": "available" }), "tester").await.unwrap();
+        update(serde_json::json!({ "status": "in_use" }), "viewer").await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_equipment_assembly_hierarchy() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+
+        let hplc = create_test_equipment(&app_state, "HPLC System", None).await;
+        let pump = create_test_equipment(&app_state, "Pump", Some(&hplc)).await;
+        let detector = create_test_equipment(&app_state, "Detector", Some(&hplc)).await;
+        let seal = create_test_equipment(&app_state, "Pump seal kit", Some(&pump)).await;
+
+        // Циклы запрещены: сборка не может стать компонентом своего компонента
+        let err = validate_parent_equipment(&pool, Some(&hplc), &seal).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+        let err = validate_parent_equipment(&pool, Some(&pump), &pump).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+        let err = validate_parent_equipment(&pool, None, "missing").await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+
+        let direct = get_equipment_components(
+            app_state.clone(),
+            web::Path::from(hplc.clone()),
+            web::Query(ComponentsQuery { recursive: None }),
+        ).await.unwrap();
+        let direct = response_json(direct).await;
+        assert_eq!(direct["data"].as_array().unwrap().len(), 2);
+
+        let all = get_equipment_components(
+            app_state.clone(),
+            web::Path::from(hplc.clone()),
+            web::Query(ComponentsQuery { recursive: Some(true) }),
+        ).await.unwrap();
+        let all = response_json(all).await;
+        let all = all["data"].as_array().unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[2]["name"], "Pump seal kit");
+        assert_eq!(all[2]["depth"], 2);
+
+        // Обслуживание компонентов агрегируется в родителе
+        for (id, date, cost, status) in [
+            (&pump, "2020-01-15", None, "scheduled"),
+            (&detector, "2099-03-01", None, "scheduled"),
+            (&seal, "2023-05-01", Some(120.5), "completed"),
+        ] {
+            sqlx::query(
+                "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, status, scheduled_date, cost, created_at, updated_at) \
+                 VALUES (?, ?, 'inspection', ?, ?, ?, datetime('now'), datetime('now'))"
+            )
+                .bind(Uuid::new_v4().to_string())
+                .bind(id)
+                .bind(status)
+                .bind(date)
+                .bind(cost)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let detail = response_json(
+            get_equipment_by_id(app_state.clone(), web::Path::from(hplc.clone()), ApiVersion::LATEST).await.unwrap()
+        ).await;
+        let summary = &detail["data"]["assembly_maintenance"];
+        assert_eq!(detail["data"]["components"].as_array().unwrap().len(), 2);
+        assert_eq!(summary["components_count"], 3);
+        assert_eq!(summary["next_maintenance_date"], "2020-01-15");
+        assert_eq!(summary["scheduled_count"], 2);
+        assert_eq!(summary["overdue_count"], 1);
+        assert_eq!(summary["total_maintenance_cost"], 120.5);
+
+        let leaf = response_json(
+            get_equipment_by_id(app_state.clone(), web::Path::from(seal.clone()), ApiVersion::LATEST).await.unwrap()
+        ).await;
+        assert!(leaf["data"]["assembly_maintenance"].is_null());
+
+        // Каскадная смена статуса
+        let update: UpdateEquipmentRequest = serde_json::from_value(serde_json::json!({ "status": "damaged" })).unwrap();
+        update_equipment(app_state.clone(), web::Path::from(hplc.clone()), web::Json(update), "tester".to_string(), true, ApiVersion::LATEST)
+            .await
+            .unwrap();
+        let damaged: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM equipment WHERE status = 'damaged'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(damaged, 4);
+
+        let update: UpdateEquipmentRequest = serde_json::from_value(serde_json::json!({ "status": "available" })).unwrap();
+        update_equipment(app_state.clone(), web::Path::from(pump.clone()), web::Json(update), "tester".to_string(), false, ApiVersion::LATEST)
+            .await
+            .unwrap();
+        let seal_status: String = sqlx::query_scalar("SELECT status FROM equipment WHERE id = ?")
+            .bind(&seal)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(seal_status, "damaged");
+
+        // Экспорт показывает дерево сборки
+        let export = response_json(crate::import_export::export_equipment(app_state.clone(), web::Query(Default::default())).await.unwrap()).await;
+        let paths: Vec<&str> = export.as_array().unwrap().iter()
+            .map(|row| row["assembly_path"].as_str().unwrap())
+            .collect();
+        assert_eq!(paths, vec![
+            "HPLC System",
+            "HPLC System / Detector",
+            "HPLC System / Pump",
+            "HPLC System / Pump / Pump seal kit",
+        ]);
+
+        // Отвязка пустой строкой и удаление родителя
+        let update: UpdateEquipmentRequest = serde_json::from_value(serde_json::json!({ "parent_equipment_id": "" })).unwrap();
+        update_equipment(app_state.clone(), web::Path::from(detector.clone()), web::Json(update), "tester".to_string(), false, ApiVersion::LATEST)
+            .await
+            .unwrap();
+        delete_equipment(app_state.clone(), web::Path::from(hplc)).await.unwrap();
+        let orphans: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM equipment WHERE parent_equipment_id IS NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(orphans, 2);
+    }
+
+    #[actix_web::test]
+    async fn test_part_linked_to_batch_reads_and_consumes_through_batch() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let hplc = create_test_equipment(&app_state, "HPLC System", None).await;
+
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'C18 column', 'active', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, status, received_date, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'COL-2024', 5, 5, 'pcs', 'available', datetime('now'), datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        // Связь с партией и собственный остаток одновременно запрещены
+        let both: CreateEquipmentPartRequest = serde_json::from_value(serde_json::json!({
+            "name": "Column", "quantity": 3, "linked_batch_id": "b1",
+        })).unwrap();
+        let err = add_equipment_part(app_state.clone(), web::Path::from(hplc.clone()), web::Json(both), "tester".to_string())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        let linked: CreateEquipmentPartRequest = serde_json::from_value(serde_json::json!({
+            "name": "Column", "linked_batch_id": "b1",
+        })).unwrap();
+        let created = response_json(
+            add_equipment_part(app_state.clone(), web::Path::from(hplc.clone()), web::Json(linked), "tester".to_string())
+                .await
+                .unwrap()
+        ).await;
+        let column_id = created["data"]["id"].as_str().unwrap().to_string();
+        assert_eq!(created["data"]["quantity"], 5);
+        assert_eq!(created["data"]["linked_batch"]["batch_number"], "COL-2024");
+
+        let filters: CreateEquipmentPartRequest = serde_json::from_value(serde_json::json!({
+            "name": "Inlet filter", "quantity": 4,
+        })).unwrap();
+        let filters = response_json(
+            add_equipment_part(app_state.clone(), web::Path::from(hplc.clone()), web::Json(filters), "tester".to_string())
+                .await
+                .unwrap()
+        ).await;
+        let filter_id = filters["data"]["id"].as_str().unwrap().to_string();
+
+        let edit: UpdateEquipmentPartRequest = serde_json::from_value(serde_json::json!({ "quantity": 10 })).unwrap();
+        let err = update_equipment_part(
+            app_state.clone(), web::Path::from((hplc.clone(), column_id.clone())), web::Json(edit), "tester".to_string(),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        // Обслуживание расходует колонку из партии и фильтры из собственного остатка
+        let maintenance_id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, status, scheduled_date, created_at, updated_at) \
+             VALUES (?, ?, 'replacement', 'scheduled', '2024-05-01', datetime('now'), datetime('now'))"
+        )
+            .bind(&maintenance_id)
+            .bind(&hplc)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let body: CompleteMaintenanceRequest = serde_json::from_value(serde_json::json!({
+            "consumed_parts": [
+                { "part_id": column_id, "quantity": 2 },
+                { "part_id": filter_id, "quantity": 1 },
+            ],
+        })).unwrap();
+        let completed = response_json(
+            complete_maintenance(
+                app_state.clone(), web::Path::from((hplc.clone(), maintenance_id.clone())), web::Json(body), "tester".to_string(),
+            ).await.unwrap()
+        ).await;
+        assert_eq!(completed["data"]["status"], "completed");
+        assert_eq!(completed["data"]["consumed_parts"][0]["batch_id"], "b1");
+        assert_eq!(completed["data"]["consumed_parts"][0]["remaining_quantity"], 3.0);
+        assert!(completed["data"]["consumed_parts"][1]["batch_id"].is_null());
+
+        let batch_quantity: f64 = sqlx::query_scalar("SELECT quantity FROM batches WHERE id = 'b1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(batch_quantity, 3.0);
+        let usage: (f64, String) = sqlx::query_as("SELECT quantity_used, purpose FROM usage_logs WHERE batch_id = 'b1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(usage, (2.0, "Maintenance: replacement".to_string()));
+
+        let parts = get_equipment_parts_internal(&pool, &hplc).await.unwrap().rows;
+        let column = parts.iter().find(|p| p.id == column_id).unwrap();
+        let filter = parts.iter().find(|p| p.id == filter_id).unwrap();
+        assert_eq!(column.quantity, 3);
+        assert_eq!(filter.quantity, 3);
+
+        // Повторное завершение не списывает второй раз
+        let again: CompleteMaintenanceRequest = serde_json::from_value(serde_json::json!({
+            "consumed_parts": [{ "part_id": column_id, "quantity": 1 }],
+        })).unwrap();
+        assert!(complete_maintenance(
+            app_state.clone(), web::Path::from((hplc.clone(), maintenance_id)), web::Json(again), "tester".to_string(),
+        ).await.is_err());
+
+        let linked_parts = crate::batch_handlers::fetch_linked_parts(&pool, "b1").await.unwrap();
+        assert_eq!(linked_parts.len(), 1);
+        assert_eq!(linked_parts[0].equipment_name, "HPLC System");
+
+        // Отвязка фиксирует последний остаток партии
+        let unlink: UpdateEquipmentPartRequest = serde_json::from_value(serde_json::json!({ "linked_batch_id": "" })).unwrap();
+        let unlinked = response_json(
+            update_equipment_part(
+                app_state.clone(), web::Path::from((hplc.clone(), column_id)), web::Json(unlink), "tester".to_string(),
+            ).await.unwrap()
+        ).await;
+        assert!(unlinked["data"]["linked_batch_id"].is_null());
+        assert_eq!(unlinked["data"]["quantity"], 3);
+        assert!(crate::batch_handlers::fetch_linked_parts(&pool, "b1").await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_maintenance_window_blocks_equipment_and_lists_upcoming() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let hplc = create_test_equipment(&app_state, "HPLC System", None).await;
+        let hood = create_test_equipment(&app_state, "Fume hood", None).await;
+        sqlx::query("UPDATE equipment SET location = 'Room 12' WHERE id = ?").bind(&hplc).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO rooms (id, name, status, created_at, updated_at) \
+             VALUES ('room12', 'room 12', 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        let now = Utc::now();
+        let maintenance = |start: DateTime<Utc>, end: DateTime<Utc>| -> CreateMaintenanceRequest {
+            serde_json::from_value(serde_json::json!({
+                "maintenance_type": "calibration",
+                "scheduled_start": start,
+                "scheduled_end": end,
+            })).unwrap()
+        };
+
+        // Конец окна раньше начала
+        let err = create_maintenance(
+            app_state.clone(), web::Path::from(hplc.clone()),
+            web::Json(maintenance(now, now - Duration::hours(1))), "tester".to_string(),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        let active = response_json(
+            create_maintenance(
+                app_state.clone(), web::Path::from(hplc.clone()),
+                web::Json(maintenance(now - Duration::hours(1), now + Duration::hours(2))), "tester".to_string(),
+            ).await.unwrap()
+        ).await;
+        assert_eq!(active["data"]["scheduled_date"], (now - Duration::hours(1)).format("%Y-%m-%d").to_string());
+
+        // Во время окна оборудование нельзя взять в работу
+        let in_use: UpdateEquipmentRequest = serde_json::from_value(serde_json::json!({ "status": "in_use" })).unwrap();
+        let err = update_equipment(app_state.clone(), web::Path::from(hplc.clone()), web::Json(in_use), "tester".to_string(), false, ApiVersion::LATEST)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+        let detail = response_json(get_equipment_by_id(app_state.clone(), web::Path::from(hplc.clone()), ApiVersion::LATEST).await.unwrap()).await;
+        assert_eq!(detail["data"]["active_maintenance"]["id"], active["data"]["id"]);
+
+        create_maintenance(
+            app_state.clone(), web::Path::from(hood.clone()),
+            web::Json(maintenance(now + Duration::days(3), now + Duration::days(3) + Duration::hours(4))), "tester".to_string(),
+        ).await.unwrap();
+
+        // Запись только с датой блокирует весь день
+        let dated: CreateMaintenanceRequest = serde_json::from_value(serde_json::json!({
+            "maintenance_type": "inspection",
+            "scheduled_date": "2031-05-04",
+        })).unwrap();
+        let dated = response_json(
+            create_maintenance(app_state.clone(), web::Path::from(hood.clone()), web::Json(dated), "tester".to_string())
+                .await
+                .unwrap()
+        ).await;
+        let start: DateTime<Utc> = serde_json::from_value(dated["data"]["scheduled_start"].clone()).unwrap();
+        let end: DateTime<Utc> = serde_json::from_value(dated["data"]["scheduled_end"].clone()).unwrap();
+        assert_eq!(start.to_rfc3339(), "2031-05-04T00:00:00+00:00");
+        assert_eq!(end - start, Duration::days(1));
+
+        let upcoming = |params: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let query = web::Query::<UpcomingMaintenanceQuery>::from_query(params).unwrap();
+                response_json(get_upcoming_maintenance(app_state, query).await.unwrap()).await["data"]
+                    .as_array()
+                    .unwrap()
+                    .clone()
+            }
+        };
+
+        let all = upcoming("days=7").await;
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0]["equipment_name"], "HPLC System");
+        assert_eq!(all[0]["is_active"], true);
+        assert_eq!(all[0]["seconds_until_start"], 0);
+        assert_eq!(all[1]["equipment_name"], "Fume hood");
+        let remaining = all[1]["seconds_until_start"].as_i64().unwrap();
+        assert!((3 * 86400 - 60..=3 * 86400).contains(&remaining));
+
+        let in_room = upcoming("room_id=room12").await;
+        assert_eq!(in_room.len(), 1);
+        assert_eq!(in_room[0]["equipment_name"], "HPLC System");
+        assert!(upcoming("days=7&type=glassware").await.is_empty());
+        assert_eq!(upcoming("days=7&type=instrument").await.len(), 2);
+
+        // Миграция заполняет окно для старых записей
+        sqlx::query(
+            "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, status, scheduled_date, created_at, updated_at) \
+             VALUES ('legacy', ?, 'cleaning', 'scheduled', '2030-01-15', datetime('now'), datetime('now'))"
+        ).bind(&hood).execute(&pool).await.unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        let legacy: EquipmentMaintenance = sqlx::query_as("SELECT * FROM equipment_maintenance WHERE id = 'legacy'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(legacy.scheduled_start.unwrap().to_rfc3339(), "2030-01-15T00:00:00+00:00");
+        assert_eq!(legacy.scheduled_end.unwrap().to_rfc3339(), "2030-01-16T00:00:00+00:00");
+    }
+
+    #[actix_web::test]
+    async fn test_maintenance_reminder_lead_times() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let hood = create_test_equipment(&app_state, "Fume hood", None).await;
+        let in_days = |days: i64| (Utc::now() + Duration::days(days)).format("%Y-%m-%d").to_string();
+
+        let create = |body: serde_json::Value| {
+            let app_state = app_state.clone();
+            let hood = hood.clone();
+            async move {
+                let request: CreateMaintenanceRequest = serde_json::from_value(body).unwrap();
+                response_json(
+                    create_maintenance(app_state, web::Path::from(hood), web::Json(request), "tester".to_string())
+                        .await
+                        .unwrap()
+                ).await["data"].clone()
+            }
+        };
+        // Срок по умолчанию - из конфигурации для типа: уборка за день, калибровка за две недели
+        let cleaning = create(serde_json::json!({ "maintenance_type": "cleaning", "scheduled_date": in_days(2) })).await;
+        assert_eq!(cleaning["reminder_lead_days"], 1);
+        let calibration = create(serde_json::json!({ "maintenance_type": "calibration", "scheduled_date": in_days(10) })).await;
+        assert_eq!(calibration["reminder_lead_days"], 14);
+        let inspection = create(serde_json::json!({
+            "maintenance_type": "inspection", "scheduled_date": in_days(5), "reminder_lead_days": 10,
+        })).await;
+        let (cleaning_id, inspection_id) = (cleaning["id"].as_str().unwrap(), inspection["id"].as_str().unwrap());
+
+        let query = web::Query::<UpcomingMaintenanceQuery>::from_query("use_lead_times=true").unwrap();
+        let upcoming = response_json(get_upcoming_maintenance(app_state.clone(), query).await.unwrap()).await;
+        let types: Vec<_> = upcoming["data"].as_array().unwrap().iter()
+            .map(|m| (m["maintenance_type"].as_str().unwrap().to_string(), m["effective_reminder_lead_days"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(types, vec![("inspection".to_string(), 10), ("calibration".to_string(), 14)]);
+        let both = web::Query::<UpcomingMaintenanceQuery>::from_query("use_lead_times=true&days=3").unwrap();
+        assert!(matches!(get_upcoming_maintenance(app_state.clone(), both).await.unwrap_err(), ApiError::BadRequest(_)));
+
+        let maintenance_config = &app_state.config.maintenance;
+        crate::alert_handlers::refresh_alerts(&pool, maintenance_config).await.unwrap();
+        let due: Vec<(String, String)> = sqlx::query_as(
+            "SELECT entity_id, context FROM alerts WHERE kind = 'maintenance_due' AND status = 'open'"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, inspection_id);
+        let context: serde_json::Value = serde_json::from_str(&due[0].1).unwrap();
+        assert_eq!(context["reminder_lead_days"], 10);
+        assert_eq!(context["remind_from"], in_days(-5));
+        let calibration_alerts: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM alerts WHERE kind = 'calibration_due' AND entity_id = ? AND status = 'open'"
+        ).bind(&hood).fetch_one(&pool).await.unwrap();
+        assert_eq!(calibration_alerts, 1);
+
+        // Перенос даты сразу пересчитывает напоминания, окно на весь день переезжает вместе с датой
+        let update = |id: &str, body: serde_json::Value| {
+            let app_state = app_state.clone();
+            let path = web::Path::from((hood.clone(), id.to_string()));
+            async move {
+                let request: UpdateMaintenanceRequest = serde_json::from_value(body).unwrap();
+                response_json(update_maintenance(app_state, path, web::Json(request), "tester".to_string()).await.unwrap()).await
+            }
+        };
+        let moved = update(cleaning_id, serde_json::json!({ "scheduled_date": in_days(1) })).await;
+        assert!(moved["data"]["scheduled_start"].as_str().unwrap().starts_with(&in_days(1)), "{}", moved);
+        update(inspection_id, serde_json::json!({ "scheduled_date": in_days(30) })).await;
+        let open: Vec<String> = sqlx::query_scalar(
+            "SELECT entity_id FROM alerts WHERE kind = 'maintenance_due' AND status = 'open'"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(open, vec![cleaning_id.to_string()]);
+    }
+
+    #[actix_web::test]
+    async fn test_maintenance_sla_escalation() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u-tech', 'tech', 'tech@example.com', 'x', 'researcher', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        let hood = create_test_equipment(&app_state, "Fume hood", None).await;
+        let in_days = |days: i64| (Utc::now() + Duration::days(days)).format("%Y-%m-%d").to_string();
+        let create = |body: serde_json::Value| {
+            let app_state = app_state.clone();
+            let hood = hood.clone();
+            async move {
+                let request: CreateMaintenanceRequest = serde_json::from_value(body).unwrap();
+                create_maintenance(app_state, web::Path::from(hood), web::Json(request), "tester".to_string()).await
+            }
+        };
+
+        // SLA ремонта по умолчанию - 2 дня: просрочен на 2 дня, ступень 1 (ответственный)
+        let repair = response_json(create(serde_json::json!({
+            "maintenance_type": "repair", "scheduled_date": in_days(-4), "assigned_to": "u-tech",
+        })).await.unwrap()).await["data"].clone();
+        assert_eq!(repair["sla_days"], 2);
+        // Свой SLA в 1 день: просрочен на 4 дня - уже больше ещё одного SLA, ступень 2 (администраторы)
+        let cleaning = response_json(create(serde_json::json!({
+            "maintenance_type": "cleaning", "scheduled_date": in_days(-5), "sla_days": 1,
+        })).await.unwrap()).await["data"].clone();
+        // Проверка в пределах SLA (7 дней) - не просрочена
+        create(serde_json::json!({ "maintenance_type": "inspection", "scheduled_date": in_days(-3) })).await.unwrap();
+        let unknown = create(serde_json::json!({
+            "maintenance_type": "repair", "scheduled_date": in_days(1), "assigned_to": "nobody",
+        })).await.unwrap_err();
+        assert!(matches!(unknown, ApiError::NotFound(_)), "{:?}", unknown);
+        let (repair_id, cleaning_id) = (repair["id"].as_str().unwrap().to_string(), cleaning["id"].as_str().unwrap().to_string());
+
+        let maintenance_config = &app_state.config.maintenance;
+        let refresh = crate::alert_handlers::refresh_alerts(&pool, maintenance_config).await.unwrap();
+        assert_eq!(refresh.escalated, 2);
+        let events: Vec<String> = sqlx::query_scalar(
+            "SELECT payload FROM outbox WHERE event_type = 'maintenance_escalated' ORDER BY id"
+        ).fetch_all(&pool).await.unwrap();
+        let mut notified: Vec<(String, i64, serde_json::Value)> = events.iter()
+            .map(|payload| serde_json::from_str::<serde_json::Value>(payload).unwrap())
+            .map(|event| (event["maintenance_id"].as_str().unwrap().to_string(), event["level"].as_i64().unwrap(), event["notify"].clone()))
+            .collect();
+        notified.sort_by_key(|(_, level, _)| *level);
+        assert_eq!(notified, vec![
+            (repair_id.clone(), 1, serde_json::json!(["tech@example.com"])),
+            (cleaning_id.clone(), 2, serde_json::json!(["tester@example.com"])),
+        ]);
+        // Повторный проход не уведомляет снова
+        assert_eq!(crate::alert_handlers::refresh_alerts(&pool, maintenance_config).await.unwrap().escalated, 0);
+
+        let overdue = |query: &str| {
+            let app_state = app_state.clone();
+            let query = web::Query::<OverdueMaintenanceQuery>::from_query(query).unwrap();
+            async move { response_json(get_overdue_maintenance(app_state, query).await.unwrap()).await["data"].clone() }
+        };
+        let all = overdue("").await;
+        let listed: Vec<(&str, i64, i64)> = all.as_array().unwrap().iter()
+            .map(|m| (m["id"].as_str().unwrap(), m["days_overdue"].as_i64().unwrap(), m["escalation_level"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(listed, vec![(cleaning_id.as_str(), 4, 2), (repair_id.as_str(), 2, 1)]);
+        // Хранимый статус не меняется, просрочка - в вычисляемом
+        assert_eq!(all[1]["status"], "scheduled");
+        assert_eq!(all[1]["effective_status"], "overdue");
+        assert_eq!(all[1]["equipment_status"], "available");
+        assert_eq!(all[1]["assigned_to_username"], "tech");
+        assert_eq!(overdue("escalation_level=1").await.as_array().unwrap().len(), 1);
+        assert_eq!(equipment_fleet_summary(&pool).await.unwrap().maintenance_sla_overdue, 2);
+
+        // Завершение обрывает цепочку: запись не просрочена, предупреждение закрыто
+        let request: CompleteMaintenanceRequest = serde_json::from_value(serde_json::json!({})).unwrap();
+        let completed = response_json(complete_maintenance(
+            app_state.clone(), web::Path::from((hood.clone(), repair_id.clone())), web::Json(request), "tester".to_string(),
+        ).await.unwrap()).await;
+        assert_eq!(completed["data"]["effective_status"], "completed");
+        assert_eq!(completed["data"]["escalation_level"], 0);
+        let open: Vec<String> = sqlx::query_scalar(
+            "SELECT entity_id FROM alerts WHERE kind = 'maintenance_escalation' AND status = 'open'"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(open, vec![cleaning_id.clone()]);
+
+        // Больший SLA возвращает запись в срок без повторного уведомления
+        let request: UpdateMaintenanceRequest = serde_json::from_value(serde_json::json!({ "sla_days": 30 })).unwrap();
+        let relaxed = response_json(update_maintenance(
+            app_state.clone(), web::Path::from((hood.clone(), cleaning_id)), web::Json(request), "tester".to_string(),
+        ).await.unwrap()).await;
+        assert_eq!(relaxed["data"]["effective_status"], "scheduled");
+        assert_eq!(equipment_fleet_summary(&pool).await.unwrap().maintenance_sla_overdue, 0);
+    }
+
+    const UPLOAD_BOUNDARY: &str = "XUPLOADBOUNDARY";
+
+    fn upload_multipart(chunks: Vec<Result<actix_web::web::Bytes, actix_web::error::PayloadError>>) -> Multipart {
+        let mut headers = actix_web::http::header::HeaderMap::new();
+        headers.insert(
+            actix_web::http::header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", UPLOAD_BOUNDARY).parse().unwrap(),
+        );
+        Multipart::new(&headers, futures_util::stream::iter(chunks))
+    }
+
+    fn upload_body(content: &[u8]) -> Vec<u8> {
+        upload_body_named("manual.pdf", content)
+    }
+
+    fn upload_body_named(filename: &str, content: &[u8]) -> Vec<u8> {
+        let mut body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"file_type\"\r\n\r\nmanual\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{f}\"\r\n\
+             Content-Type: application/pdf\r\n\r\n",
+            b = UPLOAD_BOUNDARY,
+            f = filename
+        ).into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", UPLOAD_BOUNDARY).as_bytes());
+        body
+    }
+
+    fn files_under(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                files.extend(files_under(&path));
+            } else {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    #[actix_web::test]
+    async fn test_interrupted_upload_leaves_no_files_or_rows() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let dir = tempfile::tempdir().unwrap();
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at) \
+             VALUES ('eq1', 'HPLC System', 'instrument', 1, 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        let rows = || async {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM equipment_files").fetch_one(&pool).await.unwrap()
+        };
+
+        let content = vec![b'x'; 4096];
+        let body = upload_body(&content);
+        let cut = body.len() - 2048;
+
+        // Клиент отключился: поток закончился посреди файла
+        let truncated = upload_multipart(vec![Ok(web::Bytes::copy_from_slice(&body[..cut]))]);
+        let err = store_equipment_upload(&pool, dir.path(), "eq1", truncated, "tester").await.unwrap_err();
+        assert!(matches!(err, ApiError::RequestTimeout(_)), "{:?}", err);
+
+        // Обрыв соединения с ошибкой транспорта
+        let aborted = upload_multipart(vec![
+            Ok(web::Bytes::copy_from_slice(&body[..cut])),
+            Err(actix_web::error::PayloadError::Incomplete(None)),
+        ]);
+        let err = store_equipment_upload(&pool, dir.path(), "eq1", aborted, "tester").await.unwrap_err();
+        assert!(matches!(err, ApiError::RequestTimeout(_)), "{:?}", err);
+        assert_eq!(actix_web::ResponseError::error_response(&err).status(), actix_web::http::StatusCode::REQUEST_TIMEOUT);
+
+        // Файл больше лимита - ошибка валидации, а не обрыв
+        let oversized = upload_body(&vec![b'x'; max_file_size() + 1]);
+        let chunks = oversized.chunks(64 * 1024).map(|c| Ok(web::Bytes::copy_from_slice(c))).collect();
+        let err = store_equipment_upload(&pool, dir.path(), "eq1", upload_multipart(chunks), "tester").await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+
+        assert!(files_under(dir.path()).is_empty());
+        assert_eq!(rows().await, 0);
+
+        // Полная загрузка: одна строка и один файл на месте, staging пуст
+        let complete = upload_multipart(vec![Ok(web::Bytes::from(body))]);
+        let created = store_equipment_upload(&pool, dir.path(), "eq1", complete, "tester").await.unwrap();
+        assert_eq!(created.file_size, 4096);
+        assert_eq!(files_under(dir.path()), vec![std::path::PathBuf::from(&created.file_path)]);
+        assert!(created.file_path.contains("manuals"));
+        assert_eq!(std::fs::read(&created.file_path).unwrap(), content);
+        assert_eq!(rows().await, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_identical_uploads_share_one_file() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let dir = tempfile::tempdir().unwrap();
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at) \
+             VALUES ('eq1', 'HPLC System', 'instrument', 1, 'available', datetime('now'), datetime('now')), \
+                    ('eq2', 'GC System', 'instrument', 1, 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        let upload = |equipment_id: &'static str, content: &'static [u8]| {
+            let pool = pool.clone();
+            let base_dir = dir.path().to_path_buf();
+            async move {
+                let payload = upload_multipart(vec![Ok(web::Bytes::from(upload_body(content)))]);
+                store_equipment_upload(&pool, &base_dir, equipment_id, payload, "tester").await.unwrap()
+            }
+        };
+        let first = upload("eq1", b"same manual").await;
+        let second = upload("eq2", b"same manual").await;
+        let other = upload("eq2", b"another manual").await;
+
+        // Отдельные строки, но один файл на диске для одинакового содержимого
+        assert_ne!(first.id, second.id);
+        assert_eq!(second.equipment_id, "eq2");
+        assert_eq!(first.file_path, second.file_path);
+        assert_eq!(first.content_hash, second.content_hash);
+        assert_eq!(files_under(dir.path()).len(), 2);
+        let report = file_blobs::dedup_report(&pool).await.unwrap();
+        assert_eq!((report.blobs, report.references, report.saved_bytes), (2, 3, 11));
+
+        // Удаление одного вложения не трогает файл, пока на него ссылается другое
+        let path = |equipment_id: &str, file_id: &str| web::Path::from((equipment_id.to_string(), file_id.to_string()));
+        delete_equipment_file(app_state.clone(), path("eq1", &first.id)).await.unwrap();
+        assert_eq!(std::fs::read(&second.file_path).unwrap(), b"same manual");
+
+        assert!(purge_equipment(&pool, "eq2").await.unwrap());
+        assert!(files_under(dir.path()).is_empty());
+        assert_eq!(file_blobs::dedup_report(&pool).await.unwrap().blobs, 0);
+        assert!(!std::path::Path::new(&other.file_path).exists());
+    }
+
+    #[actix_web::test]
+    async fn test_upload_sanitizes_original_filename_and_download_encodes_it() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let dir = tempfile::tempdir().unwrap();
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at) \
+             VALUES ('eq1', 'HPLC System', 'instrument', 1, 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        let upload = |filename: &str| {
+            let payload = upload_multipart(vec![Ok(web::Bytes::from(upload_body_named(filename, b"%PDF")))]);
+            store_equipment_upload(&pool, dir.path(), "eq1", payload, "tester")
+        };
+        let download = |file_id: String| {
+            download_equipment_file(app_state.clone(), web::Path::from(("eq1".to_string(), file_id)))
+        };
+
+        let unicode = upload("отчёт 2024/финал.pdf").await.unwrap();
+        assert_eq!(unicode.original_filename, "финал.pdf");
+        assert!(unicode.stored_filename.ends_with(".pdf"));
+        let response = download(unicode.id).await.unwrap();
+        assert_eq!(
+            response.headers().get(actix_web::http::header::CONTENT_DISPOSITION).unwrap(),
+            "attachment; filename=\"_____.pdf\"; filename*=UTF-8''%D1%84%D0%B8%D0%BD%D0%B0%D0%BB.pdf"
+        );
+
+        // Путь в имени не выводит файл за пределы каталога загрузок
+        let traversal = upload("../../../etc/passwd").await.unwrap();
+        assert_eq!(traversal.original_filename, "passwd");
+        assert!(traversal.file_path.starts_with(dir.path().to_str().unwrap()));
+
+        let long_name = format!("{}.pdf", "протокол".repeat(40));
+        let overlong = upload(&long_name).await.unwrap();
+        assert!(overlong.original_filename.len() <= crate::query_builders::MAX_ORIGINAL_FILENAME_BYTES);
+        assert!(overlong.original_filename.ends_with(".pdf"));
+
+        // Старая запись с кавычками и переводом строки в имени не ломает заголовок
+        sqlx::query("UPDATE equipment_files SET original_filename = ? WHERE id = ?")
+            .bind("a\"; filename=evil.exe\r\nSet-Cookie: x=1")
+            .bind(&traversal.id)
+            .execute(&pool).await.unwrap();
+        let response = download(traversal.id).await.unwrap();
+        let header = response.headers().get(actix_web::http::header::CONTENT_DISPOSITION).unwrap().to_str().unwrap();
+        assert_eq!(header.matches('"').count(), 2, "{}", header);
+        assert!(header.starts_with("attachment; filename=\"a; filename=evil.exeSet-Cookie x=1\""), "{}", header);
+    }
+
+    #[actix_web::test]
+    async fn test_equipment_fleet_summary_counts_each_state() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let day = |offset: i64| (Utc::now() + Duration::days(offset)).format("%Y-%m-%d").to_string();
+        for (id, status, next_maintenance, warranty_until, updated_at) in [
+            ("ok", "available", Some(day(60)), Some(day(400)), "2024-01-01 00:00:00"),
+            ("busy", "in_use", Some(day(-3)), None, "2024-01-01 00:00:00"),
+            ("serviced", "maintenance", None, Some(day(10)), "2024-01-01 00:00:00"),
+            ("broken-old", "damaged", None, Some(day(-5)), "2024-02-01 00:00:00"),
+            ("broken-new", "damaged", None, None, "2024-03-01 00:00:00"),
+            ("calibrating", "calibration", None, None, "2024-01-01 00:00:00"),
+            ("retired", "retired", Some(day(-30)), Some(day(5)), "2024-01-01 00:00:00"),
+        ] {
+            sqlx::query(
+                "INSERT INTO equipment (id, name, type_, quantity, status, next_maintenance, warranty_until, created_at, updated_at) \
+                 VALUES (?, ?, 'instrument', 1, ?, ?, ?, datetime('now'), ?)"
+            )
+                .bind(id).bind(id).bind(status).bind(next_maintenance).bind(warranty_until).bind(updated_at)
+                .execute(&pool).await.unwrap();
+        }
+        for (id, equipment_id, maintenance_type, status, scheduled) in [
+            ("m1", "ok", "inspection", "scheduled", day(-1)),
+            ("m2", "busy", "repair", "scheduled", day(-10)),
+            ("m3", "calibrating", "calibration", "scheduled", day(20)),
+            ("m4", "ok", "calibration", "scheduled", day(45)),
+            ("m5", "serviced", "calibration", "completed", day(-2)),
+            ("m6", "retired", "calibration", "scheduled", day(1)),
+        ] {
+            sqlx::query(
+                "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, status, scheduled_date, created_at, updated_at) \
+                 VALUES (?, ?, ?, ?, ?, datetime('now'), datetime('now'))"
+            )
+                .bind(id).bind(equipment_id).bind(maintenance_type).bind(status).bind(scheduled)
+                .execute(&pool).await.unwrap();
+        }
+
+        let summary = response_json(get_equipment_summary(app_state.clone()).await.unwrap()).await["data"].clone();
+        assert_eq!(summary["by_status"], serde_json::json!({
+            "available": 1, "in_use": 1, "maintenance": 1, "damaged": 2, "calibration": 1, "retired": 1,
+        }));
+        // ok (журнал) и busy (next_maintenance и журнал) - по одному разу
+        assert_eq!(summary["maintenance_overdue"], 2);
+        assert_eq!(summary["calibration_due"], 1);
+        assert_eq!(summary["warranty_expiring"], 1);
+        assert_eq!(summary["maintenance_sla_overdue"], 0);
+        let broken: Vec<&str> = summary["recently_broken"].as_array().unwrap()
+            .iter().map(|e| e["id"].as_str().unwrap()).collect();
+        assert_eq!(broken, vec!["broken-new", "broken-old"]);
+
+        // В общей статистике сводка появляется только по флагу include
+        let stats = |include: Option<&str>| {
+            let query = crate::handlers::DashboardStatsQuery { include: include.map(str::to_string) };
+            crate::handlers::get_dashboard_stats(app_state.clone(), web::Query(query))
+        };
+        let plain = response_json(stats(None).await.unwrap()).await;
+        assert!(plain["data"].get("equipment_summary").is_none());
+        assert_eq!(plain["data"]["total_equipment"], 6);
+        let with_equipment = response_json(stats(Some("equipment")).await.unwrap()).await;
+        assert_eq!(with_equipment["data"]["equipment_summary"], summary);
+        let err = stats(Some("equipment,reagents")).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref m) if m.contains("reagents")), "{:?}", err);
+    }
+
+    #[actix_web::test]
+    async fn test_equipment_shape_follows_api_version() {
+        let app_state = fts_app_state().await;
+        let id = create_test_equipment(&app_state, "HPLC", None).await;
+        create_test_equipment(&app_state, "Pump", Some(&id)).await;
+
+        let v1 = response_json(
+            get_equipment_by_id(app_state.clone(), web::Path::from(id.clone()), ApiVersion::V1).await.unwrap()
+        ).await;
+        assert_eq!(v1["data"]["type_"], "instrument");
+        assert_eq!(v1["data"]["quantity"], 1);
+        assert_eq!(v1["data"]["components"][0]["type_"], "instrument");
+        assert!(v1["data"].get("equipment_type").is_none());
+
+        let v2 = response_json(
+            get_equipment_by_id(app_state.clone(), web::Path::from(id.clone()), ApiVersion::V2).await.unwrap()
+        ).await;
+        assert_eq!(v2["data"]["equipment_type"], "instrument");
+        assert_eq!(v2["data"]["stock"]["quantity"], 1);
+        assert_eq!(v2["data"]["components"][0]["equipment_type"], "instrument");
+        assert!(v2["data"].get("type_").is_none());
+        assert!(v2["data"].get("quantity").is_none());
+
+        // v2-клиент может прислать тип под новым именем
+        let request: CreateEquipmentRequest = serde_json::from_value(serde_json::json!({
+            "name": "Balance",
+            "equipment_type": "instrument",
+            "quantity": 2,
+        })).unwrap();
+        let created = response_json(
+            create_equipment(app_state.clone(), web::Json(request), "tester".to_string(), ApiVersion::V2).await.unwrap()
+        ).await;
+        assert_eq!(created["data"]["equipment_type"], "instrument");
+        assert_eq!(created["data"]["stock"]["quantity"], 2);
+    }
+
+    #[actix_web::test]
+    async fn test_nested_lists_report_truncation() {
+        let app_state = fts_app_state().await;
+        let id = create_test_equipment(&app_state, "HPLC", None).await;
+
+        let parts = get_equipment_parts(app_state.clone(), web::Path::from(id.clone())).await.unwrap();
+        assert!(parts.headers().get(crate::handlers::TRUNCATED_HEADER).is_none());
+
+        sqlx::query(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i <= ?) \
+             INSERT INTO equipment_parts (id, equipment_id, name, created_at, updated_at) \
+             SELECT 'p' || i, ?, 'Part ' || i, datetime('now'), datetime('now') FROM n"
+        )
+            .bind(MAX_NESTED_LIST_ROWS)
+            .bind(&id)
+            .execute(&app_state.db_pool).await.unwrap();
+
+        let parts = get_equipment_parts(app_state.clone(), web::Path::from(id.clone())).await.unwrap();
+        assert_eq!(parts.headers().get(crate::handlers::TRUNCATED_HEADER).unwrap(), "true");
+        let parts = response_json(parts).await;
+        assert_eq!(parts["data"].as_array().unwrap().len() as i64, MAX_NESTED_LIST_ROWS);
+
+        let detail = response_json(
+            get_equipment_by_id(app_state.clone(), web::Path::from(id.clone()), ApiVersion::LATEST).await.unwrap()
+        ).await;
+        assert_eq!(detail["data"]["truncated"], serde_json::json!(["parts"]));
+        assert_eq!(detail["data"]["parts"].as_array().unwrap().len() as i64, MAX_NESTED_LIST_ROWS);
+    }
+
+    #[actix_web::test]
+    async fn test_updates_clear_fields_with_null_and_keep_absent_ones() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let hplc = create_test_equipment(&app_state, "HPLC System", None).await;
+
+        let filled: UpdateEquipmentRequest = serde_json::from_value(serde_json::json!({
+            "description": "Main line", "serial_number": "SN-1", "purchase_date": "2024-01-15",
+        })).unwrap();
+        update_equipment(app_state.clone(), web::Path::from(hplc.clone()), web::Json(filled), "tester".to_string(), false, ApiVersion::LATEST)
+            .await
+            .unwrap();
+
+        // null очищает поле, отсутствующие поля не меняются
+        let cleared: UpdateEquipmentRequest = serde_json::from_value(serde_json::json!({
+            "description": null, "purchase_date": null,
+        })).unwrap();
+        update_equipment(app_state.clone(), web::Path::from(hplc.clone()), web::Json(cleared), "tester".to_string(), false, ApiVersion::LATEST)
+            .await
+            .unwrap();
+        let row: (Option<String>, Option<String>, Option<String>) = sqlx::query_as(
+            "SELECT description, serial_number, purchase_date FROM equipment WHERE id = ?"
+        )
+            .bind(&hplc)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(row, (None, Some("SN-1".to_string()), None));
+
+        let part: CreateEquipmentPartRequest = serde_json::from_value(serde_json::json!({
+            "name": "Inlet filter", "quantity": 4, "notes": "Replace monthly",
+        })).unwrap();
+        let part = response_json(
+            add_equipment_part(app_state.clone(), web::Path::from(hplc.clone()), web::Json(part), "tester".to_string())
+                .await
+                .unwrap()
+        ).await;
+        let part_id = part["data"]["id"].as_str().unwrap().to_string();
+        let edit: UpdateEquipmentPartRequest = serde_json::from_value(serde_json::json!({ "notes": null, "min_quantity": 2 })).unwrap();
+        let edited = response_json(
+            update_equipment_part(app_state.clone(), web::Path::from((hplc.clone(), part_id)), web::Json(edit), "tester".to_string())
+                .await
+                .unwrap()
+        ).await;
+        assert!(edited["data"]["notes"].is_null());
+        assert_eq!(edited["data"]["min_quantity"], 2);
+
+        let maintenance_id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, status, scheduled_date, cost, notes, created_at, updated_at) \
+             VALUES (?, ?, 'repair', 'scheduled', '2024-05-01', 120.5, 'Quote received', datetime('now'), datetime('now'))"
+        )
+            .bind(&maintenance_id)
+            .bind(&hplc)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let edit: UpdateMaintenanceRequest = serde_json::from_value(serde_json::json!({ "cost": null, "notes": "" })).unwrap();
+        update_maintenance(app_state.clone(), web::Path::from((hplc.clone(), maintenance_id.clone())), web::Json(edit), "tester".to_string())
+            .await
+            .unwrap();
+        let row: (Option<f64>, Option<String>) = sqlx::query_as("SELECT cost, notes FROM equipment_maintenance WHERE id = ?")
+            .bind(&maintenance_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(row, (None, None));
+
+        // Стоимость сохраняется числом, а не строкой
+        let edit: UpdateMaintenanceRequest = serde_json::from_value(serde_json::json!({ "cost": 75.25 })).unwrap();
+        update_maintenance(app_state.clone(), web::Path::from((hplc, maintenance_id.clone())), web::Json(edit), "tester".to_string())
+            .await
+            .unwrap();
+        let cost_type: String = sqlx::query_scalar("SELECT typeof(cost) FROM equipment_maintenance WHERE id = ?")
+            .bind(&maintenance_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(cost_type, "real");
+    }
+
+    #[actix_web::test]
+    async fn test_part_reorder_suggestions_merge_part_numbers_and_flag_replacements() {
+        let app_state = fts_app_state().await;
+        let pool = app_state.db_pool.clone();
+        for (id, name, status) in [("hplc-1", "HPLC 1", "available"), ("hplc-2", "HPLC 2", "in_use"), ("old", "Old HPLC", "retired")] {
+            sqlx::query(
+                "INSERT INTO equipment (id, name, type_, status, created_at, updated_at) \
+                 VALUES (?, ?, 'instrument', ?, datetime('now'), datetime('now'))"
+            ).bind(id).bind(name).bind(status).execute(&pool).await.unwrap();
+        }
+        let parts = [
+            ("p1", "hplc-1", "C18 column", Some("COL-C18"), 0, 2),
+            ("p2", "hplc-2", "C18 column", Some(" col-c18 "), 1, 2),
+            ("p3", "hplc-2", "Inlet filter", None, 0, 5),
+            ("p4", "old", "C18 column", Some("COL-C18"), 0, 4),
+            ("p5", "hplc-1", "Lamp", Some("LMP-1"), 3, 1),
+        ];
+        for (id, equipment_id, name, part_number, quantity, min_quantity) in parts {
+            sqlx::query(
+                "INSERT INTO equipment_parts (id, equipment_id, name, part_number, quantity, min_quantity, created_at, updated_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))"
+            ).bind(id).bind(equipment_id).bind(name).bind(part_number).bind(quantity).bind(min_quantity)
+                .execute(&pool).await.unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO equipment_maintenance (id, equipment_id, maintenance_type, status, scheduled_date, parts_replaced, created_at, updated_at) \
+             VALUES ('m1', 'hplc-2', 'replacement', 'scheduled', '2030-01-01', 'Swap col-c18 column', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        let query = web::Query(PartReorderQuery { format: None });
+        let resp = get_part_reorder_suggestions(app_state.clone(), query).await.unwrap();
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let lines = body["data"].as_array().unwrap();
+        assert_eq!(lines.len(), 2, "{}", body);
+
+        // Фильтр: 5 к заказу; колонка: дефицит 2 + 1, но замена на HPLC 2 уже запланирована
+        assert_eq!(lines[0]["name"], "Inlet filter");
+        assert_eq!(lines[0]["deficit_to_order"], 5);
+        let column = &lines[1];
+        assert_eq!(column["part_number"], "COL-C18");
+        assert_eq!((column["total_deficit"].as_i64(), column["deficit_to_order"].as_i64()), (Some(3), Some(2)));
+        assert_eq!(column["replacement_scheduled"], true);
+        assert_eq!(column["equipment"].as_array().unwrap().len(), 2);
+        assert_eq!(column["equipment"][1]["replacement_maintenance_id"], "m1");
+
+        let query = web::Query(PartReorderQuery { format: Some("csv".to_string()) });
+        let resp = get_part_reorder_suggestions(app_state.clone(), query).await.unwrap();
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let csv = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(csv.starts_with(PART_REORDER_CSV_HEADER));
+        assert!(csv.contains("COL-C18,C18 column,,3,2,yes,HPLC 1 (2); HPLC 2 (1)\n"), "{}", csv);
+
+        let query = web::Query(PartReorderQuery { format: Some("xml".to_string()) });
+        assert!(get_part_reorder_suggestions(app_state, query).await.is_err());
+    }
 }
\ No newline at end of file
@@ -13,14 +13,51 @@ pub enum ApiError {
     ValidationError(String),
     DatabaseError(sqlx::Error),
     AuthError(String),
+    /// Клиент прервал передачу тела запроса (например, оборванная загрузка файла)
+    RequestTimeout(String),
+    /// Состояние объекта не допускает операцию; `code` - машиночитаемая причина для UI
+    Conflict { code: &'static str, message: String },
+    /// Очередь тяжёлых операций заполнена; клиенту стоит повторить через `retry_after_secs`
+    TooManyRequests { message: String, retry_after_secs: u64 },
+    /// Сервис временно не принимает запрос (например, режим обслуживания только для чтения)
+    ServiceUnavailable { code: &'static str, message: String },
 }
 
+/// Партия ждёт сертификат анализа (COA) и не может использоваться или резервироваться
+pub const BATCH_AWAITING_COA: &str = "BATCH_AWAITING_COA";
+
+/// Реагент деактивирован: его партии только для чтения, новые партии, расход и резервы запрещены
+pub const REAGENT_INACTIVE: &str = "REAGENT_INACTIVE";
+
+/// Пересчёт mol/mmol в массу невозможен: у реагента не задана молярная масса
+pub const MOLECULAR_WEIGHT_REQUIRED: &str = "MOLECULAR_WEIGHT_REQUIRED";
+
+/// Эксперимент нельзя запустить: не все предшествующие эксперименты завершены
+pub const DEPENDENCIES_INCOMPLETE: &str = "DEPENDENCIES_INCOMPLETE";
+
+/// Новая зависимость замкнула бы цепочку экспериментов в цикл
+pub const DEPENDENCY_CYCLE: &str = "DEPENDENCY_CYCLE";
+
+/// Действие недоступно в текущем статусе заказа поставщику (например, приёмка черновика)
+pub const PURCHASE_ORDER_STATUS: &str = "PURCHASE_ORDER_STATUS";
+
+/// Имя пользователя уже занято (сравнение без учёта регистра)
+pub const USERNAME_TAKEN: &str = "USERNAME_TAKEN";
+
+/// Email уже используется другой учётной записью (сравнение без учёта регистра)
+pub const EMAIL_TAKEN: &str = "EMAIL_TAKEN";
+
+/// Серийные номера оборудования повторяются, уникальный индекс не создан
+pub const SERIAL_NUMBERS_NOT_UNIQUE: &str = "SERIAL_NUMBERS_NOT_UNIQUE";
+
 pub type ApiResult<T> = Result<T, ApiError>;
 
 #[derive(Serialize)]
 struct ErrorResponse {
     success: bool,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
 }
 
 impl fmt::Display for ApiError {
@@ -34,6 +71,10 @@ impl fmt::Display for ApiError {
             ApiError::ValidationError(msg) => write!(f, "Validation Error: {}", msg),
             ApiError::DatabaseError(err) => write!(f, "Database Error: {}", err),
             ApiError::AuthError(msg) => write!(f, "Auth Error: {}", msg),
+            ApiError::RequestTimeout(msg) => write!(f, "Request Timeout: {}", msg),
+            ApiError::Conflict { message, .. } => write!(f, "Conflict: {}", message),
+            ApiError::TooManyRequests { message, .. } => write!(f, "Too Many Requests: {}", message),
+            ApiError::ServiceUnavailable { message, .. } => write!(f, "Service Unavailable: {}", message),
         }
     }
 }
@@ -43,6 +84,10 @@ impl ResponseError for ApiError {
         let error_response = ErrorResponse {
             success: false,
             message: self.to_string(),
+            code: match self {
+                ApiError::Conflict { code, .. } | ApiError::ServiceUnavailable { code, .. } => Some(code),
+                _ => None,
+            },
         };
 
         match self {
@@ -51,13 +96,49 @@ impl ResponseError for ApiError {
             ApiError::Unauthorized(_) => HttpResponse::Unauthorized().json(error_response),
             ApiError::Forbidden(_) => HttpResponse::Forbidden().json(error_response),
             ApiError::ValidationError(_) => HttpResponse::UnprocessableEntity().json(error_response),
+            ApiError::DatabaseError(err) if is_database_busy(err) => {
+                HttpResponse::ServiceUnavailable()
+                    .insert_header(("Retry-After", DATABASE_BUSY_RETRY_AFTER_SECS.to_string()))
+                    .json(ErrorResponse {
+                        success: false,
+                        message: "Database is busy, please retry later".to_string(),
+                        code: None,
+                    })
+            }
             ApiError::DatabaseError(_) => HttpResponse::InternalServerError().json(error_response),
             ApiError::AuthError(_) => HttpResponse::Unauthorized().json(error_response),
+            ApiError::RequestTimeout(_) => HttpResponse::RequestTimeout().json(error_response),
             ApiError::InternalServerError(_) => HttpResponse::InternalServerError().json(error_response),
+            ApiError::Conflict { .. } => HttpResponse::Conflict().json(error_response),
+            ApiError::TooManyRequests { retry_after_secs, .. } => HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", retry_after_secs.to_string()))
+                .json(error_response),
+            ApiError::ServiceUnavailable { .. } => HttpResponse::ServiceUnavailable().json(error_response),
         }
     }
 }
 
+/// Через сколько секунд клиенту стоит повторить запрос при занятой БД
+pub const DATABASE_BUSY_RETRY_AFTER_SECS: u64 = 2;
+
+/// SQLITE_BUSY / SQLITE_LOCKED (включая расширенные коды) или исчерпанный пул соединений —
+/// временное состояние, а не ошибка сервера
+pub fn is_database_busy(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Database(db_err) => {
+            // Младший байт расширенного кода — первичный код SQLite
+            let primary = db_err
+                .code()
+                .and_then(|code| code.parse::<i32>().ok())
+                .map(|code| code & 0xff);
+            matches!(primary, Some(5) | Some(6))
+                || db_err.message().contains("database is locked")
+        }
+        _ => false,
+    }
+}
+
 impl From<sqlx::Error> for ApiError {
     fn from(err: sqlx::Error) -> Self {
         ApiError::DatabaseError(err)
@@ -78,7 +159,18 @@ impl From<std::io::Error> for ApiError {
 
 impl From<actix_multipart::MultipartError> for ApiError {
     fn from(err: actix_multipart::MultipartError) -> Self {
-        ApiError::BadRequest(format!("Multipart Error: {}", err))
+        use actix_multipart::MultipartError;
+        use actix_web::error::PayloadError;
+        match err {
+            // Поток оборвался до конца формы - клиент отключился посреди загрузки
+            MultipartError::Incomplete
+            | MultipartError::Payload(PayloadError::Incomplete(_))
+            | MultipartError::Payload(PayloadError::Io(_))
+            | MultipartError::Payload(PayloadError::Http2Payload(_)) => {
+                ApiError::RequestTimeout(format!("Upload interrupted: {}", err))
+            }
+            _ => ApiError::BadRequest(format!("Multipart Error: {}", err)),
+        }
     }
 }
 
@@ -148,6 +240,83 @@ impl ApiError {
         ApiError::BadRequest("Expiry date cannot be in the past".to_string())
     }
 
+    pub fn batch_awaiting_coa(batch_number: &str) -> Self {
+        ApiError::Conflict {
+            code: BATCH_AWAITING_COA,
+            message: format!("Batch '{}' is awaiting its Certificate of Analysis and cannot be used yet", batch_number),
+        }
+    }
+
+    pub fn molecular_weight_required(reagent_name: &str) -> Self {
+        ApiError::Conflict {
+            code: MOLECULAR_WEIGHT_REQUIRED,
+            message: format!(
+                "Reagent '{}' has no molecular weight, so molar amounts cannot be converted to mass. Set molecular_weight on the reagent first",
+                reagent_name
+            ),
+        }
+    }
+
+    /// `blockers` - "название (статус)" каждого незавершённого предшественника
+    pub fn dependencies_incomplete(blockers: &[String]) -> Self {
+        ApiError::Conflict {
+            code: DEPENDENCIES_INCOMPLETE,
+            message: format!(
+                "Experiment cannot start until its prerequisite experiments are completed: {}",
+                blockers.join(", ")
+            ),
+        }
+    }
+
+    pub fn dependency_cycle(experiment_id: &str, depends_on_id: &str) -> Self {
+        ApiError::Conflict {
+            code: DEPENDENCY_CYCLE,
+            message: format!(
+                "Experiment '{}' already depends (directly or indirectly) on '{}'; the dependency would create a cycle",
+                depends_on_id, experiment_id
+            ),
+        }
+    }
+
+    pub fn reagent_inactive(reagent_name: &str) -> Self {
+        ApiError::Conflict {
+            code: REAGENT_INACTIVE,
+            message: format!(
+                "Reagent '{}' is inactive: its batches are read-only and no new batches can be added. Reactivate it first",
+                reagent_name
+            ),
+        }
+    }
+
+    pub fn purchase_order_status(po_number: &str, status: &str, action: &str) -> Self {
+        ApiError::Conflict {
+            code: PURCHASE_ORDER_STATUS,
+            message: format!("Purchase order '{}' is {} and cannot be {}", po_number, status, action),
+        }
+    }
+
+    pub fn username_taken(username: &str) -> Self {
+        ApiError::Conflict {
+            code: USERNAME_TAKEN,
+            message: format!("Username '{}' is already taken (usernames are case-insensitive)", username),
+        }
+    }
+
+    pub fn serial_numbers_not_unique() -> Self {
+        ApiError::Conflict {
+            code: SERIAL_NUMBERS_NOT_UNIQUE,
+            message: "Equipment serial numbers are not unique; resolve the duplicates listed in \
+                      /admin/normalizations/equipment-serial".to_string(),
+        }
+    }
+
+    pub fn email_taken(email: &str) -> Self {
+        ApiError::Conflict {
+            code: EMAIL_TAKEN,
+            message: format!("Email '{}' is already in use (emails are case-insensitive)", email),
+        }
+    }
+
     pub fn cannot_modify_depleted_batch() -> Self {
         ApiError::BadRequest("Cannot modify depleted batch".to_string())
     }
@@ -208,3 +377,53 @@ pub fn validate_unit(unit: &str) -> Result<(), ApiError> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+    use std::time::Duration;
+
+    #[actix_web::test]
+    async fn test_sqlite_busy_maps_to_503_with_retry_after() {
+        let path = std::env::temp_dir().join(format!("lims-busy-{}.db", uuid::Uuid::new_v4()));
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .busy_timeout(Duration::from_millis(0));
+        let pool = SqlitePoolOptions::new()
+            .max_connections(2)
+            .connect_with(options)
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE t (x INTEGER)").execute(&pool).await.unwrap();
+
+        // Первое соединение держит write-блокировку, второе получает SQLITE_BUSY
+        let mut holder = pool.acquire().await.unwrap();
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut *holder).await.unwrap();
+        let err = sqlx::query("INSERT INTO t (x) VALUES (1)")
+            .execute(&pool)
+            .await
+            .unwrap_err();
+        assert!(is_database_busy(&err), "unexpected error: {err}");
+
+        let resp = ApiError::from(err).error_response();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            resp.headers().get("Retry-After").unwrap().to_str().unwrap(),
+            DATABASE_BUSY_RETRY_AFTER_SECS.to_string()
+        );
+
+        sqlx::query("ROLLBACK").execute(&mut *holder).await.unwrap();
+        drop(holder);
+        pool.close().await;
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_other_database_errors_stay_500() {
+        let resp = ApiError::DatabaseError(sqlx::Error::RowNotFound).error_response();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(resp.headers().get("Retry-After").is_none());
+    }
+}
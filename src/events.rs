@@ -0,0 +1,274 @@
+// src/events.rs
+//! Поток бизнес-событий для ELK: типизированные события, по одной JSON-строке
+//! в отдельный tracing-target `lims::events` (не смешивается с журналом доступа Logger).
+//!
+//! События не пишутся напрямую: обработчик кладёт их в outbox в своей транзакции
+//! (`outbox::enqueue`), а диспетчер outbox после фиксации пишет строку в журнал,
+//! учитывает KPI и рассылает вебхуки. Автор и request id фиксируются при постановке в очередь.
+//! Request id берётся из заголовка `X-Request-Id` (или генерируется) в `RequestLogger`
+//! и доступен на всё время обработки запроса через task-local.
+//! Куда писать (stdout, файл, оба) - `config.events`, см. `setup_logging` в main.rs.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::future::Future;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+pub const EVENTS_TARGET: &str = "lims::events";
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Ограничение на request id из заголовка клиента
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+pub enum BusinessEvent {
+    ReagentCreated {
+        reagent_id: String,
+        name: String,
+    },
+    BatchCreated {
+        reagent_id: String,
+        batch_id: String,
+        quantity: f64,
+        unit: String,
+    },
+    BatchConsumed {
+        reagent_id: String,
+        batch_id: String,
+        quantity: f64,
+        unit: String,
+        remaining_quantity: f64,
+    },
+    /// Корректировка остатка (не расход): `delta` со знаком
+    BatchAdjusted {
+        reagent_id: String,
+        batch_id: String,
+        delta: f64,
+        unit: String,
+        reason: String,
+        remaining_quantity: f64,
+    },
+    EquipmentCreated {
+        equipment_id: String,
+        name: String,
+    },
+    /// `automatic` - завершён фоновой задачей по end_date (автора нет)
+    ExperimentCompleted {
+        experiment_id: String,
+        reagents_consumed: i64,
+        automatic: bool,
+    },
+    /// Обслуживание просрочено по SLA: level 1 - уведомляется ответственный,
+    /// level 2 - ещё и администраторы. `notify` - адреса для письма
+    MaintenanceEscalated {
+        maintenance_id: String,
+        equipment_id: String,
+        level: i64,
+        days_overdue: i64,
+        notify: Vec<String>,
+    },
+    /// Сработало правило аномалий расхода (stock_anomalies): `user_id` - автор операции,
+    /// `notify` - адреса администраторов
+    StockAnomalyDetected {
+        anomaly_id: String,
+        rule: String,
+        operation: String,
+        batch_id: String,
+        user_id: Option<String>,
+        observed: f64,
+        threshold: f64,
+        message: String,
+        notify: Vec<String>,
+    },
+}
+
+impl BusinessEvent {
+    /// Все значения event_type (для фильтров вебхуков в конфигурации)
+    pub const TYPES: &'static [&'static str] = &[
+        "reagent_created",
+        "batch_created",
+        "batch_consumed",
+        "batch_adjusted",
+        "equipment_created",
+        "experiment_completed",
+        "maintenance_escalated",
+        "stock_anomaly_detected",
+    ];
+
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            BusinessEvent::ReagentCreated { .. } => "reagent_created",
+            BusinessEvent::BatchCreated { .. } => "batch_created",
+            BusinessEvent::BatchConsumed { .. } => "batch_consumed",
+            BusinessEvent::BatchAdjusted { .. } => "batch_adjusted",
+            BusinessEvent::EquipmentCreated { .. } => "equipment_created",
+            BusinessEvent::ExperimentCompleted { .. } => "experiment_completed",
+            BusinessEvent::MaintenanceEscalated { .. } => "maintenance_escalated",
+            BusinessEvent::StockAnomalyDetected { .. } => "stock_anomaly_detected",
+        }
+    }
+
+    /// Сущность, в пределах которой outbox сохраняет порядок доставки
+    pub fn entity(&self) -> (&'static str, &str) {
+        match self {
+            BusinessEvent::ReagentCreated { reagent_id, .. } => ("reagent", reagent_id),
+            BusinessEvent::BatchCreated { batch_id, .. }
+            | BusinessEvent::BatchConsumed { batch_id, .. }
+            | BusinessEvent::BatchAdjusted { batch_id, .. }
+            | BusinessEvent::StockAnomalyDetected { batch_id, .. } => ("batch", batch_id),
+            BusinessEvent::EquipmentCreated { equipment_id, .. } => ("equipment", equipment_id),
+            BusinessEvent::ExperimentCompleted { experiment_id, .. } => ("experiment", experiment_id),
+            BusinessEvent::MaintenanceEscalated { maintenance_id, .. } => ("maintenance", maintenance_id),
+        }
+    }
+
+    /// Адресаты письма о самом событии (помимо `email_to` из `[outbox]`)
+    pub fn recipients(&self) -> &[String] {
+        match self {
+            BusinessEvent::MaintenanceEscalated { notify, .. }
+            | BusinessEvent::StockAnomalyDetected { notify, .. } => notify,
+            _ => &[],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    event: &'a BusinessEvent,
+    actor: Option<&'a str>,
+    request_id: Option<String>,
+}
+
+/// Запись события: время, поля события, автор и request id одной JSON-строкой
+pub fn to_json_line(event: &BusinessEvent, actor: Option<&str>, request_id: Option<String>) -> serde_json::Result<String> {
+    serde_json::to_string(&EventRecord { timestamp: Utc::now(), event, actor, request_id })
+}
+
+/// Записать готовую строку события в `lims::events` (вызывает диспетчер outbox)
+pub fn write_line(line: &str) {
+    tracing::info!(target: EVENTS_TARGET, "{}", line);
+}
+
+// ==================== REQUEST ID ====================
+
+/// Request id клиента, если он безопасен для логов, иначе новый UUID
+pub fn request_id_from_header(value: Option<&str>) -> String {
+    match value.map(str::trim) {
+        Some(id) if !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LEN
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) => id.to_string(),
+        _ => uuid::Uuid::new_v4().to_string(),
+    }
+}
+
+/// Выполнить обработку запроса с request id (виден из `emit`)
+pub async fn with_request_id<F: Future>(request_id: String, fut: F) -> F::Output {
+    REQUEST_ID.scope(request_id, fut).await
+}
+
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+// ==================== OUTPUT FORMAT ====================
+
+/// Формат слоёв `lims::events`: только сама JSON-строка, без времени, уровня и target
+pub struct EventLineFormat;
+
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for EventLineFormat
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        writeln!(writer, "{}", visitor.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_serializes_as_flat_json_line() {
+        let event = BusinessEvent::BatchConsumed {
+            reagent_id: "r-1".to_string(),
+            batch_id: "b-1".to_string(),
+            quantity: 2.5,
+            unit: "ml".to_string(),
+            remaining_quantity: 7.5,
+        };
+        let line = to_json_line(&event, Some("u-1"), Some("req-1".to_string())).unwrap();
+        assert!(!line.contains('\n'));
+
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["event_type"], "batch_consumed");
+        assert_eq!(json["batch_id"], "b-1");
+        assert_eq!(json["quantity"], 2.5);
+        assert_eq!(json["remaining_quantity"], 7.5);
+        assert_eq!(json["actor"], "u-1");
+        assert_eq!(json["request_id"], "req-1");
+        assert!(json["timestamp"].is_string());
+
+        let automatic = to_json_line(
+            &BusinessEvent::ExperimentCompleted { experiment_id: "e-1".to_string(), reagents_consumed: 0, automatic: true },
+            None,
+            None,
+        ).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&automatic).unwrap();
+        assert_eq!(json["event_type"], "experiment_completed");
+        assert!(json["actor"].is_null());
+
+        // Диспетчер outbox восстанавливает событие из сохранённой строки
+        let restored: BusinessEvent = serde_json::from_str(&line).unwrap();
+        assert_eq!(restored, event);
+        assert_eq!(restored.entity(), ("batch", "b-1"));
+        assert!(BusinessEvent::TYPES.contains(&restored.event_type()));
+    }
+
+    #[actix_web::test]
+    async fn test_request_id_is_scoped_to_the_request() {
+        assert_eq!(request_id_from_header(Some("abc-123")), "abc-123");
+        for hostile in [Some(""), Some("a b"), Some("x\ny"), Some(&"a".repeat(65)[..]), None] {
+            let generated = request_id_from_header(hostile);
+            assert!(uuid::Uuid::parse_str(&generated).is_ok());
+        }
+
+        assert_eq!(current_request_id(), None);
+        let seen = with_request_id("req-42".to_string(), async {
+            tokio::task::yield_now().await;
+            current_request_id()
+        }).await;
+        assert_eq!(seen.as_deref(), Some("req-42"));
+        assert_eq!(current_request_id(), None);
+    }
+}
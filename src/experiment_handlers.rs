@@ -4,12 +4,17 @@
 use actix_web::{web, HttpResponse};
 use actix_files::NamedFile;
 use std::sync::Arc;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use crate::AppState;
 use crate::models::*;
 use crate::error::{ApiError, ApiResult};
+use crate::events::BusinessEvent;
+use crate::outbox;
 use crate::handlers::{ApiResponse, PaginatedResponse};
-use chrono::Utc;
+use crate::pagination::{PageGroup, PageLimits};
+use crate::query_builders::{content_disposition, CountQueryBuilder, FieldWhitelist, FtsQueryBuilder, SafeQueryBuilder, SqlParam};
+use crate::validator::{CustomValidate, ValidationResult};
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 use validator::Validate;
 use log::info;
@@ -23,19 +28,53 @@ pub struct ExperimentQuery {
     pub search: Option<String>,
     pub status: Option<String>,
     pub experiment_type: Option<String>,
+    pub outcome: Option<String>,
     pub location: Option<String>,
     pub date_from: Option<String>,
     pub date_to: Option<String>,
+    /// Только эксперименты команды
+    pub team_id: Option<String>,
+    /// Созданные текущим пользователем или его командами
+    pub mine: Option<bool>,
+    /// Поле сортировки (только из FieldWhitelist::for_experiments, по умолчанию experiment_date)
+    pub sort_by: Option<String>,
     pub sort_order: Option<String>,
     pub page: Option<i64>,
     pub per_page: Option<i64>,
+    /// `?fields=id,title,status` - вернуть только перечисленные поля
+    pub fields: Option<String>,
+    /// Неизвестное поле сортировки - 400 вместо сортировки по умолчанию
+    pub strict: Option<bool>,
+    /// `false` - без reagent_count/equipment_count/document_count в элементах списка
+    pub include_counts: Option<bool>,
 }
 
+/// Статусы и типы эксперимента (CHECK таблицы experiments) - допустимые значения фильтров списка
+pub const EXPERIMENT_STATUSES: &[&str] = &["draft", "planned", "in_progress", "completed", "cancelled", "on_hold"];
+const EXPERIMENT_TYPES: &[&str] = &["educational", "research"];
+
+/// Чужие черновики скрыты: видны только автору и участникам команды-владельца; параметры - user_id дважды
+pub const DRAFT_VISIBILITY_CONDITION: &str =
+    "(status != 'draft' OR created_by = ? OR team_id IN (SELECT team_id FROM team_members WHERE user_id = ?))";
+
+/// Счётчики связанных записей для списка: коррелированные подзапросы по индексам experiment_id
+/// вычисляются в том же SELECT, без отдельного запроса на каждый эксперимент
+const EXPERIMENT_COUNT_COLUMNS: &[&str] = &[
+    "(SELECT COUNT(*) FROM experiment_reagents er WHERE er.experiment_id = experiments.id) AS reagent_count",
+    "(SELECT COUNT(*) FROM experiment_equipment ee WHERE ee.experiment_id = experiments.id) AS equipment_count",
+    "(SELECT COUNT(*) FROM experiment_documents ed WHERE ed.experiment_id = experiments.id) AS document_count",
+];
+
+/// Колонки experiments типа DateTime (для ответов с `?fields=`)
+const EXPERIMENT_DATETIME_COLUMNS: &[&str] = &[
+    "experiment_date", "start_date", "end_date", "created_at", "updated_at",
+];
+
 impl ExperimentQuery {
     /// Normalize pagination parameters and return (page, per_page, offset)
-    pub fn normalize(&self) -> (i64, i64, i64) {
+    pub fn normalize(&self, limits: PageLimits) -> (i64, i64, i64) {
         let page = self.page.unwrap_or(1).max(1);
-        let per_page = self.per_page.unwrap_or(20).clamp(1, 100);
+        let per_page = limits.per_page(self.per_page);
         let offset = (page - 1) * per_page;
         (page, per_page, offset)
     }
@@ -45,102 +84,387 @@ impl ExperimentQuery {
 
 #[derive(Debug, Serialize, sqlx::FromRow)]
 pub struct ExperimentStats {
+    /// Без черновиков - они считаются отдельно в `drafts`
     pub total: i64,
+    pub drafts: i64,
     pub planned: i64,
     pub in_progress: i64,
     pub completed: i64,
     pub cancelled: i64,
     pub educational: i64,
     pub research: i64,
+    // Итоги завершённых экспериментов
+    pub successful: i64,
+    pub partial: i64,
+    pub failed: i64,
+    pub inconclusive: i64,
+    /// Завершённые без указанного итога
+    pub outcome_unset: i64,
 }
 
-// ==================== EXPERIMENT CRUD ====================
+// ==================== OWNERSHIP ====================
+
+/// (created_by, instructor_user_id, team_id, status)
+type ExperimentOwners = (Option<String>, Option<String>, Option<String>, String);
+
+/// Изменять, отменять и удалять эксперимент могут автор, инструктор (instructor_user_id),
+/// участники команды-владельца и администраторы; остальные с правом Edit только читают и комментируют.
+/// Чужой черновик остальным не виден, поэтому для них это 404, а не 403.
+/// Вызывается из *_protected обёрток до хендлера.
+pub async fn ensure_can_manage_experiment(
+    pool: &sqlx::SqlitePool,
+    experiment_id: &str,
+    user_id: &str,
+    is_admin: bool,
+) -> ApiResult<()> {
+    let owners: Option<ExperimentOwners> = sqlx::query_as(
+        "SELECT created_by, instructor_user_id, team_id, status FROM experiments WHERE id = ?"
+    )
+        .bind(experiment_id)
+        .fetch_optional(pool)
+        .await?;
+    let (created_by, instructor_user_id, team_id, status) = owners.ok_or_else(|| ApiError::not_found("Experiment"))?;
 
-pub async fn get_all_experiments(
-    app_state: web::Data<Arc<AppState>>,
-    query: web::Query<ExperimentQuery>,
-) -> ApiResult<HttpResponse> {
-    let (page, per_page, offset) = query.normalize();
-    
-    let mut conditions: Vec<String> = vec!["1=1".to_string()];
-    let mut params: Vec<String> = Vec::new();
+    if is_admin
+        || created_by.as_deref() == Some(user_id)
+        || instructor_user_id.as_deref() == Some(user_id)
+    {
+        return Ok(());
+    }
+    if let Some(team_id) = team_id {
+        if crate::team_handlers::is_team_member(pool, &team_id, user_id).await? {
+            return Ok(());
+        }
+    }
+    if status == "draft" {
+        return Err(ApiError::not_found("Experiment"));
+    }
+    Err(ApiError::Forbidden(
+        "Only the experiment's creator, its instructor, its team or an administrator can modify it".to_string()
+    ))
+}
 
-    // Поиск
-    if let Some(ref search) = query.search {
-        if !search.trim().is_empty() {
-            let pattern = format!("%{}%", search.trim());
-            conditions.push("(title LIKE ? OR description LIKE ? OR instructor LIKE ? OR student_group LIKE ?)".to_string());
-            params.push(pattern.clone());
-            params.push(pattern.clone());
-            params.push(pattern.clone());
-            params.push(pattern);
+/// Черновик виден только тем, кто может им управлять (см. ensure_can_manage_experiment)
+async fn can_view_experiment(
+    pool: &sqlx::SqlitePool,
+    experiment: &Experiment,
+    claims: &crate::auth::Claims,
+) -> ApiResult<bool> {
+    if experiment.status != "draft"
+        || claims.role == crate::auth::UserRole::Admin
+        || experiment.created_by == claims.sub
+        || experiment.instructor_user_id.as_deref() == Some(claims.sub.as_str())
+    {
+        return Ok(true);
+    }
+    match experiment.team_id.as_deref() {
+        Some(team_id) => crate::team_handlers::is_team_member(pool, team_id, &claims.sub).await,
+        None => Ok(false),
+    }
+}
+
+/// Эксперимент, видимый текущему пользователю (чужой черновик - 404)
+pub(crate) async fn fetch_visible_experiment(
+    pool: &sqlx::SqlitePool,
+    experiment_id: &str,
+    claims: &crate::auth::Claims,
+) -> ApiResult<Experiment> {
+    let experiment: Option<Experiment> = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(experiment_id)
+        .fetch_optional(pool)
+        .await?;
+    match experiment {
+        Some(experiment) if can_view_experiment(pool, &experiment, claims).await? => Ok(experiment),
+        _ => Err(ApiError::not_found("Experiment")),
+    }
+}
+
+/// instructor_user_id должен ссылаться на существующего пользователя
+async fn validate_instructor_user(pool: &sqlx::SqlitePool, instructor_user_id: Option<&str>) -> ApiResult<()> {
+    if let Some(uid) = instructor_user_id {
+        let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM users WHERE id = ?")
+            .bind(uid)
+            .fetch_optional(pool)
+            .await?;
+        if exists.is_none() {
+            return Err(ApiError::not_found("Instructor user"));
         }
     }
-    
-    // Фильтры
-    if let Some(ref status) = query.status {
-        conditions.push("status = ?".to_string());
-        params.push(status.clone());
+    Ok(())
+}
+
+/// Проверка формы эксперимента для create_experiment и POST /validate/experiment.
+/// Для черновика помещение не проверяется - это делает publish_experiment
+pub async fn validate_experiment_request(
+    pool: &sqlx::SqlitePool,
+    experiment: &CreateExperimentRequest,
+    existing_id: Option<&str>,
+) -> ApiResult<ValidationResult> {
+    let mut result = ValidationResult::from_validate(experiment);
+    result.merge(experiment.custom_validate());
+
+    let instructor_user_id = experiment.instructor_user_id.as_deref().map(str::trim).filter(|u| !u.is_empty());
+    match validate_instructor_user(pool, instructor_user_id).await {
+        Ok(()) => {}
+        Err(ApiError::NotFound(message)) => result.add_error("instructor_user_id", message),
+        Err(e) => return Err(e),
     }
-    if let Some(ref exp_type) = query.experiment_type {
-        conditions.push("experiment_type = ?".to_string());
-        params.push(exp_type.clone());
+    let team_id = crate::team_handlers::normalize_team_id(experiment.team_id.as_deref());
+    match crate::team_handlers::ensure_team_exists(pool, team_id).await {
+        Ok(()) => {}
+        Err(ApiError::NotFound(message)) => result.add_error("team_id", message),
+        Err(e) => return Err(e),
     }
-    if let Some(ref location) = query.location {
-        conditions.push("location = ?".to_string());
-        params.push(location.clone());
+
+    if let Some(room_id) = experiment.room_id.as_deref().map(str::trim).filter(|r| !r.is_empty() && !experiment.is_draft()) {
+        let start = experiment.start_date.or(experiment.experiment_date);
+        check_room_conflicts(pool, room_id, start, experiment.end_date, existing_id, &mut result).await?;
     }
-    if let Some(ref date_from) = query.date_from {
-        conditions.push("experiment_date >= ?".to_string());
-        params.push(date_from.clone());
+
+    Ok(result)
+}
+
+/// Время вне часов работы помещения или помещение уже занято запланированным или идущим
+/// экспериментом на это время (room_id или, для старых записей, location = имя помещения).
+/// Без начала проверяется только само помещение
+pub async fn check_room_conflicts(
+    pool: &sqlx::SqlitePool,
+    room_id: &str,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    existing_id: Option<&str>,
+    result: &mut ValidationResult,
+) -> ApiResult<()> {
+    let room: Option<crate::models::Room> = sqlx::query_as("SELECT * FROM rooms WHERE id = ?")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await?;
+    let Some(room) = room else {
+        result.add_error("room_id", "Room not found");
+        return Ok(());
+    };
+    if room.status == "maintenance" || room.status == "unavailable" {
+        result.add_warning("room_id", format!("Room is currently {}", room.status));
     }
-    if let Some(ref date_to) = query.date_to {
-        conditions.push("experiment_date <= ?".to_string());
-        params.push(date_to.clone());
+
+    let Some(start) = start else { return Ok(()) };
+    let hours = crate::room_handlers::OpeningHours::from_room(&room);
+    if let Err((field, message)) = hours.check_booking(&room.name, start, end) {
+        result.add_error(field, message);
+    }
+
+    // Без времени окончания пересечение не определить
+    let Some(end) = end else { return Ok(()) };
+
+    let conflict: Option<(String,)> = sqlx::query_as(r#"
+        SELECT e.title FROM experiments e
+        JOIN rooms r ON r.id = ?
+        WHERE (e.room_id = r.id OR (e.room_id IS NULL AND e.location = r.name))
+          AND e.id IS NOT ?
+          AND e.status IN ('planned', 'in_progress')
+          AND e.end_date IS NOT NULL
+          AND julianday(e.start_date) < julianday(?)
+          AND julianday(e.end_date) > julianday(?)
+        ORDER BY e.start_date
+        LIMIT 1
+    "#)
+        .bind(room_id)
+        .bind(existing_id)
+        .bind(end)
+        .bind(start)
+        .fetch_optional(pool)
+        .await?;
+
+    if let Some((title,)) = conflict {
+        result.add_error("room_id", format!("Room is already booked for '{}' at this time", title));
     }
+    Ok(())
+}
+
+// ==================== EXPERIMENT CRUD ====================
+
+#[derive(Debug, Serialize)]
+pub struct ExperimentDetailResponse {
+    #[serde(flatten)]
+    pub experiment: Experiment,
+    pub participants: Vec<ExperimentParticipant>,
+    pub links: Vec<ExternalLink>,
+    /// Списанные реагенты с реквизитами партии на момент списания
+    pub consumptions: Vec<ExperimentConsumption>,
+    #[serde(flatten)]
+    pub signoff_status: SignoffStatus,
+    #[serde(flatten)]
+    pub dependency_status: DependencyStatus,
+}
 
-    let where_clause = conditions.join(" AND ");
-    let sort_order = query.sort_order.as_deref().unwrap_or("DESC");
+pub async fn get_all_experiments(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ExperimentQuery>,
+    http_request: actix_web::HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let viewer = crate::auth::get_current_user(&http_request)?.sub;
+    let limits = app_state.page_limits(PageGroup::Experiments);
+    let capped = limits.is_capped(query.per_page);
+    let (page, per_page, offset) = query.normalize(limits);
+    let whitelist = FieldWhitelist::for_experiments();
+    let fields = crate::handlers::parse_fields_param(query.fields.as_deref(), &whitelist)?;
+    let strict = crate::handlers::strict_fields(query.strict);
+
+    // Поле сортировки только из whitelist (без префикса таблицы), направление нормализуется в order_by
+    let sort_field = match query.sort_by.as_deref() {
+        Some(field) if whitelist.is_allowed(field) && !field.contains('.') => field,
+        Some(field) if strict => return Err(ApiError::BadRequest(whitelist.unknown_fields_error(&[field]))),
+        _ => "experiment_date",
+    };
 
     // Подсчёт
-    let count_sql = format!("SELECT COUNT(*) as count FROM experiments WHERE {}", where_clause);
+    let mut count_builder = CountQueryBuilder::new("experiments")
+        .map_err(ApiError::InternalServerError)?
+        .with_whitelist(&whitelist)
+        .strict(strict);
+    let use_fts = FtsQueryBuilder::check_fts_table_available(&app_state.db_pool, "experiments_fts").await;
+    let conditions = experiment_conditions(&query, use_fts, &viewer);
+    for (condition, params) in &conditions {
+        count_builder.add_condition(condition, params.clone());
+    }
+    let multi_filters = experiment_multi_filters(&query)?;
+    for (field, values) in &multi_filters {
+        count_builder.add_in_clause(field, values);
+    }
+    count_builder.check_rejected().map_err(ApiError::BadRequest)?;
+
+    let (count_sql, count_params) = count_builder.build();
     let mut count_query = sqlx::query_scalar::<_, i64>(&count_sql);
-    for p in &params {
+    for p in &count_params {
         count_query = count_query.bind(p);
     }
     let total: i64 = count_query.fetch_one(&app_state.db_pool).await?;
 
     // Выборка данных
-    let sql = format!(
-        "SELECT * FROM experiments WHERE {} ORDER BY experiment_date {} LIMIT ? OFFSET ?",
-        where_clause, sort_order
-    );
+    let mut select_builder = SafeQueryBuilder::new("SELECT * FROM experiments")
+        .map_err(ApiError::InternalServerError)?
+        .with_whitelist(&whitelist)
+        .strict(strict);
+    for (condition, params) in conditions {
+        select_builder.add_condition(condition, params);
+    }
+    for (field, values) in &multi_filters {
+        select_builder.add_in_clause(field, values);
+    }
+    select_builder.order_by(sort_field, query.sort_order.as_deref().unwrap_or("DESC"));
+    select_builder.check_rejected().map_err(ApiError::BadRequest)?;
+    select_builder.limit(per_page);
+    select_builder.offset(offset);
+    let total_pages = (total + per_page - 1) / per_page;
+
+    if let Some(fields) = fields {
+        select_builder.select_columns(&fields).map_err(ApiError::BadRequest)?;
+        let (sql, params) = select_builder.build();
+        let data = crate::handlers::fetch_json_rows(
+            &app_state.db_pool, &sql, &params, EXPERIMENT_DATETIME_COLUMNS,
+        ).await?;
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
+            data, total, page, per_page, total_pages, limits, capped
+        })));
+    }
+
+    if query.include_counts.unwrap_or(true) {
+        for column in EXPERIMENT_COUNT_COLUMNS {
+            select_builder.select_extra(column);
+        }
+    }
+    let (sql, params) = select_builder.build();
     let mut select_query = sqlx::query_as::<_, Experiment>(&sql);
     for p in &params {
         select_query = select_query.bind(p);
     }
-    select_query = select_query.bind(per_page).bind(offset);
-    let experiments: Vec<Experiment> = select_query.fetch_all(&app_state.db_pool).await?;
+    let mut experiments: Vec<Experiment> = select_query.fetch_all(&app_state.db_pool).await?;
+    crate::team_handlers::expand_team_names(&app_state.db_pool, &mut experiments).await?;
 
-    let total_pages = (total + per_page - 1) / per_page;
     Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse { 
-        data: experiments, total, page, per_page, total_pages 
+        data: experiments, total, page, per_page, total_pages, limits, capped
     })))
 }
 
+/// Общий набор условий для выборки и подсчёта экспериментов.
+/// При наличии experiments_fts текстовые поля (в т.ч. results/protocol/notes) ищутся через FTS5.
+/// Черновики видны только их автору (`viewer`) и его командам, в том числе при `?status=draft`.
+fn experiment_conditions(query: &ExperimentQuery, use_fts: bool, viewer: &str) -> Vec<(&'static str, Vec<SqlParam>)> {
+    let mut conditions = vec![(DRAFT_VISIBILITY_CONDITION, vec![viewer.into(), viewer.into()])];
+
+    // Поиск
+    if let Some(ref search) = query.search {
+        let fts_query = FtsQueryBuilder::build_fts_query(search.trim());
+        if use_fts && !fts_query.is_empty() {
+            let pattern = format!("%{}%", search.trim());
+            conditions.push((
+                "(rowid IN (SELECT rowid FROM experiments_fts WHERE experiments_fts MATCH ?) \
+                 OR instructor LIKE ? OR student_group LIKE ?)",
+                vec![fts_query.into(), pattern.clone().into(), pattern.into()],
+            ));
+        } else if !search.trim().is_empty() {
+            let pattern = format!("%{}%", search.trim());
+            conditions.push((
+                "(title LIKE ? OR description LIKE ? OR instructor LIKE ? OR student_group LIKE ?)",
+                vec![pattern.clone().into(), pattern.clone().into(), pattern.clone().into(), pattern.into()],
+            ));
+        }
+    }
+
+    // Фильтры (status, experiment_type, location - см. experiment_multi_filters)
+    if let Some(ref outcome) = query.outcome {
+        conditions.push(("outcome = ?", vec![outcome.into()]));
+    }
+    if let Some(ref date_from) = query.date_from {
+        conditions.push(("experiment_date >= ?", vec![date_from.into()]));
+    }
+    if let Some(ref date_to) = query.date_to {
+        conditions.push(("experiment_date <= ?", vec![date_to.into()]));
+    }
+    if let Some(team_id) = crate::team_handlers::normalize_team_id(query.team_id.as_deref()) {
+        conditions.push(("team_id = ?", vec![team_id.into()]));
+    }
+    if query.mine.unwrap_or(false) {
+        conditions.push((crate::team_handlers::MINE_CONDITION, vec![viewer.into(), viewer.into()]));
+    }
+
+    conditions
+}
+
+/// Мультивыбор `?status=planned,in_progress&experiment_type=...&location=...` -> (колонка, значения)
+fn experiment_multi_filters(query: &ExperimentQuery) -> ApiResult<[(&'static str, Vec<String>); 3]> {
+    use crate::handlers::parse_multi_filter;
+    Ok([
+        ("status", parse_multi_filter(query.status.as_deref(), "status", Some(EXPERIMENT_STATUSES))?),
+        ("experiment_type", parse_multi_filter(query.experiment_type.as_deref(), "experiment_type", Some(EXPERIMENT_TYPES))?),
+        ("location", parse_multi_filter(query.location.as_deref(), "location", None)?),
+    ])
+}
+
 pub async fn get_experiment(
     app_state: web::Data<Arc<AppState>>, 
-    path: web::Path<String>
+    path: web::Path<String>,
+    http_request: actix_web::HttpRequest,
 ) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
     let experiment_id = path.into_inner();
-    let experiment: Option<Experiment> = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
-        .bind(&experiment_id)
-        .fetch_optional(&app_state.db_pool)
-        .await?;
-    match experiment {
-        Some(exp) => Ok(HttpResponse::Ok().json(ApiResponse::success(exp))),
-        None => Err(ApiError::not_found("Experiment")),
-    }
+    let mut experiment = fetch_visible_experiment(&app_state.db_pool, &experiment_id, &claims).await?;
+    crate::team_handlers::expand_team_names(&app_state.db_pool, std::slice::from_mut(&mut experiment)).await?;
+    let participants = fetch_participants(&app_state.db_pool, &experiment_id).await?;
+    let links = crate::link_handlers::fetch_links(&app_state.db_pool, LinkEntityType::Experiment, &experiment_id).await?;
+    let consumptions = fetch_consumptions(&app_state.db_pool, Some(&experiment_id)).await?;
+    let signoff_status = fetch_signoff_status(
+        &app_state.db_pool, &experiment_id, app_state.config.safety.signoff_hazard_threshold,
+    ).await?;
+    let dependency_status = fetch_dependency_status(&app_state.db_pool, &experiment_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(ExperimentDetailResponse {
+        experiment,
+        participants,
+        links,
+        consumptions,
+        signoff_status,
+        dependency_status,
+    })))
 }
 
 pub async fn create_experiment(
@@ -148,20 +472,23 @@ pub async fn create_experiment(
     experiment: web::Json<CreateExperimentRequest>, 
     user_id: String
 ) -> ApiResult<HttpResponse> {
-    experiment.validate()?;
-    experiment.validate_educational().map_err(|e| ApiError::bad_request(&e))?;
+    validate_experiment_request(&app_state.db_pool, &experiment, None).await?.ensure_valid()?;
 
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
     let exp_date = experiment.experiment_date.unwrap_or(now);
     let start_date = experiment.start_date.unwrap_or(exp_date);
+    let instructor_user_id = experiment.instructor_user_id.as_deref().map(str::trim).filter(|u| !u.is_empty());
+    let room_id = experiment.room_id.as_deref().map(str::trim).filter(|r| !r.is_empty());
+    let team_id = crate::team_handlers::normalize_team_id(experiment.team_id.as_deref());
+    let status = if experiment.is_draft() { "draft" } else { "planned" };
 
     sqlx::query(r#"
         INSERT INTO experiments 
         (id, title, description, experiment_date, experiment_type, 
-         instructor, student_group, location, protocol, start_date, end_date, notes,
-         status, created_by, updated_by, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'planned', ?, ?, ?, ?)
+         instructor, instructor_user_id, student_group, location, room_id, protocol, start_date, end_date, notes,
+         status, team_id, created_by, updated_by, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
     "#)
         .bind(&id)
         .bind(&experiment.title)
@@ -169,12 +496,16 @@ pub async fn create_experiment(
         .bind(&exp_date)
         .bind(&experiment.experiment_type)
         .bind(&experiment.instructor)
+        .bind(instructor_user_id)
         .bind(&experiment.student_group)
         .bind(&experiment.location)
+        .bind(room_id)
         .bind(&experiment.protocol)
         .bind(&start_date)
         .bind(&experiment.end_date)
         .bind(&experiment.notes)
+        .bind(status)
+        .bind(team_id)
         .bind(&user_id)
         .bind(&user_id)
         .bind(&now)
@@ -182,15 +513,28 @@ pub async fn create_experiment(
         .execute(&app_state.db_pool)
         .await?;
 
-    let created: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    let mut created: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&id)
         .fetch_one(&app_state.db_pool)
         .await?;
+    crate::team_handlers::expand_team_names(&app_state.db_pool, std::slice::from_mut(&mut created)).await?;
 
     info!("User {} created experiment: {}", user_id, id);
     Ok(HttpResponse::Created().json(ApiResponse::success(created)))
 }
 
+/// Черновик становится запланированным только через publish_experiment,
+/// а опубликованный эксперимент не возвращается в черновики
+fn ensure_draft_transition(current: &str, next: &str) -> ApiResult<()> {
+    match (current == "draft", next == "draft") {
+        (true, false) => Err(ApiError::bad_request(
+            "Draft experiments must be published via POST /experiments/{id}/publish"
+        )),
+        (false, true) => Err(ApiError::bad_request("A published experiment cannot return to draft")),
+        _ => Ok(()),
+    }
+}
+
 pub async fn update_experiment(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
@@ -217,8 +561,26 @@ pub async fn update_experiment(
     let experiment_date = update.experiment_date.unwrap_or(existing.experiment_date);
     let experiment_type = update.experiment_type.clone().or(existing.experiment_type.clone());
     let instructor = update.instructor.clone().or(existing.instructor.clone());
+    // Пустая строка снимает привязку инструктора
+    let instructor_user_id = match update.instructor_user_id.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(uid) => Some(uid.to_string()),
+        None => existing.instructor_user_id.clone(),
+    };
+    if instructor_user_id != existing.instructor_user_id {
+        validate_instructor_user(&app_state.db_pool, instructor_user_id.as_deref()).await?;
+    }
+    // Пустая строка снимает команду-владельца
+    let team_id = match update.team_id.as_deref() {
+        Some(team_id) => crate::team_handlers::normalize_team_id(Some(team_id)).map(str::to_string),
+        None => existing.team_id.clone(),
+    };
+    if team_id != existing.team_id {
+        crate::team_handlers::ensure_team_exists(&app_state.db_pool, team_id.as_deref()).await?;
+    }
     let student_group = update.student_group.clone().or(existing.student_group.clone());
     let status = update.status.as_ref().unwrap_or(&existing.status);
+    ensure_draft_transition(&existing.status, status)?;
     let location = update.location.clone().or(existing.location.clone());
     let room_id = update.room_id.clone().or(existing.room_id.clone());
     let protocol = update.protocol.clone().or(existing.protocol.clone());
@@ -227,10 +589,38 @@ pub async fn update_experiment(
     let start_date = update.start_date.unwrap_or(existing.start_date);
     let end_date = update.end_date.or(existing.end_date);
 
+    // Те же проверки расписания, что и при создании, - по итоговым значениям
+    let mut checks = ValidationResult::new();
+    if status != "draft" && (update.experiment_type.is_some() || update.start_date.is_some() || update.end_date.is_some()) {
+        if let Err((field, message)) = educational_schedule(experiment_type.as_deref(), Some(start_date), end_date) {
+            checks.add_error(field, message);
+        }
+    }
+    let schedule_changed = room_id != existing.room_id
+        || start_date != existing.start_date
+        || end_date != existing.end_date;
+    if schedule_changed && matches!(status.as_str(), "planned" | "in_progress") {
+        if let Some(room_id) = room_id.as_deref().filter(|r| !r.is_empty()) {
+            check_room_conflicts(&app_state.db_pool, room_id, Some(start_date), end_date, Some(&experiment_id), &mut checks).await?;
+        }
+    }
+    checks.ensure_valid()?;
+
+    if status == "in_progress" && existing.status != "in_progress" {
+        ensure_signoff(&app_state.db_pool, &experiment_id, app_state.config.safety.signoff_hazard_threshold).await?;
+        ensure_dependencies_completed(&app_state.db_pool, &experiment_id).await?;
+    }
+
+    // Итог задаётся только в момент завершения
+    let completing = status == "completed" && existing.status != "completed";
+    if update.outcome.is_some() && !completing {
+        return Err(ApiError::bad_request("Outcome can only be set when completing an experiment"));
+    }
+
     // === ЖЕЛЕЗОБЕТОННОЕ АВТО-СПИСАНИЕ (в единой транзакции с обновлением) ===
     let mut tx = app_state.db_pool.begin().await?;
 
-    if status == "completed" && existing.status != "completed" {
+    if completing {
         let reagents: Vec<ExperimentReagent> = sqlx::query_as(r#"
             SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
             FROM experiment_reagents 
@@ -257,10 +647,7 @@ pub async fn update_experiment(
                         .await?;
                 }
 
-                sqlx::query("UPDATE experiment_reagents SET is_consumed = 1 WHERE id = ?")
-                    .bind(&reagent.id)
-                    .execute(&mut *tx)
-                    .await?;
+                mark_reagent_consumed(&mut tx, &reagent.id, Some(&user_id), None, now).await?;
             }
         }
     } else if status == "cancelled" && existing.status != "cancelled" {
@@ -294,9 +681,9 @@ pub async fn update_experiment(
     sqlx::query(r#"
         UPDATE experiments SET 
         title = ?, description = ?, experiment_date = ?, experiment_type = ?, 
-        instructor = ?, student_group = ?, status = ?, location = ?, room_id = ?,
+        instructor = ?, instructor_user_id = ?, student_group = ?, status = ?, location = ?, room_id = ?,
         protocol = ?, start_date = ?, end_date = ?, results = ?, notes = ?,
-        updated_by = ?, updated_at = ?
+        outcome = COALESCE(?, outcome), team_id = ?, updated_by = ?, updated_at = ?
         WHERE id = ?
     "#)
         .bind(title)
@@ -304,6 +691,7 @@ pub async fn update_experiment(
         .bind(&experiment_date)
         .bind(&experiment_type)
         .bind(&instructor)
+        .bind(&instructor_user_id)
         .bind(&student_group)
         .bind(status)
         .bind(&location)
@@ -313,6 +701,8 @@ pub async fn update_experiment(
         .bind(&end_date)
         .bind(&results)
         .bind(&notes)
+        .bind(&update.outcome)
+        .bind(&team_id)
         .bind(&user_id)
         .bind(&now)
         .bind(&experiment_id)
@@ -321,10 +711,15 @@ pub async fn update_experiment(
 
     tx.commit().await?;
 
-    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    if protocol != existing.protocol {
+        crate::protocol_render::invalidate(&experiment_id);
+    }
+
+    let mut updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&experiment_id)
         .fetch_one(&app_state.db_pool)
         .await?;
+    crate::team_handlers::expand_team_names(&app_state.db_pool, std::slice::from_mut(&mut updated)).await?;
 
     info!("User {} updated experiment: {}", user_id, experiment_id);
     Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
@@ -337,10 +732,12 @@ pub async fn delete_experiment(
 ) -> ApiResult<HttpResponse> {
     let experiment_id = path.into_inner();
 
+    // Реагенты черновика не зарезервированы
     let reagents: Vec<ExperimentReagent> = sqlx::query_as(r#"
-        SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
-        FROM experiment_reagents 
-        WHERE experiment_id = ? AND is_consumed = 0
+        SELECT er.id, er.experiment_id, er.batch_id, er.planned_quantity, er.is_consumed, er.notes, er.created_at
+        FROM experiment_reagents er
+        JOIN experiments e ON e.id = er.experiment_id
+        WHERE er.experiment_id = ? AND er.is_consumed = 0 AND e.status != 'draft'
     "#)
         .bind(&experiment_id)
         .fetch_all(&app_state.db_pool)
@@ -364,6 +761,12 @@ pub async fn delete_experiment(
         .execute(&mut *tx)
         .await?;
 
+    // Журнал расхода остаётся, но без ссылки на удаляемый эксперимент (иначе удаление отклонит внешний ключ)
+    sqlx::query("UPDATE usage_logs SET experiment_id = NULL WHERE experiment_id = ?")
+        .bind(&experiment_id)
+        .execute(&mut *tx)
+        .await?;
+
     let result = sqlx::query("DELETE FROM experiments WHERE id = ?")
         .bind(&experiment_id)
         .execute(&mut *tx)
@@ -374,6 +777,7 @@ pub async fn delete_experiment(
     }
 
     tx.commit().await?;
+    crate::protocol_render::invalidate(&experiment_id);
 
     info!("User {} deleted experiment: {}", user_id, experiment_id);
     Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
@@ -404,6 +808,17 @@ pub async fn update_experiment_status(
         )));
     }
 
+    let current: Option<String> = sqlx::query_scalar("SELECT status FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?;
+    let current = current.ok_or_else(|| ApiError::not_found("Experiment"))?;
+    ensure_draft_transition(&current, &body.status)?;
+    if body.status == "in_progress" && current != "in_progress" {
+        ensure_signoff(&app_state.db_pool, &experiment_id, app_state.config.safety.signoff_hazard_threshold).await?;
+        ensure_dependencies_completed(&app_state.db_pool, &experiment_id).await?;
+    }
+
     let result = sqlx::query(
         "UPDATE experiments SET status = ?, updated_by = ?, updated_at = ? WHERE id = ?"
     )
@@ -434,16 +849,22 @@ pub async fn get_experiment_stats(
 ) -> ApiResult<HttpResponse> {
     let stats: ExperimentStats = sqlx::query_as(r#"
         SELECT 
-            COUNT(*) as total,
+            SUM(CASE WHEN status != 'draft' THEN 1 ELSE 0 END) as total,
+            SUM(CASE WHEN status = 'draft' THEN 1 ELSE 0 END) as drafts,
             SUM(CASE WHEN status = 'planned' THEN 1 ELSE 0 END) as planned,
             SUM(CASE WHEN status = 'in_progress' THEN 1 ELSE 0 END) as in_progress,
             SUM(CASE WHEN status = 'completed' THEN 1 ELSE 0 END) as completed,
             SUM(CASE WHEN status = 'cancelled' THEN 1 ELSE 0 END) as cancelled,
-            SUM(CASE WHEN experiment_type = 'educational' THEN 1 ELSE 0 END) as educational,
-            SUM(CASE WHEN experiment_type = 'research' THEN 1 ELSE 0 END) as research
+            SUM(CASE WHEN experiment_type = 'educational' AND status != 'draft' THEN 1 ELSE 0 END) as educational,
+            SUM(CASE WHEN experiment_type = 'research' AND status != 'draft' THEN 1 ELSE 0 END) as research,
+            SUM(CASE WHEN status = 'completed' AND outcome = 'successful' THEN 1 ELSE 0 END) as successful,
+            SUM(CASE WHEN status = 'completed' AND outcome = 'partial' THEN 1 ELSE 0 END) as partial,
+            SUM(CASE WHEN status = 'completed' AND outcome = 'failed' THEN 1 ELSE 0 END) as failed,
+            SUM(CASE WHEN status = 'completed' AND outcome = 'inconclusive' THEN 1 ELSE 0 END) as inconclusive,
+            SUM(CASE WHEN status = 'completed' AND outcome IS NULL THEN 1 ELSE 0 END) as outcome_unset
         FROM experiments
     "#)
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(app_state.read_pool())
         .await?;
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(stats)))
@@ -471,6 +892,16 @@ pub struct ExperimentReagentWithDetails {
     pub is_consumed: bool,
     pub notes: Option<String>,
     pub created_at: chrono::DateTime<Utc>,
+    /// Количество в единице ввода (например, mmol); quantity_used - зарезервированное в единице партии
+    pub entered_quantity: Option<f64>,
+    pub entered_unit: Option<String>,
+    /// Списание: когда, кем, пометка и реквизиты партии на момент списания (не меняются вместе с партией)
+    pub consumed_at: Option<chrono::DateTime<Utc>>,
+    pub consumed_by: Option<String>,
+    pub consumption_notes: Option<String>,
+    pub lot_batch_number: Option<String>,
+    pub lot_supplier: Option<String>,
+    pub lot_expiry_date: Option<chrono::DateTime<Utc>>,
     // Batch details
     pub batch_number: String,
     pub unit: String,
@@ -497,6 +928,9 @@ pub async fn get_experiment_reagents(
         SELECT 
             er.id, er.experiment_id, er.batch_id, 
             er.planned_quantity as quantity_used, er.is_consumed, er.notes, er.created_at,
+            er.entered_quantity, er.entered_unit,
+            er.consumed_at, er.consumed_by, er.consumption_notes,
+            er.lot_batch_number, er.lot_supplier, er.lot_expiry_date,
             b.batch_number, b.unit, b.quantity as available_quantity,
             b.reagent_id, r.name as reagent_name
         FROM experiment_reagents er
@@ -504,12 +938,14 @@ pub async fn get_experiment_reagents(
         JOIN reagents r ON b.reagent_id = r.id
         WHERE er.experiment_id = ?
         ORDER BY er.created_at DESC
+        LIMIT ?
     "#)
         .bind(&experiment_id)
+        .bind(crate::handlers::MAX_NESTED_LIST_ROWS + 1)
         .fetch_all(&app_state.db_pool)
         .await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(reagents)))
+    Ok(crate::handlers::NestedList::new(reagents).into_response())
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -517,87 +953,207 @@ pub struct AddReagentToExperimentRequest {
     pub batch_id: String,
     #[validate(range(min = 0.001, message = "Quantity must be positive"))]
     pub quantity_used: f64,
+    /// Единица quantity_used, если она не единица партии: другая единица массы/объёма или
+    /// mol/mmol (пересчитываются в массу по молярной массе реагента)
+    pub unit: Option<String>,
     pub notes: Option<String>,
 }
 
+/// Количество в единице партии и количество в единице ввода (если она указана и отличается)
+async fn quantity_in_batch_unit(
+    conn: &mut sqlx::SqliteConnection,
+    batch_id: &str,
+    quantity: f64,
+    unit: Option<&str>,
+) -> ApiResult<(f64, Option<(f64, String)>)> {
+    let Some(unit) = unit.map(str::trim).filter(|u| !u.is_empty()) else {
+        return Ok((quantity, None));
+    };
+    let (batch_unit, reagent_name, molecular_weight): (String, String, Option<f64>) = sqlx::query_as(
+        "SELECT b.unit, r.name, r.molecular_weight FROM batches b JOIN reagents r ON r.id = b.reagent_id WHERE b.id = ?"
+    )
+        .bind(batch_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Batch"))?;
+    if unit == batch_unit {
+        return Ok((quantity, None));
+    }
+    let converted = crate::batch_handlers::convert_amount(quantity, unit, &batch_unit, &reagent_name, molecular_weight)?;
+    Ok((converted, Some((quantity, unit.to_string()))))
+}
+
 pub async fn add_reagent_to_experiment(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
     body: web::Json<AddReagentToExperimentRequest>,
-    _user_id: String,
+    user_id: String,
 ) -> ApiResult<HttpResponse> {
     body.validate()?;
     let experiment_id = path.into_inner();
 
+    let mut tx = app_state.db_pool.begin().await?;
+    let (quantity, entered) = quantity_in_batch_unit(&mut tx, &body.batch_id, body.quantity_used, body.unit.as_deref()).await?;
+    let (batch, reserve) = check_reagent_reservation(&mut tx, &experiment_id, &body.batch_id, quantity).await?;
+
+    // Крупный резерв выполняется только после согласования
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+        .bind(&batch.reagent_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if crate::approval_handlers::exceeds_approval_threshold(&reagent, quantity, &batch.unit) {
+        tx.rollback().await?;
+        let approval = crate::approval_handlers::NewApproval {
+            operation: crate::approval_handlers::ApprovalOperation::ExperimentReagent,
+            reagent_id: &batch.reagent_id,
+            batch_id: &body.batch_id,
+            experiment_id: Some(&experiment_id),
+            quantity,
+            unit: &batch.unit,
+            purpose: None,
+            notes: body.notes.as_deref(),
+            requested_by: &user_id,
+        };
+        return crate::approval_handlers::request_approval(&app_state, approval, None).await;
+    }
+
+    let id = insert_reagent_reservation(&mut tx, &experiment_id, &body.batch_id, &batch, quantity, body.notes.as_deref(), reserve).await?;
+    if let Some((entered_quantity, entered_unit)) = &entered {
+        sqlx::query("UPDATE experiment_reagents SET entered_quantity = ?, entered_unit = ? WHERE id = ?")
+            .bind(entered_quantity)
+            .bind(entered_unit)
+            .bind(&id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(serde_json::json!({
+        "id": id,
+        "quantity": quantity,
+        "unit": batch.unit,
+        "entered_quantity": entered.as_ref().map(|(q, _)| *q),
+        "entered_unit": entered.as_ref().map(|(_, u)| u),
+        "message": "Reagent added to experiment"
+    }))))
+}
+
+/// Партия, из которой резервируется реагент для эксперимента
+#[derive(sqlx::FromRow)]
+struct ReservationBatch {
+    reagent_id: String,
+    unit: String,
+    quantity: f64,
+    reserved_quantity: f64,
+    batch_number: String,
+    status: String,
+}
+
+/// Проверки перед резервом: эксперимент изменяем, партия существует, получен COA и хватает остатка.
+/// В черновик реагент записывается без резерва и проверки остатка (второе значение - резервировать ли сейчас)
+async fn check_reagent_reservation(
+    conn: &mut sqlx::SqliteConnection,
+    experiment_id: &str,
+    batch_id: &str,
+    quantity: f64,
+) -> ApiResult<(ReservationBatch, bool)> {
     // Check experiment exists and is modifiable
     let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
-        .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
+        .bind(experiment_id)
+        .fetch_one(&mut *conn)
         .await
         .map_err(|_| ApiError::not_found("Experiment"))?;
 
-    if !["planned", "in_progress"].contains(&experiment.status.as_str()) {
+    if !["draft", "planned", "in_progress"].contains(&experiment.status.as_str()) {
         return Err(ApiError::bad_request("Cannot add reagents to completed or cancelled experiment"));
     }
 
-    #[derive(sqlx::FromRow)]
-    struct BatchInfo {
-        reagent_id: String,
-        unit: String,
-        quantity: f64,
-        reserved_quantity: f64,
-    }
+    let reserve = experiment.status != "draft";
+    let batch = check_reservation_batch(conn, batch_id, reserve.then_some(quantity)).await?;
+    Ok((batch, reserve))
+}
 
-    // Check batch exists and has enough quantity
-    let batch: BatchInfo = sqlx::query_as("SELECT reagent_id, unit, quantity, reserved_quantity FROM batches WHERE id = ?")
-        .bind(&body.batch_id)
-        .fetch_one(&app_state.db_pool)
+/// Партия существует, получен COA, реагент активен и (если задано количество) хватает свободного остатка
+async fn check_reservation_batch(
+    conn: &mut sqlx::SqliteConnection,
+    batch_id: &str,
+    quantity: Option<f64>,
+) -> ApiResult<ReservationBatch> {
+    let batch: ReservationBatch = sqlx::query_as(
+        "SELECT reagent_id, unit, quantity, reserved_quantity, batch_number, status FROM batches WHERE id = ?"
+    )
+        .bind(batch_id)
+        .fetch_one(&mut *conn)
         .await
         .map_err(|_| ApiError::not_found("Batch"))?;
 
+    if batch.status == "awaiting_coa" {
+        return Err(ApiError::batch_awaiting_coa(&batch.batch_number));
+    }
+    // Новые резервы из партий деактивированного реагента запрещены; уже созданные завершаются как обычно
+    crate::reagent_handlers::ensure_reagent_active_by_id(&mut *conn, &batch.reagent_id).await?;
+
     let available = batch.quantity - batch.reserved_quantity;
-    if body.quantity_used > available {
-        return Err(ApiError::insufficient_quantity(available, body.quantity_used));
+    if let Some(quantity) = quantity.filter(|q| *q > available) {
+        return Err(ApiError::insufficient_quantity(available, quantity));
     }
+    Ok(batch)
+}
 
+async fn insert_reagent_reservation(
+    conn: &mut sqlx::SqliteConnection,
+    experiment_id: &str,
+    batch_id: &str,
+    batch: &ReservationBatch,
+    quantity: f64,
+    notes: Option<&str>,
+    reserve: bool,
+) -> ApiResult<String> {
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
 
-    let mut tx = app_state.db_pool.begin().await?;
-
     // Add reagent to experiment
-sqlx::query(r#"
+    sqlx::query(r#"
         INSERT INTO experiment_reagents (
-            id, experiment_id, reagent_id, batch_id, 
+            id, experiment_id, reagent_id, batch_id,
             planned_quantity, unit, notes, created_at, updated_at
         )
         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
     "#)
         .bind(&id)
-        .bind(&experiment_id)
+        .bind(experiment_id)
         .bind(&batch.reagent_id)
-        .bind(&body.batch_id)
-        .bind(body.quantity_used)
+        .bind(batch_id)
+        .bind(quantity)
         .bind(&batch.unit)
-        .bind(&body.notes)
-        .bind(&now)
-        .bind(&now)
-        .execute(&mut *tx)
+        .bind(notes)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *conn)
         .await?;
 
     // Reserve quantity in batch
-    sqlx::query("UPDATE batches SET reserved_quantity = reserved_quantity + ? WHERE id = ?")
-        .bind(body.quantity_used)
-        .bind(&body.batch_id)
-        .execute(&mut *tx)
-        .await?;
+    if reserve {
+        sqlx::query("UPDATE batches SET reserved_quantity = reserved_quantity + ? WHERE id = ?")
+            .bind(quantity)
+            .bind(batch_id)
+            .execute(&mut *conn)
+            .await?;
+    }
 
-    tx.commit().await?;
+    Ok(id)
+}
 
-    Ok(HttpResponse::Created().json(ApiResponse::success(serde_json::json!({
-        "id": id,
-        "message": "Reagent added to experiment"
-    }))))
+/// Резерв реагента для эксперимента в транзакции вызывающего (согласованная заявка на расход)
+pub(crate) async fn reserve_experiment_reagent(
+    conn: &mut sqlx::SqliteConnection,
+    experiment_id: &str,
+    batch_id: &str,
+    quantity: f64,
+    notes: Option<&str>,
+) -> ApiResult<String> {
+    let (batch, reserve) = check_reagent_reservation(conn, experiment_id, batch_id, quantity).await?;
+    insert_reagent_reservation(conn, experiment_id, batch_id, &batch, quantity, notes, reserve).await
 }
 
 pub async fn remove_reagent_from_experiment(
@@ -612,16 +1168,19 @@ pub async fn remove_reagent_from_experiment(
         batch_id: String,
         planned_quantity: Option<f64>,
         is_consumed: bool,
+        experiment_status: String,
     }
 
     let link: ReagentLink = sqlx::query_as(
-        "SELECT batch_id, planned_quantity, is_consumed FROM experiment_reagents WHERE id = ? AND experiment_id = ?"
+        "SELECT er.batch_id, er.planned_quantity, er.is_consumed, e.status AS experiment_status \
+         FROM experiment_reagents er JOIN experiments e ON e.id = er.experiment_id \
+         WHERE er.id = ? AND er.experiment_id = ?"
     )
         .bind(&reagent_link_id)
         .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Experiment reagent link"))?;
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Experiment reagent"))?;
 
     if link.is_consumed {
         return Err(ApiError::bad_request("Cannot remove already consumed reagent"));
@@ -635,13 +1194,15 @@ pub async fn remove_reagent_from_experiment(
         .execute(&mut *tx)
         .await?;
 
-    // Unreserve quantity
-    let qty = link.planned_quantity.unwrap_or(0.0);
-    sqlx::query("UPDATE batches SET reserved_quantity = MAX(0, reserved_quantity - ?) WHERE id = ?")
-        .bind(qty)
-        .bind(&link.batch_id)
-        .execute(&mut *tx)
-        .await?;
+    // Unreserve quantity (у черновика резерва нет)
+    if link.experiment_status != "draft" {
+        let qty = link.planned_quantity.unwrap_or(0.0);
+        sqlx::query("UPDATE batches SET reserved_quantity = MAX(0, reserved_quantity - ?) WHERE id = ?")
+            .bind(qty)
+            .bind(&link.batch_id)
+            .execute(&mut *tx)
+            .await?;
+    }
 
     tx.commit().await?;
 
@@ -650,61 +1211,364 @@ pub async fn remove_reagent_from_experiment(
     }))))
 }
 
-// ==================== START/COMPLETE/CANCEL EXPERIMENT ====================
+// ==================== DRAFT PUBLISHING ====================
 
-/// Запустить эксперимент (planned -> in_progress)
-pub async fn start_experiment(
+/// POST /experiments/{id}/publish - черновик становится запланированным. Проверки, отложенные
+/// при создании (учебное расписание, помещение, подпись по технике безопасности), и резерв всех
+/// реагентов черновика выполняются целиком: при любой ошибке черновик не меняется
+pub async fn publish_experiment(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
     user_id: String,
 ) -> ApiResult<HttpResponse> {
     let experiment_id = path.into_inner();
-    let now = Utc::now();
+    let pool = &app_state.db_pool;
 
-    let existing: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    let existing: Option<Experiment> = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Experiment"))?;
-
-    if existing.status != "planned" {
+        .fetch_optional(pool)
+        .await?;
+    let existing = existing.ok_or_else(|| ApiError::not_found("Experiment"))?;
+    if existing.status != "draft" {
         return Err(ApiError::bad_request(&format!(
-            "Cannot start experiment with status '{}'. Only 'planned' experiments can be started.",
+            "Cannot publish experiment with status '{}'. Only drafts can be published.",
             existing.status
         )));
     }
 
-    sqlx::query(r#"
-        UPDATE experiments 
-        SET status = 'in_progress', start_date = COALESCE(start_date, ?), updated_by = ?, updated_at = ?
-        WHERE id = ?
-    "#)
-        .bind(&now)
+    let request = CreateExperimentRequest {
+        title: existing.title.clone(),
+        description: existing.description.clone(),
+        experiment_date: Some(existing.experiment_date),
+        experiment_type: existing.experiment_type.clone(),
+        instructor: existing.instructor.clone(),
+        instructor_user_id: existing.instructor_user_id.clone(),
+        student_group: existing.student_group.clone(),
+        location: existing.location.clone(),
+        room_id: existing.room_id.clone(),
+        protocol: existing.protocol.clone(),
+        start_date: Some(existing.start_date),
+        end_date: existing.end_date,
+        notes: existing.notes.clone(),
+        status: None,
+        team_id: existing.team_id.clone(),
+    };
+    validate_experiment_request(pool, &request, Some(&experiment_id)).await?.ensure_valid()?;
+    ensure_signoff(pool, &experiment_id, app_state.config.safety.signoff_hazard_threshold).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let links: Vec<(String, Option<f64>)> = sqlx::query_as(
+        "SELECT batch_id, planned_quantity FROM experiment_reagents \
+         WHERE experiment_id = ? AND is_consumed = 0 ORDER BY created_at"
+    )
+        .bind(&experiment_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    for (batch_id, planned_quantity) in &links {
+        let qty = planned_quantity.unwrap_or(0.0);
+        // Остаток проверяется с учётом уже зарезервированного этим же черновиком
+        check_reservation_batch(&mut tx, batch_id, Some(qty)).await?;
+        sqlx::query("UPDATE batches SET reserved_quantity = reserved_quantity + ? WHERE id = ?")
+            .bind(qty)
+            .bind(batch_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let result = sqlx::query(
+        "UPDATE experiments SET status = 'planned', updated_by = ?, updated_at = ? WHERE id = ? AND status = 'draft'"
+    )
         .bind(&user_id)
-        .bind(&now)
+        .bind(Utc::now())
         .bind(&experiment_id)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::bad_request("Experiment was published concurrently"));
+    }
 
-    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    tx.commit().await?;
+
+    let published: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(pool)
         .await?;
 
-    info!("User {} started experiment: {}", user_id, experiment_id);
-    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+    info!("User {} published experiment {} ({} reagents reserved)", user_id, experiment_id, links.len());
+    Ok(HttpResponse::Ok().json(ApiResponse::success(published)))
 }
 
+// ==================== CLONE ====================
 
-/// Завершить эксперимент (in_progress -> completed) и израсходовать реагенты
-pub async fn complete_experiment(
+/// Суффикс названия копии
+const CLONE_TITLE_SUFFIX: &str = " (copy)";
+
+/// Строка реагентов источника, не перенесённая в копию
+#[derive(Debug, Serialize)]
+pub struct CloneWarning {
+    pub batch_id: String,
+    pub batch_number: Option<String>,
+    pub reagent_name: Option<String>,
+    pub quantity: f64,
+    pub unit: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CloneExperimentResponse {
+    pub experiment: Experiment,
+    pub reagents_copied: usize,
+    pub warnings: Vec<CloneWarning>,
+}
+
+#[derive(sqlx::FromRow)]
+struct CloneSourceLine {
+    batch_id: String,
+    planned_quantity: Option<f64>,
+    entered_quantity: Option<f64>,
+    entered_unit: Option<String>,
+    notes: Option<String>,
+    batch_number: Option<String>,
+    unit: Option<String>,
+    batch_deleted: Option<bool>,
+    reagent_name: Option<String>,
+}
+
+/// Название копии: источник с суффиксом, не длиннее 255 символов
+fn clone_title(title: &str) -> String {
+    let keep = 255 - CLONE_TITLE_SUFFIX.chars().count();
+    format!("{}{}", title.chars().take(keep).collect::<String>().trim_end(), CLONE_TITLE_SUFFIX)
+}
+
+/// POST /experiments/{id}/clone - черновик с полями и реагентами источника, автор - вызывающий.
+/// Строки реагентов проверяются по текущему остатку (как при резерве); не прошедшие проверку
+/// возвращаются в warnings и не копируются. Резерв, как у любого черновика, делает publish_experiment
+pub async fn clone_experiment(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
-    user_id: String,
+    body: Option<web::Json<CloneExperimentRequest>>,
+    http_request: actix_web::HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    let experiment_id = path.into_inner();
-    let now = Utc::now();
-
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let source_id = path.into_inner();
+    let overrides = body.map(web::Json::into_inner).unwrap_or_default();
+    overrides.validate()?;
+    let pool = &app_state.db_pool;
+
+    let source = fetch_visible_experiment(pool, &source_id, &claims).await?;
+
+    // Новое начало без нового окончания сдвигает окончание на ту же длительность
+    let start_date = overrides.start_date.unwrap_or(source.start_date);
+    let end_date = overrides.end_date
+        .or_else(|| source.end_date.map(|end| end + (start_date - source.start_date)));
+    let room_override = overrides.room_id.as_deref().map(str::trim);
+    let request = CreateExperimentRequest {
+        title: overrides.title.as_deref().map(str::trim).map(str::to_string)
+            .unwrap_or_else(|| clone_title(&source.title)),
+        description: source.description.clone(),
+        experiment_date: Some(if overrides.start_date.is_some() { start_date } else { source.experiment_date }),
+        experiment_type: source.experiment_type.clone(),
+        instructor: source.instructor.clone(),
+        instructor_user_id: source.instructor_user_id.clone(),
+        student_group: source.student_group.clone(),
+        // Старое текстовое место относится к помещению источника
+        location: if room_override.is_some() { None } else { source.location.clone() },
+        room_id: match room_override {
+            Some(room) => Some(room.to_string()).filter(|r| !r.is_empty()),
+            None => source.room_id.clone(),
+        },
+        protocol: source.protocol.clone(),
+        start_date: Some(start_date),
+        end_date,
+        notes: source.notes.clone(),
+        status: Some("draft".to_string()),
+        team_id: source.team_id.clone(),
+    };
+    let mut validation = validate_experiment_request(pool, &request, None).await?;
+    if let Some(room_id) = request.room_id.as_deref() {
+        let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM rooms WHERE id = ?")
+            .bind(room_id)
+            .fetch_optional(pool)
+            .await?;
+        if exists.is_none() {
+            validation.add_error("room_id", "Room not found");
+        }
+    }
+    validation.ensure_valid()?;
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    sqlx::query(r#"
+        INSERT INTO experiments
+        (id, title, description, experiment_date, experiment_type,
+         instructor, instructor_user_id, student_group, location, room_id, protocol, start_date, end_date, notes,
+         status, cloned_from, team_id, created_by, updated_by, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'draft', ?, ?, ?, ?, ?, ?)
+    "#)
+        .bind(&id)
+        .bind(&request.title)
+        .bind(&request.description)
+        .bind(request.experiment_date)
+        .bind(&request.experiment_type)
+        .bind(&request.instructor)
+        .bind(&request.instructor_user_id)
+        .bind(&request.student_group)
+        .bind(&request.location)
+        .bind(&request.room_id)
+        .bind(&request.protocol)
+        .bind(start_date)
+        .bind(end_date)
+        .bind(&request.notes)
+        .bind(&source_id)
+        .bind(&request.team_id)
+        .bind(&claims.sub)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    let lines: Vec<CloneSourceLine> = sqlx::query_as(r#"
+        SELECT er.batch_id, er.planned_quantity, er.entered_quantity, er.entered_unit, er.notes,
+               b.batch_number, b.unit, b.deleted_at IS NOT NULL AS batch_deleted, r.name AS reagent_name
+        FROM experiment_reagents er
+        LEFT JOIN batches b ON b.id = er.batch_id
+        LEFT JOIN reagents r ON r.id = b.reagent_id
+        WHERE er.experiment_id = ?
+        ORDER BY er.created_at
+    "#)
+        .bind(&source_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    let mut warnings = Vec::new();
+    let mut copied = 0;
+    for line in lines {
+        let quantity = line.planned_quantity.unwrap_or(0.0);
+        let warning = |reason: String| CloneWarning {
+            batch_id: line.batch_id.clone(),
+            batch_number: line.batch_number.clone(),
+            reagent_name: line.reagent_name.clone(),
+            quantity,
+            unit: line.unit.clone(),
+            reason,
+        };
+        if line.batch_deleted != Some(false) {
+            warnings.push(warning("Batch no longer exists".to_string()));
+            continue;
+        }
+        let batch = match check_reservation_batch(&mut tx, &line.batch_id, Some(quantity)).await {
+            Ok(batch) => batch,
+            Err(ApiError::BadRequest(reason) | ApiError::NotFound(reason) | ApiError::ValidationError(reason))
+            | Err(ApiError::Conflict { message: reason, .. }) => {
+                warnings.push(warning(reason));
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let link_id = insert_reagent_reservation(
+            &mut tx, &id, &line.batch_id, &batch, quantity, line.notes.as_deref(), false,
+        ).await?;
+        if line.entered_unit.is_some() {
+            sqlx::query("UPDATE experiment_reagents SET entered_quantity = ?, entered_unit = ? WHERE id = ?")
+                .bind(line.entered_quantity)
+                .bind(&line.entered_unit)
+                .bind(&link_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        copied += 1;
+    }
+    tx.commit().await?;
+
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&id)
+        .fetch_one(pool)
+        .await?;
+    crate::audit::audit(
+        pool, &claims.sub, "clone", "experiment", &id,
+        &format!(
+            "Cloned experiment \"{}\" from {} ({} reagents copied, {} skipped)",
+            experiment.title, source_id, copied, warnings.len()
+        ),
+        &http_request,
+    ).await;
+    info!("User {} cloned experiment {} as {}", claims.sub, source_id, id);
+
+    let message = if warnings.is_empty() {
+        "Experiment cloned as draft".to_string()
+    } else {
+        format!("Experiment cloned as draft; {} reagent line(s) could not be copied", warnings.len())
+    };
+    Ok(HttpResponse::Created().json(ApiResponse::success_with_message(
+        CloneExperimentResponse { experiment, reagents_copied: copied, warnings },
+        message,
+    )))
+}
+
+// ==================== START/COMPLETE/CANCEL EXPERIMENT ====================
+
+/// Запустить эксперимент (planned -> in_progress)
+pub async fn start_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let now = Utc::now();
+
+    let existing: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| ApiError::not_found("Experiment"))?;
+
+    if existing.status != "planned" {
+        return Err(ApiError::bad_request(&format!(
+            "Cannot start experiment with status '{}'. Only 'planned' experiments can be started.",
+            existing.status
+        )));
+    }
+    ensure_signoff(&app_state.db_pool, &experiment_id, app_state.config.safety.signoff_hazard_threshold).await?;
+    ensure_dependencies_completed(&app_state.db_pool, &experiment_id).await?;
+
+    sqlx::query(r#"
+        UPDATE experiments 
+        SET status = 'in_progress', start_date = COALESCE(start_date, ?), updated_by = ?, updated_at = ?
+        WHERE id = ?
+    "#)
+        .bind(&now)
+        .bind(&user_id)
+        .bind(&now)
+        .bind(&experiment_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    info!("User {} started experiment: {}", user_id, experiment_id);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
+}
+
+
+/// Завершить эксперимент (in_progress -> completed) и израсходовать реагенты
+pub async fn complete_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: Option<web::Json<CompleteExperimentRequest>>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let body = body.map(|b| b.into_inner()).unwrap_or_default();
+    body.validate()?;
+    let experiment_id = path.into_inner();
+    let now = Utc::now();
+
     let existing: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&experiment_id)
         .fetch_one(&app_state.db_pool)
@@ -730,7 +1594,17 @@ pub async fn complete_experiment(
         .fetch_all(&mut *tx)
         .await?;
 
+    if let Some(unknown) = body.consumption_notes.keys()
+        .find(|id| !reagents.iter().any(|r| &r.id == *id && !r.is_consumed))
+    {
+        return Err(ApiError::bad_request(&format!(
+            "Consumption note refers to '{}', which is not a pending reagent line of this experiment",
+            unknown
+        )));
+    }
+
     let mut consumed_count = 0;
+    let mut consumed_batches = Vec::new();
 
     // Проверяем статус is_consumed на стороне Rust (самый надежный способ)
     for reagent in reagents {
@@ -738,6 +1612,7 @@ pub async fn complete_experiment(
             let qty = reagent.planned_quantity.unwrap_or(0.0);
             
             if qty > 0.0 {
+                consumed_batches.push((reagent.batch_id.clone(), qty));
                 // Списываем количество из батча
                 sqlx::query(r#"
                     UPDATE batches 
@@ -752,12 +1627,9 @@ pub async fn complete_experiment(
                     .await?;
             }
 
-            // Помечаем как consumed
-            sqlx::query("UPDATE experiment_reagents SET is_consumed = 1 WHERE id = ?")
-                .bind(&reagent.id)
-                .execute(&mut *tx)
-                .await?;
-                
+            let note = body.consumption_notes.get(&reagent.id).map(String::as_str);
+            mark_reagent_consumed(&mut tx, &reagent.id, Some(&user_id), note, now).await?;
+
             consumed_count += 1;
         }
     }
@@ -765,17 +1637,35 @@ pub async fn complete_experiment(
     // Обновляем статус эксперимента
     sqlx::query(r#"
         UPDATE experiments 
-        SET status = 'completed', end_date = COALESCE(end_date, ?), updated_by = ?, updated_at = ?
+        SET status = 'completed', end_date = COALESCE(end_date, ?), outcome = ?, updated_by = ?, updated_at = ?
         WHERE id = ?
     "#)
         .bind(&now)
+        .bind(&body.outcome)
         .bind(&user_id)
         .bind(&now)
         .bind(&experiment_id)
         .execute(&mut *tx)
         .await?;
 
+    outbox::enqueue(
+        &mut *tx,
+        &BusinessEvent::ExperimentCompleted {
+            experiment_id: experiment_id.clone(),
+            reagents_consumed: consumed_count,
+            automatic: false,
+        },
+        Some(&user_id),
+    ).await?;
     tx.commit().await?;
+    for (batch_id, quantity) in &consumed_batches {
+        crate::stock_anomalies::track(&app_state, crate::stock_anomalies::StockOp {
+            operation: crate::stock_anomalies::StockOperation::ExperimentConsumption,
+            batch_id,
+            user_id: Some(&user_id),
+            quantity: *quantity,
+        }).await;
+    }
 
     let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
         .bind(&experiment_id)
@@ -846,105 +1736,920 @@ pub async fn cancel_experiment(
         }
     }
 
-    // Обновляем статус эксперимента
-    sqlx::query(r#"
-        UPDATE experiments 
-        SET status = 'cancelled', updated_by = ?, updated_at = ?
-        WHERE id = ?
+    // Обновляем статус эксперимента
+    sqlx::query(r#"
+        UPDATE experiments 
+        SET status = 'cancelled', updated_by = ?, updated_at = ?
+        WHERE id = ?
+    "#)
+        .bind(&user_id)
+        .bind(&now)
+        .bind(&experiment_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    info!("User {} cancelled experiment: {} (returned {} reagents)", 
+          user_id, experiment_id, returned_count);
+    
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "experiment": updated,
+        "reagents_returned": returned_count
+    }))))
+}
+
+
+/// Помечает строку реагента списанной и фиксирует реквизиты партии (номер, поставщик, срок
+/// годности) на момент списания; user_id отсутствует при автоматическом завершении
+async fn mark_reagent_consumed(
+    conn: &mut sqlx::SqliteConnection,
+    reagent_link_id: &str,
+    user_id: Option<&str>,
+    note: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let note = note.map(str::trim).filter(|n| !n.is_empty());
+    sqlx::query(r#"
+        UPDATE experiment_reagents
+        SET is_consumed = 1, consumed_at = ?, consumed_by = ?, consumption_notes = ?,
+            lot_batch_number = (SELECT batch_number FROM batches WHERE id = experiment_reagents.batch_id),
+            lot_supplier = (SELECT supplier FROM batches WHERE id = experiment_reagents.batch_id),
+            lot_expiry_date = (SELECT expiry_date FROM batches WHERE id = experiment_reagents.batch_id)
+        WHERE id = ?
+    "#)
+        .bind(now)
+        .bind(user_id)
+        .bind(note)
+        .bind(reagent_link_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Списания эксперимента с реквизитами партии на момент списания (деталь и экспорт)
+pub(crate) async fn fetch_consumptions(
+    pool: &sqlx::SqlitePool,
+    experiment_id: Option<&str>,
+) -> Result<Vec<ExperimentConsumption>, sqlx::Error> {
+    sqlx::query_as(r#"
+        SELECT er.id, er.experiment_id, er.batch_id, r.name AS reagent_name,
+               er.planned_quantity AS quantity, er.unit,
+               er.consumed_at, er.consumed_by, er.consumption_notes,
+               er.lot_batch_number, er.lot_supplier, er.lot_expiry_date
+        FROM experiment_reagents er
+        JOIN reagents r ON r.id = er.reagent_id
+        WHERE er.is_consumed = 1 AND (?1 IS NULL OR er.experiment_id = ?1)
+        ORDER BY er.experiment_id, er.consumed_at, er.created_at
+    "#)
+        .bind(experiment_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Израсходовать конкретный реагент эксперимента
+pub async fn consume_experiment_reagent(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: Option<web::Json<ConsumeExperimentReagentRequest>>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let body = body.map(|b| b.into_inner()).unwrap_or_default();
+    body.validate()?;
+    let (experiment_id, reagent_link_id) = path.into_inner();
+
+    let reagent: ExperimentReagent = sqlx::query_as(r#"
+        SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
+        FROM experiment_reagents 
+        WHERE id = ? AND experiment_id = ?
+    "#)
+        .bind(&reagent_link_id)
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Experiment reagent"))?;
+
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    if experiment.status != "in_progress" {
+        return Err(ApiError::bad_request(
+            "Can only consume reagents from 'in_progress' experiments"
+        ));
+    }
+
+    if reagent.is_consumed {
+        return Err(ApiError::bad_request("Reagent is already consumed"));
+    }
+
+    let qty = reagent.planned_quantity.unwrap_or(0.0);
+    
+    if qty <= 0.0 {
+        return Err(ApiError::bad_request("Reagent has no quantity to consume"));
+    }
+    
+    let mut tx = app_state.db_pool.begin().await?;
+
+    // Списываем из батча
+    sqlx::query(r#"
+        UPDATE batches 
+        SET quantity = MAX(0, quantity - ?),
+            reserved_quantity = MAX(0, reserved_quantity - ?)
+        WHERE id = ?
+    "#)
+        .bind(qty)
+        .bind(qty)
+        .bind(&reagent.batch_id)
+        .execute(&mut *tx)
+        .await?;
+
+    mark_reagent_consumed(&mut tx, &reagent_link_id, Some(&user_id), body.notes.as_deref(), Utc::now()).await?;
+
+    tx.commit().await?;
+    crate::stock_anomalies::track(&app_state, crate::stock_anomalies::StockOp {
+        operation: crate::stock_anomalies::StockOperation::ExperimentConsumption,
+        batch_id: &reagent.batch_id,
+        user_id: Some(&user_id),
+        quantity: qty,
+    }).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "message": "Reagent consumed successfully",
+        "reagent_id": reagent_link_id,
+        "quantity_consumed": qty
+    }))))
+}
+
+// ==================== PARTICIPANTS ====================
+
+const PARTICIPANT_SELECT: &str = r#"
+    SELECT p.id, p.experiment_id, p.user_id, p.name, p.email, p.role,
+           p.signed_in_at, p.created_by, p.created_at, u.username
+    FROM experiment_participants p
+    LEFT JOIN users u ON p.user_id = u.id
+"#;
+
+pub(crate) async fn fetch_participants(
+    pool: &sqlx::SqlitePool,
+    experiment_id: &str,
+) -> Result<Vec<ExperimentParticipant>, sqlx::Error> {
+    let sql = format!(
+        "{} WHERE p.experiment_id = ? ORDER BY p.role, COALESCE(u.username, p.name) LIMIT ?",
+        PARTICIPANT_SELECT
+    );
+    sqlx::query_as(&sql)
+        .bind(experiment_id)
+        .bind(crate::handlers::MAX_NESTED_LIST_ROWS)
+        .fetch_all(pool)
+        .await
+}
+
+async fn fetch_participant(
+    pool: &sqlx::SqlitePool,
+    participant_id: &str,
+) -> Result<ExperimentParticipant, sqlx::Error> {
+    let sql = format!("{} WHERE p.id = ?", PARTICIPANT_SELECT);
+    sqlx::query_as(&sql).bind(participant_id).fetch_one(pool).await
+}
+
+pub async fn get_experiment_participants(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+
+    let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?;
+    if exists.is_none() {
+        return Err(ApiError::not_found("Experiment"));
+    }
+
+    let participants = fetch_participants(&app_state.db_pool, &experiment_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(participants)))
+}
+
+/// Добавить участника (пользователь системы или внешний по имени/email)
+pub async fn add_experiment_participant(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<AddParticipantRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let request = body.into_inner();
+    request.validate()?;
+    request.validate_identity()?;
+
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| ApiError::not_found("Experiment"))?;
+
+    if experiment.status == "completed" || experiment.status == "cancelled" {
+        return Err(ApiError::bad_request(&format!(
+            "Cannot add participants to experiment with status '{}'",
+            experiment.status
+        )));
+    }
+
+    let participant_user = request.user_id.as_deref().map(str::trim).filter(|u| !u.is_empty());
+    if let Some(uid) = participant_user {
+        let user: Option<(String,)> = sqlx::query_as("SELECT id FROM users WHERE id = ?")
+            .bind(uid)
+            .fetch_optional(&app_state.db_pool)
+            .await?;
+        if user.is_none() {
+            return Err(ApiError::not_found("User"));
+        }
+
+        let duplicate: Option<(String,)> = sqlx::query_as(
+            "SELECT id FROM experiment_participants WHERE experiment_id = ? AND user_id = ?"
+        )
+            .bind(&experiment_id)
+            .bind(uid)
+            .fetch_optional(&app_state.db_pool)
+            .await?;
+        if duplicate.is_some() {
+            return Err(ApiError::bad_request("User is already a participant of this experiment"));
+        }
+    }
+
+    let role = request.role.as_deref()
+        .and_then(ParticipantRole::from_str)
+        .unwrap_or(ParticipantRole::Student);
+    let name = request.name.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    let email = request.email.as_deref().map(str::trim).filter(|e| !e.is_empty());
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    sqlx::query(r#"
+        INSERT INTO experiment_participants
+            (id, experiment_id, user_id, name, email, role, signed_in_at, created_by, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, NULL, ?, ?)
+    "#)
+        .bind(&id)
+        .bind(&experiment_id)
+        .bind(participant_user)
+        .bind(name)
+        .bind(email)
+        .bind(role.as_str())
+        .bind(&user_id)
+        .bind(now)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    let participant = fetch_participant(&app_state.db_pool, &id).await?;
+
+    info!("User {} added participant {} to experiment {}", user_id, id, experiment_id);
+    Ok(HttpResponse::Created().json(ApiResponse::success(participant)))
+}
+
+pub async fn remove_experiment_participant(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    _user_id: String,
+) -> ApiResult<HttpResponse> {
+    let (experiment_id, participant_id) = path.into_inner();
+
+    let result = sqlx::query("DELETE FROM experiment_participants WHERE id = ? AND experiment_id = ?")
+        .bind(&participant_id)
+        .bind(&experiment_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Experiment participant"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "message": "Participant removed from experiment"
+    }))))
+}
+
+/// Отметить присутствие (только для 'in_progress').
+/// Без participant_id отмечается сам пользователь; отметка других — только с правом Edit.
+pub async fn sign_in_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: Option<web::Json<SignInRequest>>,
+    user_id: String,
+    can_manage: bool,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let request = body.map(|b| b.into_inner()).unwrap_or_default();
+
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_one(&app_state.db_pool)
+        .await
+        .map_err(|_| ApiError::not_found("Experiment"))?;
+
+    if experiment.status != "in_progress" {
+        return Err(ApiError::bad_request(
+            "Sign-in is only available while the experiment is 'in_progress'"
+        ));
+    }
+
+    let participant_id: String = match request.participant_id {
+        Some(pid) => {
+            let participant = fetch_participant(&app_state.db_pool, &pid)
+                .await
+                .map_err(|_| ApiError::not_found("Experiment participant"))?;
+            if participant.experiment_id != experiment_id {
+                return Err(ApiError::not_found("Experiment participant"));
+            }
+            if participant.user_id.as_deref() != Some(user_id.as_str()) && !can_manage {
+                return Err(ApiError::Forbidden(
+                    "Only experiment managers can sign in other participants".to_string()
+                ));
+            }
+            pid
+        }
+        None => {
+            let own: Option<(String,)> = sqlx::query_as(
+                "SELECT id FROM experiment_participants WHERE experiment_id = ? AND user_id = ?"
+            )
+                .bind(&experiment_id)
+                .bind(&user_id)
+                .fetch_optional(&app_state.db_pool)
+                .await?;
+            own.map(|(id,)| id)
+                .ok_or_else(|| ApiError::Forbidden("You are not a participant of this experiment".to_string()))?
+        }
+    };
+
+    // Повторная отметка не перезаписывает первое время прихода
+    sqlx::query(
+        "UPDATE experiment_participants SET signed_in_at = COALESCE(signed_in_at, ?) WHERE id = ?"
+    )
+        .bind(Utc::now())
+        .bind(&participant_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    let participant = fetch_participant(&app_state.db_pool, &participant_id).await?;
+
+    info!("User {} signed in participant {} for experiment {}", user_id, participant_id, experiment_id);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(participant)))
+}
+
+// ==================== COMMENTS ====================
+
+pub async fn get_experiment_comments(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+
+    let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?;
+    if exists.is_none() {
+        return Err(ApiError::not_found("Experiment"));
+    }
+
+    let comments: Vec<ExperimentComment> = sqlx::query_as(r#"
+        SELECT c.id, c.experiment_id, c.user_id, c.body, c.created_at, u.username
+        FROM experiment_comments c
+        LEFT JOIN users u ON c.user_id = u.id
+        WHERE c.experiment_id = ?
+        ORDER BY c.created_at
+        LIMIT ?
+    "#)
+        .bind(&experiment_id)
+        .bind(crate::handlers::MAX_NESTED_LIST_ROWS)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(comments)))
+}
+
+/// Комментировать может любой с правом Edit, не только автор и инструктор
+pub async fn add_experiment_comment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<AddExperimentCommentRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let experiment_id = path.into_inner();
+    let text = body.body.trim();
+    if text.is_empty() {
+        return Err(ApiError::bad_request("Comment cannot be empty"));
+    }
+
+    let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?;
+    if exists.is_none() {
+        return Err(ApiError::not_found("Experiment"));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO experiment_comments (id, experiment_id, user_id, body, created_at) VALUES (?, ?, ?, ?, ?)"
+    )
+        .bind(&id)
+        .bind(&experiment_id)
+        .bind(&user_id)
+        .bind(text)
+        .bind(Utc::now())
+        .execute(&app_state.db_pool)
+        .await?;
+
+    let comment: ExperimentComment = sqlx::query_as(r#"
+        SELECT c.id, c.experiment_id, c.user_id, c.body, c.created_at, u.username
+        FROM experiment_comments c
+        LEFT JOIN users u ON c.user_id = u.id
+        WHERE c.id = ?
+    "#)
+        .bind(&id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    info!("User {} commented on experiment {}", user_id, experiment_id);
+    Ok(HttpResponse::Created().json(ApiResponse::success(comment)))
+}
+
+// ==================== SAFETY SIGN-OFF ====================
+
+/// Реагент эксперимента с тяжестью опасности выше порога допуска
+#[derive(Debug, Serialize)]
+pub struct HazardousReagent {
+    pub reagent_id: String,
+    pub name: String,
+    pub hazard_pictograms: String,
+    pub severity: u8,
+}
+
+/// Нужен ли допуск к эксперименту и выдан ли он
+#[derive(Debug, Serialize)]
+pub struct SignoffStatus {
+    pub requires_signoff: bool,
+    pub hazardous_reagents: Vec<HazardousReagent>,
+    pub signoff: Option<ExperimentSignoff>,
+}
+
+/// Статусы, в которых эксперимент ещё не начат и допуск можно выдать
+const SIGNOFF_STATUSES: &[&str] = &["draft", "planned", "on_hold"];
+
+/// SQL-условие по hazard_pictograms (алиас reagents - `r`) для кодов GHS выше порога;
+/// коды - константы из GHS_HAZARD_SEVERITY, подстановка безопасна
+pub(crate) fn hazard_match_condition(signoff_threshold: u8) -> String {
+    let codes = hazard_codes_above(signoff_threshold);
+    if codes.is_empty() {
+        return "0".to_string();
+    }
+    let matches: Vec<String> = codes.iter()
+        .map(|code| format!("UPPER(r.hazard_pictograms) LIKE '%{}%'", code))
+        .collect();
+    format!("({})", matches.join(" OR "))
+}
+
+/// SQL-условие "эксперимент ждёт допуска": есть опасный реагент и нет записи о допуске.
+/// `experiment_id_column` - всегда литерал из кода (например, "e.id")
+pub(crate) fn awaiting_signoff_condition(experiment_id_column: &str, signoff_threshold: u8) -> String {
+    format!(
+        "(EXISTS (SELECT 1 FROM experiment_reagents er JOIN reagents r ON r.id = er.reagent_id \
+         WHERE er.experiment_id = {col} AND {hazard}) \
+         AND NOT EXISTS (SELECT 1 FROM experiment_signoffs s WHERE s.experiment_id = {col}))",
+        col = experiment_id_column,
+        hazard = hazard_match_condition(signoff_threshold),
+    )
+}
+
+async fn fetch_signoff(pool: &sqlx::SqlitePool, experiment_id: &str) -> Result<Option<ExperimentSignoff>, sqlx::Error> {
+    sqlx::query_as(r#"
+        SELECT s.*, u.username AS signed_by_username
+        FROM experiment_signoffs s
+        LEFT JOIN users u ON u.id = s.signed_by
+        WHERE s.experiment_id = ?
+    "#)
+        .bind(experiment_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn fetch_signoff_status(
+    pool: &sqlx::SqlitePool,
+    experiment_id: &str,
+    signoff_threshold: u8,
+) -> ApiResult<SignoffStatus> {
+    let reagents: Vec<(String, String, String)> = sqlx::query_as(r#"
+        SELECT DISTINCT r.id, r.name, r.hazard_pictograms
+        FROM experiment_reagents er
+        JOIN reagents r ON r.id = er.reagent_id
+        WHERE er.experiment_id = ? AND r.hazard_pictograms IS NOT NULL
+        ORDER BY r.name
+    "#)
+        .bind(experiment_id)
+        .fetch_all(pool)
+        .await?;
+
+    let hazardous_reagents: Vec<HazardousReagent> = reagents.into_iter()
+        .map(|(reagent_id, name, hazard_pictograms)| HazardousReagent {
+            severity: hazard_severity(&hazard_pictograms),
+            reagent_id,
+            name,
+            hazard_pictograms,
+        })
+        .filter(|r| r.severity > signoff_threshold)
+        .collect();
+
+    Ok(SignoffStatus {
+        requires_signoff: !hazardous_reagents.is_empty(),
+        hazardous_reagents,
+        signoff: fetch_signoff(pool, experiment_id).await?,
+    })
+}
+
+/// Отказ в переходе в in_progress, пока нет обязательного допуска
+async fn ensure_signoff(pool: &sqlx::SqlitePool, experiment_id: &str, signoff_threshold: u8) -> ApiResult<()> {
+    let status = fetch_signoff_status(pool, experiment_id, signoff_threshold).await?;
+    if status.requires_signoff && status.signoff.is_none() {
+        let names: Vec<&str> = status.hazardous_reagents.iter().map(|r| r.name.as_str()).collect();
+        return Err(ApiError::BadRequest(format!(
+            "Experiment uses hazardous reagents ({}) and requires an instructor safety sign-off before it can start",
+            names.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Статус допуска эксперимента
+pub async fn get_experiment_signoff(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let exists: Option<String> = sqlx::query_scalar("SELECT id FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?;
+    if exists.is_none() {
+        return Err(ApiError::not_found("Experiment"));
+    }
+
+    let status = fetch_signoff_status(
+        &app_state.db_pool, &experiment_id, app_state.config.safety.signoff_hazard_threshold,
+    ).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(status)))
+}
+
+/// Выдать допуск (POST /experiments/{id}/signoff).
+/// Подписывать могут администраторы и инструкторы этого эксперимента (participants.role = 'instructor').
+pub async fn sign_off_experiment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<SignoffRequest>,
+    user_id: String,
+    is_admin: bool,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let experiment_id = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Experiment"))?;
+
+    if !is_admin {
+        let is_instructor: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM experiment_participants WHERE experiment_id = ? AND user_id = ? AND role = 'instructor'"
+        )
+            .bind(&experiment_id)
+            .bind(&user_id)
+            .fetch_one(pool)
+            .await?;
+        if is_instructor == 0 {
+            return Err(ApiError::Forbidden(
+                "Only administrators or instructors of this experiment can sign it off".to_string()
+            ));
+        }
+    }
+
+    if !SIGNOFF_STATUSES.contains(&experiment.status.as_str()) {
+        return Err(ApiError::BadRequest(format!(
+            "Cannot sign off experiment with status '{}'. Sign-off is recorded before the experiment starts.",
+            experiment.status
+        )));
+    }
+    if fetch_signoff(pool, &experiment_id).await?.is_some() {
+        return Err(ApiError::bad_request("Experiment has already been signed off"));
+    }
+
+    let notes = body.notes.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    sqlx::query(r#"
+        INSERT INTO experiment_signoffs (id, experiment_id, signed_by, signed_at, checklist, notes)
+        VALUES (?, ?, ?, ?, ?, ?)
+    "#)
+        .bind(Uuid::new_v4().to_string())
+        .bind(&experiment_id)
+        .bind(&user_id)
+        .bind(Utc::now())
+        .bind(body.checklist.to_string())
+        .bind(notes)
+        .execute(pool)
+        .await?;
+
+    let status = fetch_signoff_status(pool, &experiment_id, app_state.config.safety.signoff_hazard_threshold).await?;
+    info!("User {} signed off experiment {}", user_id, experiment_id);
+    Ok(HttpResponse::Created().json(ApiResponse::success(status)))
+}
+
+// ==================== EXPERIMENT DEPENDENCIES ====================
+
+/// Зависимости эксперимента (цепочки этапов исследования)
+#[derive(Debug, Serialize)]
+pub struct DependencyStatus {
+    /// Предшественники: должны быть завершены до старта
+    pub dependencies: Vec<ExperimentDependency>,
+    /// Эксперименты, которые ждут завершения этого
+    pub dependents: Vec<ExperimentDependency>,
+    /// Незавершённые предшественники; пока список не пуст, эксперимент не запускается
+    pub blocked_by: Vec<ExperimentDependency>,
+}
+
+/// SQL-условие "есть незавершённый предшественник".
+/// `experiment_id_column` - всегда литерал из кода (например, "e.id")
+pub(crate) fn blocked_by_dependencies_condition(experiment_id_column: &str) -> String {
+    format!(
+        "EXISTS (SELECT 1 FROM experiment_dependencies d JOIN experiments p ON p.id = d.depends_on_id \
+         WHERE d.experiment_id = {} AND p.status != 'completed')",
+        experiment_id_column
+    )
+}
+
+pub async fn fetch_dependency_status(pool: &sqlx::SqlitePool, experiment_id: &str) -> Result<DependencyStatus, sqlx::Error> {
+    let dependencies: Vec<ExperimentDependency> = sqlx::query_as(r#"
+        SELECT e.id, e.title, e.status, e.start_date
+        FROM experiment_dependencies d
+        JOIN experiments e ON e.id = d.depends_on_id
+        WHERE d.experiment_id = ?
+        ORDER BY datetime(COALESCE(e.start_date, e.experiment_date)), e.title
+    "#)
+        .bind(experiment_id)
+        .fetch_all(pool)
+        .await?;
+    let dependents: Vec<ExperimentDependency> = sqlx::query_as(r#"
+        SELECT e.id, e.title, e.status, e.start_date
+        FROM experiment_dependencies d
+        JOIN experiments e ON e.id = d.experiment_id
+        WHERE d.depends_on_id = ?
+        ORDER BY datetime(COALESCE(e.start_date, e.experiment_date)), e.title
+    "#)
+        .bind(experiment_id)
+        .fetch_all(pool)
+        .await?;
+    let blocked_by = dependencies.iter().filter(|d| d.status != "completed").cloned().collect();
+
+    Ok(DependencyStatus { dependencies, dependents, blocked_by })
+}
+
+/// Отказ в переходе в in_progress, пока не завершены все предшественники
+async fn ensure_dependencies_completed(pool: &sqlx::SqlitePool, experiment_id: &str) -> ApiResult<()> {
+    let status = fetch_dependency_status(pool, experiment_id).await?;
+    if !status.blocked_by.is_empty() {
+        let blockers: Vec<String> = status.blocked_by.iter()
+            .map(|d| format!("{} ({})", d.title, d.status))
+            .collect();
+        return Err(ApiError::dependencies_incomplete(&blockers));
+    }
+    Ok(())
+}
+
+/// Зависимости эксперимента (GET /experiments/{id}/dependencies)
+pub async fn get_experiment_dependencies(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: actix_web::HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    fetch_visible_experiment(&app_state.db_pool, &experiment_id, &claims).await?;
+
+    let status = fetch_dependency_status(&app_state.db_pool, &experiment_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(status)))
+}
+
+/// Добавить предшественника (POST /experiments/{id}/dependencies).
+/// Зависимость, замыкающая цепочку в цикл, отклоняется (409 DEPENDENCY_CYCLE).
+/// Право управления экспериментом проверяется в обёртке.
+pub async fn add_experiment_dependency(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<AddDependencyRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let request = body.into_inner();
+    request.validate()?;
+    let depends_on_id = request.depends_on_id.trim();
+    if depends_on_id == experiment_id {
+        return Err(ApiError::bad_request("An experiment cannot depend on itself"));
+    }
+
+    let pool = &app_state.db_pool;
+    let mut tx = pool.begin().await?;
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let status = status.ok_or_else(|| ApiError::not_found("Experiment"))?;
+    if status == "completed" || status == "cancelled" {
+        return Err(ApiError::bad_request(&format!(
+            "Cannot add dependencies to experiment with status '{}'", status
+        )));
+    }
+    let predecessor: Option<String> = sqlx::query_scalar("SELECT id FROM experiments WHERE id = ?")
+        .bind(depends_on_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if predecessor.is_none() {
+        return Err(ApiError::not_found("Prerequisite experiment"));
+    }
+
+    let duplicate: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM experiment_dependencies WHERE experiment_id = ? AND depends_on_id = ?"
+    )
+        .bind(&experiment_id)
+        .bind(depends_on_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if duplicate.is_some() {
+        return Err(ApiError::bad_request("Experiment already depends on this experiment"));
+    }
+
+    // Цикл появится, если текущий эксперимент уже достижим из предшественника по его зависимостям.
+    // UNION (не UNION ALL) останавливает обход на уже посещённых узлах
+    let creates_cycle: Option<i64> = sqlx::query_scalar(r#"
+        WITH RECURSIVE chain(id) AS (
+            SELECT ?
+            UNION
+            SELECT d.depends_on_id FROM experiment_dependencies d JOIN chain c ON d.experiment_id = c.id
+        )
+        SELECT 1 FROM chain WHERE id = ? LIMIT 1
     "#)
-        .bind(&user_id)
-        .bind(&now)
+        .bind(depends_on_id)
         .bind(&experiment_id)
-        .execute(&mut *tx)
+        .fetch_optional(&mut *tx)
         .await?;
+    if creates_cycle.is_some() {
+        return Err(ApiError::dependency_cycle(&experiment_id, depends_on_id));
+    }
 
-    tx.commit().await?;
-
-    let updated: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    sqlx::query(
+        "INSERT INTO experiment_dependencies (experiment_id, depends_on_id, created_by, created_at) VALUES (?, ?, ?, ?)"
+    )
         .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
+        .bind(depends_on_id)
+        .bind(&user_id)
+        .bind(Utc::now())
+        .execute(&mut *tx)
         .await?;
+    tx.commit().await?;
 
-    info!("User {} cancelled experiment: {} (returned {} reagents)", 
-          user_id, experiment_id, returned_count);
-    
-    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
-        "experiment": updated,
-        "reagents_returned": returned_count
-    }))))
+    let status = fetch_dependency_status(pool, &experiment_id).await?;
+    info!("User {} made experiment {} depend on {}", user_id, experiment_id, depends_on_id);
+    Ok(HttpResponse::Created().json(ApiResponse::success(status)))
 }
 
-
-/// Израсходовать конкретный реагент эксперимента
-pub async fn consume_experiment_reagent(
+/// Удалить предшественника (DELETE /experiments/{id}/dependencies/{depends_on_id})
+pub async fn remove_experiment_dependency(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
-    _user_id: String,
+    user_id: String,
 ) -> ApiResult<HttpResponse> {
-    let (experiment_id, reagent_link_id) = path.into_inner();
-
-    let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+    let (experiment_id, depends_on_id) = path.into_inner();
+    let result = sqlx::query("DELETE FROM experiment_dependencies WHERE experiment_id = ? AND depends_on_id = ?")
         .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Experiment"))?;
-
-    if experiment.status != "in_progress" {
-        return Err(ApiError::bad_request(
-            "Can only consume reagents from 'in_progress' experiments"
-        ));
+        .bind(&depends_on_id)
+        .execute(&app_state.db_pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Experiment dependency"));
     }
 
-    let reagent: ExperimentReagent = sqlx::query_as(r#"
-        SELECT id, experiment_id, batch_id, planned_quantity, is_consumed, notes, created_at
-        FROM experiment_reagents 
-        WHERE id = ? AND experiment_id = ?
-    "#)
-        .bind(&reagent_link_id)
-        .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Experiment reagent"))?;
+    info!("User {} removed dependency of experiment {} on {}", user_id, experiment_id, depends_on_id);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+        "message": "Dependency removed from experiment"
+    }))))
+}
 
-    if reagent.is_consumed {
-        return Err(ApiError::bad_request("Reagent is already consumed"));
-    }
+#[derive(Debug, Serialize)]
+pub struct DependencyGraphNode {
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub start: Option<DateTime<Utc>>,
+    /// false - эксперимент вне периода, попал в граф как соседний по зависимости
+    pub in_range: bool,
+    /// Есть незавершённый предшественник
+    pub blocked: bool,
+}
 
-    let qty = reagent.planned_quantity.unwrap_or(0.0);
-    
-    if qty <= 0.0 {
-        return Err(ApiError::bad_request("Reagent has no quantity to consume"));
-    }
-    
-    let mut tx = app_state.db_pool.begin().await?;
+/// Ребро от предшественника (`from`) к зависящему эксперименту (`to`)
+#[derive(Debug, Serialize)]
+pub struct DependencyGraphEdge {
+    pub from: String,
+    pub to: String,
+    /// Предшественник завершён
+    pub satisfied: bool,
+}
 
-    // Списываем из батча
-    sqlx::query(r#"
-        UPDATE batches 
-        SET quantity = MAX(0, quantity - ?),
-            reserved_quantity = MAX(0, reserved_quantity - ?)
-        WHERE id = ?
-    "#)
-        .bind(qty)
-        .bind(qty)
-        .bind(&reagent.batch_id)
-        .execute(&mut *tx)
-        .await?;
+#[derive(Debug, Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<DependencyGraphNode>,
+    pub edges: Vec<DependencyGraphEdge>,
+}
 
-    // Помечаем как consumed
-    sqlx::query("UPDATE experiment_reagents SET is_consumed = 1 WHERE id = ?")
-        .bind(&reagent_link_id)
-        .execute(&mut *tx)
+/// GET /experiments/dependency-graph - граф зависимостей для визуализации, фильтры как у календаря.
+/// Узлы - эксперименты периода и их непосредственные соседи по зависимостям; черновики не показываются
+pub async fn get_experiment_dependency_graph(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<CalendarQuery>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let rows = fetch_calendar_experiments(pool, &query, false, CALENDAR_KIND_LIMIT).await?;
+    let in_range: std::collections::HashSet<&str> = rows.iter().map(|r| r.id.as_str()).collect();
+
+    // (предшественник, зависящий, статус предшественника)
+    let all_edges: Vec<(String, String, String)> = sqlx::query_as(r#"
+        SELECT d.depends_on_id, d.experiment_id, p.status
+        FROM experiment_dependencies d
+        JOIN experiments p ON p.id = d.depends_on_id
+        JOIN experiments e ON e.id = d.experiment_id
+        WHERE p.status != 'draft' AND e.status != 'draft'
+        ORDER BY d.created_at
+    "#)
+        .fetch_all(pool)
         .await?;
+    let blocked: std::collections::HashSet<&str> = all_edges.iter()
+        .filter(|(_, _, status)| status != "completed")
+        .map(|(_, to, _)| to.as_str())
+        .collect();
+    let edges: Vec<&(String, String, String)> = all_edges.iter()
+        .filter(|(from, to, _)| in_range.contains(from.as_str()) || in_range.contains(to.as_str()))
+        .collect();
+    let neighbours: std::collections::HashSet<&str> = edges.iter()
+        .flat_map(|(from, to, _)| [from.as_str(), to.as_str()])
+        .filter(|id| !in_range.contains(id))
+        .collect();
+
+    let mut nodes: Vec<DependencyGraphNode> = rows.iter()
+        .map(|row| DependencyGraphNode {
+            id: row.id.clone(),
+            title: row.title.clone(),
+            status: row.status.clone(),
+            start: Some(row.start),
+            in_range: true,
+            blocked: blocked.contains(row.id.as_str()),
+        })
+        .collect();
+    if !neighbours.is_empty() {
+        let linked: Vec<ExperimentDependency> = sqlx::query_as(r#"
+            SELECT id, title, status, start_date FROM experiments
+            WHERE id IN (SELECT experiment_id FROM experiment_dependencies UNION SELECT depends_on_id FROM experiment_dependencies)
+            ORDER BY datetime(COALESCE(start_date, experiment_date)), title
+        "#)
+            .fetch_all(pool)
+            .await?;
+        nodes.extend(linked.into_iter()
+            .filter(|e| neighbours.contains(e.id.as_str()))
+            .map(|e| DependencyGraphNode {
+                blocked: blocked.contains(e.id.as_str()),
+                id: e.id,
+                title: e.title,
+                status: e.status,
+                start: e.start_date,
+                in_range: false,
+            }));
+    }
 
-    tx.commit().await?;
-
-    Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
-        "message": "Reagent consumed successfully",
-        "reagent_id": reagent_link_id,
-        "quantity_consumed": qty
-    }))))
+    let edges = edges.into_iter()
+        .map(|(from, to, status)| DependencyGraphEdge {
+            from: from.clone(),
+            to: to.clone(),
+            satisfied: status == "completed",
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(ApiResponse::success(DependencyGraph { nodes, edges })))
 }
+
 // ==================== AUTO UPDATE STATUSES ====================
 
 #[derive(Debug, Serialize, Clone)]
@@ -952,24 +2657,37 @@ pub struct AutoUpdateResult {
     pub started: i32,
     pub completed: i32,
     pub total_updated: i32,
+    /// Пора запускать, но нет допуска по технике безопасности - остаются 'planned'
+    pub awaiting_signoff: i32,
+    /// Пора запускать, но не завершены предшествующие эксперименты - остаются 'planned'
+    pub awaiting_dependencies: i32,
 }
 
 /// Сколько секунд до ближайшего события (для smart sleep в фоновой задаче).
 /// Возвращает None если нет pending экспериментов.
-pub async fn seconds_until_next_transition(pool: &sqlx::SqlitePool) -> Result<Option<i64>, sqlx::Error> {
+/// Эксперименты без обязательного допуска или с незавершёнными предшественниками не учитываются -
+/// иначе просроченный старт даёт busy loop.
+pub async fn seconds_until_next_transition(
+    pool: &sqlx::SqlitePool,
+    signoff_threshold: u8,
+) -> Result<Option<i64>, sqlx::Error> {
     // Один лёгкий запрос: MIN из ближайшего start и ближайшего end.
     // datetime() нормализует любой формат даты перед сравнением.
-    let row: Option<i64> = sqlx::query_scalar(r#"
+    let sql = format!(r#"
         SELECT MIN(seconds) FROM (
             SELECT CAST((julianday(datetime(start_date)) - julianday(datetime('now'))) * 86400 AS INTEGER) as seconds
             FROM experiments
-            WHERE status = 'planned' AND start_date IS NOT NULL
+            WHERE status = 'planned' AND start_date IS NOT NULL AND NOT {awaiting} AND NOT {blocked}
             UNION ALL
             SELECT CAST((julianday(datetime(end_date)) - julianday(datetime('now'))) * 86400 AS INTEGER) as seconds
             FROM experiments
             WHERE status = 'in_progress' AND end_date IS NOT NULL
         )
-    "#)
+    "#,
+        awaiting = awaiting_signoff_condition("experiments.id", signoff_threshold),
+        blocked = blocked_by_dependencies_condition("experiments.id"),
+    );
+    let row: Option<i64> = sqlx::query_scalar(&sql)
         .fetch_one(pool)
         .await?;
 
@@ -981,19 +2699,29 @@ pub async fn seconds_until_next_transition(pool: &sqlx::SqlitePool) -> Result<Op
 /// КЛЮЧЕВОЙ ФИX: datetime() нормализует формат дат перед сравнением.
 /// Без этого SQLite сравнивает даты как текст и "2025-01-01T09:00:00Z" > "2025-01-01 12:00:00+00:00"
 /// потому что 'T' (0x54) > ' ' (0x20) в ASCII.
-pub async fn run_auto_update_statuses(pool: &sqlx::SqlitePool) -> Result<AutoUpdateResult, sqlx::Error> {
+/// Эксперименты с опасными реагентами без допуска (порог signoff_threshold) и эксперименты
+/// с незавершёнными предшественниками не запускаются.
+pub async fn run_auto_update_statuses(
+    pool: &sqlx::SqlitePool,
+    signoff_threshold: u8,
+) -> Result<AutoUpdateResult, sqlx::Error> {
     let now = Utc::now();
     let mut tx = pool.begin().await?;
+    let awaiting = awaiting_signoff_condition("experiments.id", signoff_threshold);
+    let blocked = blocked_by_dependencies_condition("experiments.id");
 
     // 1. planned → in_progress (пришло время start_date)
-    // datetime() нормализует оба операнда в "YYYY-MM-DD HH:MM:SS"
-    let started_result = sqlx::query(r#"
+    // datetime() нормализует оба операнда в "YYYY-MM-DD HH:MM:SS"; updated_by = NULL -
+    // в истории статусов автопереход записывается без автора
+    let started_result = sqlx::query(&format!(r#"
         UPDATE experiments
-        SET status = 'in_progress', updated_at = ?
+        SET status = 'in_progress', updated_by = NULL, updated_at = ?
         WHERE status = 'planned'
           AND start_date IS NOT NULL
           AND datetime(start_date) <= datetime(?)
-    "#)
+          AND NOT {}
+          AND NOT {}
+    "#, awaiting, blocked))
         .bind(&now)
         .bind(&now)
         .execute(&mut *tx)
@@ -1001,6 +2729,36 @@ pub async fn run_auto_update_statuses(pool: &sqlx::SqlitePool) -> Result<AutoUpd
 
     let started = started_result.rows_affected() as i32;
 
+    let awaiting_signoff: i64 = sqlx::query_scalar(&format!(r#"
+        SELECT COUNT(*) FROM experiments
+        WHERE status = 'planned'
+          AND start_date IS NOT NULL
+          AND datetime(start_date) <= datetime(?)
+          AND {}
+    "#, awaiting))
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+    let awaiting_signoff = awaiting_signoff as i32;
+    if awaiting_signoff > 0 {
+        log::warn!("{} experiment(s) due to start are waiting for a safety sign-off", awaiting_signoff);
+    }
+
+    let awaiting_dependencies: i64 = sqlx::query_scalar(&format!(r#"
+        SELECT COUNT(*) FROM experiments
+        WHERE status = 'planned'
+          AND start_date IS NOT NULL
+          AND datetime(start_date) <= datetime(?)
+          AND {}
+    "#, blocked))
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await?;
+    let awaiting_dependencies = awaiting_dependencies as i32;
+    if awaiting_dependencies > 0 {
+        log::info!("{} experiment(s) due to start are waiting for prerequisite experiments", awaiting_dependencies);
+    }
+
     // 2. in_progress → completed (пришло время end_date)
     let to_complete: Vec<String> = sqlx::query_scalar(r#"
         SELECT id FROM experiments
@@ -1024,6 +2782,11 @@ pub async fn run_auto_update_statuses(pool: &sqlx::SqlitePool) -> Result<AutoUpd
             .bind(exp_id)
             .fetch_all(&mut *tx)
             .await?;
+        outbox::enqueue(&mut *tx, &BusinessEvent::ExperimentCompleted {
+            experiment_id: exp_id.clone(),
+            reagents_consumed: reagents.len() as i64,
+            automatic: true,
+        }, None).await?;
 
         for reagent in reagents {
             let qty = reagent.planned_quantity.unwrap_or(0.0);
@@ -1040,15 +2803,12 @@ pub async fn run_auto_update_statuses(pool: &sqlx::SqlitePool) -> Result<AutoUpd
                     .execute(&mut *tx)
                     .await?;
             }
-            sqlx::query("UPDATE experiment_reagents SET is_consumed = 1 WHERE id = ?")
-                .bind(&reagent.id)
-                .execute(&mut *tx)
-                .await?;
+            mark_reagent_consumed(&mut tx, &reagent.id, None, None, now).await?;
         }
 
         sqlx::query(r#"
             UPDATE experiments
-            SET status = 'completed', updated_at = ?
+            SET status = 'completed', updated_by = NULL, updated_at = ?
             WHERE id = ?
         "#)
             .bind(&now)
@@ -1064,14 +2824,14 @@ pub async fn run_auto_update_statuses(pool: &sqlx::SqlitePool) -> Result<AutoUpd
         info!("Auto-updated: {} started, {} completed (reagents consumed)", started, completed);
     }
 
-    Ok(AutoUpdateResult { started, completed, total_updated })
+    Ok(AutoUpdateResult { started, completed, total_updated, awaiting_signoff, awaiting_dependencies })
 }
 
 /// HTTP-хендлер (обёртка)
 pub async fn auto_update_experiment_statuses(
     app_state: web::Data<Arc<AppState>>,
 ) -> ApiResult<HttpResponse> {
-    let result = run_auto_update_statuses(&app_state.db_pool)
+    let result = run_auto_update_statuses(&app_state.db_pool, app_state.config.safety.signoff_hazard_threshold)
         .await
         .map_err(|e| ApiError::InternalServerError(format!("Auto-update failed: {}", e)))?;
 
@@ -1120,39 +2880,179 @@ pub async fn diagnose_experiment_dates(
 
 // ==================== CALENDAR ====================
 
-#[derive(Debug, Serialize, sqlx::FromRow)]
+/// Длительность события без end_date (если оно не на весь день)
+pub const CALENDAR_DEFAULT_DURATION_HOURS: i64 = 2;
+/// Предел событий одного вида в ответе календаря
+pub const CALENDAR_KIND_LIMIT: i64 = 500;
+
+#[derive(Debug, Serialize)]
 pub struct CalendarEvent {
     pub id: String,
     pub title: String,
     pub start: chrono::DateTime<Utc>,
+    /// end_date или start + длительность по умолчанию (сутки для событий без времени)
+    pub end: chrono::DateTime<Utc>,
+    pub all_day: bool,
     pub status: String,
     pub experiment_type: Option<String>,
     pub location: Option<String>,
+    pub room_id: Option<String>,
+    /// Цвет помещения для раскраски календаря
+    pub color: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Строка эксперимента для календаря (вместе с помещением: room_id или, для старых записей, location = имя)
+#[derive(Debug, sqlx::FromRow)]
+pub(crate) struct CalendarExperimentRow {
+    pub id: String,
+    pub title: String,
+    pub start: chrono::DateTime<Utc>,
+    pub end_date: Option<chrono::DateTime<Utc>>,
+    pub status: String,
+    pub experiment_type: Option<String>,
+    pub location: Option<String>,
+    pub room_id: Option<String>,
+    pub room_name: Option<String>,
+    pub color: Option<String>,
+}
+
+impl From<CalendarExperimentRow> for CalendarEvent {
+    fn from(row: CalendarExperimentRow) -> Self {
+        let (end, all_day) = calendar_event_end(row.start, row.end_date);
+        CalendarEvent {
+            id: row.id,
+            title: row.title,
+            start: row.start,
+            end,
+            all_day,
+            status: row.status,
+            experiment_type: row.experiment_type,
+            location: row.location,
+            room_id: row.room_id,
+            color: row.color,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
 pub struct CalendarQuery {
+    /// YYYY-MM-DD или RFC 3339
     pub start: Option<String>,
+    /// YYYY-MM-DD (день включительно) или RFC 3339
     pub end: Option<String>,
+    pub room_id: Option<String>,
+    pub experiment_type: Option<String>,
+}
+
+impl CalendarQuery {
+    /// Период [start, end) для выборки пересекающихся событий
+    pub(crate) fn range(&self) -> ApiResult<(chrono::DateTime<Utc>, chrono::DateTime<Utc>)> {
+        let start = parse_calendar_bound(self.start.as_deref().unwrap_or("1970-01-01"), false)?;
+        let end = parse_calendar_bound(self.end.as_deref().unwrap_or("2100-12-31"), true)?;
+        if end <= start {
+            return Err(ApiError::bad_request("Calendar end must be after start"));
+        }
+        Ok((start, end))
+    }
+
+    pub(crate) fn experiment_type(&self) -> ApiResult<Option<&'static str>> {
+        self.experiment_type
+            .as_deref()
+            .map(|value| {
+                ExperimentType::from_str(value).map(|t| t.as_str()).ok_or_else(|| {
+                    ApiError::bad_request("experiment_type must be 'educational' or 'research'")
+                })
+            })
+            .transpose()
+    }
+}
+
+fn parse_calendar_bound(value: &str, end_of_day: bool) -> ApiResult<chrono::DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let date = if end_of_day { date + chrono::Duration::days(1) } else { date };
+        return Ok(date.and_time(chrono::NaiveTime::MIN).and_utc());
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| ApiError::bad_request(&format!("Invalid calendar date '{}': use YYYY-MM-DD or RFC 3339", value)))
 }
 
+/// Конец события и признак «весь день». Без end_date событие с началом в полночь
+/// занимает сутки, остальные - CALENDAR_DEFAULT_DURATION_HOURS.
+/// С end_date весь день - это интервал от полуночи до полуночи.
+pub(crate) fn calendar_event_end(
+    start: chrono::DateTime<Utc>,
+    end: Option<chrono::DateTime<Utc>>,
+) -> (chrono::DateTime<Utc>, bool) {
+    let starts_at_midnight = start.time() == chrono::NaiveTime::MIN;
+    match end {
+        Some(end) if end > start => (end, starts_at_midnight && end.time() == chrono::NaiveTime::MIN),
+        _ if starts_at_midnight => (start + chrono::Duration::days(1), true),
+        _ => (start + chrono::Duration::hours(CALENDAR_DEFAULT_DURATION_HOURS), false),
+    }
+}
+
+/// Эксперименты, пересекающиеся с периодом (черновики в календарь не попадают).
+/// `bookings_only` - только занимающие помещение (есть помещение, не отменён), как при расчёте загрузки помещений
+pub(crate) async fn fetch_calendar_experiments(
+    pool: &sqlx::SqlitePool,
+    query: &CalendarQuery,
+    bookings_only: bool,
+    limit: i64,
+) -> ApiResult<Vec<CalendarExperimentRow>> {
+    let (range_start, range_end) = query.range()?;
+    let experiment_type = query.experiment_type()?;
+    let bookings_filter = if bookings_only {
+        "AND r.id IS NOT NULL AND e.status != 'cancelled'"
+    } else {
+        ""
+    };
+
+    // Точный конец считается в Rust (calendar_event_end); в SQL - верхняя оценка (сутки)
+    let rows: Vec<CalendarExperimentRow> = sqlx::query_as(&format!(
+        r#"
+        SELECT e.id, e.title, COALESCE(e.start_date, e.experiment_date) AS start, e.end_date,
+               e.status, e.experiment_type, e.location, r.id AS room_id, r.name AS room_name, r.color
+        FROM experiments e
+        LEFT JOIN rooms r ON r.id = e.room_id OR (e.room_id IS NULL AND r.name = e.location)
+        WHERE e.status != 'draft'
+          AND datetime(COALESCE(e.start_date, e.experiment_date)) < datetime(?)
+          AND datetime(COALESCE(e.end_date, datetime(COALESCE(e.start_date, e.experiment_date), '+1 day'))) > datetime(?)
+          AND (? IS NULL OR r.id = ?)
+          AND (? IS NULL OR e.experiment_type = ?)
+          {}
+        ORDER BY datetime(COALESCE(e.start_date, e.experiment_date)) ASC
+        LIMIT ?
+        "#,
+        bookings_filter
+    ))
+        .bind(range_end)
+        .bind(range_start)
+        .bind(&query.room_id)
+        .bind(&query.room_id)
+        .bind(experiment_type)
+        .bind(experiment_type)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter(|row| calendar_event_end(row.start, row.end_date).0 > range_start)
+        .collect())
+}
+
+/// GET /experiments/calendar - эксперименты периода с помещением и его цветом
 pub async fn get_experiments_calendar(
     app_state: web::Data<Arc<AppState>>,
     query: web::Query<CalendarQuery>,
 ) -> ApiResult<HttpResponse> {
-    let start = query.start.as_deref().unwrap_or("1970-01-01");
-    let end = query.end.as_deref().unwrap_or("2100-12-31");
-
-    let events: Vec<CalendarEvent> = sqlx::query_as(r#"
-        SELECT id, title, experiment_date as start, status, experiment_type, location
-        FROM experiments
-        WHERE experiment_date >= ? AND experiment_date <= ?
-        ORDER BY experiment_date ASC
-    "#)
-        .bind(start)
-        .bind(end)
-        .fetch_all(&app_state.db_pool)
-        .await?;
+    let events: Vec<CalendarEvent> = fetch_calendar_experiments(&app_state.db_pool, &query, false, CALENDAR_KIND_LIMIT)
+        .await?
+        .into_iter()
+        .map(CalendarEvent::from)
+        .collect();
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(events)))
 }
@@ -1187,30 +3087,969 @@ pub async fn get_experiment_documents(
     Ok(HttpResponse::Ok().json(ApiResponse::success(docs)))
 }
 
+const EXPERIMENT_DOCUMENTS_DIR: &str = "./uploads/experiments";
+
 pub async fn download_experiment_document(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
-) -> Result<NamedFile, ApiError> {
+    req: actix_web::HttpRequest,
+) -> ApiResult<HttpResponse> {
     let (experiment_id, doc_id) = path.into_inner();
+    serve_experiment_document(&app_state.db_pool, Path::new(EXPERIMENT_DOCUMENTS_DIR), &experiment_id, &doc_id, &req).await
+}
+
+/// Имя файла на диске из БД должно быть одним компонентом пути внутри каталога документов
+fn experiment_document_path(base_dir: &Path, filename: &str) -> Option<PathBuf> {
+    let mut components = Path::new(filename).components();
+    let plain = matches!((components.next(), components.next()), (Some(Component::Normal(_)), None));
+    (plain && !filename.contains('\\')).then(|| base_dir.join(filename))
+}
 
+async fn serve_experiment_document(
+    pool: &sqlx::SqlitePool,
+    base_dir: &Path,
+    experiment_id: &str,
+    doc_id: &str,
+    req: &actix_web::HttpRequest,
+) -> ApiResult<HttpResponse> {
     #[derive(sqlx::FromRow)]
     struct DocInfo {
         filename: String,
-        #[allow(dead_code)]
         original_name: String,
     }
 
     let doc: DocInfo = sqlx::query_as(
         "SELECT filename, original_name FROM experiment_documents WHERE id = ? AND experiment_id = ?"
     )
-        .bind(&doc_id)
-        .bind(&experiment_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::not_found("Document"))?;
+        .bind(doc_id)
+        .bind(experiment_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Document"))?;
+
+    let file_path = experiment_document_path(base_dir, &doc.filename)
+        .ok_or_else(|| ApiError::not_found("Document file"))?;
+    let file = NamedFile::open(&file_path)
+        .map_err(|_| ApiError::not_found("Document file"))?;
+
+    let mut response = file.into_response(req);
+    let disposition = content_disposition("attachment", &doc.original_name);
+    response.headers_mut().insert(
+        actix_web::http::header::CONTENT_DISPOSITION,
+        actix_web::http::header::HeaderValue::from_str(&disposition)
+            .map_err(|e| ApiError::InternalServerError(format!("Invalid Content-Disposition: {}", e)))?,
+    );
+    Ok(response)
+}
 
-    let file_path = PathBuf::from("./uploads/experiments").join(&doc.filename);
-    
-    NamedFile::open(&file_path)
-        .map_err(|_| ApiError::not_found("Document file"))
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use actix_web::HttpMessage;
+    use serde_json::json;
+
+    fn viewer(user_id: &str) -> actix_web::HttpRequest {
+        let req = actix_web::test::TestRequest::get().to_http_request();
+        req.extensions_mut().insert(crate::auth::Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{}@example.com", user_id),
+            role: crate::auth::UserRole::Researcher,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn test_app_state() -> web::Data<Arc<AppState>> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('tester', 'tester', 'tester@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        for (id, title, date) in [("e1", "Titration", "2024-01-10 09:00:00"), ("e2", "Chromatography", "2024-03-05 09:00:00")] {
+            sqlx::query(
+                "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, \
+                 created_by, created_at, updated_at) \
+                 VALUES (?, ?, ?, ?, 'planned', 'research', 'tester', datetime('now'), datetime('now'))"
+            )
+                .bind(id)
+                .bind(title)
+                .bind(date)
+                .bind(date)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    async fn list_ids(app_state: &web::Data<Arc<AppState>>, sort_by: Option<&str>, sort_order: Option<&str>) -> Vec<String> {
+        let query = ExperimentQuery {
+            search: None,
+            status: None,
+            experiment_type: None,
+            outcome: None,
+            location: None,
+            date_from: None,
+            date_to: None,
+            team_id: None,
+            mine: None,
+            sort_by: sort_by.map(str::to_string),
+            sort_order: sort_order.map(str::to_string),
+            page: None,
+            per_page: None,
+            fields: None,
+            strict: None,
+            include_counts: None,
+        };
+        let response = get_all_experiments(app_state.clone(), web::Query(query), viewer("tester")).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        json["data"]["data"].as_array().unwrap()
+            .iter()
+            .map(|e| e["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_experiment_listing_sorting() {
+        let app_state = test_app_state().await;
+
+        assert_eq!(list_ids(&app_state, None, None).await, vec!["e2", "e1"]);
+        assert_eq!(list_ids(&app_state, None, Some("asc")).await, vec!["e1", "e2"]);
+        assert_eq!(list_ids(&app_state, Some("title"), Some("ASC")).await, vec!["e2", "e1"]);
+    }
+
+    #[actix_web::test]
+    async fn test_experiment_listing_ignores_hostile_sort_params() {
+        let app_state = test_app_state().await;
+
+        let hostile_orders = [
+            "ASC; DROP TABLE experiments",
+            "DESC, (SELECT password_hash FROM users)",
+            "ASC --",
+        ];
+        for order in hostile_orders {
+            // Направление нормализуется в DESC по experiment_date
+            assert_eq!(list_ids(&app_state, None, Some(order)).await, vec!["e2", "e1"]);
+        }
+
+        let hostile_fields = [
+            "experiment_date; DROP TABLE experiments",
+            "x; DROP TABLE experiments.title",
+            "(SELECT password_hash FROM users)",
+            "users.password_hash",
+            "e.title",
+        ];
+        for field in hostile_fields {
+            // Неизвестное поле заменяется сортировкой по умолчанию
+            assert_eq!(list_ids(&app_state, Some(field), Some("ASC")).await, vec!["e1", "e2"]);
+        }
+
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM experiments")
+            .fetch_one(&app_state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 2);
+    }
+
+    async fn search_ids(app_state: &web::Data<Arc<AppState>>, search: Option<&str>, outcome: Option<&str>) -> Vec<String> {
+        let query: ExperimentQuery = serde_json::from_value(serde_json::json!({
+            "search": search, "outcome": outcome,
+        })).unwrap();
+        let response = get_all_experiments(app_state.clone(), web::Query(query), viewer("tester")).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        json["data"]["data"].as_array().unwrap()
+            .iter()
+            .map(|e| e["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_outcome_set_on_completion_and_results_searchable() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+
+        // results индексируются триггером experiments_fts
+        sqlx::query("UPDATE experiments SET results = 'Yellow precipitate formed after heating' WHERE id = 'e1'")
+            .execute(&pool).await.unwrap();
+        assert_eq!(search_ids(&app_state, Some("precipitate"), None).await, vec!["e1"]);
+        assert_eq!(search_ids(&app_state, Some("chromat"), None).await, vec!["e2"]);
+
+        // Итог нельзя задать вне завершения
+        let update: UpdateExperimentRequest = serde_json::from_value(serde_json::json!({ "outcome": "failed" })).unwrap();
+        let err = update_experiment(app_state.clone(), web::Path::from("e1".to_string()), web::Json(update), "tester".to_string())
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        sqlx::query("UPDATE experiments SET status = 'in_progress' WHERE id IN ('e1', 'e2')")
+            .execute(&pool).await.unwrap();
+        let bad = CompleteExperimentRequest { outcome: Some("great".to_string()), ..Default::default() };
+        let err = complete_experiment(app_state.clone(), web::Path::from("e2".to_string()), Some(web::Json(bad)), "tester".to_string())
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::ValidationError(_)));
+
+        let body = CompleteExperimentRequest { outcome: Some("partial".to_string()), ..Default::default() };
+        complete_experiment(app_state.clone(), web::Path::from("e2".to_string()), Some(web::Json(body)), "tester".to_string())
+            .await.unwrap();
+        complete_experiment(app_state.clone(), web::Path::from("e1".to_string()), None, "tester".to_string())
+            .await.unwrap();
+        assert_eq!(search_ids(&app_state, None, Some("partial")).await, vec!["e2"]);
+
+        let response = get_experiment_stats(app_state.clone()).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"]["completed"], 2);
+        assert_eq!(json["data"]["partial"], 1);
+        assert_eq!(json["data"]["outcome_unset"], 1);
+    }
+
+    #[actix_web::test]
+    async fn test_inactive_reagent_blocks_new_reservations_but_completes_existing() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Acetonitrile', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'LOT-1', 100, 100, 'mL', datetime('now'), 'available', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        let reserve = |experiment_id: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let body = AddReagentToExperimentRequest { batch_id: "b1".to_string(), quantity_used: 10.0, unit: None, notes: None };
+                add_reagent_to_experiment(app_state, web::Path::from(experiment_id.to_string()), web::Json(body), "tester".to_string()).await
+            }
+        };
+        reserve("e1").await.unwrap();
+
+        sqlx::query("UPDATE reagents SET status = 'inactive' WHERE id = 'r1'").execute(&pool).await.unwrap();
+        let err = reserve("e2").await.unwrap_err();
+        assert!(matches!(err, ApiError::Conflict { code: crate::error::REAGENT_INACTIVE, .. }), "{:?}", err);
+
+        // Резерв, сделанный до деактивации, списывается при завершении как обычно
+        sqlx::query("UPDATE experiments SET status = 'in_progress' WHERE id = 'e1'").execute(&pool).await.unwrap();
+        complete_experiment(app_state.clone(), web::Path::from("e1".to_string()), None, "tester".to_string())
+            .await.unwrap();
+        let (quantity,): (f64,) = sqlx::query_as("SELECT quantity FROM batches WHERE id = 'b1'").fetch_one(&pool).await.unwrap();
+        assert_eq!(quantity, 90.0);
+    }
+
+    #[actix_web::test]
+    async fn test_consumption_keeps_note_and_lot_snapshot() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        for sql in [
+            "INSERT INTO reagents (id, name, created_at, updated_at) VALUES ('r1', 'Acetonitrile', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, supplier, expiry_date, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'LOT-1', 'Sigma', '2030-01-01 00:00:00', 100, 100, 'mL', datetime('now'), 'available', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        for experiment_id in ["e1", "e2"] {
+            let body = AddReagentToExperimentRequest { batch_id: "b1".to_string(), quantity_used: 10.0, unit: None, notes: None };
+            add_reagent_to_experiment(app_state.clone(), web::Path::from(experiment_id.to_string()), web::Json(body), "tester".to_string())
+                .await.unwrap();
+        }
+        sqlx::query("UPDATE experiments SET status = 'in_progress'").execute(&pool).await.unwrap();
+        let line = |experiment_id: &'static str| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, String>("SELECT id FROM experiment_reagents WHERE experiment_id = ?")
+                    .bind(experiment_id).fetch_one(&pool).await.unwrap()
+            }
+        };
+        let (line1, line2) = (line("e1").await, line("e2").await);
+
+        let body = ConsumeExperimentReagentRequest { notes: Some("calibration curve points 1-5".to_string()) };
+        consume_experiment_reagent(
+            app_state.clone(), web::Path::from(("e1".to_string(), line1.clone())), Some(web::Json(body)), "tester".to_string(),
+        ).await.unwrap();
+
+        // Пометка к чужой строке отклоняется целиком
+        let notes = std::collections::HashMap::from([(line1.clone(), "wrong".to_string())]);
+        let body = CompleteExperimentRequest { consumption_notes: notes, ..Default::default() };
+        let err = complete_experiment(app_state.clone(), web::Path::from("e2".to_string()), Some(web::Json(body)), "tester".to_string())
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+        let notes = std::collections::HashMap::from([(line2.clone(), "blank run".to_string())]);
+        let body = CompleteExperimentRequest { consumption_notes: notes, ..Default::default() };
+        complete_experiment(app_state.clone(), web::Path::from("e2".to_string()), Some(web::Json(body)), "tester".to_string())
+            .await.unwrap();
+
+        // Правка партии после списания не меняет записанное в эксперименте
+        sqlx::query("UPDATE batches SET batch_number = 'LOT-1A', supplier = 'Merck', expiry_date = '2031-01-01 00:00:00'")
+            .execute(&pool).await.unwrap();
+        let consumptions = fetch_consumptions(&pool, None).await.unwrap();
+        assert_eq!(consumptions.len(), 2);
+        for consumption in &consumptions {
+            assert_eq!(consumption.lot_batch_number.as_deref(), Some("LOT-1"));
+            assert_eq!(consumption.lot_supplier.as_deref(), Some("Sigma"));
+            assert_eq!(consumption.lot_expiry_date.unwrap().to_rfc3339(), "2030-01-01T00:00:00+00:00");
+            assert_eq!(consumption.consumed_by.as_deref(), Some("tester"));
+        }
+        assert_eq!(consumptions[0].consumption_notes.as_deref(), Some("calibration curve points 1-5"));
+        assert_eq!(consumptions[1].consumption_notes.as_deref(), Some("blank run"));
+
+        let response = get_experiment(app_state.clone(), web::Path::from("e1".to_string()), viewer("tester")).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"]["consumptions"][0]["lot_batch_number"], "LOT-1");
+    }
+
+    #[actix_web::test]
+    async fn test_experiment_listing_multi_select_filters() {
+        let app_state = test_app_state().await;
+        sqlx::query("UPDATE experiments SET status = 'on_hold', experiment_type = 'educational', location = 'Lab 1' WHERE id = 'e1'")
+            .execute(&app_state.db_pool).await.unwrap();
+        sqlx::query("UPDATE experiments SET location = 'Lab 2' WHERE id = 'e2'")
+            .execute(&app_state.db_pool).await.unwrap();
+
+        let ids = |filters: serde_json::Value| {
+            let app_state = app_state.clone();
+            async move {
+                let query: ExperimentQuery = serde_json::from_value(filters).unwrap();
+                let response = get_all_experiments(app_state, web::Query(query), viewer("tester")).await?;
+                let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+                let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                Ok::<_, ApiError>(json["data"]["data"].as_array().unwrap()
+                    .iter()
+                    .map(|e| e["id"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>())
+            }
+        };
+        assert_eq!(ids(serde_json::json!({ "status": "planned,on_hold" })).await.unwrap(), vec!["e2", "e1"]);
+        assert_eq!(ids(serde_json::json!({ "status": "planned,completed" })).await.unwrap(), vec!["e2"]);
+        assert_eq!(ids(serde_json::json!({ "experiment_type": "educational" })).await.unwrap(), vec!["e1"]);
+        assert_eq!(ids(serde_json::json!({ "location": "Lab 1,Lab 2", "experiment_type": "research,educational" })).await.unwrap(), vec!["e2", "e1"]);
+
+        let err = ids(serde_json::json!({ "status": "planned,archived" })).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref m) if m.contains("archived") && m.contains("on_hold")), "{:?}", err);
+        let err = ids(serde_json::json!({ "experiment_type": "учебный" })).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref m) if m.contains("educational, research")), "{:?}", err);
+    }
+
+    fn claims(user_id: &str, role: crate::auth::UserRole) -> crate::auth::Claims {
+        crate::auth::Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{}@lims.local", user_id),
+            role,
+            exp: i64::MAX,
+            iat: 0,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_only_owner_instructor_or_admin_manage_experiment() {
+        use crate::access_control::authorize_route;
+        use crate::auth::UserRole;
+        use actix_web::http::Method;
+
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        for (id, role) in [("owner", "researcher"), ("teacher", "researcher"), ("colleague", "researcher"), ("guest", "viewer")] {
+            sqlx::query(
+                "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+                 VALUES (?, ?, ?, 'x', ?, datetime('now'), datetime('now'))"
+            ).bind(id).bind(id).bind(format!("{}@example.com", id)).bind(role).execute(&pool).await.unwrap();
+        }
+        let request: CreateExperimentRequest = serde_json::from_value(serde_json::json!({
+            "title": "Owned titration", "experiment_type": "research", "instructor_user_id": "teacher",
+        })).unwrap();
+        let response = create_experiment(app_state.clone(), web::Json(request), "owner".to_string()).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = json["data"]["id"].as_str().unwrap().to_string();
+        assert_eq!(json["data"]["instructor_user_id"], "teacher");
+
+        let managing_routes = [
+            (Method::PUT, "/experiments/x"),
+            (Method::POST, "/experiments/x/start"),
+            (Method::POST, "/experiments/x/complete"),
+            (Method::POST, "/experiments/x/cancel"),
+        ];
+        // (пользователь, роль, может изменять)
+        let cases = [
+            ("tester", UserRole::Admin, true),
+            ("owner", UserRole::Researcher, true),
+            ("teacher", UserRole::Researcher, true),
+            ("colleague", UserRole::Researcher, false),
+            ("guest", UserRole::Viewer, false),
+            // Автор, у которого роль понижена до viewer, теряет право Edit по таблице маршрутов
+            ("owner", UserRole::Viewer, false),
+        ];
+        for (user, role, expected) in cases {
+            let is_admin = role == UserRole::Admin;
+            let claims = claims(user, role.clone());
+            for (method, path) in &managing_routes {
+                let allowed = authorize_route(&claims, method, path, &pool).await.is_ok()
+                    && ensure_can_manage_experiment(&pool, &id, user, is_admin).await.is_ok();
+                assert_eq!(allowed, expected, "{} as {:?} on {} {}", user, role, method, path);
+            }
+            // Удаление по таблице маршрутов остаётся за администраторами
+            let can_delete = authorize_route(&claims, &Method::DELETE, "/experiments/x", &pool).await.is_ok()
+                && ensure_can_manage_experiment(&pool, &id, user, is_admin).await.is_ok();
+            assert_eq!(can_delete, is_admin);
+            // Комментировать может любой с правом Edit, читать - любой с правом View
+            let can_comment = authorize_route(&claims, &Method::POST, "/experiments/x/comments", &pool).await.is_ok();
+            assert_eq!(can_comment, role != UserRole::Viewer);
+            assert!(authorize_route(&claims, &Method::GET, "/experiments/x/comments", &pool).await.is_ok());
+        }
+
+        let err = ensure_can_manage_experiment(&pool, &id, "colleague", false).await.unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        let err = ensure_can_manage_experiment(&pool, "missing", "tester", true).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+
+        let comment: AddExperimentCommentRequest =
+            serde_json::from_value(serde_json::json!({ "body": "  Try a slower titration rate  " })).unwrap();
+        add_experiment_comment(app_state.clone(), web::Path::from(id.clone()), web::Json(comment), "colleague".to_string())
+            .await.unwrap();
+        let response = get_experiment_comments(app_state.clone(), web::Path::from(id.clone())).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"][0]["username"], "colleague");
+        assert_eq!(json["data"][0]["body"], "Try a slower titration rate");
+
+        // Инструктора можно снять, после чего он теряет право изменять
+        let update: UpdateExperimentRequest =
+            serde_json::from_value(serde_json::json!({ "instructor_user_id": "" })).unwrap();
+        update_experiment(app_state.clone(), web::Path::from(id.clone()), web::Json(update), "owner".to_string())
+            .await.unwrap();
+        assert!(ensure_can_manage_experiment(&pool, &id, "teacher", false).await.is_err());
+    }
+
+    /// e1 использует реагент с GHS06 (тяжесть 3), e2 - только раздражающий GHS07
+    async fn seed_hazardous_reagents(pool: &sqlx::SqlitePool) {
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('teacher', 'teacher', 'teacher@example.com', 'x', 'researcher', datetime('now'), datetime('now'))"
+        ).execute(pool).await.unwrap();
+        for (reagent_id, name, pictograms, experiment_id) in [
+            ("r1", "Sodium cyanide", "GHS06, GHS09", "e1"),
+            ("r2", "Acetone", "GHS02,GHS07", "e2"),
+        ] {
+            sqlx::query(
+                "INSERT INTO reagents (id, name, hazard_pictograms, status, created_at, updated_at) \
+                 VALUES (?, ?, ?, 'active', datetime('now'), datetime('now'))"
+            ).bind(reagent_id).bind(name).bind(pictograms).execute(pool).await.unwrap();
+            let batch_id = format!("b-{}", reagent_id);
+            sqlx::query(
+                "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+                 received_date, status, created_at, updated_at) \
+                 VALUES (?, ?, 'LOT-1', 100, 100, 'g', datetime('now'), 'available', datetime('now'), datetime('now'))"
+            ).bind(&batch_id).bind(reagent_id).execute(pool).await.unwrap();
+            sqlx::query(
+                "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, \
+                 created_at, updated_at) VALUES (?, ?, ?, ?, 5, 'g', datetime('now'), datetime('now'))"
+            ).bind(format!("er-{}", reagent_id)).bind(experiment_id).bind(reagent_id).bind(&batch_id)
+                .execute(pool).await.unwrap();
+        }
+    }
+
+    fn signoff_request() -> web::Json<SignoffRequest> {
+        web::Json(serde_json::from_value(serde_json::json!({
+            "checklist": { "ppe_checked": true, "fume_hood_available": true, "antidote_kit": "room 204" },
+        })).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn test_signoff_required_before_start() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        seed_hazardous_reagents(&pool).await;
+
+        let status = fetch_signoff_status(&pool, "e1", 2).await.unwrap();
+        assert!(status.requires_signoff);
+        assert_eq!(status.hazardous_reagents[0].name, "Sodium cyanide");
+        assert!(!fetch_signoff_status(&pool, "e2", 2).await.unwrap().requires_signoff);
+        // Порог 1 включает и средние опасности
+        assert!(fetch_signoff_status(&pool, "e2", 1).await.unwrap().requires_signoff);
+
+        let err = start_experiment(app_state.clone(), web::Path::from("e1".to_string()), "tester".to_string())
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref msg) if msg.contains("Sodium cyanide")));
+
+        // Авто-обновление запускает только e2; e1 ждёт допуска и не сбивает расписание фоновой задачи
+        let result = run_auto_update_statuses(&pool, 2).await.unwrap();
+        assert_eq!((result.started, result.awaiting_signoff), (1, 1));
+        assert_eq!(seconds_until_next_transition(&pool, 2).await.unwrap(), None);
+
+        // Подписать может только админ или инструктор эксперимента
+        let err = sign_off_experiment(
+            app_state.clone(), web::Path::from("e1".to_string()), signoff_request(), "teacher".to_string(), false,
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        sqlx::query(
+            "INSERT INTO experiment_participants (id, experiment_id, user_id, role, created_at) \
+             VALUES ('p1', 'e1', 'teacher', 'instructor', datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sign_off_experiment(
+            app_state.clone(), web::Path::from("e1".to_string()), signoff_request(), "teacher".to_string(), false,
+        ).await.unwrap();
+        let err = sign_off_experiment(
+            app_state.clone(), web::Path::from("e1".to_string()), signoff_request(), "tester".to_string(), true,
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        let signoff = fetch_signoff(&pool, "e1").await.unwrap().unwrap();
+        assert_eq!(signoff.signed_by_username.as_deref(), Some("teacher"));
+        assert!(signoff.checklist.contains("fume_hood_available"));
+
+        start_experiment(app_state.clone(), web::Path::from("e1".to_string()), "tester".to_string()).await.unwrap();
+        let status: String = sqlx::query_scalar("SELECT status FROM experiments WHERE id = 'e1'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(status, "in_progress");
+    }
+
+    fn dependency_request(depends_on_id: &str) -> web::Json<AddDependencyRequest> {
+        web::Json(AddDependencyRequest { depends_on_id: depends_on_id.to_string() })
+    }
+
+    #[actix_web::test]
+    async fn test_dependencies_block_start_and_reject_cycles() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let add = |id: &str, depends_on: &str| {
+            add_experiment_dependency(
+                app_state.clone(), web::Path::from(id.to_string()), dependency_request(depends_on), "tester".to_string(),
+            )
+        };
+
+        // e2 (март) - следующий этап после e1 (январь)
+        add("e2", "e1").await.unwrap();
+        let err = add("e2", "e1").await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+        let err = add("e1", "e2").await.unwrap_err();
+        assert!(matches!(err, ApiError::Conflict { code: crate::error::DEPENDENCY_CYCLE, .. }));
+        let err = add("e1", "e1").await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        let err = start_experiment(app_state.clone(), web::Path::from("e2".to_string()), "tester".to_string())
+            .await.unwrap_err();
+        assert!(matches!(
+            err,
+            ApiError::Conflict { code: crate::error::DEPENDENCIES_INCOMPLETE, ref message } if message.contains("Titration (planned)")
+        ));
+
+        // Авто-обновление запускает только e1; e2 ждёт и не сбивает расписание фоновой задачи
+        let result = run_auto_update_statuses(&pool, 2).await.unwrap();
+        assert_eq!((result.started, result.awaiting_dependencies), (1, 1));
+        assert_eq!(seconds_until_next_transition(&pool, 2).await.unwrap(), None);
+
+        // Граф за март: e2 в периоде, e1 - соседний узел вне периода
+        let query = CalendarQuery {
+            start: Some("2024-03-01".to_string()),
+            end: Some("2024-03-31".to_string()),
+            ..Default::default()
+        };
+        let response = get_experiment_dependency_graph(app_state.clone(), web::Query(query)).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let graph: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let nodes: Vec<(&str, bool, bool)> = graph["data"]["nodes"].as_array().unwrap().iter()
+            .map(|n| (n["id"].as_str().unwrap(), n["in_range"].as_bool().unwrap(), n["blocked"].as_bool().unwrap()))
+            .collect();
+        assert_eq!(nodes, vec![("e2", true, true), ("e1", false, false)]);
+        assert_eq!(graph["data"]["edges"], serde_json::json!([{ "from": "e1", "to": "e2", "satisfied": false }]));
+
+        sqlx::query("UPDATE experiments SET status = 'completed' WHERE id = 'e1'").execute(&pool).await.unwrap();
+        let status = fetch_dependency_status(&pool, "e1").await.unwrap();
+        assert_eq!(status.dependents[0].id, "e2");
+        assert!(fetch_dependency_status(&pool, "e2").await.unwrap().blocked_by.is_empty());
+        start_experiment(app_state.clone(), web::Path::from("e2".to_string()), "tester".to_string()).await.unwrap();
+
+        let remove = || remove_experiment_dependency(
+            app_state.clone(), web::Path::from(("e2".to_string(), "e1".to_string())), "tester".to_string(),
+        );
+        remove().await.unwrap();
+        assert!(matches!(remove().await.unwrap_err(), ApiError::NotFound(_)));
+    }
+
+    #[actix_web::test]
+    async fn test_draft_hidden_unreserved_until_published() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Ethanol', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'LOT-1', 100, 100, 'mL', datetime('now'), 'available', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        let reserved = || async {
+            sqlx::query_scalar::<_, f64>("SELECT reserved_quantity FROM batches WHERE id = 'b1'")
+                .fetch_one(&pool).await.unwrap()
+        };
+
+        // Учебный черновик без end_date создаётся, хотя planned-эксперимент был бы отклонён
+        let draft: CreateExperimentRequest = serde_json::from_value(serde_json::json!({
+            "title": "Distillation", "experiment_type": "educational",
+            "start_date": "2030-01-10T09:00:00Z", "status": "draft",
+        })).unwrap();
+        let response = create_experiment(app_state.clone(), web::Json(draft), "tester".to_string()).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created["data"]["status"], "draft");
+        let id = created["data"]["id"].as_str().unwrap().to_string();
+
+        // В черновик реагент записывается без резерва и проверки остатка
+        let body = AddReagentToExperimentRequest { batch_id: "b1".to_string(), quantity_used: 150.0, unit: None, notes: None };
+        add_reagent_to_experiment(app_state.clone(), web::Path::from(id.clone()), web::Json(body), "tester".to_string())
+            .await.unwrap();
+        assert_eq!(reserved().await, 0.0);
+
+        // Черновик виден только автору и не входит в общую статистику
+        let list = |user: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let query: ExperimentQuery = serde_json::from_value(serde_json::json!({})).unwrap();
+                let response = get_all_experiments(app_state, web::Query(query), viewer(user)).await.unwrap();
+                let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+                let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                json["data"]["total"].as_i64().unwrap()
+            }
+        };
+        assert_eq!((list("tester").await, list("someone").await), (3, 2));
+        let response = get_experiment_stats(app_state.clone()).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!((stats["data"]["total"].as_i64(), stats["data"]["drafts"].as_i64()), (Some(2), Some(1)));
+
+        // Запустить или перевести в planned в обход публикации нельзя
+        let err = start_experiment(app_state.clone(), web::Path::from(id.clone()), "tester".to_string()).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+        let status = web::Json(UpdateStatusRequest { status: "planned".to_string() });
+        let err = update_experiment_status(app_state.clone(), web::Path::from(id.clone()), status, "tester".to_string())
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        let publish = || publish_experiment(app_state.clone(), web::Path::from(id.clone()), "tester".to_string());
+        let err = publish().await.unwrap_err();
+        assert!(matches!(err, ApiError::ValidationError(_)), "{:?}", err);
+
+        let update: UpdateExperimentRequest = serde_json::from_value(serde_json::json!({
+            "end_date": "2030-01-10T11:00:00Z",
+        })).unwrap();
+        update_experiment(app_state.clone(), web::Path::from(id.clone()), web::Json(update), "tester".to_string())
+            .await.unwrap();
+
+        // Остатка не хватает - публикация откатывается целиком
+        assert!(publish().await.is_err());
+        assert_eq!(reserved().await, 0.0);
+
+        sqlx::query("UPDATE experiment_reagents SET planned_quantity = 40 WHERE experiment_id = ?")
+            .bind(&id).execute(&pool).await.unwrap();
+        publish().await.unwrap();
+        assert_eq!(reserved().await, 40.0);
+        let status: String = sqlx::query_scalar("SELECT status FROM experiments WHERE id = ?")
+            .bind(&id).fetch_one(&pool).await.unwrap();
+        assert_eq!(status, "planned");
+        assert!(matches!(publish().await.unwrap_err(), ApiError::BadRequest(_)));
+    }
+
+    #[actix_web::test]
+    async fn test_team_members_share_experiment_ownership() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('member', 'member', 'member@example.com', 'x', 'researcher', datetime('now'), datetime('now'))",
+            "INSERT INTO teams (id, name, created_at, updated_at) \
+             VALUES ('t1', 'Analytical Chemistry', datetime('now'), datetime('now'))",
+            "INSERT INTO team_members (team_id, user_id, role, created_at) VALUES ('t1', 'member', 'member', datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let unknown_team: CreateExperimentRequest = serde_json::from_value(serde_json::json!({
+            "title": "Assay", "experiment_type": "research", "status": "draft", "team_id": "missing",
+        })).unwrap();
+        let err = create_experiment(app_state.clone(), web::Json(unknown_team), "tester".to_string()).await.unwrap_err();
+        assert!(matches!(err, ApiError::ValidationError(_)), "{:?}", err);
+
+        let draft: CreateExperimentRequest = serde_json::from_value(serde_json::json!({
+            "title": "Assay", "experiment_type": "research", "status": "draft", "team_id": "t1",
+        })).unwrap();
+        let response = create_experiment(app_state.clone(), web::Json(draft), "tester".to_string()).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(created["data"]["team_name"], "Analytical Chemistry");
+        let id = created["data"]["id"].as_str().unwrap().to_string();
+
+        // Участник команды управляет чужим черновиком, посторонний его не видит
+        ensure_can_manage_experiment(&pool, &id, "member", false).await.unwrap();
+        let err = ensure_can_manage_experiment(&pool, &id, "outsider", false).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        let response = get_experiment(app_state.clone(), web::Path::from(id.clone()), viewer("member")).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let detail: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(detail["data"]["team_id"], "t1");
+
+        let list = |user: &'static str, filters: serde_json::Value| {
+            let app_state = app_state.clone();
+            async move {
+                let query: ExperimentQuery = serde_json::from_value(filters).unwrap();
+                let response = get_all_experiments(app_state, web::Query(query), viewer(user)).await.unwrap();
+                let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+                let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                json["data"]["total"].as_i64().unwrap()
+            }
+        };
+        assert_eq!(list("member", serde_json::json!({})).await, 3);
+        assert_eq!(list("outsider", serde_json::json!({})).await, 2);
+        assert_eq!(list("member", serde_json::json!({ "mine": true })).await, 1);
+        assert_eq!(list("tester", serde_json::json!({ "mine": true })).await, 3);
+        assert_eq!(list("outsider", serde_json::json!({ "team_id": "t1" })).await, 0);
+
+        // Пустая строка снимает команду - участник теряет права
+        let update: UpdateExperimentRequest = serde_json::from_value(serde_json::json!({ "team_id": "" })).unwrap();
+        update_experiment(app_state.clone(), web::Path::from(id.clone()), web::Json(update), "tester".to_string())
+            .await.unwrap();
+        assert!(ensure_can_manage_experiment(&pool, &id, "member", false).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_clone_copies_fields_and_available_reagent_lines() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Ethanol', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity, unit, \
+             received_date, status, created_at, updated_at) VALUES \
+             ('b1', 'r1', 'LOT-1', 10, 10, 8, 'mL', datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b2', 'r1', 'LOT-2', 10, 10, 0, 'mL', datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b3', 'r1', 'LOT-3', 10, 10, 2, 'mL', datetime('now'), 'available', datetime('now'), datetime('now'))",
+            "UPDATE experiments SET description = 'Acid-base', protocol = 'Step 1', student_group = 'G-1', \
+             end_date = '2024-01-10 11:00:00' WHERE id = 'e1'",
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, notes, \
+             created_at, updated_at) VALUES \
+             ('l1', 'e1', 'r1', 'b1', 4, 'mL', NULL, '2024-01-01', '2024-01-01'), \
+             ('l2', 'e1', 'r1', 'b3', 5, 'mL', 'indicator', '2024-01-02', '2024-01-02'), \
+             ('l3', 'e1', 'r1', 'b2', 3, 'mL', NULL, '2024-01-03', '2024-01-03')",
+            // Выбывшая партия
+            "UPDATE batches SET deleted_at = datetime('now') WHERE id = 'b2'",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let overrides: CloneExperimentRequest = serde_json::from_value(serde_json::json!({
+            "start_date": "2030-02-01T09:00:00Z",
+        })).unwrap();
+        let response = clone_experiment(app_state.clone(), web::Path::from("e1".to_string()), Some(web::Json(overrides)), viewer("tester"))
+            .await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let clone = &json["data"]["experiment"];
+        assert_eq!(clone["title"], "Titration (copy)");
+        assert_eq!((clone["status"].as_str(), clone["cloned_from"].as_str()), (Some("draft"), Some("e1")));
+        assert_eq!((clone["protocol"].as_str(), clone["student_group"].as_str()), (Some("Step 1"), Some("G-1")));
+        // Длительность источника сохраняется
+        assert_eq!(clone["end_date"], "2030-02-01T11:00:00Z");
+
+        // В b1 свободно 2 mL из нужных 4, b2 удалена
+        assert_eq!(json["data"]["reagents_copied"], 1);
+        let warnings = json["data"]["warnings"].as_array().unwrap();
+        let skipped: Vec<&str> = warnings.iter().map(|w| w["batch_number"].as_str().unwrap()).collect();
+        assert_eq!(skipped, vec!["LOT-1", "LOT-2"]);
+        assert!(warnings[0]["reason"].as_str().unwrap().contains("Insufficient quantity"));
+
+        let id = clone["id"].as_str().unwrap();
+        let lines: Vec<(String, f64, Option<String>)> = sqlx::query_as(
+            "SELECT batch_id, planned_quantity, notes FROM experiment_reagents WHERE experiment_id = ?"
+        ).bind(id).fetch_all(&pool).await.unwrap();
+        assert_eq!(lines, vec![("b3".to_string(), 5.0, Some("indicator".to_string()))]);
+        // Резерв появится при публикации черновика
+        let reserved: f64 = sqlx::query_scalar("SELECT reserved_quantity FROM batches WHERE id = 'b3'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(reserved, 2.0);
+
+        // Чужой черновик не копируется; несуществующее помещение отклоняется
+        let err = clone_experiment(app_state.clone(), web::Path::from(id.to_string()), None, viewer("someone"))
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        let overrides = CloneExperimentRequest { room_id: Some("missing".to_string()), ..Default::default() };
+        let err = clone_experiment(app_state.clone(), web::Path::from("e2".to_string()), Some(web::Json(overrides)), viewer("tester"))
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::ValidationError(_)), "{:?}", err);
+    }
+
+    #[actix_web::test]
+    async fn test_document_download_escapes_name_and_rejects_traversal() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("stored.pdf"), b"%PDF-1.4").unwrap();
+        for (id, filename, original) in [
+            ("d1", "stored.pdf", "отчёт \"финал\"\r\nX-Injected: 1.pdf"),
+            ("d2", "../outside-secret.txt", "secret.txt"),
+            ("d3", "..\\outside-secret.txt", "secret.txt"),
+        ] {
+            sqlx::query("INSERT INTO experiment_documents (id, experiment_id, filename, original_name) VALUES (?, 'e1', ?, ?)")
+                .bind(id).bind(filename).bind(original)
+                .execute(&pool).await.unwrap();
+        }
+
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        let response = serve_experiment_document(&pool, dir.path(), "e1", "d1", &req).await.unwrap();
+        let header = response.headers().get(actix_web::http::header::CONTENT_DISPOSITION).unwrap().to_str().unwrap();
+        assert!(header.starts_with("attachment; filename=\"_____ _____X-Injected 1.pdf\""), "{}", header);
+        assert!(header.contains("filename*=UTF-8''%D0%BE%D1%82%D1%87%D1%91%D1%82%20"), "{}", header);
+
+        for id in ["d2", "d3"] {
+            let err = serve_experiment_document(&pool, dir.path(), "e1", id, &req).await.unwrap_err();
+            assert!(matches!(err, ApiError::NotFound(_)), "{:?}", err);
+        }
+        // Документ другого эксперимента не отдаётся
+        let err = serve_experiment_document(&pool, dir.path(), "e2", "d1", &req).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+    }
+
+    #[actix_web::test]
+    async fn test_document_routes_list_and_download() {
+        use crate::test_support::fixtures;
+        let app = TestApp::new().await;
+        sqlx::query(
+            "INSERT INTO experiment_documents (id, experiment_id, filename, original_name) \
+             VALUES ('doc-1', ?, 'missing-on-disk.pdf', 'protocol.pdf')"
+        ).bind(fixtures::EXPERIMENT_ID).execute(&app.pool).await.unwrap();
+
+        let (status, body) = app.get(UserRole::Viewer, &format!("/experiments/{}/documents", fixtures::EXPERIMENT_ID)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"][0]["original_name"], "protocol.pdf");
+
+        let (status, _) = app.get(UserRole::Viewer, &format!("/experiments/{}/documents/doc-1", fixtures::EXPERIMENT_ID)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    async fn list_json(app_state: &web::Data<Arc<AppState>>, query: &str) -> serde_json::Value {
+        let query = web::Query::<ExperimentQuery>::from_query(query).unwrap();
+        let response = get_all_experiments(app_state.clone(), query, viewer("tester")).await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_list_counts_in_single_query() {
+        let app = TestApp::new().await;
+        let app_state = app.state();
+        let pool = app.pool.clone();
+        for sql in [
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, \
+             created_by, created_at, updated_at) \
+             VALUES ('e1', 'Titration', '2024-01-10 09:00:00', '2024-01-10 09:00:00', 'planned', 'research', \
+             'fx-admin', datetime('now'), datetime('now')), \
+             ('e2', 'Chromatography', '2024-03-05 09:00:00', '2024-03-05 09:00:00', 'planned', 'research', \
+             'fx-admin', datetime('now'), datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, \
+             created_by, created_at, updated_at) \
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 5000) \
+             SELECT printf('bulk-%04d', i), 'Zeta run ' || i, datetime('2023-01-01', '+' || i || ' hours'), \
+             datetime('2023-01-01'), 'planned', 'research', 'fx-admin', datetime('now'), datetime('now') FROM n",
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Methanol', 'active', datetime('now'), datetime('now')), \
+             ('r2', 'Acetone', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, planned_quantity, unit, created_at, updated_at) \
+             VALUES ('l1', 'e2', 'r1', 1, 'mL', datetime('now'), datetime('now')), \
+             ('l2', 'e2', 'r2', 1, 'mL', datetime('now'), datetime('now'))",
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at) \
+             VALUES ('eq1', 'Burette', 'glassware', 1, 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO experiment_equipment (id, experiment_id, equipment_id) VALUES ('x1', 'e2', 'eq1')",
+            "INSERT INTO experiment_documents (id, experiment_id, filename, original_name) \
+             VALUES ('d1', 'e2', 'a.pdf', 'a.pdf'), ('d2', 'e2', 'b.pdf', 'b.pdf'), ('d3', 'e1', 'c.pdf', 'c.pdf')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        app.statements.take();
+        let json = list_json(&app_state, "per_page=100&sort_by=title&sort_order=asc").await;
+        let items = json["data"]["data"].as_array().unwrap();
+        assert_eq!(items.len(), 100);
+        assert_eq!(json["data"]["total"], 5003);
+        for item in items {
+            assert!(item["reagent_count"].is_i64() && item["equipment_count"].is_i64() && item["document_count"].is_i64());
+        }
+        let e2 = items.iter().find(|e| e["id"] == "e2").unwrap();
+        assert_eq!((&e2["reagent_count"], &e2["equipment_count"], &e2["document_count"]), (&2.into(), &1.into(), &2.into()));
+        let e1 = items.iter().find(|e| e["id"] == "e1").unwrap();
+        assert_eq!((&e1["reagent_count"], &e1["document_count"]), (&0.into(), &1.into()));
+
+        // Страница целиком - один SELECT по experiments (плюс COUNT для total), без запросов на строку
+        let statements = app.statements.take();
+        let list_selects: Vec<_> = statements.iter().filter(|sql| sql.contains("FROM experiments")).collect();
+        assert_eq!(list_selects.len(), 2, "{:#?}", statements);
+        assert_eq!(statements.iter().filter(|sql| sql.contains("reagent_count")).count(), 1, "{:#?}", statements);
+        assert!(statements.iter().all(|sql| !sql.contains("WHERE experiment_id = ?")), "{:#?}", statements);
+
+        // Без счётчиков - поля отсутствуют
+        let json = list_json(&app_state, "per_page=5&include_counts=false").await;
+        let items = json["data"]["data"].as_array().unwrap();
+        assert_eq!(items.len(), 5);
+        assert!(items.iter().all(|e| e.get("reagent_count").is_none() && e.get("document_count").is_none()));
+
+        // Подзапросы счётчиков идут по индексам experiment_id, без полного просмотра связанных таблиц
+        let plan: Vec<String> = sqlx::query(&format!(
+            "EXPLAIN QUERY PLAN SELECT experiments.*, {} FROM experiments", EXPERIMENT_COUNT_COLUMNS.join(", ")
+        ))
+            .fetch_all(&pool).await.unwrap()
+            .iter()
+            .map(|row| sqlx::Row::get::<String, _>(row, "detail"))
+            .collect();
+        for table in ["er", "ee", "ed"] {
+            assert!(plan.iter().any(|d| d.starts_with(&format!("SEARCH {} USING", table))), "{:#?}", plan);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_forbidden_only_for_visible_experiments() {
+        let app = TestApp::new().await;
+
+        let (status, body) = app.post(UserRole::Admin, "/experiments", json!({ "title": "Secret", "experiment_type": "research", "status": "draft" })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        let draft = format!("/experiments/{}", body["data"]["id"].as_str().unwrap());
+        let (status, body) = app.post(UserRole::Admin, "/experiments", json!({ "title": "Shared", "experiment_type": "research" })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        let shared = format!("/experiments/{}", body["data"]["id"].as_str().unwrap());
+
+        // Чужой черновик для исследователя не существует, видимый чужой эксперимент - 403
+        let (status, _) = app.get(UserRole::Researcher, &draft).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = app.put(UserRole::Researcher, &draft, json!({ "notes": "x" })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = app.post(UserRole::Researcher, &format!("{}/publish", draft), json!({})).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = app.put(UserRole::Researcher, &shared, json!({ "notes": "x" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, body) = app.get(UserRole::Admin, &draft).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["status"], "draft");
+    }
 }
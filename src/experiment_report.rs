@@ -0,0 +1,930 @@
+// src/experiment_report.rs
+//! Аудиторский PDF по одному эксперименту: GET /experiments/{id}/report.pdf
+//!
+//! Титульная страница с метаданными, затем разделы: протокол (тот же `render_protocol`, что и
+//! /protocol/rendered; HTML раскладывается на абзацы), участники, реагенты с партией, лотом и
+//! фактически списанным количеством, оборудование, результаты, приложенные документы и история
+//! статусов (experiment_status_history). Текст переносится по словам и продолжается на
+//! следующей странице, ничего не обрезается; "Page N of M" проставляется после вёрстки.
+//! Незавершённый эксперимент получает водяной знак DRAFT (`?watermark=false` - без него).
+//!
+//! Шрифт - `experiment_report.font_path` или первый найденный системный TTF с кириллицей;
+//! без него встроенный Helvetica, и символы вне Latin-1 заменяются на "?".
+//! Вёрстка идёт в очереди export (spawn_blocking).
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use printpdf::{
+    Color, Greyscale, IndirectFontRef, Line, Mm, PdfDocument, PdfDocumentReference,
+    PdfLayerReference, Point, TextMatrix,
+};
+use regex::Regex;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::ExperimentReportConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::models::{Experiment, ExperimentParticipant};
+use crate::AppState;
+
+/// Системные шрифты с кириллицей, которые ищутся, если font_path не задан
+const SYSTEM_FONT_CANDIDATES: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/liberation/LiberationSans-Regular.ttf",
+];
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+/// Нижняя граница текста; ниже - колонтитул с номером страницы
+const CONTENT_BOTTOM: f32 = 22.0;
+const FOOTER_Y: f32 = 12.0;
+/// Ширина колонки подписей в блоках "поле: значение"
+const LABEL_WIDTH: f32 = 45.0;
+const PT_PER_MM: f32 = 72.0 / 25.4;
+
+const BODY_SIZE: f32 = 10.0;
+const HEADING_SIZE: f32 = 13.0;
+const TITLE_SIZE: f32 = 20.0;
+const FOOTER_SIZE: f32 = 8.0;
+const WATERMARK_SIZE: f32 = 110.0;
+const WATERMARK_TEXT: &str = "DRAFT";
+
+lazy_static::lazy_static! {
+    static ref HTML_TAG: Regex = Regex::new(r"<(/?)([a-zA-Z][a-zA-Z0-9]*)[^>]*>").unwrap();
+}
+
+// ==================== DATA ====================
+
+#[derive(Debug, sqlx::FromRow)]
+struct ReagentLine {
+    reagent_name: String,
+    batch_number: String,
+    lot_number: Option<String>,
+    planned_quantity: f64,
+    /// None - строка ещё не списана
+    consumed_quantity: Option<f64>,
+    unit: String,
+    consumed_at: Option<DateTime<Utc>>,
+    consumed_by: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct EquipmentLine {
+    name: String,
+    equipment_type: String,
+    serial_number: Option<String>,
+    quantity_used: i64,
+    notes: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct DocumentLine {
+    original_name: String,
+    mime_type: String,
+    size: i64,
+    uploaded_by: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct StatusChange {
+    from_status: Option<String>,
+    to_status: String,
+    changed_by: Option<String>,
+    changed_at: DateTime<Utc>,
+    backfilled: bool,
+}
+
+/// Блок отрисованного протокола
+#[derive(Debug, Clone, PartialEq)]
+enum ProtocolBlock {
+    Heading(String),
+    Paragraph(String),
+    Bullet(String),
+}
+
+/// Всё содержимое отчёта; собирается до вёрстки, чтобы вёрстка шла без обращений к БД
+pub struct ExperimentReport {
+    experiment: Experiment,
+    created_by: Option<String>,
+    room: Option<String>,
+    protocol: Vec<ProtocolBlock>,
+    dangling_references: usize,
+    participants: Vec<ExperimentParticipant>,
+    reagents: Vec<ReagentLine>,
+    equipment: Vec<EquipmentLine>,
+    documents: Vec<DocumentLine>,
+    status_history: Vec<StatusChange>,
+    generated_by: String,
+    generated_at: DateTime<Utc>,
+}
+
+impl ExperimentReport {
+    /// Водяной знак ставится на всё, что ещё может измениться
+    pub fn is_draft(&self) -> bool {
+        self.experiment.status != "completed"
+    }
+}
+
+pub async fn collect_report(
+    pool: &SqlitePool,
+    experiment: Experiment,
+    generated_by: &str,
+) -> ApiResult<ExperimentReport> {
+    let experiment_id = experiment.id.clone();
+    let created_by: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+        .bind(&experiment.created_by)
+        .fetch_optional(pool)
+        .await?;
+    let room: Option<String> = match experiment.room_id.as_deref() {
+        Some(room_id) => sqlx::query_scalar("SELECT name FROM rooms WHERE id = ?")
+            .bind(room_id)
+            .fetch_optional(pool)
+            .await?,
+        None => None,
+    };
+
+    let protocol_source = experiment.protocol.clone().unwrap_or_default();
+    let (protocol, dangling_references) = if protocol_source.trim().is_empty() {
+        (Vec::new(), 0)
+    } else {
+        let (rendered, _) = crate::protocol_render::render_protocol(pool, &experiment_id, &protocol_source).await?;
+        (protocol_blocks(&rendered.html), rendered.dangling_references)
+    };
+
+    let participants = crate::experiment_handlers::fetch_participants(pool, &experiment_id).await?;
+
+    let reagents: Vec<ReagentLine> = sqlx::query_as(
+        r#"SELECT r.name AS reagent_name, COALESCE(er.lot_batch_number, b.batch_number, '-') AS batch_number,
+                  b.lot_number, er.planned_quantity,
+                  CASE WHEN er.is_consumed = 1 THEN COALESCE(er.actual_quantity, er.planned_quantity) END AS consumed_quantity,
+                  er.unit, er.consumed_at, COALESCE(u.username, er.consumed_by) AS consumed_by
+           FROM experiment_reagents er
+           JOIN reagents r ON r.id = er.reagent_id
+           LEFT JOIN batches b ON b.id = er.batch_id
+           LEFT JOIN users u ON u.id = er.consumed_by
+           WHERE er.experiment_id = ?
+           ORDER BY er.created_at, r.name"#
+    )
+        .bind(&experiment_id)
+        .fetch_all(pool)
+        .await?;
+
+    let equipment: Vec<EquipmentLine> = sqlx::query_as(
+        r#"SELECT e.name, e.type_ AS equipment_type, e.serial_number, ee.quantity_used, ee.notes
+           FROM experiment_equipment ee
+           JOIN equipment e ON e.id = ee.equipment_id
+           WHERE ee.experiment_id = ?
+           ORDER BY e.name"#
+    )
+        .bind(&experiment_id)
+        .fetch_all(pool)
+        .await?;
+
+    let documents: Vec<DocumentLine> = sqlx::query_as(
+        r#"SELECT d.original_name, d.mime_type, d.size, COALESCE(u.username, d.uploaded_by) AS uploaded_by, d.created_at
+           FROM experiment_documents d
+           LEFT JOIN users u ON u.id = d.uploaded_by
+           WHERE d.experiment_id = ?
+           ORDER BY d.created_at, d.original_name"#
+    )
+        .bind(&experiment_id)
+        .fetch_all(pool)
+        .await?;
+
+    let status_history: Vec<StatusChange> = sqlx::query_as(
+        r#"SELECT h.from_status, h.to_status, COALESCE(u.username, h.changed_by) AS changed_by,
+                  h.changed_at, h.backfilled
+           FROM experiment_status_history h
+           LEFT JOIN users u ON u.id = h.changed_by
+           WHERE h.experiment_id = ?
+           ORDER BY h.changed_at, h.rowid"#
+    )
+        .bind(&experiment_id)
+        .fetch_all(pool)
+        .await?;
+
+    let generated_by: Option<String> = sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+        .bind(generated_by)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(ExperimentReport {
+        experiment,
+        created_by,
+        room,
+        protocol,
+        dangling_references,
+        participants,
+        reagents,
+        equipment,
+        documents,
+        status_history,
+        generated_by: generated_by.unwrap_or_else(|| "unknown".to_string()),
+        generated_at: Utc::now(),
+    })
+}
+
+/// Отрисованный протокол (санитизированный HTML) -> заголовки, абзацы и пункты списков.
+/// Ячейки таблиц идут в строку через " | ", <pre> сохраняет переводы строк
+fn protocol_blocks(html: &str) -> Vec<ProtocolBlock> {
+    fn flush(blocks: &mut Vec<ProtocolBlock>, current: &mut String, kind: fn(String) -> ProtocolBlock) {
+        let text = current.lines().map(str::trim_end).collect::<Vec<_>>().join("\n");
+        let text = text.trim();
+        if !text.is_empty() {
+            blocks.push(kind(text.to_string()));
+        }
+        current.clear();
+    }
+
+    let mut blocks = Vec::new();
+    let mut current = String::new();
+    let mut kind: fn(String) -> ProtocolBlock = ProtocolBlock::Paragraph;
+    let mut preformatted = false;
+    let mut last = 0;
+
+    for tag in HTML_TAG.captures_iter(html) {
+        let whole = tag.get(0).unwrap();
+        let text = decode_entities(&html[last..whole.start()]);
+        if preformatted {
+            current.push_str(&text);
+        } else {
+            for word in text.split_whitespace() {
+                if !current.is_empty() && !current.ends_with([' ', '\n']) {
+                    current.push(' ');
+                }
+                current.push_str(word);
+            }
+            if text.ends_with(char::is_whitespace) && !current.is_empty() && !current.ends_with([' ', '\n']) {
+                current.push(' ');
+            }
+        }
+        last = whole.end();
+
+        let closing = &tag[1] == "/";
+        match tag[2].to_ascii_lowercase().as_str() {
+            "br" => current.push('\n'),
+            "td" | "th" if !closing => {
+                let trimmed = current.trim_end().len();
+                current.truncate(trimmed);
+                if !current.is_empty() {
+                    current.push_str(" | ");
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                flush(&mut blocks, &mut current, kind);
+                kind = if closing { ProtocolBlock::Paragraph } else { ProtocolBlock::Heading };
+            }
+            "li" => {
+                flush(&mut blocks, &mut current, kind);
+                kind = if closing { ProtocolBlock::Paragraph } else { ProtocolBlock::Bullet };
+            }
+            "pre" => {
+                flush(&mut blocks, &mut current, kind);
+                preformatted = !closing;
+            }
+            "p" | "blockquote" | "tr" | "table" | "ul" | "ol" | "hr" => flush(&mut blocks, &mut current, kind),
+            _ => {}
+        }
+    }
+    current.push_str(&decode_entities(&html[last..]));
+    flush(&mut blocks, &mut current, kind);
+    blocks
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+// ==================== FONTS ====================
+
+/// Шрифт отчёта: TTF (Unicode) или встроенный Helvetica (WinAnsi)
+pub enum ReportFont {
+    Builtin,
+    /// Жирное начертание - файл `<имя>-Bold.ttf` рядом с основным, если есть
+    Ttf { regular: Vec<u8>, bold: Option<Vec<u8>> },
+}
+
+impl ReportFont {
+    pub fn load(config: &ExperimentReportConfig) -> ApiResult<Self> {
+        let path = match &config.font_path {
+            Some(path) => Some(path.as_str()),
+            None => SYSTEM_FONT_CANDIDATES.iter().copied().find(|p| Path::new(p).is_file()),
+        };
+        let Some(path) = path else {
+            return Ok(ReportFont::Builtin);
+        };
+        let regular = std::fs::read(path)
+            .map_err(|e| ApiError::InternalServerError(format!("Cannot read report font '{}': {}", path, e)))?;
+        let bold = bold_variant_path(path).and_then(|p| std::fs::read(p).ok());
+        Ok(ReportFont::Ttf { regular, bold })
+    }
+}
+
+/// DejaVuSans.ttf -> DejaVuSans-Bold.ttf, LiberationSans-Regular.ttf -> LiberationSans-Bold.ttf
+fn bold_variant_path(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    let stem = path.file_stem()?.to_str()?;
+    let stem = stem.strip_suffix("-Regular").unwrap_or(stem);
+    let extension = path.extension()?.to_str()?;
+    Some(path.with_file_name(format!("{}-Bold.{}", stem, extension)).to_string_lossy().into_owned())
+}
+
+/// Ширины Helvetica (AFM, 1/1000 em) для ASCII 32..=126
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Измерение строк для переноса
+struct Metrics<'a> {
+    regular: Option<ttf_parser::Face<'a>>,
+    bold: Option<ttf_parser::Face<'a>>,
+}
+
+impl<'a> Metrics<'a> {
+    fn new(font: &'a ReportFont) -> Self {
+        match font {
+            ReportFont::Builtin => Metrics { regular: None, bold: None },
+            ReportFont::Ttf { regular, bold } => Metrics {
+                regular: ttf_parser::Face::parse(regular, 0).ok(),
+                bold: bold.as_deref().and_then(|b| ttf_parser::Face::parse(b, 0).ok()),
+            },
+        }
+    }
+
+    /// Ширина в пунктах
+    fn width(&self, text: &str, size: f32, bold: bool) -> f32 {
+        let face = if bold { self.bold.as_ref().or(self.regular.as_ref()) } else { self.regular.as_ref() };
+        let em: f32 = match face {
+            Some(face) => {
+                let units = face.units_per_em() as f32;
+                text.chars()
+                    .map(|ch| {
+                        face.glyph_index(ch)
+                            .and_then(|g| face.glyph_hor_advance(g))
+                            .map_or(0.0, |advance| advance as f32 / units)
+                    })
+                    .sum()
+            }
+            None => {
+                // У Helvetica-Bold глифы в среднем на 5% шире
+                let scale = if bold { 1.05 } else { 1.0 };
+                let widths: f32 = text.chars()
+                    .map(|ch| match ch as u32 {
+                        code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as f32 / 1000.0,
+                        _ => 0.556,
+                    })
+                    .sum();
+                widths * scale
+            }
+        };
+        em * size
+    }
+
+    /// Перенос по словам в пределах width (пункты); слово длиннее строки режется по символам
+    fn wrap(&self, text: &str, size: f32, bold: bool, width: f32) -> Vec<String> {
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+                if self.width(&candidate, size, bold) <= width {
+                    line = candidate;
+                    continue;
+                }
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                for ch in word.chars() {
+                    line.push(ch);
+                    if self.width(&line, size, bold) > width && line.chars().count() > 1 {
+                        line.pop();
+                        lines.push(std::mem::replace(&mut line, ch.to_string()));
+                    }
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+// ==================== LAYOUT ====================
+
+struct Composer<'a> {
+    doc: PdfDocumentReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    builtin: bool,
+    metrics: Metrics<'a>,
+    layers: Vec<PdfLayerReference>,
+    /// Текущая позиция по вертикали, мм от низа страницы
+    y: f32,
+    watermark: bool,
+}
+
+impl<'a> Composer<'a> {
+    fn new(title: &str, font: &'a ReportFont, watermark: bool) -> Result<Self, printpdf::Error> {
+        let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+        let (regular, bold) = match font {
+            ReportFont::Builtin => (
+                doc.add_builtin_font(printpdf::BuiltinFont::Helvetica)?,
+                doc.add_builtin_font(printpdf::BuiltinFont::HelveticaBold)?,
+            ),
+            ReportFont::Ttf { regular, bold } => {
+                let regular_ref = doc.add_external_font(regular.as_slice())?;
+                let bold_ref = match bold {
+                    Some(bold) => doc.add_external_font(bold.as_slice())?,
+                    None => regular_ref.clone(),
+                };
+                (regular_ref, bold_ref)
+            }
+        };
+        let first = doc.get_page(page).get_layer(layer);
+        let mut composer = Composer {
+            doc,
+            regular,
+            bold,
+            builtin: matches!(font, ReportFont::Builtin),
+            metrics: Metrics::new(font),
+            layers: Vec::new(),
+            y: 0.0,
+            watermark,
+        };
+        composer.start_page(first);
+        Ok(composer)
+    }
+
+    fn layer(&self) -> &PdfLayerReference {
+        self.layers.last().unwrap()
+    }
+
+    fn start_page(&mut self, layer: PdfLayerReference) {
+        if self.watermark {
+            // Под текстом: слой рисуется в порядке операций
+            let width = self.metrics.width(WATERMARK_TEXT, WATERMARK_SIZE, true) / PT_PER_MM;
+            let half = width / 2.0 * std::f32::consts::FRAC_1_SQRT_2;
+            layer.save_graphics_state();
+            layer.set_fill_color(Color::Greyscale(Greyscale::new(0.88, None)));
+            layer.begin_text_section();
+            layer.set_font(&self.bold, WATERMARK_SIZE);
+            layer.set_text_matrix(TextMatrix::TranslateRotate(
+                Mm(PAGE_WIDTH / 2.0 - half + 12.0).into(),
+                Mm(PAGE_HEIGHT / 2.0 - half - 12.0).into(),
+                45.0,
+            ));
+            layer.write_text(WATERMARK_TEXT, &self.bold);
+            layer.end_text_section();
+            layer.restore_graphics_state();
+        }
+        self.layers.push(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Content");
+        let layer = self.doc.get_page(page).get_layer(layer);
+        self.start_page(layer);
+    }
+
+    /// Новая страница, если до нижнего поля осталось меньше height мм
+    fn ensure_space(&mut self, height: f32) {
+        if self.y - height < CONTENT_BOTTOM {
+            self.new_page();
+        }
+    }
+
+    fn line_height(size: f32) -> f32 {
+        size * 1.35 / PT_PER_MM
+    }
+
+    /// Встроенный шрифт кодирует WinAnsi: всё вне Latin-1 заменяется
+    fn printable(&self, text: &str) -> String {
+        text.chars()
+            .map(|ch| match ch {
+                '\t' => ' ',
+                ch if ch.is_control() => ' ',
+                ch if self.builtin && ch as u32 > 0xFF => '?',
+                ch => ch,
+            })
+            .collect()
+    }
+
+    fn put(&self, text: &str, size: f32, bold: bool, x: f32, y: f32) {
+        let font = if bold { &self.bold } else { &self.regular };
+        self.layer().use_text(self.printable(text), size, Mm(x), Mm(y), font);
+    }
+
+    fn wrap(&self, text: &str, size: f32, bold: bool, width_mm: f32) -> Vec<String> {
+        self.metrics.wrap(&self.printable(text), size, bold, width_mm * PT_PER_MM)
+    }
+
+    fn text(&mut self, text: &str, size: f32, bold: bool, indent: f32) {
+        let line_height = Self::line_height(size);
+        for line in self.wrap(text, size, bold, PAGE_WIDTH - 2.0 * MARGIN - indent) {
+            self.ensure_space(line_height);
+            self.y -= line_height;
+            self.put(&line, size, bold, MARGIN + indent, self.y);
+        }
+    }
+
+    fn paragraph(&mut self, text: &str) {
+        self.text(text, BODY_SIZE, false, 0.0);
+        self.y -= 1.5;
+    }
+
+    fn bullet(&mut self, text: &str) {
+        let line_height = Self::line_height(BODY_SIZE);
+        self.ensure_space(line_height);
+        self.put("-", BODY_SIZE, false, MARGIN + 2.0, self.y - line_height);
+        self.text(text, BODY_SIZE, false, 6.0);
+        self.y -= 1.0;
+    }
+
+    /// Заголовок раздела с линейкой; не остаётся последней строкой страницы
+    fn heading(&mut self, text: &str, size: f32) {
+        let line_height = Self::line_height(size);
+        self.ensure_space(line_height + 3.0 * Self::line_height(BODY_SIZE) + 4.0);
+        self.y -= 4.0;
+        self.text(text, size, true, 0.0);
+        self.y -= 1.5;
+        self.layer().set_outline_thickness(0.5);
+        self.layer().add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.y)), false),
+                (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(self.y)), false),
+            ],
+            is_closed: false,
+        });
+        self.y -= 3.0;
+    }
+
+    /// Подпись слева, значение с переносом справа
+    fn field(&mut self, label: &str, value: &str) {
+        let line_height = Self::line_height(BODY_SIZE);
+        let lines = self.wrap(value, BODY_SIZE, false, PAGE_WIDTH - 2.0 * MARGIN - LABEL_WIDTH);
+        for (index, line) in lines.iter().enumerate() {
+            self.ensure_space(line_height);
+            self.y -= line_height;
+            if index == 0 {
+                self.put(label, BODY_SIZE, true, MARGIN, self.y);
+            }
+            self.put(line, BODY_SIZE, false, MARGIN + LABEL_WIDTH, self.y);
+        }
+    }
+
+    /// Запись списка: жирная первая строка и строки подробностей с отступом
+    fn entry(&mut self, title: &str, details: &[String]) {
+        let line_height = Self::line_height(BODY_SIZE);
+        self.ensure_space(line_height * (1 + details.len().min(2)) as f32);
+        self.text(title, BODY_SIZE, true, 0.0);
+        for detail in details {
+            self.text(detail, BODY_SIZE, false, 6.0);
+        }
+        self.y -= 2.0;
+    }
+
+    fn none_recorded(&mut self) {
+        self.text("None recorded.", BODY_SIZE, false, 0.0);
+    }
+
+    /// Колонтитулы с номерами страниц (общее число известно только после вёрстки)
+    fn finish(self, footer: &str) -> Result<Vec<u8>, printpdf::Error> {
+        let total = self.layers.len();
+        for (index, layer) in self.layers.iter().enumerate() {
+            let number = format!("Page {} of {}", index + 1, total);
+            let width = self.metrics.width(&number, FOOTER_SIZE, false) / PT_PER_MM;
+            layer.use_text(self.printable(footer), FOOTER_SIZE, Mm(MARGIN), Mm(FOOTER_Y), &self.regular);
+            layer.use_text(number, FOOTER_SIZE, Mm(PAGE_WIDTH - MARGIN - width), Mm(FOOTER_Y), &self.regular);
+        }
+        self.doc.save_to_bytes()
+    }
+}
+
+fn format_time(at: &DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn format_quantity(quantity: f64, unit: &str) -> String {
+    let rounded = (quantity * 1000.0).round() / 1000.0;
+    format!("{} {}", rounded, unit)
+}
+
+fn format_size(bytes: i64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
+
+/// Вёрстка отчёта; синхронная - PdfDocumentReference не Send
+pub fn render_pdf(report: &ExperimentReport, font: &ReportFont, watermark: bool) -> Result<Vec<u8>, printpdf::Error> {
+    let experiment = &report.experiment;
+    let mut pdf = Composer::new(&experiment.title, font, watermark && report.is_draft())?;
+
+    // Титульная страница
+    pdf.y -= 30.0;
+    pdf.text("Experiment report", 12.0, false, 0.0);
+    pdf.y -= 2.0;
+    pdf.text(&experiment.title, TITLE_SIZE, true, 0.0);
+    pdf.y -= 8.0;
+    pdf.field("Experiment ID", &experiment.id);
+    pdf.field("Status", &experiment.status);
+    if let Some(outcome) = &experiment.outcome {
+        pdf.field("Outcome", outcome);
+    }
+    pdf.field("Type", experiment.experiment_type.as_deref().unwrap_or("research"));
+    pdf.field("Experiment date", &format_time(&experiment.experiment_date));
+    pdf.field("Start", &format_time(&experiment.start_date));
+    pdf.field("End", &experiment.end_date.as_ref().map(format_time).unwrap_or_else(|| "-".to_string()));
+    let location = report.room.as_deref().or(experiment.location.as_deref()).unwrap_or("-");
+    pdf.field("Location", location);
+    pdf.field("Instructor", experiment.instructor.as_deref().unwrap_or("-"));
+    pdf.field("Student group", experiment.student_group.as_deref().unwrap_or("-"));
+    pdf.field("Created by", report.created_by.as_deref().unwrap_or(&experiment.created_by));
+    pdf.field("Created", &format_time(&experiment.created_at));
+    pdf.field("Last updated", &format_time(&experiment.updated_at));
+    if let Some(description) = experiment.description.as_deref().filter(|d| !d.trim().is_empty()) {
+        pdf.heading("Description", HEADING_SIZE);
+        pdf.paragraph(description);
+    }
+    pdf.y -= 6.0;
+    pdf.field("Generated", &format!("{} by {}", format_time(&report.generated_at), report.generated_by));
+
+    pdf.new_page();
+    pdf.heading("1. Protocol", HEADING_SIZE);
+    if report.protocol.is_empty() {
+        pdf.none_recorded();
+    }
+    for block in &report.protocol {
+        match block {
+            ProtocolBlock::Heading(text) => {
+                pdf.y -= 2.0;
+                pdf.text(text, 11.0, true, 0.0);
+                pdf.y -= 1.0;
+            }
+            ProtocolBlock::Paragraph(text) => pdf.paragraph(text),
+            ProtocolBlock::Bullet(text) => pdf.bullet(text),
+        }
+    }
+    if report.dangling_references > 0 {
+        pdf.paragraph(&format!(
+            "Note: {} reference(s) in the protocol point to reagents, batches or equipment that are not part of this experiment.",
+            report.dangling_references
+        ));
+    }
+
+    pdf.heading("2. Participants", HEADING_SIZE);
+    if report.participants.is_empty() {
+        pdf.none_recorded();
+    }
+    for participant in &report.participants {
+        let name = participant.username.as_deref().or(participant.name.as_deref()).unwrap_or("-");
+        let mut details = Vec::new();
+        if let Some(email) = &participant.email {
+            details.push(format!("Email: {}", email));
+        }
+        details.push(match &participant.signed_in_at {
+            Some(at) => format!("Signed in: {}", format_time(at)),
+            None => "Not signed in".to_string(),
+        });
+        pdf.entry(&format!("{} ({})", name, participant.role), &details);
+    }
+
+    pdf.heading("3. Reagents", HEADING_SIZE);
+    if report.reagents.is_empty() {
+        pdf.none_recorded();
+    }
+    for line in &report.reagents {
+        let mut title = format!("{} - batch {}", line.reagent_name, line.batch_number);
+        if let Some(lot) = &line.lot_number {
+            title.push_str(&format!(", lot {}", lot));
+        }
+        let mut details = vec![format!("Planned: {}", format_quantity(line.planned_quantity, &line.unit))];
+        details.push(match line.consumed_quantity {
+            Some(consumed) => {
+                let mut text = format!("Consumed: {}", format_quantity(consumed, &line.unit));
+                if let Some(at) = &line.consumed_at {
+                    text.push_str(&format!(" on {}", format_time(at)));
+                }
+                if let Some(by) = &line.consumed_by {
+                    text.push_str(&format!(" by {}", by));
+                }
+                text
+            }
+            None => "Consumed: not yet".to_string(),
+        });
+        pdf.entry(&title, &details);
+    }
+
+    pdf.heading("4. Equipment", HEADING_SIZE);
+    if report.equipment.is_empty() {
+        pdf.none_recorded();
+    }
+    for line in &report.equipment {
+        let mut details = vec![format!("Type: {}, quantity used: {}", line.equipment_type, line.quantity_used)];
+        if let Some(serial) = &line.serial_number {
+            details.push(format!("Serial number: {}", serial));
+        }
+        if let Some(notes) = &line.notes {
+            details.push(notes.clone());
+        }
+        pdf.entry(&line.name, &details);
+    }
+
+    pdf.heading("5. Results", HEADING_SIZE);
+    match experiment.results.as_deref().filter(|r| !r.trim().is_empty()) {
+        Some(results) => pdf.paragraph(results),
+        None => pdf.none_recorded(),
+    }
+    if let Some(notes) = experiment.notes.as_deref().filter(|n| !n.trim().is_empty()) {
+        pdf.y -= 2.0;
+        pdf.text("Notes", BODY_SIZE, true, 0.0);
+        pdf.paragraph(notes);
+    }
+
+    pdf.heading("6. Attached documents", HEADING_SIZE);
+    if report.documents.is_empty() {
+        pdf.none_recorded();
+    }
+    for document in &report.documents {
+        let uploaded = match &document.uploaded_by {
+            Some(by) => format!("Uploaded {} by {}", format_time(&document.created_at), by),
+            None => format!("Uploaded {}", format_time(&document.created_at)),
+        };
+        pdf.entry(&document.original_name, &[
+            format!("{}, {}", document.mime_type, format_size(document.size)),
+            uploaded,
+        ]);
+    }
+
+    pdf.heading("7. Status history", HEADING_SIZE);
+    if report.status_history.is_empty() {
+        pdf.none_recorded();
+    }
+    for change in &report.status_history {
+        let transition = match &change.from_status {
+            Some(from) => format!("{} -> {}", from, change.to_status),
+            None => change.to_status.clone(),
+        };
+        let by = change.changed_by.as_deref().unwrap_or("system");
+        let mut line = format!("{}  {}  by {}", format_time(&change.changed_at), transition, by);
+        if change.backfilled {
+            line.push_str(" (status at the time history tracking began)");
+        }
+        pdf.text(&line, BODY_SIZE, false, 0.0);
+        pdf.y -= 1.0;
+    }
+
+    let footer = format!("{} - {}", experiment.title, experiment.id);
+    pdf.finish(&footer)
+}
+
+// ==================== HANDLER ====================
+
+#[derive(Debug, Deserialize)]
+pub struct ExperimentReportQuery {
+    /// false - без водяного знака DRAFT на незавершённом эксперименте
+    pub watermark: Option<bool>,
+}
+
+/// GET /experiments/{id}/report.pdf
+pub async fn get_experiment_report_pdf(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<ExperimentReportQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let pool = app_state.read_pool();
+    let experiment = crate::experiment_handlers::fetch_visible_experiment(pool, &path.into_inner(), &claims).await?;
+    let filename = format!("experiment_{}_report.pdf", experiment.id);
+    let report = collect_report(pool, experiment, &claims.sub).await?;
+    let font = ReportFont::load(&app_state.config.experiment_report)?;
+    let watermark = query.watermark.unwrap_or(true);
+
+    let (pdf, ticket) = crate::work_queue::export_queue()
+        .run_blocking(move || render_pdf(&report, &font, watermark))
+        .await?;
+    let pdf = pdf.map_err(|e| ApiError::InternalServerError(format!("Failed to render experiment report: {}", e)))?;
+
+    Ok(ticket.annotate(HttpResponse::Ok()
+        .insert_header(("Content-Type", "application/pdf"))
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(pdf)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::{fixtures, TestApp};
+    use actix_web::http::StatusCode;
+    use printpdf::lopdf;
+
+    #[test]
+    fn test_protocol_html_becomes_blocks() {
+        let html = "<h2>Setup</h2>\n<p>Fill the <strong>burette</strong> &amp; zero it.<br>Record volume.</p>\n\
+                    <ul>\n<li>Ethanol</li>\n<li>NaCl</li>\n</ul>\n\
+                    <table><tr><th>Step</th><th>Time</th></tr><tr><td>1</td><td>5 min</td></tr></table>";
+        assert_eq!(protocol_blocks(html), vec![
+            ProtocolBlock::Heading("Setup".to_string()),
+            ProtocolBlock::Paragraph("Fill the burette & zero it.\nRecord volume.".to_string()),
+            ProtocolBlock::Bullet("Ethanol".to_string()),
+            ProtocolBlock::Bullet("NaCl".to_string()),
+            ProtocolBlock::Paragraph("Step | Time".to_string()),
+            ProtocolBlock::Paragraph("1 | 5 min".to_string()),
+        ]);
+    }
+
+    fn page_texts(pdf: &[u8]) -> Vec<String> {
+        let doc = lopdf::Document::load_mem(pdf).unwrap();
+        doc.get_pages().values()
+            .map(|&id| String::from_utf8_lossy(&doc.get_page_content(id).unwrap()).into_owned())
+            .collect()
+    }
+
+    /// Строка в том виде, в каком printpdf пишет её встроенным шрифтом (hex WinAnsi)
+    fn hex(text: &str) -> String {
+        text.bytes().map(|b| format!("{:02X}", b)).collect()
+    }
+
+    #[actix_web::test]
+    async fn test_long_report_paginates_with_numbers_and_draft_watermark() {
+        let app = TestApp::new().await;
+        let protocol = (1..=12).map(|i| format!("Step {}: {}", i, "add the titrant slowly and swirl the flask. ".repeat(3)))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        sqlx::query("UPDATE experiments SET protocol = ?, results = ? WHERE id = ?")
+            .bind(&protocol[..protocol.len().min(2000)])
+            .bind("Endpoint reached at 24.6 ml. ".repeat(150))
+            .bind(fixtures::EXPERIMENT_ID)
+            .execute(&app.pool).await.unwrap();
+        for i in 0..40 {
+            sqlx::query(
+                "INSERT INTO experiment_participants (id, experiment_id, name, role, created_at) \
+                 VALUES (?, 'fx-experiment-titration', ?, 'student', datetime('now'))"
+            )
+                .bind(format!("p-{}", i))
+                .bind(format!("Student {:02}", i))
+                .execute(&app.pool).await.unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, actual_quantity, unit, \
+             is_consumed, consumed_at, consumed_by, created_at, updated_at) VALUES \
+             ('er-1', 'fx-experiment-titration', 'fx-reagent-ethanol', 'fx-batch-ethanol-1', 25.0, 23.5, 'ml', 1, \
+              datetime('now'), 'fx-researcher', datetime('now'), datetime('now'))"
+        ).execute(&app.pool).await.unwrap();
+        sqlx::query("UPDATE experiments SET status = 'in_progress', updated_by = 'fx-admin' WHERE id = ?")
+            .bind(fixtures::EXPERIMENT_ID)
+            .execute(&app.pool).await.unwrap();
+
+        let experiment: Experiment = sqlx::query_as("SELECT * FROM experiments WHERE id = ?")
+            .bind(fixtures::EXPERIMENT_ID)
+            .fetch_one(&app.pool).await.unwrap();
+        let report = collect_report(&app.pool, experiment, fixtures::VIEWER_ID).await.unwrap();
+        assert_eq!(report.status_history.iter().map(|c| c.to_status.as_str()).collect::<Vec<_>>(), ["planned", "in_progress"]);
+        assert_eq!(report.status_history[1].changed_by.as_deref(), Some("fx_admin"));
+        assert_eq!(report.reagents[0].consumed_quantity, Some(23.5));
+
+        let pages = page_texts(&render_pdf(&report, &ReportFont::Builtin, true).unwrap());
+        assert!(pages.len() >= 4, "expected the long report to span several pages, got {}", pages.len());
+        let last = format!("Page {} of {}", pages.len(), pages.len());
+        assert!(pages.iter().all(|p| p.contains(&hex(WATERMARK_TEXT))));
+        assert!(pages.last().unwrap().contains(&hex(&last)));
+        assert!(pages.iter().all(|p| p.contains(&hex("Titration - fx-experiment-titration"))));
+
+        let pages = page_texts(&render_pdf(&report, &ReportFont::Builtin, false).unwrap());
+        assert!(pages.iter().all(|p| !p.contains(&hex(WATERMARK_TEXT))));
+    }
+
+    #[actix_web::test]
+    async fn test_report_pdf_endpoint() {
+        let app = TestApp::new().await;
+        let path = format!("/experiments/{}/report.pdf", fixtures::EXPERIMENT_ID);
+        let (status, body) = app.get(UserRole::Viewer, &path).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.as_str().unwrap().starts_with("%PDF-"));
+
+        let (status, _) = app.get(UserRole::Viewer, "/experiments/missing/report.pdf").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,594 @@
+// src/export_archive.rs
+//! Архив экспорта: GET /export/archive?entities=reagents,batches&format=csv|xlsx
+//!
+//! Один zip с файлом на каждую сущность (данные берутся теми же функциями, что и
+//! отдельные /…/export) и manifest.json. Архив отдаётся потоком: zip пишется в буфер,
+//! который отправляется клиенту частями, поэтому целиком архив в памяти не собирается.
+//! Сущности без прав на чтение не ломают архив, а попадают в `omitted` манифеста.
+
+use actix_web::http::{header, Method};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::io::{Cursor, Write};
+use std::rc::Rc;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use zip::write::{SimpleFileOptions, StreamWriter};
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::access_control::authorize_route;
+use crate::error::{ApiError, ApiResult};
+use crate::import_export;
+use crate::AppState;
+
+/// Накопленные байты архива отправляются клиенту частями не меньше этого размера
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// ==================== PARAMETERS ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveEntity {
+    Reagents,
+    Batches,
+    Equipment,
+    Experiments,
+    /// Списания реагентов в экспериментах с реквизитами партии на момент списания
+    ExperimentConsumptions,
+}
+
+impl ArchiveEntity {
+    pub const ALL: [ArchiveEntity; 5] = [
+        ArchiveEntity::Reagents,
+        ArchiveEntity::Batches,
+        ArchiveEntity::Equipment,
+        ArchiveEntity::Experiments,
+        ArchiveEntity::ExperimentConsumptions,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArchiveEntity::Reagents => "reagents",
+            ArchiveEntity::Batches => "batches",
+            ArchiveEntity::Equipment => "equipment",
+            ArchiveEntity::Experiments => "experiments",
+            ArchiveEntity::ExperimentConsumptions => "experiment_consumptions",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| value.trim().eq_ignore_ascii_case(e.as_str()))
+    }
+
+    /// Маршрут, права на который нужны, чтобы сущность попала в архив
+    fn source_route(&self) -> &'static str {
+        match self {
+            ArchiveEntity::Reagents => "/reagents/export",
+            ArchiveEntity::Batches => "/batches/export",
+            ArchiveEntity::Equipment => "/equipment/export",
+            ArchiveEntity::Experiments | ArchiveEntity::ExperimentConsumptions => "/experiments",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    Csv,
+    Xlsx,
+}
+
+impl ArchiveFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Csv => "csv",
+            ArchiveFormat::Xlsx => "xlsx",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "csv" => Some(ArchiveFormat::Csv),
+            "xlsx" => Some(ArchiveFormat::Xlsx),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveQuery {
+    /// Список через запятую; по умолчанию все сущности
+    pub entities: Option<String>,
+    /// csv (по умолчанию) или xlsx
+    pub format: Option<String>,
+}
+
+fn parse_archive_query(query: &ArchiveQuery) -> ApiResult<(Vec<ArchiveEntity>, ArchiveFormat)> {
+    let format = match query.format.as_deref() {
+        None => ArchiveFormat::Csv,
+        Some(value) => ArchiveFormat::parse(value)
+            .ok_or_else(|| ApiError::bad_request("Invalid format. Must be one of: csv, xlsx"))?,
+    };
+
+    let mut entities = Vec::new();
+    match query.entities.as_deref() {
+        None => entities.extend(ArchiveEntity::ALL),
+        Some(list) => {
+            for name in list.split(',').filter(|s| !s.trim().is_empty()) {
+                let entity = ArchiveEntity::parse(name).ok_or_else(|| {
+                    ApiError::bad_request(&format!(
+                        "Unknown entity '{}'. Must be one of: reagents, batches, equipment, experiments, experiment_consumptions",
+                        name.trim()
+                    ))
+                })?;
+                if !entities.contains(&entity) {
+                    entities.push(entity);
+                }
+            }
+        }
+    }
+    if entities.is_empty() {
+        return Err(ApiError::bad_request("At least one entity is required"));
+    }
+
+    Ok((entities, format))
+}
+
+// ==================== MANIFEST ====================
+
+#[derive(Debug, Serialize)]
+pub struct ManifestUser {
+    pub id: String,
+    pub username: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestFilters {
+    pub entities: Vec<ArchiveEntity>,
+    pub format: ArchiveFormat,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ManifestFile {
+    pub entity: ArchiveEntity,
+    pub file: String,
+    pub rows: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OmittedEntity {
+    pub entity: ArchiveEntity,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveManifest {
+    pub generated_at: DateTime<Utc>,
+    pub generated_by: ManifestUser,
+    pub format: ArchiveFormat,
+    pub filters: ManifestFilters,
+    pub files: Vec<ManifestFile>,
+    /// Запрошенные сущности, на чтение которых у пользователя нет прав
+    pub omitted: Vec<OmittedEntity>,
+}
+
+// ==================== STREAMING ====================
+
+type ArchiveChunk = Result<web::Bytes, std::io::Error>;
+
+/// Буфер, в который пишет ZipWriter; содержимое периодически уходит клиенту
+#[derive(Clone, Default)]
+struct ChunkBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for ChunkBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+struct ArchiveSink {
+    buffer: ChunkBuffer,
+    tx: mpsc::Sender<ArchiveChunk>,
+}
+
+impl ArchiveSink {
+    /// Отправляет накопленное, если набралось не меньше `min` байт
+    async fn send(&self, min: usize) -> anyhow::Result<()> {
+        let chunk = {
+            let mut buffer = self.buffer.0.borrow_mut();
+            if buffer.is_empty() || buffer.len() < min {
+                return Ok(());
+            }
+            std::mem::take(&mut *buffer)
+        };
+        self.tx
+            .send(Ok(web::Bytes::from(chunk)))
+            .await
+            .map_err(|_| anyhow::anyhow!("client disconnected"))
+    }
+}
+
+type ArchiveZip = ZipWriter<StreamWriter<ChunkBuffer>>;
+
+/// Строки сущности как JSON-объекты и список колонок в порядке первого появления
+pub(crate) fn to_table<T: Serialize>(rows: Vec<T>) -> anyhow::Result<(Vec<String>, Vec<Value>)> {
+    let values = rows.into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut headers: Vec<String> = Vec::new();
+    for value in &values {
+        if let Value::Object(map) = value {
+            for key in map.keys() {
+                if !headers.contains(key) {
+                    headers.push(key.clone());
+                }
+            }
+        }
+    }
+    Ok((headers, values))
+}
+
+fn cell_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+async fn load_entity(pool: &sqlx::SqlitePool, entity: ArchiveEntity) -> anyhow::Result<(Vec<String>, Vec<Value>)> {
+    let loaded = match entity {
+        ArchiveEntity::Reagents => import_export::load_reagents_export(pool).await.map(to_table),
+        ArchiveEntity::Batches => import_export::load_batches_export(pool).await.map(to_table),
+        ArchiveEntity::Equipment => import_export::load_equipment_export(pool).await.map(to_table),
+        ArchiveEntity::Experiments => import_export::load_experiments_export(pool).await.map(to_table),
+        ArchiveEntity::ExperimentConsumptions => crate::experiment_handlers::fetch_consumptions(pool, None).await
+            .map_err(ApiError::from)
+            .map(to_table),
+    };
+    loaded.map_err(|e| anyhow::anyhow!("Failed to load {}: {}", entity.as_str(), e))?
+}
+
+async fn write_csv(
+    zip: &mut ArchiveZip,
+    sink: &ArchiveSink,
+    headers: &[String],
+    rows: &[Value],
+) -> anyhow::Result<()> {
+    // BOM для корректного отображения UTF-8 в Excel
+    zip.write_all("\u{FEFF}".as_bytes())?;
+    let mut writer = csv::Writer::from_writer(zip);
+    writer.write_record(headers)?;
+    for row in rows {
+        writer.write_record(headers.iter().map(|h| cell_text(row.get(h))))?;
+        sink.send(CHUNK_SIZE).await?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+async fn write_archive(
+    pool: sqlx::SqlitePool,
+    entities: Vec<ArchiveEntity>,
+    mut manifest: ArchiveManifest,
+    sink: ArchiveSink,
+) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new_stream(sink.buffer.clone());
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // xlsx уже сжат, повторно не сжимаем
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    for entity in entities {
+        let (headers, rows) = load_entity(&pool, entity).await?;
+        let file = format!("{}.{}", entity.as_str(), manifest.format.as_str());
+        match manifest.format {
+            ArchiveFormat::Csv => {
+                zip.start_file(file.as_str(), deflated)?;
+                write_csv(&mut zip, &sink, &headers, &rows).await?;
+            }
+            ArchiveFormat::Xlsx => {
+                let workbook = build_xlsx(entity.as_str(), &headers, &rows)?;
+                zip.start_file(file.as_str(), stored)?;
+                for part in workbook.chunks(CHUNK_SIZE) {
+                    zip.write_all(part)?;
+                    sink.send(CHUNK_SIZE).await?;
+                }
+            }
+        }
+        manifest.files.push(ManifestFile { entity, file, rows: rows.len() });
+        sink.send(CHUNK_SIZE).await?;
+    }
+
+    zip.start_file("manifest.json", deflated)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.finish()?;
+    sink.send(0).await
+}
+
+// ==================== XLSX ====================
+
+/// Номер колонки (с 0) в буквенное обозначение Excel: 0 -> A, 26 -> AA
+fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// Экранирование для XML; управляющие символы, недопустимые в XML 1.0, отбрасываются
+fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn xlsx_cell(reference: &str, value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::Number(n)) => format!(r#"<c r="{}"><v>{}</v></c>"#, reference, n),
+        Some(Value::Bool(b)) => format!(r#"<c r="{}" t="b"><v>{}</v></c>"#, reference, u8::from(*b)),
+        other => format!(
+            r#"<c r="{}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+            reference,
+            xml_escape(&cell_text(other))
+        ),
+    }
+}
+
+pub(crate) const XLSX_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+/// Минимальная книга xlsx с одним листом (строки хранятся как inline strings)
+pub(crate) fn build_xlsx(sheet_name: &str, headers: &[String], rows: &[Value]) -> anyhow::Result<Vec<u8>> {
+    let mut sheet = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#,
+    );
+    sheet.push_str(r#"<row r="1">"#);
+    for (col, header) in headers.iter().enumerate() {
+        let value = Value::String(header.clone());
+        sheet.push_str(&xlsx_cell(&format!("{}1", column_name(col)), Some(&value)));
+    }
+    sheet.push_str("</row>");
+    for (index, row) in rows.iter().enumerate() {
+        let line = index + 2;
+        sheet.push_str(&format!(r#"<row r="{}">"#, line));
+        for (col, header) in headers.iter().enumerate() {
+            sheet.push_str(&xlsx_cell(&format!("{}{}", column_name(col), line), row.get(header)));
+        }
+        sheet.push_str("</row>");
+    }
+    sheet.push_str("</sheetData></worksheet>");
+
+    let parts: [(&str, String); 5] = [
+        (
+            "[Content_Types].xml",
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/></Types>"#.to_string(),
+        ),
+        (
+            "_rels/.rels",
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#.to_string(),
+        ),
+        (
+            "xl/workbook.xml",
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="{}" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+                xml_escape(sheet_name)
+            ),
+        ),
+        (
+            "xl/_rels/workbook.xml.rels",
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#.to_string(),
+        ),
+        ("xl/worksheets/sheet1.xml", sheet),
+    ];
+
+    let mut workbook = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in parts {
+        workbook.start_file(name, options)?;
+        workbook.write_all(content.as_bytes())?;
+    }
+    Ok(workbook.finish()?.into_inner())
+}
+
+// ==================== HANDLER ====================
+
+/// GET /export/archive - zip со всеми запрошенными сущностями и manifest.json
+pub async fn export_archive(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ArchiveQuery>,
+    link: web::Query<crate::signed_downloads::DownloadLinkQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let (requested, format) = parse_archive_query(&query)?;
+    if link.wants_link() {
+        let artifact = crate::signed_downloads::DownloadArtifact::Archive { query: query.into_inner() };
+        return crate::signed_downloads::link_response(&app_state, &http_request, &claims.sub, artifact);
+    }
+
+    // Права проверяются так же, как на отдельных эндпоинтах экспорта
+    let mut entities = Vec::new();
+    let mut omitted = Vec::new();
+    for entity in &requested {
+        match authorize_route(&claims, &Method::GET, entity.source_route(), &app_state.db_pool).await {
+            Ok(()) => entities.push(*entity),
+            Err(ApiError::Forbidden(reason)) => omitted.push(OmittedEntity { entity: *entity, reason }),
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Архив собирается в фоне; разрешение очереди держится до конца записи
+    let ticket = crate::work_queue::export_queue().acquire().await?;
+
+    let names = |list: &[ArchiveEntity]| list.iter().map(|e| e.as_str()).collect::<Vec<_>>().join(", ");
+    crate::audit::audit(
+        &app_state.db_pool,
+        &claims.sub,
+        "export",
+        "archive",
+        format.as_str(),
+        &format!("Exported archive ({}): {}", format.as_str(), names(&entities)),
+        &http_request,
+    ).await;
+
+    let now = Utc::now();
+    let manifest = ArchiveManifest {
+        generated_at: now,
+        generated_by: ManifestUser { id: claims.sub.clone(), username: claims.username.clone() },
+        format,
+        filters: ManifestFilters { entities: requested, format },
+        files: Vec::new(),
+        omitted,
+    };
+
+    let (tx, rx) = mpsc::channel::<ArchiveChunk>(4);
+    let sink = ArchiveSink { buffer: ChunkBuffer::default(), tx: tx.clone() };
+    let pool = app_state.read_pool().clone();
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    let filename = format!("lims_export_{}.zip", now.format("%Y%m%d_%H%M%S"));
+    let response = ticket.annotate(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)))
+        .streaming(stream));
+
+    actix_web::rt::spawn(async move {
+        let _ticket = ticket;
+        if let Err(e) = write_archive(pool, entities, manifest, sink).await {
+            log::error!("Export archive failed: {}", e);
+            // Обрываем поток, чтобы клиент не получил «целый» усечённый архив
+            let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+        }
+    });
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Claims, UserRole};
+    use actix_web::{test as actix_test, HttpMessage};
+    use calamine::Reader;
+    use std::io::Read;
+
+    fn request_as(role: UserRole) -> HttpRequest {
+        let req = actix_test::TestRequest::default().to_http_request();
+        req.extensions_mut().insert(Claims {
+            sub: "u1".to_string(),
+            username: "alice".to_string(),
+            email: "alice@lims.local".to_string(),
+            role,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn fetch_archive(
+        app_state: &web::Data<Arc<AppState>>,
+        role: UserRole,
+        entities: Option<&str>,
+        format: Option<&str>,
+    ) -> zip::ZipArchive<Cursor<Vec<u8>>> {
+        let query = ArchiveQuery { entities: entities.map(str::to_string), format: format.map(str::to_string) };
+        let response = export_archive(app_state.clone(), web::Query(query), web::Query(Default::default()), request_as(role))
+            .await.unwrap();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        zip::ZipArchive::new(Cursor::new(body.to_vec())).unwrap()
+    }
+
+    fn read_entry(archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        archive.by_name(name).unwrap().read_to_end(&mut content).unwrap();
+        content
+    }
+
+    #[actix_web::test]
+    async fn test_archive_respects_permissions_and_formats() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u1', 'alice', 'alice@lims.local', 'x', 'viewer', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        for (id, name) in [("r1", "Ethanol"), ("r2", "Sodium \"chloride\", pure")] {
+            sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES (?, ?, datetime('now'), datetime('now'))")
+                .bind(id).bind(name).execute(&pool).await.unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, \
+             created_by, created_at, updated_at) \
+             VALUES ('e1', 'Titration', datetime('now'), datetime('now'), 'planned', 'research', 'u1', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        let app_state = web::Data::new(Arc::new(AppState { db_pool: pool, config: crate::config::Config::default(), read_replica: None, reference_cache: Default::default() }));
+
+        let err = export_archive(
+            app_state.clone(),
+            web::Query(ArchiveQuery { entities: Some("reagents,users".to_string()), format: None }),
+            web::Query(Default::default()),
+            request_as(UserRole::Admin),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        // Viewer может читать эксперименты, но не экспортировать реагенты/партии/оборудование
+        let mut archive = fetch_archive(&app_state, UserRole::Viewer, None, None).await;
+        assert!(archive.by_name("reagents.csv").is_err());
+        let manifest: Value = serde_json::from_slice(&read_entry(&mut archive, "manifest.json")).unwrap();
+        assert_eq!(manifest["generated_by"]["username"], "alice");
+        assert_eq!(manifest["files"].as_array().unwrap().len(), 2);
+        assert_eq!(manifest["files"][0]["file"], "experiments.csv");
+        assert_eq!(manifest["files"][0]["rows"], 1);
+        assert_eq!(manifest["files"][1]["file"], "experiment_consumptions.csv");
+        assert_eq!(manifest["files"][1]["rows"], 0);
+        assert_eq!(manifest["omitted"].as_array().unwrap().len(), 3);
+        let csv_text = String::from_utf8(read_entry(&mut archive, "experiments.csv")).unwrap();
+        assert!(csv_text.contains("Titration"));
+
+        let mut archive = fetch_archive(&app_state, UserRole::Researcher, Some("reagents"), Some("csv")).await;
+        let csv_bytes = read_entry(&mut archive, "reagents.csv");
+        let mut reader = csv::Reader::from_reader(&csv_bytes[3..]);
+        let name_col = reader.headers().unwrap().iter().position(|h| h == "name").unwrap();
+        let names: Vec<String> = reader.records().map(|r| r.unwrap()[name_col].to_string()).collect();
+        assert!(names.contains(&"Sodium \"chloride\", pure".to_string()));
+
+        // xlsx-файлы внутри архива читаются calamine
+        let mut archive = fetch_archive(&app_state, UserRole::Admin, Some("reagents,experiments"), Some("xlsx")).await;
+        let manifest: Value = serde_json::from_slice(&read_entry(&mut archive, "manifest.json")).unwrap();
+        assert!(manifest["omitted"].as_array().unwrap().is_empty());
+        let mut workbook: calamine::Xlsx<_> =
+            calamine::open_workbook_from_rs(Cursor::new(read_entry(&mut archive, "reagents.xlsx"))).unwrap();
+        let range = workbook.worksheet_range("reagents").unwrap();
+        assert_eq!(range.height(), 3);
+        assert!(range.rows().any(|row| row.iter().any(|c| *c == "Sodium \"chloride\", pure")));
+    }
+}
@@ -0,0 +1,335 @@
+// src/favorites_handlers.rs
+//! Избранное пользователя: закреплённые реагенты и оборудование.
+//!
+//! `POST/DELETE /favorites/{entity_type}/{id}` закрепляет и открепляет объект,
+//! `GET /favorites` возвращает закреплённое. Списки `/reagents` и `/equipment`
+//! с `?favorites_first=true` присоединяют user_favorites через LEFT JOIN, ставят
+//! закреплённое в начало и добавляют в строки `is_favorite`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::auth::get_current_user;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Флаг в SELECT списка с `favorites_first` (алиас присоединённой таблицы - `fav`)
+pub const FAVORITE_FLAG_COLUMN: &str = "(fav.entity_id IS NOT NULL) AS is_favorite";
+/// Закреплённые строки первыми
+pub const FAVORITES_FIRST_ORDER: &str = "is_favorite DESC";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FavoriteEntity {
+    Reagent,
+    Equipment,
+}
+
+impl FavoriteEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FavoriteEntity::Reagent => "reagent",
+            FavoriteEntity::Equipment => "equipment",
+        }
+    }
+
+    fn parse(value: &str) -> ApiResult<Self> {
+        match value {
+            "reagent" | "reagents" => Ok(FavoriteEntity::Reagent),
+            "equipment" => Ok(FavoriteEntity::Equipment),
+            other => Err(ApiError::bad_request(&format!(
+                "Unsupported favorite type '{}'. Valid types: reagent, equipment", other
+            ))),
+        }
+    }
+
+    /// Запрос существования: удалённые и ожидающие удаления объекты закрепить нельзя
+    fn exists_sql(&self) -> &'static str {
+        match self {
+            FavoriteEntity::Reagent => "SELECT EXISTS(SELECT 1 FROM reagents WHERE id = ? AND deleted_at IS NULL)",
+            FavoriteEntity::Equipment => "SELECT EXISTS(SELECT 1 FROM equipment WHERE id = ? AND pending_deletion_id IS NULL)",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            FavoriteEntity::Reagent => "Reagent",
+            FavoriteEntity::Equipment => "Equipment",
+        }
+    }
+}
+
+/// LEFT JOIN избранного текущего пользователя к таблице списка: (join, параметры)
+pub fn favorites_join(entity: FavoriteEntity, table: &str, user_id: &str) -> (String, Vec<String>) {
+    (
+        format!(
+            "user_favorites fav ON fav.user_id = ? AND fav.entity_type = '{}' AND fav.entity_id = {}.id",
+            entity.as_str(),
+            table
+        ),
+        vec![user_id.to_string()],
+    )
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Favorite {
+    pub entity_type: String,
+    pub entity_id: String,
+    pub name: String,
+    pub pinned_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FavoritesQuery {
+    pub entity_type: Option<String>,
+}
+
+// ==================== HANDLERS ====================
+
+/// GET /favorites - закреплённое текущим пользователем, последние закреплённые первыми
+pub async fn get_favorites(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<FavoritesQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let entity_type = query.entity_type.as_deref().map(FavoriteEntity::parse).transpose()?;
+
+    let favorites: Vec<Favorite> = sqlx::query_as(
+        r#"SELECT f.entity_type, f.entity_id, COALESCE(r.name, e.name) AS name, f.pinned_at
+           FROM user_favorites f
+           LEFT JOIN reagents r ON f.entity_type = 'reagent' AND r.id = f.entity_id AND r.deleted_at IS NULL
+           LEFT JOIN equipment e ON f.entity_type = 'equipment' AND e.id = f.entity_id AND e.pending_deletion_id IS NULL
+           WHERE f.user_id = ? AND (? IS NULL OR f.entity_type = ?)
+             AND COALESCE(r.id, e.id) IS NOT NULL
+           ORDER BY f.pinned_at DESC"#
+    )
+        .bind(&claims.sub)
+        .bind(entity_type.map(|e| e.as_str()))
+        .bind(entity_type.map(|e| e.as_str()))
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(favorites)))
+}
+
+/// POST /favorites/{entity_type}/{id} - закрепить (повторный вызов ничего не меняет)
+pub async fn add_favorite(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let (entity_type, entity_id) = path.into_inner();
+    let entity = FavoriteEntity::parse(&entity_type)?;
+    let pool = &app_state.db_pool;
+
+    let exists: bool = sqlx::query_scalar(entity.exists_sql())
+        .bind(&entity_id)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found(entity.label()));
+    }
+
+    sqlx::query(
+        "INSERT OR IGNORE INTO user_favorites (user_id, entity_type, entity_id, pinned_at) VALUES (?, ?, ?, ?)"
+    )
+        .bind(&claims.sub)
+        .bind(entity.as_str())
+        .bind(&entity_id)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        serde_json::json!({ "entity_type": entity.as_str(), "entity_id": entity_id, "is_favorite": true }),
+        format!("{} added to favorites", entity.label()),
+    )))
+}
+
+/// DELETE /favorites/{entity_type}/{id} - открепить
+pub async fn remove_favorite(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let (entity_type, entity_id) = path.into_inner();
+    let entity = FavoriteEntity::parse(&entity_type)?;
+
+    let result = sqlx::query("DELETE FROM user_favorites WHERE user_id = ? AND entity_type = ? AND entity_id = ?")
+        .bind(&claims.sub)
+        .bind(entity.as_str())
+        .bind(&entity_id)
+        .execute(&app_state.db_pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Favorite"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        serde_json::json!({ "entity_type": entity.as_str(), "entity_id": entity_id, "is_favorite": false }),
+        format!("{} removed from favorites", entity.label()),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Claims;
+    use crate::UserRole;
+    use actix_web::{test, HttpMessage};
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::SqlitePool;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        for id in ["u-1", "u-2"] {
+            sqlx::query(
+                "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+                 VALUES (?, ?, ?, 'x', 'researcher', datetime('now'), datetime('now'))"
+            )
+                .bind(id).bind(id).bind(format!("{}@example.com", id))
+                .execute(&pool).await.unwrap();
+        }
+        for (id, name) in [("r-1", "Acetone"), ("r-2", "Benzene"), ("r-3", "Chloroform")] {
+            sqlx::query(
+                "INSERT INTO reagents (id, name, status, created_at, updated_at)
+                 VALUES (?, ?, 'active', datetime('now'), datetime('now'))"
+            )
+                .bind(id).bind(name)
+                .execute(&pool).await.unwrap();
+        }
+        for (id, name) in [("e-1", "Balance"), ("e-2", "Centrifuge")] {
+            sqlx::query(
+                "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at)
+                 VALUES (?, ?, 'equipment', 1, 'available', datetime('now'), datetime('now'))"
+            )
+                .bind(id).bind(name)
+                .execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    fn app_state(pool: &SqlitePool) -> web::Data<Arc<AppState>> {
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    fn request_as(user_id: &str) -> HttpRequest {
+        let req = test::TestRequest::post().to_http_request();
+        req.extensions_mut().insert(Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{}@example.com", user_id),
+            role: UserRole::Researcher,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn response_json(response: HttpResponse) -> serde_json::Value {
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn names_and_flags(json: &serde_json::Value) -> Vec<(String, Option<bool>)> {
+        json["data"]["data"].as_array().unwrap()
+            .iter()
+            .map(|row| (row["name"].as_str().unwrap().to_string(), row["is_favorite"].as_bool()))
+            .collect()
+    }
+
+    async fn pin(state: &web::Data<Arc<AppState>>, user: &str, entity_type: &str, id: &str) -> ApiResult<HttpResponse> {
+        add_favorite(
+            state.clone(),
+            web::Path::from((entity_type.to_string(), id.to_string())),
+            request_as(user),
+        ).await
+    }
+
+    #[actix_web::test]
+    async fn test_favorites_sort_first_per_user_and_follow_deletes() {
+        let pool = setup().await;
+        let state = app_state(&pool);
+
+        pin(&state, "u-1", "reagent", "r-3").await.unwrap();
+        pin(&state, "u-1", "reagent", "r-3").await.unwrap();
+        pin(&state, "u-1", "equipment", "e-2").await.unwrap();
+        pin(&state, "u-2", "reagent", "r-2").await.unwrap();
+        assert!(matches!(pin(&state, "u-1", "reagent", "missing").await, Err(ApiError::NotFound(_))));
+        assert!(matches!(pin(&state, "u-1", "room", "r-1").await, Err(ApiError::BadRequest(_))));
+
+        // Закреплённое первым, is_favorite только при favorites_first
+        let query = web::Query::<crate::pagination::HybridPaginationQuery>::from_query(
+            "favorites_first=true&sort_by=name&sort_order=asc"
+        ).unwrap();
+        let json = response_json(
+            crate::reagent_handlers::get_reagents(state.clone(), query, "u-1".to_string()).await.unwrap()
+        ).await;
+        assert_eq!(names_and_flags(&json), vec![
+            ("Chloroform".to_string(), Some(true)),
+            ("Acetone".to_string(), Some(false)),
+            ("Benzene".to_string(), Some(false)),
+        ]);
+
+        let query = web::Query::<crate::pagination::HybridPaginationQuery>::from_query("sort_by=name&sort_order=asc").unwrap();
+        let json = response_json(
+            crate::reagent_handlers::get_reagents(state.clone(), query, "u-1".to_string()).await.unwrap()
+        ).await;
+        assert!(names_and_flags(&json).iter().all(|(_, flag)| flag.is_none()));
+
+        let query = web::Query::<crate::equipment_handlers::EquipmentPaginationQuery>::from_query(
+            "favorites_first=true&sort_by=name&sort_order=asc"
+        ).unwrap();
+        let json = response_json(
+            crate::equipment_handlers::get_equipment(state.clone(), query, "u-1".to_string(), crate::api_version::ApiVersion::LATEST).await.unwrap()
+        ).await;
+        assert_eq!(names_and_flags(&json), vec![
+            ("Centrifuge".to_string(), Some(true)),
+            ("Balance".to_string(), Some(false)),
+        ]);
+
+        let json = response_json(
+            get_favorites(state.clone(), web::Query(FavoritesQuery { entity_type: None }), request_as("u-1")).await.unwrap()
+        ).await;
+        assert_eq!(json["data"].as_array().unwrap().len(), 2);
+
+        // Удаление сущностей чистит избранное
+        sqlx::query("UPDATE reagents SET deleted_at = datetime('now') WHERE id = 'r-3'")
+            .execute(&pool).await.unwrap();
+        crate::equipment_handlers::purge_equipment(&pool, "e-2").await.unwrap();
+        let remaining: Vec<(String, String)> = sqlx::query_as(
+            "SELECT user_id, entity_id FROM user_favorites ORDER BY user_id"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(remaining, vec![("u-2".to_string(), "r-2".to_string())]);
+
+        remove_favorite(
+            state.clone(),
+            web::Path::from(("reagent".to_string(), "r-2".to_string())),
+            request_as("u-2"),
+        ).await.unwrap();
+        assert!(matches!(
+            remove_favorite(
+                state.clone(),
+                web::Path::from(("reagent".to_string(), "r-2".to_string())),
+                request_as("u-2"),
+            ).await,
+            Err(ApiError::NotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,300 @@
+// src/file_blobs.rs
+//! Дедупликация загруженных файлов по SHA-256 содержимого.
+//!
+//! Один и тот же 40-мегабайтный мануал, приложенный к пяти приборам, хранится на диске
+//! одним blob'ом: `file_blobs` (хэш -> путь, размер, число ссылок). Строки вложений
+//! (`equipment_files`, в будущем - файлы реагентов и экспериментов) остаются независимыми:
+//! своё имя, тип, описание и автор, а `content_hash` указывает на общий blob.
+//! Файл удаляется с диска только вместе с последней ссылкой.
+//!
+//! Строки без `content_hash` (загруженные до дедупликации) владеют файлом единолично;
+//! `backfill_equipment_files` при запуске хэширует их и объединяет найденные дубликаты.
+//! GET /admin/storage/dedup - сколько места сэкономлено.
+
+use actix_web::{web, HttpResponse};
+use anyhow::Result;
+use chrono::Utc;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::ApiResult;
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Хэш содержимого в виде hex-строки (как хранится в `content_hash`)
+pub fn hex_digest(hasher: Sha256) -> String {
+    format!("{:x}", hasher.finalize())
+}
+
+/// SHA-256 файла на диске потоком, без чтения целиком в память
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex_digest(hasher))
+}
+
+/// Ссылка на blob в транзакции вставки вложения.
+/// None - такого содержимого ещё нет, blob создан по `file_path` (файл нужно перенести туда);
+/// Some(path) - содержимое уже хранится по `path`, новая копия не нужна.
+pub async fn acquire(
+    conn: &mut SqliteConnection,
+    hash: &str,
+    file_path: &str,
+    size: i64,
+) -> Result<Option<String>, sqlx::Error> {
+    let stored: String = sqlx::query_scalar(
+        r#"INSERT INTO file_blobs (sha256, file_path, file_size, ref_count, created_at)
+           VALUES (?, ?, ?, 1, ?)
+           ON CONFLICT(sha256) DO UPDATE SET ref_count = ref_count + 1
+           RETURNING file_path"#
+    )
+        .bind(hash)
+        .bind(file_path)
+        .bind(size)
+        .bind(Utc::now())
+        .fetch_one(&mut *conn)
+        .await?;
+
+    Ok((stored != file_path).then_some(stored))
+}
+
+/// Снять ссылку вложения на файл. Возвращает путь, который нужно удалить с диска
+/// после коммита: последняя ссылка на blob или файл строки без хэша.
+pub async fn release(
+    conn: &mut SqliteConnection,
+    content_hash: Option<&str>,
+    file_path: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let Some(hash) = content_hash else {
+        return Ok(Some(file_path.to_string()));
+    };
+
+    let remaining: Option<(i64, String)> = sqlx::query_as(
+        "UPDATE file_blobs SET ref_count = ref_count - 1 WHERE sha256 = ? RETURNING ref_count, file_path"
+    )
+        .bind(hash)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    match remaining {
+        Some((count, path)) if count <= 0 => {
+            sqlx::query("DELETE FROM file_blobs WHERE sha256 = ?")
+                .bind(hash)
+                .execute(&mut *conn)
+                .await?;
+            Ok(Some(path))
+        }
+        Some(_) => Ok(None),
+        // Blob потерян (ручная правка БД) - файл строки больше никому не принадлежит
+        None => Ok(Some(file_path.to_string())),
+    }
+}
+
+/// Удаление с диска файлов, освобождённых `release` (после коммита транзакции)
+pub fn remove_files(paths: impl IntoIterator<Item = String>) {
+    for path in paths {
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+// ==================== BACKFILL ====================
+
+/// Хэширование вложений, загруженных до дедупликации, и объединение дубликатов:
+/// строки-дубликаты переводятся на уже хранящийся blob, их копии удаляются с диска.
+/// Файлы, которых нет на диске, остаются без хэша (и удаляются как раньше).
+pub async fn backfill_equipment_files(pool: &SqlitePool) -> Result<()> {
+    let legacy: Vec<(String, String, i64)> = sqlx::query_as(
+        "SELECT id, file_path, file_size FROM equipment_files WHERE content_hash IS NULL ORDER BY created_at, id"
+    )
+        .fetch_all(pool)
+        .await?;
+    if legacy.is_empty() {
+        return Ok(());
+    }
+
+    let (mut hashed, mut merged, mut freed) = (0u64, 0u64, 0i64);
+    for (id, file_path, file_size) in legacy {
+        let hash = match hash_file(Path::new(&file_path)) {
+            Ok(hash) => hash,
+            Err(e) => {
+                log::warn!("Content hash backfill skipped equipment file {} ({}): {}", id, file_path, e);
+                continue;
+            }
+        };
+
+        let mut tx = pool.begin().await?;
+        let existing = acquire(&mut tx, &hash, &file_path, file_size).await?;
+        match &existing {
+            Some(blob_path) => {
+                let stored_filename = Path::new(blob_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                sqlx::query(
+                    "UPDATE equipment_files SET content_hash = ?, file_path = ?, stored_filename = ? WHERE id = ?"
+                )
+                    .bind(&hash)
+                    .bind(blob_path)
+                    .bind(&stored_filename)
+                    .bind(&id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            None => {
+                sqlx::query("UPDATE equipment_files SET content_hash = ? WHERE id = ?")
+                    .bind(&hash)
+                    .bind(&id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+
+        hashed += 1;
+        if existing.is_some() {
+            merged += 1;
+            freed += file_size;
+            remove_files([file_path]);
+        }
+    }
+
+    log::info!(
+        "Content hash backfill: {} equipment file(s) hashed, {} duplicate(s) merged, {} bytes freed",
+        hashed, merged, freed
+    );
+    Ok(())
+}
+
+// ==================== REPORT ====================
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DedupReport {
+    /// Уникальных файлов на диске
+    pub blobs: i64,
+    /// Вложений, ссылающихся на них
+    pub references: i64,
+    /// Фактически занято на диске
+    pub stored_bytes: i64,
+    /// Заняли бы без дедупликации
+    pub referenced_bytes: i64,
+    pub saved_bytes: i64,
+    /// Вложения без хэша (файл не найден при backfill)
+    pub unhashed_files: i64,
+}
+
+pub async fn dedup_report(pool: &SqlitePool) -> ApiResult<DedupReport> {
+    let (blobs, references, stored_bytes, referenced_bytes): (i64, i64, i64, i64) = sqlx::query_as(
+        r#"SELECT COUNT(*), COALESCE(SUM(ref_count), 0),
+                  COALESCE(SUM(file_size), 0), COALESCE(SUM(file_size * ref_count), 0)
+           FROM file_blobs"#
+    )
+        .fetch_one(pool)
+        .await?;
+    let unhashed_files: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM equipment_files WHERE content_hash IS NULL")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(DedupReport {
+        blobs,
+        references,
+        stored_bytes,
+        referenced_bytes,
+        saved_bytes: referenced_bytes - stored_bytes,
+        unhashed_files,
+    })
+}
+
+/// GET /admin/storage/dedup
+pub async fn get_dedup_report(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let report = dedup_report(&app_state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at)
+             VALUES ('eq1', 'HPLC', 'instrument', 1, 'available', datetime('now'), datetime('now')),
+                    ('eq2', 'GC', 'instrument', 1, 'available', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        pool
+    }
+
+    async fn insert_legacy_file(pool: &SqlitePool, id: &str, equipment_id: &str, path: &Path, content: &[u8]) {
+        std::fs::write(path, content).unwrap();
+        sqlx::query(
+            "INSERT INTO equipment_files (id, equipment_id, file_type, original_filename, stored_filename,
+                                          file_path, file_size, mime_type, created_at)
+             VALUES (?, ?, 'manual', 'manual.pdf', ?, ?, ?, 'application/pdf', datetime('now'))"
+        )
+            .bind(id)
+            .bind(equipment_id)
+            .bind(path.file_name().unwrap().to_string_lossy().to_string())
+            .bind(path.to_string_lossy().to_string())
+            .bind(content.len() as i64)
+            .execute(pool).await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_backfill_merges_duplicates_and_release_keeps_shared_blob() {
+        let pool = setup().await;
+        let dir = tempfile::tempdir().unwrap();
+        let manual = vec![b'm'; 1000];
+        insert_legacy_file(&pool, "f1", "eq1", &dir.path().join("a.pdf"), &manual).await;
+        insert_legacy_file(&pool, "f2", "eq2", &dir.path().join("b.pdf"), &manual).await;
+        insert_legacy_file(&pool, "f3", "eq2", &dir.path().join("c.pdf"), b"other").await;
+        sqlx::query(
+            "INSERT INTO equipment_files (id, equipment_id, file_type, original_filename, stored_filename,
+                                          file_path, file_size, mime_type, created_at)
+             VALUES ('f4', 'eq1', 'other', 'gone.txt', 'gone.txt', '/nonexistent/gone.txt', 1, 'text/plain', datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+
+        backfill_equipment_files(&pool).await.unwrap();
+
+        // Дубликат переведён на первый файл, его копия удалена
+        let paths: Vec<(String, String)> = sqlx::query_as("SELECT id, file_path FROM equipment_files WHERE id IN ('f1', 'f2') ORDER BY id")
+            .fetch_all(&pool).await.unwrap();
+        assert_eq!(paths[0].1, paths[1].1);
+        assert!(!dir.path().join("b.pdf").exists());
+        assert_eq!(
+            dedup_report(&pool).await.unwrap(),
+            DedupReport {
+                blobs: 2,
+                references: 3,
+                stored_bytes: 1005,
+                referenced_bytes: 2005,
+                saved_bytes: 1000,
+                unhashed_files: 1,
+            }
+        );
+        // Повторный запуск ничего не меняет
+        backfill_equipment_files(&pool).await.unwrap();
+        assert_eq!(dedup_report(&pool).await.unwrap().references, 3);
+
+        let (hash,): (String,) = sqlx::query_as("SELECT content_hash FROM equipment_files WHERE id = 'f1'")
+            .fetch_one(&pool).await.unwrap();
+        let mut conn = pool.acquire().await.unwrap();
+        // Первая ссылка уходит - файл остаётся для второй
+        assert_eq!(release(&mut conn, Some(&hash), &paths[0].1).await.unwrap(), None);
+        assert_eq!(release(&mut conn, Some(&hash), &paths[1].1).await.unwrap().as_deref(), Some(paths[0].1.as_str()));
+        assert_eq!(release(&mut conn, None, "/legacy/file").await.unwrap().as_deref(), Some("/legacy/file"));
+        let blobs: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM file_blobs").fetch_one(&mut *conn).await.unwrap();
+        assert_eq!(blobs, 1);
+    }
+}
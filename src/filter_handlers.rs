@@ -1,14 +1,16 @@
 // src/filter_handlers.rs
 
 use actix_web::{web, HttpResponse};
-use sqlx::SqlitePool;
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Utc};
+use std::sync::Arc;
 
 use crate::query_builders::{
-    FilterGroup, FieldWhitelist, Filter, FilterItem,
+    FilterGroup, FieldWhitelist, Filter, FilterItem, normalize_sort_order, SqlParam,
 };
 use crate::handlers::PaginatedResponse;
+use crate::pagination::PageGroup;
+use crate::AppState;
 use crate::error::{ApiError, ApiResult};
 use crate::models::{Experiment, Batch};
 
@@ -26,7 +28,8 @@ const BATCH_SORT_FIELDS: &[&str] = &[
 /// Разрешённые поля сортировки для experiments
 const EXPERIMENT_SORT_FIELDS: &[&str] = &[
     "id", "title", "experiment_date", "experiment_type",
-    "instructor", "student_group", "status", "room_id",
+    "instructor", "student_group", "status", "room_id", "outcome",
+    "start_date", "end_date", "room_name", "instructor_username",
     "created_at", "updated_at",
 ];
 
@@ -154,24 +157,38 @@ pub struct AdvancedFilterRequest {
     pub search: Option<String>,
     #[serde(default = "default_page")]
     pub page: i64,
-    #[serde(default = "default_per_page")]
-    pub per_page: i64,
+    /// Не задан - default_per_page группы; больше max_per_page - урезается (`capped: true`)
+    #[serde(default)]
+    pub per_page: Option<i64>,
     pub sort_by: Option<String>,
     #[serde(default = "default_sort_order")]
     pub sort_order: String,
+    /// Строгий режим: неизвестные поля фильтра/сортировки дают 400 (по умолчанию - настройка strict_field_filters)
+    #[serde(default)]
+    pub strict: Option<bool>,
 }
 
 fn default_page() -> i64 { 1 }
-fn default_per_page() -> i64 { 20 }
 fn default_sort_order() -> String { "DESC".to_string() }
 
 // === Фильтрация партий ===
 pub async fn get_batches_filtered(
-    pool: web::Data<SqlitePool>,
+    app_state: web::Data<Arc<AppState>>,
     body: web::Json<AdvancedFilterRequest>,
 ) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let limits = app_state.page_limits(PageGroup::Batches);
+    let per_page = limits.per_page(body.per_page);
     let whitelist = FieldWhitelist::for_batches();
-    let offset = (body.page - 1) * body.per_page;
+    let strict = crate::handlers::strict_fields(body.strict);
+    if strict {
+        if let Some(field) = body.sort_by.as_deref().filter(|f| validate_sort_field(f, BATCH_SORT_FIELDS).is_none()) {
+            return Err(ApiError::BadRequest(format!(
+                "Unknown sort field: {}. Valid fields: {}", field, BATCH_SORT_FIELDS.join(", ")
+            )));
+        }
+    }
+    let offset = (body.page - 1) * per_page;
 
     // Базовый SQL запрос
     let base_sql = r#"
@@ -187,17 +204,22 @@ pub async fn get_batches_filtered(
     "#;
 
     let mut conditions: Vec<String> = vec!["1=1".to_string()];
-    let mut params: Vec<String> = Vec::new();
+    let mut params: Vec<SqlParam> = Vec::new();
 
     // Применяем фильтры через FilterBuilder
     if let Some(ref filters) = body.filters {
         let filter_builder = crate::query_builders::FilterBuilder::new()
-            .with_whitelist(&whitelist);
-        if let Ok((cond, filter_params)) = filter_builder.build_condition(filters) {
-            if !cond.is_empty() {
-                conditions.push(cond);
-                params.extend(filter_params);
+            .with_whitelist(&whitelist)
+            .strict(strict);
+        match filter_builder.build_condition(filters) {
+            Ok((cond, filter_params)) => {
+                if !cond.is_empty() {
+                    conditions.push(cond);
+                    params.extend(filter_params);
+                }
             }
+            Err(e) if strict => return Err(ApiError::BadRequest(e)),
+            Err(_) => {}
         }
     }
 
@@ -210,10 +232,10 @@ pub async fn get_batches_filtered(
                 "(r.name LIKE ? ESCAPE '\\' OR b.batch_number LIKE ? ESCAPE '\\' \
                  OR b.cat_number LIKE ? ESCAPE '\\' OR b.supplier LIKE ? ESCAPE '\\')".to_string()
             );
-            params.push(search_pattern.clone());
-            params.push(search_pattern.clone());
-            params.push(search_pattern.clone());
-            params.push(search_pattern);
+            params.push(search_pattern.clone().into());
+            params.push(search_pattern.clone().into());
+            params.push(search_pattern.clone().into());
+            params.push(search_pattern.into());
         }
     }
 
@@ -221,7 +243,7 @@ pub async fn get_batches_filtered(
     let sort_field = body.sort_by.as_deref()
         .and_then(|f| validate_sort_field(f, BATCH_SORT_FIELDS))
         .unwrap_or("b.created_at");
-    let sort_order = if body.sort_order.to_uppercase() == "ASC" { "ASC" } else { "DESC" };
+    let sort_order = normalize_sort_order(&body.sort_order);
 
     let sql = format!(
         "{} WHERE {} ORDER BY {} {} LIMIT ? OFFSET ?",
@@ -236,14 +258,15 @@ pub async fn get_batches_filtered(
     for param in &params {
         query = query.bind(param);
     }
-    query = query.bind(body.per_page).bind(offset);
+    query = query.bind(per_page).bind(offset);
 
-    let batches_db: Vec<BatchFromDb> = query.fetch_all(pool.get_ref()).await?;
+    let batches_db: Vec<BatchFromDb> = query.fetch_all(pool).await?;
     let batches: Vec<BatchFilterResponse> = batches_db.into_iter().map(Into::into).collect();
 
-    // Подсчёт общего количества
+    // Подсчёт общего количества: по тому же запросу, чтобы были доступны алиасы (days_until_expiry)
     let count_sql = format!(
-        "SELECT COUNT(*) FROM batches b LEFT JOIN reagents r ON b.reagent_id = r.id WHERE {} AND r.deleted_at IS NULL",
+        "SELECT COUNT(*) FROM ({} WHERE {}) AS filtered",
+        base_sql,
         conditions.join(" AND ")
     );
     
@@ -251,40 +274,42 @@ pub async fn get_batches_filtered(
     for param in &params {
         count_query = count_query.bind(param);
     }
-    let total: i64 = count_query.fetch_one(pool.get_ref()).await?;
+    let total: i64 = count_query.fetch_one(pool).await?;
 
-    let total_pages = if body.per_page > 0 { (total + body.per_page - 1) / body.per_page } else { 1 };
+    let total_pages = (total + per_page - 1) / per_page;
 
     Ok(HttpResponse::Ok().json(PaginatedResponse {
         data: batches,
         total,
         page: body.page,
-        per_page: body.per_page,
+        per_page,
         total_pages,
+        limits,
+        capped: limits.is_capped(body.per_page),
     }))
 }
 
 // === Пресеты ===
 pub async fn get_batches_by_preset(
-    pool: web::Data<SqlitePool>,
+    app_state: web::Data<Arc<AppState>>,
     preset: web::Path<String>,
     query: web::Query<crate::handlers::PaginationQuery>,
 ) -> ApiResult<HttpResponse> {
     let filters = match preset.as_str() {
         "low_stock" => FilterGroup::and(vec![
             FilterItem::filter(Filter::lte("quantity", 10.0)),
-            FilterItem::filter(Filter::eq("status", "available")),
+            FilterItem::filter(Filter::eq("b.status", "available")),
         ]),
         "expiring_soon" => FilterGroup::and(vec![
             FilterItem::filter(Filter::between_numbers("days_until_expiry", 0.0, 30.0)),
-            FilterItem::filter(Filter::neq("status", "expired")),
+            FilterItem::filter(Filter::neq("b.status", "expired")),
         ]),
         "expired" => FilterGroup::or(vec![
-            FilterItem::filter(Filter::eq("status", "expired")),
+            FilterItem::filter(Filter::eq("b.status", "expired")),
             FilterItem::filter(Filter::lt("days_until_expiry", 0.0)),
         ]),
         "available" => FilterGroup::and(vec![
-            FilterItem::filter(Filter::eq("status", "available")),
+            FilterItem::filter(Filter::eq("b.status", "available")),
             FilterItem::filter(Filter::gt("quantity", 0.0)),
         ]),
         _ => return Err(ApiError::bad_request("Unknown preset")),
@@ -294,34 +319,95 @@ pub async fn get_batches_by_preset(
         filters: Some(filters),
         search: None,
         page: query.page.unwrap_or(1),
-        per_page: query.per_page.unwrap_or(20),
+        per_page: query.per_page,
         sort_by: query.sort_by.clone(),
         sort_order: query.sort_order.clone().unwrap_or("DESC".to_string()),
+        strict: None,
     };
 
-    get_batches_filtered(pool, web::Json(req)).await
+    get_batches_filtered(app_state, web::Json(req)).await
 }
 
 // === Фильтрация экспериментов ===
+
+/// Эксперименты с названием помещения, логином инструктора и нормализованными датами.
+/// Обёрнуто в подзапрос, чтобы поля фильтра и сортировки не были неоднозначными после JOIN;
+/// datetime()/date() приводят любой сохранённый формат даты к сравнимому тексту.
+const EXPERIMENT_FILTER_SQL: &str = r#"
+    SELECT * FROM (
+        SELECT
+            e.*,
+            rm.name AS room_name,
+            u.username AS instructor_username,
+            date(e.experiment_date) AS experiment_day,
+            datetime(e.start_date) AS start_at,
+            datetime(e.end_date) AS end_at
+        FROM experiments e
+        LEFT JOIN rooms rm ON rm.id = e.room_id
+        LEFT JOIN users u ON u.id = e.instructor_user_id
+    ) AS x
+"#;
+
+/// Поля подзапроса сверх колонок experiments
+const EXPERIMENT_FILTER_FIELDS: &[&str] = &[
+    "room_name", "instructor_username", "experiment_day", "start_at", "end_at",
+];
+
+/// Статусы, при которых эксперимент ещё может начаться или завершиться
+const EXPERIMENT_OPEN_STATUSES: &[&str] = &["planned", "in_progress"];
+
+fn experiment_filter_whitelist() -> FieldWhitelist {
+    FieldWhitelist::for_experiments().with_fields(EXPERIMENT_FILTER_FIELDS)
+}
+
+// === Строка ответа фильтра экспериментов ===
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExperimentFilterRow {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub experiment: Experiment,
+    pub room_name: Option<String>,
+    pub instructor_username: Option<String>,
+}
+
 pub async fn get_experiments_filtered(
-    pool: web::Data<SqlitePool>,
+    app_state: web::Data<Arc<AppState>>,
     body: web::Json<AdvancedFilterRequest>,
+    http_request: actix_web::HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    let whitelist = FieldWhitelist::for_experiments();
-    let offset = (body.page - 1) * body.per_page;
+    let pool = &app_state.db_pool;
+    let limits = app_state.page_limits(PageGroup::Experiments);
+    let per_page = limits.per_page(body.per_page);
+    let whitelist = experiment_filter_whitelist();
+    let strict = crate::handlers::strict_fields(body.strict);
+    if strict {
+        if let Some(field) = body.sort_by.as_deref().filter(|f| validate_sort_field(f, EXPERIMENT_SORT_FIELDS).is_none()) {
+            return Err(ApiError::BadRequest(format!(
+                "Unknown sort field: {}. Valid fields: {}", field, EXPERIMENT_SORT_FIELDS.join(", ")
+            )));
+        }
+    }
+    let offset = (body.page - 1) * per_page;
 
-    let mut conditions: Vec<String> = vec!["1=1".to_string()];
-    let mut params: Vec<String> = Vec::new();
+    // Черновики видны только автору и его командам
+    let viewer = crate::auth::get_current_user(&http_request)?.sub;
+    let mut conditions: Vec<String> = vec![crate::experiment_handlers::DRAFT_VISIBILITY_CONDITION.to_string()];
+    let mut params: Vec<SqlParam> = vec![viewer.clone().into(), viewer.into()];
 
-    // Применяем фильтры
+    // Применяем фильтры через FilterBuilder (вложенные группы AND/OR)
     if let Some(ref filters) = body.filters {
         let filter_builder = crate::query_builders::FilterBuilder::new()
-            .with_whitelist(&whitelist);
-        if let Ok((cond, filter_params)) = filter_builder.build_condition(filters) {
-            if !cond.is_empty() {
-                conditions.push(cond);
-                params.extend(filter_params);
+            .with_whitelist(&whitelist)
+            .strict(strict);
+        match filter_builder.build_condition(filters) {
+            Ok((cond, filter_params)) => {
+                if !cond.is_empty() {
+                    conditions.push(cond);
+                    params.extend(filter_params);
+                }
             }
+            Err(e) if strict => return Err(ApiError::BadRequest(e)),
+            Err(_) => {}
         }
     }
 
@@ -332,12 +418,12 @@ pub async fn get_experiments_filtered(
             let search_pattern = format!("%{}%", escaped);
             conditions.push(
                 "(title LIKE ? ESCAPE '\\' OR description LIKE ? ESCAPE '\\' \
-                 OR instructor LIKE ? ESCAPE '\\' OR student_group LIKE ? ESCAPE '\\')".to_string()
+                 OR instructor LIKE ? ESCAPE '\\' OR student_group LIKE ? ESCAPE '\\' \
+                 OR room_name LIKE ? ESCAPE '\\')".to_string()
             );
-            params.push(search_pattern.clone());
-            params.push(search_pattern.clone());
-            params.push(search_pattern.clone());
-            params.push(search_pattern);
+            for _ in 0..5 {
+                params.push(search_pattern.clone().into());
+            }
         }
     }
 
@@ -345,26 +431,28 @@ pub async fn get_experiments_filtered(
     let sort_field = body.sort_by.as_deref()
         .and_then(|f| validate_sort_field(f, EXPERIMENT_SORT_FIELDS))
         .unwrap_or("created_at");
-    let sort_order = if body.sort_order.to_uppercase() == "ASC" { "ASC" } else { "DESC" };
+    let sort_order = normalize_sort_order(&body.sort_order);
 
     let sql = format!(
-        "SELECT * FROM experiments WHERE {} ORDER BY {} {} LIMIT ? OFFSET ?",
+        "{} WHERE {} ORDER BY {} {} LIMIT ? OFFSET ?",
+        EXPERIMENT_FILTER_SQL,
         conditions.join(" AND "),
         sort_field,
         sort_order
     );
 
-    let mut query = sqlx::query_as::<_, Experiment>(&sql);
+    let mut query = sqlx::query_as::<_, ExperimentFilterRow>(&sql);
     for param in &params {
         query = query.bind(param);
     }
-    query = query.bind(body.per_page).bind(offset);
+    query = query.bind(per_page).bind(offset);
 
-    let experiments: Vec<Experiment> = query.fetch_all(pool.get_ref()).await?;
+    let experiments: Vec<ExperimentFilterRow> = query.fetch_all(pool).await?;
 
-    // Подсчёт
+    // Подсчёт по тому же подзапросу, чтобы были доступны поля из JOIN
     let count_sql = format!(
-        "SELECT COUNT(*) FROM experiments WHERE {}",
+        "SELECT COUNT(*) FROM ({} WHERE {}) AS filtered",
+        EXPERIMENT_FILTER_SQL,
         conditions.join(" AND ")
     );
     
@@ -372,19 +460,120 @@ pub async fn get_experiments_filtered(
     for param in &params {
         count_query = count_query.bind(param);
     }
-    let total: i64 = count_query.fetch_one(pool.get_ref()).await?;
+    let total: i64 = count_query.fetch_one(pool).await?;
 
-    let total_pages = if body.per_page > 0 { (total + body.per_page - 1) / body.per_page } else { 1 };
+    let total_pages = (total + per_page - 1) / per_page;
 
     Ok(HttpResponse::Ok().json(PaginatedResponse {
         data: experiments,
         total,
         page: body.page,
-        per_page: body.per_page,
+        per_page,
         total_pages,
+        limits,
+        capped: limits.is_capped(body.per_page),
     }))
 }
 
+/// Фильтр пресета экспериментов относительно момента `now` (UTC)
+fn experiment_preset(preset: &str, now: DateTime<Utc>) -> ApiResult<FilterGroup> {
+    let today = now.date_naive();
+    let now_text = now.format("%Y-%m-%d %H:%M:%S").to_string();
+    let open_statuses = || FilterGroup::or(
+        EXPERIMENT_OPEN_STATUSES.iter().map(|s| FilterItem::filter(Filter::eq("status", *s))).collect(),
+    );
+
+    let filters = match preset {
+        "today" => FilterGroup::and(vec![
+            FilterItem::filter(Filter::eq("experiment_day", today.to_string())),
+        ]),
+        // Неделя с понедельника по воскресенье
+        "this_week" => {
+            let monday = today - chrono::Duration::days(today.weekday().num_days_from_monday() as i64);
+            let sunday = monday + chrono::Duration::days(6);
+            FilterGroup::and(vec![
+                FilterItem::filter(Filter::between("experiment_day", &monday.to_string(), &sunday.to_string())),
+            ])
+        }
+        // Не завершён, хотя время окончания прошло
+        "overdue" => FilterGroup::and(vec![
+            FilterItem::group(open_statuses()),
+            FilterItem::filter(Filter::lt("end_at", now_text)),
+        ]),
+        // Всё ещё planned, хотя время начала прошло
+        "unstarted" => FilterGroup::and(vec![
+            FilterItem::filter(Filter::eq("status", "planned")),
+            FilterItem::filter(Filter::lt("start_at", now_text)),
+        ]),
+        _ => return Err(ApiError::bad_request("Unknown preset")),
+    };
+    Ok(filters)
+}
+
+// === Пресеты экспериментов ===
+pub async fn get_experiments_by_preset(
+    app_state: web::Data<Arc<AppState>>,
+    preset: web::Path<String>,
+    query: web::Query<crate::handlers::PaginationQuery>,
+    http_request: actix_web::HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let filters = experiment_preset(preset.as_str(), Utc::now())?;
+
+    let req = AdvancedFilterRequest {
+        filters: Some(filters),
+        search: None,
+        page: query.page.unwrap_or(1),
+        per_page: query.per_page,
+        sort_by: query.sort_by.clone(),
+        sort_order: query.sort_order.clone().unwrap_or("DESC".to_string()),
+        strict: None,
+    };
+
+    get_experiments_filtered(app_state, web::Json(req), http_request).await
+}
+
+/// Поля фильтра экспериментов и допустимые операторы (для конструктора фильтров на фронтенде)
+pub async fn get_experiment_filter_fields() -> ApiResult<HttpResponse> {
+    const TEXT_OPERATORS: &[&str] = &["eq", "neq", "like", "not_like", "in", "not_in", "is_null", "is_not_null"];
+    const ENUM_OPERATORS: &[&str] = &["eq", "neq", "in", "not_in", "is_null", "is_not_null"];
+    const DATE_OPERATORS: &[&str] = &["eq", "neq", "lt", "lte", "gt", "gte", "between", "is_null", "is_not_null"];
+
+    let fields: [(&str, &str, &str, Option<&[&str]>); 15] = [
+        ("title", "Title", "text", None),
+        ("description", "Description", "text", None),
+        ("experiment_type", "Experiment Type", "enum", Some(&["educational", "research"])),
+        ("status", "Status", "enum", Some(&["draft", "planned", "in_progress", "completed", "cancelled", "on_hold"])),
+        ("outcome", "Outcome", "enum", Some(&["successful", "partial", "failed", "inconclusive"])),
+        ("instructor", "Instructor", "text", None),
+        ("instructor_username", "Instructor Account", "text", None),
+        ("student_group", "Student Group", "text", None),
+        ("location", "Location", "text", None),
+        ("room_id", "Room ID", "text", None),
+        ("room_name", "Room", "text", None),
+        ("experiment_day", "Experiment Day (YYYY-MM-DD)", "date", None),
+        ("start_at", "Start (YYYY-MM-DD HH:MM:SS, UTC)", "datetime", None),
+        ("end_at", "End (YYYY-MM-DD HH:MM:SS, UTC)", "datetime", None),
+        ("created_at", "Created At", "datetime", None),
+    ];
+
+    let fields: Vec<crate::report_handlers::AvailableField> = fields
+        .into_iter()
+        .map(|(field, label, data_type, values)| crate::report_handlers::AvailableField {
+            field: field.to_string(),
+            label: label.to_string(),
+            data_type: data_type.to_string(),
+            operators: match data_type {
+                "enum" => ENUM_OPERATORS,
+                "date" | "datetime" => DATE_OPERATORS,
+                _ => TEXT_OPERATORS,
+            }.iter().map(|op| op.to_string()).collect(),
+            values: values.map(|v| v.iter().map(|s| s.to_string()).collect()),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(crate::handlers::ApiResponse::success(fields)))
+}
+
 // ==================== ТЕСТЫ БЕЗОПАСНОСТИ ====================
 
 #[cfg(test)]
@@ -424,4 +613,254 @@ mod security_tests {
         assert_eq!(calculate_expiration_status(Some(30)), "expiring_soon");
         assert_eq!(calculate_expiration_status(Some(31)), "ok");
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::SqlitePool;
+
+    fn app_state(pool: SqlitePool) -> web::Data<Arc<AppState>> {
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    async fn seeded_pool() -> SqlitePool {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Acetone', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             expiry_date, received_date, status, created_at, updated_at) VALUES \
+             ('b1', 'r1', 'LOT-SOON', 5, 100, 'mL', datetime('now', '+10 days'), datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b2', 'r1', 'LOT-LATER', 100, 100, 'mL', datetime('now', '+90 days'), datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b3', 'r1', 'LOT-EXPIRED', 10, 100, 'mL', datetime('now', '-5 days'), datetime('now'), 'available', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    async fn batch_numbers(resp: HttpResponse) -> Vec<String> {
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let mut lots: Vec<String> = json["data"].as_array().unwrap().iter()
+            .map(|b| b["batch_number"].as_str().unwrap().to_string())
+            .collect();
+        lots.sort();
+        lots
+    }
+
+    #[actix_web::test]
+    async fn test_numeric_filters_bind_as_numbers() {
+        let state = app_state(seeded_pool().await);
+
+        // days_until_expiry - вычисляемый алиас без affinity: со строковым '30' BETWEEN не находил ничего
+        let resp = get_batches_by_preset(
+            state.clone(),
+            web::Path::from("expiring_soon".to_string()),
+            web::Query::<crate::handlers::PaginationQuery>::from_query("").unwrap(),
+        ).await.unwrap();
+        assert_eq!(batch_numbers(resp).await, vec!["LOT-SOON"]);
+
+        let filters: FilterGroup = serde_json::from_value(serde_json::json!({
+            "logic": "AND",
+            "items": [
+                { "field": "quantity", "operator": "gt", "value": 6 },
+                { "field": "quantity", "operator": "lte", "value": 10.5 },
+                { "field": "days_until_expiry", "operator": "lt", "value": 0 },
+            ]
+        })).unwrap();
+        let resp = get_batches_filtered(state.clone(), web::Json(AdvancedFilterRequest {
+            filters: Some(filters),
+            search: None,
+            page: 1,
+            per_page: Some(20),
+            sort_by: None,
+            sort_order: "DESC".to_string(),
+            strict: None,
+        })).await.unwrap();
+        assert_eq!(batch_numbers(resp).await, vec!["LOT-EXPIRED"]);
+
+        let filters: FilterGroup = serde_json::from_value(serde_json::json!({
+            "logic": "AND",
+            "items": [{ "field": "quantity", "operator": "in", "value": [5, 100] }]
+        })).unwrap();
+        let resp = get_batches_filtered(state, web::Json(AdvancedFilterRequest {
+            filters: Some(filters),
+            search: None,
+            page: 1,
+            per_page: Some(20),
+            sort_by: None,
+            sort_order: "DESC".to_string(),
+            strict: None,
+        })).await.unwrap();
+        assert_eq!(batch_numbers(resp).await, vec!["LOT-LATER", "LOT-SOON"]);
+    }
+
+    #[actix_web::test]
+    async fn test_strict_filter_rejects_unknown_fields() {
+        let state = app_state(seeded_pool().await);
+        let request = |strict, sort_by: Option<&str>| {
+            let filters: FilterGroup = serde_json::from_value(serde_json::json!({
+                "logic": "AND",
+                "items": [{ "field": "quantty", "operator": "gt", "value": 6 }]
+            })).unwrap();
+            web::Json(AdvancedFilterRequest {
+                filters: Some(filters),
+                search: None,
+                page: 1,
+                per_page: Some(20),
+                sort_by: sort_by.map(str::to_string),
+                sort_order: "DESC".to_string(),
+                strict,
+            })
+        };
+
+        // Без strict опечатка молча отбрасывается - возвращаются все партии
+        let resp = get_batches_filtered(state.clone(), request(None, None)).await.unwrap();
+        assert_eq!(batch_numbers(resp).await.len(), 3);
+
+        let err = get_batches_filtered(state.clone(), request(Some(true), None)).await.unwrap_err();
+        assert!(err.to_string().contains("Unknown field(s): quantty"), "{}", err);
+
+        let err = get_batches_filtered(state, request(Some(true), Some("b.expiry"))).await.unwrap_err();
+        assert!(err.to_string().contains("Unknown sort field: b.expiry"), "{}", err);
+    }
+
+
+    fn request_as(user_id: &str) -> actix_web::HttpRequest {
+        use actix_web::HttpMessage;
+        let req = actix_web::test::TestRequest::post().to_http_request();
+        req.extensions_mut().insert(crate::auth::Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{}@example.com", user_id),
+            role: crate::auth::UserRole::Researcher,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn seeded_experiments() -> web::Data<Arc<AppState>> {
+        let pool = seeded_pool().await;
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) VALUES \
+             ('u1', 'teacher', 'teacher@example.com', 'x', 'researcher', datetime('now'), datetime('now')), \
+             ('u2', 'other', 'other@example.com', 'x', 'researcher', datetime('now'), datetime('now'))",
+            "INSERT INTO rooms (id, name, capacity, status, created_at, updated_at) VALUES \
+             ('rm1', 'Lab A', 10, 'available', datetime('now'), datetime('now'))",
+            // Даты в разных форматах (ISO с 'T' и SQLite) - фильтр нормализует их
+            "INSERT INTO experiments (id, title, experiment_date, start_date, end_date, status, room_id, \
+             instructor_user_id, created_by, created_at, updated_at) VALUES \
+             ('e1', 'Today titration', datetime('now'), datetime('now', '+1 hour'), NULL, 'planned', 'rm1', \
+              'u1', 'u1', datetime('now'), datetime('now')), \
+             ('e2', 'Late start', strftime('%Y-%m-%dT%H:%M:%SZ', 'now', '-20 days'), \
+              strftime('%Y-%m-%dT%H:%M:%SZ', 'now', '-20 days'), NULL, 'planned', NULL, \
+              NULL, 'u1', datetime('now'), datetime('now')), \
+             ('e3', 'Overdue run', datetime('now', '-30 days'), datetime('now', '-30 days'), datetime('now', '-1 day'), \
+              'in_progress', 'rm1', NULL, 'u1', datetime('now'), datetime('now')), \
+             ('e4', 'Hidden draft', datetime('now'), datetime('now', '+1 hour'), NULL, 'draft', 'rm1', \
+              NULL, 'u2', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        app_state(pool)
+    }
+
+    async fn experiment_rows(resp: HttpResponse) -> Vec<serde_json::Value> {
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        json["data"].as_array().unwrap().clone()
+    }
+
+    fn titles(rows: &[serde_json::Value]) -> Vec<&str> {
+        rows.iter().map(|r| r["title"].as_str().unwrap()).collect()
+    }
+
+    async fn by_preset(state: &web::Data<Arc<AppState>>, preset: &str) -> Vec<serde_json::Value> {
+        let resp = get_experiments_by_preset(
+            state.clone(),
+            web::Path::from(preset.to_string()),
+            web::Query::<crate::handlers::PaginationQuery>::from_query("sort_by=title&sort_order=ASC").unwrap(),
+            request_as("u1"),
+        ).await.unwrap();
+        experiment_rows(resp).await
+    }
+
+    #[test]
+    fn test_experiment_presets_relative_to_now() {
+        let now = "2024-03-13T10:30:00Z".parse::<DateTime<Utc>>().unwrap();
+        let week = serde_json::to_value(experiment_preset("this_week", now).unwrap()).unwrap();
+        assert_eq!(week["items"][0]["value"], serde_json::json!(["2024-03-11", "2024-03-17"]));
+        let overdue = serde_json::to_value(experiment_preset("overdue", now).unwrap()).unwrap();
+        assert_eq!(overdue["items"][1]["value"], "2024-03-13 10:30:00");
+        assert!(matches!(experiment_preset("someday", now), Err(ApiError::BadRequest(_))));
+    }
+
+    #[actix_web::test]
+    async fn test_experiment_presets_join_room_and_instructor() {
+        let state = seeded_experiments().await;
+
+        let today = by_preset(&state, "today").await;
+        assert_eq!(titles(&today), vec!["Today titration"]);
+        assert_eq!(today[0]["room_name"], "Lab A");
+        assert_eq!(today[0]["instructor_username"], "teacher");
+        assert_eq!(today[0]["status"], "planned");
+
+        assert_eq!(titles(&by_preset(&state, "unstarted").await), vec!["Late start"]);
+        assert_eq!(titles(&by_preset(&state, "overdue").await), vec!["Overdue run"]);
+        assert!(titles(&by_preset(&state, "this_week").await).contains(&"Today titration"));
+    }
+
+    #[actix_web::test]
+    async fn test_experiment_filter_nested_groups_and_join_fields() {
+        let state = seeded_experiments().await;
+        let filters: FilterGroup = serde_json::from_value(serde_json::json!({
+            "logic": "OR",
+            "items": [
+                { "field": "room_name", "operator": "eq", "value": "Lab A" },
+                { "logic": "AND", "items": [
+                    { "field": "status", "operator": "eq", "value": "planned" },
+                    { "field": "room_id", "operator": "is_null", "value": null },
+                ]},
+            ]
+        })).unwrap();
+        let resp = get_experiments_filtered(state.clone(), web::Json(AdvancedFilterRequest {
+            filters: Some(filters),
+            search: None,
+            page: 1,
+            per_page: Some(2),
+            sort_by: Some("room_name".to_string()),
+            sort_order: "ASC".to_string(),
+            strict: None,
+        }), request_as("u1")).await.unwrap();
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        // Черновик чужого автора не попадает, хотя подходит под фильтр
+        assert_eq!(json["total"], 3);
+        assert_eq!(json["total_pages"], 2);
+        assert_eq!(json["data"][0]["title"], "Late start");
+
+        let resp = get_experiments_filtered(state, web::Json(AdvancedFilterRequest {
+            filters: None,
+            search: Some("lab a".to_string()),
+            page: 1,
+            per_page: Some(20),
+            sort_by: Some("title".to_string()),
+            sort_order: "ASC".to_string(),
+            strict: None,
+        }), request_as("u1")).await.unwrap();
+        assert_eq!(titles(&experiment_rows(resp).await), vec!["Overdue run", "Today titration"]);
+    }
 }
\ No newline at end of file
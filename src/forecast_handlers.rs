@@ -0,0 +1,421 @@
+// src/forecast_handlers.rs
+//! Прогноз исчерпания запаса реагентов.
+//!
+//! Средний дневной расход считается за скользящее окно (настройка `forecast_window_days`)
+//! по журналу использования партий и по списаниям реагентов в экспериментах.
+//! Остаток - доступные (не просроченные) партии за вычетом резерва. Все количества
+//! приводятся к основной единице реагента; записи в несовместимых единицах пропускаются.
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::batch_handlers::convert_quantity;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Допустимые границы окна в запросе (как у настройки forecast_window_days)
+const MIN_WINDOW_DAYS: i64 = 7;
+const MAX_WINDOW_DAYS: i64 = 730;
+const DEFAULT_LIST_LIMIT: i64 = 20;
+/// Сколько реагентов с ближайшим исчерпанием показывать на дашборде
+pub const DASHBOARD_FORECAST_LIMIT: usize = 5;
+
+#[derive(Debug, Deserialize)]
+pub struct ForecastQuery {
+    pub window_days: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl ForecastQuery {
+    fn window_days(&self) -> i64 {
+        self.window_days
+            .unwrap_or_else(|| crate::settings::settings().get_i64(crate::settings::FORECAST_WINDOW_DAYS))
+            .clamp(MIN_WINDOW_DAYS, MAX_WINDOW_DAYS)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReagentForecast {
+    pub reagent_id: String,
+    pub reagent_name: String,
+    /// Единица, в которой выражены все количества
+    pub unit: Option<String>,
+    pub window_days: i64,
+    /// Фактическая длина истории внутри окна (для новых реагентов короче окна)
+    pub observed_days: i64,
+    pub consumed_quantity: f64,
+    pub avg_daily_consumption: f64,
+    pub available_quantity: f64,
+    /// `None` - расхода за окно не было, исчерпание не прогнозируется
+    pub days_until_stockout: Option<f64>,
+    pub projected_stockout_date: Option<DateTime<Utc>>,
+    pub earliest_expiry: Option<DateTime<Utc>>,
+    pub procurement_lead_time_days: Option<i64>,
+    /// Ближайшая партия истечёт раньше, чем запас будет израсходован
+    pub waste_risk: bool,
+    /// Запас закончится раньше, чем придёт новая поставка
+    pub reorder_risk: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct ReagentRow {
+    id: String,
+    name: String,
+    primary_unit: Option<String>,
+    procurement_lead_time_days: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+struct BatchStockRow {
+    reagent_id: String,
+    quantity: f64,
+    reserved_quantity: f64,
+    unit: String,
+    status: String,
+    expiry_date: Option<DateTime<Utc>>,
+    received_date: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ConsumptionRow {
+    reagent_id: String,
+    unit: String,
+    quantity: f64,
+}
+
+#[derive(Debug, Default)]
+struct StockSnapshot {
+    unit: Option<String>,
+    available: f64,
+    earliest_expiry: Option<DateTime<Utc>>,
+    first_received: Option<DateTime<Utc>>,
+}
+
+fn round_to(value: f64, digits: i32) -> f64 {
+    let factor = 10f64.powi(digits);
+    (value * factor).round() / factor
+}
+
+fn to_unit(quantity: f64, from: &str, to: Option<&str>) -> Option<f64> {
+    match to {
+        None => Some(quantity),
+        Some(to) => match convert_quantity(quantity, from, to) {
+            Ok(q) => Some(q),
+            Err(e) => {
+                log::debug!("Forecast: skipping {} {} ({})", quantity, from, e);
+                None
+            }
+        },
+    }
+}
+
+/// Проекция по уже собранным величинам (без обращения к БД)
+fn project(
+    reagent: &ReagentRow,
+    stock: &StockSnapshot,
+    consumed: f64,
+    window_days: i64,
+    now: DateTime<Utc>,
+) -> ReagentForecast {
+    let observed_days = stock.first_received
+        .map(|first| (now - first).num_days())
+        .unwrap_or(window_days)
+        .clamp(1, window_days);
+    let avg_daily = consumed / observed_days as f64;
+
+    let days_until_stockout = (avg_daily > 0.0).then(|| stock.available / avg_daily);
+    let projected_stockout_date = days_until_stockout
+        .map(|days| now + Duration::seconds((days * 86_400.0) as i64));
+
+    let waste_risk = stock.available > 0.0
+        && stock.earliest_expiry.is_some_and(|expiry| {
+            projected_stockout_date.is_none_or(|stockout| expiry < stockout)
+        });
+    let reorder_risk = match (days_until_stockout, reagent.procurement_lead_time_days) {
+        (Some(days), Some(lead_time)) => days <= lead_time as f64,
+        _ => false,
+    };
+
+    ReagentForecast {
+        reagent_id: reagent.id.clone(),
+        reagent_name: reagent.name.clone(),
+        unit: stock.unit.clone(),
+        window_days,
+        observed_days,
+        consumed_quantity: round_to(consumed, 3),
+        avg_daily_consumption: round_to(avg_daily, 3),
+        available_quantity: round_to(stock.available, 3),
+        days_until_stockout: days_until_stockout.map(|d| round_to(d, 1)),
+        projected_stockout_date,
+        earliest_expiry: stock.earliest_expiry,
+        procurement_lead_time_days: reagent.procurement_lead_time_days,
+        waste_risk,
+        reorder_risk,
+    }
+}
+
+/// Прогноз для одного реагента (`reagent_id`) или для всех активных реагентов
+pub async fn compute_forecasts(
+    pool: &SqlitePool,
+    reagent_id: Option<&str>,
+    window_days: i64,
+) -> ApiResult<Vec<ReagentForecast>> {
+    let reagents: Vec<ReagentRow> = sqlx::query_as(
+        r#"SELECT id, name, primary_unit, procurement_lead_time_days FROM reagents
+           WHERE deleted_at IS NULL AND (?1 IS NULL AND status = 'active' OR id = ?1)"#
+    )
+        .bind(reagent_id)
+        .fetch_all(pool)
+        .await?;
+
+    let batches: Vec<BatchStockRow> = sqlx::query_as(
+        r#"SELECT b.reagent_id, b.quantity, b.reserved_quantity, b.unit, b.status,
+                  CASE WHEN r.expiry_policy = 'strict' THEN b.expiry_date END AS expiry_date, b.received_date
+           FROM batches b
+           JOIN reagents r ON r.id = b.reagent_id
+           WHERE b.deleted_at IS NULL AND (?1 IS NULL OR b.reagent_id = ?1)
+           ORDER BY b.received_date ASC"#
+    )
+        .bind(reagent_id)
+        .fetch_all(pool)
+        .await?;
+
+    let window = format!("-{} days", window_days);
+    let usage: Vec<ConsumptionRow> = sqlx::query_as(
+        r#"SELECT reagent_id, unit, SUM(quantity_used) AS quantity
+           FROM usage_logs
+           WHERE created_at >= datetime('now', ?1) AND (?2 IS NULL OR reagent_id = ?2)
+           GROUP BY reagent_id, unit"#
+    )
+        .bind(&window)
+        .bind(reagent_id)
+        .fetch_all(pool)
+        .await?;
+
+    // Списания в экспериментах не пишутся в usage_logs - учитываем их отдельно
+    let experiment_usage: Vec<ConsumptionRow> = sqlx::query_as(
+        r#"SELECT er.reagent_id, er.unit, SUM(COALESCE(er.actual_quantity, er.planned_quantity)) AS quantity
+           FROM experiment_reagents er
+           JOIN experiments e ON e.id = er.experiment_id
+           WHERE er.is_consumed = 1
+             AND COALESCE(e.end_date, e.updated_at) >= datetime('now', ?1)
+             AND (?2 IS NULL OR er.reagent_id = ?2)
+           GROUP BY er.reagent_id, er.unit"#
+    )
+        .bind(&window)
+        .bind(reagent_id)
+        .fetch_all(pool)
+        .await?;
+
+    let now = Utc::now();
+    let primary_units: HashMap<&str, Option<&str>> = reagents.iter()
+        .map(|r| (r.id.as_str(), r.primary_unit.as_deref()))
+        .collect();
+
+    let mut stock: HashMap<String, StockSnapshot> = HashMap::new();
+    for batch in &batches {
+        let snapshot = stock.entry(batch.reagent_id.clone()).or_default();
+        if snapshot.unit.is_none() {
+            snapshot.unit = primary_units.get(batch.reagent_id.as_str())
+                .copied()
+                .flatten()
+                .map(str::to_string)
+                .or_else(|| Some(batch.unit.clone()));
+        }
+        snapshot.first_received = Some(snapshot.first_received.map_or(batch.received_date, |d| d.min(batch.received_date)));
+
+        let expired = batch.expiry_date.is_some_and(|d| d <= now);
+        if batch.status != "available" || expired {
+            continue;
+        }
+        let free = (batch.quantity - batch.reserved_quantity).max(0.0);
+        if free <= 0.0 {
+            continue;
+        }
+        if let Some(q) = to_unit(free, &batch.unit, snapshot.unit.as_deref()) {
+            snapshot.available += q;
+        }
+        if let Some(expiry) = batch.expiry_date {
+            snapshot.earliest_expiry = Some(snapshot.earliest_expiry.map_or(expiry, |d| d.min(expiry)));
+        }
+    }
+
+    let mut consumed: HashMap<String, f64> = HashMap::new();
+    for row in usage.iter().chain(experiment_usage.iter()) {
+        let unit = stock.get(&row.reagent_id)
+            .and_then(|s| s.unit.clone())
+            .or_else(|| primary_units.get(row.reagent_id.as_str()).copied().flatten().map(str::to_string));
+        if let Some(q) = to_unit(row.quantity, &row.unit, unit.as_deref()) {
+            *consumed.entry(row.reagent_id.clone()).or_insert(0.0) += q;
+        }
+    }
+
+    Ok(reagents.iter()
+        .map(|r| {
+            let snapshot = stock.remove(&r.id).unwrap_or_else(|| StockSnapshot {
+                unit: r.primary_unit.clone(),
+                ..Default::default()
+            });
+            project(r, &snapshot, consumed.get(&r.id).copied().unwrap_or(0.0), window_days, now)
+        })
+        .collect())
+}
+
+/// Ближайшее исчерпание - первым; реагенты без расхода - в конце
+pub fn sort_by_stockout(forecasts: &mut [ReagentForecast]) {
+    forecasts.sort_by(|a, b| {
+        let key = |f: &ReagentForecast| f.days_until_stockout.unwrap_or(f64::INFINITY);
+        key(a).total_cmp(&key(b)).then_with(|| a.reagent_name.cmp(&b.reagent_name))
+    });
+}
+
+/// Реагенты с ближайшим прогнозируемым исчерпанием (для дашборда)
+pub async fn soonest_stockouts(pool: &SqlitePool, limit: usize) -> ApiResult<Vec<ReagentForecast>> {
+    let window_days = crate::settings::settings().get_i64(crate::settings::FORECAST_WINDOW_DAYS);
+    let mut forecasts = compute_forecasts(pool, None, window_days).await?;
+    forecasts.retain(|f| f.days_until_stockout.is_some());
+    sort_by_stockout(&mut forecasts);
+    forecasts.truncate(limit);
+    Ok(forecasts)
+}
+
+// ==================== HANDLERS ====================
+
+/// GET /reagents/{id}/forecast
+pub async fn get_reagent_forecast(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<ForecastQuery>,
+) -> ApiResult<HttpResponse> {
+    let reagent_id = path.into_inner();
+    let forecast = compute_forecasts(app_state.read_pool(), Some(&reagent_id), query.window_days())
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ApiError::not_found("Reagent"))?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(forecast)))
+}
+
+/// GET /reagents/forecast - активные реагенты, ближайшее исчерпание первым
+pub async fn get_reagent_forecasts(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ForecastQuery>,
+) -> ApiResult<HttpResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, crate::handlers::max_per_page()) as usize;
+    let mut forecasts = compute_forecasts(app_state.read_pool(), None, query.window_days()).await?;
+    sort_by_stockout(&mut forecasts);
+    forecasts.truncate(limit);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(forecasts)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn forecast_pool() -> SqlitePool {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        pool
+    }
+
+    async fn insert_reagent(pool: &SqlitePool, id: &str, name: &str, lead_time: Option<i64>) {
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, procurement_lead_time_days, created_at, updated_at) \
+             VALUES (?, ?, 'active', ?, datetime('now'), datetime('now'))"
+        ).bind(id).bind(name).bind(lead_time).execute(pool).await.unwrap();
+    }
+
+    async fn insert_batch(pool: &SqlitePool, id: &str, reagent_id: &str, quantity: f64, unit: &str, expiry_in_days: Option<i64>) {
+        let expiry = expiry_in_days.map(|d| Utc::now() + Duration::days(d));
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             expiry_date, received_date, status, created_at, updated_at) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, datetime('now', '-200 days'), 'available', datetime('now'), datetime('now'))"
+        ).bind(id).bind(reagent_id).bind(id).bind(quantity).bind(quantity).bind(unit).bind(expiry)
+            .execute(pool).await.unwrap();
+    }
+
+    async fn insert_usage(pool: &SqlitePool, reagent_id: &str, batch_id: &str, quantity: f64, unit: &str, days_ago: i64) {
+        sqlx::query(
+            "INSERT INTO usage_logs (id, reagent_id, batch_id, quantity_used, unit, created_at) \
+             VALUES (?, ?, ?, ?, ?, datetime('now', ?))"
+        ).bind(uuid::Uuid::new_v4().to_string()).bind(reagent_id).bind(batch_id).bind(quantity).bind(unit)
+            .bind(format!("-{} days", days_ago))
+            .execute(pool).await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_forecast_from_usage_and_experiments() {
+        let pool = forecast_pool().await;
+
+        // Ацетонитрил: 900 mL + 0.9 L, расход 300 mL по журналу + 0.3 L в эксперименте за 30 дней
+        insert_reagent(&pool, "acn", "Acetonitrile", Some(30)).await;
+        insert_batch(&pool, "acn-1", "acn", 900.0, "mL", Some(365)).await;
+        insert_batch(&pool, "acn-2", "acn", 0.9, "L", Some(400)).await;
+        insert_usage(&pool, "acn", "acn-1", 200.0, "mL", 5).await;
+        insert_usage(&pool, "acn", "acn-1", 100.0, "mL", 20).await;
+        // За пределами окна - не учитывается
+        insert_usage(&pool, "acn", "acn-1", 5000.0, "mL", 60).await;
+        sqlx::query(
+            "INSERT INTO experiments (id, title, experiment_date, status, end_date, created_at, updated_at) \
+             VALUES ('e1', 'HPLC run', datetime('now', '-3 days'), 'completed', datetime('now', '-2 days'), datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, is_consumed, created_at, updated_at) \
+             VALUES ('er1', 'e1', 'acn', 'acn-2', 0.3, 'L', 1, datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        // Метанол: расход большой, но ближайшая партия истекает раньше исчерпания
+        insert_reagent(&pool, "meoh", "Methanol", None).await;
+        insert_batch(&pool, "meoh-1", "meoh", 3000.0, "mL", Some(10)).await;
+        insert_usage(&pool, "meoh", "meoh-1", 300.0, "mL", 1).await;
+
+        // Без расхода - исчерпание не прогнозируется
+        insert_reagent(&pool, "nacl", "Sodium chloride", Some(7)).await;
+        insert_batch(&pool, "nacl-1", "nacl", 500.0, "g", None).await;
+
+        let forecasts = compute_forecasts(&pool, None, 30).await.unwrap();
+        let by_id: HashMap<_, _> = forecasts.iter().map(|f| (f.reagent_id.as_str(), f)).collect();
+
+        let acn = by_id["acn"];
+        assert_eq!(acn.unit.as_deref(), Some("mL"));
+        assert_eq!(acn.observed_days, 30);
+        assert_eq!(acn.consumed_quantity, 600.0);
+        assert_eq!(acn.avg_daily_consumption, 20.0);
+        assert_eq!(acn.available_quantity, 1800.0);
+        assert_eq!(acn.days_until_stockout, Some(90.0));
+        assert!(!acn.waste_risk);
+        assert!(!acn.reorder_risk);
+
+        let meoh = by_id["meoh"];
+        assert_eq!(meoh.days_until_stockout, Some(300.0));
+        assert!(meoh.waste_risk);
+        assert!(!meoh.reorder_risk);
+
+        let nacl = by_id["nacl"];
+        assert_eq!(nacl.days_until_stockout, None);
+        assert!(!nacl.waste_risk && !nacl.reorder_risk);
+
+        // Срок поставки длиннее остатка запаса - пора заказывать
+        sqlx::query("UPDATE reagents SET procurement_lead_time_days = 120 WHERE id = 'acn'")
+            .execute(&pool).await.unwrap();
+        let single = compute_forecasts(&pool, Some("acn"), 30).await.unwrap();
+        assert_eq!(single.len(), 1);
+        assert!(single[0].reorder_risk);
+
+        let top = soonest_stockouts(&pool, DASHBOARD_FORECAST_LIMIT).await.unwrap();
+        let order: Vec<&str> = top.iter().map(|f| f.reagent_id.as_str()).collect();
+        assert_eq!(order, vec!["acn", "meoh"]);
+    }
+}
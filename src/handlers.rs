@@ -7,10 +7,12 @@ use serde::{Serialize, Deserialize};
 use crate::jwt_rotation::{get_rotation_stats, rotate_jwt_secret};
 use chrono::{DateTime, Utc};
 use crate::AppState;
-use crate::models::{Reagent, Batch};
+use crate::models::{container_state, Reagent, Batch};
 use crate::error::{ApiError, ApiResult, validate_quantity};
 use crate::auth::get_current_user;
 use crate::audit::ChangeSet;
+use crate::report_handlers::escape_like_pattern;
+use crate::pagination::{PageGroup, PageLimits};
 use std::env;
 
 // ==================== COMMON STRUCTURES ====================
@@ -47,6 +49,10 @@ pub struct PaginatedResponse<T> {
     pub page: i64,
     pub per_page: i64,
     pub total_pages: i64,
+    /// Действующие пределы per_page этого списка
+    pub limits: PageLimits,
+    /// per_page запроса превысил max_per_page и был урезан
+    pub capped: bool,
 }
 
 // ==================== ENHANCED PAGINATION STRUCTURES ====================
@@ -117,10 +123,159 @@ pub struct PaginationQuery {
     pub date_to: Option<DateTime<Utc>>,
 }
 
+/// Верхняя граница per_page для JSON-списков по умолчанию
+/// (действующее значение - настройка max_per_page, см. max_per_page())
+pub const MAX_PER_PAGE: i64 = 100;
+
+/// Общая верхняя граница per_page (группа списков может задать свою, см. pagination::page_limits)
+pub fn max_per_page() -> i64 {
+    crate::settings::settings().get_i64(crate::settings::MAX_PER_PAGE).max(1)
+}
+
+/// Предел строк для вложенных списков без пагинации
+/// (файлы и обслуживание оборудования, реагенты эксперимента)
+pub const MAX_NESTED_LIST_ROWS: i64 = 500;
+
+/// Заголовок ответа с вложенным списком, обрезанным до MAX_NESTED_LIST_ROWS
+pub const TRUNCATED_HEADER: &str = "X-Truncated";
+
+/// Вложенный список, выбранный с `LIMIT MAX_NESTED_LIST_ROWS + 1`:
+/// лишняя строка отбрасывается и означает, что список обрезан
+#[derive(Debug)]
+pub struct NestedList<T> {
+    pub rows: Vec<T>,
+    pub truncated: bool,
+}
+
+impl<T> NestedList<T> {
+    pub fn new(mut rows: Vec<T>) -> Self {
+        let truncated = rows.len() as i64 > MAX_NESTED_LIST_ROWS;
+        rows.truncate(MAX_NESTED_LIST_ROWS as usize);
+        Self { rows, truncated }
+    }
+}
+
+impl<T: Serialize> NestedList<T> {
+    /// Ответ со списком; обрезанный список помечается заголовком `X-Truncated: true`
+    pub fn into_response(self) -> HttpResponse {
+        let mut response = HttpResponse::Ok();
+        if self.truncated {
+            response.insert_header((TRUNCATED_HEADER, "true"));
+        }
+        response.json(ApiResponse::success(self.rows))
+    }
+}
+
+/// Строгий режим whitelist для списка: `?strict=` запроса, иначе настройка strict_field_filters
+pub fn strict_fields(requested: Option<bool>) -> bool {
+    requested.unwrap_or_else(|| crate::settings::settings().get_bool(crate::settings::STRICT_FIELD_FILTERS))
+}
+
+// ==================== FIELD SELECTION (?fields=) ====================
+
+/// Разбор `?fields=id,name` по whitelist сущности; None - вернуть все поля.
+/// Неизвестные поля -> 400 со списком допустимых.
+pub fn parse_fields_param(
+    fields: Option<&str>,
+    whitelist: &crate::query_builders::FieldWhitelist,
+) -> ApiResult<Option<Vec<String>>> {
+    match fields {
+        None => Ok(None),
+        Some(raw) => whitelist.parse_selection(raw).map(Some).map_err(ApiError::BadRequest),
+    }
+}
+
+// ==================== MULTI-SELECT FILTERS (?status=a,b) ====================
+
+/// Разбор фильтра с несколькими значениями через запятую (`?status=available,in_use`)
+/// для IN-условия. Пустой список - фильтр не задан.
+/// `accepted` - допустимые значения; неизвестные -> 400 со списком допустимых
+/// (а не пустая или полная выборка). None - значения не ограничены (например, location).
+pub fn parse_multi_filter(
+    raw: Option<&str>,
+    param: &str,
+    accepted: Option<&[&str]>,
+) -> ApiResult<Vec<String>> {
+    let mut values: Vec<String> = Vec::new();
+    let mut unknown: Vec<&str> = Vec::new();
+    for value in raw.unwrap_or("").split(',').map(str::trim).filter(|v| !v.is_empty()) {
+        if accepted.is_some_and(|accepted| !accepted.contains(&value)) {
+            unknown.push(value);
+        } else if !values.iter().any(|v| v == value) {
+            values.push(value.to_string());
+        }
+    }
+    if let Some(accepted) = accepted.filter(|_| !unknown.is_empty()) {
+        return Err(ApiError::BadRequest(format!(
+            "Unknown {} value(s): {}. Accepted values: {}",
+            param,
+            unknown.join(", "),
+            accepted.join(", ")
+        )));
+    }
+    Ok(values)
+}
+
+/// Строка выборки с произвольным набором колонок -> JSON-объект.
+/// Колонки из `datetime_columns` сериализуются так же, как `DateTime<Utc>` в полных ответах.
+pub fn row_to_json(
+    row: &sqlx::sqlite::SqliteRow,
+    datetime_columns: &[&str],
+) -> serde_json::Map<String, serde_json::Value> {
+    use serde_json::Value;
+    use sqlx::{Column, Row, TypeInfo, ValueRef};
+
+    let mut object = serde_json::Map::new();
+    for column in row.columns() {
+        let name = column.name();
+        let index = column.ordinal();
+        let is_null = row.try_get_raw(index).map(|v| v.is_null()).unwrap_or(true);
+
+        let value = if is_null {
+            Value::Null
+        } else if datetime_columns.contains(&name) {
+            match row.try_get::<DateTime<Utc>, _>(index) {
+                Ok(dt) => serde_json::to_value(dt).unwrap_or(Value::Null),
+                Err(_) => row.try_get::<String, _>(index).map(Value::String).unwrap_or(Value::Null),
+            }
+        } else {
+            let type_name = row.try_get_raw(index)
+                .map(|v| v.type_info().name().to_string())
+                .unwrap_or_default();
+            match type_name.as_str() {
+                "INTEGER" => row.try_get::<i64, _>(index).map(Value::from).unwrap_or(Value::Null),
+                "REAL" => row.try_get::<f64, _>(index).map(Value::from).unwrap_or(Value::Null),
+                "TEXT" => row.try_get::<String, _>(index).map(Value::String).unwrap_or(Value::Null),
+                _ => Value::Null,
+            }
+        };
+        object.insert(name.to_string(), value);
+    }
+    object
+}
+
+/// Выполняет запрос с выбранными колонками и возвращает строки как JSON-объекты
+pub async fn fetch_json_rows<P>(
+    pool: &sqlx::SqlitePool,
+    sql: &str,
+    params: &[P],
+    datetime_columns: &[&str],
+) -> ApiResult<Vec<serde_json::Map<String, serde_json::Value>>>
+where
+    P: for<'q> sqlx::Encode<'q, sqlx::Sqlite> + sqlx::Type<sqlx::Sqlite> + Sync,
+{
+    let mut query = sqlx::query(sql);
+    for p in params {
+        query = query.bind(p);
+    }
+    let rows = query.fetch_all(pool).await?;
+    Ok(rows.iter().map(|row| row_to_json(row, datetime_columns)).collect())
+}
+
 impl PaginationQuery {
-    pub fn normalize(&self) -> (i64, i64, i64) {
+    pub fn normalize(&self, limits: PageLimits) -> (i64, i64, i64) {
         let page = self.page.unwrap_or(1).max(1);
-        let per_page = self.per_page.unwrap_or(20).clamp(1, 100);
+        let per_page = limits.per_page(self.per_page);
         let offset = (page - 1) * per_page;
         (page, per_page, offset)
     }
@@ -163,8 +318,16 @@ pub async fn get_reagent_with_batches(
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct UseReagentRequest {
+    /// Можно не указывать при списании целых тар (containers_used)
+    #[serde(default)]
     #[validate(range(min = 0.0, message = "Quantity must be positive"))]
     pub quantity_used: f64,
+    /// Списать целые запечатанные тары: quantity_used = containers_used × pack_size
+    #[validate(range(min = 1, message = "Containers used must be at least 1"))]
+    pub containers_used: Option<i64>,
+    /// Единица quantity_used, если она не единица партии (в т.ч. mol/mmol - через молярную массу).
+    /// В истории сохраняется вместе с исходным количеством
+    pub unit: Option<String>,
     #[validate(length(max = 500, message = "Purpose cannot exceed 500 characters"))]
     pub purpose: Option<String>,
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
@@ -183,73 +346,196 @@ pub struct UsageLog {
     pub notes: Option<String>,
     pub used_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
+    /// Запись перенесена импортом истории расхода
+    #[sqlx(default)]
+    pub imported: bool,
+    /// `consumption` - расход, `adjustment` - корректировка остатка (quantity_used = -delta)
+    pub kind: String,
+    /// Код причины корректировки
+    pub reason: Option<String>,
+    /// Количество в единице ввода, если расход вводился не в единице партии (например, mmol)
+    pub entered_quantity: Option<f64>,
+    pub entered_unit: Option<String>,
 }
 
-pub async fn use_reagent(
-    app_state: web::Data<Arc<AppState>>,
-    path: web::Path<(String, String)>,
-    request: web::Json<UseReagentRequest>,
-    http_request: HttpRequest,
-) -> ApiResult<HttpResponse> {
-    let (reagent_id, batch_id) = path.into_inner();
-    request.validate()?;
-
-    let claims = get_current_user(&http_request)?;
-    validate_quantity(request.quantity_used)?;
-
-    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
-        .bind(&reagent_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::reagent_not_found(&reagent_id))?;
-
-    let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ? AND reagent_id = ?")
-        .bind(&batch_id)
-        .bind(&reagent_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::batch_not_found(&batch_id))?;
+/// Результат списания из партии через общий путь расхода
+#[derive(Debug, Serialize)]
+pub struct BatchUsageOutcome {
+    pub usage_id: String,
+    pub remaining_quantity: f64,
+    pub status: &'static str,
+}
 
+/// Можно ли списать количество из партии (без записи)
+pub(crate) fn check_batch_usage(batch: &Batch, quantity_used: f64) -> ApiResult<()> {
+    if batch.status == "awaiting_coa" {
+        return Err(ApiError::batch_awaiting_coa(&batch.batch_number));
+    }
     if batch.status != "available" {
         return Err(ApiError::BadRequest("Batch is not available for use".to_string()));
     }
 
-    if request.quantity_used > batch.quantity {
-        return Err(ApiError::insufficient_quantity(batch.quantity, request.quantity_used));
+    if quantity_used > batch.quantity {
+        return Err(ApiError::insufficient_quantity(batch.quantity, quantity_used));
     }
+    Ok(())
+}
+
+/// Количество для списания целыми тарами; `quantity_used` при этом можно не указывать
+fn container_usage_quantity(batch: &Batch, quantity_used: f64, containers_used: i64) -> ApiResult<f64> {
+    let (Some(size), Some(state)) = (batch.pack_size, container_state(batch.quantity, batch.pack_size, batch.container_count)) else {
+        return Err(ApiError::bad_request("Batch does not track containers (container_count and pack_size are not set)"));
+    };
+    if containers_used > state.sealed {
+        return Err(ApiError::bad_request(&format!(
+            "Only {} sealed container(s) left, cannot use {}", state.sealed, containers_used
+        )));
+    }
+    let quantity = containers_used as f64 * size;
+    if quantity_used > 0.0 && (quantity_used - quantity).abs() > 1e-9 {
+        return Err(ApiError::bad_request(&format!(
+            "quantity_used {} does not match {} container(s) of {}", quantity_used, containers_used, size
+        )));
+    }
+    Ok(quantity)
+}
+
+/// Общий путь расхода партии: проверка доступности, запись в usage_logs, уменьшение остатка
+/// и перевод в depleted. Выполняется в транзакции вызывающего (use_reagent, расходники при обслуживании,
+/// согласованная заявка на расход). `containers_used` - сколько запечатанных тар списано целиком;
+/// при расходе по объёму запечатанных остаётся не больше, чем помещается в остаток.
+pub(crate) async fn record_batch_usage(
+    conn: &mut sqlx::SqliteConnection,
+    batch: &Batch,
+    user_id: &str,
+    quantity_used: f64,
+    purpose: Option<&str>,
+    notes: Option<&str>,
+    containers_used: Option<i64>,
+) -> ApiResult<BatchUsageOutcome> {
+    check_batch_usage(batch, quantity_used)?;
+    // Партии деактивированного реагента только для чтения
+    crate::reagent_handlers::ensure_reagent_active_by_id(&mut *conn, &batch.reagent_id).await?;
 
     let now = Utc::now();
     let usage_id = Uuid::new_v4().to_string();
-    let mut tx = app_state.db_pool.begin().await?;
 
     sqlx::query(
         r#"INSERT INTO usage_logs (id, reagent_id, batch_id, user_id, quantity_used, unit, purpose, notes, created_at)
            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#
     )
         .bind(&usage_id)
-        .bind(&reagent_id)
-        .bind(&batch_id)
-        .bind(&claims.sub)
-        .bind(request.quantity_used)
+        .bind(&batch.reagent_id)
+        .bind(&batch.id)
+        .bind(user_id)
+        .bind(quantity_used)
         .bind(&batch.unit)
-        .bind(&request.purpose)
-        .bind(&request.notes)
-        .bind(&now)
-        .execute(&mut *tx)
+        .bind(purpose)
+        .bind(notes)
+        .bind(now)
+        .execute(&mut *conn)
         .await?;
 
-    let new_quantity = batch.quantity - request.quantity_used;
+    let new_quantity = (batch.quantity - quantity_used).max(0.0);
     let new_status = if new_quantity <= 0.0 { "depleted" } else { "available" };
+    let container_count = batch.container_count.map(|count| count - containers_used.unwrap_or(0));
+    let container_count = container_state(new_quantity, batch.pack_size, container_count).map(|c| c.sealed);
 
-    sqlx::query("UPDATE batches SET quantity = ?, status = ?, updated_at = ? WHERE id = ?")
-        .bind(new_quantity.max(0.0))
+    sqlx::query("UPDATE batches SET quantity = ?, status = ?, container_count = ?, updated_at = ? WHERE id = ?")
+        .bind(new_quantity)
         .bind(new_status)
-        .bind(&now)
-        .bind(&batch_id)
-        .execute(&mut *tx)
+        .bind(container_count)
+        .bind(now)
+        .bind(&batch.id)
+        .execute(&mut *conn)
         .await?;
 
+    Ok(BatchUsageOutcome { usage_id, remaining_quantity: new_quantity, status: new_status })
+}
+
+pub async fn use_reagent(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    request: web::Json<UseReagentRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let (reagent_id, batch_id) = path.into_inner();
+    request.validate()?;
+
+    let claims = get_current_user(&http_request)?;
+
+    let batch = crate::batch_handlers::fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+        .bind(&reagent_id)
+        .fetch_one(&app_state.db_pool)
+        .await?;
+
+    let entered_unit = request.unit.as_deref().map(str::trim).filter(|u| !u.is_empty() && *u != batch.unit);
+    let quantity_used = match (request.containers_used, entered_unit) {
+        (Some(_), Some(_)) => return Err(ApiError::bad_request("unit cannot be combined with containers_used")),
+        (Some(containers), None) => container_usage_quantity(&batch, request.quantity_used, containers)?,
+        (None, Some(unit)) => crate::batch_handlers::convert_amount(
+            request.quantity_used, unit, &batch.unit, &reagent.name, reagent.molecular_weight,
+        )?,
+        (None, None) => request.quantity_used,
+    };
+    validate_quantity(quantity_used)?;
+
+    // Крупный расход выполняется только после согласования
+    check_batch_usage(&batch, quantity_used)?;
+    if crate::approval_handlers::exceeds_approval_threshold(&reagent, quantity_used, &batch.unit) {
+        let approval = crate::approval_handlers::NewApproval {
+            operation: crate::approval_handlers::ApprovalOperation::BatchUse,
+            reagent_id: &reagent_id,
+            batch_id: &batch_id,
+            experiment_id: None,
+            quantity: quantity_used,
+            unit: &batch.unit,
+            purpose: request.purpose.as_deref(),
+            notes: request.notes.as_deref(),
+            requested_by: &claims.sub,
+        };
+        return crate::approval_handlers::request_approval(&app_state, approval, Some(&http_request)).await;
+    }
+
+    let mut tx = app_state.db_pool.begin().await?;
+    let usage = record_batch_usage(
+        &mut tx,
+        &batch,
+        &claims.sub,
+        quantity_used,
+        request.purpose.as_deref(),
+        request.notes.as_deref(),
+        request.containers_used,
+    ).await?;
+    let (usage_id, new_quantity, new_status) = (usage.usage_id, usage.remaining_quantity, usage.status);
+    if let Some(unit) = entered_unit {
+        sqlx::query("UPDATE usage_logs SET entered_quantity = ?, entered_unit = ? WHERE id = ?")
+            .bind(request.quantity_used)
+            .bind(unit)
+            .bind(&usage_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    crate::outbox::enqueue(
+        &mut *tx,
+        &crate::events::BusinessEvent::BatchConsumed {
+            reagent_id: reagent_id.clone(),
+            batch_id: batch_id.clone(),
+            quantity: quantity_used,
+            unit: batch.unit.clone(),
+            remaining_quantity: new_quantity.max(0.0),
+        },
+        Some(&claims.sub),
+    ).await?;
+
     tx.commit().await?;
+    crate::stock_anomalies::track(&app_state, crate::stock_anomalies::StockOp {
+        operation: crate::stock_anomalies::StockOperation::Use,
+        batch_id: &batch_id,
+        user_id: Some(&claims.sub),
+        quantity: quantity_used,
+    }).await;
 
     // Detailed audit with reagent name, batch number, and quantity change
     let mut cs = ChangeSet::new();
@@ -262,7 +548,7 @@ pub async fn use_reagent(
         &app_state.db_pool, &claims.sub, "use_reagent", "batch", &batch_id,
         &format!(
             "Used {} {} from reagent \"{}\" batch {} (remaining: {} {})",
-            request.quantity_used, batch.unit, reagent.name, batch.batch_number,
+            quantity_used, batch.unit, reagent.name, batch.batch_number,
             new_quantity.max(0.0), batch.unit
         ),
         &cs, &http_request,
@@ -270,7 +556,7 @@ pub async fn use_reagent(
 
     log::info!(
         "User {} used {} {} from reagent \"{}\" batch {} (reagent_id: {}, batch_id: {})",
-        claims.username, request.quantity_used, batch.unit, reagent.name, batch.batch_number, reagent_id, batch_id
+        claims.username, quantity_used, batch.unit, reagent.name, batch.batch_number, reagent_id, batch_id
     );
 
     Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
@@ -283,50 +569,232 @@ pub async fn use_reagent(
     )))
 }
 
+/// Остаток, меньше которого расход по партиям считается выполненным (погрешность пересчёта единиц)
+const CONSUME_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ConsumeReagentRequest {
+    #[validate(range(exclusive_min = 0.0, max = 1e9, message = "Quantity must be positive and at most 1e9"))]
+    pub quantity: f64,
+    /// Единица quantity; партии в других совместимых единицах пересчитываются
+    #[validate(length(min = 1, max = 20, message = "Unit must be 1-20 characters"))]
+    pub unit: String,
+    #[validate(length(max = 500, message = "Purpose cannot exceed 500 characters"))]
+    pub purpose: Option<String>,
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+/// Списание из одной партии при расходе по реагенту (в единице партии)
+#[derive(Debug, Serialize)]
+pub struct BatchDraw {
+    pub batch_id: String,
+    pub batch_number: String,
+    pub expiry_date: Option<DateTime<Utc>>,
+    pub quantity: f64,
+    pub unit: String,
+    pub usage_id: String,
+    pub remaining_quantity: f64,
+    pub status: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsumeReagentResponse {
+    pub reagent_id: String,
+    pub quantity: f64,
+    pub unit: String,
+    pub draws: Vec<BatchDraw>,
+}
+
+/// POST /reagents/{reagent_id}/consume - расход без выбора партии: партии берутся по сроку
+/// годности (ранние первыми, без срока - последними), из каждой не больше свободного остатка
+/// (quantity - reserved_quantity). Одна запись usage_logs на партию, всё в одной транзакции
+pub async fn consume_reagent(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    request: web::Json<ConsumeReagentRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let reagent_id = path.into_inner();
+    request.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let unit = request.unit.trim();
+
+    let mut tx = app_state.db_pool.begin().await?;
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+        .bind(&reagent_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::reagent_not_found(&reagent_id))?;
+    crate::reagent_handlers::ensure_reagent_active(&reagent)?;
+    // Согласование привязано к партии - крупный расход идёт через /batches/{batch_id}/use
+    if crate::approval_handlers::exceeds_approval_threshold(&reagent, request.quantity, unit) {
+        return Err(ApiError::bad_request(&format!(
+            "Consuming {} {} of \"{}\" requires approval: use POST /reagents/{}/batches/{{batch_id}}/use",
+            request.quantity, unit, reagent.name, reagent_id
+        )));
+    }
+
+    let batches: Vec<Batch> = sqlx::query_as(
+        r#"SELECT * FROM batches
+           WHERE reagent_id = ? AND status = 'available' AND deleted_at IS NULL AND quantity > reserved_quantity
+           ORDER BY expiry_date IS NULL, expiry_date, received_date, created_at, id"#
+    )
+        .bind(&reagent_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    // Партии в несовместимых единицах (например, г при расходе в мл без плотности) пропускаются
+    let mut plan = Vec::new();
+    let mut remaining = request.quantity;
+    let mut available = 0.0;
+    for batch in batches {
+        let free = batch.quantity - batch.reserved_quantity;
+        let Ok(free_in_unit) = crate::batch_handlers::convert_amount(free, &batch.unit, unit, &reagent.name, reagent.molecular_weight) else {
+            continue;
+        };
+        available += free_in_unit;
+        if remaining <= CONSUME_EPSILON {
+            continue;
+        }
+        let take = remaining.min(free_in_unit);
+        let take_in_batch_unit = if take >= free_in_unit {
+            free
+        } else {
+            let converted = crate::batch_handlers::convert_amount(take, unit, &batch.unit, &reagent.name, reagent.molecular_weight)?;
+            crate::report_handlers::round_quantity(converted).min(free)
+        };
+        remaining -= take;
+        plan.push((batch, take_in_batch_unit, crate::report_handlers::round_quantity(take)));
+    }
+    if remaining > CONSUME_EPSILON {
+        return Err(ApiError::insufficient_quantity(available, request.quantity));
+    }
+
+    let mut draws = Vec::new();
+    for (batch, quantity, entered) in plan {
+        let usage = record_batch_usage(
+            &mut tx,
+            &batch,
+            &claims.sub,
+            quantity,
+            request.purpose.as_deref(),
+            request.notes.as_deref(),
+            None,
+        ).await?;
+        if batch.unit != unit {
+            sqlx::query("UPDATE usage_logs SET entered_quantity = ?, entered_unit = ? WHERE id = ?")
+                .bind(entered)
+                .bind(unit)
+                .bind(&usage.usage_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        crate::outbox::enqueue(
+            &mut *tx,
+            &crate::events::BusinessEvent::BatchConsumed {
+                reagent_id: reagent_id.clone(),
+                batch_id: batch.id.clone(),
+                quantity,
+                unit: batch.unit.clone(),
+                remaining_quantity: usage.remaining_quantity,
+            },
+            Some(&claims.sub),
+        ).await?;
+        draws.push(BatchDraw {
+            batch_id: batch.id,
+            batch_number: batch.batch_number,
+            expiry_date: batch.expiry_date,
+            quantity,
+            unit: batch.unit,
+            usage_id: usage.usage_id,
+            remaining_quantity: usage.remaining_quantity,
+            status: usage.status,
+        });
+    }
+    tx.commit().await?;
+
+    for draw in &draws {
+        crate::stock_anomalies::track(&app_state, crate::stock_anomalies::StockOp {
+            operation: crate::stock_anomalies::StockOperation::Use,
+            batch_id: &draw.batch_id,
+            user_id: Some(&claims.sub),
+            quantity: draw.quantity,
+        }).await;
+    }
+    let drawn: Vec<String> = draws.iter()
+        .map(|d| format!("{} {} from batch {}", d.quantity, d.unit, d.batch_number))
+        .collect();
+    crate::audit::audit(
+        &app_state.db_pool, &claims.sub, "consume_reagent", "reagent", &reagent_id,
+        &format!("Consumed {} {} of reagent \"{}\": {}", request.quantity, unit, reagent.name, drawn.join(", ")),
+        &http_request,
+    ).await;
+    log::info!(
+        "User {} consumed {} {} of reagent \"{}\" from {} batch(es) (reagent_id: {})",
+        claims.username, request.quantity, unit, reagent.name, draws.len(), reagent_id
+    );
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        ConsumeReagentResponse {
+            reagent_id,
+            quantity: request.quantity,
+            unit: unit.to_string(),
+            draws,
+        },
+        "Reagent usage recorded successfully".to_string(),
+    )))
+}
+
 pub async fn get_usage_history(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
     query: web::Query<PaginationQuery>,
 ) -> ApiResult<HttpResponse> {
     let (reagent_id, batch_id) = path.into_inner();
-    let (page, per_page, offset) = query.normalize();
-
-    let _reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
-        .bind(&reagent_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::reagent_not_found(&reagent_id))?;
+    let limits = app_state.page_limits(PageGroup::Other);
+    let (page, per_page, offset) = query.normalize(limits);
 
-    let _batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ? AND reagent_id = ?")
-        .bind(&batch_id)
-        .bind(&reagent_id)
-        .fetch_one(&app_state.db_pool)
-        .await
-        .map_err(|_| ApiError::batch_not_found(&batch_id))?;
+    crate::batch_handlers::fetch_reagent_batch(&app_state.db_pool, &reagent_id, &batch_id).await?;
 
-    let total: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM usage_logs WHERE batch_id = ?")
+    let total: (i64,) = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM usage_logs WHERE batch_id = ?1) + (SELECT COUNT(*) FROM stock_adjustments WHERE batch_id = ?1)"
+    )
         .bind(&batch_id)
         .fetch_one(&app_state.db_pool)
         .await?;
 
+    // Корректировки остатка идут в той же ленте, но отдельным видом записи
     let usage_logs: Vec<UsageLog> = sqlx::query_as(
         r#"SELECT
-            ul.id,
-            ul.batch_id,
-            ul.user_id,
+            h.id,
+            h.batch_id,
+            h.user_id,
             u.username as username,
-            ul.quantity_used,
+            h.quantity_used,
             b.unit as unit,
-            ul.purpose,
-            ul.notes,
-            ul.created_at as used_at,
-            ul.created_at
-           FROM usage_logs ul
-           LEFT JOIN users u ON ul.user_id = u.id
-           LEFT JOIN batches b ON ul.batch_id = b.id
-           WHERE ul.batch_id = ?
-           ORDER BY ul.created_at DESC
-           LIMIT ? OFFSET ?"#
+            h.purpose,
+            h.notes,
+            h.created_at as used_at,
+            h.created_at,
+            h.imported,
+            h.kind,
+            h.reason,
+            h.entered_quantity,
+            h.entered_unit
+           FROM (
+               SELECT id, batch_id, user_id, quantity_used, purpose, notes, created_at, imported,
+                      'consumption' AS kind, NULL AS reason, entered_quantity, entered_unit
+               FROM usage_logs WHERE batch_id = ?1
+               UNION ALL
+               SELECT id, batch_id, user_id, -delta, NULL, note, created_at, 0,
+                      'adjustment', reason, NULL, NULL
+               FROM stock_adjustments WHERE batch_id = ?1
+           ) h
+           LEFT JOIN users u ON h.user_id = u.id
+           LEFT JOIN batches b ON h.batch_id = b.id
+           ORDER BY h.created_at DESC
+           LIMIT ?2 OFFSET ?3"#
     )
         .bind(&batch_id)
         .bind(per_page)
@@ -342,6 +810,8 @@ pub async fn get_usage_history(
         page,
         per_page,
         total_pages,
+        limits,
+        capped: limits.is_capped(query.per_page),
     };
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
@@ -349,9 +819,31 @@ pub async fn get_usage_history(
 
 // ==================== DASHBOARD STATISTICS ====================
 
+/// Дополнительные секции статистики дашборда
+const DASHBOARD_INCLUDES: &[&str] = &["equipment"];
+
+#[derive(Debug, Deserialize)]
+pub struct DashboardStatsQuery {
+    /// Список секций через запятую, например `include=equipment`
+    pub include: Option<String>,
+}
+
 pub async fn get_dashboard_stats(
     app_state: web::Data<Arc<AppState>>,
+    query: web::Query<DashboardStatsQuery>,
 ) -> ApiResult<HttpResponse> {
+    let pool = app_state.read_pool();
+    let includes: Vec<&str> = query.include.as_deref().unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|section| !section.is_empty())
+        .collect();
+    if let Some(unknown) = includes.iter().find(|section| !DASHBOARD_INCLUDES.contains(section)) {
+        return Err(ApiError::bad_request(&format!(
+            "Unknown include '{}', allowed: {}", unknown, DASHBOARD_INCLUDES.join(", ")
+        )));
+    }
+
     #[derive(Debug, Serialize)]
     struct DashboardStats {
         total_reagents: i64,
@@ -361,40 +853,60 @@ pub async fn get_dashboard_stats(
         total_equipment: i64,
         equipment_alerts: i64,
         active_experiments: i64,
+        /// Открытые (не подтверждённые) предупреждения
+        open_alerts: i64,
+        /// Загрузка помещений за последние 30 дней (только если помещения заведены)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        room_utilization_percent: Option<f64>,
+        /// Реагенты с ближайшим прогнозируемым исчерпанием запаса
+        stockout_forecast: Vec<crate::forecast_handlers::ReagentForecast>,
+        /// Активные реагенты по агрегатному состоянию
+        reagents_by_physical_state: crate::physical_state::PhysicalStateSummary,
+        /// Сводка по парку оборудования (только с `include=equipment`)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        equipment_summary: Option<crate::models::EquipmentFleetSummary>,
     }
 
     let total_reagents: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM reagents WHERE status = 'active' AND deleted_at IS NULL")
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(pool)
         .await?;
 
     let total_batches: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM batches WHERE deleted_at IS NULL")
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(pool)
         .await?;
 
+    // Пороги - настройки low_stock_threshold_percent / expiring_soon_days (как в /batches/low-stock и /batches/expiring);
+    // партии с подтверждёнными предупреждениями (alerts) не считаются
+    let runtime = crate::settings::settings();
     let low_stock: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM batches WHERE quantity <= 10 AND status = 'available' AND deleted_at IS NULL AND reagent_id NOT IN (SELECT id FROM reagents WHERE deleted_at IS NOT NULL)")
-        .fetch_one(&app_state.db_pool)
+        "SELECT COUNT(*) FROM batches WHERE original_quantity > 0 AND quantity * 100.0 / original_quantity <= ? AND status = 'available' AND deleted_at IS NULL AND reagent_id NOT IN (SELECT id FROM reagents WHERE deleted_at IS NOT NULL) \
+         AND id NOT IN (SELECT entity_id FROM alerts WHERE kind = 'low_stock' AND status = 'acknowledged')")
+        .bind(runtime.get_i64(crate::settings::LOW_STOCK_THRESHOLD_PERCENT))
+        .fetch_one(pool)
         .await?;
 
     let expiring_soon: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM batches WHERE expiry_date IS NOT NULL AND expiry_date <= datetime('now', '+30 days') AND status = 'available' AND deleted_at IS NULL AND reagent_id NOT IN (SELECT id FROM reagents WHERE deleted_at IS NOT NULL)"
+        "SELECT COUNT(*) FROM batches WHERE expiry_date IS NOT NULL AND expiry_date <= datetime('now', ?) AND status = 'available' AND deleted_at IS NULL \
+         AND reagent_id IN (SELECT id FROM reagents WHERE deleted_at IS NULL AND expiry_policy = 'strict') \
+         AND id NOT IN (SELECT entity_id FROM alerts WHERE kind = 'batch_expiring' AND status = 'acknowledged')"
     )
-        .fetch_one(&app_state.db_pool)
+        .bind(format!("+{} days", runtime.get_i64(crate::settings::EXPIRING_SOON_DAYS)))
+        .fetch_one(pool)
         .await?;
 
     // Equipment: total count
     let total_equipment: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM equipment WHERE status != 'retired'"
+        "SELECT COUNT(*) FROM equipment WHERE status != 'retired' AND pending_deletion_id IS NULL"
     )
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(pool)
         .await
         .unwrap_or((0,));
 
     // Equipment alerts: maintenance + damaged + calibration
     let equipment_alerts: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM equipment WHERE status IN ('maintenance', 'damaged', 'calibration')"
+        "SELECT COUNT(*) FROM equipment WHERE status IN ('maintenance', 'damaged', 'calibration') AND pending_deletion_id IS NULL"
     )
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(pool)
         .await
         .unwrap_or((0,));
 
@@ -402,10 +914,41 @@ pub async fn get_dashboard_stats(
     let active_experiments: (i64,) = sqlx::query_as(
         "SELECT COUNT(*) FROM experiments WHERE status IN ('in_progress', 'planned')"
     )
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(pool)
         .await
         .unwrap_or((0,));
 
+    let open_alerts: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM alerts WHERE status = 'open'")
+        .fetch_one(pool)
+        .await?;
+
+    let today = Utc::now().date_naive();
+    let room_utilization_percent = crate::room_handlers::overall_utilization(
+        pool,
+        today - chrono::Duration::days(29),
+        today,
+    )
+        .await
+        .unwrap_or(None);
+
+    let stockout_forecast = crate::forecast_handlers::soonest_stockouts(
+        pool,
+        crate::forecast_handlers::DASHBOARD_FORECAST_LIMIT,
+    )
+        .await
+        .unwrap_or_else(|e| {
+            log::warn!("Dashboard stock-out forecast failed: {}", e);
+            Vec::new()
+        });
+
+    let reagents_by_physical_state = crate::physical_state::physical_state_summary(pool).await?;
+
+    let equipment_summary = if includes.contains(&"equipment") {
+        Some(crate::equipment_handlers::equipment_fleet_summary(pool).await?)
+    } else {
+        None
+    };
+
     let stats = DashboardStats {
         total_reagents: total_reagents.0,
         total_batches: total_batches.0,
@@ -414,14 +957,101 @@ pub async fn get_dashboard_stats(
         total_equipment: total_equipment.0,
         equipment_alerts: equipment_alerts.0,
         active_experiments: active_experiments.0,
+        open_alerts: open_alerts.0,
+        room_utilization_percent,
+        stockout_forecast,
+        reagents_by_physical_state,
+        equipment_summary,
     };
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(stats)))
 }
 
-// ==================== RECENT ACTIVITY (from audit_logs) ====================
+// ==================== GLOBAL SEARCH ====================
+
+#[derive(Debug, Deserialize)]
+pub struct GlobalSearchQuery {
+    pub q: String,
+    /// Максимум результатов в каждой категории
+    pub limit: Option<i64>,
+}
 
+/// Найденная запись; для ссылок parent_* указывают на сущность, к которой она привязана
 #[derive(Debug, Serialize)]
+pub struct GlobalSearchHit {
+    pub entity_type: &'static str,
+    pub id: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+}
+
+/// Поиск по реагентам, оборудованию, экспериментам и внешним ссылкам
+pub async fn global_search(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<GlobalSearchQuery>,
+) -> ApiResult<HttpResponse> {
+    let term = query.q.trim();
+    if term.chars().count() < 2 {
+        return Err(ApiError::bad_request("Search query must be at least 2 characters"));
+    }
+    let limit = query.limit.unwrap_or(10).clamp(1, 50);
+    let pool = &app_state.db_pool;
+    let pattern = format!("%{}%", escape_like_pattern(term));
+
+    let mut hits = Vec::new();
+
+    let sections: [(&'static str, &str); 3] = [
+        ("reagent", "SELECT id, name, cas_number FROM reagents \
+                     WHERE deleted_at IS NULL AND (name LIKE ?1 ESCAPE '\\' OR cas_number LIKE ?1 ESCAPE '\\') \
+                     ORDER BY name COLLATE NOCASE LIMIT ?2"),
+        ("equipment", "SELECT id, name, serial_number FROM equipment \
+                       WHERE pending_deletion_id IS NULL AND (name LIKE ?1 ESCAPE '\\' OR serial_number LIKE ?1 ESCAPE '\\') \
+                       ORDER BY name COLLATE NOCASE LIMIT ?2"),
+        ("experiment", "SELECT id, title, status FROM experiments \
+                        WHERE status != 'draft' AND title LIKE ?1 ESCAPE '\\' \
+                        ORDER BY experiment_date DESC LIMIT ?2"),
+    ];
+    for (entity_type, sql) in sections {
+        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(sql)
+            .bind(&pattern)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?;
+        hits.extend(rows.into_iter().map(|(id, title, subtitle)| GlobalSearchHit {
+            entity_type,
+            id,
+            title,
+            subtitle,
+            url: None,
+            parent_type: None,
+            parent_id: None,
+        }));
+    }
+
+    let links = crate::link_handlers::search_links(pool, term, limit).await?;
+    hits.extend(links.into_iter().map(|link| GlobalSearchHit {
+        entity_type: "link",
+        id: link.id,
+        title: link.title,
+        subtitle: None,
+        url: Some(link.url),
+        parent_type: Some(link.entity_type),
+        parent_id: Some(link.entity_id),
+    }));
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(hits)))
+}
+
+// ==================== RECENT ACTIVITY (from audit_logs) ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
 pub struct ActivityItem {
     pub id: String,
     pub action: String,
@@ -429,6 +1059,10 @@ pub struct ActivityItem {
     pub entity_id: Option<String>,
     pub description: Option<String>,
     pub username: Option<String>,
+    /// Действие выполнено через киоск-токен помещения
+    pub via_kiosk: bool,
+    /// Идентификатор, введённый на киоске (для via_kiosk)
+    pub actor_identifier: Option<String>,
     pub created_at: String,
 }
 
@@ -437,25 +1071,18 @@ pub async fn get_recent_activity(
 ) -> ApiResult<HttpResponse> {
     let limit = 15i64;
 
-    let rows: Vec<(String, String, String, Option<String>, Option<String>, Option<String>, String)> =
-        sqlx::query_as(
-            r#"SELECT
-                a.id, a.action, a.entity_type, a.entity_id,
-                a.description, u.username, a.created_at
-            FROM audit_logs a
-            LEFT JOIN users u ON a.user_id = u.id
-            ORDER BY a.created_at DESC
-            LIMIT ?"#
-        )
-        .bind(limit)
-        .fetch_all(&app_state.db_pool)
-        .await?;
-
-    let activities: Vec<ActivityItem> = rows.into_iter()
-        .map(|(id, action, entity_type, entity_id, description, username, created_at)| {
-            ActivityItem { id, action, entity_type, entity_id, description, username, created_at }
-        })
-        .collect();
+    let activities: Vec<ActivityItem> = sqlx::query_as(
+        r#"SELECT
+            a.id, a.action, a.entity_type, a.entity_id,
+            a.description, u.username, a.via_kiosk, a.actor_identifier, a.created_at
+        FROM audit_logs a
+        LEFT JOIN users u ON a.user_id = u.id
+        ORDER BY a.created_at DESC
+        LIMIT ?"#
+    )
+    .bind(limit)
+    .fetch_all(app_state.read_pool())
+    .await?;
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(activities)))
 }
@@ -481,9 +1108,18 @@ pub struct DashboardTrends {
     pub expiring_by_week: Vec<ExpiringWeekPoint>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DashboardTrendsQuery {
+    /// Учитывать импортированную историю расхода (по умолчанию нет)
+    #[serde(default)]
+    pub include_imported: bool,
+}
+
 pub async fn get_dashboard_trends(
     app_state: web::Data<Arc<AppState>>,
+    query: web::Query<DashboardTrendsQuery>,
 ) -> ApiResult<HttpResponse> {
+    let pool = app_state.read_pool();
     // Usage per day (last 30 days)
     let usage_rows: Vec<(String, i64, f64)> = sqlx::query_as(
         r#"SELECT
@@ -492,10 +1128,12 @@ pub async fn get_dashboard_trends(
             COALESCE(SUM(quantity_used), 0) as total_quantity
         FROM usage_logs
         WHERE created_at >= datetime('now', '-30 days')
+          AND (imported = 0 OR ?)
         GROUP BY DATE(created_at)
         ORDER BY day ASC"#
     )
-    .fetch_all(&app_state.db_pool)
+    .bind(query.include_imported)
+    .fetch_all(pool)
     .await?;
 
     let usage_by_day: Vec<UsageTrendPoint> = usage_rows.into_iter()
@@ -517,13 +1155,14 @@ pub async fn get_dashboard_trends(
           AND expiry_date <= datetime('now', '+28 days')
           AND expiry_date > datetime('now')
           AND status = 'available'
+          AND reagent_id IN (SELECT id FROM reagents WHERE expiry_policy = 'strict')
         GROUP BY week_label
         ORDER BY CASE week_label
             WHEN 'This week' THEN 1 WHEN 'Week 2' THEN 2
             WHEN 'Week 3' THEN 3 WHEN 'Week 4' THEN 4
         END"#
     )
-    .fetch_all(&app_state.db_pool)
+    .fetch_all(pool)
     .await?;
 
     let expiring_by_week: Vec<ExpiringWeekPoint> = expiring_rows.into_iter()
@@ -588,4 +1227,58 @@ crate::audit::audit(
     };
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::UserRole;
+    use crate::test_support::fixtures::*;
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_reagent_consume_draws_batches_by_expiry() {
+        let app = TestApp::new().await;
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity, unit, status, \
+             expiry_date, received_date, created_at, updated_at) VALUES \
+             ('nacl-late', 'fx-reagent-nacl', 'NACL-LATE', 300.0, 300.0, 0.0, 'g', 'available', \
+              datetime('now', '+400 days'), datetime('now'), datetime('now'), datetime('now')), \
+             ('nacl-early', 'fx-reagent-nacl', 'NACL-EARLY', 100.0, 100.0, 20.0, 'g', 'available', \
+              datetime('now', '+10 days'), datetime('now'), datetime('now'), datetime('now')), \
+             ('nacl-expired', 'fx-reagent-nacl', 'NACL-OLD', 50.0, 50.0, 0.0, 'g', 'expired', \
+              datetime('now', '-1 days'), datetime('now'), datetime('now'), datetime('now'))"
+        ).execute(&app.pool).await.unwrap();
+        let consume_path = format!("/reagents/{}/consume", NACL_ID);
+
+        // Ранний срок первым, резерв не трогается, партия без срока - последней
+        let (status, body) = app.post(UserRole::Researcher, &consume_path, json!({ "quantity": 0.5, "unit": "kg" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let draws: Vec<(&str, f64)> = body["data"]["draws"].as_array().unwrap().iter()
+            .map(|d| (d["batch_id"].as_str().unwrap(), d["quantity"].as_f64().unwrap()))
+            .collect();
+        assert_eq!(draws, vec![("nacl-early", 80.0), ("nacl-late", 300.0), (NACL_BATCH_ID, 120.0)]);
+        assert_eq!(body["data"]["draws"][1]["status"], "depleted");
+        assert_eq!(app.batch_stock("nacl-early").await, (20.0, 20.0));
+        assert_eq!(app.batch_stock(NACL_BATCH_ID).await.0, 380.0);
+        let entered: Vec<(f64, String)> = sqlx::query_as(
+            "SELECT entered_quantity, entered_unit FROM usage_logs WHERE reagent_id = ? ORDER BY quantity_used"
+        ).bind(NACL_ID).fetch_all(&app.pool).await.unwrap();
+        assert_eq!(entered.len(), 3);
+        assert!(entered.iter().all(|(_, unit)| unit == "kg"));
+        assert!((entered.iter().map(|(q, _)| q).sum::<f64>() - 0.5).abs() < 1e-9);
+
+        let (status, body) = app.post(UserRole::Researcher, &consume_path, json!({ "quantity": 0.0, "unit": "g" })).await;
+        assert!(status.is_client_error(), "{}: {}", status, body);
+
+        // Не хватает - ничего не списано
+        let (status, body) = app.post(UserRole::Researcher, &consume_path, json!({ "quantity": 400.0, "unit": "g" })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert!(body["message"].as_str().unwrap().contains("Available: 380"), "{}", body);
+        assert_eq!(app.batch_stock(NACL_BATCH_ID).await.0, 380.0);
+        let usage: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM usage_logs WHERE reagent_id = ?")
+            .bind(NACL_ID).fetch_one(&app.pool).await.unwrap();
+        assert_eq!(usage, 3);
+    }
+}
@@ -0,0 +1,128 @@
+// src/http_client.rs
+//! Исходящие соединения: общий HTTP-клиент (справочник PubChem, вебхуки outbox)
+//! поверх rustls с корнями webpki-roots; та же TLS-конфигурация используется SMTP (mailer).
+//!
+//! Клиент - `reqwest` (chunked encoding, редиректы, HTTP(S)_PROXY из окружения).
+//! Тело ответа ограничено MAX_RESPONSE_BYTES, таймаут задаётся на каждый запрос.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+pub use reqwest::Method;
+
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+lazy_static::lazy_static! {
+    static ref TLS_CONFIG: Arc<rustls::ClientConfig> = {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+        Arc::new(
+            rustls::ClientConfig::builder()
+                .with_safe_defaults()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        )
+    };
+
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .use_preconfigured_tls(TLS_CONFIG.as_ref().clone())
+        .user_agent(concat!("LIMS/", env!("CARGO_PKG_VERSION")))
+        // Клиент общий для всех воркеров actix, у каждого свой рантайм tokio:
+        // соединение из пула другого рантайма повторно не используется
+        .pool_max_idle_per_host(0)
+        .build()
+        .expect("HTTP client configuration is static");
+}
+
+/// TLS-конфигурация исходящих соединений
+pub fn tls_config() -> Arc<rustls::ClientConfig> {
+    TLS_CONFIG.clone()
+}
+
+/// Запрос с таймаутом на всё время ответа; Ok - (статус, тело) при любом статусе
+pub async fn request(
+    method: Method,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Vec<u8>,
+    timeout: Duration,
+) -> Result<(u16, Vec<u8>), String> {
+    let mut builder = CLIENT.request(method, url).timeout(timeout);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    if !body.is_empty() {
+        builder = builder.body(body);
+    }
+
+    let mut response = builder.send().await.map_err(|e| format!("Request error: {}", e))?;
+    let status = response.status().as_u16();
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| format!("Read error: {}", e))? {
+        if data.len() + chunk.len() > MAX_RESPONSE_BYTES {
+            return Err(format!("Response exceeds {} bytes", MAX_RESPONSE_BYTES));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok((status, data))
+}
+
+/// Кодирование компонента URL (RFC 3986, unreserved символы без изменений)
+pub fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// Сервер на один запрос: отвечает chunked-телом заданной длины
+    fn spawn_server(body_len: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/data", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf);
+            let body = "x".repeat(body_len);
+            let _ = stream.write_all(format!(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                body.len(), body
+            ).as_bytes());
+        });
+        url
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(percent_encode("sodium chloride"), "sodium%20chloride");
+        assert_eq!(percent_encode("a/b"), "a%2Fb");
+        assert_eq!(percent_encode("64-17-5"), "64-17-5");
+    }
+
+    #[actix_web::test]
+    async fn test_request_reads_chunked_body_within_limit() {
+        let url = spawn_server(10);
+        let (status, body) = request(Method::GET, &url, &[], Vec::new(), Duration::from_secs(5)).await.unwrap();
+        assert_eq!((status, body), (200, b"xxxxxxxxxx".to_vec()));
+
+        let url = spawn_server(MAX_RESPONSE_BYTES + 1);
+        let err = request(Method::GET, &url, &[], Vec::new(), Duration::from_secs(5)).await.unwrap_err();
+        assert!(err.contains("exceeds"), "{}", err);
+    }
+}
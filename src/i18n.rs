@@ -0,0 +1,387 @@
+// src/i18n.rs
+//! Локализация экспортов: `?locale=ru|en` и `?excel_locale=ru|en`.
+//!
+//! Подписи колонок берутся из каталога (ключ - имя поля или колонки отчёта, текст en/ru).
+//! Без параметров экспорты не меняются: JSON-экспорты отдают имена полей, CSV отчётов -
+//! английские подписи и даты `YYYY-MM-DD`. `excel_locale` даёт CSV в соглашениях Excel этой
+//! локали (для ru - разделитель `;` и десятичная запятая) и подразумевает такую же `locale`.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::error::{ApiError, ApiResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    En,
+    Ru,
+}
+
+impl Locale {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "ru" => Some(Locale::Ru),
+            _ => None,
+        }
+    }
+
+    fn date_format(&self) -> &'static str {
+        match self {
+            Locale::En => "%Y-%m-%d",
+            Locale::Ru => "%d.%m.%Y",
+        }
+    }
+
+    fn datetime_format(&self) -> &'static str {
+        match self {
+            Locale::En => "%Y-%m-%d %H:%M",
+            Locale::Ru => "%d.%m.%Y %H:%M",
+        }
+    }
+}
+
+// ==================== CATALOG ====================
+
+/// (ключ, en, ru); английские подписи колонок отчётов совпадают с прежними заголовками CSV
+const CATALOG: &[(&str, &str, &str)] = &[
+    ("yes", "yes", "да"),
+    ("no", "no", "нет"),
+    // Общие поля
+    ("id", "ID", "ID"),
+    ("name", "Name", "Название"),
+    ("title", "Title", "Название"),
+    ("description", "Description", "Описание"),
+    ("status", "Status", "Статус"),
+    ("quantity", "Quantity", "Количество"),
+    ("unit", "Unit", "Единица"),
+    ("location", "Location", "Место хранения"),
+    ("manufacturer", "Manufacturer", "Производитель"),
+    ("notes", "Notes", "Примечания"),
+    ("created_by", "Created By", "Создал"),
+    ("updated_by", "Updated By", "Изменил"),
+    ("created_at", "Created At", "Создано"),
+    ("updated_at", "Updated At", "Изменено"),
+    ("deleted_at", "Deleted At", "Удалено"),
+    // Реагенты
+    ("formula", "Formula", "Формула"),
+    ("cas_number", "CAS Number", "CAS-номер"),
+    ("molecular_weight", "Molecular Weight", "Молекулярная масса"),
+    ("physical_state", "Physical State", "Агрегатное состояние"),
+    ("storage_conditions", "Storage Conditions", "Условия хранения"),
+    ("appearance", "Appearance", "Внешний вид"),
+    ("hazard_pictograms", "Hazard Pictograms", "Пиктограммы опасности"),
+    ("total_quantity", "Total Quantity", "Общее количество"),
+    ("batches_count", "Batches", "Партий"),
+    ("primary_unit", "Primary Unit", "Основная единица"),
+    ("procurement_lead_time_days", "Lead Time (days)", "Срок поставки (дни)"),
+    ("image_id", "Image", "Изображение"),
+    ("coa_required", "COA Required", "Требуется COA"),
+    ("publicly_visible", "Publicly Visible", "В публичном каталоге"),
+    ("approval_threshold", "Approval Threshold", "Порог согласования"),
+    ("approval_threshold_unit", "Approval Threshold Unit", "Единица порога согласования"),
+    ("default_shelf_life_days", "Default Shelf Life (days)", "Срок годности по умолчанию (дни)"),
+    ("expiry_policy", "Expiry Policy", "Контроль сроков годности"),
+    // Партии
+    ("reagent_id", "Reagent ID", "ID реагента"),
+    ("reagent_name", "Reagent", "Реагент"),
+    ("lot_number", "Lot Number", "Номер лота"),
+    ("batch_number", "Batch Number", "Номер партии"),
+    ("cat_number", "Catalog Number", "Каталожный номер"),
+    ("original_quantity", "Original Quantity", "Исходное количество"),
+    ("reserved_quantity", "Reserved Quantity", "Зарезервировано"),
+    ("pack_size", "Pack Size", "Фасовка"),
+    ("expiry_date", "Expiry Date", "Срок годности"),
+    ("supplier", "Supplier", "Поставщик"),
+    ("received_date", "Received Date", "Дата поступления"),
+    ("barcode", "Barcode", "Штрихкод"),
+    ("coa_reference", "COA Reference", "Номер COA"),
+    ("coa_received_at", "COA Received At", "COA получен"),
+    ("coa_received_by", "COA Received By", "COA принял"),
+    ("location_id", "Location ID", "ID места хранения"),
+    ("container_count", "Containers", "Контейнеров"),
+    ("expiry_extension_history", "Expiry Extensions", "Продления срока"),
+    ("container_size", "Container Size", "Объём контейнера"),
+    ("sealed_containers", "Sealed Containers", "Запечатанных контейнеров"),
+    ("open_containers", "Open Containers", "Вскрытых контейнеров"),
+    ("advisory_expiry", "Advisory Expiry (not blocking)", "Рекомендательные сроки (без блокировки)"),
+    // Оборудование
+    ("type", "Type", "Тип"),
+    ("serial_number", "Serial Number", "Серийный номер"),
+    ("model", "Model", "Модель"),
+    ("purchase_date", "Purchase Date", "Дата покупки"),
+    ("warranty_until", "Warranty Until", "Гарантия до"),
+    ("parent_equipment_id", "Parent Equipment ID", "ID родительского оборудования"),
+    ("restricted_to_roles", "Restricted To Roles", "Доступно ролям"),
+    ("parent_name", "Parent Equipment", "Родительское оборудование"),
+    ("assembly_path", "Assembly Path", "Путь в сборке"),
+    ("assembly_depth", "Assembly Depth", "Уровень в сборке"),
+    // Отчёты
+    ("experiment_date", "Experiment Date", "Дата эксперимента"),
+    ("start_date", "Start Date", "Дата начала"),
+    ("instructor", "Instructor", "Преподаватель"),
+    ("student_group", "Student Group", "Учебная группа"),
+    ("hazardous_reagents", "Hazardous Reagents", "Опасные реагенты"),
+    ("experiments", "Experiments", "Экспериментов"),
+    ("students", "Students", "Студентов"),
+    ("signed_in", "Signed In", "Отметились"),
+    ("attendance_rate", "Attendance Rate (%)", "Посещаемость (%)"),
+    ("group_key", "Group", "Группа"),
+    ("group_label", "Label", "Подпись"),
+    ("convertible", "Convertible", "Пересчитывается"),
+    ("lines", "Lines", "Строк"),
+    ("missing_actual", "Missing Actual", "Без факта"),
+    ("planned_total", "Planned Total", "План"),
+    ("actual_total", "Actual Total", "Факт"),
+    ("variance", "Variance", "Отклонение"),
+    ("variance_pct", "Variance (%)", "Отклонение (%)"),
+    ("adjustment_total", "Adjustments", "Корректировки"),
+];
+
+/// Текст каталога для локали; None - ключа в каталоге нет
+pub fn text(locale: Locale, key: &str) -> Option<&'static str> {
+    CATALOG.iter()
+        .find(|(k, _, _)| *k == key)
+        .map(|(_, en, ru)| match locale {
+            Locale::En => *en,
+            Locale::Ru => *ru,
+        })
+}
+
+// ==================== EXPORT STYLE ====================
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportLocaleQuery {
+    /// ru | en - подписи колонок и формат дат
+    pub locale: Option<String>,
+    /// ru | en - CSV в соглашениях Excel этой локали
+    pub excel_locale: Option<String>,
+}
+
+/// Оформление экспорта; Default - прежний вид без локализации
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportStyle {
+    pub locale: Option<Locale>,
+    pub excel_locale: Option<Locale>,
+}
+
+impl ExportStyle {
+    pub fn from_query(query: &ExportLocaleQuery) -> ApiResult<Self> {
+        let parse = |value: Option<&str>, param: &str| -> ApiResult<Option<Locale>> {
+            value.map(|v| {
+                Locale::parse(v).ok_or_else(|| ApiError::bad_request(&format!(
+                    "Invalid {} '{}'. Must be one of: en, ru", param, v.trim()
+                )))
+            }).transpose()
+        };
+        let excel_locale = parse(query.excel_locale.as_deref(), "excel_locale")?;
+        let locale = parse(query.locale.as_deref(), "locale")?.or(excel_locale);
+        Ok(Self { locale, excel_locale })
+    }
+
+    /// Экспорт сущностей отдаётся в прежнем виде
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// JSON-экспорт сущностей заменяется на CSV
+    pub fn wants_csv(&self) -> bool {
+        self.excel_locale.is_some()
+    }
+
+    fn delimiter(&self) -> char {
+        match self.excel_locale {
+            Some(Locale::Ru) => ';',
+            _ => ',',
+        }
+    }
+
+    fn decimal_comma(&self) -> bool {
+        self.excel_locale == Some(Locale::Ru)
+    }
+
+    /// Подпись колонки отчёта; без locale - английская
+    pub fn label<'a>(&self, key: &'a str) -> &'a str {
+        text(self.locale.unwrap_or(Locale::En), key).unwrap_or(key)
+    }
+
+    /// Подпись колонки экспорта сущности; без locale - имя поля
+    pub fn column<'a>(&self, key: &'a str) -> &'a str {
+        match self.locale {
+            Some(locale) => text(locale, key).unwrap_or(key),
+            None => key,
+        }
+    }
+
+    pub fn date(&self, value: &NaiveDate) -> String {
+        value.format(self.locale.unwrap_or(Locale::En).date_format()).to_string()
+    }
+
+    pub fn datetime(&self, value: &DateTime<Utc>) -> String {
+        value.format(self.locale.unwrap_or(Locale::En).datetime_format()).to_string()
+    }
+
+    pub fn number(&self, value: f64) -> String {
+        let text = value.to_string();
+        if self.decimal_comma() { text.replace('.', ",") } else { text }
+    }
+
+    pub fn yes_no(&self, value: bool) -> &'static str {
+        text(self.locale.unwrap_or(Locale::En), if value { "yes" } else { "no" }).unwrap_or_default()
+    }
+
+    // ==================== CSV ====================
+
+    /// Экранирование поля CSV (разделитель, кавычки, переносы строк)
+    pub fn csv_field(&self, field: &str) -> String {
+        if field.contains(self.delimiter()) || field.contains('"') || field.contains('\n') || field.contains('\r') {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    pub fn csv_line(&self, fields: &[String]) -> String {
+        let mut line = fields.iter()
+            .map(|f| self.csv_field(f))
+            .collect::<Vec<_>>()
+            .join(&self.delimiter().to_string());
+        line.push('\n');
+        line
+    }
+
+    /// BOM (для UTF-8 в Excel) и строка подписей колонок отчёта
+    pub fn csv_header(&self, keys: &[&str]) -> String {
+        let labels: Vec<String> = keys.iter().map(|k| self.label(k).to_string()).collect();
+        format!("\u{FEFF}{}", self.csv_line(&labels))
+    }
+
+    // ==================== ENTITY TABLES ====================
+
+    /// Дата или дата-время в строке JSON (RFC 3339 / YYYY-MM-DD) в формате локали
+    fn localized_date(&self, text: &str) -> Option<String> {
+        if let Ok(value) = DateTime::parse_from_rfc3339(text) {
+            return Some(self.datetime(&value.with_timezone(&Utc)));
+        }
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().map(|d| self.date(&d))
+    }
+
+    fn localized_value(&self, value: &Value) -> Value {
+        match value {
+            Value::String(s) if self.locale.is_some() => {
+                self.localized_date(s).map(Value::String).unwrap_or_else(|| value.clone())
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// Строки таблицы (см. `export_archive::to_table`) с подписями и датами локали; числа не меняются
+    pub fn localize_rows(&self, headers: &[String], rows: &[Value]) -> Vec<LocalizedRow> {
+        rows.iter()
+            .map(|row| LocalizedRow(headers.iter()
+                .map(|h| (
+                    self.column(h).to_string(),
+                    self.localized_value(row.get(h).unwrap_or(&Value::Null)),
+                ))
+                .collect()))
+            .collect()
+    }
+
+    fn csv_cell(&self, value: Option<&Value>) -> String {
+        match value.map(|v| self.localized_value(v)) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => s,
+            Some(Value::Bool(b)) => self.yes_no(b).to_string(),
+            Some(Value::Number(n)) => match n.as_i64() {
+                Some(i) => i.to_string(),
+                None => self.number(n.as_f64().unwrap_or_default()),
+            },
+            Some(other) => other.to_string(),
+        }
+    }
+
+    /// CSV экспорта сущности: BOM, подписи колонок, строки
+    pub fn csv_table(&self, headers: &[String], rows: &[Value]) -> String {
+        let labels: Vec<String> = headers.iter().map(|h| self.column(h).to_string()).collect();
+        let mut content = format!("\u{FEFF}{}", self.csv_line(&labels));
+        for row in rows {
+            let cells: Vec<String> = headers.iter().map(|h| self.csv_cell(row.get(h))).collect();
+            content.push_str(&self.csv_line(&cells));
+        }
+        content
+    }
+}
+
+/// Строка экспорта с колонками в заданном порядке (serde_json::Map их сортирует)
+#[derive(Debug)]
+pub struct LocalizedRow(pub Vec<(String, Value)>);
+
+impl Serialize for LocalizedRow {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(locale: Option<&str>, excel_locale: Option<&str>) -> ApiResult<ExportStyle> {
+        ExportStyle::from_query(&ExportLocaleQuery {
+            locale: locale.map(str::to_string),
+            excel_locale: excel_locale.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn test_style_from_query() {
+        assert!(style(None, None).unwrap().is_default());
+        let ru = style(None, Some("RU")).unwrap();
+        assert_eq!((ru.locale, ru.excel_locale), (Some(Locale::Ru), Some(Locale::Ru)));
+        let mixed = style(Some("en"), Some("ru")).unwrap();
+        assert_eq!(mixed.label("batch_number"), "Batch Number");
+        assert!(mixed.wants_csv());
+        assert!(matches!(style(Some("de"), None), Err(ApiError::BadRequest(ref msg)) if msg.contains("locale 'de'")));
+    }
+
+    #[test]
+    fn test_default_style_keeps_previous_output() {
+        let style = ExportStyle::default();
+        assert_eq!(style.csv_header(&["id", "reagent_name", "variance_pct"]), "\u{FEFF}ID,Reagent,Variance (%)\n");
+        assert_eq!(style.column("reagent_name"), "reagent_name");
+        assert_eq!(style.number(12.5), "12.5");
+        assert_eq!(style.csv_field("a,b"), "\"a,b\"");
+        assert_eq!(style.date(&NaiveDate::from_ymd_opt(2024, 3, 5).unwrap()), "2024-03-05");
+    }
+
+    #[test]
+    fn test_excel_ru_csv_table() {
+        let style = style(None, Some("ru")).unwrap();
+        let headers = vec!["batch_number".to_string(), "quantity".to_string(), "expiry_date".to_string(), "coa_required".to_string()];
+        let rows = vec![serde_json::json!({
+            "batch_number": "LOT;1",
+            "quantity": 12.5,
+            "expiry_date": "2024-03-05T14:30:00Z",
+            "coa_required": true,
+        })];
+        assert_eq!(
+            style.csv_table(&headers, &rows),
+            "\u{FEFF}Номер партии;Количество;Срок годности;Требуется COA\n\"LOT;1\";12,5;05.03.2024 14:30;да\n"
+        );
+
+        let localized = serde_json::to_string(&style.localize_rows(&headers, &rows)).unwrap();
+        assert_eq!(
+            localized,
+            r#"[{"Номер партии":"LOT;1","Количество":12.5,"Срок годности":"05.03.2024 14:30","Требуется COA":true}]"#
+        );
+    }
+}
@@ -25,8 +25,10 @@ use std::collections::HashMap;
 use sqlx::Row;
 use chrono::{Utc, NaiveDate, NaiveDateTime}; // Added Chrono types
 use crate::{AppState, error::{ApiResult, ApiError}, handlers::ApiResponse};
-use crate::query_builders::{SafeQueryBuilder, FieldWhitelist};
+use crate::query_builders::{SafeQueryBuilder, FieldWhitelist, PhysicalState, sanitize_original_filename};
 use crate::auth::get_current_user;
+use crate::i18n::{ExportLocaleQuery, ExportStyle};
+use crate::work_queue::{self, WorkTicket};
 
 // ==========================================
 // CUSTOM DESERIALIZER (FIX FOR DATE ISSUE)
@@ -103,6 +105,100 @@ where
     }
 }
 
+// ==========================================
+// DECIMAL NUMBERS (1.5 / 1,5)
+// ==========================================
+
+/// Десятичный разделитель чисел в импортируемом файле; auto - определяется по каждой ячейке
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecimalSeparator {
+    #[default]
+    Auto,
+    Dot,
+    Comma,
+}
+
+/// Числовая ячейка: число (JSON, xlsx) или текст в любой из двух десятичных нотаций
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ImportNumber {
+    Number(f64),
+    Text(String),
+}
+
+impl From<f64> for ImportNumber {
+    fn from(value: f64) -> Self {
+        ImportNumber::Number(value)
+    }
+}
+
+impl ImportNumber {
+    pub fn resolve(&self, separator: DecimalSeparator) -> Option<f64> {
+        match self {
+            ImportNumber::Number(n) => Some(*n),
+            ImportNumber::Text(s) => parse_decimal(s, separator),
+        }
+    }
+
+    fn raw(&self) -> String {
+        match self {
+            ImportNumber::Number(n) => n.to_string(),
+            ImportNumber::Text(s) => s.clone(),
+        }
+    }
+}
+
+/// Разбор "1 234,5" / "1,234.5" / "1234.5". В режиме auto десятичным считается
+/// последний из двух разных разделителей, а одиночный ',' или '.' - десятичным;
+/// повторяющийся разделитель - разделитель тысяч. Пробелы и апострофы отбрасываются.
+pub fn parse_decimal(value: &str, separator: DecimalSeparator) -> Option<f64> {
+    let cleaned: String = value.chars()
+        .filter(|c| !c.is_whitespace() && *c != '\'' && *c != '\u{2019}')
+        .collect();
+    if cleaned.is_empty() {
+        return None;
+    }
+    let decimal = match separator {
+        DecimalSeparator::Dot => Some('.'),
+        DecimalSeparator::Comma => Some(','),
+        DecimalSeparator::Auto => match (cleaned.rfind('.'), cleaned.rfind(',')) {
+            (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+            (Some(_), None) => (cleaned.matches('.').count() == 1).then_some('.'),
+            (None, Some(_)) => (cleaned.matches(',').count() == 1).then_some(','),
+            (None, None) => None,
+        },
+    };
+    let normalized: String = cleaned.chars()
+        .filter_map(|c| match c {
+            '.' | ',' if Some(c) == decimal => Some('.'),
+            '.' | ',' => None,
+            other => Some(other),
+        })
+        .collect();
+    normalized.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Числовое поле строки импорта; нераспознанное значение отклоняет импорт
+fn resolve_import_number(
+    value: Option<&ImportNumber>,
+    separator: DecimalSeparator,
+    field: &str,
+    owner: &str,
+) -> ApiResult<Option<f64>> {
+    value.map(|v| v.resolve(separator).ok_or_else(|| ApiError::bad_request(&format!(
+        "Invalid {} '{}' for '{}'", field, v.raw(), owner
+    )))).transpose()
+}
+
+/// Параметры импорта реагентов и партий
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportQuery {
+    /// auto | dot | comma
+    #[serde(default)]
+    pub decimal_separator: DecimalSeparator,
+}
+
 // ==========================================
 // MODELS (DTOs)
 // ==========================================
@@ -115,14 +211,18 @@ pub struct ReagentImportDto {
     #[serde(alias = "Formula", alias = "chemical_formula", alias = "Формула")]
     pub formula: Option<String>,
     
-    #[serde(alias = "CAS", alias = "cas", alias = "cas_number", alias = "CAS Number")]
+    #[serde(alias = "CAS", alias = "cas", alias = "cas_number", alias = "CAS Number", alias = "CAS-номер")]
     pub cas_number: Option<String>,
     
-    #[serde(alias = "Molecular weight", alias = "MW", alias = "Molecular Weight", alias = "Mol. Weight")]
-    pub molecular_weight: Option<f64>,
+    #[serde(alias = "Molecular weight", alias = "MW", alias = "Molecular Weight", alias = "Mol. Weight", alias = "Молекулярная масса")]
+    pub molecular_weight: Option<ImportNumber>,
     
     #[serde(alias = "Manufacturer", alias = "manufacturer", alias = "Производитель")]
     pub manufacturer: Option<String>,
+
+    /// Каноническое значение, синоним ("powder", "твердый", "жидкий") или написание из карты администратора
+    #[serde(alias = "Physical State", alias = "Physical state", alias = "State", alias = "Агрегатное состояние", alias = "Состояние")]
+    pub physical_state: Option<String>,
     
     #[serde(alias = "Description", alias = "description", alias = "Описание")]
     pub description: Option<String>,
@@ -146,11 +246,11 @@ pub struct ReagentImportDto {
     #[serde(alias = "Lot number", alias = "Lot Number", alias = "batch_number", alias = "Партия")]
     pub batch_number: Option<String>,
     
-    #[serde(alias = "Pack_size", alias = "Pack size", alias = "Pack Size", alias = "PackSize", alias = "pack_size", alias = "Unit Size", alias = "UnitSize")]
-    pub pack_size: Option<f64>,
+    #[serde(alias = "Pack_size", alias = "Pack size", alias = "Pack Size", alias = "PackSize", alias = "pack_size", alias = "Unit Size", alias = "UnitSize", alias = "Фасовка")]
+    pub pack_size: Option<ImportNumber>,
     
     #[serde(alias = "Quantity", alias = "quantity", alias = "Количество")]
-    pub quantity: Option<f64>,
+    pub quantity: Option<ImportNumber>,
     
     #[serde(alias = "Units", alias = "units", alias = "Unit", alias = "unit", alias = "Единицы",)]
     pub units: Option<String>,
@@ -164,35 +264,100 @@ pub struct ReagentImportDto {
 
     #[serde(alias = "Hazard", alias = "hazard_pictograms", alias = "GHS", alias = "Pictograms", alias = "Hazard Pictograms")]
     pub hazard_pictograms: Option<String>,
+
+    #[serde(alias = "Shelf Life", alias = "Shelf life (days)", alias = "Default Shelf Life (days)", alias = "default_shelf_life_days", alias = "Срок годности по умолчанию (дни)", alias = "Срок хранения (дни)")]
+    pub default_shelf_life_days: Option<ImportNumber>,
+
+    #[serde(alias = "Expiry Policy", alias = "expiry_policy", alias = "Контроль сроков годности")]
+    pub expiry_policy: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BatchImportDto {
-    #[serde(alias = "Reagent Name", alias = "reagent_name")]
+    #[serde(alias = "Reagent Name", alias = "reagent_name", alias = "Реагент")]
     pub reagent_name: String,
-    #[serde(alias = "Batch Number", alias = "batch_number", alias = "Lot Number", alias = "Lot number")]
+    #[serde(alias = "Batch Number", alias = "batch_number", alias = "Lot Number", alias = "Lot number", alias = "Номер партии")]
     pub batch_number: String,
-    #[serde(alias = "Catalog Number", alias = "cat_number", alias = "Catalogue No", alias = "Catalog #")]
+    #[serde(alias = "Catalog Number", alias = "cat_number", alias = "Catalogue No", alias = "Catalog #", alias = "Каталожный номер")]
     pub cat_number: Option<String>,
+    #[serde(alias = "Supplier", alias = "Поставщик")]
     pub supplier: Option<String>,
-    #[serde(alias = "quantity", alias = "Quantity", alias = "Amount")]
-    pub quantity: f64, 
-    #[serde(alias = "unit", alias = "Unit", alias = "units", alias = "Units", alias = "Umits")]
+    #[serde(alias = "quantity", alias = "Quantity", alias = "Amount", alias = "Количество")]
+    pub quantity: ImportNumber,
+    #[serde(alias = "unit", alias = "Unit", alias = "units", alias = "Units", alias = "Umits", alias = "Единица")]
     pub units: String,
-    #[serde(alias = "Pack_size", alias = "Pack size", alias = "Pack Size", alias = "PackSize", alias = "pack_size", alias = "Unit Size")]
-    pub pack_size: Option<f64>,
+    #[serde(alias = "Pack_size", alias = "Pack size", alias = "Pack Size", alias = "PackSize", alias = "pack_size", alias = "Unit Size", alias = "Фасовка")]
+    pub pack_size: Option<ImportNumber>,
     
+    #[serde(alias = "expiry_date", alias = "Срок годности")]
     #[serde(default, deserialize_with = "deserialize_flexible_date")] // <--- ПРИМЕНЕНО ЗДЕСЬ
     pub expiration_date: Option<String>,
     
+    #[serde(alias = "Location", alias = "Место хранения")]
     pub location: Option<String>,
+    #[serde(alias = "Notes", alias = "Примечания")]
     pub notes: Option<String>,
 }
 
+/// Строка исторического расхода: партия задаётся номером партии и/или названием реагента
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageImportDto {
+    #[serde(default, alias = "Reagent", alias = "Reagent Name", alias = "reagent", alias = "Реагент")]
+    pub reagent_name: Option<String>,
+    #[serde(default, alias = "Batch Number", alias = "Lot Number", alias = "Lot number", alias = "batch", alias = "Партия")]
+    pub batch_number: Option<String>,
+    #[serde(alias = "Date", alias = "date", alias = "Used at", alias = "Дата")]
+    #[serde(default, deserialize_with = "deserialize_flexible_date")]
+    pub used_at: Option<String>,
+    #[serde(alias = "Quantity", alias = "quantity_used", alias = "Amount", alias = "Количество")]
+    pub quantity: ImportNumber,
+    #[serde(default, alias = "Unit", alias = "units", alias = "Units", alias = "Единицы")]
+    pub unit: Option<String>,
+    /// Email, логин или имя пользователя
+    #[serde(default, alias = "User", alias = "Email", alias = "email", alias = "Пользователь")]
+    pub user: Option<String>,
+    #[serde(default, alias = "Notes", alias = "Комментарий")]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageImportQuery {
+    /// Пересчитать original_quantity партий: текущий остаток + весь зафиксированный расход
+    #[serde(default)]
+    pub reconstruct_original: bool,
+    /// auto | dot | comma
+    #[serde(default)]
+    pub decimal_separator: DecimalSeparator,
+}
+
+/// Ошибка импорта с номером строки файла (строка 1 - заголовки)
+#[derive(Debug, Serialize)]
+pub struct ImportRowError {
+    pub row: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchImportReport {
+    pub total_rows: usize,
+    pub imported: usize,
+    pub failed: usize,
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageImportReport {
+    pub total_rows: usize,
+    pub imported: usize,
+    pub failed: usize,
+    pub batches_reconstructed: usize,
+    pub errors: Vec<ImportRowError>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EquipmentImportDto {
     pub name: String,
-    #[serde(alias = "type")]
+    #[serde(alias = "type", alias = "type_")]
     pub equipment_type: String,
     pub serial_number: Option<String>,
     pub manufacturer: Option<String>,
@@ -206,7 +371,12 @@ pub struct EquipmentImportDto {
 // HELPERS
 // ==========================================
 
-async fn save_multipart_to_temp(mut payload: Multipart) -> ApiResult<PathBuf> {
+async fn save_multipart_to_temp(payload: Multipart) -> ApiResult<PathBuf> {
+    save_multipart_upload(payload).await.map(|(path, _)| path)
+}
+
+/// Saves the first uploaded file to a temp file, returns (temp path, original file name)
+async fn save_multipart_upload(mut payload: Multipart) -> ApiResult<(PathBuf, String)> {
     let temp_dir = std::env::temp_dir();
     let file_name = format!("lims_import_{}.xlsx", Uuid::new_v4());
     let file_path = temp_dir.join(file_name);
@@ -215,18 +385,34 @@ async fn save_multipart_to_temp(mut payload: Multipart) -> ApiResult<PathBuf> {
         .map_err(|e| ApiError::InternalServerError(format!("Failed to create temp file: {}", e)))?;
 
     while let Ok(Some(mut field)) = payload.try_next().await {
-        if field.content_disposition().get_filename().is_some() {
+        if let Some(original_name) = field.content_disposition().get_filename().map(sanitize_original_filename) {
             while let Some(chunk) = field.next().await {
                 let data = chunk.map_err(|e| ApiError::BadRequest(e.to_string()))?;
                 f.write_all(&data)
                     .map_err(|e| ApiError::InternalServerError(format!("Failed to write to temp file: {}", e)))?;
             }
-            return Ok(file_path);
+            return Ok((file_path, original_name));
         }
     }
+    let _ = fs::remove_file(&file_path);
     Err(ApiError::BadRequest("No file found in request".to_string()))
 }
 
+/// Разбор загруженного файла в очереди xlsx_parse (spawn_blocking); при отказе очереди (429)
+/// временный файл удаляется
+async fn parse_in_queue<T, F>(file_path: &std::path::Path, parse: F) -> ApiResult<(T, WorkTicket)>
+where
+    F: FnOnce(PathBuf) -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let path = file_path.to_path_buf();
+    let result = work_queue::xlsx_parse_queue().run_blocking(move || parse(path)).await;
+    if result.is_err() {
+        let _ = fs::remove_file(file_path);
+    }
+    result
+}
+
 /// Preload all users into HashMap (username lowercase -> id)
 async fn preload_users(pool: &SqlitePool) -> ApiResult<HashMap<String, String>> {
     let rows = sqlx::query("SELECT username, id FROM users")
@@ -267,6 +453,56 @@ async fn preload_reagents(pool: &SqlitePool) -> ApiResult<HashMap<String, String
     Ok(map)
 }
 
+/// Сроки годности по умолчанию существующих реагентов (id -> дни)
+async fn preload_shelf_lives(pool: &SqlitePool) -> ApiResult<HashMap<String, i64>> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT id, default_shelf_life_days FROM reagents WHERE deleted_at IS NULL AND default_shelf_life_days IS NOT NULL"
+    )
+        .fetch_all(pool)
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Failed to preload reagents: {}", e)))?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Срок годности по умолчанию из строки импорта: целое число дней больше нуля
+fn resolve_shelf_life(value: Option<&ImportNumber>, separator: DecimalSeparator, owner: &str) -> ApiResult<Option<i64>> {
+    match resolve_import_number(value, separator, "default_shelf_life_days", owner)? {
+        Some(days) if days >= 1.0 && days.fract() == 0.0 => Ok(Some(days as i64)),
+        Some(_) => Err(ApiError::bad_request(&format!(
+            "Invalid default_shelf_life_days '{}' for '{}'", value.map(ImportNumber::raw).unwrap_or_default(), owner
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Политика сроков годности из строки импорта (регистр не важен; пусто - не задана)
+fn resolve_physical_state(
+    value: Option<&str>,
+    mapper: &crate::physical_state::PhysicalStateMapper,
+    owner: &str,
+) -> ApiResult<Option<PhysicalState>> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(state) => mapper.resolve(state)
+            .map(Some)
+            .ok_or_else(|| ApiError::bad_request(&format!(
+                "Invalid physical_state '{}' for '{}', expected one of: {}",
+                state, owner, <PhysicalState as strum::VariantNames>::VARIANTS.join(", ")
+            ))),
+        None => Ok(None),
+    }
+}
+
+fn resolve_expiry_policy(value: Option<&str>, owner: &str) -> ApiResult<Option<&'static str>> {
+    match value.map(|v| v.trim().to_lowercase()).filter(|v| !v.is_empty()) {
+        Some(policy) => crate::models::EXPIRY_POLICIES.iter()
+            .find(|&&p| p == policy)
+            .copied()
+            .map(Some)
+            .ok_or_else(|| ApiError::bad_request(&format!("Invalid expiry_policy '{}' for '{}'", policy, owner))),
+        None => Ok(None),
+    }
+}
+
 // ==========================================
 // PRAGMA OPTIMIZATION (for bulk imports)
 // ==========================================
@@ -307,6 +543,9 @@ struct PreparedReagent {
     appearance: Option<String>,
     hazard_pictograms: Option<String>,
     molecular_weight: Option<f64>,
+    physical_state: Option<PhysicalState>,
+    default_shelf_life_days: Option<i64>,
+    expiry_policy: Option<&'static str>,
     owner_id: String,
     created_at: String,
 }
@@ -331,15 +570,15 @@ struct PreparedBatch {
 pub async fn import_reagents_excel(
     app_state: web::Data<Arc<AppState>>,
     payload: Multipart,
+    query: web::Query<ImportQuery>,
     req: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = get_current_user(&req)?;
     let current_user_id = claims.sub;
 
     let file_path = save_multipart_to_temp(payload).await?;
-    let path_clone = file_path.clone();
     
-    let reagents_result = web::block(move || {
+    let (reagents_result, ticket) = parse_in_queue(&file_path, move |path_clone| {
         let mut workbook: Xlsx<_> = open_workbook(&path_clone)
             .map_err(|e: XlsxError| format!("Excel error: {}", e))?;
         
@@ -370,7 +609,7 @@ pub async fn import_reagents_excel(
         }
 
         Ok::<Vec<ReagentImportDto>, String>(reagents)
-    }).await.map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    }).await?;
 
     let reagents = match reagents_result {
         Ok(r) => r,
@@ -380,32 +619,39 @@ pub async fn import_reagents_excel(
         }
     };
 
-    let imported_count = import_reagents_logic(&app_state.db_pool, reagents, current_user_id).await;
+    let imported_count = import_reagents_logic(&app_state.db_pool, reagents, current_user_id, query.decimal_separator).await;
     let _ = fs::remove_file(file_path);
 
     let count = imported_count?;
-    Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), format!("Imported {} items", count))))
+    Ok(ticket.annotate(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), format!("Imported {} items", count)))))
 }
 
 pub async fn import_reagents_json(
     app_state: web::Data<Arc<AppState>>,
     body: web::Json<Vec<ReagentImportDto>>,
+    query: web::Query<ImportQuery>,
     req: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = get_current_user(&req)?;
-    let count = import_reagents_logic(&app_state.db_pool, body.into_inner(), claims.sub).await?;
+    let count = import_reagents_logic(&app_state.db_pool, body.into_inner(), claims.sub, query.decimal_separator).await?;
     Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), format!("Imported {} reagents", count))))
 }
 
 pub async fn import_reagents(
     app_state: web::Data<Arc<AppState>>,
     body: web::Json<Vec<ReagentImportDto>>,
+    query: web::Query<ImportQuery>,
     req: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    import_reagents_json(app_state, body, req).await
+    import_reagents_json(app_state, body, query, req).await
 }
 
-async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto>, current_user_id: String) -> ApiResult<usize> {
+async fn import_reagents_logic(
+    pool: &SqlitePool,
+    reagents: Vec<ReagentImportDto>,
+    current_user_id: String,
+    decimal_separator: DecimalSeparator,
+) -> ApiResult<usize> {
     let total_items = reagents.len();
     let start_time = Instant::now();
     
@@ -417,6 +663,8 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
     // Preload all users and reagents ONCE
     let users_map = preload_users(pool).await?;
     let mut reagents_map = preload_reagents(pool).await?;
+    let mut shelf_lives = preload_shelf_lives(pool).await?;
+    let state_mapper = crate::physical_state::PhysicalStateMapper::load(&mut *pool.acquire().await?).await?;
     
     log::info!("📦 Preloaded {} users, {} reagents", users_map.len(), reagents_map.len());
     
@@ -429,6 +677,12 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
         if name.is_empty() { continue; }
         
         let name_key = name.to_lowercase();
+        let molecular_weight = resolve_import_number(r.molecular_weight.as_ref(), decimal_separator, "molecular_weight", name)?;
+        let quantity = resolve_import_number(r.quantity.as_ref(), decimal_separator, "quantity", name)?;
+        let pack_size = resolve_import_number(r.pack_size.as_ref(), decimal_separator, "pack_size", name)?;
+        let default_shelf_life_days = resolve_shelf_life(r.default_shelf_life_days.as_ref(), decimal_separator, name)?;
+        let expiry_policy = resolve_expiry_policy(r.expiry_policy.as_deref(), name)?;
+        let physical_state = resolve_physical_state(r.physical_state.as_deref(), &state_mapper, name)?;
         
         let owner_id = r.owner.as_ref()
             .and_then(|o| users_map.get(&o.trim().to_lowercase()))
@@ -443,6 +697,9 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
             .entry(name_key)
             .or_insert_with(|| Uuid::new_v4().to_string())
             .clone();
+        if let Some(days) = default_shelf_life_days {
+            shelf_lives.insert(reagent_id.clone(), days);
+        }
         
         prepared_reagents.push(PreparedReagent {
             id: reagent_id.clone(),
@@ -454,13 +711,16 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
             storage: r.storage.clone(),
             appearance: r.appearance.clone(),
             hazard_pictograms: r.hazard_pictograms.clone(),
-            molecular_weight: r.molecular_weight,
+            molecular_weight,
+            physical_state,
+            default_shelf_life_days,
+            expiry_policy,
             owner_id: owner_id.clone(),
             created_at,
         });
         
         // Prepare batch if present
-        if let (Some(batch_num), Some(qty), Some(unit)) = (&r.batch_number, r.quantity, &r.units) {
+        if let (Some(batch_num), Some(qty), Some(unit)) = (&r.batch_number, quantity, &r.units) {
             if !batch_num.trim().is_empty() && qty > 0.0 {
                 prepared_batches.push(PreparedBatch {
                     id: Uuid::new_v4().to_string(),
@@ -469,8 +729,12 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
                     cat_number: r.catalog_number.clone(),
                     quantity: qty,
                     unit: unit.clone(),
-                    pack_size: r.pack_size,
-                    expiry_date: r.expiry_date.clone(),
+                    pack_size,
+                    // Без срока годности в строке - дата импорта + срок реагента по умолчанию
+                    expiry_date: r.expiry_date.clone().filter(|d| !d.trim().is_empty()).or_else(|| {
+                        shelf_lives.get(&reagent_id)
+                            .map(|&days| (Utc::now() + chrono::Duration::days(days)).to_rfc3339())
+                    }),
                     location: r.location.clone(),
                     owner_id: owner_id,
                 });
@@ -494,15 +758,16 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
     
     for chunk in prepared_reagents.chunks(REAGENT_CHUNK_SIZE) {
         let values_clause: String = chunk.iter()
-            .map(|_| "(?,?,?,?,?,?,?,?,?,?,?,?,?,datetime('now'))")
+            .map(|_| "(?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,datetime('now'))")
             .collect::<Vec<_>>()
             .join(",");
         
+        // Политика без значения в строке не перезаписывает существующую (см. ниже)
         let sql = format!(
             r#"INSERT INTO reagents (
                 id, name, formula, cas_number, manufacturer, description,
                 storage_conditions, appearance, hazard_pictograms, status, 
-                molecular_weight, created_by, created_at, updated_at
+                molecular_weight, physical_state, default_shelf_life_days, expiry_policy, created_by, created_at, updated_at
             ) VALUES {}
             ON CONFLICT(name) DO UPDATE SET 
                 formula = COALESCE(excluded.formula, formula),
@@ -513,6 +778,8 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
                 appearance = COALESCE(excluded.appearance, appearance),
                 hazard_pictograms = COALESCE(excluded.hazard_pictograms, hazard_pictograms),
                 molecular_weight = COALESCE(excluded.molecular_weight, molecular_weight),
+                physical_state = COALESCE(excluded.physical_state, physical_state),
+                default_shelf_life_days = COALESCE(excluded.default_shelf_life_days, default_shelf_life_days),
                 updated_at = datetime('now')"#,
             values_clause
         );
@@ -531,6 +798,9 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
                 .bind(&r.hazard_pictograms)
                 .bind("active")
                 .bind(&r.molecular_weight)
+                .bind(r.physical_state.as_ref().map(|s| s.as_ref()))
+                .bind(r.default_shelf_life_days)
+                .bind(r.expiry_policy.unwrap_or(crate::models::EXPIRY_POLICY_STRICT))
                 .bind(&r.owner_id)
                 .bind(&r.created_at);
         }
@@ -544,6 +814,26 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
         }
     }
     log::info!("📥 Reagents complete: {}", processed_reagents);
+
+    // Явно заданные политики - и для уже существующих реагентов
+    for policy in crate::models::EXPIRY_POLICIES {
+        let ids: Vec<&str> = prepared_reagents.iter()
+            .filter(|r| r.expiry_policy == Some(*policy))
+            .map(|r| r.id.as_str())
+            .collect();
+        for chunk in ids.chunks(REAGENT_CHUNK_SIZE) {
+            let sql = format!(
+                "UPDATE reagents SET expiry_policy = ? WHERE id IN ({})",
+                vec!["?"; chunk.len()].join(",")
+            );
+            let mut query = sqlx::query(&sql).bind(*policy);
+            for id in chunk {
+                query = query.bind(*id);
+            }
+            query.execute(&mut *tx).await
+                .map_err(|e| ApiError::InternalServerError(format!("Bulk reagent insert failed: {}", e)))?;
+        }
+    }
     
     // PHASE 3: Bulk insert batches
     const BATCH_CHUNK_SIZE: usize = 60;
@@ -616,11 +906,40 @@ async fn import_reagents_logic(pool: &SqlitePool, reagents: Vec<ReagentImportDto
     };
     
     log::info!("✅ BULK import completed in {:.2?}. {} items at {:.0} items/sec", elapsed, total_items, rate);
+    crate::kpi::record(crate::kpi::Kpi::ImportsRun, "reagents");
 
     Ok(total_items)
 }
 
-pub async fn export_reagents(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+pub async fn export_reagents(
+    app_state: web::Data<Arc<AppState>>,
+    locale: web::Query<ExportLocaleQuery>,
+) -> ApiResult<HttpResponse> {
+    let style = ExportStyle::from_query(&locale)?;
+    let ticket = work_queue::export_queue().acquire().await?;
+    let reagents = load_reagents_export(app_state.read_pool()).await?;
+    Ok(ticket.annotate(localized_export_response("reagents", reagents, &style)?))
+}
+
+/// Ответ экспорта сущности: без locale - прежний JSON, с excel_locale - CSV-файл,
+/// иначе JSON с подписями колонок и датами локали
+fn localized_export_response<T: Serialize>(stem: &str, rows: Vec<T>, style: &ExportStyle) -> ApiResult<HttpResponse> {
+    if style.is_default() {
+        return Ok(HttpResponse::Ok().json(rows));
+    }
+    let (headers, values) = crate::export_archive::to_table(rows)
+        .map_err(|e| ApiError::internal_error(e.to_string()))?;
+    if style.wants_csv() {
+        return Ok(HttpResponse::Ok()
+            .insert_header(("Content-Type", "text/csv; charset=utf-8"))
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.csv\"", stem)))
+            .body(style.csv_table(&headers, &values)));
+    }
+    Ok(HttpResponse::Ok().json(style.localize_rows(&headers, &values)))
+}
+
+/// Данные экспорта реагентов (общие для /reagents/export и архива)
+pub(crate) async fn load_reagents_export(pool: &SqlitePool) -> ApiResult<Vec<crate::models::Reagent>> {
     let whitelist = FieldWhitelist::for_reagents();
     let builder = SafeQueryBuilder::new("SELECT * FROM reagents WHERE deleted_at IS NULL")
         .map_err(|e| ApiError::InternalServerError(e))?
@@ -629,32 +948,38 @@ pub async fn export_reagents(app_state: web::Data<Arc<AppState>>) -> ApiResult<H
     let (sql, _) = builder.build();
     
     let reagents = sqlx::query_as::<_, crate::models::Reagent>(&sql)
-        .fetch_all(&app_state.db_pool)
+        .fetch_all(pool)
         .await?;
     
-    Ok(HttpResponse::Ok().json(reagents))
+    Ok(reagents)
 }
 
 // ==========================================
 // BATCHES IMPORT (OPTIMIZED)
 // ==========================================
 
+fn batch_import_message(report: &BatchImportReport) -> String {
+    format!("Imported {} of {} batches, {} rows rejected", report.imported, report.total_rows, report.failed)
+}
+
 pub async fn import_batches_json(
     app_state: web::Data<Arc<AppState>>,
     body: web::Json<Vec<BatchImportDto>>,
+    query: web::Query<ImportQuery>,
 ) -> ApiResult<HttpResponse> {
-    let count = import_batches_logic(&app_state.db_pool, body.into_inner()).await?;
-    Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), format!("Imported {} batches", count))))
+    let report = import_batches_logic(&app_state.db_pool, body.into_inner(), query.decimal_separator).await?;
+    let message = batch_import_message(&report);
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(report, message)))
 }
 
 pub async fn import_batches_excel(
     app_state: web::Data<Arc<AppState>>,
     payload: Multipart,
+    query: web::Query<ImportQuery>,
 ) -> ApiResult<HttpResponse> {
     let file_path = save_multipart_to_temp(payload).await?;
-    let path_clone = file_path.clone();
 
-    let batches_result = web::block(move || {
+    let (batches_result, ticket) = parse_in_queue(&file_path, move |path_clone| {
         let mut workbook: Xlsx<_> = open_workbook(&path_clone)
             .map_err(|e: XlsxError| e.to_string())?;
         let range = workbook.worksheet_range_at(0)
@@ -670,13 +995,14 @@ pub async fn import_batches_excel(
             }
         }
         Ok::<Vec<BatchImportDto>, String>(list)
-    }).await.map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    }).await?;
 
     match batches_result {
         Ok(batches) => {
-            let count = import_batches_logic(&app_state.db_pool, batches).await?;
-            let _ = fs::remove_file(file_path);
-            Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), format!("Imported {} batches", count))))
+            let _ = fs::remove_file(&file_path);
+            let report = import_batches_logic(&app_state.db_pool, batches, query.decimal_separator).await?;
+            let message = batch_import_message(&report);
+            Ok(ticket.annotate(HttpResponse::Ok().json(ApiResponse::success_with_message(report, message))))
         }
         Err(e) => {
             let _ = fs::remove_file(file_path);
@@ -685,192 +1011,231 @@ pub async fn import_batches_excel(
     }
 }
 
-pub async fn import_batches(app_state: web::Data<Arc<AppState>>, body: web::Json<Vec<BatchImportDto>>) -> ApiResult<HttpResponse> {
-    import_batches_json(app_state, body).await
+pub async fn import_batches(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<Vec<BatchImportDto>>,
+    query: web::Query<ImportQuery>,
+) -> ApiResult<HttpResponse> {
+    import_batches_json(app_state, body, query).await
 }
 
-async fn import_batches_logic(pool: &SqlitePool, batches: Vec<BatchImportDto>) -> ApiResult<usize> {
-    let total_items = batches.len();
+/// Проверки партий над staging-таблицей: (условие, сообщение - SQL-выражение над строкой).
+/// Выполняются по порядку; строка получает первую найденную ошибку
+const BATCH_STAGE_CHECKS: &[(&str, &str)] = &[
+    ("reagent_key = ''", "'Reagent name is required'"),
+    ("batch_number = ''", "'Batch number is required'"),
+    ("length(batch_number) > 100", "'Batch number cannot exceed 100 characters'"),
+    ("quantity IS NULL OR NOT quantity > 0", "'Quantity must be greater than 0'"),
+    ("pack_size IS NOT NULL AND NOT pack_size > 0", "'Pack size must be positive'"),
+    ("unit NOT IN (SELECT value FROM json_each(?1))", "'Invalid unit ''' || unit || ''''"),
+    ("expiry_date IS NOT NULL AND julianday(expiry_date) IS NULL", "'Invalid expiry date ''' || expiry_date || ''''"),
+    ("reagent_id IS NULL", "'Reagent ''' || reagent_name || ''' not found'"),
+];
+
+async fn import_batches_logic(
+    pool: &SqlitePool,
+    batches: Vec<BatchImportDto>,
+    decimal_separator: DecimalSeparator,
+) -> ApiResult<BatchImportReport> {
+    let total_rows = batches.len();
     let start_time = Instant::now();
-    
-    log::info!("🚀 Starting BULK batch import of {} items...", total_items);
-    
-    // Apply PRAGMA optimizations
+
+    log::info!("🚀 Starting staged batch import of {} rows...", total_rows);
+
+    // PHASE 1: разбор строк в памяти; нераспознанные числа - ошибка строки
+    let staged: Vec<serde_json::Value> = batches.iter().enumerate().map(|(index, b)| {
+        let owner = b.batch_number.trim();
+        let amounts = resolve_import_number(Some(&b.quantity), decimal_separator, "quantity", owner)
+            .and_then(|quantity| Ok((quantity, resolve_import_number(b.pack_size.as_ref(), decimal_separator, "pack_size", owner)?)));
+        let (quantity, pack_size, error) = match amounts {
+            Ok((quantity, pack_size)) => (quantity, pack_size, None),
+            Err(e) => (None, None, Some(e.to_string())),
+        };
+        let text = |value: &Option<String>| value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+        serde_json::json!({
+            // Строка 1 - заголовки
+            "row": index + 2,
+            "id": Uuid::new_v4().to_string(),
+            "reagent_name": b.reagent_name.trim(),
+            "reagent_key": b.reagent_name.trim().to_lowercase(),
+            "batch_number": owner,
+            "cat_number": text(&b.cat_number),
+            "supplier": text(&b.supplier),
+            "quantity": quantity,
+            "unit": b.units.trim(),
+            "pack_size": pack_size,
+            "expiry_date": text(&b.expiration_date),
+            "location": text(&b.location),
+            "notes": text(&b.notes),
+            "error": error,
+        })
+    }).collect();
+    // Ключи реагентов считаются в Rust: lower() SQLite не приводит кириллицу
+    let reagent_keys: Vec<serde_json::Value> = preload_reagents(pool).await?
+        .into_iter()
+        .map(|(key, id)| serde_json::json!({ "key": key, "id": id }))
+        .collect();
+
     optimize_sqlite_for_bulk(pool).await?;
-    
-    // Preload reagents map
-    let mut reagent_map = preload_reagents(pool).await?;
-    
-    // PHASE 1: Find and create missing reagents first
-    let mut new_reagents: Vec<(String, String)> = Vec::new(); // (id, name)
-    for b in &batches {
-        let r_name_raw = b.reagent_name.trim();
-        if r_name_raw.is_empty() { continue; }
-        
-        let r_name_key = r_name_raw.to_lowercase();
-        if !reagent_map.contains_key(&r_name_key) {
-            let new_id = Uuid::new_v4().to_string();
-            reagent_map.insert(r_name_key, new_id.clone());
-            new_reagents.push((new_id, r_name_raw.to_string()));
-        }
-    }
-    
-    // Bulk insert new reagents in single transaction
-    if !new_reagents.is_empty() {
-        log::info!("📦 Creating {} new reagents...", new_reagents.len());
-        
-        sqlx::query("PRAGMA synchronous = OFF").execute(pool).await
-            .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
-        
-        let mut tx = pool.begin().await
-            .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
-        
-        const REAGENT_CHUNK: usize = 200;
-        for chunk in new_reagents.chunks(REAGENT_CHUNK) {
-            let values_clause: String = chunk.iter()
-                .map(|_| "(?,?,'active',datetime('now'),datetime('now'))")
-                .collect::<Vec<_>>()
-                .join(",");
-            
-            let sql = format!(
-                "INSERT OR IGNORE INTO reagents (id, name, status, created_at, updated_at) VALUES {}",
-                values_clause
-            );
-            
-            let mut query = sqlx::query(&sql);
-            for (id, name) in chunk {
-                query = query.bind(id).bind(name);
-            }
-            
-            query.execute(&mut *tx).await
-                .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
-        }
-        
-        tx.commit().await
-            .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    sqlx::query("PRAGMA synchronous = OFF").execute(pool).await?;
+
+    // Временные таблицы живут в соединении транзакции
+    let mut tx = pool.begin().await?;
+    for sql in [
+        "DROP TABLE IF EXISTS temp.batch_import_stage",
+        "DROP TABLE IF EXISTS temp.batch_import_reagents",
+        r#"CREATE TEMP TABLE batch_import_stage (
+            row INTEGER PRIMARY KEY,
+            id TEXT NOT NULL,
+            reagent_name TEXT NOT NULL,
+            reagent_key TEXT NOT NULL,
+            reagent_id TEXT,
+            batch_number TEXT NOT NULL,
+            cat_number TEXT,
+            supplier TEXT,
+            quantity REAL,
+            unit TEXT NOT NULL,
+            pack_size REAL,
+            expiry_date TEXT,
+            location TEXT,
+            notes TEXT,
+            error TEXT
+        )"#,
+        "CREATE TEMP TABLE batch_import_reagents (key TEXT PRIMARY KEY, id TEXT NOT NULL)",
+    ] {
+        sqlx::query(sql).execute(&mut *tx).await?;
     }
-    
-    // PHASE 2: Prepare batches with resolved reagent IDs
-    struct PrepBatch {
-        id: String,
-        reagent_id: String,
-        batch_number: String,
-        cat_number: Option<String>,
-        supplier: Option<String>,
-        quantity: f64,
-        units: String,
-        pack_size: Option<f64>,
-        expiration_date: Option<String>,
-        location: Option<String>,
-        notes: Option<String>,
+
+    // PHASE 2: загрузка одним INSERT из JSON-массива (без предела числа параметров)
+    sqlx::query(
+        r#"INSERT INTO batch_import_stage (row, id, reagent_name, reagent_key, batch_number, cat_number, supplier,
+                                           quantity, unit, pack_size, expiry_date, location, notes, error)
+           SELECT json_extract(value, '$.row'), json_extract(value, '$.id'), json_extract(value, '$.reagent_name'),
+                  json_extract(value, '$.reagent_key'), json_extract(value, '$.batch_number'),
+                  json_extract(value, '$.cat_number'), json_extract(value, '$.supplier'), json_extract(value, '$.quantity'),
+                  json_extract(value, '$.unit'), json_extract(value, '$.pack_size'), json_extract(value, '$.expiry_date'),
+                  json_extract(value, '$.location'), json_extract(value, '$.notes'), json_extract(value, '$.error')
+           FROM json_each(?)"#
+    )
+        .bind(serde_json::Value::Array(staged).to_string())
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"INSERT OR IGNORE INTO batch_import_reagents (key, id)
+           SELECT json_extract(value, '$.key'), json_extract(value, '$.id') FROM json_each(?)"#
+    )
+        .bind(serde_json::Value::Array(reagent_keys).to_string())
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "UPDATE batch_import_stage SET reagent_id = k.id FROM batch_import_reagents k WHERE k.key = batch_import_stage.reagent_key"
+    )
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("CREATE INDEX temp.idx_batch_import_stage_key ON batch_import_stage(reagent_id, batch_number)")
+        .execute(&mut *tx)
+        .await?;
+
+    // Партии деактивированных реагентов не добавляются: импорт отклоняется целиком
+    let inactive: Option<String> = sqlx::query_scalar(
+        r#"SELECT r.name FROM batch_import_stage s
+           JOIN reagents r ON r.id = s.reagent_id
+           WHERE r.status = 'inactive' ORDER BY s.row LIMIT 1"#
+    )
+        .fetch_optional(&mut *tx)
+        .await?;
+    if let Some(name) = inactive {
+        return Err(ApiError::reagent_inactive(&name));
     }
-    
-    let mut prepared: Vec<PrepBatch> = Vec::with_capacity(total_items);
-    for b in &batches {
-        let r_name_raw = b.reagent_name.trim();
-        if b.batch_number.trim().is_empty() || r_name_raw.is_empty() { continue; }
-        
-        let r_name_key = r_name_raw.to_lowercase();
-        let r_id = reagent_map.get(&r_name_key).cloned().unwrap_or_default();
-        if r_id.is_empty() { continue; }
-        
-        prepared.push(PrepBatch {
-            id: Uuid::new_v4().to_string(),
-            reagent_id: r_id,
-            batch_number: b.batch_number.trim().to_string(),
-            cat_number: b.cat_number.clone(),
-            supplier: b.supplier.clone(),
-            quantity: b.quantity,
-            units: b.units.clone(),
-            pack_size: b.pack_size,
-            expiration_date: b.expiration_date.clone(),
-            location: b.location.clone(),
-            notes: b.notes.clone(),
-        });
+
+    // PHASE 3: проверки по множеству строк
+    let units = serde_json::to_string(crate::validator::VALID_UNITS).unwrap_or_default();
+    for (condition, message) in BATCH_STAGE_CHECKS {
+        sqlx::query(&format!(
+            "UPDATE batch_import_stage SET error = {} WHERE error IS NULL AND ({})", message, condition
+        ))
+            .bind(&units)
+            .execute(&mut *tx)
+            .await?;
     }
-    
-    log::info!("📋 Prepared {} batches for bulk insert", prepared.len());
-    
-    // === PRAGMA BEFORE TRANSACTION ===
-    sqlx::query("PRAGMA synchronous = OFF").execute(pool).await
-        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
-    
-    // === SINGLE TRANSACTION FOR ENTIRE IMPORT ===
-    let mut tx = pool.begin().await
-        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
-    
-    const BATCH_CHUNK: usize = 60;
-    let mut processed = 0;
+    // Повтор партии в файле: ошибка у всех вхождений, кроме первого
+    sqlx::query(
+        r#"UPDATE batch_import_stage SET error = 'Duplicate of row ' || d.first_row
+           FROM (SELECT reagent_id, batch_number, MIN(row) AS first_row FROM batch_import_stage
+                 WHERE error IS NULL GROUP BY reagent_id, batch_number HAVING COUNT(*) > 1) d
+           WHERE batch_import_stage.error IS NULL
+             AND batch_import_stage.reagent_id = d.reagent_id
+             AND batch_import_stage.batch_number = d.batch_number
+             AND batch_import_stage.row > d.first_row"#
+    )
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"UPDATE batch_import_stage SET error = 'Batch ''' || b.batch_number || ''' already exists for reagent ''' || reagent_name || ''''
+           FROM batches b
+           WHERE batch_import_stage.error IS NULL
+             AND b.reagent_id = batch_import_stage.reagent_id
+             AND b.batch_number = batch_import_stage.batch_number"#
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    // PHASE 4: запись прошедших проверку строк
     let now = Utc::now().to_rfc3339();
-    
-    for chunk in prepared.chunks(BATCH_CHUNK) {
-        let values_clause: String = chunk.iter()
-            .map(|_| "(?,?,?,?,?,?,?,0.0,?,?,?,?,?,?,datetime('now'),'available')")
-            .collect::<Vec<_>>()
-            .join(",");
-        
-        let sql = format!(
-            r#"INSERT INTO batches (
-                id, reagent_id, batch_number, cat_number, supplier, 
-                quantity, original_quantity, reserved_quantity,
-                unit, pack_size, expiry_date, received_date,
-                location, notes, updated_at, status
-            ) VALUES {}
-            ON CONFLICT(reagent_id, batch_number) DO UPDATE SET 
-                quantity = quantity + excluded.quantity,
-                original_quantity = original_quantity + excluded.original_quantity,
-                pack_size = COALESCE(excluded.pack_size, pack_size),
-                cat_number = COALESCE(excluded.cat_number, cat_number)"#,
-            values_clause
-        );
-        
-        let mut query = sqlx::query(&sql);
-        for b in chunk {
-            query = query
-                .bind(&b.id)
-                .bind(&b.reagent_id)
-                .bind(&b.batch_number)
-                .bind(&b.cat_number)
-                .bind(&b.supplier)
-                .bind(b.quantity)
-                .bind(b.quantity)
-                .bind(&b.units)
-                .bind(&b.pack_size)
-                .bind(&b.expiration_date)
-                .bind(&now)
-                .bind(&b.location)
-                .bind(&b.notes);
-        }
-        
-        query.execute(&mut *tx).await
-            .map_err(|e| ApiError::InternalServerError(format!("Bulk batch insert failed: {}", e)))?;
-        
-        processed += chunk.len();
-        if processed % 50000 == 0 {
-            log::info!("📥 Batches: {}/{}", processed, prepared.len());
-        }
-    }
-    
-    // === SINGLE COMMIT ===
-    tx.commit().await
-        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
-    
-    // Restore safe mode
-    sqlx::query("PRAGMA synchronous = NORMAL").execute(pool).await
-        .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
-    
-    let elapsed = start_time.elapsed();
-    let rate = if elapsed.as_secs_f64() > 0.0 { 
-        total_items as f64 / elapsed.as_secs_f64() 
-    } else { 
-        0.0 
-    };
-    log::info!("✅ BULK batch import completed in {:.2?}. {} items at {:.0} items/sec", elapsed, total_items, rate);
-    
-    Ok(total_items)
+    let imported = sqlx::query(
+        r#"INSERT INTO batches (
+               id, reagent_id, batch_number, cat_number, supplier,
+               quantity, original_quantity, reserved_quantity,
+               unit, pack_size, expiry_date, received_date,
+               location, notes, created_at, updated_at, status
+           )
+           SELECT id, reagent_id, batch_number, cat_number, supplier,
+                  quantity, quantity, 0.0,
+                  unit, pack_size, expiry_date, ?1,
+                  location, notes, ?1, ?1, 'available'
+           FROM batch_import_stage WHERE error IS NULL ORDER BY row"#
+    )
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected() as usize;
+
+    let errors: Vec<ImportRowError> = sqlx::query_as::<_, (i64, String)>(
+        "SELECT row, error FROM batch_import_stage WHERE error IS NOT NULL ORDER BY row"
+    )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(row, message)| ImportRowError { row: row as usize, message })
+        .collect();
+
+    sqlx::query("DROP TABLE temp.batch_import_stage").execute(&mut *tx).await?;
+    sqlx::query("DROP TABLE temp.batch_import_reagents").execute(&mut *tx).await?;
+    tx.commit().await?;
+
+    sqlx::query("PRAGMA synchronous = NORMAL").execute(pool).await?;
+
+    log::info!(
+        "✅ Staged batch import completed in {:.2?}: {} of {} rows imported",
+        start_time.elapsed(), imported, total_rows
+    );
+    crate::kpi::record(crate::kpi::Kpi::ImportsRun, "batches");
+
+    Ok(BatchImportReport { total_rows, imported, failed: errors.len(), errors })
+}
+
+pub async fn export_batches(
+    app_state: web::Data<Arc<AppState>>,
+    locale: web::Query<ExportLocaleQuery>,
+) -> ApiResult<HttpResponse> {
+    let style = ExportStyle::from_query(&locale)?;
+    let ticket = work_queue::export_queue().acquire().await?;
+    let batches = load_batches_export(app_state.read_pool()).await?;
+    Ok(ticket.annotate(localized_export_response("batches", batches, &style)?))
 }
 
-pub async fn export_batches(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+/// Данные экспорта партий (общие для /batches/export и архива)
+pub(crate) async fn load_batches_export(pool: &SqlitePool) -> ApiResult<Vec<crate::models::Batch>> {
     let whitelist = FieldWhitelist::for_batches();
     let builder = SafeQueryBuilder::new("SELECT * FROM batches")
         .map_err(|e| ApiError::InternalServerError(e))?
@@ -878,9 +1243,356 @@ pub async fn export_batches(app_state: web::Data<Arc<AppState>>) -> ApiResult<Ht
     
     let (sql, _) = builder.build();
     let batches = sqlx::query_as::<_, crate::models::Batch>(&sql)
-        .fetch_all(&app_state.db_pool)
+        .fetch_all(pool)
         .await?;
-    Ok(HttpResponse::Ok().json(batches))
+    Ok(batches)
+}
+
+// ==========================================
+// USAGE HISTORY IMPORT
+// ==========================================
+
+/// Допуск на погрешность округления при сравнении количеств
+const USAGE_EPSILON: f64 = 1e-9;
+
+type UsageRow = (usize, Result<UsageImportDto, String>);
+
+/// Читает строки csv/xlsx; номер строки соответствует строке файла (заголовок - строка 1)
+fn read_usage_rows(path: &std::path::Path, is_csv: bool) -> Result<Vec<UsageRow>, String> {
+    if is_csv {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(sniff_csv_delimiter(path))
+            .trim(csv::Trim::All)
+            .flexible(true)
+            .from_path(path)
+            .map_err(|e| format!("CSV error: {}", e))?;
+        let headers = reader.headers().map_err(|e| format!("CSV error: {}", e))?.clone();
+        // Ячейки передаются текстом (csv сам распознал бы "1.25" как число),
+        // чтобы десятичный разделитель разбирался при импорте
+        return Ok(reader.records()
+            .enumerate()
+            .map(|(i, r)| (i + 2, r.map_err(|e| e.to_string()).and_then(|record| {
+                let row: serde_json::Map<String, serde_json::Value> = headers.iter()
+                    .zip(record.iter())
+                    .map(|(h, v)| (h.to_string(), match v {
+                        "" => serde_json::Value::Null,
+                        text => serde_json::Value::String(text.to_string()),
+                    }))
+                    .collect();
+                serde_json::from_value::<UsageImportDto>(serde_json::Value::Object(row)).map_err(|e| e.to_string())
+            })))
+            .collect());
+    }
+
+    let mut workbook: Xlsx<_> = open_workbook(path)
+        .map_err(|e: XlsxError| format!("Excel error: {}", e))?;
+    let range = workbook.worksheet_range_at(0)
+        .ok_or("Excel file is empty".to_string())?
+        .map_err(|e| e.to_string())?;
+    let iter = RangeDeserializerBuilder::new().from_range(&range)
+        .map_err(|e| format!("Header error: {}", e))?;
+    Ok(iter.enumerate()
+        .map(|(i, r)| (i + 2, r.map_err(|e| e.to_string())))
+        .collect())
+}
+
+/// Excel в русской локали сохраняет CSV через ';' - разделитель определяется по строке заголовков
+fn sniff_csv_delimiter(path: &std::path::Path) -> u8 {
+    use std::io::BufRead;
+    let mut header = String::new();
+    if let Ok(file) = fs::File::open(path) {
+        let _ = std::io::BufReader::new(file).read_line(&mut header);
+    }
+    if header.matches(';').count() > header.matches(',').count() { b';' } else { b',' }
+}
+
+fn parse_usage_timestamp(value: &str) -> Option<NaiveDateTime> {
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%d.%m.%Y %H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(value, fmt).ok())
+}
+
+struct UsageBatchRef {
+    id: String,
+    reagent_id: String,
+    reagent_name: String,
+    batch_number: String,
+    unit: String,
+    quantity: f64,
+    original_quantity: f64,
+}
+
+struct PreparedUsage {
+    row: usize,
+    batch: usize,
+    user_id: Option<String>,
+    quantity: f64,
+    unit: String,
+    notes: Option<String>,
+    used_at: NaiveDateTime,
+}
+
+/// Preload users for usage import (email / username / name lowercase -> id)
+async fn preload_user_identities(pool: &SqlitePool) -> ApiResult<HashMap<String, String>> {
+    let rows: Vec<(String, String, String, Option<String>)> =
+        sqlx::query_as("SELECT id, username, email, name FROM users")
+            .fetch_all(pool)
+            .await?;
+
+    let mut map = HashMap::new();
+    for (id, username, email, _) in &rows {
+        map.insert(email.trim().to_lowercase(), id.clone());
+        map.insert(username.trim().to_lowercase(), id.clone());
+    }
+    // Имена не уникальны: используем только однозначные и не перекрывающие логины/email
+    let mut name_counts: HashMap<String, usize> = HashMap::new();
+    for (_, _, _, name) in &rows {
+        if let Some(name) = name {
+            *name_counts.entry(name.trim().to_lowercase()).or_default() += 1;
+        }
+    }
+    for (id, _, _, name) in &rows {
+        if let Some(key) = name.as_ref().map(|n| n.trim().to_lowercase()) {
+            if name_counts.get(&key) == Some(&1) {
+                map.entry(key).or_insert_with(|| id.clone());
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// Находит партию по номеру (и реагенту, если указан) либо единственную партию реагента
+fn resolve_usage_batch(batches: &[UsageBatchRef], dto: &UsageImportDto) -> Result<usize, String> {
+    let reagent = dto.reagent_name.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    let batch_number = dto.batch_number.as_deref().map(str::trim).filter(|s| !s.is_empty());
+
+    let candidates: Vec<usize> = match (batch_number, reagent) {
+        (None, None) => return Err("Either reagent name or batch number is required".to_string()),
+        _ => batches.iter()
+            .enumerate()
+            .filter(|(_, b)| batch_number.is_none_or(|n| b.batch_number.eq_ignore_ascii_case(n)))
+            .filter(|(_, b)| reagent.is_none_or(|r| b.reagent_name.to_lowercase() == r.to_lowercase()))
+            .map(|(i, _)| i)
+            .collect(),
+    };
+
+    match (candidates.len(), batch_number) {
+        (1, _) => Ok(candidates[0]),
+        (0, Some(n)) => Err(format!("Batch '{}' not found", n)),
+        (0, None) => Err(format!("Reagent '{}' has no batches", reagent.unwrap_or_default())),
+        (count, Some(n)) => Err(format!("Batch number '{}' matches {} batches, specify the reagent name", n, count)),
+        (count, None) => Err(format!(
+            "Reagent '{}' has {} batches, specify the batch number", reagent.unwrap_or_default(), count
+        )),
+    }
+}
+
+/// Импорт истории расхода: записи получают исходные даты и флаг imported.
+/// Остатки партий не меняются (они уже отражают расход); суммарный расход по партии
+/// не может превышать original_quantity, либо original_quantity пересчитывается.
+async fn import_usage_logic(
+    pool: &SqlitePool,
+    rows: Vec<UsageRow>,
+    reconstruct_original: bool,
+    decimal_separator: DecimalSeparator,
+) -> ApiResult<UsageImportReport> {
+    let total_rows = rows.len();
+    let mut errors: Vec<ImportRowError> = Vec::new();
+
+    let users = preload_user_identities(pool).await?;
+    let batches: Vec<UsageBatchRef> = sqlx::query_as::<_, (String, String, String, String, String, f64, f64)>(
+        r#"SELECT b.id, b.reagent_id, r.name, b.batch_number, b.unit, b.quantity, b.original_quantity
+           FROM batches b
+           JOIN reagents r ON r.id = b.reagent_id AND r.deleted_at IS NULL
+           WHERE b.deleted_at IS NULL"#
+    )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(id, reagent_id, reagent_name, batch_number, unit, quantity, original_quantity)| UsageBatchRef {
+            id, reagent_id, reagent_name, batch_number, unit, quantity, original_quantity,
+        })
+        .collect();
+    let used_so_far: HashMap<String, f64> = sqlx::query_as::<_, (String, f64)>(
+        "SELECT batch_id, COALESCE(SUM(quantity_used), 0) FROM usage_logs GROUP BY batch_id"
+    )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+
+    // PHASE 1: построчная проверка
+    let now = Utc::now().naive_utc();
+    let mut prepared: Vec<PreparedUsage> = Vec::new();
+    for (row, parsed) in rows {
+        let dto = match parsed {
+            Ok(dto) => dto,
+            Err(e) => {
+                errors.push(ImportRowError { row, message: e });
+                continue;
+            }
+        };
+
+        let result = (|| -> Result<PreparedUsage, String> {
+            let batch = resolve_usage_batch(&batches, &dto)?;
+            let batch_ref = &batches[batch];
+
+            let quantity = dto.quantity.resolve(decimal_separator)
+                .ok_or_else(|| format!("Invalid quantity '{}'", dto.quantity.raw()))?;
+            if !quantity.is_finite() || quantity <= 0.0 {
+                return Err("Quantity must be greater than 0".to_string());
+            }
+            let raw_date = dto.used_at.as_deref().ok_or("Date is required")?;
+            let used_at = parse_usage_timestamp(raw_date)
+                .ok_or_else(|| format!("Invalid date '{}'", raw_date))?;
+            if used_at > now {
+                return Err(format!("Date '{}' is in the future", raw_date));
+            }
+            if let Some(unit) = dto.unit.as_deref().map(str::trim).filter(|u| !u.is_empty()) {
+                if unit != batch_ref.unit {
+                    return Err(format!("Unit '{}' does not match batch unit '{}'", unit, batch_ref.unit));
+                }
+            }
+
+            // Неизвестный пользователь не блокирует перенос истории - сохраняем его в заметках
+            let user = dto.user.as_deref().map(str::trim).filter(|u| !u.is_empty());
+            let user_id = user.and_then(|u| users.get(&u.to_lowercase()).cloned());
+            let notes = match (user, &user_id) {
+                (Some(u), None) => Some(match dto.notes.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+                    Some(n) => format!("[user: {}] {}", u, n),
+                    None => format!("[user: {}]", u),
+                }),
+                _ => dto.notes.clone().filter(|n| !n.trim().is_empty()),
+            };
+            if notes.as_ref().is_some_and(|n| n.chars().count() > 1000) {
+                return Err("Notes cannot exceed 1000 characters".to_string());
+            }
+
+            Ok(PreparedUsage {
+                row, batch, user_id, quantity,
+                unit: batch_ref.unit.clone(), notes, used_at,
+            })
+        })();
+
+        match result {
+            Ok(p) => prepared.push(p),
+            Err(message) => errors.push(ImportRowError { row, message }),
+        }
+    }
+
+    // PHASE 2: суммарный расход по партиям в хронологическом порядке
+    prepared.sort_by(|a, b| a.batch.cmp(&b.batch).then(a.used_at.cmp(&b.used_at)).then(a.row.cmp(&b.row)));
+
+    let mut originals: HashMap<usize, f64> = HashMap::new();
+    if reconstruct_original {
+        let mut imported_by_batch: HashMap<usize, f64> = HashMap::new();
+        for p in &prepared {
+            *imported_by_batch.entry(p.batch).or_default() += p.quantity;
+        }
+        for (batch_idx, imported) in imported_by_batch {
+            let batch_ref = &batches[batch_idx];
+            let used = used_so_far.get(&batch_ref.id).copied().unwrap_or(0.0);
+            let original = batch_ref.quantity + used + imported;
+            if original > batch_ref.original_quantity + USAGE_EPSILON {
+                originals.insert(batch_idx, original);
+            }
+        }
+    }
+
+    let mut accepted: Vec<PreparedUsage> = Vec::with_capacity(prepared.len());
+    let mut running: HashMap<usize, f64> = HashMap::new();
+    for p in prepared {
+        let batch_ref = &batches[p.batch];
+        let original = originals.get(&p.batch).copied().unwrap_or(batch_ref.original_quantity);
+        let used = running.entry(p.batch)
+            .or_insert_with(|| used_so_far.get(&batch_ref.id).copied().unwrap_or(0.0));
+
+        if *used + p.quantity > original + USAGE_EPSILON {
+            errors.push(ImportRowError {
+                row: p.row,
+                message: format!(
+                    "Cumulative usage {} {} exceeds original quantity {} {} of batch '{}'",
+                    *used + p.quantity, batch_ref.unit, original, batch_ref.unit, batch_ref.batch_number
+                ),
+            });
+        } else {
+            *used += p.quantity;
+            accepted.push(p);
+        }
+    }
+
+    // PHASE 3: запись одной транзакцией
+    let mut tx = pool.begin().await?;
+    for p in &accepted {
+        let batch_ref = &batches[p.batch];
+        sqlx::query(
+            r#"INSERT INTO usage_logs (id, reagent_id, batch_id, user_id, quantity_used, unit, notes, imported, created_at)
+               VALUES (?, ?, ?, ?, ?, ?, ?, 1, ?)"#
+        )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&batch_ref.reagent_id)
+            .bind(&batch_ref.id)
+            .bind(&p.user_id)
+            .bind(p.quantity)
+            .bind(&p.unit)
+            .bind(&p.notes)
+            .bind(p.used_at.and_utc())
+            .execute(&mut *tx)
+            .await?;
+    }
+    for (batch_idx, original) in &originals {
+        sqlx::query("UPDATE batches SET original_quantity = ?, updated_at = ? WHERE id = ?")
+            .bind(original)
+            .bind(Utc::now())
+            .bind(&batches[*batch_idx].id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    crate::kpi::record(crate::kpi::Kpi::ImportsRun, "usage");
+
+    errors.sort_by_key(|e| e.row);
+    Ok(UsageImportReport {
+        total_rows,
+        imported: accepted.len(),
+        failed: errors.len(),
+        batches_reconstructed: originals.len(),
+        errors,
+    })
+}
+
+/// POST /batches/import/usage - multipart csv/xlsx с историей расхода
+pub async fn import_usage_history(
+    app_state: web::Data<Arc<AppState>>,
+    payload: Multipart,
+    query: web::Query<UsageImportQuery>,
+    req: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&req)?;
+    let (file_path, original_name) = save_multipart_upload(payload).await?;
+    let is_csv = original_name.to_lowercase().ends_with(".csv");
+
+    let (rows_result, ticket) = parse_in_queue(&file_path, move |path| read_usage_rows(&path, is_csv)).await?;
+    let _ = fs::remove_file(&file_path);
+
+    let rows = rows_result.map_err(ApiError::BadRequest)?;
+    if rows.is_empty() {
+        return Err(ApiError::bad_request("No rows found in file"));
+    }
+
+    let report = import_usage_logic(&app_state.db_pool, rows, query.reconstruct_original, query.decimal_separator).await?;
+
+    crate::audit::audit(
+        &app_state.db_pool, &claims.sub, "import", "usage_history", &original_name,
+        &format!(
+            "Imported usage history from {}: {} of {} rows, {} batches reconstructed",
+            original_name, report.imported, report.total_rows, report.batches_reconstructed
+        ),
+        &req,
+    ).await;
+
+    let message = format!("Imported {} of {} usage rows", report.imported, report.total_rows);
+    Ok(ticket.annotate(HttpResponse::Ok().json(ApiResponse::success_with_message(report, message))))
 }
 
 // ==========================================
@@ -894,21 +1606,20 @@ pub async fn import_equipment_json(app_state: web::Data<Arc<AppState>>, body: we
 
 pub async fn import_equipment_excel(app_state: web::Data<Arc<AppState>>, payload: Multipart) -> ApiResult<HttpResponse> {
     let file_path = save_multipart_to_temp(payload).await?;
-    let path_clone = file_path.clone();
-    let items_res = web::block(move || {
+    let (items_res, ticket) = parse_in_queue(&file_path, move |path_clone| {
         let mut workbook: Xlsx<_> = open_workbook(&path_clone).map_err(|e: XlsxError| e.to_string())?;
         let range = workbook.worksheet_range_at(0).ok_or("Empty")?.map_err(|e| e.to_string())?;
         let mut list = Vec::new();
         let iter = RangeDeserializerBuilder::new().from_range(&range).map_err(|e| e.to_string())?;
         for res in iter { if let Ok(r) = res { list.push(r); } }
         Ok::<Vec<EquipmentImportDto>, String>(list)
-    }).await.map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+    }).await?;
     
     match items_res {
         Ok(items) => {
             let count = import_equipment_logic(&app_state.db_pool, items).await?;
             let _ = fs::remove_file(file_path);
-            Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), format!("Imported {} equipment", count))))
+            Ok(ticket.annotate(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message((), format!("Imported {} equipment", count)))))
         },
         Err(e) => { let _ = fs::remove_file(file_path); Err(ApiError::BadRequest(e)) }
     }
@@ -924,9 +1635,17 @@ async fn import_equipment_logic(pool: &SqlitePool, items: Vec<EquipmentImportDto
     
     log::info!("🚀 Starting BULK equipment import of {} items...", total_items);
     
+    // ON CONFLICT(serial_number) требует уникального индекса (см. equipment_catalog::ensure_serial_index)
+    if !crate::equipment_catalog::serial_index_exists(pool).await? {
+        return Err(ApiError::serial_numbers_not_unique());
+    }
+    
     // Apply PRAGMA optimizations
     optimize_sqlite_for_bulk(pool).await?;
     
+    // Написания производителя приводятся к каноническим (/admin/normalizations/manufacturer)
+    let normalizer = crate::equipment_catalog::ManufacturerNormalizer::load(pool).await?;
+    
     // Prepare equipment data
     struct PrepEquip {
         id: String,
@@ -953,7 +1672,7 @@ async fn import_equipment_logic(pool: &SqlitePool, items: Vec<EquipmentImportDto
                 name: item.name.trim().to_string(),
                 eq_type,
                 serial_number: item.serial_number.clone(),
-                manufacturer: item.manufacturer.clone(),
+                manufacturer: normalizer.apply_opt(item.manufacturer.as_deref()),
                 location: item.location.clone(),
                 description: item.description.clone(),
             }
@@ -1026,19 +1745,460 @@ async fn import_equipment_logic(pool: &SqlitePool, items: Vec<EquipmentImportDto
         0.0 
     };
     log::info!("✅ BULK equipment import completed in {:.2?}. {} items at {:.0} items/sec", elapsed, total_items, rate);
+    crate::kpi::record(crate::kpi::Kpi::ImportsRun, "equipment");
     
     Ok(total_items)
 }
 
-pub async fn export_equipment(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+/// Строка экспорта оборудования с положением в дереве сборок
+#[derive(Debug, Serialize)]
+pub struct EquipmentExportRow {
+    #[serde(flatten)]
+    pub equipment: crate::models::Equipment,
+    pub parent_name: Option<String>,
+    /// Путь от корня сборки, например "HPLC System / Pump"
+    pub assembly_path: String,
+    pub assembly_depth: usize,
+}
+
+/// Строит для каждого оборудования путь в дереве сборок (с защитой от циклов)
+fn build_assembly_rows(items: Vec<crate::models::Equipment>) -> Vec<EquipmentExportRow> {
+    let by_id: HashMap<&str, &crate::models::Equipment> = items.iter()
+        .map(|e| (e.id.as_str(), e))
+        .collect();
+
+    let mut paths: Vec<(String, usize, Option<String>)> = Vec::with_capacity(items.len());
+    for item in &items {
+        let mut chain = vec![item.name.clone()];
+        let mut seen = vec![item.id.as_str()];
+        let mut current = item.parent_equipment_id.as_deref();
+        while let Some(parent) = current.and_then(|id| by_id.get(id)) {
+            if seen.contains(&parent.id.as_str()) {
+                break;
+            }
+            seen.push(parent.id.as_str());
+            chain.push(parent.name.clone());
+            current = parent.parent_equipment_id.as_deref();
+        }
+        let parent_name = item.parent_equipment_id.as_deref()
+            .and_then(|id| by_id.get(id))
+            .map(|p| p.name.clone());
+        let depth = chain.len() - 1;
+        chain.reverse();
+        paths.push((chain.join(" / "), depth, parent_name));
+    }
+
+    let mut rows: Vec<EquipmentExportRow> = items.into_iter()
+        .zip(paths)
+        .map(|(equipment, (assembly_path, assembly_depth, parent_name))| EquipmentExportRow {
+            equipment,
+            parent_name,
+            assembly_path,
+            assembly_depth,
+        })
+        .collect();
+    // Компоненты идут сразу за своей сборкой
+    rows.sort_by(|a, b| a.assembly_path.cmp(&b.assembly_path));
+    rows
+}
+
+pub async fn export_equipment(
+    app_state: web::Data<Arc<AppState>>,
+    locale: web::Query<ExportLocaleQuery>,
+) -> ApiResult<HttpResponse> {
+    let style = ExportStyle::from_query(&locale)?;
+    let ticket = work_queue::export_queue().acquire().await?;
+    let rows = load_equipment_export(app_state.read_pool()).await?;
+    Ok(ticket.annotate(localized_export_response("equipment", rows, &style)?))
+}
+
+/// Данные экспорта оборудования с деревом сборок (общие для /equipment/export и архива)
+pub(crate) async fn load_equipment_export(pool: &SqlitePool) -> ApiResult<Vec<EquipmentExportRow>> {
     let whitelist = FieldWhitelist::for_equipment();
-    let builder = SafeQueryBuilder::new("SELECT * FROM equipment")
+    let mut builder = SafeQueryBuilder::new("SELECT * FROM equipment")
         .map_err(|e| ApiError::InternalServerError(e))?
         .with_whitelist(&whitelist);
+    builder.add_condition("pending_deletion_id IS NULL", vec![]);
     
     let (sql, _) = builder.build();
     let equipment = sqlx::query_as::<_, crate::models::Equipment>(&sql)
-        .fetch_all(&app_state.db_pool)
+        .fetch_all(pool)
         .await?;
-    Ok(HttpResponse::Ok().json(equipment))
+    Ok(build_assembly_rows(equipment))
+}
+
+/// Данные экспорта экспериментов (для архива; отдельного /experiments/export нет)
+pub(crate) async fn load_experiments_export(pool: &SqlitePool) -> ApiResult<Vec<crate::models::Experiment>> {
+    let whitelist = FieldWhitelist::for_experiments();
+    let mut builder = SafeQueryBuilder::new("SELECT * FROM experiments")
+        .map_err(ApiError::InternalServerError)?
+        .with_whitelist(&whitelist);
+    builder.order_by("experiment_date", "DESC");
+    
+    let (sql, _) = builder.build();
+    let experiments = sqlx::query_as::<_, crate::models::Experiment>(&sql)
+        .fetch_all(pool)
+        .await?;
+    Ok(experiments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn usage_test_pool() -> SqlitePool {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u1', 'alice', 'alice@example.com', 'x', 'researcher', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        for (id, name) in [("r1", "Ethanol"), ("r2", "Acetone")] {
+            sqlx::query("INSERT INTO reagents (id, name, created_at, updated_at) VALUES (?, ?, datetime('now'), datetime('now'))")
+                .bind(id).bind(name).execute(&pool).await.unwrap();
+        }
+        for (id, reagent, number, quantity, original) in [
+            ("b1", "r1", "LOT-1", 60.0, 100.0),
+            ("b2", "r1", "LOT-2", 10.0, 10.0),
+            ("b3", "r2", "LOT-1", 5.0, 5.0),
+        ] {
+            sqlx::query(
+                "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, received_date, created_at, updated_at) \
+                 VALUES (?, ?, ?, ?, ?, 'mL', datetime('now'), datetime('now'), datetime('now'))"
+            ).bind(id).bind(reagent).bind(number).bind(quantity).bind(original).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    fn csv_rows(content: &str) -> Vec<UsageRow> {
+        let mut file = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        read_usage_rows(file.path(), true).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_import_usage_history_report() {
+        let pool = usage_test_pool().await;
+        let rows = csv_rows(
+            "Reagent,Batch Number,Date,Quantity,Unit,User,Notes\n\
+             Ethanol,LOT-1,2024-01-10,30,mL,alice@example.com,first\n\
+             ,LOT-1,2024-01-11,5,mL,,\n\
+             Ethanol,LOT-1,15.01.2024,20,mL,Bob Unknown,\n\
+             Ethanol,LOT-1,2024-01-20,60,mL,,\n\
+             Ethanol,LOT-2,2024-01-05,1,g,,\n\
+             Ethanol,,2024-01-05,1,mL,,\n\
+             Ethanol,LOT-2,2099-01-01,1,mL,,\n\
+             Ethanol,LOT-2,not a date,1,mL,,\n\
+             Ethanol,LOT-2,2024-01-01,abc,mL,,\n"
+        );
+
+        let report = import_usage_logic(&pool, rows, false, DecimalSeparator::Auto).await.unwrap();
+        assert_eq!(report.total_rows, 9);
+        assert_eq!(report.imported, 2);
+        let failed_rows: Vec<usize> = report.errors.iter().map(|e| e.row).collect();
+        assert_eq!(failed_rows, vec![3, 5, 6, 7, 8, 9, 10]);
+        assert!(report.errors[1].message.contains("exceeds original quantity"));
+
+        type ImportedLog = (Option<String>, f64, Option<String>, bool, String);
+        let logs: Vec<ImportedLog> = sqlx::query_as(
+            "SELECT user_id, quantity_used, notes, imported, DATE(created_at) FROM usage_logs WHERE batch_id = 'b1' ORDER BY created_at"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0], (Some("u1".to_string()), 30.0, Some("first".to_string()), true, "2024-01-10".to_string()));
+        assert_eq!(logs[1].0, None);
+        assert_eq!(logs[1].2.as_deref(), Some("[user: Bob Unknown]"));
+        assert_eq!(logs[1].4, "2024-01-15");
+
+        // Остатки партий не меняются
+        let quantity: f64 = sqlx::query_scalar("SELECT quantity FROM batches WHERE id = 'b1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(quantity, 60.0);
+    }
+
+    #[actix_web::test]
+    async fn test_import_usage_reconstructs_original_quantity() {
+        let pool = usage_test_pool().await;
+        let content = "batch_number,reagent_name,used_at,quantity\n\
+                       LOT-2,Ethanol,2023-11-01,8\n\
+                       LOT-2,Ethanol,2023-12-01,7\n";
+
+        let rejected = import_usage_logic(&pool, csv_rows(content), false, DecimalSeparator::Auto).await.unwrap();
+        assert_eq!((rejected.imported, rejected.failed), (1, 1));
+        sqlx::query("DELETE FROM usage_logs").execute(&pool).await.unwrap();
+
+        let report = import_usage_logic(&pool, csv_rows(content), true, DecimalSeparator::Auto).await.unwrap();
+        assert_eq!((report.imported, report.failed, report.batches_reconstructed), (2, 0, 1));
+
+        let original: f64 = sqlx::query_scalar("SELECT original_quantity FROM batches WHERE id = 'b2'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(original, 25.0);
+    }
+
+    #[test]
+    fn test_parse_decimal_conventions() {
+        use DecimalSeparator::*;
+        assert_eq!(parse_decimal("1,5", Auto), Some(1.5));
+        assert_eq!(parse_decimal("1.5", Auto), Some(1.5));
+        assert_eq!(parse_decimal("1 234,56", Auto), Some(1234.56));
+        assert_eq!(parse_decimal("1.234,56", Auto), Some(1234.56));
+        assert_eq!(parse_decimal("1,234.56", Auto), Some(1234.56));
+        assert_eq!(parse_decimal("1,234,567", Auto), Some(1234567.0));
+        assert_eq!(parse_decimal("1,234", Dot), Some(1234.0));
+        assert_eq!(parse_decimal("1.234", Comma), Some(1234.0));
+        assert_eq!(parse_decimal("1,2,3.4,5", Auto), None);
+        assert_eq!(parse_decimal("abc", Auto), None);
+        assert_eq!(ImportNumber::Number(2.5).resolve(Comma), Some(2.5));
+    }
+
+    #[actix_web::test]
+    async fn test_import_usage_semicolon_csv_with_decimal_comma() {
+        let pool = usage_test_pool().await;
+        let content = "Реагент;Партия;Дата;Количество\n\
+                       Ethanol;LOT-1;10.01.2024;12,5\n\
+                       Ethanol;LOT-1;11.01.2024;1.25\n";
+
+        let report = import_usage_logic(&pool, csv_rows(content), false, DecimalSeparator::Auto).await.unwrap();
+        assert_eq!((report.imported, report.failed), (2, 0));
+        let total: f64 = sqlx::query_scalar("SELECT SUM(quantity_used) FROM usage_logs WHERE batch_id = 'b1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(total, 13.75);
+
+        // Явный разделитель: "1.25" в режиме comma - 125, что превышает исходное количество
+        let report = import_usage_logic(&pool, csv_rows(content), false, DecimalSeparator::Comma).await.unwrap();
+        assert_eq!(report.failed, 1);
+        assert!(report.errors[0].message.contains("exceeds original quantity"));
+    }
+
+    #[actix_web::test]
+    async fn test_import_reagents_rejects_unparsable_number() {
+        let pool = usage_test_pool().await;
+        let rows: Vec<ReagentImportDto> = serde_json::from_value(serde_json::json!([
+            { "Название": "Toluene", "Молекулярная масса": "92,14" },
+            { "name": "Xylene", "molecular_weight": "1,2,3.4,5" },
+        ])).unwrap();
+
+        let err = import_reagents_logic(&pool, rows, "u1".to_string(), DecimalSeparator::Auto).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref msg) if msg.contains("molecular_weight") && msg.contains("Xylene")), "{:?}", err);
+    }
+
+    #[actix_web::test]
+    async fn test_import_reagents_expiry_policy_and_shelf_life() {
+        let pool = usage_test_pool().await;
+        sqlx::query("UPDATE reagents SET expiry_policy = 'none' WHERE id = 'r2'").execute(&pool).await.unwrap();
+
+        let rows: Vec<ReagentImportDto> = serde_json::from_value(serde_json::json!([
+            { "name": "Xylene", "expiry_policy": "never" },
+        ])).unwrap();
+        let err = import_reagents_logic(&pool, rows, "u1".to_string(), DecimalSeparator::Auto).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref msg) if msg.contains("expiry_policy")), "{:?}", err);
+
+        let rows: Vec<ReagentImportDto> = serde_json::from_value(serde_json::json!([
+            { "Название": "Ethanol", "Контроль сроков годности": "Advisory", "Срок годности по умолчанию (дни)": "14",
+              "Партия": "LOT-9", "Количество": 5, "Единицы": "mL" },
+            { "name": "Acetone", "Lot number": "LOT-7", "Quantity": 1, "Units": "mL", "Expiry Date": "2031-05-01" },
+        ])).unwrap();
+        import_reagents_logic(&pool, rows, "u1".to_string(), DecimalSeparator::Auto).await.unwrap();
+
+        let policies: Vec<(String, String, Option<i64>)> = sqlx::query_as(
+            "SELECT id, expiry_policy, default_shelf_life_days FROM reagents WHERE id IN ('r1', 'r2') ORDER BY id"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(policies, vec![
+            ("r1".to_string(), "advisory".to_string(), Some(14)),
+            ("r2".to_string(), "none".to_string(), None),
+        ]);
+
+        // Срок годности партии без даты - дата импорта + 14 дней
+        let (days,): (f64,) = sqlx::query_as(
+            "SELECT julianday(expiry_date) - julianday('now') FROM batches WHERE batch_number = 'LOT-9'"
+        ).fetch_one(&pool).await.unwrap();
+        assert!((days - 14.0).abs() < 0.01, "{}", days);
+    }
+
+    #[actix_web::test]
+    async fn test_import_reagents_physical_state_synonyms() {
+        let pool = usage_test_pool().await;
+        let rows: Vec<ReagentImportDto> = serde_json::from_value(serde_json::json!([
+            { "name": "Xylene", "Physical State": "slurry" },
+        ])).unwrap();
+        let err = import_reagents_logic(&pool, rows, "u1".to_string(), DecimalSeparator::Auto).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref msg) if msg.contains("physical_state") && msg.contains("slurry")), "{:?}", err);
+
+        // Написание из карты администратора принимается наравне со встроенными синонимами
+        sqlx::query("INSERT INTO physical_state_mappings (variant, physical_state, updated_at) VALUES ('slurry', 'other', datetime('now'))")
+            .execute(&pool).await.unwrap();
+        let rows: Vec<ReagentImportDto> = serde_json::from_value(serde_json::json!([
+            { "Название": "Ethanol", "Агрегатное состояние": "Жидкий" },
+            { "name": "Sodium chloride", "State": "твёрдый" },
+            { "name": "Xylene", "Physical State": "Slurry" },
+        ])).unwrap();
+        import_reagents_logic(&pool, rows, "u1".to_string(), DecimalSeparator::Auto).await.unwrap();
+
+        let states: Vec<(String, Option<String>)> = sqlx::query_as(
+            "SELECT name, physical_state FROM reagents WHERE name IN ('Ethanol', 'Sodium chloride', 'Xylene') ORDER BY name"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(states, vec![
+            ("Ethanol".to_string(), Some("liquid".to_string())),
+            ("Sodium chloride".to_string(), Some("solid".to_string())),
+            ("Xylene".to_string(), Some("other".to_string())),
+        ]);
+    }
+
+    #[actix_web::test]
+    async fn test_localized_export_response() {
+        let pool = usage_test_pool().await;
+        let body = |response: HttpResponse| async move {
+            String::from_utf8(actix_web::body::to_bytes(response.into_body()).await.unwrap().to_vec()).unwrap()
+        };
+        sqlx::query("UPDATE batches SET quantity = 60.5, expiry_date = '2025-03-01T00:00:00Z' WHERE id = 'b1'")
+            .execute(&pool).await.unwrap();
+
+        let style = |locale: Option<&str>, excel_locale: Option<&str>| ExportStyle::from_query(&ExportLocaleQuery {
+            locale: locale.map(str::to_string),
+            excel_locale: excel_locale.map(str::to_string),
+        }).unwrap();
+
+        let plain = body(localized_export_response("batches", load_batches_export(&pool).await.unwrap(), &style(None, None)).unwrap()).await;
+        assert!(plain.contains("\"batch_number\":\"LOT-1\""));
+
+        let json = body(localized_export_response("batches", load_batches_export(&pool).await.unwrap(), &style(Some("ru"), None)).unwrap()).await;
+        assert!(json.contains("\"Номер партии\":\"LOT-1\""), "{}", json);
+        assert!(json.contains("\"01.03.2025 00:00\""), "{}", json);
+
+        let response = localized_export_response("batches", load_batches_export(&pool).await.unwrap(), &style(None, Some("ru"))).unwrap();
+        assert_eq!(response.headers().get("Content-Disposition").unwrap(), "attachment; filename=\"batches.csv\"");
+        let csv = body(response).await;
+        assert!(csv.starts_with('\u{FEFF}'));
+        assert!(csv.contains(";Номер партии;"), "{}", csv);
+        assert!(csv.contains(";60,5;"), "{}", csv);
+    }
+
+    #[actix_web::test]
+    async fn test_import_equipment_accepts_both_type_spellings() {
+        let pool = usage_test_pool().await;
+        let items: Vec<EquipmentImportDto> = serde_json::from_value(serde_json::json!([
+            { "name": "Centrifuge", "type": "instrument", "serial_number": "C-1" },
+            { "name": "Beaker", "type_": "glassware", "serial_number": "B-1" },
+        ])).unwrap();
+
+        assert_eq!(import_equipment_logic(&pool, items).await.unwrap(), 2);
+
+        let types: Vec<(String, String)> = sqlx::query_as("SELECT name, type_ FROM equipment ORDER BY name")
+            .fetch_all(&pool).await.unwrap();
+        assert_eq!(types, vec![
+            ("Beaker".to_string(), "glassware".to_string()),
+            ("Centrifuge".to_string(), "instrument".to_string()),
+        ]);
+
+        let exported = serde_json::to_value(load_equipment_export(&pool).await.unwrap()).unwrap();
+        assert!(exported[0].get("type").is_some());
+        assert!(exported[0].get("type_").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_import_batches_reports_row_errors() {
+        let pool = usage_test_pool().await;
+        let rows: Vec<BatchImportDto> = serde_json::from_value(serde_json::json!([
+            { "reagent_name": "Ethanol", "batch_number": "LOT-10", "quantity": "2,5", "units": "mL", "expiry_date": "01.05.2031" },
+            { "reagent_name": "ethanol", "batch_number": "LOT-10", "quantity": 1, "units": "mL" },
+            { "reagent_name": "Ethanol", "batch_number": "LOT-1", "quantity": 1, "units": "mL" },
+            { "reagent_name": "Benzene", "batch_number": "LOT-11", "quantity": 1, "units": "mL" },
+            { "reagent_name": "Acetone", "batch_number": "LOT-12", "quantity": 1, "units": "bottle" },
+            { "reagent_name": "Acetone", "batch_number": "LOT-13", "quantity": 0, "units": "mL" },
+            { "reagent_name": "Acetone", "batch_number": "LOT-14", "quantity": "1,2,3.4,5", "units": "mL" },
+            { "reagent_name": "Acetone", "batch_number": "LOT-15", "quantity": 3, "units": "g", "expiry_date": "someday" },
+            { "Реагент": " acetone ", "Номер партии": "LOT-16", "Количество": 4, "Единица": "g" },
+        ])).unwrap();
+
+        let report = import_batches_logic(&pool, rows, DecimalSeparator::Auto).await.unwrap();
+        assert_eq!((report.total_rows, report.imported, report.failed), (9, 2, 7), "{:?}", report.errors);
+        let errors: Vec<(usize, &str)> = report.errors.iter().map(|e| (e.row, e.message.as_str())).collect();
+        assert_eq!(errors[0], (3, "Duplicate of row 2"));
+        assert_eq!(errors[1], (4, "Batch 'LOT-1' already exists for reagent 'Ethanol'"));
+        assert_eq!(errors[2], (5, "Reagent 'Benzene' not found"));
+        assert_eq!(errors[3], (6, "Invalid unit 'bottle'"));
+        assert_eq!(errors[4], (7, "Quantity must be greater than 0"));
+        assert!(errors[5].0 == 8 && errors[5].1.contains("quantity"), "{:?}", errors[5]);
+        assert_eq!(errors[6], (9, "Invalid expiry date 'someday'"));
+
+        let imported: Vec<(String, String, f64, Option<String>)> = sqlx::query_as(
+            "SELECT reagent_id, batch_number, quantity, expiry_date FROM batches WHERE batch_number IN ('LOT-10', 'LOT-16') ORDER BY batch_number"
+        ).fetch_all(&pool).await.unwrap();
+        assert_eq!(imported, vec![
+            ("r1".to_string(), "LOT-10".to_string(), 2.5, Some("2031-05-01T00:00:00".to_string())),
+            ("r2".to_string(), "LOT-16".to_string(), 4.0, None),
+        ]);
+        let (quantity,): (f64,) = sqlx::query_as("SELECT quantity FROM batches WHERE id = 'b2'").fetch_one(&pool).await.unwrap();
+        assert_eq!(quantity, 10.0);
+    }
+
+    /// 500 реагентов и `count` строк партий, каждая сотая строка повторяет предыдущую
+    async fn bulk_batch_rows(pool: &SqlitePool, count: usize) -> Vec<BatchImportDto> {
+        sqlx::query(
+            r#"WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < 499)
+               INSERT INTO reagents (id, name, created_at, updated_at)
+               SELECT 'bench-' || i, 'Bench reagent ' || i, datetime('now'), datetime('now') FROM n"#
+        ).execute(pool).await.unwrap();
+        (0..count)
+            .map(|i| {
+                let lot = if i % 100 == 99 { i - 1 } else { i };
+                serde_json::from_value(serde_json::json!({
+                    "reagent_name": format!("Bench reagent {}", lot % 500), "batch_number": format!("BENCH-{}", lot),
+                    "quantity": 10, "units": "g", "expiry_date": "2031-01-01",
+                })).unwrap()
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn test_import_batches_bulk_rows_and_duplicates() {
+        let pool = usage_test_pool().await;
+        let rows = bulk_batch_rows(&pool, 5_000).await;
+
+        let report = import_batches_logic(&pool, rows, DecimalSeparator::Auto).await.unwrap();
+        assert_eq!((report.total_rows, report.imported, report.failed), (5_000, 4_950, 50));
+        assert!(report.errors.iter().all(|e| e.message.starts_with("Duplicate of row")), "{:?}", report.errors.first());
+
+        let (batches, total): (i64, f64) = sqlx::query_as(
+            "SELECT SUM(batches_count), SUM(total_quantity) FROM reagents WHERE id LIKE 'bench-%'"
+        ).fetch_one(&pool).await.unwrap();
+        assert_eq!((batches, total), (4_950, 49_500.0));
+    }
+
+    /// 50 000 строк - в пределах 10 секунд. Время зависит от машины, поэтому только вручную:
+    /// `cargo test -- --ignored bench_import_batches`
+    #[actix_web::test]
+    #[ignore]
+    async fn bench_import_batches_50k_rows() {
+        let pool = usage_test_pool().await;
+        let rows = bulk_batch_rows(&pool, 50_000).await;
+
+        let started = Instant::now();
+        let report = import_batches_logic(&pool, rows, DecimalSeparator::Auto).await.unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!((report.imported, report.failed), (49_500, 500));
+        assert!(elapsed.as_secs_f64() < 10.0, "50k rows took {:.2?}", elapsed);
+    }
+
+    #[actix_web::test]
+    async fn test_import_batches_rejects_inactive_reagent() {
+        let pool = usage_test_pool().await;
+        sqlx::query("UPDATE reagents SET status = 'inactive' WHERE id = 'r2'").execute(&pool).await.unwrap();
+        let rows = |names: &[&str]| -> Vec<BatchImportDto> {
+            let rows: Vec<serde_json::Value> = names.iter()
+                .map(|name| serde_json::json!({ "reagent_name": name, "batch_number": "LOT-9", "quantity": 5.0, "units": "mL" }))
+                .collect();
+            serde_json::from_value(serde_json::Value::Array(rows)).unwrap()
+        };
+
+        let err = import_batches_logic(&pool, rows(&["Ethanol", " acetone "]), DecimalSeparator::Auto).await.unwrap_err();
+        assert!(matches!(err, ApiError::Conflict { code: crate::error::REAGENT_INACTIVE, ref message } if message.contains("Acetone")), "{:?}", err);
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM batches WHERE batch_number = 'LOT-9'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(count, 0);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,724 @@
+// src/kiosk_handlers.rs
+//! Киоск помещения: планшет у входа с токеном, привязанным к комнате.
+//!
+//! Администратор выпускает токен (`POST /rooms/{id}/kiosk-token`) с узкими областями
+//! действия. Токен не является пользователем: jwt_middleware кладёт в запрос
+//! `KioskPrincipal` вместо Claims, и доступны ему только маршруты `/kiosk/*`.
+//! Каждое действие на киоске требует введённый или отсканированный идентификатор
+//! человека и пишется в журнал аудита с флагом `via_kiosk`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::access_control::KioskScope;
+use crate::audit::audit_kiosk;
+use crate::auth::{get_kiosk_principal, KioskPrincipal};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::Equipment;
+use crate::AppState;
+
+/// Префикс отличает киоск-токены от JWT в заголовке Authorization
+pub const KIOSK_TOKEN_PREFIX: &str = "kiosk_";
+const KIOSK_TOKEN_LENGTH: usize = 40;
+const MAX_KIOSK_TOKEN_LIFETIME_DAYS: i64 = 365;
+const MAX_USER_IDENTIFIER_LENGTH: usize = 255;
+
+// ==================== TOKENS ====================
+
+fn generate_kiosk_token() -> String {
+    let random: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(KIOSK_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    format!("{}{}", KIOSK_TOKEN_PREFIX, random)
+}
+
+fn hash_kiosk_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn format_scopes(scopes: &[KioskScope]) -> String {
+    scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(",")
+}
+
+fn parse_scopes(value: &str) -> Vec<KioskScope> {
+    value.split(',').filter_map(KioskScope::parse).collect()
+}
+
+/// Проверка киоск-токена из заголовка Authorization (вызывается из jwt_middleware)
+pub async fn resolve_kiosk_token(pool: &SqlitePool, token: &str) -> ApiResult<KioskPrincipal> {
+    let row: Option<(String, String, String, String)> = sqlx::query_as(
+        r#"SELECT t.id, t.room_id, r.name, t.scopes
+           FROM kiosk_tokens t
+           JOIN rooms r ON r.id = t.room_id
+           WHERE t.token_hash = ?
+             AND t.revoked_at IS NULL
+             AND (t.expires_at IS NULL OR datetime(t.expires_at) > datetime(?))"#
+    )
+    .bind(hash_kiosk_token(token))
+    .bind(Utc::now())
+    .fetch_optional(pool)
+    .await?;
+
+    let (token_id, room_id, room_name, scopes) = row
+        .ok_or_else(|| ApiError::Unauthorized("Invalid, revoked or expired kiosk token".to_string()))?;
+
+    // Отметка использования не должна задерживать запрос
+    let pool = pool.clone();
+    let used_id = token_id.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = sqlx::query("UPDATE kiosk_tokens SET last_used_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(&used_id)
+            .execute(&pool)
+            .await
+        {
+            log::warn!("Failed to update kiosk token last_used_at: {}", e);
+        }
+    });
+
+    Ok(KioskPrincipal { token_id, room_id, room_name, scopes: parse_scopes(&scopes) })
+}
+
+// ==================== ADMIN: ISSUE / LIST / REVOKE ====================
+
+#[derive(Debug, Deserialize)]
+pub struct CreateKioskTokenRequest {
+    pub label: String,
+    /// По умолчанию - все области киоска
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+    #[serde(default)]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct KioskTokenInfo {
+    pub id: String,
+    pub room_id: String,
+    pub label: String,
+    /// Последние символы токена, чтобы отличить планшеты
+    pub token_hint: String,
+    pub scopes: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedKioskToken {
+    #[serde(flatten)]
+    pub info: KioskTokenInfo,
+    /// Показывается только один раз
+    pub token: String,
+}
+
+async fn ensure_room_exists(pool: &SqlitePool, room_id: &str) -> ApiResult<()> {
+    let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM rooms WHERE id = ?")
+        .bind(room_id)
+        .fetch_optional(pool)
+        .await?;
+    exists.map(|_| ()).ok_or_else(|| ApiError::not_found("Room"))
+}
+
+async fn fetch_token_info(pool: &SqlitePool, token_id: &str) -> ApiResult<KioskTokenInfo> {
+    sqlx::query_as(
+        r#"SELECT id, room_id, label, token_hint, scopes, expires_at, last_used_at,
+                  revoked_at, created_by, created_at
+           FROM kiosk_tokens WHERE id = ?"#
+    )
+    .bind(token_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| ApiError::not_found("Kiosk token"))
+}
+
+pub async fn create_kiosk_token(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<CreateKioskTokenRequest>,
+    user_id: String,
+) -> ApiResult<CreatedKioskToken> {
+    let room_id = path.into_inner();
+    let body = body.into_inner();
+    ensure_room_exists(&app_state.db_pool, &room_id).await?;
+
+    let label = body.label.trim();
+    if label.is_empty() || label.chars().count() > 100 {
+        return Err(ApiError::ValidationError("Label must be 1-100 characters".to_string()));
+    }
+
+    let scopes = match body.scopes {
+        None => KioskScope::ALL.to_vec(),
+        Some(requested) => {
+            let mut scopes = Vec::new();
+            for value in &requested {
+                let scope = KioskScope::parse(value)
+                    .ok_or_else(|| ApiError::bad_request(&format!("Unknown kiosk scope '{}'", value)))?;
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            }
+            if scopes.is_empty() {
+                return Err(ApiError::bad_request("At least one kiosk scope is required"));
+            }
+            scopes
+        }
+    };
+
+    let now = Utc::now();
+    let expires_at = match body.expires_in_days {
+        None => None,
+        Some(days) if (1..=MAX_KIOSK_TOKEN_LIFETIME_DAYS).contains(&days) => Some(now + Duration::days(days)),
+        Some(_) => {
+            return Err(ApiError::ValidationError(format!(
+                "expires_in_days must be between 1 and {}", MAX_KIOSK_TOKEN_LIFETIME_DAYS
+            )));
+        }
+    };
+
+    let token = generate_kiosk_token();
+    let token_hint = token[token.len() - 4..].to_string();
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query(
+        r#"INSERT INTO kiosk_tokens
+           (id, room_id, label, token_hash, token_hint, scopes, expires_at, created_by, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+    .bind(&id)
+    .bind(&room_id)
+    .bind(label)
+    .bind(hash_kiosk_token(&token))
+    .bind(&token_hint)
+    .bind(format_scopes(&scopes))
+    .bind(expires_at)
+    .bind(&user_id)
+    .bind(now)
+    .execute(&app_state.db_pool)
+    .await?;
+
+    let info = fetch_token_info(&app_state.db_pool, &id).await?;
+    Ok(CreatedKioskToken { info, token })
+}
+
+pub async fn list_kiosk_tokens(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let room_id = path.into_inner();
+    ensure_room_exists(&app_state.db_pool, &room_id).await?;
+
+    let tokens: Vec<KioskTokenInfo> = sqlx::query_as(
+        r#"SELECT id, room_id, label, token_hint, scopes, expires_at, last_used_at,
+                  revoked_at, created_by, created_at
+           FROM kiosk_tokens WHERE room_id = ?
+           ORDER BY revoked_at IS NOT NULL, created_at DESC"#
+    )
+    .bind(&room_id)
+    .fetch_all(&app_state.db_pool)
+    .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(tokens)))
+}
+
+pub async fn revoke_kiosk_token(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    user_id: String,
+) -> ApiResult<KioskTokenInfo> {
+    let (room_id, token_id) = path.into_inner();
+
+    let result = sqlx::query(
+        "UPDATE kiosk_tokens SET revoked_at = ?, revoked_by = ? WHERE id = ? AND room_id = ? AND revoked_at IS NULL"
+    )
+    .bind(Utc::now())
+    .bind(&user_id)
+    .bind(&token_id)
+    .bind(&room_id)
+    .execute(&app_state.db_pool)
+    .await?;
+
+    let info = fetch_token_info(&app_state.db_pool, &token_id).await?;
+    if info.room_id != room_id {
+        return Err(ApiError::not_found("Kiosk token"));
+    }
+    if result.rows_affected() == 0 {
+        return Err(ApiError::bad_request("Kiosk token is already revoked"));
+    }
+    Ok(info)
+}
+
+// ==================== KIOSK ACTIONS ====================
+
+/// Кто выполняет действие на киоске (ввод с клавиатуры или сканер бейджа)
+#[derive(Debug, Deserialize)]
+pub struct KioskActorRequest {
+    pub user_identifier: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KioskAttendanceRequest {
+    pub user_identifier: String,
+    /// check_in | check_out
+    pub event: String,
+}
+
+fn normalize_identifier(value: &str) -> ApiResult<String> {
+    let value = value.trim();
+    if value.is_empty() || value.chars().count() > MAX_USER_IDENTIFIER_LENGTH {
+        return Err(ApiError::ValidationError(format!(
+            "user_identifier must be 1-{} characters", MAX_USER_IDENTIFIER_LENGTH
+        )));
+    }
+    Ok(value.to_string())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct KioskEquipmentItem {
+    pub id: String,
+    pub name: String,
+    #[sqlx(rename = "type_")]
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub quantity: i32,
+    pub status: String,
+    pub serial_number: Option<String>,
+    /// Роли, которым можно брать оборудование (None - всем)
+    pub restricted_to_roles: Option<String>,
+    /// Открытые сеансы использования
+    pub checked_out: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct EquipmentUsageSession {
+    pub id: String,
+    pub equipment_id: String,
+    pub room_id: Option<String>,
+    pub user_identifier: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+    pub kiosk_token_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RoomAttendanceRecord {
+    pub id: String,
+    pub room_id: String,
+    pub user_identifier: String,
+    pub event: String,
+    pub kiosk_token_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Оборудование помещения киоска (связь по equipment.location = rooms.name)
+pub async fn get_kiosk_equipment(
+    app_state: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let kiosk = get_kiosk_principal(&http_request)?;
+
+    let items: Vec<KioskEquipmentItem> = sqlx::query_as(
+        r#"SELECT e.id, e.name, e.type_, e.quantity, e.status, e.serial_number, e.restricted_to_roles,
+                  (SELECT COUNT(*) FROM equipment_usage_sessions s
+                   WHERE s.equipment_id = e.id AND s.ended_at IS NULL) AS checked_out
+           FROM equipment e
+           WHERE e.location = ? COLLATE NOCASE AND e.status != 'retired' AND e.pending_deletion_id IS NULL
+           ORDER BY e.name"#
+    )
+    .bind(&kiosk.room_name)
+    .fetch_all(&app_state.db_pool)
+    .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(items)))
+}
+
+/// Оборудование, доступное этому киоску: только из его помещения
+async fn fetch_room_equipment(
+    pool: &SqlitePool,
+    kiosk: &KioskPrincipal,
+    equipment_id: &str,
+) -> ApiResult<(String, i32, String)> {
+    sqlx::query_as(
+        "SELECT name, quantity, status FROM equipment WHERE id = ? AND location = ? COLLATE NOCASE AND pending_deletion_id IS NULL"
+    )
+    .bind(equipment_id)
+    .bind(&kiosk.room_name)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| ApiError::not_found("Equipment"))
+}
+
+/// Роль пользователя по идентификатору с киоска (id, имя пользователя или email)
+async fn identifier_role(pool: &SqlitePool, identifier: &str) -> ApiResult<Option<String>> {
+    let role: Option<(String,)> = sqlx::query_as(
+        r#"SELECT role FROM users
+           WHERE is_active = 1 AND (id = ?1 OR username = ?1 COLLATE NOCASE OR email = ?1 COLLATE NOCASE)
+           LIMIT 1"#
+    )
+    .bind(identifier)
+    .fetch_optional(pool)
+    .await?;
+    Ok(role.map(|(role,)| role))
+}
+
+async fn count_open_sessions(pool: &SqlitePool, equipment_id: &str) -> ApiResult<i64> {
+    let (count,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM equipment_usage_sessions WHERE equipment_id = ? AND ended_at IS NULL"
+    )
+    .bind(equipment_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(count)
+}
+
+pub async fn kiosk_checkout_equipment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<KioskActorRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let kiosk = get_kiosk_principal(&http_request)?;
+    let equipment_id = path.into_inner();
+    let identifier = normalize_identifier(&body.user_identifier)?;
+    let pool = &app_state.db_pool;
+
+    let (name, quantity, status) = fetch_room_equipment(pool, &kiosk, &equipment_id).await?;
+    if status != "available" && status != "in_use" {
+        return Err(ApiError::bad_request(&format!("Equipment is not available (status: {})", status)));
+    }
+    if let Some(window) = crate::equipment_handlers::find_active_maintenance(pool, &equipment_id).await? {
+        return Err(ApiError::bad_request(&format!(
+            "Equipment is under maintenance until {}",
+            window.scheduled_end.map(|end| end.to_rfc3339()).unwrap_or_default()
+        )));
+    }
+
+    let equipment: Equipment = sqlx::query_as("SELECT * FROM equipment WHERE id = ?")
+        .bind(&equipment_id)
+        .fetch_one(pool)
+        .await?;
+    if equipment.restricted_to_roles.is_some() {
+        let role = identifier_role(pool, &identifier).await?;
+        crate::equipment_handlers::ensure_role_allowed(&equipment, role.as_deref())?;
+    }
+
+    let open_sessions = count_open_sessions(pool, &equipment_id).await?;
+    if open_sessions >= quantity as i64 {
+        return Err(ApiError::bad_request("All units of this equipment are already checked out"));
+    }
+
+    let session_id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"INSERT INTO equipment_usage_sessions
+           (id, equipment_id, room_id, user_identifier, started_at, notes, kiosk_token_id)
+           VALUES (?, ?, ?, ?, ?, ?, ?)"#
+    )
+    .bind(&session_id)
+    .bind(&equipment_id)
+    .bind(&kiosk.room_id)
+    .bind(&identifier)
+    .bind(now)
+    .bind(&body.notes)
+    .bind(&kiosk.token_id)
+    .execute(&mut *tx)
+    .await?;
+    if open_sessions + 1 >= quantity as i64 {
+        sqlx::query("UPDATE equipment SET status = 'in_use', updated_at = ? WHERE id = ?")
+            .bind(now)
+            .bind(&equipment_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    audit_kiosk(
+        pool, &kiosk, &identifier, "kiosk_checkout", "equipment", &equipment_id,
+        &format!("Checked out '{}' at kiosk in {}", name, kiosk.room_name), &http_request,
+    ).await;
+
+    let session: EquipmentUsageSession = sqlx::query_as("SELECT * FROM equipment_usage_sessions WHERE id = ?")
+        .bind(&session_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(session)))
+}
+
+pub async fn kiosk_checkin_equipment(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<KioskActorRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let kiosk = get_kiosk_principal(&http_request)?;
+    let equipment_id = path.into_inner();
+    let identifier = normalize_identifier(&body.user_identifier)?;
+    let pool = &app_state.db_pool;
+
+    let (name, _, status) = fetch_room_equipment(pool, &kiosk, &equipment_id).await?;
+
+    let open: Option<(String,)> = sqlx::query_as(
+        r#"SELECT id FROM equipment_usage_sessions
+           WHERE equipment_id = ? AND ended_at IS NULL AND user_identifier = ? COLLATE NOCASE
+           ORDER BY started_at LIMIT 1"#
+    )
+    .bind(&equipment_id)
+    .bind(&identifier)
+    .fetch_optional(pool)
+    .await?;
+    let (session_id,) = open.ok_or_else(|| {
+        ApiError::bad_request("No open check-out of this equipment for this user")
+    })?;
+
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE equipment_usage_sessions SET ended_at = ?, notes = COALESCE(?, notes) WHERE id = ?"
+    )
+    .bind(now)
+    .bind(&body.notes)
+    .bind(&session_id)
+    .execute(&mut *tx)
+    .await?;
+    // Освободилась хотя бы одна единица - оборудование снова доступно
+    if status == "in_use" {
+        sqlx::query("UPDATE equipment SET status = 'available', updated_at = ? WHERE id = ?")
+            .bind(now)
+            .bind(&equipment_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    audit_kiosk(
+        pool, &kiosk, &identifier, "kiosk_checkin", "equipment", &equipment_id,
+        &format!("Returned '{}' at kiosk in {}", name, kiosk.room_name), &http_request,
+    ).await;
+
+    let session: EquipmentUsageSession = sqlx::query_as("SELECT * FROM equipment_usage_sessions WHERE id = ?")
+        .bind(&session_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(session)))
+}
+
+pub async fn kiosk_record_attendance(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<KioskAttendanceRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let kiosk = get_kiosk_principal(&http_request)?;
+    let identifier = normalize_identifier(&body.user_identifier)?;
+    let event = body.event.trim();
+    if event != "check_in" && event != "check_out" {
+        return Err(ApiError::bad_request("event must be 'check_in' or 'check_out'"));
+    }
+    let pool = &app_state.db_pool;
+
+    let record = RoomAttendanceRecord {
+        id: Uuid::new_v4().to_string(),
+        room_id: kiosk.room_id.clone(),
+        user_identifier: identifier,
+        event: event.to_string(),
+        kiosk_token_id: Some(kiosk.token_id.clone()),
+        created_at: Utc::now(),
+    };
+    sqlx::query(
+        r#"INSERT INTO room_attendance (id, room_id, user_identifier, event, kiosk_token_id, created_at)
+           VALUES (?, ?, ?, ?, ?, ?)"#
+    )
+    .bind(&record.id)
+    .bind(&record.room_id)
+    .bind(&record.user_identifier)
+    .bind(&record.event)
+    .bind(&record.kiosk_token_id)
+    .bind(record.created_at)
+    .execute(pool)
+    .await?;
+
+    audit_kiosk(
+        pool, &kiosk, &record.user_identifier, &format!("kiosk_{}", record.event), "room", &kiosk.room_id,
+        &format!("Attendance {} in {}", record.event, kiosk.room_name), &http_request,
+    ).await;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(record)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test as actix_test;
+    use actix_web::{HttpMessage, ResponseError};
+
+    async fn test_app_state() -> web::Data<Arc<AppState>> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('admin', 'admin', 'admin@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO rooms (id, name, status, created_at, updated_at) \
+             VALUES ('room-1', 'Lab 101', 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, location, created_at, updated_at) VALUES \
+             ('eq-1', 'Centrifuge', 'instrument', 1, 'available', 'lab 101', datetime('now'), datetime('now')), \
+             ('eq-2', 'Balance', 'instrument', 1, 'available', 'Lab 202', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    fn kiosk_request(kiosk: &KioskPrincipal) -> HttpRequest {
+        let req = actix_test::TestRequest::default().to_http_request();
+        req.extensions_mut().insert(kiosk.clone());
+        req
+    }
+
+    fn actor(identifier: &str) -> web::Json<KioskActorRequest> {
+        web::Json(KioskActorRequest { user_identifier: identifier.to_string(), notes: None })
+    }
+
+    #[actix_web::test]
+    async fn test_kiosk_token_checkout_checkin_and_revoke() {
+        let state = test_app_state().await;
+        let pool = state.db_pool.clone();
+
+        let created = create_kiosk_token(
+            state.clone(),
+            web::Path::from("room-1".to_string()),
+            web::Json(CreateKioskTokenRequest {
+                label: "Entrance tablet".to_string(),
+                scopes: Some(vec!["equipment:read".to_string(), "usage:write".to_string()]),
+                expires_in_days: Some(30),
+            }),
+            "admin".to_string(),
+        ).await.unwrap();
+        assert!(created.token.starts_with(KIOSK_TOKEN_PREFIX));
+        assert_eq!(created.info.scopes, "equipment:read,usage:write");
+
+        // Храним только хэш
+        let (stored_hash,): (String,) = sqlx::query_as("SELECT token_hash FROM kiosk_tokens")
+            .fetch_one(&pool).await.unwrap();
+        assert_ne!(stored_hash, created.token);
+
+        let kiosk = resolve_kiosk_token(&pool, &created.token).await.unwrap();
+        assert_eq!(kiosk.room_name, "Lab 101");
+        assert!(kiosk.has_scope(KioskScope::UsageWrite));
+        assert!(!kiosk.has_scope(KioskScope::AttendanceWrite));
+        assert!(resolve_kiosk_token(&pool, "kiosk_not-a-real-token").await.is_err());
+
+        // Оборудование другого помещения киоску недоступно
+        let err = kiosk_checkout_equipment(
+            state.clone(), web::Path::from("eq-2".to_string()), actor("badge-42"), kiosk_request(&kiosk),
+        ).await.unwrap_err();
+        assert_eq!(err.error_response().status(), 404);
+
+        let err = kiosk_checkout_equipment(
+            state.clone(), web::Path::from("eq-1".to_string()), actor("   "), kiosk_request(&kiosk),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::ValidationError(_)));
+
+        kiosk_checkout_equipment(
+            state.clone(), web::Path::from("eq-1".to_string()), actor("badge-42"), kiosk_request(&kiosk),
+        ).await.unwrap();
+        let (status,): (String,) = sqlx::query_as("SELECT status FROM equipment WHERE id = 'eq-1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(status, "in_use");
+
+        // Единственная единица уже выдана
+        assert!(kiosk_checkout_equipment(
+            state.clone(), web::Path::from("eq-1".to_string()), actor("badge-7"), kiosk_request(&kiosk),
+        ).await.is_err());
+
+        kiosk_checkin_equipment(
+            state.clone(), web::Path::from("eq-1".to_string()), actor("BADGE-42"), kiosk_request(&kiosk),
+        ).await.unwrap();
+        let (status,): (String,) = sqlx::query_as("SELECT status FROM equipment WHERE id = 'eq-1'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(status, "available");
+
+        let (kiosk_entries, null_users): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), SUM(user_id IS NULL) FROM audit_logs \
+             WHERE via_kiosk = 1 AND actor_identifier = 'badge-42' COLLATE NOCASE AND kiosk_token_id = ?"
+        ).bind(&kiosk.token_id).fetch_one(&pool).await.unwrap();
+        assert_eq!((kiosk_entries, null_users), (2, 2));
+
+        revoke_kiosk_token(
+            state.clone(),
+            web::Path::from(("room-1".to_string(), created.info.id.clone())),
+            "admin".to_string(),
+        ).await.unwrap();
+        let err = resolve_kiosk_token(&pool, &created.token).await.unwrap_err();
+        assert_eq!(err.error_response().status(), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_restricted_equipment_checkout_requires_role() {
+        let state = test_app_state().await;
+        let pool = state.db_pool.clone();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) VALUES \
+             ('u-res', 'chemist', 'chemist@example.com', 'x', 'researcher', datetime('now'), datetime('now')), \
+             ('u-view', 'student', 'student@example.com', 'x', 'viewer', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query("UPDATE equipment SET restricted_to_roles = 'researcher', quantity = 3 WHERE id = 'eq-1'")
+            .execute(&pool).await.unwrap();
+
+        let created = create_kiosk_token(
+            state.clone(),
+            web::Path::from("room-1".to_string()),
+            web::Json(CreateKioskTokenRequest {
+                label: "Bench".to_string(),
+                scopes: Some(vec!["equipment:read".to_string(), "usage:write".to_string()]),
+                expires_in_days: None,
+            }),
+            "admin".to_string(),
+        ).await.unwrap();
+        let kiosk = resolve_kiosk_token(&pool, &created.token).await.unwrap();
+
+        let listed = get_kiosk_equipment(state.clone(), kiosk_request(&kiosk)).await.unwrap();
+        let body = actix_web::body::to_bytes(listed.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["data"][0]["restricted_to_roles"], "researcher");
+
+        for identifier in ["student@example.com", "badge-42"] {
+            let err = kiosk_checkout_equipment(
+                state.clone(), web::Path::from("eq-1".to_string()), actor(identifier), kiosk_request(&kiosk),
+            ).await.unwrap_err();
+            assert_eq!(err.error_response().status(), 403);
+            assert!(err.to_string().contains("restricted to roles: researcher"), "{}", err);
+        }
+
+        // Исследователь по имени пользователя и администратор - допускаются
+        for identifier in ["Chemist", "admin"] {
+            kiosk_checkout_equipment(
+                state.clone(), web::Path::from("eq-1".to_string()), actor(identifier), kiosk_request(&kiosk),
+            ).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,458 @@
+// src/kpi.rs
+//! Бизнес-показатели (KPI): расход партий, завершённые эксперименты, импорты,
+//! загрузки файлов и активные пользователи по дням.
+//!
+//! Счётчики ведутся в памяти (`Metrics::kpis`, общие для всего процесса) и попадают в
+//! /metrics. Фоновая задача раз в несколько минут переносит накопленные приращения в
+//! `metrics_daily` (сутки UTC), поэтому история переживает перезапуск.
+//! `GET /admin/kpis?from=&to=&metric=` отдаёт дневные ряды с нулями в пропущенных днях.
+
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::error::{ApiError, ApiResult};
+use crate::events::BusinessEvent;
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Период по умолчанию для GET /admin/kpis
+const DEFAULT_PERIOD_DAYS: i64 = 30;
+/// Предел длины ряда (дней) в одном запросе
+const MAX_PERIOD_DAYS: i64 = 366;
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kpi {
+    BatchesConsumed,
+    ExperimentsCompleted,
+    ImportsRun,
+    FilesUploaded,
+    ActiveUsers,
+}
+
+impl Kpi {
+    pub const ALL: [Kpi; 5] = [
+        Kpi::BatchesConsumed,
+        Kpi::ExperimentsCompleted,
+        Kpi::ImportsRun,
+        Kpi::FilesUploaded,
+        Kpi::ActiveUsers,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kpi::BatchesConsumed => "batches_consumed",
+            Kpi::ExperimentsCompleted => "experiments_completed",
+            Kpi::ImportsRun => "imports_run",
+            Kpi::FilesUploaded => "files_uploaded",
+            Kpi::ActiveUsers => "active_users",
+        }
+    }
+
+    pub fn parse(value: &str) -> ApiResult<Self> {
+        Self::ALL.iter()
+            .copied()
+            .find(|k| k.as_str() == value.trim())
+            .ok_or_else(|| ApiError::bad_request(&format!(
+                "Invalid metric '{}'. Must be one of: {}",
+                value.trim(),
+                Self::ALL.iter().map(Kpi::as_str).collect::<Vec<_>>().join(", ")
+            )))
+    }
+
+    /// Имя метрики Prometheus, имя метки (если есть), тип и описание
+    fn prometheus(&self) -> (&'static str, Option<&'static str>, &'static str, &'static str) {
+        match self {
+            Kpi::BatchesConsumed => ("lims_batches_consumed_total", None, "counter", "Batch consumption records"),
+            Kpi::ExperimentsCompleted => ("lims_experiments_completed_total", Some("trigger"), "counter", "Experiments completed"),
+            Kpi::ImportsRun => ("lims_imports_total", Some("entity"), "counter", "Successful bulk imports"),
+            Kpi::FilesUploaded => ("lims_files_uploaded_total", Some("kind"), "counter", "Files uploaded"),
+            Kpi::ActiveUsers => ("lims_active_users", None, "gauge", "Distinct users with authenticated requests today (UTC)"),
+        }
+    }
+}
+
+/// Значение счётчика для /metrics; для active_users - число пользователей за текущие сутки
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KpiTotal {
+    pub metric: &'static str,
+    pub label: String,
+    pub value: u64,
+}
+
+// ==================== COUNTERS ====================
+
+#[derive(Debug, Default)]
+struct KpiState {
+    /// С запуска процесса (counter в Prometheus)
+    totals: BTreeMap<(Kpi, String), u64>,
+    /// Ещё не перенесённые в metrics_daily приращения по суткам
+    pending: BTreeMap<(NaiveDate, Kpi, String), u64>,
+    users_day: Option<NaiveDate>,
+    users_today: HashSet<String>,
+    pending_users: HashSet<(NaiveDate, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct KpiCounters {
+    state: Mutex<KpiState>,
+}
+
+impl KpiCounters {
+    pub fn increment(&self, kpi: Kpi, label: &str, day: NaiveDate) {
+        if let Ok(mut state) = self.state.lock() {
+            *state.totals.entry((kpi, label.to_string())).or_default() += 1;
+            *state.pending.entry((day, kpi, label.to_string())).or_default() += 1;
+        }
+    }
+
+    pub fn record_active_user(&self, user_id: &str, day: NaiveDate) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.users_day != Some(day) {
+                state.users_day = Some(day);
+                state.users_today.clear();
+            }
+            if state.users_today.insert(user_id.to_string()) {
+                state.pending_users.insert((day, user_id.to_string()));
+            }
+        }
+    }
+
+    pub fn totals(&self) -> Vec<KpiTotal> {
+        let Ok(state) = self.state.lock() else { return Vec::new() };
+        let mut totals: Vec<KpiTotal> = state.totals.iter()
+            .map(|((kpi, label), value)| KpiTotal { metric: kpi.as_str(), label: label.clone(), value: *value })
+            .collect();
+        let today = Utc::now().date_naive();
+        totals.push(KpiTotal {
+            metric: Kpi::ActiveUsers.as_str(),
+            label: String::new(),
+            value: if state.users_day == Some(today) { state.users_today.len() as u64 } else { 0 },
+        });
+        totals
+    }
+
+    /// Забрать накопленные приращения (для записи в metrics_daily)
+    fn take_pending(&self) -> PendingKpis {
+        match self.state.lock() {
+            Ok(mut state) => PendingKpis {
+                counts: std::mem::take(&mut state.pending),
+                users: std::mem::take(&mut state.pending_users),
+            },
+            Err(_) => PendingKpis::default(),
+        }
+    }
+
+    /// Вернуть приращения, которые не удалось записать
+    fn restore(&self, pending: PendingKpis) {
+        if let Ok(mut state) = self.state.lock() {
+            for (key, value) in pending.counts {
+                *state.pending.entry(key).or_default() += value;
+            }
+            state.pending_users.extend(pending.users);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PendingKpis {
+    counts: BTreeMap<(NaiveDate, Kpi, String), u64>,
+    users: HashSet<(NaiveDate, String)>,
+}
+
+static COUNTERS: OnceLock<Arc<KpiCounters>> = OnceLock::new();
+
+/// Счётчики процесса (их же держит `Metrics::kpis`)
+pub fn counters() -> Arc<KpiCounters> {
+    COUNTERS.get_or_init(|| Arc::new(KpiCounters::default())).clone()
+}
+
+pub fn record(kpi: Kpi, label: &str) {
+    counters().increment(kpi, label, Utc::now().date_naive());
+}
+
+/// Учёт доставленных из outbox событий: расход партий и завершение экспериментов
+pub fn record_event(event: &BusinessEvent) {
+    match event {
+        BusinessEvent::BatchConsumed { .. } => record(Kpi::BatchesConsumed, ""),
+        BusinessEvent::ExperimentCompleted { automatic, .. } => {
+            record(Kpi::ExperimentsCompleted, if *automatic { "automatic" } else { "manual" })
+        }
+        _ => {}
+    }
+}
+
+// ==================== DAILY ROLLUP ====================
+
+/// Перенести накопленные приращения в metrics_daily; при ошибке они остаются в памяти
+pub async fn flush(pool: &SqlitePool, counters: &KpiCounters) -> Result<(), sqlx::Error> {
+    let pending = counters.take_pending();
+    if pending.counts.is_empty() && pending.users.is_empty() {
+        return Ok(());
+    }
+    if let Err(e) = write_pending(pool, &pending).await {
+        counters.restore(pending);
+        return Err(e);
+    }
+    Ok(())
+}
+
+async fn write_pending(pool: &SqlitePool, pending: &PendingKpis) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    for ((day, kpi, label), value) in &pending.counts {
+        sqlx::query(
+            r#"INSERT INTO metrics_daily (day, metric, label, value, updated_at) VALUES (?, ?, ?, ?, ?)
+               ON CONFLICT(day, metric, label) DO UPDATE SET value = value + excluded.value, updated_at = excluded.updated_at"#
+        )
+            .bind(day.format("%Y-%m-%d").to_string())
+            .bind(kpi.as_str())
+            .bind(label)
+            .bind(*value as i64)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // Активные пользователи - число различных id за сутки, а не сумма приращений
+    let mut days: Vec<NaiveDate> = pending.users.iter().map(|(day, _)| *day).collect();
+    days.sort();
+    days.dedup();
+    for (day, user_id) in &pending.users {
+        sqlx::query("INSERT OR IGNORE INTO metrics_daily_users (day, user_id) VALUES (?, ?)")
+            .bind(day.format("%Y-%m-%d").to_string())
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for day in days {
+        let day = day.format("%Y-%m-%d").to_string();
+        sqlx::query(
+            r#"INSERT INTO metrics_daily (day, metric, label, value, updated_at)
+               SELECT ?, ?, '', COUNT(*), ? FROM metrics_daily_users WHERE day = ?
+               ON CONFLICT(day, metric, label) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at"#
+        )
+            .bind(&day)
+            .bind(Kpi::ActiveUsers.as_str())
+            .bind(now)
+            .bind(&day)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await
+}
+
+// ==================== TIME SERIES ====================
+
+#[derive(Debug, Deserialize)]
+pub struct KpiQuery {
+    /// YYYY-MM-DD, по умолчанию - 30 дней до `to`
+    pub from: Option<String>,
+    /// YYYY-MM-DD, по умолчанию - сегодня (UTC)
+    pub to: Option<String>,
+    pub metric: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KpiPoint {
+    pub date: NaiveDate,
+    pub value: i64,
+}
+
+/// Ряд по одной метрике и метке; по точке на каждый день периода
+#[derive(Debug, Serialize)]
+pub struct KpiSeries {
+    pub metric: &'static str,
+    pub label: String,
+    pub points: Vec<KpiPoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KpiReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub series: Vec<KpiSeries>,
+}
+
+pub(crate) fn kpi_period(from: Option<&str>, to: Option<&str>) -> ApiResult<(NaiveDate, NaiveDate)> {
+    let parse = |value: &str, key: &str| {
+        NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+            .map_err(|_| ApiError::bad_request(&format!("Invalid {}: expected YYYY-MM-DD", key)))
+    };
+    let to = match to {
+        Some(value) => parse(value, "to")?,
+        None => Utc::now().date_naive(),
+    };
+    let from = match from {
+        Some(value) => parse(value, "from")?,
+        None => to - Duration::days(DEFAULT_PERIOD_DAYS - 1),
+    };
+    if from > to {
+        return Err(ApiError::bad_request("from must not be after to"));
+    }
+    if (to - from).num_days() >= MAX_PERIOD_DAYS {
+        return Err(ApiError::bad_request(&format!("Period cannot exceed {} days", MAX_PERIOD_DAYS)));
+    }
+    Ok((from, to))
+}
+
+pub async fn load_kpi_series(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    to: NaiveDate,
+    metric: Option<Kpi>,
+) -> ApiResult<Vec<KpiSeries>> {
+    let rows: Vec<(String, String, String, i64)> = sqlx::query_as(
+        r#"SELECT day, metric, label, value FROM metrics_daily
+           WHERE day BETWEEN ? AND ? AND (? IS NULL OR metric = ?)
+           ORDER BY metric, label, day"#
+    )
+        .bind(from.format("%Y-%m-%d").to_string())
+        .bind(to.format("%Y-%m-%d").to_string())
+        .bind(metric.map(|m| m.as_str()))
+        .bind(metric.map(|m| m.as_str()))
+        .fetch_all(pool)
+        .await?;
+
+    let mut values: BTreeMap<(Kpi, String), BTreeMap<NaiveDate, i64>> = BTreeMap::new();
+    for kpi in metric.map(|m| vec![m]).unwrap_or_else(|| Kpi::ALL.to_vec()) {
+        // Метрики без меток всегда дают ряд, пусть и из нулей
+        if kpi.prometheus().1.is_none() {
+            values.entry((kpi, String::new())).or_default();
+        }
+    }
+    for (day, name, label, value) in rows {
+        let (Ok(kpi), Ok(day)) = (Kpi::parse(&name), NaiveDate::parse_from_str(&day, "%Y-%m-%d")) else { continue };
+        values.entry((kpi, label)).or_default().insert(day, value);
+    }
+
+    let days: Vec<NaiveDate> = from.iter_days().take_while(|d| *d <= to).collect();
+    Ok(values.into_iter()
+        .map(|((kpi, label), by_day)| KpiSeries {
+            metric: kpi.as_str(),
+            label,
+            points: days.iter()
+                .map(|date| KpiPoint { date: *date, value: by_day.get(date).copied().unwrap_or(0) })
+                .collect(),
+        })
+        .collect())
+}
+
+/// GET /admin/kpis?from=&to=&metric=
+pub async fn get_kpis(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<KpiQuery>,
+) -> ApiResult<HttpResponse> {
+    let (from, to) = kpi_period(query.from.as_deref(), query.to.as_deref())?;
+    let metric = query.metric.as_deref().map(Kpi::parse).transpose()?;
+
+    // Сегодняшние приращения ещё в памяти - сначала переносим их в таблицу
+    flush(&app_state.db_pool, &counters()).await?;
+    let series = load_kpi_series(app_state.read_pool(), from, to, metric).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(KpiReport { from, to, series })))
+}
+
+// ==================== PROMETHEUS ====================
+
+/// KPI в текстовом формате Prometheus (часть /metrics?format=prometheus)
+pub fn render_prometheus(totals: &[KpiTotal]) -> String {
+    let mut out = String::new();
+    for kpi in Kpi::ALL {
+        let (name, label_name, kind, help) = kpi.prometheus();
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+        let values = totals.iter().filter(|t| t.metric == kpi.as_str());
+        match label_name {
+            Some(label_name) => {
+                for total in values {
+                    out.push_str(&format!("{name}{{{label_name}=\"{}\"}} {}\n", total.label, total.value));
+                }
+            }
+            None => out.push_str(&format!("{name} {}\n", values.map(|t| t.value).sum::<u64>())),
+        }
+    }
+    out
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixtures::{ADMIN_ID, RESEARCHER_ID};
+    use crate::test_support::TestApp;
+
+    fn day(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_flush_accumulates_daily_rollups() {
+        let app = TestApp::new().await;
+        let pool = app.pool.clone();
+        let counters = KpiCounters::default();
+
+        counters.increment(Kpi::ImportsRun, "reagents", day("2025-03-01"));
+        counters.increment(Kpi::ImportsRun, "reagents", day("2025-03-01"));
+        counters.increment(Kpi::BatchesConsumed, "", day("2025-03-02"));
+        counters.record_active_user(RESEARCHER_ID, day("2025-03-01"));
+        counters.record_active_user(RESEARCHER_ID, day("2025-03-01"));
+        counters.record_active_user(ADMIN_ID, day("2025-03-01"));
+        flush(&pool, &counters).await.unwrap();
+
+        // Второй прогон добавляет приращения; пользователь, уже учтённый за сутки, не удваивается
+        counters.increment(Kpi::ImportsRun, "reagents", day("2025-03-01"));
+        counters.record_active_user(RESEARCHER_ID, day("2025-03-02"));
+        counters.record_active_user(RESEARCHER_ID, day("2025-03-01"));
+        flush(&pool, &counters).await.unwrap();
+
+        let series = load_kpi_series(&pool, day("2025-03-01"), day("2025-03-03"), None).await.unwrap();
+        let values = |metric: &str, label: &str| -> Vec<i64> {
+            series.iter()
+                .find(|s| s.metric == metric && s.label == label)
+                .map(|s| s.points.iter().map(|p| p.value).collect())
+                .unwrap_or_default()
+        };
+        assert_eq!(values("imports_run", "reagents"), vec![3, 0, 0]);
+        assert_eq!(values("batches_consumed", ""), vec![0, 1, 0]);
+        assert_eq!(values("active_users", ""), vec![2, 1, 0]);
+        assert!(series.iter().all(|s| s.points.len() == 3));
+
+        let only = load_kpi_series(&pool, day("2025-03-01"), day("2025-03-01"), Some(Kpi::FilesUploaded)).await.unwrap();
+        assert!(only.is_empty());
+        let totals = counters.totals();
+        assert!(totals.contains(&KpiTotal { metric: "imports_run", label: "reagents".to_string(), value: 3 }));
+    }
+
+    #[test]
+    fn test_kpi_period_and_metric_validation() {
+        let (from, to) = kpi_period(None, Some("2025-03-30")).unwrap();
+        assert_eq!((from, to), (day("2025-03-01"), day("2025-03-30")));
+        assert!(kpi_period(Some("2025-04-01"), Some("2025-03-01")).is_err());
+        assert!(kpi_period(Some("2023-01-01"), Some("2025-03-01")).is_err());
+        assert!(kpi_period(Some("01.03.2025"), None).is_err());
+        assert_eq!(Kpi::parse("files_uploaded").unwrap(), Kpi::FilesUploaded);
+        assert!(matches!(Kpi::parse("logins"), Err(ApiError::BadRequest(ref msg)) if msg.contains("active_users")));
+    }
+
+    #[test]
+    fn test_render_prometheus_names_and_labels() {
+        let totals = vec![
+            KpiTotal { metric: "imports_run", label: "batches".to_string(), value: 4 },
+            KpiTotal { metric: "experiments_completed", label: "automatic".to_string(), value: 2 },
+            KpiTotal { metric: "active_users", label: String::new(), value: 7 },
+        ];
+        let text = render_prometheus(&totals);
+        assert!(text.contains("# TYPE lims_imports_total counter\nlims_imports_total{entity=\"batches\"} 4\n"));
+        assert!(text.contains("lims_experiments_completed_total{trigger=\"automatic\"} 2\n"));
+        assert!(text.contains("lims_batches_consumed_total 0\n"));
+        assert!(text.contains("# TYPE lims_active_users gauge\nlims_active_users 7\n"));
+    }
+}
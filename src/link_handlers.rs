@@ -0,0 +1,334 @@
+// src/link_handlers.rs
+//! Внешние ссылки на документы (SOP, страницы поставщиков), привязанные к
+//! реагентам, партиям, оборудованию и экспериментам.
+//!
+//! Ссылки хранятся в одной таблице `external_links` с полиморфной привязкой
+//! (entity_type, entity_id); при удалении родителя их удаляют триггеры БД.
+
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{CreateExternalLinkRequest, ExternalLink, LinkEntityType};
+use crate::report_handlers::escape_like_pattern;
+use crate::AppState;
+
+/// Максимальное число ссылок на одну сущность
+const MAX_LINKS_PER_ENTITY: i64 = 100;
+
+const LINK_SELECT: &str = r#"
+    SELECT l.id, l.entity_type, l.entity_id, l.title, l.url, l.created_by,
+           u.username AS created_by_username, l.created_at
+    FROM external_links l
+    LEFT JOIN users u ON u.id = l.created_by
+"#;
+
+/// Ссылки сущности, новые - первыми
+pub async fn fetch_links(
+    pool: &SqlitePool,
+    entity: LinkEntityType,
+    entity_id: &str,
+) -> ApiResult<Vec<ExternalLink>> {
+    let links = sqlx::query_as::<_, ExternalLink>(&format!(
+        "{} WHERE l.entity_type = ? AND l.entity_id = ? ORDER BY l.created_at DESC, l.id",
+        LINK_SELECT
+    ))
+        .bind(entity.as_str())
+        .bind(entity_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(links)
+}
+
+/// Поиск ссылок по названию (для глобального поиска)
+pub async fn search_links(pool: &SqlitePool, term: &str, limit: i64) -> ApiResult<Vec<ExternalLink>> {
+    let pattern = format!("%{}%", escape_like_pattern(term));
+    let links = sqlx::query_as::<_, ExternalLink>(&format!(
+        "{} WHERE l.title LIKE ? ESCAPE '\\' ORDER BY l.title COLLATE NOCASE LIMIT ?",
+        LINK_SELECT
+    ))
+        .bind(pattern)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(links)
+}
+
+async fn ensure_entity_exists(pool: &SqlitePool, entity: LinkEntityType, entity_id: &str) -> ApiResult<()> {
+    let deleted_filter = if entity.soft_deletable() { " AND deleted_at IS NULL" } else { "" };
+    let sql = format!("SELECT COUNT(*) FROM {} WHERE id = ?{}", entity.table(), deleted_filter);
+    let (count,): (i64,) = sqlx::query_as(&sql)
+        .bind(entity_id)
+        .fetch_one(pool)
+        .await?;
+    if count == 0 {
+        let name = match entity {
+            LinkEntityType::Reagent => "Reagent",
+            LinkEntityType::Batch => "Batch",
+            LinkEntityType::Equipment => "Equipment",
+            LinkEntityType::Experiment => "Experiment",
+        };
+        return Err(ApiError::not_found(name));
+    }
+    Ok(())
+}
+
+// ==================== LIST ====================
+
+pub async fn list_links(
+    app_state: web::Data<Arc<AppState>>,
+    entity: LinkEntityType,
+    entity_id: String,
+) -> ApiResult<HttpResponse> {
+    ensure_entity_exists(&app_state.db_pool, entity, &entity_id).await?;
+    let links = fetch_links(&app_state.db_pool, entity, &entity_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(links)))
+}
+
+pub async fn get_reagent_links(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    list_links(app_state, LinkEntityType::Reagent, path.into_inner()).await
+}
+
+pub async fn get_batch_links(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    list_links(app_state, LinkEntityType::Batch, path.into_inner()).await
+}
+
+pub async fn get_equipment_links(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    list_links(app_state, LinkEntityType::Equipment, path.into_inner()).await
+}
+
+pub async fn get_experiment_links(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    list_links(app_state, LinkEntityType::Experiment, path.into_inner()).await
+}
+
+// ==================== ADD ====================
+
+pub async fn add_link(
+    app_state: web::Data<Arc<AppState>>,
+    entity: LinkEntityType,
+    entity_id: String,
+    body: web::Json<CreateExternalLinkRequest>,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let pool = &app_state.db_pool;
+    ensure_entity_exists(pool, entity, &entity_id).await?;
+
+    let (count,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM external_links WHERE entity_type = ? AND entity_id = ?"
+    )
+        .bind(entity.as_str())
+        .bind(&entity_id)
+        .fetch_one(pool)
+        .await?;
+    if count >= MAX_LINKS_PER_ENTITY {
+        return Err(ApiError::bad_request(&format!(
+            "Cannot add more than {} links to one {}", MAX_LINKS_PER_ENTITY, entity.as_str()
+        )));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO external_links (id, entity_type, entity_id, title, url, created_by, created_at) \
+         VALUES (?, ?, ?, ?, ?, ?, ?)"
+    )
+        .bind(&id)
+        .bind(entity.as_str())
+        .bind(&entity_id)
+        .bind(body.title.trim())
+        .bind(&body.url)
+        .bind(&user_id)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+    let link = sqlx::query_as::<_, ExternalLink>(&format!("{} WHERE l.id = ?", LINK_SELECT))
+        .bind(&id)
+        .fetch_one(pool)
+        .await?;
+
+    log::info!("🔗 Link '{}' added to {} {}", link.title, entity.as_str(), entity_id);
+    Ok(HttpResponse::Created().json(ApiResponse::success(link)))
+}
+
+// ==================== DELETE ====================
+
+pub async fn delete_link(
+    app_state: web::Data<Arc<AppState>>,
+    entity: LinkEntityType,
+    entity_id: String,
+    link_id: String,
+) -> ApiResult<HttpResponse> {
+    let result = sqlx::query(
+        "DELETE FROM external_links WHERE id = ? AND entity_type = ? AND entity_id = ?"
+    )
+        .bind(&link_id)
+        .bind(entity.as_str())
+        .bind(&entity_id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Link"));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        (),
+        "Link deleted successfully".to_string(),
+    )))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_app_state() -> web::Data<Arc<AppState>> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('tester', 'tester', 'tester@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Acetone', 'active', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, status, \
+             received_date, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'B-1', 1.0, 1.0, 'L', 'available', datetime('now'), datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, \
+             created_by, created_at, updated_at) \
+             VALUES ('e1', 'Titration', '2024-01-10 09:00:00', '2024-01-10 09:00:00', 'planned', 'research', \
+             'tester', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    fn link(title: &str, url: &str) -> web::Json<CreateExternalLinkRequest> {
+        web::Json(CreateExternalLinkRequest { title: title.to_string(), url: url.to_string() })
+    }
+
+    async fn links_count(pool: &SqlitePool) -> i64 {
+        sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM external_links")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+            .0
+    }
+
+    #[actix_web::test]
+    async fn test_add_list_and_delete_link() {
+        let state = test_app_state().await;
+        let resp = add_link(
+            state.clone(), LinkEntityType::Reagent, "r1".to_string(),
+            link("Handling SOP", "https://confluence.example.com/SOP-12"), "tester".to_string(),
+        ).await.unwrap();
+        assert_eq!(resp.status(), actix_web::http::StatusCode::CREATED);
+
+        let links = fetch_links(&state.db_pool, LinkEntityType::Reagent, "r1").await.unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].created_by_username.as_deref(), Some("tester"));
+
+        // Ссылка другой сущности не удаляется через чужой путь
+        assert!(delete_link(state.clone(), LinkEntityType::Batch, "b1".to_string(), links[0].id.clone()).await.is_err());
+        delete_link(state.clone(), LinkEntityType::Reagent, "r1".to_string(), links[0].id.clone()).await.unwrap();
+        assert_eq!(links_count(&state.db_pool).await, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_add_link_validation() {
+        let state = test_app_state().await;
+        assert!(add_link(
+            state.clone(), LinkEntityType::Experiment, "e1".to_string(),
+            link("Bad", "javascript:alert(1)"), "tester".to_string(),
+        ).await.is_err());
+        assert!(add_link(
+            state.clone(), LinkEntityType::Equipment, "missing".to_string(),
+            link("Manual", "https://vendor.example.com"), "tester".to_string(),
+        ).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_links_removed_with_parent() {
+        let state = test_app_state().await;
+        for (entity, id) in [
+            (LinkEntityType::Reagent, "r1"),
+            (LinkEntityType::Batch, "b1"),
+            (LinkEntityType::Experiment, "e1"),
+        ] {
+            add_link(
+                state.clone(), entity, id.to_string(),
+                link("Vendor page", "https://vendor.example.com"), "tester".to_string(),
+            ).await.unwrap();
+        }
+        assert_eq!(links_count(&state.db_pool).await, 3);
+
+        sqlx::query("DELETE FROM experiments WHERE id = 'e1'").execute(&state.db_pool).await.unwrap();
+        assert_eq!(links_count(&state.db_pool).await, 2);
+
+        // Мягкое удаление реагента каскадно помечает партии - ссылки обеих удаляются
+        sqlx::query("UPDATE reagents SET deleted_at = datetime('now') WHERE id = 'r1'")
+            .execute(&state.db_pool).await.unwrap();
+        sqlx::query("UPDATE batches SET deleted_at = datetime('now') WHERE reagent_id = 'r1'")
+            .execute(&state.db_pool).await.unwrap();
+        assert_eq!(links_count(&state.db_pool).await, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_search_links_by_title() {
+        let state = test_app_state().await;
+        for title in ["Handling SOP", "Vendor datasheet", "100%_sure"] {
+            add_link(
+                state.clone(), LinkEntityType::Reagent, "r1".to_string(),
+                link(title, "https://example.com"), "tester".to_string(),
+            ).await.unwrap();
+        }
+        let found = search_links(&state.db_pool, "sop", 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].title, "Handling SOP");
+        assert_eq!(search_links(&state.db_pool, "%", 10).await.unwrap().len(), 1);
+
+        let resp = crate::handlers::global_search(
+            state.clone(),
+            web::Query::from_query("q=sop").unwrap(),
+        ).await.unwrap();
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let hits = json["data"].as_array().unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0]["entity_type"], "link");
+        assert_eq!(hits[0]["parent_type"], "reagent");
+        assert_eq!(hits[0]["parent_id"], "r1");
+    }
+}
@@ -0,0 +1,720 @@
+// src/location_handlers.rs
+//! Иерархия мест хранения (здание > помещение > шкаф > полка > коробка).
+//!
+//! Endpoints:
+//!   GET    /locations                  — все узлы с полным путём
+//!   POST   /locations                  — создать узел
+//!   GET    /locations/{id}             — узел с путём
+//!   PUT    /locations/{id}             — имя, тип, помещение, описание
+//!   POST   /locations/{id}/move        — перенести узел вместе со всем содержимым
+//!   DELETE /locations/{id}?move_to=    — удалить; непустой узел требует move_to
+//!   GET    /locations/{id}/contents    — партии в узле и во всех вложенных узлах
+//!
+//! Партия ссылается на узел через batches.location_id, а batches.location хранит
+//! путь узла ("Fridge 2, Shelf B, Box 3") для отображения в старых клиентах и отчётах.
+//! Путь строится только в SQL (рекурсивные CTE ниже) и пересчитывается у всех партий
+//! поддерева при переименовании, переносе и удалении узла.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::audit;
+use crate::auth::get_current_user;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{CreateLocationRequest, Location, LocationType, MoveLocationRequest, UpdateLocationRequest};
+use crate::AppState;
+
+/// Ограничение CHECK на batches.location
+const MAX_LOCATION_TEXT_LEN: usize = 255;
+/// Предел партий в ответе /contents
+const CONTENTS_LIMIT: i64 = 1000;
+
+/// id узла и всех вложенных узлов; единственный параметр - id узла.
+/// Подзапрос для фильтров: `b.location_id IN (...)`
+pub const LOCATION_SUBTREE_SQL: &str = "WITH RECURSIVE subtree(id) AS (\
+    SELECT ? UNION ALL SELECT l.id FROM locations l JOIN subtree s ON l.parent_id = s.id\
+    ) SELECT id FROM subtree";
+
+/// Цепочка от узла (параметр) к корню; у строки корня (parent_id IS NULL)
+/// path - полный путь узла, depth - его глубина
+const CHAIN_CTE: &str = "chain(id, parent_id, path, depth) AS (\
+    SELECT id, parent_id, name, 0 FROM locations WHERE id = ? \
+    UNION ALL \
+    SELECT p.id, p.parent_id, p.name || ', ' || c.path, c.depth + 1 \
+    FROM locations p JOIN chain c ON p.id = c.parent_id)";
+
+/// Узел и все вложенные узлы с полными путями; после CHAIN_CTE, параметр - id узла
+const TREE_CTE: &str = "tree(id, path) AS (\
+    SELECT ?, (SELECT path FROM chain WHERE parent_id IS NULL) \
+    UNION ALL \
+    SELECT l.id, t.path || ', ' || l.name FROM locations l JOIN tree t ON l.parent_id = t.id)";
+
+const ALL_NODES_SQL: &str = r#"
+    WITH RECURSIVE nodes AS (
+        SELECT l.*, l.name AS path, 0 AS depth FROM locations l WHERE l.parent_id IS NULL
+        UNION ALL
+        SELECT c.*, n.path || ', ' || c.name, n.depth + 1 FROM locations c JOIN nodes n ON c.parent_id = n.id
+    )
+    SELECT * FROM nodes ORDER BY path
+"#;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct LocationNode {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub location: Location,
+    /// Полный путь от корня, как в batches.location
+    pub path: String,
+    /// 0 - узел верхнего уровня
+    pub depth: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct LocationBatch {
+    pub id: String,
+    pub reagent_id: String,
+    pub reagent_name: String,
+    pub batch_number: String,
+    pub quantity: f64,
+    pub unit: String,
+    pub status: String,
+    pub expiry_date: Option<DateTime<Utc>>,
+    pub location_id: String,
+    pub location_path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocationContents {
+    pub location: LocationNode,
+    pub batches: Vec<LocationBatch>,
+    /// Партий больше CONTENTS_LIMIT - используйте GET /batches?location_id=
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteLocationQuery {
+    /// Узел, который получит вложенные узлы и партии непустого узла
+    pub move_to: Option<String>,
+}
+
+// ==================== HELPERS ====================
+
+async fn fetch_location(pool: &SqlitePool, id: &str) -> ApiResult<Option<Location>> {
+    Ok(sqlx::query_as("SELECT * FROM locations WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?)
+}
+
+async fn fetch_node(pool: &SqlitePool, id: &str) -> ApiResult<LocationNode> {
+    let location = fetch_location(pool, id).await?.ok_or_else(|| ApiError::not_found("Location"))?;
+    let (path, depth): (String, i64) = sqlx::query_as(&format!(
+        "WITH RECURSIVE {} SELECT path, depth FROM chain WHERE parent_id IS NULL",
+        CHAIN_CTE
+    ))
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+    Ok(LocationNode { location, path, depth })
+}
+
+/// Путь узла для batches.location (в пределах ограничения колонки); None - узла нет
+pub async fn location_display_path(pool: &SqlitePool, location_id: &str) -> ApiResult<Option<String>> {
+    Ok(sqlx::query_scalar(&format!(
+        "WITH RECURSIVE {} SELECT substr(path, 1, {}) FROM chain WHERE parent_id IS NULL",
+        CHAIN_CTE, MAX_LOCATION_TEXT_LEN
+    ))
+    .bind(location_id)
+    .fetch_optional(pool)
+    .await?)
+}
+
+fn location_type_of(location: &Location) -> ApiResult<LocationType> {
+    LocationType::parse(&location.location_type).ok_or_else(|| {
+        ApiError::InternalServerError(format!("Unknown location type '{}'", location.location_type))
+    })
+}
+
+/// Родитель существует и может содержать узел данного типа
+async fn check_parent(pool: &SqlitePool, parent_id: &str, child_type: LocationType) -> ApiResult<Location> {
+    let parent = fetch_location(pool, parent_id).await?
+        .ok_or_else(|| ApiError::bad_request("Parent location not found"))?;
+    let parent_type = location_type_of(&parent)?;
+    if !parent_type.can_contain(child_type) {
+        return Err(ApiError::bad_request(&format!(
+            "A {} cannot be placed inside a {}", child_type.as_str(), parent_type.as_str()
+        )));
+    }
+    Ok(parent)
+}
+
+async fn check_room(pool: &SqlitePool, room_id: &str) -> ApiResult<()> {
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM rooms WHERE id = ?)")
+        .bind(room_id)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::bad_request("Room not found"));
+    }
+    Ok(())
+}
+
+/// Имена соседних узлов уникальны без учёта регистра
+async fn check_sibling_name(pool: &SqlitePool, parent_id: Option<&str>, name: &str, exclude_id: &str) -> ApiResult<()> {
+    let duplicate: Option<String> = sqlx::query_scalar(
+        "SELECT id FROM locations WHERE parent_id IS ? AND LOWER(name) = LOWER(?) AND id != ?"
+    )
+    .bind(parent_id)
+    .bind(name)
+    .bind(exclude_id)
+    .fetch_optional(pool)
+    .await?;
+    if duplicate.is_some() {
+        return Err(ApiError::bad_request(&format!("Location '{}' already exists at this level", name)));
+    }
+    Ok(())
+}
+
+/// candidate_id - сам узел root_id или один из вложенных в него
+async fn is_within(pool: &SqlitePool, root_id: &str, candidate_id: &str) -> ApiResult<bool> {
+    Ok(sqlx::query_scalar(&format!("SELECT ? IN ({})", LOCATION_SUBTREE_SQL))
+        .bind(candidate_id)
+        .bind(root_id)
+        .fetch_one(pool)
+        .await?)
+}
+
+/// Пересчитать текстовый путь у партий узла и всех вложенных узлов
+async fn refresh_batch_locations(
+    conn: &mut SqliteConnection,
+    location_id: &str,
+    user_id: &str,
+    now: DateTime<Utc>,
+) -> ApiResult<u64> {
+    let result = sqlx::query(&format!(
+        "WITH RECURSIVE {}, {} \
+         UPDATE batches SET location = substr((SELECT path FROM tree WHERE tree.id = batches.location_id), 1, {}), \
+                updated_by = ?, updated_at = ? \
+         WHERE location_id IN (SELECT id FROM tree)",
+        CHAIN_CTE, TREE_CTE, MAX_LOCATION_TEXT_LEN
+    ))
+    .bind(location_id)
+    .bind(location_id)
+    .bind(user_id)
+    .bind(now)
+    .execute(&mut *conn)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+fn trimmed_name(name: &str) -> ApiResult<&str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiError::bad_request("Location name cannot be empty"));
+    }
+    Ok(name)
+}
+
+// ==================== CRUD ====================
+
+pub async fn get_locations(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let nodes: Vec<LocationNode> = sqlx::query_as(ALL_NODES_SQL)
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(nodes)))
+}
+
+pub async fn get_location(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let node = fetch_node(&app_state.db_pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(node)))
+}
+
+pub async fn create_location(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreateLocationRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    body.validate()?;
+    let pool = &app_state.db_pool;
+    let name = trimmed_name(&body.name)?;
+
+    if let Some(ref parent_id) = body.parent_id {
+        check_parent(pool, parent_id, body.location_type).await?;
+    }
+    if let Some(ref room_id) = body.room_id {
+        check_room(pool, room_id).await?;
+    }
+    check_sibling_name(pool, body.parent_id.as_deref(), name, "").await?;
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query(
+        r#"INSERT INTO locations (id, parent_id, name, location_type, room_id, description,
+                                  created_by, updated_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+    .bind(&id)
+    .bind(&body.parent_id)
+    .bind(name)
+    .bind(body.location_type.as_str())
+    .bind(&body.room_id)
+    .bind(&body.description)
+    .bind(&claims.sub)
+    .bind(&claims.sub)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    let node = fetch_node(pool, &id).await?;
+    audit::audit(
+        pool, &claims.sub, "create", "location", &id,
+        &format!("Created {} '{}'", body.location_type.as_str(), node.path),
+        &http_request,
+    ).await;
+    Ok(HttpResponse::Created().json(ApiResponse::success(node)))
+}
+
+pub async fn update_location(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<UpdateLocationRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    body.validate()?;
+    let pool = &app_state.db_pool;
+    let id = path.into_inner();
+    let existing = fetch_location(pool, &id).await?.ok_or_else(|| ApiError::not_found("Location"))?;
+
+    let location_type = match body.location_type {
+        Some(new_type) => {
+            if let Some(ref parent_id) = existing.parent_id {
+                check_parent(pool, parent_id, new_type).await?;
+            }
+            let child_types: Vec<String> = sqlx::query_scalar(
+                "SELECT DISTINCT location_type FROM locations WHERE parent_id = ?"
+            )
+            .bind(&id)
+            .fetch_all(pool)
+            .await?;
+            if let Some(child) = child_types.iter().filter_map(|t| LocationType::parse(t)).find(|c| !new_type.can_contain(*c)) {
+                return Err(ApiError::bad_request(&format!(
+                    "A {} cannot contain a {}", new_type.as_str(), child.as_str()
+                )));
+            }
+            new_type
+        }
+        None => location_type_of(&existing)?,
+    };
+
+    let name = match body.name {
+        Some(ref name) => trimmed_name(name)?,
+        None => existing.name.as_str(),
+    };
+    let renamed = name != existing.name;
+    if renamed {
+        check_sibling_name(pool, existing.parent_id.as_deref(), name, &id).await?;
+    }
+    if let Some(ref room_id) = body.room_id {
+        check_room(pool, room_id).await?;
+    }
+
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"UPDATE locations SET
+            name = ?, location_type = ?,
+            room_id = COALESCE(?, room_id),
+            description = COALESCE(?, description),
+            updated_by = ?, updated_at = ?
+        WHERE id = ?"#
+    )
+    .bind(name)
+    .bind(location_type.as_str())
+    .bind(&body.room_id)
+    .bind(&body.description)
+    .bind(&claims.sub)
+    .bind(now)
+    .bind(&id)
+    .execute(&mut *tx)
+    .await?;
+    if renamed {
+        refresh_batch_locations(&mut tx, &id, &claims.sub, now).await?;
+    }
+    tx.commit().await?;
+
+    let node = fetch_node(pool, &id).await?;
+    audit::audit(
+        pool, &claims.sub, "edit", "location", &id,
+        &format!("Location '{}' updated", node.path),
+        &http_request,
+    ).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(node)))
+}
+
+/// Перенос узла: вложенные узлы и партии остаются в нём, у партий обновляется путь
+pub async fn move_location(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<MoveLocationRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let id = path.into_inner();
+    let existing = fetch_location(pool, &id).await?.ok_or_else(|| ApiError::not_found("Location"))?;
+    let old_path = fetch_node(pool, &id).await?.path;
+
+    if let Some(ref parent_id) = body.parent_id {
+        if is_within(pool, &id, parent_id).await? {
+            return Err(ApiError::bad_request("A location cannot be moved inside itself"));
+        }
+        check_parent(pool, parent_id, location_type_of(&existing)?).await?;
+    }
+    check_sibling_name(pool, body.parent_id.as_deref(), &existing.name, &id).await?;
+
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE locations SET parent_id = ?, updated_by = ?, updated_at = ? WHERE id = ?")
+        .bind(&body.parent_id)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await?;
+    let moved_batches = refresh_batch_locations(&mut tx, &id, &claims.sub, now).await?;
+    tx.commit().await?;
+
+    let node = fetch_node(pool, &id).await?;
+    audit::audit(
+        pool, &claims.sub, "move", "location", &id,
+        &format!("Moved location '{}' to '{}' ({} batches re-homed)", old_path, node.path, moved_batches),
+        &http_request,
+    ).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(node)))
+}
+
+/// Удаление узла. Вложенные узлы и партии непустого узла переносятся в move_to
+pub async fn delete_location(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<DeleteLocationQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let id = path.into_inner();
+    let node = fetch_node(pool, &id).await?;
+
+    let (children, batches): (i64, i64) = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM locations WHERE parent_id = ?), \
+                (SELECT COUNT(*) FROM batches WHERE location_id = ? AND deleted_at IS NULL)"
+    )
+    .bind(&id)
+    .bind(&id)
+    .fetch_one(pool)
+    .await?;
+
+    let target = if children + batches > 0 {
+        let target_id = query.move_to.as_deref().ok_or_else(|| ApiError::bad_request(&format!(
+            "Location is not empty ({} sub-locations, {} batches): pass move_to to choose where its contents go",
+            children, batches
+        )))?;
+        if is_within(pool, &id, target_id).await? {
+            return Err(ApiError::bad_request("move_to must be outside the deleted location"));
+        }
+        let target = fetch_location(pool, target_id).await?
+            .ok_or_else(|| ApiError::bad_request("Target location not found"))?;
+        let target_type = location_type_of(&target)?;
+        let sublocations: Vec<Location> = sqlx::query_as("SELECT * FROM locations WHERE parent_id = ?")
+            .bind(&id)
+            .fetch_all(pool)
+            .await?;
+        for sub in &sublocations {
+            let sub_type = location_type_of(sub)?;
+            if !target_type.can_contain(sub_type) {
+                return Err(ApiError::bad_request(&format!(
+                    "A {} cannot be placed inside a {}", sub_type.as_str(), target_type.as_str()
+                )));
+            }
+            check_sibling_name(pool, Some(target_id), &sub.name, &sub.id).await?;
+        }
+        Some(target)
+    } else {
+        None
+    };
+
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    let target_id = target.as_ref().map(|t| t.id.as_str());
+    sqlx::query("UPDATE locations SET parent_id = ?, updated_by = ?, updated_at = ? WHERE parent_id = ?")
+        .bind(target_id)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await?;
+    // Удалённые партии тоже переносятся (или отвязываются), чтобы не ссылаться на удалённый узел
+    sqlx::query("UPDATE batches SET location_id = ? WHERE location_id = ?")
+        .bind(target_id)
+        .bind(&id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM locations WHERE id = ?")
+        .bind(&id)
+        .execute(&mut *tx)
+        .await?;
+    if let Some(target_id) = target_id {
+        refresh_batch_locations(&mut tx, target_id, &claims.sub, now).await?;
+    }
+    tx.commit().await?;
+
+    let description = match target {
+        Some(ref target) => format!(
+            "Deleted location '{}', moved {} sub-locations and {} batches to '{}'",
+            node.path, children, batches, target.name
+        ),
+        None => format!("Deleted location '{}'", node.path),
+    };
+    audit::audit(pool, &claims.sub, "delete", "location", &id, &description, &http_request).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        (),
+        "Location deleted successfully".to_string(),
+    )))
+}
+
+// ==================== CONTENTS ====================
+
+pub async fn fetch_location_contents(pool: &SqlitePool, id: &str) -> ApiResult<LocationContents> {
+    let location = fetch_node(pool, id).await?;
+    let mut batches: Vec<LocationBatch> = sqlx::query_as(&format!(
+        "WITH RECURSIVE {}, {} \
+         SELECT b.id, b.reagent_id, r.name AS reagent_name, b.batch_number, b.quantity, b.unit, \
+                b.status, b.expiry_date, b.location_id, tree.path AS location_path \
+         FROM batches b \
+         JOIN tree ON tree.id = b.location_id \
+         JOIN reagents r ON r.id = b.reagent_id \
+         WHERE b.deleted_at IS NULL AND r.deleted_at IS NULL \
+         ORDER BY tree.path, r.name, b.batch_number \
+         LIMIT ?",
+        CHAIN_CTE, TREE_CTE
+    ))
+    .bind(id)
+    .bind(id)
+    .bind(CONTENTS_LIMIT + 1)
+    .fetch_all(pool)
+    .await?;
+
+    let truncated = batches.len() as i64 > CONTENTS_LIMIT;
+    batches.truncate(CONTENTS_LIMIT as usize);
+    Ok(LocationContents { location, batches, truncated })
+}
+
+pub async fn get_location_contents(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let contents = fetch_location_contents(&app_state.db_pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(contents)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Claims;
+    use crate::UserRole;
+    use actix_web::{test, HttpMessage};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+             VALUES ('u-1', 'u-1', 'u-1@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at)
+             VALUES ('r-1', 'Acetone', 'active', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        pool
+    }
+
+    fn app_state(pool: &SqlitePool) -> web::Data<Arc<AppState>> {
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    fn request() -> HttpRequest {
+        let req = test::TestRequest::post().to_http_request();
+        req.extensions_mut().insert(Claims {
+            sub: "u-1".to_string(),
+            username: "u-1".to_string(),
+            email: "u-1@example.com".to_string(),
+            role: UserRole::Admin,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn response_json(response: HttpResponse) -> serde_json::Value {
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn create(state: &web::Data<Arc<AppState>>, name: &str, location_type: LocationType, parent_id: Option<&str>) -> ApiResult<String> {
+        let response = create_location(
+            state.clone(),
+            web::Json(CreateLocationRequest {
+                name: name.to_string(),
+                location_type,
+                parent_id: parent_id.map(str::to_string),
+                room_id: None,
+                description: None,
+            }),
+            request(),
+        ).await?;
+        Ok(response_json(response).await["data"]["id"].as_str().unwrap().to_string())
+    }
+
+    async fn add_batch(pool: &SqlitePool, id: &str, location_id: &str) {
+        let path = location_display_path(pool, location_id).await.unwrap().unwrap();
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, location, location_id, created_at, updated_at) \
+             VALUES (?, 'r-1', ?, 1, 1, 'mL', datetime('now'), 'available', ?, ?, datetime('now'), datetime('now'))"
+        )
+            .bind(id).bind(id).bind(path).bind(location_id)
+            .execute(pool).await.unwrap();
+    }
+
+    async fn batch_location(pool: &SqlitePool, id: &str) -> (Option<String>, Option<String>) {
+        sqlx::query_as("SELECT location_id, location FROM batches WHERE id = ?")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_contents_cover_subtree_and_moves_rehome_batches() {
+        let pool = setup().await;
+        let state = app_state(&pool);
+
+        let lab = create(&state, "Lab 1", LocationType::Room, None).await.unwrap();
+        let fridge = create(&state, "Fridge 2", LocationType::Cabinet, Some(&lab)).await.unwrap();
+        let shelf = create(&state, "Shelf B", LocationType::Shelf, Some(&fridge)).await.unwrap();
+        let shelf_box = create(&state, "Box 3", LocationType::Box, Some(&shelf)).await.unwrap();
+        let cabinet = create(&state, "Cabinet 7", LocationType::Cabinet, Some(&lab)).await.unwrap();
+
+        // Тип должен быть "глубже" родителя, имена соседей уникальны
+        assert!(create(&state, "Wing", LocationType::Building, Some(&lab)).await.is_err());
+        assert!(create(&state, "fridge 2", LocationType::Cabinet, Some(&lab)).await.is_err());
+
+        add_batch(&pool, "b-box", &shelf_box).await;
+        add_batch(&pool, "b-shelf", &shelf).await;
+        add_batch(&pool, "b-cabinet", &cabinet).await;
+        assert_eq!(batch_location(&pool, "b-box").await.1.as_deref(), Some("Lab 1, Fridge 2, Shelf B, Box 3"));
+
+        let contents = fetch_location_contents(&pool, &fridge).await.unwrap();
+        let ids: Vec<&str> = contents.batches.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, vec!["b-shelf", "b-box"]);
+        assert_eq!(contents.location.path, "Lab 1, Fridge 2");
+        assert!(!contents.truncated);
+
+        // Фильтр списка партий по поддереву
+        let query = web::Query::<crate::batch_handlers::BatchQuery>::from_query(&format!("location_id={}", lab)).unwrap();
+        let response = crate::batch_handlers::get_all_batches(state.clone(), query, crate::api_version::ApiVersion::LATEST).await.unwrap();
+        assert_eq!(response_json(response).await["data"]["total"], 3);
+
+        // Нельзя перенести узел внутрь себя или в узел того же уровня
+        let into_itself = move_location(
+            state.clone(), web::Path::from(fridge.clone()),
+            web::Json(MoveLocationRequest { parent_id: Some(shelf_box.clone()) }), request(),
+        ).await;
+        assert!(into_itself.is_err());
+        let into_cabinet = move_location(
+            state.clone(), web::Path::from(fridge.clone()),
+            web::Json(MoveLocationRequest { parent_id: Some(cabinet.clone()) }), request(),
+        ).await;
+        assert!(into_cabinet.is_err());
+
+        // Перенос полки со всем содержимым в шкаф
+        move_location(
+            state.clone(), web::Path::from(shelf.clone()),
+            web::Json(MoveLocationRequest { parent_id: Some(cabinet.clone()) }), request(),
+        ).await.unwrap();
+        assert_eq!(
+            batch_location(&pool, "b-box").await,
+            (Some(shelf_box.clone()), Some("Lab 1, Cabinet 7, Shelf B, Box 3".to_string()))
+        );
+        assert!(fetch_location_contents(&pool, &fridge).await.unwrap().batches.is_empty());
+        assert_eq!(fetch_location_contents(&pool, &cabinet).await.unwrap().batches.len(), 3);
+
+        // Переименование обновляет путь у партий поддерева
+        update_location(
+            state.clone(), web::Path::from(lab.clone()),
+            web::Json(UpdateLocationRequest { name: Some("Lab 9".to_string()), location_type: None, room_id: None, description: None }),
+            request(),
+        ).await.unwrap();
+        assert_eq!(batch_location(&pool, "b-shelf").await.1.as_deref(), Some("Lab 9, Cabinet 7, Shelf B"));
+    }
+
+    #[actix_web::test]
+    async fn test_deleting_non_empty_location_requires_target() {
+        let pool = setup().await;
+        let state = app_state(&pool);
+
+        let fridge = create(&state, "Fridge", LocationType::Cabinet, None).await.unwrap();
+        let shelf = create(&state, "Shelf A", LocationType::Shelf, Some(&fridge)).await.unwrap();
+        let freezer = create(&state, "Freezer", LocationType::Cabinet, None).await.unwrap();
+        let other_shelf = create(&state, "Shelf Z", LocationType::Shelf, None).await.unwrap();
+        add_batch(&pool, "b-1", &fridge).await;
+        add_batch(&pool, "b-2", &shelf).await;
+
+        let delete = |id: &str, move_to: &str| {
+            let query = if move_to.is_empty() { String::new() } else { format!("move_to={}", move_to) };
+            delete_location(
+                state.clone(),
+                web::Path::from(id.to_string()),
+                web::Query::<DeleteLocationQuery>::from_query(&query).unwrap(),
+                request(),
+            )
+        };
+
+        assert!(delete(&fridge, "").await.is_err());
+        // Цель внутри удаляемого узла и цель, которая не может содержать полку
+        assert!(delete(&fridge, &shelf).await.is_err());
+        assert!(delete(&fridge, &other_shelf).await.is_err());
+
+        delete(&fridge, &freezer).await.unwrap();
+        assert!(fetch_location(&pool, &fridge).await.unwrap().is_none());
+        assert_eq!(batch_location(&pool, "b-1").await, (Some(freezer.clone()), Some("Freezer".to_string())));
+        assert_eq!(batch_location(&pool, "b-2").await.1.as_deref(), Some("Freezer, Shelf A"));
+
+        // Пустой узел удаляется без цели
+        delete(&other_shelf, "").await.unwrap();
+    }
+}
@@ -0,0 +1,381 @@
+// src/mailer.rs
+//! Минимальный SMTP-клиент для рассылки отчётов.
+//!
+//! Работает поверх std::net + rustls (TLS-конфигурация общая с http_client)
+//! в blocking-потоке: EHLO, STARTTLS или неявный TLS, AUTH PLAIN, одно письмо
+//! multipart/mixed с вложениями в base64. Настройки - секция `smtp` конфигурации.
+
+use base64::Engine;
+use chrono::Utc;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::config::{SmtpConfig, SmtpSecurity};
+
+/// Ограничение строки ответа сервера
+const MAX_REPLY_LINE: usize = 4096;
+/// Длина строки base64 в теле письма (RFC 2045)
+const BASE64_LINE: usize = 76;
+
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: Vec<String>,
+    pub subject: String,
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+}
+
+/// Отправка письма; Err - текст ошибки для журнала запусков
+pub async fn send(config: &SmtpConfig, email: Email) -> Result<(), String> {
+    if !config.is_enabled() {
+        return Err("SMTP is not configured (smtp.host is empty)".to_string());
+    }
+    if email.to.is_empty() {
+        return Err("No recipients".to_string());
+    }
+
+    let config = config.clone();
+    let timeout = Duration::from_secs(config.timeout_seconds.max(1));
+    let task = tokio::task::spawn_blocking(move || send_blocking(&config, &email, timeout));
+    // Несколько обменов командами, каждый со своим таймаутом сокета
+    match tokio::time::timeout(timeout * 4, task).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(format!("Mail task failed: {}", e)),
+        Err(_) => Err("SMTP timed out".to_string()),
+    }
+}
+
+fn send_blocking(config: &SmtpConfig, email: &Email, timeout: Duration) -> Result<(), String> {
+    let addr = (config.host.as_str(), config.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("DNS error: {}", e))?
+        .next()
+        .ok_or("SMTP host not found")?;
+    let stream = TcpStream::connect_timeout(&addr, timeout).map_err(|e| format!("Connect error: {}", e))?;
+    stream.set_read_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+
+    match config.security {
+        SmtpSecurity::None => deliver(&mut SmtpSession::new(stream), config, email, true),
+        SmtpSecurity::Tls => deliver(&mut SmtpSession::new(tls_stream(&config.host, stream)?), config, email, true),
+        SmtpSecurity::Starttls => {
+            let mut session = SmtpSession::new(stream);
+            session.expect(&[220])?;
+            session.command(&format!("EHLO {}", helo_domain(&config.from)), &[250])?;
+            session.command("STARTTLS", &[220])?;
+            let tls = tls_stream(&config.host, session.into_inner())?;
+            deliver(&mut SmtpSession::new(tls), config, email, false)
+        }
+    }
+}
+
+fn tls_stream(host: &str, stream: TcpStream) -> Result<rustls::StreamOwned<rustls::ClientConnection, TcpStream>, String> {
+    let server_name = rustls::ServerName::try_from(host).map_err(|_| "Invalid SMTP host name")?;
+    let conn = rustls::ClientConnection::new(crate::http_client::tls_config(), server_name)
+        .map_err(|e| format!("TLS error: {}", e))?;
+    Ok(rustls::StreamOwned::new(conn, stream))
+}
+
+/// Диалог после установки соединения; `greeting` - приветствие 220 ещё не прочитано
+fn deliver<S: Read + Write>(
+    session: &mut SmtpSession<S>,
+    config: &SmtpConfig,
+    email: &Email,
+    greeting: bool,
+) -> Result<(), String> {
+    if greeting {
+        session.expect(&[220])?;
+    }
+    session.command(&format!("EHLO {}", helo_domain(&config.from)), &[250])?;
+
+    if let Some(ref username) = config.username {
+        let password = config.password.as_deref().unwrap_or("");
+        let token = base64::engine::general_purpose::STANDARD.encode(format!("\0{}\0{}", username, password));
+        session.command(&format!("AUTH PLAIN {}", token), &[235])?;
+    }
+
+    session.command(&format!("MAIL FROM:<{}>", sanitize_header(&config.from)), &[250])?;
+    for recipient in &email.to {
+        session.command(&format!("RCPT TO:<{}>", sanitize_header(recipient)), &[250, 251])?;
+    }
+    session.command("DATA", &[354])?;
+
+    let boundary = format!("lims-{}", uuid::Uuid::new_v4().simple());
+    let message = build_message(&config.from, email, &boundary);
+    session.write_raw(&dot_stuff(&message))?;
+    session.command(".", &[250])?;
+
+    // Письмо принято; ошибка QUIT уже не важна
+    let _ = session.command("QUIT", &[221]);
+    Ok(())
+}
+
+struct SmtpSession<S> {
+    stream: S,
+}
+
+impl<S: Read + Write> SmtpSession<S> {
+    fn new(stream: S) -> Self {
+        Self { stream }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn write_raw(&mut self, data: &str) -> Result<(), String> {
+        self.stream
+            .write_all(data.as_bytes())
+            .and_then(|_| self.stream.flush())
+            .map_err(|e| format!("SMTP write error: {}", e))
+    }
+
+    fn command(&mut self, line: &str, expected: &[u16]) -> Result<String, String> {
+        self.write_raw(&format!("{}\r\n", line))?;
+        self.expect(expected).map_err(|e| {
+            // Не выводим учётные данные в журнал
+            let verb = line.split_whitespace().next().unwrap_or(line);
+            format!("{} failed: {}", verb, e)
+        })
+    }
+
+    fn expect(&mut self, expected: &[u16]) -> Result<String, String> {
+        let (code, text) = self.read_reply()?;
+        if expected.contains(&code) {
+            Ok(text)
+        } else {
+            Err(format!("SMTP {} {}", code, text))
+        }
+    }
+
+    /// Ответ сервера, в т.ч. многострочный ("250-...", последняя строка "250 ...")
+    fn read_reply(&mut self) -> Result<(u16, String), String> {
+        let mut text = String::new();
+        loop {
+            let line = self.read_line()?;
+            let code = line
+                .get(..3)
+                .and_then(|c| c.parse::<u16>().ok())
+                .ok_or_else(|| format!("Malformed SMTP reply: {}", line))?;
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(line.get(4..).unwrap_or("").trim());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text));
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String, String> {
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        loop {
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Err("SMTP connection closed".to_string()),
+                Ok(_) if byte[0] == b'\n' => break,
+                Ok(_) if line.len() >= MAX_REPLY_LINE => return Err("SMTP reply line too long".to_string()),
+                Ok(_) => line.push(byte[0]),
+                Err(e) => return Err(format!("SMTP read error: {}", e)),
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end_matches('\r').to_string())
+    }
+}
+
+/// Домен для EHLO - из адреса отправителя
+fn helo_domain(from: &str) -> &str {
+    from.rsplit_once('@').map(|(_, domain)| domain).filter(|d| !d.is_empty()).unwrap_or("localhost")
+}
+
+/// Защита от подстановки заголовков через CR/LF
+fn sanitize_header(value: &str) -> String {
+    value.chars().filter(|c| *c != '\r' && *c != '\n').collect()
+}
+
+/// Не-ASCII тема кодируется по RFC 2047
+fn encode_header(value: &str) -> String {
+    let value = sanitize_header(value);
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", base64::engine::general_purpose::STANDARD.encode(value))
+    }
+}
+
+fn base64_lines(data: &[u8]) -> String {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / BASE64_LINE * 2 + 2);
+    for chunk in encoded.as_bytes().chunks(BASE64_LINE) {
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn build_message(from: &str, email: &Email, boundary: &str) -> String {
+    let to: Vec<String> = email.to.iter().map(|r| sanitize_header(r)).collect();
+    let mut message = String::new();
+    message.push_str(&format!("From: {}\r\n", sanitize_header(from)));
+    message.push_str(&format!("To: {}\r\n", to.join(", ")));
+    message.push_str(&format!("Subject: {}\r\n", encode_header(&email.subject)));
+    message.push_str(&format!("Date: {}\r\n", Utc::now().to_rfc2822()));
+    message.push_str(&format!("Message-ID: <{}@{}>\r\n", uuid::Uuid::new_v4(), helo_domain(from)));
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n", boundary));
+
+    message.push_str(&format!("--{}\r\n", boundary));
+    message.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+    message.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+    message.push_str(&base64_lines(email.body.as_bytes()));
+
+    for attachment in &email.attachments {
+        let filename = sanitize_header(&attachment.filename).replace('"', "");
+        message.push_str(&format!("--{}\r\n", boundary));
+        message.push_str(&format!("Content-Type: {}; name=\"{}\"\r\n", attachment.content_type, filename));
+        message.push_str(&format!("Content-Disposition: attachment; filename=\"{}\"\r\n", filename));
+        message.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+        message.push_str(&base64_lines(&attachment.data));
+    }
+    message.push_str(&format!("--{}--\r\n", boundary));
+    message
+}
+
+/// Строки, начинающиеся с точки, удваивают её (RFC 5321, 4.5.2)
+fn dot_stuff(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    for line in message.split_inclusive("\r\n") {
+        if line.starts_with('.') {
+            out.push('.');
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+/// Локальный SMTP-сервер для тестов: принимает одно письмо и возвращает весь диалог клиента
+#[cfg(test)]
+pub(crate) fn spawn_test_server() -> (u16, std::sync::mpsc::Receiver<String>) {
+    use std::io::{BufRead, BufReader};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut transcript = String::new();
+        let mut in_data = false;
+        writer.write_all(b"220 test ESMTP ready\r\n").unwrap();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                break;
+            }
+            transcript.push_str(&line);
+            if in_data {
+                if line == ".\r\n" {
+                    in_data = false;
+                    writer.write_all(b"250 2.0.0 queued\r\n").unwrap();
+                }
+                continue;
+            }
+            let reply: &[u8] = match line.split_whitespace().next().unwrap_or("") {
+                "EHLO" => b"250-test greets you\r\n250 AUTH PLAIN\r\n",
+                "AUTH" => b"235 2.7.0 accepted\r\n",
+                "DATA" => {
+                    in_data = true;
+                    b"354 end with .\r\n"
+                }
+                "QUIT" => {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                }
+                _ => b"250 OK\r\n",
+            };
+            writer.write_all(reply).unwrap();
+        }
+        let _ = tx.send(transcript);
+    });
+    (port, rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(port: u16) -> SmtpConfig {
+        SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: Some("reports".to_string()),
+            password: Some("secret".to_string()),
+            from: "lims@lab.example".to_string(),
+            timeout_seconds: 5,
+        }
+    }
+
+    #[test]
+    fn test_message_encoding() {
+        let email = Email {
+            to: vec!["head@lab.example".to_string()],
+            subject: "Отчёт\r\nBcc: evil@example.com".to_string(),
+            body: "Hello".to_string(),
+            attachments: vec![Attachment {
+                filename: "report.csv".to_string(),
+                content_type: "text/csv; charset=utf-8".to_string(),
+                data: vec![b'x'; 200],
+            }],
+        };
+        let message = build_message("lims@lab.example", &email, "b1");
+        assert!(message.contains("Subject: =?UTF-8?B?"));
+        assert!(!message.contains("\r\nBcc:"));
+        assert!(message.contains("Content-Disposition: attachment; filename=\"report.csv\""));
+        assert!(message.lines().all(|l| l.len() <= 998));
+        assert!(message.ends_with("--b1--\r\n"));
+
+        assert_eq!(dot_stuff(".hidden\r\nok\r\n..x\r\n"), "..hidden\r\nok\r\n...x\r\n");
+        assert_eq!(helo_domain("lims@lab.example"), "lab.example");
+        assert_eq!(helo_domain("broken"), "localhost");
+    }
+
+    #[actix_web::test]
+    async fn test_send_delivers_attachment() {
+        let (port, transcript) = spawn_test_server();
+        let email = Email {
+            to: vec!["head@lab.example".to_string(), "deputy@lab.example".to_string()],
+            subject: "Expiring stock".to_string(),
+            body: "Weekly report".to_string(),
+            attachments: vec![Attachment {
+                filename: "report.csv".to_string(),
+                content_type: "text/csv; charset=utf-8".to_string(),
+                data: b"ID,Name\n1,Acetone\n".to_vec(),
+            }],
+        };
+        send(&test_config(port), email).await.unwrap();
+
+        let transcript = transcript.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(transcript.contains("EHLO lab.example\r\n"));
+        assert!(transcript.contains("AUTH PLAIN AHJlcG9ydHMAc2VjcmV0\r\n"));
+        assert!(transcript.contains("RCPT TO:<deputy@lab.example>\r\n"));
+        assert!(transcript.contains(&base64::engine::general_purpose::STANDARD.encode(b"ID,Name\n1,Acetone\n")));
+        assert!(transcript.ends_with("QUIT\r\n"));
+    }
+
+    #[actix_web::test]
+    async fn test_send_without_host_fails() {
+        let email = Email { to: vec!["a@b.c".to_string()], subject: String::new(), body: String::new(), attachments: vec![] };
+        let err = send(&SmtpConfig::default(), email).await.unwrap_err();
+        assert!(err.contains("not configured"));
+    }
+}
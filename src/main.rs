@@ -1,11 +1,11 @@
 // Updated main.rs with modular handlers and import/export functionality
 use actix_web::{
-    middleware::{Logger, DefaultHeaders},
+    middleware::Logger,
     web, App, HttpResponse, HttpServer, HttpRequest, Result,
 };
 use actix_multipart::Multipart;
 use actix_web_httpauth::middleware::HttpAuthentication;
-use actix_web::http::header;
+use actix_web::http::{header, Method};
 use actix_cors::Cors;
 use actix_files::{NamedFile, Files};
 use std::env;
@@ -21,7 +21,6 @@ use crate::models::{
     CreateEquipmentRequest, UpdateEquipmentRequest, 
     CreateEquipmentPartRequest, UpdateEquipmentPartRequest,
     CreateMaintenanceRequest, UpdateMaintenanceRequest, CompleteMaintenanceRequest,
-    UpcomingMaintenanceQuery,
     
     // Experiment
     CreateExperimentRequest, UpdateExperimentRequest,
@@ -38,10 +37,12 @@ use rand::{thread_rng, Rng, distributions::Alphanumeric};
 use rand::distributions::Distribution;
 use rand::seq::SliceRandom;
 use anyhow::Context;
-use sqlx::{sqlite::SqliteConnectOptions, migrate::MigrateDatabase, Sqlite, SqlitePool};
+use sqlx::{migrate::MigrateDatabase, Sqlite, SqlitePool};
 use std::sync::Arc;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 // Module declarations
+mod access_control;
+mod api_version;
 mod auth;
 mod audit;
 mod auth_handlers;
@@ -65,7 +66,58 @@ mod batch_handlers;
 mod equipment_handlers;
 mod import_export;
 mod pagination;
-use actix_web::middleware::Compress;
+mod compression;
+mod http_client;
+mod crypto;
+mod catalog_lookup;
+mod scan_handlers;
+mod query_log;
+mod link_handlers;
+mod forecast_handlers;
+mod reagent_image_handlers;
+mod kiosk_handlers;
+mod settings;
+mod schema;
+mod export_archive;
+mod pending_deletion_handlers;
+mod favorites_handlers;
+mod location_handlers;
+mod events;
+mod calendar_handlers;
+mod validation_handlers;
+mod reconciliation;
+mod mailer;
+mod report_schedule_handlers;
+mod file_blobs;
+mod protocol_render;
+mod equipment_catalog;
+mod work_queue;
+mod public_catalogue;
+mod approval_handlers;
+mod alert_handlers;
+mod stock_adjustment_handlers;
+mod i18n;
+mod kpi;
+mod demo_seed;
+mod request_timeout;
+mod client_ip;
+mod security_headers;
+mod suggest_handlers;
+mod outbox;
+mod team_handlers;
+mod config_transfer;
+mod support_bundle;
+mod purchase_order_handlers;
+mod signed_downloads;
+mod physical_state;
+mod maintenance_mode;
+mod batch_genealogy;
+mod experiment_report;
+mod stock_anomalies;
+mod user_identity;
+mod reference_cache;
+#[cfg(test)]
+mod test_support;
 use config::Config;
 use auth::{AuthService, jwt_middleware};
 
@@ -75,7 +127,7 @@ use crate::audit::ChangeSet;
 
 // Handlers - only common utilities and specific functions
 use handlers::{
-    get_dashboard_stats, use_reagent, get_usage_history,
+    get_dashboard_stats, use_reagent, consume_reagent, get_usage_history,
     get_reagent_with_batches, get_jwt_rotation_status, force_jwt_rotation
 };
 
@@ -94,14 +146,14 @@ use batch_handlers::{
     get_batches_for_reagent, dispense_units, get_batch_units_info
 };
 
-// Equipment handlers - FIXED: removed get_upcoming_maintenance (doesn't exist)
+// Equipment handlers
 use equipment_handlers::{
     get_equipment, get_equipment_by_id,
     // Parts
     get_equipment_parts, add_equipment_part, update_equipment_part, delete_equipment_part,
     // Maintenance
     get_equipment_maintenance, create_maintenance, 
-    update_maintenance, complete_maintenance, delete_maintenance,
+    update_maintenance, complete_maintenance, delete_maintenance, get_upcoming_maintenance, get_overdue_maintenance,
     // Files
     get_equipment_files, upload_equipment_file, download_equipment_file, delete_equipment_file,
     get_part_files,
@@ -120,23 +172,46 @@ use experiment_handlers::{
     create_experiment, get_experiment, get_all_experiments,
     update_experiment, delete_experiment,
     add_reagent_to_experiment, get_experiment_reagents, remove_reagent_from_experiment,
-    get_experiment_stats, start_experiment, complete_experiment, cancel_experiment,
+    get_experiment_stats, publish_experiment, start_experiment, complete_experiment, cancel_experiment,
     consume_experiment_reagent, auto_update_experiment_statuses,
-    run_auto_update_statuses, seconds_until_next_transition,
+    get_experiment_participants, add_experiment_participant, remove_experiment_participant,
+    sign_in_experiment, run_auto_update_statuses, seconds_until_next_transition,
+    get_experiment_signoff, sign_off_experiment,
+    get_experiment_dependencies, add_experiment_dependency, remove_experiment_dependency,
+    ensure_can_manage_experiment, get_experiment_comments, add_experiment_comment,
+    get_experiment_documents, download_experiment_document,
 };
 
 // Room handlers
 use room_handlers::{
-    get_all_rooms, get_room, get_available_rooms
+    get_all_rooms, get_room, get_available_rooms,
+    get_room_utilization, get_rooms_utilization,
 };
 
 use auth_handlers::*;
 use monitoring::{Metrics, RequestLogger, start_maintenance_tasks};
+use pending_deletion_handlers::{DeferredEntity, DeleteQuery};
 use error::ApiResult;
 
 pub struct AppState {
     pub db_pool: SqlitePool,
     pub config: Config,
+    /// Read-only пул для отчётов, экспорта и аналитики; None - всё через db_pool
+    pub read_replica: Option<SqlitePool>,
+    /// Справочники для каждой загрузки страницы (помещения, пресеты отчётов)
+    pub reference_cache: reference_cache::ReferenceCache,
+}
+
+impl AppState {
+    /// Пул для тяжёлых запросов только на чтение; записи всегда идут через db_pool
+    pub fn read_pool(&self) -> &SqlitePool {
+        db::select_read_pool(&self.db_pool, self.read_replica.as_ref())
+    }
+
+    /// Действующие пределы per_page группы списков (конфигурация + настройка max_per_page)
+    pub fn page_limits(&self, group: pagination::PageGroup) -> pagination::PageLimits {
+        pagination::page_limits(&self.config.pagination, group)
+    }
 }
 
 // ==================== EXPERIMENT PROTECTED WRAPPERS ====================
@@ -146,7 +221,6 @@ async fn create_experiment_protected(
     experiment: web::Json<crate::models::experiment::CreateExperimentRequest>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Create, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
 
@@ -177,10 +251,10 @@ async fn update_experiment_protected(
     update_data: web::Json<crate::models::experiment::UpdateExperimentRequest>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
     let experiment_id = path.into_inner();
+    ensure_can_manage_experiment(&app_state.db_pool, &experiment_id, &user_id, claims.role == UserRole::Admin).await?;
 
     // Fetch old experiment data for comparison
     let mut cs = ChangeSet::new();
@@ -217,10 +291,10 @@ async fn delete_experiment_protected(
     path: web::Path<String>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Delete, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
     let experiment_id = path.into_inner();
+    ensure_can_manage_experiment(&app_state.db_pool, &experiment_id, &user_id, claims.role == UserRole::Admin).await?;
 
     // Fetch data before deletion
     let mut cs = ChangeSet::new();
@@ -246,7 +320,6 @@ async fn add_experiment_reagent_protected(
     reagent: web::Json<experiment_handlers::AddReagentToExperimentRequest>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
     add_reagent_to_experiment(app_state, path, reagent, claims.sub).await
 }
@@ -256,29 +329,42 @@ async fn remove_experiment_reagent_protected(
     path: web::Path<(String, String)>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
     remove_reagent_from_experiment(app_state, path, claims.sub).await
 }
 
+async fn publish_experiment_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    ensure_can_manage_experiment(&app_state.db_pool, &experiment_id, &claims.sub, claims.role == UserRole::Admin).await?;
+    publish_experiment(app_state, web::Path::from(experiment_id), claims.sub).await
+}
+
 async fn start_experiment_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
-    start_experiment(app_state, path, claims.sub).await
+    let experiment_id = path.into_inner();
+    ensure_can_manage_experiment(&app_state.db_pool, &experiment_id, &claims.sub, claims.role == UserRole::Admin).await?;
+    start_experiment(app_state, web::Path::from(experiment_id), claims.sub).await
 }
 
 async fn complete_experiment_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    body: Option<web::Json<models::CompleteExperimentRequest>>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
-    complete_experiment(app_state, path, claims.sub).await
+    let experiment_id = path.into_inner();
+    ensure_can_manage_experiment(&app_state.db_pool, &experiment_id, &claims.sub, claims.role == UserRole::Admin).await?;
+    complete_experiment(app_state, web::Path::from(experiment_id), body, claims.sub).await
 }
 
 async fn cancel_experiment_protected(
@@ -286,19 +372,154 @@ async fn cancel_experiment_protected(
     path: web::Path<String>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
-    cancel_experiment(app_state, path, claims.sub).await
+    let experiment_id = path.into_inner();
+    ensure_can_manage_experiment(&app_state.db_pool, &experiment_id, &claims.sub, claims.role == UserRole::Admin).await?;
+    cancel_experiment(app_state, web::Path::from(experiment_id), claims.sub).await
+}
+
+async fn add_experiment_comment_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<models::AddExperimentCommentRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    let user_id = claims.sub.clone();
+    let response = add_experiment_comment(app_state.clone(), web::Path::from(experiment_id.clone()), body, claims.sub).await?;
+    audit::audit(
+        &app_state.db_pool, &user_id, "comment", "experiment", &experiment_id,
+        "Commented on experiment", &http_request,
+    ).await;
+    Ok(response)
 }
 
 async fn consume_experiment_reagent_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
+    body: Option<web::Json<models::ConsumeExperimentReagentRequest>>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_experiment_permission(&http_request, auth_handlers::ExperimentAction::Edit, &app_state.db_pool).await?;
     let claims = crate::auth::get_current_user(&http_request)?;
-    consume_experiment_reagent(app_state, path, claims.sub).await
+    consume_experiment_reagent(app_state, path, body, claims.sub).await
+}
+
+async fn add_experiment_participant_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<models::AddParticipantRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    let user_id = claims.sub.clone();
+    let response = add_experiment_participant(app_state.clone(), web::Path::from(experiment_id.clone()), body, claims.sub).await?;
+    audit::audit(
+        &app_state.db_pool, &user_id, "add_participant", "experiment", &experiment_id,
+        "Added participant to experiment", &http_request,
+    ).await;
+    Ok(response)
+}
+
+async fn remove_experiment_participant_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let (experiment_id, participant_id) = path.into_inner();
+    let user_id = claims.sub.clone();
+    let response = remove_experiment_participant(
+        app_state.clone(), web::Path::from((experiment_id.clone(), participant_id.clone())), claims.sub,
+    ).await?;
+    audit::audit(
+        &app_state.db_pool, &user_id, "remove_participant", "experiment", &experiment_id,
+        &format!("Removed participant {}", participant_id), &http_request,
+    ).await;
+    Ok(response)
+}
+
+/// Отметиться может любой участник; отмечать других — только с правом Edit
+async fn sign_in_experiment_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: Option<web::Json<models::SignInRequest>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let can_manage = access_control::authorize(
+        &claims, access_control::Resource::Experiment, access_control::Action::Edit,
+        &UserRole::Researcher, &app_state.db_pool,
+    ).await.is_ok();
+    let experiment_id = path.into_inner();
+    let user_id = claims.sub.clone();
+    let response = sign_in_experiment(
+        app_state.clone(), web::Path::from(experiment_id.clone()), body, claims.sub, can_manage,
+    ).await?;
+    audit::audit(
+        &app_state.db_pool, &user_id, "sign_in", "experiment", &experiment_id,
+        "Attendance sign-in", &http_request,
+    ).await;
+    Ok(response)
+}
+
+/// Допуск по технике безопасности: администратор или инструктор эксперимента (проверка в хендлере)
+async fn sign_off_experiment_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<models::SignoffRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    let user_id = claims.sub.clone();
+    let response = sign_off_experiment(
+        app_state.clone(), web::Path::from(experiment_id.clone()), body, claims.sub, claims.role == UserRole::Admin,
+    ).await?;
+    audit::audit(
+        &app_state.db_pool, &user_id, "safety_signoff", "experiment", &experiment_id,
+        "Safety sign-off recorded", &http_request,
+    ).await;
+    Ok(response)
+}
+
+async fn add_experiment_dependency_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<models::AddDependencyRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let experiment_id = path.into_inner();
+    ensure_can_manage_experiment(&app_state.db_pool, &experiment_id, &claims.sub, claims.role == UserRole::Admin).await?;
+    let user_id = claims.sub.clone();
+    let depends_on_id = body.depends_on_id.trim().to_string();
+    let response = add_experiment_dependency(app_state.clone(), web::Path::from(experiment_id.clone()), body, claims.sub).await?;
+    audit::audit(
+        &app_state.db_pool, &user_id, "add_dependency", "experiment", &experiment_id,
+        &format!("Experiment now depends on {}", depends_on_id), &http_request,
+    ).await;
+    Ok(response)
+}
+
+async fn remove_experiment_dependency_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let (experiment_id, depends_on_id) = path.into_inner();
+    ensure_can_manage_experiment(&app_state.db_pool, &experiment_id, &claims.sub, claims.role == UserRole::Admin).await?;
+    let user_id = claims.sub.clone();
+    let response = remove_experiment_dependency(
+        app_state.clone(), web::Path::from((experiment_id.clone(), depends_on_id.clone())), claims.sub,
+    ).await?;
+    audit::audit(
+        &app_state.db_pool, &user_id, "remove_dependency", "experiment", &experiment_id,
+        &format!("Removed dependency on {}", depends_on_id), &http_request,
+    ).await;
+    Ok(response)
 }
 
 async fn auto_update_experiment_statuses_handler(
@@ -309,12 +530,20 @@ async fn auto_update_experiment_statuses_handler(
 
 // ==================== REAGENT PROTECTED WRAPPERS ====================
 
+async fn get_reagents_protected(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<pagination::HybridPaginationQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    get_reagents(app_state, query, claims.sub).await
+}
+
 async fn create_reagent_protected(
     app_state: web::Data<Arc<AppState>>,
     reagent: web::Json<crate::models::reagent::CreateReagentRequest>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_reagent_permission_async(&http_request, auth_handlers::ReagentAction::Create, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
 
@@ -326,6 +555,7 @@ async fn create_reagent_protected(
     if let Some(ref v) = reagent.physical_state { cs.created("physical_state", v); }
     if let Some(ref v) = reagent.hazard_pictograms { cs.created("hazard_pictograms", v); }
     if let Some(ref v) = reagent.storage_conditions { cs.created("storage_conditions", v); }
+    if let Some(ref batch) = reagent.initial_batch { cs.created("initial_batch", &batch.batch_number); }
 
     let response = reagent_handlers::create_reagent(app_state.clone(), reagent, claims.sub).await?;
     audit::audit_with_changes(
@@ -342,7 +572,6 @@ async fn update_reagent_protected(
     update_data: web::Json<crate::models::reagent::UpdateReagentRequest>,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_reagent_permission_async(&http_request, auth_handlers::ReagentAction::Edit, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
     let reagent_id = path.into_inner();
@@ -361,15 +590,15 @@ async fn update_reagent_protected(
     ).bind(&reagent_id).fetch_one(&app_state.db_pool).await {
         reagent_name = old.0.clone();
         if let Some(ref new_val) = update_data.name { cs.add("name", &old.0, new_val); }
-        if let Some(ref new_val) = update_data.formula { cs.add_opt("formula", &old.1, &Some(new_val.clone())); }
-        if let Some(new_val) = update_data.molecular_weight { cs.add_opt_f64("molecular_weight", old.2, Some(new_val)); }
-        if let Some(ref new_val) = update_data.physical_state { cs.add_opt("physical_state", &old.3, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.cas_number { cs.add_opt("cas_number", &old.4, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.manufacturer { cs.add_opt("manufacturer", &old.5, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.description { cs.add_opt("description", &old.6, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.storage_conditions { cs.add_opt("storage_conditions", &old.7, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.appearance { cs.add_opt("appearance", &old.8, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.hazard_pictograms { cs.add_opt("hazard_pictograms", &old.9, &Some(new_val.clone())); }
+        if let Some(ref new_val) = update_data.formula { cs.add_opt("formula", &old.1, new_val); }
+        if let Some(new_val) = update_data.molecular_weight { cs.add_opt_f64("molecular_weight", old.2, new_val); }
+        if let Some(ref new_val) = update_data.physical_state { cs.add_opt("physical_state", &old.3, new_val); }
+        if let Some(ref new_val) = update_data.cas_number { cs.add_opt("cas_number", &old.4, new_val); }
+        if let Some(ref new_val) = update_data.manufacturer { cs.add_opt("manufacturer", &old.5, new_val); }
+        if let Some(ref new_val) = update_data.description { cs.add_opt("description", &old.6, new_val); }
+        if let Some(ref new_val) = update_data.storage_conditions { cs.add_opt("storage_conditions", &old.7, new_val); }
+        if let Some(ref new_val) = update_data.appearance { cs.add_opt("appearance", &old.8, new_val); }
+        if let Some(ref new_val) = update_data.hazard_pictograms { cs.add_opt("hazard_pictograms", &old.9, new_val); }
         if let Some(ref new_val) = update_data.status { cs.add("status", &old.10, new_val); }
     }
 
@@ -390,11 +619,12 @@ async fn update_reagent_protected(
 async fn delete_reagent_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    query: web::Query<DeleteQuery>,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_reagent_permission_async(&http_request, auth_handlers::ReagentAction::Delete, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let reagent_id = path.into_inner();
+    let immediate = pending_deletion_handlers::is_immediate(&query, &claims)?;
 
     // Fetch info before deletion
     let mut cs = ChangeSet::new();
@@ -407,11 +637,19 @@ async fn delete_reagent_protected(
         cs.deleted("status", &old.3);
     }
 
-    let response = reagent_handlers::delete_reagent(app_state.clone(), web::Path::from(reagent_id.clone()), claims.sub.clone()).await?;
+    let (response, desc) = if immediate {
+        let response = reagent_handlers::delete_reagent(app_state.clone(), web::Path::from(reagent_id.clone()), claims.sub.clone()).await?;
+        (response, format!("Deleted reagent: {}", cs.to_description()))
+    } else {
+        let deferred = pending_deletion_handlers::defer_delete(
+            &app_state.db_pool, DeferredEntity::Reagent, &reagent_id, None, &claims.sub,
+        ).await?;
+        let desc = format!("Deleted reagent ({}): {}", deferred.audit_note(), cs.to_description());
+        (deferred.into_response("Reagent"), desc)
+    };
     audit::audit_with_changes(
         &app_state.db_pool, &claims.sub, "delete", "reagent", &reagent_id,
-        &format!("Deleted reagent: {}", cs.to_description()),
-        &cs, &http_request,
+        &desc, &cs, &http_request,
     ).await;
     Ok(response)
 }
@@ -422,9 +660,9 @@ async fn create_batch_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
     batch: web::Json<crate::models::batch::CreateBatchRequest>,
+    api_version: api_version::ApiVersion,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Create, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
     let reagent_id = path.into_inner();
@@ -445,7 +683,7 @@ async fn create_batch_protected(
     if let Some(ref v) = batch.cat_number { cs.created("cat_number", v); }
     if let Some(ref v) = batch.expiry_date { cs.created("expiry_date", &v.to_string()); }
 
-    let response = batch_handlers::create_batch(app_state.clone(), web::Path::from(reagent_id.clone()), batch, claims.sub).await?;
+    let response = batch_handlers::create_batch(app_state.clone(), web::Path::from(reagent_id.clone()), batch, claims.sub, api_version).await?;
     audit::audit_with_changes(
         &app_state.db_pool, &user_id, "create", "batch", "",
         &format!("Created batch for '{}': {}", reagent_name, cs.to_description()),
@@ -458,9 +696,9 @@ async fn update_batch_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
     update_data: web::Json<crate::models::batch::UpdateBatchRequest>,
+    api_version: api_version::ApiVersion,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
     let (reagent_id, batch_id) = path.into_inner();
@@ -483,13 +721,13 @@ async fn update_batch_protected(
         if let Some(new_val) = update_data.quantity { cs.add_f64("quantity", old.1, new_val); }
         if let Some(ref new_val) = update_data.unit { cs.add("unit", &old.2, new_val); }
         if let Some(ref new_val) = update_data.status { cs.add("status", &old.3, new_val); }
-        if let Some(ref new_val) = update_data.lot_number { cs.add_opt("lot_number", &old.4, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.location { cs.add_opt("location", &old.5, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.supplier { cs.add_opt("supplier", &old.6, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.manufacturer { cs.add_opt("manufacturer", &old.7, &Some(new_val.clone())); }
+        if let Some(ref new_val) = update_data.lot_number { cs.add_opt("lot_number", &old.4, new_val); }
+        if let Some(ref new_val) = update_data.location { cs.add_opt("location", &old.5, new_val); }
+        if let Some(ref new_val) = update_data.supplier { cs.add_opt("supplier", &old.6, new_val); }
+        if let Some(ref new_val) = update_data.manufacturer { cs.add_opt("manufacturer", &old.7, new_val); }
         if let Some(ref new_val) = update_data.expiry_date { cs.add_opt("expiry_date", &old.8, &Some(new_val.to_string())); }
-        if let Some(ref new_val) = update_data.notes { cs.add_opt("notes", &old.9, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.cat_number { cs.add_opt("cat_number", &old.10, &Some(new_val.clone())); }
+        if let Some(ref new_val) = update_data.notes { cs.add_opt("notes", &old.9, new_val); }
+        if let Some(ref new_val) = update_data.cat_number { cs.add_opt("cat_number", &old.10, new_val); }
     }
 
     // Fetch reagent name for description
@@ -504,7 +742,7 @@ async fn update_batch_protected(
         format!("Batch {} of reagent '{}' updated", batch_label, reagent_name)
     };
 
-    let response = batch_handlers::update_batch(app_state.clone(), web::Path::from((reagent_id.clone(), batch_id.clone())), update_data, claims.sub).await?;
+    let response = batch_handlers::update_batch(app_state.clone(), web::Path::from((reagent_id.clone(), batch_id.clone())), update_data, claims.sub, api_version).await?;
     audit::audit_with_changes(
         &app_state.db_pool, &user_id, "edit", "batch", &batch_id,
         &desc, &cs, &http_request,
@@ -512,15 +750,111 @@ async fn update_batch_protected(
     Ok(response)
 }
 
+async fn import_usage_history_protected(
+    app_state: web::Data<Arc<AppState>>,
+    payload: actix_multipart::Multipart,
+    query: web::Query<import_export::UsageImportQuery>,
+    http_request: HttpRequest,
+) -> error::ApiResult<HttpResponse> {
+    import_export::import_usage_history(app_state, payload, query, http_request).await
+}
+
+async fn set_batch_barcode_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<crate::models::SetBatchBarcodeRequest>,
+    http_request: HttpRequest,
+) -> error::ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    let user_id = claims.sub.clone();
+    let (reagent_id, batch_id) = path.into_inner();
+
+    let mut cs = ChangeSet::new();
+    if let Ok(old) = sqlx::query_as::<_, (Option<String>,)>(
+        "SELECT barcode FROM batches WHERE id = ? AND reagent_id = ?"
+    ).bind(&batch_id).bind(&reagent_id).fetch_one(&app_state.db_pool).await {
+        let new_code = body.barcode.as_deref().map(str::trim).filter(|b| !b.is_empty()).map(String::from);
+        cs.add_opt("barcode", &old.0, &new_code);
+    }
+
+    let response = batch_handlers::set_batch_barcode(
+        app_state.clone(), web::Path::from((reagent_id, batch_id.clone())), body, claims.sub,
+    ).await?;
+    audit::audit_with_changes(
+        &app_state.db_pool, &user_id, "edit", "batch", &batch_id,
+        &format!("Batch barcode updated: {}", cs.to_description()),
+        &cs, &http_request,
+    ).await;
+    Ok(response)
+}
+
+async fn extend_batch_expiry_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<crate::models::ExtendBatchExpiryRequest>,
+    http_request: HttpRequest,
+) -> error::ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    let user_id = claims.sub.clone();
+    let (reagent_id, batch_id) = path.into_inner();
+
+    let old_expiry: Option<(Option<String>,)> = sqlx::query_as(
+        "SELECT expiry_date FROM batches WHERE id = ? AND reagent_id = ?"
+    ).bind(&batch_id).bind(&reagent_id).fetch_optional(&app_state.db_pool).await.unwrap_or(None);
+    let mut cs = ChangeSet::new();
+    cs.add_opt(
+        "expiry_date",
+        &old_expiry.and_then(|r| r.0),
+        &Some(body.new_expiry_date.to_rfc3339()),
+    );
+    cs.created("justification", body.justification.trim());
+    if let Some(doc) = body.document_reference.as_deref().filter(|d| !d.trim().is_empty()) {
+        cs.created("document_reference", doc.trim());
+    }
+
+    let response = batch_handlers::extend_batch_expiry(
+        app_state.clone(), web::Path::from((reagent_id, batch_id.clone())), body, claims.sub,
+    ).await?;
+    audit::audit_with_changes(
+        &app_state.db_pool, &user_id, "extend_expiry", "batch", &batch_id,
+        &format!("Batch expiry extended: {}", cs.to_description()),
+        &cs, &http_request,
+    ).await;
+    Ok(response)
+}
+
+async fn mark_coa_received_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    body: web::Json<crate::models::CoaReceivedRequest>,
+    http_request: HttpRequest,
+) -> error::ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    let user_id = claims.sub.clone();
+    let batch_id = path.1.clone();
+
+    let mut cs = ChangeSet::new();
+    cs.created("coa_reference", body.reference_number.trim());
+
+    let response = batch_handlers::mark_coa_received(app_state.clone(), path, body, claims.sub).await?;
+    audit::audit_with_changes(
+        &app_state.db_pool, &user_id, "coa_received", "batch", &batch_id,
+        &format!("Certificate of Analysis received: {}", cs.to_description()),
+        &cs, &http_request,
+    ).await;
+    Ok(response)
+}
+
 async fn delete_batch_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
+    query: web::Query<DeleteQuery>,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_batch_permission_async(&http_request, auth_handlers::BatchAction::Delete, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
     let (reagent_id, batch_id) = path.into_inner();
+    let immediate = pending_deletion_handlers::is_immediate(&query, &claims)?;
 
     // Fetch info before deletion
     let mut cs = ChangeSet::new();
@@ -538,38 +872,58 @@ async fn delete_batch_protected(
         if let Some(ref lot) = old.4 { cs.deleted("lot_number", lot); }
     }
 
-    // FIXED: pass user_id as third argument
-    let response = batch_handlers::delete_batch(app_state.clone(), web::Path::from((reagent_id.clone(), batch_id.clone())), claims.sub).await?;
+    let (response, desc) = if immediate {
+        // FIXED: pass user_id as third argument
+        let response = batch_handlers::delete_batch(app_state.clone(), web::Path::from((reagent_id.clone(), batch_id.clone())), claims.sub).await?;
+        (response, format!("Deleted batch of reagent '{}': {}", reagent_name, cs.to_description()))
+    } else {
+        let deferred = pending_deletion_handlers::defer_delete(
+            &app_state.db_pool, DeferredEntity::Batch, &batch_id, Some(&reagent_id), &user_id,
+        ).await?;
+        let desc = format!("Deleted batch of reagent '{}' ({}): {}", reagent_name, deferred.audit_note(), cs.to_description());
+        (deferred.into_response("Batch"), desc)
+    };
     audit::audit_with_changes(
         &app_state.db_pool, &user_id, "delete", "batch", &batch_id,
-        &format!("Deleted batch of reagent '{}': {}", reagent_name, cs.to_description()),
-        &cs, &http_request,
+        &desc, &cs, &http_request,
     ).await;
     Ok(response)
 }
 
 // ==================== EQUIPMENT PROTECTED WRAPPERS ====================
 
+async fn get_equipment_protected(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<equipment_handlers::EquipmentPaginationQuery>,
+    api_version: api_version::ApiVersion,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    get_equipment(app_state, query, claims.sub, api_version).await
+}
+
 async fn create_equipment_protected(
     app_state: web::Data<Arc<AppState>>,
     equipment: web::Json<CreateEquipmentRequest>,
+    api_version: api_version::ApiVersion,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Create, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
 
     let mut cs = ChangeSet::new();
     cs.created("name", &equipment.name);
-    cs.created("type", &equipment.type_);
+    cs.created("type", equipment.requested_type());
     cs.created("quantity", &format!("{}", equipment.quantity));
     if let Some(ref v) = equipment.location { cs.created("location", v); }
     if let Some(ref v) = equipment.serial_number { cs.created("serial_number", v); }
     if let Some(ref v) = equipment.manufacturer { cs.created("manufacturer", v); }
     if let Some(ref v) = equipment.model { cs.created("model", v); }
+    if let Some(ref v) = equipment.parent_equipment_id { cs.created("parent_equipment_id", v); }
+    if let Some(ref v) = equipment.catalog_id { cs.created("catalog_id", v); }
  
 
-    let response = equipment_handlers::create_equipment(app_state.clone(), equipment, claims.sub).await?;
+    let response = equipment_handlers::create_equipment(app_state.clone(), equipment, claims.sub, api_version).await?;
     audit::audit_with_changes(
         &app_state.db_pool, &user_id, "create", "equipment", "",
         &format!("Created equipment: {}", cs.to_description()),
@@ -582,9 +936,10 @@ async fn update_equipment_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
     update_data: web::Json<UpdateEquipmentRequest>,
+    query: web::Query<equipment_handlers::CascadeQuery>,
+    api_version: api_version::ApiVersion,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
     let equipment_id = path.into_inner();
@@ -594,20 +949,25 @@ async fn update_equipment_protected(
 
     if let Ok(old) = sqlx::query_as::<_, (
         String, i64, String, Option<String>, Option<String>,
-        Option<String>, Option<String>, Option<String>
+        Option<String>, Option<String>, Option<String>, Option<String>, String
     )>(
         "SELECT name, quantity, status, location, serial_number, \
-         manufacturer, model, description FROM equipment WHERE id = ?"
+         manufacturer, model, description, parent_equipment_id, type_ FROM equipment WHERE id = ?"
     ).bind(&equipment_id).fetch_one(&app_state.db_pool).await {
         equip_name = old.0.clone();
         if let Some(ref new_val) = update_data.name { cs.add("name", &old.0, new_val); }
+        if let Some(new_val) = update_data.requested_type() { cs.add("type", &old.9, new_val); }
         if let Some(new_val) = update_data.quantity { cs.add_i64("quantity", old.1, new_val as i64); }
         if let Some(ref new_val) = update_data.status { cs.add("status", &old.2, new_val); }
-        if let Some(ref new_val) = update_data.location { cs.add_opt("location", &old.3, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.serial_number { cs.add_opt("serial_number", &old.4, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.manufacturer { cs.add_opt("manufacturer", &old.5, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.model { cs.add_opt("model", &old.6, &Some(new_val.clone())); }
-        if let Some(ref new_val) = update_data.description { cs.add_opt("description", &old.7, &Some(new_val.clone())); }
+        if let Some(ref new_val) = update_data.location { cs.add_opt("location", &old.3, new_val); }
+        if let Some(ref new_val) = update_data.serial_number { cs.add_opt("serial_number", &old.4, new_val); }
+        if let Some(ref new_val) = update_data.manufacturer { cs.add_opt("manufacturer", &old.5, new_val); }
+        if let Some(ref new_val) = update_data.model { cs.add_opt("model", &old.6, new_val); }
+        if let Some(ref new_val) = update_data.description { cs.add_opt("description", &old.7, new_val); }
+        if let Some(ref new_val) = update_data.parent_equipment_id {
+            let new_parent = new_val.clone().filter(|p| !p.trim().is_empty());
+            cs.add_opt("parent_equipment_id", &old.8, &new_parent);
+        }
     }
 
     let desc = if cs.has_changes() {
@@ -616,7 +976,8 @@ async fn update_equipment_protected(
         format!("Equipment '{}' updated", equip_name)
     };
 
-    let response = equipment_handlers::update_equipment(app_state.clone(), web::Path::from(equipment_id.clone()), update_data, claims.sub).await?;
+    let cascade = query.cascade.unwrap_or(false);
+    let response = equipment_handlers::update_equipment(app_state.clone(), web::Path::from(equipment_id.clone()), update_data, claims.sub, cascade, api_version).await?;
     audit::audit_with_changes(
         &app_state.db_pool, &user_id, "edit", "equipment", &equipment_id,
         &desc, &cs, &http_request,
@@ -627,11 +988,12 @@ async fn update_equipment_protected(
 async fn delete_equipment_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<String>,
+    query: web::Query<DeleteQuery>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Delete, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let equipment_id = path.into_inner();
+    let immediate = pending_deletion_handlers::is_immediate(&query, &claims)?;
 
     let mut cs = ChangeSet::new();
     if let Ok(old) = sqlx::query_as::<_, (String, String, String)>(
@@ -642,11 +1004,19 @@ async fn delete_equipment_protected(
         cs.deleted("status", &old.2);
     }
 
-    let response = equipment_handlers::delete_equipment(app_state.clone(), web::Path::from(equipment_id.clone())).await?;
+    let (response, desc) = if immediate {
+        let response = equipment_handlers::delete_equipment(app_state.clone(), web::Path::from(equipment_id.clone())).await?;
+        (response, format!("Deleted equipment: {}", cs.to_description()))
+    } else {
+        let deferred = pending_deletion_handlers::defer_delete(
+            &app_state.db_pool, DeferredEntity::Equipment, &equipment_id, None, &claims.sub,
+        ).await?;
+        let desc = format!("Deleted equipment ({}): {}", deferred.audit_note(), cs.to_description());
+        (deferred.into_response("Equipment"), desc)
+    };
     audit::audit_with_changes(
         &app_state.db_pool, &claims.sub, "delete", "equipment", &equipment_id,
-        &format!("Deleted equipment: {}", cs.to_description()),
-        &cs, &http_request,
+        &desc, &cs, &http_request,
     ).await;
     Ok(response)
 }
@@ -666,7 +1036,6 @@ async fn add_equipment_part_protected(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = auth_handlers::get_claims_from_request(&http_request)?;
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
     add_equipment_part(app_state, path, part, claims.sub).await
 }
 
@@ -677,16 +1046,13 @@ async fn update_equipment_part_protected(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = auth_handlers::get_claims_from_request(&http_request)?;
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
     update_equipment_part(app_state, path, update, claims.sub).await
 }
 
 async fn delete_equipment_part_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
-    http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Delete, &app_state.db_pool).await?;
     delete_equipment_part(app_state, path).await
 }
 
@@ -705,7 +1071,6 @@ async fn create_maintenance_protected(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = auth_handlers::get_claims_from_request(&http_request)?;
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
     create_maintenance(app_state, path, maintenance, claims.sub).await
 }
 
@@ -716,7 +1081,6 @@ async fn update_maintenance_protected(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = auth_handlers::get_claims_from_request(&http_request)?;
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
     update_maintenance(app_state, path, update, claims.sub).await
 }
 
@@ -727,16 +1091,13 @@ async fn complete_maintenance_protected(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = auth_handlers::get_claims_from_request(&http_request)?;
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
     complete_maintenance(app_state, path, body, claims.sub).await
 }
 
 async fn delete_maintenance_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
-    http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Delete, &app_state.db_pool).await?;
     delete_maintenance(app_state, path).await
 }
 
@@ -755,7 +1116,6 @@ async fn upload_equipment_file_protected(
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
     let claims = auth_handlers::get_claims_from_request(&http_request)?;
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Edit, &app_state.db_pool).await?;
     upload_equipment_file(app_state, path, payload, claims.sub).await
 }
 
@@ -769,9 +1129,7 @@ async fn download_equipment_file_protected(
 async fn delete_equipment_file_protected(
     app_state: web::Data<Arc<AppState>>,
     path: web::Path<(String, String)>,
-    http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_equipment_permission(&http_request, auth_handlers::EquipmentAction::Delete, &app_state.db_pool).await?;
     delete_equipment_file(app_state, path).await
 }
 
@@ -782,6 +1140,161 @@ async fn get_part_files_protected(
     get_part_files(app_state, path).await
 }
 
+// ==================== EXTERNAL LINK PROTECTED WRAPPERS ====================
+// Добавление/удаление ссылки = редактирование родительской сущности (см. access_control)
+
+async fn add_external_link_for(
+    app_state: web::Data<Arc<AppState>>,
+    entity: models::LinkEntityType,
+    entity_id: String,
+    body: web::Json<models::CreateExternalLinkRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    let user_id = claims.sub.clone();
+
+    let mut cs = ChangeSet::new();
+    cs.created("title", &body.title);
+    cs.created("url", &body.url);
+
+    let response = link_handlers::add_link(app_state.clone(), entity, entity_id.clone(), body, claims.sub).await?;
+    audit::audit_with_changes(
+        &app_state.db_pool, &user_id, "add_link", entity.as_str(), &entity_id,
+        &format!("Added external link: {}", cs.to_description()),
+        &cs, &http_request,
+    ).await;
+    Ok(response)
+}
+
+async fn delete_external_link_for(
+    app_state: web::Data<Arc<AppState>>,
+    entity: models::LinkEntityType,
+    entity_id: String,
+    link_id: String,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth::get_current_user(&http_request)?;
+    let response = link_handlers::delete_link(app_state.clone(), entity, entity_id.clone(), link_id.clone()).await?;
+    audit::audit(
+        &app_state.db_pool, &claims.sub, "delete_link", entity.as_str(), &entity_id,
+        &format!("Deleted external link {}", link_id), &http_request,
+    ).await;
+    Ok(response)
+}
+
+async fn upload_reagent_image_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    payload: Multipart,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    reagent_image_handlers::upload_reagent_image(app_state, path, payload, claims.sub).await
+}
+
+async fn create_kiosk_token_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<kiosk_handlers::CreateKioskTokenRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    let created = kiosk_handlers::create_kiosk_token(app_state.clone(), path, body, claims.sub.clone()).await?;
+    audit::audit(
+        &app_state.db_pool, &claims.sub, "create_kiosk_token", "room", &created.info.room_id,
+        &format!("Issued kiosk token '{}' (…{}) with scopes {}", created.info.label, created.info.token_hint, created.info.scopes),
+        &http_request,
+    ).await;
+    Ok(HttpResponse::Created().json(handlers::ApiResponse::success(created)))
+}
+
+async fn revoke_kiosk_token_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = auth_handlers::get_claims_from_request(&http_request)?;
+    let revoked = kiosk_handlers::revoke_kiosk_token(app_state.clone(), path, claims.sub.clone()).await?;
+    audit::audit(
+        &app_state.db_pool, &claims.sub, "revoke_kiosk_token", "room", &revoked.room_id,
+        &format!("Revoked kiosk token '{}' (…{})", revoked.label, revoked.token_hint),
+        &http_request,
+    ).await;
+    Ok(HttpResponse::Ok().json(handlers::ApiResponse::success(revoked)))
+}
+
+async fn add_reagent_link_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<models::CreateExternalLinkRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    add_external_link_for(app_state, models::LinkEntityType::Reagent, path.into_inner(), body, http_request).await
+}
+
+async fn delete_reagent_link_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let (id, link_id) = path.into_inner();
+    delete_external_link_for(app_state, models::LinkEntityType::Reagent, id, link_id, http_request).await
+}
+
+async fn add_batch_link_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<models::CreateExternalLinkRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    add_external_link_for(app_state, models::LinkEntityType::Batch, path.into_inner(), body, http_request).await
+}
+
+async fn delete_batch_link_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let (id, link_id) = path.into_inner();
+    delete_external_link_for(app_state, models::LinkEntityType::Batch, id, link_id, http_request).await
+}
+
+async fn add_equipment_link_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<models::CreateExternalLinkRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    add_external_link_for(app_state, models::LinkEntityType::Equipment, path.into_inner(), body, http_request).await
+}
+
+async fn delete_equipment_link_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let (id, link_id) = path.into_inner();
+    delete_external_link_for(app_state, models::LinkEntityType::Equipment, id, link_id, http_request).await
+}
+
+async fn add_experiment_link_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<models::CreateExternalLinkRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    add_external_link_for(app_state, models::LinkEntityType::Experiment, path.into_inner(), body, http_request).await
+}
+
+async fn delete_experiment_link_protected(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let (id, link_id) = path.into_inner();
+    delete_external_link_for(app_state, models::LinkEntityType::Experiment, id, link_id, http_request).await
+}
+
 // ==================== ROOM PROTECTED WRAPPERS ====================
 
 async fn create_room_protected(
@@ -789,7 +1302,6 @@ async fn create_room_protected(
     room: web::Json<crate::models::room::CreateRoomRequest>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_room_permission(&http_request, auth_handlers::RoomAction::Create, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
 
@@ -813,7 +1325,6 @@ async fn update_room_protected(
     update_data: web::Json<crate::models::room::UpdateRoomRequest>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_room_permission(&http_request, auth_handlers::RoomAction::Edit, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let user_id = claims.sub.clone();
     let room_id = path.into_inner();
@@ -851,7 +1362,6 @@ async fn delete_room_protected(
     path: web::Path<String>,
     http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    auth_handlers::check_room_permission(&http_request, auth_handlers::RoomAction::Delete, &app_state.db_pool).await?;
     let claims = auth::get_current_user(&http_request)?;
     let room_id = path.into_inner();
 
@@ -880,9 +1390,6 @@ async fn create_placement_protected(
     request: web::Json<crate::models::batch_placement::CreatePlacementRequest>,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_batch_permission_async(
-        &http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool
-    ).await?;
     placement_handlers::create_placement(app_state, path, request, http_request).await
 }
 
@@ -892,9 +1399,6 @@ async fn update_placement_protected(
     request: web::Json<crate::models::batch_placement::UpdatePlacementRequest>,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_batch_permission_async(
-        &http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool
-    ).await?;
     placement_handlers::update_placement(app_state, path, request, http_request).await
 }
 
@@ -903,9 +1407,6 @@ async fn delete_placement_protected(
     path: web::Path<(String, String)>,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_batch_permission_async(
-        &http_request, auth_handlers::BatchAction::Delete, &app_state.db_pool
-    ).await?;
     placement_handlers::delete_placement(app_state, path, http_request).await
 }
 
@@ -915,9 +1416,6 @@ async fn move_placement_protected(
     request: web::Json<crate::models::batch_placement::MovePlacementRequest>,
     http_request: HttpRequest,
 ) -> error::ApiResult<HttpResponse> {
-    auth_handlers::check_batch_permission_async(
-        &http_request, auth_handlers::BatchAction::Edit, &app_state.db_pool
-    ).await?;
     placement_handlers::move_placement(app_state, path, request, http_request).await
 }
 
@@ -933,19 +1431,408 @@ async fn logout(
         "Logged out successfully".to_string(),
     )))
 }
-async fn rebuild_cache_protected(
-    app_state: web::Data<Arc<AppState>>,
-    http_request: HttpRequest,
-) -> ApiResult<HttpResponse> {
-    let claims = auth::get_current_user(&http_request)?;
-        if claims.role != crate::auth::UserRole::Admin {
-            return Err(crate::error::ApiError::Forbidden("Admin access required".to_string()));
-    }
 
-    reagent_handlers::rebuild_cache(app_state).await
+// ==================== API ROUTES ====================
+
+/// Маршрут /api/v1: путь относительно /api/v1, в точности как в access_control::ROUTE_PERMISSIONS
+pub struct ApiRoute {
+    pub method: Method,
+    pub path: &'static str,
+    pub route: actix_web::Route,
+}
+
+fn api_route<F, Args>(method: Method, path: &'static str, handler: F) -> ApiRoute
+where
+    F: actix_web::Handler<Args>,
+    Args: actix_web::FromRequest + 'static,
+    F::Output: actix_web::Responder + 'static,
+{
+    ApiRoute { route: web::method(method.clone()).to(handler), method, path }
+}
+
+fn api_get<F, Args>(path: &'static str, handler: F) -> ApiRoute
+where
+    F: actix_web::Handler<Args>,
+    Args: actix_web::FromRequest + 'static,
+    F::Output: actix_web::Responder + 'static,
+{
+    api_route(Method::GET, path, handler)
+}
+
+fn api_post<F, Args>(path: &'static str, handler: F) -> ApiRoute
+where
+    F: actix_web::Handler<Args>,
+    Args: actix_web::FromRequest + 'static,
+    F::Output: actix_web::Responder + 'static,
+{
+    api_route(Method::POST, path, handler)
+}
+
+fn api_put<F, Args>(path: &'static str, handler: F) -> ApiRoute
+where
+    F: actix_web::Handler<Args>,
+    Args: actix_web::FromRequest + 'static,
+    F::Output: actix_web::Responder + 'static,
+{
+    api_route(Method::PUT, path, handler)
+}
+
+fn api_delete<F, Args>(path: &'static str, handler: F) -> ApiRoute
+where
+    F: actix_web::Handler<Args>,
+    Args: actix_web::FromRequest + 'static,
+    F::Output: actix_web::Responder + 'static,
+{
+    api_route(Method::DELETE, path, handler)
+}
+
+/// Все маршруты /api/v1 в порядке регистрации (порядок важен: `/reagents/search` до `/reagents/{id}`).
+/// Каждый маршрут обязан иметь запись в таблице прав — это проверяет тест в access_control.
+pub fn api_v1_routes() -> Vec<ApiRoute> {
+    vec![
+        // Public file access (без токена, см. access_control)
+        api_get("/public/equipment/{id}/files/{file_id}", download_equipment_file),
+        api_get("/public/downloads/{token}", signed_downloads::redeem_download),
+
+        // API versions (X-API-Version)
+        api_get("/version", api_version::get_versions),
+
+        // Unit conversion
+        api_post("/units/convert", batch_handlers::convert_units),
+
+        // Inline form validation (read-only)
+        api_post("/validate/{entity}", validation_handlers::validate_form),
+
+        // Form field autosuggest (whitelisted fields only)
+        api_get("/suggest/{field}", suggest_handlers::get_suggestions),
+
+        // Auth management
+        api_get("/auth/profile", get_profile),
+        api_post("/auth/change-password", change_password),
+        api_post("/auth/logout", logout),
+        api_get("/auth/roles", get_roles),
+        api_get("/auth/users", get_users),
+        api_post("/auth/users", create_user),
+        api_get("/auth/users/inactive", auth_handlers::get_inactive_users),
+        api_get("/auth/users/{id}", get_user),
+        api_put("/auth/users/{id}", update_user),
+        api_delete("/auth/users/{id}", delete_user),
+        api_put("/auth/users/{id}/reset-password", change_user_password),
+        // User Permissions & Activity
+        api_get("/auth/users/{id}/permissions", auth_handlers::get_user_permissions),
+        api_put("/auth/users/{id}/permissions", auth_handlers::update_user_permissions),
+        api_get("/auth/users/{id}/activity", auth_handlers::get_user_activity),
+        api_post("/auth/users/{id}/anonymize", auth_handlers::anonymize_user),
+        api_get("/auth/jwt/status", get_jwt_rotation_status),
+        api_post("/auth/jwt/rotate", force_jwt_rotation),
+
+        // Dashboard
+        api_get("/dashboard/stats", get_dashboard_stats),
+        api_get("/dashboard/recent-activity", get_recent_activity),
+        api_get("/dashboard/trends", get_dashboard_trends),
+        api_get("/dashboard/equipment-summary", equipment_handlers::get_equipment_summary),
+
+        // Global search
+        api_get("/search", handlers::global_search),
+
+        // Barcode / QR scanning
+        api_get("/scan/{code}", scan_handlers::scan_code),
+
+        // Admin (cache management, query diagnostics)
+        api_post("/admin/cache/rebuild", rebuild_cache),
+        api_get("/admin/slow-queries", query_log::get_slow_queries),
+        api_get("/admin/kpis", kpi::get_kpis),
+        api_get("/admin/anomalies", stock_anomalies::get_anomalies),
+        api_get("/admin/retention/dry-run", monitoring::get_retention_dry_run),
+        api_post("/admin/reconcile-reservations", reconciliation::reconcile_reservations_handler),
+        api_get("/admin/storage/dedup", file_blobs::get_dedup_report),
+        api_get("/admin/normalizations/manufacturer", equipment_catalog::get_manufacturer_normalizations),
+        api_put("/admin/normalizations/manufacturer", equipment_catalog::put_manufacturer_normalization),
+        api_get("/admin/normalizations/physical-state", physical_state::get_physical_state_normalizations),
+        api_put("/admin/normalizations/physical-state", physical_state::put_physical_state_mapping),
+        api_get("/admin/normalizations/user-identity", user_identity::get_user_identity_normalizations),
+        api_get("/admin/normalizations/equipment-serial", equipment_catalog::get_serial_normalizations),
+        api_post("/admin/reference-cache/flush", reference_cache::flush_reference_cache),
+        api_get("/admin/settings", settings::get_settings),
+        api_put("/admin/settings", settings::update_settings),
+        api_get("/admin/maintenance-mode", maintenance_mode::get_maintenance_mode),
+        api_post("/admin/maintenance-mode", maintenance_mode::set_maintenance_mode),
+        api_get("/admin/schema", schema::get_schema_info),
+        api_post("/admin/migrate", schema::run_pending_migrations),
+        api_post("/admin/seed-demo", demo_seed::seed_demo_handler),
+        api_post("/admin/seed-demo/clear", demo_seed::clear_demo_handler),
+        api_get("/admin/pending-deletions", pending_deletion_handlers::get_pending_deletions),
+        api_get("/admin/outbox", outbox::get_outbox_stats),
+        api_get("/admin/outbox/dead-letters", outbox::get_dead_letters),
+        api_post("/admin/outbox/{id}/requeue", outbox::requeue_dead_letter),
+        api_post("/admin/outbox/{id}/discard", outbox::discard_dead_letter),
+        api_get("/admin/config-export", config_transfer::export_config),
+        api_post("/admin/config-import", config_transfer::import_config),
+        api_get("/admin/support-bundle", support_bundle::get_support_bundle),
+        api_post("/undo/{token}", pending_deletion_handlers::undo_deletion),
+        api_get("/approvals", approval_handlers::get_approvals),
+        api_post("/approvals/{id}/approve", approval_handlers::approve_consumption),
+        api_post("/approvals/{id}/reject", approval_handlers::reject_consumption),
+        api_get("/alerts", alert_handlers::get_alerts),
+        api_post("/alerts/{id}/acknowledge", alert_handlers::acknowledge_alert),
+        api_get("/favorites", favorites_handlers::get_favorites),
+        api_post("/favorites/{entity_type}/{id}", favorites_handlers::add_favorite),
+        api_delete("/favorites/{entity_type}/{id}", favorites_handlers::remove_favorite),
+        api_get("/purchase-orders", purchase_order_handlers::list_purchase_orders),
+        api_post("/purchase-orders", purchase_order_handlers::create_purchase_order),
+        api_post("/purchase-orders/from-suggestions", purchase_order_handlers::create_purchase_order_from_suggestions),
+        api_get("/purchase-orders/{id}", purchase_order_handlers::get_purchase_order),
+        api_post("/purchase-orders/{id}/submit", purchase_order_handlers::submit_purchase_order),
+        api_post("/purchase-orders/{id}/cancel", purchase_order_handlers::cancel_purchase_order),
+        api_get("/purchase-orders/{id}/receive", purchase_order_handlers::get_receive_drafts),
+        api_post("/purchase-orders/{id}/receive", purchase_order_handlers::receive_purchase_order),
+        api_get("/teams", team_handlers::list_teams),
+        api_post("/teams", team_handlers::create_team),
+        api_get("/teams/{id}", team_handlers::get_team),
+        api_put("/teams/{id}", team_handlers::update_team),
+        api_delete("/teams/{id}", team_handlers::delete_team),
+        api_post("/teams/{id}/members", team_handlers::add_team_member),
+        api_delete("/teams/{id}/members/{user_id}", team_handlers::remove_team_member),
+
+        // Batches
+        api_post("/batches/filter", filter_handlers::get_batches_filtered),
+        api_get("/batches/preset/{preset}", filter_handlers::get_batches_by_preset),
+        api_get("/batches", get_all_batches),
+        api_get("/batches/low-stock", get_low_stock_batches),
+        api_get("/batches/expiring", get_expiring_batches),
+        api_get("/batches/export", export_batches),
+        api_post("/batches/import", import_batches),
+        api_post("/batches/import/json", import_batches_json),
+        api_post("/batches/import/excel", import_batches_excel),
+        api_post("/batches/import/usage", import_usage_history_protected),
+        api_get("/batches/{batch_id}/placements", placement_handlers::get_batch_placements),
+        api_post("/batches/{batch_id}/placements", create_placement_protected),
+        api_post("/batches/{batch_id}/placements/move", move_placement_protected),
+        api_put("/batches/{batch_id}/placements/{placement_id}", update_placement_protected),
+        api_delete("/batches/{batch_id}/placements/{placement_id}", delete_placement_protected),
+        api_post("/batches/{batch_id}/adjust", stock_adjustment_handlers::adjust_batch_stock),
+        api_get("/batches/{batch_id}/genealogy", batch_genealogy::get_batch_genealogy),
+        api_get("/batches/{batch_id}/links", link_handlers::get_batch_links),
+        api_post("/batches/{batch_id}/links", add_batch_link_protected),
+        api_delete("/batches/{batch_id}/links/{link_id}", delete_batch_link_protected),
+
+        // Reagents
+        api_post("/reagents", create_reagent_protected),
+        api_get("/reagents", get_reagents_protected),
+        api_get("/reagents/search", search_reagents),
+        api_get("/reagents/lookup", catalog_lookup::lookup_reagent),
+        api_get("/reagents/forecast", forecast_handlers::get_reagent_forecasts),
+        api_get("/reagents/export", export_reagents),
+        api_get("/reagents/without-batches", reagent_handlers::get_reagents_without_batches),
+        api_post("/reagents/import", import_reagents),
+        api_post("/reagents/import/json", import_reagents_json),
+        api_post("/reagents/import/excel", import_reagents_excel),
+        api_get("/reagents/{id}", get_reagent_by_id),
+        api_put("/reagents/{id}", update_reagent_protected),
+        api_delete("/reagents/{id}", delete_reagent_protected),
+        api_post("/reagents/{id}/deactivate", reagent_handlers::deactivate_reagent),
+        api_post("/reagents/{id}/reactivate", reagent_handlers::reactivate_reagent),
+        api_get("/reagents/{id}/details", get_reagent_with_batches),
+        api_get("/reagents/{id}/forecast", forecast_handlers::get_reagent_forecast),
+        api_get("/reagents/{id}/adjustments/summary", stock_adjustment_handlers::get_reagent_adjustment_summary),
+        api_get("/reagents/{id}/image", reagent_image_handlers::get_reagent_image),
+        api_post("/reagents/{id}/image", upload_reagent_image_protected),
+        api_get("/reagents/{id}/batches", get_batches_for_reagent),
+        api_post("/reagents/{id}/batches", create_batch_protected),
+        api_get("/reagents/{id}/links", link_handlers::get_reagent_links),
+        api_post("/reagents/{id}/links", add_reagent_link_protected),
+        api_delete("/reagents/{id}/links/{link_id}", delete_reagent_link_protected),
+        api_get("/reagents/{reagent_id}/batches/{batch_id}", get_batch),
+        api_put("/reagents/{reagent_id}/batches/{batch_id}", update_batch_protected),
+        api_delete("/reagents/{reagent_id}/batches/{batch_id}", delete_batch_protected),
+        api_put("/reagents/{reagent_id}/batches/{batch_id}/barcode", set_batch_barcode_protected),
+        api_post("/reagents/{reagent_id}/batches/{batch_id}/extend-expiry", extend_batch_expiry_protected),
+        api_post("/reagents/{reagent_id}/batches/{batch_id}/coa-received", mark_coa_received_protected),
+        api_post("/reagents/{reagent_id}/batches/{batch_id}/use", use_reagent),
+        api_post("/reagents/{reagent_id}/consume", consume_reagent),
+        api_get("/reagents/{reagent_id}/batches/{batch_id}/usage", get_usage_history),
+        api_post("/reagents/{reagent_id}/batches/{batch_id}/dispense-units", dispense_units),
+        api_get("/reagents/{reagent_id}/batches/{batch_id}/units-info", get_batch_units_info),
+
+        // Equipment
+        api_post("/equipment", create_equipment_protected),
+        api_get("/equipment", get_equipment_protected),
+        api_get("/equipment/search", search_equipment),
+        api_get("/equipment/manufacturers", equipment_catalog::get_manufacturers),
+        api_get("/equipment/models", equipment_catalog::get_models),
+        api_get("/equipment/catalog", equipment_catalog::get_catalog),
+        api_post("/equipment/catalog", equipment_catalog::create_catalog_entry),
+        api_get("/equipment/catalog/{id}", equipment_catalog::get_catalog_entry),
+        api_put("/equipment/catalog/{id}", equipment_catalog::update_catalog_entry),
+        api_delete("/equipment/catalog/{id}", equipment_catalog::delete_catalog_entry),
+        api_get("/equipment/maintenance/upcoming", get_upcoming_maintenance),
+        api_get("/equipment/maintenance/overdue", get_overdue_maintenance),
+        api_get("/equipment/parts/reorder-suggestions", equipment_handlers::get_part_reorder_suggestions),
+        api_get("/equipment/export", export_equipment),
+        api_post("/equipment/import", import_equipment),
+        api_post("/equipment/import/json", import_equipment_json),
+        api_post("/equipment/import/excel", import_equipment_excel),
+        api_get("/equipment/{id}", get_equipment_by_id),
+        api_put("/equipment/{id}", update_equipment_protected),
+        api_delete("/equipment/{id}", delete_equipment_protected),
+        api_get("/equipment/{id}/components", equipment_handlers::get_equipment_components),
+        api_get("/equipment/{id}/parts", get_equipment_parts_protected),
+        api_post("/equipment/{id}/parts", add_equipment_part_protected),
+        api_put("/equipment/{id}/parts/{part_id}", update_equipment_part_protected),
+        api_delete("/equipment/{id}/parts/{part_id}", delete_equipment_part_protected),
+        api_get("/equipment/{id}/parts/{part_id}/files", get_part_files_protected),
+        api_get("/equipment/{id}/maintenance", get_equipment_maintenance_protected),
+        api_post("/equipment/{id}/maintenance", create_maintenance_protected),
+        api_put("/equipment/{id}/maintenance/{maintenance_id}", update_maintenance_protected),
+        api_post("/equipment/{id}/maintenance/{maintenance_id}/complete", complete_maintenance_protected),
+        api_delete("/equipment/{id}/maintenance/{maintenance_id}", delete_maintenance_protected),
+        api_get("/equipment/{id}/files", get_equipment_files_protected),
+        api_post("/equipment/{id}/files", upload_equipment_file_protected),
+        api_get("/equipment/{id}/files/{file_id}", download_equipment_file_protected),
+        api_delete("/equipment/{id}/files/{file_id}", delete_equipment_file_protected),
+        api_get("/equipment/{id}/links", link_handlers::get_equipment_links),
+        api_post("/equipment/{id}/links", add_equipment_link_protected),
+        api_delete("/equipment/{id}/links/{link_id}", delete_equipment_link_protected),
+
+        // Rooms
+        api_get("/rooms", get_all_rooms),
+        api_post("/rooms", create_room_protected),
+        api_get("/rooms/available", get_available_rooms),
+        api_get("/rooms/utilization", get_rooms_utilization),
+        api_get("/rooms/{id}", get_room),
+        api_put("/rooms/{id}", update_room_protected),
+        api_delete("/rooms/{id}", delete_room_protected),
+        api_get("/rooms/{id}/utilization", get_room_utilization),
+        api_get("/rooms/{id}/inventory", placement_handlers::get_room_inventory),
+        api_get("/rooms/{id}/placements", placement_handlers::get_room_placements),
+        api_post("/rooms/{id}/kiosk-token", create_kiosk_token_protected),
+        api_get("/rooms/{id}/kiosk-tokens", kiosk_handlers::list_kiosk_tokens),
+        api_delete("/rooms/{id}/kiosk-tokens/{token_id}", revoke_kiosk_token_protected),
+
+        // Storage locations (иерархия мест хранения)
+        api_get("/locations", location_handlers::get_locations),
+        api_post("/locations", location_handlers::create_location),
+        api_get("/locations/{id}", location_handlers::get_location),
+        api_put("/locations/{id}", location_handlers::update_location),
+        api_delete("/locations/{id}", location_handlers::delete_location),
+        api_post("/locations/{id}/move", location_handlers::move_location),
+        api_get("/locations/{id}/contents", location_handlers::get_location_contents),
+
+        // Kiosk (киоск-токен помещения)
+        api_get("/kiosk/equipment", kiosk_handlers::get_kiosk_equipment),
+        api_post("/kiosk/equipment/{id}/checkout", kiosk_handlers::kiosk_checkout_equipment),
+        api_post("/kiosk/equipment/{id}/checkin", kiosk_handlers::kiosk_checkin_equipment),
+        api_post("/kiosk/attendance", kiosk_handlers::kiosk_record_attendance),
+
+        // Experiments
+        api_post("/experiments", create_experiment_protected),
+        api_get("/experiments", get_all_experiments),
+        api_get("/experiments/stats", get_experiment_stats),
+        api_post("/experiments/filter", filter_handlers::get_experiments_filtered),
+        api_get("/experiments/filter/fields", filter_handlers::get_experiment_filter_fields),
+        api_get("/experiments/preset/{preset}", filter_handlers::get_experiments_by_preset),
+        api_post("/experiments/auto-update-statuses", auto_update_experiment_statuses_handler),
+        api_get("/experiments/diagnose-dates", experiment_handlers::diagnose_experiment_dates),
+        api_get("/experiments/calendar", experiment_handlers::get_experiments_calendar),
+        api_get("/experiments/dependency-graph", experiment_handlers::get_experiment_dependency_graph),
+        api_get("/calendar", calendar_handlers::get_calendar),
+        api_get("/experiments/{id}", get_experiment),
+        api_put("/experiments/{id}", update_experiment_protected),
+        api_delete("/experiments/{id}", delete_experiment_protected),
+        api_post("/experiments/{id}/publish", publish_experiment_protected),
+        api_post("/experiments/{id}/clone", experiment_handlers::clone_experiment),
+        api_post("/experiments/{id}/start", start_experiment_protected),
+        api_post("/experiments/{id}/complete", complete_experiment_protected),
+        api_post("/experiments/{id}/cancel", cancel_experiment_protected),
+        api_get("/experiments/{id}/reagents", get_experiment_reagents),
+        api_post("/experiments/{id}/reagents", add_experiment_reagent_protected),
+        api_delete("/experiments/{id}/reagents/{reagent_id}", remove_experiment_reagent_protected),
+        api_post("/experiments/{id}/reagents/{reagent_id}/consume", consume_experiment_reagent_protected),
+        api_get("/experiments/{id}/participants", get_experiment_participants),
+        api_post("/experiments/{id}/participants", add_experiment_participant_protected),
+        api_delete("/experiments/{id}/participants/{participant_id}", remove_experiment_participant_protected),
+        api_get("/experiments/{id}/comments", get_experiment_comments),
+        api_post("/experiments/{id}/comments", add_experiment_comment_protected),
+        api_get("/experiments/{id}/documents", get_experiment_documents),
+        api_get("/experiments/{id}/documents/{doc_id}", download_experiment_document),
+        api_post("/experiments/{id}/sign-in", sign_in_experiment_protected),
+        api_get("/experiments/{id}/signoff", get_experiment_signoff),
+        api_post("/experiments/{id}/signoff", sign_off_experiment_protected),
+        api_get("/experiments/{id}/dependencies", get_experiment_dependencies),
+        api_post("/experiments/{id}/dependencies", add_experiment_dependency_protected),
+        api_delete("/experiments/{id}/dependencies/{depends_on_id}", remove_experiment_dependency_protected),
+        api_get("/experiments/{id}/links", link_handlers::get_experiment_links),
+        api_get("/experiments/{id}/protocol/rendered", protocol_render::get_rendered_protocol),
+        api_get("/experiments/{id}/report.pdf", experiment_report::get_experiment_report_pdf),
+        api_post("/experiments/{id}/links", add_experiment_link_protected),
+        api_delete("/experiments/{id}/links/{link_id}", delete_experiment_link_protected),
+
+        // Reports
+        api_get("/reports/presets", report_handlers::get_report_presets),
+        api_post("/reports/presets", report_handlers::create_report_preset),
+        api_put("/reports/presets/{id}", report_handlers::update_report_preset),
+        api_delete("/reports/presets/{id}", report_handlers::delete_report_preset),
+        api_get("/reports/fields", report_handlers::get_report_fields),
+        api_post("/reports/generate", report_handlers::generate_report),
+        api_post("/reports/export", report_handlers::export_report),
+        api_get("/reports/consumption-variance", report_handlers::get_consumption_variance),
+        api_get("/reports/schedules", report_schedule_handlers::get_report_schedules),
+        api_post("/reports/schedules", report_schedule_handlers::create_report_schedule),
+        api_get("/reports/schedules/{id}", report_schedule_handlers::get_report_schedule),
+        api_put("/reports/schedules/{id}", report_schedule_handlers::update_report_schedule),
+        api_delete("/reports/schedules/{id}", report_schedule_handlers::delete_report_schedule),
+        api_get("/reports/schedules/{id}/runs", report_schedule_handlers::get_report_schedule_runs),
+        api_get("/export/archive", export_archive::export_archive),
+    ]
 }
+
 // ==================== MAIN ====================
 
+/// /auth, публичные маршруты, каталог и защищённый /api/v1 (auth middleware + таблица прав).
+/// Общий для сервера и тестового стенда (test_support), чтобы тесты шли через те же маршруты.
+pub fn configure_api(cfg: &mut web::ServiceConfig) {
+    let auth_middleware = HttpAuthentication::bearer(jwt_middleware);
+    let (public_routes, protected_routes): (Vec<ApiRoute>, Vec<ApiRoute>) = api_v1_routes()
+        .into_iter()
+        .partition(|r| access_control::is_public(&r.method, r.path));
+
+    cfg
+        // Auth endpoints (no authentication required)
+        .service(
+            web::scope("/auth")
+                .wrap(maintenance_mode::ReadOnlyGuard)
+                .route("/login", web::post().to(login))
+                .route("/register", web::post().to(register))
+        )
+
+        // Public file access (без auth middleware; см. access_control::ROUTE_PERMISSIONS)
+        .service(public_routes.into_iter().fold(
+            web::scope("/api/v1/public").wrap(api_version::ApiVersionHeaders),
+            |scope, r| scope.route(r.path.trim_start_matches("/public"), r.route),
+        ))
+
+        // Публичный каталог реагентов: без JWT, свой токен, лимит и набор полей
+        .service(
+            web::scope("/api/v1/catalogue")
+                .wrap(api_version::ApiVersionHeaders)
+                .route("", web::get().to(public_catalogue::list_catalogue))
+                .route("/{id}", web::get().to(public_catalogue::get_catalogue_entry))
+        )
+        .route("/catalogue", web::get().to(public_catalogue::catalogue_page))
+
+        // Protected API endpoints: auth middleware, затем таблица прав (deny-by-default),
+        // обработка ограничена таймаутом маршрута; в режиме обслуживания запись отклоняется
+        .service(protected_routes.into_iter().fold(
+            web::scope(access_control::API_PREFIX)
+                .wrap(access_control::RouteAuthorization)
+                .wrap(maintenance_mode::ReadOnlyGuard)
+                .wrap(request_timeout::RequestTimeout)
+                .wrap(auth_middleware)
+                .wrap(api_version::ApiVersionHeaders),
+            |scope, r| scope.route(r.path, r.route),
+        ));
+}
+
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
     // Load configuration (this calls load_env_file internally)
@@ -962,11 +1849,21 @@ async fn main() -> anyhow::Result<()> {
     // Setup database
     setup_database(&config.database.url).await?;
 
-    // Create database pool
-    let pool = create_database_pool(&config.database).await?;
+    // Create database pool (с профилированием запросов, если включено)
+    query_log::query_stats().configure(&config.query_log);
+    work_queue::configure(&config.work_queues);
+    let pool = db::create_pool(&config.database).await?;
+
+    // Schema: apply (auto_migrate) or refuse to start with pending migrations
+    schema::startup_check(&pool, &config.database).await?;
 
-    // Run migrations
-    db::run_migrations(&pool).await?;
+    // Runtime settings: значения по умолчанию из Config + переопределения из БД
+    settings::settings().configure(&config.settings);
+    settings::settings().load(&pool).await?;
+
+    // Режим обслуживания "только чтение" переживает перезапуск
+    let maintenance_mode = web::Data::new(maintenance_mode::MaintenanceModeStore::default());
+    maintenance_mode.load(&pool).await?;
 
     // Initialize JWT rotation table
     jwt_rotation::init_rotation_table(&pool).await?;
@@ -977,22 +1874,39 @@ async fn main() -> anyhow::Result<()> {
     // Create default admin if needed
     create_default_admin_if_needed(&pool, &auth_service).await?;
 
+    // Read-only пул открывается после миграций, когда файл базы уже создан
+    let read_pool = db::create_read_pool(&config.database).await?;
+
     // Create app state
     let app_state = Arc::new(AppState {
         db_pool: pool.clone(),
         config: config.clone(),
+        read_replica: read_pool.clone(),
+        reference_cache: reference_cache::ReferenceCache::new(config.reference_cache.ttl_seconds),
     });
 
+    // Демо-данные для холодного старта (--seed-demo)
+    if env::args().any(|arg| arg == demo_seed::SEED_DEMO_FLAG) {
+        demo_seed::seed_on_startup(&web::Data::new(app_state.clone()), &auth_service).await?;
+    }
+
     // Start maintenance tasks
     let pool_clone = pool.clone();
+    let inactivity_policy = config.inactivity.clone();
+    let retention_policy = config.retention.clone();
+    let mut report_scheduler = report_schedule_handlers::ReportScheduler::from_config(&config);
+    report_scheduler.read_pool = read_pool;
+    let alert_smtp = config.smtp.clone();
+    let maintenance_reminders = config.maintenance.clone();
     tokio::spawn(async move {
-        start_maintenance_tasks(pool_clone).await;
+        start_maintenance_tasks(pool_clone, inactivity_policy, retention_policy, report_scheduler, alert_smtp, maintenance_reminders).await;
     });
 
     // Фоновая задача: авто-обновление статусов экспериментов (event-driven, не поллинг)
     // Спрашивает у БД «через сколько секунд ближайшее событие?» и спит ровно до него.
     // Если нет pending экспериментов — спит 5 минут и проверяет снова (на случай новых).
     let experiment_pool = pool.clone();
+    let signoff_threshold = config.safety.signoff_hazard_threshold;
     tokio::spawn(async move {
         use tokio::time::{sleep, Duration};
 
@@ -1004,10 +1918,10 @@ async fn main() -> anyhow::Result<()> {
 
         loop {
             // 1. Спрашиваем: сколько секунд до ближайшего перехода?
-            let sleep_secs = match seconds_until_next_transition(&experiment_pool).await {
+            let sleep_secs = match seconds_until_next_transition(&experiment_pool, signoff_threshold).await {
                 Ok(Some(secs)) if secs <= 0 => {
                     // Уже просрочено — обрабатываем сейчас
-                    match run_auto_update_statuses(&experiment_pool).await {
+                    match run_auto_update_statuses(&experiment_pool, signoff_threshold).await {
                         Ok(r) if r.total_updated > 0 => {
                             log::info!(
                                 "BG auto-update: {} started, {} completed (reagents consumed)",
@@ -1046,6 +1960,14 @@ async fn main() -> anyhow::Result<()> {
         jwt_rotation::start_rotation_task(rotation_pool, env_file).await;
     });
 
+    // Доставка бизнес-событий из outbox (журнал, KPI, вебхуки, почта)
+    let outbox_pool = pool.clone();
+    let outbox_config = config.outbox.clone();
+    let outbox_smtp = config.smtp.clone();
+    tokio::spawn(async move {
+        outbox::start_dispatcher(outbox_pool, outbox_config, outbox_smtp).await;
+    });
+
     let bind_address = format!("{}:{}", config.server.host, config.server.port);
     log::info!("Starting server at http://{}", bind_address);
 
@@ -1053,201 +1975,42 @@ async fn main() -> anyhow::Result<()> {
     let metrics_arc = Arc::new(Metrics::new());
     let metrics = web::Data::from(metrics_arc.clone());
 
+    // Список уже проверен в Config::validate
+    let trusted_proxies = client_ip::TrustedProxies::parse(&config.security.trusted_proxies).unwrap_or_default();
+
     HttpServer::new(move || {
         let cors = setup_improved_cors(&config.security.allowed_origins);
-        let auth_middleware = HttpAuthentication::bearer(jwt_middleware);
-        let security_headers = setup_security_headers(&config.security);
+        let security_headers = security_headers::SecurityHeaders::new(&config.security);
 
         // Create App and save to variable
         let app = App::new()
             .wrap(cors)
             .wrap(security_headers)
-            .wrap(Logger::default())
-            .wrap(Compress::default())
-            .wrap(RequestLogger::new(metrics_arc.clone()))
+            // Формат Logger::default(), но вместо %a - IP из client_ip (без доверия к чужим заголовкам)
+            .wrap(
+                Logger::new(r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+                    .custom_request_replace("client_ip", |req| {
+                        client_ip::client_ip(req.request()).unwrap_or_else(|| "-".to_string())
+                    }),
+            )
+            .wrap(compression::ResponseCompression::new(&config.compression))
+            .wrap(RequestLogger::new(metrics_arc.clone(), trusted_proxies.clone()))
             .app_data(web::Data::new(app_state.clone()))
             .app_data(web::Data::new(auth_service.clone()))
             .app_data(metrics.clone())
+            .app_data(maintenance_mode.clone())
 
-            // Health check and metrics (no auth)
+            // Health check and metrics (no auth); оба health-эндпоинта сообщают о режиме обслуживания
             .service(
                 web::scope("/health")
-                    .route("", web::get().to(|| async { HttpResponse::Ok().body("OK") }))
+                    .route("", web::get().to(monitoring::health_check))
+                    .route("/ready", web::get().to(monitoring::readiness_check))
                     .route("/metrics", web::get().to(monitoring::metrics_endpoint))
             )
+            // Отчёты браузера о нарушениях CSP (без auth)
+            .route(security_headers::CSP_REPORT_PATH, web::post().to(security_headers::csp_report))
 
-            // Auth endpoints (no authentication required)
-            .service(
-                web::scope("/auth")
-                    .route("/login", web::post().to(login))
-                    .route("/register", web::post().to(register))
-            )
-
-            // Public file access (endpoints)
-            .service(
-                web::scope("/api/v1/public")
-                    .route("/equipment/{id}/files/{file_id}", web::get().to(download_equipment_file))
-            )
-
-            // Protected API endpoints
-            .service(
-                web::scope("/api/v1")
-                    .wrap(auth_middleware)
-                    // Unit conversion
-                    .service(
-                        web::scope("/units")
-                            .route("/convert", web::post().to(batch_handlers::convert_units))
-                    )
-
-                    // Auth management
-                    .service(
-                        web::scope("/auth")
-                            .route("/profile", web::get().to(get_profile))
-                            .route("/change-password", web::post().to(change_password))
-                            .route("/logout", web::post().to(logout))
-                            .route("/roles", web::get().to(get_roles))
-                            .route("/users", web::get().to(get_users))
-                            .route("/users", web::post().to(create_user))
-                            .route("/users/{id}", web::get().to(get_user))
-                            .route("/users/{id}", web::put().to(update_user))
-                            .route("/users/{id}", web::delete().to(delete_user))
-                            .route("/users/{id}/reset-password", web::put().to(change_user_password))
-                            // User Permissions & Activity
-                            .route("/users/{id}/permissions", web::get().to(auth_handlers::get_user_permissions))
-                            .route("/users/{id}/permissions", web::put().to(auth_handlers::update_user_permissions))
-                            .route("/users/{id}/activity", web::get().to(auth_handlers::get_user_activity))
-                            .route("/jwt/status", web::get().to(get_jwt_rotation_status))
-                            .route("/jwt/rotate", web::post().to(force_jwt_rotation))
-                    )
-
-                    // Dashboard
-                    .service(
-                        web::scope("/dashboard")
-                            .route("/stats", web::get().to(get_dashboard_stats))
-                            .route("/recent-activity", web::get().to(get_recent_activity))
-                            .route("/trends", web::get().to(get_dashboard_trends))
-                    )
-                    // Admin (cache management)
-                    .service(
-                        web::scope("/admin")
-                            .route("/cache/rebuild", web::post().to(rebuild_cache_protected))
-                    )
-                    // Batches
-                    .service(
-                        web::scope("/batches")
-                            .route("/filter", web::post().to(filter_handlers::get_batches_filtered))
-                            .route("/preset/{preset}", web::get().to(filter_handlers::get_batches_by_preset))
-                            .route("", web::get().to(get_all_batches))
-                            .route("/low-stock", web::get().to(get_low_stock_batches))
-                            .route("/expiring", web::get().to(get_expiring_batches))
-                            .route("/export", web::get().to(export_batches))
-                            .route("/import", web::post().to(import_batches))
-                            .route("/import/json", web::post().to(import_batches_json))
-                            .route("/import/excel", web::post().to(import_batches_excel))
-                            .route("/{batch_id}/placements", web::get().to(placement_handlers::get_batch_placements))
-                            .route("/{batch_id}/placements", web::post().to(create_placement_protected))
-                            .route("/{batch_id}/placements/move", web::post().to(move_placement_protected))
-                            .route("/{batch_id}/placements/{placement_id}", web::put().to(update_placement_protected))
-                            .route("/{batch_id}/placements/{placement_id}", web::delete().to(delete_placement_protected))
-                    )
-
-                    // Reagents
-                    .service(
-                        web::scope("/reagents")
-                            .route("", web::post().to(create_reagent_protected))
-                            .route("", web::get().to(get_reagents))
-                            .route("/search", web::get().to(search_reagents))
-                            .route("/export", web::get().to(export_reagents))
-                            .route("/import", web::post().to(import_reagents))
-                            .route("/import/json", web::post().to(import_reagents_json))
-                            .route("/import/excel", web::post().to(import_reagents_excel))
-                            .route("/{id}", web::get().to(get_reagent_by_id))
-                            .route("/{id}", web::put().to(update_reagent_protected))
-                            .route("/{id}", web::delete().to(delete_reagent_protected))
-                            .route("/{id}/details", web::get().to(get_reagent_with_batches))
-                            .route("/{id}/batches", web::get().to(get_batches_for_reagent))
-                            .route("/{id}/batches", web::post().to(create_batch_protected))
-                            .route("/{reagent_id}/batches/{batch_id}", web::get().to(get_batch))
-                            .route("/{reagent_id}/batches/{batch_id}", web::put().to(update_batch_protected))
-                            .route("/{reagent_id}/batches/{batch_id}", web::delete().to(delete_batch_protected))
-                            .route("/{reagent_id}/batches/{batch_id}/use", web::post().to(use_reagent))
-                            .route("/{reagent_id}/batches/{batch_id}/usage", web::get().to(get_usage_history))
-                            .route("/{reagent_id}/batches/{batch_id}/dispense-units", web::post().to(dispense_units))
-                            .route("/{reagent_id}/batches/{batch_id}/units-info", web::get().to(get_batch_units_info))
-                    )
-
-                    // Equipment
-                    .service(
-                        web::scope("/equipment")
-                            .route("", web::post().to(create_equipment_protected))
-                            .route("", web::get().to(get_equipment))
-                            .route("/search", web::get().to(search_equipment))
-                            .route("/export", web::get().to(export_equipment))
-                            .route("/import", web::post().to(import_equipment))
-                            .route("/import/json", web::post().to(import_equipment_json))
-                            .route("/import/excel", web::post().to(import_equipment_excel))
-                            .route("/{id}", web::get().to(get_equipment_by_id))
-                            .route("/{id}", web::put().to(update_equipment_protected))
-                            .route("/{id}", web::delete().to(delete_equipment_protected))
-                            .route("/{id}/parts", web::get().to(get_equipment_parts_protected))
-                            .route("/{id}/parts", web::post().to(add_equipment_part_protected))
-                            .route("/{id}/parts/{part_id}", web::put().to(update_equipment_part_protected))
-                            .route("/{id}/parts/{part_id}", web::delete().to(delete_equipment_part_protected))
-                            .route("/{id}/parts/{part_id}/files", web::get().to(get_part_files_protected))
-                            .route("/{id}/maintenance", web::get().to(get_equipment_maintenance_protected))
-                            .route("/{id}/maintenance", web::post().to(create_maintenance_protected))
-                            .route("/{id}/maintenance/{maintenance_id}", web::put().to(update_maintenance_protected))
-                            .route("/{id}/maintenance/{maintenance_id}/complete", web::post().to(complete_maintenance_protected))
-                            .route("/{id}/maintenance/{maintenance_id}", web::delete().to(delete_maintenance_protected))
-                            .route("/{id}/files", web::get().to(get_equipment_files_protected))
-                            .route("/{id}/files", web::post().to(upload_equipment_file_protected))
-                            .route("/{id}/files/{file_id}", web::get().to(download_equipment_file_protected))
-                            .route("/{id}/files/{file_id}", web::delete().to(delete_equipment_file_protected))
-                    )
-
-                    // Rooms
-                    .service(
-                        web::scope("/rooms")
-                            .route("", web::get().to(get_all_rooms))
-                            .route("", web::post().to(create_room_protected))
-                            .route("/available", web::get().to(get_available_rooms))
-                            .route("/{id}", web::get().to(get_room))
-                            .route("/{id}", web::put().to(update_room_protected))
-                            .route("/{id}", web::delete().to(delete_room_protected))
-                            .route("/{id}/inventory", web::get().to(placement_handlers::get_room_inventory))
-                            .route("/{id}/placements", web::get().to(placement_handlers::get_room_placements))
-                    )
-
-                    // Experiments
-                    .service(
-                        web::scope("/experiments")
-                            .route("", web::post().to(create_experiment_protected))
-                            .route("", web::get().to(get_all_experiments))
-                            .route("/stats", web::get().to(get_experiment_stats))
-                            .route("/filter", web::post().to(filter_handlers::get_experiments_filtered))
-                            .route("/auto-update-statuses", web::post().to(auto_update_experiment_statuses_handler))
-                            .route("/diagnose-dates", web::get().to(experiment_handlers::diagnose_experiment_dates))
-                            .route("/{id}", web::get().to(get_experiment))
-                            .route("/{id}", web::put().to(update_experiment_protected))
-                            .route("/{id}", web::delete().to(delete_experiment_protected))
-                            .route("/{id}/start", web::post().to(start_experiment_protected))
-                            .route("/{id}/complete", web::post().to(complete_experiment_protected))
-                            .route("/{id}/cancel", web::post().to(cancel_experiment_protected))
-                            .route("/{id}/reagents", web::get().to(get_experiment_reagents))
-                            .route("/{id}/reagents", web::post().to(add_experiment_reagent_protected))
-                            .route("/{id}/reagents/{reagent_id}", web::delete().to(remove_experiment_reagent_protected))
-                            .route("/{id}/reagents/{reagent_id}/consume", web::post().to(consume_experiment_reagent_protected))
-                    )
-
-                    // Reports
-                    .service(
-                        web::scope("/reports")
-                            .route("/presets", web::get().to(report_handlers::get_report_presets))
-                            .route("/fields", web::get().to(report_handlers::get_report_fields))
-                            .route("/generate", web::post().to(report_handlers::generate_report))
-                            .route("/export", web::post().to(report_handlers::export_report))
-                    )
-            ); // <-- End of chain, app contains everything
+            .configure(configure_api); // <-- End of chain, app contains everything
 
         // Add static files to the SAME app
         if env::var("LIMS_ENV").as_deref() == Ok("production") {
@@ -1290,8 +2053,15 @@ pub fn setup_improved_cors(allowed_origins: &[String]) -> Cors {
             header::ACCEPT,
             header::USER_AGENT,
             header::REFERER,
+            header::HeaderName::from_static(api_version::API_VERSION_HEADER),
+        ])
+        .expose_headers(vec![
+            header::CONTENT_LENGTH,
+            header::HeaderName::from_static(api_version::API_VERSION_HEADER),
+            header::HeaderName::from_static("deprecation"),
+            header::HeaderName::from_static("sunset"),
+            header::LINK,
         ])
-        .expose_headers(vec![header::CONTENT_LENGTH])
         .max_age(3600);
 
     let is_production = std::env::var("LIMS_ENV").as_deref() == Ok("production");
@@ -1339,9 +2109,33 @@ fn setup_logging(config: &Config) -> anyhow::Result<()> {
             tracing_subscriber::EnvFilter::new(level)
         });
 
+    // Бизнес-события идут только в свои слои (stdout и/или файл), не в общий журнал
+    let filter = filter.add_directive(format!("{}=off", events::EVENTS_TARGET).parse()?);
+    let events_only = || tracing_subscriber::filter::Targets::new()
+        .with_target(events::EVENTS_TARGET, tracing::Level::INFO);
+
+    let events_stdout = config.events.output.writes_stdout().then(|| {
+        tracing_subscriber::fmt::layer()
+            .event_format(events::EventLineFormat)
+            .with_writer(std::io::stdout)
+    });
+    let events_file = if config.events.output.writes_file() {
+        let path = std::path::Path::new(&config.events.file_path);
+        let directory = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+        let file_name = path.file_name().context("events file_path must name a file")?;
+        std::fs::create_dir_all(directory)?;
+        Some(tracing_subscriber::fmt::layer()
+            .event_format(events::EventLineFormat)
+            .with_ansi(false)
+            .with_writer(tracing_appender::rolling::daily(directory, file_name)))
+    } else {
+        None
+    };
+
     tracing_subscriber::registry()
-        .with(filter)
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_filter(filter))
+        .with(events_stdout.with_filter(events_only()))
+        .with(events_file.with_filter(events_only()))
         .init();
 
     Ok(())
@@ -1367,32 +2161,6 @@ async fn setup_database(database_url: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn create_database_pool(db_config: &crate::config::DatabaseConfig) -> anyhow::Result<SqlitePool> {
-    let options = SqliteConnectOptions::new()
-        .filename(&db_config.url)
-        .create_if_missing(true);
-
-    let pool = SqlitePool::connect_with(options).await?;
-    Ok(pool)
-}
-
-fn setup_security_headers(config: &crate::config::SecurityConfig) -> DefaultHeaders {
-    let mut headers = DefaultHeaders::new()
-        .add(("X-Content-Type-Options", "nosniff"))
-        .add(("X-Frame-Options", "DENY"))
-        .add(("X-XSS-Protection", "1; mode=block"))
-        .add(("Referrer-Policy", "strict-origin-when-cross-origin"));
-
-    if config.require_https {
-        headers = headers.add((
-            "Strict-Transport-Security",
-            "max-age=31536000; includeSubDomains; preload"
-        ));
-    }
-
-    headers
-}
-
 async fn create_default_admin_if_needed(
     pool: &SqlitePool,
     auth_service: &AuthService,
@@ -0,0 +1,329 @@
+// src/maintenance_mode.rs
+//! Режим обслуживания "только чтение": на время миграций и резервного копирования API
+//! остаётся доступным, но не принимает изменений.
+//!
+//! `POST /admin/maintenance-mode` включает и выключает режим (сообщение для клиентов и список
+//! администраторов, которым запись остаётся разрешена). Состояние хранится строкой
+//! `maintenance_mode` в таблице `settings` (переживает перезапуск) и в кэше процесса
+//! (`MaintenanceModeStore`, app_data). Пока режим включён, `ReadOnlyGuard` отвечает 503
+//! `MAINTENANCE_MODE` на любые запросы, кроме GET/HEAD/OPTIONS, входа (`/auth/login`)
+//! и самого переключателя. Режим виден в `/health`, `/health/ready` и в support bundle.
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+use crate::auth::{get_current_user, Claims};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Ключ строки в таблице settings (не входит в settings::DEFINITIONS)
+pub const SETTING_KEY: &str = "maintenance_mode";
+/// Код ошибки в теле ответа 503
+pub const MAINTENANCE_MODE: &str = "MAINTENANCE_MODE";
+/// Переключатель режима - единственная запись, доступная при включённом режиме всем администраторам
+pub const TOGGLE_PATH: &str = "/admin/maintenance-mode";
+const LOGIN_PATH: &str = "/auth/login";
+
+const MAX_MESSAGE_LEN: usize = 500;
+const MAX_ALLOWED_USERS: usize = 20;
+const DEFAULT_MESSAGE: &str = "The system is in read-only maintenance mode; changes are temporarily disabled";
+
+// ==================== STATE ====================
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceMode {
+    pub enabled: bool,
+    pub message: Option<String>,
+    /// Администраторы, которым запись разрешена и при включённом режиме
+    #[serde(default)]
+    pub allowed_user_ids: Vec<String>,
+    #[serde(default, skip_deserializing)]
+    pub updated_by: Option<String>,
+    #[serde(default, skip_deserializing)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl MaintenanceMode {
+    /// Сообщение для клиентов (своё или стандартное)
+    pub fn message(&self) -> &str {
+        self.message.as_deref().unwrap_or(DEFAULT_MESSAGE)
+    }
+
+    fn allows_writes_for(&self, claims: Option<&Claims>) -> bool {
+        !self.enabled || claims.is_some_and(|c| self.allowed_user_ids.contains(&c.sub))
+    }
+}
+
+/// Кэш состояния в процессе; источник правды - таблица settings
+#[derive(Debug, Default)]
+pub struct MaintenanceModeStore {
+    state: RwLock<MaintenanceMode>,
+}
+
+impl MaintenanceModeStore {
+    /// Загрузить сохранённое состояние (при старте)
+    pub async fn load(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let mode = load_mode(pool).await?;
+        if mode.enabled {
+            log::warn!("Starting in read-only maintenance mode: {}", mode.message());
+        }
+        self.set(mode);
+        Ok(())
+    }
+
+    pub fn current(&self) -> MaintenanceMode {
+        self.state.read().map(|state| state.clone()).unwrap_or_default()
+    }
+
+    fn set(&self, mode: MaintenanceMode) {
+        if let Ok(mut state) = self.state.write() {
+            *state = mode;
+        }
+    }
+}
+
+/// Сохранённое состояние; строки нет или она повреждена - режим выключен
+pub async fn load_mode(pool: &SqlitePool) -> Result<MaintenanceMode, sqlx::Error> {
+    let row: Option<(Option<String>, Option<String>, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT value, updated_by, updated_at FROM settings WHERE key = ?"
+    )
+        .bind(SETTING_KEY)
+        .fetch_optional(pool)
+        .await?;
+    let Some((Some(raw), updated_by, updated_at)) = row else {
+        return Ok(MaintenanceMode::default());
+    };
+    match serde_json::from_str::<MaintenanceMode>(&raw) {
+        Ok(mode) => Ok(MaintenanceMode { updated_by, updated_at: Some(updated_at), ..mode }),
+        Err(e) => {
+            log::warn!("Ignoring stored maintenance mode: {}", e);
+            Ok(MaintenanceMode::default())
+        }
+    }
+}
+
+// ==================== HANDLERS ====================
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceModeRequest {
+    pub enabled: bool,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub allowed_user_ids: Vec<String>,
+}
+
+/// GET /admin/maintenance-mode
+pub async fn get_maintenance_mode(store: web::Data<MaintenanceModeStore>) -> ApiResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(ApiResponse::success(store.current())))
+}
+
+/// POST /admin/maintenance-mode - `{ "enabled": true, "message": "...", "allowed_user_ids": [...] }`;
+/// выключение сбрасывает сообщение и список
+pub async fn set_maintenance_mode(
+    app_state: web::Data<Arc<AppState>>,
+    store: web::Data<MaintenanceModeStore>,
+    body: web::Json<MaintenanceModeRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = get_current_user(&http_request)?.sub;
+    let pool = &app_state.db_pool;
+    let request = body.into_inner();
+
+    let message = request.message.map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+    if message.as_ref().is_some_and(|m| m.chars().count() > MAX_MESSAGE_LEN) {
+        return Err(ApiError::bad_request(&format!("Message cannot exceed {} characters", MAX_MESSAGE_LEN)));
+    }
+    let mut allowed_user_ids: Vec<String> = Vec::new();
+    for id in request.allowed_user_ids.iter().map(|id| id.trim()).filter(|id| !id.is_empty()) {
+        if !allowed_user_ids.iter().any(|existing| existing == id) {
+            allowed_user_ids.push(id.to_string());
+        }
+    }
+    if allowed_user_ids.len() > MAX_ALLOWED_USERS {
+        return Err(ApiError::bad_request(&format!("At most {} users can be allowed to write", MAX_ALLOWED_USERS)));
+    }
+    for id in &allowed_user_ids {
+        let admin: Option<bool> = sqlx::query_scalar("SELECT is_active FROM users WHERE id = ? AND role = 'admin'")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        if admin != Some(true) {
+            return Err(ApiError::bad_request(&format!("User {} is not an active admin", id)));
+        }
+    }
+
+    let previous = store.current();
+    let mode = if request.enabled {
+        MaintenanceMode { enabled: true, message, allowed_user_ids, ..Default::default() }
+    } else {
+        MaintenanceMode::default()
+    };
+    let now = Utc::now();
+    let value = serde_json::to_string(&MaintenanceMode { updated_by: None, updated_at: None, ..mode.clone() })
+        .map_err(|e| ApiError::internal_error(e.to_string()))?;
+    sqlx::query(
+        r#"INSERT INTO settings (key, value, default_value, updated_by, updated_at) VALUES (?, ?, ?, ?, ?)
+           ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_by = excluded.updated_by,
+                                          updated_at = excluded.updated_at"#
+    )
+        .bind(SETTING_KEY)
+        .bind(&value)
+        .bind(r#"{"enabled":false}"#)
+        .bind(&user_id)
+        .bind(now)
+        .execute(pool)
+        .await?;
+    let mode = MaintenanceMode { updated_by: Some(user_id.clone()), updated_at: Some(now), ..mode };
+    store.set(mode.clone());
+
+    let description = if mode.enabled {
+        log::warn!("Read-only maintenance mode enabled by {}: {}", user_id, mode.message());
+        format!("Maintenance mode enabled: {}", mode.message())
+    } else {
+        log::info!("Read-only maintenance mode disabled by {}", user_id);
+        "Maintenance mode disabled".to_string()
+    };
+    let mut cs = crate::audit::ChangeSet::new();
+    cs.add_bool("enabled", previous.enabled, mode.enabled);
+    cs.add_opt("message", &previous.message, &mode.message);
+    crate::audit::audit_with_changes(
+        pool, &user_id, "update_maintenance_mode", "system", SETTING_KEY, &description, &cs, &http_request,
+    ).await;
+
+    let message = if mode.enabled { "Maintenance mode enabled" } else { "Maintenance mode disabled" };
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(mode, message.to_string())))
+}
+
+// ==================== MIDDLEWARE ====================
+
+/// Запрос, не изменяющий данные, или исключение из режима
+fn is_exempt(method: &Method, path: &str) -> bool {
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return true;
+    }
+    let relative = path.strip_prefix(crate::access_control::API_PREFIX).unwrap_or(path);
+    *method == Method::POST && (path == LOGIN_PATH || relative == TOGGLE_PATH)
+}
+
+/// Middleware для scope /auth и /api/v1 (после аутентификации, чтобы знать пользователя);
+/// без MaintenanceModeStore в app_data пропускает всё
+pub struct ReadOnlyGuard;
+
+impl<S, B> Transform<S, ServiceRequest> for ReadOnlyGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = ReadOnlyGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ReadOnlyGuardMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct ReadOnlyGuardMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ReadOnlyGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if !is_exempt(req.method(), req.path()) {
+            if let Some(store) = req.app_data::<web::Data<MaintenanceModeStore>>() {
+                let mode = store.current();
+                if !mode.allows_writes_for(req.extensions().get::<Claims>()) {
+                    let error = ApiError::ServiceUnavailable { code: MAINTENANCE_MODE, message: mode.message().to_string() };
+                    return Box::pin(ready(Err(error.into())));
+                }
+            }
+        }
+        let fut = self.service.call(req);
+        Box::pin(fut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::{fixtures, TestApp};
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    #[test]
+    fn test_only_reads_login_and_toggle_are_exempt() {
+        assert!(is_exempt(&Method::GET, "/api/v1/reagents"));
+        assert!(is_exempt(&Method::OPTIONS, "/api/v1/reagents"));
+        assert!(is_exempt(&Method::POST, "/auth/login"));
+        assert!(is_exempt(&Method::POST, "/api/v1/admin/maintenance-mode"));
+        assert!(!is_exempt(&Method::POST, "/auth/register"));
+        assert!(!is_exempt(&Method::PUT, "/api/v1/admin/settings"));
+        assert!(!is_exempt(&Method::DELETE, "/api/v1/reagents/r1"));
+    }
+
+    #[actix_web::test]
+    async fn test_maintenance_mode_blocks_writes_and_survives_reload() {
+        let app = TestApp::new().await;
+
+        let (status, _) = app.post(UserRole::Researcher, TOGGLE_PATH, json!({ "enabled": true })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) = app.post(UserRole::Admin, TOGGLE_PATH, json!({
+            "enabled": true, "allowed_user_ids": [fixtures::RESEARCHER_ID],
+        })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+
+        let (status, body) = app.post(UserRole::Admin, TOGGLE_PATH, json!({
+            "enabled": true, "message": "Backup in progress", "allowed_user_ids": [fixtures::ADMIN_ID],
+        })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["updated_by"], fixtures::ADMIN_ID);
+
+        // Чтение работает, запись - 503 с сообщением; администратор из списка пишет
+        let (status, _) = app.get(UserRole::Viewer, "/reagents").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = app.post(UserRole::Researcher, "/reagents", json!({ "name": "Toluene" })).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+        assert_eq!(body["code"], MAINTENANCE_MODE);
+        assert!(body["message"].as_str().unwrap().contains("Backup in progress"));
+        let (status, body) = app.post(UserRole::Admin, "/reagents", json!({ "name": "Toluene" })).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+        // Состояние в БД: новый процесс загружает его при старте
+        let restarted = MaintenanceModeStore::default();
+        restarted.load(&app.pool).await.unwrap();
+        let mode = restarted.current();
+        assert!(mode.enabled);
+        assert_eq!(mode.message(), "Backup in progress");
+        assert_eq!(mode.updated_by.as_deref(), Some(fixtures::ADMIN_ID));
+
+        let (status, _) = app.post(UserRole::Admin, TOGGLE_PATH, json!({ "enabled": false })).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = app.post(UserRole::Researcher, "/reagents", json!({ "name": "Xylene" })).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(load_mode(&app.pool).await.unwrap().allowed_user_ids, Vec::<String>::new());
+    }
+}
@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use validator::Validate;
 use chrono::{DateTime, Utc};
 
+use crate::query_builders::sql::nullable;
+
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
 pub struct Batch {
     pub id: String,
@@ -27,6 +29,45 @@ pub struct Batch {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
+    /// Штрихкод/QR этикетки (уникальный)
+    #[sqlx(default)]
+    pub barcode: Option<String>,
+    /// Номер сертификата анализа (COA)
+    #[sqlx(default)]
+    pub coa_reference: Option<String>,
+    #[sqlx(default)]
+    pub coa_received_at: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub coa_received_by: Option<String>,
+    /// Узел иерархии мест хранения; `location` содержит его путь
+    #[sqlx(default)]
+    pub location_id: Option<String>,
+    /// Запечатанные тары (флаконы) по `pack_size`; см. container_state
+    #[sqlx(default)]
+    pub container_count: Option<i64>,
+}
+
+/// Допуск при сверке количества тар с остатком: |count × size − quantity| ≤ 2% от count × size
+pub const CONTAINER_FILL_TOLERANCE: f64 = 0.02;
+const CONTAINER_EPSILON: f64 = 1e-9;
+
+/// Состояние тар партии: запечатанные и вскрытые (вскрытые выводятся из остатка)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ContainerState {
+    pub sealed: i64,
+    pub open: i64,
+}
+
+/// Тары партии для остатка `quantity`. Запечатанных не больше, чем помещается целиком
+/// в остаток; остаток сверх них лежит во вскрытых тарах. None - учёт тар не ведётся.
+pub fn container_state(quantity: f64, container_size: Option<f64>, container_count: Option<i64>) -> Option<ContainerState> {
+    let size = container_size.filter(|s| *s > 0.0)?;
+    let count = container_count?;
+    let whole = ((quantity.max(0.0) + CONTAINER_EPSILON) / size).floor() as i64;
+    let sealed = count.clamp(0, whole.max(0));
+    let remainder = quantity - sealed as f64 * size;
+    let open = if remainder > CONTAINER_EPSILON { (remainder / size - CONTAINER_EPSILON).ceil() as i64 } else { 0 };
+    Some(ContainerState { sealed, open })
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -63,8 +104,13 @@ pub struct CreateBatchRequest {
     pub quantity: f64,
     #[validate(length(min = 1, max = 20, message = "Unit must be between 1 and 20 characters"))]
     pub unit: String,
+    /// Размер одной тары; в запросе также `container_size`
+    #[serde(alias = "container_size")]
     #[validate(range(min = 0.001, message = "Pack size must be positive"))]
     pub pack_size: Option<f64>,
+    /// Число запечатанных тар; count × pack_size должно совпадать с quantity
+    #[validate(range(min = 0, message = "Container count must be non-negative"))]
+    pub container_count: Option<i64>,
     pub expiry_date: Option<DateTime<Utc>>,
     #[validate(length(max = 255, message = "Supplier cannot exceed 255 characters"))]
     pub supplier: Option<String>,
@@ -72,6 +118,8 @@ pub struct CreateBatchRequest {
     pub manufacturer: Option<String>,
     #[validate(length(max = 255, message = "Location cannot exceed 255 characters"))]
     pub location: Option<String>,
+    /// Узел иерархии мест хранения; если задан, `location` заполняется его путём
+    pub location_id: Option<String>,
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
     pub notes: Option<String>,
     pub received_date: Option<DateTime<Utc>>,
@@ -80,26 +128,80 @@ pub struct CreateBatchRequest {
 #[derive(Debug, Deserialize, Validate)]
 pub struct UpdateBatchRequest {
     #[validate(length(max = 100, message = "Lot number cannot exceed 100 characters"))]
-    pub lot_number: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub lot_number: Option<Option<String>>,
     #[validate(length(min = 1, max = 100, message = "Batch number must be between 1 and 100 characters"))]
     pub batch_number: Option<String>,
     #[validate(length(max = 100, message = "Cat number cannot exceed 100 characters"))]
-    pub cat_number: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub cat_number: Option<Option<String>>,
     #[validate(range(min = 0.0, message = "Quantity must be non-negative"))]
     pub quantity: Option<f64>,
     #[validate(length(min = 1, max = 20, message = "Unit must be between 1 and 20 characters"))]
     pub unit: Option<String>,
+    #[serde(alias = "container_size")]
     #[validate(range(min = 0.001, message = "Pack size must be positive"))]
-    pub pack_size: Option<f64>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub pack_size: Option<Option<f64>>,
+    /// Пересчёт запечатанных тар (например, после инвентаризации)
+    #[validate(range(min = 0, message = "Container count must be non-negative"))]
+    pub container_count: Option<i64>,
     pub expiry_date: Option<DateTime<Utc>>,
     #[validate(length(max = 255, message = "Supplier name cannot exceed 255 characters"))]
-    pub supplier: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub supplier: Option<Option<String>>,
     #[validate(length(max = 255, message = "Manufacturer cannot exceed 255 characters"))]
-    pub manufacturer: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub manufacturer: Option<Option<String>>,
     #[validate(length(max = 255, message = "Location cannot exceed 255 characters"))]
-    pub location: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub location: Option<Option<String>>,
+    /// Перенос в узел иерархии мест хранения; null или пустая строка отвязывает партию от узла
+    #[serde(default, deserialize_with = "nullable")]
+    pub location_id: Option<Option<String>>,
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
-    pub notes: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub notes: Option<Option<String>>,
     pub received_date: Option<DateTime<Utc>>,
     pub status: Option<String>,
-}
\ No newline at end of file
+}
+
+/// Назначение штрихкода партии; `null` или пустая строка снимает код
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub struct SetBatchBarcodeRequest {
+    #[validate(length(max = 128, message = "Barcode cannot exceed 128 characters"))]
+    pub barcode: Option<String>,
+}
+
+/// Отметка о получении сертификата анализа (COA) партии
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub struct CoaReceivedRequest {
+    #[validate(length(min = 1, max = 100, message = "COA reference must be between 1 and 100 characters"))]
+    pub reference_number: String,
+}
+
+/// Продление срока годности партии после повторного контроля качества
+#[derive(Debug, Deserialize, Validate, Clone)]
+pub struct ExtendBatchExpiryRequest {
+    pub new_expiry_date: DateTime<Utc>,
+    #[validate(length(min = 1, max = 2000, message = "Justification must be between 1 and 2000 characters"))]
+    pub justification: String,
+    #[validate(length(max = 500, message = "Document reference cannot exceed 500 characters"))]
+    pub document_reference: Option<String>,
+}
+
+/// Запись истории продлений срока годности
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct BatchExpiryExtension {
+    pub id: String,
+    pub batch_id: String,
+    pub reagent_id: String,
+    pub old_expiry_date: DateTime<Utc>,
+    pub new_expiry_date: DateTime<Utc>,
+    pub justification: String,
+    pub document_reference: Option<String>,
+    pub approved_by: String,
+    #[sqlx(default)]
+    pub approved_by_username: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
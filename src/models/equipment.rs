@@ -1,6 +1,8 @@
 // src/models/equipment.rs
 use serde::{Deserialize, Serialize};
 use validator::Validate;
+
+use crate::query_builders::sql::nullable;
 use chrono::{DateTime, Utc};
 
 // ==================== EQUIPMENT (ОБОРУДОВАНИЕ) ====================
@@ -9,8 +11,9 @@ use chrono::{DateTime, Utc};
 pub struct Equipment {
     pub id: String,
     pub name: String,
+    /// В API - `type`; колонка и устаревшее имя поля - `type_`
     #[sqlx(rename = "type_")]
-    #[serde(rename = "type_")]
+    #[serde(rename = "type", alias = "type_")]
     pub type_: String,
     pub quantity: i32,
     pub unit: Option<String>,
@@ -23,6 +26,19 @@ pub struct Equipment {
     pub model: Option<String>,
     pub purchase_date: Option<String>,
     pub warranty_until: Option<String>,
+    /// Родительская сборка (например, HPLC система для насоса)
+    #[sqlx(default)]
+    pub parent_equipment_id: Option<String>,
+    /// Роли через запятую, которым разрешено брать оборудование в работу (None - всем)
+    #[sqlx(default)]
+    pub restricted_to_roles: Option<String>,
+    /// Команда-владелец: её участники изменяют объект наравне с автором
+    #[sqlx(default)]
+    pub team_id: Option<String>,
+    /// Название команды (заполняется expand_team_names)
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
     pub created_by: Option<String>,
     pub updated_by: Option<String>,
     pub created_at: DateTime<Utc>,
@@ -35,9 +51,14 @@ pub struct CreateEquipmentRequest {
     pub name: String,
 
     #[validate(length(min = 1, max = 50, message = "Type must be 'equipment' or 'labware'"))]
-    #[serde(rename = "type_")]
+    /// `equipment_type` - имя поля в ответах API v2; можно не указывать при `catalog_id`
+    #[serde(rename = "type", alias = "equipment_type", default)]
     pub type_: String,
 
+    /// Устаревшее написание `type_` - переносится в `type` через `take_legacy_type`
+    #[serde(rename = "type_", default)]
+    pub legacy_type: Option<String>,
+
     #[validate(range(min = 1, message = "Quantity must be at least 1"))]
     pub quantity: i32,
 
@@ -62,6 +83,19 @@ pub struct CreateEquipmentRequest {
 
     pub purchase_date: Option<String>,
     pub warranty_until: Option<String>,
+
+    pub parent_equipment_id: Option<String>,
+
+    /// Например "researcher,admin"; не задано - без ограничений
+    #[validate(custom(function = "validate_role_list"))]
+    pub restricted_to_roles: Option<String>,
+
+    /// Команда-владелец (teams.id)
+    pub team_id: Option<String>,
+
+    /// Модель из каталога (/equipment/catalog): незаданные тип, производитель, модель
+    /// и описание берутся из неё, интервал обслуживания и руководство - тоже
+    pub catalog_id: Option<String>,
 }
 
 /// Расширенный запрос на создание (с большим списком допустимых типов)
@@ -71,7 +105,7 @@ pub struct CreateEquipmentRequestExtended {
     pub name: String,
 
     #[validate(length(min = 1, max = 50, message = "Type must be 'equipment', 'labware', 'instrument', or 'consumable'"))]
-    #[serde(rename = "type_")]
+    #[serde(rename = "type", alias = "type_")]
     pub type_: String,
 
     #[validate(range(min = 1, message = "Quantity must be at least 1"))]
@@ -105,33 +139,133 @@ pub struct UpdateEquipmentRequest {
     pub name: Option<String>,
 
     #[validate(length(max = 20, message = "Unit cannot exceed 20 characters"))]
-    pub unit: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub unit: Option<Option<String>>,
 
     #[validate(length(max = 255, message = "Location cannot exceed 255 characters"))]
-    pub location: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub location: Option<Option<String>>,
 
     #[validate(length(max = 1000, message = "Description cannot exceed 1000 characters"))]
-    pub description: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
 
     pub status: Option<String>,
 
+    #[serde(rename = "type", alias = "equipment_type", default)]
+    pub type_: Option<String>,
+
+    /// Устаревшее написание `type_` - переносится в `type` через `take_legacy_type`
+    #[serde(rename = "type_", default)]
+    pub legacy_type: Option<String>,
+
     pub quantity: Option<i32>,
 
     #[validate(length(max = 100, message = "Serial number cannot exceed 100 characters"))]
-    pub serial_number: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub serial_number: Option<Option<String>>,
 
     #[validate(length(max = 255, message = "Manufacturer cannot exceed 255 characters"))]
-    pub manufacturer: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub manufacturer: Option<Option<String>>,
 
     #[validate(length(max = 255, message = "Model cannot exceed 255 characters"))]
-    pub model: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub model: Option<Option<String>>,
+
+    #[serde(default, deserialize_with = "nullable")]
+    pub purchase_date: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub warranty_until: Option<Option<String>>,
+
+    /// null или пустая строка отвязывает оборудование от родительской сборки
+    #[serde(default, deserialize_with = "nullable")]
+    pub parent_equipment_id: Option<Option<String>>,
+
+    /// null или пустая строка снимает ограничение по ролям
+    #[validate(custom(function = "validate_role_list"))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub restricted_to_roles: Option<Option<String>>,
+
+    /// null или пустая строка снимает команду-владельца
+    #[serde(default, deserialize_with = "nullable")]
+    pub team_id: Option<Option<String>>,
+}
 
-    pub purchase_date: Option<String>,
-    pub warranty_until: Option<String>,
+/// Разбор списка ролей "researcher, admin"; None - неизвестная роль. Пустой список - без ограничений
+pub fn parse_role_list(value: &str) -> Option<Vec<String>> {
+    let mut roles: Vec<String> = Vec::new();
+    for part in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let role = crate::auth::UserRole::from_str(part)?.as_str().to_string();
+        if !roles.contains(&role) {
+            roles.push(role);
+        }
+    }
+    Some(roles)
+}
+
+pub fn validate_role_list(value: &str) -> Result<(), validator::ValidationError> {
+    if parse_role_list(value).is_some() {
+        Ok(())
+    } else {
+        let mut error = validator::ValidationError::new("invalid_role_list");
+        error.message = Some("Roles must be a comma-separated list of: admin, researcher, viewer".into());
+        Err(error)
+    }
 }
 
 pub type UpdateEquipmentRequestExtended = UpdateEquipmentRequest;
 
+/// Устаревшее имя поля типа оборудования (совпадает с колонкой БД)
+pub const LEGACY_TYPE_FIELD: &str = "type_";
+
+/// Верхняя граница срока напоминания об обслуживании (CHECK столбца reminder_lead_days)
+pub const MAX_REMINDER_LEAD_DAYS: i64 = 365;
+
+/// Верхняя граница SLA обслуживания (CHECK столбца sla_days)
+pub const MAX_SLA_DAYS: i64 = 365;
+
+impl CreateEquipmentRequest {
+    /// Перенести `type_` в `type` (при обоих задан `type`); true - использовано старое имя
+    pub fn take_legacy_type(&mut self) -> bool {
+        match self.legacy_type.take() {
+            Some(legacy) => {
+                if self.type_.is_empty() {
+                    self.type_ = legacy;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Тип из запроса под любым из имён (до `take_legacy_type`)
+    pub fn requested_type(&self) -> &str {
+        match self.legacy_type.as_deref() {
+            Some(legacy) if self.type_.is_empty() => legacy,
+            _ => &self.type_,
+        }
+    }
+}
+
+impl UpdateEquipmentRequest {
+    /// Перенести `type_` в `type` (при обоих задан `type`); true - использовано старое имя
+    pub fn take_legacy_type(&mut self) -> bool {
+        match self.legacy_type.take() {
+            Some(legacy) => {
+                self.type_.get_or_insert(legacy);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Тип из запроса под любым из имён (до `take_legacy_type`)
+    pub fn requested_type(&self) -> Option<&str> {
+        self.type_.as_deref().or(self.legacy_type.as_deref())
+    }
+}
+
 // ==================== PARTS (ЗАПЧАСТИ) ====================
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -150,6 +284,34 @@ pub struct EquipmentPart {
     pub created_by: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Расходник, учитываемый как партия реагента: quantity читается из партии
+    #[sqlx(default)]
+    pub linked_batch_id: Option<String>,
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_batch: Option<PartBatchLink>,
+}
+
+/// Партия, с которой связана запчасть (единственный источник остатка)
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone, Default)]
+pub struct PartBatchLink {
+    pub batch_id: String,
+    pub reagent_id: String,
+    pub reagent_name: String,
+    pub batch_number: String,
+    pub quantity: f64,
+    pub unit: String,
+    pub status: String,
+}
+
+/// Запчасть, связанная с партией (для детальной карточки партии)
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct LinkedEquipmentPart {
+    pub id: String,
+    pub name: String,
+    pub part_number: Option<String>,
+    pub equipment_id: String,
+    pub equipment_name: String,
 }
 
 #[derive(Debug, Deserialize, Validate, Clone)]
@@ -174,6 +336,9 @@ pub struct CreateEquipmentPartRequest {
 
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
     pub notes: Option<String>,
+
+    /// Связать с партией реагента; несовместимо с quantity
+    pub linked_batch_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -182,10 +347,12 @@ pub struct UpdateEquipmentPartRequest {
     pub name: Option<String>,
 
     #[validate(length(max = 100, message = "Part number cannot exceed 100 characters"))]
-    pub part_number: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub part_number: Option<Option<String>>,
 
     #[validate(length(max = 255, message = "Manufacturer cannot exceed 255 characters"))]
-    pub manufacturer: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub manufacturer: Option<Option<String>>,
 
     pub quantity: Option<i32>,
     pub min_quantity: Option<i32>,
@@ -193,11 +360,18 @@ pub struct UpdateEquipmentPartRequest {
     #[validate(length(max = 50, message = "Status cannot exceed 50 characters"))]
     pub status: Option<String>,
 
-    pub last_replaced: Option<String>,
-    pub next_replacement: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub last_replaced: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub next_replacement: Option<Option<String>>,
 
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
-    pub notes: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub notes: Option<Option<String>>,
+
+    /// ID партии - связать, null или пустая строка - отвязать (остаток партии копируется в quantity)
+    #[serde(default, deserialize_with = "nullable")]
+    pub linked_batch_id: Option<Option<String>>,
 }
 
 // ==================== MAINTENANCE (ОБСЛУЖИВАНИЕ) ====================
@@ -210,6 +384,28 @@ pub struct EquipmentMaintenance {
     pub maintenance_type: String,
     pub status: String,
     pub scheduled_date: String,
+    /// Окно обслуживания, в течение которого оборудование недоступно
+    #[sqlx(default)]
+    pub scheduled_start: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub scheduled_end: Option<DateTime<Utc>>,
+    /// За сколько дней до scheduled_date напоминать; None - значение для типа из конфигурации
+    #[sqlx(default)]
+    pub reminder_lead_days: Option<i64>,
+    /// Сколько дней после scheduled_date допустимо не выполнять; None - значение для типа
+    #[sqlx(default)]
+    pub sla_days: Option<i64>,
+    /// Ответственный: первым получает эскалацию просроченного обслуживания
+    #[sqlx(default)]
+    pub assigned_to: Option<String>,
+    /// 0 - в срок, 1 - просрочено (уведомлён ответственный), 2 - уведомлены администраторы
+    #[sqlx(default)]
+    pub escalation_level: i64,
+    #[sqlx(default)]
+    pub escalated_at: Option<DateTime<Utc>>,
+    /// Статус с учётом просрочки: 'overdue' для эскалированной открытой записи, иначе status
+    #[sqlx(default)]
+    pub effective_status: Option<String>,
     pub completed_date: Option<String>,
     pub performed_by: Option<String>,
     pub description: Option<String>,
@@ -230,6 +426,27 @@ pub struct EquipmentMaintenanceWithEquipment {
     pub maintenance_type: String,
     pub status: String,
     pub scheduled_date: String,
+    #[sqlx(default)]
+    pub scheduled_start: Option<DateTime<Utc>>,
+    #[sqlx(default)]
+    pub scheduled_end: Option<DateTime<Utc>>,
+    /// За сколько дней до scheduled_date напоминать; None - значение для типа из конфигурации
+    #[sqlx(default)]
+    pub reminder_lead_days: Option<i64>,
+    /// Сколько дней после scheduled_date допустимо не выполнять; None - значение для типа
+    #[sqlx(default)]
+    pub sla_days: Option<i64>,
+    /// Ответственный: первым получает эскалацию просроченного обслуживания
+    #[sqlx(default)]
+    pub assigned_to: Option<String>,
+    /// 0 - в срок, 1 - просрочено (уведомлён ответственный), 2 - уведомлены администраторы
+    #[sqlx(default)]
+    pub escalation_level: i64,
+    #[sqlx(default)]
+    pub escalated_at: Option<DateTime<Utc>>,
+    /// Статус с учётом просрочки: 'overdue' для эскалированной открытой записи, иначе status
+    #[sqlx(default)]
+    pub effective_status: Option<String>,
     pub completed_date: Option<String>,
     pub performed_by: Option<String>,
     pub description: Option<String>,
@@ -253,9 +470,25 @@ pub struct CreateMaintenanceRequest {
     #[validate(length(max = 50, message = "Status cannot exceed 50 characters"))]
     pub status: Option<String>,
 
+    /// Можно не указывать, если задано окно - тогда берётся дата scheduled_start
+    #[serde(default)]
     pub scheduled_date: String,
+    /// Окно обслуживания; без него блокируется весь день scheduled_date
+    pub scheduled_start: Option<DateTime<Utc>>,
+    pub scheduled_end: Option<DateTime<Utc>>,
     pub completed_date: Option<String>,
 
+    /// Без значения берётся срок для типа обслуживания из конфигурации ([maintenance])
+    #[validate(range(min = 0, max = 365, message = "Reminder lead days must be between 0 and 365"))]
+    pub reminder_lead_days: Option<i64>,
+
+    /// Без значения берётся SLA для типа обслуживания из конфигурации ([maintenance])
+    #[validate(range(min = 0, max = 365, message = "SLA days must be between 0 and 365"))]
+    pub sla_days: Option<i64>,
+
+    /// id пользователя, отвечающего за обслуживание
+    pub assigned_to: Option<String>,
+
     #[validate(length(max = 255, message = "Performed by cannot exceed 255 characters"))]
     pub performed_by: Option<String>,
 
@@ -276,21 +509,46 @@ pub struct UpdateMaintenanceRequest {
     #[validate(length(max = 50, message = "Status cannot exceed 50 characters"))]
     pub status: Option<String>,
 
-    pub completed_date: Option<String>,
+    /// YYYY-MM-DD; перенос даты пересчитывает напоминания
+    pub scheduled_date: Option<String>,
+    pub scheduled_start: Option<DateTime<Utc>>,
+    pub scheduled_end: Option<DateTime<Utc>>,
+
+    /// null - вернуть срок по умолчанию для типа обслуживания
+    #[validate(range(min = 0, max = 365, message = "Reminder lead days must be between 0 and 365"))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub reminder_lead_days: Option<Option<i64>>,
+
+    /// null - вернуть SLA по умолчанию для типа обслуживания
+    #[validate(range(min = 0, max = 365, message = "SLA days must be between 0 and 365"))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub sla_days: Option<Option<i64>>,
+
+    /// null - снять ответственного
+    #[serde(default, deserialize_with = "nullable")]
+    pub assigned_to: Option<Option<String>>,
+
+    #[serde(default, deserialize_with = "nullable")]
+    pub completed_date: Option<Option<String>>,
 
     #[validate(length(max = 255, message = "Performed by cannot exceed 255 characters"))]
-    pub performed_by: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub performed_by: Option<Option<String>>,
 
     #[validate(length(max = 2000, message = "Description cannot exceed 2000 characters"))]
-    pub description: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
 
-    pub cost: Option<f64>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub cost: Option<Option<f64>>,
 
     #[validate(length(max = 1000, message = "Parts replaced cannot exceed 1000 characters"))]
-    pub parts_replaced: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub parts_replaced: Option<Option<String>>,
 
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
-    pub notes: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub notes: Option<Option<String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -298,12 +556,108 @@ pub struct CompleteMaintenanceRequest {
     pub completed_date: Option<String>,
     pub performed_by: Option<String>,
     pub notes: Option<String>,
+    /// Израсходованные при обслуживании запчасти/расходники
+    #[serde(default)]
+    pub consumed_parts: Vec<ConsumedPartRequest>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ConsumedPartRequest {
+    pub part_id: String,
+    pub quantity: i32,
+}
+
+/// Итог списания одной запчасти: из партии (batch_id/usage_id) или из собственного остатка
+#[derive(Debug, Serialize, Clone)]
+pub struct ConsumedPartResult {
+    pub part_id: String,
+    pub part_name: String,
+    pub quantity: i32,
+    pub batch_id: Option<String>,
+    pub usage_id: Option<String>,
+    pub remaining_quantity: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceCompletionResponse {
+    #[serde(flatten)]
+    pub maintenance: EquipmentMaintenance,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub consumed_parts: Vec<ConsumedPartResult>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpcomingMaintenanceQuery {
     pub days: Option<i32>,
+    /// Вместо окна `days` - у каждой записи свой срок напоминания (reminder_lead_days)
+    #[serde(default)]
+    pub use_lead_times: bool,
     pub limit: Option<i32>,
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    /// Помещение: оборудование, у которого location совпадает с названием комнаты
+    pub room_id: Option<String>,
+    pub location: Option<String>,
+}
+
+/// Предстоящее окно обслуживания с временем до начала
+#[derive(Debug, Serialize)]
+pub struct UpcomingMaintenance {
+    #[serde(flatten)]
+    pub maintenance: EquipmentMaintenanceWithEquipment,
+    /// Секунд до начала окна (0, если окно уже идёт)
+    pub seconds_until_start: i64,
+    pub is_active: bool,
+    /// Срок напоминания с учётом значения по умолчанию для типа
+    pub effective_reminder_lead_days: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OverdueMaintenanceQuery {
+    /// Только записи на этой ступени эскалации (1 или 2)
+    pub escalation_level: Option<i64>,
+    pub limit: Option<i32>,
+}
+
+/// Обслуживание, просроченное по SLA, с текущим состоянием оборудования
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct OverdueMaintenance {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub maintenance: EquipmentMaintenanceWithEquipment,
+    /// Текущий статус оборудования (EquipmentStatus)
+    pub equipment_status: String,
+    /// SLA с учётом значения по умолчанию для типа
+    pub effective_sla_days: i64,
+    /// Дней сверх SLA на сегодня
+    pub days_overdue: i64,
+    pub assigned_to_username: Option<String>,
+}
+
+/// Сводка по парку оборудования для дашборда
+#[derive(Debug, Serialize)]
+pub struct EquipmentFleetSummary {
+    /// Количество по каждому статусу EquipmentStatus (включая нулевые)
+    pub by_status: std::collections::BTreeMap<String, i64>,
+    /// Просрочено плановое обслуживание (next_maintenance или запись в журнале)
+    pub maintenance_overdue: i64,
+    /// Калибровка запланирована в ближайшие 30 дней или уже просрочена
+    pub calibration_due: i64,
+    /// Гарантия истекает в ближайшие 30 дней
+    pub warranty_expiring: i64,
+    /// Записи обслуживания, просроченные по SLA (effective_status = 'overdue')
+    pub maintenance_sla_overdue: i64,
+    pub recently_broken: Vec<BrokenEquipment>,
+}
+
+/// Повреждённое оборудование; updated_at - время последнего изменения записи
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct BrokenEquipment {
+    pub id: String,
+    pub name: String,
+    pub location: Option<String>,
+    pub serial_number: Option<String>,
+    pub updated_at: DateTime<Utc>,
 }
 
 // ==================== FILES (ФАЙЛЫ) ====================
@@ -322,6 +676,8 @@ pub struct EquipmentFile {
     pub description: Option<String>,
     pub uploaded_by: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// SHA-256 содержимого (file_blobs); None - загружен до дедупликации
+    pub content_hash: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -343,4 +699,66 @@ pub struct EquipmentDetailResponse {
     pub parts: Vec<EquipmentPart>,
     pub recent_maintenance: Vec<EquipmentMaintenance>,
     pub files: Vec<EquipmentFile>,
+    /// Вложенные списки (`parts`, `files`), обрезанные до MAX_NESTED_LIST_ROWS
+    pub truncated: Vec<&'static str>,
+    pub components: Vec<Equipment>,
+    /// Сводка обслуживания по всей сборке (только если есть компоненты)
+    pub assembly_maintenance: Option<AssemblyMaintenanceSummary>,
+    /// Идущее сейчас окно обслуживания - оборудование недоступно
+    pub active_maintenance: Option<EquipmentMaintenance>,
+    pub links: Vec<super::ExternalLink>,
+}
+
+/// Компонент сборки с глубиной вложенности относительно корня
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct EquipmentComponent {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub equipment: Equipment,
+    pub depth: i64,
+}
+
+/// Агрегированные данные обслуживания: сама сборка + все её компоненты
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AssemblyMaintenanceSummary {
+    pub components_count: i64,
+    pub next_maintenance_date: Option<String>,
+    pub scheduled_count: i64,
+    pub overdue_count: i64,
+    pub total_maintenance_cost: f64,
+}
+
+// ==================== PART REORDER SUGGESTIONS ====================
+
+#[derive(Debug, Deserialize)]
+pub struct PartReorderQuery {
+    /// json (по умолчанию) или csv
+    pub format: Option<String>,
+}
+
+/// Запчасть одного прибора с остатком ниже min_quantity
+#[derive(Debug, Serialize, Clone)]
+pub struct PartReorderUsage {
+    pub part_id: String,
+    pub equipment_id: String,
+    pub equipment_name: String,
+    pub quantity: i64,
+    pub min_quantity: i64,
+    pub deficit: i64,
+    /// Открытое обслуживание типа replacement, в parts_replaced которого упомянута запчасть
+    pub replacement_maintenance_id: Option<String>,
+}
+
+/// Строка заказа запчастей: одинаковые part_number с разных приборов объединены
+#[derive(Debug, Serialize)]
+pub struct PartReorderSuggestion {
+    pub part_number: Option<String>,
+    pub name: String,
+    pub manufacturer: Option<String>,
+    /// Сумма дефицитов по всем приборам
+    pub total_deficit: i64,
+    /// Дефицит без запчастей, замена которых уже запланирована обслуживанием
+    pub deficit_to_order: i64,
+    pub replacement_scheduled: bool,
+    pub equipment: Vec<PartReorderUsage>,
 }
\ No newline at end of file
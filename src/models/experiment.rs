@@ -60,6 +60,77 @@ impl std::fmt::Display for ExperimentType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticipantRole {
+    Student,
+    Instructor,
+    Assistant,
+}
+
+impl ParticipantRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParticipantRole::Student => "student",
+            ParticipantRole::Instructor => "instructor",
+            ParticipantRole::Assistant => "assistant",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "student" => Some(ParticipantRole::Student),
+            "instructor" => Some(ParticipantRole::Instructor),
+            "assistant" => Some(ParticipantRole::Assistant),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ParticipantRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Итог завершённого эксперимента
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExperimentOutcome {
+    Successful,
+    Partial,
+    Failed,
+    Inconclusive,
+}
+
+impl ExperimentOutcome {
+    pub const ALL: [ExperimentOutcome; 4] = [
+        ExperimentOutcome::Successful,
+        ExperimentOutcome::Partial,
+        ExperimentOutcome::Failed,
+        ExperimentOutcome::Inconclusive,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExperimentOutcome::Successful => "successful",
+            ExperimentOutcome::Partial => "partial",
+            ExperimentOutcome::Failed => "failed",
+            ExperimentOutcome::Inconclusive => "inconclusive",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|o| s.eq_ignore_ascii_case(o.as_str()))
+    }
+}
+
+impl std::fmt::Display for ExperimentOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 // === EXPERIMENT ===
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -80,6 +151,32 @@ pub struct Experiment {
     pub end_date: Option<DateTime<Utc>>, 
     pub results: Option<String>, 
     pub notes: Option<String>, 
+    /// successful | partial | failed | inconclusive, задаётся при завершении
+    #[sqlx(default)]
+    pub outcome: Option<String>,
+    /// Учётная запись инструктора (вместе с автором может изменять эксперимент)
+    #[sqlx(default)]
+    pub instructor_user_id: Option<String>,
+    /// Эксперимент, копией которого создан этот (POST /experiments/{id}/clone)
+    #[sqlx(default)]
+    pub cloned_from: Option<String>,
+    /// Команда-владелец: её участники изменяют объект наравне с автором
+    #[sqlx(default)]
+    pub team_id: Option<String>,
+    /// Название команды (заполняется expand_team_names)
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
+    /// Число реагентов, оборудования и документов (только в списке, без `?include_counts=false`)
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reagent_count: Option<i64>,
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equipment_count: Option<i64>,
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_count: Option<i64>,
     pub created_by: String,
     pub updated_by: Option<String>,
     pub created_at: DateTime<Utc>,
@@ -187,6 +284,33 @@ pub struct ExperimentWithDetails {
     pub equipment: Vec<ExperimentEquipmentDetail>,
 }
 
+/// Участник эксперимента: пользователь системы или внешний (имя + email)
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct ExperimentParticipant {
+    pub id: String,
+    pub experiment_id: String,
+    pub user_id: Option<String>,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub role: String,
+    pub signed_in_at: Option<DateTime<Utc>>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[sqlx(default)]
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct ExperimentComment {
+    pub id: String,
+    pub experiment_id: String,
+    pub user_id: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    #[sqlx(default)]
+    pub username: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ExperimentStats {
     pub total: i64,
@@ -211,6 +335,7 @@ pub struct CreateExperimentRequest {
     pub experiment_type: Option<String>,
     #[validate(length(max = 255, message = "Instructor name cannot exceed 255 characters"))]
     pub instructor: Option<String>,
+    pub instructor_user_id: Option<String>,
     #[validate(length(max = 100, message = "Student group cannot exceed 100 characters"))]
     pub student_group: Option<String>,
     #[validate(length(max = 255, message = "Location cannot exceed 255 characters"))]
@@ -222,33 +347,50 @@ pub struct CreateExperimentRequest {
     pub end_date: Option<DateTime<Utc>>,
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
     pub notes: Option<String>,
+    /// Начальный статус: `draft` или `planned` (по умолчанию)
+    pub status: Option<String>,
+    /// Команда-владелец (teams.id)
+    pub team_id: Option<String>,
 }
 
+/// Статусы, в которых можно создать эксперимент
+pub const CREATE_EXPERIMENT_STATUSES: &[&str] = &["draft", "planned"];
+
 impl CreateExperimentRequest {
-    pub fn validate_educational(&self) -> Result<(), String> {
-        let exp_type = self.experiment_type
-            .as_ref()
-            .and_then(|t| ExperimentType::from_str(t))
-            .unwrap_or_default();
-
-        if exp_type == ExperimentType::Educational {
-            let start = self.start_date
-                .ok_or("Educational experiments require start_date")?;
-            let end = self.end_date
-                .ok_or("Educational experiments require end_date")?;
-            if end <= start {
-                return Err("End time must be after start time".to_string());
-            }
-            let duration = end - start;
-            if duration.num_minutes() < 15 {
-                return Err("Educational experiment must be at least 15 minutes".to_string());
-            }
-            if duration.num_hours() > 8 {
-                return Err("Educational experiment cannot exceed 8 hours".to_string());
-            }
+    /// Черновик: без проверки учебного расписания, помещения и резерва реагентов до публикации
+    pub fn is_draft(&self) -> bool {
+        self.status.as_deref() == Some("draft")
+    }
+}
+
+/// Временные рамки учебного эксперимента; ошибка - (поле, сообщение).
+/// Общая для создания, обновления и POST /validate/experiment
+pub fn educational_schedule(
+    experiment_type: Option<&str>,
+    start_date: Option<DateTime<Utc>>,
+    end_date: Option<DateTime<Utc>>,
+) -> Result<(), (&'static str, &'static str)> {
+    let exp_type = experiment_type
+        .and_then(ExperimentType::from_str)
+        .unwrap_or_default();
+
+    if exp_type == ExperimentType::Educational {
+        let start = start_date
+            .ok_or(("start_date", "Educational experiments require start_date"))?;
+        let end = end_date
+            .ok_or(("end_date", "Educational experiments require end_date"))?;
+        if end <= start {
+            return Err(("end_date", "End time must be after start time"));
+        }
+        let duration = end - start;
+        if duration.num_minutes() < 15 {
+            return Err(("end_date", "Educational experiment must be at least 15 minutes"));
+        }
+        if duration.num_hours() > 8 {
+            return Err(("end_date", "Educational experiment cannot exceed 8 hours"));
         }
-        Ok(())
     }
+    Ok(())
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -262,6 +404,7 @@ pub struct UpdateExperimentRequest {
     pub experiment_type: Option<String>,
     #[validate(length(max = 255, message = "Instructor name cannot exceed 255 characters"))]
     pub instructor: Option<String>,
+    pub instructor_user_id: Option<String>,
     #[validate(length(max = 100, message = "Student group cannot exceed 100 characters"))]
     pub student_group: Option<String>,
     #[validate(length(max = 255, message = "Location cannot exceed 255 characters"))]
@@ -276,6 +419,52 @@ pub struct UpdateExperimentRequest {
     pub results: Option<String>,
     #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
     pub notes: Option<String>,
+    /// Принимается только вместе с переводом в status = completed
+    #[validate(custom(function = "validate_experiment_outcome"))]
+    pub outcome: Option<String>,
+    /// Пустая строка снимает команду-владельца
+    pub team_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AddExperimentCommentRequest {
+    #[validate(length(min = 1, max = 2000, message = "Comment must be between 1 and 2000 characters"))]
+    pub body: String,
+}
+
+/// Тело POST /experiments/{id}/complete (необязательное)
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct CompleteExperimentRequest {
+    #[validate(custom(function = "validate_experiment_outcome"))]
+    pub outcome: Option<String>,
+    /// Пометки к списанию по id строк experiment_reagents, которые списываются при завершении
+    #[serde(default)]
+    #[validate(custom(function = "validate_consumption_notes"))]
+    pub consumption_notes: std::collections::HashMap<String, String>,
+}
+
+/// Тело POST /experiments/{id}/reagents/{reagent_id}/consume (необязательное)
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct ConsumeExperimentReagentRequest {
+    #[validate(length(max = 1000, message = "Consumption notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+/// Списание реагента в эксперименте с реквизитами партии на момент списания
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ExperimentConsumption {
+    pub id: String,
+    pub experiment_id: String,
+    pub batch_id: String,
+    pub reagent_name: String,
+    pub quantity: f64,
+    pub unit: String,
+    pub consumed_at: Option<DateTime<Utc>>,
+    pub consumed_by: Option<String>,
+    pub consumption_notes: Option<String>,
+    pub lot_batch_number: Option<String>,
+    pub lot_supplier: Option<String>,
+    pub lot_expiry_date: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -297,8 +486,117 @@ pub struct AddExperimentEquipmentRequest {
     pub notes: Option<String>,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct AddParticipantRequest {
+    pub user_id: Option<String>,
+    #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters"))]
+    pub name: Option<String>,
+    #[validate(email(message = "Invalid email format"))]
+    pub email: Option<String>,
+    #[validate(custom(function = "validate_participant_role"))]
+    pub role: Option<String>,
+}
+
+impl AddParticipantRequest {
+    pub fn validate_identity(&self) -> Result<(), String> {
+        let has_user = self.user_id.as_deref().map(|u| !u.trim().is_empty()).unwrap_or(false);
+        let has_name = self.name.as_deref().map(|n| !n.trim().is_empty()).unwrap_or(false);
+        if !has_user && !has_name {
+            return Err("Participant requires either user_id or name".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct SignInRequest {
+    /// Не указан — отмечается текущий пользователь
+    pub participant_id: Option<String>,
+}
+
+/// Допуск по технике безопасности перед запуском эксперимента с опасными реагентами
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct ExperimentSignoff {
+    pub id: String,
+    pub experiment_id: String,
+    pub signed_by: String,
+    pub signed_at: DateTime<Utc>,
+    /// JSON-объект чек-листа ({"ppe_checked": true, ...})
+    pub checklist: String,
+    pub notes: Option<String>,
+    #[sqlx(default)]
+    pub signed_by_username: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct SignoffRequest {
+    #[validate(custom(function = "validate_signoff_checklist"))]
+    pub checklist: serde_json::Value,
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+}
+
+/// Максимальный размер сериализованного чек-листа
+pub const MAX_SIGNOFF_CHECKLIST_LEN: usize = 10_000;
+
+/// Связанный эксперимент: предшественник (dependencies) или зависящий (dependents)
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ExperimentDependency {
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub start_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AddDependencyRequest {
+    /// Эксперимент, который должен быть завершён до старта текущего
+    #[validate(length(min = 1, message = "depends_on_id is required"))]
+    pub depends_on_id: String,
+}
+
+/// Необязательные изменения копии (POST /experiments/{id}/clone); остальное берётся из источника
+#[derive(Debug, Deserialize, Validate, Default)]
+pub struct CloneExperimentRequest {
+    /// По умолчанию - название источника с суффиксом " (copy)"
+    #[validate(length(min = 1, max = 255, message = "Title must be between 1 and 255 characters"))]
+    pub title: Option<String>,
+    /// Без end_date длительность источника сохраняется
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+    pub room_id: Option<String>,
+}
+
 // === VALIDATORS ===
 
+fn validate_participant_role(value: &str) -> Result<(), validator::ValidationError> {
+    if ParticipantRole::from_str(value).is_some() {
+        Ok(())
+    } else {
+        let mut error = validator::ValidationError::new("invalid_participant_role");
+        error.message = Some("Role must be 'student', 'instructor' or 'assistant'".into());
+        Err(error)
+    }
+}
+
+/// Чек-лист - непустой JSON-объект; пункт со значением false считается неподтверждённым
+fn validate_signoff_checklist(value: &serde_json::Value) -> Result<(), validator::ValidationError> {
+    let message = match value.as_object() {
+        None => "Checklist must be a JSON object".to_string(),
+        Some(items) if items.is_empty() => "Checklist must contain at least one item".to_string(),
+        Some(_) if value.to_string().len() > MAX_SIGNOFF_CHECKLIST_LEN => {
+            format!("Checklist cannot exceed {} characters", MAX_SIGNOFF_CHECKLIST_LEN)
+        }
+        Some(items) => match items.iter().find(|(_, v)| v.as_bool() == Some(false)) {
+            Some((key, _)) => format!("Checklist item '{}' is not confirmed", key),
+            None => return Ok(()),
+        },
+    };
+    let mut error = validator::ValidationError::new("invalid_signoff_checklist");
+    error.message = Some(message.into());
+    Err(error)
+}
+
 fn validate_experiment_type(value: &str) -> Result<(), validator::ValidationError> {
     if ExperimentType::from_str(value).is_some() {
         Ok(())
@@ -319,11 +617,32 @@ fn validate_experiment_type_option(value: &str) -> Result<(), validator::Validat
     }
 }
 
+fn validate_experiment_outcome(value: &str) -> Result<(), validator::ValidationError> {
+    if ExperimentOutcome::from_str(value).is_some() {
+        Ok(())
+    } else {
+        let mut error = validator::ValidationError::new("invalid_experiment_outcome");
+        error.message = Some("Outcome must be one of: successful, partial, failed, inconclusive".into());
+        Err(error)
+    }
+}
+
+fn validate_consumption_notes(notes: &std::collections::HashMap<String, String>) -> Result<(), validator::ValidationError> {
+    if notes.values().all(|note| note.chars().count() <= 1000) {
+        Ok(())
+    } else {
+        let mut error = validator::ValidationError::new("consumption_note_too_long");
+        error.message = Some("Consumption notes cannot exceed 1000 characters".into());
+        Err(error)
+    }
+}
+
 // === TESTS ===
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::validator::CustomValidate;
 
     #[test]
     fn test_experiment_type_from_str() {
@@ -333,6 +652,30 @@ mod tests {
         assert_eq!(ExperimentType::from_str("учебный"), Some(ExperimentType::Educational));
         assert_eq!(ExperimentType::from_str("invalid"), None);
     }
+    #[test]
+    fn test_participant_request_validation() {
+        let request = AddParticipantRequest {
+            user_id: None,
+            name: Some("Ivan Petrov".to_string()),
+            email: Some("ivan@example.com".to_string()),
+            role: Some("student".to_string()),
+        };
+        assert!(request.validate().is_ok());
+        assert!(request.validate_identity().is_ok());
+
+        let anonymous = AddParticipantRequest { user_id: None, name: None, email: None, role: None };
+        assert!(anonymous.validate_identity().is_err());
+
+        let bad_role = AddParticipantRequest {
+            user_id: Some("u1".to_string()),
+            name: None,
+            email: None,
+            role: Some("visitor".to_string()),
+        };
+        assert!(bad_role.validate().is_err());
+        assert_eq!(ParticipantRole::from_str("Instructor"), Some(ParticipantRole::Instructor));
+    }
+
     #[test]
     fn test_experiment_type_requires_time_bounds() {
         assert!(ExperimentType::Educational.requires_time_bounds());
@@ -355,15 +698,19 @@ mod tests {
             experiment_date: Some(Utc::now()),
             experiment_type: Some("educational".to_string()),
             instructor: Some("Dr. Smith".to_string()),
+            instructor_user_id: None,
             student_group: Some("Group 101".to_string()),
             location: Some("Lab 101".to_string()),
+            room_id: None,
             protocol: None,
             start_date: Some(Utc::now()),
             end_date: Some(Utc::now() + chrono::Duration::hours(2)),
             notes: None,
+            status: None,
+            team_id: None,
         };
 
-        assert!(request.validate_educational().is_ok());
+        assert!(request.custom_validate().is_valid());
     }
 
     #[test]
@@ -374,15 +721,38 @@ mod tests {
             experiment_date: Some(Utc::now()),
             experiment_type: Some("educational".to_string()),
             instructor: None,
+            instructor_user_id: None,
             student_group: None,
             location: None,
+            room_id: None,
             protocol: None,
             start_date: Some(Utc::now()),
             end_date: None, // Missing!
             notes: None,
+            status: None,
+            team_id: None,
         };
 
-        assert!(request.validate_educational().is_err());
+        let result = request.custom_validate();
+        assert_eq!(result.errors["end_date"], vec!["Educational experiments require end_date"]);
+
+        // Черновик проверяется только при публикации
+        let draft = CreateExperimentRequest { status: Some("draft".to_string()), ..request };
+        assert!(draft.custom_validate().is_valid());
+        let invalid = CreateExperimentRequest { status: Some("completed".to_string()), ..draft };
+        assert!(invalid.custom_validate().errors.contains_key("status"));
     }
 
-}
\ No newline at end of file
+
+    #[test]
+    fn test_signoff_checklist_validation() {
+        let request = |checklist: serde_json::Value| SignoffRequest { checklist, notes: None };
+
+        assert!(request(serde_json::json!({ "ppe_checked": true, "fume_hood": "cert. 2024-05" })).validate().is_ok());
+        assert!(request(serde_json::json!({})).validate().is_err());
+        assert!(request(serde_json::json!(["ppe_checked"])).validate().is_err());
+
+        let err = request(serde_json::json!({ "ppe_checked": true, "spill_kit": false })).validate().unwrap_err();
+        assert!(err.to_string().contains("spill_kit"));
+    }
+}
@@ -0,0 +1,113 @@
+// src/models/external_link.rs
+//! Внешние ссылки (SOP в Confluence, страницы поставщиков и т.п.),
+//! привязанные к реагентам, партиям, оборудованию и экспериментам.
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use chrono::{DateTime, Utc};
+
+/// Максимальная длина URL ссылки
+pub const MAX_LINK_URL_LENGTH: usize = 2048;
+
+/// Тип сущности, к которой привязана ссылка
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkEntityType {
+    Reagent,
+    Batch,
+    Equipment,
+    Experiment,
+}
+
+impl LinkEntityType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkEntityType::Reagent => "reagent",
+            LinkEntityType::Batch => "batch",
+            LinkEntityType::Equipment => "equipment",
+            LinkEntityType::Experiment => "experiment",
+        }
+    }
+
+    /// Таблица родительской сущности
+    pub fn table(&self) -> &'static str {
+        match self {
+            LinkEntityType::Reagent => "reagents",
+            LinkEntityType::Batch => "batches",
+            LinkEntityType::Equipment => "equipment",
+            LinkEntityType::Experiment => "experiments",
+        }
+    }
+
+    /// Поддерживает ли таблица мягкое удаление (deleted_at)
+    pub fn soft_deletable(&self) -> bool {
+        matches!(self, LinkEntityType::Reagent | LinkEntityType::Batch)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct ExternalLink {
+    pub id: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub title: String,
+    pub url: String,
+    pub created_by: Option<String>,
+    #[sqlx(default)]
+    pub created_by_username: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateExternalLinkRequest {
+    #[validate(length(min = 1, max = 255, message = "Title must be between 1 and 255 characters"))]
+    pub title: String,
+    #[validate(custom(function = "validate_link_url"))]
+    pub url: String,
+}
+
+/// Допускаются только абсолютные http/https URL с хостом
+pub fn validate_link_url(url: &str) -> Result<(), validator::ValidationError> {
+    let error = |code: &'static str, message: &'static str| {
+        let mut error = validator::ValidationError::new(code);
+        error.message = Some(message.into());
+        error
+    };
+
+    if url.len() > MAX_LINK_URL_LENGTH {
+        return Err(error("url_too_long", "URL cannot exceed 2048 characters"));
+    }
+    if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(error("invalid_url", "URL must not contain whitespace"));
+    }
+
+    let lower = url.to_ascii_lowercase();
+    let rest = lower.strip_prefix("https://")
+        .or_else(|| lower.strip_prefix("http://"))
+        .ok_or_else(|| error("invalid_url_scheme", "Only http and https URLs are allowed"))?;
+
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    if host.is_empty() || host.starts_with(':') {
+        return Err(error("invalid_url", "URL must include a host"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_link_url() {
+        assert!(validate_link_url("https://confluence.example.com/display/SOP/42").is_ok());
+        assert!(validate_link_url("HTTP://vendor.example.com").is_ok());
+        assert!(validate_link_url("javascript:alert(1)").is_err());
+        assert!(validate_link_url("ftp://files.example.com/sop.pdf").is_err());
+        assert!(validate_link_url("https://").is_err());
+        assert!(validate_link_url("https:///path").is_err());
+        assert!(validate_link_url("https://example.com/a b").is_err());
+        let long = format!("https://example.com/{}", "a".repeat(MAX_LINK_URL_LENGTH));
+        assert!(validate_link_url(&long).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+// src/models/location.rs
+//! Иерархия мест хранения: здание > помещение > шкаф > полка > коробка.
+//! Вложенный узел всегда "глубже" родителя, поэтому циклов и дерева глубже 5 уровней не бывает.
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct Location {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub location_type: String,
+    /// Помещение из справочника rooms, к которому относится узел
+    pub room_id: Option<String>,
+    pub description: Option<String>,
+    pub created_by: Option<String>,
+    pub updated_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationType {
+    Building,
+    Room,
+    Cabinet,
+    Shelf,
+    Box,
+}
+
+impl LocationType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LocationType::Building => "building",
+            LocationType::Room => "room",
+            LocationType::Cabinet => "cabinet",
+            LocationType::Shelf => "shelf",
+            LocationType::Box => "box",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "building" => Some(LocationType::Building),
+            "room" => Some(LocationType::Room),
+            "cabinet" => Some(LocationType::Cabinet),
+            "shelf" => Some(LocationType::Shelf),
+            "box" => Some(LocationType::Box),
+            _ => None,
+        }
+    }
+
+    /// Уровень вложенности: дочерний узел должен иметь больший уровень, чем родитель
+    pub fn rank(&self) -> u8 {
+        match self {
+            LocationType::Building => 0,
+            LocationType::Room => 1,
+            LocationType::Cabinet => 2,
+            LocationType::Shelf => 3,
+            LocationType::Box => 4,
+        }
+    }
+
+    pub fn can_contain(&self, child: LocationType) -> bool {
+        child.rank() > self.rank()
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateLocationRequest {
+    #[validate(length(min = 1, max = 100, message = "Location name must be between 1 and 100 characters"))]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub location_type: LocationType,
+    pub parent_id: Option<String>,
+    pub room_id: Option<String>,
+    #[validate(length(max = 500, message = "Description cannot exceed 500 characters"))]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateLocationRequest {
+    #[validate(length(min = 1, max = 100, message = "Location name must be between 1 and 100 characters"))]
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub location_type: Option<LocationType>,
+    pub room_id: Option<String>,
+    #[validate(length(max = 500, message = "Description cannot exceed 500 characters"))]
+    pub description: Option<String>,
+}
+
+/// Перенос узла вместе со всем содержимым; `parent_id: null` - на верхний уровень
+#[derive(Debug, Deserialize)]
+pub struct MoveLocationRequest {
+    pub parent_id: Option<String>,
+}
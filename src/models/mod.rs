@@ -7,8 +7,12 @@ pub mod batch;
 pub mod batch_placement;
 pub mod equipment;
 pub mod experiment;
+pub mod external_link;
+pub mod location;
+pub mod purchase_order;
 pub mod reagent;
 pub mod room;
+pub mod team;
 pub mod user;
 
 // 2. Ре-экспортируем содержимое (Re-export), чтобы структуры были доступны как crate::models::StructName
@@ -16,8 +20,12 @@ pub use batch::*;
 pub use batch_placement::*;
 pub use equipment::*;
 pub use experiment::*;
+pub use external_link::*;
+pub use location::*;
+pub use purchase_order::*;
 pub use reagent::*;
 pub use room::*;
+pub use team::*;
 pub use user::*;
 
 use serde::{Deserialize, Serialize};
@@ -0,0 +1,186 @@
+// src/models/purchase_order.rs
+//! Заказы поставщику: строки заказа при приёмке превращаются в партии,
+//! партии ссылаются на строку через `batches.purchase_order_line_id`.
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use chrono::{DateTime, Utc};
+
+pub const PO_STATUS_DRAFT: &str = "draft";
+pub const PO_STATUS_OPEN: &str = "open";
+pub const PO_STATUS_PARTIALLY_RECEIVED: &str = "partially_received";
+pub const PO_STATUS_RECEIVED: &str = "received";
+pub const PO_STATUS_CANCELLED: &str = "cancelled";
+
+/// Статусы, в которых по заказу ещё ожидается поставка (список по умолчанию)
+pub const PO_OPEN_STATUSES: &[&str] = &[PO_STATUS_OPEN, PO_STATUS_PARTIALLY_RECEIVED];
+
+/// Все статусы (CHECK таблицы purchase_orders)
+pub const PO_STATUSES: &[&str] = &[
+    PO_STATUS_DRAFT, PO_STATUS_OPEN, PO_STATUS_PARTIALLY_RECEIVED, PO_STATUS_RECEIVED, PO_STATUS_CANCELLED,
+];
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct PurchaseOrder {
+    pub id: String,
+    pub po_number: String,
+    pub supplier: String,
+    pub status: String,
+    pub expected_date: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[sqlx(default)]
+    pub lines_count: i64,
+    /// Строки, по которым получено меньше заказанного
+    #[sqlx(default)]
+    pub pending_lines: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct PurchaseOrderLine {
+    pub id: String,
+    pub purchase_order_id: String,
+    pub line_no: i64,
+    pub reagent_id: Option<String>,
+    #[sqlx(default)]
+    pub reagent_name: Option<String>,
+    pub description: Option<String>,
+    pub cat_number: Option<String>,
+    pub quantity: f64,
+    pub received_quantity: f64,
+    pub unit: String,
+    pub expected_date: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PurchaseOrderLine {
+    /// Сколько ещё ожидается по строке (в единицах строки)
+    pub fn remaining_quantity(&self) -> f64 {
+        (self.quantity - self.received_quantity).max(0.0)
+    }
+}
+
+/// Партия, принятая по строке заказа
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct PurchaseOrderBatch {
+    pub id: String,
+    pub purchase_order_line_id: String,
+    pub reagent_id: String,
+    pub batch_number: String,
+    pub quantity: f64,
+    pub original_quantity: f64,
+    pub unit: String,
+    pub received_date: DateTime<Utc>,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PurchaseOrderDetailResponse {
+    #[serde(flatten)]
+    pub order: PurchaseOrder,
+    pub lines: Vec<PurchaseOrderLine>,
+    pub batches: Vec<PurchaseOrderBatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct CreatePurchaseOrderLine {
+    /// Реагент из справочника; без него строка описывается текстом (`description`)
+    pub reagent_id: Option<String>,
+    #[validate(length(min = 1, max = 500, message = "Description must be between 1 and 500 characters"))]
+    pub description: Option<String>,
+    #[validate(length(max = 100, message = "Cat number cannot exceed 100 characters"))]
+    pub cat_number: Option<String>,
+    #[validate(range(exclusive_min = 0.0, message = "Quantity must be positive"))]
+    pub quantity: f64,
+    #[validate(length(min = 1, max = 20, message = "Unit must be between 1 and 20 characters"))]
+    pub unit: String,
+    pub expected_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePurchaseOrderRequest {
+    /// Без номера генерируется `PO-XXXXXXXX`
+    #[validate(length(min = 1, max = 50, message = "PO number must be between 1 and 50 characters"))]
+    pub po_number: Option<String>,
+    #[validate(length(min = 1, max = 255, message = "Supplier must be between 1 and 255 characters"))]
+    pub supplier: String,
+    pub expected_date: Option<DateTime<Utc>>,
+    #[validate(length(max = 1000, message = "Notes cannot exceed 1000 characters"))]
+    pub notes: Option<String>,
+    /// true - черновик (status = draft), иначе заказ сразу открыт
+    #[serde(default)]
+    pub draft: bool,
+    #[validate(length(min = 1, message = "At least one line is required"))]
+    pub lines: Vec<CreatePurchaseOrderLine>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PurchaseOrderListQuery {
+    /// Без параметра - открытые заказы (open, partially_received); `all` - все
+    pub status: Option<String>,
+    pub supplier: Option<String>,
+}
+
+/// Черновик заказа из предложений по дозаказу запчастей
+#[derive(Debug, Deserialize, Validate)]
+pub struct PurchaseOrderFromSuggestionsRequest {
+    #[validate(length(min = 1, max = 255, message = "Supplier must be between 1 and 255 characters"))]
+    pub supplier: String,
+    /// Ограничить заказ этими part_number (без учёта регистра); пусто - все
+    #[serde(default)]
+    pub part_numbers: Vec<String>,
+}
+
+/// Строка приёмки: поля партии предзаполнены из заказа, переданные значения их заменяют
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ReceivePurchaseOrderLine {
+    pub line_id: String,
+    /// Для текстовой строки - реагент, в который принять партию
+    pub reagent_id: Option<String>,
+    pub batch_number: Option<String>,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub cat_number: Option<String>,
+    pub supplier: Option<String>,
+    pub lot_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub expiry_date: Option<DateTime<Utc>>,
+    pub pack_size: Option<f64>,
+    pub container_count: Option<i64>,
+    pub location: Option<String>,
+    pub location_id: Option<String>,
+    pub notes: Option<String>,
+    pub received_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReceivePurchaseOrderRequest {
+    pub lines: Vec<ReceivePurchaseOrderLine>,
+}
+
+/// Предзаполненная форма партии для строки заказа (GET .../receive)
+#[derive(Debug, Serialize)]
+pub struct ReceiveDraft {
+    pub line_id: String,
+    pub line_no: i64,
+    pub reagent_id: Option<String>,
+    pub reagent_name: Option<String>,
+    pub description: Option<String>,
+    pub batch_number: String,
+    pub quantity: f64,
+    pub unit: String,
+    pub cat_number: Option<String>,
+    pub supplier: String,
+    /// false для текстовой строки без реагента: партия не создаётся, строка лишь отмечается полученной
+    pub creates_batch: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReceivePurchaseOrderResponse {
+    #[serde(flatten)]
+    pub order: PurchaseOrder,
+    pub received_lines: Vec<PurchaseOrderLine>,
+    pub created_batches: Vec<PurchaseOrderBatch>,
+}
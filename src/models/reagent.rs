@@ -3,6 +3,13 @@ use serde::{Deserialize, Serialize};
 use validator::Validate;
 use chrono::{DateTime, Utc};
 
+use std::str::FromStr;
+use strum::VariantNames;
+
+use crate::query_builders::sql::nullable;
+use crate::query_builders::PhysicalState;
+use super::batch::CreateBatchRequest;
+
 // ==================== REAGENT ====================
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -30,9 +37,47 @@ pub struct Reagent {
     pub updated_at: DateTime<Utc>,
     #[sqlx(default)]
     pub deleted_at: Option<DateTime<Utc>>,
-
+    /// Срок поставки, дней (для прогноза исчерпания)
+    #[sqlx(default)]
+    pub procurement_lead_time_days: Option<i64>,
+    /// Текущее изображение (reagent_images.id)
+    #[sqlx(default)]
+    pub image_id: Option<String>,
+    /// Партии не используются до получения сертификата анализа
+    #[sqlx(default)]
+    pub coa_required: bool,
+    /// Показывается в публичном каталоге (см. public_catalogue)
+    #[sqlx(default)]
+    pub publicly_visible: bool,
+    /// Расход больше порога (в approval_threshold_unit) требует согласования
+    #[sqlx(default)]
+    pub approval_threshold: Option<f64>,
+    #[sqlx(default)]
+    pub approval_threshold_unit: Option<String>,
+    /// Срок годности новой партии от даты поступления, если expiry_date не указан
+    #[sqlx(default)]
+    pub default_shelf_life_days: Option<i64>,
+    /// Контроль сроков годности: strict, advisory или none (см. EXPIRY_POLICIES)
+    #[sqlx(default)]
+    pub expiry_policy: String,
+    /// Команда-владелец: её участники изменяют объект наравне с автором
+    #[sqlx(default)]
+    pub team_id: Option<String>,
+    /// Название команды (заполняется expand_team_names)
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
 }
 
+pub const EXPIRY_POLICY_STRICT: &str = "strict";
+pub const EXPIRY_POLICY_ADVISORY: &str = "advisory";
+pub const EXPIRY_POLICY_NONE: &str = "none";
+
+/// strict - истёкшие партии блокируются и попадают в предупреждения;
+/// advisory - только отдельный раздел отчёта об истекающих партиях;
+/// none - сроки годности не проверяются
+pub const EXPIRY_POLICIES: &[&str] = &[EXPIRY_POLICY_STRICT, EXPIRY_POLICY_ADVISORY, EXPIRY_POLICY_NONE];
+
 #[derive(Debug, Deserialize, Validate, Clone)]
 pub struct CreateReagentRequest {
     #[validate(length(min = 1, max = 255, message = "Name must be between 1 and 255 characters"))]
@@ -50,7 +95,7 @@ pub struct CreateReagentRequest {
     #[validate(range(min = 0.0001, message = "Molecular weight must be positive (>0)"))]
     pub molecular_weight: Option<f64>,
 
-    #[validate(length(max = 50, message = "Physical state cannot exceed 50 characters"))]
+    #[validate(custom(function = "validate_physical_state"))]
     pub physical_state: Option<String>,
 
     #[validate(length(max = 1000, message = "Description cannot exceed 1000 characters"))]
@@ -64,6 +109,33 @@ pub struct CreateReagentRequest {
 
     #[validate(length(max = 100, message = "Hazard pictograms cannot exceed 100 characters"))]
     pub hazard_pictograms: Option<String>,
+
+    #[validate(range(min = 0, max = 3650, message = "Procurement lead time must be between 0 and 3650 days"))]
+    pub procurement_lead_time_days: Option<i64>,
+
+    #[serde(default)]
+    pub coa_required: Option<bool>,
+
+    #[serde(default)]
+    pub publicly_visible: Option<bool>,
+
+    #[validate(range(exclusive_min = 0.0, message = "Approval threshold must be positive"))]
+    pub approval_threshold: Option<f64>,
+
+    #[validate(length(min = 1, max = 20, message = "Approval threshold unit must be between 1 and 20 characters"))]
+    pub approval_threshold_unit: Option<String>,
+
+    #[validate(range(min = 1, max = 36500, message = "Default shelf life must be between 1 and 36500 days"))]
+    pub default_shelf_life_days: Option<i64>,
+
+    pub expiry_policy: Option<String>,
+
+    /// Команда-владелец (teams.id)
+    pub team_id: Option<String>,
+
+    /// Первая партия: создаётся в одной транзакции с реагентом, ошибки - с префиксом `initial_batch.`
+    #[serde(default)]
+    pub initial_batch: Option<CreateBatchRequest>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -72,33 +144,144 @@ pub struct UpdateReagentRequest {
     pub name: Option<String>,
 
     #[validate(length(max = 500, message = "Formula cannot exceed 500 characters"))]
-    pub formula: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub formula: Option<Option<String>>,
 
     #[validate(length(max = 50, message = "CAS number cannot exceed 50 characters"))]
-    pub cas_number: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub cas_number: Option<Option<String>>,
 
     #[validate(length(max = 255, message = "Manufacturer cannot exceed 255 characters"))]
-    pub manufacturer: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub manufacturer: Option<Option<String>>,
 
     #[validate(range(min = 0.0001, message = "Molecular weight must be positive (>0)"))]
-    pub molecular_weight: Option<f64>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub molecular_weight: Option<Option<f64>>,
 
-    #[validate(length(max = 50, message = "Physical state cannot exceed 50 characters"))]
-    pub physical_state: Option<String>,
+    /// null или пустая строка очищает поле
+    #[validate(custom(function = "validate_physical_state_option"))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub physical_state: Option<Option<String>>,
 
     #[validate(length(max = 1000, message = "Description cannot exceed 1000 characters"))]
-    pub description: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
 
     #[validate(length(max = 255, message = "Storage conditions cannot exceed 255 characters"))]
-    pub storage_conditions: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub storage_conditions: Option<Option<String>>,
 
     #[validate(length(max = 255, message = "Appearance cannot exceed 255 characters"))]
-    pub appearance: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub appearance: Option<Option<String>>,
 
     #[validate(length(max = 100, message = "Hazard pictograms cannot exceed 100 characters"))]
-    pub hazard_pictograms: Option<String>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub hazard_pictograms: Option<Option<String>>,
+
+    #[validate(range(min = 0, max = 3650, message = "Procurement lead time must be between 0 and 3650 days"))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub procurement_lead_time_days: Option<Option<i64>>,
+
+    pub coa_required: Option<bool>,
+
+    pub publicly_visible: Option<bool>,
+
+    /// null или 0 снимает порог согласования
+    #[validate(range(min = 0.0, message = "Approval threshold cannot be negative"))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub approval_threshold: Option<Option<f64>>,
+
+    #[validate(length(min = 1, max = 20, message = "Approval threshold unit must be between 1 and 20 characters"))]
+    pub approval_threshold_unit: Option<String>,
+
+    /// null или 0 снимает срок годности по умолчанию
+    #[validate(range(min = 0, max = 36500, message = "Default shelf life must be between 0 and 36500 days"))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub default_shelf_life_days: Option<Option<i64>>,
+
+    pub expiry_policy: Option<String>,
 
     pub status: Option<String>,
+
+    /// null или пустая строка снимает команду-владельца
+    #[serde(default, deserialize_with = "nullable")]
+    pub team_id: Option<Option<String>>,
+}
+
+fn physical_state_error() -> validator::ValidationError {
+    let mut error = validator::ValidationError::new("invalid_physical_state");
+    error.message = Some(format!("Physical state must be one of: {}", PhysicalState::VARIANTS.join(", ")).into());
+    error
+}
+
+fn validate_physical_state(value: &str) -> Result<(), validator::ValidationError> {
+    PhysicalState::from_str(value).map(|_| ()).map_err(|_| physical_state_error())
+}
+
+fn validate_physical_state_option(value: &str) -> Result<(), validator::ValidationError> {
+    if value.trim().is_empty() {
+        Ok(())
+    } else {
+        validate_physical_state(value)
+    }
+}
+
+// ==================== REAGENT IMAGE ====================
+
+/// Изображение реагента (оригинал + миниатюра)
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct ReagentImage {
+    pub id: String,
+    pub reagent_id: String,
+    pub original_filename: String,
+    #[serde(skip_serializing)]
+    pub file_path: String,
+    #[serde(skip_serializing)]
+    pub thumbnail_path: String,
+    pub mime_type: String,
+    pub thumbnail_mime_type: String,
+    pub file_size: i64,
+    pub width: i64,
+    pub height: i64,
+    pub uploaded_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// ==================== HAZARD SEVERITY ====================
+
+/// Тяжесть опасности пиктограмм GHS: 1 - низкая, 2 - средняя, 3 - высокая
+pub const GHS_HAZARD_SEVERITY: &[(&str, u8)] = &[
+    ("GHS01", 3), // взрывчатые вещества
+    ("GHS02", 2), // воспламеняющиеся
+    ("GHS03", 2), // окислители
+    ("GHS04", 1), // газы под давлением
+    ("GHS05", 2), // коррозионные
+    ("GHS06", 3), // острая токсичность
+    ("GHS07", 1), // раздражающие, вредные
+    ("GHS08", 3), // опасность для здоровья (CMR)
+    ("GHS09", 1), // опасность для окружающей среды
+];
+
+pub const MAX_HAZARD_SEVERITY: u8 = 3;
+
+/// Максимальная тяжесть по строке пиктограмм ("GHS02, GHS06"); 0 - опасностей нет
+pub fn hazard_severity(pictograms: &str) -> u8 {
+    let upper = pictograms.to_uppercase();
+    GHS_HAZARD_SEVERITY.iter()
+        .filter(|(code, _)| upper.contains(code))
+        .map(|&(_, severity)| severity)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Коды GHS с тяжестью выше порога (для SQL-условий по hazard_pictograms)
+pub fn hazard_codes_above(threshold: u8) -> Vec<&'static str> {
+    GHS_HAZARD_SEVERITY.iter()
+        .filter(|&&(_, severity)| severity > threshold)
+        .map(|&(code, _)| code)
+        .collect()
 }
 
 // ==================== REAGENT WITH STOCK (legacy compatibility) ====================
@@ -172,3 +355,20 @@ impl From<Reagent> for ReagentWithStock {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hazard_severity() {
+        assert_eq!(hazard_severity(""), 0);
+        assert_eq!(hazard_severity("GHS07"), 1);
+        assert_eq!(hazard_severity("ghs02, GHS07"), 2);
+        assert_eq!(hazard_severity("GHS02;GHS06"), 3);
+        assert_eq!(hazard_severity("flammable"), 0);
+
+        assert_eq!(hazard_codes_above(2), vec!["GHS01", "GHS06", "GHS08"]);
+        assert!(hazard_codes_above(MAX_HAZARD_SEVERITY).is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 // src/models/room.rs
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use validator::Validate;
 use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
 pub struct Room {
@@ -11,6 +12,17 @@ pub struct Room {
     pub capacity: Option<i32>,
     pub color: Option<String>,
     pub status: String,
+    /// Часы работы (UTC, HH:MM) и рабочие дни ISO через запятую (1=Пн..7=Вс)
+    #[sqlx(default)]
+    pub opening_time: Option<String>,
+    #[sqlx(default)]
+    pub closing_time: Option<String>,
+    #[sqlx(default)]
+    pub open_days: Option<String>,
+    /// Недельное расписание (JSON, см. WeeklyHours); если задано, заменяет три поля выше
+    #[sqlx(default)]
+    #[serde(default, serialize_with = "serialize_weekly_hours", deserialize_with = "deserialize_weekly_hours")]
+    pub weekly_hours: Option<String>,
     pub created_by: Option<String>,
     pub updated_by: Option<String>,
     pub created_at: DateTime<Utc>,
@@ -74,6 +86,14 @@ pub struct CreateRoomRequest {
     pub capacity: Option<i32>,
     #[validate(length(max = 20, message = "Color code cannot exceed 20 characters"))]
     pub color: Option<String>,
+    #[validate(custom(function = "validate_time_of_day"))]
+    pub opening_time: Option<String>,
+    #[validate(custom(function = "validate_time_of_day"))]
+    pub closing_time: Option<String>,
+    #[validate(custom(function = "validate_open_days"))]
+    pub open_days: Option<String>,
+    #[validate(custom(function = "validate_weekly_hours"))]
+    pub weekly_hours: Option<WeeklyHours>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -87,6 +107,103 @@ pub struct UpdateRoomRequest {
     #[validate(length(max = 20, message = "Color code cannot exceed 20 characters"))]
     pub color: Option<String>,
     pub status: Option<String>,
+    #[validate(custom(function = "validate_time_of_day"))]
+    pub opening_time: Option<String>,
+    #[validate(custom(function = "validate_time_of_day"))]
+    pub closing_time: Option<String>,
+    #[validate(custom(function = "validate_open_days"))]
+    pub open_days: Option<String>,
+    /// Пустой объект `{}` снимает недельное расписание
+    #[validate(custom(function = "validate_weekly_hours"))]
+    pub weekly_hours: Option<WeeklyHours>,
+}
+
+/// Часы работы в один день недели (UTC, HH:MM)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DayHours {
+    pub open: String,
+    pub close: String,
+}
+
+/// Расписание по дням: ключ - день ISO "1".."7" (1 = Пн); отсутствующий день - выходной
+pub type WeeklyHours = BTreeMap<String, DayHours>;
+
+/// Разбор сохранённого расписания; None - некорректный JSON или значения
+pub fn parse_weekly_hours(value: &str) -> Option<WeeklyHours> {
+    let hours: WeeklyHours = serde_json::from_str(value).ok()?;
+    check_weekly_hours(&hours).ok()?;
+    Some(hours)
+}
+
+fn check_weekly_hours(hours: &WeeklyHours) -> Result<(), String> {
+    for (day, window) in hours {
+        if !matches!(day.parse::<u32>(), Ok(1..=7)) {
+            return Err(format!("Unknown weekday '{}': use ISO weekdays 1-7 (1 = Monday)", day));
+        }
+        let open = chrono::NaiveTime::parse_from_str(&window.open, "%H:%M");
+        let close = chrono::NaiveTime::parse_from_str(&window.close, "%H:%M");
+        match (open, close) {
+            (Ok(open), Ok(close)) if close > open => {}
+            (Ok(_), Ok(_)) => return Err(format!("Day {}: close must be later than open", day)),
+            _ => return Err(format!("Day {}: times must be in HH:MM format", day)),
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_weekly_hours(hours: &WeeklyHours) -> Result<(), validator::ValidationError> {
+    check_weekly_hours(hours).map_err(|message| {
+        let mut error = validator::ValidationError::new("invalid_weekly_hours");
+        error.message = Some(message.into());
+        error
+    })
+}
+
+/// В ответах расписание - объект, а не строка JSON
+fn serialize_weekly_hours<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    value.as_deref().and_then(parse_weekly_hours).serialize(serializer)
+}
+
+fn deserialize_weekly_hours<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let hours = Option::<WeeklyHours>::deserialize(deserializer)?;
+    hours.map(|h| serde_json::to_string(&h).map_err(serde::de::Error::custom)).transpose()
+}
+
+/// Время суток в формате HH:MM
+pub fn validate_time_of_day(value: &str) -> Result<(), validator::ValidationError> {
+    if value.len() == 5 && chrono::NaiveTime::parse_from_str(value, "%H:%M").is_ok() {
+        Ok(())
+    } else {
+        let mut error = validator::ValidationError::new("invalid_time_of_day");
+        error.message = Some("Time must be in HH:MM format".into());
+        Err(error)
+    }
+}
+
+/// Рабочие дни: номера ISO 1..7 через запятую, например "1,2,3,4,5"
+pub fn validate_open_days(value: &str) -> Result<(), validator::ValidationError> {
+    if parse_open_days(value).is_some() {
+        Ok(())
+    } else {
+        let mut error = validator::ValidationError::new("invalid_open_days");
+        error.message = Some("Open days must be comma-separated ISO weekdays 1-7 (1 = Monday)".into());
+        Err(error)
+    }
+}
+
+/// Разбор списка рабочих дней; пустой список допустим (помещение не работает)
+pub fn parse_open_days(value: &str) -> Option<Vec<u32>> {
+    let mut days = Vec::new();
+    for part in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let day: u32 = part.parse().ok()?;
+        if !(1..=7).contains(&day) {
+            return None;
+        }
+        if !days.contains(&day) {
+            days.push(day);
+        }
+    }
+    Some(days)
 }
 
 pub fn validate_room_status(value: &str) -> Result<(), validator::ValidationError> {
@@ -0,0 +1,79 @@
+// src/models/team.rs
+//! Команды (группы пользователей), совместно владеющие реагентами,
+//! оборудованием и экспериментами.
+
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+use chrono::{DateTime, Utc};
+
+use crate::query_builders::sql::nullable;
+
+pub const TEAM_ROLE_LEAD: &str = "lead";
+pub const TEAM_ROLE_MEMBER: &str = "member";
+
+/// Роли участника (CHECK таблицы team_members)
+pub const TEAM_ROLES: &[&str] = &[TEAM_ROLE_LEAD, TEAM_ROLE_MEMBER];
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
+pub struct Team {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[sqlx(default)]
+    pub members_count: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, Clone)]
+pub struct TeamMember {
+    pub user_id: String,
+    pub username: String,
+    pub role: String,
+    pub added_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TeamDetailResponse {
+    #[serde(flatten)]
+    pub team: Team,
+    pub members: Vec<TeamMember>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateTeamRequest {
+    #[validate(length(min = 1, max = 100, message = "Team name must be between 1 and 100 characters"))]
+    pub name: String,
+    #[validate(length(max = 1000, message = "Description cannot exceed 1000 characters"))]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateTeamRequest {
+    #[validate(length(min = 1, max = 100, message = "Team name must be between 1 and 100 characters"))]
+    pub name: Option<String>,
+    #[validate(length(max = 1000, message = "Description cannot exceed 1000 characters"))]
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+}
+
+/// Добавление участника или смена его роли (повторный запрос с тем же user_id)
+#[derive(Debug, Deserialize, Validate)]
+pub struct AddTeamMemberRequest {
+    #[validate(length(min = 1, message = "user_id is required"))]
+    pub user_id: String,
+    #[validate(custom(function = "validate_team_role"))]
+    pub role: Option<String>,
+}
+
+fn validate_team_role(role: &str) -> Result<(), validator::ValidationError> {
+    if TEAM_ROLES.contains(&role) {
+        Ok(())
+    } else {
+        let mut error = validator::ValidationError::new("invalid_team_role");
+        error.message = Some("Team role must be 'lead' or 'member'".into());
+        Err(error)
+    }
+}
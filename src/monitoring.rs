@@ -1,16 +1,29 @@
 // src/monitoring.rs
-use actix_web::{HttpResponse, web};
-use serde::Serialize;
+use actix_web::{HttpMessage, HttpResponse, web};
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
 use chrono::{DateTime, Utc};
 use sqlx::SqlitePool;
 use tokio::time::{interval, sleep, Duration};
 
+use crate::config::{InactivityConfig, MaintenanceConfig, RetentionConfig, SmtpConfig};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::maintenance_mode::MaintenanceModeStore;
+use crate::query_log::{query_stats, QueryLatencyHistogram};
+
 #[derive(Debug, Clone)]
 pub struct Metrics {
     pub request_count: Arc<AtomicU64>,
     pub error_count: Arc<AtomicU64>,
     pub response_times: Arc<std::sync::Mutex<Vec<u64>>>,
+    pub external_lookups: Arc<AtomicU64>,
+    pub lookup_cache_hits: Arc<AtomicU64>,
+    pub lookup_failures: Arc<AtomicU64>,
+    /// Бизнес-показатели с метками (общие для процесса, см. `kpi::counters`)
+    pub kpis: Arc<crate::kpi::KpiCounters>,
+    /// Частота операций с остатком партий по партиям и пользователям (см. `stock_anomalies::rates`)
+    pub stock_rates: Arc<crate::stock_anomalies::StockRates>,
 }
 
 impl Metrics {
@@ -19,6 +32,11 @@ impl Metrics {
             request_count: Arc::new(AtomicU64::new(0)),
             error_count: Arc::new(AtomicU64::new(0)),
             response_times: Arc::new(std::sync::Mutex::new(Vec::new())),
+            external_lookups: Arc::new(AtomicU64::new(0)),
+            lookup_cache_hits: Arc::new(AtomicU64::new(0)),
+            lookup_failures: Arc::new(AtomicU64::new(0)),
+            kpis: crate::kpi::counters(),
+            stock_rates: crate::stock_anomalies::rates(),
         }
     }
 
@@ -30,6 +48,18 @@ impl Metrics {
         self.error_count.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn record_lookup(&self, cache_hit: bool) {
+        if cache_hit {
+            self.lookup_cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.external_lookups.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_lookup_failure(&self) {
+        self.lookup_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn record_response_time(&self, time_ms: u64) {
         if let Ok(mut times) = self.response_times.lock() {
             times.push(time_ms);
@@ -46,6 +76,10 @@ pub struct HealthResponse {
     pub timestamp: DateTime<Utc>,
     pub version: String,
     pub uptime_seconds: u64,
+    /// Режим обслуживания "только чтение": запись отклоняется с 503
+    pub read_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance_message: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -55,28 +89,120 @@ pub struct MetricsResponse {
     pub avg_response_time_ms: f64,
     pub database_connections: i32,
     pub memory_usage_mb: f64,
+    pub external_lookups_total: u64,
+    pub external_lookup_failures_total: u64,
+    pub lookup_cache_hits_total: u64,
+    pub lookup_cache_hit_rate: f64,
+    pub db_query_latency: QueryLatencyHistogram,
+    pub db_pool: DbPoolStats,
+    /// Read-only пул отчётов и аналитики; отсутствует при read_pool_size = 0
+    pub db_read_pool: Option<DbPoolStats>,
+    /// Какой пул получили запросы только на чтение
+    pub db_pool_selections: crate::db::PoolSelectionStats,
+    /// Таймауты запросов по маршрутам ("POST /reports/generate")
+    pub request_timeouts: std::collections::BTreeMap<String, u64>,
+    /// Расхождения reserved_quantity в последней сверке резервов
+    pub reservation_discrepancies: u64,
+    /// Очереди разбора импорта и экспортов
+    pub work_queues: Vec<crate::work_queue::WorkQueueStats>,
+    /// Бизнес-показатели с запуска; active_users - за текущие сутки
+    pub kpis: Vec<crate::kpi::KpiTotal>,
+    /// Операции с остатком партий и срабатывания правил аномалий с запуска
+    pub stock_operations: crate::stock_anomalies::StockRateTotals,
+    /// Попадания и промахи кэша справочников по наборам
+    pub reference_cache: Vec<crate::reference_cache::ReferenceCacheStats>,
+}
+
+/// Состояние пула соединений SQLite на момент запроса метрик
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DbPoolStats {
+    pub max_connections: u32,
+    pub size: u32,
+    pub idle: u32,
+    pub in_use: u32,
+    /// Сколько заняло получение соединения пробным acquire (при исчерпанном пуле растёт)
+    pub acquire_wait_ms: f64,
+    pub acquire_timed_out: bool,
 }
 
-pub async fn health_check() -> HttpResponse {
+/// Предел ожидания пробного acquire, чтобы /metrics не зависал на исчерпанном пуле
+const POOL_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub async fn collect_pool_stats(pool: &SqlitePool) -> DbPoolStats {
+    let size = pool.size();
+    let idle = pool.num_idle() as u32;
+
+    let started = std::time::Instant::now();
+    let probe = tokio::time::timeout(POOL_PROBE_TIMEOUT, pool.acquire()).await;
+    let acquire_wait_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    DbPoolStats {
+        max_connections: pool.options().get_max_connections(),
+        size,
+        idle,
+        in_use: size.saturating_sub(idle),
+        acquire_wait_ms,
+        acquire_timed_out: !matches!(probe, Ok(Ok(_))),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricsQuery {
+    /// `prometheus` - текстовый формат экспозиции вместо JSON
+    pub format: Option<String>,
+}
+
+/// Заголовок для балансировщиков: "read-only" при включённом режиме обслуживания
+pub const MAINTENANCE_MODE_HEADER: &str = "X-Maintenance-Mode";
+
+fn maintenance_state(store: Option<web::Data<MaintenanceModeStore>>) -> (bool, Option<String>) {
+    let mode = store.map(|s| s.current()).unwrap_or_default();
+    let message = mode.enabled.then(|| mode.message().to_string());
+    (mode.enabled, message)
+}
+
+pub async fn health_check(store: Option<web::Data<MaintenanceModeStore>>) -> HttpResponse {
+    let (read_only, maintenance_message) = maintenance_state(store);
     let response = HealthResponse {
-        status: "healthy".to_string(),
+        status: if read_only { "read_only" } else { "healthy" }.to_string(),
         timestamp: Utc::now(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         uptime_seconds: 0, 
+        read_only,
+        maintenance_message,
     };
 
-    HttpResponse::Ok().json(response)
+    let mut builder = HttpResponse::Ok();
+    if read_only {
+        builder.insert_header((MAINTENANCE_MODE_HEADER, "read-only"));
+    }
+    builder.json(response)
 }
 
-pub async fn readiness_check(pool: web::Data<SqlitePool>) -> HttpResponse {
-    match sqlx::query("SELECT 1").fetch_one(pool.get_ref()).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "status": "ready",
-            "database": "connected"
-        })),
+/// Готовность: БД доступна (иначе 503); в режиме обслуживания 200 со status "read_only",
+/// чтобы балансировщик мог увести запись, не снимая чтение
+pub async fn readiness_check(
+    app_state: web::Data<Arc<crate::AppState>>,
+    store: Option<web::Data<MaintenanceModeStore>>,
+) -> HttpResponse {
+    let (read_only, maintenance_message) = maintenance_state(store);
+    match sqlx::query("SELECT 1").fetch_one(&app_state.db_pool).await {
+        Ok(_) => {
+            let mut builder = HttpResponse::Ok();
+            if read_only {
+                builder.insert_header((MAINTENANCE_MODE_HEADER, "read-only"));
+            }
+            builder.json(serde_json::json!({
+                "status": if read_only { "read_only" } else { "ready" },
+                "database": "connected",
+                "read_only": read_only,
+                "maintenance_message": maintenance_message,
+            }))
+        }
         Err(_) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
             "status": "not ready",
-            "database": "disconnected"
+            "database": "disconnected",
+            "read_only": read_only,
         })),
     }
 }
@@ -88,32 +214,165 @@ pub async fn liveness_check() -> HttpResponse {
     }))
 }
 
-pub async fn metrics_endpoint(metrics: web::Data<Arc<Metrics>>) -> HttpResponse {
+pub async fn metrics_endpoint(
+    metrics: web::Data<Metrics>,
+    app_state: web::Data<Arc<crate::AppState>>,
+    query: web::Query<MetricsQuery>,
+) -> HttpResponse {
+    let response = collect_metrics(&metrics, &app_state).await;
+
+    if query.format.as_deref() == Some("prometheus") {
+        return HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(render_prometheus(&response));
+    }
+
+    HttpResponse::Ok().json(response)
+}
+
+/// Снимок метрик процесса (/health/metrics и support bundle)
+pub async fn collect_metrics(metrics: &Metrics, app_state: &crate::AppState) -> MetricsResponse {
     let request_count = metrics.request_count.load(Ordering::Relaxed);
     let error_count = metrics.error_count.load(Ordering::Relaxed);
+    let external_lookups = metrics.external_lookups.load(Ordering::Relaxed);
+    let lookup_cache_hits = metrics.lookup_cache_hits.load(Ordering::Relaxed);
+    let total_lookups = external_lookups + lookup_cache_hits;
 
     let avg_response_time = if let Ok(times) = metrics.response_times.lock() {
         if times.is_empty() { 0.0 } else { times.iter().sum::<u64>() as f64 / times.len() as f64 }
     } else { 0.0 };
 
-    let response = MetricsResponse {
+    let db_pool = collect_pool_stats(&app_state.db_pool).await;
+    let db_read_pool = match &app_state.read_replica {
+        Some(pool) => Some(collect_pool_stats(pool).await),
+        None => None,
+    };
+
+    MetricsResponse {
         requests_total: request_count,
         errors_total: error_count,
         avg_response_time_ms: avg_response_time,
-        database_connections: 0,
+        database_connections: db_pool.size as i32,
         memory_usage_mb: 0.0,
-    };
+        external_lookups_total: external_lookups,
+        external_lookup_failures_total: metrics.lookup_failures.load(Ordering::Relaxed),
+        lookup_cache_hits_total: lookup_cache_hits,
+        lookup_cache_hit_rate: if total_lookups == 0 { 0.0 } else { lookup_cache_hits as f64 / total_lookups as f64 },
+        db_query_latency: query_stats().histogram(),
+        db_pool,
+        db_read_pool,
+        db_pool_selections: crate::db::pool_selection_stats(),
+        request_timeouts: crate::request_timeout::timeout_stats(),
+        reservation_discrepancies: crate::reconciliation::last_discrepancy_count(),
+        work_queues: crate::work_queue::all_stats(),
+        kpis: metrics.kpis.totals(),
+        stock_operations: metrics.stock_rates.totals(),
+        reference_cache: app_state.reference_cache.stats(),
+    }
+}
 
-    HttpResponse::Ok().json(response)
+type QueueMetric = fn(&crate::work_queue::WorkQueueStats) -> u64;
+type CacheMetric = fn(&crate::reference_cache::ReferenceCacheStats) -> u64;
+
+/// Текстовый формат Prometheus: счётчики, состояние пула + гистограмма латентности SQL (в секундах)
+fn render_prometheus(metrics: &MetricsResponse) -> String {
+    let mut out = String::new();
+    let counters = [
+        ("lims_http_requests_total", "Total HTTP requests", metrics.requests_total),
+        ("lims_http_errors_total", "Total HTTP error responses", metrics.errors_total),
+        ("lims_external_lookups_total", "External catalog lookups", metrics.external_lookups_total),
+        ("lims_external_lookup_failures_total", "Failed external catalog lookups", metrics.external_lookup_failures_total),
+        ("lims_lookup_cache_hits_total", "Catalog lookup cache hits", metrics.lookup_cache_hits_total),
+        ("lims_db_slow_queries_total", "SQL statements over the slow query threshold", metrics.db_query_latency.slow_queries_total),
+    ];
+    for (name, help, value) in counters {
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n"));
+    }
+
+    let pool = &metrics.db_pool;
+    let gauges = [
+        ("lims_db_pool_max_connections", "Configured maximum pool size", pool.max_connections as f64),
+        ("lims_db_pool_connections", "Open pool connections", pool.size as f64),
+        ("lims_db_pool_idle_connections", "Idle pool connections", pool.idle as f64),
+        ("lims_db_pool_in_use_connections", "Pool connections in use", pool.in_use as f64),
+        ("lims_db_pool_acquire_wait_seconds", "Time to acquire a probe connection", pool.acquire_wait_ms / 1000.0),
+        ("lims_reservation_discrepancies", "Batches whose reserved quantity disagreed with open reservations at the last reconciliation", metrics.reservation_discrepancies as f64),
+    ];
+    for (name, help, value) in gauges {
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"));
+    }
+
+    if let Some(read_pool) = &metrics.db_read_pool {
+        let read_gauges = [
+            ("lims_db_read_pool_max_connections", "Configured maximum read-only pool size", read_pool.max_connections as f64),
+            ("lims_db_read_pool_connections", "Open read-only pool connections", read_pool.size as f64),
+            ("lims_db_read_pool_idle_connections", "Idle read-only pool connections", read_pool.idle as f64),
+            ("lims_db_read_pool_in_use_connections", "Read-only pool connections in use", read_pool.in_use as f64),
+            ("lims_db_read_pool_acquire_wait_seconds", "Time to acquire a probe read-only connection", read_pool.acquire_wait_ms / 1000.0),
+        ];
+        for (name, help, value) in read_gauges {
+            out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"));
+        }
+    }
+    let selections = &metrics.db_pool_selections;
+    out.push_str("# HELP lims_db_read_pool_selections_total Read-only queries by the pool that served them\n");
+    out.push_str("# TYPE lims_db_read_pool_selections_total counter\n");
+    out.push_str(&format!("lims_db_read_pool_selections_total{{pool=\"read\"}} {}\n", selections.read_pool));
+    out.push_str(&format!("lims_db_read_pool_selections_total{{pool=\"primary\"}} {}\n", selections.primary));
+
+    let cache_counters: [(&str, &str, CacheMetric); 2] = [
+        ("lims_reference_cache_hits_total", "Reference data requests served from the cache", |s| s.hits),
+        ("lims_reference_cache_misses_total", "Reference data requests that loaded from the database", |s| s.misses),
+    ];
+    for (name, help, value) in cache_counters {
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} counter\n"));
+        for stats in &metrics.reference_cache {
+            out.push_str(&format!("{}{{dataset=\"{}\"}} {}\n", name, stats.dataset, value(stats)));
+        }
+    }
+
+    out.push_str("# HELP lims_http_request_timeouts_total Requests answered with 504 after the route timeout\n");
+    out.push_str("# TYPE lims_http_request_timeouts_total counter\n");
+    for (route, count) in &metrics.request_timeouts {
+        out.push_str(&format!("lims_http_request_timeouts_total{{route=\"{}\"}} {}\n", route, count));
+    }
+
+    let queue_metrics: [(&str, &str, &str, QueueMetric); 5] = [
+        ("lims_work_queue_depth", "gauge", "Operations waiting for a work queue slot", |q| q.queued as u64),
+        ("lims_work_queue_processing", "gauge", "Operations currently holding a work queue slot", |q| q.processing as u64),
+        ("lims_work_queue_concurrency", "gauge", "Configured work queue concurrency", |q| q.concurrency as u64),
+        ("lims_work_queue_completed_total", "counter", "Operations finished by a work queue", |q| q.completed_total),
+        ("lims_work_queue_rejected_total", "counter", "Operations rejected with 429 because the work queue was full", |q| q.rejected_total),
+    ];
+    for (name, kind, help, value) in queue_metrics {
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+        for queue in &metrics.work_queues {
+            out.push_str(&format!("{name}{{queue=\"{}\"}} {}\n", queue.queue, value(queue)));
+        }
+    }
+
+    let histogram = &metrics.db_query_latency;
+    out.push_str("# HELP lims_db_query_duration_seconds SQL statement execution time\n");
+    out.push_str("# TYPE lims_db_query_duration_seconds histogram\n");
+    for bucket in &histogram.buckets {
+        let le = bucket.le_ms.map(|ms| (ms / 1000.0).to_string()).unwrap_or_else(|| "+Inf".to_string());
+        out.push_str(&format!("lims_db_query_duration_seconds_bucket{{le=\"{}\"}} {}\n", le, bucket.count));
+    }
+    out.push_str(&format!("lims_db_query_duration_seconds_sum {}\n", histogram.sum_ms / 1000.0));
+    out.push_str(&format!("lims_db_query_duration_seconds_count {}\n", histogram.queries_total));
+    out.push_str(&crate::kpi::render_prometheus(&metrics.kpis));
+    out.push_str(&crate::stock_anomalies::render_prometheus(&metrics.stock_operations));
+    out
 }
 
 pub struct RequestLogger {
     metrics: Arc<Metrics>,
+    trusted_proxies: Arc<crate::client_ip::TrustedProxies>,
 }
 
 impl RequestLogger {
-    pub fn new(metrics: Arc<Metrics>) -> Self {
-        Self { metrics }
+    pub fn new(metrics: Arc<Metrics>, trusted_proxies: crate::client_ip::TrustedProxies) -> Self {
+        Self { metrics, trusted_proxies: Arc::new(trusted_proxies) }
     }
 }
 
@@ -137,6 +396,7 @@ where
         std::future::ready(Ok(RequestLoggerMiddleware {
             service,
             metrics: self.metrics.clone(),
+            trusted_proxies: self.trusted_proxies.clone(),
         }))
     }
 }
@@ -144,6 +404,7 @@ where
 pub struct RequestLoggerMiddleware<S> {
     service: S,
     metrics: Arc<Metrics>,
+    trusted_proxies: Arc<crate::client_ip::TrustedProxies>,
 }
 
 impl<S, B> actix_web::dev::Service<actix_web::dev::ServiceRequest> for RequestLoggerMiddleware<S>
@@ -167,11 +428,23 @@ where
     fn call(&self, req: actix_web::dev::ServiceRequest) -> Self::Future {
         let start_time = std::time::Instant::now();
         let metrics = self.metrics.clone();
+        // Request id для потока бизнес-событий; возвращается клиенту в X-Request-Id
+        let request_id = crate::events::request_id_from_header(
+            req.headers().get(crate::events::REQUEST_ID_HEADER).and_then(|v| v.to_str().ok()),
+        );
+        // IP клиента для аудита, входа и лимитов: заголовки прокси - только от доверенных адресов
+        crate::client_ip::assign(&req, &self.trusted_proxies);
         let fut = self.service.call(req);
 
         Box::pin(async move {
             metrics.increment_requests();
-            let res = fut.await;
+            let mut res = crate::events::with_request_id(request_id.clone(), fut).await;
+            if let (Ok(response), Ok(value)) = (&mut res, actix_web::http::header::HeaderValue::from_str(&request_id)) {
+                response.headers_mut().insert(
+                    actix_web::http::header::HeaderName::from_static(crate::events::REQUEST_ID_HEADER),
+                    value,
+                );
+            }
             let elapsed = start_time.elapsed().as_millis() as u64;
             metrics.record_response_time(elapsed);
 
@@ -179,61 +452,197 @@ where
                 if response.status().is_client_error() || response.status().is_server_error() {
                     metrics.increment_errors();
                 }
+                if let Some(claims) = response.request().extensions().get::<crate::auth::Claims>() {
+                    metrics.kpis.record_active_user(&claims.sub, Utc::now().date_naive());
+                }
             }
             res
         })
     }
 }
 
-pub async fn start_maintenance_tasks(pool: SqlitePool) {
+pub async fn start_maintenance_tasks(
+    pool: SqlitePool,
+    inactivity: InactivityConfig,
+    retention: RetentionConfig,
+    report_scheduler: crate::report_schedule_handlers::ReportScheduler,
+    smtp: SmtpConfig,
+    maintenance: MaintenanceConfig,
+) {
     let pool_clone1 = pool.clone();
     let pool_clone2 = pool.clone();
     
     tokio::spawn(async move {
-        cleanup_old_audit_logs(pool_clone1).await;
+        apply_retention_rules(pool_clone1, retention).await;
     });
     
     tokio::spawn(async move {
         update_batch_statuses(pool_clone2).await;
     });
+
+    let pool_clone4 = pool.clone();
+    tokio::spawn(async move {
+        finalize_pending_deletions(pool_clone4).await;
+    });
+
+    let pool_clone7 = pool.clone();
+    tokio::spawn(async move {
+        expire_consumption_approvals(pool_clone7).await;
+    });
+
+    let pool_clone5 = pool.clone();
+    tokio::spawn(async move {
+        reconcile_reservations_weekly(pool_clone5).await;
+    });
+
+    let pool_clone6 = pool.clone();
+    tokio::spawn(async move {
+        run_report_schedules(pool_clone6, report_scheduler).await;
+    });
+
+    let pool_clone8 = pool.clone();
+    let alert_maintenance = maintenance.clone();
+    tokio::spawn(async move {
+        refresh_alerts_hourly(pool_clone8, alert_maintenance).await;
+    });
+
+    let pool_clone9 = pool.clone();
+    tokio::spawn(async move {
+        send_alert_digest_daily(pool_clone9, smtp, maintenance).await;
+    });
+
+    let pool_clone10 = pool.clone();
+    tokio::spawn(async move {
+        flush_kpis(pool_clone10).await;
+    });
+
+    if inactivity.is_enabled() {
+        let pool_clone3 = pool.clone();
+        tokio::spawn(async move {
+            deactivate_inactive_users(pool_clone3, inactivity).await;
+        });
+    }
 }
 
-async fn cleanup_old_audit_logs(pool: SqlitePool) {
+async fn apply_retention_rules(pool: SqlitePool, retention: RetentionConfig) {
     let mut interval = interval(Duration::from_secs(24 * 3600)); // Раз в день
 
     loop {
         interval.tick().await;
-        log::info!("Starting daily cleanup of audit logs...");
-        let mut total_deleted = 0;
-
-        loop {
-            // Удаляем пачками по 1000
-            let result = sqlx::query(
-                "DELETE FROM audit_logs 
-                 WHERE id IN (
-                     SELECT id FROM audit_logs 
-                     WHERE created_at < datetime('now', '-90 days') 
-                     LIMIT 1000
-                 )"
-            )
-            .execute(&pool)
-            .await;
+        log::info!("Starting daily data retention cleanup...");
 
-            match result {
-                Ok(res) => {
-                    let count = res.rows_affected();
-                    total_deleted += count;
-                    if count < 1000 { break; }
-                    sleep(Duration::from_millis(50)).await;
-                },
-                Err(e) => {
-                    log::error!("Failed to cleanup audit logs chunk: {}", e);
-                    break;
+        match run_retention_policy(&pool, &retention, false).await {
+            Ok(report) => {
+                for rule in report.rules.iter().filter(|r| r.deleted > 0) {
+                    log::info!(
+                        "Retention: purged {} {} entries older than {} year(s)",
+                        rule.deleted, rule.table, rule.retention_years
+                    );
                 }
             }
+            Err(e) => log::error!("Data retention cleanup failed: {}", e),
         }
-        if total_deleted > 0 {
-            log::info!("Cleaned up {} old audit log entries in chunks", total_deleted);
+    }
+}
+
+async fn finalize_pending_deletions(pool: SqlitePool) {
+    let mut interval = interval(Duration::from_secs(60)); // Раз в минуту
+
+    loop {
+        interval.tick().await;
+        match crate::pending_deletion_handlers::finalize_expired_deletions(&pool).await {
+            Ok(0) => {}
+            Ok(count) => log::info!("Finalized {} pending deletion(s)", count),
+            Err(e) => log::error!("Failed to finalize pending deletions: {}", e),
+        }
+    }
+}
+
+async fn expire_consumption_approvals(pool: SqlitePool) {
+    let mut interval = interval(Duration::from_secs(3600)); // Раз в час
+
+    loop {
+        interval.tick().await;
+        match crate::approval_handlers::expire_stale_approvals(&pool).await {
+            Ok(0) => {}
+            Ok(count) => log::info!("Expired {} consumption approval request(s)", count),
+            Err(e) => log::error!("Failed to expire consumption approvals: {}", e),
+        }
+    }
+}
+
+async fn flush_kpis(pool: SqlitePool) {
+    let mut interval = interval(Duration::from_secs(300)); // Раз в 5 минут
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = crate::kpi::flush(&pool, &crate::kpi::counters()).await {
+            log::error!("Failed to persist KPI rollups: {}", e);
+        }
+    }
+}
+
+async fn refresh_alerts_hourly(pool: SqlitePool, maintenance: MaintenanceConfig) {
+    let mut interval = interval(Duration::from_secs(3600)); // Раз в час
+
+    loop {
+        interval.tick().await;
+        match crate::alert_handlers::refresh_alerts(&pool, &maintenance).await {
+            Ok(r) if r.opened > 0 || r.resolved > 0 || r.reopened > 0 => log::info!(
+                "Alerts: {} opened, {} resolved, {} reopened after snooze",
+                r.opened, r.resolved, r.reopened
+            ),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to refresh alerts: {}", e),
+        }
+    }
+}
+
+async fn send_alert_digest_daily(pool: SqlitePool, smtp: SmtpConfig, maintenance: MaintenanceConfig) {
+    let mut settings_changes = crate::settings::settings().subscribe();
+
+    loop {
+        // Раз в день, в час уведомлений (настройка notification_hour)
+        let hour = crate::settings::settings().get_i64(crate::settings::NOTIFICATION_HOUR).clamp(0, 23) as u32;
+        tokio::select! {
+            _ = sleep(Duration::from_secs(seconds_until_hour(Utc::now(), hour))) => {}
+            Ok(()) = settings_changes.changed() => continue,
+        }
+        match crate::alert_handlers::send_alert_digest(&pool, &smtp, &maintenance).await {
+            Ok(0) => {}
+            Ok(count) => log::info!("Alert digest sent with {} open alert(s)", count),
+            Err(e) => log::error!("Alert digest failed: {}", e),
+        }
+    }
+}
+
+/// Только отчёт: исправление - решение администратора (POST /admin/reconcile-reservations?fix=true)
+async fn reconcile_reservations_weekly(pool: SqlitePool) {
+    let mut interval = interval(Duration::from_secs(7 * 24 * 3600)); // Раз в неделю
+
+    loop {
+        interval.tick().await;
+        match crate::reconciliation::check_reservations(&pool).await {
+            Ok(report) if report.discrepancies.is_empty() => {}
+            Ok(report) => log::warn!(
+                "Reservation reconciliation: {} batch(es) with reserved_quantity out of sync, \
+                 run POST /api/v1/admin/reconcile-reservations?fix=true",
+                report.discrepancies.len()
+            ),
+            Err(e) => log::error!("Reservation reconciliation failed: {}", e),
+        }
+    }
+}
+
+async fn run_report_schedules(pool: SqlitePool, scheduler: crate::report_schedule_handlers::ReportScheduler) {
+    let mut interval = interval(Duration::from_secs(60)); // Раз в минуту
+
+    loop {
+        interval.tick().await;
+        match crate::report_schedule_handlers::run_due_schedules(&pool, &scheduler).await {
+            Ok(0) => {}
+            Ok(count) => log::info!("Ran {} scheduled report(s)", count),
+            Err(e) => log::error!("Scheduled reports failed: {}", e),
         }
     }
 }
@@ -247,11 +656,12 @@ async fn update_batch_statuses(pool: SqlitePool) {
         let mut total_updated = 0;
 
         loop {
-            // 1. Ищем ID просроченных (по 1000)
+            // 1. Ищем ID просроченных (по 1000); advisory и none не блокируются по сроку
             let batch_ids: Vec<String> = match sqlx::query_scalar(
                 r#"SELECT id FROM batches 
                    WHERE expiry_date < datetime('now') 
                    AND status = 'available' 
+                   AND reagent_id IN (SELECT id FROM reagents WHERE expiry_policy = 'strict')
                    LIMIT 1000"#
             )
             .fetch_all(&pool)
@@ -289,4 +699,489 @@ async fn update_batch_statuses(pool: SqlitePool) {
             log::info!("Updated {} expired batches in chunks", total_updated);
         }
     }
-}
\ No newline at end of file
+}
+
+// ==================== INACTIVE ACCOUNTS ====================
+
+#[derive(Debug, Default, PartialEq)]
+pub struct InactivityRunResult {
+    pub notified: usize,
+    pub deactivated: usize,
+}
+
+/// Секунд до ближайшего наступления часа `hour` (UTC)
+fn seconds_until_hour(now: DateTime<Utc>, hour: u32) -> u64 {
+    let today = now.date_naive().and_hms_opt(hour.min(23), 0, 0).unwrap_or_default().and_utc();
+    let next = if today > now { today } else { today + chrono::Duration::days(1) };
+    (next - now).num_seconds().max(0) as u64
+}
+
+async fn deactivate_inactive_users(pool: SqlitePool, policy: InactivityConfig) {
+    let mut settings_changes = crate::settings::settings().subscribe();
+
+    loop {
+        // Раз в день, в час уведомлений (настройка notification_hour)
+        let hour = crate::settings::settings().get_i64(crate::settings::NOTIFICATION_HOUR).clamp(0, 23) as u32;
+        tokio::select! {
+            _ = sleep(Duration::from_secs(seconds_until_hour(Utc::now(), hour))) => {}
+            // Настройки изменились - пересчитываем расписание
+            Ok(()) = settings_changes.changed() => continue,
+        }
+        log::info!("Starting daily inactive accounts check...");
+
+        match run_inactivity_policy(&pool, &policy).await {
+            Ok(result) if result.notified > 0 || result.deactivated > 0 => {
+                log::info!(
+                    "Inactive accounts: {} scheduled for deactivation, {} deactivated",
+                    result.notified, result.deactivated
+                );
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("Inactive accounts check failed: {}", e),
+        }
+    }
+}
+
+/// Системная запись в audit_logs (без пользователя и HTTP-запроса)
+async fn log_system_event(
+    pool: &SqlitePool,
+    action: &str,
+    user_id: &str,
+    description: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO audit_logs (id, user_id, action, entity_type, entity_id, description, created_at)
+           VALUES (?, NULL, ?, 'user', ?, ?, ?)"#
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(action)
+    .bind(user_id)
+    .bind(description)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Два этапа: сначала администраторы получают уведомление (audit_logs) о предстоящей деактивации,
+/// деактивируются только учётные записи, уведомление по которым отправлено не менее notice_days назад.
+/// Администраторы не деактивируются; любая активность пользователя сбрасывает уведомление.
+pub async fn run_inactivity_policy(
+    pool: &SqlitePool,
+    policy: &InactivityConfig,
+) -> Result<InactivityRunResult, sqlx::Error> {
+    let mut result = InactivityRunResult::default();
+    if !policy.is_enabled() {
+        return Ok(result);
+    }
+
+    // 1. Деактивация после истечения срока уведомления
+    let due: Vec<(String, String)> = sqlx::query_as(
+        r#"SELECT id, username FROM users
+           WHERE is_active = 1 AND role != 'admin'
+             AND inactivity_notice_at IS NOT NULL
+             AND datetime(inactivity_notice_at) <= datetime('now', ?)
+             AND datetime(COALESCE(last_activity_at, last_login, created_at)) < datetime('now', ?)"#
+    )
+    .bind(format!("-{} days", policy.notice_days))
+    .bind(format!("-{} days", policy.deactivate_after_days))
+    .fetch_all(pool)
+    .await?;
+
+    for (id, username) in due {
+        sqlx::query("UPDATE users SET is_active = 0, updated_at = datetime('now') WHERE id = ? AND is_active = 1")
+            .bind(&id)
+            .execute(pool)
+            .await?;
+
+        let description = format!(
+            "User {} deactivated after {} days of inactivity",
+            username, policy.deactivate_after_days
+        );
+        if let Err(e) = log_system_event(pool, "deactivate_user", &id, &description).await {
+            log::warn!("Failed to log deactivation of user {}: {}", username, e);
+        }
+        log::warn!("{}", description);
+        result.deactivated += 1;
+    }
+
+    // 2. Уведомление администраторам о предстоящей деактивации
+    let upcoming: Vec<(String, String, String)> = sqlx::query_as(
+        r#"SELECT id, username, datetime(COALESCE(last_activity_at, last_login, created_at)) AS last_seen
+           FROM users
+           WHERE is_active = 1 AND role != 'admin'
+             AND inactivity_notice_at IS NULL
+             AND datetime(COALESCE(last_activity_at, last_login, created_at)) < datetime('now', ?)"#
+    )
+    .bind(format!("-{} days", policy.deactivate_after_days - policy.notice_days))
+    .fetch_all(pool)
+    .await?;
+
+    for (id, username, last_seen) in upcoming {
+        sqlx::query("UPDATE users SET inactivity_notice_at = datetime('now') WHERE id = ?")
+            .bind(&id)
+            .execute(pool)
+            .await?;
+
+        let description = format!(
+            "User {} inactive since {}: account will be deactivated in {} days unless the user signs in",
+            username, last_seen, policy.notice_days
+        );
+        if let Err(e) = log_system_event(pool, "inactivity_notice", &id, &description).await {
+            log::warn!("Failed to log inactivity notice for user {}: {}", username, e);
+        }
+        log::warn!("{}", description);
+        result.notified += 1;
+    }
+
+    Ok(result)
+}
+
+// ==================== DATA RETENTION ====================
+
+/// Одно правило хранения: таблица, срок и сколько записей его превысили
+#[derive(Debug, Serialize)]
+pub struct RetentionRuleResult {
+    pub table: &'static str,
+    pub retention_years: u32,
+    /// Удаляются записи старше этой даты; None - правило выключено
+    pub cutoff: Option<DateTime<Utc>>,
+    pub eligible: i64,
+    pub deleted: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    pub rules: Vec<RetentionRuleResult>,
+}
+
+fn retention_rules(config: &RetentionConfig) -> [(&'static str, u32); 2] {
+    [
+        ("audit_logs", config.audit_log_years),
+        ("usage_logs", config.usage_history_years),
+    ]
+}
+
+/// Применяет правила хранения; при dry_run только считает записи, которые будут удалены
+pub async fn run_retention_policy(
+    pool: &SqlitePool,
+    config: &RetentionConfig,
+    dry_run: bool,
+) -> Result<RetentionReport, sqlx::Error> {
+    let now = Utc::now();
+    let mut rules = Vec::new();
+
+    for (table, years) in retention_rules(config) {
+        let cutoff = if years > 0 { now.checked_sub_months(chrono::Months::new(12 * years)) } else { None };
+        let mut rule = RetentionRuleResult { table, retention_years: years, cutoff, eligible: 0, deleted: 0 };
+
+        if let Some(cutoff) = cutoff {
+            let cutoff = cutoff.format("%Y-%m-%d %H:%M:%S").to_string();
+            rule.eligible = sqlx::query_scalar(&format!(
+                "SELECT COUNT(*) FROM {} WHERE datetime(created_at) < datetime(?)", table
+            ))
+            .bind(&cutoff)
+            .fetch_one(pool)
+            .await?;
+
+            if !dry_run && rule.eligible > 0 {
+                // Удаляем пачками по 1000, чтобы не блокировать БД надолго
+                let delete_sql = format!(
+                    "DELETE FROM {table} WHERE id IN (
+                         SELECT id FROM {table} WHERE datetime(created_at) < datetime(?) LIMIT 1000
+                     )"
+                );
+                loop {
+                    let count = sqlx::query(&delete_sql)
+                        .bind(&cutoff)
+                        .execute(pool)
+                        .await?
+                        .rows_affected();
+                    rule.deleted += count;
+                    if count < 1000 { break; }
+                    sleep(Duration::from_millis(50)).await;
+                }
+            }
+        }
+        rules.push(rule);
+    }
+
+    Ok(RetentionReport { dry_run, rules })
+}
+
+/// GET /admin/retention/dry-run - что удалит следующий прогон правил хранения
+pub async fn get_retention_dry_run(
+    app_state: web::Data<Arc<crate::AppState>>,
+    http_request: actix_web::HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    if claims.role != crate::auth::UserRole::Admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+
+    let report = run_retention_policy(&app_state.db_pool, &app_state.config.retention, true).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seconds_until_hour() {
+        let at = |h: u32, m: u32| chrono::NaiveDate::from_ymd_opt(2024, 3, 10).unwrap().and_hms_opt(h, m, 0).unwrap().and_utc();
+        assert_eq!(seconds_until_hour(at(6, 30), 8), 90 * 60);
+        // Час уже наступил - следующий запуск завтра
+        assert_eq!(seconds_until_hour(at(8, 0), 8), 24 * 3600);
+        assert_eq!(seconds_until_hour(at(23, 0), 0), 3600);
+    }
+
+    #[test]
+    fn test_render_prometheus_histogram() {
+        let stats = crate::query_log::QueryStats::new(&crate::config::QueryLogConfig::default());
+        let details = crate::query_log::StatementDetails::default();
+        stats.record("SELECT 1", std::time::Duration::from_micros(80), details);
+        stats.record("SELECT 2", std::time::Duration::from_millis(2), details);
+
+        let response = MetricsResponse {
+            requests_total: 3,
+            errors_total: 1,
+            avg_response_time_ms: 0.0,
+            database_connections: 0,
+            memory_usage_mb: 0.0,
+            external_lookups_total: 0,
+            external_lookup_failures_total: 0,
+            lookup_cache_hits_total: 0,
+            lookup_cache_hit_rate: 0.0,
+            db_query_latency: stats.histogram(),
+            db_pool: DbPoolStats { max_connections: 10, size: 3, idle: 1, in_use: 2, ..Default::default() },
+            db_read_pool: Some(DbPoolStats { max_connections: 4, size: 2, idle: 2, ..Default::default() }),
+            db_pool_selections: crate::db::PoolSelectionStats { read_pool: 7, primary: 0 },
+            request_timeouts: [("POST /reports/generate".to_string(), 2)].into_iter().collect(),
+            reservation_discrepancies: 4,
+            work_queues: vec![crate::work_queue::WorkQueueStats {
+                queue: "xlsx_parse",
+                concurrency: 1,
+                max_queued: 8,
+                queued: 2,
+                processing: 1,
+                completed_total: 5,
+                rejected_total: 1,
+            }],
+            kpis: vec![crate::kpi::KpiTotal { metric: "files_uploaded", label: "reagent_image".to_string(), value: 6 }],
+            stock_operations: crate::stock_anomalies::StockRateTotals {
+                operations: [("use", 12)].into_iter().collect(),
+                anomalies: [("batch_rate", 1)].into_iter().collect(),
+                max_batch_ops_in_window: 51,
+                max_user_ops_in_window: 3,
+            },
+            reference_cache: vec![crate::reference_cache::ReferenceCacheStats { dataset: "rooms", entries: 1, hits: 9, misses: 2 }],
+        };
+        let text = render_prometheus(&response);
+        assert!(text.contains("# TYPE lims_db_pool_in_use_connections gauge\nlims_db_pool_in_use_connections 2\n"));
+        assert!(text.contains("lims_http_requests_total 3\n"));
+        assert!(text.contains("# TYPE lims_reference_cache_hits_total counter\nlims_reference_cache_hits_total{dataset=\"rooms\"} 9\n"));
+        assert!(text.contains("# TYPE lims_db_read_pool_max_connections gauge\nlims_db_read_pool_max_connections 4\n"));
+        assert!(text.contains("lims_db_read_pool_selections_total{pool=\"read\"} 7\n"));
+        assert!(text.contains("lims_http_request_timeouts_total{route=\"POST /reports/generate\"} 2\n"));
+        assert!(text.contains("# TYPE lims_reservation_discrepancies gauge\nlims_reservation_discrepancies 4\n"));
+        assert!(text.contains("# TYPE lims_work_queue_depth gauge\nlims_work_queue_depth{queue=\"xlsx_parse\"} 2\n"));
+        assert!(text.contains("lims_work_queue_rejected_total{queue=\"xlsx_parse\"} 1\n"));
+        assert!(text.contains("# TYPE lims_db_query_duration_seconds histogram\n"));
+        assert!(text.contains("lims_db_query_duration_seconds_bucket{le=\"0.0001\"} 1\n"));
+        assert!(text.contains("lims_db_query_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("lims_db_query_duration_seconds_count 2\n"));
+        assert!(text.contains("lims_files_uploaded_total{kind=\"reagent_image\"} 6\n"));
+        assert!(text.contains("lims_stock_operations_total{operation=\"use\"} 12\n"));
+        assert!(text.contains("lims_stock_anomalies_total{rule=\"batch_rate\"} 1\n"));
+        assert!(text.contains("# TYPE lims_stock_batch_ops_in_window_max gauge\nlims_stock_batch_ops_in_window_max 51\n"));
+    }
+
+    #[actix_web::test]
+    async fn test_config_driven_pool_and_stats() {
+        let path = std::env::temp_dir().join(format!("lims-pool-{}.db", uuid::Uuid::new_v4()));
+        let config = crate::config::DatabaseConfig {
+            url: path.to_string_lossy().to_string(),
+            max_connections: 3,
+            min_connections: 1,
+            busy_timeout_ms: 1234,
+            synchronous: "FULL".to_string(),
+            ..Default::default()
+        };
+        let pool = crate::db::create_pool(&config).await.unwrap();
+
+        let mode: (String,) = sqlx::query_as("PRAGMA journal_mode").fetch_one(&pool).await.unwrap();
+        assert_eq!(mode.0.to_uppercase(), "WAL");
+        let sync: (i64,) = sqlx::query_as("PRAGMA synchronous").fetch_one(&pool).await.unwrap();
+        assert_eq!(sync.0, 2);
+        let busy: (i64,) = sqlx::query_as("PRAGMA busy_timeout").fetch_one(&pool).await.unwrap();
+        assert_eq!(busy.0, 1234);
+        let fk: (i64,) = sqlx::query_as("PRAGMA foreign_keys").fetch_one(&pool).await.unwrap();
+        assert_eq!(fk.0, 1);
+
+        let held = pool.acquire().await.unwrap();
+        let stats = collect_pool_stats(&pool).await;
+        assert_eq!(stats.max_connections, 3);
+        assert!(stats.in_use >= 1);
+        assert_eq!(stats.in_use, stats.size - stats.idle);
+        assert!(!stats.acquire_timed_out);
+
+        drop(held);
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_read_pool_serves_reads_and_rejects_writes() {
+        let path = std::env::temp_dir().join(format!("lims-read-pool-{}.db", uuid::Uuid::new_v4()));
+        let config = crate::config::DatabaseConfig {
+            url: path.to_string_lossy().to_string(),
+            read_pool_size: 2,
+            ..Default::default()
+        };
+        let pool = crate::db::create_pool(&config).await.unwrap();
+        sqlx::query("CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT)").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO items (name) VALUES ('a')").execute(&pool).await.unwrap();
+
+        let read_pool = crate::db::create_read_pool(&config).await.unwrap().expect("read pool enabled");
+        let before = crate::db::pool_selection_stats();
+        let selected = crate::db::select_read_pool(&pool, Some(&read_pool));
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM items").fetch_one(selected).await.unwrap();
+        assert_eq!(count.0, 1);
+        assert!(crate::db::pool_selection_stats().read_pool > before.read_pool);
+        assert!(sqlx::query("INSERT INTO items (name) VALUES ('b')").execute(&read_pool).await.is_err());
+
+        // Записи основного пула видны read-only соединениям
+        sqlx::query("INSERT INTO items (name) VALUES ('c')").execute(&pool).await.unwrap();
+        let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM items").fetch_one(&read_pool).await.unwrap();
+        assert_eq!(count.0, 2);
+        assert_eq!(collect_pool_stats(&read_pool).await.max_connections, 2);
+
+        // read_pool_size = 0 - один общий пул
+        let single = crate::config::DatabaseConfig { read_pool_size: 0, ..config };
+        assert!(crate::db::create_read_pool(&single).await.unwrap().is_none());
+        assert!(std::ptr::eq(crate::db::select_read_pool(&pool, None), &pool));
+
+        read_pool.close().await;
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    async fn insert_user(pool: &SqlitePool, id: &str, role: &str, inactive_days: i64) {
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at, last_activity_at) \
+             VALUES (?, ?, ?, 'x', ?, datetime('now', '-400 days'), datetime('now'), datetime('now', ?))"
+        )
+            .bind(id)
+            .bind(id)
+            .bind(format!("{}@example.com", id))
+            .bind(role)
+            .bind(format!("-{} days", inactive_days))
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    async fn is_active(pool: &SqlitePool, id: &str) -> bool {
+        sqlx::query_scalar("SELECT is_active FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_inactivity_policy_notifies_before_deactivation() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        insert_user(&pool, "old_viewer", "viewer", 120).await;
+        insert_user(&pool, "soon_viewer", "viewer", 85).await;
+        insert_user(&pool, "recent_viewer", "viewer", 10).await;
+        insert_user(&pool, "old_admin", "admin", 200).await;
+
+        let policy = InactivityConfig { deactivate_after_days: 90, notice_days: 7 };
+
+        // Первый прогон: только уведомления, никто не деактивирован
+        let first = run_inactivity_policy(&pool, &policy).await.unwrap();
+        assert_eq!(first, InactivityRunResult { notified: 2, deactivated: 0 });
+        assert!(is_active(&pool, "old_viewer").await);
+
+        let notices: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_logs WHERE action = 'inactivity_notice'")
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(notices, 2);
+
+        // Повторный прогон до истечения срока уведомления ничего не меняет
+        let again = run_inactivity_policy(&pool, &policy).await.unwrap();
+        assert_eq!(again, InactivityRunResult::default());
+
+        // Срок уведомления истёк; soon_viewer успел войти в систему
+        sqlx::query("UPDATE users SET inactivity_notice_at = datetime('now', '-8 days') WHERE inactivity_notice_at IS NOT NULL")
+            .execute(&pool).await.unwrap();
+        crate::auth::record_user_activity(&pool, "soon_viewer").await.unwrap();
+
+        let second = run_inactivity_policy(&pool, &policy).await.unwrap();
+        assert_eq!(second, InactivityRunResult { notified: 0, deactivated: 1 });
+        assert!(!is_active(&pool, "old_viewer").await);
+        assert!(is_active(&pool, "soon_viewer").await);
+        assert!(is_active(&pool, "recent_viewer").await);
+        assert!(is_active(&pool, "old_admin").await);
+
+        let disabled = InactivityConfig { deactivate_after_days: 0, notice_days: 7 };
+        assert_eq!(run_inactivity_policy(&pool, &disabled).await.unwrap(), InactivityRunResult::default());
+    }
+
+    #[actix_web::test]
+    async fn test_retention_policy_dry_run_then_purge() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Ethanol', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) \
+             VALUES ('b1', 'r1', 'LOT-1', 10, 10, 'mL', datetime('now'), 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO audit_logs (id, action, entity_type, created_at) VALUES \
+             ('a1', 'login', 'user', datetime('now', '-3 years')), \
+             ('a2', 'login', 'user', datetime('now', '-13 months')), \
+             ('a3', 'login', 'user', datetime('now', '-6 months'))",
+            "INSERT INTO usage_logs (id, reagent_id, batch_id, quantity_used, unit, created_at) VALUES \
+             ('u1', 'r1', 'b1', 1, 'mL', datetime('now', '-3 years')), \
+             ('u2', 'r1', 'b1', 1, 'mL', datetime('now', '-1 month'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let config = RetentionConfig { audit_log_years: 1, usage_history_years: 2 };
+        let preview = run_retention_policy(&pool, &config, true).await.unwrap();
+        let counts: Vec<(&str, i64, u64)> = preview.rules.iter().map(|r| (r.table, r.eligible, r.deleted)).collect();
+        assert_eq!(counts, vec![("audit_logs", 2, 0), ("usage_logs", 1, 0)]);
+        let audit_rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_logs").fetch_one(&pool).await.unwrap();
+        assert_eq!(audit_rows, 3);
+
+        let purge = run_retention_policy(&pool, &config, false).await.unwrap();
+        let counts: Vec<(&str, u64)> = purge.rules.iter().map(|r| (r.table, r.deleted)).collect();
+        assert_eq!(counts, vec![("audit_logs", 2), ("usage_logs", 1)]);
+        let remaining: Vec<String> = sqlx::query_scalar("SELECT id FROM audit_logs UNION ALL SELECT id FROM usage_logs ORDER BY 1")
+            .fetch_all(&pool).await.unwrap();
+        assert_eq!(remaining, vec!["a3", "u2"]);
+
+        // 0 лет - правило выключено
+        let keep_all = RetentionConfig { audit_log_years: 0, usage_history_years: 0 };
+        let report = run_retention_policy(&pool, &keep_all, false).await.unwrap();
+        assert!(report.rules.iter().all(|r| r.cutoff.is_none() && r.eligible == 0));
+    }
+}
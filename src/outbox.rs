@@ -0,0 +1,531 @@
+// src/outbox.rs
+//! Транзакционный outbox бизнес-событий - единственный путь их публикации.
+//!
+//! Обработчик вызывает `enqueue` в той же транзакции, что меняет данные: событие появляется
+//! только вместе с изменением, а после фиксации уже не теряется при падении процесса.
+//! Диспетчер (`start_dispatcher`) опрашивает таблицу и доставляет события:
+//! - строка в журнал `lims::events` и учёт KPI - один раз, при первой попытке;
+//! - POST на вебхуки из `[outbox]` (подпись HMAC-SHA256 в X-LIMS-Signature, если задан secret);
+//! - письмо получателям `email_to` для типов из `email_events` и адресатам самого события
+//!   (`BusinessEvent::recipients`, например эскалация просроченного обслуживания).
+//!
+//! Доставка "хотя бы один раз": при повторе вебхуки получают событие снова, поэтому
+//! получатель отбрасывает дубли по X-LIMS-Delivery. Порядок сохраняется в пределах сущности
+//! (`BusinessEvent::entity`): следующее событие ждёт, пока предыдущее не доставлено.
+//! После `max_attempts` неудач событие становится dead letter и держит очередь своей сущности,
+//! пока администратор не вернёт его в очередь или не отбросит.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{OutboxConfig, SmtpConfig, WebhookConfig};
+use crate::crypto::hmac_sha256_hex;
+use crate::error::{ApiError, ApiResult};
+use crate::events::{self, BusinessEvent};
+use crate::handlers::{ApiResponse, PaginatedResponse, PaginationQuery};
+use crate::AppState;
+
+/// Наибольшая пауза перед повтором
+const MAX_RETRY_DELAY_SECS: u64 = 3600;
+/// Сколько символов ошибки доставки сохраняется
+const MAX_ERROR_LEN: usize = 1000;
+
+pub const SIGNATURE_HEADER: &str = "X-LIMS-Signature";
+
+// ==================== ENQUEUE ====================
+
+/// Поставить событие в очередь в транзакции обработчика (`&mut *tx`).
+/// Автор и request id текущего запроса сохраняются в строке события
+pub async fn enqueue<'e, E>(executor: E, event: &BusinessEvent, actor: Option<&str>) -> Result<(), sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let payload = events::to_json_line(event, actor, events::current_request_id())
+        .map_err(|e| sqlx::Error::Io(e.into()))?;
+    let (entity_type, entity_id) = event.entity();
+    let now = Utc::now();
+    sqlx::query(
+        "INSERT INTO outbox (event_type, entity_type, entity_id, payload, created_at, next_attempt_at) \
+         VALUES (?, ?, ?, ?, ?, ?)"
+    )
+        .bind(event.event_type())
+        .bind(entity_type)
+        .bind(entity_id)
+        .bind(payload)
+        .bind(now)
+        .bind(now)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+// ==================== DISPATCH ====================
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub event_type: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct DispatchReport {
+    pub delivered: u64,
+    /// Неудачные попытки, которые будут повторены
+    pub retried: u64,
+    /// Перешли в dead letter
+    pub dead: u64,
+}
+
+/// Готовые к доставке события: первые недоставленные в своей сущности и с наступившим временем попытки
+async fn fetch_ready(pool: &SqlitePool, limit: i64) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+    sqlx::query_as(r#"
+        SELECT o.* FROM outbox o
+        WHERE o.status = 'pending'
+          AND datetime(o.next_attempt_at) <= datetime(?)
+          AND NOT EXISTS (
+              SELECT 1 FROM outbox p
+              WHERE p.entity_type = o.entity_type AND p.entity_id = o.entity_id
+                AND p.id < o.id AND p.status IN ('pending', 'dead')
+          )
+        ORDER BY o.id
+        LIMIT ?
+    "#)
+        .bind(Utc::now())
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// Доставить всё, что готово. Повторяет выборку, пока есть успехи: после доставки
+/// события становится доступным следующее в той же сущности
+pub async fn dispatch_pending(
+    pool: &SqlitePool,
+    config: &OutboxConfig,
+    smtp: &SmtpConfig,
+) -> Result<DispatchReport, sqlx::Error> {
+    let mut report = DispatchReport::default();
+    loop {
+        let entries = fetch_ready(pool, config.batch_size).await?;
+        let mut progressed = false;
+        for entry in entries {
+            match deliver(&entry, config, smtp).await {
+                Ok(()) => {
+                    sqlx::query("UPDATE outbox SET status = 'delivered', attempts = attempts + 1, last_error = NULL, delivered_at = ? WHERE id = ?")
+                        .bind(Utc::now())
+                        .bind(entry.id)
+                        .execute(pool)
+                        .await?;
+                    report.delivered += 1;
+                    progressed = true;
+                }
+                Err(error) => {
+                    let attempts = entry.attempts + 1;
+                    let dead = attempts >= config.max_attempts;
+                    let error: String = error.chars().take(MAX_ERROR_LEN).collect();
+                    sqlx::query("UPDATE outbox SET status = ?, attempts = ?, last_error = ?, next_attempt_at = ? WHERE id = ?")
+                        .bind(if dead { "dead" } else { "pending" })
+                        .bind(attempts)
+                        .bind(&error)
+                        .bind(Utc::now() + chrono::Duration::seconds(retry_delay(config, attempts) as i64))
+                        .bind(entry.id)
+                        .execute(pool)
+                        .await?;
+                    if dead {
+                        log::error!(
+                            "Outbox event {} ({} {}:{}) moved to dead letters after {} attempts: {}",
+                            entry.id, entry.event_type, entry.entity_type, entry.entity_id, attempts, error
+                        );
+                        report.dead += 1;
+                    } else {
+                        log::warn!("Outbox event {} delivery failed (attempt {}): {}", entry.id, attempts, error);
+                        report.retried += 1;
+                    }
+                }
+            }
+        }
+        if !progressed {
+            return Ok(report);
+        }
+    }
+}
+
+/// Пауза перед попыткой `attempts + 1`
+fn retry_delay(config: &OutboxConfig, attempts: i64) -> u64 {
+    let exponent = (attempts - 1).clamp(0, 20) as u32;
+    config.retry_base_secs.saturating_mul(1 << exponent).min(MAX_RETRY_DELAY_SECS)
+}
+
+async fn deliver(entry: &OutboxEntry, config: &OutboxConfig, smtp: &SmtpConfig) -> Result<(), String> {
+    // Возвращённый в очередь dead letter сохраняет last_error и повторно не учитывается
+    if entry.attempts == 0 && entry.last_error.is_none() {
+        events::write_line(&entry.payload);
+        match serde_json::from_str::<BusinessEvent>(&entry.payload) {
+            Ok(event) => crate::kpi::record_event(&event),
+            Err(e) => log::warn!("Outbox event {} has an unreadable payload: {}", entry.id, e),
+        }
+    }
+
+    let mut errors = Vec::new();
+    for hook in config.webhooks.iter().filter(|h| h.events.is_empty() || h.events.contains(&entry.event_type)) {
+        if let Err(e) = post_webhook(hook, entry, Duration::from_secs(config.webhook_timeout_secs.max(1))).await {
+            errors.push(format!("{}: {}", hook.url, e));
+        }
+    }
+    let mut to = if config.email_events.contains(&entry.event_type) { config.email_to.clone() } else { Vec::new() };
+    // Личные адресаты события (эскалация обслуживания) - только при настроенной почте
+    if smtp.is_enabled() {
+        if let Ok(event) = serde_json::from_str::<BusinessEvent>(&entry.payload) {
+            for recipient in event.recipients() {
+                if !to.contains(recipient) {
+                    to.push(recipient.clone());
+                }
+            }
+        }
+    }
+    if !to.is_empty() {
+        let email = crate::mailer::Email {
+            to,
+            subject: format!("LIMS: {} ({} {})", entry.event_type, entry.entity_type, entry.entity_id),
+            body: entry.payload.clone(),
+            attachments: Vec::new(),
+        };
+        if let Err(e) = crate::mailer::send(smtp, email).await {
+            errors.push(format!("email: {}", e));
+        }
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors.join("; ")) }
+}
+
+async fn post_webhook(hook: &WebhookConfig, entry: &OutboxEntry, timeout: Duration) -> Result<(), String> {
+    let body = entry.payload.clone().into_bytes();
+    let delivery = entry.id.to_string();
+    let signature = hook.secret.as_deref().map(|secret| format!("sha256={}", hmac_sha256_hex(secret.as_bytes(), &body)));
+
+    let mut headers = vec![
+        ("Content-Type", "application/json"),
+        ("X-LIMS-Event", entry.event_type.as_str()),
+        ("X-LIMS-Delivery", delivery.as_str()),
+    ];
+    if let Some(signature) = signature.as_deref() {
+        headers.push((SIGNATURE_HEADER, signature));
+    }
+    let (status, _) = crate::http_client::request(crate::http_client::Method::POST, &hook.url, &headers, body, timeout).await?;
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", status))
+    }
+}
+
+/// Удалить доставленные события старше delivered_retention_days
+async fn purge_delivered(pool: &SqlitePool, retention_days: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM outbox WHERE status = 'delivered' AND datetime(delivered_at) < datetime('now', ?)"
+    )
+        .bind(format!("-{} days", retention_days.max(0)))
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Фоновый диспетчер: опрос раз в poll_interval_secs, чистка доставленных раз в час
+pub async fn start_dispatcher(pool: SqlitePool, config: OutboxConfig, smtp: SmtpConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.poll_interval_secs.max(1)));
+    let mut last_purge: Option<std::time::Instant> = None;
+    log::info!(
+        "Outbox dispatcher started ({} webhook(s), poll every {}s)",
+        config.webhooks.len(), config.poll_interval_secs
+    );
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = dispatch_pending(&pool, &config, &smtp).await {
+            log::error!("Outbox dispatch failed: {}", e);
+        }
+        if last_purge.is_none_or(|at| at.elapsed() >= Duration::from_secs(3600)) {
+            last_purge = Some(std::time::Instant::now());
+            match purge_delivered(&pool, config.delivered_retention_days).await {
+                Ok(0) => {}
+                Ok(count) => log::info!("Purged {} delivered outbox event(s)", count),
+                Err(e) => log::error!("Failed to purge delivered outbox events: {}", e),
+            }
+        }
+    }
+}
+
+// ==================== ADMIN ====================
+
+#[derive(Debug, Serialize, Default)]
+pub struct OutboxStats {
+    pub pending: i64,
+    pub delivered: i64,
+    pub dead: i64,
+    pub discarded: i64,
+    pub oldest_pending_at: Option<DateTime<Utc>>,
+}
+
+/// GET /admin/outbox - размер очереди по статусам
+pub async fn get_outbox_stats(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let counts: Vec<(String, i64)> = sqlx::query_as("SELECT status, COUNT(*) FROM outbox GROUP BY status")
+        .fetch_all(pool)
+        .await?;
+    let mut stats = OutboxStats::default();
+    for (status, count) in counts {
+        match status.as_str() {
+            "pending" => stats.pending = count,
+            "delivered" => stats.delivered = count,
+            "dead" => stats.dead = count,
+            "discarded" => stats.discarded = count,
+            _ => {}
+        }
+    }
+    stats.oldest_pending_at = sqlx::query_scalar("SELECT MIN(created_at) FROM outbox WHERE status = 'pending'")
+        .fetch_one(pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(stats)))
+}
+
+/// GET /admin/outbox/dead-letters - недоставленные события, старые первыми
+pub async fn get_dead_letters(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<PaginationQuery>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let page = query.page.unwrap_or(1).max(1);
+    let limits = app_state.page_limits(crate::pagination::PageGroup::Other);
+    let per_page = limits.per_page(query.per_page);
+
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox WHERE status = 'dead'")
+        .fetch_one(pool)
+        .await?;
+    let data: Vec<OutboxEntry> = sqlx::query_as("SELECT * FROM outbox WHERE status = 'dead' ORDER BY id LIMIT ? OFFSET ?")
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
+        data,
+        total,
+        page,
+        per_page,
+        total_pages: (total + per_page - 1) / per_page,
+        limits,
+        capped: limits.is_capped(query.per_page),
+    })))
+}
+
+/// Перевести dead letter в `status` (pending - повтор с нуля, discarded - отбросить)
+async fn resolve_dead_letter(pool: &SqlitePool, id: i64, status: &str) -> ApiResult<OutboxEntry> {
+    let result = sqlx::query(
+        "UPDATE outbox SET status = ?, attempts = CASE WHEN ? = 'pending' THEN 0 ELSE attempts END, \
+         next_attempt_at = ? WHERE id = ? AND status = 'dead'"
+    )
+        .bind(status)
+        .bind(status)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        let exists: Option<String> = sqlx::query_scalar("SELECT status FROM outbox WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+        return Err(match exists {
+            Some(current) => ApiError::bad_request(&format!("Outbox event is '{}', only dead letters can be changed", current)),
+            None => ApiError::not_found("Outbox event"),
+        });
+    }
+    Ok(sqlx::query_as("SELECT * FROM outbox WHERE id = ?").bind(id).fetch_one(pool).await?)
+}
+
+/// POST /admin/outbox/{id}/requeue - повторить доставку; очередь сущности продолжится за ним
+pub async fn requeue_dead_letter(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<i64>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let id = path.into_inner();
+    let entry = resolve_dead_letter(&app_state.db_pool, id, "pending").await?;
+    crate::audit::audit(
+        &app_state.db_pool, &claims.sub, "requeue", "outbox", &id.to_string(),
+        &format!("Requeued {} event for {} {}", entry.event_type, entry.entity_type, entry.entity_id), &http_request,
+    ).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(entry)))
+}
+
+/// POST /admin/outbox/{id}/discard - отказаться от доставки и освободить очередь сущности
+pub async fn discard_dead_letter(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<i64>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let id = path.into_inner();
+    let entry = resolve_dead_letter(&app_state.db_pool, id, "discarded").await?;
+    crate::audit::audit(
+        &app_state.db_pool, &claims.sub, "discard", "outbox", &id.to_string(),
+        &format!("Discarded {} event for {} {}", entry.event_type, entry.entity_type, entry.entity_id), &http_request,
+    ).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(entry)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::fixtures::{ETHANOL_BATCH_ID, ETHANOL_ID, NACL_BATCH_ID, NACL_ID, RESEARCHER_ID};
+    use crate::test_support::TestApp;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn consumed(reagent_id: &str, batch_id: &str, remaining_quantity: f64) -> BusinessEvent {
+        BusinessEvent::BatchConsumed {
+            reagent_id: reagent_id.to_string(),
+            batch_id: batch_id.to_string(),
+            quantity: 1.0,
+            unit: "g".to_string(),
+            remaining_quantity,
+        }
+    }
+
+    /// Вебхук-приёмник: отвечает 500, пока `failing`, и отдаёт полученные запросы в канал
+    fn spawn_webhook(failing: Arc<AtomicBool>) -> (String, std::sync::mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut raw = Vec::new();
+                let mut buf = [0u8; 4096];
+                // Читаем заголовки и тело по Content-Length; имена заголовков клиент
+                // шлёт в нижнем регистре, поэтому заголовки приводятся к нему целиком
+                let mut head_len = None;
+                loop {
+                    let n = stream.read(&mut buf).unwrap_or(0);
+                    raw.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&raw).to_string();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        head_len = Some(end);
+                        let length = text[..end].to_lowercase().lines()
+                            .find_map(|l| l.strip_prefix("content-length: ").map(str::to_string))
+                            .and_then(|v| v.trim().parse::<usize>().ok())
+                            .unwrap_or(0);
+                        if raw.len() >= end + 4 + length || n == 0 {
+                            break;
+                        }
+                    } else if n == 0 {
+                        break;
+                    }
+                }
+                let status = if failing.load(Ordering::SeqCst) { "500 Internal Server Error" } else { "200 OK" };
+                let _ = stream.write_all(
+                    format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).as_bytes(),
+                );
+                let text = String::from_utf8_lossy(&raw).to_string();
+                let end = head_len.unwrap_or(text.len());
+                let _ = tx.send(format!("{}{}", text[..end].to_lowercase(), &text[end..]));
+            }
+        });
+        (url, rx)
+    }
+
+    fn statuses(entries: &[(i64, String)]) -> Vec<&str> {
+        entries.iter().map(|(_, s)| s.as_str()).collect()
+    }
+
+    async fn outbox_statuses(pool: &SqlitePool) -> Vec<(i64, String)> {
+        sqlx::query_as("SELECT id, status FROM outbox ORDER BY id").fetch_all(pool).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_enqueue_follows_the_transaction() {
+        let app = TestApp::new().await;
+        let pool = app.pool.clone();
+
+        let mut tx = pool.begin().await.unwrap();
+        enqueue(&mut *tx, &consumed(ETHANOL_ID, ETHANOL_BATCH_ID, 5.0), Some(RESEARCHER_ID)).await.unwrap();
+        tx.rollback().await.unwrap();
+        assert!(outbox_statuses(&pool).await.is_empty());
+
+        let mut tx = pool.begin().await.unwrap();
+        enqueue(&mut *tx, &consumed(ETHANOL_ID, ETHANOL_BATCH_ID, 5.0), Some(RESEARCHER_ID)).await.unwrap();
+        tx.commit().await.unwrap();
+        let entry: OutboxEntry = sqlx::query_as("SELECT * FROM outbox").fetch_one(&pool).await.unwrap();
+        assert_eq!((entry.event_type.as_str(), entry.entity_type.as_str(), entry.entity_id.as_str()), ("batch_consumed", "batch", ETHANOL_BATCH_ID));
+        let payload: serde_json::Value = serde_json::from_str(&entry.payload).unwrap();
+        assert_eq!(payload["actor"], RESEARCHER_ID);
+        assert_eq!(payload["remaining_quantity"], 5.0);
+    }
+
+    #[actix_web::test]
+    async fn test_dispatch_retries_dead_letters_and_preserves_entity_order() {
+        let app = TestApp::new().await;
+        let pool = app.pool.clone();
+        let failing = Arc::new(AtomicBool::new(true));
+        let (url, received) = spawn_webhook(failing.clone());
+        let config = OutboxConfig {
+            max_attempts: 2,
+            retry_base_secs: 0,
+            webhooks: vec![WebhookConfig { url, secret: Some("s3cret".to_string()), events: Vec::new() }],
+            ..Default::default()
+        };
+        let smtp = SmtpConfig::default();
+
+        for event in [
+            consumed(ETHANOL_ID, ETHANOL_BATCH_ID, 4.0),
+            consumed(ETHANOL_ID, ETHANOL_BATCH_ID, 3.0),
+            consumed(NACL_ID, NACL_BATCH_ID, 9.0),
+        ] {
+            let mut tx = pool.begin().await.unwrap();
+            enqueue(&mut *tx, &event, None).await.unwrap();
+            tx.commit().await.unwrap();
+        }
+
+        // Приёмник недоступен: голова очереди каждой партии пробуется, второе событие этанола ждёт
+        let report = dispatch_pending(&pool, &config, &smtp).await.unwrap();
+        assert_eq!(report, DispatchReport { delivered: 0, retried: 2, dead: 0 });
+        let report = dispatch_pending(&pool, &config, &smtp).await.unwrap();
+        assert_eq!(report, DispatchReport { delivered: 0, retried: 0, dead: 2 });
+        assert_eq!(statuses(&outbox_statuses(&pool).await), vec!["dead", "pending", "dead"]);
+        while received.try_recv().is_ok() {}
+
+        // Dead letter держит очередь своей сущности
+        failing.store(false, Ordering::SeqCst);
+        assert_eq!(dispatch_pending(&pool, &config, &smtp).await.unwrap().delivered, 0);
+
+        resolve_dead_letter(&pool, 1, "pending").await.unwrap();
+        resolve_dead_letter(&pool, 3, "discarded").await.unwrap();
+        assert!(matches!(resolve_dead_letter(&pool, 3, "pending").await, Err(ApiError::BadRequest(_))));
+        assert!(matches!(resolve_dead_letter(&pool, 99, "pending").await, Err(ApiError::NotFound(_))));
+
+        let report = dispatch_pending(&pool, &config, &smtp).await.unwrap();
+        assert_eq!(report.delivered, 2);
+        assert_eq!(statuses(&outbox_statuses(&pool).await), vec!["delivered", "delivered", "discarded"]);
+
+        let first = received.recv().unwrap();
+        let second = received.recv().unwrap();
+        assert!(first.contains("x-lims-delivery: 1") && first.contains("\"remaining_quantity\":4.0"));
+        assert!(second.contains("x-lims-delivery: 2"));
+        let body = &first[first.find("\r\n\r\n").unwrap() + 4..];
+        assert!(first.contains(&format!("{}: sha256={}", SIGNATURE_HEADER.to_lowercase(), hmac_sha256_hex(b"s3cret", body.as_bytes()))));
+    }
+}
@@ -57,6 +57,75 @@ pub fn decode_cursor_datetime(cursor: &str) -> Option<(i64, String)> {
     Some((ts, id))
 }
 
+// ==================== PER_PAGE LIMITS ====================
+
+/// Верхняя граница пределов в `[pagination]` (печать этикеток - до 1000 партий за запрос)
+pub const MAX_PAGE_SIZE: i64 = 10_000;
+
+/// Группа списков со своими пределами per_page (`[pagination.<group>]`);
+/// Other - служебные списки (оповещения, согласования, outbox, история расхода) с общими пределами
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageGroup {
+    Reagents,
+    Batches,
+    Experiments,
+    Equipment,
+    Other,
+}
+
+impl PageGroup {
+    /// Группы, которые можно переопределить в конфигурации
+    pub const CONFIGURABLE: [PageGroup; 4] = [Self::Reagents, Self::Batches, Self::Experiments, Self::Equipment];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Reagents => "reagents",
+            Self::Batches => "batches",
+            Self::Experiments => "experiments",
+            Self::Equipment => "equipment",
+            Self::Other => "other",
+        }
+    }
+
+    fn builtin_default_per_page(self) -> i64 {
+        match self {
+            Self::Reagents => 50,
+            _ => 20,
+        }
+    }
+}
+
+/// Действующие пределы per_page; возвращаются в метаданных списка, чтобы клиент знал,
+/// сколько строк можно запросить
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PageLimits {
+    pub default_per_page: i64,
+    pub max_per_page: i64,
+}
+
+impl PageLimits {
+    /// per_page запроса в пределах группы; без параметра - default_per_page
+    pub fn per_page(&self, requested: Option<i64>) -> i64 {
+        requested.unwrap_or(self.default_per_page).clamp(1, self.max_per_page)
+    }
+
+    /// Запрошено больше максимума: страница урезана, в ответе `capped: true` (не ошибка)
+    pub fn is_capped(&self, requested: Option<i64>) -> bool {
+        requested.is_some_and(|n| n > self.max_per_page)
+    }
+}
+
+/// Пределы группы: `[pagination.<group>]`, затем общие `pagination.default_per_page`
+/// и настройка max_per_page
+pub fn page_limits(config: &crate::config::PaginationConfig, group: PageGroup) -> PageLimits {
+    let overrides = config.group(group).copied().unwrap_or_default();
+    let max_per_page = overrides.max.unwrap_or_else(crate::handlers::max_per_page).max(1);
+    let default_per_page = overrides.default
+        .or(config.default_per_page)
+        .unwrap_or_else(|| group.builtin_default_per_page());
+    PageLimits { default_per_page: default_per_page.clamp(1, max_per_page), max_per_page }
+}
+
 // ==================== QUERY PARAMETERS ====================
 
 #[derive(Debug, Deserialize, Clone)]
@@ -74,17 +143,31 @@ pub struct HybridPaginationQuery {
     pub q: Option<String>,          // Frontend naming (alias for search)
     pub status: Option<String>,
     pub manufacturer: Option<String>,
+    /// Агрегатное состояние (мультивыбор: ?physical_state=solid,liquid)
+    pub physical_state: Option<String>,
     pub has_stock: Option<bool>,
+    /// Только реагенты команды
+    pub team_id: Option<String>,
+    /// Созданные текущим пользователем или его командами
+    pub mine: Option<bool>,
+    /// Показывать деактивированные реагенты (по умолчанию скрыты, если `status` не задан)
+    pub include_inactive: Option<bool>,
 
     // Sorting
     pub sort_by: Option<String>,
     pub sort_order: Option<String>,
+
+    // Partial response: `?fields=id,name,status`
+    pub fields: Option<String>,
+
+    // Закреплённые пользователем реагенты первыми (+ is_favorite в строках)
+    pub favorites_first: Option<bool>,
 }
 
 impl HybridPaginationQuery {
-    pub fn normalize(&self) -> (i64, i64, i64) {
+    pub fn normalize(&self, limits: PageLimits) -> (i64, i64, i64) {
         let page = self.page.unwrap_or(1).max(1);
-        let per_page = self.per_page.unwrap_or(50).clamp(1, 100);
+        let per_page = limits.per_page(self.per_page);
         let offset = (page - 1) * per_page;
         (page, per_page, offset)
     }
@@ -179,6 +262,9 @@ pub struct HybridPaginatedResponse<T> {
     pub data: Vec<T>,
     pub pagination: HybridPaginationInfo,
     pub sorting: SortingInfo,
+    pub limits: PageLimits,
+    /// per_page запроса превысил max_per_page и был урезан
+    pub capped: bool,
 }
 
 // ==================== CTE PAGINATION BUILDER ====================
@@ -208,6 +294,29 @@ pub struct CtePaginationBuilder {
     keyset_params: Vec<String>,       // Параметры для keyset (НЕ используются в COUNT)
     limit: i64,
     keyset_condition: Option<String>,
+    joins: Vec<String>,
+    join_params: Vec<String>,     // Параметры JOIN идут перед параметрами фильтров
+    extra_columns: Vec<String>,
+    leading_order: Option<String>,
+}
+
+/// Квалифицирует колонки SELECT алиасом `r` для CTE-запроса:
+/// `ids` тоже содержит id и колонку сортировки, без алиаса они неоднозначны
+fn qualify_select_columns(columns: &str) -> String {
+    columns
+        .split(',')
+        .map(|c| {
+            let c = c.trim();
+            if c == "*" {
+                "r.*".to_string()
+            } else if c.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_') {
+                format!("r.{}", c)
+            } else {
+                c.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl CtePaginationBuilder {
@@ -222,6 +331,10 @@ impl CtePaginationBuilder {
             keyset_params: Vec::new(),
             limit: 50,
             keyset_condition: None,
+            joins: Vec::new(),
+            join_params: Vec::new(),
+            extra_columns: Vec::new(),
+            leading_order: None,
         }
     }
 
@@ -252,6 +365,16 @@ impl CtePaginationBuilder {
         self
     }
 
+    /// `field IN (?, ...)`; пустой список значений - без условия
+    pub fn add_in_clause(&mut self, field: &str, values: &[String]) -> &mut Self {
+        if !values.is_empty() {
+            let placeholders = vec!["?"; values.len()].join(", ");
+            self.conditions.push(format!("{} IN ({})", field, placeholders));
+            self.filter_params.extend(values.iter().cloned());
+        }
+        self
+    }
+
     /// Добавляет условие с множественными параметрами (для LIKE поиска)
     pub fn add_search(&mut self, condition: &str, params: Vec<String>) -> &mut Self {
         self.conditions.push(condition.to_string());
@@ -259,6 +382,26 @@ impl CtePaginationBuilder {
         self
     }
 
+    /// LEFT JOIN для offset-запроса (build_simple); на COUNT и CTE не влияет.
+    /// Колонки присоединяемой таблицы не должны пересекаться с колонками фильтров.
+    pub fn left_join(&mut self, join: &str, params: Vec<String>) -> &mut Self {
+        self.joins.push(format!("LEFT JOIN {}", join));
+        self.join_params.extend(params);
+        self
+    }
+
+    /// Дополнительное выражение в SELECT (например, флаг из присоединённой таблицы)
+    pub fn select_extra(&mut self, expression: &str) -> &mut Self {
+        self.extra_columns.push(expression.to_string());
+        self
+    }
+
+    /// Сортировка, применяемая до основной (например, закреплённые строки сверху)
+    pub fn order_first_by(&mut self, expression: &str) -> &mut Self {
+        self.leading_order = Some(expression.to_string());
+        self
+    }
+
     /// Добавляет keyset условие для cursor-based пагинации
     ///
     /// Для DESC: (sort_col, id) < (cursor_value, cursor_id)
@@ -337,7 +480,7 @@ impl CtePaginationBuilder {
                           where_clause = where_clause,
                           order_dir = order_dir,
                           secondary_order = secondary_order,
-                          select_cols = self.select_columns.replace("*", "r.*"),
+                          select_cols = qualify_select_columns(&self.select_columns),
         );
 
         // Собираем все параметры: filter + keyset + limit
@@ -358,22 +501,35 @@ impl CtePaginationBuilder {
             format!("WHERE {}", self.conditions.join(" AND "))
         };
 
+        let mut select_cols = self.select_columns.clone();
+        for extra in &self.extra_columns {
+            select_cols.push_str(", ");
+            select_cols.push_str(extra);
+        }
+        let leading_order = self.leading_order.as_ref()
+            .map(|order| format!("{}, ", order))
+            .unwrap_or_default();
+
         let sql = format!(r#"
             SELECT {select_cols}
             FROM {table}
+            {joins}
             {where_clause}
-            ORDER BY {sort_col} {sort_order}, id {secondary_order}
+            ORDER BY {leading_order}{sort_col} {sort_order}, {table}.id {secondary_order}
             LIMIT ? OFFSET ?
         "#,
-                          select_cols = self.select_columns,
+                          select_cols = select_cols,
                           table = self.table,
+                          joins = self.joins.join(" "),
                           where_clause = where_clause,
+                          leading_order = leading_order,
                           sort_col = self.sort_column,
                           sort_order = self.sort_order,
                           secondary_order = secondary_order,
         );
 
-        let mut all_params = self.filter_params.clone();
+        let mut all_params = self.join_params.clone();
+        all_params.extend(self.filter_params.clone());
         all_params.push(self.limit.to_string());
         all_params.push(offset.to_string());
 
@@ -427,6 +583,45 @@ impl ReagentSortWhitelist {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_page_limits_resolve_group_then_global() {
+        use crate::config::{PageLimitsConfig, PaginationConfig};
+
+        let config = PaginationConfig::default();
+        let reagents = page_limits(&config, PageGroup::Reagents);
+        assert_eq!(reagents, PageLimits { default_per_page: 50, max_per_page: crate::handlers::MAX_PER_PAGE });
+        assert_eq!(page_limits(&config, PageGroup::Other).default_per_page, 20);
+
+        let config = PaginationConfig {
+            default_per_page: Some(30),
+            batches: PageLimitsConfig { default: None, max: Some(1000) },
+            equipment: PageLimitsConfig { default: Some(500), max: Some(10) },
+            ..Default::default()
+        };
+        let batches = page_limits(&config, PageGroup::Batches);
+        assert_eq!(batches, PageLimits { default_per_page: 30, max_per_page: 1000 });
+        assert_eq!(batches.per_page(Some(1000)), 1000);
+        assert!(!batches.is_capped(Some(1000)));
+        assert_eq!(page_limits(&config, PageGroup::Reagents).default_per_page, 30);
+
+        // default группы не больше её max; запрос сверх max урезается
+        let equipment = page_limits(&config, PageGroup::Equipment);
+        assert_eq!(equipment, PageLimits { default_per_page: 10, max_per_page: 10 });
+        assert_eq!(equipment.per_page(Some(50)), 10);
+        assert!(equipment.is_capped(Some(50)));
+        assert_eq!(equipment.per_page(Some(0)), 1);
+        assert!(!equipment.is_capped(None));
+    }
+
+    #[test]
+    fn test_reagent_sort_whitelist_rejects_hostile_input() {
+        assert_eq!(ReagentSortWhitelist::validate("name"), "name");
+        assert_eq!(ReagentSortWhitelist::validate("name; DROP TABLE reagents"), "total_quantity");
+        assert_eq!(ReagentSortWhitelist::validate("r.name"), "total_quantity");
+        assert_eq!(ReagentSortWhitelist::validate_order("asc"), "ASC");
+        assert_eq!(ReagentSortWhitelist::validate_order("ASC; DROP TABLE reagents"), "DESC");
+    }
+
     #[test]
     fn test_hex_encode_decode() {
         let original = "hello world";
@@ -484,6 +679,19 @@ mod tests {
         assert!(sql.contains("WITH ids AS"));
         assert!(sql.contains("INNER JOIN ids"));
         assert_eq!(params.len(), 5); // status + 3 keyset + limit
+        assert!(sql.contains("SELECT r.*"));
+    }
+
+    #[test]
+    fn test_cte_builder_qualifies_columns() {
+        let builder = CtePaginationBuilder::new("reagents")
+            .select("id, name, total_quantity, r.status")
+            .sort("total_quantity", "DESC")
+            .limit(10);
+
+        let (sql, _) = builder.build_cte("next", true);
+
+        assert!(sql.contains("SELECT r.id, r.name, r.total_quantity, r.status"));
     }
 
     #[test]
@@ -505,6 +713,21 @@ mod tests {
         assert_eq!(params.len(), 4); // 2 search + limit + offset
     }
 
+    #[test]
+    fn test_cte_builder_with_in_clause() {
+        let mut builder = CtePaginationBuilder::new("reagents")
+            .sort("total_quantity", "DESC")
+            .limit(50);
+
+        builder.add_in_clause("status", &["active".to_string(), "inactive".to_string()]);
+        builder.add_in_clause("manufacturer", &[]);
+
+        let (count_sql, count_params) = builder.build_count();
+        assert!(count_sql.contains("status IN (?, ?)"));
+        assert!(!count_sql.contains("manufacturer"));
+        assert_eq!(count_params, vec!["active", "inactive"]);
+    }
+
     #[test]
     fn test_sort_whitelist() {
         assert_eq!(ReagentSortWhitelist::validate("total_quantity"), "total_quantity");
@@ -528,9 +751,15 @@ mod tests {
             q: None,
             status: None,
             manufacturer: None,
+            physical_state: None,
             has_stock: None,
+            team_id: None,
+            mine: None,
+            include_inactive: None,
             sort_by: None,
             sort_order: None,
+            fields: None,
+            favorites_first: None,
         };
         assert_eq!(query.get_search(), Some("acetone"));
 
@@ -544,9 +773,15 @@ mod tests {
             q: Some("benzene".to_string()),
             status: None,
             manufacturer: None,
+            physical_state: None,
             has_stock: None,
+            team_id: None,
+            mine: None,
+            include_inactive: None,
             sort_by: None,
             sort_order: None,
+            fields: None,
+            favorites_first: None,
         };
         assert_eq!(query2.get_search(), Some("benzene"));
 
@@ -560,9 +795,15 @@ mod tests {
             q: None,
             status: None,
             manufacturer: None,
+            physical_state: None,
             has_stock: None,
+            team_id: None,
+            mine: None,
+            include_inactive: None,
             sort_by: None,
             sort_order: None,
+            fields: None,
+            favorites_first: None,
         };
         assert_eq!(query3.get_search(), None);
     }
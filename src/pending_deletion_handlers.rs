@@ -0,0 +1,601 @@
+// src/pending_deletion_handlers.rs
+//! Отложенное удаление реагентов, партий и оборудования.
+//!
+//! DELETE не удаляет объект сразу: он скрывается из списков на `undo_window_minutes`
+//! минут, а клиент получает `undo_token` для `POST /undo/{token}`. По истечении окна
+//! задача обслуживания завершает удаление той же логикой, что и немедленное удаление
+//! (`?immediate=true`, только для администраторов). Реагенты и партии при этом
+//! остаются мягко удалёнными (deleted_at), оборудование удаляется каскадно.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::auth::{get_current_user, Claims, UserRole};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{Batch, Equipment, Reagent};
+use crate::AppState;
+
+const UNDO_TOKEN_PREFIX: &str = "undo_";
+const UNDO_TOKEN_LENGTH: usize = 32;
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeferredEntity {
+    Reagent,
+    Batch,
+    Equipment,
+}
+
+impl DeferredEntity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeferredEntity::Reagent => "reagent",
+            DeferredEntity::Batch => "batch",
+            DeferredEntity::Equipment => "equipment",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reagent" => Some(DeferredEntity::Reagent),
+            "batch" => Some(DeferredEntity::Batch),
+            "equipment" => Some(DeferredEntity::Equipment),
+            _ => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            DeferredEntity::Reagent => "Reagent",
+            DeferredEntity::Batch => "Batch",
+            DeferredEntity::Equipment => "Equipment",
+        }
+    }
+}
+
+/// Параметры DELETE для реагентов, партий и оборудования
+#[derive(Debug, Deserialize, Default)]
+pub struct DeleteQuery {
+    /// Удалить сразу, без окна отмены (только администраторы)
+    pub immediate: Option<bool>,
+}
+
+/// Ответ DELETE: токен отмены возвращается один раз и в базе хранится только его хэш
+#[derive(Debug, Serialize)]
+pub struct DeferredDeletion {
+    pub id: String,
+    pub entity_type: &'static str,
+    pub undo_token: String,
+    pub finalize_after: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct PendingDeletionRow {
+    id: String,
+    entity_type: String,
+    entity_id: String,
+    parent_id: Option<String>,
+    previous_status: Option<String>,
+    requested_by: Option<String>,
+    finalize_after: DateTime<Utc>,
+}
+
+/// Элемент очереди GET /admin/pending-deletions
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PendingDeletion {
+    pub id: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub parent_id: Option<String>,
+    pub entity_name: Option<String>,
+    pub requested_by: Option<String>,
+    pub requested_by_username: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    pub finalize_after: DateTime<Utc>,
+}
+
+// ==================== TOKENS ====================
+
+fn generate_undo_token() -> String {
+    let random: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(UNDO_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    format!("{}{}", UNDO_TOKEN_PREFIX, random)
+}
+
+fn hash_undo_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// `?immediate=true` обходит окно отмены и доступен только администраторам
+pub fn is_immediate(query: &DeleteQuery, claims: &Claims) -> ApiResult<bool> {
+    let immediate = query.immediate.unwrap_or(false);
+    if immediate && claims.role != UserRole::Admin {
+        return Err(ApiError::Forbidden("Only admins can delete immediately".to_string()));
+    }
+    Ok(immediate)
+}
+
+fn undo_window() -> Duration {
+    Duration::minutes(crate::settings::settings().get_i64(crate::settings::UNDO_WINDOW_MINUTES).max(1))
+}
+
+// ==================== DEFER ====================
+
+/// Перевести объект в состояние ожидания удаления. Для партии `parent_id` - её реагент.
+pub async fn defer_delete(
+    pool: &SqlitePool,
+    entity: DeferredEntity,
+    entity_id: &str,
+    parent_id: Option<&str>,
+    user_id: &str,
+) -> ApiResult<DeferredDeletion> {
+    let current: Option<(String, String)> = match entity {
+        DeferredEntity::Reagent => {
+            sqlx::query_as("SELECT name, status FROM reagents WHERE id = ? AND deleted_at IS NULL")
+                .bind(entity_id)
+                .fetch_optional(pool)
+                .await?
+        }
+        DeferredEntity::Batch => {
+            sqlx::query_as(
+                "SELECT batch_number, status FROM batches WHERE id = ? AND reagent_id = ? AND deleted_at IS NULL"
+            )
+                .bind(entity_id)
+                .bind(parent_id)
+                .fetch_optional(pool)
+                .await?
+        }
+        DeferredEntity::Equipment => {
+            sqlx::query_as("SELECT name, status FROM equipment WHERE id = ? AND pending_deletion_id IS NULL")
+                .bind(entity_id)
+                .fetch_optional(pool)
+                .await?
+        }
+    };
+    let (name, status) = current.ok_or_else(|| ApiError::not_found(entity.label()))?;
+
+    let id = Uuid::new_v4().to_string();
+    let token = generate_undo_token();
+    let now = Utc::now();
+    let finalize_after = now + undo_window();
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"INSERT INTO pending_deletions
+           (id, entity_type, entity_id, parent_id, entity_name, previous_status,
+            undo_token_hash, requested_by, requested_at, finalize_after)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(entity.as_str())
+        .bind(entity_id)
+        .bind(parent_id)
+        .bind(&name)
+        .bind(&status)
+        .bind(hash_undo_token(&token))
+        .bind(user_id)
+        .bind(now)
+        .bind(finalize_after)
+        .execute(&mut *tx)
+        .await?;
+
+    match entity {
+        DeferredEntity::Reagent => {
+            sqlx::query(
+                "UPDATE reagents SET deleted_at = datetime('now'), updated_by = ?, status = 'inactive', pending_deletion_id = ? WHERE id = ?"
+            )
+                .bind(user_id)
+                .bind(&id)
+                .bind(entity_id)
+                .execute(&mut *tx)
+                .await?;
+
+            // Партии реагента удаляются и восстанавливаются вместе с ним
+            sqlx::query(
+                "UPDATE batches SET deleted_at = datetime('now'), updated_by = ?, pending_deletion_id = ? WHERE reagent_id = ? AND deleted_at IS NULL"
+            )
+                .bind(user_id)
+                .bind(&id)
+                .bind(entity_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        DeferredEntity::Batch => {
+            sqlx::query("UPDATE batches SET deleted_at = datetime('now'), updated_by = ?, pending_deletion_id = ? WHERE id = ?")
+                .bind(user_id)
+                .bind(&id)
+                .bind(entity_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        DeferredEntity::Equipment => {
+            sqlx::query("UPDATE equipment SET pending_deletion_id = ?, updated_by = ? WHERE id = ?")
+                .bind(&id)
+                .bind(user_id)
+                .bind(entity_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    log::info!(
+        "🗑️ {} {} scheduled for deletion by user {} (finalize after {})",
+        entity.label(), entity_id, user_id, finalize_after
+    );
+
+    Ok(DeferredDeletion {
+        id,
+        entity_type: entity.as_str(),
+        undo_token: token,
+        finalize_after,
+    })
+}
+
+impl DeferredDeletion {
+    /// Описание для журнала аудита
+    pub fn audit_note(&self) -> String {
+        format!("undo available until {}", self.finalize_after.to_rfc3339())
+    }
+
+    pub fn into_response(self, label: &str) -> HttpResponse {
+        let message = format!(
+            "{} scheduled for deletion; it can be restored with the undo token until {}",
+            label, self.finalize_after.to_rfc3339()
+        );
+        HttpResponse::Ok().json(ApiResponse::success_with_message(self, message))
+    }
+}
+
+// ==================== UNDO ====================
+
+/// POST /undo/{token} - восстановить объект; доступно автору удаления и администраторам
+pub async fn undo_deletion(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let token = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    let pending: PendingDeletionRow = sqlx::query_as(
+        r#"SELECT id, entity_type, entity_id, parent_id, previous_status, requested_by, finalize_after
+           FROM pending_deletions WHERE undo_token_hash = ?"#
+    )
+        .bind(hash_undo_token(token.trim()))
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Pending deletion"))?;
+
+    if claims.role != UserRole::Admin && pending.requested_by.as_deref() != Some(claims.sub.as_str()) {
+        return Err(ApiError::Forbidden("Only the user who deleted this item or an admin can undo the deletion".to_string()));
+    }
+    if pending.finalize_after <= Utc::now() {
+        return Err(ApiError::bad_request("The undo window for this deletion has expired"));
+    }
+
+    let entity = DeferredEntity::parse(&pending.entity_type)
+        .ok_or_else(|| ApiError::InternalServerError(format!("Unknown pending deletion type '{}'", pending.entity_type)))?;
+
+    if entity == DeferredEntity::Batch {
+        let reagent_deleted: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM reagents WHERE id = ? AND deleted_at IS NOT NULL)"
+        )
+            .bind(&pending.parent_id)
+            .fetch_one(pool)
+            .await?;
+        if reagent_deleted {
+            return Err(ApiError::bad_request("The batch's reagent has been deleted; restore the reagent first"));
+        }
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let restored = match entity {
+        DeferredEntity::Reagent => {
+            let result = sqlx::query(
+                r#"UPDATE reagents SET deleted_at = NULL, pending_deletion_id = NULL,
+                       status = COALESCE(?, status), updated_by = ?
+                   WHERE pending_deletion_id = ?"#
+            )
+                .bind(&pending.previous_status)
+                .bind(&claims.sub)
+                .bind(&pending.id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE batches SET deleted_at = NULL, pending_deletion_id = NULL, updated_by = ? WHERE pending_deletion_id = ?")
+                .bind(&claims.sub)
+                .bind(&pending.id)
+                .execute(&mut *tx)
+                .await?;
+            result.rows_affected()
+        }
+        DeferredEntity::Batch => {
+            sqlx::query("UPDATE batches SET deleted_at = NULL, pending_deletion_id = NULL, updated_by = ? WHERE pending_deletion_id = ?")
+                .bind(&claims.sub)
+                .bind(&pending.id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+        }
+        DeferredEntity::Equipment => {
+            sqlx::query("UPDATE equipment SET pending_deletion_id = NULL, updated_by = ? WHERE pending_deletion_id = ?")
+                .bind(&claims.sub)
+                .bind(&pending.id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+        }
+    };
+
+    sqlx::query("DELETE FROM pending_deletions WHERE id = ?")
+        .bind(&pending.id)
+        .execute(&mut *tx)
+        .await?;
+
+    if restored == 0 {
+        // Объект уже удалён окончательно (например, повторным DELETE ?immediate=true)
+        tx.commit().await?;
+        return Err(ApiError::not_found(entity.label()));
+    }
+
+    tx.commit().await?;
+
+    let data = match entity {
+        DeferredEntity::Reagent => {
+            crate::reagent_handlers::refresh_reagent_cache(pool, &pending.entity_id).await?;
+            let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+                .bind(&pending.entity_id)
+                .fetch_one(pool)
+                .await?;
+            serde_json::to_value(reagent)
+        }
+        DeferredEntity::Batch => {
+            let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ?")
+                .bind(&pending.entity_id)
+                .fetch_one(pool)
+                .await?;
+            serde_json::to_value(batch)
+        }
+        DeferredEntity::Equipment => {
+            let equipment: Equipment = sqlx::query_as("SELECT * FROM equipment WHERE id = ?")
+                .bind(&pending.entity_id)
+                .fetch_one(pool)
+                .await?;
+            serde_json::to_value(equipment)
+        }
+    }
+    .map_err(|e| ApiError::InternalServerError(e.to_string()))?;
+
+    crate::audit::audit(
+        pool, &claims.sub, "restore", entity.as_str(), &pending.entity_id,
+        &format!("{} restored from pending deletion", entity.label()),
+        &http_request,
+    ).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        serde_json::json!({
+            "entity_type": entity.as_str(),
+            "entity_id": pending.entity_id,
+            "entity": data,
+        }),
+        format!("{} restored successfully", entity.label()),
+    )))
+}
+
+// ==================== QUEUE ====================
+
+/// GET /admin/pending-deletions - удаления в окне отмены, ближайшие к завершению первыми
+pub async fn get_pending_deletions(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let items: Vec<PendingDeletion> = sqlx::query_as(
+        r#"SELECT p.id, p.entity_type, p.entity_id, p.parent_id, p.entity_name,
+                  p.requested_by, u.username AS requested_by_username,
+                  p.requested_at, p.finalize_after
+           FROM pending_deletions p
+           LEFT JOIN users u ON u.id = p.requested_by
+           ORDER BY p.finalize_after"#
+    )
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(items)))
+}
+
+// ==================== FINALIZE ====================
+
+/// Завершить удаления, у которых истекло окно отмены (вызывается задачей обслуживания)
+pub async fn finalize_expired_deletions(pool: &SqlitePool) -> ApiResult<usize> {
+    let expired: Vec<PendingDeletionRow> = sqlx::query_as(
+        r#"SELECT id, entity_type, entity_id, parent_id, previous_status, requested_by, finalize_after
+           FROM pending_deletions WHERE finalize_after <= ? ORDER BY finalize_after"#
+    )
+        .bind(Utc::now())
+        .fetch_all(pool)
+        .await?;
+
+    let mut finalized = 0;
+    for pending in expired {
+        match finalize_deletion(pool, &pending).await {
+            Ok(()) => finalized += 1,
+            Err(e) => log::error!(
+                "Failed to finalize deletion of {} {}: {}",
+                pending.entity_type, pending.entity_id, e
+            ),
+        }
+    }
+    Ok(finalized)
+}
+
+async fn finalize_deletion(pool: &SqlitePool, pending: &PendingDeletionRow) -> ApiResult<()> {
+    match DeferredEntity::parse(&pending.entity_type) {
+        Some(DeferredEntity::Reagent) | Some(DeferredEntity::Batch) => {
+            // Мягкое удаление остаётся; снятие отметки запускает триггеры удаления ссылок
+            let mut tx = pool.begin().await?;
+            sqlx::query("UPDATE reagents SET pending_deletion_id = NULL WHERE pending_deletion_id = ?")
+                .bind(&pending.id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE batches SET pending_deletion_id = NULL WHERE pending_deletion_id = ?")
+                .bind(&pending.id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM pending_deletions WHERE id = ?")
+                .bind(&pending.id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        }
+        Some(DeferredEntity::Equipment) => {
+            crate::equipment_handlers::purge_equipment(pool, &pending.entity_id).await?;
+            sqlx::query("DELETE FROM pending_deletions WHERE id = ?")
+                .bind(&pending.id)
+                .execute(pool)
+                .await?;
+        }
+        None => {
+            return Err(ApiError::InternalServerError(format!(
+                "Unknown pending deletion type '{}'", pending.entity_type
+            )));
+        }
+    }
+
+    log::info!("🗑️ Deletion of {} {} finalized", pending.entity_type, pending.entity_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, HttpMessage};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        for (id, role) in [("u-admin", "admin"), ("u-res", "researcher"), ("u-other", "researcher")] {
+            sqlx::query(
+                "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+                 VALUES (?, ?, ?, 'x', ?, datetime('now'), datetime('now'))"
+            )
+                .bind(id).bind(id).bind(format!("{}@example.com", id)).bind(role)
+                .execute(&pool).await.unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at)
+             VALUES ('r-1', 'Ethanol', 'active', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, status, received_date, created_at, updated_at)
+             VALUES ('b-1', 'r-1', 'B-001', 10, 10, 'ml', 'available', datetime('now'), datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at)
+             VALUES ('e-1', 'Centrifuge', 'equipment', 1, 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO external_links (id, entity_type, entity_id, url, title, created_at)
+             VALUES ('l-1', 'reagent', 'r-1', 'https://example.com/sds', 'SDS', datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        pool
+    }
+
+    fn app_state(pool: &SqlitePool) -> web::Data<Arc<AppState>> {
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    fn request_as(user_id: &str, role: UserRole) -> HttpRequest {
+        let req = test::TestRequest::post().to_http_request();
+        req.extensions_mut().insert(Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{}@example.com", user_id),
+            role,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn count(pool: &SqlitePool, sql: &str) -> i64 {
+        sqlx::query_scalar(sql).fetch_one(pool).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_deferred_delete_undo_and_finalize() {
+        let pool = setup().await;
+        let state = app_state(&pool);
+
+        // Реагент вместе с партией скрыт, ссылки сохраняются до завершения удаления
+        let deferred = defer_delete(&pool, DeferredEntity::Reagent, "r-1", None, "u-res").await.unwrap();
+        assert!(deferred.undo_token.starts_with(UNDO_TOKEN_PREFIX));
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM reagents WHERE deleted_at IS NULL").await, 0);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM batches WHERE deleted_at IS NULL").await, 0);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM external_links").await, 1);
+        assert!(defer_delete(&pool, DeferredEntity::Reagent, "r-1", None, "u-res").await.is_err());
+
+        // Чужой пользователь отменить не может, автор - может
+        let err = undo_deletion(state.clone(), web::Path::from(deferred.undo_token.clone()), request_as("u-other", UserRole::Researcher))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        let resp = undo_deletion(state.clone(), web::Path::from(deferred.undo_token.clone()), request_as("u-res", UserRole::Researcher))
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+        let (status, batch_deleted): (String, Option<String>) = sqlx::query_as(
+            "SELECT r.status, b.deleted_at FROM reagents r JOIN batches b ON b.reagent_id = r.id WHERE r.id = 'r-1' AND r.deleted_at IS NULL"
+        ).fetch_one(&pool).await.unwrap();
+        assert_eq!(status, "active");
+        assert!(batch_deleted.is_none());
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM pending_deletions").await, 0);
+
+        // Токен одноразовый
+        let err = undo_deletion(state.clone(), web::Path::from(deferred.undo_token), request_as("u-admin", UserRole::Admin))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+
+        // Истёкшее окно: реагент остаётся мягко удалённым, ссылки удаляются; оборудование удаляется
+        let reagent = defer_delete(&pool, DeferredEntity::Reagent, "r-1", None, "u-res").await.unwrap();
+        defer_delete(&pool, DeferredEntity::Equipment, "e-1", None, "u-admin").await.unwrap();
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM equipment WHERE pending_deletion_id IS NULL").await, 0);
+        sqlx::query("UPDATE pending_deletions SET finalize_after = ?")
+            .bind(Utc::now() - Duration::minutes(1))
+            .execute(&pool).await.unwrap();
+
+        let err = undo_deletion(state.clone(), web::Path::from(reagent.undo_token), request_as("u-admin", UserRole::Admin))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        assert_eq!(finalize_expired_deletions(&pool).await.unwrap(), 2);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM pending_deletions").await, 0);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM reagents WHERE deleted_at IS NOT NULL AND pending_deletion_id IS NULL").await, 1);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM external_links").await, 0);
+        assert_eq!(count(&pool, "SELECT COUNT(*) FROM equipment").await, 0);
+    }
+}
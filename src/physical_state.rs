@@ -0,0 +1,363 @@
+// src/physical_state.rs
+//! Агрегатное состояние реагентов (`PhysicalState`).
+//!
+//! - свободный текст ("Solid", "powder", "твёрдый") сводится к значению перечисления
+//!   встроенными синонимами (`PhysicalState::from_synonym`) и картой администратора
+//!   `physical_state_mappings`;
+//! - при запуске сохранённые значения reagents.physical_state переписываются на
+//!   канонические; несопоставленные остаются как есть и выводятся в
+//!   GET /admin/normalizations/physical-state;
+//! - PUT /admin/normalizations/physical-state сопоставляет написание и сразу переписывает реагенты;
+//! - `physical_state_summary` - количество активных реагентов по состояниям (/dashboard/stats).
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use strum::VariantNames;
+use validator::Validate;
+
+use crate::audit::ChangeSet;
+use crate::auth::get_current_user;
+use crate::equipment_catalog::variant_key;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::query_builders::PhysicalState;
+use crate::AppState;
+
+/// Канонические значения для `NOT IN (...)` (константы перечисления, не пользовательский ввод)
+fn canonical_list() -> String {
+    PhysicalState::VARIANTS.iter().map(|v| format!("'{}'", v)).collect::<Vec<_>>().join(", ")
+}
+
+// ==================== MAPPER ====================
+
+/// Встроенные синонимы и карта написаний, загруженная для серии записей
+pub struct PhysicalStateMapper {
+    state_by_variant: HashMap<String, PhysicalState>,
+}
+
+impl PhysicalStateMapper {
+    pub async fn load(conn: &mut SqliteConnection) -> Result<Self, sqlx::Error> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT variant, physical_state FROM physical_state_mappings")
+            .fetch_all(conn)
+            .await?;
+        let state_by_variant = rows
+            .into_iter()
+            .filter_map(|(variant, state)| PhysicalState::from_str(&state).ok().map(|state| (variant, state)))
+            .collect();
+        Ok(Self { state_by_variant })
+    }
+
+    pub fn resolve(&self, raw: &str) -> Option<PhysicalState> {
+        PhysicalState::from_synonym(raw).or_else(|| self.state_by_variant.get(&variant_key(raw)).cloned())
+    }
+}
+
+/// Сохранённые неканонические значения с количеством реагентов
+async fn stored_variants(conn: &mut SqliteConnection) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT physical_state, COUNT(*) FROM reagents \
+         WHERE physical_state IS NOT NULL AND physical_state NOT IN ({}) \
+         GROUP BY physical_state ORDER BY physical_state",
+        canonical_list()
+    ))
+        .fetch_all(conn)
+        .await
+}
+
+/// Перезапись сопоставимых значений; пустые строки -> NULL.
+/// Возвращает (переписано реагентов, осталось несопоставленных)
+async fn rewrite_stored_states(
+    conn: &mut SqliteConnection,
+    mapper: &PhysicalStateMapper,
+    updated_at: Option<DateTime<Utc>>,
+) -> Result<(u64, i64), sqlx::Error> {
+    let mut rewritten = 0;
+    let mut unmapped = 0;
+    for (value, count) in stored_variants(&mut *conn).await? {
+        let state = if value.trim().is_empty() {
+            None
+        } else if let Some(state) = mapper.resolve(&value) {
+            Some(state)
+        } else {
+            unmapped += count;
+            continue;
+        };
+        let result = sqlx::query(
+            "UPDATE reagents SET physical_state = ?, updated_at = COALESCE(?, updated_at) WHERE physical_state = ?"
+        )
+            .bind(state.as_ref().map(|s| s.as_ref()))
+            .bind(updated_at)
+            .bind(&value)
+            .execute(&mut *conn)
+            .await?;
+        rewritten += result.rows_affected();
+    }
+    Ok((rewritten, unmapped))
+}
+
+/// Миграция свободного текста старых записей (вызывается из run_migrations)
+pub async fn normalize_stored_states(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let mapper = PhysicalStateMapper::load(&mut conn).await?;
+    let (rewritten, unmapped) = rewrite_stored_states(&mut conn, &mapper, None).await?;
+    if rewritten > 0 {
+        log::info!("Normalized physical_state of {} reagent(s)", rewritten);
+    }
+    if unmapped > 0 {
+        log::warn!(
+            "{} reagent(s) have an unrecognized physical_state; map them via /admin/normalizations/physical-state",
+            unmapped
+        );
+    }
+    Ok(())
+}
+
+// ==================== ADMIN NORMALIZATIONS ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PhysicalStateMapping {
+    pub variant: String,
+    pub physical_state: String,
+    pub updated_by: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct UnmappedPhysicalState {
+    pub value: String,
+    pub reagents: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PhysicalStateNormalizations {
+    pub states: &'static [&'static str],
+    pub mappings: Vec<PhysicalStateMapping>,
+    /// Сохранённые значения, которые не удалось свести к `states`
+    pub unmapped: Vec<UnmappedPhysicalState>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PhysicalStateMappingRequest {
+    #[validate(length(min = 1, max = 255, message = "Variant must be between 1 and 255 characters"))]
+    pub variant: String,
+    /// null удаляет сопоставление
+    pub physical_state: Option<PhysicalState>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PhysicalStateMappingResult {
+    pub variant: String,
+    pub physical_state: Option<PhysicalState>,
+    pub reagents_rewritten: u64,
+}
+
+pub async fn physical_state_normalizations(pool: &SqlitePool) -> ApiResult<PhysicalStateNormalizations> {
+    let mut conn = pool.acquire().await?;
+    let mappings: Vec<PhysicalStateMapping> = sqlx::query_as(
+        "SELECT variant, physical_state, updated_by, updated_at FROM physical_state_mappings ORDER BY physical_state, variant"
+    )
+        .fetch_all(&mut *conn)
+        .await?;
+    let unmapped = stored_variants(&mut conn)
+        .await?
+        .into_iter()
+        .map(|(value, reagents)| UnmappedPhysicalState { value, reagents })
+        .collect();
+    Ok(PhysicalStateNormalizations { states: PhysicalState::VARIANTS, mappings, unmapped })
+}
+
+/// Сопоставление написания со значением PhysicalState и перезапись реагентов
+pub async fn set_physical_state_mapping(
+    pool: &SqlitePool,
+    request: &PhysicalStateMappingRequest,
+    user_id: &str,
+) -> ApiResult<PhysicalStateMappingResult> {
+    request.validate()?;
+    let variant = variant_key(&request.variant);
+    if variant.is_empty() {
+        return Err(ApiError::bad_request("Variant cannot be empty"));
+    }
+    if let Some(builtin) = PhysicalState::from_synonym(&variant) {
+        return Err(ApiError::bad_request(&format!(
+            "'{}' is a built-in spelling of '{}' and cannot be remapped", variant, builtin
+        )));
+    }
+
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+    match request.physical_state {
+        Some(ref state) => {
+            sqlx::query(
+                r#"INSERT INTO physical_state_mappings (variant, physical_state, updated_by, updated_at) VALUES (?, ?, ?, ?)
+                   ON CONFLICT(variant) DO UPDATE SET physical_state = excluded.physical_state,
+                                                      updated_by = excluded.updated_by, updated_at = excluded.updated_at"#
+            )
+                .bind(&variant)
+                .bind(state.as_ref())
+                .bind(user_id)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+        }
+        None => {
+            sqlx::query("DELETE FROM physical_state_mappings WHERE variant = ?")
+                .bind(&variant)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    let mapper = PhysicalStateMapper::load(&mut tx).await?;
+    let (reagents_rewritten, _) = rewrite_stored_states(&mut tx, &mapper, Some(now)).await?;
+    tx.commit().await?;
+
+    Ok(PhysicalStateMappingResult { variant, physical_state: request.physical_state.clone(), reagents_rewritten })
+}
+
+/// GET /admin/normalizations/physical-state
+pub async fn get_physical_state_normalizations(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let normalizations = physical_state_normalizations(&app_state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(normalizations)))
+}
+
+/// PUT /admin/normalizations/physical-state - `{ "variant": "...", "physical_state": "solid" }`
+pub async fn put_physical_state_mapping(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<PhysicalStateMappingRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = get_current_user(&http_request)?.sub;
+    let result = set_physical_state_mapping(&app_state.db_pool, &body, &user_id).await?;
+
+    let state = result.physical_state.as_ref().map(|s| s.to_string()).unwrap_or_default();
+    let mut cs = ChangeSet::new();
+    cs.add("physical_state", "", &state);
+    crate::audit::audit_with_changes(
+        &app_state.db_pool, &user_id, "update_normalization", "physical_state", &result.variant,
+        &format!(
+            "Physical state mapping '{}' -> '{}': {} reagent(s) rewritten",
+            result.variant, state, result.reagents_rewritten
+        ),
+        &cs, &http_request,
+    ).await;
+
+    let message = format!("{} reagent(s) rewritten", result.reagents_rewritten);
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(result, message)))
+}
+
+// ==================== SUMMARY ====================
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PhysicalStateSummary {
+    /// Активные реагенты по каждому значению PhysicalState (включая нулевые)
+    pub by_state: BTreeMap<String, i64>,
+    /// Состояние не указано
+    pub unspecified: i64,
+    /// Значение не сопоставлено (см. /admin/normalizations/physical-state)
+    pub unmapped: i64,
+}
+
+pub async fn physical_state_summary(pool: &SqlitePool) -> ApiResult<PhysicalStateSummary> {
+    let rows: Vec<(Option<String>, i64)> = sqlx::query_as(
+        "SELECT physical_state, COUNT(*) FROM reagents WHERE status = 'active' AND deleted_at IS NULL GROUP BY physical_state"
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let mut summary = PhysicalStateSummary {
+        by_state: PhysicalState::VARIANTS.iter().map(|v| (v.to_string(), 0)).collect(),
+        unspecified: 0,
+        unmapped: 0,
+    };
+    for (state, count) in rows {
+        match state {
+            Some(state) => match summary.by_state.get_mut(&state) {
+                Some(slot) => *slot += count,
+                None => summary.unmapped += count,
+            },
+            None => summary.unspecified += count,
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixtures, TestApp};
+
+    async fn insert_reagent(pool: &SqlitePool, id: &str, state: Option<&str>) {
+        sqlx::query(
+            "INSERT INTO reagents (id, name, physical_state, status, created_at, updated_at) \
+             VALUES (?, ?, ?, 'active', datetime('now'), datetime('now'))"
+        )
+            .bind(id)
+            .bind(format!("Reagent {}", id))
+            .bind(state)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    async fn states(pool: &SqlitePool) -> Vec<(String, Option<String>)> {
+        sqlx::query_as("SELECT id, physical_state FROM reagents WHERE id NOT LIKE 'fx-%' ORDER BY id").fetch_all(pool).await.unwrap()
+    }
+
+    #[test]
+    fn test_synonyms() {
+        assert_eq!(PhysicalState::from_synonym("Solid"), Some(PhysicalState::Solid));
+        assert_eq!(PhysicalState::from_synonym(" powder "), Some(PhysicalState::Solid));
+        assert_eq!(PhysicalState::from_synonym("Твёрдый"), Some(PhysicalState::Solid));
+        assert_eq!(PhysicalState::from_synonym("жидкий"), Some(PhysicalState::Liquid));
+        assert_eq!(PhysicalState::from_synonym("Водный   раствор"), Some(PhysicalState::Solution));
+        assert_eq!(PhysicalState::from_synonym("g"), Some(PhysicalState::Gas));
+        assert_eq!(PhysicalState::from_synonym("paste"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_stored_values_normalized_and_unmapped_reported() {
+        let app = TestApp::new().await;
+        let pool = app.pool.clone();
+        insert_reagent(&pool, "r1", Some("Solid")).await;
+        insert_reagent(&pool, "r2", Some("жидкость")).await;
+        insert_reagent(&pool, "r3", Some("Paste")).await;
+        insert_reagent(&pool, "r4", Some(" ")).await;
+        insert_reagent(&pool, "r5", Some("gas")).await;
+
+        normalize_stored_states(&pool).await.unwrap();
+        assert_eq!(states(&pool).await, vec![
+            ("r1".to_string(), Some("solid".to_string())),
+            ("r2".to_string(), Some("liquid".to_string())),
+            ("r3".to_string(), Some("Paste".to_string())),
+            ("r4".to_string(), None),
+            ("r5".to_string(), Some("gas".to_string())),
+        ]);
+
+        let report = physical_state_normalizations(&pool).await.unwrap();
+        assert_eq!(report.unmapped, vec![UnmappedPhysicalState { value: "Paste".to_string(), reagents: 1 }]);
+        let summary = physical_state_summary(&pool).await.unwrap();
+        assert_eq!(summary.by_state["solid"], 1);
+        assert_eq!(summary.by_state["solution"], 0);
+        // Плюс два реагента фикстур без состояния
+        assert_eq!((summary.unspecified, summary.unmapped), (3, 1));
+
+        // Сопоставление администратора переписывает реагенты и снимает значение с отчёта
+        let request = PhysicalStateMappingRequest { variant: " PASTE".to_string(), physical_state: Some(PhysicalState::Other) };
+        let result = set_physical_state_mapping(&pool, &request, fixtures::ADMIN_ID).await.unwrap();
+        assert_eq!((result.variant.as_str(), result.reagents_rewritten), ("paste", 1));
+        insert_reagent(&pool, "r6", Some("paste")).await;
+        normalize_stored_states(&pool).await.unwrap();
+        let stored = states(&pool).await;
+        assert_eq!(stored[2].1.as_deref(), Some("other"));
+        assert_eq!(stored[5].1.as_deref(), Some("other"));
+        assert!(physical_state_normalizations(&pool).await.unwrap().unmapped.is_empty());
+
+        let builtin = PhysicalStateMappingRequest { variant: "Liquid".to_string(), physical_state: Some(PhysicalState::Gas) };
+        let err = set_physical_state_mapping(&pool, &builtin, fixtures::ADMIN_ID).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(ref m) if m.contains("built-in")), "{:?}", err);
+    }
+}
@@ -235,9 +235,9 @@ pub async fn update_placement(
     )
     .bind(&placement_id)
     .bind(&batch_id)
-    .fetch_one(&app_state.db_pool)
-    .await
-    .map_err(|_| ApiError::not_found("Placement"))?;
+    .fetch_optional(&app_state.db_pool)
+    .await?
+    .ok_or_else(|| ApiError::not_found("Placement"))?;
 
     // Валидация нового количества
     let new_qty = request.quantity.unwrap_or(existing.quantity);
@@ -0,0 +1,375 @@
+// src/protocol_render.rs
+//! Протокол эксперимента: Markdown -> безопасный HTML на сервере.
+//!
+//! GET /experiments/{id}/protocol/rendered
+//!
+//! Ссылки вида `{{reagent:id}}`, `{{batch:id}}`, `{{equipment:id}}` заменяются ссылкой
+//! с названием. Реагенты и партии разрешаются, только если добавлены в эксперимент
+//! (experiment_reagents); списка оборудования у эксперимента нет, поэтому оборудование -
+//! если существует. Остальные ссылки помечаются как висячие (`protocol-ref-dangling`).
+//!
+//! Сырой HTML из протокола проходит через ammonia по whitelist тегов (без script/style/img
+//! и обработчиков событий). Результат кэшируется в памяти по отпечатку протокола и
+//! разрешённых ссылок; изменение протокола или удаление эксперимента сбрасывает запись.
+//! Печатные экспорты протокола используют тот же `render_protocol`.
+
+use actix_web::{web, HttpResponse};
+use pulldown_cmark::{CowStr, Event, Options, Parser, TextMergeStream};
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Предел записей кэша; при переполнении кэш очищается целиком
+const MAX_CACHED_PROTOCOLS: usize = 512;
+
+/// Теги, которые может содержать отрисованный протокол
+const ALLOWED_TAGS: &[&str] = &[
+    "p", "br", "hr", "h1", "h2", "h3", "h4", "h5", "h6",
+    "strong", "em", "del", "code", "pre", "blockquote",
+    "ul", "ol", "li", "a", "span",
+    "table", "thead", "tbody", "tr", "th", "td",
+];
+
+lazy_static::lazy_static! {
+    static ref REFERENCE_TOKEN: Regex =
+        Regex::new(r"\{\{\s*(reagent|batch|equipment):([A-Za-z0-9_-]+)\s*\}\}").unwrap();
+    static ref SANITIZER: ammonia::Builder<'static> = {
+        let mut builder = ammonia::Builder::default();
+        builder
+            .tags(ALLOWED_TAGS.iter().copied().collect())
+            .add_tag_attributes("a", &["class"])
+            .add_tag_attributes("span", &["class", "title"]);
+        builder
+    };
+    static ref CACHE: Mutex<HashMap<String, CachedProtocol>> = Mutex::new(HashMap::new());
+}
+
+struct CachedProtocol {
+    fingerprint: String,
+    rendered: RenderedProtocol,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceKind {
+    Reagent,
+    Batch,
+    Equipment,
+}
+
+impl ReferenceKind {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reagent" => Some(ReferenceKind::Reagent),
+            "batch" => Some(ReferenceKind::Batch),
+            "equipment" => Some(ReferenceKind::Equipment),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ReferenceKind::Reagent => "reagent",
+            ReferenceKind::Batch => "batch",
+            ReferenceKind::Equipment => "equipment",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolReference {
+    pub kind: ReferenceKind,
+    pub id: String,
+    /// Название для ссылки; None - ссылка висячая
+    pub label: Option<String>,
+    pub url: Option<String>,
+    pub resolved: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedProtocol {
+    pub html: String,
+    pub references: Vec<ProtocolReference>,
+    pub dangling_references: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderedProtocolResponse {
+    pub experiment_id: String,
+    #[serde(flatten)]
+    pub rendered: RenderedProtocol,
+    pub cached: bool,
+}
+
+// ==================== REFERENCES ====================
+
+/// Уникальные ссылки протокола в порядке появления
+fn collect_references(protocol: &str) -> Vec<(ReferenceKind, String)> {
+    let mut seen = HashSet::new();
+    REFERENCE_TOKEN
+        .captures_iter(protocol)
+        .filter_map(|c| Some((ReferenceKind::parse(&c[1])?, c[2].to_string())))
+        .filter(|reference| seen.insert(reference.clone()))
+        .collect()
+}
+
+async fn resolve_reference(
+    pool: &SqlitePool,
+    experiment_id: &str,
+    kind: ReferenceKind,
+    id: &str,
+) -> ApiResult<ProtocolReference> {
+    let found: Option<(String, String)> = match kind {
+        ReferenceKind::Reagent => sqlx::query_as(
+            r#"SELECT r.name, '/reagents/' || r.id FROM reagents r
+               WHERE r.id = ? AND r.deleted_at IS NULL
+                 AND EXISTS (SELECT 1 FROM experiment_reagents er
+                             WHERE er.experiment_id = ? AND er.reagent_id = r.id)"#
+        )
+            .bind(id)
+            .bind(experiment_id)
+            .fetch_optional(pool)
+            .await?,
+        ReferenceKind::Batch => sqlx::query_as(
+            r#"SELECT r.name || ' (' || b.batch_number || ')', '/reagents/' || b.reagent_id || '/batches/' || b.id
+               FROM batches b JOIN reagents r ON r.id = b.reagent_id
+               WHERE b.id = ? AND b.deleted_at IS NULL
+                 AND EXISTS (SELECT 1 FROM experiment_reagents er
+                             WHERE er.experiment_id = ? AND er.batch_id = b.id)"#
+        )
+            .bind(id)
+            .bind(experiment_id)
+            .fetch_optional(pool)
+            .await?,
+        ReferenceKind::Equipment => sqlx::query_as(
+            "SELECT name, '/equipment/' || id FROM equipment WHERE id = ? AND pending_deletion_id IS NULL"
+        )
+            .bind(id)
+            .fetch_optional(pool)
+            .await?,
+    };
+
+    Ok(match found {
+        Some((label, url)) => ProtocolReference { kind, id: id.to_string(), label: Some(label), url: Some(url), resolved: true },
+        None => ProtocolReference { kind, id: id.to_string(), label: None, url: None, resolved: false },
+    })
+}
+
+pub(crate) fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn reference_html(reference: &ProtocolReference) -> String {
+    match (&reference.label, &reference.url) {
+        (Some(label), Some(url)) => format!(
+            r#"<a class="protocol-ref protocol-ref-{}" href="{}">{}</a>"#,
+            reference.kind.as_str(), escape_html(url), escape_html(label)
+        ),
+        _ => format!(
+            r#"<span class="protocol-ref-dangling" title="Unresolved {} reference">{{{{{}:{}}}}}</span>"#,
+            reference.kind.as_str(), reference.kind.as_str(), escape_html(&reference.id)
+        ),
+    }
+}
+
+// ==================== RENDERING ====================
+
+/// Markdown -> санитизированный HTML; ссылки в тексте (не в коде) заменяются разметкой ссылок
+pub fn render_markdown(protocol: &str, references: &[ProtocolReference]) -> String {
+    let by_key: HashMap<(ReferenceKind, &str), &ProtocolReference> = references
+        .iter()
+        .map(|r| ((r.kind, r.id.as_str()), r))
+        .collect();
+
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let events = TextMergeStream::new(Parser::new_ext(protocol, options)).flat_map(|event| match event {
+        Event::Text(text) if REFERENCE_TOKEN.is_match(&text) => {
+            let mut parts = Vec::new();
+            let mut last = 0;
+            for captures in REFERENCE_TOKEN.captures_iter(&text) {
+                let whole = captures.get(0).unwrap();
+                let reference = ReferenceKind::parse(&captures[1])
+                    .and_then(|kind| by_key.get(&(kind, &captures[2])));
+                let Some(reference) = reference else { continue };
+                parts.push(Event::Text(CowStr::from(text[last..whole.start()].to_string())));
+                parts.push(Event::InlineHtml(CowStr::from(reference_html(reference))));
+                last = whole.end();
+            }
+            parts.push(Event::Text(CowStr::from(text[last..].to_string())));
+            parts
+        }
+        other => vec![other],
+    });
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    SANITIZER.clean(&html).to_string()
+}
+
+/// Отрисовка протокола эксперимента с разрешением ссылок; bool - взято из кэша
+pub async fn render_protocol(
+    pool: &SqlitePool,
+    experiment_id: &str,
+    protocol: &str,
+) -> ApiResult<(RenderedProtocol, bool)> {
+    let mut references = Vec::new();
+    for (kind, id) in collect_references(protocol) {
+        references.push(resolve_reference(pool, experiment_id, kind, &id).await?);
+    }
+
+    // Отпечаток: текст протокола и то, во что разрешились ссылки (название могли переименовать)
+    let mut hasher = Sha256::new();
+    hasher.update(protocol.as_bytes());
+    for reference in &references {
+        hasher.update(format!("\0{}:{}={:?}", reference.kind.as_str(), reference.id, reference.label).as_bytes());
+    }
+    let fingerprint = format!("{:x}", hasher.finalize());
+
+    if let Some(cached) = CACHE.lock().unwrap().get(experiment_id) {
+        if cached.fingerprint == fingerprint {
+            return Ok((cached.rendered.clone(), true));
+        }
+    }
+
+    let rendered = RenderedProtocol {
+        html: render_markdown(protocol, &references),
+        dangling_references: references.iter().filter(|r| !r.resolved).count(),
+        references,
+    };
+
+    let mut cache = CACHE.lock().unwrap();
+    if cache.len() >= MAX_CACHED_PROTOCOLS {
+        cache.clear();
+    }
+    cache.insert(experiment_id.to_string(), CachedProtocol { fingerprint, rendered: rendered.clone() });
+    Ok((rendered, false))
+}
+
+/// Сброс кэша при изменении протокола или удалении эксперимента
+pub fn invalidate(experiment_id: &str) {
+    CACHE.lock().unwrap().remove(experiment_id);
+}
+
+// ==================== HANDLER ====================
+
+/// GET /experiments/{id}/protocol/rendered
+pub async fn get_rendered_protocol(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let experiment_id = path.into_inner();
+    let protocol: Option<Option<String>> = sqlx::query_scalar("SELECT protocol FROM experiments WHERE id = ?")
+        .bind(&experiment_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?;
+    let protocol = protocol.ok_or_else(|| ApiError::not_found("Experiment"))?.unwrap_or_default();
+
+    let (rendered, cached) = render_protocol(&app_state.db_pool, &experiment_id, &protocol).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(RenderedProtocolResponse {
+        experiment_id,
+        rendered,
+        cached,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at)
+             VALUES ('r-1', 'Acetone <HPLC>', 'active', datetime('now'), datetime('now')),
+                    ('r-2', 'Ethanol', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, status, received_date, created_at, updated_at)
+             VALUES ('b-1', 'r-1', 'LOT-7', 100, 100, 'ml', 'available', datetime('now'), datetime('now'), datetime('now'))",
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at)
+             VALUES ('eq-1', 'HPLC System', 'instrument', 1, 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, created_at, updated_at)
+             VALUES ('e-1', 'Titration', datetime('now'), datetime('now'), 'planned', datetime('now'), datetime('now'))",
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at)
+             VALUES ('er-1', 'e-1', 'r-1', 'b-1', 5, 'ml', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    #[actix_web::test]
+    async fn test_protocol_resolves_linked_references_and_flags_dangling() {
+        let pool = setup().await;
+        let protocol = "## Steps\n\n1. Rinse with {{reagent:r-1}} from {{batch:b-1}}\n\
+                        2. Run on {{ equipment:eq-1 }}, then add {{reagent:r-2}}\n\n\
+                        `{{reagent:r-1}}` stays literal in code";
+
+        let (rendered, cached) = render_protocol(&pool, "e-1", protocol).await.unwrap();
+        assert!(!cached);
+        assert!(rendered.html.contains("<h2>Steps</h2>"), "{}", rendered.html);
+        assert!(rendered.html.contains(r#"href="/reagents/r-1""#), "{}", rendered.html);
+        assert!(rendered.html.contains("Acetone &lt;HPLC&gt;</a>"), "{}", rendered.html);
+        assert!(rendered.html.contains("Acetone &lt;HPLC&gt; (LOT-7)"), "{}", rendered.html);
+        assert!(rendered.html.contains(r#"href="/equipment/eq-1""#), "{}", rendered.html);
+        assert!(rendered.html.contains("<code>{{reagent:r-1}}</code>"), "{}", rendered.html);
+        // Ethanol существует, но не добавлен в эксперимент
+        assert_eq!(rendered.dangling_references, 1);
+        assert!(rendered.html.contains(r#"<span class="protocol-ref-dangling""#), "{}", rendered.html);
+        let dangling = rendered.references.iter().find(|r| !r.resolved).unwrap();
+        assert_eq!((dangling.kind, dangling.id.as_str()), (ReferenceKind::Reagent, "r-2"));
+
+        // Повторный запрос - из кэша; добавление реагента в эксперимент меняет отпечаток
+        assert!(render_protocol(&pool, "e-1", protocol).await.unwrap().1);
+        sqlx::query(
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, planned_quantity, unit, created_at, updated_at)
+             VALUES ('er-2', 'e-1', 'r-2', 1, 'ml', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        let (rendered, cached) = render_protocol(&pool, "e-1", protocol).await.unwrap();
+        assert!(!cached);
+        assert_eq!(rendered.dangling_references, 0);
+
+        invalidate("e-1");
+        assert!(!render_protocol(&pool, "e-1", protocol).await.unwrap().1);
+    }
+
+    #[test]
+    fn test_markdown_is_sanitized() {
+        let html = render_markdown(
+            "Hello <script>alert(1)</script> <img src=x onerror=alert(1)> \
+             [click](javascript:alert(1)) <b onclick=\"x()\">bold</b> **ok**",
+            &[],
+        );
+        assert!(!html.contains("script"), "{}", html);
+        assert!(!html.contains("<img"), "{}", html);
+        assert!(!html.contains("javascript:"), "{}", html);
+        assert!(!html.contains("onclick"), "{}", html);
+        assert!(html.contains("<strong>ok</strong>"), "{}", html);
+    }
+}
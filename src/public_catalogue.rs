@@ -0,0 +1,490 @@
+// src/public_catalogue.rs
+//! Публичный каталог реагентов для других подразделений - только чтение.
+//!
+//! Включается `[public_catalogue] enabled` и работает вне JWT: если задан `access_token`,
+//! нужен общий токен (заголовок `X-Catalogue-Token` или `?token=`), а не учётная запись.
+//! Отдаётся ограниченная проекция реагента - поля из `[public_catalogue] fields`, которые
+//! могут быть только из `CATALOGUE_FIELDS`, - и суммарная доступность по единицам:
+//! без мест хранения, партий и цен. Реагенты с `publicly_visible = 0` не показываются.
+//! Свой лимит запросов на IP клиента, независимый от основного API.
+//!
+//! `GET /catalogue` - та же выборка простой HTML-страницей для тех, кто не работает с API.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::PublicCatalogueConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::{ApiResponse, PaginatedResponse};
+use crate::pagination::PageLimits;
+use crate::protocol_render::escape_html;
+use crate::AppState;
+
+/// Поля реагента, которые вообще можно открыть вне лаборатории
+pub const CATALOGUE_FIELDS: &[&str] = &[
+    "name", "formula", "cas_number", "molecular_weight", "physical_state", "appearance", "hazard_pictograms",
+];
+
+pub const CATALOGUE_TOKEN_HEADER: &str = "x-catalogue-token";
+/// Пределы per_page каталога (не зависят от `[pagination]` основного API)
+const CATALOGUE_PAGE_LIMITS: PageLimits = PageLimits { default_per_page: 20, max_per_page: 100 };
+/// Сверх этого числа клиентов устаревшие окна лимита вычищаются
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+lazy_static::lazy_static! {
+    static ref CATALOGUE_LIMITER: RateLimiter = RateLimiter::default();
+}
+
+// ==================== RATE LIMIT ====================
+
+/// Фиксированное окно запросов на клиента
+#[derive(Default)]
+pub struct RateLimiter {
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    /// Err(секунд до нового окна), если лимит исчерпан
+    pub fn check(&self, client: &str, limit: u32, window: Duration, now: Instant) -> Result<(), u64> {
+        let mut windows = self.windows.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if windows.len() > MAX_TRACKED_CLIENTS {
+            windows.retain(|_, (started, _)| now.duration_since(*started) < window);
+        }
+
+        let entry = windows.entry(client.to_string()).or_insert((now, 0));
+        if now.duration_since(entry.0) >= window {
+            *entry = (now, 0);
+        }
+        if entry.1 >= limit {
+            let remaining = window.saturating_sub(now.duration_since(entry.0));
+            return Err(remaining.as_secs().max(1));
+        }
+        entry.1 += 1;
+        Ok(())
+    }
+}
+
+// ==================== ACCESS ====================
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CatalogueQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    pub search: Option<String>,
+    /// Общий токен для клиентов, которые не могут задать заголовок (ссылка на HTML-страницу)
+    pub token: Option<String>,
+}
+
+/// Каталог выключен - 404, как будто его нет; лимит проверяется до токена,
+/// чтобы токен нельзя было перебирать
+fn authorize_catalogue(req: &HttpRequest, config: &PublicCatalogueConfig, token_param: Option<&str>) -> ApiResult<()> {
+    if !config.enabled {
+        return Err(ApiError::not_found("Catalogue"));
+    }
+
+    let client = crate::client_ip::client_ip(req).unwrap_or_else(|| "unknown".to_string());
+    let window = Duration::from_secs(config.rate_limit_window_seconds);
+    if let Err(retry_after_secs) = CATALOGUE_LIMITER.check(&client, config.rate_limit_requests, window, Instant::now()) {
+        return Err(ApiError::TooManyRequests {
+            message: "Catalogue rate limit exceeded; retry later".to_string(),
+            retry_after_secs,
+        });
+    }
+
+    if let Some(expected) = config.access_token.as_deref().filter(|t| !t.is_empty()) {
+        let provided = req.headers()
+            .get(CATALOGUE_TOKEN_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .or(token_param);
+        // Сравнение хэшей: время не зависит от совпавшего префикса
+        let valid = provided.is_some_and(|p| Sha256::digest(p.as_bytes()) == Sha256::digest(expected.as_bytes()));
+        if !valid {
+            return Err(ApiError::Unauthorized("A valid catalogue access token is required".to_string()));
+        }
+    }
+    Ok(())
+}
+
+// ==================== DATA ====================
+
+/// Суммарное количество, доступное к выдаче (без резервов, просроченных и списанных партий)
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CatalogueAvailability {
+    pub unit: String,
+    pub quantity: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CatalogueEntry {
+    pub id: String,
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+    pub in_stock: bool,
+    pub availability: Vec<CatalogueAvailability>,
+}
+
+/// Разрешённые поля в порядке CATALOGUE_FIELDS (конфигурация проверена при старте)
+fn exposed_fields(config: &PublicCatalogueConfig) -> Vec<&'static str> {
+    CATALOGUE_FIELDS.iter()
+        .copied()
+        .filter(|field| config.fields.iter().any(|f| f == field))
+        .collect()
+}
+
+const VISIBLE_CONDITION: &str = "deleted_at IS NULL AND status = 'active' AND publicly_visible = 1";
+
+/// Доступность по реагентам; совместимые единицы пересчитываются в базовую (g, mL)
+async fn fetch_availability(
+    pool: &SqlitePool,
+    reagent_ids: &[String],
+) -> ApiResult<HashMap<String, Vec<CatalogueAvailability>>> {
+    let mut result: HashMap<String, Vec<CatalogueAvailability>> = HashMap::new();
+    if reagent_ids.is_empty() {
+        return Ok(result);
+    }
+
+    let placeholders = vec!["?"; reagent_ids.len()].join(", ");
+    let sql = format!(
+        r#"SELECT reagent_id, unit, SUM(quantity - reserved_quantity)
+           FROM batches
+           WHERE reagent_id IN ({})
+             AND deleted_at IS NULL
+             AND status = 'available'
+             AND quantity > reserved_quantity
+             AND (expiry_date IS NULL OR datetime(expiry_date) > datetime('now')
+                  OR reagent_id IN (SELECT id FROM reagents WHERE expiry_policy != 'strict'))
+           GROUP BY reagent_id, unit"#,
+        placeholders
+    );
+    let mut query = sqlx::query_as::<_, (String, String, f64)>(&sql);
+    for id in reagent_ids {
+        query = query.bind(id);
+    }
+
+    let converter = crate::validator::UnitConverter::new();
+    for (reagent_id, unit, quantity) in query.fetch_all(pool).await? {
+        let (quantity, unit) = match converter.to_base(quantity, &unit) {
+            Some((base_quantity, base_unit)) => (base_quantity, base_unit.to_string()),
+            None => (quantity, unit),
+        };
+        let entries = result.entry(reagent_id).or_default();
+        match entries.iter_mut().find(|a| a.unit == unit) {
+            Some(existing) => existing.quantity += quantity,
+            None => entries.push(CatalogueAvailability { unit, quantity }),
+        }
+    }
+    for entries in result.values_mut() {
+        for entry in entries.iter_mut() {
+            entry.quantity = (entry.quantity * 1_000_000.0).round() / 1_000_000.0;
+        }
+        entries.sort_by(|a, b| a.unit.cmp(&b.unit));
+    }
+    Ok(result)
+}
+
+async fn into_entries(pool: &SqlitePool, rows: Vec<Map<String, Value>>) -> ApiResult<Vec<CatalogueEntry>> {
+    let ids: Vec<String> = rows.iter()
+        .filter_map(|row| row.get("id").and_then(|v| v.as_str()).map(str::to_string))
+        .collect();
+    let mut availability = fetch_availability(pool, &ids).await?;
+
+    Ok(rows.into_iter()
+        .filter_map(|mut fields| {
+            let id = fields.remove("id")?.as_str()?.to_string();
+            let availability = availability.remove(&id).unwrap_or_default();
+            Some(CatalogueEntry {
+                in_stock: availability.iter().any(|a| a.quantity > 0.0),
+                id,
+                fields,
+                availability,
+            })
+        })
+        .collect())
+}
+
+pub async fn fetch_catalogue(
+    pool: &SqlitePool,
+    config: &PublicCatalogueConfig,
+    query: &CatalogueQuery,
+) -> ApiResult<PaginatedResponse<CatalogueEntry>> {
+    let fields = exposed_fields(config);
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = CATALOGUE_PAGE_LIMITS.per_page(query.per_page);
+
+    // Поиск только по открытым полям, чтобы не угадывать скрытые значения
+    let mut condition = VISIBLE_CONDITION.to_string();
+    let mut params: Vec<String> = Vec::new();
+    if let Some(search) = query.search.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        let searchable: Vec<&str> = ["name", "formula", "cas_number"].into_iter()
+            .filter(|f| fields.contains(f))
+            .collect();
+        if !searchable.is_empty() {
+            let pattern = format!("%{}%", crate::report_handlers::escape_like_pattern(search));
+            let clauses: Vec<String> = searchable.iter().map(|f| format!("{} LIKE ? ESCAPE '\\'", f)).collect();
+            condition.push_str(&format!(" AND ({})", clauses.join(" OR ")));
+            params.extend(std::iter::repeat_n(pattern, searchable.len()));
+        }
+    }
+
+    let count_sql = format!("SELECT COUNT(*) FROM reagents WHERE {}", condition);
+    let mut count_query = sqlx::query_scalar::<_, i64>(&count_sql);
+    for p in &params {
+        count_query = count_query.bind(p);
+    }
+    let total = count_query.fetch_one(pool).await?;
+
+    let columns = std::iter::once("id").chain(fields.iter().copied()).collect::<Vec<_>>().join(", ");
+    let sql = format!(
+        "SELECT {} FROM reagents WHERE {} ORDER BY name COLLATE NOCASE LIMIT {} OFFSET {}",
+        columns, condition, per_page, (page - 1) * per_page
+    );
+    let rows = crate::handlers::fetch_json_rows(pool, &sql, &params, &[]).await?;
+    let data = into_entries(pool, rows).await?;
+
+    Ok(PaginatedResponse {
+        data,
+        total,
+        page,
+        per_page,
+        total_pages: ((total + per_page - 1) / per_page).max(1),
+        limits: CATALOGUE_PAGE_LIMITS,
+        capped: CATALOGUE_PAGE_LIMITS.is_capped(query.per_page),
+    })
+}
+
+async fn fetch_catalogue_entry(pool: &SqlitePool, config: &PublicCatalogueConfig, id: &str) -> ApiResult<CatalogueEntry> {
+    let columns = std::iter::once("id").chain(exposed_fields(config)).collect::<Vec<_>>().join(", ");
+    let sql = format!("SELECT {} FROM reagents WHERE id = ? AND {}", columns, VISIBLE_CONDITION);
+    let rows = crate::handlers::fetch_json_rows(pool, &sql, &[id.to_string()], &[]).await?;
+    into_entries(pool, rows).await?
+        .pop()
+        .ok_or_else(|| ApiError::not_found("Reagent"))
+}
+
+// ==================== HANDLERS ====================
+
+/// GET /api/v1/catalogue?search=&page=&per_page=
+pub async fn list_catalogue(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<CatalogueQuery>,
+    req: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let config = &app_state.config.public_catalogue;
+    authorize_catalogue(&req, config, query.token.as_deref())?;
+    let response = fetch_catalogue(&app_state.db_pool, config, &query).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+}
+
+/// GET /api/v1/catalogue/{id}
+pub async fn get_catalogue_entry(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<CatalogueQuery>,
+    req: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let config = &app_state.config.public_catalogue;
+    authorize_catalogue(&req, config, query.token.as_deref())?;
+    let entry = fetch_catalogue_entry(&app_state.db_pool, config, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(entry)))
+}
+
+fn field_label(field: &str) -> String {
+    let label = field.replace('_', " ");
+    let mut chars = label.chars();
+    chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+fn cell_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn render_catalogue_page(page: &PaginatedResponse<CatalogueEntry>, fields: &[&str], query: &CatalogueQuery) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>Reagent catalogue</title>\n\
+         <style>body{font-family:sans-serif;margin:2rem}table{border-collapse:collapse;width:100%}\
+         th,td{border:1px solid #ccc;padding:.4rem .6rem;text-align:left}th{background:#f4f4f4}\
+         .out{color:#999}</style>\n</head>\n<body>\n<h1>Reagent catalogue</h1>\n",
+    );
+
+    let token_input = query.token.as_deref()
+        .map(|t| format!("<input type=\"hidden\" name=\"token\" value=\"{}\">", escape_html(t)))
+        .unwrap_or_default();
+    html.push_str(&format!(
+        "<form method=\"get\"><input type=\"search\" name=\"search\" value=\"{}\" placeholder=\"Search\">{}<button>Search</button></form>\n",
+        escape_html(query.search.as_deref().unwrap_or("")),
+        token_input,
+    ));
+
+    html.push_str("<table>\n<thead><tr>");
+    for field in fields {
+        html.push_str(&format!("<th>{}</th>", escape_html(&field_label(field))));
+    }
+    html.push_str("<th>Available</th></tr></thead>\n<tbody>\n");
+    for entry in &page.data {
+        html.push_str(if entry.in_stock { "<tr>" } else { "<tr class=\"out\">" });
+        for field in fields {
+            html.push_str(&format!("<td>{}</td>", escape_html(&cell_text(entry.fields.get(*field)))));
+        }
+        let availability = if entry.in_stock {
+            entry.availability.iter()
+                .map(|a| format!("{} {}", a.quantity, a.unit))
+                .collect::<Vec<_>>()
+                .join(", ")
+        } else {
+            "Out of stock".to_string()
+        };
+        html.push_str(&format!("<td>{}</td></tr>\n", escape_html(&availability)));
+    }
+    html.push_str("</tbody>\n</table>\n");
+
+    let link = |target: i64| {
+        let mut params = vec![format!("page={}", target), format!("per_page={}", page.per_page)];
+        if let Some(search) = query.search.as_deref().filter(|s| !s.is_empty()) {
+            params.push(format!("search={}", crate::http_client::percent_encode(search)));
+        }
+        if let Some(token) = query.token.as_deref() {
+            params.push(format!("token={}", crate::http_client::percent_encode(token)));
+        }
+        escape_html(&format!("?{}", params.join("&")))
+    };
+    html.push_str(&format!("<p>Page {} of {} ({} reagents)", page.page, page.total_pages, page.total));
+    if page.page > 1 {
+        html.push_str(&format!(" <a href=\"{}\">Previous</a>", link(page.page - 1)));
+    }
+    if page.page < page.total_pages {
+        html.push_str(&format!(" <a href=\"{}\">Next</a>", link(page.page + 1)));
+    }
+    html.push_str("</p>\n</body>\n</html>\n");
+    html
+}
+
+/// GET /catalogue - HTML-страница каталога для тех, кто не работает с API
+pub async fn catalogue_page(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<CatalogueQuery>,
+    req: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let config = &app_state.config.public_catalogue;
+    authorize_catalogue(&req, config, query.token.as_deref())?;
+    let page = fetch_catalogue(&app_state.db_pool, config, &query).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(render_catalogue_page(&page, &exposed_fields(config), &query)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_rate_limiter_fixed_window_per_client() {
+        let limiter = RateLimiter::default();
+        let window = Duration::from_secs(60);
+        let start = Instant::now();
+
+        assert!(limiter.check("10.0.0.1", 2, window, start).is_ok());
+        assert!(limiter.check("10.0.0.1", 2, window, start).is_ok());
+        assert_eq!(limiter.check("10.0.0.1", 2, window, start + Duration::from_secs(15)), Err(45));
+        // Другой клиент считается отдельно, новое окно снова пускает
+        assert!(limiter.check("10.0.0.2", 2, window, start).is_ok());
+        assert!(limiter.check("10.0.0.1", 2, window, start + window).is_ok());
+    }
+
+    #[test]
+    fn test_catalogue_access_requires_enabled_and_token() {
+        let mut config = PublicCatalogueConfig { rate_limit_requests: 1000, ..Default::default() };
+        let request = |token: Option<&str>| {
+            let mut builder = TestRequest::get().uri("/api/v1/catalogue").peer_addr("192.0.2.10:4000".parse().unwrap());
+            if let Some(token) = token {
+                builder = builder.insert_header((CATALOGUE_TOKEN_HEADER, token));
+            }
+            builder.to_http_request()
+        };
+
+        assert!(matches!(authorize_catalogue(&request(None), &config, None), Err(ApiError::NotFound(_))));
+
+        config.enabled = true;
+        assert!(authorize_catalogue(&request(None), &config, None).is_ok());
+
+        config.access_token = Some("shared-department-token".to_string());
+        assert!(matches!(authorize_catalogue(&request(None), &config, None), Err(ApiError::Unauthorized(_))));
+        assert!(matches!(authorize_catalogue(&request(Some("wrong")), &config, None), Err(ApiError::Unauthorized(_))));
+        assert!(authorize_catalogue(&request(Some("shared-department-token")), &config, None).is_ok());
+        assert!(authorize_catalogue(&request(None), &config, Some("shared-department-token")).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn test_catalogue_projection_hides_opted_out_and_batch_details() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        for sql in [
+            "INSERT INTO reagents (id, name, formula, cas_number, storage_conditions, status, created_at, updated_at) VALUES \
+             ('r1', 'Acetone', 'C3H6O', '67-64-1', 'Cabinet 3', 'active', datetime('now'), datetime('now')), \
+             ('r2', 'Benzene', 'C6H6', '71-43-2', NULL, 'active', datetime('now'), datetime('now')), \
+             ('r3', 'Chloroform', 'CHCl3', '67-66-3', NULL, 'active', datetime('now'), datetime('now'))",
+            "UPDATE reagents SET publicly_visible = 0 WHERE id = 'r2'",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity, unit, \
+             expiry_date, received_date, status, location, created_at, updated_at) VALUES \
+             ('b1', 'r1', 'LOT-1', 2, 2, 0.5, 'L', NULL, datetime('now'), 'available', 'Fridge A', datetime('now'), datetime('now')), \
+             ('b2', 'r1', 'LOT-2', 250, 250, 0, 'mL', '2099-01-01T00:00:00+00:00', datetime('now'), 'available', 'Fridge B', datetime('now'), datetime('now')), \
+             ('b3', 'r1', 'LOT-3', 900, 900, 0, 'mL', '2000-01-01T00:00:00+00:00', datetime('now'), 'available', NULL, datetime('now'), datetime('now')), \
+             ('b4', 'r3', 'LOT-4', 1, 1, 0, 'L', NULL, datetime('now'), 'depleted', NULL, datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let config = PublicCatalogueConfig {
+            enabled: true,
+            fields: vec!["name".to_string(), "cas_number".to_string()],
+            ..Default::default()
+        };
+        let page = fetch_catalogue(&pool, &config, &CatalogueQuery::default()).await.unwrap();
+        assert_eq!(page.total, 2);
+
+        let acetone = serde_json::to_value(&page.data[0]).unwrap();
+        assert_eq!(acetone["name"], "Acetone");
+        assert_eq!(acetone["cas_number"], "67-64-1");
+        assert!(acetone.get("formula").is_none());
+        assert!(acetone.get("storage_conditions").is_none());
+        assert_eq!(acetone["in_stock"], true);
+        // 2 L - 0.5 L в резерве + 250 mL; просроченная партия не считается
+        assert_eq!(acetone["availability"], serde_json::json!([{ "unit": "mL", "quantity": 1750.0 }]));
+        let serialized = acetone.to_string();
+        assert!(!serialized.contains("Fridge") && !serialized.contains("LOT-"));
+
+        assert_eq!(page.data[1].id, "r3");
+        assert!(!page.data[1].in_stock);
+
+        // Поиск только по открытым полям: формула скрыта - не ищется
+        let by_formula = CatalogueQuery { search: Some("CHCl3".to_string()), ..Default::default() };
+        assert_eq!(fetch_catalogue(&pool, &config, &by_formula).await.unwrap().total, 0);
+        let by_cas = CatalogueQuery { search: Some("67-66".to_string()), ..Default::default() };
+        assert_eq!(fetch_catalogue(&pool, &config, &by_cas).await.unwrap().total, 1);
+
+        assert!(matches!(fetch_catalogue_entry(&pool, &config, "r2").await, Err(ApiError::NotFound(_))));
+
+        let html = render_catalogue_page(&page, &exposed_fields(&config), &CatalogueQuery::default());
+        assert!(html.contains("<th>Cas number</th>"));
+        assert!(html.contains("<td>1750 mL</td>"));
+        assert!(html.contains("Out of stock"));
+    }
+}
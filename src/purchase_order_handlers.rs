@@ -0,0 +1,788 @@
+// src/purchase_order_handlers.rs
+//! Заказы поставщику и приёмка по ним.
+//!
+//! Заказ содержит строки (реагент из справочника или текстовое описание). Приёмка
+//! превращает выбранные строки в партии с предзаполненными поставщиком, каталожным
+//! номером, количеством и единицей; любое поле можно поправить в запросе. Частичная
+//! приёмка увеличивает `received_quantity` строки, созданные партии ссылаются на строку
+//! через `batches.purchase_order_line_id`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::get_current_user;
+use crate::batch_handlers::{convert_quantity, insert_batch, resolve_batch_location, validate_batch_request};
+use crate::error::{ApiError, ApiResult};
+use crate::events::BusinessEvent;
+use crate::handlers::ApiResponse;
+use crate::models::*;
+use crate::outbox;
+use crate::reagent_handlers::ensure_reagent_active;
+use crate::validator::ValidationResult;
+use crate::AppState;
+
+const PO_SELECT: &str = r#"
+    SELECT po.id, po.po_number, po.supplier, po.status, po.expected_date, po.notes,
+           po.created_by, po.created_at, po.updated_at,
+           (SELECT COUNT(*) FROM purchase_order_lines l WHERE l.purchase_order_id = po.id) AS lines_count,
+           (SELECT COUNT(*) FROM purchase_order_lines l
+            WHERE l.purchase_order_id = po.id AND l.received_quantity < l.quantity) AS pending_lines
+    FROM purchase_orders po
+"#;
+
+const LINE_SELECT: &str = r#"
+    SELECT l.id, l.purchase_order_id, l.line_no, l.reagent_id, r.name AS reagent_name,
+           l.description, l.cat_number, l.quantity, l.received_quantity, l.unit,
+           l.expected_date, l.created_at
+    FROM purchase_order_lines l
+    LEFT JOIN reagents r ON r.id = l.reagent_id
+"#;
+
+/// Допуск сравнения полученного и заказанного количества
+const QUANTITY_EPSILON: f64 = 1e-9;
+
+// ==================== QUERIES ====================
+
+async fn fetch_order(pool: &SqlitePool, order_id: &str) -> ApiResult<PurchaseOrder> {
+    sqlx::query_as::<_, PurchaseOrder>(&format!("{} WHERE po.id = ?", PO_SELECT))
+        .bind(order_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Purchase order"))
+}
+
+async fn fetch_lines(pool: &SqlitePool, order_id: &str) -> ApiResult<Vec<PurchaseOrderLine>> {
+    let lines = sqlx::query_as::<_, PurchaseOrderLine>(
+        &format!("{} WHERE l.purchase_order_id = ? ORDER BY l.line_no", LINE_SELECT)
+    )
+        .bind(order_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(lines)
+}
+
+/// Партии, принятые по строкам заказа (в т.ч. удалённые мягко - для прослеживаемости)
+async fn fetch_order_batches(pool: &SqlitePool, order_id: &str) -> ApiResult<Vec<PurchaseOrderBatch>> {
+    let batches = sqlx::query_as::<_, PurchaseOrderBatch>(
+        r#"SELECT b.id, b.purchase_order_line_id, b.reagent_id, b.batch_number,
+                  b.quantity, b.original_quantity, b.unit, b.received_date, b.status
+           FROM batches b
+           JOIN purchase_order_lines l ON l.id = b.purchase_order_line_id
+           WHERE l.purchase_order_id = ?
+           ORDER BY l.line_no, b.created_at"#
+    )
+        .bind(order_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(batches)
+}
+
+fn generate_po_number() -> String {
+    format!("PO-{}", Uuid::new_v4().simple().to_string()[..8].to_uppercase())
+}
+
+fn duplicate_po_number(e: sqlx::Error) -> ApiError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            ApiError::bad_request("A purchase order with this number already exists")
+        }
+        other => ApiError::from(other),
+    }
+}
+
+/// Номер партии по умолчанию: `{po_number}-{line_no}`, для повторных поставок по строке - `-2`, `-3`...
+fn default_batch_number(po_number: &str, line_no: i64, received_batches: i64) -> String {
+    if received_batches == 0 {
+        format!("{}-{}", po_number, line_no)
+    } else {
+        format!("{}-{}-{}", po_number, line_no, received_batches + 1)
+    }
+}
+
+/// Проверка строк нового заказа: derive-правила, наличие реагента или описания
+async fn validate_order_lines(pool: &SqlitePool, lines: &[CreatePurchaseOrderLine]) -> ApiResult<ValidationResult> {
+    let mut result = ValidationResult::new();
+    for (i, line) in lines.iter().enumerate() {
+        let prefix = format!("lines[{}]", i);
+        result.merge_prefixed(&prefix, ValidationResult::from_validate(line));
+        match line.reagent_id.as_deref().filter(|id| !id.is_empty()) {
+            Some(reagent_id) => {
+                let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM reagents WHERE id = ? AND deleted_at IS NULL")
+                    .bind(reagent_id)
+                    .fetch_optional(pool)
+                    .await?;
+                if exists.is_none() {
+                    result.add_error(format!("{}.reagent_id", prefix), "Reagent not found");
+                }
+            }
+            None if line.description.as_deref().is_none_or(|d| d.trim().is_empty()) => {
+                result.add_error(format!("{}.description", prefix), "Either reagent_id or description is required");
+            }
+            None => {}
+        }
+    }
+    Ok(result)
+}
+
+/// Вставка заказа со строками в транзакции вызывающего; возвращает id заказа
+#[allow(clippy::too_many_arguments)]
+async fn insert_order(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    po_number: &str,
+    supplier: &str,
+    status: &str,
+    expected_date: Option<chrono::DateTime<Utc>>,
+    notes: Option<&str>,
+    lines: &[CreatePurchaseOrderLine],
+    user_id: &str,
+) -> ApiResult<String> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"INSERT INTO purchase_orders (id, po_number, supplier, status, expected_date, notes, created_by, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&id)
+        .bind(po_number)
+        .bind(supplier)
+        .bind(status)
+        .bind(expected_date)
+        .bind(notes)
+        .bind(user_id)
+        .bind(now)
+        .bind(now)
+        .execute(&mut **tx)
+        .await
+        .map_err(duplicate_po_number)?;
+
+    for (i, line) in lines.iter().enumerate() {
+        sqlx::query(
+            r#"INSERT INTO purchase_order_lines
+               (id, purchase_order_id, line_no, reagent_id, description, cat_number, quantity, unit, expected_date, created_at)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+        )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&id)
+            .bind(i as i64 + 1)
+            .bind(line.reagent_id.as_deref().filter(|r| !r.is_empty()))
+            .bind(line.description.as_deref().map(str::trim).filter(|d| !d.is_empty()))
+            .bind(&line.cat_number)
+            .bind(line.quantity)
+            .bind(line.unit.trim())
+            .bind(line.expected_date.or(expected_date))
+            .bind(now)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(id)
+}
+
+async fn order_detail(pool: &SqlitePool, order_id: &str) -> ApiResult<PurchaseOrderDetailResponse> {
+    let order = fetch_order(pool, order_id).await?;
+    let lines = fetch_lines(pool, order_id).await?;
+    let batches = fetch_order_batches(pool, order_id).await?;
+    Ok(PurchaseOrderDetailResponse { order, lines, batches })
+}
+
+// ==================== CRUD ====================
+
+/// POST /purchase-orders
+pub async fn create_purchase_order(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreatePurchaseOrderRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    validate_order_lines(pool, &body.lines).await?.ensure_valid()?;
+
+    let po_number = body.po_number.as_deref().map(str::trim).filter(|n| !n.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(generate_po_number);
+    let status = if body.draft { PO_STATUS_DRAFT } else { PO_STATUS_OPEN };
+
+    let mut tx = pool.begin().await?;
+    let id = insert_order(
+        &mut tx, &po_number, body.supplier.trim(), status, body.expected_date,
+        body.notes.as_deref(), &body.lines, &claims.sub,
+    ).await?;
+    crate::audit::log_activity(
+        &mut *tx, Some(&claims.sub), "create", "purchase_order", Some(&id),
+        Some(&format!("Created purchase order {} for {} ({} lines)", po_number, body.supplier.trim(), body.lines.len())),
+        None, Some(&http_request),
+    ).await?;
+    tx.commit().await?;
+
+    let detail = order_detail(pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(detail)))
+}
+
+/// GET /purchase-orders - по умолчанию только открытые заказы
+pub async fn list_purchase_orders(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<PurchaseOrderListQuery>,
+) -> ApiResult<HttpResponse> {
+    let statuses: Vec<&str> = match query.status.as_deref() {
+        None => PO_OPEN_STATUSES.to_vec(),
+        Some("all") => PO_STATUSES.to_vec(),
+        Some(status) if PO_STATUSES.contains(&status) => vec![status],
+        Some(other) => {
+            return Err(ApiError::bad_request(&format!(
+                "Invalid status '{}'. Allowed: all, {}", other, PO_STATUSES.join(", ")
+            )));
+        }
+    };
+
+    let mut sql = format!(
+        "{} WHERE po.status IN ({})",
+        PO_SELECT,
+        vec!["?"; statuses.len()].join(", ")
+    );
+    let supplier = query.supplier.as_deref().map(str::trim).filter(|s| !s.is_empty());
+    if supplier.is_some() {
+        sql.push_str(" AND po.supplier LIKE ?");
+    }
+    sql.push_str(" ORDER BY COALESCE(po.expected_date, po.created_at), po.created_at");
+
+    let mut db_query = sqlx::query_as::<_, PurchaseOrder>(&sql);
+    for status in &statuses {
+        db_query = db_query.bind(*status);
+    }
+    if let Some(supplier) = supplier {
+        db_query = db_query.bind(format!("%{}%", supplier));
+    }
+    let orders = db_query.fetch_all(app_state.read_pool()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(orders)))
+}
+
+/// GET /purchase-orders/{id} - строки и принятые по ним партии
+pub async fn get_purchase_order(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let detail = order_detail(&app_state.db_pool, &path.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(detail)))
+}
+
+async fn set_order_status(
+    pool: &SqlitePool,
+    order_id: &str,
+    from: &[&str],
+    to: &str,
+    action: &str,
+    http_request: &HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(http_request)?;
+    let order = fetch_order(pool, order_id).await?;
+    if !from.contains(&order.status.as_str()) {
+        return Err(ApiError::purchase_order_status(&order.po_number, &order.status, action));
+    }
+
+    sqlx::query("UPDATE purchase_orders SET status = ?, updated_at = ? WHERE id = ?")
+        .bind(to)
+        .bind(Utc::now())
+        .bind(order_id)
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(
+        pool, &claims.sub, "update", "purchase_order", order_id,
+        &format!("Purchase order {}: {} -> {}", order.po_number, order.status, to), http_request,
+    ).await;
+
+    let detail = order_detail(pool, order_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(detail)))
+}
+
+/// POST /purchase-orders/{id}/submit - черновик отправлен поставщику
+pub async fn submit_purchase_order(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    set_order_status(
+        &app_state.db_pool, &path.into_inner(), &[PO_STATUS_DRAFT], PO_STATUS_OPEN, "submitted", &http_request,
+    ).await
+}
+
+/// POST /purchase-orders/{id}/cancel - уже принятые партии остаются связанными с заказом
+pub async fn cancel_purchase_order(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    set_order_status(
+        &app_state.db_pool, &path.into_inner(),
+        &[PO_STATUS_DRAFT, PO_STATUS_OPEN, PO_STATUS_PARTIALLY_RECEIVED], PO_STATUS_CANCELLED, "cancelled",
+        &http_request,
+    ).await
+}
+
+/// POST /purchase-orders/from-suggestions - черновик из предложений по дозаказу запчастей
+pub async fn create_purchase_order_from_suggestions(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<PurchaseOrderFromSuggestionsRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+
+    let wanted: HashSet<String> = body.part_numbers.iter()
+        .map(|n| n.trim().to_lowercase())
+        .filter(|n| !n.is_empty())
+        .collect();
+    let lines: Vec<CreatePurchaseOrderLine> = crate::equipment_handlers::part_reorder_suggestions(pool).await?
+        .into_iter()
+        .filter(|s| s.deficit_to_order > 0)
+        .filter(|s| wanted.is_empty() || s.part_number.as_ref().is_some_and(|n| wanted.contains(&n.to_lowercase())))
+        .map(|s| CreatePurchaseOrderLine {
+            reagent_id: None,
+            description: Some(match &s.manufacturer {
+                Some(manufacturer) => format!("{} ({})", s.name, manufacturer),
+                None => s.name.clone(),
+            }),
+            cat_number: s.part_number,
+            quantity: s.deficit_to_order as f64,
+            unit: "pcs".to_string(),
+            expected_date: None,
+        })
+        .collect();
+    if lines.is_empty() {
+        return Err(ApiError::bad_request("There are no part reorder suggestions to order"));
+    }
+
+    let po_number = generate_po_number();
+    let mut tx = pool.begin().await?;
+    let id = insert_order(
+        &mut tx, &po_number, body.supplier.trim(), PO_STATUS_DRAFT, None,
+        Some("Created from part reorder suggestions"), &lines, &claims.sub,
+    ).await?;
+    crate::audit::log_activity(
+        &mut *tx, Some(&claims.sub), "create", "purchase_order", Some(&id),
+        Some(&format!("Drafted purchase order {} from {} reorder suggestions", po_number, lines.len())),
+        None, Some(&http_request),
+    ).await?;
+    tx.commit().await?;
+
+    let detail = order_detail(pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(detail)))
+}
+
+// ==================== RECEIVING ====================
+
+/// Предзаполненные формы партий для строк с неполученным остатком
+async fn build_receive_drafts(
+    pool: &SqlitePool,
+    order: &PurchaseOrder,
+    lines: &[PurchaseOrderLine],
+) -> ApiResult<Vec<ReceiveDraft>> {
+    let received: HashMap<String, i64> = sqlx::query_as::<_, (String, i64)>(
+        r#"SELECT b.purchase_order_line_id, COUNT(*)
+           FROM batches b
+           JOIN purchase_order_lines l ON l.id = b.purchase_order_line_id
+           WHERE l.purchase_order_id = ?
+           GROUP BY b.purchase_order_line_id"#
+    )
+        .bind(&order.id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+
+    Ok(lines.iter()
+        .filter(|line| line.remaining_quantity() > QUANTITY_EPSILON)
+        .map(|line| ReceiveDraft {
+            line_id: line.id.clone(),
+            line_no: line.line_no,
+            reagent_id: line.reagent_id.clone(),
+            reagent_name: line.reagent_name.clone(),
+            description: line.description.clone(),
+            batch_number: default_batch_number(
+                &order.po_number, line.line_no, received.get(&line.id).copied().unwrap_or(0),
+            ),
+            quantity: line.remaining_quantity(),
+            unit: line.unit.clone(),
+            cat_number: line.cat_number.clone(),
+            supplier: order.supplier.clone(),
+            creates_batch: line.reagent_id.is_some(),
+        })
+        .collect())
+}
+
+fn ensure_receivable(order: &PurchaseOrder) -> ApiResult<()> {
+    if PO_OPEN_STATUSES.contains(&order.status.as_str()) {
+        Ok(())
+    } else {
+        Err(ApiError::purchase_order_status(&order.po_number, &order.status, "received"))
+    }
+}
+
+/// GET /purchase-orders/{id}/receive - черновики партий для формы приёмки
+pub async fn get_receive_drafts(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let order = fetch_order(pool, &path.into_inner()).await?;
+    ensure_receivable(&order)?;
+    let lines = fetch_lines(pool, &order.id).await?;
+    let drafts = build_receive_drafts(pool, &order, &lines).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(drafts)))
+}
+
+/// Подготовленная к записи строка приёмки
+struct ReceivePlan {
+    line_id: String,
+    /// Прирост received_quantity в единицах строки
+    line_quantity: f64,
+    batch: Option<(Reagent, CreateBatchRequest, Option<String>)>,
+}
+
+/// POST /purchase-orders/{id}/receive - выбранные строки становятся партиями
+pub async fn receive_purchase_order(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<ReceivePurchaseOrderRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let pool = &app_state.db_pool;
+    let order = fetch_order(pool, &path.into_inner()).await?;
+    ensure_receivable(&order)?;
+    if body.lines.is_empty() {
+        return Err(ApiError::bad_request("Select at least one line to receive"));
+    }
+
+    let lines = fetch_lines(pool, &order.id).await?;
+    let drafts: HashMap<String, ReceiveDraft> = build_receive_drafts(pool, &order, &lines).await?
+        .into_iter()
+        .map(|d| (d.line_id.clone(), d))
+        .collect();
+    let lines_by_id: HashMap<&str, &PurchaseOrderLine> = lines.iter().map(|l| (l.id.as_str(), l)).collect();
+
+    let mut seen = HashSet::new();
+    let mut validation = ValidationResult::new();
+    let mut plans = Vec::with_capacity(body.lines.len());
+    for (i, item) in body.lines.iter().enumerate() {
+        let prefix = format!("lines[{}]", i);
+        let line = *lines_by_id.get(item.line_id.as_str())
+            .ok_or_else(|| ApiError::not_found("Purchase order line"))?;
+        if !seen.insert(line.id.as_str()) {
+            return Err(ApiError::bad_request(&format!("Line {} is listed more than once", line.line_no)));
+        }
+        let draft = drafts.get(&line.id);
+
+        let quantity = item.quantity.unwrap_or_else(|| draft.map_or(0.0, |d| d.quantity));
+        if quantity <= 0.0 {
+            validation.add_error(format!("{}.quantity", prefix), "Quantity must be positive");
+            continue;
+        }
+        let unit = item.unit.clone().unwrap_or_else(|| line.unit.clone());
+        let line_quantity = match convert_quantity(quantity, &unit, &line.unit) {
+            Ok(q) => q,
+            Err(e) => {
+                validation.add_error(format!("{}.unit", prefix), e);
+                continue;
+            }
+        };
+
+        let reagent_id = item.reagent_id.as_deref().filter(|r| !r.is_empty()).or(line.reagent_id.as_deref());
+        let batch = match reagent_id {
+            None => None,
+            Some(reagent_id) => {
+                let reagent: Option<Reagent> = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+                    .bind(reagent_id)
+                    .fetch_optional(pool)
+                    .await?;
+                let Some(reagent) = reagent else {
+                    validation.add_error(format!("{}.reagent_id", prefix), "Reagent not found");
+                    continue;
+                };
+                ensure_reagent_active(&reagent)?;
+
+                let request = CreateBatchRequest {
+                    lot_number: item.lot_number.clone(),
+                    batch_number: item.batch_number.clone()
+                        .or_else(|| draft.map(|d| d.batch_number.clone()))
+                        .unwrap_or_else(|| default_batch_number(&order.po_number, line.line_no, 0)),
+                    cat_number: item.cat_number.clone().or_else(|| line.cat_number.clone()),
+                    quantity,
+                    unit,
+                    pack_size: item.pack_size,
+                    container_count: item.container_count,
+                    expiry_date: item.expiry_date,
+                    supplier: item.supplier.clone().or_else(|| Some(order.supplier.clone())),
+                    manufacturer: item.manufacturer.clone(),
+                    location: item.location.clone(),
+                    location_id: item.location_id.clone(),
+                    notes: item.notes.clone(),
+                    received_date: item.received_date,
+                };
+                let result = validate_batch_request(pool, &reagent.id, &request, None).await?;
+                if !result.is_valid() {
+                    validation.merge_prefixed(&prefix, result);
+                    continue;
+                }
+                let location = resolve_batch_location(pool, &request).await?;
+                Some((reagent, request, location))
+            }
+        };
+        plans.push(ReceivePlan { line_id: line.id.clone(), line_quantity, batch });
+    }
+    validation.ensure_valid()?;
+
+    let mut tx = pool.begin().await?;
+    let mut batch_ids = Vec::new();
+    for plan in &plans {
+        if let Some((reagent, request, location)) = &plan.batch {
+            let batch_id = insert_batch(&mut *tx, reagent, request, location.clone(), &claims.sub).await
+                .map_err(|e| match e {
+                    ApiError::DatabaseError(sqlx::Error::Database(ref db)) if db.is_unique_violation() => ApiError::bad_request(&format!(
+                        "Batch number '{}' is used more than once for reagent '{}'", request.batch_number, reagent.name
+                    )),
+                    other => other,
+                })?;
+            sqlx::query("UPDATE batches SET purchase_order_line_id = ? WHERE id = ?")
+                .bind(&plan.line_id)
+                .bind(&batch_id)
+                .execute(&mut *tx)
+                .await?;
+            outbox::enqueue(
+                &mut *tx,
+                &BusinessEvent::BatchCreated {
+                    reagent_id: reagent.id.clone(),
+                    batch_id: batch_id.clone(),
+                    quantity: request.quantity,
+                    unit: request.unit.clone(),
+                },
+                Some(&claims.sub),
+            ).await?;
+            batch_ids.push(batch_id);
+        }
+        sqlx::query("UPDATE purchase_order_lines SET received_quantity = received_quantity + ? WHERE id = ?")
+            .bind(plan.line_quantity)
+            .bind(&plan.line_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let (pending,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM purchase_order_lines WHERE purchase_order_id = ? AND received_quantity + ? < quantity"
+    )
+        .bind(&order.id)
+        .bind(QUANTITY_EPSILON)
+        .fetch_one(&mut *tx)
+        .await?;
+    let status = if pending == 0 { PO_STATUS_RECEIVED } else { PO_STATUS_PARTIALLY_RECEIVED };
+    sqlx::query("UPDATE purchase_orders SET status = ?, updated_at = ? WHERE id = ?")
+        .bind(status)
+        .bind(Utc::now())
+        .bind(&order.id)
+        .execute(&mut *tx)
+        .await?;
+    crate::audit::log_activity(
+        &mut *tx, Some(&claims.sub), "receive", "purchase_order", Some(&order.id),
+        Some(&format!(
+            "Received {} lines of purchase order {} ({} batches created), status {}",
+            plans.len(), order.po_number, batch_ids.len(), status
+        )),
+        None, Some(&http_request),
+    ).await?;
+    tx.commit().await?;
+
+    let touched: HashSet<&str> = plans.iter().map(|p| p.line_id.as_str()).collect();
+    let received_lines = fetch_lines(pool, &order.id).await?
+        .into_iter()
+        .filter(|l| touched.contains(l.id.as_str()))
+        .collect();
+    let created_batches = fetch_order_batches(pool, &order.id).await?
+        .into_iter()
+        .filter(|b| batch_ids.contains(&b.id))
+        .collect();
+    let order = fetch_order(pool, &order.id).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(ReceivePurchaseOrderResponse {
+        order,
+        received_lines,
+        created_batches,
+    })))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Claims, UserRole};
+    use actix_web::HttpMessage;
+
+    async fn test_app_state() -> web::Data<Arc<AppState>> {
+        let app = crate::test_support::TestApp::new().await;
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u1', 'buyer', 'buyer@example.com', 'x', 'researcher', datetime('now'), datetime('now'))"
+        )
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Acetone', 'active', datetime('now'), datetime('now'))"
+        )
+            .execute(&app.pool)
+            .await
+            .unwrap();
+        app.state()
+    }
+
+    fn request() -> HttpRequest {
+        let req = actix_web::test::TestRequest::get().to_http_request();
+        req.extensions_mut().insert(Claims {
+            sub: "u1".to_string(),
+            username: "buyer".to_string(),
+            email: "buyer@example.com".to_string(),
+            role: UserRole::Researcher,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn body_json(resp: HttpResponse) -> serde_json::Value {
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn line(reagent_id: Option<&str>, description: Option<&str>, quantity: f64, unit: &str) -> CreatePurchaseOrderLine {
+        CreatePurchaseOrderLine {
+            reagent_id: reagent_id.map(str::to_string),
+            description: description.map(str::to_string),
+            cat_number: Some("A-100".to_string()),
+            quantity,
+            unit: unit.to_string(),
+            expected_date: None,
+        }
+    }
+
+    async fn receive(state: &web::Data<Arc<AppState>>, order_id: &str, lines: Vec<ReceivePurchaseOrderLine>) -> ApiResult<HttpResponse> {
+        receive_purchase_order(
+            state.clone(),
+            web::Path::from(order_id.to_string()),
+            web::Json(ReceivePurchaseOrderRequest { lines }),
+            request(),
+        ).await
+    }
+
+    #[actix_web::test]
+    async fn test_partial_receipt_creates_linked_batches() {
+        let state = test_app_state().await;
+        let resp = create_purchase_order(
+            state.clone(),
+            web::Json(CreatePurchaseOrderRequest {
+                po_number: Some("PO-7".to_string()),
+                supplier: "Sigma".to_string(),
+                expected_date: None,
+                notes: None,
+                draft: false,
+                lines: vec![line(Some("r1"), None, 1000.0, "g"), line(None, Some("Filter paper"), 10.0, "pcs")],
+            }),
+            request(),
+        ).await.unwrap();
+        let json = body_json(resp).await;
+        let order_id = json["data"]["id"].as_str().unwrap().to_string();
+        let reagent_line = json["data"]["lines"][0]["id"].as_str().unwrap().to_string();
+        let paper_line = json["data"]["lines"][1]["id"].as_str().unwrap().to_string();
+
+        // Первая поставка: 0.4 кг реагента - пересчёт в единицы строки, поставщик и каталожный номер из заказа
+        let resp = receive(&state, &order_id, vec![ReceivePurchaseOrderLine {
+            line_id: reagent_line.clone(),
+            quantity: Some(0.4),
+            unit: Some("kg".to_string()),
+            ..Default::default()
+        }]).await.unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["data"]["status"], PO_STATUS_PARTIALLY_RECEIVED);
+        assert_eq!(json["data"]["received_lines"][0]["received_quantity"], 400.0);
+        let batch_id = json["data"]["created_batches"][0]["id"].as_str().unwrap().to_string();
+        let (batch_number, supplier, cat_number, line_ref): (String, Option<String>, Option<String>, Option<String>) =
+            sqlx::query_as("SELECT batch_number, supplier, cat_number, purchase_order_line_id FROM batches WHERE id = ?")
+                .bind(&batch_id)
+                .fetch_one(&state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(batch_number, "PO-7-1");
+        assert_eq!(supplier.as_deref(), Some("Sigma"));
+        assert_eq!(cat_number.as_deref(), Some("A-100"));
+        assert_eq!(line_ref.as_deref(), Some(reagent_line.as_str()));
+
+        // Форма приёмки предлагает остаток и следующий номер партии
+        let resp = get_receive_drafts(state.clone(), web::Path::from(order_id.clone())).await.unwrap();
+        let drafts = body_json(resp).await;
+        assert_eq!(drafts["data"][0]["quantity"], 600.0);
+        assert_eq!(drafts["data"][0]["batch_number"], "PO-7-1-2");
+        assert_eq!(drafts["data"][1]["creates_batch"], false);
+
+        // Остаток: текстовая строка отмечается без партии, заказ закрывается
+        let resp = receive(&state, &order_id, vec![
+            ReceivePurchaseOrderLine { line_id: reagent_line.clone(), ..Default::default() },
+            ReceivePurchaseOrderLine { line_id: paper_line.clone(), ..Default::default() },
+        ]).await.unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["data"]["status"], PO_STATUS_RECEIVED);
+        assert_eq!(json["data"]["created_batches"].as_array().unwrap().len(), 1);
+
+        let resp = get_purchase_order(state.clone(), web::Path::from(order_id.clone())).await.unwrap();
+        let detail = body_json(resp).await;
+        assert_eq!(detail["data"]["batches"].as_array().unwrap().len(), 2);
+        assert_eq!(detail["data"]["pending_lines"], 0);
+
+        let err = receive(&state, &order_id, vec![
+            ReceivePurchaseOrderLine { line_id: paper_line, quantity: Some(1.0), ..Default::default() },
+        ]).await.unwrap_err();
+        assert!(matches!(err, ApiError::Conflict { code: crate::error::PURCHASE_ORDER_STATUS, .. }));
+    }
+
+    #[actix_web::test]
+    async fn test_draft_from_part_reorder_suggestions() {
+        let state = test_app_state().await;
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at) \
+             VALUES ('e1', 'HPLC', 'instrument', 1, 'available', datetime('now'), datetime('now'))"
+        )
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO equipment_parts (id, equipment_id, name, part_number, quantity, min_quantity, status, created_at, updated_at) \
+             VALUES ('p1', 'e1', 'Seal', 'SL-1', 1, 4, 'good', datetime('now'), datetime('now'))"
+        )
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let resp = create_purchase_order_from_suggestions(
+            state.clone(),
+            web::Json(PurchaseOrderFromSuggestionsRequest { supplier: "Agilent".to_string(), part_numbers: vec![] }),
+            request(),
+        ).await.unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["data"]["status"], PO_STATUS_DRAFT);
+        assert_eq!(json["data"]["lines"][0]["cat_number"], "SL-1");
+        assert_eq!(json["data"]["lines"][0]["quantity"], 3.0);
+
+        // Черновик не принимается, пока не отправлен
+        let order_id = json["data"]["id"].as_str().unwrap().to_string();
+        assert!(get_receive_drafts(state.clone(), web::Path::from(order_id.clone())).await.is_err());
+        submit_purchase_order(state.clone(), web::Path::from(order_id.clone()), request()).await.unwrap();
+        get_receive_drafts(state.clone(), web::Path::from(order_id)).await.unwrap();
+    }
+}
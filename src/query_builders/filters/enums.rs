@@ -95,6 +95,7 @@ define_status_enum! {
         Expired => "expired",
         Reserved => "reserved",
         Depleted => "depleted",
+        AwaitingCoa => "awaiting_coa",
     }
 }
 
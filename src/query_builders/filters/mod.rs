@@ -1,14 +1,25 @@
 // src/query_builders/filters/mod.rs
 //! Фильтры и whitelist для безопасных запросов
 
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use std::collections::HashSet;
+use sqlx::encode::IsNull;
+use sqlx::sqlite::{Sqlite, SqliteArgumentValue, SqliteTypeInfo};
+use std::collections::{HashMap, HashSet};
 
 // ==================== FIELD WHITELIST ====================
 
+pub(crate) fn is_plain_identifier(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with(|c: char| c.is_ascii_digit())
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 #[derive(Debug, Clone)]
 pub struct FieldWhitelist {
     fields: HashSet<String>,
+    /// Публичное имя поля -> колонка (например, `type` -> `type_` у оборудования)
+    aliases: HashMap<String, String>,
     table: String,
 }
 
@@ -17,12 +28,91 @@ impl FieldWhitelist {
         Self {
             table: table.to_string(),
             fields: fields.iter().map(|s| s.to_string()).collect(),
+            aliases: HashMap::new(),
         }
     }
 
+    /// Имя `alias` принимается вместо колонки `column` (которая должна быть в whitelist)
+    pub fn with_alias(mut self, alias: &str, column: &str) -> Self {
+        debug_assert!(self.fields.contains(column), "alias target must be whitelisted");
+        self.aliases.insert(alias.to_string(), column.to_string());
+        self
+    }
+
+    /// Дополнительные поля: вычисляемые колонки и поля из JOIN конкретного запроса
+    pub fn with_fields(mut self, fields: &[&str]) -> Self {
+        self.fields.extend(fields.iter().map(|s| s.to_string()));
+        self
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Колонка для имени поля: псевдоним заменяется колонкой, остальное возвращается как есть.
+    /// `is_allowed` псевдонимы не принимает - их нужно разрешить до передачи в построители.
+    pub fn column<'a>(&'a self, field: &'a str) -> &'a str {
+        self.aliases.get(field).map(String::as_str).unwrap_or(field)
+    }
+
+    /// Допускает как `field`, так и `alias.field`; префикс таблицы должен быть
+    /// простым идентификатором, иначе поле отклоняется.
     pub fn is_allowed(&self, field: &str) -> bool {
-        let clean_field = field.split('.').last().unwrap_or(field);
-        self.fields.contains(clean_field) || self.fields.contains(field)
+        if self.fields.contains(field) {
+            return true;
+        }
+        match field.rsplit_once('.') {
+            Some((prefix, clean_field)) => {
+                is_plain_identifier(prefix) && self.fields.contains(clean_field)
+            }
+            None => false,
+        }
+    }
+
+    /// Все разрешённые поля в алфавитном порядке (для сообщений об ошибках)
+    pub fn field_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.fields.iter().chain(self.aliases.keys()).map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Сообщение строгого режима: отклонённые поля и список допустимых
+    pub fn unknown_fields_error<S: AsRef<str>>(&self, rejected: &[S]) -> String {
+        format!(
+            "Unknown field(s): {}. Valid fields: {}",
+            rejected.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(", "),
+            self.field_names().join(", ")
+        )
+    }
+
+    /// Разбор параметра `?fields=a,b,c` - только точные имена из whitelist,
+    /// порядок сохраняется, дубликаты отбрасываются. Псевдоним выбирается как `колонка AS псевдоним`.
+    pub fn parse_selection(&self, raw: &str) -> Result<Vec<String>, String> {
+        let mut selected: Vec<String> = Vec::new();
+        let mut unknown: Vec<&str> = Vec::new();
+        for field in raw.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let column = match self.aliases.get(field) {
+                Some(column) => format!("{} AS {}", column, field),
+                None if self.fields.contains(field) => field.to_string(),
+                None => {
+                    unknown.push(field);
+                    continue;
+                }
+            };
+            if !selected.contains(&column) {
+                selected.push(column);
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(self.unknown_fields_error(&unknown));
+        }
+        if selected.is_empty() {
+            return Err(format!(
+                "fields must list at least one field. Valid fields: {}",
+                self.field_names().join(", ")
+            ));
+        }
+        Ok(selected)
     }
 
     pub fn for_batches() -> Self {
@@ -40,6 +130,8 @@ impl FieldWhitelist {
             "id", "name", "formula", "cas_number", "manufacturer",
             "molecular_weight", "physical_state", "description", "status",
             "created_by", "updated_by", "created_at", "updated_at",
+            "storage_conditions", "appearance", "hazard_pictograms",
+            "total_quantity", "batches_count", "primary_unit", "team_id",
         ])
     }
 
@@ -47,6 +139,7 @@ impl FieldWhitelist {
         Self::new("experiments", &[
             "id", "title", "description", "experiment_date", "experiment_type",
             "instructor", "student_group", "location", "status", "room_id",
+            "start_date", "end_date", "outcome", "instructor_user_id", "team_id",
             "created_by", "updated_by", "created_at", "updated_at",
         ])
     }
@@ -55,9 +148,11 @@ impl FieldWhitelist {
         Self::new("equipment", &[
             "id", "name", "type_", "quantity", "unit", "status", "location",
             "description", "serial_number", "manufacturer", "model",
-            "purchase_date", "warranty_until", "created_by", "updated_by",
+            "purchase_date", "warranty_until", "team_id", "created_by", "updated_by",
             "created_at", "updated_at",
         ])
+        // `type_` - устаревшее имя, принимается наравне с `type`
+        .with_alias("type", "type_")
     }
 
     pub fn for_rooms() -> Self {
@@ -80,7 +175,52 @@ impl FieldWhitelist {
             "original_quantity", "reserved_quantity", "unit", "expiry_date",
             "supplier", "manufacturer", "received_date", "status", "location",
             "notes", "created_at", "updated_at", "days_until_expiry",
-            "reagent_name", "expiration_status",
+            "reagent_name", "expiration_status", "expiry_extension_history",
+            "container_size", "container_count", "open_containers", "expiry_policy",
+        ])
+    }
+
+    // Колонки, изменяемые через PUT (`UpdateBuilder`): без вычисляемых и служебных полей
+
+    pub fn for_equipment_update() -> Self {
+        Self::new("equipment", &[
+            "name", "type_", "quantity", "unit", "status", "location", "description",
+            "serial_number", "manufacturer", "model", "purchase_date", "warranty_until",
+            "parent_equipment_id", "restricted_to_roles", "team_id", "updated_by", "updated_at",
+        ])
+        .with_alias("type", "type_")
+    }
+
+    pub fn for_equipment_part_update() -> Self {
+        Self::new("equipment_parts", &[
+            "name", "part_number", "manufacturer", "quantity", "min_quantity", "status",
+            "last_replaced", "next_replacement", "notes", "linked_batch_id", "updated_at",
+        ])
+    }
+
+    pub fn for_maintenance_update() -> Self {
+        Self::new("equipment_maintenance", &[
+            "status", "scheduled_date", "scheduled_start", "scheduled_end", "reminder_lead_days",
+            "sla_days", "assigned_to", "escalation_level", "escalated_at",
+            "completed_date", "performed_by", "description", "cost", "parts_replaced", "notes", "updated_at",
+        ])
+    }
+
+    pub fn for_reagent_update() -> Self {
+        Self::new("reagents", &[
+            "name", "formula", "cas_number", "manufacturer", "molecular_weight",
+            "physical_state", "description", "storage_conditions", "appearance",
+            "hazard_pictograms", "status", "expiry_policy", "procurement_lead_time_days",
+            "coa_required", "publicly_visible", "approval_threshold", "approval_threshold_unit",
+            "default_shelf_life_days", "team_id", "updated_by", "updated_at",
+        ])
+    }
+
+    pub fn for_batch_update() -> Self {
+        Self::new("batches", &[
+            "lot_number", "batch_number", "cat_number", "quantity", "unit", "pack_size",
+            "container_count", "expiry_date", "supplier", "manufacturer", "status",
+            "location", "location_id", "notes", "updated_by", "updated_at",
         ])
     }
 }
@@ -120,7 +260,7 @@ pub enum FilterValue {
     Number(f64),
     Integer(i64),
     Boolean(bool),
-    Array(Vec<String>),
+    Array(Vec<FilterValue>),
     Null,
 }
 
@@ -131,10 +271,91 @@ impl FilterValue {
             FilterValue::Number(n) => n.to_string(),
             FilterValue::Integer(i) => i.to_string(),
             FilterValue::Boolean(b) => if *b { "1" } else { "0" }.to_string(),
-            FilterValue::Array(arr) => arr.join(","),
+            FilterValue::Array(arr) => arr.iter().map(FilterValue::to_string_value).collect::<Vec<_>>().join(","),
             FilterValue::Null => "NULL".to_string(),
         }
     }
+
+    /// Скалярное значение как параметр запроса с сохранением типа
+    pub fn to_param(&self) -> Result<SqlParam, String> {
+        match self {
+            FilterValue::String(s) => Ok(SqlParam::Text(s.clone())),
+            FilterValue::Number(n) => Ok(SqlParam::number(*n)),
+            FilterValue::Integer(i) => Ok(SqlParam::Integer(*i)),
+            FilterValue::Boolean(b) => Ok(SqlParam::Boolean(*b)),
+            FilterValue::Null => Ok(SqlParam::Null),
+            FilterValue::Array(_) => Err("Nested arrays are not supported in filters".to_string()),
+        }
+    }
+}
+
+// ==================== SQL PARAMS ====================
+
+/// Типизированный параметр запроса. Числа связываются как INTEGER/REAL, а не как
+/// строки: иначе SQLite сравнивает выражения без affinity (например,
+/// `days_until_expiry <= '30'`) как число со строкой и условие всегда ложно/истинно.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlParam {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Text(String),
+    Null,
+}
+
+impl SqlParam {
+    /// Число из JSON: целые значения связываются как INTEGER, чтобы
+    /// `batch_number = 5` по-прежнему совпадал с текстом '5'
+    pub fn number(n: f64) -> Self {
+        const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+        if n.fract() == 0.0 && n.abs() <= MAX_EXACT_INTEGER {
+            SqlParam::Integer(n as i64)
+        } else {
+            SqlParam::Float(n)
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            SqlParam::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for SqlParam { fn from(s: String) -> Self { SqlParam::Text(s) } }
+impl From<&String> for SqlParam { fn from(s: &String) -> Self { SqlParam::Text(s.clone()) } }
+impl From<&str> for SqlParam { fn from(s: &str) -> Self { SqlParam::Text(s.to_string()) } }
+impl From<i64> for SqlParam { fn from(i: i64) -> Self { SqlParam::Integer(i) } }
+impl From<i32> for SqlParam { fn from(i: i32) -> Self { SqlParam::Integer(i.into()) } }
+impl From<f64> for SqlParam { fn from(n: f64) -> Self { SqlParam::Float(n) } }
+impl From<bool> for SqlParam { fn from(b: bool) -> Self { SqlParam::Boolean(b) } }
+impl<T: Into<SqlParam>> From<Option<T>> for SqlParam {
+    fn from(value: Option<T>) -> Self { value.map_or(SqlParam::Null, Into::into) }
+}
+/// Тот же формат RFC 3339, что и при связывании `DateTime<Utc>` напрямую
+impl From<DateTime<Utc>> for SqlParam { fn from(at: DateTime<Utc>) -> Self { SqlParam::Text(at.to_rfc3339()) } }
+
+impl sqlx::Type<Sqlite> for SqlParam {
+    fn type_info() -> SqliteTypeInfo {
+        <String as sqlx::Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(_ty: &SqliteTypeInfo) -> bool {
+        true
+    }
+}
+
+impl<'q> sqlx::Encode<'q, Sqlite> for SqlParam {
+    fn encode_by_ref(&self, buf: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        match self {
+            SqlParam::Integer(i) => <i64 as sqlx::Encode<'q, Sqlite>>::encode_by_ref(i, buf),
+            SqlParam::Float(n) => <f64 as sqlx::Encode<'q, Sqlite>>::encode_by_ref(n, buf),
+            SqlParam::Boolean(b) => <bool as sqlx::Encode<'q, Sqlite>>::encode_by_ref(b, buf),
+            SqlParam::Text(s) => <String as sqlx::Encode<'q, Sqlite>>::encode_by_ref(s, buf),
+            SqlParam::Null => IsNull::Yes,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -167,10 +388,10 @@ impl Filter {
         Self { field: field.to_string(), operator: FilterOperator::Like, value: FilterValue::String(value.into()) }
     }
     pub fn between(field: &str, from: &str, to: &str) -> Self {
-        Self { field: field.to_string(), operator: FilterOperator::Between, value: FilterValue::Array(vec![from.to_string(), to.to_string()]) }
+        Self { field: field.to_string(), operator: FilterOperator::Between, value: FilterValue::Array(vec![from.into(), to.into()]) }
     }
     pub fn between_numbers(field: &str, from: f64, to: f64) -> Self {
-        Self { field: field.to_string(), operator: FilterOperator::Between, value: FilterValue::Array(vec![from.to_string(), to.to_string()]) }
+        Self { field: field.to_string(), operator: FilterOperator::Between, value: FilterValue::Array(vec![from.into(), to.into()]) }
     }
     pub fn is_null(field: &str) -> Self {
         Self { field: field.to_string(), operator: FilterOperator::IsNull, value: FilterValue::Null }
@@ -213,19 +434,37 @@ impl FilterGroup {
 
 pub struct FilterBuilder<'a> {
     whitelist: Option<&'a FieldWhitelist>,
+    strict: bool,
 }
 
 impl<'a> FilterBuilder<'a> {
-    pub fn new() -> Self { Self { whitelist: None } }
+    pub fn new() -> Self { Self { whitelist: None, strict: false } }
 
     pub fn with_whitelist(mut self, whitelist: &'a FieldWhitelist) -> Self {
         self.whitelist = Some(whitelist);
         self
     }
 
-    pub fn build_condition(&self, group: &FilterGroup) -> Result<(String, Vec<String>), String> {
+    /// Строгий режим: фильтр по полю вне whitelist - ошибка, а не молча отброшенное условие
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn build_condition(&self, group: &FilterGroup) -> Result<(String, Vec<SqlParam>), String> {
+        if let (true, Some(wl)) = (self.strict, self.whitelist) {
+            let mut rejected = Vec::new();
+            collect_rejected_fields(group, wl, &mut rejected);
+            if !rejected.is_empty() {
+                return Err(wl.unknown_fields_error(&rejected));
+            }
+        }
+        self.build_group(group)
+    }
+
+    fn build_group(&self, group: &FilterGroup) -> Result<(String, Vec<SqlParam>), String> {
         let mut conditions: Vec<String> = Vec::new();
-        let mut params: Vec<String> = Vec::new();
+        let mut params: Vec<SqlParam> = Vec::new();
 
         for item in &group.items {
             match item {
@@ -240,7 +479,7 @@ impl<'a> FilterBuilder<'a> {
                     }
                 }
                 FilterItem::Group(g) => {
-                    let (cond, p) = self.build_condition(g)?;
+                    let (cond, p) = self.build_group(g)?;
                     if !cond.is_empty() {
                         conditions.push(format!("({})", cond));
                         params.extend(p);
@@ -253,26 +492,28 @@ impl<'a> FilterBuilder<'a> {
         Ok((conditions.join(&format!(" {} ", group.logic)), params))
     }
 
-    fn build_filter_condition(&self, filter: &Filter) -> Result<(String, Vec<String>), String> {
-        let mut params: Vec<String> = Vec::new();
+    fn build_filter_condition(&self, filter: &Filter) -> Result<(String, Vec<SqlParam>), String> {
+        let mut params: Vec<SqlParam> = Vec::new();
         let condition = match &filter.operator {
             FilterOperator::IsNull => format!("{} IS NULL", filter.field),
             FilterOperator::IsNotNull => format!("{} IS NOT NULL", filter.field),
             FilterOperator::In | FilterOperator::NotIn => {
                 if let FilterValue::Array(arr) = &filter.value {
                     let placeholders: Vec<_> = arr.iter().map(|_| "?").collect();
-                    params.extend(arr.clone());
+                    for value in arr {
+                        params.push(value.to_param()?);
+                    }
                     format!("{} {} ({})", filter.field, filter.operator.to_sql(), placeholders.join(", "))
                 } else {
-                    params.push(filter.value.to_string_value());
+                    params.push(filter.value.to_param()?);
                     format!("{} {} (?)", filter.field, filter.operator.to_sql())
                 }
             }
             FilterOperator::Between => {
                 if let FilterValue::Array(arr) = &filter.value {
                     if arr.len() >= 2 {
-                        params.push(arr[0].clone());
-                        params.push(arr[1].clone());
+                        params.push(arr[0].to_param()?);
+                        params.push(arr[1].to_param()?);
                         format!("{} BETWEEN ? AND ?", filter.field)
                     } else { return Ok((String::new(), Vec::new())); }
                 } else { return Ok((String::new(), Vec::new())); }
@@ -282,11 +523,11 @@ impl<'a> FilterBuilder<'a> {
                     FilterValue::String(s) => if s.contains('%') { s.clone() } else { format!("%{}%", s) },
                     _ => format!("%{}%", filter.value.to_string_value()),
                 };
-                params.push(pattern);
+                params.push(SqlParam::Text(pattern));
                 format!("{} {} ?", filter.field, filter.operator.to_sql())
             }
             _ => {
-                params.push(filter.value.to_string_value());
+                params.push(filter.value.to_param()?);
                 format!("{} {} ?", filter.field, filter.operator.to_sql())
             }
         };
@@ -298,6 +539,20 @@ impl Default for FilterBuilder<'_> {
     fn default() -> Self { Self::new() }
 }
 
+/// Поля фильтров (включая вложенные группы), отклонённые whitelist, без повторов
+fn collect_rejected_fields<'g>(group: &'g FilterGroup, whitelist: &FieldWhitelist, rejected: &mut Vec<&'g str>) {
+    for item in &group.items {
+        match item {
+            FilterItem::Filter(f) => {
+                if !whitelist.is_allowed(&f.field) && !rejected.contains(&f.field.as_str()) {
+                    rejected.push(&f.field);
+                }
+            }
+            FilterItem::Group(g) => collect_rejected_fields(g, whitelist, rejected),
+        }
+    }
+}
+
 // ==================== REPORT TYPES ====================
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -427,8 +682,10 @@ impl ReportConfig {
         config
     }
     
+    /// Основной список - только strict; advisory выводится отдельным разделом
+    /// (см. `with_expiry_policy`), none в отчёты о сроках не попадает
     pub fn expiring_soon(days: i64) -> Self {
-        let mut config = Self::new("expiring_soon");
+        let mut config = Self::new("expiring_soon").with_expiry_policy(crate::models::EXPIRY_POLICY_STRICT);
         config.filters.push(ReportFilter {
             field: "days_until_expiry".to_string(),
             operator: ComparisonOperator::Lte,
@@ -443,7 +700,7 @@ impl ReportConfig {
     }
     
     pub fn expired() -> Self {
-        let mut config = Self::new("expired");
+        let mut config = Self::new("expired").with_expiry_policy(crate::models::EXPIRY_POLICY_STRICT);
         config.filters.push(ReportFilter {
             field: "days_until_expiry".to_string(),
             operator: ComparisonOperator::Lt,
@@ -452,10 +709,64 @@ impl ReportConfig {
         config
     }
 
-    pub fn build_where_clause(&self, whitelist: &FieldWhitelist) -> (String, Vec<String>) {
-        if self.filters.is_empty() { return ("1=1".to_string(), Vec::new()); }
+    /// Тот же отчёт по реагентам с другой политикой сроков годности
+    pub fn with_expiry_policy(mut self, policy: &str) -> Self {
+        self.filters.retain(|f| f.field != "expiry_policy");
+        self.filters.push(ReportFilter {
+            field: "expiry_policy".to_string(),
+            operator: ComparisonOperator::Eq,
+            value: ReportFilterValue::Exact(policy.to_string()),
+        });
+        self
+    }
+
+    /// Лист инвентаризации: непустые партии по местам хранения с ожидаемым числом
+    /// запечатанных и вскрытых упаковок
+    pub fn stocktake() -> Self {
+        let mut config = Self::new("stocktake");
+        config.filters.push(ReportFilter {
+            field: "status".to_string(),
+            operator: ComparisonOperator::Ne,
+            value: ReportFilterValue::Exact("depleted".to_string()),
+        });
+        config.columns = vec![
+            ReportColumn::new("location", "Location"),
+            ReportColumn::new("reagent_name", "Reagent"),
+            ReportColumn::new("batch_number", "Batch #"),
+            ReportColumn::new("container_size", "Container Size"),
+            ReportColumn::new("container_count", "Expected Sealed Containers"),
+            ReportColumn::new("open_containers", "Expected Open Containers"),
+            ReportColumn::new("quantity", "Quantity"),
+            ReportColumn::new("unit", "Unit"),
+        ];
+        config.sort_by = Some("location".to_string());
+        config.sort_order = "ASC".to_string();
+        config
+    }
+
+    /// Поля фильтров вне whitelist, без повторов
+    pub fn rejected_fields(&self, whitelist: &FieldWhitelist) -> Vec<String> {
+        let mut rejected: Vec<String> = Vec::new();
+        for filter in &self.filters {
+            if !whitelist.is_allowed(&filter.field) && !rejected.contains(&filter.field) {
+                rejected.push(filter.field.clone());
+            }
+        }
+        rejected
+    }
+
+    /// Фильтры по полям вне whitelist отбрасываются; в строгом режиме вместо этого
+    /// возвращается ошибка с перечнем таких полей
+    pub fn build_where_clause(&self, whitelist: &FieldWhitelist, strict: bool) -> Result<(String, Vec<SqlParam>), String> {
+        if strict {
+            let rejected = self.rejected_fields(whitelist);
+            if !rejected.is_empty() {
+                return Err(whitelist.unknown_fields_error(&rejected));
+            }
+        }
+        if self.filters.is_empty() { return Ok(("1=1".to_string(), Vec::new())); }
         let mut conditions: Vec<String> = Vec::new();
-        let mut params: Vec<String> = Vec::new();
+        let mut params: Vec<SqlParam> = Vec::new();
         for filter in &self.filters {
             if whitelist.is_allowed(&filter.field) {
                 let (cond, p) = build_report_filter_condition(filter);
@@ -465,26 +776,26 @@ impl ReportConfig {
                 }
             }
         }
-        if conditions.is_empty() { ("1=1".to_string(), Vec::new()) } else { (conditions.join(" AND "), params) }
+        if conditions.is_empty() { Ok(("1=1".to_string(), Vec::new())) } else { Ok((conditions.join(" AND "), params)) }
     }
 }
 
-fn build_report_filter_condition(filter: &ReportFilter) -> (String, Vec<String>) {
-    let mut params: Vec<String> = Vec::new();
+fn build_report_filter_condition(filter: &ReportFilter) -> (String, Vec<SqlParam>) {
+    let mut params: Vec<SqlParam> = Vec::new();
     let condition = match &filter.operator {
         ComparisonOperator::IsNull => format!("{} IS NULL", filter.field),
         ComparisonOperator::IsNotNull => format!("{} IS NOT NULL", filter.field),
         ComparisonOperator::In | ComparisonOperator::NotIn => {
             if let ReportFilterValue::List(arr) = &filter.value {
                 let placeholders: Vec<_> = arr.iter().map(|_| "?").collect();
-                params.extend(arr.clone());
+                params.extend(arr.iter().map(SqlParam::from));
                 format!("{} {} ({})", filter.field, filter.operator.to_sql(), placeholders.join(", "))
             } else { return (String::new(), Vec::new()); }
         }
         ComparisonOperator::Between => {
             if let ReportFilterValue::Range { from, to } = &filter.value {
-                params.push(from.clone());
-                params.push(to.clone());
+                params.push(SqlParam::from(from));
+                params.push(SqlParam::from(to));
                 format!("{} BETWEEN ? AND ?", filter.field)
             } else { return (String::new(), Vec::new()); }
         }
@@ -494,14 +805,14 @@ fn build_report_filter_condition(filter: &ReportFilter) -> (String, Vec<String>)
                 ReportFilterValue::Exact(s) => format!("%{}%", s),
                 _ => return (String::new(), Vec::new()),
             };
-            params.push(pattern);
+            params.push(SqlParam::Text(pattern));
             format!("{} LIKE ?", filter.field)
         }
         _ => {
             let val = match &filter.value {
-                ReportFilterValue::Exact(s) => s.clone(),
-                ReportFilterValue::Number(n) => n.to_string(),
-                ReportFilterValue::Contains(s) => s.clone(),
+                ReportFilterValue::Exact(s) => SqlParam::from(s),
+                ReportFilterValue::Number(n) => SqlParam::number(*n),
+                ReportFilterValue::Contains(s) => SqlParam::from(s),
                 _ => return (String::new(), Vec::new()),
             };
             params.push(val);
@@ -121,7 +121,7 @@ impl FieldWhitelist {
         Self::new(&[
             "id", "title", "description", "experiment_date", "instructor", "student_group",
             "location", "status", "protocol", "start_date", "end_date", "results", "notes",
-            "experiment_type", "room_id", "created_by", "updated_by", "created_at", "updated_at",
+            "experiment_type", "room_id", "outcome", "created_by", "updated_by", "created_at", "updated_at",
         ])
     }
 
@@ -37,7 +37,7 @@ impl FtsConfig {
             fts_table: "experiments_fts",
             main_table: "experiments",
             id_field: "id",
-            search_fields: vec!["title", "description", "protocol", "notes"],
+            search_fields: vec!["title", "description", "protocol", "results", "notes"],
         }
     }
 
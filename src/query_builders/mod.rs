@@ -3,34 +3,38 @@
 
 pub mod filters;
 pub mod fts;
+pub mod sql;
 
 // Re-export основных типов
 pub use filters::{
     FieldWhitelist, FilterBuilder, FilterGroup, Filter, FilterItem, FilterValue,
     ComparisonOperator, ReportFilterValue, ReportFilter, ReportColumn, ReportPreset, ReportConfig,
+    SqlParam,
 };
 pub use fts::{FtsQueryBuilder, escape_fts_query};
 
 use serde::{Serialize, Deserialize};
-use strum::{EnumString, Display, AsRefStr};
+use strum::{EnumString, Display, AsRefStr, VariantNames};
 use std::str::FromStr;
 
 
 
 // ==================== EQUIPMENT ENUMS ====================
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, AsRefStr)]
+/// Совпадает с CHECK(status IN (...)) таблицы equipment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, AsRefStr, VariantNames)]
 #[strum(serialize_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum EquipmentStatus {
     Available,
     InUse,
     Maintenance,
-    Broken,
+    Damaged,
+    Calibration,
     Retired,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, AsRefStr)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, AsRefStr, VariantNames)]
 #[strum(serialize_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum EquipmentType {
@@ -52,7 +56,8 @@ pub enum EquipmentFileType {
     Other,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, AsRefStr)]
+/// Совпадает с CHECK(maintenance_type IN (...)) таблицы equipment_maintenance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, AsRefStr, VariantNames)]
 #[strum(serialize_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum MaintenanceType {
@@ -84,17 +89,61 @@ pub enum PartStatus {
     Ordered,
 }
 
+// ==================== REAGENT ENUMS ====================
+
+/// Агрегатное состояние реагента (reagents.physical_state)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumString, Display, AsRefStr, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum PhysicalState {
+    Solid,
+    Liquid,
+    Gas,
+    Solution,
+    Other,
+}
+
+impl PhysicalState {
+    /// Значение из свободного текста (импорт, старые записи): каноническое имя или
+    /// встроенный синоним - "Solid", "powder", "s", "твёрдый", "жидкость", "р-р".
+    /// Регистр и лишние пробелы не учитываются; прочие написания - через карту
+    /// /admin/normalizations/physical-state
+    pub fn from_synonym(raw: &str) -> Option<Self> {
+        let key = raw.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase().replace('ё', "е");
+        if let Ok(state) = Self::from_str(&key) {
+            return Some(state);
+        }
+        match key.as_str() {
+            "s" | "powder" | "crystal" | "crystals" | "crystalline" | "granules" | "pellets"
+            | "твердый" | "твердое" | "твердая" | "тв." | "порошок" | "кристаллы" | "кристаллический"
+            | "гранулы" => Some(Self::Solid),
+            "l" | "liq" | "oil" | "жидкий" | "жидкое" | "жидкая" | "жидкость" | "ж." => Some(Self::Liquid),
+            "g" | "gaseous" | "compressed gas" | "газ" | "газообразный" | "газообразное" | "сжатый газ" => Some(Self::Gas),
+            "soln" | "aq" | "aqueous solution" | "раствор" | "р-р" | "водный раствор" => Some(Self::Solution),
+            "другое" | "прочее" => Some(Self::Other),
+            _ => None,
+        }
+    }
+}
+
 // ==================== SAFE QUERY BUILDER ====================
 
 /// Безопасный построитель SELECT запросов
 pub struct SafeQueryBuilder<'a> {
     base_query: &'a str,
     conditions: Vec<String>,
-    params: Vec<String>,
+    params: Vec<SqlParam>,
     whitelist: Option<&'a FieldWhitelist>,
     order_by: Option<(String, String)>,
     limit: Option<i64>,
     offset: Option<i64>,
+    select_columns: Option<Vec<String>>,
+    joins: Vec<String>,
+    join_params: Vec<SqlParam>,
+    extra_columns: Vec<String>,
+    leading_order: Option<String>,
+    strict: bool,
+    rejected: Vec<String>,
 }
 
 impl<'a> SafeQueryBuilder<'a> {
@@ -110,22 +159,74 @@ impl<'a> SafeQueryBuilder<'a> {
             order_by: None,
             limit: None,
             offset: None,
+            select_columns: None,
+            joins: Vec::new(),
+            join_params: Vec::new(),
+            extra_columns: Vec::new(),
+            leading_order: None,
+            strict: false,
+            rejected: Vec::new(),
         })
     }
 
+    /// LEFT JOIN сразу после базового запроса (база не должна содержать WHERE);
+    /// параметры JOIN связываются раньше параметров условий. На build_count не влияет.
+    pub fn left_join(&mut self, join: &str, params: Vec<SqlParam>) -> &mut Self {
+        self.joins.push(format!("LEFT JOIN {}", join));
+        self.join_params.extend(params);
+        self
+    }
+
+    /// Дополнительное выражение в списке SELECT (вне whitelist - только константные строки)
+    pub fn select_extra(&mut self, expression: &str) -> &mut Self {
+        self.extra_columns.push(expression.to_string());
+        self
+    }
+
+    /// Сортировка, применяемая до order_by (например, закреплённые строки сверху)
+    pub fn order_first_by(&mut self, expression: &str) -> &mut Self {
+        self.leading_order = Some(expression.to_string());
+        self
+    }
+
     pub fn with_whitelist(mut self, whitelist: &'a FieldWhitelist) -> Self {
         self.whitelist = Some(whitelist);
         self
     }
 
-    pub fn add_condition(&mut self, condition: &str, params: Vec<String>) -> &mut Self {
+    /// Заменяет список колонок базового `SELECT ... FROM`.
+    /// Каждая колонка - поле из whitelist, опционально с `AS alias`.
+    pub fn select_columns<S: AsRef<str>>(&mut self, columns: &[S]) -> Result<&mut Self, String> {
+        if columns.is_empty() {
+            return Err("At least one column must be selected".to_string());
+        }
+        if select_list_bounds(self.base_query).is_none() {
+            return Err("Base query must have the form SELECT ... FROM ...".to_string());
+        }
+        let mut validated = Vec::with_capacity(columns.len());
+        for column in columns {
+            let column = column.as_ref().trim();
+            let (field, alias) = split_column_alias(column);
+            if !self.is_field_allowed(field) || alias.is_some_and(|a| !filters::is_plain_identifier(a)) {
+                return Err(format!("Column '{}' is not allowed", column));
+            }
+            validated.push(match alias {
+                Some(alias) => format!("{} AS {}", field, alias),
+                None => field.to_string(),
+            });
+        }
+        self.select_columns = Some(validated);
+        Ok(self)
+    }
+
+    pub fn add_condition(&mut self, condition: &str, params: Vec<SqlParam>) -> &mut Self {
         self.conditions.push(condition.to_string());
         self.params.extend(params);
         self
     }
 
-    pub fn add_exact_match(&mut self, field: &str, value: impl Into<String>) -> &mut Self {
-        if self.is_field_allowed(field) {
+    pub fn add_exact_match(&mut self, field: &str, value: impl Into<SqlParam>) -> &mut Self {
+        if self.accept_field(field) {
             self.conditions.push(format!("{} = ?", field));
             self.params.push(value.into());
         }
@@ -133,62 +234,61 @@ impl<'a> SafeQueryBuilder<'a> {
     }
 
     pub fn add_like(&mut self, field: &str, pattern: impl Into<String>) -> &mut Self {
-        if self.is_field_allowed(field) {
+        if self.accept_field(field) {
             let p = pattern.into();
             let escaped = if p.contains('%') { p } else { format!("%{}%", p) };
             self.conditions.push(format!("{} LIKE ?", field));
-            self.params.push(escaped);
+            self.params.push(SqlParam::Text(escaped));
         }
         self
     }
 
-    pub fn add_comparison(&mut self, field: &str, op: &str, value: impl ToString) -> &mut Self {
+    pub fn add_comparison(&mut self, field: &str, op: &str, value: impl Into<SqlParam>) -> &mut Self {
         let valid_ops = ["=", "!=", "<", ">", "<=", ">=", "<>"];
-        if self.is_field_allowed(field) && valid_ops.contains(&op) {
+        if self.accept_field(field) && valid_ops.contains(&op) {
             self.conditions.push(format!("{} {} ?", field, op));
-            self.params.push(value.to_string());
+            self.params.push(value.into());
         }
         self
     }
 
     pub fn add_is_null(&mut self, field: &str) -> &mut Self {
-        if self.is_field_allowed(field) {
+        if self.accept_field(field) {
             self.conditions.push(format!("{} IS NULL", field));
         }
         self
     }
 
     pub fn add_is_not_null(&mut self, field: &str) -> &mut Self {
-        if self.is_field_allowed(field) {
+        if self.accept_field(field) {
             self.conditions.push(format!("{} IS NOT NULL", field));
         }
         self
     }
 
-    pub fn add_in_clause(&mut self, field: &str, values: &[impl ToString]) -> &mut Self {
-        if self.is_field_allowed(field) && !values.is_empty() {
+    pub fn add_in_clause<T: Clone + Into<SqlParam>>(&mut self, field: &str, values: &[T]) -> &mut Self {
+        if self.accept_field(field) && !values.is_empty() {
             let placeholders: Vec<_> = values.iter().map(|_| "?").collect();
             self.conditions.push(format!("{} IN ({})", field, placeholders.join(", ")));
             for v in values {
-                self.params.push(v.to_string());
+                self.params.push(v.clone().into());
             }
         }
         self
     }
 
-    pub fn add_between(&mut self, field: &str, from: impl ToString, to: impl ToString) -> &mut Self {
-        if self.is_field_allowed(field) {
+    pub fn add_between(&mut self, field: &str, from: impl Into<SqlParam>, to: impl Into<SqlParam>) -> &mut Self {
+        if self.accept_field(field) {
             self.conditions.push(format!("{} BETWEEN ? AND ?", field));
-            self.params.push(from.to_string());
-            self.params.push(to.to_string());
+            self.params.push(from.into());
+            self.params.push(to.into());
         }
         self
     }
 
     pub fn order_by(&mut self, field: &str, direction: &str) -> &mut Self {
-        if self.is_field_allowed(field) {
-            let dir = if direction.to_uppercase() == "ASC" { "ASC" } else { "DESC" };
-            self.order_by = Some((field.to_string(), dir.to_string()));
+        if self.accept_field(field) {
+            self.order_by = Some((field.to_string(), normalize_sort_order(direction).to_string()));
         }
         self
     }
@@ -203,12 +303,30 @@ impl<'a> SafeQueryBuilder<'a> {
         self
     }
 
-    pub fn build(&self) -> (String, Vec<String>) {
-        let mut sql = self.base_query.to_string();
+    pub fn build(&self) -> (String, Vec<SqlParam>) {
+        let mut sql = match (&self.select_columns, select_list_bounds(self.base_query)) {
+            (Some(columns), Some((start, end))) => format!(
+                "{}{}{}",
+                &self.base_query[..start],
+                columns.join(", "),
+                &self.base_query[end..]
+            ),
+            _ => self.base_query.to_string(),
+        };
+
+        if !self.extra_columns.is_empty() {
+            if let Some((_, end)) = select_list_bounds(&sql) {
+                sql.insert_str(end, &format!(", {}", self.extra_columns.join(", ")));
+            }
+        }
+        for join in &self.joins {
+            sql.push(' ');
+            sql.push_str(join);
+        }
         
         if !self.conditions.is_empty() {
-            // Проверяем, есть ли уже WHERE в базовом запросе
-            if sql.to_uppercase().contains("WHERE") {
+            // Проверяем, есть ли уже WHERE в базовом запросе (подзапросы select_extra не в счёт)
+            if self.base_query.to_uppercase().contains("WHERE") {
                 sql.push_str(" AND ");
             } else {
                 sql.push_str(" WHERE ");
@@ -216,8 +334,11 @@ impl<'a> SafeQueryBuilder<'a> {
             sql.push_str(&self.conditions.join(" AND "));
         }
         
-        if let Some((field, dir)) = &self.order_by {
-            sql.push_str(&format!(" ORDER BY {} {}", field, dir));
+        match (&self.leading_order, &self.order_by) {
+            (Some(first), Some((field, dir))) => sql.push_str(&format!(" ORDER BY {}, {} {}", first, field, dir)),
+            (Some(first), None) => sql.push_str(&format!(" ORDER BY {}", first)),
+            (None, Some((field, dir))) => sql.push_str(&format!(" ORDER BY {} {}", field, dir)),
+            (None, None) => {}
         }
         
         if let Some(limit) = self.limit {
@@ -228,10 +349,12 @@ impl<'a> SafeQueryBuilder<'a> {
             sql.push_str(&format!(" OFFSET {}", offset));
         }
         
-        (sql, self.params.clone())
+        let mut params = self.join_params.clone();
+        params.extend(self.params.iter().cloned());
+        (sql, params)
     }
 
-    pub fn build_count(&self) -> (String, Vec<String>) {
+    pub fn build_count(&self) -> (String, Vec<SqlParam>) {
         let mut sql = format!("SELECT COUNT(*) as count FROM ({}) as subquery", self.base_query);
         
         if !self.conditions.is_empty() {
@@ -251,6 +374,34 @@ impl<'a> SafeQueryBuilder<'a> {
         !self.conditions.is_empty()
     }
 
+    /// Строгий режим: поля вне whitelist запоминаются, и `check_rejected` возвращает ошибку
+    /// с их перечнем вместо запроса без этих условий
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Поля, отброшенные whitelist в add_* и order_by
+    pub fn rejected_fields(&self) -> &[String] {
+        &self.rejected
+    }
+
+    /// В строгом режиме - ошибка с перечнем отклонённых полей; вызывается до выполнения запроса
+    pub fn check_rejected(&self) -> Result<(), String> {
+        match self.whitelist {
+            Some(wl) if self.strict && !self.rejected.is_empty() => Err(wl.unknown_fields_error(&self.rejected)),
+            _ => Ok(()),
+        }
+    }
+
+    fn accept_field(&mut self, field: &str) -> bool {
+        let allowed = self.is_field_allowed(field);
+        if !allowed && !self.rejected.iter().any(|f| f == field) {
+            self.rejected.push(field.to_string());
+        }
+        allowed
+    }
+
     fn is_field_allowed(&self, field: &str) -> bool {
         match &self.whitelist {
             Some(wl) => wl.is_allowed(field),
@@ -259,14 +410,33 @@ impl<'a> SafeQueryBuilder<'a> {
     }
 }
 
+/// Границы списка колонок в `SELECT <list> FROM ...` (байтовые смещения)
+fn select_list_bounds(query: &str) -> Option<(usize, usize)> {
+    let upper = query.to_ascii_uppercase();
+    let start = upper.find("SELECT ")? + "SELECT ".len();
+    let end = upper[start..].find(" FROM ")? + start;
+    Some((start, end))
+}
+
+/// `field AS alias` -> (field, Some(alias)); регистр AS не важен
+fn split_column_alias(column: &str) -> (&str, Option<&str>) {
+    let upper = column.to_ascii_uppercase();
+    match upper.find(" AS ") {
+        Some(pos) => (column[..pos].trim(), Some(column[pos + 4..].trim())),
+        None => (column, None),
+    }
+}
+
 // ==================== COUNT QUERY BUILDER ====================
 
 /// Построитель COUNT запросов
 pub struct CountQueryBuilder<'a> {
     table: &'a str,
     conditions: Vec<String>,
-    params: Vec<String>,
+    params: Vec<SqlParam>,
     whitelist: Option<&'a FieldWhitelist>,
+    strict: bool,
+    rejected: Vec<String>,
 }
 
 impl<'a> CountQueryBuilder<'a> {
@@ -279,6 +449,8 @@ impl<'a> CountQueryBuilder<'a> {
             conditions: Vec::new(),
             params: Vec::new(),
             whitelist: None,
+            strict: false,
+            rejected: Vec::new(),
         })
     }
 
@@ -287,74 +459,74 @@ impl<'a> CountQueryBuilder<'a> {
         self
     }
 
-    pub fn add_exact_match(&mut self, field: &str, value: impl Into<String>) -> &mut Self {
-        if self.is_field_allowed(field) {
+    pub fn add_exact_match(&mut self, field: &str, value: impl Into<SqlParam>) -> &mut Self {
+        if self.accept_field(field) {
             self.conditions.push(format!("{} = ?", field));
             self.params.push(value.into());
         }
         self
     }
 
-    pub fn add_comparison(&mut self, field: &str, op: &str, value: impl ToString) -> &mut Self {
+    pub fn add_comparison(&mut self, field: &str, op: &str, value: impl Into<SqlParam>) -> &mut Self {
         let valid_ops = ["=", "!=", "<", ">", "<=", ">=", "<>"];
-        if self.is_field_allowed(field) && valid_ops.contains(&op) {
+        if self.accept_field(field) && valid_ops.contains(&op) {
             self.conditions.push(format!("{} {} ?", field, op));
-            self.params.push(value.to_string());
+            self.params.push(value.into());
         }
         self
     }
 
     pub fn add_like(&mut self, field: &str, pattern: impl Into<String>) -> &mut Self {
-        if self.is_field_allowed(field) {
+        if self.accept_field(field) {
             let p = pattern.into();
             let escaped = if p.contains('%') { p } else { format!("%{}%", p) };
             self.conditions.push(format!("{} LIKE ?", field));
-            self.params.push(escaped);
+            self.params.push(SqlParam::Text(escaped));
         }
         self
     }
 
     pub fn add_is_null(&mut self, field: &str) -> &mut Self {
-        if self.is_field_allowed(field) {
+        if self.accept_field(field) {
             self.conditions.push(format!("{} IS NULL", field));
         }
         self
     }
 
     pub fn add_is_not_null(&mut self, field: &str) -> &mut Self {
-        if self.is_field_allowed(field) {
+        if self.accept_field(field) {
             self.conditions.push(format!("{} IS NOT NULL", field));
         }
         self
     }
 
-    pub fn add_in_clause(&mut self, field: &str, values: &[impl ToString]) -> &mut Self {
-        if self.is_field_allowed(field) && !values.is_empty() {
+    pub fn add_in_clause<T: Clone + Into<SqlParam>>(&mut self, field: &str, values: &[T]) -> &mut Self {
+        if self.accept_field(field) && !values.is_empty() {
             let placeholders: Vec<_> = values.iter().map(|_| "?").collect();
             self.conditions.push(format!("{} IN ({})", field, placeholders.join(", ")));
             for v in values {
-                self.params.push(v.to_string());
+                self.params.push(v.clone().into());
             }
         }
         self
     }
 
-    pub fn add_between(&mut self, field: &str, from: impl ToString, to: impl ToString) -> &mut Self {
-        if self.is_field_allowed(field) {
+    pub fn add_between(&mut self, field: &str, from: impl Into<SqlParam>, to: impl Into<SqlParam>) -> &mut Self {
+        if self.accept_field(field) {
             self.conditions.push(format!("{} BETWEEN ? AND ?", field));
-            self.params.push(from.to_string());
-            self.params.push(to.to_string());
+            self.params.push(from.into());
+            self.params.push(to.into());
         }
         self
     }
 
-    pub fn add_condition(&mut self, condition: &str, params: Vec<String>) -> &mut Self {
+    pub fn add_condition(&mut self, condition: &str, params: Vec<SqlParam>) -> &mut Self {
         self.conditions.push(condition.to_string());
         self.params.extend(params);
         self
     }
 
-    pub fn build(&self) -> (String, Vec<String>) {
+    pub fn build(&self) -> (String, Vec<SqlParam>) {
         let mut sql = format!("SELECT COUNT(*) as count FROM {}", self.table);
         
         if !self.conditions.is_empty() {
@@ -369,7 +541,7 @@ impl<'a> CountQueryBuilder<'a> {
         self.build().0
     }
 
-    pub fn params(&self) -> Vec<String> {
+    pub fn params(&self) -> Vec<SqlParam> {
         self.build().1
     }
 
@@ -377,6 +549,34 @@ impl<'a> CountQueryBuilder<'a> {
         !self.conditions.is_empty()
     }
 
+    /// Строгий режим: поля вне whitelist запоминаются, и `check_rejected` возвращает ошибку
+    /// с их перечнем вместо запроса без этих условий
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Поля, отброшенные whitelist в add_* и order_by
+    pub fn rejected_fields(&self) -> &[String] {
+        &self.rejected
+    }
+
+    /// В строгом режиме - ошибка с перечнем отклонённых полей; вызывается до выполнения запроса
+    pub fn check_rejected(&self) -> Result<(), String> {
+        match self.whitelist {
+            Some(wl) if self.strict && !self.rejected.is_empty() => Err(wl.unknown_fields_error(&self.rejected)),
+            _ => Ok(()),
+        }
+    }
+
+    fn accept_field(&mut self, field: &str) -> bool {
+        let allowed = self.is_field_allowed(field);
+        if !allowed && !self.rejected.iter().any(|f| f == field) {
+            self.rejected.push(field.to_string());
+        }
+        allowed
+    }
+
     fn is_field_allowed(&self, field: &str) -> bool {
         match &self.whitelist {
             Some(wl) => wl.is_allowed(field),
@@ -387,15 +587,74 @@ impl<'a> CountQueryBuilder<'a> {
 
 // ==================== HELPER FUNCTIONS ====================
 
-/// Генерация уникального имени файла
+/// Нормализация направления сортировки: всё, кроме "ASC", превращается в "DESC".
+/// Результат всегда статическая строка, поэтому её можно безопасно подставлять в ORDER BY.
+pub fn normalize_sort_order(order: &str) -> &'static str {
+    if order.trim().eq_ignore_ascii_case("ASC") { "ASC" } else { "DESC" }
+}
+
+/// Генерация уникального имени файла; из исходного имени берётся только ASCII-расширение
 pub fn generate_unique_filename(original: &str) -> String {
     let ext = std::path::Path::new(original)
         .extension()
         .and_then(|e| e.to_str())
-        .unwrap_or("bin");
+        .filter(|e| !e.is_empty() && e.len() <= 10 && e.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_else(|| "bin".to_string());
     format!("{}_{}.{}", uuid::Uuid::new_v4(), chrono::Utc::now().timestamp(), ext)
 }
 
+/// Предел длины исходного имени файла в байтах (как у большинства файловых систем)
+pub const MAX_ORIGINAL_FILENAME_BYTES: usize = 255;
+
+/// Исходное имя загруженного файла для хранения и показа: только последний компонент пути
+/// (браузеры и клиенты присылают `отчёт 2024/финал.pdf` или `..\..\x`), без управляющих
+/// символов и символов, недопустимых в именах файлов Windows; юникод сохраняется.
+/// Слишком длинное имя обрезается по границе символа с сохранением расширения.
+pub fn sanitize_original_filename(original: &str) -> String {
+    let base = original.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base.chars()
+        .filter(|c| !c.is_control() && !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    if cleaned.is_empty() {
+        return "file".to_string();
+    }
+    if cleaned.len() <= MAX_ORIGINAL_FILENAME_BYTES {
+        return cleaned.to_string();
+    }
+
+    let extension = cleaned.rfind('.')
+        .map(|dot| &cleaned[dot..])
+        .filter(|ext| ext.len() <= 16)
+        .unwrap_or("");
+    let stem = &cleaned[..cleaned.len() - extension.len()];
+    let mut end = MAX_ORIGINAL_FILENAME_BYTES - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", stem[..end].trim_end(), extension)
+}
+
+/// Значение Content-Disposition по RFC 6266/5987: ASCII-запасной `filename` для старых клиентов
+/// и точное имя в `filename*=UTF-8''...`. Кавычки, `\` и переводы строк в заголовок не попадают.
+pub fn content_disposition(disposition: &str, filename: &str) -> String {
+    let filename = sanitize_original_filename(filename);
+    let fallback: String = filename.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' { c } else { '_' })
+        .collect();
+
+    let mut encoded = String::with_capacity(filename.len() * 3);
+    for byte in filename.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+            | b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition, fallback, encoded)
+}
+
 /// Валидация размера файла
 pub fn validate_file_size(size: usize, max_size: usize) -> Result<(), String> {
     if size > max_size {
@@ -464,6 +723,15 @@ mod tests {
         assert_eq!(s, "maintenance");
     }
 
+    #[test]
+    fn test_equipment_status_variants_match_schema() {
+        assert_eq!(
+            EquipmentStatus::VARIANTS,
+            &["available", "in_use", "maintenance", "damaged", "calibration", "retired"]
+        );
+        assert!(EquipmentType::VARIANTS.contains(&"glassware"));
+    }
+
     #[test]
     fn test_maintenance_status_roundtrip() {
         for status in [
@@ -477,4 +745,238 @@ mod tests {
             assert_eq!(status, parsed);
         }
     }
+
+    #[test]
+    fn test_sanitize_original_filename() {
+        assert_eq!(sanitize_original_filename("отчёт 2024/финал.pdf"), "финал.pdf");
+        assert_eq!(sanitize_original_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_original_filename("..\\..\\boot.ini"), "boot.ini");
+        assert_eq!(sanitize_original_filename("report \"final\".pdf"), "report final.pdf");
+        assert_eq!(sanitize_original_filename("bad\r\nX-Injected: 1.txt"), "badX-Injected 1.txt");
+        assert_eq!(sanitize_original_filename(".."), "file");
+        assert_eq!(sanitize_original_filename("../"), "file");
+        assert_eq!(sanitize_original_filename(".hidden"), "hidden");
+
+        let overlong = format!("{}.pdf", "я".repeat(200));
+        let sanitized = sanitize_original_filename(&overlong);
+        assert!(sanitized.len() <= MAX_ORIGINAL_FILENAME_BYTES);
+        assert!(sanitized.ends_with(".pdf"));
+        assert!(sanitized.starts_with("яя"));
+    }
+
+    #[test]
+    fn test_content_disposition_encodes_unicode_and_quotes() {
+        assert_eq!(
+            content_disposition("attachment", "финал.pdf"),
+            "attachment; filename=\"_____.pdf\"; filename*=UTF-8''%D1%84%D0%B8%D0%BD%D0%B0%D0%BB.pdf"
+        );
+        // Имена из старых записей могли сохраниться без очистки
+        let header = content_disposition("inline", "a\"; filename=evil.exe\r\nSet-Cookie: x=1");
+        assert_eq!(header.matches('"').count(), 2);
+        assert!(!header.contains('\r') && !header.contains('\n'));
+        assert!(actix_web::http::header::HeaderValue::from_str(&header).is_ok());
+        assert_eq!(content_disposition("attachment", "../../secret.txt"), "attachment; filename=\"secret.txt\"; filename*=UTF-8''secret.txt");
+
+        let parsed = actix_web::http::header::ContentDisposition::from_raw(
+            &actix_web::http::header::HeaderValue::from_str(&content_disposition("attachment", "отчёт 2024.pdf")).unwrap()
+        ).unwrap();
+        assert_eq!(parsed.get_filename_ext().map(|v| String::from_utf8(v.value.clone()).unwrap()), Some("отчёт 2024.pdf".to_string()));
+    }
+
+    #[test]
+    fn test_generate_unique_filename_extension() {
+        assert!(generate_unique_filename("финал.PDF").ends_with(".pdf"));
+        assert!(generate_unique_filename("x.p\\..\\hp").ends_with(".bin"));
+        assert!(generate_unique_filename("noext").ends_with(".bin"));
+    }
+
+    #[test]
+    fn test_normalize_sort_order() {
+        assert_eq!(normalize_sort_order("asc"), "ASC");
+        assert_eq!(normalize_sort_order(" ASC "), "ASC");
+        assert_eq!(normalize_sort_order("desc"), "DESC");
+        assert_eq!(normalize_sort_order(""), "DESC");
+        assert_eq!(normalize_sort_order("ASC; DROP TABLE users"), "DESC");
+        assert_eq!(normalize_sort_order("ASC, (SELECT 1)"), "DESC");
+    }
+
+    #[test]
+    fn test_order_by_rejects_hostile_input() {
+        let whitelist = FieldWhitelist::for_experiments();
+        let hostile_fields = [
+            "title; DROP TABLE users",
+            "x; DROP TABLE users.title",
+            "(SELECT password_hash FROM users).title",
+            "experiment_date--",
+            "1",
+        ];
+        for field in hostile_fields {
+            let mut builder = SafeQueryBuilder::new("SELECT * FROM experiments")
+                .unwrap()
+                .with_whitelist(&whitelist);
+            builder.order_by(field, "ASC");
+            let (sql, _) = builder.build();
+            assert!(!sql.contains("ORDER BY"), "field {:?} must be ignored, got {}", field, sql);
+        }
+
+        let mut builder = SafeQueryBuilder::new("SELECT * FROM experiments")
+            .unwrap()
+            .with_whitelist(&whitelist);
+        builder.order_by("title", "ASC; DELETE FROM experiments");
+        let (sql, _) = builder.build();
+        assert!(sql.ends_with("ORDER BY title DESC"), "got {}", sql);
+    }
+
+    #[test]
+    fn test_select_columns() {
+        let whitelist = FieldWhitelist::new("batches", &["b.id", "b.status", "r.name"]);
+        let mut builder = SafeQueryBuilder::new(
+            "SELECT b.*, r.name as reagent_name FROM batches b JOIN reagents r ON b.reagent_id = r.id"
+        )
+            .unwrap()
+            .with_whitelist(&whitelist);
+        builder.select_columns(&["b.id", "r.name AS reagent_name"]).unwrap();
+        builder.add_exact_match("b.status", "available").limit(10);
+        let (sql, params) = builder.build();
+        assert_eq!(
+            sql,
+            "SELECT b.id, r.name AS reagent_name FROM batches b JOIN reagents r ON b.reagent_id = r.id \
+             WHERE b.status = ? LIMIT 10"
+        );
+        assert_eq!(params, vec![SqlParam::Text("available".to_string())]);
+
+        // COUNT не зависит от выбранных колонок
+        let (count_sql, _) = builder.build_count();
+        assert!(count_sql.contains("SELECT b.*, r.name as reagent_name"));
+    }
+
+    #[test]
+    fn test_filter_builder_keeps_param_types() {
+        let whitelist = FieldWhitelist::for_batches();
+        let group = FilterGroup::and(vec![
+            FilterItem::filter(Filter::gt("quantity", 5.5)),
+            FilterItem::filter(Filter::between_numbers("days_until_expiry", 0.0, 30.0)),
+            FilterItem::filter(Filter::eq("status", "available")),
+            FilterItem::filter(Filter::neq("reserved_quantity", 0i64)),
+            FilterItem::filter(Filter::eq("notes", true)),
+        ]);
+        let (sql, params) = FilterBuilder::new().with_whitelist(&whitelist).build_condition(&group).unwrap();
+        assert_eq!(
+            sql,
+            "quantity > ? AND days_until_expiry BETWEEN ? AND ? AND status = ? AND reserved_quantity != ? AND notes = ?"
+        );
+        assert_eq!(params, vec![
+            SqlParam::Float(5.5),
+            SqlParam::Integer(0),
+            SqlParam::Integer(30),
+            SqlParam::Text("available".to_string()),
+            SqlParam::Integer(0),
+            SqlParam::Boolean(true),
+        ]);
+
+        let group: FilterGroup = serde_json::from_value(serde_json::json!({
+            "logic": "OR",
+            "items": [
+                { "field": "quantity", "operator": "in", "value": [1, 2.5, "3"] },
+                { "field": "quantity", "operator": "in", "value": [[1]] },
+            ]
+        })).unwrap();
+        let (_, params) = FilterBuilder::new().build_condition(&FilterGroup::or(group.items[..1].to_vec())).unwrap();
+        assert_eq!(params, vec![SqlParam::Integer(1), SqlParam::Float(2.5), SqlParam::Text("3".to_string())]);
+        assert!(FilterBuilder::new().build_condition(&group).is_err());
+    }
+
+    #[test]
+    fn test_select_columns_rejects_hostile_input() {
+        let whitelist = FieldWhitelist::for_experiments();
+        for column in [
+            "password_hash",
+            "(SELECT password_hash FROM users)",
+            "title AS x, password_hash",
+            "title AS x; DROP TABLE users",
+            "title AS 1x",
+        ] {
+            let mut builder = SafeQueryBuilder::new("SELECT * FROM experiments")
+                .unwrap()
+                .with_whitelist(&whitelist);
+            assert!(builder.select_columns(&[column]).is_err(), "column {:?} must be rejected", column);
+            assert_eq!(builder.build().0, "SELECT * FROM experiments");
+        }
+
+        let mut builder = SafeQueryBuilder::new("PRAGMA table_info(experiments)").unwrap();
+        assert!(builder.select_columns(&["title"]).is_err());
+    }
+
+    #[test]
+    fn test_whitelist_parse_selection() {
+        let whitelist = FieldWhitelist::for_reagents();
+        assert_eq!(
+            whitelist.parse_selection(" id, name ,status,id ").unwrap(),
+            vec!["id", "name", "status"]
+        );
+        let err = whitelist.parse_selection("id,password_hash,r.name").unwrap_err();
+        assert!(err.starts_with("Unknown field(s): password_hash, r.name."), "{}", err);
+        assert!(err.contains("Valid fields: appearance, batches_count"), "{}", err);
+        assert!(whitelist.parse_selection(" , ").is_err());
+    }
+    #[test]
+    fn test_strict_mode_safe_and_count_builders() {
+        let whitelist = FieldWhitelist::for_experiments();
+        for strict in [false, true] {
+            let mut builder = SafeQueryBuilder::new("SELECT * FROM experiments")
+                .unwrap()
+                .with_whitelist(&whitelist)
+                .strict(strict);
+            builder.add_exact_match("status", "planned").add_exact_match("stauts", "planned");
+            builder.order_by("titel", "ASC");
+            assert_eq!(builder.rejected_fields(), ["stauts", "titel"]);
+            let (sql, _) = builder.build();
+            assert_eq!(sql, "SELECT * FROM experiments WHERE status = ?");
+
+            let mut count = CountQueryBuilder::new("experiments")
+                .unwrap()
+                .with_whitelist(&whitelist)
+                .strict(strict);
+            count.add_like("descripton", "x");
+            assert_eq!(count.rejected_fields(), ["descripton"]);
+
+            if strict {
+                let err = builder.check_rejected().unwrap_err();
+                assert!(err.starts_with("Unknown field(s): stauts, titel."), "{}", err);
+                assert!(count.check_rejected().unwrap_err().contains("descripton"));
+            } else {
+                assert!(builder.check_rejected().is_ok());
+                assert!(count.check_rejected().is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn test_strict_mode_filter_builder_and_report_config() {
+        let whitelist = FieldWhitelist::for_batches();
+        let group = FilterGroup::and(vec![
+            FilterItem::filter(Filter::eq("status", "available")),
+            FilterItem::group(FilterGroup::or(vec![
+                FilterItem::filter(Filter::eq("supplier", "Acme")),
+                FilterItem::filter(Filter::eq("suplier", "Acme")),
+            ])),
+        ]);
+        let (sql, _) = FilterBuilder::new().with_whitelist(&whitelist).build_condition(&group).unwrap();
+        assert_eq!(sql, "status = ? AND (supplier = ?)");
+        let err = FilterBuilder::new().with_whitelist(&whitelist).strict(true).build_condition(&group).unwrap_err();
+        assert!(err.starts_with("Unknown field(s): suplier."), "{}", err);
+
+        let reports = FieldWhitelist::for_reports();
+        let mut config = ReportConfig::new("custom");
+        config.filters = vec![
+            ReportFilter { field: "status".to_string(), operator: ComparisonOperator::Eq, value: ReportFilterValue::Exact("available".to_string()) },
+            ReportFilter { field: "expiry".to_string(), operator: ComparisonOperator::Eq, value: ReportFilterValue::Exact("2024-01-01".to_string()) },
+        ];
+        assert_eq!(config.rejected_fields(&reports), ["expiry"]);
+        let (where_clause, params) = config.build_where_clause(&reports, false).unwrap();
+        assert!(!where_clause.contains("expiry"), "{}", where_clause);
+        assert_eq!(params.len(), 1);
+        let err = config.build_where_clause(&reports, true).unwrap_err();
+        assert!(err.starts_with("Unknown field(s): expiry."), "{}", err);
+    }
 }
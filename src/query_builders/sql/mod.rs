@@ -1,8 +1,6 @@
 // src/query_builders/sql/mod.rs
 //! SQL построители запросов
 
-pub mod select;
-pub mod count;
+pub mod update;
 
-pub use select::SafeQueryBuilder;
-pub use count::CountQueryBuilder;
+pub use update::{nullable, UpdateBuilder};
@@ -0,0 +1,239 @@
+// src/query_builders/sql/update.rs
+//! Построитель динамического UPDATE
+//!
+//! Значения передаются типизированными (`SqlParam`), поэтому REAL/INTEGER-колонки
+//! получают числа, а не строки. Поле частичного обновления объявляется как
+//! `Option<Option<T>>` с `#[serde(default, deserialize_with = "nullable")]`:
+//! поля нет - не менять, `null` - очистить (NULL), значение - записать.
+
+use serde::{Deserialize, Deserializer};
+use sqlx::sqlite::SqliteExecutor;
+
+use crate::error::{ApiError, ApiResult};
+use crate::query_builders::filters::{FieldWhitelist, SqlParam};
+
+/// serde-обёртка для `Option<Option<T>>`: `null` -> Some(None); отсутствие поля даёт
+/// None через `#[serde(default)]`
+pub fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Assignment {
+    Value(SqlParam),
+    /// Статическое SQL-выражение, например `datetime('now')`
+    Expr(&'static str),
+}
+
+/// Построитель `UPDATE {table} SET ... WHERE ...`.
+/// Колонки проверяются по whitelist таблицы (псевдонимы разрешаются в колонки);
+/// повторная установка колонки заменяет прежнее значение.
+#[derive(Debug)]
+pub struct UpdateBuilder {
+    whitelist: FieldWhitelist,
+    sets: Vec<(String, Assignment)>,
+    keys: Vec<(&'static str, SqlParam)>,
+    rejected: Vec<String>,
+}
+
+impl UpdateBuilder {
+    pub fn new(whitelist: FieldWhitelist) -> Self {
+        Self { whitelist, sets: Vec::new(), keys: Vec::new(), rejected: Vec::new() }
+    }
+
+    fn assign(&mut self, field: &str, assignment: Assignment) -> &mut Self {
+        let column = self.whitelist.column(field);
+        // Префикс таблицы в SET недопустим, хотя `is_allowed` его принимает
+        if column.contains('.') || !self.whitelist.is_allowed(column) {
+            self.rejected.push(field.to_string());
+            return self;
+        }
+        let column = column.to_string();
+        match self.sets.iter_mut().find(|(existing, _)| *existing == column) {
+            Some(slot) => slot.1 = assignment,
+            None => self.sets.push((column, assignment)),
+        }
+        self
+    }
+
+    /// Записать значение
+    pub fn set(&mut self, field: &str, value: impl Into<SqlParam>) -> &mut Self {
+        self.assign(field, Assignment::Value(value.into()))
+    }
+
+    /// Записать значение, если поле передано
+    pub fn set_opt<V: Clone + Into<SqlParam>>(&mut self, field: &str, value: &Option<V>) -> &mut Self {
+        match value {
+            Some(value) => self.set(field, value.clone()),
+            None => self,
+        }
+    }
+
+    /// Поле с очисткой: None - не менять, Some(None) - NULL, Some(Some(v)) - значение
+    pub fn patch<V: Clone + Into<SqlParam>>(&mut self, field: &str, value: &Option<Option<V>>) -> &mut Self {
+        match value {
+            Some(Some(value)) => self.set(field, value.clone()),
+            Some(None) => self.set_null(field),
+            None => self,
+        }
+    }
+
+    /// Как `patch` для текста: пустая строка (после trim) тоже записывается как NULL
+    pub fn patch_text(&mut self, field: &str, value: &Option<Option<String>>) -> &mut Self {
+        match value.as_ref().map(|v| v.as_deref().map(str::trim).filter(|t| !t.is_empty())) {
+            Some(Some(text)) => self.set(field, text),
+            Some(None) => self.set_null(field),
+            None => self,
+        }
+    }
+
+    pub fn set_null(&mut self, field: &str) -> &mut Self {
+        self.assign(field, Assignment::Value(SqlParam::Null))
+    }
+
+    pub fn set_sql(&mut self, field: &str, expr: &'static str) -> &mut Self {
+        self.assign(field, Assignment::Expr(expr))
+    }
+
+    /// Условие `WHERE column = ?` (несколько условий объединяются через AND)
+    pub fn where_eq(&mut self, column: &'static str, value: impl Into<SqlParam>) -> &mut Self {
+        self.keys.push((column, value.into()));
+        self
+    }
+
+    /// Ни одной колонки для SET
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    /// SQL и параметры по порядку
+    pub fn build(&self) -> ApiResult<(String, Vec<SqlParam>)> {
+        let table = self.whitelist.table();
+        if !self.rejected.is_empty() {
+            return Err(ApiError::internal_error(format!(
+                "Columns not updatable in {}: {}", table, self.rejected.join(", ")
+            )));
+        }
+        if self.sets.is_empty() {
+            return Err(ApiError::bad_request("No fields to update"));
+        }
+        if self.keys.is_empty() {
+            return Err(ApiError::internal_error(format!("UPDATE {} without a WHERE condition", table)));
+        }
+
+        let mut params = Vec::with_capacity(self.sets.len() + self.keys.len());
+        let mut sets = Vec::with_capacity(self.sets.len());
+        for (column, assignment) in &self.sets {
+            match assignment {
+                Assignment::Value(param) => {
+                    sets.push(format!("{} = ?", column));
+                    params.push(param.clone());
+                }
+                Assignment::Expr(expr) => sets.push(format!("{} = {}", column, expr)),
+            }
+        }
+        let mut conditions = Vec::with_capacity(self.keys.len());
+        for (column, param) in &self.keys {
+            conditions.push(format!("{} = ?", column));
+            params.push(param.clone());
+        }
+
+        let sql = format!("UPDATE {} SET {} WHERE {}", table, sets.join(", "), conditions.join(" AND "));
+        Ok((sql, params))
+    }
+
+    /// Выполнить UPDATE; возвращает число изменённых строк
+    pub async fn execute<'e>(&self, executor: impl SqliteExecutor<'e>) -> ApiResult<u64> {
+        let (sql, params) = self.build()?;
+        let mut query = sqlx::query(&sql);
+        for param in params {
+            query = query.bind(param);
+        }
+        Ok(query.execute(executor).await?.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    fn items() -> FieldWhitelist {
+        FieldWhitelist::new("items", &["name", "notes", "cost", "quantity", "updated_at"])
+            .with_alias("comment", "notes")
+    }
+
+    #[derive(Deserialize)]
+    struct Patch {
+        #[serde(default, deserialize_with = "nullable")]
+        notes: Option<Option<String>>,
+        #[serde(default, deserialize_with = "nullable")]
+        cost: Option<Option<f64>>,
+    }
+
+    fn patch(json: serde_json::Value) -> Patch {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_absent_null_and_value_are_distinguished() {
+        let absent = patch(serde_json::json!({}));
+        let cleared = patch(serde_json::json!({ "notes": null, "cost": null }));
+        let set = patch(serde_json::json!({ "notes": "  ", "cost": 12.5 }));
+
+        let mut builder = UpdateBuilder::new(items());
+        builder.patch_text("notes", &absent.notes).patch("cost", &absent.cost);
+        assert!(builder.is_empty());
+
+        builder.patch_text("notes", &cleared.notes).patch("cost", &cleared.cost).where_eq("id", "i1");
+        let (sql, values) = builder.build().unwrap();
+        assert_eq!(sql, "UPDATE items SET notes = ?, cost = ? WHERE id = ?");
+        assert_eq!(values, [SqlParam::Null, SqlParam::Null, SqlParam::Text("i1".into())]);
+
+        // Пустой текст - тоже NULL; число остаётся числом
+        let mut builder = UpdateBuilder::new(items());
+        builder.patch_text("notes", &set.notes).patch("cost", &set.cost).where_eq("id", "i1");
+        let (_, values) = builder.build().unwrap();
+        assert_eq!(values[..2], [SqlParam::Null, SqlParam::Float(12.5)]);
+    }
+
+    #[test]
+    fn test_whitelist_expressions_and_empty_updates() {
+        let mut builder = UpdateBuilder::new(items());
+        builder.set("name", "x").set("password_hash", "y").set("i.notes", "z").where_eq("id", "i1");
+        assert!(matches!(builder.build(), Err(ApiError::InternalServerError(ref m)) if m.contains("password_hash")));
+
+        let mut builder = UpdateBuilder::new(items());
+        builder.where_eq("id", "i1");
+        assert!(matches!(builder.build(), Err(ApiError::BadRequest(_))));
+
+        let mut builder = UpdateBuilder::new(items());
+        builder.set("quantity", 1).set("quantity", 2).set("comment", "via alias").set_sql("updated_at", "datetime('now')")
+            .where_eq("id", "i1").where_eq("name", "x");
+        let (sql, values) = builder.build().unwrap();
+        assert_eq!(sql, "UPDATE items SET quantity = ?, notes = ?, updated_at = datetime('now') WHERE id = ? AND name = ?");
+        assert_eq!(values[0], SqlParam::Integer(2));
+    }
+
+    #[actix_web::test]
+    async fn test_execute_binds_typed_values_and_clears_to_null() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::query("CREATE TABLE items (id TEXT PRIMARY KEY, name TEXT, notes TEXT, cost REAL, quantity INTEGER, updated_at TEXT)")
+            .execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO items VALUES ('i1', 'x', 'old', 1.0, 1, NULL)").execute(&pool).await.unwrap();
+
+        let mut builder = UpdateBuilder::new(items());
+        builder.patch_text("notes", &Some(None)).set("cost", 2.75).set("quantity", 7i64).where_eq("id", "i1");
+        assert_eq!(builder.execute(&pool).await.unwrap(), 1);
+
+        let (notes, cost_type, quantity_type): (Option<String>, String, String) = sqlx::query_as(
+            "SELECT notes, typeof(cost), typeof(quantity) FROM items WHERE id = 'i1'"
+        ).fetch_one(&pool).await.unwrap();
+        assert_eq!(notes, None);
+        assert_eq!((cost_type.as_str(), quantity_type.as_str()), ("real", "integer"));
+    }
+}
@@ -0,0 +1,568 @@
+// src/query_log.rs
+//! Профилирование SQL-запросов через `sqlite3_trace_v2`.
+//!
+//! Каждое соединение пула получает trace-хук (см. `install_query_tracing`), который
+//! считает длительность, число строк и параметров каждого выполненного statement.
+//! Для всех запросов обновляется гистограмма латентности (только атомики),
+//! медленные запросы нормализуются в fingerprint и попадают в лог и кольцевой буфер.
+//! Значения параметров никуда не пишутся - только их количество.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use libsqlite3_sys as ffi;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteConnection;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::auth::{get_current_user, UserRole};
+use crate::config::QueryLogConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+
+// ==================== HISTOGRAM BUCKETS ====================
+
+/// Верхние границы корзин гистограммы, мс (семантика `le` как в Prometheus)
+pub const LATENCY_BUCKETS_MS: [f64; 12] = [
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 1000.0,
+];
+
+const BUCKET_COUNT: usize = LATENCY_BUCKETS_MS.len() + 1; // + "+Inf"
+
+// ==================== QUERY STATS ====================
+
+/// Запись кольцевого буфера медленных запросов
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowQueryEntry {
+    pub fingerprint_id: String,
+    pub fingerprint: String,
+    pub duration_ms: f64,
+    pub param_count: i32,
+    pub rows_returned: u64,
+    pub rows_affected: u64,
+    /// Шаги полного сканирования таблиц (SQLITE_STMTSTATUS_FULLSCAN_STEP)
+    pub full_scan_steps: i32,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Агрегат по одному fingerprint для `GET /admin/slow-queries`
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowQuerySummary {
+    pub fingerprint_id: String,
+    pub fingerprint: String,
+    pub occurrences: u64,
+    pub max_duration_ms: f64,
+    pub avg_duration_ms: f64,
+    pub total_duration_ms: f64,
+    pub param_count: i32,
+    pub max_rows_returned: u64,
+    pub max_full_scan_steps: i32,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyBucket {
+    /// Верхняя граница, мс; `None` = +Inf
+    pub le_ms: Option<f64>,
+    /// Кумулятивное число запросов с длительностью <= le
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryLatencyHistogram {
+    pub enabled: bool,
+    pub slow_threshold_ms: f64,
+    pub queries_total: u64,
+    pub slow_queries_total: u64,
+    pub sum_ms: f64,
+    pub buckets: Vec<LatencyBucket>,
+}
+
+struct SlowQueryBuffer {
+    entries: VecDeque<SlowQueryEntry>,
+    capacity: usize,
+}
+
+/// Счётчики запросов; глобальный экземпляр - `query_stats()`
+pub struct QueryStats {
+    enabled: AtomicBool,
+    slow_threshold_ns: AtomicU64,
+    buckets: [AtomicU64; BUCKET_COUNT],
+    count: AtomicU64,
+    sum_ns: AtomicU64,
+    slow_count: AtomicU64,
+    slow_log: Mutex<SlowQueryBuffer>,
+}
+
+lazy_static::lazy_static! {
+    static ref QUERY_STATS: QueryStats = QueryStats::new(&QueryLogConfig::default());
+    /// Точка отсчёта для времени старта statement (хранится в AtomicU64)
+    static ref TRACE_EPOCH: Instant = Instant::now();
+}
+
+pub fn query_stats() -> &'static QueryStats {
+    &QUERY_STATS
+}
+
+impl QueryStats {
+    pub fn new(config: &QueryLogConfig) -> Self {
+        Self {
+            enabled: AtomicBool::new(config.enabled),
+            slow_threshold_ns: AtomicU64::new(config.slow_threshold_ms.saturating_mul(1_000_000)),
+            buckets: Default::default(),
+            count: AtomicU64::new(0),
+            sum_ns: AtomicU64::new(0),
+            slow_count: AtomicU64::new(0),
+            slow_log: Mutex::new(SlowQueryBuffer {
+                entries: VecDeque::new(),
+                capacity: config.ring_buffer_size.max(1),
+            }),
+        }
+    }
+
+    pub fn configure(&self, config: &QueryLogConfig) {
+        self.enabled.store(config.enabled, Ordering::Relaxed);
+        self.slow_threshold_ns
+            .store(config.slow_threshold_ms.saturating_mul(1_000_000), Ordering::Relaxed);
+        if let Ok(mut log) = self.slow_log.lock() {
+            log.capacity = config.ring_buffer_size.max(1);
+            while log.entries.len() > log.capacity {
+                log.entries.pop_front();
+            }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Учёт одного выполненного statement. Быстрый путь - только атомики;
+    /// нормализация SQL и блокировка буфера происходят лишь для медленных запросов.
+    pub fn record(&self, sql: &str, elapsed: Duration, details: StatementDetails) {
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        let ms = nanos as f64 / 1_000_000.0;
+        let bucket = LATENCY_BUCKETS_MS.iter()
+            .position(|&le| ms <= le)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_ns.fetch_add(nanos, Ordering::Relaxed);
+
+        if nanos < self.slow_threshold_ns.load(Ordering::Relaxed) {
+            return;
+        }
+        self.slow_count.fetch_add(1, Ordering::Relaxed);
+
+        let fingerprint = normalize_sql(sql);
+        let fingerprint_id = fingerprint_id(&fingerprint);
+        log::warn!(
+            "Slow query {:.1} ms [{}] params={} rows_returned={} rows_affected={} full_scan_steps={}: {}",
+            ms, fingerprint_id, details.param_count, details.rows_returned,
+            details.rows_affected, details.full_scan_steps, fingerprint
+        );
+
+        if let Ok(mut log) = self.slow_log.lock() {
+            if log.entries.len() >= log.capacity {
+                log.entries.pop_front();
+            }
+            log.entries.push_back(SlowQueryEntry {
+                fingerprint_id,
+                fingerprint,
+                duration_ms: ms,
+                param_count: details.param_count,
+                rows_returned: details.rows_returned,
+                rows_affected: details.rows_affected,
+                full_scan_steps: details.full_scan_steps,
+                recorded_at: Utc::now(),
+            });
+        }
+    }
+
+    pub fn histogram(&self) -> QueryLatencyHistogram {
+        let mut cumulative = 0;
+        let buckets = (0..BUCKET_COUNT)
+            .map(|i| {
+                cumulative += self.buckets[i].load(Ordering::Relaxed);
+                LatencyBucket { le_ms: LATENCY_BUCKETS_MS.get(i).copied(), count: cumulative }
+            })
+            .collect();
+
+        QueryLatencyHistogram {
+            enabled: self.is_enabled(),
+            slow_threshold_ms: self.slow_threshold_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            queries_total: self.count.load(Ordering::Relaxed),
+            slow_queries_total: self.slow_count.load(Ordering::Relaxed),
+            sum_ms: self.sum_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            buckets,
+        }
+    }
+
+    /// Top-N fingerprint'ов из буфера, по суммарному времени
+    pub fn top_slow(&self, limit: usize) -> Vec<SlowQuerySummary> {
+        let entries: Vec<SlowQueryEntry> = match self.slow_log.lock() {
+            Ok(log) => log.entries.iter().cloned().collect(),
+            Err(_) => return Vec::new(),
+        };
+
+        let mut grouped: HashMap<String, SlowQuerySummary> = HashMap::new();
+        for entry in entries {
+            let summary = grouped.entry(entry.fingerprint_id.clone()).or_insert_with(|| SlowQuerySummary {
+                fingerprint_id: entry.fingerprint_id.clone(),
+                fingerprint: entry.fingerprint.clone(),
+                occurrences: 0,
+                max_duration_ms: 0.0,
+                avg_duration_ms: 0.0,
+                total_duration_ms: 0.0,
+                param_count: entry.param_count,
+                max_rows_returned: 0,
+                max_full_scan_steps: 0,
+                last_seen: entry.recorded_at,
+            });
+            summary.occurrences += 1;
+            summary.total_duration_ms += entry.duration_ms;
+            summary.max_duration_ms = summary.max_duration_ms.max(entry.duration_ms);
+            summary.max_rows_returned = summary.max_rows_returned.max(entry.rows_returned);
+            summary.max_full_scan_steps = summary.max_full_scan_steps.max(entry.full_scan_steps);
+            summary.last_seen = summary.last_seen.max(entry.recorded_at);
+        }
+
+        let mut summaries: Vec<SlowQuerySummary> = grouped.into_values()
+            .map(|mut s| {
+                s.avg_duration_ms = s.total_duration_ms / s.occurrences as f64;
+                s
+            })
+            .collect();
+        summaries.sort_by(|a, b| b.total_duration_ms.total_cmp(&a.total_duration_ms));
+        summaries.truncate(limit);
+        summaries
+    }
+}
+
+/// Детали statement, собранные trace-хуком
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatementDetails {
+    pub param_count: i32,
+    pub rows_returned: u64,
+    pub rows_affected: u64,
+    pub full_scan_steps: i32,
+}
+
+// ==================== FINGERPRINT ====================
+
+/// Нормализация SQL: литералы -> `?`, списки `(?, ?, ...)` сворачиваются,
+/// пробелы схлопываются. Одинаковые по форме запросы дают один fingerprint.
+pub fn normalize_sql(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut prev: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // Строковый литерал ('' внутри - экранированная кавычка)
+                while let Some(n) = chars.next() {
+                    if n == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                out.push('?');
+                prev = Some('?');
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                // Комментарий до конца строки
+                for n in chars.by_ref() {
+                    if n == '\n' {
+                        break;
+                    }
+                }
+                if prev != Some(' ') {
+                    out.push(' ');
+                    prev = Some(' ');
+                }
+            }
+            c if c.is_whitespace() => {
+                if prev.is_some() && prev != Some(' ') {
+                    out.push(' ');
+                    prev = Some(' ');
+                }
+            }
+            c if c.is_ascii_digit()
+                && !prev.is_some_and(|p| p.is_alphanumeric() || p == '_' || p == '.') =>
+            {
+                while chars.peek().is_some_and(|n| n.is_ascii_digit() || *n == '.') {
+                    chars.next();
+                }
+                out.push('?');
+                prev = Some('?');
+            }
+            c => {
+                out.push(c);
+                prev = Some(c);
+            }
+        }
+    }
+
+    collapse_placeholder_lists(out.trim_end())
+}
+
+/// `(?, ?, ?)` -> `(?, ...)`: IN-списки разной длины дают один fingerprint
+fn collapse_placeholder_lists(sql: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref PLACEHOLDER_LIST: regex::Regex =
+            regex::Regex::new(r"\(\s*\?(?:\s*,\s*\?)+\s*\)").unwrap();
+    }
+    PLACEHOLDER_LIST.replace_all(sql, "(?, ...)").into_owned()
+}
+
+/// Короткий стабильный идентификатор fingerprint (FNV-1a, 64 бит)
+pub fn fingerprint_id(fingerprint: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in fingerprint.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+// ==================== SQLITE TRACE HOOK ====================
+
+/// Состояние одного соединения; освобождается по SQLITE_TRACE_CLOSE
+struct ConnectionTrace {
+    /// Время старта текущего statement (нс от TRACE_EPOCH), 0 - нет
+    started_ns: AtomicU64,
+    rows_returned: AtomicU64,
+    capture: Option<Arc<StatementCapture>>,
+}
+
+/// Тексты statement верхнего уровня, выполненных соединением (подзапросы и триггеры не видны).
+/// Нужен тестам, проверяющим число запросов обработчика.
+#[derive(Default)]
+pub struct StatementCapture(Mutex<Vec<String>>);
+
+impl StatementCapture {
+    /// Забирает накопленные тексты, буфер очищается
+    #[cfg(test)]
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+fn epoch_nanos() -> u64 {
+    (TRACE_EPOCH.elapsed().as_nanos() as u64).max(1)
+}
+
+/// Подключает профилирование к соединению. Вызывается из `after_connect` пула.
+pub async fn install_query_tracing(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    if !query_stats().is_enabled() {
+        return Ok(());
+    }
+    install_trace(conn, None).await
+}
+
+/// То же профилирование плюс запись текстов statement в `capture`
+#[cfg(test)]
+pub async fn install_statement_capture(
+    conn: &mut SqliteConnection,
+    capture: Arc<StatementCapture>,
+) -> Result<(), sqlx::Error> {
+    install_trace(conn, Some(capture)).await
+}
+
+async fn install_trace(conn: &mut SqliteConnection, capture: Option<Arc<StatementCapture>>) -> Result<(), sqlx::Error> {
+    let mut handle = conn.lock_handle().await?;
+    let db = handle.as_raw_handle().as_ptr();
+    let ctx = Box::into_raw(Box::new(ConnectionTrace {
+        started_ns: AtomicU64::new(0),
+        rows_returned: AtomicU64::new(0),
+        capture,
+    }));
+    let mask = (ffi::SQLITE_TRACE_STMT | ffi::SQLITE_TRACE_PROFILE
+        | ffi::SQLITE_TRACE_ROW | ffi::SQLITE_TRACE_CLOSE) as c_uint;
+
+    // SAFETY: db - живой handle, пока держим LockedSqliteHandle; ctx живёт до SQLITE_TRACE_CLOSE
+    let rc = unsafe { ffi::sqlite3_trace_v2(db, mask, Some(trace_callback), ctx.cast::<c_void>()) };
+    if rc != ffi::SQLITE_OK {
+        // SAFETY: хук не установлен, ctx больше никем не используется
+        drop(unsafe { Box::from_raw(ctx) });
+        return Err(sqlx::Error::Protocol(format!("sqlite3_trace_v2 failed with code {}", rc)));
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn trace_callback(event: c_uint, ctx: *mut c_void, p: *mut c_void, x: *mut c_void) -> c_int {
+    let trace = ctx.cast::<ConnectionTrace>();
+    match event as c_int {
+        ffi::SQLITE_TRACE_STMT => {
+            // Для триггеров X - комментарий "-- TRIGGER name": не сбрасываем старт внешнего statement
+            let text = x as *const c_char;
+            let is_trigger = !text.is_null() && *text == b'-' as c_char;
+            if !is_trigger {
+                (*trace).started_ns.store(epoch_nanos(), Ordering::Relaxed);
+                (*trace).rows_returned.store(0, Ordering::Relaxed);
+                if let Some(capture) = &(*trace).capture {
+                    if !text.is_null() {
+                        let sql = CStr::from_ptr(text).to_string_lossy().into_owned();
+                        capture.0.lock().unwrap_or_else(|e| e.into_inner()).push(sql);
+                    }
+                }
+            }
+        }
+        ffi::SQLITE_TRACE_ROW => {
+            (*trace).rows_returned.fetch_add(1, Ordering::Relaxed);
+        }
+        ffi::SQLITE_TRACE_PROFILE => {
+            let started = (*trace).started_ns.swap(0, Ordering::Relaxed);
+            let elapsed = if started > 0 {
+                Duration::from_nanos(epoch_nanos().saturating_sub(started))
+            } else {
+                // Запасной вариант: оценка самого SQLite (миллисекундная точность)
+                Duration::from_nanos((*(x as *const i64)).max(0) as u64)
+            };
+            let stmt = p.cast::<ffi::sqlite3_stmt>();
+            let sql_ptr = ffi::sqlite3_sql(stmt);
+            if sql_ptr.is_null() {
+                return 0;
+            }
+            let sql = CStr::from_ptr(sql_ptr).to_string_lossy();
+            let rows_affected = if ffi::sqlite3_stmt_readonly(stmt) == 0 {
+                ffi::sqlite3_changes(ffi::sqlite3_db_handle(stmt)).max(0) as u64
+            } else {
+                0
+            };
+            let details = StatementDetails {
+                param_count: ffi::sqlite3_bind_parameter_count(stmt),
+                rows_returned: (*trace).rows_returned.swap(0, Ordering::Relaxed),
+                rows_affected,
+                full_scan_steps: ffi::sqlite3_stmt_status(stmt, ffi::SQLITE_STMTSTATUS_FULLSCAN_STEP, 0),
+            };
+            query_stats().record(&sql, elapsed, details);
+        }
+        ffi::SQLITE_TRACE_CLOSE => {
+            drop(Box::from_raw(trace));
+        }
+        _ => {}
+    }
+    0
+}
+
+// ==================== HANDLERS ====================
+
+#[derive(Debug, Deserialize)]
+pub struct SlowQueriesQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SlowQueriesResponse {
+    pub slow_threshold_ms: f64,
+    pub slow_queries_total: u64,
+    pub fingerprints: Vec<SlowQuerySummary>,
+}
+
+/// GET /admin/slow-queries - самые тяжёлые fingerprint'ы из кольцевого буфера
+pub async fn get_slow_queries(
+    query: web::Query<SlowQueriesQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    if claims.role != UserRole::Admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let stats = query_stats();
+    let histogram = stats.histogram();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(SlowQueriesResponse {
+        slow_threshold_ms: histogram.slow_threshold_ms,
+        slow_queries_total: histogram.slow_queries_total,
+        fingerprints: stats.top_slow(limit),
+    })))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(slow_threshold_ms: u64, ring_buffer_size: usize) -> QueryLogConfig {
+        QueryLogConfig { enabled: true, slow_threshold_ms, ring_buffer_size }
+    }
+
+    #[test]
+    fn test_normalize_sql() {
+        assert_eq!(
+            normalize_sql("SELECT *\n  FROM batches   WHERE status = 'available' AND quantity > 10.5 LIMIT 20 OFFSET 40"),
+            "SELECT * FROM batches WHERE status = ? AND quantity > ? LIMIT ? OFFSET ?"
+        );
+        assert_eq!(
+            normalize_sql("SELECT id FROM t2 WHERE id IN (?, ?, ?) AND name = 'O''Brien' -- note\n"),
+            "SELECT id FROM t2 WHERE id IN (?, ...) AND name = ?"
+        );
+        assert_eq!(
+            normalize_sql("SELECT id FROM t2 WHERE id IN (?,?)"),
+            normalize_sql("SELECT id FROM t2 WHERE id IN (?, ?, ?, ?)")
+        );
+        assert_eq!(fingerprint_id("SELECT 1").len(), 16);
+    }
+
+    #[test]
+    fn test_histogram_and_slow_buffer() {
+        let stats = QueryStats::new(&config(100, 2));
+        let details = StatementDetails { param_count: 2, rows_returned: 5, ..Default::default() };
+
+        stats.record("SELECT 1", Duration::from_micros(50), details);
+        stats.record("SELECT * FROM batches WHERE id = ?", Duration::from_millis(3), details);
+        stats.record("SELECT * FROM batches WHERE id = ?", Duration::from_millis(150), details);
+        stats.record("SELECT * FROM reagents WHERE name LIKE 'a%'", Duration::from_millis(120), details);
+        stats.record("SELECT * FROM batches WHERE id = ?", Duration::from_millis(400), details);
+
+        let histogram = stats.histogram();
+        assert_eq!(histogram.queries_total, 5);
+        assert_eq!(histogram.slow_queries_total, 3);
+        assert_eq!(histogram.buckets[0].count, 1); // <= 0.1 ms
+        assert_eq!(histogram.buckets.last().unwrap().count, 5);
+        assert!(histogram.buckets.windows(2).all(|w| w[0].count <= w[1].count));
+
+        // Буфер на 2 записи: самая старая медленная (150 ms) вытеснена
+        let top = stats.top_slow(10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].fingerprint, "SELECT * FROM batches WHERE id = ?");
+        assert_eq!(top[0].occurrences, 1);
+        assert_eq!(top[0].param_count, 2);
+        assert_eq!(top[1].fingerprint, "SELECT * FROM reagents WHERE name LIKE ?");
+        assert_eq!(stats.top_slow(1).len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_trace_hook_records_statements() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .after_connect(|conn, _meta| Box::pin(install_query_tracing(conn)))
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        let before = query_stats().histogram().queries_total;
+        sqlx::query("CREATE TABLE t (id INTEGER)").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO t VALUES (1), (2), (3)").execute(&pool).await.unwrap();
+        let rows: Vec<(i64,)> = sqlx::query_as("SELECT id FROM t WHERE id > ?")
+            .bind(0)
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+
+        assert!(query_stats().histogram().queries_total >= before + 3);
+        pool.close().await;
+    }
+}
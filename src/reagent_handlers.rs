@@ -3,23 +3,63 @@
 //! Оптимизировано для 270,000+ записей
 //! ✅ FTS5 поиск с автоматическим fallback на LIKE
 
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use std::sync::Arc;
 use crate::AppState;
 use crate::models::*;
 use crate::error::{ApiError, ApiResult};
+use crate::audit::ChangeSet;
+use crate::events::BusinessEvent;
+use crate::outbox;
 use crate::handlers::ApiResponse;
-use crate::validator::FieldValidator;
+use crate::reagent_image_handlers::{image_url, ImageSize};
+use crate::favorites_handlers::{favorites_join, FavoriteEntity, FAVORITES_FIRST_ORDER, FAVORITE_FLAG_COLUMN};
+use crate::validator::{CustomValidate, ValidationResult};
+use crate::query_builders::{FieldWhitelist, PhysicalState};
+use crate::query_builders::sql::UpdateBuilder;
 use crate::pagination::{
     HybridPaginationQuery, HybridPaginatedResponse, HybridPaginationInfo, SortingInfo,
-    CtePaginationBuilder, ReagentSortWhitelist,
+    CtePaginationBuilder, ReagentSortWhitelist, PageGroup,
     encode_cursor, decode_cursor,
 };
+use strum::VariantNames;
 use uuid::Uuid;
 use validator::Validate;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 
+/// Колонки reagents типа DateTime (для ответов с `?fields=`)
+const REAGENT_DATETIME_COLUMNS: &[&str] = &["created_at", "updated_at"];
+
+/// Статусы реагента (CHECK таблицы reagents) - допустимые значения `?status=`
+pub const REAGENT_STATUSES: &[&str] = &["active", "inactive", "discontinued"];
+
+/// Статус деактивированного реагента: скрыт из списков и выбора, партии только для чтения
+pub const INACTIVE_STATUS: &str = "inactive";
+
+/// Запрет изменений для деактивированного реагента (новые партии, расход, резервы)
+pub(crate) fn ensure_reagent_active(reagent: &Reagent) -> ApiResult<()> {
+    if reagent.status == INACTIVE_STATUS {
+        return Err(ApiError::reagent_inactive(&reagent.name));
+    }
+    Ok(())
+}
+
+/// То же по id реагента; несуществующий реагент не проверяется (его ищет вызывающий код)
+pub(crate) async fn ensure_reagent_active_by_id<'e, E>(executor: E, reagent_id: &str) -> ApiResult<()>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Sqlite>,
+{
+    let row: Option<(String, String)> = sqlx::query_as("SELECT name, status FROM reagents WHERE id = ?")
+        .bind(reagent_id)
+        .fetch_optional(executor)
+        .await?;
+    match row {
+        Some((name, status)) if status == INACTIVE_STATUS => Err(ApiError::reagent_inactive(&name)),
+        _ => Ok(()),
+    }
+}
+
 // ==================== FTS SEARCH HELPER ====================
 
 /// Проверка доступности FTS таблицы (кэшируется при старте)
@@ -114,7 +154,36 @@ pub struct ReagentListItem {
     pub total_quantity: f64,
     pub batches_count: i64,
     pub primary_unit: Option<String>,
-    
+    #[sqlx(default)]
+    pub team_id: Option<String>,
+    // Заполняется expand_team_names
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
+    #[sqlx(default)]
+    #[serde(skip_serializing)]
+    pub image_id: Option<String>,
+    // Ссылки на изображение (заполняются fill_image_urls)
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[sqlx(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
+    // Только с ?favorites_first=true
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_favorite: Option<bool>,
+}
+
+/// Ссылки на изображение прямо в строках списка - без отдельного запроса на каждую строку
+fn fill_image_urls(reagents: &mut [ReagentListItem]) {
+    for reagent in reagents {
+        if let Some(ref image_id) = reagent.image_id {
+            reagent.image_url = Some(image_url(&reagent.id, image_id, ImageSize::Full));
+            reagent.thumbnail_url = Some(image_url(&reagent.id, image_id, ImageSize::Thumb));
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -135,6 +204,9 @@ pub struct ReagentDetailResponse {
     pub updated_by: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub team_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
     // Stock info (вычисляется на лету для одного реагента)
     pub total_quantity: f64,
     pub total_unit: String,
@@ -146,6 +218,11 @@ pub struct ReagentDetailResponse {
     pub expiring_soon_count: i64,
     pub expired_count: i64,
     pub batches: Vec<Batch>,
+    pub links: Vec<ExternalLink>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_url: Option<String>,
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -174,10 +251,16 @@ struct StockAggregation {
 pub async fn get_reagents(
     app_state: web::Data<Arc<AppState>>,
     query: web::Query<HybridPaginationQuery>,
+    user_id: String,
 ) -> ApiResult<HttpResponse> {
     let pool = &app_state.db_pool;
 
-    let (page, per_page, offset) = query.normalize();
+    let fields = crate::handlers::parse_fields_param(
+        query.fields.as_deref(), &FieldWhitelist::for_reagents(),
+    )?;
+    let limits = app_state.page_limits(PageGroup::Reagents);
+    let capped = limits.is_capped(query.per_page);
+    let (page, per_page, offset) = query.normalize(limits);
     let sort_by = ReagentSortWhitelist::validate(query.sort_by());
     let sort_order = ReagentSortWhitelist::validate_order(query.sort_order());
     let is_desc = sort_order == "DESC";
@@ -192,7 +275,7 @@ pub async fn get_reagents(
         .select("id, name, formula, cas_number, manufacturer, molecular_weight, \
                  physical_state, description, storage_conditions, appearance, \
                  hazard_pictograms, status, created_by, updated_by, created_at, \
-                 updated_at, total_quantity, batches_count, primary_unit")
+                 updated_at, total_quantity, batches_count, primary_unit, team_id, image_id")
         .sort(sort_by, sort_order)
         .limit(per_page);
         
@@ -210,9 +293,11 @@ pub async fn get_reagents(
         add_search_condition_with_fts(&mut builder, search, use_fts);
     }
 
-    // Status filter
-    if let Some(ref status) = query.status {
-        builder.add_condition("status = ?", status.clone());
+    // Status filter (мультивыбор: ?status=active,inactive)
+    let statuses = crate::handlers::parse_multi_filter(query.status.as_deref(), "status", Some(REAGENT_STATUSES))?;
+    builder.add_in_clause("status", &statuses);
+    if statuses.is_empty() && !query.include_inactive.unwrap_or(false) {
+        builder.add_raw_condition("status != 'inactive'");
     }
 
     // Manufacturer filter
@@ -220,6 +305,12 @@ pub async fn get_reagents(
         builder.add_condition("manufacturer = ?", manufacturer.clone());
     }
 
+    // Physical state filter (мультивыбор: ?physical_state=solid,liquid)
+    let physical_states = crate::handlers::parse_multi_filter(
+        query.physical_state.as_deref(), "physical_state", Some(PhysicalState::VARIANTS),
+    )?;
+    builder.add_in_clause("physical_state", &physical_states);
+
     // Has stock filter
     if let Some(has_stock) = query.has_stock {
         if has_stock {
@@ -229,6 +320,24 @@ pub async fn get_reagents(
         }
     }
 
+    // Команда-владелец и «мои» (созданные мной или моими командами)
+    if let Some(team_id) = crate::team_handlers::normalize_team_id(query.team_id.as_deref()) {
+        builder.add_condition("team_id = ?", team_id.to_string());
+    }
+    if query.mine.unwrap_or(false) {
+        builder.add_search(crate::team_handlers::MINE_CONDITION, vec![user_id.clone(); 2]);
+    }
+
+    // ===== FAVORITES FIRST =====
+    // LEFT JOIN избранного; закреплённое сверху работает только с offset-пагинацией
+    let favorites_first = query.favorites_first.unwrap_or(false);
+    if favorites_first {
+        let (join, params) = favorites_join(FavoriteEntity::Reagent, "reagents", &user_id);
+        builder.left_join(&join, params);
+        builder.select_extra(FAVORITE_FLAG_COLUMN);
+        builder.order_first_by(FAVORITES_FIRST_ORDER);
+    }
+
     // ===== COUNT =====
     let (count_sql, count_params) = builder.build_count();
     let mut count_query = sqlx::query_scalar::<_, i64>(&count_sql);
@@ -238,76 +347,140 @@ pub async fn get_reagents(
     let total: i64 = count_query.fetch_one(pool).await?;
 
     // ===== FETCH DATA =====
-    let use_cursor = query.is_cursor_mode() && ReagentSortWhitelist::supports_keyset(sort_by);
+    let use_cursor = !favorites_first && query.is_cursor_mode() && ReagentSortWhitelist::supports_keyset(sort_by);
 
-    let mut reagents: Vec<ReagentListItem> = if use_cursor {
+    if use_cursor {
         // Cursor-based (keyset) pagination
         if let Some(ref cursor) = query.cursor {
             if let Some((cursor_value, cursor_id)) = decode_cursor(cursor) {
                 builder.keyset_after(cursor_value, &cursor_id, is_desc, direction);
             }
         }
+    }
+
+    let sorting = SortingInfo {
+        sort_by: sort_by.to_string(),
+        sort_order: sort_order.to_string(),
+    };
 
-        let (sql, params) = builder.build_cte(direction, is_desc);
+    // ===== PARTIAL RESPONSE (?fields=) =====
+    if let Some(fields) = fields {
+        // id и total_quantity нужны для курсора; в ответ попадают только запрошенные поля
+        let mut columns = fields.clone();
+        if use_cursor {
+            for extra in ["id", "total_quantity"] {
+                if !columns.iter().any(|c| c == extra) {
+                    columns.push(extra.to_string());
+                }
+            }
+        }
+        let builder = builder.select(&columns.join(", "));
+        let (sql, params) = if use_cursor {
+            builder.build_cte(direction, is_desc)
+        } else {
+            builder.build_simple(offset)
+        };
 
-        let mut db_query = sqlx::query_as::<_, ReagentListItem>(&sql);
-        for p in &params {
-            db_query = db_query.bind(p);
+        let mut rows = crate::handlers::fetch_json_rows(pool, &sql, &params, REAGENT_DATETIME_COLUMNS).await?;
+        let pagination = reagent_pagination(&mut rows, &query, use_cursor, total, page, per_page, |row| {
+            (
+                row.get("total_quantity").and_then(serde_json::Value::as_f64).unwrap_or(0.0),
+                row.get("id").and_then(serde_json::Value::as_str).unwrap_or_default().to_string(),
+            )
+        });
+        for row in &mut rows {
+            row.retain(|key, _| fields.iter().any(|f| f == key) || (favorites_first && key == "is_favorite"));
+            if let Some(flag) = row.get_mut("is_favorite") {
+                *flag = serde_json::Value::Bool(flag.as_i64().unwrap_or(0) != 0);
+            }
         }
 
-        db_query.fetch_all(pool).await?
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(HybridPaginatedResponse {
+            data: rows,
+            pagination,
+            sorting,
+            limits,
+            capped,
+        })));
+    }
+
+    let (sql, params) = if use_cursor {
+        builder.build_cte(direction, is_desc)
     } else {
         // Page-based (offset) pagination
-        let (sql, params) = builder.build_simple(offset);
-
-        let mut db_query = sqlx::query_as::<_, ReagentListItem>(&sql);
-        for p in &params {
-            db_query = db_query.bind(p);
-        }
-
-        db_query.fetch_all(pool).await?
+        builder.build_simple(offset)
     };
 
+    let mut db_query = sqlx::query_as::<_, ReagentListItem>(&sql);
+    for p in &params {
+        db_query = db_query.bind(p);
+    }
+    let mut reagents: Vec<ReagentListItem> = db_query.fetch_all(pool).await?;
+    fill_image_urls(&mut reagents);
+    crate::team_handlers::expand_team_names(pool, &mut reagents).await?;
+
     // ===== PAGINATION STATE =====
-    let pagination = if use_cursor {
-        let has_more = reagents.len() > per_page as usize;
-        if has_more {
-            reagents.pop();
-        }
+    let pagination = reagent_pagination(&mut reagents, &query, use_cursor, total, page, per_page, |r| {
+        (r.total_quantity, r.id.clone())
+    });
 
-        // Reverse if going backwards
-        if direction == "prev" {
-            reagents.reverse();
-        }
+    Ok(HttpResponse::Ok().json(ApiResponse::success(HybridPaginatedResponse {
+        data: reagents,
+        pagination,
+        sorting,
+        limits,
+        capped,
+    })))
+}
 
-        let has_next = if direction == "prev" { query.cursor.is_some() } else { has_more };
-        let has_prev = if direction == "prev" { has_more } else { query.cursor.is_some() };
+/// Состояние пагинации списка реагентов; в cursor-режиме срезает лишнюю (+1) строку
+/// и разворачивает выдачу при движении назад
+fn reagent_pagination<T>(
+    items: &mut Vec<T>,
+    query: &HybridPaginationQuery,
+    use_cursor: bool,
+    total: i64,
+    page: i64,
+    per_page: i64,
+    cursor_key: impl Fn(&T) -> (f64, String),
+) -> HybridPaginationInfo {
+    if !use_cursor {
+        return HybridPaginationInfo::from_page(total, page, per_page);
+    }
 
-        let next_cursor = if has_next {
-            reagents.last().map(|r| encode_cursor(r.total_quantity, &r.id))
-        } else {
-            None
-        };
+    let direction = query.direction();
+    let has_more = items.len() > per_page as usize;
+    if has_more {
+        items.pop();
+    }
 
-        let prev_cursor = if has_prev {
-            reagents.first().map(|r| encode_cursor(r.total_quantity, &r.id))
-        } else {
-            None
-        };
+    // Reverse if going backwards
+    if direction == "prev" {
+        items.reverse();
+    }
+
+    let has_next = if direction == "prev" { query.cursor.is_some() } else { has_more };
+    let has_prev = if direction == "prev" { has_more } else { query.cursor.is_some() };
 
-        HybridPaginationInfo::from_cursor(total, per_page, has_next, has_prev, next_cursor, prev_cursor)
+    let next_cursor = if has_next {
+        items.last().map(|r| {
+            let (value, id) = cursor_key(r);
+            encode_cursor(value, &id)
+        })
     } else {
-        HybridPaginationInfo::from_page(total, page, per_page)
+        None
     };
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(HybridPaginatedResponse {
-        data: reagents,
-        pagination,
-        sorting: SortingInfo {
-            sort_by: sort_by.to_string(),
-            sort_order: sort_order.to_string(),
-        },
-    })))
+    let prev_cursor = if has_prev {
+        items.first().map(|r| {
+            let (value, id) = cursor_key(r);
+            encode_cursor(value, &id)
+        })
+    } else {
+        None
+    };
+
+    HybridPaginationInfo::from_cursor(total, per_page, has_next, has_prev, next_cursor, prev_cursor)
 }
 
 // ==================== SEARCH (autocomplete) ====================
@@ -316,6 +489,8 @@ pub async fn get_reagents(
 pub struct SearchQuery {
     pub q: String,
     pub limit: Option<i64>,
+    /// Деактивированные реагенты в подсказках не показываются, пока не запрошены явно
+    pub include_inactive: Option<bool>,
 }
 
 pub async fn search_reagents(
@@ -328,12 +503,13 @@ pub async fn search_reagents(
     }
 
     let limit = query.limit.unwrap_or(10).min(50);
+    let include_inactive = query.include_inactive.unwrap_or(false);
     let pool = &app_state.db_pool;
 
     // Проверяем FTS
     let use_fts = check_fts_available(pool).await;
 
-    let reagents: Vec<ReagentListItem> = if use_fts {
+    let mut reagents: Vec<ReagentListItem> = if use_fts {
         let fts_query = build_fts_query(q);
         if fts_query.is_empty() {
             return Ok(HttpResponse::Ok().json(ApiResponse::success(Vec::<ReagentListItem>::new())));
@@ -343,14 +519,16 @@ pub async fn search_reagents(
             r#"SELECT id, name, formula, cas_number, manufacturer, molecular_weight,
                       physical_state, description, storage_conditions, appearance,
                       hazard_pictograms, status, created_by, updated_by, created_at,
-                      updated_at, total_quantity, batches_count, primary_unit
+                      updated_at, total_quantity, batches_count, primary_unit, image_id
                FROM reagents
                WHERE rowid IN (SELECT rowid FROM reagents_fts WHERE reagents_fts MATCH ?)
                AND deleted_at IS NULL
+               AND (? OR status != 'inactive')
                ORDER BY total_quantity DESC
                LIMIT ?"#
         )
             .bind(&fts_query)
+            .bind(include_inactive)
             .bind(limit)
             .fetch_all(pool)
             .await?
@@ -360,24 +538,70 @@ pub async fn search_reagents(
             r#"SELECT id, name, formula, cas_number, manufacturer, molecular_weight,
                       physical_state, description, storage_conditions, appearance,
                       hazard_pictograms, status, created_by, updated_by, created_at,
-                      updated_at, total_quantity, batches_count, primary_unit
+                      updated_at, total_quantity, batches_count, primary_unit, image_id
                FROM reagents
-               WHERE name LIKE ? OR cas_number LIKE ? OR formula LIKE ?
+               WHERE (name LIKE ? OR cas_number LIKE ? OR formula LIKE ?)
                AND deleted_at IS NULL
+               AND (? OR status != 'inactive')
                ORDER BY total_quantity DESC
                LIMIT ?"#
         )
             .bind(&pattern)
             .bind(&pattern)
             .bind(&pattern)
+            .bind(include_inactive)
             .bind(limit)
             .fetch_all(pool)
             .await?
     };
+    fill_image_urls(&mut reagents);
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(reagents)))
 }
 
+// ==================== WITHOUT BATCHES ====================
+
+/// Реагенты без единой партии (брошенные после первого шага создания); старые - первыми
+pub async fn get_reagents_without_batches(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<crate::handlers::PaginationQuery>,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+    let limits = app_state.page_limits(PageGroup::Reagents);
+    let (page, per_page, offset) = query.normalize(limits);
+    let filter = r#"WHERE r.deleted_at IS NULL
+        AND NOT EXISTS (SELECT 1 FROM batches b WHERE b.reagent_id = r.id AND b.deleted_at IS NULL)"#;
+
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM reagents r {}", filter))
+        .fetch_one(pool)
+        .await?;
+    let mut data: Vec<ReagentListItem> = sqlx::query_as(&format!(
+        r#"SELECT r.id, r.name, r.formula, r.cas_number, r.manufacturer, r.molecular_weight,
+                  r.physical_state, r.description, r.storage_conditions, r.appearance,
+                  r.hazard_pictograms, r.status, r.created_by, r.updated_by, r.created_at,
+                  r.updated_at, r.total_quantity, r.batches_count, r.primary_unit, r.image_id
+           FROM reagents r {}
+           ORDER BY r.created_at, r.id
+           LIMIT ? OFFSET ?"#,
+        filter
+    ))
+        .bind(per_page)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+    fill_image_urls(&mut data);
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(crate::handlers::PaginatedResponse {
+        data,
+        total,
+        page,
+        per_page,
+        total_pages: (total + per_page - 1) / per_page,
+        limits,
+        capped: limits.is_capped(query.per_page),
+    })))
+}
+
 // ==================== GET BY ID ====================
 
 pub async fn get_reagent_by_id(
@@ -387,18 +611,19 @@ pub async fn get_reagent_by_id(
     let id = path.into_inner();
     let pool = &app_state.db_pool;
 
-    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+    let mut reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
         .bind(&id)
         .fetch_optional(pool)
         .await?
         .ok_or_else(|| ApiError::not_found("Reagent"))?;
+    crate::team_handlers::expand_team_names(pool, std::slice::from_mut(&mut reagent)).await?;
 
     // Получаем агрегированные данные по батчам
-    let stock: StockAggregation = sqlx::query_as(r#"
+    let mut stock: StockAggregation = sqlx::query_as(r#"
         SELECT
-            COALESCE(SUM(CASE WHEN status = 'available' THEN quantity ELSE 0 END), 0) as total_quantity,
-            COALESCE(SUM(CASE WHEN status = 'reserved' THEN quantity ELSE 0 END), 0) as reserved_quantity,
-            COALESCE(SUM(original_quantity), 0) as original_quantity,
+            COALESCE(SUM(CASE WHEN status = 'available' THEN quantity ELSE 0.0 END), 0.0) as total_quantity,
+            COALESCE(SUM(CASE WHEN status = 'reserved' THEN quantity ELSE 0.0 END), 0.0) as reserved_quantity,
+            COALESCE(SUM(original_quantity), 0.0) as original_quantity,
             COUNT(*) as batches_count,
             COUNT(CASE WHEN status = 'available' THEN 1 END) as available_batches,
             COUNT(CASE WHEN expiry_date IS NOT NULL AND expiry_date <= date('now', '+30 days') AND expiry_date > date('now') THEN 1 END) as expiring_soon_count,
@@ -410,12 +635,18 @@ pub async fn get_reagent_by_id(
         .bind(&id)
         .fetch_one(pool)
         .await?;
+    if reagent.expiry_policy == EXPIRY_POLICY_NONE {
+        stock.expiring_soon_count = 0;
+        stock.expired_count = 0;
+    }
 
     let batches: Vec<Batch> = sqlx::query_as("SELECT * FROM batches WHERE reagent_id = ? ORDER BY created_at DESC")
         .bind(&id)
         .fetch_all(pool)
         .await?;
 
+    let links = crate::link_handlers::fetch_links(pool, LinkEntityType::Reagent, &id).await?;
+
     let total_qty = stock.total_quantity.unwrap_or(0.0);
     let reserved_qty = stock.reserved_quantity.unwrap_or(0.0);
 
@@ -436,6 +667,8 @@ pub async fn get_reagent_by_id(
         updated_by: reagent.updated_by,
         created_at: reagent.created_at,
         updated_at: reagent.updated_at,
+        team_id: reagent.team_id,
+        team_name: reagent.team_name,
         total_quantity: total_qty,
         total_unit: stock.primary_unit.clone().unwrap_or_default(),
         batches_count: stock.batches_count,
@@ -446,6 +679,9 @@ pub async fn get_reagent_by_id(
         expiring_soon_count: stock.expiring_soon_count,
         expired_count: stock.expired_count,
         batches,
+        links,
+        image_url: reagent.image_id.as_deref().map(|image_id| image_url(&id, image_id, ImageSize::Full)),
+        thumbnail_url: reagent.image_id.as_deref().map(|image_id| image_url(&id, image_id, ImageSize::Thumb)),
     };
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
@@ -458,24 +694,25 @@ pub async fn create_reagent(
     body: web::Json<CreateReagentRequest>,
     user_id: String,
 ) -> ApiResult<HttpResponse> {
-    body.validate().map_err(|e| ApiError::bad_request(&e.to_string()))?;
-
-    if let Some(ref cas) = body.cas_number {
-        if !cas.trim().is_empty() {
-            FieldValidator::cas_number(cas.trim()).map_err(|e| ApiError::bad_request(&e))?;
-        }
-    }
+    validate_reagent_request(&app_state.db_pool, &body, None).await?.ensure_valid()?;
+    let initial_location = match body.initial_batch {
+        Some(ref batch) => crate::batch_handlers::resolve_batch_location(&app_state.db_pool, batch).await?,
+        None => None,
+    };
 
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
+    // Реагент и первая партия - одна транзакция: ошибка партии не оставляет реагент без партий
+    let mut tx = app_state.db_pool.begin().await?;
 
     sqlx::query(r#"
         INSERT INTO reagents (
             id, name, formula, cas_number, manufacturer, molecular_weight,
             physical_state, description, storage_conditions, appearance,
-            hazard_pictograms, status, total_quantity, batches_count,
-            created_by, created_at, updated_at
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'active', 0, 0, ?, ?, ?)
+            hazard_pictograms, procurement_lead_time_days, coa_required, publicly_visible,
+            approval_threshold, approval_threshold_unit, default_shelf_life_days, expiry_policy,
+            team_id, status, total_quantity, batches_count, created_by, created_at, updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'active', 0, 0, ?, ?, ?)
     "#)
         .bind(&id)
         .bind(&body.name)
@@ -488,23 +725,125 @@ pub async fn create_reagent(
         .bind(&body.storage_conditions)
         .bind(&body.appearance)
         .bind(&body.hazard_pictograms)
+        .bind(body.procurement_lead_time_days)
+        .bind(body.coa_required.unwrap_or(false))
+        .bind(body.publicly_visible.unwrap_or(true))
+        .bind(body.approval_threshold)
+        .bind(body.approval_threshold.and(body.approval_threshold_unit.as_deref().map(str::trim)))
+        .bind(body.default_shelf_life_days)
+        .bind(body.expiry_policy.as_deref().unwrap_or(EXPIRY_POLICY_STRICT))
+        .bind(crate::team_handlers::normalize_team_id(body.team_id.as_deref()))
         .bind(&user_id)
         .bind(&now)
         .bind(&now)
-        .execute(&app_state.db_pool)
+        .execute(&mut *tx)
         .await?;
 
-    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+    let mut initial_batch_id = None;
+    if let Some(ref batch) = body.initial_batch {
+        let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+            .bind(&id)
+            .fetch_one(&mut *tx)
+            .await?;
+        initial_batch_id = Some(
+            crate::batch_handlers::insert_batch(&mut *tx, &reagent, batch, initial_location, &user_id).await?
+        );
+    }
+
+    let mut reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
         .bind(&id)
-        .fetch_one(&app_state.db_pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+    outbox::enqueue(
+        &mut *tx,
+        &BusinessEvent::ReagentCreated { reagent_id: reagent.id.clone(), name: reagent.name.clone() },
+        Some(&user_id),
+    ).await?;
+    if let (Some(batch_id), Some(batch)) = (&initial_batch_id, &body.initial_batch) {
+        outbox::enqueue(
+            &mut *tx,
+            &BusinessEvent::BatchCreated {
+                reagent_id: reagent.id.clone(),
+                batch_id: batch_id.clone(),
+                quantity: batch.quantity,
+                unit: batch.unit.clone(),
+            },
+            Some(&user_id),
+        ).await?;
+    }
+    tx.commit().await?;
+    crate::team_handlers::expand_team_names(&app_state.db_pool, std::slice::from_mut(&mut reagent)).await?;
+
+    let message = if initial_batch_id.is_some() {
+        "Reagent and initial batch created successfully"
+    } else {
+        "Reagent created successfully"
+    };
     Ok(HttpResponse::Created().json(ApiResponse::success_with_message(
-        reagent,
-        "Reagent created successfully".to_string(),
+        CreatedReagentResponse { reagent, initial_batch_id },
+        message.to_string(),
     )))
 }
 
+/// Ответ create_reagent: поля реагента и id первой партии, если она создавалась
+#[derive(Debug, Serialize)]
+pub struct CreatedReagentResponse {
+    #[serde(flatten)]
+    pub reagent: Reagent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_batch_id: Option<String>,
+}
+
+/// Проверка формы реагента для create_reagent и POST /validate/reagent
+pub async fn validate_reagent_request(
+    pool: &sqlx::SqlitePool,
+    reagent: &CreateReagentRequest,
+    existing_id: Option<&str>,
+) -> ApiResult<ValidationResult> {
+    let mut result = ValidationResult::from_validate(reagent);
+    result.merge(reagent.custom_validate());
+    check_reagent_name(pool, &reagent.name, existing_id, &mut result).await?;
+    match crate::team_handlers::ensure_team_exists(pool, crate::team_handlers::normalize_team_id(reagent.team_id.as_deref())).await {
+        Ok(()) => {}
+        Err(ApiError::NotFound(message)) => result.add_error("team_id", message),
+        Err(e) => return Err(e),
+    }
+    if let Some(ref batch) = reagent.initial_batch {
+        // Реагента ещё нет: срок годности проверяется по политике из самого запроса
+        let check_expiry = reagent.expiry_policy.as_deref() != Some(EXPIRY_POLICY_NONE);
+        let batch_result = crate::batch_handlers::validate_batch_fields(pool, batch, check_expiry).await?;
+        result.merge_prefixed("initial_batch", batch_result);
+    }
+    Ok(result)
+}
+
+/// Имя реагента уникально (с учётом регистра, как UNIQUE в схеме);
+/// то же имя в другом регистре - только предупреждение
+pub async fn check_reagent_name(
+    pool: &sqlx::SqlitePool,
+    name: &str,
+    existing_id: Option<&str>,
+    result: &mut ValidationResult,
+) -> ApiResult<()> {
+    let similar: Vec<(String,)> = sqlx::query_as(
+        "SELECT name FROM reagents WHERE LOWER(name) = LOWER(?) AND id IS NOT ? LIMIT 5"
+    )
+        .bind(name)
+        .bind(existing_id)
+        .fetch_all(pool)
+        .await?;
+
+    for (existing,) in similar {
+        if existing == name {
+            result.add_error("name", "Reagent with this name already exists");
+        } else {
+            result.add_warning("name", format!("Similar reagent already exists: {}", existing));
+        }
+    }
+    Ok(())
+}
+
 // ==================== UPDATE ====================
 
 pub async fn update_reagent(
@@ -518,61 +857,83 @@ pub async fn update_reagent(
 
     body.validate().map_err(|e| ApiError::bad_request(&e.to_string()))?;
 
-    let _: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+    let existing: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
         .bind(&id)
         .fetch_optional(pool)
         .await?
         .ok_or_else(|| ApiError::not_found("Reagent"))?;
 
-    if let Some(ref cas) = body.cas_number {
-        if !cas.trim().is_empty() {
-            FieldValidator::cas_number(cas.trim()).map_err(|e| ApiError::bad_request(&e))?;
+    // Вход в 'inactive' и выход из него - только через /deactivate и /reactivate
+    if let Some(ref status) = body.status {
+        if *status != existing.status && (status == INACTIVE_STATUS || existing.status == INACTIVE_STATUS) {
+            return Err(ApiError::bad_request(
+                "Use POST /reagents/{id}/deactivate or /reagents/{id}/reactivate to change whether a reagent is inactive",
+            ));
         }
     }
 
-    let mut sets = Vec::new();
-    let mut vals: Vec<String> = Vec::new();
-
-    macro_rules! upd {
-        ($f:ident, $c:expr) => {
-            if let Some(ref v) = body.$f { sets.push(concat!($c, " = ?")); vals.push(v.clone()); }
-        };
+    let mut checks = body.custom_validate();
+    if let Some(ref name) = body.name {
+        check_reagent_name(pool, name, Some(&id), &mut checks).await?;
+    }
+    checks.ensure_valid()?;
+    if let Some(ref team_id) = body.team_id {
+        crate::team_handlers::ensure_team_exists(pool, crate::team_handlers::normalize_team_id(team_id.as_deref())).await?;
     }
 
-    upd!(name, "name");
-    upd!(formula, "formula");
-    upd!(cas_number, "cas_number");
-    upd!(manufacturer, "manufacturer");
-    upd!(physical_state, "physical_state");
-    upd!(description, "description");
-    upd!(storage_conditions, "storage_conditions");
-    upd!(appearance, "appearance");
-    upd!(hazard_pictograms, "hazard_pictograms");
-    upd!(status, "status");
-
-    if let Some(mw) = body.molecular_weight {
-        sets.push("molecular_weight = ?");
-        vals.push(mw.to_string());
+    let mut builder = UpdateBuilder::new(FieldWhitelist::for_reagent_update());
+    builder
+        .set_opt("name", &body.name)
+        .patch_text("formula", &body.formula)
+        .patch_text("cas_number", &body.cas_number)
+        .patch_text("manufacturer", &body.manufacturer)
+        .patch("molecular_weight", &body.molecular_weight)
+        .patch_text("physical_state", &body.physical_state)
+        .patch_text("description", &body.description)
+        .patch_text("storage_conditions", &body.storage_conditions)
+        .patch_text("appearance", &body.appearance)
+        .patch_text("hazard_pictograms", &body.hazard_pictograms)
+        .set_opt("status", &body.status)
+        .set_opt("expiry_policy", &body.expiry_policy)
+        .patch("procurement_lead_time_days", &body.procurement_lead_time_days)
+        // Относится к новым партиям; уже принятые партии статус не меняют
+        .set_opt("coa_required", &body.coa_required)
+        .set_opt("publicly_visible", &body.publicly_visible)
+        .patch_text("team_id", &body.team_id);
+
+    // Действует на новые расходы; уже созданные заявки на согласование не пересматриваются
+    let threshold = body.approval_threshold.map(|t| t.filter(|t| *t > 0.0));
+    match (threshold, body.approval_threshold_unit.as_deref().map(str::trim)) {
+        (Some(None), _) => {
+            builder.set_null("approval_threshold").set_null("approval_threshold_unit");
+        }
+        (Some(Some(threshold)), Some(unit)) => {
+            builder.set("approval_threshold", threshold).set("approval_threshold_unit", unit);
+        }
+        (None, Some(unit)) => {
+            builder.set("approval_threshold_unit", unit);
+        }
+        _ => {}
     }
 
-    if sets.is_empty() {
+    // Срок по умолчанию применяется только к новым партиям
+    builder.patch("default_shelf_life_days", &body.default_shelf_life_days.map(|d| d.filter(|d| *d > 0)));
+
+    if builder.is_empty() {
         return Err(ApiError::bad_request("No fields to update"));
     }
 
-    sets.push("updated_by = ?");
-    vals.push(user_id);
-    sets.push("updated_at = datetime('now')");
-
-    let sql = format!("UPDATE reagents SET {} WHERE id = ?", sets.join(", "));
-    let mut q = sqlx::query(&sql);
-    for v in vals { q = q.bind(v); }
-    q = q.bind(&id);
-    q.execute(pool).await?;
+    builder
+        .set("updated_by", user_id)
+        .set_sql("updated_at", "datetime('now')")
+        .where_eq("id", id.as_str());
+    builder.execute(pool).await?;
 
-    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+    let mut reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
         .bind(&id)
         .fetch_one(pool)
         .await?;
+    crate::team_handlers::expand_team_names(pool, std::slice::from_mut(&mut reagent)).await?;
 
     Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
         reagent,
@@ -619,6 +980,81 @@ pub async fn delete_reagent(
     )))
 }
 
+// ==================== DEACTIVATE / REACTIVATE ====================
+
+/// POST /reagents/{id}/deactivate - реагент скрывается из списков и выбора,
+/// партии остаются видимыми только для чтения, уже зарезервированное в экспериментах
+/// завершается как обычно
+pub async fn deactivate_reagent(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = crate::auth::get_current_user(&http_request)?.sub;
+    set_reagent_active(&app_state, &path.into_inner(), false, &user_id, &http_request).await
+}
+
+/// POST /reagents/{id}/reactivate - возвращает деактивированный реагент в статус 'active'
+pub async fn reactivate_reagent(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = crate::auth::get_current_user(&http_request)?.sub;
+    set_reagent_active(&app_state, &path.into_inner(), true, &user_id, &http_request).await
+}
+
+async fn set_reagent_active(
+    app_state: &AppState,
+    id: &str,
+    active: bool,
+    user_id: &str,
+    http_request: &HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let pool = &app_state.db_pool;
+
+    let existing: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Reagent"))?;
+
+    let is_inactive = existing.status == INACTIVE_STATUS;
+    if active && !is_inactive {
+        return Err(ApiError::bad_request("Reagent is not inactive"));
+    }
+    if !active && is_inactive {
+        return Err(ApiError::bad_request("Reagent is already inactive"));
+    }
+
+    let new_status = if active { "active" } else { INACTIVE_STATUS };
+    sqlx::query("UPDATE reagents SET status = ?, updated_by = ?, updated_at = datetime('now') WHERE id = ?")
+        .bind(new_status)
+        .bind(user_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    let (action, verb) = if active { ("reactivate_reagent", "reactivated") } else { ("deactivate_reagent", "deactivated") };
+    let mut cs = ChangeSet::new();
+    cs.add("status", &existing.status, new_status);
+    crate::audit::audit_with_changes(
+        pool, user_id, action, "reagent", id,
+        &format!("Reagent '{}' {}", existing.name, verb),
+        &cs, http_request,
+    ).await;
+
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        reagent,
+        format!("Reagent {} successfully", verb),
+    )))
+}
+
 // ==================== CACHE MANAGEMENT ====================
 
 /// Пересчитать кэш для конкретного реагента
@@ -683,6 +1119,12 @@ pub async fn rebuild_cache(
         .execute(&app_state.db_pool)
         .await?;
 
+    // FTS индексы обычно синхронизируются триггерами; здесь - полная перестройка
+    crate::db::rebuild_fts_index(&app_state.db_pool)
+        .await
+        .map_err(|e| ApiError::internal_error(format!("Failed to rebuild reagents FTS: {}", e)))?;
+    crate::equipment_handlers::update_equipment_fts(&app_state.db_pool).await?;
+
     let elapsed = start.elapsed();
 
     Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
@@ -690,7 +1132,7 @@ pub async fn rebuild_cache(
             "rows_updated": result.rows_affected(),
             "duration_ms": elapsed.as_millis()
         }),
-        format!("Cache rebuilt: {} reagents in {:?}", result.rows_affected(), elapsed),
+        format!("Cache and search indexes rebuilt: {} reagents in {:?}", result.rows_affected(), elapsed),
     )))
 }
 
@@ -702,4 +1144,349 @@ pub async fn get_reagent_with_batches(
 ) -> ApiResult<HttpResponse> {
     // Перенаправляем на get_reagent_by_id
     get_reagent_by_id(app_state, path).await
-}
\ No newline at end of file
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::fixtures::NACL_ID;
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    /// 50 реагентов с длинными описаниями - типичная нагрузка выпадающего списка
+    async fn seeded_app_state() -> web::Data<Arc<AppState>> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        let description = "Reagent grade solvent for HPLC and spectroscopy. ".repeat(10);
+        for i in 0..50 {
+            sqlx::query(
+                "INSERT INTO reagents (id, name, formula, cas_number, manufacturer, description, \
+                 storage_conditions, status, total_quantity, created_at, updated_at) \
+                 VALUES (?, ?, 'C2H3N', '75-05-8', 'Sigma-Aldrich', ?, 'Store below 25 C, away from light', \
+                 'active', ?, datetime('now'), datetime('now'))"
+            )
+                .bind(format!("r{:02}", i))
+                .bind(format!("Reagent {:02}", i))
+                .bind(&description)
+                .bind(i as f64)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    async fn list_body(app_state: &web::Data<Arc<AppState>>, params: &str) -> Vec<u8> {
+        let query = web::Query::<HybridPaginationQuery>::from_query(params).unwrap();
+        let resp = get_reagents(app_state.clone(), query, "tester".to_string()).await.unwrap();
+        actix_web::body::to_bytes(resp.into_body()).await.unwrap().to_vec()
+    }
+
+    /// Замер на сиде выше (50 строк): полный ответ ~46 КБ (47435 байт),
+    /// `fields=id,name,status` ~2.7 КБ (2795 байт) - payload меньше примерно в 17 раз
+    #[actix_web::test]
+    async fn test_fields_selection_reduces_payload() {
+        let app_state = seeded_app_state().await;
+        let full = list_body(&app_state, "per_page=50").await;
+        let selected = list_body(&app_state, "per_page=50&fields=id,name,status").await;
+        assert!(selected.len() * 5 < full.len(), "full = {}, selected = {}", full.len(), selected.len());
+
+        let full: serde_json::Value = serde_json::from_slice(&full).unwrap();
+        let selected: serde_json::Value = serde_json::from_slice(&selected).unwrap();
+        assert_eq!(selected["data"]["pagination"], full["data"]["pagination"]);
+        let full_rows = full["data"]["data"].as_array().unwrap();
+        let rows = selected["data"]["data"].as_array().unwrap();
+        assert_eq!(rows.len(), 50);
+        for (row, full_row) in rows.iter().zip(full_rows) {
+            let keys: Vec<&String> = row.as_object().unwrap().keys().collect();
+            assert_eq!(keys.len(), 3);
+            for key in ["id", "name", "status"] {
+                assert_eq!(row[key], full_row[key]);
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_fields_selection_with_cursor_and_dates() {
+        let app_state = seeded_app_state().await;
+        let full = list_body(&app_state, "per_page=10&sort_by=total_quantity&cursor=").await;
+        let first = list_body(&app_state, "per_page=10&sort_by=total_quantity&cursor=&fields=name,created_at").await;
+        let first: serde_json::Value = serde_json::from_slice(&first).unwrap();
+        let full: serde_json::Value = serde_json::from_slice(&full).unwrap();
+
+        // Курсор строится по id/total_quantity, даже если они не запрошены
+        assert_eq!(first["data"]["pagination"], full["data"]["pagination"]);
+        let row = &first["data"]["data"][0];
+        assert_eq!(row.as_object().unwrap().len(), 2);
+        assert_eq!(row["created_at"], full["data"]["data"][0]["created_at"]);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_fields_rejected() {
+        let app_state = seeded_app_state().await;
+        let query = web::Query::<HybridPaginationQuery>::from_query("fields=id,password_hash").unwrap();
+        match get_reagents(app_state, query, "tester".to_string()).await {
+            Err(ApiError::BadRequest(msg)) => {
+                assert!(msg.contains("password_hash"), "{}", msg);
+                assert!(msg.contains("Valid fields:"), "{}", msg);
+            }
+            other => panic!("expected BadRequest, got {:?}", other.map(|r| r.status())),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_status_multi_select() {
+        let app_state = seeded_app_state().await;
+        sqlx::query("UPDATE reagents SET status = 'inactive' WHERE id IN ('r01', 'r02')")
+            .execute(&app_state.db_pool).await.unwrap();
+        sqlx::query("UPDATE reagents SET status = 'discontinued' WHERE id = 'r03'")
+            .execute(&app_state.db_pool).await.unwrap();
+
+        let total = |params: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let body: serde_json::Value = serde_json::from_slice(&list_body(&app_state, params).await).unwrap();
+                body["data"]["pagination"]["total"].as_i64().unwrap()
+            }
+        };
+        assert_eq!(total("status=inactive").await, 2);
+        assert_eq!(total("status=inactive,discontinued").await, 3);
+        assert_eq!(total("status=active,%20inactive").await, 49);
+
+        let query = web::Query::<HybridPaginationQuery>::from_query("status=active,archived").unwrap();
+        match get_reagents(app_state.clone(), query, "tester".to_string()).await {
+            Err(ApiError::BadRequest(msg)) => {
+                assert!(msg.contains("archived"), "{}", msg);
+                assert!(msg.contains("active, inactive, discontinued"), "{}", msg);
+            }
+            other => panic!("expected BadRequest, got {:?}", other.map(|r| r.status())),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_physical_state_filter_and_validation() {
+        let app_state = seeded_app_state().await;
+        sqlx::query("UPDATE reagents SET physical_state = 'liquid' WHERE id IN ('r01', 'r02')")
+            .execute(&app_state.db_pool).await.unwrap();
+        sqlx::query("UPDATE reagents SET physical_state = 'solid' WHERE id = 'r03'")
+            .execute(&app_state.db_pool).await.unwrap();
+
+        let total = |params: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let body: serde_json::Value = serde_json::from_slice(&list_body(&app_state, params).await).unwrap();
+                body["data"]["pagination"]["total"].as_i64().unwrap()
+            }
+        };
+        assert_eq!(total("physical_state=liquid").await, 2);
+        assert_eq!(total("physical_state=liquid,solid").await, 3);
+        assert_eq!(total("physical_state=gas").await, 0);
+
+        let query = web::Query::<HybridPaginationQuery>::from_query("physical_state=powder").unwrap();
+        match get_reagents(app_state.clone(), query, "tester".to_string()).await {
+            Err(ApiError::BadRequest(msg)) => assert!(msg.contains("solid, liquid, gas, solution, other"), "{}", msg),
+            other => panic!("expected BadRequest, got {:?}", other.map(|r| r.status())),
+        }
+
+        // Запись через API - только канонические значения; пустая строка очищает поле
+        let create: CreateReagentRequest = serde_json::from_value(serde_json::json!({ "name": "Salt", "physical_state": "Solid" })).unwrap();
+        assert!(create.validate().is_err());
+        let update: UpdateReagentRequest = serde_json::from_value(serde_json::json!({ "physical_state": "твердый" })).unwrap();
+        assert!(update.validate().is_err());
+        for state in ["solution", ""] {
+            let update: UpdateReagentRequest = serde_json::from_value(serde_json::json!({ "physical_state": state })).unwrap();
+            assert!(update.validate().is_ok(), "{}", state);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_team_and_mine_filters() {
+        let app_state = seeded_app_state().await;
+        let pool = &app_state.db_pool;
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('tester', 'tester', 'tester@example.com', 'x', 'researcher', datetime('now'), datetime('now'))",
+            "INSERT INTO teams (id, name, created_at, updated_at) VALUES ('t1', 'Synthesis', datetime('now'), datetime('now'))",
+            "INSERT INTO team_members (team_id, user_id, role, created_at) VALUES ('t1', 'tester', 'member', datetime('now'))",
+            "UPDATE reagents SET team_id = 't1' WHERE id IN ('r00', 'r01', 'r02')",
+            "UPDATE reagents SET created_by = 'tester' WHERE id IN ('r02', 'r10')",
+        ] {
+            sqlx::query(sql).execute(pool).await.unwrap();
+        }
+
+        let body = |params: &'static str| {
+            let app_state = app_state.clone();
+            async move { serde_json::from_slice::<serde_json::Value>(&list_body(&app_state, params).await).unwrap() }
+        };
+        let team = body("team_id=t1&sort_by=name&sort_order=asc").await;
+        assert_eq!(team["data"]["pagination"]["total"], 3);
+        assert_eq!(team["data"]["data"][0]["team_name"], "Synthesis");
+        // Созданное мной или моими командами, в том числе вместе с избранным (LEFT JOIN)
+        assert_eq!(body("mine=true").await["data"]["pagination"]["total"], 4);
+        assert_eq!(body("mine=true&favorites_first=true").await["data"]["data"].as_array().unwrap().len(), 4);
+    }
+
+    fn editor_request() -> HttpRequest {
+        use actix_web::HttpMessage;
+        let req = actix_web::test::TestRequest::post().to_http_request();
+        req.extensions_mut().insert(crate::auth::Claims {
+            sub: "u-editor".to_string(),
+            username: "editor".to_string(),
+            email: "editor@example.com".to_string(),
+            role: crate::auth::UserRole::Researcher,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    #[actix_web::test]
+    async fn test_deactivate_hides_reagent_until_reactivated() {
+        let app_state = seeded_app_state().await;
+        let pool = &app_state.db_pool;
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u-editor', 'editor', 'editor@example.com', 'x', 'researcher', datetime('now'), datetime('now'))"
+        ).execute(pool).await.unwrap();
+
+        let total = |params: &'static str| {
+            let app_state = app_state.clone();
+            async move {
+                let body: serde_json::Value = serde_json::from_slice(&list_body(&app_state, params).await).unwrap();
+                body["data"]["pagination"]["total"].as_i64().unwrap()
+            }
+        };
+        let search = |include_inactive: bool| {
+            let app_state = app_state.clone();
+            async move {
+                let query = web::Query(SearchQuery { q: "Reagent 07".to_string(), limit: None, include_inactive: Some(include_inactive) });
+                let resp = search_reagents(app_state, query).await.unwrap();
+                let body: serde_json::Value =
+                    serde_json::from_slice(&actix_web::body::to_bytes(resp.into_body()).await.unwrap()).unwrap();
+                body["data"].as_array().unwrap().len()
+            }
+        };
+
+        deactivate_reagent(app_state.clone(), web::Path::from("r07".to_string()), editor_request()).await.unwrap();
+        assert_eq!(total("").await, 49);
+        assert_eq!(total("include_inactive=true").await, 50);
+        assert_eq!(total("status=inactive").await, 1);
+        assert_eq!(search(false).await, 0);
+        assert_eq!(search(true).await, 1);
+
+        let err = deactivate_reagent(app_state.clone(), web::Path::from("r07".to_string()), editor_request()).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+
+        // Обычное обновление не выводит реагент из 'inactive' и не переводит в него
+        for (id, status) in [("r07", "active"), ("r08", "inactive")] {
+            let body: UpdateReagentRequest = serde_json::from_value(serde_json::json!({ "status": status })).unwrap();
+            let err = update_reagent(app_state.clone(), web::Path::from(id.to_string()), web::Json(body), "u-editor".to_string())
+                .await.unwrap_err();
+            assert!(matches!(err, ApiError::BadRequest(ref msg) if msg.contains("/deactivate")), "{:?}", err);
+        }
+
+        let action: (String, Option<String>) = sqlx::query_as(
+            "SELECT action, changes FROM audit_logs WHERE entity_type = 'reagent' AND entity_id = 'r07'"
+        ).fetch_one(pool).await.unwrap();
+        assert_eq!(action.0, "deactivate_reagent");
+        assert!(action.1.unwrap().contains("inactive"));
+
+        reactivate_reagent(app_state.clone(), web::Path::from("r07".to_string()), editor_request()).await.unwrap();
+        assert_eq!(total("").await, 50);
+        assert_eq!(search(false).await, 1);
+        let err = reactivate_reagent(app_state.clone(), web::Path::from("r07".to_string()), editor_request()).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+    }
+
+    #[actix_web::test]
+    async fn test_update_reagent_clears_fields_with_null() {
+        let app_state = seeded_app_state().await;
+        let pool = &app_state.db_pool;
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u-editor', 'editor', 'editor@example.com', 'x', 'researcher', datetime('now'), datetime('now'))"
+        ).execute(pool).await.unwrap();
+        let update = |json: serde_json::Value| {
+            let app_state = app_state.clone();
+            async move {
+                let body: UpdateReagentRequest = serde_json::from_value(json).unwrap();
+                update_reagent(app_state, web::Path::from("r01".to_string()), web::Json(body), "u-editor".to_string())
+                    .await
+                    .unwrap();
+            }
+        };
+
+        update(serde_json::json!({
+            "molecular_weight": 41.05, "approval_threshold": 500, "approval_threshold_unit": "mL",
+        })).await;
+        update(serde_json::json!({
+            "formula": null, "cas_number": null, "molecular_weight": null, "approval_threshold": null,
+        })).await;
+
+        type NullableColumns = (Option<String>, Option<String>, Option<String>, Option<f64>, Option<f64>, Option<String>);
+        let row: NullableColumns = sqlx::query_as(
+            "SELECT formula, cas_number, manufacturer, molecular_weight, approval_threshold, approval_threshold_unit \
+             FROM reagents WHERE id = 'r01'"
+        ).fetch_one(pool).await.unwrap();
+        assert_eq!(row, (None, None, Some("Sigma-Aldrich".to_string()), None, None, None));
+
+        update(serde_json::json!({ "molecular_weight": 41.05 })).await;
+        let weight_type: String = sqlx::query_scalar("SELECT typeof(molecular_weight) FROM reagents WHERE id = 'r01'")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(weight_type, "real");
+    }
+
+    #[actix_web::test]
+    async fn test_reagent_with_initial_batch_is_created_atomically() {
+        let app = TestApp::new().await;
+
+        let (status, body) = app.post(UserRole::Researcher, "/reagents", json!({
+            "name": "Potassium nitrate",
+            "initial_batch": {"batch_number": "KN-1", "quantity": 250, "unit": "g"},
+        })).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        let reagent_id = body["data"]["id"].as_str().unwrap().to_string();
+        let batch_id = body["data"]["initial_batch_id"].as_str().unwrap().to_string();
+        assert_eq!(body["data"]["batches_count"], 1);
+        let (quantity, _) = app.batch_stock(&batch_id).await;
+        assert_eq!(quantity, 250.0);
+
+        // Ошибка в партии откатывает и реагент; поле ошибки - с префиксом initial_batch.
+        let (status, body) = app.post(UserRole::Researcher, "/reagents", json!({
+            "name": "Sodium nitrate",
+            "initial_batch": {"batch_number": "NN-1", "quantity": -5, "unit": "g"},
+        })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        assert!(body["message"].as_str().unwrap().contains("initial_batch.quantity"), "{}", body);
+        let (_, body) = app.get(UserRole::Viewer, "/reagents/search?q=Sodium%20nitrate").await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 0, "{}", body);
+
+        let (status, body) = app.post(UserRole::Researcher, "/reagents", json!({"name": "Stray reagent"})).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        let stray_id = body["data"]["id"].as_str().unwrap().to_string();
+        assert!(body["data"].get("initial_batch_id").is_none());
+
+        let (status, body) = app.get(UserRole::Viewer, "/reagents/without-batches?per_page=100").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let ids: Vec<&str> = body["data"]["data"].as_array().unwrap().iter()
+            .map(|r| r["id"].as_str().unwrap())
+            .collect();
+        assert!(ids.contains(&stray_id.as_str()), "{}", body);
+        assert!(!ids.contains(&reagent_id.as_str()) && !ids.contains(&NACL_ID), "{}", body);
+    }
+}
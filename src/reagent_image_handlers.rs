@@ -0,0 +1,449 @@
+// src/reagent_image_handlers.rs
+//! Изображения реагентов (фото флакона или структура).
+//!
+//! У реагента одно актуальное изображение (`reagents.image_id`) и миниатюра до 200px
+//! для списков. При загрузке нового изображения предыдущие удаляются вместе с файлами.
+
+use actix_multipart::Multipart;
+use actix_web::http::header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures_util::StreamExt;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::access_control::API_PREFIX;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::ReagentImage;
+use crate::query_builders::{content_disposition, sanitize_original_filename, validate_file_size, validate_mime_type};
+use crate::AppState;
+
+/// Максимальный размер изображения (5 МБ)
+const MAX_REAGENT_IMAGE_SIZE: usize = 5 * 1024 * 1024;
+/// Сторона миниатюры, px
+const THUMBNAIL_SIZE: u32 = 200;
+/// Защита от "бомб" - максимальная сторона исходного изображения
+const MAX_IMAGE_DIMENSION: u32 = 12_000;
+const ALLOWED_REAGENT_IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/webp"];
+const IMAGE_CACHE_CONTROL: &str = "private, max-age=86400";
+
+/// Директория изображений реагентов (кроссплатформенно)
+fn get_reagent_images_dir() -> PathBuf {
+    std::env::var("REAGENT_IMAGES_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(".").join("uploads").join("reagents"))
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSize {
+    Thumb,
+    #[default]
+    Full,
+}
+
+impl ImageSize {
+    fn as_str(self) -> &'static str {
+        match self {
+            ImageSize::Thumb => "thumb",
+            ImageSize::Full => "full",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReagentImageQuery {
+    #[serde(default)]
+    pub size: ImageSize,
+}
+
+/// URL изображения; `v` меняется вместе с изображением, поэтому кэш браузера не устаревает
+pub fn image_url(reagent_id: &str, image_id: &str, size: ImageSize) -> String {
+    format!("{}/reagents/{}/image?size={}&v={}", API_PREFIX, reagent_id, size.as_str(), image_id)
+}
+
+/// Декодированное изображение и готовая миниатюра
+struct ProcessedImage {
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+    thumbnail: Vec<u8>,
+    thumbnail_format: ImageFormat,
+}
+
+/// Проверяет формат по содержимому (а не по заявленному MIME) и строит миниатюру:
+/// JPEG остаётся JPEG, PNG/WebP сохраняются в PNG (с прозрачностью для структур)
+fn process_image(bytes: &[u8]) -> ApiResult<ProcessedImage> {
+    let mut reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| ApiError::bad_request(&format!("Cannot read image: {}", e)))?;
+
+    let format = reader.format()
+        .filter(|f| matches!(f, ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP))
+        .ok_or_else(|| ApiError::bad_request("Image must be JPEG, PNG or WebP"))?;
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    reader.limits(limits);
+
+    let image = reader.decode()
+        .map_err(|e| ApiError::bad_request(&format!("Invalid image: {}", e)))?;
+
+    let thumb = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+    } else {
+        image.clone()
+    };
+
+    let (thumb, thumbnail_format) = if format == ImageFormat::Jpeg {
+        (DynamicImage::ImageRgb8(thumb.to_rgb8()), ImageFormat::Jpeg)
+    } else {
+        (thumb, ImageFormat::Png)
+    };
+
+    let mut thumbnail = Vec::new();
+    thumb.write_to(&mut Cursor::new(&mut thumbnail), thumbnail_format)
+        .map_err(|e| ApiError::InternalServerError(format!("Failed to encode thumbnail: {}", e)))?;
+
+    Ok(ProcessedImage {
+        format,
+        width: image.width(),
+        height: image.height(),
+        thumbnail,
+        thumbnail_format,
+    })
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> ApiResult<()> {
+    std::fs::write(path, contents)
+        .map_err(|e| ApiError::InternalServerError(format!("Failed to write file: {}", e)))
+}
+
+fn remove_image_files(image: &ReagentImage) {
+    let _ = std::fs::remove_file(&image.file_path);
+    let _ = std::fs::remove_file(&image.thumbnail_path);
+}
+
+/// Сохраняет изображение в `dir/{reagent_id}/`, делает его текущим и удаляет предыдущие
+pub(crate) async fn store_reagent_image(
+    pool: &SqlitePool,
+    dir: &Path,
+    reagent_id: &str,
+    original_filename: &str,
+    bytes: Vec<u8>,
+    user_id: &str,
+) -> ApiResult<ReagentImage> {
+    let exists: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM reagents WHERE id = ? AND deleted_at IS NULL"
+    )
+        .bind(reagent_id)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Err(ApiError::not_found("Reagent"));
+    }
+
+    let (bytes, processed) = web::block(move || process_image(&bytes).map(|p| (bytes, p)))
+        .await
+        .map_err(|e| ApiError::InternalServerError(format!("Image processing failed: {}", e)))??;
+
+    let reagent_dir = dir.join(reagent_id);
+    std::fs::create_dir_all(&reagent_dir)
+        .map_err(|e| ApiError::InternalServerError(format!("Failed to create directory: {}", e)))?;
+
+    let id = Uuid::new_v4().to_string();
+    let extension = |format: ImageFormat| format.extensions_str().first().copied().unwrap_or("bin");
+    let file_path = reagent_dir.join(format!("{}.{}", id, extension(processed.format)));
+    let thumbnail_path = reagent_dir.join(format!("{}_thumb.{}", id, extension(processed.thumbnail_format)));
+
+    let image = ReagentImage {
+        id: id.clone(),
+        reagent_id: reagent_id.to_string(),
+        original_filename: original_filename.to_string(),
+        file_path: file_path.to_string_lossy().to_string(),
+        thumbnail_path: thumbnail_path.to_string_lossy().to_string(),
+        mime_type: processed.format.to_mime_type().to_string(),
+        thumbnail_mime_type: processed.thumbnail_format.to_mime_type().to_string(),
+        file_size: bytes.len() as i64,
+        width: i64::from(processed.width),
+        height: i64::from(processed.height),
+        uploaded_by: Some(user_id.to_string()),
+        created_at: Utc::now(),
+    };
+
+    write_file(&file_path, &bytes)?;
+    if let Err(e) = write_file(&thumbnail_path, &processed.thumbnail) {
+        remove_image_files(&image);
+        return Err(e);
+    }
+
+    let replaced = match save_as_current(pool, &image, user_id).await {
+        Ok(replaced) => replaced,
+        Err(e) => {
+            remove_image_files(&image);
+            return Err(e);
+        }
+    };
+
+    for old in &replaced {
+        remove_image_files(old);
+    }
+
+    Ok(image)
+}
+
+/// Запись изображения и переключение reagents.image_id; возвращает замещённые изображения
+async fn save_as_current(pool: &SqlitePool, image: &ReagentImage, user_id: &str) -> ApiResult<Vec<ReagentImage>> {
+    let mut tx = pool.begin().await?;
+
+    let replaced: Vec<ReagentImage> = sqlx::query_as(
+        "SELECT * FROM reagent_images WHERE reagent_id = ?"
+    )
+        .bind(&image.reagent_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        r#"INSERT INTO reagent_images
+           (id, reagent_id, original_filename, file_path, thumbnail_path, mime_type,
+            thumbnail_mime_type, file_size, width, height, uploaded_by, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&image.id)
+        .bind(&image.reagent_id)
+        .bind(&image.original_filename)
+        .bind(&image.file_path)
+        .bind(&image.thumbnail_path)
+        .bind(&image.mime_type)
+        .bind(&image.thumbnail_mime_type)
+        .bind(image.file_size)
+        .bind(image.width)
+        .bind(image.height)
+        .bind(&image.uploaded_by)
+        .bind(image.created_at)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE reagents SET image_id = ?, updated_by = ?, updated_at = ? WHERE id = ?")
+        .bind(&image.id)
+        .bind(user_id)
+        .bind(image.created_at)
+        .bind(&image.reagent_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM reagent_images WHERE reagent_id = ? AND id != ?")
+        .bind(&image.reagent_id)
+        .bind(&image.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(replaced)
+}
+
+// ==================== HANDLERS ====================
+
+/// POST /reagents/{id}/image - multipart с полем `file` (JPEG/PNG/WebP, до 5 МБ)
+pub async fn upload_reagent_image(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    mut payload: Multipart,
+    user_id: String,
+) -> ApiResult<HttpResponse> {
+    let reagent_id = path.into_inner();
+
+    let mut upload: Option<(String, Vec<u8>)> = None;
+
+    while let Some(item) = payload.next().await {
+        let mut field = item.map_err(|e| ApiError::bad_request(&format!("Multipart error: {}", e)))?;
+
+        if field.content_disposition().get_name() != Some("file") {
+            continue;
+        }
+
+        let filename = field.content_disposition()
+            .get_filename()
+            .map(sanitize_original_filename)
+            .ok_or_else(|| ApiError::bad_request("Filename not provided"))?;
+
+        let mime = field.content_type()
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        validate_mime_type(&mime, ALLOWED_REAGENT_IMAGE_TYPES)?;
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| ApiError::bad_request(&format!("Read error: {}", e)))?;
+            bytes.extend_from_slice(&chunk);
+            validate_file_size(bytes.len(), MAX_REAGENT_IMAGE_SIZE)?;
+        }
+
+        upload = Some((filename, bytes));
+    }
+
+    let (filename, bytes) = upload.ok_or_else(|| ApiError::bad_request("No file provided"))?;
+    if bytes.is_empty() {
+        return Err(ApiError::bad_request("Empty file"));
+    }
+
+    let image = store_reagent_image(
+        &app_state.db_pool, &get_reagent_images_dir(), &reagent_id, &filename, bytes, &user_id,
+    ).await?;
+    crate::kpi::record(crate::kpi::Kpi::FilesUploaded, "reagent_image");
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(image)))
+}
+
+/// GET /reagents/{id}/image?size=thumb|full - текущее изображение с ETag/Cache-Control
+pub async fn get_reagent_image(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<ReagentImageQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let reagent_id = path.into_inner();
+
+    let image: ReagentImage = sqlx::query_as(
+        r#"SELECT i.* FROM reagent_images i
+           JOIN reagents r ON r.image_id = i.id
+           WHERE r.id = ? AND r.deleted_at IS NULL"#
+    )
+        .bind(&reagent_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Reagent image"))?;
+
+    let etag = format!("\"{}-{}\"", image.id, query.size.as_str());
+    let not_modified = http_request.headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((ETAG, etag))
+            .insert_header((CACHE_CONTROL, IMAGE_CACHE_CONTROL))
+            .finish());
+    }
+
+    let (file_path, mime_type) = match query.size {
+        ImageSize::Thumb => (&image.thumbnail_path, &image.thumbnail_mime_type),
+        ImageSize::Full => (&image.file_path, &image.mime_type),
+    };
+    let contents = std::fs::read(file_path)
+        .map_err(|e| ApiError::InternalServerError(format!("Failed to read file: {}", e)))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(mime_type.as_str())
+        .insert_header(("Content-Disposition", content_disposition("inline", &image.original_filename)))
+        .insert_header((ETAG, etag))
+        .insert_header((CACHE_CONTROL, IMAGE_CACHE_CONTROL))
+        .body(contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test as actix_test;
+    use image::{Rgba, RgbaImage};
+
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([20, 120, 200, 255])));
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        bytes
+    }
+
+    async fn test_app_state() -> web::Data<Arc<AppState>> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('tester', 'tester', 'tester@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Acetone', 'active', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    #[actix_web::test]
+    async fn test_reagent_image_upload_thumbnail_replace_and_caching() {
+        let app_state = test_app_state().await;
+        let pool = app_state.db_pool.clone();
+        let dir = tempfile::tempdir().unwrap();
+
+        // Содержимое проверяется независимо от имени файла и MIME
+        let err = store_reagent_image(&pool, dir.path(), "r1", "fake.png", b"not an image".to_vec(), "tester")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        let first = store_reagent_image(&pool, dir.path(), "r1", "bottle.png", png_bytes(800, 400), "tester")
+            .await
+            .unwrap();
+        assert_eq!((first.width, first.height), (800, 400));
+        let thumb = image::open(&first.thumbnail_path).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (200, 100));
+
+        let second = store_reagent_image(&pool, dir.path(), "r1", "structure.png", png_bytes(120, 80), "tester")
+            .await
+            .unwrap();
+        let current: Option<String> = sqlx::query_scalar("SELECT image_id FROM reagents WHERE id = 'r1'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(current.as_deref(), Some(second.id.as_str()));
+        // Замещённое изображение удалено из БД и с диска
+        let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM reagent_images").fetch_one(&pool).await.unwrap();
+        assert_eq!(rows, 1);
+        assert!(!Path::new(&first.file_path).exists());
+        assert!(!Path::new(&first.thumbnail_path).exists());
+
+        let fetch = |size: &'static str, if_none_match: Option<String>| {
+            let app_state = app_state.clone();
+            async move {
+                let mut request = actix_test::TestRequest::default();
+                if let Some(tag) = if_none_match {
+                    request = request.insert_header((IF_NONE_MATCH, tag));
+                }
+                let query = web::Query::<ReagentImageQuery>::from_query(size).unwrap();
+                get_reagent_image(app_state, web::Path::from("r1".to_string()), query, request.to_http_request())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let response = fetch("size=thumb", None).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), IMAGE_CACHE_CONTROL);
+        let etag = response.headers().get(ETAG).unwrap().to_str().unwrap().to_string();
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let thumb = image::load_from_memory(&body).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (120, 80));
+
+        let cached = fetch("size=thumb", Some(etag.clone())).await;
+        assert_eq!(cached.status(), actix_web::http::StatusCode::NOT_MODIFIED);
+        // У оригинала свой ETag
+        let full = fetch("size=full", Some(etag)).await;
+        assert_eq!(full.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(full.headers().get("content-type").unwrap(), "image/png");
+    }
+}
@@ -0,0 +1,238 @@
+// src/reconciliation.rs
+//! Сверка batches.reserved_quantity с открытыми резервами.
+//!
+//! Ожидаемый резерв партии - сумма planned_quantity не списанных experiment_reagents
+//! экспериментов в статусе planned/in_progress. Резервов вне экспериментов в системе нет,
+//! поэтому других источников у reserved_quantity нет. После сбоя посреди транзакции
+//! резерв может "застрять" выше ожидаемого - тогда доступное количество партии занижено.
+//!
+//! Запускается раз в неделю из maintenance-задач (только отчёт) и вручную:
+//!   POST /admin/reconcile-reservations           — отчёт о расхождениях
+//!   POST /admin/reconcile-reservations?fix=true  — исправить в одной транзакции с записями аудита
+//! Число расхождений последнего прогона - метрика `lims_reservation_discrepancies`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::audit::{log_activity, ChangeSet};
+use crate::auth::get_current_user;
+use crate::error::ApiResult;
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Расхождения меньше этого - погрешность суммирования REAL
+const QUANTITY_TOLERANCE: f64 = 1e-6;
+
+const DISCREPANCIES_SQL: &str = r#"
+    WITH expected AS (
+        SELECT er.batch_id, SUM(er.planned_quantity) AS quantity
+        FROM experiment_reagents er
+        JOIN experiments e ON e.id = er.experiment_id
+        WHERE er.is_consumed = 0
+          AND er.batch_id IS NOT NULL
+          AND e.status IN ('planned', 'in_progress')
+        GROUP BY er.batch_id
+    )
+    SELECT b.id AS batch_id, b.reagent_id, b.batch_number, b.unit,
+           b.reserved_quantity, COALESCE(x.quantity, 0.0) AS expected_quantity
+    FROM batches b
+    LEFT JOIN expected x ON x.batch_id = b.id
+    WHERE ABS(b.reserved_quantity - COALESCE(x.quantity, 0.0)) > ?
+    ORDER BY b.reagent_id, b.batch_number
+"#;
+
+/// Расхождения последнего прогона (для /health/metrics)
+static LAST_DISCREPANCIES: AtomicU64 = AtomicU64::new(0);
+
+pub fn last_discrepancy_count() -> u64 {
+    LAST_DISCREPANCIES.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ReservationDiscrepancy {
+    pub batch_id: String,
+    pub reagent_id: String,
+    pub batch_number: String,
+    pub unit: String,
+    pub reserved_quantity: f64,
+    pub expected_quantity: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReconciliationReport {
+    pub fixed: bool,
+    pub checked_at: DateTime<Utc>,
+    pub discrepancies: Vec<ReservationDiscrepancy>,
+}
+
+async fn find_discrepancies(conn: &mut SqliteConnection) -> ApiResult<Vec<ReservationDiscrepancy>> {
+    Ok(sqlx::query_as(DISCREPANCIES_SQL)
+        .bind(QUANTITY_TOLERANCE)
+        .fetch_all(conn)
+        .await?)
+}
+
+/// Только отчёт о расхождениях (еженедельная задача и запрос без `fix`)
+pub async fn check_reservations(pool: &SqlitePool) -> ApiResult<ReconciliationReport> {
+    let mut conn = pool.acquire().await?;
+    let discrepancies = find_discrepancies(&mut conn).await?;
+    LAST_DISCREPANCIES.store(discrepancies.len() as u64, Ordering::Relaxed);
+
+    Ok(ReconciliationReport { fixed: false, checked_at: Utc::now(), discrepancies })
+}
+
+/// Записать ожидаемый резерв в той же транзакции, что и поиск расхождений
+pub async fn fix_reservations(
+    pool: &SqlitePool,
+    user_id: &str,
+    request: Option<&HttpRequest>,
+) -> ApiResult<ReconciliationReport> {
+    let mut tx = pool.begin().await?;
+    let discrepancies = find_discrepancies(&mut tx).await?;
+    let now = Utc::now();
+
+    for d in &discrepancies {
+        sqlx::query("UPDATE batches SET reserved_quantity = ?, updated_by = ?, updated_at = ? WHERE id = ?")
+            .bind(d.expected_quantity)
+            .bind(user_id)
+            .bind(now)
+            .bind(&d.batch_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let mut changes = ChangeSet::new();
+        changes.add_f64("reserved_quantity", d.reserved_quantity, d.expected_quantity);
+        let description = format!(
+            "Reservation reconciled for batch {}: {} -> {} {}",
+            d.batch_number, d.reserved_quantity, d.expected_quantity, d.unit
+        );
+        log_activity(
+            &mut *tx,
+            Some(user_id),
+            "reconcile_reservation",
+            "batch",
+            Some(&d.batch_id),
+            Some(&description),
+            changes.to_json().as_deref(),
+            request,
+        ).await?;
+    }
+
+    tx.commit().await?;
+    // После исправления расхождений не остаётся
+    LAST_DISCREPANCIES.store(0, Ordering::Relaxed);
+
+    Ok(ReconciliationReport { fixed: true, checked_at: now, discrepancies })
+}
+
+// ==================== HANDLER ====================
+
+#[derive(Debug, Deserialize)]
+pub struct ReconcileQuery {
+    #[serde(default)]
+    pub fix: bool,
+}
+
+pub async fn reconcile_reservations_handler(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ReconcileQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let report = if query.fix {
+        fix_reservations(&app_state.db_pool, &claims.sub, Some(&http_request)).await?
+    } else {
+        check_reservations(&app_state.db_pool).await?
+    };
+
+    if !report.discrepancies.is_empty() {
+        log::warn!(
+            "Reservation reconciliation by {}: {} discrepancies{}",
+            claims.username,
+            report.discrepancies.len(),
+            if report.fixed { " fixed" } else { "" }
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+             VALUES ('u-1', 'admin', 'admin@example.com', 'x', 'admin', datetime('now'), datetime('now'))",
+            "INSERT INTO reagents (id, name, status, created_at, updated_at)
+             VALUES ('r-1', 'Acetone', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity, unit, status, received_date, created_at, updated_at)
+             VALUES ('b-stuck', 'r-1', 'B-1', 100, 100, 7.5, 'ml', 'available', datetime('now'), datetime('now'), datetime('now')),
+                    ('b-ok', 'r-1', 'B-2', 100, 100, 4, 'ml', 'available', datetime('now'), datetime('now'), datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, created_at, updated_at)
+             VALUES ('e-open', 'Open', datetime('now'), datetime('now'), 'planned', datetime('now'), datetime('now')),
+                    ('e-run', 'Running', datetime('now'), datetime('now'), 'in_progress', datetime('now'), datetime('now')),
+                    ('e-done', 'Done', datetime('now'), datetime('now'), 'cancelled', datetime('now'), datetime('now'))",
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, is_consumed, created_at, updated_at)
+             VALUES ('er-1', 'e-open', 'r-1', 'b-stuck', 2.5, 'ml', 0, datetime('now'), datetime('now')),
+                    ('er-2', 'e-run', 'r-1', 'b-stuck', 3, 'ml', 1, datetime('now'), datetime('now')),
+                    ('er-3', 'e-done', 'r-1', 'b-stuck', 2, 'ml', 0, datetime('now'), datetime('now')),
+                    ('er-4', 'e-open', 'r-1', 'b-ok', 4, 'ml', 0, datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    #[actix_web::test]
+    async fn test_reconcile_reports_then_fixes_stuck_reservations() {
+        let pool = setup().await;
+
+        let report = check_reservations(&pool).await.unwrap();
+        assert!(!report.fixed);
+        assert_eq!(report.discrepancies.len(), 1);
+        let d = &report.discrepancies[0];
+        assert_eq!(d.batch_id, "b-stuck");
+        assert_eq!(d.reserved_quantity, 7.5);
+        // Списанные резервы и отменённые эксперименты не учитываются
+        assert_eq!(d.expected_quantity, 2.5);
+        assert_eq!(last_discrepancy_count(), 1);
+
+        let reserved = |id: &'static str| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, f64>("SELECT reserved_quantity FROM batches WHERE id = ?")
+                    .bind(id).fetch_one(&pool).await.unwrap()
+            }
+        };
+        assert_eq!(reserved("b-stuck").await, 7.5);
+
+        let report = fix_reservations(&pool, "u-1", None).await.unwrap();
+        assert!(report.fixed);
+        assert_eq!(report.discrepancies.len(), 1);
+        assert_eq!(reserved("b-stuck").await, 2.5);
+        assert_eq!(reserved("b-ok").await, 4.0);
+        assert_eq!(last_discrepancy_count(), 0);
+
+        let audited: (String, String) = sqlx::query_as(
+            "SELECT entity_id, changes FROM audit_logs WHERE action = 'reconcile_reservation'"
+        )
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(audited.0, "b-stuck");
+        assert!(audited.1.contains("reserved_quantity"));
+
+        assert!(check_reservations(&pool).await.unwrap().discrepancies.is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+// src/reference_cache.rs
+//! Кэш справочников, которые клиент запрашивает при каждой загрузке страницы:
+//! список помещений (GET /rooms) и пресеты отчётов (GET /reports/presets, по пользователю).
+//!
+//! - запись живёт `reference_cache.ttl_seconds` и сбрасывается явно хендлерами изменений:
+//!   CRUD помещений и пресетов, импорт конфигурации, изменение настроек;
+//! - сброс во время загрузки не теряется: результат загрузки, начатой до сброса, не сохраняется;
+//! - ответы справочников (и статических ролей и полей отчётов) несут
+//!   `Cache-Control: private, max-age=<ttl>`, чтобы браузер не повторял запрос;
+//! - попадания и промахи по наборам - в /metrics (`reference_cache`),
+//!   POST /admin/reference-cache/flush сбрасывает всё.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::error::ApiResult;
+use crate::handlers::ApiResponse;
+use crate::models::Room;
+use crate::report_handlers::AvailablePreset;
+use crate::AppState;
+
+pub const DEFAULT_TTL_SECONDS: u64 = 60;
+/// Справочники меняются редко, но дольше часа клиент не должен видеть удалённое помещение
+pub const MAX_TTL_SECONDS: u64 = 3600;
+
+/// Набор справочных данных с собственной инвалидацией
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceDataset {
+    Rooms,
+    ReportPresets,
+}
+
+impl ReferenceDataset {
+    pub const ALL: [ReferenceDataset; 2] = [Self::Rooms, Self::ReportPresets];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Rooms => "rooms",
+            Self::ReportPresets => "report_presets",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ReferenceCacheStats {
+    pub dataset: &'static str,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Entry<V> {
+    stored_at: Instant,
+    value: Arc<V>,
+}
+
+/// Записи одного набора; ключ - "" для общих данных или id пользователя
+struct TtlMap<V> {
+    entries: RwLock<HashMap<String, Entry<V>>>,
+    /// Растёт при каждом сбросе: загрузка, начатая до сброса, не сохраняется
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<V> Default for TtlMap<V> {
+    fn default() -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<V> TtlMap<V> {
+    async fn get_or_load<F, Fut>(&self, key: &str, ttl: Duration, load: F) -> ApiResult<Arc<V>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = ApiResult<V>>,
+    {
+        if let Some(entry) = self.entries.read().unwrap().get(key) {
+            if entry.stored_at.elapsed() < ttl {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(entry.value.clone());
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let value = Arc::new(load().await?);
+        if !ttl.is_zero() {
+            let mut entries = self.entries.write().unwrap();
+            if self.generation.load(Ordering::Acquire) == generation {
+                entries.insert(key.to_string(), Entry { stored_at: Instant::now(), value: value.clone() });
+            }
+        }
+        Ok(value)
+    }
+
+    fn clear(&self) {
+        let mut entries = self.entries.write().unwrap();
+        self.generation.fetch_add(1, Ordering::Release);
+        entries.clear();
+    }
+
+    fn stats(&self, dataset: ReferenceDataset) -> ReferenceCacheStats {
+        ReferenceCacheStats {
+            dataset: dataset.as_str(),
+            entries: self.entries.read().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct ReferenceCache {
+    /// 0 - кэш выключен, каждый запрос идёт в БД
+    ttl: Duration,
+    rooms: TtlMap<Vec<Room>>,
+    report_presets: TtlMap<Vec<AvailablePreset>>,
+}
+
+impl Default for ReferenceCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL_SECONDS)
+    }
+}
+
+impl ReferenceCache {
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self {
+            ttl: Duration::from_secs(ttl_seconds),
+            rooms: TtlMap::default(),
+            report_presets: TtlMap::default(),
+        }
+    }
+
+    /// Все помещения (GET /rooms)
+    pub async fn rooms<F, Fut>(&self, load: F) -> ApiResult<Arc<Vec<Room>>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = ApiResult<Vec<Room>>>,
+    {
+        self.rooms.get_or_load("", self.ttl, load).await
+    }
+
+    /// Пресеты, доступные пользователю: свои, общие и встроенные
+    pub async fn report_presets<F, Fut>(&self, user_id: &str, load: F) -> ApiResult<Arc<Vec<AvailablePreset>>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = ApiResult<Vec<AvailablePreset>>>,
+    {
+        self.report_presets.get_or_load(user_id, self.ttl, load).await
+    }
+
+    /// Сброс набора после изменения его данных. Пресеты сбрасываются целиком:
+    /// общий пресет виден всем пользователям
+    pub fn invalidate(&self, dataset: ReferenceDataset) {
+        match dataset {
+            ReferenceDataset::Rooms => self.rooms.clear(),
+            ReferenceDataset::ReportPresets => self.report_presets.clear(),
+        }
+    }
+
+    pub fn flush(&self) {
+        for dataset in ReferenceDataset::ALL {
+            self.invalidate(dataset);
+        }
+    }
+
+    pub fn stats(&self) -> Vec<ReferenceCacheStats> {
+        vec![
+            self.rooms.stats(ReferenceDataset::Rooms),
+            self.report_presets.stats(ReferenceDataset::ReportPresets),
+        ]
+    }
+
+    /// Заголовок Cache-Control справочных ответов: браузер хранит их не дольше кэша сервера
+    pub fn cache_control(&self) -> String {
+        match self.ttl.as_secs() {
+            0 => "no-cache".to_string(),
+            ttl => format!("private, max-age={}", ttl),
+        }
+    }
+}
+
+/// Ответ справочника с Cache-Control по TTL кэша
+pub fn reference_response<T: Serialize>(app_state: &AppState, data: T) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", app_state.reference_cache.cache_control()))
+        .json(ApiResponse::success(data))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReferenceCacheFlushResponse {
+    /// Счётчики до сброса
+    pub datasets: Vec<ReferenceCacheStats>,
+}
+
+/// POST /admin/reference-cache/flush
+pub async fn flush_reference_cache(
+    app_state: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = crate::auth::get_current_user(&http_request)?;
+    let datasets = app_state.reference_cache.stats();
+    app_state.reference_cache.flush();
+
+    crate::audit::audit(
+        &app_state.db_pool, &user.sub, "flush_reference_cache", "system", "reference_cache",
+        "Reference data cache flushed", &http_request,
+    ).await;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        ReferenceCacheFlushResponse { datasets },
+        "Reference data cache flushed".to_string(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    fn stats(app: &TestApp, dataset: ReferenceDataset) -> ReferenceCacheStats {
+        app.state().reference_cache.stats().into_iter().find(|s| s.dataset == dataset.as_str()).unwrap()
+    }
+
+    async fn room_names(app: &TestApp) -> Vec<String> {
+        let (status, body) = app.get(UserRole::Viewer, "/rooms").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        body["data"].as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap().to_string()).collect()
+    }
+
+    #[actix_web::test]
+    async fn test_rooms_are_cached_until_a_room_changes() {
+        let app = TestApp::new().await;
+        assert_eq!(room_names(&app).await, ["Lab 1"]);
+
+        // Запись в обход хендлеров видна только после сброса
+        sqlx::query(
+            "INSERT INTO rooms (id, name, status, created_at, updated_at) \
+             VALUES ('r-direct', 'Annex', 'available', datetime('now'), datetime('now'))"
+        ).execute(&app.pool).await.unwrap();
+        assert_eq!(room_names(&app).await, ["Lab 1"]);
+        let rooms = stats(&app, ReferenceDataset::Rooms);
+        assert_eq!((rooms.hits, rooms.misses, rooms.entries), (1, 1, 1));
+
+        let (status, body) = app.post(UserRole::Admin, "/rooms", json!({ "name": "Cold room" })).await;
+        assert!(status.is_success(), "{}", body);
+        assert_eq!(room_names(&app).await, ["Annex", "Cold room", "Lab 1"]);
+
+        let response = crate::room_handlers::get_all_rooms(app.state()).await.unwrap();
+        assert_eq!(response.headers().get("Cache-Control").unwrap(), "private, max-age=60");
+    }
+
+    #[actix_web::test]
+    async fn test_report_presets_are_cached_per_user_and_flushed() {
+        let app = TestApp::new().await;
+        let preset_names = |body: serde_json::Value| -> Vec<String> {
+            body["data"].as_array().unwrap().iter()
+                .filter(|p| p["builtin"] == false)
+                .map(|p| p["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let (_, body) = app.get(UserRole::Viewer, "/reports/presets").await;
+        assert!(preset_names(body).is_empty());
+        let (status, body) = app.post(UserRole::Admin, "/reports/presets", json!({
+            "name": "Expiring stock", "is_shared": true,
+        })).await;
+        assert!(status.is_success(), "{}", body);
+        let (_, body) = app.get(UserRole::Viewer, "/reports/presets").await;
+        assert_eq!(preset_names(body), ["Expiring stock"]);
+        let (_, body) = app.get(UserRole::Researcher, "/reports/presets").await;
+        assert_eq!(preset_names(body), ["Expiring stock"]);
+        assert_eq!(stats(&app, ReferenceDataset::ReportPresets).entries, 2);
+
+        let (status, _) = app.post(UserRole::Researcher, "/admin/reference-cache/flush", json!({})).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, body) = app.post(UserRole::Admin, "/admin/reference-cache/flush", json!({})).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["datasets"][1]["dataset"], "report_presets");
+        assert_eq!(body["data"]["datasets"][1]["entries"], 2);
+        assert_eq!(stats(&app, ReferenceDataset::ReportPresets).entries, 0);
+    }
+
+    #[actix_web::test]
+    async fn test_zero_ttl_disables_the_cache() {
+        let cache = ReferenceCache::new(0);
+        for _ in 0..2 {
+            let rooms = cache.rooms(|| async { Ok(Vec::new()) }).await.unwrap();
+            assert!(rooms.is_empty());
+        }
+        assert_eq!(cache.stats()[0], ReferenceCacheStats { dataset: "rooms", entries: 0, hits: 0, misses: 2 });
+        assert_eq!(cache.cache_control(), "no-cache");
+    }
+}
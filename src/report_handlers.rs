@@ -5,13 +5,17 @@ use actix_web::{web, HttpResponse, HttpRequest};
 use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
+use validator::Validate;
 
 use crate::AppState;
+use crate::auth::{get_current_user, UserRole};
 use crate::error::{ApiError, ApiResult};
 use crate::handlers::ApiResponse;
+use crate::reference_cache::{reference_response, ReferenceDataset};
+use crate::i18n::{ExportLocaleQuery, ExportStyle};
 use crate::query_builders::{
     FieldWhitelist, ReportConfig, ReportFilter, ReportColumn,
-    ComparisonOperator, ReportFilterValue,
+    ComparisonOperator, ReportFilterValue, SqlParam,
 };
 
 // ==================== SECURITY CONSTANTS ====================
@@ -22,7 +26,7 @@ const ALLOWED_SORT_FIELDS: &[&str] = &[
     "quantity", "original_quantity", "reserved_quantity", "unit",
     "expiry_date", "supplier", "manufacturer", "received_date",
     "status", "location", "created_at", "updated_at", "days_until_expiry",
-    "expiration_status",
+    "expiration_status", "expiry_policy", "container_size", "container_count", "open_containers",
 ];
 
 /// Валидация поля сортировки
@@ -33,7 +37,7 @@ fn validate_sort_field(field: &str) -> Option<&'static str> {
 }
 
 /// Экранирование спецсимволов LIKE для предотвращения LIKE-инъекций
-fn escape_like_pattern(pattern: &str) -> String {
+pub(crate) fn escape_like_pattern(pattern: &str) -> String {
     pattern
         .replace('\\', "\\\\")
         .replace('%', "\\%")
@@ -41,7 +45,7 @@ fn escape_like_pattern(pattern: &str) -> String {
 }
 
 /// Экранирование CSV-полей (обработка запятых, кавычек и переносов строк)
-fn escape_csv_field(field: &str) -> String {
+pub(crate) fn escape_csv_field(field: &str) -> String {
     if field.contains(',') || field.contains('"') || field.contains('\n') || field.contains('\r') {
         format!("\"{}\"", field.replace('"', "\"\""))
     } else {
@@ -56,6 +60,8 @@ pub struct BatchReportRow {
     pub id: String,
     pub reagent_id: String,
     pub reagent_name: String,
+    /// Политика сроков годности реагента (strict, advisory, none)
+    pub expiry_policy: String,
     pub batch_number: String,
     pub cat_number: Option<String>,
     pub quantity: f64,
@@ -73,6 +79,14 @@ pub struct BatchReportRow {
     pub updated_at: DateTime<Utc>,
     pub days_until_expiry: Option<i64>,
     pub expiration_status: String,
+    /// Продления срока годности: "старая -> новая (кто: обоснование)", через "; "
+    pub expiry_extension_history: Option<String>,
+    /// Объём одной упаковки (pack_size партии)
+    pub container_size: Option<f64>,
+    /// Ожидаемое число запечатанных упаковок
+    pub container_count: Option<i64>,
+    /// Ожидаемое число вскрытых упаковок
+    pub open_containers: Option<i64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -90,6 +104,9 @@ pub struct ReportResponse {
     pub metadata: ReportMetadata,
     pub data: Vec<BatchReportRow>,
     pub pagination: PaginationInfo,
+    /// Отчёты о сроках годности: партии реагентов с политикой advisory (без пагинации)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub advisory: Option<Vec<BatchReportRow>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -106,6 +123,14 @@ pub struct AvailablePreset {
     pub name: String,
     pub description: String,
     pub default_params: serde_json::Value,
+    /// Встроенный пресет - не редактируется и не удаляется
+    pub builtin: bool,
+    pub owner_id: Option<String>,
+    pub is_shared: bool,
+    pub filters: Vec<ReportFilterRequest>,
+    pub columns: Vec<String>,
+    pub default_sort: Option<String>,
+    pub default_sort_order: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -117,9 +142,46 @@ pub struct AvailableField {
     pub values: Option<Vec<String>>,
 }
 
+/// Строка отчёта по посещаемости (группировка по учебной группе)
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AttendanceSummaryRow {
+    pub student_group: String,
+    pub experiments: i64,
+    pub students: i64,
+    pub signed_in: i64,
+    pub attendance_rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttendanceReportResponse {
+    pub metadata: ReportMetadata,
+    pub data: Vec<AttendanceSummaryRow>,
+    pub pagination: PaginationInfo,
+}
+
+/// Эксперимент с опасными реагентами, ожидающий допуска по технике безопасности
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct PendingSignoffRow {
+    pub id: String,
+    pub title: String,
+    pub experiment_date: DateTime<Utc>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub status: String,
+    pub instructor: Option<String>,
+    pub student_group: Option<String>,
+    pub hazardous_reagents: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PendingSignoffReportResponse {
+    pub metadata: ReportMetadata,
+    pub data: Vec<PendingSignoffRow>,
+    pub pagination: PaginationInfo,
+}
+
 // ==================== REQUEST STRUCTURES ====================
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GenerateReportRequest {
     pub preset: Option<String>,
     pub preset_params: Option<serde_json::Map<String, serde_json::Value>>,
@@ -130,15 +192,35 @@ pub struct GenerateReportRequest {
     pub page: Option<i64>,
     pub per_page: Option<i64>,
     pub search: Option<String>,
+    /// Строгий режим (по умолчанию): неизвестные поля фильтров, колонок и сортировки дают 400
+    #[serde(default)]
+    pub strict: Option<bool>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReportFilterRequest {
     pub field: String,
     pub operator: String,
     pub value: serde_json::Value,
 }
 
+/// Создание/обновление пользовательского пресета отчёта
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct SaveReportPresetRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    #[validate(length(max = 500, message = "Description cannot exceed 500 characters"))]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub filters: Vec<ReportFilterRequest>,
+    #[serde(default)]
+    pub columns: Vec<String>,
+    pub default_sort: Option<String>,
+    pub default_sort_order: Option<String>,
+    #[serde(default)]
+    pub is_shared: bool,
+}
+
 impl ReportFilterRequest {
     pub fn to_report_filter(&self) -> Option<ReportFilter> {
         let operator = match self.operator.as_str() {
@@ -227,10 +309,11 @@ fn build_report_config(request: &GenerateReportRequest) -> ReportConfig {
             let days = request.preset_params.as_ref()
                 .and_then(|p| p.get("days"))
                 .and_then(|v| v.as_i64())
-                .unwrap_or(30);
+                .unwrap_or_else(|| crate::settings::settings().get_i64(crate::settings::EXPIRING_SOON_DAYS));
             ReportConfig::expiring_soon(days)
         },
         "expired" => ReportConfig::expired(),
+        "stocktake" => ReportConfig::stocktake(),
         _ => ReportConfig::all_batches(),
     };
 
@@ -239,6 +322,7 @@ fn build_report_config(request: &GenerateReportRequest) -> ReportConfig {
         "low_stock" => "Low Stock Report".to_string(),
         "expiring_soon" => "Expiring Soon Report".to_string(),
         "expired" => "Expired Items Report".to_string(),
+        "stocktake" => "Stocktake Sheet".to_string(),
         _ => "All Batches Report".to_string(),
     };
 
@@ -251,6 +335,13 @@ fn build_report_config(request: &GenerateReportRequest) -> ReportConfig {
         }
     }
 
+    if let Some(ref columns) = request.columns {
+        let selected = select_report_columns(columns);
+        if !selected.is_empty() {
+            config.columns = selected;
+        }
+    }
+
     // ✅ ИСПРАВЛЕНО: Валидация сортировки через whitelist
     if let Some(ref sort_by) = request.sort_by {
         if validate_sort_field(sort_by).is_some() {
@@ -265,9 +356,104 @@ fn build_report_config(request: &GenerateReportRequest) -> ReportConfig {
     config
 }
 
-fn build_filter_sql(config: &ReportConfig, whitelist: &FieldWhitelist) -> (String, Vec<String>) {
-    let (where_clause, params) = config.build_where_clause(whitelist);
-    (where_clause, params)
+/// Поле отчёта допустимо только как точное совпадение с whitelist (без префиксов таблиц)
+fn is_report_field(field: &str) -> bool {
+    !field.contains('.') && FieldWhitelist::for_reports().is_allowed(field)
+}
+
+/// Колонки отчёта в заданном порядке; подписи берутся из колонок по умолчанию
+fn select_report_columns(fields: &[String]) -> Vec<ReportColumn> {
+    let defaults = ReportConfig::default_batch_columns();
+    fields.iter()
+        .filter(|field| is_report_field(field))
+        .map(|field| {
+            defaults.iter()
+                .find(|c| &c.field == field)
+                .cloned()
+                .unwrap_or_else(|| ReportColumn::new(field, field))
+        })
+        .collect()
+}
+
+fn build_filter_sql(config: &ReportConfig, whitelist: &FieldWhitelist, strict: bool) -> ApiResult<(String, Vec<SqlParam>)> {
+    config.build_where_clause(whitelist, strict).map_err(ApiError::BadRequest)
+}
+
+/// Отчёты по умолчанию строгие: `"strict": false` возвращает прежнее молчаливое отбрасывание
+fn report_is_strict(request: &GenerateReportRequest) -> bool {
+    request.strict.unwrap_or(true)
+}
+
+/// В строгом режиме неизвестные поля фильтров, колонок и сортировки - 400 с их перечнем,
+/// а не отчёт по всем данным
+fn ensure_known_report_fields(request: &GenerateReportRequest) -> ApiResult<()> {
+    if !report_is_strict(request) {
+        return Ok(());
+    }
+    let mut rejected: Vec<&str> = Vec::new();
+    let filter_fields = request.filters.iter().flatten().map(|f| f.field.as_str());
+    let columns = request.columns.iter().flatten().map(String::as_str);
+    for field in filter_fields.chain(columns) {
+        if !is_report_field(field) && !rejected.contains(&field) {
+            rejected.push(field);
+        }
+    }
+    if let Some(sort) = request.sort_by.as_deref() {
+        if validate_sort_field(sort).is_none() && !rejected.contains(&sort) {
+            rejected.push(sort);
+        }
+    }
+    if rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(ApiError::BadRequest(FieldWhitelist::for_reports().unknown_fields_error(&rejected)))
+    }
+}
+
+/// Поиск по отчёту с экранированием LIKE-спецсимволов; параметры добавляются в `params`
+fn report_search_condition(search: Option<&str>, params: &mut Vec<SqlParam>) -> String {
+    match search.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(search) => {
+            let pattern = format!("%{}%", escape_like_pattern(search));
+            for _ in 0..4 {
+                params.push(pattern.clone().into());
+            }
+            " AND (reagent_name LIKE ? ESCAPE '\\' OR batch_number LIKE ? ESCAPE '\\' OR supplier LIKE ? ESCAPE '\\' OR location LIKE ? ESCAPE '\\')".to_string()
+        }
+        None => String::new(),
+    }
+}
+
+// ==================== ADVISORY EXPIRY ====================
+
+/// Встроенные отчёты о сроках годности; реагенты с политикой advisory в них - отдельный раздел
+const EXPIRY_PRESETS: &[&str] = &["expiring_soon", "expired"];
+
+/// Конфигурация раздела advisory: те же фильтры, другая политика сроков
+fn advisory_section_config(config: &ReportConfig) -> Option<ReportConfig> {
+    EXPIRY_PRESETS.contains(&config.preset.as_str())
+        .then(|| config.clone().with_expiry_policy(crate::models::EXPIRY_POLICY_ADVISORY))
+}
+
+async fn fetch_advisory_section(
+    pool: &sqlx::SqlitePool,
+    config: Option<ReportConfig>,
+    search: Option<&str>,
+) -> ApiResult<Option<Vec<BatchReportRow>>> {
+    let Some(config) = config else { return Ok(None) };
+    // Раздел advisory повторяет уже проверенные фильтры основного отчёта
+    let (where_clause, mut params) = build_filter_sql(&config, &FieldWhitelist::for_reports(), false)?;
+    let search_condition = report_search_condition(search, &mut params);
+    let sql = format!(
+        "{} WHERE {}{} ORDER BY expiry_date ASC",
+        BASE_REPORT_QUERY, where_clause, search_condition
+    );
+
+    let mut query = sqlx::query_as::<_, BatchReportRow>(&sql);
+    for p in &params {
+        query = query.bind(p);
+    }
+    Ok(Some(query.fetch_all(pool).await?))
 }
 
 // ==================== BASE QUERY ====================
@@ -275,7 +461,7 @@ fn build_filter_sql(config: &ReportConfig, whitelist: &FieldWhitelist) -> (Strin
 const BASE_REPORT_QUERY: &str = r#"
     WITH batch_data AS (
         SELECT 
-            b.id, b.reagent_id, r.name as reagent_name, b.batch_number, b.cat_number,
+            b.id, b.reagent_id, r.name as reagent_name, r.expiry_policy, b.batch_number, b.cat_number,
             b.quantity, b.original_quantity, b.reserved_quantity, b.unit, b.expiry_date,
             b.supplier, b.manufacturer, b.received_date, b.status, b.location, b.notes,
             b.created_at, b.updated_at,
@@ -287,50 +473,980 @@ const BASE_REPORT_QUERY: &str = r#"
                  WHEN julianday(b.expiry_date) - julianday('now') <= 7 THEN 'critical'
                  WHEN julianday(b.expiry_date) - julianday('now') <= 30 THEN 'warning'
                  ELSE 'ok'
-            END as expiration_status
+            END as expiration_status,
+            (SELECT group_concat(entry, '; ') FROM (
+                SELECT date(x.old_expiry_date) || ' -> ' || date(x.new_expiry_date)
+                       || ' (' || COALESCE(u.username, x.approved_by) || ': ' || x.justification || ')' as entry
+                FROM batch_expiry_extensions x
+                LEFT JOIN users u ON u.id = x.approved_by
+                WHERE x.batch_id = b.id
+                ORDER BY x.created_at, x.rowid
+            )) as expiry_extension_history,
+            b.pack_size as container_size,
+            CASE WHEN b.pack_size > 0 AND b.container_count IS NOT NULL
+                 THEN MIN(b.container_count, CAST((b.quantity + 1e-9) / b.pack_size AS INTEGER))
+            END as container_count,
+            CASE WHEN b.pack_size > 0 AND b.container_count IS NOT NULL
+                 THEN CAST(((b.quantity - MIN(b.container_count, CAST((b.quantity + 1e-9) / b.pack_size AS INTEGER)) * b.pack_size)
+                            / b.pack_size) + 0.999999999 AS INTEGER)
+            END as open_containers
         FROM batches b
         JOIN reagents r ON b.reagent_id = r.id AND r.deleted_at IS NULL
     )
     SELECT * FROM batch_data
 "#;
 
-// ==================== HANDLERS ====================
+// ==================== ATTENDANCE ====================
 
-pub async fn get_report_presets(
-    _app_state: web::Data<Arc<AppState>>,
-) -> ApiResult<HttpResponse> {
-    let presets = vec![
-        AvailablePreset {
-            id: "all_batches".to_string(),
-            name: "All Batches".to_string(),
-            description: "Complete list of all batches".to_string(),
-            default_params: serde_json::json!({}),
+const ATTENDANCE_PRESET: &str = "attendance_summary";
+/// Семестр по умолчанию - примерно полгода назад от текущей даты
+const DEFAULT_ATTENDANCE_PERIOD_DAYS: i64 = 183;
+
+/// Извлекает период (date_from, date_to) из preset_params, формат YYYY-MM-DD
+fn attendance_period(request: &GenerateReportRequest) -> ApiResult<(String, String)> {
+    let param = |key: &str| request.preset_params.as_ref().and_then(|p| p.get(key)).and_then(|v| v.as_str());
+    report_period(param("date_from"), param("date_to"), ("date_from", "date_to"))
+}
+
+/// Период отчёта YYYY-MM-DD; по умолчанию - последний семестр. `keys` - имена параметров для ошибок
+pub(crate) fn report_period(from: Option<&str>, to: Option<&str>, keys: (&str, &str)) -> ApiResult<(String, String)> {
+    let parse = |value: Option<&str>, key: &str| -> ApiResult<Option<String>> {
+        match value {
+            Some(value) => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|d| Some(d.format("%Y-%m-%d").to_string()))
+                .map_err(|_| ApiError::bad_request(&format!("Invalid {}: expected YYYY-MM-DD", key))),
+            None => Ok(None),
+        }
+    };
+
+    let now = Utc::now();
+    let date_from = parse(from, keys.0)?.unwrap_or_else(|| {
+        (now - chrono::Duration::days(DEFAULT_ATTENDANCE_PERIOD_DAYS)).format("%Y-%m-%d").to_string()
+    });
+    let date_to = parse(to, keys.1)?.unwrap_or_else(|| now.format("%Y-%m-%d").to_string());
+
+    if date_from > date_to {
+        return Err(ApiError::bad_request(&format!("{} must not be after {}", keys.0, keys.1)));
+    }
+    Ok((date_from, date_to))
+}
+
+async fn fetch_attendance_summary(
+    pool: &sqlx::SqlitePool,
+    date_from: &str,
+    date_to: &str,
+) -> Result<Vec<AttendanceSummaryRow>, sqlx::Error> {
+    sqlx::query_as(r#"
+        SELECT
+            COALESCE(e.student_group, '') AS student_group,
+            COUNT(DISTINCT e.id) AS experiments,
+            COUNT(p.id) AS students,
+            COUNT(p.signed_in_at) AS signed_in,
+            CASE WHEN COUNT(p.id) = 0 THEN 0.0
+                 ELSE ROUND(CAST(COUNT(p.signed_in_at) AS REAL) * 100.0 / COUNT(p.id), 1)
+            END AS attendance_rate
+        FROM experiments e
+        LEFT JOIN experiment_participants p
+            ON p.experiment_id = e.id AND p.role = 'student'
+        WHERE e.status NOT IN ('cancelled', 'draft')
+          AND DATE(e.experiment_date) BETWEEN ? AND ?
+        GROUP BY COALESCE(e.student_group, '')
+        ORDER BY student_group
+    "#)
+        .bind(date_from)
+        .bind(date_to)
+        .fetch_all(pool)
+        .await
+}
+
+async fn generate_attendance_report(
+    pool: &sqlx::SqlitePool,
+    request: &GenerateReportRequest,
+) -> ApiResult<AttendanceReportResponse> {
+    let (date_from, date_to) = attendance_period(request)?;
+    let data = fetch_attendance_summary(pool, &date_from, &date_to).await?;
+    let total = data.len() as i64;
+
+    Ok(AttendanceReportResponse {
+        metadata: ReportMetadata {
+            name: "Attendance Summary".to_string(),
+            description: Some(format!("Attendance per student group from {} to {}", date_from, date_to)),
+            preset: ATTENDANCE_PRESET.to_string(),
+            total_items: total,
+            generated_at: Utc::now(),
+            columns: Vec::new(),
         },
-        AvailablePreset {
-            id: "low_stock".to_string(),
-            name: "Low Stock Items".to_string(),
-            description: "Batches with quantity below threshold".to_string(),
-            default_params: serde_json::json!({ "threshold": 10 }),
+        data,
+        pagination: PaginationInfo {
+            page: 1,
+            per_page: total,
+            total,
+            total_pages: 1,
         },
-        AvailablePreset {
-            id: "expiring_soon".to_string(),
-            name: "Expiring Soon".to_string(),
-            description: "Batches expiring within specified days".to_string(),
-            default_params: serde_json::json!({ "days": 30 }),
+    })
+}
+
+// ==================== PENDING SAFETY SIGN-OFF ====================
+
+const PENDING_SIGNOFF_PRESET: &str = "pending_signoff";
+
+/// Не начатые эксперименты, которым нужен допуск; ближайшие по дате старта - первыми
+async fn fetch_pending_signoffs(
+    pool: &sqlx::SqlitePool,
+    signoff_threshold: u8,
+) -> Result<Vec<PendingSignoffRow>, sqlx::Error> {
+    let sql = format!(r#"
+        SELECT
+            e.id, e.title, e.experiment_date, e.start_date, e.status, e.instructor, e.student_group,
+            (SELECT group_concat(DISTINCT r.name)
+             FROM experiment_reagents er
+             JOIN reagents r ON r.id = er.reagent_id
+             WHERE er.experiment_id = e.id AND {hazard}) AS hazardous_reagents
+        FROM experiments e
+        WHERE e.status IN ('draft', 'planned', 'on_hold')
+          AND {awaiting}
+        ORDER BY datetime(COALESCE(e.start_date, e.experiment_date)), e.title
+    "#,
+        hazard = crate::experiment_handlers::hazard_match_condition(signoff_threshold),
+        awaiting = crate::experiment_handlers::awaiting_signoff_condition("e.id", signoff_threshold),
+    );
+    sqlx::query_as(&sql).fetch_all(pool).await
+}
+
+async fn generate_pending_signoff_report(
+    pool: &sqlx::SqlitePool,
+    signoff_threshold: u8,
+) -> ApiResult<PendingSignoffReportResponse> {
+    let data = fetch_pending_signoffs(pool, signoff_threshold).await?;
+    let total = data.len() as i64;
+
+    Ok(PendingSignoffReportResponse {
+        metadata: ReportMetadata {
+            name: "Pending Safety Sign-off".to_string(),
+            description: Some("Experiments with hazardous reagents awaiting an instructor sign-off".to_string()),
+            preset: PENDING_SIGNOFF_PRESET.to_string(),
+            total_items: total,
+            generated_at: Utc::now(),
+            columns: Vec::new(),
+        },
+        data,
+        pagination: PaginationInfo {
+            page: 1,
+            per_page: total,
+            total,
+            total_pages: 1,
         },
+    })
+}
+
+fn pending_signoff_csv(data: &[PendingSignoffRow], style: &ExportStyle) -> String {
+    let mut csv_content = style.csv_header(&[
+        "id", "title", "experiment_date", "start_date", "status", "instructor", "student_group", "hazardous_reagents",
+    ]);
+    for row in data {
+        csv_content.push_str(&style.csv_line(&[
+            row.id.clone(),
+            row.title.clone(),
+            style.datetime(&row.experiment_date),
+            row.start_date.map(|d| style.datetime(&d)).unwrap_or_default(),
+            row.status.clone(),
+            row.instructor.clone().unwrap_or_default(),
+            row.student_group.clone().unwrap_or_default(),
+            row.hazardous_reagents.clone().unwrap_or_default(),
+        ]));
+    }
+    csv_content
+}
+
+// ==================== CONSUMPTION VARIANCE ====================
+
+const CONSUMPTION_VARIANCE_PRESET: &str = "consumption_variance";
+
+/// Группировка отчёта план/факт расхода реагентов
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VarianceGroupBy {
+    #[default]
+    Experiment,
+    StudentGroup,
+    Reagent,
+}
+
+impl VarianceGroupBy {
+    fn parse(value: &str) -> ApiResult<Self> {
+        match value.trim() {
+            "experiment" => Ok(VarianceGroupBy::Experiment),
+            "student_group" => Ok(VarianceGroupBy::StudentGroup),
+            "reagent" => Ok(VarianceGroupBy::Reagent),
+            other => Err(ApiError::BadRequest(format!(
+                "Invalid group_by '{}': expected experiment, student_group or reagent", other
+            ))),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            VarianceGroupBy::Experiment => "experiment",
+            VarianceGroupBy::StudentGroup => "student_group",
+            VarianceGroupBy::Reagent => "reagent",
+        }
+    }
+
+    /// (ключ, подпись) группы для запроса experiment_reagents er / experiments e / reagents r
+    fn columns(&self) -> (&'static str, &'static str) {
+        match self {
+            VarianceGroupBy::Experiment => ("e.id", "e.title"),
+            VarianceGroupBy::StudentGroup => ("COALESCE(e.student_group, '')", "COALESCE(e.student_group, '')"),
+            VarianceGroupBy::Reagent => ("r.id", "r.name"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsumptionVarianceQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub group_by: Option<String>,
+    /// json (по умолчанию), csv или xlsx
+    pub format: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ConsumptionLine {
+    group_key: String,
+    group_label: String,
+    planned_quantity: f64,
+    actual_quantity: Option<f64>,
+    unit: String,
+}
+
+/// Корректировка остатка (stock_adjustments) для группировки по реагенту
+#[derive(Debug, sqlx::FromRow)]
+struct AdjustmentLine {
+    group_key: String,
+    group_label: String,
+    delta: f64,
+    unit: String,
+}
+
+/// Итог по группе в одной единице измерения
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ConsumptionVarianceRow {
+    pub group_key: String,
+    pub group_label: String,
+    /// Базовая единица (g, mL) или исходная, если её нельзя пересчитать
+    pub unit: String,
+    /// false - единица не приводится к базовой, суммируются только строки с той же единицей
+    pub convertible: bool,
+    /// Строки с записанным фактическим расходом
+    pub lines: i64,
+    /// Строки без actual_quantity - в суммы не входят
+    pub missing_actual: i64,
+    pub planned_total: f64,
+    pub actual_total: f64,
+    /// actual - planned: положительное значение - перерасход
+    pub variance: f64,
+    pub variance_pct: Option<f64>,
+    /// Сумма корректировок остатка за период (отрицательная - потери); только для group_by=reagent
+    pub adjustment_total: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsumptionVarianceReport {
+    pub from: String,
+    pub to: String,
+    pub group_by: VarianceGroupBy,
+    pub rows: Vec<ConsumptionVarianceRow>,
+    pub unconvertible: Vec<ConsumptionVarianceRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsumptionVarianceReportResponse {
+    pub metadata: ReportMetadata,
+    pub data: Vec<ConsumptionVarianceRow>,
+    pub pagination: PaginationInfo,
+}
+
+/// Убирает хвосты двоичной арифметики (0.30000000000000004)
+pub(crate) fn round_quantity(value: f64) -> f64 {
+    (value * 1_000_000.0).round() / 1_000_000.0
+}
+
+type VarianceRowKey = (String, String, bool);
+
+/// Строка группы в единице `unit` (создаётся пустой при первом обращении)
+fn variance_row<'a>(
+    rows: &'a mut Vec<ConsumptionVarianceRow>,
+    index: &mut std::collections::HashMap<VarianceRowKey, usize>,
+    group_key: String,
+    group_label: String,
+    unit: String,
+    convertible: bool,
+) -> &'a mut ConsumptionVarianceRow {
+    let key = (group_key.clone(), unit.clone(), convertible);
+    let position = *index.entry(key).or_insert_with(|| {
+        rows.push(ConsumptionVarianceRow {
+            group_key,
+            group_label,
+            unit,
+            convertible,
+            lines: 0,
+            missing_actual: 0,
+            planned_total: 0.0,
+            actual_total: 0.0,
+            variance: 0.0,
+            variance_pct: None,
+            adjustment_total: 0.0,
+        });
+        rows.len() - 1
+    });
+    &mut rows[position]
+}
+
+/// Суммы план/факт по группам: совместимые единицы пересчитываются в базовую,
+/// остальные попадают в строки с convertible = false и не смешиваются между собой.
+/// Корректировки остатка суммируются в `adjustment_total` тех же строк
+fn aggregate_consumption(lines: Vec<ConsumptionLine>, adjustments: Vec<AdjustmentLine>) -> Vec<ConsumptionVarianceRow> {
+    let converter = crate::validator::UnitConverter::new();
+    let mut rows: Vec<ConsumptionVarianceRow> = Vec::new();
+    let mut index = std::collections::HashMap::new();
+
+    for line in lines {
+        let (planned, actual, unit, convertible) = match converter.to_base(line.planned_quantity, &line.unit) {
+            Some((planned, base_unit)) => {
+                let actual = line.actual_quantity.and_then(|a| converter.to_base(a, &line.unit)).map(|(a, _)| a);
+                (planned, actual, base_unit.to_string(), true)
+            }
+            None => (line.planned_quantity, line.actual_quantity, line.unit, false),
+        };
+
+        let row = variance_row(&mut rows, &mut index, line.group_key, line.group_label, unit, convertible);
+        match actual {
+            Some(actual) => {
+                row.lines += 1;
+                row.planned_total += planned;
+                row.actual_total += actual;
+            }
+            None => row.missing_actual += 1,
+        }
+    }
+
+    for adjustment in adjustments {
+        let (delta, unit, convertible) = match converter.to_base(adjustment.delta, &adjustment.unit) {
+            Some((delta, base_unit)) => (delta, base_unit.to_string(), true),
+            None => (adjustment.delta, adjustment.unit, false),
+        };
+        let row = variance_row(&mut rows, &mut index, adjustment.group_key, adjustment.group_label, unit, convertible);
+        row.adjustment_total += delta;
+    }
+
+    for row in &mut rows {
+        row.planned_total = round_quantity(row.planned_total);
+        row.actual_total = round_quantity(row.actual_total);
+        row.adjustment_total = round_quantity(row.adjustment_total);
+        row.variance = round_quantity(row.actual_total - row.planned_total);
+        row.variance_pct = (row.planned_total > 0.0)
+            .then(|| (row.variance / row.planned_total * 1000.0).round() / 10.0);
+    }
+    rows.sort_by(|a, b| {
+        a.group_label.cmp(&b.group_label)
+            .then_with(|| a.group_key.cmp(&b.group_key))
+            .then_with(|| b.convertible.cmp(&a.convertible))
+            .then_with(|| a.unit.cmp(&b.unit))
+    });
+    rows
+}
+
+/// Строки расхода экспериментов за период (по дате эксперимента), отменённые не учитываются.
+/// При группировке по реагенту добавляются корректировки остатка за период (по дате корректировки)
+async fn fetch_consumption_variance(
+    pool: &sqlx::SqlitePool,
+    group_by: VarianceGroupBy,
+    date_from: &str,
+    date_to: &str,
+) -> Result<Vec<ConsumptionVarianceRow>, sqlx::Error> {
+    let (key, label) = group_by.columns();
+    let sql = format!(r#"
+        SELECT {key} AS group_key, {label} AS group_label,
+               er.planned_quantity, er.actual_quantity, er.unit
+        FROM experiment_reagents er
+        JOIN experiments e ON e.id = er.experiment_id
+        JOIN reagents r ON r.id = er.reagent_id
+        WHERE e.status NOT IN ('cancelled', 'draft')
+          AND DATE(e.experiment_date) BETWEEN ? AND ?
+    "#);
+    let lines: Vec<ConsumptionLine> = sqlx::query_as(&sql)
+        .bind(date_from)
+        .bind(date_to)
+        .fetch_all(pool)
+        .await?;
+
+    let adjustments: Vec<AdjustmentLine> = if group_by == VarianceGroupBy::Reagent {
+        sqlx::query_as(
+            r#"SELECT r.id AS group_key, r.name AS group_label, sa.delta, sa.unit
+               FROM stock_adjustments sa
+               JOIN reagents r ON r.id = sa.reagent_id
+               WHERE DATE(sa.created_at) BETWEEN ? AND ?"#
+        )
+            .bind(date_from)
+            .bind(date_to)
+            .fetch_all(pool)
+            .await?
+    } else {
+        Vec::new()
+    };
+    Ok(aggregate_consumption(lines, adjustments))
+}
+
+/// Параметры пресета: from, to (YYYY-MM-DD), group_by
+fn consumption_variance_params(request: &GenerateReportRequest) -> ApiResult<(VarianceGroupBy, String, String)> {
+    let param = |key: &str| request.preset_params.as_ref().and_then(|p| p.get(key)).and_then(|v| v.as_str());
+    let (date_from, date_to) = report_period(param("from"), param("to"), ("from", "to"))?;
+    let group_by = param("group_by").map(VarianceGroupBy::parse).transpose()?.unwrap_or_default();
+    Ok((group_by, date_from, date_to))
+}
+
+async fn generate_consumption_variance_report(
+    pool: &sqlx::SqlitePool,
+    request: &GenerateReportRequest,
+) -> ApiResult<ConsumptionVarianceReportResponse> {
+    let (group_by, date_from, date_to) = consumption_variance_params(request)?;
+    let data = fetch_consumption_variance(pool, group_by, &date_from, &date_to).await?;
+    let total = data.len() as i64;
+
+    Ok(ConsumptionVarianceReportResponse {
+        metadata: ReportMetadata {
+            name: "Consumption Variance".to_string(),
+            description: Some(format!(
+                "Planned vs actual reagent consumption per {} from {} to {}",
+                group_by.as_str(), date_from, date_to
+            )),
+            preset: CONSUMPTION_VARIANCE_PRESET.to_string(),
+            total_items: total,
+            generated_at: Utc::now(),
+            columns: Vec::new(),
+        },
+        data,
+        pagination: PaginationInfo {
+            page: 1,
+            per_page: total,
+            total,
+            total_pages: 1,
+        },
+    })
+}
+
+fn consumption_variance_csv(data: &[ConsumptionVarianceRow], style: &ExportStyle) -> String {
+    let mut csv_content = style.csv_header(&[
+        "group_key", "group_label", "unit", "convertible", "lines", "missing_actual",
+        "planned_total", "actual_total", "variance", "variance_pct", "adjustment_total",
+    ]);
+    for row in data {
+        csv_content.push_str(&style.csv_line(&[
+            row.group_key.clone(),
+            row.group_label.clone(),
+            row.unit.clone(),
+            style.yes_no(row.convertible).to_string(),
+            row.lines.to_string(),
+            row.missing_actual.to_string(),
+            style.number(row.planned_total),
+            style.number(row.actual_total),
+            style.number(row.variance),
+            row.variance_pct.map(|p| style.number(p)).unwrap_or_default(),
+            style.number(row.adjustment_total),
+        ]));
+    }
+    csv_content
+}
+
+fn consumption_variance_xlsx(data: &[ConsumptionVarianceRow]) -> ApiResult<Vec<u8>> {
+    let headers: Vec<String> = [
+        "group_key", "group_label", "unit", "convertible", "lines", "missing_actual",
+        "planned_total", "actual_total", "variance", "variance_pct", "adjustment_total",
+    ].iter().map(|h| h.to_string()).collect();
+    let rows = data.iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ApiError::internal_error(e.to_string()))?;
+    crate::export_archive::build_xlsx("consumption_variance", &headers, &rows)
+        .map_err(|e| ApiError::internal_error(e.to_string()))
+}
+
+/// GET /reports/consumption-variance?from=&to=&group_by=experiment|student_group|reagent&format=json|csv|xlsx&locale=&excel_locale=
+pub async fn get_consumption_variance(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<ConsumptionVarianceQuery>,
+    locale: web::Query<ExportLocaleQuery>,
+) -> ApiResult<HttpResponse> {
+    let pool = app_state.read_pool();
+    let style = ExportStyle::from_query(&locale)?;
+    let (date_from, date_to) = report_period(query.from.as_deref(), query.to.as_deref(), ("from", "to"))?;
+    let group_by = query.group_by.as_deref().map(VarianceGroupBy::parse).transpose()?.unwrap_or_default();
+    let format = query.format.as_deref().unwrap_or("json").trim().to_lowercase();
+    if !matches!(format.as_str(), "json" | "csv" | "xlsx") {
+        return Err(ApiError::bad_request("Invalid format: expected json, csv or xlsx"));
+    }
+
+    if format == "json" {
+        let data = fetch_consumption_variance(pool, group_by, &date_from, &date_to).await?;
+        let (rows, unconvertible) = data.into_iter().partition(|row| row.convertible);
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(ConsumptionVarianceReport {
+            from: date_from,
+            to: date_to,
+            group_by,
+            rows,
+            unconvertible,
+        })));
+    }
+
+    let ticket = crate::work_queue::export_queue().acquire().await?;
+    let data = fetch_consumption_variance(pool, group_by, &date_from, &date_to).await?;
+    let (content_type, body) = if format == "csv" {
+        (ExportFormat::Csv.content_type(), consumption_variance_csv(&data, &style).into_bytes())
+    } else {
+        (crate::export_archive::XLSX_CONTENT_TYPE, consumption_variance_xlsx(&data)?)
+    };
+    let filename = format!(
+        "report_{}_{}_{}_{}.{}",
+        CONSUMPTION_VARIANCE_PRESET, group_by.as_str(), date_from, date_to, format
+    );
+
+    Ok(ticket.annotate(HttpResponse::Ok()
+        .insert_header(("Content-Type", content_type))
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(body)))
+}
+
+// ==================== CUSTOM PRESETS ====================
+
+/// Встроенные пресеты (id, name) - пользовательские пресеты не могут их перекрыть
+const BUILTIN_PRESETS: &[(&str, &str)] = &[
+    ("all_batches", "All Batches"),
+    ("low_stock", "Low Stock Items"),
+    ("expiring_soon", "Expiring Soon"),
+    ("expired", "Expired Items"),
+    ("stocktake", "Stocktake Sheet"),
+    (ATTENDANCE_PRESET, "Attendance Summary"),
+    (PENDING_SIGNOFF_PRESET, "Pending Safety Sign-off"),
+    (CONSUMPTION_VARIANCE_PRESET, "Consumption Variance"),
+];
+
+const STORED_PRESET_COLUMNS: &str =
+    "id, name, description, filters, columns, default_sort, default_sort_order, owner_id, is_shared";
+
+fn is_builtin_preset(id: &str) -> bool {
+    BUILTIN_PRESETS.iter().any(|(builtin_id, _)| *builtin_id == id)
+}
+
+fn builtin_presets() -> Vec<AvailablePreset> {
+    let now = Utc::now();
+    BUILTIN_PRESETS.iter()
+        .map(|&(id, name)| {
+            let (description, default_params) = match id {
+                "low_stock" => ("Batches with quantity below threshold", serde_json::json!({ "threshold": 10 })),
+                "expiring_soon" => ("Batches expiring within specified days", serde_json::json!({ "days": 30 })),
+                "expired" => ("Batches that have expired", serde_json::json!({})),
+                "stocktake" => ("Non-depleted batches by location with expected sealed and open container counts", serde_json::json!({})),
+                ATTENDANCE_PRESET => ("Experiment attendance per student group over a period", serde_json::json!({
+                    "date_from": (now - chrono::Duration::days(DEFAULT_ATTENDANCE_PERIOD_DAYS)).format("%Y-%m-%d").to_string(),
+                    "date_to": now.format("%Y-%m-%d").to_string(),
+                })),
+                PENDING_SIGNOFF_PRESET => ("Experiments with hazardous reagents awaiting an instructor sign-off", serde_json::json!({})),
+                CONSUMPTION_VARIANCE_PRESET => ("Planned vs actual reagent consumption per experiment, student group or reagent", serde_json::json!({
+                    "from": (now - chrono::Duration::days(DEFAULT_ATTENDANCE_PERIOD_DAYS)).format("%Y-%m-%d").to_string(),
+                    "to": now.format("%Y-%m-%d").to_string(),
+                    "group_by": "experiment",
+                })),
+                _ => ("Complete list of all batches", serde_json::json!({})),
+            };
+            AvailablePreset {
+                id: id.to_string(),
+                name: name.to_string(),
+                description: description.to_string(),
+                default_params,
+                builtin: true,
+                owner_id: None,
+                is_shared: true,
+                filters: Vec::new(),
+                columns: Vec::new(),
+                default_sort: None,
+                default_sort_order: None,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct StoredPresetRow {
+    id: String,
+    name: String,
+    description: Option<String>,
+    filters: String,
+    columns: String,
+    default_sort: Option<String>,
+    default_sort_order: Option<String>,
+    owner_id: String,
+    is_shared: bool,
+}
+
+impl StoredPresetRow {
+    fn into_available(self) -> AvailablePreset {
         AvailablePreset {
-            id: "expired".to_string(),
-            name: "Expired Items".to_string(),
-            description: "Batches that have expired".to_string(),
+            id: self.id,
+            name: self.name,
+            description: self.description.unwrap_or_default(),
             default_params: serde_json::json!({}),
-        },
-    ];
+            builtin: false,
+            owner_id: Some(self.owner_id),
+            is_shared: self.is_shared,
+            filters: serde_json::from_str(&self.filters).unwrap_or_default(),
+            columns: serde_json::from_str(&self.columns).unwrap_or_default(),
+            default_sort: self.default_sort,
+            default_sort_order: self.default_sort_order,
+        }
+    }
+}
+
+/// Проверка пресета перед сохранением: имя не совпадает со встроенными,
+/// фильтры, колонки и сортировка - только из whitelist отчётов
+pub(crate) fn validate_preset_request(request: &SaveReportPresetRequest) -> ApiResult<()> {
+    request.validate()?;
+
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::bad_request("Preset name cannot be empty"));
+    }
+    if BUILTIN_PRESETS.iter().any(|(id, label)| id.eq_ignore_ascii_case(name) || label.eq_ignore_ascii_case(name)) {
+        return Err(ApiError::BadRequest(format!("Preset name '{}' is reserved by a built-in preset", name)));
+    }
+
+    for filter in &request.filters {
+        if !is_report_field(&filter.field) {
+            return Err(ApiError::BadRequest(format!("Filter field '{}' is not allowed", filter.field)));
+        }
+        if filter.to_report_filter().is_none() {
+            return Err(ApiError::BadRequest(format!(
+                "Invalid operator '{}' or value for filter field '{}'", filter.operator, filter.field
+            )));
+        }
+    }
+
+    if let Some(column) = request.columns.iter().find(|c| !is_report_field(c)) {
+        return Err(ApiError::BadRequest(format!("Column '{}' is not allowed", column)));
+    }
+
+    if let Some(ref sort) = request.default_sort {
+        if validate_sort_field(sort).is_none() {
+            return Err(ApiError::BadRequest(format!("Sort field '{}' is not allowed", sort)));
+        }
+    }
+    if let Some(ref order) = request.default_sort_order {
+        if !order.eq_ignore_ascii_case("ASC") && !order.eq_ignore_ascii_case("DESC") {
+            return Err(ApiError::bad_request("default_sort_order must be ASC or DESC"));
+        }
+    }
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(presets)))
+    Ok(())
+}
+
+/// Владелец управляет своими пресетами, администратор - также общими
+fn can_manage_preset(preset: &StoredPresetRow, user_id: &str, role: &UserRole) -> bool {
+    preset.owner_id == user_id || (preset.is_shared && *role == UserRole::Admin)
+}
+
+/// Пресет, доступный пользователю: собственный или общий
+async fn fetch_visible_preset(
+    pool: &sqlx::SqlitePool,
+    id: &str,
+    user_id: &str,
+) -> ApiResult<StoredPresetRow> {
+    let sql = format!(
+        "SELECT {} FROM report_presets WHERE id = ? AND (owner_id = ? OR is_shared = 1)",
+        STORED_PRESET_COLUMNS
+    );
+    sqlx::query_as::<_, StoredPresetRow>(&sql)
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Report preset"))
+}
+
+async fn fetch_manageable_preset(
+    pool: &sqlx::SqlitePool,
+    id: &str,
+    user_id: &str,
+    role: &UserRole,
+) -> ApiResult<StoredPresetRow> {
+    if is_builtin_preset(id) {
+        return Err(ApiError::Forbidden("Built-in presets cannot be modified".to_string()));
+    }
+    let preset = fetch_visible_preset(pool, id, user_id).await?;
+    if !can_manage_preset(&preset, user_id, role) {
+        return Err(ApiError::Forbidden("You can only manage your own report presets".to_string()));
+    }
+    Ok(preset)
+}
+
+async fn ensure_unique_preset_name(
+    pool: &sqlx::SqlitePool,
+    name: &str,
+    owner_id: &str,
+    exclude_id: Option<&str>,
+) -> ApiResult<()> {
+    let exists: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM report_presets WHERE name = ? COLLATE NOCASE AND owner_id = ? AND id != ?"
+    )
+        .bind(name)
+        .bind(owner_id)
+        .bind(exclude_id.unwrap_or(""))
+        .fetch_one(pool)
+        .await?;
+    if exists > 0 {
+        return Err(ApiError::BadRequest(format!("Report preset '{}' already exists", name)));
+    }
+    Ok(())
+}
+
+fn ensure_can_share(request: &SaveReportPresetRequest, role: &UserRole) -> ApiResult<()> {
+    if request.is_shared && !role.can_share_report_presets() {
+        return Err(ApiError::Forbidden("Insufficient permissions to share report presets".to_string()));
+    }
+    Ok(())
+}
+
+/// Название пресета, доступного пользователю: встроенного, собственного или общего
+pub(crate) async fn visible_preset_name(pool: &sqlx::SqlitePool, id: &str, user_id: &str) -> ApiResult<String> {
+    if let Some((_, name)) = BUILTIN_PRESETS.iter().find(|(builtin_id, _)| *builtin_id == id) {
+        return Ok(name.to_string());
+    }
+    Ok(fetch_visible_preset(pool, id, user_id).await?.name)
+}
+
+/// Встроенные пресеты + собственные и общие пользовательские
+async fn list_presets(pool: &sqlx::SqlitePool, user_id: &str) -> ApiResult<Vec<AvailablePreset>> {
+    let sql = format!(
+        "SELECT {} FROM report_presets WHERE owner_id = ? OR is_shared = 1 ORDER BY name COLLATE NOCASE",
+        STORED_PRESET_COLUMNS
+    );
+    let stored = sqlx::query_as::<_, StoredPresetRow>(&sql)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+    let mut presets = builtin_presets();
+    presets.extend(stored.into_iter().map(StoredPresetRow::into_available));
+    Ok(presets)
+}
+
+async fn create_preset(
+    pool: &sqlx::SqlitePool,
+    user_id: &str,
+    role: &UserRole,
+    request: &SaveReportPresetRequest,
+) -> ApiResult<AvailablePreset> {
+    validate_preset_request(request)?;
+    ensure_can_share(request, role)?;
+    let name = request.name.trim();
+    ensure_unique_preset_name(pool, name, user_id, None).await?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now();
+    sqlx::query(r#"
+        INSERT INTO report_presets
+            (id, name, description, filters, columns, default_sort, default_sort_order, owner_id, is_shared, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+    "#)
+        .bind(&id)
+        .bind(name)
+        .bind(&request.description)
+        .bind(serde_json::to_string(&request.filters).map_err(|e| ApiError::internal_error(e.to_string()))?)
+        .bind(serde_json::to_string(&request.columns).map_err(|e| ApiError::internal_error(e.to_string()))?)
+        .bind(&request.default_sort)
+        .bind(request.default_sort_order.as_ref().map(|o| o.to_uppercase()))
+        .bind(user_id)
+        .bind(request.is_shared)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    Ok(fetch_visible_preset(pool, &id, user_id).await?.into_available())
+}
+
+async fn update_preset(
+    pool: &sqlx::SqlitePool,
+    id: &str,
+    user_id: &str,
+    role: &UserRole,
+    request: &SaveReportPresetRequest,
+) -> ApiResult<AvailablePreset> {
+    let existing = fetch_manageable_preset(pool, id, user_id, role).await?;
+    validate_preset_request(request)?;
+    ensure_can_share(request, role)?;
+    let name = request.name.trim();
+    ensure_unique_preset_name(pool, name, &existing.owner_id, Some(id)).await?;
+
+    sqlx::query(r#"
+        UPDATE report_presets
+        SET name = ?, description = ?, filters = ?, columns = ?, default_sort = ?,
+            default_sort_order = ?, is_shared = ?, updated_at = ?
+        WHERE id = ?
+    "#)
+        .bind(name)
+        .bind(&request.description)
+        .bind(serde_json::to_string(&request.filters).map_err(|e| ApiError::internal_error(e.to_string()))?)
+        .bind(serde_json::to_string(&request.columns).map_err(|e| ApiError::internal_error(e.to_string()))?)
+        .bind(&request.default_sort)
+        .bind(request.default_sort_order.as_ref().map(|o| o.to_uppercase()))
+        .bind(request.is_shared)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(fetch_visible_preset(pool, id, &existing.owner_id).await?.into_available())
+}
+
+async fn delete_preset(
+    pool: &sqlx::SqlitePool,
+    id: &str,
+    user_id: &str,
+    role: &UserRole,
+) -> ApiResult<AvailablePreset> {
+    let existing = fetch_manageable_preset(pool, id, user_id, role).await?;
+    sqlx::query("DELETE FROM report_presets WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(existing.into_available())
+}
+
+/// Подставляет сохранённый пресет в запрос: его фильтры добавляются к фильтрам запроса,
+/// колонки и сортировка берутся из пресета, если не заданы явно
+fn merge_stored_preset(request: &mut GenerateReportRequest, preset: &AvailablePreset) {
+    let mut filters = preset.filters.clone();
+    filters.extend(request.filters.take().unwrap_or_default());
+    request.filters = Some(filters);
+
+    if request.columns.as_ref().is_none_or(|c| c.is_empty()) && !preset.columns.is_empty() {
+        request.columns = Some(preset.columns.clone());
+    }
+    if request.sort_by.is_none() {
+        request.sort_by = preset.default_sort.clone();
+    }
+    if request.sort_order.is_none() {
+        request.sort_order = preset.default_sort_order.clone();
+    }
+}
+
+/// Загружает пользовательский пресет (если запрошен) и применяет его к запросу
+async fn resolve_stored_preset(
+    pool: &sqlx::SqlitePool,
+    request: &mut GenerateReportRequest,
+    http_request: &HttpRequest,
+) -> ApiResult<Option<AvailablePreset>> {
+    match request.preset.as_deref() {
+        Some(id) if !is_builtin_preset(id) => {
+            let user = get_current_user(http_request)?;
+            resolve_stored_preset_for(pool, request, &user.sub).await
+        }
+        _ => Ok(None),
+    }
+}
+
+/// То же для известного пользователя (рассылка по расписанию от имени владельца)
+async fn resolve_stored_preset_for(
+    pool: &sqlx::SqlitePool,
+    request: &mut GenerateReportRequest,
+    user_id: &str,
+) -> ApiResult<Option<AvailablePreset>> {
+    let preset_id = match request.preset.as_deref() {
+        Some(id) if !is_builtin_preset(id) => id.to_string(),
+        _ => return Ok(None),
+    };
+    let preset = fetch_visible_preset(pool, &preset_id, user_id).await?.into_available();
+    merge_stored_preset(request, &preset);
+    Ok(Some(preset))
+}
+
+fn apply_preset_metadata(config: &mut ReportConfig, preset: &AvailablePreset) {
+    config.preset = preset.id.clone();
+    config.name = preset.name.clone();
+    if !preset.description.is_empty() {
+        config.description = Some(preset.description.clone());
+    }
+}
+
+// ==================== HANDLERS ====================
+
+pub async fn get_report_presets(
+    app_state: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let presets = app_state.reference_cache
+        .report_presets(&user.sub, || list_presets(&app_state.db_pool, &user.sub))
+        .await?;
+    Ok(reference_response(&app_state, &*presets))
+}
+
+pub async fn create_report_preset(
+    app_state: web::Data<Arc<AppState>>,
+    request: web::Json<SaveReportPresetRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let preset = create_preset(&app_state.db_pool, &user.sub, &user.role, &request).await?;
+    app_state.reference_cache.invalidate(ReferenceDataset::ReportPresets);
+
+    crate::audit::audit(
+        &app_state.db_pool, &user.sub, "create", "report_preset", &preset.id,
+        &format!("Created report preset '{}'", preset.name), &http_request,
+    ).await;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(preset)))
+}
+
+pub async fn update_report_preset(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    request: web::Json<SaveReportPresetRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let preset = update_preset(&app_state.db_pool, &path, &user.sub, &user.role, &request).await?;
+    app_state.reference_cache.invalidate(ReferenceDataset::ReportPresets);
+
+    crate::audit::audit(
+        &app_state.db_pool, &user.sub, "update", "report_preset", &preset.id,
+        &format!("Updated report preset '{}'", preset.name), &http_request,
+    ).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(preset)))
+}
+
+pub async fn delete_report_preset(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let preset = delete_preset(&app_state.db_pool, &path, &user.sub, &user.role).await?;
+    app_state.reference_cache.invalidate(ReferenceDataset::ReportPresets);
+
+    crate::audit::audit(
+        &app_state.db_pool, &user.sub, "delete", "report_preset", &preset.id,
+        &format!("Deleted report preset '{}'", preset.name), &http_request,
+    ).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message(
+        (), "Report preset deleted successfully".to_string(),
+    )))
 }
 
 pub async fn get_report_fields(
-    _app_state: web::Data<Arc<AppState>>,
+    app_state: web::Data<Arc<AppState>>,
 ) -> ApiResult<HttpResponse> {
     let fields = vec![
         AvailableField {
@@ -399,7 +1515,7 @@ pub async fn get_report_fields(
         },
     ];
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(fields)))
+    Ok(reference_response(&app_state, fields))
 }
 
 pub async fn get_report_columns(
@@ -412,9 +1528,33 @@ pub async fn get_report_columns(
 pub async fn generate_report(
     app_state: web::Data<Arc<AppState>>,
     request: web::Json<GenerateReportRequest>,
-    _http_request: HttpRequest,
+    http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    let config = build_report_config(&request);
+    let pool = app_state.read_pool();
+    let mut request = request.into_inner();
+    let stored_preset = resolve_stored_preset(pool, &mut request, &http_request).await?;
+
+    if request.preset.as_deref() == Some(ATTENDANCE_PRESET) {
+        let response = generate_attendance_report(pool, &request).await?;
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(response)));
+    }
+    if request.preset.as_deref() == Some(PENDING_SIGNOFF_PRESET) {
+        let response = generate_pending_signoff_report(
+            pool, app_state.config.safety.signoff_hazard_threshold,
+        ).await?;
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(response)));
+    }
+    if request.preset.as_deref() == Some(CONSUMPTION_VARIANCE_PRESET) {
+        let response = generate_consumption_variance_report(pool, &request).await?;
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(response)));
+    }
+
+    ensure_known_report_fields(&request)?;
+    let mut config = build_report_config(&request);
+    let advisory_config = advisory_section_config(&config);
+    if let Some(ref preset) = stored_preset {
+        apply_preset_metadata(&mut config, preset);
+    }
     let whitelist = FieldWhitelist::for_reports();
 
     // Пагинация
@@ -423,21 +1563,8 @@ pub async fn generate_report(
     let offset = (page - 1) * per_page;
 
     // Строим WHERE условия
-    let (where_clause, mut params) = build_filter_sql(&config, &whitelist);
-    
-    // ✅ ИСПРАВЛЕНО: Добавляем поиск с экранированием LIKE-спецсимволов
-    let mut search_condition = String::new();
-    if let Some(ref search) = request.search {
-        if !search.trim().is_empty() {
-            let escaped = escape_like_pattern(search.trim());
-            let pattern = format!("%{}%", escaped);
-            search_condition = " AND (reagent_name LIKE ? ESCAPE '\\' OR batch_number LIKE ? ESCAPE '\\' OR supplier LIKE ? ESCAPE '\\' OR location LIKE ? ESCAPE '\\')".to_string();
-            params.push(pattern.clone());
-            params.push(pattern.clone());
-            params.push(pattern.clone());
-            params.push(pattern);
-        }
-    }
+    let (where_clause, mut params) = build_filter_sql(&config, &whitelist, report_is_strict(&request))?;
+    let search_condition = report_search_condition(request.search.as_deref(), &mut params);
 
     // ✅ ИСПРАВЛЕНО: Валидация сортировки через whitelist
     let sort_field = config.sort_by.as_deref()
@@ -459,7 +1586,7 @@ pub async fn generate_report(
     for p in &params {
         count_query = count_query.bind(p);
     }
-    let total: i64 = count_query.fetch_one(&app_state.db_pool).await?;
+    let total: i64 = count_query.fetch_one(pool).await?;
 
     // DATA запрос
     let data_sql = format!(
@@ -473,7 +1600,8 @@ pub async fn generate_report(
     }
     data_query = data_query.bind(per_page).bind(offset);
     
-    let data: Vec<BatchReportRow> = data_query.fetch_all(&app_state.db_pool).await?;
+    let data: Vec<BatchReportRow> = data_query.fetch_all(pool).await?;
+    let advisory = fetch_advisory_section(pool, advisory_config, request.search.as_deref()).await?;
 
     let total_pages = if per_page > 0 { (total + per_page - 1) / per_page } else { 1 };
 
@@ -493,6 +1621,7 @@ pub async fn generate_report(
             total,
             total_pages,
         },
+        advisory,
     };
 
     Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
@@ -501,26 +1630,136 @@ pub async fn generate_report(
 pub async fn export_report(
     app_state: web::Data<Arc<AppState>>,
     request: web::Json<GenerateReportRequest>,
-    _http_request: HttpRequest,
+    locale: web::Query<ExportLocaleQuery>,
+    link: web::Query<crate::signed_downloads::DownloadLinkQuery>,
+    http_request: HttpRequest,
 ) -> ApiResult<HttpResponse> {
-    let config = build_report_config(&request);
-    let whitelist = FieldWhitelist::for_reports();
+    let user = get_current_user(&http_request)?;
+    let style = ExportStyle::from_query(&locale)?;
+    if link.wants_link() {
+        let artifact = crate::signed_downloads::DownloadArtifact::ReportExport {
+            request: Box::new(request.into_inner()),
+            locale: locale.into_inner(),
+        };
+        return crate::signed_downloads::link_response(&app_state, &http_request, &user.sub, artifact);
+    }
+    let ticket = crate::work_queue::export_queue().acquire().await?;
+    let file = render_report(
+        app_state.read_pool(),
+        request.into_inner(),
+        &user.sub,
+        app_state.config.safety.signoff_hazard_threshold,
+        ExportFormat::Csv,
+        &style,
+    ).await?;
 
-    let (where_clause, mut params) = build_filter_sql(&config, &whitelist);
-    
-    // ✅ ИСПРАВЛЕНО: Добавляем поиск с экранированием
-    let mut search_condition = String::new();
-    if let Some(ref search) = request.search {
-        if !search.trim().is_empty() {
-            let escaped = escape_like_pattern(search.trim());
-            let pattern = format!("%{}%", escaped);
-            search_condition = " AND (reagent_name LIKE ? ESCAPE '\\' OR batch_number LIKE ? ESCAPE '\\' OR supplier LIKE ? ESCAPE '\\' OR location LIKE ? ESCAPE '\\')".to_string();
-            params.push(pattern.clone());
-            params.push(pattern.clone());
-            params.push(pattern.clone());
-            params.push(pattern);
+    Ok(ticket.annotate(HttpResponse::Ok()
+        .insert_header(("Content-Type", file.content_type))
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", file.filename)))
+        .body(file.body)))
+}
+
+// ==================== EXPORT ====================
+
+/// Формат файла отчёта
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
         }
     }
+}
+
+/// Готовый файл отчёта; один путь для POST /reports/export и рассылки по расписанию
+#[derive(Debug)]
+pub struct ReportFile {
+    pub filename: String,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    pub row_count: usize,
+}
+
+fn report_file<T: Serialize>(
+    stem: String,
+    format: ExportFormat,
+    rows: &[T],
+    to_csv: fn(&[T], &ExportStyle) -> String,
+    style: &ExportStyle,
+) -> ApiResult<ReportFile> {
+    let body = match format {
+        ExportFormat::Csv => to_csv(rows, style).into_bytes(),
+        ExportFormat::Json => serde_json::to_vec_pretty(rows).map_err(|e| ApiError::internal_error(e.to_string()))?,
+    };
+    Ok(ReportFile {
+        filename: format!("{}.{}", stem, format.as_str()),
+        content_type: format.content_type(),
+        body,
+        row_count: rows.len(),
+    })
+}
+
+/// Все строки отчёта без пагинации; пользовательский пресет ищется среди доступных `user_id`
+pub async fn render_report(
+    pool: &sqlx::SqlitePool,
+    mut request: GenerateReportRequest,
+    user_id: &str,
+    signoff_threshold: u8,
+    format: ExportFormat,
+    style: &ExportStyle,
+) -> ApiResult<ReportFile> {
+    let stored_preset = resolve_stored_preset_for(pool, &mut request, user_id).await?;
+
+    if request.preset.as_deref() == Some(ATTENDANCE_PRESET) {
+        let (date_from, date_to) = attendance_period(&request)?;
+        let data = fetch_attendance_summary(pool, &date_from, &date_to).await?;
+        let stem = format!("report_{}_{}_{}", ATTENDANCE_PRESET, date_from, date_to);
+        return report_file(stem, format, &data, attendance_csv, style);
+    }
+    if request.preset.as_deref() == Some(PENDING_SIGNOFF_PRESET) {
+        let data = fetch_pending_signoffs(pool, signoff_threshold).await?;
+        let stem = format!("report_{}_{}", PENDING_SIGNOFF_PRESET, Utc::now().format("%Y%m%d_%H%M%S"));
+        return report_file(stem, format, &data, pending_signoff_csv, style);
+    }
+    if request.preset.as_deref() == Some(CONSUMPTION_VARIANCE_PRESET) {
+        let (group_by, date_from, date_to) = consumption_variance_params(&request)?;
+        let data = fetch_consumption_variance(pool, group_by, &date_from, &date_to).await?;
+        let stem = format!("report_{}_{}_{}_{}", CONSUMPTION_VARIANCE_PRESET, group_by.as_str(), date_from, date_to);
+        return report_file(stem, format, &data, consumption_variance_csv, style);
+    }
+
+    ensure_known_report_fields(&request)?;
+    let mut config = build_report_config(&request);
+    let advisory_config = advisory_section_config(&config);
+    if let Some(ref preset) = stored_preset {
+        apply_preset_metadata(&mut config, preset);
+    }
+    let whitelist = FieldWhitelist::for_reports();
+
+    let (where_clause, mut params) = build_filter_sql(&config, &whitelist, report_is_strict(&request))?;
+    let search_condition = report_search_condition(request.search.as_deref(), &mut params);
 
     // ✅ ИСПРАВЛЕНО: Валидация сортировки
     let sort_field = config.sort_by.as_deref()
@@ -539,36 +1778,85 @@ pub async fn export_report(
         data_query = data_query.bind(p);
     }
     
-    let data: Vec<BatchReportRow> = data_query.fetch_all(&app_state.db_pool).await?;
+    let data: Vec<BatchReportRow> = data_query.fetch_all(pool).await?;
 
-    // ✅ ИСПРАВЛЕНО: Генерируем CSV с правильным экранированием
-    let mut csv_content = String::new();
-    // BOM для корректного отображения UTF-8 в Excel
-    csv_content.push('\u{FEFF}');
-    csv_content.push_str("ID,Reagent,Batch Number,Quantity,Unit,Expiry Date,Status,Location,Supplier,Notes\n");
-    
-    for row in &data {
-        csv_content.push_str(&format!(
-            "{},{},{},{},{},{},{},{},{},{}\n",
-            escape_csv_field(&row.id),
-            escape_csv_field(&row.reagent_name),
-            escape_csv_field(&row.batch_number),
-            row.quantity,
-            escape_csv_field(&row.unit),
-            row.expiry_date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default(),
-            escape_csv_field(&row.status),
-            escape_csv_field(row.location.as_deref().unwrap_or("")),
-            escape_csv_field(row.supplier.as_deref().unwrap_or("")),
-            escape_csv_field(row.notes.as_deref().unwrap_or("")),
-        ));
-    }
-
-    let filename = format!("report_{}_{}.csv", config.preset, Utc::now().format("%Y%m%d_%H%M%S"));
-
-    Ok(HttpResponse::Ok()
-        .insert_header(("Content-Type", "text/csv; charset=utf-8"))
-        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
-        .body(csv_content))
+    let stem = format!("report_{}_{}", config.preset, Utc::now().format("%Y%m%d_%H%M%S"));
+    match fetch_advisory_section(pool, advisory_config, request.search.as_deref()).await? {
+        Some(advisory) => expiry_report_file(stem, format, &data, &advisory, style),
+        None => report_file(stem, format, &data, batch_report_csv, style),
+    }
+}
+
+/// Отчёт о сроках годности: основные строки, затем раздел advisory
+/// (в CSV - после пустой строки и заголовка раздела, в JSON - поле `advisory`)
+fn expiry_report_file(
+    stem: String,
+    format: ExportFormat,
+    rows: &[BatchReportRow],
+    advisory: &[BatchReportRow],
+    style: &ExportStyle,
+) -> ApiResult<ReportFile> {
+    let body = match format {
+        ExportFormat::Csv => {
+            let mut csv = batch_report_csv(rows, style);
+            if !advisory.is_empty() {
+                csv.push('\n');
+                csv.push_str(&style.csv_line(&[style.label("advisory_expiry").to_string()]));
+                csv.push_str(batch_report_csv(advisory, style).trim_start_matches('\u{FEFF}'));
+            }
+            csv.into_bytes()
+        }
+        ExportFormat::Json => serde_json::to_vec_pretty(&serde_json::json!({ "data": rows, "advisory": advisory }))
+            .map_err(|e| ApiError::internal_error(e.to_string()))?,
+    };
+    Ok(ReportFile {
+        filename: format!("{}.{}", stem, format.as_str()),
+        content_type: format.content_type(),
+        body,
+        row_count: rows.len() + advisory.len(),
+    })
+}
+
+// ✅ ИСПРАВЛЕНО: Генерируем CSV с правильным экранированием
+fn batch_report_csv(data: &[BatchReportRow], style: &ExportStyle) -> String {
+    let mut csv_content = style.csv_header(&[
+        "id", "reagent_name", "batch_number", "quantity", "unit", "expiry_date", "status", "location", "supplier",
+        "notes", "expiry_extension_history", "container_size", "sealed_containers", "open_containers",
+    ]);
+
+    for row in data {
+        csv_content.push_str(&style.csv_line(&[
+            row.id.clone(),
+            row.reagent_name.clone(),
+            row.batch_number.clone(),
+            style.number(row.quantity),
+            row.unit.clone(),
+            row.expiry_date.map(|d| style.date(&d.date_naive())).unwrap_or_default(),
+            row.status.clone(),
+            row.location.clone().unwrap_or_default(),
+            row.supplier.clone().unwrap_or_default(),
+            row.notes.clone().unwrap_or_default(),
+            row.expiry_extension_history.clone().unwrap_or_default(),
+            row.container_size.map(|v| style.number(v)).unwrap_or_default(),
+            row.container_count.map(|v| v.to_string()).unwrap_or_default(),
+            row.open_containers.map(|v| v.to_string()).unwrap_or_default(),
+        ]));
+    }
+    csv_content
+}
+
+fn attendance_csv(data: &[AttendanceSummaryRow], style: &ExportStyle) -> String {
+    let mut csv_content = style.csv_header(&["student_group", "experiments", "students", "signed_in", "attendance_rate"]);
+    for row in data {
+        csv_content.push_str(&style.csv_line(&[
+            row.student_group.clone(),
+            row.experiments.to_string(),
+            row.students.to_string(),
+            row.signed_in.to_string(),
+            style.number(row.attendance_rate),
+        ]));
+    }
+    csv_content
 }
 
 // ==================== TESTS ====================
@@ -577,6 +1865,14 @@ pub async fn export_report(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_report_sort_field_rejects_hostile_input() {
+        assert_eq!(validate_sort_field("expiry_date"), Some("expiry_date"));
+        assert_eq!(validate_sort_field("expiry_date; DROP TABLE batches"), None);
+        assert_eq!(validate_sort_field("(SELECT password_hash FROM users)"), None);
+        assert_eq!(validate_sort_field("b.expiry_date"), None);
+    }
+
     #[test]
     fn test_validate_sort_field() {
         // Валидные поля
@@ -635,4 +1931,496 @@ mod tests {
         
         assert!(req.to_report_filter().is_none());
     }
+
+    #[test]
+    fn test_reports_reject_unknown_fields_unless_lenient() {
+        let mut request = GenerateReportRequest {
+            filters: Some(vec![ReportFilterRequest {
+                field: "expiry".to_string(),
+                operator: "eq".to_string(),
+                value: serde_json::json!("2024-01-01"),
+            }]),
+            columns: Some(vec!["reagent_name".to_string(), "lot".to_string()]),
+            sort_by: Some("expiry_date".to_string()),
+            ..Default::default()
+        };
+        let err = ensure_known_report_fields(&request).unwrap_err().to_string();
+        assert!(err.contains("Unknown field(s): expiry, lot."), "{}", err);
+
+        request.strict = Some(false);
+        assert!(ensure_known_report_fields(&request).is_ok());
+        let config = build_report_config(&request);
+        let (where_clause, _) = build_filter_sql(&config, &FieldWhitelist::for_reports(), false).unwrap();
+        assert!(!where_clause.contains("expiry"), "{}", where_clause);
+        assert!(build_filter_sql(&config, &FieldWhitelist::for_reports(), true).is_err());
+    }
+
+    fn attendance_request(params: serde_json::Value) -> GenerateReportRequest {
+        GenerateReportRequest {
+            preset: Some(ATTENDANCE_PRESET.to_string()),
+            preset_params: params.as_object().cloned(),
+            filters: None,
+            columns: None,
+            sort_by: None,
+            sort_order: None,
+            page: None,
+            per_page: None,
+            search: None,
+            strict: None,
+        }
+    }
+
+    #[test]
+    fn test_attendance_period_params() {
+        let request = attendance_request(serde_json::json!({ "date_from": "2024-09-01", "date_to": "2024-12-31" }));
+        let (from, to) = attendance_period(&request).unwrap();
+        assert_eq!(from, "2024-09-01");
+        assert_eq!(to, "2024-12-31");
+
+        let reversed = attendance_request(serde_json::json!({ "date_from": "2025-01-01", "date_to": "2024-01-01" }));
+        assert!(attendance_period(&reversed).is_err());
+
+        let invalid = attendance_request(serde_json::json!({ "date_from": "01.09.2024" }));
+        assert!(attendance_period(&invalid).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_attendance_summary_groups_students() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u1', 'teacher', 't@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        for (id, group) in [("e1", "CHEM-101"), ("e2", "CHEM-101"), ("e3", "BIO-201")] {
+            sqlx::query(
+                "INSERT INTO experiments (id, title, experiment_date, student_group, status, created_by, created_at, updated_at) \
+                 VALUES (?, 'Lab', '2024-10-01 10:00:00', ?, 'completed', 'u1', datetime('now'), datetime('now'))"
+            ).bind(id).bind(group).execute(&pool).await.unwrap();
+        }
+
+        let participants = [
+            ("p1", "e1", "student", true),
+            ("p2", "e1", "student", false),
+            ("p3", "e2", "student", true),
+            ("p4", "e2", "instructor", true),
+            ("p5", "e3", "student", false),
+        ];
+        for (id, exp, role, signed) in participants {
+            sqlx::query(
+                "INSERT INTO experiment_participants (id, experiment_id, name, role, signed_in_at, created_at) \
+                 VALUES (?, ?, 'Someone', ?, CASE WHEN ? THEN datetime('now') END, datetime('now'))"
+            ).bind(id).bind(exp).bind(role).bind(signed).execute(&pool).await.unwrap();
+        }
+
+        let rows = fetch_attendance_summary(&pool, "2024-09-01", "2024-12-31").await.unwrap();
+        assert_eq!(rows.len(), 2);
+
+        let bio = &rows[0];
+        assert_eq!(bio.student_group, "BIO-201");
+        assert_eq!((bio.experiments, bio.students, bio.signed_in), (1, 1, 0));
+
+        let chem = &rows[1];
+        assert_eq!(chem.student_group, "CHEM-101");
+        assert_eq!((chem.experiments, chem.students, chem.signed_in), (2, 3, 2));
+        assert!((chem.attendance_rate - 66.7).abs() < 0.01);
+
+        let outside = fetch_attendance_summary(&pool, "2025-01-01", "2025-06-30").await.unwrap();
+        assert!(outside.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_consumption_variance_converts_units_and_separates_unconvertible() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u1', 'teacher', 't@example.com', 'x', 'admin', datetime('now'), datetime('now'))",
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) VALUES \
+             ('r1', 'Sodium chloride', 'active', datetime('now'), datetime('now')), \
+             ('r2', 'Ethanol', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, student_group, status, created_by, created_at, updated_at) VALUES \
+             ('e1', 'Titration', '2024-10-01 10:00:00', 'CHEM-101', 'completed', 'u1', datetime('now'), datetime('now')), \
+             ('e2', 'Extraction', '2024-10-08 10:00:00', 'CHEM-101', 'completed', 'u1', datetime('now'), datetime('now')), \
+             ('e3', 'Cancelled lab', '2024-10-15 10:00:00', 'CHEM-101', 'cancelled', 'u1', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let lines = [
+            ("l1", "e1", "r1", 10.0, Some(12.0), "g"),
+            ("l2", "e2", "r1", 500.0, Some(400.0), "mg"),
+            ("l3", "e2", "r2", 1.0, Some(1.5), "L"),
+            ("l4", "e1", "r2", 2.0, Some(3.0), "mol"),
+            ("l5", "e2", "r2", 5.0, None, "mL"),
+            ("l6", "e3", "r1", 100.0, Some(300.0), "g"),
+        ];
+        for (id, experiment, reagent, planned, actual, unit) in lines {
+            sqlx::query(
+                "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, planned_quantity, actual_quantity, unit, created_at, updated_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))"
+            ).bind(id).bind(experiment).bind(reagent).bind(planned).bind(actual).bind(unit).execute(&pool).await.unwrap();
+        }
+
+        let rows = fetch_consumption_variance(&pool, VarianceGroupBy::StudentGroup, "2024-09-01", "2024-12-31").await.unwrap();
+        let summary: Vec<_> = rows.iter()
+            .map(|r| (r.unit.as_str(), r.convertible, r.lines, r.missing_actual, r.planned_total, r.actual_total, r.variance))
+            .collect();
+        // g и mg суммируются в граммах, L/mL - в миллилитрах, mol не смешивается ни с чем
+        assert_eq!(summary, vec![
+            ("g", true, 2, 0, 10.5, 12.4, 1.9),
+            ("mL", true, 1, 1, 1000.0, 1500.0, 500.0),
+            ("mol", false, 1, 0, 2.0, 3.0, 1.0),
+        ]);
+        assert_eq!(rows[1].variance_pct, Some(50.0));
+
+        let by_reagent = fetch_consumption_variance(&pool, VarianceGroupBy::Reagent, "2024-09-01", "2024-12-31").await.unwrap();
+        assert_eq!(by_reagent[0].group_label, "Ethanol");
+        assert_eq!(by_reagent.last().unwrap().group_key, "r1");
+        assert_eq!(by_reagent.last().unwrap().actual_total, 12.4);
+        assert!(by_reagent.iter().all(|r| r.adjustment_total == 0.0));
+
+        // Корректировки остатка входят в строки реагента за период
+        for sql in [
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, received_date, created_at, updated_at) \
+             VALUES ('b1', 'r2', 'ET-1', 800, 1000, 'mL', datetime('now'), datetime('now'), datetime('now'))",
+            "INSERT INTO stock_adjustments (id, reagent_id, batch_id, delta, unit, quantity_before, quantity_after, reason, created_at) VALUES \
+             ('a1', 'r2', 'b1', -0.25, 'L', 1000, 750, 'evaporation', '2024-10-20 09:00:00'), \
+             ('a2', 'r2', 'b1', 50, 'mL', 750, 800, 'recount', '2024-10-21 09:00:00'), \
+             ('a3', 'r2', 'b1', -10, 'mL', 800, 790, 'spillage', '2025-03-01 09:00:00')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        let by_reagent = fetch_consumption_variance(&pool, VarianceGroupBy::Reagent, "2024-09-01", "2024-12-31").await.unwrap();
+        let ethanol_ml = by_reagent.iter().find(|r| r.group_key == "r2" && r.unit == "mL").unwrap();
+        assert_eq!((ethanol_ml.actual_total, ethanol_ml.adjustment_total), (1500.0, -200.0));
+
+        let csv = consumption_variance_csv(&rows, &ExportStyle::default());
+        assert!(csv.contains("CHEM-101,CHEM-101,mol,no,1,0,2,3,1,50,0\n"));
+        let ru = crate::i18n::Locale::Ru;
+        let csv_ru = consumption_variance_csv(&rows, &ExportStyle { locale: Some(ru), excel_locale: Some(ru) });
+        assert!(csv_ru.starts_with("\u{FEFF}Группа;Подпись;Единица;Пересчитывается;"));
+        assert!(csv_ru.contains("CHEM-101;CHEM-101;mol;нет;1;0;2;3;1;50;0\n"));
+        assert!(VarianceGroupBy::parse("instructor").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_report_rows_include_expiry_extension_history() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('qc', 'qc_admin', 'qc@example.com', 'x', 'admin', datetime('now'), datetime('now'))",
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Acetonitrile', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             expiry_date, received_date, status, created_at, updated_at) VALUES \
+             ('b1', 'r1', 'LOT-1', 1, 1, 'mL', '2026-01-31T00:00:00+00:00', datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b2', 'r1', 'LOT-2', 1, 1, 'mL', '2026-01-31T00:00:00+00:00', datetime('now'), 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO batch_expiry_extensions (id, batch_id, reagent_id, old_expiry_date, new_expiry_date, \
+             justification, approved_by, created_at) VALUES \
+             ('x1', 'b1', 'r1', '2024-01-31T00:00:00+00:00', '2025-01-31T00:00:00+00:00', 'Re-test', 'qc', '2024-01-20T00:00:00+00:00'), \
+             ('x2', 'b1', 'r1', '2025-01-31T00:00:00+00:00', '2026-01-31T00:00:00+00:00', 'Second re-test', 'qc', '2025-01-20T00:00:00+00:00')",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let rows: Vec<BatchReportRow> = sqlx::query_as(&format!("{} ORDER BY batch_number", BASE_REPORT_QUERY))
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[0].expiry_extension_history.as_deref(),
+            Some("2024-01-31 -> 2025-01-31 (qc_admin: Re-test); 2025-01-31 -> 2026-01-31 (qc_admin: Second re-test)")
+        );
+        assert_eq!(rows[1].expiry_extension_history, None);
+    }
+
+    #[actix_web::test]
+    async fn test_stocktake_sheet_lists_expected_containers() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Methanol', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, pack_size, \
+             container_count, location, received_date, status, created_at, updated_at) VALUES \
+             ('b1', 'r1', 'LOT-A', 2500, 3000, 'mL', 1000, 3, 'Cabinet B', datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b2', 'r1', 'LOT-B', 500, 500, 'mL', NULL, NULL, 'Cabinet A', datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b3', 'r1', 'LOT-C', 0, 1000, 'mL', 1000, 0, 'Cabinet A', datetime('now'), 'depleted', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let config = build_report_config(&GenerateReportRequest {
+            preset: Some("stocktake".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(config.name, "Stocktake Sheet");
+        let (where_clause, params) = build_filter_sql(&config, &FieldWhitelist::for_reports(), true).unwrap();
+        let sql = format!(
+            "{} WHERE {} ORDER BY {} {}",
+            BASE_REPORT_QUERY, where_clause, config.sort_by.as_deref().unwrap(), config.sort_order
+        );
+        let mut query = sqlx::query_as::<_, BatchReportRow>(&sql);
+        for p in &params {
+            query = query.bind(p);
+        }
+        let rows = query.fetch_all(&pool).await.unwrap();
+
+        let sheet: Vec<_> = rows.iter()
+            .map(|r| (r.batch_number.as_str(), r.container_count, r.open_containers))
+            .collect();
+        // Вскрытая бутыль не считается запечатанной, истраченная партия не попадает в лист
+        assert_eq!(sheet, vec![("LOT-B", None, None), ("LOT-A", Some(2), Some(1))]);
+        assert!(batch_report_csv(&rows, &ExportStyle::default()).contains(",1000,2,1\n"));
+    }
+
+    #[actix_web::test]
+    async fn test_numeric_report_filters_compare_as_numbers() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('r1', 'Acetone', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             expiry_date, received_date, status, created_at, updated_at) VALUES \
+             ('b1', 'r1', 'LOT-SOON', 5, 100, 'mL', datetime('now', '+10 days'), datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b2', 'r1', 'LOT-LATER', 100, 100, 'mL', datetime('now', '+90 days'), datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b3', 'r1', 'LOT-EXPIRED', 10, 100, 'mL', datetime('now', '-5 days'), datetime('now'), 'available', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        let lots = |config: ReportConfig| {
+            let pool = pool.clone();
+            async move {
+                let (where_clause, params) = build_filter_sql(&config, &FieldWhitelist::for_reports(), true).unwrap();
+                let sql = format!("{} WHERE {} ORDER BY batch_number", BASE_REPORT_QUERY, where_clause);
+                let mut query = sqlx::query_as::<_, BatchReportRow>(&sql);
+                for p in &params {
+                    query = query.bind(p);
+                }
+                query.fetch_all(&pool).await.unwrap()
+                    .into_iter().map(|row| row.batch_number).collect::<Vec<_>>()
+            }
+        };
+
+        // days_until_expiry - вычисляемая колонка без affinity: строка '30' сравнивалась бы как текст
+        assert_eq!(lots(ReportConfig::expiring_soon(30)).await, vec!["LOT-SOON"]);
+        assert_eq!(lots(ReportConfig::expired()).await, vec!["LOT-EXPIRED"]);
+        assert_eq!(lots(ReportConfig::low_stock(10.0)).await, vec!["LOT-EXPIRED", "LOT-SOON"]);
+
+        let mut config = ReportConfig::all_batches();
+        for (operator, value) in [("gt", serde_json::json!("6")), ("lt", serde_json::json!(50))] {
+            config.filters.push(ReportFilterRequest {
+                field: "quantity".to_string(),
+                operator: operator.to_string(),
+                value,
+            }.to_report_filter().unwrap());
+        }
+        assert_eq!(lots(config).await, vec!["LOT-EXPIRED"]);
+    }
+
+    #[actix_web::test]
+    async fn test_pending_signoff_report() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('admin', 'admin', 'admin@example.com', 'x', 'admin', datetime('now'), datetime('now'))",
+            "INSERT INTO reagents (id, name, hazard_pictograms, status, created_at, updated_at) VALUES \
+             ('r1', 'Benzene', 'GHS02, GHS08', 'active', datetime('now'), datetime('now')), \
+             ('r2', 'Ethanol', 'GHS02', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, \
+             received_date, status, created_at, updated_at) VALUES \
+             ('b1', 'r1', 'LOT-1', 10, 10, 'mL', datetime('now'), 'available', datetime('now'), datetime('now')), \
+             ('b2', 'r2', 'LOT-2', 10, 10, 'mL', datetime('now'), 'available', datetime('now'), datetime('now'))",
+            "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, created_at, updated_at) VALUES \
+             ('e1', 'Extraction', '2030-02-01 09:00:00', '2030-02-01 09:00:00', 'planned', 'research', datetime('now'), datetime('now')), \
+             ('e2', 'Distillation', '2030-01-15 09:00:00', '2030-01-15 09:00:00', 'planned', 'research', datetime('now'), datetime('now')), \
+             ('e3', 'Fermentation', '2030-01-10 09:00:00', '2030-01-10 09:00:00', 'planned', 'research', datetime('now'), datetime('now'))",
+            "INSERT INTO experiment_reagents (id, experiment_id, reagent_id, batch_id, planned_quantity, unit, created_at, updated_at) VALUES \
+             ('er1', 'e1', 'r1', 'b1', 1, 'mL', datetime('now'), datetime('now')), \
+             ('er2', 'e1', 'r2', 'b2', 1, 'mL', datetime('now'), datetime('now')), \
+             ('er3', 'e2', 'r1', 'b1', 1, 'mL', datetime('now'), datetime('now')), \
+             ('er4', 'e3', 'r2', 'b2', 1, 'mL', datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        // e3 использует только GHS02 (тяжесть 2) - допуск не нужен
+        let rows = fetch_pending_signoffs(&pool, 2).await.unwrap();
+        let ids: Vec<&str> = rows.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["e2", "e1"]);
+        assert_eq!(rows[1].hazardous_reagents.as_deref(), Some("Benzene"));
+
+        sqlx::query(
+            "INSERT INTO experiment_signoffs (id, experiment_id, signed_by, signed_at, checklist) \
+             VALUES ('s1', 'e2', 'admin', datetime('now'), '{\"ppe_checked\": true}')"
+        ).execute(&pool).await.unwrap();
+        let rows = fetch_pending_signoffs(&pool, 2).await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, "e1");
+
+        assert!(fetch_pending_signoffs(&pool, crate::models::MAX_HAZARD_SEVERITY).await.unwrap().is_empty());
+    }
+
+    fn preset_request(value: serde_json::Value) -> SaveReportPresetRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_validate_preset_request() {
+        let valid = preset_request(serde_json::json!({
+            "name": "My stock",
+            "filters": [{ "field": "quantity", "operator": "lt", "value": 5 }],
+            "columns": ["reagent_name", "quantity"],
+            "default_sort": "quantity",
+            "default_sort_order": "asc"
+        }));
+        assert!(validate_preset_request(&valid).is_ok());
+
+        for name in ["low_stock", "expired items", "ALL BATCHES"] {
+            let reserved = preset_request(serde_json::json!({ "name": name }));
+            assert!(validate_preset_request(&reserved).is_err(), "{} must be reserved", name);
+        }
+
+        let bad_field = preset_request(serde_json::json!({
+            "name": "x", "filters": [{ "field": "password_hash", "operator": "eq", "value": "a" }]
+        }));
+        assert!(validate_preset_request(&bad_field).is_err());
+
+        let prefixed = preset_request(serde_json::json!({
+            "name": "x", "filters": [{ "field": "users.quantity", "operator": "eq", "value": 1 }]
+        }));
+        assert!(validate_preset_request(&prefixed).is_err());
+
+        let bad_operator = preset_request(serde_json::json!({
+            "name": "x", "filters": [{ "field": "status", "operator": "regexp", "value": "a" }]
+        }));
+        assert!(validate_preset_request(&bad_operator).is_err());
+
+        let bad_column = preset_request(serde_json::json!({ "name": "x", "columns": ["1; DROP TABLE batches"] }));
+        assert!(validate_preset_request(&bad_column).is_err());
+
+        let bad_order = preset_request(serde_json::json!({ "name": "x", "default_sort_order": "sideways" }));
+        assert!(validate_preset_request(&bad_order).is_err());
+    }
+
+    #[test]
+    fn test_merge_stored_preset() {
+        let preset = StoredPresetRow {
+            id: "p1".to_string(),
+            name: "Cold room".to_string(),
+            description: None,
+            filters: r#"[{"field":"location","operator":"eq","value":"Fridge"}]"#.to_string(),
+            columns: r#"["reagent_name","location"]"#.to_string(),
+            default_sort: Some("expiry_date".to_string()),
+            default_sort_order: Some("ASC".to_string()),
+            owner_id: "u1".to_string(),
+            is_shared: false,
+        }.into_available();
+
+        let mut request = attendance_request(serde_json::json!({}));
+        request.preset = Some("p1".to_string());
+        request.filters = Some(vec![ReportFilterRequest {
+            field: "status".to_string(),
+            operator: "eq".to_string(),
+            value: serde_json::json!("available"),
+        }]);
+        request.sort_order = Some("DESC".to_string());
+        merge_stored_preset(&mut request, &preset);
+
+        let filters = request.filters.as_ref().unwrap();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0].field, "location");
+        assert_eq!(request.sort_by.as_deref(), Some("expiry_date"));
+        assert_eq!(request.sort_order.as_deref(), Some("DESC"));
+
+        let mut config = build_report_config(&request);
+        apply_preset_metadata(&mut config, &preset);
+        assert_eq!(config.name, "Cold room");
+        assert_eq!(config.filters.len(), 2);
+        let columns: Vec<&str> = config.columns.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(columns, vec!["reagent_name", "location"]);
+    }
+
+    #[actix_web::test]
+    async fn test_report_preset_permissions() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        for (id, role) in [("admin", "admin"), ("alice", "researcher"), ("bob", "viewer")] {
+            sqlx::query(
+                "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+                 VALUES (?, ?, ?, 'x', ?, datetime('now'), datetime('now'))"
+            ).bind(id).bind(id).bind(format!("{}@example.com", id)).bind(role).execute(&pool).await.unwrap();
+        }
+
+        let own = preset_request(serde_json::json!({ "name": "Bob's list", "columns": ["reagent_name"] }));
+        let bob_preset = create_preset(&pool, "bob", &UserRole::Viewer, &own).await.unwrap();
+        assert!(!bob_preset.builtin);
+
+        // Viewer не может публиковать общие пресеты
+        let shared = preset_request(serde_json::json!({ "name": "Team stock", "is_shared": true }));
+        assert!(matches!(create_preset(&pool, "bob", &UserRole::Viewer, &shared).await, Err(ApiError::Forbidden(_))));
+        let alice_preset = create_preset(&pool, "alice", &UserRole::Researcher, &shared).await.unwrap();
+
+        // Повтор имени у того же владельца
+        assert!(create_preset(&pool, "alice", &UserRole::Researcher, &shared).await.is_err());
+
+        let alice_sees = list_presets(&pool, "alice").await.unwrap();
+        assert_eq!(alice_sees.iter().filter(|p| p.builtin).count(), BUILTIN_PRESETS.len());
+        let custom: Vec<&str> = alice_sees.iter().filter(|p| !p.builtin).map(|p| p.name.as_str()).collect();
+        assert_eq!(custom, vec!["Team stock"]);
+        assert_eq!(list_presets(&pool, "bob").await.unwrap().iter().filter(|p| !p.builtin).count(), 2);
+
+        // Чужой приватный пресет не виден, общий - виден, но не редактируется
+        assert!(matches!(delete_preset(&pool, &bob_preset.id, "alice", &UserRole::Researcher).await, Err(ApiError::NotFound(_))));
+        let rename = preset_request(serde_json::json!({ "name": "Renamed", "is_shared": true }));
+        assert!(matches!(update_preset(&pool, &alice_preset.id, "bob", &UserRole::Viewer, &rename).await, Err(ApiError::Forbidden(_))));
+
+        // Администратор управляет общими пресетами
+        let updated = update_preset(&pool, &alice_preset.id, "admin", &UserRole::Admin, &rename).await.unwrap();
+        assert_eq!(updated.name, "Renamed");
+        assert_eq!(updated.owner_id.as_deref(), Some("alice"));
+        assert!(matches!(delete_preset(&pool, &bob_preset.id, "admin", &UserRole::Admin).await, Err(ApiError::NotFound(_))));
+
+        assert!(matches!(delete_preset(&pool, "low_stock", "admin", &UserRole::Admin).await, Err(ApiError::Forbidden(_))));
+        delete_preset(&pool, &alice_preset.id, "admin", &UserRole::Admin).await.unwrap();
+        delete_preset(&pool, &bob_preset.id, "bob", &UserRole::Viewer).await.unwrap();
+        assert!(list_presets(&pool, "bob").await.unwrap().iter().all(|p| p.builtin));
+    }
 }
\ No newline at end of file
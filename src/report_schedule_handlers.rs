@@ -0,0 +1,794 @@
+// src/report_schedule_handlers.rs
+//! Рассылка отчётов по расписанию.
+//!
+//! Расписание - день недели (ISO 1=Пн..7=Вс, пусто - ежедневно) и час в часовом поясе
+//! развёртывания (`deployment.timezone`). Отчёт строится тем же путём, что и
+//! POST /reports/export (`report_handlers::render_report`) от имени владельца расписания,
+//! и уходит вложением через SMTP (`smtp`). Каждый запуск пишется в report_schedule_runs;
+//! неудачная попытка повторяется один раз, после второй неудачи владелец получает
+//! уведомление (audit_logs и письмо, если почта доступна).
+//!
+//! Пропущенный запуск (сервер был остановлен) выполняется один раз при старте,
+//! следующий планируется от текущего момента.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+use validator::Validate;
+
+use crate::auth::{get_current_user, UserRole};
+use crate::config::{Config, SmtpConfig};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::mailer::{self, Attachment, Email};
+use crate::i18n::ExportStyle;
+use crate::report_handlers::{render_report, visible_preset_name, ExportFormat, GenerateReportRequest};
+use crate::validator::FieldValidator;
+use crate::AppState;
+
+/// Первая попытка и один повтор
+pub const MAX_ATTEMPTS: i64 = 2;
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+const DEFAULT_RUNS_LIMIT: i64 = 50;
+const MAX_RUNS_LIMIT: i64 = 500;
+
+const SCHEDULE_COLUMNS: &str =
+    "id, name, preset, preset_params, weekday, hour, recipients, format, enabled, owner_id, \
+     next_run_at, last_run_at, created_at, updated_at";
+
+// ==================== СТРУКТУРЫ ====================
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ReportScheduleRow {
+    pub id: String,
+    pub name: String,
+    pub preset: String,
+    pub preset_params: Option<String>,
+    pub weekday: Option<i64>,
+    pub hour: i64,
+    pub recipients: String,
+    pub format: String,
+    pub enabled: bool,
+    pub owner_id: String,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportSchedule {
+    pub id: String,
+    pub name: String,
+    pub preset: String,
+    pub preset_params: Option<serde_json::Value>,
+    /// ISO 1=Пн..7=Вс; None - ежедневно
+    pub weekday: Option<i64>,
+    /// Час в часовом поясе развёртывания
+    pub hour: i64,
+    pub recipients: Vec<String>,
+    pub format: String,
+    pub enabled: bool,
+    pub owner_id: String,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ReportScheduleRow {
+    fn into_schedule(self) -> ReportSchedule {
+        ReportSchedule {
+            preset_params: self.preset_params.as_deref().and_then(|p| serde_json::from_str(p).ok()),
+            recipients: self.recipients(),
+            id: self.id,
+            name: self.name,
+            preset: self.preset,
+            weekday: self.weekday,
+            hour: self.hour,
+            format: self.format,
+            enabled: self.enabled,
+            owner_id: self.owner_id,
+            next_run_at: self.next_run_at,
+            last_run_at: self.last_run_at,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        }
+    }
+
+    fn recipients(&self) -> Vec<String> {
+        serde_json::from_str(&self.recipients).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ReportScheduleRun {
+    pub id: String,
+    pub schedule_id: String,
+    /// success | failed
+    pub status: String,
+    pub attempts: i64,
+    /// Строк в отчёте; None - отчёт не построен
+    pub row_count: Option<i64>,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// Создание/обновление расписания
+#[derive(Debug, Deserialize, Validate)]
+pub struct SaveReportScheduleRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    /// Встроенный пресет или id сохранённого пресета
+    #[validate(length(min = 1, message = "Preset is required"))]
+    pub preset: String,
+    pub preset_params: Option<serde_json::Map<String, serde_json::Value>>,
+    #[validate(range(min = 1, max = 7, message = "weekday must be between 1 (Monday) and 7 (Sunday)"))]
+    pub weekday: Option<i64>,
+    #[validate(range(min = 0, max = 23, message = "hour must be between 0 and 23"))]
+    pub hour: i64,
+    #[validate(length(min = 1, max = 20, message = "Between 1 and 20 recipients are required"))]
+    pub recipients: Vec<String>,
+    #[serde(default = "default_format")]
+    pub format: ExportFormat,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_format() -> ExportFormat {
+    ExportFormat::Csv
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleRunsQuery {
+    pub limit: Option<i64>,
+}
+
+// ==================== РАСПИСАНИЕ ====================
+
+/// Ближайший запуск строго после `after`: день недели (ISO) и час в часовом поясе `tz`
+pub fn next_run_after(after: DateTime<Utc>, weekday: Option<u32>, hour: u32, tz: Tz) -> DateTime<Utc> {
+    let local_date = after.with_timezone(&tz).date_naive();
+    for offset in 0..=8 {
+        let date = local_date + chrono::Duration::days(offset);
+        if weekday.is_some_and(|w| date.weekday().number_from_monday() != w) {
+            continue;
+        }
+        let Some(naive) = date.and_hms_opt(hour.min(23), 0, 0) else { continue };
+        // Час, пропущенный при переходе на летнее время, сдвигается на час позже
+        let candidate = tz
+            .from_local_datetime(&naive)
+            .earliest()
+            .or_else(|| tz.from_local_datetime(&(naive + chrono::Duration::hours(1))).earliest());
+        if let Some(candidate) = candidate.map(|c| c.with_timezone(&Utc)) {
+            if candidate > after {
+                return candidate;
+            }
+        }
+    }
+    after + chrono::Duration::days(7)
+}
+
+fn next_run_for(row: &ReportScheduleRow, after: DateTime<Utc>, tz: Tz) -> DateTime<Utc> {
+    next_run_after(after, row.weekday.map(|w| w as u32), row.hour as u32, tz)
+}
+
+// ==================== CRUD ====================
+
+async fn validate_schedule_request(
+    pool: &SqlitePool,
+    request: &SaveReportScheduleRequest,
+    owner_id: &str,
+) -> ApiResult<()> {
+    request.validate()?;
+    if request.name.trim().is_empty() {
+        return Err(ApiError::bad_request("Schedule name cannot be empty"));
+    }
+    for recipient in &request.recipients {
+        FieldValidator::email(recipient.trim())
+            .map_err(|_| ApiError::BadRequest(format!("Invalid recipient email '{}'", recipient)))?;
+    }
+    // Пресет должен быть доступен владельцу: от его имени строится отчёт
+    visible_preset_name(pool, &request.preset, owner_id).await.map_err(|e| match e {
+        ApiError::NotFound(_) => ApiError::BadRequest(format!("Report preset '{}' not found", request.preset)),
+        other => other,
+    })?;
+    Ok(())
+}
+
+fn recipients_json(request: &SaveReportScheduleRequest) -> ApiResult<String> {
+    let recipients: Vec<&str> = request.recipients.iter().map(|r| r.trim()).collect();
+    serde_json::to_string(&recipients).map_err(|e| ApiError::internal_error(e.to_string()))
+}
+
+fn preset_params_json(request: &SaveReportScheduleRequest) -> ApiResult<Option<String>> {
+    request.preset_params.as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| ApiError::internal_error(e.to_string()))
+}
+
+async fn fetch_schedule(pool: &SqlitePool, id: &str) -> ApiResult<ReportScheduleRow> {
+    let sql = format!("SELECT {} FROM report_schedules WHERE id = ?", SCHEDULE_COLUMNS);
+    sqlx::query_as::<_, ReportScheduleRow>(&sql)
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Report schedule"))
+}
+
+/// Владелец или администратор; чужие расписания для остальных не существуют
+async fn fetch_manageable_schedule(
+    pool: &SqlitePool,
+    id: &str,
+    user_id: &str,
+    role: &UserRole,
+) -> ApiResult<ReportScheduleRow> {
+    let schedule = fetch_schedule(pool, id).await?;
+    if schedule.owner_id != user_id && *role != UserRole::Admin {
+        return Err(ApiError::not_found("Report schedule"));
+    }
+    Ok(schedule)
+}
+
+async fn list_schedules(pool: &SqlitePool, user_id: &str, role: &UserRole) -> ApiResult<Vec<ReportSchedule>> {
+    let sql = format!(
+        "SELECT {} FROM report_schedules WHERE owner_id = ? OR ? ORDER BY name COLLATE NOCASE",
+        SCHEDULE_COLUMNS
+    );
+    let rows = sqlx::query_as::<_, ReportScheduleRow>(&sql)
+        .bind(user_id)
+        .bind(*role == UserRole::Admin)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().map(ReportScheduleRow::into_schedule).collect())
+}
+
+async fn create_schedule(
+    pool: &SqlitePool,
+    user_id: &str,
+    tz: Tz,
+    request: &SaveReportScheduleRequest,
+) -> ApiResult<ReportSchedule> {
+    validate_schedule_request(pool, request, user_id).await?;
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let next_run_at = next_run_after(now, request.weekday.map(|w| w as u32), request.hour as u32, tz);
+    sqlx::query(r#"
+        INSERT INTO report_schedules
+            (id, name, preset, preset_params, weekday, hour, recipients, format, enabled, owner_id,
+             next_run_at, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+    "#)
+        .bind(&id)
+        .bind(request.name.trim())
+        .bind(&request.preset)
+        .bind(preset_params_json(request)?)
+        .bind(request.weekday)
+        .bind(request.hour)
+        .bind(recipients_json(request)?)
+        .bind(request.format.as_str())
+        .bind(request.enabled)
+        .bind(user_id)
+        .bind(next_run_at)
+        .bind(now)
+        .bind(now)
+        .execute(pool)
+        .await?;
+
+    Ok(fetch_schedule(pool, &id).await?.into_schedule())
+}
+
+async fn update_schedule(
+    pool: &SqlitePool,
+    id: &str,
+    user_id: &str,
+    role: &UserRole,
+    tz: Tz,
+    request: &SaveReportScheduleRequest,
+) -> ApiResult<ReportSchedule> {
+    let existing = fetch_manageable_schedule(pool, id, user_id, role).await?;
+    validate_schedule_request(pool, request, &existing.owner_id).await?;
+
+    let now = Utc::now();
+    let next_run_at = next_run_after(now, request.weekday.map(|w| w as u32), request.hour as u32, tz);
+    sqlx::query(r#"
+        UPDATE report_schedules
+        SET name = ?, preset = ?, preset_params = ?, weekday = ?, hour = ?, recipients = ?,
+            format = ?, enabled = ?, next_run_at = ?, updated_at = ?
+        WHERE id = ?
+    "#)
+        .bind(request.name.trim())
+        .bind(&request.preset)
+        .bind(preset_params_json(request)?)
+        .bind(request.weekday)
+        .bind(request.hour)
+        .bind(recipients_json(request)?)
+        .bind(request.format.as_str())
+        .bind(request.enabled)
+        .bind(next_run_at)
+        .bind(now)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(fetch_schedule(pool, id).await?.into_schedule())
+}
+
+async fn list_runs(pool: &SqlitePool, schedule_id: &str, limit: i64) -> ApiResult<Vec<ReportScheduleRun>> {
+    Ok(sqlx::query_as(r#"
+        SELECT id, schedule_id, status, attempts, row_count, error, started_at, finished_at
+        FROM report_schedule_runs
+        WHERE schedule_id = ?
+        ORDER BY started_at DESC
+        LIMIT ?
+    "#)
+        .bind(schedule_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?)
+}
+
+// ==================== ЗАПУСК ====================
+
+/// Всё, что нужно фоновой задаче для построения и отправки отчётов
+#[derive(Debug, Clone)]
+pub struct ReportScheduler {
+    pub smtp: SmtpConfig,
+    pub timezone: Tz,
+    pub signoff_threshold: u8,
+    pub retry_delay: Duration,
+    /// Read-only пул для построения отчётов; None - основной пул
+    pub read_pool: Option<SqlitePool>,
+}
+
+impl ReportScheduler {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            smtp: config.smtp.clone(),
+            timezone: config.deployment.tz(),
+            signoff_threshold: config.safety.signoff_hazard_threshold,
+            retry_delay: RETRY_DELAY,
+            read_pool: None,
+        }
+    }
+}
+
+/// Одна попытка: построить отчёт и отправить; число строк - если отчёт построен
+async fn attempt_delivery(
+    pool: &SqlitePool,
+    scheduler: &ReportScheduler,
+    schedule: &ReportScheduleRow,
+) -> (Option<usize>, Result<(), String>) {
+    let format = ExportFormat::parse(&schedule.format).unwrap_or(ExportFormat::Csv);
+    let request = GenerateReportRequest {
+        preset: Some(schedule.preset.clone()),
+        preset_params: schedule.preset_params.as_deref().and_then(|p| serde_json::from_str(p).ok()),
+        ..Default::default()
+    };
+    let report_pool = crate::db::select_read_pool(pool, scheduler.read_pool.as_ref());
+    let file = match render_report(report_pool, request, &schedule.owner_id, scheduler.signoff_threshold, format, &ExportStyle::default()).await {
+        Ok(file) => file,
+        Err(e) => return (None, Err(format!("Report generation failed: {}", e))),
+    };
+    let row_count = file.row_count;
+
+    let local_now = Utc::now().with_timezone(&scheduler.timezone);
+    let email = Email {
+        to: schedule.recipients(),
+        subject: format!("{} - {}", schedule.name, local_now.format("%Y-%m-%d")),
+        body: format!(
+            "Scheduled report \"{}\" generated at {} ({}): {} row(s).\r\nThe report is attached as {}.\r\n",
+            schedule.name,
+            local_now.format("%Y-%m-%d %H:%M"),
+            scheduler.timezone.name(),
+            row_count,
+            file.filename,
+        ),
+        attachments: vec![Attachment {
+            filename: file.filename,
+            content_type: file.content_type.to_string(),
+            data: file.body,
+        }],
+    };
+    (Some(row_count), mailer::send(&scheduler.smtp, email).await)
+}
+
+/// Запуск с одним повтором; результат пишется в журнал запусков
+pub async fn run_schedule(
+    pool: &SqlitePool,
+    scheduler: &ReportScheduler,
+    schedule: &ReportScheduleRow,
+) -> Result<ReportScheduleRun, sqlx::Error> {
+    let started_at = Utc::now();
+    let mut attempts = 0;
+    let (row_count, outcome) = loop {
+        attempts += 1;
+        let (row_count, outcome) = attempt_delivery(pool, scheduler, schedule).await;
+        match outcome {
+            Err(ref e) if attempts < MAX_ATTEMPTS => {
+                log::warn!("Scheduled report '{}' attempt {} failed, retrying: {}", schedule.name, attempts, e);
+                tokio::time::sleep(scheduler.retry_delay).await;
+            }
+            _ => break (row_count, outcome),
+        }
+    };
+
+    let run = ReportScheduleRun {
+        id: uuid::Uuid::new_v4().to_string(),
+        schedule_id: schedule.id.clone(),
+        status: if outcome.is_ok() { "success" } else { "failed" }.to_string(),
+        attempts,
+        row_count: row_count.map(|c| c as i64),
+        error: outcome.err(),
+        started_at,
+        finished_at: Utc::now(),
+    };
+    sqlx::query(r#"
+        INSERT INTO report_schedule_runs (id, schedule_id, status, attempts, row_count, error, started_at, finished_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+    "#)
+        .bind(&run.id)
+        .bind(&run.schedule_id)
+        .bind(&run.status)
+        .bind(run.attempts)
+        .bind(run.row_count)
+        .bind(&run.error)
+        .bind(run.started_at)
+        .bind(run.finished_at)
+        .execute(pool)
+        .await?;
+    sqlx::query("UPDATE report_schedules SET last_run_at = ? WHERE id = ?")
+        .bind(started_at)
+        .bind(&schedule.id)
+        .execute(pool)
+        .await?;
+
+    if let Some(ref error) = run.error {
+        notify_owner(pool, scheduler, schedule, error).await;
+    }
+    Ok(run)
+}
+
+/// Уведомление владельца о неудачной рассылке: запись в audit_logs и письмо
+async fn notify_owner(pool: &SqlitePool, scheduler: &ReportScheduler, schedule: &ReportScheduleRow, error: &str) {
+    let description = format!(
+        "Scheduled report '{}' failed after {} attempts: {}",
+        schedule.name, MAX_ATTEMPTS, error
+    );
+    log::error!("{}", description);
+    // Напрямую, без log_activity: Option<&HttpRequest> делает future не-Send для фоновой задачи
+    let logged = sqlx::query(
+        r#"INSERT INTO audit_logs (id, user_id, action, entity_type, entity_id, description, created_at)
+           VALUES (?, ?, 'report_schedule_failed', 'report_schedule', ?, ?, ?)"#
+    )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&schedule.owner_id)
+        .bind(&schedule.id)
+        .bind(&description)
+        .bind(Utc::now())
+        .execute(pool)
+        .await;
+    if let Err(e) = logged {
+        log::warn!("Failed to log report schedule failure: {}", e);
+    }
+
+    if !scheduler.smtp.is_enabled() {
+        return;
+    }
+    let owner_email: Option<String> = sqlx::query_scalar("SELECT email FROM users WHERE id = ?")
+        .bind(&schedule.owner_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    if let Some(to) = owner_email {
+        let email = Email {
+            to: vec![to],
+            subject: format!("Scheduled report failed: {}", schedule.name),
+            body: format!("{}\r\n", description),
+            attachments: Vec::new(),
+        };
+        if let Err(e) = mailer::send(&scheduler.smtp, email).await {
+            log::warn!("Failed to notify owner of report schedule '{}': {}", schedule.name, e);
+        }
+    }
+}
+
+/// Запуск наступивших расписаний; следующий запуск планируется до отправки,
+/// чтобы сбой посреди рассылки не приводил к повторной отправке
+pub async fn run_due_schedules(pool: &SqlitePool, scheduler: &ReportScheduler) -> Result<usize, sqlx::Error> {
+    let now = Utc::now();
+    let sql = format!(
+        "SELECT {} FROM report_schedules WHERE enabled = 1 AND next_run_at IS NOT NULL AND next_run_at <= ? \
+         ORDER BY next_run_at",
+        SCHEDULE_COLUMNS
+    );
+    let due = sqlx::query_as::<_, ReportScheduleRow>(&sql)
+        .bind(now)
+        .fetch_all(pool)
+        .await?;
+
+    for schedule in &due {
+        sqlx::query("UPDATE report_schedules SET next_run_at = ? WHERE id = ?")
+            .bind(next_run_for(schedule, now, scheduler.timezone))
+            .bind(&schedule.id)
+            .execute(pool)
+            .await?;
+        let run = run_schedule(pool, scheduler, schedule).await?;
+        log::info!(
+            "Scheduled report '{}': {} ({} row(s), {} attempt(s))",
+            schedule.name, run.status, run.row_count.unwrap_or(0), run.attempts
+        );
+    }
+    Ok(due.len())
+}
+
+// ==================== HANDLERS ====================
+
+pub async fn get_report_schedules(
+    app_state: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let schedules = list_schedules(&app_state.db_pool, &user.sub, &user.role).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(schedules)))
+}
+
+pub async fn get_report_schedule(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let schedule = fetch_manageable_schedule(&app_state.db_pool, &path, &user.sub, &user.role).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(schedule.into_schedule())))
+}
+
+pub async fn create_report_schedule(
+    app_state: web::Data<Arc<AppState>>,
+    request: web::Json<SaveReportScheduleRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let tz = app_state.config.deployment.tz();
+    let schedule = create_schedule(&app_state.db_pool, &user.sub, tz, &request).await?;
+
+    crate::audit::audit(
+        &app_state.db_pool, &user.sub, "create", "report_schedule", &schedule.id,
+        &format!("Created report schedule '{}'", schedule.name), &http_request,
+    ).await;
+
+    Ok(HttpResponse::Created().json(ApiResponse::success(schedule)))
+}
+
+pub async fn update_report_schedule(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    request: web::Json<SaveReportScheduleRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let tz = app_state.config.deployment.tz();
+    let schedule = update_schedule(&app_state.db_pool, &path, &user.sub, &user.role, tz, &request).await?;
+
+    crate::audit::audit(
+        &app_state.db_pool, &user.sub, "update", "report_schedule", &schedule.id,
+        &format!("Updated report schedule '{}'", schedule.name), &http_request,
+    ).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(schedule)))
+}
+
+pub async fn delete_report_schedule(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let schedule = fetch_manageable_schedule(&app_state.db_pool, &path, &user.sub, &user.role).await?;
+    sqlx::query("DELETE FROM report_schedules WHERE id = ?")
+        .bind(&schedule.id)
+        .execute(&app_state.db_pool)
+        .await?;
+
+    crate::audit::audit(
+        &app_state.db_pool, &user.sub, "delete", "report_schedule", &schedule.id,
+        &format!("Deleted report schedule '{}'", schedule.name), &http_request,
+    ).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::<()>::success_with_message(
+        (), "Report schedule deleted successfully".to_string(),
+    )))
+}
+
+/// Журнал запусков расписания, новые первыми
+pub async fn get_report_schedule_runs(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<ScheduleRunsQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user = get_current_user(&http_request)?;
+    let schedule = fetch_manageable_schedule(&app_state.db_pool, &path, &user.sub, &user.role).await?;
+    let limit = query.limit.unwrap_or(DEFAULT_RUNS_LIMIT).clamp(1, MAX_RUNS_LIMIT);
+    let runs = list_runs(&app_state.db_pool, &schedule.id, limit).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(runs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SmtpSecurity;
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in [
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at)
+             VALUES ('u-head', 'head', 'head@lab.example', 'x', 'researcher', datetime('now'), datetime('now')),
+                    ('u-other', 'other', 'other@lab.example', 'x', 'researcher', datetime('now'), datetime('now'))",
+            "INSERT INTO report_presets (id, name, owner_id, is_shared, created_at, updated_at)
+             VALUES ('p-private', 'Mine', 'u-other', 0, datetime('now'), datetime('now'))",
+            "INSERT INTO reagents (id, name, status, created_at, updated_at)
+             VALUES ('r-1', 'Acetone', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, status,
+                                  expiry_date, received_date, created_at, updated_at)
+             VALUES ('b-1', 'r-1', 'LOT-1', 5, 10, 'ml', 'available', datetime('now', '+5 days'),
+                     datetime('now'), datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        pool
+    }
+
+    fn schedule_request(value: serde_json::Value) -> SaveReportScheduleRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn scheduler(smtp: SmtpConfig) -> ReportScheduler {
+        ReportScheduler {
+            smtp,
+            timezone: chrono_tz::Europe::Moscow,
+            signoff_threshold: 2,
+            retry_delay: Duration::ZERO,
+            read_pool: None,
+        }
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_next_run_in_deployment_timezone() {
+        let moscow = chrono_tz::Europe::Moscow;
+        // Понедельник 19.10.2026, 07:00 по Москве -> 08:00 того же дня
+        assert_eq!(next_run_after(utc("2026-10-19T04:00:00Z"), Some(1), 8, moscow), utc("2026-10-19T05:00:00Z"));
+        // Ровно в момент запуска - следующая неделя
+        assert_eq!(next_run_after(utc("2026-10-19T05:00:00Z"), Some(1), 8, moscow), utc("2026-10-26T05:00:00Z"));
+        // Ежедневно
+        assert_eq!(next_run_after(utc("2026-10-19T05:00:00Z"), None, 8, moscow), utc("2026-10-20T05:00:00Z"));
+
+        // 02:00 8 марта 2026 в Нью-Йорке не существует (переход на летнее время) -> 03:00 EDT
+        let new_york = chrono_tz::America::New_York;
+        assert_eq!(next_run_after(utc("2026-03-08T00:00:00Z"), None, 2, new_york), utc("2026-03-08T07:00:00Z"));
+    }
+
+    #[actix_web::test]
+    async fn test_schedule_validation_and_ownership() {
+        let pool = setup().await;
+        let tz = chrono_tz::UTC;
+        let valid = json!({ "name": "Weekly expiring", "preset": "expiring_soon", "preset_params": { "days": 30 },
+                            "weekday": 1, "hour": 8, "recipients": ["head@lab.example"] });
+
+        let schedule = create_schedule(&pool, "u-head", tz, &schedule_request(valid.clone())).await.unwrap();
+        assert_eq!(schedule.format, "csv");
+        assert_eq!(schedule.recipients, vec!["head@lab.example"]);
+        assert!(schedule.next_run_at.unwrap() > Utc::now());
+
+        let mut bad_recipient = valid.clone();
+        bad_recipient["recipients"] = json!(["not-an-email"]);
+        assert!(create_schedule(&pool, "u-head", tz, &schedule_request(bad_recipient)).await.is_err());
+        let mut bad_weekday = valid.clone();
+        bad_weekday["weekday"] = json!(8);
+        assert!(create_schedule(&pool, "u-head", tz, &schedule_request(bad_weekday)).await.is_err());
+        // Чужой неопубликованный пресет владельцу расписания недоступен
+        let mut foreign_preset = valid.clone();
+        foreign_preset["preset"] = json!("p-private");
+        let err = create_schedule(&pool, "u-head", tz, &schedule_request(foreign_preset)).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)), "{:?}", err);
+
+        let err = fetch_manageable_schedule(&pool, &schedule.id, "u-other", &UserRole::Researcher).await.unwrap_err();
+        assert!(matches!(err, ApiError::NotFound(_)));
+        assert!(fetch_manageable_schedule(&pool, &schedule.id, "u-other", &UserRole::Admin).await.is_ok());
+        assert!(list_schedules(&pool, "u-other", &UserRole::Researcher).await.unwrap().is_empty());
+
+        let mut disabled = valid;
+        disabled["enabled"] = json!(false);
+        disabled["format"] = json!("json");
+        let updated = update_schedule(&pool, &schedule.id, "u-head", &UserRole::Researcher, tz, &schedule_request(disabled))
+            .await.unwrap();
+        assert!(!updated.enabled);
+        assert_eq!(updated.format, "json");
+    }
+
+    #[actix_web::test]
+    async fn test_due_schedule_emails_report_and_logs_run() {
+        let pool = setup().await;
+        let request = schedule_request(json!({ "name": "Expiring stock", "preset": "expiring_soon",
+            "preset_params": { "days": 30 }, "hour": 8, "recipients": ["head@lab.example", "qa@lab.example"] }));
+        let schedule = create_schedule(&pool, "u-head", chrono_tz::UTC, &request).await.unwrap();
+        sqlx::query("UPDATE report_schedules SET next_run_at = datetime('now', '-1 minute') WHERE id = ?")
+            .bind(&schedule.id).execute(&pool).await.unwrap();
+
+        let (port, transcript) = mailer::spawn_test_server();
+        let smtp = SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "lims@lab.example".to_string(),
+            timeout_seconds: 5,
+        };
+        assert_eq!(run_due_schedules(&pool, &scheduler(smtp.clone())).await.unwrap(), 1);
+
+        let transcript = transcript.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(transcript.contains("RCPT TO:<qa@lab.example>"));
+        assert!(transcript.contains("filename=\"report_expiring_soon_"));
+
+        let runs = list_runs(&pool, &schedule.id, 10).await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].status, "success");
+        assert_eq!(runs[0].attempts, 1);
+        assert_eq!(runs[0].row_count, Some(1));
+
+        // Следующий запуск уже запланирован - повторно не отправляется
+        let row = fetch_schedule(&pool, &schedule.id).await.unwrap();
+        assert!(row.next_run_at.unwrap() > Utc::now());
+        assert!(row.last_run_at.is_some());
+        assert_eq!(run_due_schedules(&pool, &scheduler(smtp)).await.unwrap(), 0);
+    }
+
+    #[actix_web::test]
+    async fn test_failed_run_retries_once_then_notifies_owner() {
+        let pool = setup().await;
+        let request = schedule_request(json!({ "name": "Expiring stock", "preset": "expiring_soon",
+            "weekday": 1, "hour": 8, "recipients": ["head@lab.example"] }));
+        let schedule = create_schedule(&pool, "u-head", chrono_tz::UTC, &request).await.unwrap();
+        let row = fetch_schedule(&pool, &schedule.id).await.unwrap();
+
+        // SMTP не настроен - обе попытки неудачны
+        let run = run_schedule(&pool, &scheduler(SmtpConfig::default()), &row).await.unwrap();
+        assert_eq!(run.status, "failed");
+        assert_eq!(run.attempts, MAX_ATTEMPTS);
+        assert_eq!(run.row_count, Some(1));
+        assert!(run.error.as_deref().unwrap().contains("SMTP"));
+
+        let notified: (String, String) = sqlx::query_as(
+            "SELECT user_id, description FROM audit_logs WHERE action = 'report_schedule_failed' AND entity_id = ?"
+        )
+            .bind(&schedule.id)
+            .fetch_one(&pool).await.unwrap();
+        assert_eq!(notified.0, "u-head");
+        assert!(notified.1.contains("Expiring stock"));
+        assert_eq!(list_runs(&pool, &schedule.id, 10).await.unwrap()[0].status, "failed");
+    }
+}
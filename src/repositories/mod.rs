@@ -6,6 +6,7 @@ use sqlx::SqlitePool;
 use serde::{Serialize, de::DeserializeOwned};
 use crate::error::{ApiError, ApiResult};
 use crate::handlers::{PaginatedResponse, PaginationQuery};
+use crate::pagination::PageLimits;
 
 /// Базовый trait для CRUD операций
 #[async_trait]
@@ -80,10 +81,11 @@ where
         &self,
         pool: &SqlitePool,
         query: &PaginationQuery,
+        limits: PageLimits,
     ) -> ApiResult<PaginatedResponse<T>> {
-        use crate::query_builders::{SafeQueryBuilder, CountQueryBuilder};
+        use crate::query_builders::{SafeQueryBuilder, CountQueryBuilder, SqlParam};
 
-        let (page, per_page, offset) = query.normalize();
+        let (page, per_page, offset) = query.normalize(limits);
         let search_fields = self.search_fields();
 
         // === COUNT QUERY ===
@@ -98,9 +100,9 @@ where
                     .map(|f| format!("{} LIKE ?", f))
                     .collect();
                 let search_pattern = format!("%{}%", search);
-                let params: Vec<String> = search_fields
+                let params: Vec<SqlParam> = search_fields
                     .iter()
-                    .map(|_| search_pattern.clone())
+                    .map(|_| SqlParam::Text(search_pattern.clone()))
                     .collect();
                 count_builder.add_condition(
                     &format!("({})", like_conditions.join(" OR ")),
@@ -135,9 +137,9 @@ where
                     .map(|f| format!("{} LIKE ?", f))
                     .collect();
                 let search_pattern = format!("%{}%", search);
-                let params: Vec<String> = search_fields
+                let params: Vec<SqlParam> = search_fields
                     .iter()
-                    .map(|_| search_pattern.clone())
+                    .map(|_| SqlParam::Text(search_pattern.clone()))
                     .collect();
                 data_builder.add_condition(
                     &format!("({})", like_conditions.join(" OR ")),
@@ -173,6 +175,8 @@ where
             page,
             per_page,
             total_pages,
+            limits,
+            capped: limits.is_capped(query.per_page),
         })
     }
 }
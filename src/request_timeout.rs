@@ -0,0 +1,224 @@
+// src/request_timeout.rs
+//! Таймауты обработки запросов защищённого API.
+//!
+//! Каждый маршрут /api/v1 выполняется не дольше своего таймаута (`[timeouts]`): по умолчанию
+//! 30 с, для маршрутов экспорта - 120 с, отдельные маршруты переопределяются ключом вида
+//! "POST /reports/generate" (шаблон пути из таблицы доступа). Таймаут - `tokio::time::timeout`
+//! вокруг остальной цепочки; по истечении будущее обработчика сбрасывается (drop), вместе с ним
+//! отменяются ожидающие запросы sqlx и транзакции откатываются. Так же actix сбрасывает будущее,
+//! когда клиент разорвал соединение. Уже начатый шаг SQLite при этом дорабатывает до конца -
+//! отменить его изнутри sqlx не позволяет.
+//!
+//! Ответ по таймауту - 504 с request id (в теле и в X-Request-Id), счётчики по маршрутам - в
+//! /metrics (`lims_http_request_timeouts_total`), чтобы найти медленные запросы.
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::error::InternalError;
+use actix_web::http::Method;
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::access_control::{find_rule, API_PREFIX};
+use crate::config::RequestTimeoutConfig;
+use crate::AppState;
+
+/// Код ошибки в теле ответа 504
+pub const REQUEST_TIMEOUT: &str = "REQUEST_TIMEOUT";
+
+/// Ключ для маршрутов, которых нет в таблице доступа
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+lazy_static::lazy_static! {
+    static ref TIMEOUTS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+}
+
+/// Число таймаутов по маршрутам ("POST /reports/generate" -> count) с момента старта
+pub fn timeout_stats() -> BTreeMap<String, u64> {
+    TIMEOUTS.lock().map(|stats| stats.clone()).unwrap_or_default()
+}
+
+fn record_timeout(route: &str) {
+    if let Ok(mut stats) = TIMEOUTS.lock() {
+        *stats.entry(route.to_string()).or_insert(0) += 1;
+    }
+}
+
+/// Ключ маршрута для настроек и метрик: метод и шаблон пути без /api/v1
+pub fn route_key(method: &Method, path: &str) -> String {
+    let relative = path.strip_prefix(API_PREFIX).unwrap_or(path);
+    match find_rule(method, relative) {
+        Some(rule) => format!("{} {}", method, rule.path),
+        None => UNMATCHED_ROUTE.to_string(),
+    }
+}
+
+/// Таймаут маршрута: явное переопределение, затем экспорт, затем значение по умолчанию
+pub fn timeout_for(config: &RequestTimeoutConfig, route: &str) -> Duration {
+    let secs = config.routes.get(route).copied().unwrap_or_else(|| {
+        if is_export_route(route) { config.export_secs } else { config.default_secs }
+    });
+    Duration::from_secs(secs)
+}
+
+fn is_export_route(route: &str) -> bool {
+    route.split_once(' ').is_some_and(|(_, path)| path.contains("/export"))
+}
+
+#[derive(Serialize)]
+struct TimeoutResponse {
+    success: bool,
+    message: String,
+    code: &'static str,
+    request_id: Option<String>,
+}
+
+fn timeout_response(route: &str, timeout: Duration, request_id: Option<String>) -> HttpResponse {
+    let mut response = HttpResponse::GatewayTimeout();
+    if let Some(value) = request_id.as_deref().and_then(|id| HeaderValue::from_str(id).ok()) {
+        response.insert_header((HeaderName::from_static(crate::events::REQUEST_ID_HEADER), value));
+    }
+    response.json(TimeoutResponse {
+        success: false,
+        message: format!("Request to {} did not complete within {} s", route, timeout.as_secs_f64()),
+        code: REQUEST_TIMEOUT,
+        request_id,
+    })
+}
+
+// ==================== MIDDLEWARE ====================
+
+/// Middleware для scope /api/v1; настройки берутся из AppState, без него - значения по умолчанию
+pub struct RequestTimeout;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTimeout
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = RequestTimeoutMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTimeoutMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RequestTimeoutMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTimeoutMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let route = route_key(req.method(), req.path());
+        let timeout = match req.app_data::<web::Data<Arc<AppState>>>() {
+            Some(app_state) => timeout_for(&app_state.config.timeouts, &route),
+            None => timeout_for(&RequestTimeoutConfig::default(), &route),
+        };
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, fut).await {
+                Ok(res) => res,
+                Err(_) => {
+                    let request_id = crate::events::current_request_id();
+                    log::warn!(
+                        "Request {} timed out after {:?} (request id {})",
+                        route, timeout, request_id.as_deref().unwrap_or("-")
+                    );
+                    record_timeout(&route);
+                    // Ответ 504 уже собран; InternalError лишь доносит его до клиента
+                    let response = timeout_response(&route, timeout, request_id);
+                    Err(InternalError::from_response(REQUEST_TIMEOUT, response).into())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, App};
+
+    #[test]
+    fn test_route_timeouts_resolve_overrides_exports_and_default() {
+        let mut config = RequestTimeoutConfig::default();
+        config.routes.insert("POST /reports/generate".to_string(), 300);
+
+        let export = route_key(&Method::GET, "/api/v1/reagents/export");
+        assert_eq!(export, "GET /reagents/export");
+        assert_eq!(timeout_for(&config, &export), Duration::from_secs(120));
+
+        let report = route_key(&Method::POST, "/api/v1/reports/generate");
+        assert_eq!(timeout_for(&config, &report), Duration::from_secs(300));
+
+        let reagent = route_key(&Method::GET, "/api/v1/reagents/r1");
+        assert_eq!(reagent, "GET /reagents/{id}");
+        assert_eq!(timeout_for(&config, &reagent), Duration::from_secs(30));
+
+        assert_eq!(route_key(&Method::GET, "/api/v1/not-in-table"), UNMATCHED_ROUTE);
+    }
+
+    #[actix_web::test]
+    async fn test_slow_request_returns_504_with_request_id() {
+        let mut config = crate::config::Config::default();
+        config.timeouts.routes.insert("GET /reagents/{id}".to_string(), 1);
+        let test_app = crate::test_support::TestApp::with_config(config).await;
+
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(test_app.state())
+                .service(
+                    web::scope(API_PREFIX)
+                        .wrap(RequestTimeout)
+                        .route("/reagents/{id}", web::get().to(|| async {
+                            tokio::time::sleep(Duration::from_secs(5)).await;
+                            HttpResponse::Ok().finish()
+                        }))
+                        .route("/reagents", web::get().to(HttpResponse::Ok)),
+                ),
+        )
+        .await;
+
+        let before = timeout_stats().get("GET /reagents/{id}").copied().unwrap_or(0);
+        let req = actix_test::TestRequest::get().uri("/api/v1/reagents/r1").to_request();
+        // Таймаут приходит как Err — превращаем его в ответ, как это сделает сервер
+        let err = crate::events::with_request_id("req-timeout-1".to_string(), actix_test::try_call_service(&app, req))
+            .await
+            .err()
+            .unwrap();
+        let resp = err.error_response();
+        assert_eq!(resp.status(), 504);
+        assert_eq!(resp.headers().get(crate::events::REQUEST_ID_HEADER).unwrap(), "req-timeout-1");
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], REQUEST_TIMEOUT);
+        assert_eq!(body["request_id"], "req-timeout-1");
+        assert_eq!(timeout_stats()["GET /reagents/{id}"], before + 1);
+
+        let req = actix_test::TestRequest::get().uri("/api/v1/reagents").to_request();
+        assert_eq!(actix_test::call_service(&app, req).await.status(), 200);
+    }
+}
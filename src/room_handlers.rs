@@ -4,10 +4,15 @@
 use actix_web::{web, HttpResponse};
 use std::sync::Arc;
 use crate::AppState;
-use crate::models::{Room, CreateRoomRequest, UpdateRoomRequest, RoomStatus};
+use crate::models::{Room, CreateRoomRequest, UpdateRoomRequest, RoomStatus, WeeklyHours, parse_open_days, parse_weekly_hours};
 use crate::error::{ApiError, ApiResult};
 use crate::handlers::ApiResponse;
-use chrono::Utc;
+use crate::reference_cache::{reference_response, ReferenceDataset};
+use crate::report_handlers::escape_csv_field;
+use crate::validator::ValidationResult;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
 use uuid::Uuid;
 use validator::Validate;
 use log::info;
@@ -17,13 +22,16 @@ use log::info;
 pub async fn get_all_rooms(
     app_state: web::Data<Arc<AppState>>,
 ) -> ApiResult<HttpResponse> {
-    let rooms: Vec<Room> = sqlx::query_as(
-        "SELECT * FROM rooms ORDER BY name ASC"
-    )
-    .fetch_all(&app_state.db_pool)
-    .await?;
+    let rooms = app_state.reference_cache.rooms(|| async {
+        let rooms: Vec<Room> = sqlx::query_as(
+            "SELECT * FROM rooms ORDER BY name ASC"
+        )
+        .fetch_all(&app_state.db_pool)
+        .await?;
+        Ok(rooms)
+    }).await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(rooms)))
+    Ok(reference_response(&app_state, &*rooms))
 }
 
 // ==================== GET ROOM BY ID ====================
@@ -54,28 +62,21 @@ pub async fn create_room(
     room: web::Json<CreateRoomRequest>,
     user_id: String,
 ) -> ApiResult<HttpResponse> {
-    room.validate()?;
-
-    // Проверяем уникальность имени
-    let existing: Option<(String,)> = sqlx::query_as(
-        "SELECT id FROM rooms WHERE LOWER(name) = LOWER(?)"
-    )
-    .bind(&room.name)
-    .fetch_optional(&app_state.db_pool)
-    .await?;
-
-    if existing.is_some() {
-        return Err(ApiError::bad_request("Room with this name already exists"));
-    }
+    validate_room_request(&app_state.db_pool, &room, None).await?.ensure_valid()?;
 
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
     let color = room.color.clone().unwrap_or_else(|| "#667eea".to_string());
+    let opening_time = room.opening_time.clone().unwrap_or_else(|| DEFAULT_OPENING_TIME.to_string());
+    let closing_time = room.closing_time.clone().unwrap_or_else(|| DEFAULT_CLOSING_TIME.to_string());
+    let open_days = room.open_days.clone().unwrap_or_else(|| DEFAULT_OPEN_DAYS.to_string());
+    let weekly_hours = room.weekly_hours.as_ref().and_then(weekly_hours_column);
 
     sqlx::query(
         r#"
-        INSERT INTO rooms (id, name, description, capacity, color, status, created_by, updated_by, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, 'available', ?, ?, ?, ?)
+        INSERT INTO rooms (id, name, description, capacity, color, status, opening_time, closing_time, open_days,
+                           weekly_hours, created_by, updated_by, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, 'available', ?, ?, ?, ?, ?, ?, ?, ?)
         "#
     )
     .bind(&id)
@@ -83,6 +84,10 @@ pub async fn create_room(
     .bind(&room.description)
     .bind(&room.capacity)
     .bind(&color)
+    .bind(&opening_time)
+    .bind(&closing_time)
+    .bind(&open_days)
+    .bind(&weekly_hours)
     .bind(&user_id)
     .bind(&user_id)
     .bind(&now)
@@ -95,6 +100,7 @@ pub async fn create_room(
         .fetch_one(&app_state.db_pool)
         .await?;
 
+    app_state.reference_cache.invalidate(ReferenceDataset::Rooms);
     info!("🚪 Created room: {} ({})", room.name, id);
     Ok(HttpResponse::Created().json(ApiResponse::success(created)))
 }
@@ -120,21 +126,9 @@ pub async fn update_room(
 
     let existing = existing.ok_or_else(|| ApiError::not_found("Room"))?;
 
-    // Проверяем уникальность имени если оно меняется
+    let mut checks = ValidationResult::new();
     if let Some(ref new_name) = update.name {
-        if new_name.to_lowercase() != existing.name.to_lowercase() {
-            let duplicate: Option<(String,)> = sqlx::query_as(
-                "SELECT id FROM rooms WHERE LOWER(name) = LOWER(?) AND id != ?"
-            )
-            .bind(new_name)
-            .bind(&room_id)
-            .fetch_optional(&app_state.db_pool)
-            .await?;
-
-            if duplicate.is_some() {
-                return Err(ApiError::bad_request("Room with this name already exists"));
-            }
-        }
+        check_room_name(&app_state.db_pool, new_name, Some(&room_id), &mut checks).await?;
     }
 
     // Валидация статуса
@@ -152,11 +146,27 @@ pub async fn update_room(
     let capacity = update.capacity.or(existing.capacity);
     let color = update.color.clone().or(existing.color);
     let status = update.status.as_ref().unwrap_or(&existing.status);
+    let opening_time = update.opening_time.clone()
+        .or(existing.opening_time)
+        .unwrap_or_else(|| DEFAULT_OPENING_TIME.to_string());
+    let closing_time = update.closing_time.clone()
+        .or(existing.closing_time)
+        .unwrap_or_else(|| DEFAULT_CLOSING_TIME.to_string());
+    let open_days = update.open_days.clone()
+        .or(existing.open_days)
+        .unwrap_or_else(|| DEFAULT_OPEN_DAYS.to_string());
+    let weekly_hours = match update.weekly_hours {
+        Some(ref hours) => weekly_hours_column(hours),
+        None => existing.weekly_hours,
+    };
+    checks.merge(validate_opening_hours(&opening_time, &closing_time));
+    checks.ensure_valid()?;
 
     sqlx::query(
         r#"
         UPDATE rooms 
         SET name = ?, description = ?, capacity = ?, color = ?, status = ?, 
+            opening_time = ?, closing_time = ?, open_days = ?, weekly_hours = ?,
             updated_by = ?, updated_at = ?
         WHERE id = ?
         "#
@@ -166,6 +176,10 @@ pub async fn update_room(
     .bind(&capacity)
     .bind(&color)
     .bind(status)
+    .bind(&opening_time)
+    .bind(&closing_time)
+    .bind(&open_days)
+    .bind(&weekly_hours)
     .bind(&user_id)
     .bind(&now)
     .bind(&room_id)
@@ -177,10 +191,54 @@ pub async fn update_room(
         .fetch_one(&app_state.db_pool)
         .await?;
 
+    app_state.reference_cache.invalidate(ReferenceDataset::Rooms);
     info!("🚪 Updated room: {} ({})", updated.name, room_id);
     Ok(HttpResponse::Ok().json(ApiResponse::success(updated)))
 }
 
+// ==================== SHARED VALIDATION ====================
+
+/// Проверка формы помещения для create_room и POST /validate/room
+pub async fn validate_room_request(
+    pool: &SqlitePool,
+    room: &CreateRoomRequest,
+    existing_id: Option<&str>,
+) -> ApiResult<ValidationResult> {
+    let mut result = ValidationResult::from_validate(room);
+    check_room_name(pool, &room.name, existing_id, &mut result).await?;
+
+    // Формат HH:MM уже проверен derive-правилами, здесь - порядок с учётом значений по умолчанию
+    if !result.errors.contains_key("opening_time") && !result.errors.contains_key("closing_time") {
+        result.merge(validate_opening_hours(
+            room.opening_time.as_deref().unwrap_or(DEFAULT_OPENING_TIME),
+            room.closing_time.as_deref().unwrap_or(DEFAULT_CLOSING_TIME),
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Имя помещения уникально без учёта регистра
+pub async fn check_room_name(
+    pool: &SqlitePool,
+    name: &str,
+    existing_id: Option<&str>,
+    result: &mut ValidationResult,
+) -> ApiResult<()> {
+    let duplicate: Option<(String,)> = sqlx::query_as(
+        "SELECT id FROM rooms WHERE LOWER(name) = LOWER(?) AND id IS NOT ?"
+    )
+    .bind(name)
+    .bind(existing_id)
+    .fetch_optional(pool)
+    .await?;
+
+    if duplicate.is_some() {
+        result.add_error("name", "Room with this name already exists");
+    }
+    Ok(())
+}
+
 // ==================== DELETE ROOM ====================
 
 pub async fn delete_room(
@@ -213,6 +271,7 @@ pub async fn delete_room(
         return Err(ApiError::not_found("Room"));
     }
 
+    app_state.reference_cache.invalidate(ReferenceDataset::Rooms);
     info!("🚪 Deleted room: {}", room_id);
     Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
         (),
@@ -222,8 +281,16 @@ pub async fn delete_room(
 
 // ==================== GET AVAILABLE ROOMS ====================
 
+#[derive(Debug, Deserialize)]
+pub struct AvailableRoomsQuery {
+    /// RFC 3339; с `start` остаются только помещения, открытые и свободные в этот период
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
 pub async fn get_available_rooms(
     app_state: web::Data<Arc<AppState>>,
+    query: web::Query<AvailableRoomsQuery>,
 ) -> ApiResult<HttpResponse> {
     let rooms: Vec<Room> = sqlx::query_as(
         "SELECT * FROM rooms WHERE status = 'available' ORDER BY name ASC"
@@ -231,7 +298,564 @@ pub async fn get_available_rooms(
     .fetch_all(&app_state.db_pool)
     .await?;
 
-    Ok(HttpResponse::Ok().json(ApiResponse::success(rooms)))
+    let Some(start) = query.start else {
+        if query.end.is_some() {
+            return Err(ApiError::bad_request("end requires start"));
+        }
+        return Ok(HttpResponse::Ok().json(ApiResponse::success(rooms)));
+    };
+    if query.end.is_some_and(|end| end <= start) {
+        return Err(ApiError::bad_request("end must be later than start"));
+    }
+
+    // Те же проверки часов работы и пересечений, что и при планировании эксперимента
+    let mut free = Vec::with_capacity(rooms.len());
+    for room in rooms {
+        let mut checks = ValidationResult::new();
+        crate::experiment_handlers::check_room_conflicts(
+            &app_state.db_pool, &room.id, Some(start), query.end, None, &mut checks,
+        ).await?;
+        if checks.is_valid() {
+            free.push(room);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(free)))
+}
+
+// ==================== UTILIZATION ====================
+//
+// Загрузка помещения = забронированные часы / доступные часы.
+// Доступные часы считаются по часам работы помещения (opening_time..closing_time
+// в рабочие дни open_days, UTC). Забронированные часы - эксперименты, привязанные
+// к помещению (room_id или, для старых записей, location = имя помещения).
+// Отдельной таблицы бронирований в системе нет, поэтому эксперименты - единственный
+// источник занятости. Пересекающиеся эксперименты объединяются, чтобы одно и то же
+// время не учитывалось дважды.
+
+pub const DEFAULT_OPENING_TIME: &str = "08:00";
+pub const DEFAULT_CLOSING_TIME: &str = "18:00";
+pub const DEFAULT_OPEN_DAYS: &str = "1,2,3,4,5";
+
+const DEFAULT_UTILIZATION_DAYS: i64 = 30;
+const MAX_UTILIZATION_DAYS: i64 = 366;
+
+fn validate_opening_hours(opening_time: &str, closing_time: &str) -> ValidationResult {
+    let mut result = ValidationResult::new();
+    let open = NaiveTime::parse_from_str(opening_time, "%H:%M");
+    let close = NaiveTime::parse_from_str(closing_time, "%H:%M");
+    if open.is_err() {
+        result.add_error("opening_time", "opening_time must be in HH:MM format");
+    }
+    if close.is_err() {
+        result.add_error("closing_time", "closing_time must be in HH:MM format");
+    }
+    if let (Ok(open), Ok(close)) = (open, close) {
+        if close <= open {
+            result.add_error("closing_time", "closing_time must be later than opening_time");
+        }
+    }
+    result
+}
+
+/// Значение колонки weekly_hours; пустое расписание снимается (NULL)
+fn weekly_hours_column(hours: &WeeklyHours) -> Option<String> {
+    if hours.is_empty() {
+        return None;
+    }
+    serde_json::to_string(hours).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UtilizationGranularity {
+    #[default]
+    Day,
+    Week,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UtilizationQuery {
+    /// YYYY-MM-DD, по умолчанию - 30 дней до `to`
+    pub from: Option<String>,
+    /// YYYY-MM-DD включительно, по умолчанию - сегодня
+    pub to: Option<String>,
+    pub granularity: Option<UtilizationGranularity>,
+    /// `csv` - выгрузка файлом
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UtilizationPoint {
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub available_hours: f64,
+    pub booked_hours: f64,
+    pub utilization_percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomUtilization {
+    pub room_id: String,
+    pub room_name: String,
+    pub opening_time: String,
+    pub closing_time: String,
+    pub open_days: String,
+    /// Итоговое расписание с учётом weekly_hours
+    pub opening_hours: String,
+    pub available_hours: f64,
+    pub booked_hours: f64,
+    pub utilization_percent: Option<f64>,
+    pub points: Vec<UtilizationPoint>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomsUtilizationResponse {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub granularity: UtilizationGranularity,
+    pub available_hours: f64,
+    pub booked_hours: f64,
+    pub utilization_percent: Option<f64>,
+    pub rooms: Vec<RoomUtilization>,
+}
+
+/// Часы работы помещения в разобранном виде
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OpeningHours {
+    /// Окно работы по дням недели (индекс 0 = понедельник); None - выходной
+    windows: [Option<(NaiveTime, NaiveTime)>; 7],
+}
+
+const WEEKDAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+impl OpeningHours {
+    /// Одинаковые часы в перечисленные дни ISO (1 = Пн)
+    fn uniform(open: NaiveTime, close: NaiveTime, days: &[u32]) -> Self {
+        let mut windows = [None; 7];
+        for &day in days.iter().filter(|d| (1..=7).contains(*d)) {
+            windows[day as usize - 1] = Some((open, close));
+        }
+        Self { windows }
+    }
+
+    /// Недельное расписание имеет приоритет над opening_time/closing_time/open_days.
+    /// Некорректные/отсутствующие значения заменяются значениями по умолчанию
+    pub(crate) fn from_room(room: &Room) -> Self {
+        if let Some(weekly) = room.weekly_hours.as_deref().and_then(parse_weekly_hours) {
+            return Self::from_weekly(&weekly);
+        }
+        let parse_time = |value: Option<&str>| {
+            value.and_then(|v| NaiveTime::parse_from_str(v, "%H:%M").ok())
+        };
+        let default_open = parse_time(Some(DEFAULT_OPENING_TIME)).unwrap_or_default();
+        let default_close = parse_time(Some(DEFAULT_CLOSING_TIME)).unwrap_or_default();
+        let (open, close) = match (
+            parse_time(room.opening_time.as_deref()),
+            parse_time(room.closing_time.as_deref()),
+        ) {
+            (Some(open), Some(close)) if close > open => (open, close),
+            _ => (default_open, default_close),
+        };
+        let days = room.open_days.as_deref()
+            .and_then(parse_open_days)
+            .unwrap_or_else(|| vec![1, 2, 3, 4, 5]);
+        Self::uniform(open, close, &days)
+    }
+
+    /// Расписание уже проверено parse_weekly_hours
+    fn from_weekly(weekly: &WeeklyHours) -> Self {
+        let mut windows = [None; 7];
+        for (day, hours) in weekly {
+            let day = day.parse::<usize>().unwrap_or(0);
+            let open = NaiveTime::parse_from_str(&hours.open, "%H:%M");
+            let close = NaiveTime::parse_from_str(&hours.close, "%H:%M");
+            if let (1..=7, Ok(open), Ok(close)) = (day, open, close) {
+                windows[day - 1] = Some((open, close));
+            }
+        }
+        Self { windows }
+    }
+
+    /// Окно работы на конкретную дату (None - выходной)
+    fn window(&self, date: NaiveDate) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let (open, close) = self.windows[date.weekday().num_days_from_monday() as usize]?;
+        Some((date.and_time(open).and_utc(), date.and_time(close).and_utc()))
+    }
+
+    /// Момент попадает в окно работы; `closing_edge` - допустим сам момент закрытия (для окончания)
+    fn is_open_at(&self, at: DateTime<Utc>, closing_edge: bool) -> bool {
+        self.window(at.date_naive()).is_some_and(|(open, close)| {
+            at >= open && (at < close || (closing_edge && at == close))
+        })
+    }
+
+    /// Бронирование должно начинаться и заканчиваться в часы работы;
+    /// многодневные эксперименты могут продолжаться в нерабочее время между ними
+    pub(crate) fn check_booking(
+        &self,
+        room_name: &str,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+    ) -> Result<(), (&'static str, String)> {
+        let closed = |at: DateTime<Utc>| format!(
+            "Room '{}' is closed at {} (opening hours: {})",
+            room_name, at.format("%Y-%m-%d %H:%M UTC"), self.describe()
+        );
+        if !self.is_open_at(start, false) {
+            return Err(("start_date", closed(start)));
+        }
+        if let Some(end) = end.filter(|end| !self.is_open_at(*end, true)) {
+            return Err(("end_date", closed(end)));
+        }
+        Ok(())
+    }
+
+    /// "Mon-Fri 08:00-18:00, Sat 10:00-14:00"; соседние дни с одинаковыми часами объединяются
+    pub(crate) fn describe(&self) -> String {
+        let mut parts: Vec<(usize, usize, (NaiveTime, NaiveTime))> = Vec::new();
+        for (day, window) in self.windows.iter().enumerate() {
+            let Some(window) = *window else { continue };
+            match parts.last_mut() {
+                Some(last) if last.1 + 1 == day && last.2 == window => last.1 = day,
+                _ => parts.push((day, day, window)),
+            }
+        }
+        if parts.is_empty() {
+            return "closed all week".to_string();
+        }
+        parts.iter()
+            .map(|(first, last, (open, close))| {
+                let days = if first == last {
+                    WEEKDAY_NAMES[*first].to_string()
+                } else {
+                    format!("{}-{}", WEEKDAY_NAMES[*first], WEEKDAY_NAMES[*last])
+                };
+                format!("{} {}-{} UTC", days, open.format("%H:%M"), close.format("%H:%M"))
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Нерабочие интервалы внутри [from, to): ночи и выходные объединяются в один интервал
+    pub(crate) fn closed_periods(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let mut closed = Vec::new();
+        let mut cursor = from;
+        let mut date = from.date_naive();
+        while date.and_time(NaiveTime::MIN).and_utc() < to {
+            if let Some((open, close)) = self.window(date) {
+                if open > cursor {
+                    closed.push((cursor, open.min(to)));
+                }
+                cursor = cursor.max(close);
+            }
+            date += Duration::days(1);
+        }
+        if cursor < to {
+            closed.push((cursor, to));
+        }
+        closed.retain(|(start, end)| end > start);
+        closed
+    }
+}
+
+/// Объединение пересекающихся/смежных интервалов
+fn merge_intervals(
+    mut intervals: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    intervals.retain(|(start, end)| end > start);
+    intervals.sort_by_key(|(start, _)| *start);
+    let mut merged: Vec<(DateTime<Utc>, DateTime<Utc>)> = Vec::with_capacity(intervals.len());
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => {
+                if end > last.1 {
+                    last.1 = end;
+                }
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+fn percent(booked: f64, available: f64) -> Option<f64> {
+    if available > 0.0 {
+        Some(round2(booked / available * 100.0))
+    } else {
+        None
+    }
+}
+
+/// Начало периода, в который попадает дата (неделя начинается с понедельника)
+fn period_start(date: NaiveDate, granularity: UtilizationGranularity) -> NaiveDate {
+    match granularity {
+        UtilizationGranularity::Day => date,
+        UtilizationGranularity::Week => {
+            date - Duration::days(date.weekday().num_days_from_monday() as i64)
+        }
+    }
+}
+
+/// Временной ряд загрузки за [from, to] (даты включительно), часы округляются до сотых
+fn compute_utilization(
+    hours: &OpeningHours,
+    bookings: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    from: NaiveDate,
+    to: NaiveDate,
+    granularity: UtilizationGranularity,
+) -> Vec<UtilizationPoint> {
+    let bookings = merge_intervals(bookings);
+    // (period_start, period_end, available_secs, booked_secs)
+    let mut periods: Vec<(NaiveDate, NaiveDate, i64, i64)> = Vec::new();
+
+    let mut date = from;
+    while date <= to {
+        let key = period_start(date, granularity).max(from);
+        if periods.last().is_none_or(|p| p.0 != key) {
+            periods.push((key, date, 0, 0));
+        }
+        let Some(period) = periods.last_mut() else { break };
+        period.1 = date;
+
+        if let Some((win_start, win_end)) = hours.window(date) {
+            period.2 += (win_end - win_start).num_seconds();
+            period.3 += bookings.iter()
+                .filter(|(start, end)| *start < win_end && *end > win_start)
+                .map(|(start, end)| ((*end).min(win_end) - (*start).max(win_start)).num_seconds())
+                .sum::<i64>();
+        }
+
+        date += Duration::days(1);
+    }
+
+    periods.into_iter()
+        .map(|(start, end, available, booked)| {
+            let available_hours = available as f64 / 3600.0;
+            let booked_hours = booked as f64 / 3600.0;
+            UtilizationPoint {
+                period_start: start,
+                period_end: end,
+                available_hours: round2(available_hours),
+                booked_hours: round2(booked_hours),
+                utilization_percent: percent(booked_hours, available_hours),
+            }
+        })
+        .collect()
+}
+
+fn parse_utilization_range(query: &UtilizationQuery) -> ApiResult<(NaiveDate, NaiveDate)> {
+    let parse = |value: &str, field: &str| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| ApiError::bad_request(&format!("{} must be a date in YYYY-MM-DD format", field)))
+    };
+    let to = match query.to.as_deref() {
+        Some(v) => parse(v, "to")?,
+        None => Utc::now().date_naive(),
+    };
+    let from = match query.from.as_deref() {
+        Some(v) => parse(v, "from")?,
+        None => to - Duration::days(DEFAULT_UTILIZATION_DAYS - 1),
+    };
+    if from > to {
+        return Err(ApiError::bad_request("from must not be later than to"));
+    }
+    if (to - from).num_days() >= MAX_UTILIZATION_DAYS {
+        return Err(ApiError::bad_request(&format!(
+            "Date range cannot exceed {} days", MAX_UTILIZATION_DAYS
+        )));
+    }
+    Ok((from, to))
+}
+
+fn wants_csv(query: &UtilizationQuery) -> bool {
+    query.format.as_deref().is_some_and(|f| f.eq_ignore_ascii_case("csv"))
+}
+
+/// Эксперименты помещения, пересекающиеся с [from, to]. Черновики и отменённые не бронируют время.
+async fn fetch_room_bookings(
+    pool: &SqlitePool,
+    room: &Room,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> ApiResult<Vec<(DateTime<Utc>, DateTime<Utc>)>> {
+    let range_start = from.and_time(NaiveTime::MIN).and_utc();
+    let range_end = (to + Duration::days(1)).and_time(NaiveTime::MIN).and_utc();
+
+    let bookings: Vec<(DateTime<Utc>, DateTime<Utc>)> = sqlx::query_as(
+        r#"
+        SELECT start_date, end_date FROM experiments
+        WHERE (room_id = ? OR (room_id IS NULL AND location = ?))
+          AND status NOT IN ('cancelled', 'draft')
+          AND start_date IS NOT NULL AND end_date IS NOT NULL
+          AND datetime(start_date) < datetime(?)
+          AND datetime(end_date) > datetime(?)
+        "#
+    )
+    .bind(&room.id)
+    .bind(&room.name)
+    .bind(range_end)
+    .bind(range_start)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(bookings)
+}
+
+async fn room_utilization(
+    pool: &SqlitePool,
+    room: &Room,
+    from: NaiveDate,
+    to: NaiveDate,
+    granularity: UtilizationGranularity,
+) -> ApiResult<RoomUtilization> {
+    let hours = OpeningHours::from_room(room);
+    let bookings = fetch_room_bookings(pool, room, from, to).await?;
+    let points = compute_utilization(&hours, bookings, from, to, granularity);
+
+    let available_hours: f64 = points.iter().map(|p| p.available_hours).sum();
+    let booked_hours: f64 = points.iter().map(|p| p.booked_hours).sum();
+
+    Ok(RoomUtilization {
+        room_id: room.id.clone(),
+        room_name: room.name.clone(),
+        opening_time: room.opening_time.clone().unwrap_or_else(|| DEFAULT_OPENING_TIME.to_string()),
+        closing_time: room.closing_time.clone().unwrap_or_else(|| DEFAULT_CLOSING_TIME.to_string()),
+        open_days: room.open_days.clone().unwrap_or_else(|| DEFAULT_OPEN_DAYS.to_string()),
+        opening_hours: hours.describe(),
+        available_hours: round2(available_hours),
+        booked_hours: round2(booked_hours),
+        utilization_percent: percent(booked_hours, available_hours),
+        points,
+    })
+}
+
+fn utilization_csv_row(room: &RoomUtilization, point: &UtilizationPoint) -> String {
+    format!(
+        "{},{},{},{},{:.2},{:.2},{}\n",
+        escape_csv_field(&room.room_id),
+        escape_csv_field(&room.room_name),
+        point.period_start,
+        point.period_end,
+        point.available_hours,
+        point.booked_hours,
+        point.utilization_percent.map(|p| format!("{:.2}", p)).unwrap_or_default(),
+    )
+}
+
+const UTILIZATION_CSV_HEADER: &str =
+    "Room ID,Room,Period Start,Period End,Available Hours,Booked Hours,Utilization %\n";
+
+fn csv_response(filename: String, body: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Content-Type", "text/csv; charset=utf-8"))
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(body)
+}
+
+/// Общая загрузка всех помещений за период (None - помещений нет)
+pub async fn overall_utilization(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> ApiResult<Option<f64>> {
+    let rooms: Vec<Room> = sqlx::query_as("SELECT * FROM rooms")
+        .fetch_all(pool)
+        .await?;
+    if rooms.is_empty() {
+        return Ok(None);
+    }
+
+    let mut available = 0.0;
+    let mut booked = 0.0;
+    for room in &rooms {
+        let util = room_utilization(pool, room, from, to, UtilizationGranularity::Week).await?;
+        available += util.available_hours;
+        booked += util.booked_hours;
+    }
+    Ok(Some(percent(booked, available).unwrap_or(0.0)))
+}
+
+pub async fn get_room_utilization(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<UtilizationQuery>,
+) -> ApiResult<HttpResponse> {
+    let room_id = path.into_inner();
+    let (from, to) = parse_utilization_range(&query)?;
+    let granularity = query.granularity.unwrap_or_default();
+
+    let room: Room = sqlx::query_as("SELECT * FROM rooms WHERE id = ?")
+        .bind(&room_id)
+        .fetch_optional(&app_state.db_pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Room"))?;
+
+    let util = room_utilization(&app_state.db_pool, &room, from, to, granularity).await?;
+
+    if wants_csv(&query) {
+        let mut body = String::from(UTILIZATION_CSV_HEADER);
+        for point in &util.points {
+            body.push_str(&utilization_csv_row(&util, point));
+        }
+        return Ok(csv_response(format!("room_utilization_{}_{}_{}.csv", room_id, from, to), body));
+    }
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(util)))
+}
+
+pub async fn get_rooms_utilization(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<UtilizationQuery>,
+) -> ApiResult<HttpResponse> {
+    let (from, to) = parse_utilization_range(&query)?;
+    let granularity = query.granularity.unwrap_or_default();
+
+    let rooms: Vec<Room> = sqlx::query_as("SELECT * FROM rooms ORDER BY name ASC")
+        .fetch_all(&app_state.db_pool)
+        .await?;
+
+    let mut result = Vec::with_capacity(rooms.len());
+    for room in &rooms {
+        result.push(room_utilization(&app_state.db_pool, room, from, to, granularity).await?);
+    }
+    // Самые загруженные помещения - первыми
+    result.sort_by(|a, b| {
+        b.utilization_percent.unwrap_or(0.0)
+            .total_cmp(&a.utilization_percent.unwrap_or(0.0))
+            .then_with(|| a.room_name.cmp(&b.room_name))
+    });
+
+    if wants_csv(&query) {
+        let mut body = String::from(UTILIZATION_CSV_HEADER);
+        for room in &result {
+            for point in &room.points {
+                body.push_str(&utilization_csv_row(room, point));
+            }
+        }
+        return Ok(csv_response(format!("rooms_utilization_{}_{}.csv", from, to), body));
+    }
+
+    let available_hours: f64 = result.iter().map(|r| r.available_hours).sum();
+    let booked_hours: f64 = result.iter().map(|r| r.booked_hours).sum();
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(RoomsUtilizationResponse {
+        from,
+        to,
+        granularity,
+        available_hours: round2(available_hours),
+        booked_hours: round2(booked_hours),
+        utilization_percent: percent(booked_hours, available_hours),
+        rooms: result,
+    })))
 }
 
 // ==================== ROUTES CONFIGURATION ====================
@@ -242,8 +866,303 @@ pub async fn get_available_rooms(
             .route("", web::get().to(room_handlers::get_all_rooms))
             .route("", web::post().to(room_handlers::create_room))
             .route("/available", web::get().to(room_handlers::get_available_rooms))
+            .route("/utilization", web::get().to(room_handlers::get_rooms_utilization))
             .route("/{id}", web::get().to(room_handlers::get_room))
             .route("/{id}", web::put().to(room_handlers::update_room))
             .route("/{id}", web::delete().to(room_handlers::delete_room))
+            .route("/{id}/utilization", web::get().to(room_handlers::get_room_utilization))
     )
 */
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dt(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn office_hours() -> OpeningHours {
+        OpeningHours::uniform(
+            NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            &[1, 2, 3, 4, 5],
+        )
+    }
+
+    #[test]
+    fn test_merge_intervals_overlapping_and_nested() {
+        let merged = merge_intervals(vec![
+            (dt("2024-01-08T10:00:00Z"), dt("2024-01-08T12:00:00Z")),
+            (dt("2024-01-08T09:00:00Z"), dt("2024-01-08T11:00:00Z")),
+            (dt("2024-01-08T09:30:00Z"), dt("2024-01-08T09:45:00Z")),
+            (dt("2024-01-08T14:00:00Z"), dt("2024-01-08T15:00:00Z")),
+        ]);
+        assert_eq!(merged, vec![
+            (dt("2024-01-08T09:00:00Z"), dt("2024-01-08T12:00:00Z")),
+            (dt("2024-01-08T14:00:00Z"), dt("2024-01-08T15:00:00Z")),
+        ]);
+    }
+
+    #[test]
+    fn test_overlapping_bookings_not_double_counted() {
+        // 2024-01-08 - понедельник; два пересекающихся эксперимента 09-12 и 10-13 = 4 часа
+        let points = compute_utilization(
+            &office_hours(),
+            vec![
+                (dt("2024-01-08T09:00:00Z"), dt("2024-01-08T12:00:00Z")),
+                (dt("2024-01-08T10:00:00Z"), dt("2024-01-08T13:00:00Z")),
+            ],
+            date("2024-01-08"),
+            date("2024-01-08"),
+            UtilizationGranularity::Day,
+        );
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].available_hours, 10.0);
+        assert_eq!(points[0].booked_hours, 4.0);
+        assert_eq!(points[0].utilization_percent, Some(40.0));
+    }
+
+    #[test]
+    fn test_bookings_clipped_to_opening_hours_and_weeks() {
+        // Эксперимент с пятницы 16:00 до понедельника 10:00: выходные не считаются
+        let points = compute_utilization(
+            &office_hours(),
+            vec![(dt("2024-01-12T16:00:00Z"), dt("2024-01-15T10:00:00Z"))],
+            date("2024-01-10"),
+            date("2024-01-16"),
+            UtilizationGranularity::Week,
+        );
+        assert_eq!(points.len(), 2);
+        // Первая неделя обрезана началом диапазона (ср-вс): 3 рабочих дня
+        assert_eq!(points[0].period_start, date("2024-01-10"));
+        assert_eq!(points[0].period_end, date("2024-01-14"));
+        assert_eq!(points[0].available_hours, 30.0);
+        assert_eq!(points[0].booked_hours, 2.0);
+        assert_eq!(points[1].period_start, date("2024-01-15"));
+        assert_eq!(points[1].period_end, date("2024-01-16"));
+        assert_eq!(points[1].available_hours, 20.0);
+        assert_eq!(points[1].booked_hours, 2.0);
+    }
+
+    #[test]
+    fn test_closed_days_have_no_utilization() {
+        let points = compute_utilization(
+            &office_hours(),
+            vec![],
+            date("2024-01-13"),
+            date("2024-01-14"),
+            UtilizationGranularity::Day,
+        );
+        assert!(points.iter().all(|p| p.available_hours == 0.0 && p.utilization_percent.is_none()));
+    }
+
+    #[test]
+    fn test_validate_opening_hours() {
+        assert!(validate_opening_hours("08:00", "18:00").is_valid());
+        assert!(!validate_opening_hours("18:00", "08:00").is_valid());
+        assert!(!validate_opening_hours("8am", "18:00").is_valid());
+        assert_eq!(parse_open_days("1, 3,5"), Some(vec![1, 3, 5]));
+        assert_eq!(parse_open_days("0,8"), None);
+    }
+
+    #[test]
+    fn test_weekly_hours_override_and_booking_checks() {
+        let weekly = parse_weekly_hours(
+            r#"{"1":{"open":"08:00","close":"18:00"},"2":{"open":"08:00","close":"18:00"},"6":{"open":"10:00","close":"14:00"}}"#
+        ).unwrap();
+        let hours = OpeningHours::from_weekly(&weekly);
+        assert_eq!(hours.describe(), "Mon-Tue 08:00-18:00 UTC, Sat 10:00-14:00 UTC");
+        assert_eq!(office_hours().describe(), "Mon-Fri 08:00-18:00 UTC");
+        assert!(parse_weekly_hours(r#"{"8":{"open":"08:00","close":"18:00"}}"#).is_none());
+        assert!(parse_weekly_hours(r#"{"1":{"open":"18:00","close":"08:00"}}"#).is_none());
+
+        // 2024-01-13 - суббота
+        assert!(hours.check_booking("Lab", dt("2024-01-13T10:00:00Z"), Some(dt("2024-01-13T14:00:00Z"))).is_ok());
+        let (field, message) = hours.check_booking("Lab", dt("2024-01-13T09:00:00Z"), None).unwrap_err();
+        assert_eq!(field, "start_date");
+        assert_eq!(
+            message,
+            "Room 'Lab' is closed at 2024-01-13 09:00 UTC (opening hours: Mon-Tue 08:00-18:00 UTC, Sat 10:00-14:00 UTC)"
+        );
+        // Многодневный эксперимент может идти ночью, но заканчиваться должен в часы работы
+        assert!(hours.check_booking("Lab", dt("2024-01-08T16:00:00Z"), Some(dt("2024-01-09T09:00:00Z"))).is_ok());
+        let (field, _) = hours.check_booking("Lab", dt("2024-01-08T16:00:00Z"), Some(dt("2024-01-10T09:00:00Z"))).unwrap_err();
+        assert_eq!(field, "end_date");
+
+        // Расписание заменяет единые часы помещения
+        let mut room: Room = serde_json::from_value(serde_json::json!({
+            "id": "r", "name": "Lab", "status": "available", "opening_time": "09:00", "closing_time": "17:00",
+            "open_days": "1,2,3,4,5", "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-01-01T00:00:00Z",
+            "weekly_hours": { "6": { "open": "10:00", "close": "14:00" } }
+        })).unwrap();
+        assert_eq!(OpeningHours::from_room(&room).describe(), "Sat 10:00-14:00 UTC");
+        assert_eq!(serde_json::to_value(&room).unwrap()["weekly_hours"]["6"]["close"], "14:00");
+        room.weekly_hours = None;
+        assert_eq!(OpeningHours::from_room(&room).describe(), "Mon-Fri 09:00-17:00 UTC");
+    }
+
+    async fn test_app_state() -> web::Data<Arc<AppState>> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('tester', 'tester', 'tester@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        for (id, name, opening) in [("r1", "Lab A", "08:00"), ("r2", "Lab B", "10:00")] {
+            sqlx::query(
+                "INSERT INTO rooms (id, name, status, opening_time, closing_time, open_days, created_at, updated_at) \
+                 VALUES (?, ?, 'available', ?, '18:00', '1,2,3,4,5', datetime('now'), datetime('now'))"
+            )
+                .bind(id)
+                .bind(name)
+                .bind(opening)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        // e1/e2 пересекаются в Lab A, e3 - старая запись по location, e4 отменён
+        for (id, room_id, location, start, end, status) in [
+            ("e1", Some("r1"), None, "2024-01-08 09:00:00", "2024-01-08 12:00:00", "completed"),
+            ("e2", Some("r1"), None, "2024-01-08 11:00:00", "2024-01-08 13:00:00", "completed"),
+            ("e3", None, Some("Lab A"), "2024-01-09 08:00:00", "2024-01-09 10:00:00", "completed"),
+            ("e4", Some("r1"), None, "2024-01-10 08:00:00", "2024-01-10 18:00:00", "cancelled"),
+        ] {
+            sqlx::query(
+                "INSERT INTO experiments (id, title, experiment_date, start_date, end_date, status, experiment_type, \
+                 room_id, location, created_by, created_at, updated_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, 'research', ?, ?, 'tester', datetime('now'), datetime('now'))"
+            )
+                .bind(id)
+                .bind(id)
+                .bind(start)
+                .bind(start)
+                .bind(end)
+                .bind(status)
+                .bind(room_id)
+                .bind(location)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        web::Data::new(Arc::new(AppState {
+            db_pool: pool,
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }))
+    }
+
+    fn query(params: &str) -> web::Query<UtilizationQuery> {
+        web::Query::<UtilizationQuery>::from_query(params).unwrap()
+    }
+
+    async fn json_body(resp: HttpResponse) -> serde_json::Value {
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_get_room_utilization() {
+        let state = test_app_state().await;
+        let resp = get_room_utilization(
+            state,
+            web::Path::from("r1".to_string()),
+            query("from=2024-01-08&to=2024-01-12&granularity=day"),
+        ).await.unwrap();
+        let body = json_body(resp).await;
+        let data = &body["data"];
+        assert_eq!(data["available_hours"], 50.0);
+        // 09-13 (без двойного учёта) + 08-10 по location; отменённый не учитывается
+        assert_eq!(data["booked_hours"], 6.0);
+        assert_eq!(data["utilization_percent"], 12.0);
+        assert_eq!(data["points"].as_array().unwrap().len(), 5);
+        assert_eq!(data["points"][0]["booked_hours"], 4.0);
+    }
+
+    #[actix_web::test]
+    async fn test_get_rooms_utilization_and_csv() {
+        let state = test_app_state().await;
+        let resp = get_rooms_utilization(
+            state.clone(),
+            query("from=2024-01-08&to=2024-01-14&granularity=week"),
+        ).await.unwrap();
+        let body = json_body(resp).await;
+        let rooms = body["data"]["rooms"].as_array().unwrap();
+        assert_eq!(rooms.len(), 2);
+        assert_eq!(rooms[0]["room_id"], "r1");
+        assert_eq!(rooms[1]["available_hours"], 40.0);
+        assert_eq!(body["data"]["booked_hours"], 6.0);
+
+        let resp = get_rooms_utilization(
+            state,
+            query("from=2024-01-08&to=2024-01-14&granularity=week&format=csv"),
+        ).await.unwrap();
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let csv = String::from_utf8(bytes.to_vec()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], UTILIZATION_CSV_HEADER.trim_end());
+        assert_eq!(lines[1], "r1,Lab A,2024-01-08,2024-01-14,50.00,6.00,12.00");
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn test_available_rooms_honor_opening_hours_and_bookings() {
+        let state = test_app_state().await;
+        sqlx::query(
+            "INSERT INTO experiments (id, title, experiment_date, start_date, end_date, status, experiment_type, \
+             room_id, created_by, created_at, updated_at) \
+             VALUES ('e5', 'Titration', '2030-03-04 13:00:00', '2030-03-04 13:00:00', '2030-03-04 15:00:00', \
+                     'planned', 'research', 'r2', 'tester', datetime('now'), datetime('now'))"
+        ).execute(&state.db_pool).await.unwrap();
+        let available = |params: &str| {
+            let state = state.clone();
+            let query = web::Query::<AvailableRoomsQuery>::from_query(params).unwrap();
+            async move { get_available_rooms(state, query).await }
+        };
+        let names = |body: serde_json::Value| -> Vec<String> {
+            body["data"].as_array().unwrap().iter().map(|r| r["name"].as_str().unwrap().to_string()).collect()
+        };
+
+        assert_eq!(names(json_body(available("").await.unwrap()).await), vec!["Lab A", "Lab B"]);
+        // 2030-03-04 - понедельник: Lab B открывается в 10:00
+        let body = json_body(available("start=2030-03-04T09:00:00Z&end=2030-03-04T10:00:00Z").await.unwrap()).await;
+        assert_eq!(names(body), vec!["Lab A"]);
+        // Lab B занята запланированным экспериментом
+        let body = json_body(available("start=2030-03-04T14:00:00Z&end=2030-03-04T16:00:00Z").await.unwrap()).await;
+        assert_eq!(names(body), vec!["Lab A"]);
+        let body = json_body(available("start=2030-03-04T16:00:00Z&end=2030-03-04T17:00:00Z").await.unwrap()).await;
+        assert_eq!(names(body), vec!["Lab A", "Lab B"]);
+        // Воскресенье
+        let body = json_body(available("start=2030-03-03T10:00:00Z").await.unwrap()).await;
+        assert!(names(body).is_empty());
+
+        assert!(matches!(available("end=2030-03-04T10:00:00Z").await, Err(ApiError::BadRequest(_))));
+        assert!(matches!(
+            available("start=2030-03-04T10:00:00Z&end=2030-03-04T09:00:00Z").await,
+            Err(ApiError::BadRequest(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_utilization_rejects_bad_range() {
+        let state = test_app_state().await;
+        assert!(get_rooms_utilization(state.clone(), query("from=2024-02-01&to=2024-01-01")).await.is_err());
+        assert!(get_rooms_utilization(state.clone(), query("from=2023-01-01&to=2024-06-01")).await.is_err());
+        assert!(get_room_utilization(
+            state,
+            web::Path::from("missing".to_string()),
+            query("from=2024-01-08&to=2024-01-12"),
+        ).await.is_err());
+    }
+}
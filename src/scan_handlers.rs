@@ -0,0 +1,219 @@
+// src/scan_handlers.rs
+//! Распознавание отсканированных кодов (ручные сканеры штрихкодов/QR).
+//!
+//! Код проверяется по очереди: ID партии, штрихкод партии, каталожный номер партии,
+//! ID реагента, ID и серийный номер оборудования. Каждая проверка - один запрос
+//! по индексу, первое совпадение возвращается сразу.
+
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{Batch, Equipment, Reagent};
+use crate::AppState;
+
+/// Максимальная длина сканируемого кода
+const MAX_CODE_LENGTH: usize = 256;
+
+/// Интерпретации кода в порядке проверки
+const PROBES: &[(&str, &str)] = &[
+    ("batch", "id"),
+    ("batch", "barcode"),
+    ("batch", "cat_number"),
+    ("reagent", "id"),
+    ("equipment", "id"),
+    ("equipment", "serial_number"),
+];
+
+#[derive(Debug, Serialize)]
+pub struct ScanResult {
+    pub code: String,
+    pub entity_type: &'static str,
+    pub matched_by: &'static str,
+    pub record: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct ScanNotFound {
+    success: bool,
+    message: String,
+    probed: Vec<String>,
+}
+
+fn to_record<T: Serialize>(value: T) -> ApiResult<serde_json::Value> {
+    serde_json::to_value(value).map_err(|e| ApiError::internal_error(e.to_string()))
+}
+
+/// Одна проверка: запрос к соответствующей таблице по индексированной колонке
+async fn probe(
+    pool: &SqlitePool,
+    entity: &str,
+    column: &str,
+    code: &str,
+) -> ApiResult<Option<serde_json::Value>> {
+    let record = match (entity, column) {
+        ("batch", "id") => sqlx::query_as::<_, Batch>(
+            "SELECT * FROM batches WHERE id = ? AND deleted_at IS NULL"
+        ).bind(code).fetch_optional(pool).await?.map(to_record),
+        ("batch", "barcode") => sqlx::query_as::<_, Batch>(
+            "SELECT * FROM batches WHERE barcode = ? AND deleted_at IS NULL"
+        ).bind(code).fetch_optional(pool).await?.map(to_record),
+        // Каталожный номер может повторяться - берём самую свежую партию
+        ("batch", "cat_number") => sqlx::query_as::<_, Batch>(
+            "SELECT * FROM batches WHERE cat_number = ? AND deleted_at IS NULL \
+             ORDER BY received_date DESC LIMIT 1"
+        ).bind(code).fetch_optional(pool).await?.map(to_record),
+        ("reagent", "id") => sqlx::query_as::<_, Reagent>(
+            "SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL"
+        ).bind(code).fetch_optional(pool).await?.map(to_record),
+        ("equipment", "id") => sqlx::query_as::<_, Equipment>(
+            "SELECT * FROM equipment WHERE id = ?"
+        ).bind(code).fetch_optional(pool).await?.map(to_record),
+        ("equipment", "serial_number") => sqlx::query_as::<_, Equipment>(
+            "SELECT * FROM equipment WHERE serial_number = ?"
+        ).bind(code).fetch_optional(pool).await?.map(to_record),
+        _ => None,
+    };
+    record.transpose()
+}
+
+/// Найти первую сущность, соответствующую коду
+pub async fn resolve_code(pool: &SqlitePool, code: &str) -> ApiResult<Option<ScanResult>> {
+    for (entity, column) in PROBES {
+        if let Some(record) = probe(pool, entity, column, code).await? {
+            return Ok(Some(ScanResult {
+                code: code.to_string(),
+                entity_type: entity,
+                matched_by: column,
+                record,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// GET /scan/{code}
+pub async fn scan_code(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let raw = path.into_inner();
+    let code = raw.trim();
+
+    if code.is_empty() {
+        return Err(ApiError::bad_request("Scanned code cannot be empty"));
+    }
+    if code.len() > MAX_CODE_LENGTH {
+        return Err(ApiError::bad_request("Scanned code is too long"));
+    }
+
+    match resolve_code(&app_state.db_pool, code).await? {
+        Some(result) => Ok(HttpResponse::Ok().json(ApiResponse::success(result))),
+        None => Ok(HttpResponse::NotFound().json(ScanNotFound {
+            success: false,
+            message: format!("Not Found: no entity matches code '{}'", code),
+            probed: PROBES.iter().map(|(e, c)| format!("{}.{}", e, c)).collect(),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_pool() -> SqlitePool {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at) \
+             VALUES ('reagent-1', 'Ethanol', 'active', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, cat_number, barcode, quantity, original_quantity, \
+             unit, received_date, status, created_at, updated_at) \
+             VALUES ('batch-1', 'reagent-1', 'B-001', 'CAT-42', '4006381333931', 1.0, 1.0, 'L', \
+             datetime('now'), 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO equipment (id, name, type_, serial_number, created_at, updated_at) \
+             VALUES ('eq-1', 'Balance', 'instrument', 'SN-778', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        pool
+    }
+
+    #[actix_web::test]
+    async fn test_resolve_code_probe_order() {
+        let pool = test_pool().await;
+
+        let cases = [
+            ("batch-1", "batch", "id"),
+            ("4006381333931", "batch", "barcode"),
+            ("CAT-42", "batch", "cat_number"),
+            ("reagent-1", "reagent", "id"),
+            ("eq-1", "equipment", "id"),
+            ("SN-778", "equipment", "serial_number"),
+        ];
+        for (code, entity, column) in cases {
+            let result = resolve_code(&pool, code).await.unwrap().expect(code);
+            assert_eq!(result.entity_type, entity);
+            assert_eq!(result.matched_by, column);
+        }
+
+        assert!(resolve_code(&pool, "unknown-code").await.unwrap().is_none());
+
+        // Удалённые партии не находятся
+        sqlx::query("UPDATE batches SET deleted_at = datetime('now')").execute(&pool).await.unwrap();
+        assert!(resolve_code(&pool, "4006381333931").await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_assigned_barcode_is_scannable_and_unique() {
+        let pool = test_pool().await;
+        sqlx::query(
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, \
+             unit, received_date, status, created_at, updated_at) \
+             VALUES ('batch-2', 'reagent-1', 'B-002', 1.0, 1.0, 'L', \
+             datetime('now'), 'available', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('u1', 'scanner', 'scanner@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+
+        let app_state = web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }));
+        let assign = |code: &str| crate::models::SetBatchBarcodeRequest { barcode: Some(code.to_string()) };
+
+        // Код уже занят batch-1
+        let err = crate::batch_handlers::set_batch_barcode(
+            app_state.clone(),
+            web::Path::from(("reagent-1".to_string(), "batch-2".to_string())),
+            web::Json(assign("4006381333931")),
+            "u1".to_string(),
+        ).await.unwrap_err();
+        assert!(matches!(err, ApiError::BadRequest(_)));
+
+        crate::batch_handlers::set_batch_barcode(
+            app_state.clone(),
+            web::Path::from(("reagent-1".to_string(), "batch-2".to_string())),
+            web::Json(assign(" QR-0002 ")),
+            "u1".to_string(),
+        ).await.unwrap();
+
+        let result = resolve_code(&pool, "QR-0002").await.unwrap().unwrap();
+        assert_eq!(result.matched_by, "barcode");
+        assert_eq!(result.record["id"], "batch-2");
+    }
+}
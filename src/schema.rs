@@ -0,0 +1,327 @@
+// src/schema.rs
+//! Версия схемы БД (GET /admin/schema, POST /admin/migrate) и проверка при запуске.
+//!
+//! Схема создаётся идемпотентным кодом `db::run_migrations`; каждая версия, которую
+//! знает бинарник, перечислена в `SCHEMA_MIGRATIONS` и после применения записывается
+//! в таблицу `schema_migrations` (версия, имя, контрольная сумма, время применения).
+//! Изменение схемы = новая запись в конце `SCHEMA_MIGRATIONS`.
+
+use actix_web::{web, HttpResponse};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::config::{Config, DatabaseConfig};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+// ==================== DEFINITIONS ====================
+
+/// Версия схемы, известная бинарнику
+pub struct SchemaMigration {
+    pub version: i64,
+    pub name: &'static str,
+}
+
+/// Все версии схемы по порядку; последняя - ожидаемая версия
+pub const SCHEMA_MIGRATIONS: &[SchemaMigration] = &[
+    SchemaMigration { version: 1, name: "baseline" },
+    SchemaMigration { version: 2, name: "equipment_maintenance_windows" },
+    SchemaMigration { version: 3, name: "reagent_images" },
+    SchemaMigration { version: 4, name: "kiosk_tokens_and_attendance" },
+    SchemaMigration { version: 5, name: "batch_coa_hold" },
+    SchemaMigration { version: 6, name: "experiment_outcome_and_fts" },
+    SchemaMigration { version: 7, name: "experiment_ownership_and_comments" },
+    SchemaMigration { version: 8, name: "pending_deletions" },
+    SchemaMigration { version: 9, name: "user_favorites" },
+    SchemaMigration { version: 10, name: "storage_locations" },
+    SchemaMigration { version: 11, name: "report_schedules" },
+    SchemaMigration { version: 12, name: "file_blobs" },
+    SchemaMigration { version: 13, name: "equipment_catalog" },
+    SchemaMigration { version: 14, name: "reagent_public_visibility" },
+    SchemaMigration { version: 15, name: "consumption_approvals" },
+    SchemaMigration { version: 16, name: "batch_containers" },
+    SchemaMigration { version: 17, name: "alerts" },
+    SchemaMigration { version: 18, name: "stock_adjustments" },
+    SchemaMigration { version: 19, name: "room_hours_and_equipment_roles" },
+    SchemaMigration { version: 20, name: "metrics_daily" },
+    SchemaMigration { version: 21, name: "reagent_expiry_policy" },
+    SchemaMigration { version: 22, name: "demo_seed_records" },
+    SchemaMigration { version: 23, name: "molar_amount_entries" },
+    SchemaMigration { version: 24, name: "experiment_dependencies" },
+    SchemaMigration { version: 25, name: "outbox" },
+    SchemaMigration { version: 26, name: "experiment_clone" },
+    SchemaMigration { version: 27, name: "batch_low_stock_index" },
+    SchemaMigration { version: 28, name: "teams" },
+    SchemaMigration { version: 29, name: "experiment_consumption_snapshots" },
+    SchemaMigration { version: 30, name: "purchase_orders" },
+    SchemaMigration { version: 31, name: "maintenance_reminder_lead_days" },
+    SchemaMigration { version: 32, name: "experiment_list_counts" },
+    SchemaMigration { version: 33, name: "download_redemptions" },
+    SchemaMigration { version: 34, name: "required_foreign_keys" },
+    SchemaMigration { version: 35, name: "physical_state_enum" },
+    SchemaMigration { version: 36, name: "maintenance_sla_escalation" },
+];
+
+/// Действие при незапущенных миграциях и выключенном auto_migrate
+pub const GUARD_FAIL: &str = "fail";
+pub const GUARD_WARN: &str = "warn";
+
+pub fn expected_version() -> i64 {
+    SCHEMA_MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+fn checksum(migration: &SchemaMigration) -> String {
+    format!("{:x}", Sha256::digest(format!("{}:{}", migration.version, migration.name).as_bytes()))
+}
+
+// ==================== STATUS ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PendingMigration {
+    pub version: i64,
+    pub name: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchemaStatus {
+    /// Последняя версия, которую ожидает бинарник
+    pub expected_version: i64,
+    /// Наибольшая применённая версия (0 - таблица пуста)
+    pub current_version: i64,
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<PendingMigration>,
+    /// Применённые версии не совпадают с известными бинарнику
+    pub drift: bool,
+    pub drift_details: Vec<String>,
+}
+
+async fn ensure_migrations_table(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at DATETIME NOT NULL
+        )
+        "#,
+    )
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn schema_status(pool: &SqlitePool) -> Result<SchemaStatus, sqlx::Error> {
+    ensure_migrations_table(pool).await?;
+
+    let applied: Vec<AppliedMigration> = sqlx::query_as(
+        "SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version"
+    )
+        .fetch_all(pool)
+        .await?;
+
+    let mut drift_details = Vec::new();
+    for row in &applied {
+        match SCHEMA_MIGRATIONS.iter().find(|m| m.version == row.version) {
+            None => drift_details.push(format!(
+                "version {} ({}) is applied but unknown to this build", row.version, row.name
+            )),
+            Some(known) if checksum(known) != row.checksum => drift_details.push(format!(
+                "version {} checksum mismatch: applied '{}', expected '{}'", row.version, row.name, known.name
+            )),
+            Some(_) => {}
+        }
+    }
+
+    let pending = SCHEMA_MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| PendingMigration { version: m.version, name: m.name })
+        .collect();
+
+    Ok(SchemaStatus {
+        expected_version: expected_version(),
+        current_version: applied.iter().map(|a| a.version).max().unwrap_or(0),
+        applied,
+        pending,
+        drift: !drift_details.is_empty(),
+        drift_details,
+    })
+}
+
+// ==================== APPLY ====================
+
+#[derive(Debug, Serialize)]
+pub struct MigrationRun {
+    /// Версии, записанные этим запуском
+    pub applied: Vec<AppliedMigration>,
+    pub duration_ms: u128,
+    pub current_version: i64,
+}
+
+/// Применяет схему (`db::run_migrations`) и записывает недостающие версии
+pub async fn apply_pending(pool: &SqlitePool) -> Result<MigrationRun> {
+    let started = Instant::now();
+    crate::db::run_migrations(pool).await?;
+
+    let pending = schema_status(pool).await?.pending;
+    let now = Utc::now();
+    let mut applied = Vec::with_capacity(pending.len());
+    for migration in SCHEMA_MIGRATIONS.iter().filter(|m| pending.iter().any(|p| p.version == m.version)) {
+        let row = AppliedMigration {
+            version: migration.version,
+            name: migration.name.to_string(),
+            checksum: checksum(migration),
+            applied_at: now,
+        };
+        sqlx::query("INSERT INTO schema_migrations (version, name, checksum, applied_at) VALUES (?, ?, ?, ?)")
+            .bind(row.version)
+            .bind(&row.name)
+            .bind(&row.checksum)
+            .bind(row.applied_at)
+            .execute(pool)
+            .await?;
+        log::info!("Schema migration {} ({}) applied", row.version, row.name);
+        applied.push(row);
+    }
+
+    Ok(MigrationRun {
+        applied,
+        duration_ms: started.elapsed().as_millis(),
+        current_version: expected_version(),
+    })
+}
+
+/// Проверка при запуске: с auto_migrate схема применяется сразу, иначе незапущенные
+/// миграции останавливают запуск (или только логируются при migration_guard = "warn")
+pub async fn startup_check(pool: &SqlitePool, config: &DatabaseConfig) -> Result<()> {
+    if config.auto_migrate {
+        let run = apply_pending(pool).await?;
+        log::info!(
+            "Database schema at version {} ({} migration(s) recorded)", run.current_version, run.applied.len()
+        );
+        return Ok(());
+    }
+
+    let status = schema_status(pool).await?;
+    for detail in &status.drift_details {
+        log::warn!("Schema drift: {}", detail);
+    }
+    if status.pending.is_empty() {
+        log::info!("Database schema at version {}, auto_migrate disabled", status.current_version);
+        return Ok(());
+    }
+
+    let pending = status.pending.iter()
+        .map(|m| format!("{} ({})", m.version, m.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if config.migration_guard == GUARD_WARN {
+        log::warn!("Pending schema migrations: {}. auto_migrate is disabled, starting anyway", pending);
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "Pending schema migrations: {}. Enable DATABASE_AUTO_MIGRATE, run POST /admin/migrate \
+             on a staging copy, or set DATABASE_MIGRATION_GUARD=warn",
+            pending
+        ))
+    }
+}
+
+/// POST /admin/migrate доступен по умолчанию только вне production
+fn runtime_migrate_allowed(config: &Config) -> bool {
+    config.database.allow_runtime_migrate.unwrap_or(!config.is_production())
+}
+
+// ==================== HANDLERS ====================
+
+/// GET /admin/schema - применённые версии, ожидаемая версия и расхождения
+pub async fn get_schema_info(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let status = schema_status(&app_state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(status)))
+}
+
+/// POST /admin/migrate - применить незапущенные миграции и вернуть, что было выполнено
+pub async fn run_pending_migrations(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    if !runtime_migrate_allowed(&app_state.config) {
+        return Err(ApiError::Forbidden(
+            "Runtime migrations are disabled in production (DATABASE_ALLOW_RUNTIME_MIGRATE)".to_string(),
+        ));
+    }
+
+    let run = apply_pending(&app_state.db_pool)
+        .await
+        .map_err(|e| ApiError::internal_error(format!("Migration failed: {}", e)))?;
+    let message = if run.applied.is_empty() {
+        "Schema is up to date".to_string()
+    } else {
+        format!("Applied {} migration(s)", run.applied.len())
+    };
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(run, message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_pool() -> SqlitePool {
+        sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    fn database_config(auto_migrate: bool, guard: &str) -> DatabaseConfig {
+        DatabaseConfig {
+            auto_migrate,
+            migration_guard: guard.to_string(),
+            ..DatabaseConfig::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_pending_migrations_guard_and_apply() {
+        let pool = test_pool().await;
+
+        let status = schema_status(&pool).await.unwrap();
+        assert_eq!(status.current_version, 0);
+        assert_eq!(status.pending.len(), SCHEMA_MIGRATIONS.len());
+
+        assert!(startup_check(&pool, &database_config(false, GUARD_FAIL)).await.is_err());
+        assert!(startup_check(&pool, &database_config(false, GUARD_WARN)).await.is_ok());
+
+        let run = apply_pending(&pool).await.unwrap();
+        assert_eq!(run.applied.len(), SCHEMA_MIGRATIONS.len());
+        assert!(apply_pending(&pool).await.unwrap().applied.is_empty());
+
+        let status = schema_status(&pool).await.unwrap();
+        assert_eq!(status.current_version, expected_version());
+        assert!(status.pending.is_empty());
+        assert!(!status.drift);
+        startup_check(&pool, &database_config(false, GUARD_FAIL)).await.unwrap();
+
+        // База новее бинарника
+        sqlx::query(
+            "INSERT INTO schema_migrations (version, name, checksum, applied_at) \
+             VALUES (999, 'from_newer_build', 'x', datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        let status = schema_status(&pool).await.unwrap();
+        assert!(status.drift);
+        assert!(status.drift_details[0].contains("999"));
+    }
+}
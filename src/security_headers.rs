@@ -0,0 +1,264 @@
+// src/security_headers.rs
+//! Заголовки безопасности ответов (`[security.headers]`).
+//!
+//! Общие заголовки (X-Content-Type-Options, X-Frame-Options, X-XSS-Protection,
+//! Referrer-Policy, HSTS при require_https) ставятся на все ответы и выключаются по одному.
+//! Content-Security-Policy и Permissions-Policy имеют смысл только для документов, поэтому
+//! добавляются лишь к ответам text/html - JSON API их не получает. В режиме report-only
+//! политика отправляется как Content-Security-Policy-Report-Only: браузер ничего не блокирует,
+//! а нарушения присылает на /csp-report, где они пишутся в журнал (target `lims::csp`).
+//! Так политику можно ужесточать, не ломая интерфейс.
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::{web, HttpResponse};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use crate::config::SecurityConfig;
+
+/// Адрес приёма отчётов о нарушениях CSP
+pub const CSP_REPORT_PATH: &str = "/csp-report";
+
+/// Политика по умолчанию: встроенный web_interface.html использует inline-скрипты и стили,
+/// изображения реагентов и штрихкоды приходят как data:/blob:
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; \
+    script-src 'self' 'unsafe-inline'; style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data: blob:; font-src 'self' data:; connect-src 'self'; \
+    object-src 'none'; frame-ancestors 'none'; base-uri 'self'; form-action 'self'";
+
+/// Камера нужна сканеру штрихкодов; остальное запрещено
+pub const DEFAULT_PERMISSIONS_POLICY: &str =
+    "camera=(self), microphone=(), geolocation=(), payment=(), usb=()";
+
+/// Максимальный размер отчёта о нарушении, который пишется в журнал
+const MAX_LOGGED_REPORT_LEN: usize = 4096;
+
+/// Готовый набор заголовков, собранный из конфигурации при старте
+#[derive(Debug, Clone, Default)]
+pub struct SecurityHeaderSet {
+    /// Для всех ответов
+    common: Vec<(HeaderName, HeaderValue)>,
+    /// Только для text/html
+    html: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl SecurityHeaderSet {
+    pub fn from_config(config: &SecurityConfig) -> Self {
+        let headers = &config.headers;
+        let mut set = Self::default();
+        let mut common = |enabled: bool, name: HeaderName, value: &str| {
+            if let (true, Ok(value)) = (enabled, HeaderValue::from_str(value)) {
+                set.common.push((name, value));
+            }
+        };
+        common(headers.content_type_options, header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+        common(headers.frame_options, header::X_FRAME_OPTIONS, "DENY");
+        common(headers.xss_protection, header::X_XSS_PROTECTION, "1; mode=block");
+        common(headers.referrer_policy, header::REFERRER_POLICY, "strict-origin-when-cross-origin");
+        common(
+            config.require_https,
+            header::STRICT_TRANSPORT_SECURITY,
+            "max-age=31536000; includeSubDomains; preload",
+        );
+
+        if let Some(policy) = headers.content_security_policy.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+            let policy = if policy.contains("report-uri") {
+                policy.to_string()
+            } else {
+                format!("{}; report-uri {}", policy.trim_end_matches(';'), CSP_REPORT_PATH)
+            };
+            let name = if headers.csp_report_only {
+                header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+            } else {
+                header::CONTENT_SECURITY_POLICY
+            };
+            if let Ok(value) = HeaderValue::from_str(&policy) {
+                set.html.push((name, value));
+            }
+        }
+        if let Some(policy) = headers.permissions_policy.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+            if let Ok(value) = HeaderValue::from_str(policy) {
+                set.html.push((HeaderName::from_static("permissions-policy"), value));
+            }
+        }
+        set
+    }
+
+    fn apply(&self, headers: &mut header::HeaderMap) {
+        let is_html = headers.get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.trim_start().to_ascii_lowercase().starts_with("text/html"));
+        let html = if is_html { self.html.as_slice() } else { &[] };
+        // Заголовок, выставленный обработчиком, не перетирается
+        for (name, value) in self.common.iter().chain(html) {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+// ==================== MIDDLEWARE ====================
+
+pub struct SecurityHeaders {
+    set: Rc<SecurityHeaderSet>,
+}
+
+impl SecurityHeaders {
+    pub fn new(config: &SecurityConfig) -> Self {
+        Self { set: Rc::new(SecurityHeaderSet::from_config(config)) }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = SecurityHeadersMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersMiddleware { service: Rc::new(service), set: self.set.clone() }))
+    }
+}
+
+pub struct SecurityHeadersMiddleware<S> {
+    service: Rc<S>,
+    set: Rc<SecurityHeaderSet>,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let set = self.set.clone();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            set.apply(res.headers_mut());
+            Ok(res)
+        })
+    }
+}
+
+// ==================== CSP REPORTS ====================
+
+/// POST /csp-report: application/csp-report (report-uri) или application/reports+json
+/// (Reporting API, массив отчётов); без авторизации, ответ всегда 204
+pub async fn csp_report(body: web::Bytes) -> HttpResponse {
+    match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(value) => {
+            let reports = match value {
+                serde_json::Value::Array(items) => items,
+                single => vec![single],
+            };
+            for report in reports {
+                let report = report.get("csp-report").or_else(|| report.get("body")).unwrap_or(&report);
+                let text = report.to_string();
+                let text = match text.char_indices().nth(MAX_LOGGED_REPORT_LEN) {
+                    Some((cut, _)) => format!("{}...", &text[..cut]),
+                    None => text,
+                };
+                log::warn!(target: "lims::csp", "CSP violation: {}", text);
+            }
+        }
+        Err(_) => log::debug!(target: "lims::csp", "Ignored malformed CSP report ({} bytes)", body.len()),
+    }
+    HttpResponse::NoContent().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test as actix_test, App};
+
+    async fn headers_for(config: &SecurityConfig, path: &str) -> header::HeaderMap {
+        let app = actix_test::init_service(
+            App::new()
+                .wrap(SecurityHeaders::new(config))
+                .route("/page", web::get().to(|| async {
+                    HttpResponse::Ok().content_type("text/html; charset=utf-8").body("<html></html>")
+                }))
+                .route("/api", web::get().to(|| async { HttpResponse::Ok().json(serde_json::json!({"ok": true})) }))
+                .route(CSP_REPORT_PATH, web::post().to(csp_report)),
+        )
+        .await;
+        let resp = actix_test::call_service(&app, actix_test::TestRequest::get().uri(path).to_request()).await;
+        resp.headers().clone()
+    }
+
+    #[actix_web::test]
+    async fn test_csp_only_on_html_responses() {
+        let config = SecurityConfig::default();
+
+        let html = headers_for(&config, "/page").await;
+        let csp = html.get(header::CONTENT_SECURITY_POLICY).unwrap().to_str().unwrap();
+        assert!(csp.starts_with("default-src 'self'"));
+        assert!(csp.ends_with("report-uri /csp-report"), "{}", csp);
+        assert!(html.contains_key("permissions-policy"));
+        assert_eq!(html.get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+
+        let json = headers_for(&config, "/api").await;
+        assert!(!json.contains_key(header::CONTENT_SECURITY_POLICY));
+        assert!(!json.contains_key("permissions-policy"));
+        assert_eq!(json.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert!(!json.contains_key(header::STRICT_TRANSPORT_SECURITY));
+    }
+
+    #[actix_web::test]
+    async fn test_report_only_and_disabled_headers() {
+        let mut config = SecurityConfig { require_https: true, ..Default::default() };
+        config.headers.csp_report_only = true;
+        config.headers.frame_options = false;
+        config.headers.permissions_policy = None;
+        config.headers.content_security_policy = Some("default-src 'self'; report-uri /custom".to_string());
+
+        let html = headers_for(&config, "/page").await;
+        assert!(!html.contains_key(header::CONTENT_SECURITY_POLICY));
+        assert_eq!(
+            html.get(header::CONTENT_SECURITY_POLICY_REPORT_ONLY).unwrap(),
+            "default-src 'self'; report-uri /custom"
+        );
+        assert!(!html.contains_key(header::X_FRAME_OPTIONS));
+        assert!(!html.contains_key("permissions-policy"));
+        assert!(html.contains_key(header::STRICT_TRANSPORT_SECURITY));
+
+        config.headers.content_security_policy = None;
+        let html = headers_for(&config, "/page").await;
+        assert!(!html.contains_key(header::CONTENT_SECURITY_POLICY_REPORT_ONLY));
+    }
+
+    #[actix_web::test]
+    async fn test_csp_report_endpoint_accepts_both_formats() {
+        let app = actix_test::init_service(App::new().route(CSP_REPORT_PATH, web::post().to(csp_report))).await;
+        let bodies = [
+            r#"{"csp-report": {"document-uri": "http://lims/", "violated-directive": "script-src"}}"#,
+            r#"[{"type": "csp-violation", "body": {"effectiveDirective": "img-src"}}]"#,
+            "not json",
+        ];
+        for body in bodies {
+            let req = actix_test::TestRequest::post()
+                .uri(CSP_REPORT_PATH)
+                .insert_header((header::CONTENT_TYPE, "application/csp-report"))
+                .set_payload(body)
+                .to_request();
+            assert_eq!(actix_test::call_service(&app, req).await.status(), 204);
+        }
+    }
+}
@@ -0,0 +1,667 @@
+// src/settings.rs
+//! Настройки, изменяемые во время работы без перезапуска (GET/PUT /admin/settings).
+//!
+//! Приоритет значений:
+//! 1. значение в таблице `settings`, заданное администратором;
+//! 2. `Config` - config-файл и переменные окружения (см. `RuntimeSettingsConfig`);
+//! 3. встроенное значение по умолчанию (`RuntimeSettingsConfig::default()`).
+//!
+//! Значения держатся в процессном кэше: обработчики читают их через `settings()`,
+//! фоновые задачи подписываются на изменения через `SettingsStore::subscribe()`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::watch;
+
+use crate::audit::ChangeSet;
+use crate::auth::{get_current_user, UserRole};
+use crate::config::RuntimeSettingsConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+// ==================== DEFINITIONS ====================
+
+pub const LOW_STOCK_THRESHOLD_PERCENT: &str = "low_stock_threshold_percent";
+pub const EXPIRING_SOON_DAYS: &str = "expiring_soon_days";
+pub const NOTIFICATION_HOUR: &str = "notification_hour";
+pub const MAX_UPLOAD_SIZE_MB: &str = "max_upload_size_mb";
+pub const MAX_PER_PAGE: &str = "max_per_page";
+pub const FORECAST_WINDOW_DAYS: &str = "forecast_window_days";
+pub const UNDO_WINDOW_MINUTES: &str = "undo_window_minutes";
+pub const REAGENT_BATCHES_ALL_LIMIT: &str = "reagent_batches_all_limit";
+pub const APPROVAL_EXPIRY_DAYS: &str = "approval_expiry_days";
+pub const STOCK_ADJUSTMENT_REASONS: &str = "stock_adjustment_reasons";
+pub const STRICT_FIELD_FILTERS: &str = "strict_field_filters";
+
+/// Порядок, в котором ищется значение настройки (отдаётся в ответе GET /admin/settings)
+pub const PRECEDENCE: [&str; 3] = [
+    "database: value set via PUT /admin/settings (source = \"database\")",
+    "config: config file / environment variables, e.g. EXPIRING_SOON_DAYS (source = \"config\")",
+    "built-in default, used when neither of the above is set",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[allow(dead_code)] // string/json пока не используются встроенными определениями
+pub enum SettingType {
+    Int,
+    Bool,
+    String,
+    Json,
+    /// Массив непустых строк (набор допустимых кодов)
+    StringList,
+}
+
+#[derive(Debug)]
+pub struct SettingDefinition {
+    pub key: &'static str,
+    pub setting_type: SettingType,
+    pub description: &'static str,
+    /// Для int - границы значения, для string - границы длины, для string_list - число элементов
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    /// Значение по умолчанию из Config
+    pub config_default: fn(&RuntimeSettingsConfig) -> Value,
+}
+
+pub static DEFINITIONS: &[SettingDefinition] = &[
+    SettingDefinition {
+        key: LOW_STOCK_THRESHOLD_PERCENT,
+        setting_type: SettingType::Int,
+        description: "A batch is low on stock when its remaining quantity is at or below this percent of the original",
+        min: Some(0),
+        max: Some(100),
+        config_default: |c| Value::from(c.low_stock_threshold_percent),
+    },
+    SettingDefinition {
+        key: EXPIRING_SOON_DAYS,
+        setting_type: SettingType::Int,
+        description: "Batches expiring within this many days are reported as expiring soon",
+        min: Some(1),
+        max: Some(365),
+        config_default: |c| Value::from(c.expiring_soon_days),
+    },
+    SettingDefinition {
+        key: NOTIFICATION_HOUR,
+        setting_type: SettingType::Int,
+        description: "Hour of the day (UTC) when daily notifications are sent",
+        min: Some(0),
+        max: Some(23),
+        config_default: |c| Value::from(c.notification_hour),
+    },
+    SettingDefinition {
+        key: MAX_UPLOAD_SIZE_MB,
+        setting_type: SettingType::Int,
+        description: "Maximum size of an uploaded file, MB",
+        min: Some(1),
+        max: Some(500),
+        config_default: |c| Value::from(c.max_upload_size_mb),
+    },
+    SettingDefinition {
+        key: MAX_PER_PAGE,
+        setting_type: SettingType::Int,
+        description: "Maximum per_page accepted by list endpoints",
+        min: Some(10),
+        max: Some(1000),
+        config_default: |c| Value::from(c.max_per_page),
+    },
+    SettingDefinition {
+        key: FORECAST_WINDOW_DAYS,
+        setting_type: SettingType::Int,
+        description: "Trailing window of consumption history, in days, used for stock-out forecasts",
+        min: Some(7),
+        max: Some(730),
+        config_default: |c| Value::from(c.forecast_window_days),
+    },
+    SettingDefinition {
+        key: UNDO_WINDOW_MINUTES,
+        setting_type: SettingType::Int,
+        description: "Minutes a deleted reagent, batch or equipment item stays restorable before the deletion is finalized",
+        min: Some(1),
+        max: Some(1440),
+        config_default: |c| Value::from(c.undo_window_minutes),
+    },
+    SettingDefinition {
+        key: REAGENT_BATCHES_ALL_LIMIT,
+        setting_type: SettingType::Int,
+        description: "Maximum number of batches a reagent may have for GET /reagents/{id}/batches?per_page=all",
+        min: Some(100),
+        max: Some(10000),
+        config_default: |c| Value::from(c.reagent_batches_all_limit),
+    },
+    SettingDefinition {
+        key: APPROVAL_EXPIRY_DAYS,
+        setting_type: SettingType::Int,
+        description: "Days a consumption approval request stays pending before it expires",
+        min: Some(1),
+        max: Some(90),
+        config_default: |c| Value::from(c.approval_expiry_days),
+    },
+    SettingDefinition {
+        key: STOCK_ADJUSTMENT_REASONS,
+        setting_type: SettingType::StringList,
+        description: "Reason codes accepted by stock adjustments (POST /batches/{id}/adjust); \"other\" requires a note",
+        min: Some(1),
+        max: Some(50),
+        config_default: |c| Value::from(c.stock_adjustment_reasons.clone()),
+    },
+    SettingDefinition {
+        key: STRICT_FIELD_FILTERS,
+        setting_type: SettingType::Bool,
+        description: "List endpoints reject unknown filter and sort fields with 400 instead of ignoring them (per request: ?strict=)",
+        min: None,
+        max: None,
+        config_default: |c| Value::from(c.strict_field_filters),
+    },
+];
+
+pub fn definition(key: &str) -> Option<&'static SettingDefinition> {
+    DEFINITIONS.iter().find(|d| d.key == key)
+}
+
+/// Проверка значения на соответствие типу и границам определения
+pub fn validate_value(def: &SettingDefinition, value: &Value) -> Result<(), String> {
+    let out_of_range = |n: i64| {
+        def.min.is_some_and(|min| n < min) || def.max.is_some_and(|max| n > max)
+    };
+    let range = || format!("[{}, {}]",
+        def.min.map(|v| v.to_string()).unwrap_or_else(|| "-inf".to_string()),
+        def.max.map(|v| v.to_string()).unwrap_or_else(|| "+inf".to_string()),
+    );
+
+    match def.setting_type {
+        SettingType::Int => {
+            let n = value.as_i64().ok_or_else(|| "expected an integer".to_string())?;
+            if out_of_range(n) {
+                return Err(format!("{} is outside the allowed range {}", n, range()));
+            }
+        }
+        SettingType::Bool => {
+            if !value.is_boolean() {
+                return Err("expected a boolean".to_string());
+            }
+        }
+        SettingType::String => {
+            let s = value.as_str().ok_or_else(|| "expected a string".to_string())?;
+            if out_of_range(s.chars().count() as i64) {
+                return Err(format!("length must be within {}", range()));
+            }
+        }
+        SettingType::Json => {
+            if !(value.is_object() || value.is_array()) {
+                return Err("expected a JSON object or array".to_string());
+            }
+        }
+        SettingType::StringList => {
+            let items = value.as_array().ok_or_else(|| "expected an array of strings".to_string())?;
+            if items.iter().any(|item| item.as_str().is_none_or(|s| s.trim().is_empty())) {
+                return Err("expected an array of non-empty strings".to_string());
+            }
+            if out_of_range(items.len() as i64) {
+                return Err(format!("number of items must be within {}", range()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Значения по умолчанию из Config тоже должны проходить проверку определений
+pub fn validate_defaults(config: &RuntimeSettingsConfig) -> Result<(), String> {
+    for def in DEFINITIONS {
+        validate_value(def, &(def.config_default)(config))
+            .map_err(|e| format!("{}: {}", def.key, e))?;
+    }
+    Ok(())
+}
+
+// ==================== CACHE ====================
+
+pub struct SettingsStore {
+    defaults: RwLock<HashMap<&'static str, Value>>,
+    overrides: RwLock<HashMap<String, Value>>,
+    /// Счётчик версий: растёт при каждом изменении, подписчики ждут `changed()`
+    changes: watch::Sender<u64>,
+}
+
+lazy_static::lazy_static! {
+    static ref SETTINGS: SettingsStore = SettingsStore::new(&RuntimeSettingsConfig::default());
+}
+
+pub fn settings() -> &'static SettingsStore {
+    &SETTINGS
+}
+
+impl SettingsStore {
+    pub fn new(config: &RuntimeSettingsConfig) -> Self {
+        let store = Self {
+            defaults: RwLock::new(HashMap::new()),
+            overrides: RwLock::new(HashMap::new()),
+            changes: watch::channel(0).0,
+        };
+        store.configure(config);
+        store
+    }
+
+    /// Значения по умолчанию из Config (при старте)
+    pub fn configure(&self, config: &RuntimeSettingsConfig) {
+        if let Ok(mut defaults) = self.defaults.write() {
+            *defaults = DEFINITIONS.iter().map(|d| (d.key, (d.config_default)(config))).collect();
+        }
+        self.notify();
+    }
+
+    /// Засевает таблицу settings текущими значениями по умолчанию и загружает
+    /// переопределения администратора в кэш
+    pub async fn load(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let now = Utc::now();
+        for (key, default_value) in self.defaults_snapshot() {
+            sqlx::query(
+                r#"INSERT INTO settings (key, value, default_value, updated_at) VALUES (?, NULL, ?, ?)
+                   ON CONFLICT(key) DO UPDATE SET default_value = excluded.default_value"#
+            )
+                .bind(key)
+                .bind(default_value.to_string())
+                .bind(now)
+                .execute(pool)
+                .await?;
+        }
+
+        // Строку режима обслуживания ведёт maintenance_mode
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT key, value FROM settings WHERE value IS NOT NULL AND key != ?"
+        )
+            .bind(crate::maintenance_mode::SETTING_KEY)
+            .fetch_all(pool)
+            .await?;
+
+        let mut overrides = HashMap::new();
+        for (key, raw) in rows {
+            let parsed = serde_json::from_str::<Value>(&raw).map_err(|e| e.to_string()).and_then(|value| {
+                let def = definition(&key).ok_or_else(|| "unknown setting".to_string())?;
+                validate_value(def, &value).map(|_| value)
+            });
+            match parsed {
+                Ok(value) => {
+                    overrides.insert(key, value);
+                }
+                Err(e) => log::warn!("Ignoring stored setting '{}': {}", key, e),
+            }
+        }
+
+        if let Ok(mut current) = self.overrides.write() {
+            *current = overrides;
+        }
+        self.notify();
+        Ok(())
+    }
+
+    fn defaults_snapshot(&self) -> Vec<(&'static str, Value)> {
+        self.defaults
+            .read()
+            .map(|d| d.iter().map(|(k, v)| (*k, v.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn default_value(&self, key: &str) -> Option<Value> {
+        self.defaults.read().ok().and_then(|d| d.get(key).cloned())
+    }
+
+    /// Действующее значение: переопределение из БД, иначе Config
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.overrides
+            .read()
+            .ok()
+            .and_then(|o| o.get(key).cloned())
+            .or_else(|| self.default_value(key))
+    }
+
+    pub fn get_i64(&self, key: &str) -> i64 {
+        self.get(key).and_then(|v| v.as_i64()).unwrap_or_default()
+    }
+
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.get(key).and_then(|v| v.as_bool()).unwrap_or_default()
+    }
+
+    pub fn get_string_list(&self, key: &str) -> Vec<String> {
+        self.get(key)
+            .and_then(|v| v.as_array().cloned())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|item| item.as_str().map(|s| s.trim().to_string()))
+            .collect()
+    }
+
+    fn set_override(&self, key: &str, value: Option<Value>) {
+        if let Ok(mut overrides) = self.overrides.write() {
+            match value {
+                Some(value) => overrides.insert(key.to_string(), value),
+                None => overrides.remove(key),
+            };
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    fn notify(&self) {
+        self.changes.send_modify(|version| *version += 1);
+    }
+}
+
+// ==================== HANDLERS ====================
+
+#[derive(Debug, Serialize)]
+pub struct SettingView {
+    pub key: &'static str,
+    #[serde(rename = "type")]
+    pub setting_type: SettingType,
+    pub description: &'static str,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    pub value: Value,
+    pub default_value: Value,
+    /// "database" - задано администратором, "config" - из config-файла/окружения
+    pub source: &'static str,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettingsResponse {
+    pub precedence: [&'static str; 3],
+    pub settings: Vec<SettingView>,
+}
+
+fn require_admin(http_request: &HttpRequest) -> ApiResult<String> {
+    let claims = get_current_user(http_request)?;
+    if claims.role != UserRole::Admin {
+        return Err(ApiError::Forbidden("Admin access required".to_string()));
+    }
+    Ok(claims.sub)
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct SettingRow {
+    key: String,
+    value: Option<String>,
+    updated_by: Option<String>,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+async fn build_settings_response(pool: &SqlitePool, store: &SettingsStore) -> ApiResult<SettingsResponse> {
+    let rows: Vec<SettingRow> = sqlx::query_as("SELECT key, value, updated_by, updated_at FROM settings")
+        .fetch_all(pool)
+        .await?;
+    let rows: HashMap<String, SettingRow> = rows.into_iter().map(|row| (row.key.clone(), row)).collect();
+
+    let settings = DEFINITIONS
+        .iter()
+        .map(|def| {
+            let default_value = store.default_value(def.key).unwrap_or(Value::Null);
+            let row = rows.get(def.key);
+            let stored = row
+                .and_then(|row| row.value.as_deref())
+                .and_then(|raw| serde_json::from_str::<Value>(raw).ok());
+            let (updated_by, updated_at) = match (row, &stored) {
+                (Some(row), Some(_)) => (row.updated_by.clone(), row.updated_at),
+                _ => (None, None),
+            };
+            SettingView {
+                key: def.key,
+                setting_type: def.setting_type,
+                description: def.description,
+                min: def.min,
+                max: def.max,
+                source: if stored.is_some() { "database" } else { "config" },
+                value: stored.unwrap_or_else(|| default_value.clone()),
+                default_value,
+                updated_by,
+                updated_at,
+            }
+        })
+        .collect();
+
+    Ok(SettingsResponse { precedence: PRECEDENCE, settings })
+}
+
+/// GET /admin/settings - действующие значения, значения по умолчанию и источник каждого
+pub async fn get_settings(
+    app_state: web::Data<Arc<AppState>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    require_admin(&http_request)?;
+    let response = build_settings_response(&app_state.db_pool, settings()).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(response)))
+}
+
+/// Применённое изменение: старое и новое действующее значение
+#[derive(Debug)]
+pub struct AppliedSettingChange {
+    pub key: String,
+    pub old_value: Value,
+    pub new_value: Value,
+    /// false - переопределение снято, действует значение из Config
+    pub overridden: bool,
+}
+
+/// Проверяет все значения до записи и применяет их атомарно; `null` снимает переопределение
+pub async fn apply_setting_changes(
+    pool: &SqlitePool,
+    store: &SettingsStore,
+    user_id: &str,
+    changes: &HashMap<String, Value>,
+) -> ApiResult<Vec<AppliedSettingChange>> {
+    if changes.is_empty() {
+        return Err(ApiError::bad_request("No settings to update"));
+    }
+
+    let mut errors = Vec::new();
+    for (key, value) in changes {
+        match definition(key) {
+            None => errors.push(format!("{}: unknown setting", key)),
+            Some(_) if value.is_null() => {}
+            Some(def) => {
+                if let Err(e) = validate_value(def, value) {
+                    errors.push(format!("{}: {}", key, e));
+                }
+            }
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort();
+        return Err(ApiError::ValidationError(errors.join("; ")));
+    }
+
+    let now = Utc::now();
+    let mut applied = Vec::with_capacity(changes.len());
+    let mut tx = pool.begin().await?;
+    for (key, value) in changes {
+        let default_value = store.default_value(key).unwrap_or(Value::Null);
+        let overridden = !value.is_null();
+        sqlx::query(
+            r#"INSERT INTO settings (key, value, default_value, updated_by, updated_at) VALUES (?, ?, ?, ?, ?)
+               ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_by = excluded.updated_by,
+                                              updated_at = excluded.updated_at"#
+        )
+            .bind(key)
+            .bind(overridden.then(|| value.to_string()))
+            .bind(default_value.to_string())
+            .bind(user_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        applied.push(AppliedSettingChange {
+            key: key.clone(),
+            old_value: store.get(key).unwrap_or(Value::Null),
+            new_value: if overridden { value.clone() } else { default_value },
+            overridden,
+        });
+    }
+    tx.commit().await?;
+
+    for change in &applied {
+        store.set_override(&change.key, change.overridden.then(|| change.new_value.clone()));
+    }
+    store.notify();
+    Ok(applied)
+}
+
+/// PUT /admin/settings - `{ "key": value, ... }`; `null` сбрасывает к значению из Config
+pub async fn update_settings(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<HashMap<String, Value>>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = require_admin(&http_request)?;
+    let store = settings();
+    let applied = apply_setting_changes(&app_state.db_pool, store, &user_id, &body).await?;
+    if !applied.is_empty() {
+        app_state.reference_cache.flush();
+    }
+
+    for change in &applied {
+        let mut cs = ChangeSet::new();
+        cs.add("value", &change.old_value.to_string(), &change.new_value.to_string());
+        let description = if change.overridden {
+            format!("Setting '{}' changed from {} to {}", change.key, change.old_value, change.new_value)
+        } else {
+            format!("Setting '{}' reset to config default {}", change.key, change.new_value)
+        };
+        crate::audit::audit_with_changes(
+            &app_state.db_pool, &user_id, "update_setting", "setting", &change.key, &description, &cs, &http_request,
+        ).await;
+    }
+
+    let response = build_settings_response(&app_state.db_pool, store).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        response,
+        format!("{} setting(s) updated", applied.len()),
+    )))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_value_types_and_bounds() {
+        let hour = definition(NOTIFICATION_HOUR).unwrap();
+        assert!(validate_value(hour, &serde_json::json!(23)).is_ok());
+        assert!(validate_value(hour, &serde_json::json!(24)).is_err());
+        assert!(validate_value(hour, &serde_json::json!(7.5)).is_err());
+        assert!(validate_value(hour, &serde_json::json!("8")).is_err());
+
+        let flag = SettingDefinition {
+            key: "flag", setting_type: SettingType::Bool, description: "", min: None, max: None,
+            config_default: |_| Value::Bool(false),
+        };
+        assert!(validate_value(&flag, &serde_json::json!(true)).is_ok());
+        assert!(validate_value(&flag, &serde_json::json!(1)).is_err());
+
+        let label = SettingDefinition {
+            key: "label", setting_type: SettingType::String, description: "", min: Some(1), max: Some(3),
+            config_default: |_| Value::from("a"),
+        };
+        assert!(validate_value(&label, &serde_json::json!("abc")).is_ok());
+        assert!(validate_value(&label, &serde_json::json!("")).is_err());
+        assert!(validate_value(&label, &serde_json::json!("abcd")).is_err());
+
+        let rules = SettingDefinition {
+            key: "rules", setting_type: SettingType::Json, description: "", min: None, max: None,
+            config_default: |_| serde_json::json!({}),
+        };
+        assert!(validate_value(&rules, &serde_json::json!({ "a": 1 })).is_ok());
+        assert!(validate_value(&rules, &serde_json::json!(5)).is_err());
+
+        let reasons = definition(STOCK_ADJUSTMENT_REASONS).unwrap();
+        assert!(validate_value(reasons, &serde_json::json!(["spillage", "theft"])).is_ok());
+        assert!(validate_value(reasons, &serde_json::json!([])).is_err());
+        assert!(validate_value(reasons, &serde_json::json!(["spillage", " "])).is_err());
+        assert!(validate_value(reasons, &serde_json::json!("spillage")).is_err());
+
+        assert!(validate_defaults(&RuntimeSettingsConfig::default()).is_ok());
+        let bad = RuntimeSettingsConfig { notification_hour: 30, ..Default::default() };
+        assert!(validate_defaults(&bad).unwrap_err().starts_with(NOTIFICATION_HOUR));
+    }
+
+    #[actix_web::test]
+    async fn test_store_precedence_and_change_notification() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+
+        let config = RuntimeSettingsConfig { expiring_soon_days: 45, ..Default::default() };
+        let store = SettingsStore::new(&config);
+        store.load(&pool).await.unwrap();
+        assert_eq!(store.get_i64(EXPIRING_SOON_DAYS), 45);
+
+        let seeded: String = sqlx::query_scalar("SELECT default_value FROM settings WHERE key = ?")
+            .bind(EXPIRING_SOON_DAYS)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(seeded, "45");
+
+        // Значение из БД важнее Config и подхватывается при загрузке
+        sqlx::query("UPDATE settings SET value = '14' WHERE key = ?")
+            .bind(EXPIRING_SOON_DAYS)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let mut changes = store.subscribe();
+        store.load(&pool).await.unwrap();
+        assert!(changes.has_changed().unwrap());
+        changes.borrow_and_update();
+        assert_eq!(store.get_i64(EXPIRING_SOON_DAYS), 14);
+
+        let response = build_settings_response(&pool, &store).await.unwrap();
+        let view = response.settings.iter().find(|s| s.key == EXPIRING_SOON_DAYS).unwrap();
+        assert_eq!((view.source, view.value.clone(), view.default_value.clone()), ("database", Value::from(14), Value::from(45)));
+        let view = response.settings.iter().find(|s| s.key == MAX_PER_PAGE).unwrap();
+        assert_eq!((view.source, view.value.clone()), ("config", Value::from(100)));
+
+        // Ошибочные значения отклоняются целиком, ничего не записывается
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+             VALUES ('admin1', 'admin1', 'admin1@example.com', 'x', 'admin', datetime('now'), datetime('now'))"
+        ).execute(&pool).await.unwrap();
+        let bad: HashMap<String, Value> = serde_json::from_value(serde_json::json!({
+            NOTIFICATION_HOUR: 6, MAX_PER_PAGE: 5, "no_such_setting": 1,
+        })).unwrap();
+        let err = apply_setting_changes(&pool, &store, "admin1", &bad).await.unwrap_err();
+        let ApiError::ValidationError(message) = err else { panic!("expected validation error") };
+        assert!(message.contains(MAX_PER_PAGE) && message.contains("no_such_setting"));
+        assert_eq!(store.get_i64(NOTIFICATION_HOUR), 8);
+
+        // null снимает переопределение и возвращает значение из Config
+        let reset: HashMap<String, Value> = serde_json::from_value(serde_json::json!({
+            EXPIRING_SOON_DAYS: null, NOTIFICATION_HOUR: 6,
+        })).unwrap();
+        let mut applied = apply_setting_changes(&pool, &store, "admin1", &reset).await.unwrap();
+        applied.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(
+            (applied[0].old_value.clone(), applied[0].new_value.clone(), applied[0].overridden),
+            (Value::from(14), Value::from(45), false)
+        );
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(store.get_i64(EXPIRING_SOON_DAYS), 45);
+        assert_eq!(store.get_i64(NOTIFICATION_HOUR), 6);
+
+        let response = build_settings_response(&pool, &store).await.unwrap();
+        let view = response.settings.iter().find(|s| s.key == NOTIFICATION_HOUR).unwrap();
+        assert_eq!((view.source, view.updated_by.as_deref()), ("database", Some("admin1")));
+    }
+}
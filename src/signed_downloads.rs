@@ -0,0 +1,393 @@
+// src/signed_downloads.rs
+//! Подписанные ссылки на скачивание экспортов.
+//!
+//! Экспорт, запрошенный с `?link=true`, не формируется сразу: в ответе приходит
+//! `download_url` с токеном `<payload>.<hmac>`. В payload (base64url JSON) - что выгрузить
+//! (тело запроса отчёта или параметры архива), кто запросил и до какого момента ссылка
+//! действует; подпись - HMAC-SHA256 на ключе из `auth.jwt_secret`.
+//!
+//! `GET /api/v1/public/downloads/{token}` работает без заголовка Authorization: после
+//! проверки подписи и срока файл формируется заново от имени автора ссылки, с его текущими
+//! правами (заблокированный пользователь или отозванное право - отказ). Каждое скачивание
+//! пишется в журнал аудита; при `[downloads] single_use` ссылка срабатывает один раз.
+
+use actix_web::http::{header, Method};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::access_control::{authorize_route, API_PREFIX};
+use crate::auth::{Claims, User, UserRole};
+use crate::client_ip::{normalize, TrustedProxies};
+use crate::error::{ApiError, ApiResult};
+use crate::export_archive::ArchiveQuery;
+use crate::handlers::ApiResponse;
+use crate::i18n::ExportLocaleQuery;
+use crate::report_handlers::GenerateReportRequest;
+use crate::AppState;
+
+/// Верхняя граница `[downloads] ttl_seconds` - сутки
+pub const MAX_LINK_TTL_SECONDS: u64 = 24 * 60 * 60;
+
+/// Префикс ключа подписи: ссылка не совпадёт ни с одной другой HMAC-подписью на том же секрете
+const SIGNING_CONTEXT: &str = "lims-download-link:";
+
+/// `?link=true` у эндпоинтов экспорта - вернуть ссылку вместо файла
+#[derive(Debug, Default, Deserialize)]
+pub struct DownloadLinkQuery {
+    pub link: Option<bool>,
+}
+
+impl DownloadLinkQuery {
+    pub fn wants_link(&self) -> bool {
+        self.link.unwrap_or(false)
+    }
+}
+
+/// Что выгружается по ссылке: параметры исходного запроса экспорта
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DownloadArtifact {
+    ReportExport {
+        request: Box<GenerateReportRequest>,
+        locale: ExportLocaleQuery,
+    },
+    Archive {
+        query: ArchiveQuery,
+    },
+}
+
+impl DownloadArtifact {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadArtifact::ReportExport { .. } => "report_export",
+            DownloadArtifact::Archive { .. } => "archive",
+        }
+    }
+
+    /// Исходный маршрут экспорта: права на него проверяются и при скачивании
+    fn source_route(&self) -> (Method, &'static str) {
+        match self {
+            DownloadArtifact::ReportExport { .. } => (Method::POST, "/reports/export"),
+            DownloadArtifact::Archive { .. } => (Method::GET, "/export/archive"),
+        }
+    }
+}
+
+/// Содержимое токена ссылки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadToken {
+    pub jti: String,
+    /// Пользователь, запросивший ссылку
+    pub sub: String,
+    /// Unix time, после которого ссылка не действует
+    pub exp: i64,
+    #[serde(flatten)]
+    pub artifact: DownloadArtifact,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownloadLink {
+    pub download_url: String,
+    pub expires_at: DateTime<Utc>,
+    pub single_use: bool,
+}
+
+// ==================== SIGNING ====================
+
+fn signature(secret: &str, payload: &str) -> String {
+    crate::crypto::hmac_sha256_hex(format!("{}{}", SIGNING_CONTEXT, secret).as_bytes(), payload.as_bytes())
+}
+
+pub fn sign_token(secret: &str, token: &DownloadToken) -> ApiResult<String> {
+    let json = serde_json::to_vec(token).map_err(|e| ApiError::internal_error(e.to_string()))?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json);
+    let signature = signature(secret, &payload);
+    Ok(format!("{}.{}", payload, signature))
+}
+
+/// Проверка подписи и срока; причина отказа наружу не раскрывается
+pub fn verify_token(secret: &str, token: &str, now: DateTime<Utc>) -> ApiResult<DownloadToken> {
+    let invalid = || ApiError::Unauthorized("Download link is invalid or expired".to_string());
+    let (payload, provided) = token.split_once('.').ok_or_else(invalid)?;
+    // Сравнение хэшей: время не зависит от совпавшего префикса подписи
+    if Sha256::digest(provided.as_bytes()) != Sha256::digest(signature(secret, payload).as_bytes()) {
+        return Err(invalid());
+    }
+    let json = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+    let token: DownloadToken = serde_json::from_slice(&json).map_err(|_| invalid())?;
+    if token.exp <= now.timestamp() {
+        return Err(invalid());
+    }
+    Ok(token)
+}
+
+/// Схема и хост для `download_url`: `[downloads] public_base_url`, иначе адрес запроса.
+/// X-Forwarded-Host / Forwarded учитываются только от доверенного прокси (`security.trusted_proxies`),
+/// иначе клиент мог бы подставить в ссылку чужой домен.
+fn base_url(app_state: &AppState, http_request: &HttpRequest) -> String {
+    if let Some(url) = &app_state.config.downloads.public_base_url {
+        return url.trim_end_matches('/').to_string();
+    }
+    let from_proxy = match (http_request.peer_addr(), TrustedProxies::parse(&app_state.config.security.trusted_proxies)) {
+        (Some(peer), Ok(trusted)) => trusted.is_trusted(normalize(peer.ip())),
+        _ => false,
+    };
+    if from_proxy {
+        let info = http_request.connection_info();
+        return format!("{}://{}", info.scheme(), info.host());
+    }
+    let host = http_request.headers().get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_else(|| http_request.app_config().host());
+    let scheme = if http_request.app_config().secure() { "https" } else { "http" };
+    format!("{}://{}", scheme, host)
+}
+
+/// Ответ эндпоинта экспорта при `?link=true`
+pub fn link_response(
+    app_state: &AppState,
+    http_request: &HttpRequest,
+    user_id: &str,
+    artifact: DownloadArtifact,
+) -> ApiResult<HttpResponse> {
+    let config = &app_state.config.downloads;
+    let expires_at = Utc::now() + Duration::seconds(config.ttl_seconds as i64);
+    let token = DownloadToken {
+        jti: Uuid::new_v4().to_string(),
+        sub: user_id.to_string(),
+        exp: expires_at.timestamp(),
+        artifact,
+    };
+    let signed = sign_token(&app_state.config.auth.jwt_secret, &token)?;
+
+    let download_url = format!("{}{}/public/downloads/{}", base_url(app_state, http_request), API_PREFIX, signed);
+    Ok(HttpResponse::Ok().json(ApiResponse::success(DownloadLink {
+        download_url,
+        expires_at,
+        single_use: config.single_use,
+    })))
+}
+
+// ==================== REDEMPTION ====================
+
+/// Автор ссылки как Claims; заблокированный пользователь ссылками не пользуется
+async fn load_claims(pool: &SqlitePool, token: &DownloadToken) -> ApiResult<Claims> {
+    let user: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = ?")
+        .bind(&token.sub)
+        .fetch_optional(pool)
+        .await?;
+    match user {
+        Some(user) if user.is_active => Ok(Claims {
+            sub: user.id,
+            username: user.username,
+            email: user.email,
+            role: UserRole::from_str(&user.role).unwrap_or(UserRole::Viewer),
+            exp: token.exp,
+            iat: Utc::now().timestamp(),
+        }),
+        _ => Err(ApiError::Forbidden("The user who created this link can no longer download it".to_string())),
+    }
+}
+
+/// Отметка об использовании одноразовой ссылки; записи с истёкшим сроком вычищаются попутно
+async fn claim_single_use(pool: &SqlitePool, token: &DownloadToken) -> ApiResult<()> {
+    let now = Utc::now();
+    sqlx::query("DELETE FROM download_redemptions WHERE expires_at < ?")
+        .bind(now)
+        .execute(pool)
+        .await?;
+    let inserted = sqlx::query(
+        "INSERT OR IGNORE INTO download_redemptions (jti, user_id, kind, expires_at, redeemed_at) VALUES (?, ?, ?, ?, ?)"
+    )
+        .bind(&token.jti)
+        .bind(&token.sub)
+        .bind(token.artifact.as_str())
+        .bind(DateTime::<Utc>::from_timestamp(token.exp, 0).unwrap_or(now))
+        .bind(now)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    if inserted == 0 {
+        return Err(ApiError::Conflict {
+            code: "DOWNLOAD_LINK_USED",
+            message: "This download link has already been used".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// GET /public/downloads/{token}
+pub async fn redeem_download(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let token = verify_token(&app_state.config.auth.jwt_secret, &path.into_inner(), Utc::now())?;
+    let claims = load_claims(&app_state.db_pool, &token).await?;
+    let (method, route) = token.artifact.source_route();
+    authorize_route(&claims, &method, route, &app_state.db_pool).await?;
+    if app_state.config.downloads.single_use {
+        claim_single_use(&app_state.db_pool, &token).await?;
+    }
+
+    crate::audit::audit(
+        &app_state.db_pool,
+        &claims.sub,
+        "download",
+        "download_link",
+        &token.jti,
+        &format!("Downloaded {} via signed link", token.artifact.as_str()),
+        &http_request,
+    ).await;
+
+    // Дальше - обычный обработчик экспорта от имени автора ссылки
+    http_request.extensions_mut().insert(claims);
+    let no_link = || web::Query(DownloadLinkQuery::default());
+    match token.artifact {
+        DownloadArtifact::ReportExport { request, locale } => {
+            crate::report_handlers::export_report(app_state, web::Json(*request), web::Query(locale), no_link(), http_request).await
+        }
+        DownloadArtifact::Archive { query } => {
+            crate::export_archive::export_archive(app_state, web::Query(query), no_link(), http_request).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    fn token_from(body: &serde_json::Value) -> String {
+        let url = body["data"]["download_url"].as_str().unwrap();
+        url.split_once("/api/v1/public/downloads/").unwrap().1.to_string()
+    }
+
+    async fn redeem(app: &TestApp, token: &str) -> ApiResult<HttpResponse> {
+        // Без Claims и заголовка Authorization - как при открытии ссылки в новой вкладке
+        redeem_download(app.state(), web::Path::from(token.to_string()), TestRequest::default().to_http_request()).await
+    }
+
+    #[test]
+    fn test_token_signature_and_expiry() {
+        let now = Utc::now();
+        let token = DownloadToken {
+            jti: "j1".to_string(),
+            sub: "u1".to_string(),
+            exp: now.timestamp() + 60,
+            artifact: DownloadArtifact::Archive { query: ArchiveQuery { entities: Some("reagents".to_string()), format: None } },
+        };
+        let signed = sign_token("secret", &token).unwrap();
+        let verified = verify_token("secret", &signed, now).unwrap();
+        assert_eq!(verified.sub, "u1");
+        assert_eq!(verified.artifact.as_str(), "archive");
+
+        assert!(verify_token("other-secret", &signed, now).is_err());
+        assert!(verify_token("secret", &signed, now + Duration::seconds(61)).is_err());
+        // Подмена пользователя в payload ломает подпись
+        let (_, signature) = signed.split_once('.').unwrap();
+        let forged = DownloadToken { sub: "admin".to_string(), ..token };
+        let forged_payload = sign_token("attacker", &forged).unwrap();
+        let forged = format!("{}.{}", forged_payload.split_once('.').unwrap().0, signature);
+        assert!(matches!(verify_token("secret", &forged, now), Err(ApiError::Unauthorized(_))));
+        assert!(verify_token("secret", "garbage", now).is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_export_links_download_without_auth_header() {
+        let app = TestApp::new().await;
+
+        let (status, body) = app.get(UserRole::Researcher, "/export/archive?entities=reagents&link=true").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["single_use"], false);
+        let archive_token = token_from(&body);
+        let response = redeem(&app, &archive_token).await.unwrap();
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/zip");
+        let bytes = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes.to_vec())).unwrap();
+        assert!(archive.by_name("reagents.csv").is_ok());
+        // Многоразовая по умолчанию
+        assert!(redeem(&app, &archive_token).await.is_ok());
+
+        let (status, body) = app.post(UserRole::Viewer, "/reports/export?link=true", json!({ "preset": "stocktake" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let report_token = token_from(&body);
+        let response = redeem(&app, &report_token).await.unwrap();
+        assert!(response.headers().get("content-type").unwrap().to_str().unwrap().starts_with("text/csv"));
+
+        let redemptions: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM audit_logs WHERE action = 'download' AND entity_type = 'download_link'"
+        ).fetch_one(&app.pool).await.unwrap();
+        assert_eq!(redemptions, 3);
+
+        // Подделанная ссылка и ссылка заблокированного пользователя не работают
+        let tampered = format!("{}0", archive_token);
+        assert!(matches!(redeem(&app, &tampered).await, Err(ApiError::Unauthorized(_))));
+        sqlx::query("UPDATE users SET is_active = 0 WHERE id = ?")
+            .bind(TestApp::user_id(UserRole::Viewer))
+            .execute(&app.pool).await.unwrap();
+        assert!(matches!(redeem(&app, &report_token).await, Err(ApiError::Forbidden(_))));
+    }
+
+    #[actix_web::test]
+    async fn test_single_use_links() {
+        let app = TestApp::new().await;
+        let mut config = crate::config::Config::default();
+        config.downloads.single_use = true;
+        let state = web::Data::new(Arc::new(AppState { db_pool: app.pool.clone(), config, read_replica: None, reference_cache: Default::default() }));
+
+        let artifact = DownloadArtifact::Archive { query: ArchiveQuery { entities: Some("experiments".to_string()), format: None } };
+        let request = TestRequest::default().to_http_request();
+        let response = link_response(&state, &request, TestApp::user_id(UserRole::Viewer), artifact).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&actix_web::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        assert_eq!(body["data"]["single_use"], true);
+        let token = token_from(&body);
+
+        let redeem = || redeem_download(state.clone(), web::Path::from(token.clone()), TestRequest::default().to_http_request());
+        redeem().await.unwrap();
+        let err = redeem().await.unwrap_err();
+        assert!(matches!(err, ApiError::Conflict { code: "DOWNLOAD_LINK_USED", .. }), "{:?}", err);
+    }
+
+    async fn link_url(state: &AppState, peer: &str, headers: &[(&str, &str)]) -> String {
+        let mut request = TestRequest::default().peer_addr(peer.parse().unwrap());
+        for header in headers {
+            request = request.insert_header(*header);
+        }
+        let artifact = DownloadArtifact::Archive { query: ArchiveQuery { entities: None, format: None } };
+        let response = link_response(state, &request.to_http_request(), TestApp::user_id(UserRole::Viewer), artifact).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&actix_web::body::to_bytes(response.into_body()).await.unwrap()).unwrap();
+        body["data"]["download_url"].as_str().unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_link_host_ignores_spoofed_forwarded_headers() {
+        let mut config = crate::config::Config::default();
+        config.security.trusted_proxies = vec!["127.0.0.1".to_string()];
+        let app = TestApp::with_config(config).await;
+        let headers = [("Host", "lims.example.org"), ("X-Forwarded-Host", "evil.example.com"), ("X-Forwarded-Proto", "https")];
+
+        // Клиент напрямую - заголовки прокси не читаются
+        let url = link_url(&app.state(), "203.0.113.7:5000", &headers).await;
+        assert!(url.starts_with("http://lims.example.org/api/v1/public/downloads/"), "{}", url);
+        // Тот же запрос через доверенный прокси
+        let url = link_url(&app.state(), "127.0.0.1:5000", &headers).await;
+        assert!(url.starts_with("https://evil.example.com/api/v1/public/downloads/"), "{}", url);
+
+        // Заданный адрес важнее любых заголовков
+        let mut config = crate::config::Config::default();
+        config.downloads.public_base_url = Some("https://lims.example.org/".to_string());
+        let app = TestApp::with_config(config).await;
+        let url = link_url(&app.state(), "203.0.113.7:5000", &headers[1..]).await;
+        assert!(url.starts_with("https://lims.example.org/api/v1/public/downloads/"), "{}", url);
+    }
+}
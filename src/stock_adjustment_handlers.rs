@@ -0,0 +1,444 @@
+// src/stock_adjustment_handlers.rs
+//! Корректировки остатка партий (пролив, испарение, пересчёт, порча).
+//!
+//! `POST /batches/{id}/adjust` меняет остаток на `delta` (положительную или отрицательную)
+//! с кодом причины из настройки `stock_adjustment_reasons`; причина `other` требует `note`.
+//! Корректировка пишется в `stock_adjustments`, а не в `usage_logs`: это не расход, и в
+//! прогнозы и суммы расхода она не попадает. В истории расхода партии корректировки
+//! показываются отдельными строками (`kind = "adjustment"`).
+//!
+//! Остаток нельзя опустить ниже зарезервированного под эксперименты (`reserved_quantity`).
+//! `GET /reagents/{id}/adjustments/summary` - итоги по причинам для службы качества.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::audit::ChangeSet;
+use crate::auth::get_current_user;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{container_state, Batch, Reagent};
+use crate::settings::{settings, STOCK_ADJUSTMENT_REASONS};
+use crate::AppState;
+
+/// Код ошибки 409: корректировка опустила бы остаток ниже резерва
+pub const ADJUSTMENT_BELOW_RESERVED: &str = "ADJUSTMENT_BELOW_RESERVED";
+
+/// Причина, для которой обязателен текст `note`
+pub const OTHER_REASON: &str = "other";
+
+/// Погрешность сравнения остатка с резервом
+const QUANTITY_EPSILON: f64 = 1e-9;
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct StockAdjustmentRequest {
+    /// Изменение остатка в единице партии: отрицательное - потеря, положительное - излишек
+    pub delta: f64,
+    #[validate(length(min = 1, max = 50, message = "Reason must be 1-50 characters"))]
+    pub reason: String,
+    #[validate(length(max = 1000, message = "Note cannot exceed 1000 characters"))]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct StockAdjustment {
+    pub id: String,
+    pub reagent_id: String,
+    pub batch_id: String,
+    pub user_id: Option<String>,
+    pub delta: f64,
+    pub unit: String,
+    pub quantity_before: f64,
+    pub quantity_after: f64,
+    pub reason: String,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StockAdjustmentResponse {
+    pub adjustment: StockAdjustment,
+    pub remaining_quantity: f64,
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdjustmentSummaryQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Итог по причине в одной единице измерения
+#[derive(Debug, Clone, Serialize, PartialEq, sqlx::FromRow)]
+pub struct AdjustmentReasonTotal {
+    pub reason: String,
+    pub unit: String,
+    pub count: i64,
+    /// Сумма отрицательных корректировок (по модулю)
+    pub losses: f64,
+    pub gains: f64,
+    pub net: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdjustmentSummary {
+    pub reagent_id: String,
+    pub reagent_name: String,
+    pub from: String,
+    pub to: String,
+    pub total_adjustments: i64,
+    pub reasons: Vec<AdjustmentReasonTotal>,
+}
+
+// ==================== VALIDATION ====================
+
+/// Допустимые коды причин (настройка `stock_adjustment_reasons`)
+pub fn allowed_reasons() -> Vec<String> {
+    settings().get_string_list(STOCK_ADJUSTMENT_REASONS)
+}
+
+fn validate_adjustment(request: &StockAdjustmentRequest, allowed: &[String]) -> ApiResult<()> {
+    if !request.delta.is_finite() || request.delta.abs() < QUANTITY_EPSILON {
+        return Err(ApiError::bad_request("delta must be a non-zero number"));
+    }
+    let reason = request.reason.trim();
+    if !allowed.iter().any(|allowed| allowed == reason) {
+        return Err(ApiError::bad_request(&format!(
+            "Invalid reason '{}', allowed: {}", reason, allowed.join(", ")
+        )));
+    }
+    let has_note = request.note.as_deref().is_some_and(|note| !note.trim().is_empty());
+    if reason == OTHER_REASON && !has_note {
+        return Err(ApiError::bad_request("A note is required when reason is 'other'"));
+    }
+    Ok(())
+}
+
+/// Статус партии после корректировки: обнулённая - depleted, пополненная из depleted - available
+fn status_after_adjustment(status: &str, quantity_after: f64) -> &str {
+    if quantity_after <= 0.0 {
+        "depleted"
+    } else if status == "depleted" {
+        "available"
+    } else {
+        status
+    }
+}
+
+// ==================== ADJUST ====================
+
+/// Применить корректировку в транзакции вызывающего; остаток не опускается ниже резерва
+pub(crate) async fn record_stock_adjustment(
+    conn: &mut sqlx::SqliteConnection,
+    batch: &Batch,
+    user_id: &str,
+    delta: f64,
+    reason: &str,
+    note: Option<&str>,
+) -> ApiResult<StockAdjustment> {
+    let quantity_after = batch.quantity + delta;
+    if quantity_after < -QUANTITY_EPSILON {
+        return Err(ApiError::insufficient_quantity(batch.quantity, -delta));
+    }
+    if quantity_after + QUANTITY_EPSILON < batch.reserved_quantity {
+        return Err(ApiError::Conflict {
+            code: ADJUSTMENT_BELOW_RESERVED,
+            message: format!(
+                "Adjustment would leave {} {} but {} {} are reserved for experiments",
+                quantity_after.max(0.0), batch.unit, batch.reserved_quantity, batch.unit
+            ),
+        });
+    }
+    let quantity_after = quantity_after.max(0.0);
+
+    let adjustment = StockAdjustment {
+        id: Uuid::new_v4().to_string(),
+        reagent_id: batch.reagent_id.clone(),
+        batch_id: batch.id.clone(),
+        user_id: Some(user_id.to_string()),
+        delta,
+        unit: batch.unit.clone(),
+        quantity_before: batch.quantity,
+        quantity_after,
+        reason: reason.to_string(),
+        note: note.map(str::trim).filter(|n| !n.is_empty()).map(str::to_string),
+        created_at: Utc::now(),
+    };
+
+    sqlx::query(
+        r#"INSERT INTO stock_adjustments
+           (id, reagent_id, batch_id, user_id, delta, unit, quantity_before, quantity_after, reason, note, created_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+    )
+        .bind(&adjustment.id)
+        .bind(&adjustment.reagent_id)
+        .bind(&adjustment.batch_id)
+        .bind(&adjustment.user_id)
+        .bind(adjustment.delta)
+        .bind(&adjustment.unit)
+        .bind(adjustment.quantity_before)
+        .bind(adjustment.quantity_after)
+        .bind(&adjustment.reason)
+        .bind(&adjustment.note)
+        .bind(adjustment.created_at)
+        .execute(&mut *conn)
+        .await?;
+
+    // Запечатанных тар не больше, чем помещается в новый остаток
+    let container_count = container_state(quantity_after, batch.pack_size, batch.container_count).map(|c| c.sealed);
+    sqlx::query("UPDATE batches SET quantity = ?, status = ?, container_count = ?, updated_by = ?, updated_at = ? WHERE id = ?")
+        .bind(quantity_after)
+        .bind(status_after_adjustment(&batch.status, quantity_after))
+        .bind(container_count)
+        .bind(user_id)
+        .bind(adjustment.created_at)
+        .bind(&batch.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(adjustment)
+}
+
+/// POST /batches/{id}/adjust - корректировка остатка с кодом причины
+pub async fn adjust_batch_stock(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<StockAdjustmentRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    body.validate()?;
+    validate_adjustment(&body, &allowed_reasons())?;
+    let batch_id = path.into_inner();
+    let pool = &app_state.db_pool;
+
+    let mut tx = pool.begin().await?;
+    let batch: Batch = sqlx::query_as("SELECT * FROM batches WHERE id = ? AND deleted_at IS NULL")
+        .bind(&batch_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::batch_not_found(&batch_id))?;
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+        .bind(&batch.reagent_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ApiError::reagent_not_found(&batch.reagent_id))?;
+    crate::reagent_handlers::ensure_reagent_active(&reagent)?;
+
+    let adjustment = record_stock_adjustment(
+        &mut tx, &batch, &claims.sub, body.delta, body.reason.trim(), body.note.as_deref(),
+    ).await?;
+    crate::outbox::enqueue(
+        &mut *tx,
+        &crate::events::BusinessEvent::BatchAdjusted {
+            reagent_id: batch.reagent_id.clone(),
+            batch_id: batch_id.clone(),
+            delta: adjustment.delta,
+            unit: adjustment.unit.clone(),
+            reason: adjustment.reason.clone(),
+            remaining_quantity: adjustment.quantity_after,
+        },
+        Some(&claims.sub),
+    ).await?;
+    tx.commit().await?;
+    crate::stock_anomalies::track(&app_state, crate::stock_anomalies::StockOp {
+        operation: crate::stock_anomalies::StockOperation::Adjustment,
+        batch_id: &batch_id,
+        user_id: Some(&claims.sub),
+        quantity: -adjustment.delta,
+    }).await;
+
+    let status = status_after_adjustment(&batch.status, adjustment.quantity_after).to_string();
+    let mut cs = ChangeSet::new();
+    cs.add_f64("quantity", adjustment.quantity_before, adjustment.quantity_after);
+    if batch.status != status {
+        cs.add("status", &batch.status, &status);
+    }
+    crate::audit::audit_with_changes(
+        pool, &claims.sub, "adjust_stock", "batch", &batch_id,
+        &format!(
+            "Adjusted reagent \"{}\" batch {} by {:+} {} ({}{})",
+            reagent.name, batch.batch_number, adjustment.delta, adjustment.unit, adjustment.reason,
+            adjustment.note.as_deref().map(|n| format!(": {}", n)).unwrap_or_default(),
+        ),
+        &cs, &http_request,
+    ).await;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        StockAdjustmentResponse {
+            remaining_quantity: adjustment.quantity_after,
+            adjustment,
+            status,
+        },
+        "Stock adjustment recorded".to_string(),
+    )))
+}
+
+// ==================== SUMMARY ====================
+
+async fn fetch_reason_totals(
+    pool: &SqlitePool,
+    reagent_id: &str,
+    date_from: &str,
+    date_to: &str,
+) -> Result<Vec<AdjustmentReasonTotal>, sqlx::Error> {
+    sqlx::query_as(
+        r#"SELECT reason, unit, COUNT(*) AS count,
+                  TOTAL(CASE WHEN delta < 0 THEN -delta ELSE 0.0 END) AS losses,
+                  TOTAL(CASE WHEN delta > 0 THEN delta ELSE 0.0 END) AS gains,
+                  TOTAL(delta) AS net
+           FROM stock_adjustments
+           WHERE reagent_id = ? AND DATE(created_at) BETWEEN ? AND ?
+           GROUP BY reason, unit
+           ORDER BY reason, unit"#
+    )
+        .bind(reagent_id)
+        .bind(date_from)
+        .bind(date_to)
+        .fetch_all(pool)
+        .await
+}
+
+/// GET /reagents/{id}/adjustments/summary?from=&to= - корректировки реагента по причинам
+pub async fn get_reagent_adjustment_summary(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<AdjustmentSummaryQuery>,
+) -> ApiResult<HttpResponse> {
+    let reagent_id = path.into_inner();
+    let pool = &app_state.db_pool;
+    let reagent: Reagent = sqlx::query_as("SELECT * FROM reagents WHERE id = ? AND deleted_at IS NULL")
+        .bind(&reagent_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::reagent_not_found(&reagent_id))?;
+
+    let (date_from, date_to) = crate::report_handlers::report_period(
+        query.from.as_deref(), query.to.as_deref(), ("from", "to"),
+    )?;
+    let reasons = fetch_reason_totals(pool, &reagent_id, &date_from, &date_to).await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(AdjustmentSummary {
+        reagent_id,
+        reagent_name: reagent.name,
+        from: date_from,
+        to: date_to,
+        total_adjustments: reasons.iter().map(|r| r.count).sum(),
+        reasons,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::fixtures::RESEARCHER_ID;
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use serde_json::{json, Value};
+
+    async fn setup() -> TestApp {
+        let app = TestApp::new().await;
+        for sql in [
+            "INSERT INTO reagents (id, name, status, created_at, updated_at)
+             VALUES ('r-1', 'Diethyl ether', 'active', datetime('now'), datetime('now'))",
+            "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity, unit, status, received_date, created_at, updated_at)
+             VALUES ('b-1', 'r-1', 'DE-01', 100, 100, 30, 'mL', 'available', datetime('now'), datetime('now'), datetime('now'))",
+        ] {
+            sqlx::query(sql).execute(&app.pool).await.unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO usage_logs (id, reagent_id, batch_id, user_id, quantity_used, unit, created_at)
+             VALUES ('use-1', 'r-1', 'b-1', ?, 10, 'mL', datetime('now', '-1 day'))"
+        ).bind(RESEARCHER_ID).execute(&app.pool).await.unwrap();
+        app
+    }
+
+    async fn adjust(app: &TestApp, body: Value) -> (StatusCode, Value) {
+        app.post(UserRole::Researcher, "/batches/b-1/adjust", body).await
+    }
+
+    async fn batch_state(pool: &SqlitePool) -> (f64, String) {
+        sqlx::query_as("SELECT quantity, status FROM batches WHERE id = 'b-1'").fetch_one(pool).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_adjustment_checks_reason_and_reserved_quantity() {
+        let app = setup().await;
+        let pool = &app.pool;
+
+        for body in [
+            json!({ "delta": -5, "reason": "theft" }),
+            json!({ "delta": -5, "reason": "other" }),
+            json!({ "delta": 0, "reason": "spillage" }),
+        ] {
+            assert_eq!(adjust(&app, body).await.0, StatusCode::BAD_REQUEST);
+        }
+        // 100 - 80 = 20 < 30 зарезервировано
+        let (status, body) = adjust(&app, json!({ "delta": -80, "reason": "spillage" })).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+        assert_eq!(body["code"], ADJUSTMENT_BELOW_RESERVED);
+        assert_eq!(batch_state(pool).await, (100.0, "available".to_string()));
+
+        let (status, body) = adjust(&app, json!({ "delta": -20, "reason": "spillage" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["remaining_quantity"], 80.0);
+        assert_eq!(body["data"]["adjustment"]["quantity_before"], 100.0);
+        let (status, body) = adjust(&app, json!({ "delta": 2.5, "reason": "other", "note": "found in fridge" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(batch_state(pool).await, (82.5, "available".to_string()));
+
+        // В истории расхода корректировки отдельным видом, расход не меняется
+        let (status, history) = app.get(UserRole::Viewer, "/reagents/r-1/batches/b-1/usage").await;
+        assert_eq!(status, StatusCode::OK, "{}", history);
+        assert_eq!(history["data"]["total"], 3);
+        let kinds: Vec<(&str, f64)> = history["data"]["data"].as_array().unwrap().iter()
+            .map(|e| (e["kind"].as_str().unwrap(), e["quantity_used"].as_f64().unwrap()))
+            .collect();
+        assert_eq!(kinds, vec![("adjustment", -2.5), ("adjustment", 20.0), ("consumption", 10.0)]);
+        let consumed: f64 = sqlx::query_scalar("SELECT SUM(quantity_used) FROM usage_logs WHERE batch_id = 'b-1'")
+            .fetch_one(pool).await.unwrap();
+        assert_eq!(consumed, 10.0);
+
+        let (status, summary) = app.get(UserRole::Viewer, "/reagents/r-1/adjustments/summary").await;
+        assert_eq!(status, StatusCode::OK, "{}", summary);
+        assert_eq!(summary["data"]["total_adjustments"], 2);
+        assert_eq!(summary["data"]["reasons"][0]["reason"], "other");
+        assert_eq!(summary["data"]["reasons"][0]["gains"], 2.5);
+        assert_eq!(summary["data"]["reasons"][1]["reason"], "spillage");
+        assert_eq!(summary["data"]["reasons"][1]["losses"], 20.0);
+        assert_eq!(summary["data"]["reasons"][1]["net"], -20.0);
+    }
+
+    #[actix_web::test]
+    async fn test_adjustment_to_zero_depletes_and_recount_restores() {
+        let app = setup().await;
+        let pool = &app.pool;
+        sqlx::query("UPDATE batches SET reserved_quantity = 0").execute(pool).await.unwrap();
+
+        assert_eq!(adjust(&app, json!({ "delta": -101, "reason": "evaporation" })).await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(adjust(&app, json!({ "delta": -100, "reason": "evaporation" })).await.0, StatusCode::OK);
+        assert_eq!(batch_state(pool).await, (0.0, "depleted".to_string()));
+        assert_eq!(adjust(&app, json!({ "delta": 15, "reason": "recount" })).await.0, StatusCode::OK);
+        assert_eq!(batch_state(pool).await, (15.0, "available".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn test_adjustment_rejected_for_inactive_reagent() {
+        let app = setup().await;
+        let pool = &app.pool;
+        sqlx::query("UPDATE reagents SET status = 'inactive' WHERE id = 'r-1'").execute(pool).await.unwrap();
+
+        let (status, body) = adjust(&app, json!({ "delta": -5, "reason": "spillage" })).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+        assert_eq!(body["code"], crate::error::REAGENT_INACTIVE);
+        assert_eq!(batch_state(pool).await, (100.0, "available".to_string()));
+    }
+}
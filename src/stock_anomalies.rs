@@ -0,0 +1,574 @@
+// src/stock_anomalies.rs
+//! Аномалии операций, меняющих остаток партий: расход (`use_reagent`, одобренный расход,
+//! детали обслуживания), корректировки и списание реагентов эксперимента.
+//!
+//! Обработчик после фиксации транзакции вызывает `track`. Частота операций по партии и по
+//! пользователю считается в памяти (`Metrics::stock_rates`) в скользящем окне `[stock_anomalies]`;
+//! превышение порога срабатывает один раз за окно. Разовое списание больше
+//! `single_consumption_percent` от original_quantity партии проверяется на каждой операции.
+//! Срабатывание записывается в `stock_anomalies`, открывает предупреждение `stock_anomaly`
+//! и ставит в outbox событие `stock_anomaly_detected` с письмом администраторам.
+//! `GET /admin/anomalies?from=&to=` - история срабатываний.
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use uuid::Uuid;
+
+use crate::config::{Config, StockAnomalyConfig};
+use crate::error::{ApiError, ApiResult};
+use crate::events::BusinessEvent;
+use crate::handlers::{ApiResponse, PaginatedResponse};
+use crate::pagination::PageGroup;
+use crate::AppState;
+
+/// Наибольшее окно счётчиков частоты (сутки)
+pub const MAX_WINDOW_MINUTES: i64 = 1440;
+
+// ==================== TYPES ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StockOperation {
+    Use,
+    Adjustment,
+    ExperimentConsumption,
+}
+
+impl StockOperation {
+    pub const ALL: [StockOperation; 3] = [
+        StockOperation::Use,
+        StockOperation::Adjustment,
+        StockOperation::ExperimentConsumption,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StockOperation::Use => "use",
+            StockOperation::Adjustment => "adjustment",
+            StockOperation::ExperimentConsumption => "experiment_consumption",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AnomalyRule {
+    BatchRate,
+    UserRate,
+    LargeConsumption,
+}
+
+impl AnomalyRule {
+    pub const ALL: [AnomalyRule; 3] = [AnomalyRule::BatchRate, AnomalyRule::UserRate, AnomalyRule::LargeConsumption];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnomalyRule::BatchRate => "batch_rate",
+            AnomalyRule::UserRate => "user_rate",
+            AnomalyRule::LargeConsumption => "large_consumption",
+        }
+    }
+
+    pub fn parse(value: &str) -> ApiResult<Self> {
+        Self::ALL.iter()
+            .copied()
+            .find(|r| r.as_str() == value.trim())
+            .ok_or_else(|| ApiError::bad_request(&format!(
+                "Invalid rule '{}'. Must be one of: {}",
+                value.trim(),
+                Self::ALL.iter().map(AnomalyRule::as_str).collect::<Vec<_>>().join(", ")
+            )))
+    }
+}
+
+/// Операция с остатком партии; `quantity` - сколько убыло (у корректировки - минус delta,
+/// приход отрицателен и правило крупного списания не проверяет)
+#[derive(Debug, Clone, Copy)]
+pub struct StockOp<'a> {
+    pub operation: StockOperation,
+    pub batch_id: &'a str,
+    /// Нет у автоматических операций
+    pub user_id: Option<&'a str>,
+    pub quantity: f64,
+}
+
+/// Сработавшее правило (ещё не записанное)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub rule: AnomalyRule,
+    pub observed: f64,
+    pub threshold: f64,
+    /// Окно правил частоты, минут
+    pub window_minutes: Option<i64>,
+}
+
+// ==================== RATES ====================
+
+#[derive(Debug, Default)]
+struct RateState {
+    /// Моменты операций в пределах окна
+    batches: HashMap<String, VecDeque<DateTime<Utc>>>,
+    users: HashMap<String, VecDeque<DateTime<Utc>>>,
+    /// До какого момента правило частоты по ключу повторно не срабатывает
+    flagged: HashMap<(AnomalyRule, String), DateTime<Utc>>,
+    /// С запуска процесса (counter в Prometheus)
+    operations: BTreeMap<StockOperation, u64>,
+    anomalies: BTreeMap<AnomalyRule, u64>,
+}
+
+/// Счётчики частоты операций с остатком (общие для процесса, см. `rates`)
+#[derive(Debug, Default)]
+pub struct StockRates {
+    state: Mutex<RateState>,
+}
+
+/// Сводка для /metrics
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct StockRateTotals {
+    pub operations: BTreeMap<&'static str, u64>,
+    pub anomalies: BTreeMap<&'static str, u64>,
+    /// Наибольшее число операций с одной партией и одного пользователя в текущем окне
+    pub max_batch_ops_in_window: u64,
+    pub max_user_ops_in_window: u64,
+}
+
+/// Добавить момент операции, отбросив вышедшие из окна; возвращает число операций в окне
+fn push_in_window(entries: &mut VecDeque<DateTime<Utc>>, now: DateTime<Utc>, since: DateTime<Utc>) -> u64 {
+    entries.push_back(now);
+    while entries.front().is_some_and(|at| *at <= since) {
+        entries.pop_front();
+    }
+    entries.len() as u64
+}
+
+impl StockRates {
+    /// Учесть операцию и вернуть сработавшие правила частоты
+    pub fn observe(&self, op: &StockOp, config: &StockAnomalyConfig, now: DateTime<Utc>) -> Vec<Detection> {
+        let Ok(mut state) = self.state.lock() else { return Vec::new() };
+        let window = Duration::minutes(config.window_minutes);
+        let since = now - window;
+        *state.operations.entry(op.operation).or_default() += 1;
+
+        // Ключи без операций в окне больше не нужны
+        state.batches.retain(|_, entries| entries.back().is_some_and(|at| *at > since));
+        state.users.retain(|_, entries| entries.back().is_some_and(|at| *at > since));
+        state.flagged.retain(|_, until| *until > now);
+
+        let mut counts = vec![(
+            AnomalyRule::BatchRate,
+            op.batch_id.to_string(),
+            push_in_window(state.batches.entry(op.batch_id.to_string()).or_default(), now, since),
+            config.batch_ops_per_window,
+        )];
+        if let Some(user_id) = op.user_id {
+            counts.push((
+                AnomalyRule::UserRate,
+                user_id.to_string(),
+                push_in_window(state.users.entry(user_id.to_string()).or_default(), now, since),
+                config.user_ops_per_window,
+            ));
+        }
+
+        let mut detections = Vec::new();
+        for (rule, key, count, threshold) in counts {
+            if threshold == 0 || count <= threshold || state.flagged.contains_key(&(rule, key.clone())) {
+                continue;
+            }
+            state.flagged.insert((rule, key), now + window);
+            detections.push(Detection {
+                rule,
+                observed: count as f64,
+                threshold: threshold as f64,
+                window_minutes: Some(config.window_minutes),
+            });
+        }
+        detections
+    }
+
+    pub fn record_anomaly(&self, rule: AnomalyRule) {
+        if let Ok(mut state) = self.state.lock() {
+            *state.anomalies.entry(rule).or_default() += 1;
+        }
+    }
+
+    pub fn totals(&self) -> StockRateTotals {
+        let Ok(state) = self.state.lock() else { return StockRateTotals::default() };
+        StockRateTotals {
+            operations: StockOperation::ALL.iter()
+                .map(|op| (op.as_str(), state.operations.get(op).copied().unwrap_or(0)))
+                .collect(),
+            anomalies: AnomalyRule::ALL.iter()
+                .map(|rule| (rule.as_str(), state.anomalies.get(rule).copied().unwrap_or(0)))
+                .collect(),
+            max_batch_ops_in_window: state.batches.values().map(|e| e.len() as u64).max().unwrap_or(0),
+            max_user_ops_in_window: state.users.values().map(|e| e.len() as u64).max().unwrap_or(0),
+        }
+    }
+}
+
+static RATES: OnceLock<Arc<StockRates>> = OnceLock::new();
+
+/// Счётчики процесса (их же держит `Metrics::stock_rates`)
+pub fn rates() -> Arc<StockRates> {
+    RATES.get_or_init(|| Arc::new(StockRates::default())).clone()
+}
+
+/// Разовое списание больше `percent` % от исходного количества партии
+pub fn large_consumption(quantity: f64, original_quantity: f64, percent: f64) -> Option<Detection> {
+    if percent <= 0.0 || quantity <= 0.0 || original_quantity <= 0.0 {
+        return None;
+    }
+    let share = quantity * 100.0 / original_quantity;
+    (share > percent).then(|| Detection {
+        rule: AnomalyRule::LargeConsumption,
+        observed: (share * 10.0).round() / 10.0,
+        threshold: percent,
+        window_minutes: None,
+    })
+}
+
+// ==================== TRACKING ====================
+
+/// Учесть операцию после фиксации транзакции обработчика. Операция уже выполнена,
+/// поэтому ошибка записи аномалии только попадает в журнал
+pub async fn track(app_state: &AppState, op: StockOp<'_>) {
+    if let Err(e) = track_operation(&app_state.db_pool, &app_state.config, &rates(), &op).await {
+        log::error!("Failed to record stock anomaly for batch {}: {}", op.batch_id, e);
+    }
+}
+
+/// Проверить правила и записать срабатывания; возвращает id новых записей stock_anomalies
+pub async fn track_operation(
+    pool: &SqlitePool,
+    config: &Config,
+    rates: &StockRates,
+    op: &StockOp<'_>,
+) -> ApiResult<Vec<String>> {
+    let settings = &config.stock_anomalies;
+    let now = Utc::now();
+    let mut detections = rates.observe(op, settings, now);
+
+    let batch: Option<(String, String, f64)> = sqlx::query_as(
+        "SELECT b.batch_number, r.name, b.original_quantity FROM batches b JOIN reagents r ON r.id = b.reagent_id WHERE b.id = ?"
+    )
+        .bind(op.batch_id)
+        .fetch_optional(pool)
+        .await?;
+    let Some((batch_number, reagent_name, original_quantity)) = batch else {
+        return Ok(Vec::new());
+    };
+    detections.extend(large_consumption(op.quantity, original_quantity, settings.single_consumption_percent));
+    if detections.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let username: Option<String> = match op.user_id {
+        Some(user_id) => sqlx::query_scalar("SELECT username FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?,
+        None => None,
+    };
+    let actor = username.as_deref().or(op.user_id).unwrap_or("system");
+    let batch_label = format!("batch {} of {}", batch_number, reagent_name);
+    let admins: Vec<String> = sqlx::query_scalar("SELECT email FROM users WHERE role = 'admin' AND is_active = 1 ORDER BY username")
+        .fetch_all(pool)
+        .await?;
+
+    let mut ids = Vec::new();
+    let mut rules = Vec::new();
+    let mut tx = pool.begin().await?;
+    for detection in detections {
+        let message = match detection.rule {
+            AnomalyRule::BatchRate => format!(
+                "Batch {} of {} had {} stock operations in {} min (threshold {}), last by {}",
+                batch_number, reagent_name, detection.observed, settings.window_minutes, detection.threshold, actor
+            ),
+            AnomalyRule::UserRate => format!(
+                "User {} made {} stock operations in {} min (threshold {}), last on {}",
+                actor, detection.observed, settings.window_minutes, detection.threshold, batch_label
+            ),
+            AnomalyRule::LargeConsumption => format!(
+                "User {} took {}% of {} in one {} (threshold {}%)",
+                actor, detection.observed, batch_label, op.operation.as_str(), detection.threshold
+            ),
+        };
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            r#"INSERT INTO stock_anomalies
+               (id, rule, operation, batch_id, user_id, observed, threshold, window_minutes, message, detected_at)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#
+        )
+            .bind(&id)
+            .bind(detection.rule.as_str())
+            .bind(op.operation.as_str())
+            .bind(op.batch_id)
+            .bind(op.user_id)
+            .bind(detection.observed)
+            .bind(detection.threshold)
+            .bind(detection.window_minutes)
+            .bind(&message)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        crate::outbox::enqueue(&mut *tx, &BusinessEvent::StockAnomalyDetected {
+            anomaly_id: id.clone(),
+            rule: detection.rule.as_str().to_string(),
+            operation: op.operation.as_str().to_string(),
+            batch_id: op.batch_id.to_string(),
+            user_id: op.user_id.map(str::to_string),
+            observed: detection.observed,
+            threshold: detection.threshold,
+            message: message.clone(),
+            notify: admins.clone(),
+        }, op.user_id).await?;
+        log::warn!("Stock anomaly {} ({}): {}", id, detection.rule.as_str(), message);
+        ids.push(id);
+        rules.push(detection.rule);
+    }
+    tx.commit().await?;
+    for rule in rules {
+        rates.record_anomaly(rule);
+    }
+
+    crate::alert_handlers::refresh_stock_anomaly_alerts(pool, &config.maintenance).await?;
+    Ok(ids)
+}
+
+// ==================== HISTORY ====================
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StockAnomaly {
+    pub id: String,
+    pub rule: String,
+    pub operation: String,
+    pub batch_id: String,
+    pub batch_number: Option<String>,
+    pub reagent_id: Option<String>,
+    pub reagent_name: Option<String>,
+    pub user_id: Option<String>,
+    pub username: Option<String>,
+    pub observed: f64,
+    pub threshold: f64,
+    pub window_minutes: Option<i64>,
+    pub message: String,
+    pub detected_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnomalyQuery {
+    /// YYYY-MM-DD, как у /admin/kpis
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub rule: Option<String>,
+    pub batch_id: Option<String>,
+    pub user_id: Option<String>,
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+/// GET /admin/anomalies?from=&to=&rule=&batch_id=&user_id= - новые сверху
+pub async fn get_anomalies(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<AnomalyQuery>,
+) -> ApiResult<HttpResponse> {
+    let (from, to) = crate::kpi::kpi_period(query.from.as_deref(), query.to.as_deref())?;
+    let rule = query.rule.as_deref().map(AnomalyRule::parse).transpose()?.map(|r| r.as_str());
+    let batch_id = query.batch_id.as_deref().map(str::trim).filter(|b| !b.is_empty());
+    let user_id = query.user_id.as_deref().map(str::trim).filter(|u| !u.is_empty());
+    let pool = app_state.read_pool();
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limits = app_state.page_limits(PageGroup::Other);
+    let per_page = limits.per_page(query.per_page);
+    let filter = "WHERE date(s.detected_at) BETWEEN ?1 AND ?2 AND (?3 IS NULL OR s.rule = ?3) \
+                  AND (?4 IS NULL OR s.batch_id = ?4) AND (?5 IS NULL OR s.user_id = ?5)";
+    let (from_day, to_day) = (from.format("%Y-%m-%d").to_string(), to.format("%Y-%m-%d").to_string());
+
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM stock_anomalies s {}", filter))
+        .bind(&from_day)
+        .bind(&to_day)
+        .bind(rule)
+        .bind(batch_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    let data: Vec<StockAnomaly> = sqlx::query_as(&format!(
+        r#"SELECT s.id, s.rule, s.operation, s.batch_id, b.batch_number, b.reagent_id, r.name AS reagent_name,
+                  s.user_id, u.username, s.observed, s.threshold, s.window_minutes, s.message, s.detected_at
+           FROM stock_anomalies s
+           LEFT JOIN batches b ON b.id = s.batch_id
+           LEFT JOIN reagents r ON r.id = b.reagent_id
+           LEFT JOIN users u ON u.id = s.user_id
+           {} ORDER BY s.detected_at DESC, s.id LIMIT ?6 OFFSET ?7"#,
+        filter
+    ))
+        .bind(&from_day)
+        .bind(&to_day)
+        .bind(rule)
+        .bind(batch_id)
+        .bind(user_id)
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success(PaginatedResponse {
+        data,
+        total,
+        page,
+        per_page,
+        total_pages: (total + per_page - 1) / per_page,
+        limits,
+        capped: limits.is_capped(query.per_page),
+    })))
+}
+
+// ==================== PROMETHEUS ====================
+
+/// Счётчики операций с остатком в текстовом формате Prometheus (часть /metrics?format=prometheus)
+pub fn render_prometheus(totals: &StockRateTotals) -> String {
+    let mut out = String::new();
+    let labelled = [
+        ("lims_stock_operations_total", "Stock-affecting operations by type", "operation", &totals.operations),
+        ("lims_stock_anomalies_total", "Stock anomaly detections by rule", "rule", &totals.anomalies),
+    ];
+    for (name, help, label, values) in labelled {
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} counter\n"));
+        for (value_label, value) in values {
+            out.push_str(&format!("{name}{{{label}=\"{value_label}\"}} {value}\n"));
+        }
+    }
+    let gauges = [
+        ("lims_stock_batch_ops_in_window_max", "Most stock operations on a single batch in the anomaly window", totals.max_batch_ops_in_window),
+        ("lims_stock_user_ops_in_window_max", "Most stock operations by a single user in the anomaly window", totals.max_user_ops_in_window),
+    ];
+    for (name, help, value) in gauges {
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"));
+    }
+    out
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    fn thresholds(batch_ops: u64, user_ops: u64) -> StockAnomalyConfig {
+        StockAnomalyConfig { batch_ops_per_window: batch_ops, user_ops_per_window: user_ops, ..Default::default() }
+    }
+
+    fn op<'a>(batch_id: &'a str, user_id: &'a str) -> StockOp<'a> {
+        StockOp { operation: StockOperation::Use, batch_id, user_id: Some(user_id), quantity: 1.0 }
+    }
+
+    #[test]
+    fn test_rate_rules_fire_once_per_window() {
+        let rates = StockRates::default();
+        let config = thresholds(3, 5);
+        let now = Utc::now();
+
+        for _ in 0..3 {
+            assert!(rates.observe(&op("b-1", "u-1"), &config, now).is_empty());
+        }
+        let fired = rates.observe(&op("b-1", "u-1"), &config, now);
+        assert_eq!(fired, vec![Detection {
+            rule: AnomalyRule::BatchRate, observed: 4.0, threshold: 3.0, window_minutes: Some(60),
+        }]);
+        // Та же партия в том же окне повторно не срабатывает, пользователь - на шестой операции
+        assert!(rates.observe(&op("b-1", "u-1"), &config, now).is_empty());
+        let fired = rates.observe(&op("b-2", "u-1"), &config, now);
+        assert_eq!(fired.iter().map(|d| (d.rule, d.observed)).collect::<Vec<_>>(), vec![(AnomalyRule::UserRate, 6.0)]);
+
+        let totals = rates.totals();
+        assert_eq!(totals.operations["use"], 6);
+        assert_eq!((totals.max_batch_ops_in_window, totals.max_user_ops_in_window), (5, 6));
+
+        // Окно прошло - счёт и подавление начинаются заново
+        let later = now + Duration::minutes(61);
+        assert!(rates.observe(&op("b-1", "u-1"), &config, later).is_empty());
+        assert_eq!(rates.totals().max_batch_ops_in_window, 1);
+
+        // 0 отключает правило
+        let disabled = StockRates::default();
+        for _ in 0..10 {
+            assert!(disabled.observe(&op("b-1", "u-1"), &thresholds(0, 0), now).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_large_consumption_threshold() {
+        assert_eq!(large_consumption(600.0, 1000.0, 50.0).map(|d| d.observed), Some(60.0));
+        assert!(large_consumption(500.0, 1000.0, 50.0).is_none());
+        // Приход, партия без исходного количества и выключенное правило
+        assert!(large_consumption(-600.0, 1000.0, 50.0).is_none());
+        assert!(large_consumption(600.0, 0.0, 50.0).is_none());
+        assert!(large_consumption(600.0, 1000.0, 0.0).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_batch_rate_anomaly_is_recorded_once() {
+        let app = TestApp::new().await;
+        let config = crate::config::Config { stock_anomalies: thresholds(2, 0), ..Default::default() };
+        let rates = StockRates::default();
+        let op = StockOp { operation: StockOperation::Adjustment, batch_id: "fx-batch-nacl-1", user_id: Some("fx-admin"), quantity: 1.0 };
+
+        let mut recorded = Vec::new();
+        for _ in 0..4 {
+            recorded.extend(track_operation(&app.pool, &config, &rates, &op).await.unwrap());
+        }
+        assert_eq!(recorded.len(), 1);
+        let (rule, message): (String, String) = sqlx::query_as("SELECT rule, message FROM stock_anomalies WHERE id = ?")
+            .bind(&recorded[0]).fetch_one(&app.pool).await.unwrap();
+        assert_eq!(rule, "batch_rate");
+        assert!(message.contains("had 3 stock operations in 60 min (threshold 2), last by fx_admin"), "{}", message);
+        assert_eq!(rates.totals().anomalies["batch_rate"], 1);
+    }
+
+    #[actix_web::test]
+    async fn test_large_use_raises_alert_and_appears_in_history() {
+        let app = TestApp::new().await;
+        let (status, body) = app.post(
+            UserRole::Researcher,
+            "/reagents/fx-reagent-ethanol/batches/fx-batch-ethanol-1/use",
+            json!({ "quantity_used": 600.0 }),
+        ).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let (kind, status, context): (String, String, String) = sqlx::query_as(
+            "SELECT kind, status, context FROM alerts WHERE entity_id = 'fx-batch-ethanol-1'"
+        ).fetch_one(&app.pool).await.unwrap();
+        assert_eq!((kind.as_str(), status.as_str()), ("stock_anomaly", "open"));
+        let context: serde_json::Value = serde_json::from_str(&context).unwrap();
+        assert_eq!(context["rule"], "large_consumption");
+        assert_eq!(context["username"], "fx_researcher");
+        let notify: String = sqlx::query_scalar("SELECT payload FROM outbox WHERE event_type = 'stock_anomaly_detected'")
+            .fetch_one(&app.pool).await.unwrap();
+        assert!(notify.contains("\"notify\":[\""), "{}", notify);
+
+        let (status, body) = app.get(UserRole::Admin, "/admin/anomalies").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let data = &body["data"]["data"];
+        assert_eq!(body["data"]["total"], 1);
+        assert_eq!(data[0]["rule"], "large_consumption");
+        assert_eq!(data[0]["observed"], 60.0);
+        assert_eq!(data[0]["username"], "fx_researcher");
+        assert_eq!(data[0]["batch_number"], "ETH-001");
+
+        let (_, body) = app.get(UserRole::Admin, "/admin/anomalies?rule=user_rate").await;
+        assert_eq!(body["data"]["total"], 0);
+        let (_, body) = app.get(UserRole::Admin, "/admin/anomalies?from=2001-01-01&to=2001-01-31").await;
+        assert_eq!(body["data"]["total"], 0);
+        let (status, _) = app.get(UserRole::Admin, "/admin/anomalies?rule=bogus").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = app.get(UserRole::Researcher, "/admin/anomalies").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,244 @@
+// src/suggest_handlers.rs
+//! Автодополнение свободных полей форм: `GET /suggest/{field}?q=&entity=`.
+//!
+//! Поставщики, производители, места хранения и единицы вводятся текстом и со временем
+//! расходятся в написании. Подсказки - уже существующие значения поля: до 10 штук,
+//! совпадение по началу строки без учёта регистра, самые частые первыми. Написания,
+//! отличающиеся только регистром и пробелами, сливаются в самое частое из них.
+//!
+//! Доступны только пары сущность/поле из `SUGGEST_FIELDS`, остальное - 400: это не общий
+//! способ читать произвольные столбцы. Для каждой пары нужен ещё и просмотр самой сущности.
+//! Значения пары читаются одним запросом по индексу и держатся в памяти `CACHE_TTL`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::access_control::{self, Action, Resource};
+use crate::auth::UserRole;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Сколько подсказок отдаётся
+pub const SUGGEST_LIMIT: usize = 10;
+/// Через сколько значения пары перечитываются из БД
+const CACHE_TTL: Duration = Duration::from_secs(300);
+/// Предел различных значений пары в кэше (самые частые)
+const MAX_CACHED_VALUES: i64 = 5000;
+/// Предел длины строки запроса
+const MAX_QUERY_LEN: usize = 100;
+
+lazy_static::lazy_static! {
+    static ref SUGGEST_CACHE: SuggestCache = SuggestCache::default();
+}
+
+// ==================== WHITELIST ====================
+
+/// Поле, для которого разрешены подсказки. Таблица, столбец и фильтр - литералы из кода
+pub struct SuggestField {
+    pub entity: &'static str,
+    pub field: &'static str,
+    table: &'static str,
+    /// Право просмотра, нужное для подсказок
+    resource: Resource,
+    /// Какие строки учитываются (без удалённых записей и черновиков)
+    filter: &'static str,
+}
+
+pub const SUGGEST_FIELDS: &[SuggestField] = &[
+    SuggestField { entity: "batches", field: "supplier", table: "batches", resource: Resource::Batch, filter: "deleted_at IS NULL" },
+    SuggestField { entity: "batches", field: "location", table: "batches", resource: Resource::Batch, filter: "deleted_at IS NULL" },
+    SuggestField { entity: "batches", field: "unit", table: "batches", resource: Resource::Batch, filter: "deleted_at IS NULL" },
+    SuggestField { entity: "equipment", field: "manufacturer", table: "equipment", resource: Resource::Equipment, filter: "pending_deletion_id IS NULL" },
+    SuggestField { entity: "experiments", field: "instructor", table: "experiments", resource: Resource::Experiment, filter: "status != 'draft'" },
+];
+
+/// Пара из белого списка; без entity - единственная пара с таким полем
+pub fn find_field(entity: Option<&str>, field: &str) -> ApiResult<&'static SuggestField> {
+    let field = field.trim();
+    let entity = entity.map(str::trim).filter(|e| !e.is_empty());
+    let mut matches = SUGGEST_FIELDS.iter()
+        .filter(|f| f.field == field && entity.is_none_or(|e| f.entity == e));
+    match (matches.next(), matches.next()) {
+        (Some(found), None) => Ok(found),
+        _ => {
+            let allowed: Vec<String> = SUGGEST_FIELDS.iter().map(|f| format!("{}.{}", f.entity, f.field)).collect();
+            Err(ApiError::bad_request(&format!(
+                "Suggestions are not available for '{}{}'. Allowed: {}",
+                entity.map(|e| format!("{}.", e)).unwrap_or_default(),
+                field,
+                allowed.join(", ")
+            )))
+        }
+    }
+}
+
+// ==================== CACHE ====================
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Suggestion {
+    pub value: String,
+    /// Сколько записей использует это значение (все написания)
+    pub count: i64,
+}
+
+/// Значения пары и момент их загрузки
+type CachedValues = (Instant, Arc<Vec<Suggestion>>);
+
+/// Значения пар сущность/поле, упорядоченные по частоте
+#[derive(Default)]
+pub struct SuggestCache {
+    entries: Mutex<HashMap<(&'static str, &'static str), CachedValues>>,
+}
+
+impl SuggestCache {
+    async fn values(&self, pool: &SqlitePool, field: &'static SuggestField) -> ApiResult<Arc<Vec<Suggestion>>> {
+        let key = (field.entity, field.field);
+        if let Some((loaded_at, values)) = self.entries.lock().unwrap_or_else(|p| p.into_inner()).get(&key) {
+            if loaded_at.elapsed() < CACHE_TTL {
+                return Ok(values.clone());
+            }
+        }
+
+        // Запрос без блокировки: параллельные промахи просто прочитают значения дважды
+        let values = Arc::new(load_values(pool, field).await?);
+        self.entries.lock()
+            .unwrap_or_else(|p| p.into_inner())
+            .insert(key, (Instant::now(), values.clone()));
+        Ok(values)
+    }
+
+    /// Подсказки для префикса `query`; пустой префикс - самые частые значения
+    pub async fn suggest(&self, pool: &SqlitePool, field: &'static SuggestField, query: &str) -> ApiResult<Vec<Suggestion>> {
+        let prefix = query.trim().to_lowercase();
+        let values = self.values(pool, field).await?;
+        Ok(values.iter()
+            .filter(|s| s.value.to_lowercase().starts_with(&prefix))
+            .take(SUGGEST_LIMIT)
+            .cloned()
+            .collect())
+    }
+}
+
+/// Группировка по столбцу идёт по индексу idx_suggest_*; варианты написания сливаются здесь
+async fn load_values(pool: &SqlitePool, field: &SuggestField) -> Result<Vec<Suggestion>, sqlx::Error> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(&format!(
+        "SELECT {column}, COUNT(*) AS uses FROM {table} \
+         WHERE {column} IS NOT NULL AND {filter} \
+         GROUP BY {column} ORDER BY uses DESC, {column} LIMIT ?",
+        column = field.field,
+        table = field.table,
+        filter = field.filter,
+    ))
+        .bind(MAX_CACHED_VALUES)
+        .fetch_all(pool)
+        .await?;
+
+    // Строки уже по убыванию частоты, поэтому первое написание варианта - самое частое
+    let mut merged: Vec<Suggestion> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (value, uses) in rows {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match positions.get(&value.to_lowercase()) {
+            Some(&i) => merged[i].count += uses,
+            None => {
+                positions.insert(value.to_lowercase(), merged.len());
+                merged.push(Suggestion { value: value.to_string(), count: uses });
+            }
+        }
+    }
+    merged.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    Ok(merged)
+}
+
+// ==================== HANDLER ====================
+
+#[derive(Debug, Deserialize, Default)]
+pub struct SuggestQuery {
+    pub q: Option<String>,
+    pub entity: Option<String>,
+}
+
+/// GET /suggest/{field}?q=&entity=
+pub async fn get_suggestions(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<SuggestQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = crate::auth::get_current_user(&http_request)?;
+    let field = find_field(query.entity.as_deref(), &path.into_inner())?;
+    access_control::authorize(&claims, field.resource, Action::View, &UserRole::Viewer, &app_state.db_pool).await?;
+
+    let q = query.q.as_deref().unwrap_or("");
+    if q.chars().count() > MAX_QUERY_LEN {
+        return Err(ApiError::bad_request(&format!("q cannot exceed {} characters", MAX_QUERY_LEN)));
+    }
+    let suggestions = SUGGEST_CACHE.suggest(app_state.read_pool(), field, q).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(suggestions)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{fixtures, TestApp};
+
+    #[test]
+    fn test_only_whitelisted_fields() {
+        assert_eq!(find_field(None, "supplier").unwrap().entity, "batches");
+        assert_eq!(find_field(Some("equipment"), "manufacturer").unwrap().table, "equipment");
+        // Производитель партий и пароли пользователей в список не входят
+        assert!(find_field(Some("batches"), "manufacturer").is_err());
+        assert!(find_field(Some("users"), "password_hash").is_err());
+        assert!(find_field(None, "id").is_err());
+        assert!(find_field(Some("experiments"), "supplier").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_prefix_match_by_frequency_with_merged_variants() {
+        let app = TestApp::new().await;
+        let pool = app.pool.clone();
+        let suppliers = [
+            Some("Sigma-Aldrich"), Some("Sigma-Aldrich"), Some("sigma-aldrich "), Some("Merck"), Some("Merck"),
+            Some("Merck"), Some("Sigma Chemicals"), Some("  "), None,
+        ];
+        for (i, supplier) in suppliers.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, unit, supplier, \
+                 received_date, status, created_at, updated_at) \
+                 VALUES (?, ?, ?, 1, 1, 'g', ?, datetime('now'), 'available', datetime('now'), datetime('now'))"
+            ).bind(format!("b{}", i)).bind(fixtures::ETHANOL_ID).bind(format!("LOT-{}", i)).bind(supplier).execute(&pool).await.unwrap();
+        }
+        sqlx::query("UPDATE batches SET deleted_at = datetime('now') WHERE id = 'b6'").execute(&pool).await.unwrap();
+
+        let cache = SuggestCache::default();
+        let field = find_field(Some("batches"), "supplier").unwrap();
+        let all = cache.suggest(&pool, field, "").await.unwrap();
+        assert_eq!(all, vec![
+            Suggestion { value: "Merck".to_string(), count: 3 },
+            Suggestion { value: "Sigma-Aldrich".to_string(), count: 3 },
+        ]);
+        let sigma = cache.suggest(&pool, field, " SIG").await.unwrap();
+        assert_eq!(sigma.len(), 1);
+        assert_eq!(sigma[0].value, "Sigma-Aldrich");
+
+        // До истечения TTL новые значения не видны - кэш не ходит в БД на каждый символ
+        sqlx::query("UPDATE batches SET supplier = 'Fisher' WHERE id = 'b3'").execute(&pool).await.unwrap();
+        assert!(cache.suggest(&pool, field, "fi").await.unwrap().is_empty());
+        assert_eq!(SuggestCache::default().suggest(&pool, field, "fi").await.unwrap()[0].value, "Fisher");
+
+        let units = SuggestCache::default().suggest(&pool, find_field(None, "unit").unwrap(), "").await.unwrap();
+        // Плюс партии фикстур: этанол в ml, NaCl в g
+        assert_eq!(units, vec![
+            Suggestion { value: "g".to_string(), count: 9 },
+            Suggestion { value: "ml".to_string(), count: 1 },
+        ]);
+    }
+}
@@ -0,0 +1,400 @@
+// src/support_bundle.rs
+//! Диагностический архив для поддержки: GET /admin/support-bundle?log_lines=200
+//!
+//! Один zip вместо скриншотов: конфигурация без секретов, версия схемы и список миграций,
+//! снимок метрик, последние строки уровня ERROR из файла журнала (если он ведётся),
+//! результат проверки целостности БД, число строк по таблицам и состояние режима обслуживания. Содержимое таблиц
+//! (реагенты, пользователи, эксперименты) в архив не попадает - только счётчики.
+//! Генерация ограничена по частоте и записывается в журнал аудита.
+
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::auth::get_current_user;
+use crate::config::Config;
+use crate::error::{ApiError, ApiResult};
+use crate::monitoring::Metrics;
+use crate::public_catalogue::RateLimiter;
+use crate::AppState;
+
+/// Сколько архивов один администратор может собрать за окно
+const BUNDLES_PER_WINDOW: u32 = 3;
+const BUNDLE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+const DEFAULT_LOG_LINES: usize = 200;
+const MAX_LOG_LINES: usize = 2000;
+/// Журнал читается с конца не больше чем на столько байт
+const LOG_TAIL_BYTES: u64 = 4 * 1024 * 1024;
+
+const MASK: &str = "********";
+/// Строковые значения ключей, содержащих эти подстроки, заменяются маской
+const SECRET_KEY_MARKERS: &[&str] = &["secret", "password", "token", "username"];
+
+lazy_static::lazy_static! {
+    static ref BUNDLE_LIMITER: RateLimiter = RateLimiter::default();
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SupportBundleQuery {
+    /// Сколько последних строк ERROR из журнала включить (по умолчанию 200, не больше 2000)
+    pub log_lines: Option<usize>,
+}
+
+// ==================== CONTENTS ====================
+
+/// Конфигурация как JSON с замаскированными секретами; у URL вебхуков остаётся только схема и хост
+pub fn sanitized_config(config: &Config) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or(Value::Null);
+    mask_secrets(&mut value);
+    value
+}
+
+fn mask_secrets(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if SECRET_KEY_MARKERS.iter().any(|marker| key.contains(marker)) {
+                    if matches!(item, Value::String(s) if !s.is_empty()) {
+                        *item = Value::String(MASK.to_string());
+                    }
+                } else if key == "url" {
+                    if let Value::String(url) = item {
+                        *url = url_origin(url);
+                    }
+                } else {
+                    mask_secrets(item);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(mask_secrets),
+        _ => {}
+    }
+}
+
+/// "https://hooks.example.com/T000/secret-path?x=1" -> "https://hooks.example.com/…";
+/// не-HTTP значения (путь к файлу SQLite) остаются как есть
+fn url_origin(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://").filter(|(s, _)| s.starts_with("http")) else {
+        return url.to_string();
+    };
+    // user:password@host тоже отбрасывается
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    if host.len() == rest.len() {
+        format!("{}://{}", scheme, host)
+    } else {
+        format!("{}://{}/…", scheme, host)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableCount {
+    pub table: String,
+    pub rows: i64,
+}
+
+/// Число строк в каждой таблице (без служебных sqlite_*)
+pub async fn table_counts(pool: &SqlitePool) -> Result<Vec<TableCount>, sqlx::Error> {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+    )
+        .fetch_all(pool)
+        .await?;
+    let mut counts = Vec::with_capacity(tables.len());
+    for table in tables {
+        let sql = format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\""));
+        let rows: i64 = sqlx::query_scalar(&sql).fetch_one(pool).await?;
+        counts.push(TableCount { table, rows });
+    }
+    Ok(counts)
+}
+
+#[derive(Debug, Serialize)]
+pub struct IntegritySummary {
+    /// PRAGMA quick_check вернул "ok"
+    pub ok: bool,
+    /// Сообщения quick_check, если проверка не прошла (не больше 20)
+    pub problems: Vec<String>,
+    /// Нарушения внешних ключей по таблицам (только количество)
+    pub foreign_key_violations: Vec<TableCount>,
+}
+
+pub async fn integrity_summary(pool: &SqlitePool) -> Result<IntegritySummary, sqlx::Error> {
+    let mut problems: Vec<String> = sqlx::query_scalar("PRAGMA quick_check(20)").fetch_all(pool).await?;
+    let ok = problems.len() == 1 && problems[0] == "ok";
+    if ok {
+        problems.clear();
+    }
+    let foreign_key_violations: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT \"table\", COUNT(*) FROM pragma_foreign_key_check GROUP BY \"table\" ORDER BY \"table\""
+    )
+        .fetch_all(pool)
+        .await?;
+    Ok(IntegritySummary {
+        ok,
+        problems,
+        foreign_key_violations: foreign_key_violations.into_iter()
+            .map(|(table, rows)| TableCount { table, rows })
+            .collect(),
+    })
+}
+
+/// Последние `limit` строк уровня ERROR из хвоста файла журнала
+pub fn recent_error_lines(path: &str, limit: usize) -> std::io::Result<Vec<String>> {
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let start = len.saturating_sub(LOG_TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    let text = String::from_utf8_lossy(&buffer);
+
+    let mut lines: Vec<&str> = text.lines()
+        // Первая строка хвоста может быть обрезана посередине
+        .skip(usize::from(start > 0))
+        .filter(|line| line.split_whitespace().take(3).any(|word| word == "ERROR"))
+        .collect();
+    let skip = lines.len().saturating_sub(limit);
+    Ok(lines.drain(skip..).map(str::to_string).collect())
+}
+
+fn error_log_section(config: &Config, limit: usize) -> String {
+    let path = match config.logging.file_path.as_deref().filter(|p| !p.trim().is_empty()) {
+        Some(path) if config.logging.file_enabled => path,
+        _ => return "# File logging is disabled; no log lines included\n".to_string(),
+    };
+    match recent_error_lines(path, limit) {
+        Ok(lines) if lines.is_empty() => format!("# No ERROR lines in the tail of {}\n", path),
+        Ok(lines) => lines.join("\n") + "\n",
+        Err(e) => format!("# Log file {} could not be read: {}\n", path, e),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleManifest {
+    pub generated_at: DateTime<Utc>,
+    pub generated_by: String,
+    pub version: &'static str,
+    pub files: Vec<&'static str>,
+    /// Разделы, которые собрать не удалось (раздел пропускается, архив всё равно отдаётся)
+    pub errors: Vec<String>,
+}
+
+/// Собирает архив; недоступный раздел (например, метрики вне сервера) попадает в manifest.errors
+pub async fn build_bundle(
+    pool: &SqlitePool,
+    config: &Config,
+    metrics: Option<Value>,
+    generated_by: &str,
+    log_lines: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let mut manifest = BundleManifest {
+        generated_at: Utc::now(),
+        generated_by: generated_by.to_string(),
+        version: env!("CARGO_PKG_VERSION"),
+        files: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut sections: Vec<(&'static str, Vec<u8>)> = vec![
+        ("config.json", serde_json::to_vec_pretty(&sanitized_config(config))?),
+    ];
+    match crate::schema::schema_status(pool).await {
+        Ok(status) => sections.push(("schema.json", serde_json::to_vec_pretty(&status)?)),
+        Err(e) => manifest.errors.push(format!("schema.json: {}", e)),
+    }
+    match crate::maintenance_mode::load_mode(pool).await {
+        Ok(mode) => sections.push(("maintenance_mode.json", serde_json::to_vec_pretty(&mode)?)),
+        Err(e) => manifest.errors.push(format!("maintenance_mode.json: {}", e)),
+    }
+    match metrics {
+        Some(metrics) => sections.push(("metrics.json", serde_json::to_vec_pretty(&metrics)?)),
+        None => manifest.errors.push("metrics.json: metrics are not collected in this process".to_string()),
+    }
+    sections.push(("errors.log", error_log_section(config, log_lines).into_bytes()));
+    match integrity_summary(pool).await {
+        Ok(summary) => sections.push(("integrity.json", serde_json::to_vec_pretty(&summary)?)),
+        Err(e) => manifest.errors.push(format!("integrity.json: {}", e)),
+    }
+    match table_counts(pool).await {
+        Ok(counts) => sections.push(("table_counts.json", serde_json::to_vec_pretty(&counts)?)),
+        Err(e) => manifest.errors.push(format!("table_counts.json: {}", e)),
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in &sections {
+        zip.start_file(*name, options)?;
+        zip.write_all(content)?;
+        manifest.files.push(name);
+    }
+    zip.start_file("manifest.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    Ok(zip.finish()?.into_inner())
+}
+
+// ==================== HANDLER ====================
+
+/// GET /admin/support-bundle - zip отдаётся файлом; не чаще BUNDLES_PER_WINDOW раз за окно на администратора
+pub async fn get_support_bundle(
+    app_state: web::Data<Arc<AppState>>,
+    query: web::Query<SupportBundleQuery>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let user_id = get_current_user(&http_request)?.sub;
+    if let Err(retry_after_secs) = BUNDLE_LIMITER.check(&user_id, BUNDLES_PER_WINDOW, BUNDLE_WINDOW, Instant::now()) {
+        return Err(ApiError::TooManyRequests {
+            message: "Support bundle was generated recently; retry later".to_string(),
+            retry_after_secs,
+        });
+    }
+    let log_lines = query.log_lines.unwrap_or(DEFAULT_LOG_LINES).min(MAX_LOG_LINES);
+
+    // Metrics регистрируются только сервером (в тестовом приложении их нет)
+    let metrics = match http_request.app_data::<web::Data<Metrics>>() {
+        Some(metrics) => {
+            let snapshot = crate::monitoring::collect_metrics(metrics, &app_state).await;
+            Some(serde_json::to_value(snapshot).map_err(|e| ApiError::internal_error(e.to_string()))?)
+        }
+        None => None,
+    };
+    let bundle = build_bundle(&app_state.db_pool, &app_state.config, metrics, &user_id, log_lines)
+        .await
+        .map_err(|e| ApiError::internal_error(format!("Failed to build support bundle: {}", e)))?;
+
+    crate::audit::audit(
+        &app_state.db_pool, &user_id, "support_bundle", "system", "support_bundle",
+        &format!("Support bundle generated ({} bytes)", bundle.len()),
+        &http_request,
+    ).await;
+
+    let filename = format!("lims-support-{}.zip", Utc::now().format("%Y%m%d-%H%M%S"));
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)))
+        .body(bundle))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::test_support::{fixtures, TestApp};
+    use actix_web::http::StatusCode;
+    use actix_web::HttpMessage;
+
+    fn admin_request() -> HttpRequest {
+        let req = actix_web::test::TestRequest::get().to_http_request();
+        req.extensions_mut().insert(crate::auth::Claims {
+            sub: fixtures::ADMIN_ID.to_string(),
+            username: "fx_admin".to_string(),
+            email: "fx-admin@example.com".to_string(),
+            role: UserRole::Admin,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    fn read_entry(archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut content = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_config_secrets_are_masked() {
+        let mut config = Config::default();
+        config.auth.jwt_secret = "super-secret-jwt-value-0123456789".to_string();
+        config.smtp.password = Some("smtp-pass".to_string());
+        config.public_catalogue.access_token = Some("catalogue-token".to_string());
+        config.outbox.webhooks = vec![crate::config::WebhookConfig {
+            url: "https://user:pw@hooks.example.com/T000/B000/XXXX?x=1".to_string(),
+            secret: Some("hmac-key".to_string()),
+            events: vec![],
+        }];
+
+        let value = sanitized_config(&config);
+        let text = value.to_string();
+        for secret in ["super-secret-jwt-value", "smtp-pass", "catalogue-token", "hmac-key", "T000", "pw@"] {
+            assert!(!text.contains(secret), "{} leaked: {}", secret, text);
+        }
+        assert_eq!(value["auth"]["jwt_secret"], MASK);
+        assert_eq!(value["outbox"]["webhooks"][0]["url"], "https://hooks.example.com/…");
+        // Нестроковые значения ключей с "token" не трогаются
+        assert_eq!(value["auth"]["token_expiration_hours"], config.auth.token_expiration_hours);
+        assert_eq!(value["database"]["url"], config.database.url.as_str());
+    }
+
+    #[test]
+    fn test_recent_error_lines_takes_last_matches() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "2026-01-01T00:00:00Z  INFO lims: started").unwrap();
+        for i in 0..5 {
+            writeln!(file, "2026-01-01T00:00:0{}Z ERROR lims::db: failure {}", i, i).unwrap();
+        }
+        writeln!(file, "2026-01-01T00:00:09Z  WARN lims: ERROR mentioned in a message").unwrap();
+
+        let lines = recent_error_lines(file.path().to_str().unwrap(), 2).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("failure 3"));
+        assert!(lines[1].ends_with("failure 4"));
+    }
+
+    #[actix_web::test]
+    async fn test_bundle_contains_counts_but_no_rows_and_is_audited() {
+        let app = TestApp::new().await;
+        let (status, _) = app.get(UserRole::Researcher, "/admin/support-bundle").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let response = get_support_bundle(app.state(), web::Query(SupportBundleQuery::default()), admin_request())
+            .await.unwrap();
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "application/zip");
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(body.to_vec())).unwrap();
+
+        let manifest: Value = serde_json::from_str(&read_entry(&mut archive, "manifest.json")).unwrap();
+        assert_eq!(manifest["generated_by"], fixtures::ADMIN_ID);
+        assert!(manifest["errors"][0].as_str().unwrap().starts_with("metrics.json"));
+        let counts: Value = serde_json::from_str(&read_entry(&mut archive, "table_counts.json")).unwrap();
+        let reagents = counts.as_array().unwrap().iter().find(|c| c["table"] == "reagents").unwrap();
+        assert_eq!(reagents["rows"], 2);
+        let integrity: Value = serde_json::from_str(&read_entry(&mut archive, "integrity.json")).unwrap();
+        assert_eq!(integrity["ok"], true);
+        let schema: Value = serde_json::from_str(&read_entry(&mut archive, "schema.json")).unwrap();
+        assert_eq!(schema["expected_version"], crate::schema::expected_version());
+        let mode: Value = serde_json::from_str(&read_entry(&mut archive, "maintenance_mode.json")).unwrap();
+        assert_eq!(mode["enabled"], false);
+
+        // Ни одного значения из строк таблиц
+        for i in 0..archive.len() {
+            let mut content = String::new();
+            archive.by_index(i).unwrap().read_to_string(&mut content).unwrap();
+            for leaked in ["Ethanol", "fx-researcher@example.com", "ETH-001"] {
+                assert!(!content.contains(leaked), "{} found in bundle", leaked);
+            }
+        }
+
+        let audited: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_logs WHERE action = 'support_bundle'")
+            .fetch_one(&app.pool).await.unwrap();
+        assert_eq!(audited, 1);
+
+        for _ in 1..BUNDLES_PER_WINDOW {
+            get_support_bundle(app.state(), web::Query(SupportBundleQuery::default()), admin_request()).await.unwrap();
+        }
+        let err = get_support_bundle(app.state(), web::Query(SupportBundleQuery::default()), admin_request())
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::TooManyRequests { .. }));
+    }
+}
@@ -0,0 +1,501 @@
+// src/team_handlers.rs
+//! Команды: совместное владение реагентами, оборудованием и экспериментами.
+//!
+//! Команды создают и удаляют администраторы, составом управляют лиды команды
+//! (role = 'lead') и администраторы. Объекты с `team_id` изменяют все участники
+//! команды наравне с автором; в ответах рядом с `team_id` отдаётся `team_name`.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::{get_current_user, Claims, UserRole};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{
+    AddTeamMemberRequest, CreateTeamRequest, Equipment, Experiment, Reagent, Team,
+    TeamDetailResponse, TeamMember, UpdateTeamRequest, TEAM_ROLE_LEAD, TEAM_ROLE_MEMBER,
+};
+use crate::AppState;
+
+/// `?mine=true`: создано пользователем или одной из его команд; параметры - user_id дважды
+pub const MINE_CONDITION: &str =
+    "(created_by = ? OR team_id IN (SELECT team_id FROM team_members WHERE user_id = ?))";
+
+const TEAM_SELECT: &str = r#"
+    SELECT t.id, t.name, t.description, t.created_by, t.created_at, t.updated_at,
+           (SELECT COUNT(*) FROM team_members m WHERE m.team_id = t.id) AS members_count
+    FROM teams t
+"#;
+
+// ==================== OWNERSHIP HELPERS ====================
+
+pub async fn is_team_member(pool: &SqlitePool, team_id: &str, user_id: &str) -> ApiResult<bool> {
+    let member: Option<(i64,)> = sqlx::query_as(
+        "SELECT 1 FROM team_members WHERE team_id = ? AND user_id = ?"
+    )
+        .bind(team_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(member.is_some())
+}
+
+/// Пустой team_id в запросе означает «без команды»
+pub fn normalize_team_id(team_id: Option<&str>) -> Option<&str> {
+    team_id.map(str::trim).filter(|t| !t.is_empty())
+}
+
+/// team_id из запроса должен ссылаться на существующую команду
+pub async fn ensure_team_exists(pool: &SqlitePool, team_id: Option<&str>) -> ApiResult<()> {
+    if let Some(team_id) = team_id {
+        let exists: Option<(String,)> = sqlx::query_as("SELECT id FROM teams WHERE id = ?")
+            .bind(team_id)
+            .fetch_optional(pool)
+            .await?;
+        if exists.is_none() {
+            return Err(ApiError::not_found("Team"));
+        }
+    }
+    Ok(())
+}
+
+// ==================== TEAM NAME EXPANSION ====================
+
+/// Объект с командой-владельцем, для которого в ответе раскрывается название команды
+pub trait TeamOwned {
+    fn team_id(&self) -> Option<&str>;
+    fn set_team_name(&mut self, name: Option<String>);
+}
+
+macro_rules! impl_team_owned {
+    ($($ty:ty),*) => {
+        $(impl TeamOwned for $ty {
+            fn team_id(&self) -> Option<&str> {
+                self.team_id.as_deref()
+            }
+            fn set_team_name(&mut self, name: Option<String>) {
+                self.team_name = name;
+            }
+        })*
+    };
+}
+
+impl_team_owned!(Reagent, Equipment, Experiment, crate::reagent_handlers::ReagentListItem);
+
+impl TeamOwned for crate::equipment_handlers::EquipmentListItem {
+    fn team_id(&self) -> Option<&str> {
+        self.equipment.team_id()
+    }
+    fn set_team_name(&mut self, name: Option<String>) {
+        self.equipment.set_team_name(name);
+    }
+}
+
+/// Заполнить team_name одним запросом на всю выборку
+pub async fn expand_team_names<T: TeamOwned>(pool: &SqlitePool, items: &mut [T]) -> ApiResult<()> {
+    let mut ids: Vec<&str> = items.iter().filter_map(TeamOwned::team_id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let sql = format!(
+        "SELECT id, name FROM teams WHERE id IN ({})",
+        vec!["?"; ids.len()].join(", ")
+    );
+    let mut query = sqlx::query_as::<_, (String, String)>(&sql);
+    for id in &ids {
+        query = query.bind(*id);
+    }
+    let names: HashMap<String, String> = query.fetch_all(pool).await?.into_iter().collect();
+
+    for item in items.iter_mut() {
+        let name = item.team_id().and_then(|id| names.get(id).cloned());
+        item.set_team_name(name);
+    }
+    Ok(())
+}
+
+// ==================== TEAMS CRUD ====================
+
+async fn fetch_team(pool: &SqlitePool, team_id: &str) -> ApiResult<Team> {
+    sqlx::query_as::<_, Team>(&format!("{} WHERE t.id = ?", TEAM_SELECT))
+        .bind(team_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Team"))
+}
+
+async fn fetch_members(pool: &SqlitePool, team_id: &str) -> ApiResult<Vec<TeamMember>> {
+    let members = sqlx::query_as::<_, TeamMember>(
+        r#"SELECT m.user_id, u.username, m.role, m.added_by, m.created_at
+           FROM team_members m
+           JOIN users u ON u.id = m.user_id
+           WHERE m.team_id = ?
+           ORDER BY m.role = 'lead' DESC, u.username COLLATE NOCASE"#
+    )
+        .bind(team_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(members)
+}
+
+fn duplicate_team_name(e: sqlx::Error) -> ApiError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            ApiError::bad_request("A team with this name already exists")
+        }
+        other => ApiError::from(other),
+    }
+}
+
+pub async fn list_teams(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let teams = sqlx::query_as::<_, Team>(&format!("{} ORDER BY t.name COLLATE NOCASE", TEAM_SELECT))
+        .fetch_all(&app_state.db_pool)
+        .await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(teams)))
+}
+
+pub async fn get_team(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+) -> ApiResult<HttpResponse> {
+    let team_id = path.into_inner();
+    let team = fetch_team(&app_state.db_pool, &team_id).await?;
+    let members = fetch_members(&app_state.db_pool, &team_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(TeamDetailResponse { team, members })))
+}
+
+pub async fn create_team(
+    app_state: web::Data<Arc<AppState>>,
+    body: web::Json<CreateTeamRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+
+    sqlx::query(
+        "INSERT INTO teams (id, name, description, created_by, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)"
+    )
+        .bind(&id)
+        .bind(body.name.trim())
+        .bind(&body.description)
+        .bind(&claims.sub)
+        .bind(now)
+        .bind(now)
+        .execute(&app_state.db_pool)
+        .await
+        .map_err(duplicate_team_name)?;
+
+    crate::audit::audit(
+        &app_state.db_pool, &claims.sub, "create", "team", &id,
+        &format!("Created team '{}'", body.name.trim()), &http_request,
+    ).await;
+
+    let team = fetch_team(&app_state.db_pool, &id).await?;
+    Ok(HttpResponse::Created().json(ApiResponse::success(team)))
+}
+
+pub async fn update_team(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<UpdateTeamRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let team_id = path.into_inner();
+    let existing = fetch_team(&app_state.db_pool, &team_id).await?;
+
+    let name = body.name.as_deref().map(str::trim).unwrap_or(&existing.name);
+    let description = match &body.description {
+        Some(description) => description.clone(),
+        None => existing.description.clone(),
+    };
+
+    sqlx::query("UPDATE teams SET name = ?, description = ?, updated_at = ? WHERE id = ?")
+        .bind(name)
+        .bind(&description)
+        .bind(Utc::now())
+        .bind(&team_id)
+        .execute(&app_state.db_pool)
+        .await
+        .map_err(duplicate_team_name)?;
+
+    let mut changes = crate::audit::ChangeSet::new();
+    changes.add("name", &existing.name, name);
+    changes.add_opt("description", &existing.description, &description);
+    crate::audit::audit_with_changes(
+        &app_state.db_pool, &claims.sub, "update", "team", &team_id,
+        &format!("Updated team '{}'", name), &changes, &http_request,
+    ).await;
+
+    let team = fetch_team(&app_state.db_pool, &team_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(team)))
+}
+
+/// Объекты команды остаются у авторов: team_id обнуляется в той же транзакции
+pub async fn delete_team(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let team_id = path.into_inner();
+    let existing = fetch_team(&app_state.db_pool, &team_id).await?;
+
+    let mut tx = app_state.db_pool.begin().await?;
+    for table in ["reagents", "equipment", "experiments"] {
+        sqlx::query(&format!("UPDATE {} SET team_id = NULL WHERE team_id = ?", table))
+            .bind(&team_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("DELETE FROM team_members WHERE team_id = ?")
+        .bind(&team_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM teams WHERE id = ?")
+        .bind(&team_id)
+        .execute(&mut *tx)
+        .await?;
+    crate::audit::log_activity(
+        &mut *tx, Some(&claims.sub), "delete", "team", Some(&team_id),
+        Some(&format!("Deleted team '{}'", existing.name)), None, Some(&http_request),
+    ).await?;
+    tx.commit().await?;
+
+    Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+        (),
+        "Team deleted successfully".to_string(),
+    )))
+}
+
+// ==================== MEMBERSHIP ====================
+
+/// Составом управляют администраторы и лиды этой команды
+async fn ensure_can_manage_members(pool: &SqlitePool, team_id: &str, claims: &Claims) -> ApiResult<()> {
+    fetch_team(pool, team_id).await?;
+    if claims.role == UserRole::Admin {
+        return Ok(());
+    }
+    let role: Option<(String,)> = sqlx::query_as(
+        "SELECT role FROM team_members WHERE team_id = ? AND user_id = ?"
+    )
+        .bind(team_id)
+        .bind(&claims.sub)
+        .fetch_optional(pool)
+        .await?;
+    match role {
+        Some((role,)) if role == TEAM_ROLE_LEAD => Ok(()),
+        _ => Err(ApiError::Forbidden(
+            "Only team leads and administrators can manage team membership".to_string()
+        )),
+    }
+}
+
+/// Добавить участника или изменить его роль (по умолчанию member)
+pub async fn add_team_member(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    body: web::Json<AddTeamMemberRequest>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    body.validate()?;
+    let claims = get_current_user(&http_request)?;
+    let team_id = path.into_inner();
+    let pool = &app_state.db_pool;
+    ensure_can_manage_members(pool, &team_id, &claims).await?;
+
+    let user: Option<(String,)> = sqlx::query_as("SELECT username FROM users WHERE id = ?")
+        .bind(&body.user_id)
+        .fetch_optional(pool)
+        .await?;
+    let (username,) = user.ok_or_else(|| ApiError::not_found("User"))?;
+    let role = body.role.as_deref().unwrap_or(TEAM_ROLE_MEMBER);
+
+    sqlx::query(
+        r#"INSERT INTO team_members (team_id, user_id, role, added_by, created_at)
+           VALUES (?, ?, ?, ?, ?)
+           ON CONFLICT(team_id, user_id) DO UPDATE SET role = excluded.role"#
+    )
+        .bind(&team_id)
+        .bind(&body.user_id)
+        .bind(role)
+        .bind(&claims.sub)
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+    crate::audit::audit(
+        pool, &claims.sub, "add_member", "team", &team_id,
+        &format!("Set {} as {} of the team", username, role), &http_request,
+    ).await;
+
+    let members = fetch_members(pool, &team_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(members)))
+}
+
+pub async fn remove_team_member(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<(String, String)>,
+    http_request: HttpRequest,
+) -> ApiResult<HttpResponse> {
+    let claims = get_current_user(&http_request)?;
+    let (team_id, user_id) = path.into_inner();
+    let pool = &app_state.db_pool;
+    ensure_can_manage_members(pool, &team_id, &claims).await?;
+
+    let result = sqlx::query("DELETE FROM team_members WHERE team_id = ? AND user_id = ?")
+        .bind(&team_id)
+        .bind(&user_id)
+        .execute(pool)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(ApiError::not_found("Team member"));
+    }
+
+    crate::audit::audit(
+        pool, &claims.sub, "remove_member", "team", &team_id,
+        &format!("Removed user {} from the team", user_id), &http_request,
+    ).await;
+
+    let members = fetch_members(pool, &team_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(members)))
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::HttpMessage;
+
+    async fn test_app_state() -> web::Data<Arc<AppState>> {
+        let app = crate::test_support::TestApp::new().await;
+        for (id, role) in [("admin", "admin"), ("lead", "researcher"), ("member", "researcher"), ("outsider", "researcher")] {
+            sqlx::query(
+                "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) \
+                 VALUES (?, ?, ?, 'x', ?, datetime('now'), datetime('now'))"
+            )
+                .bind(id)
+                .bind(id)
+                .bind(format!("{}@example.com", id))
+                .bind(role)
+                .execute(&app.pool)
+                .await
+                .unwrap();
+        }
+        app.state()
+    }
+
+    fn request_as(user_id: &str, role: UserRole) -> HttpRequest {
+        let req = actix_web::test::TestRequest::get().to_http_request();
+        req.extensions_mut().insert(Claims {
+            sub: user_id.to_string(),
+            username: user_id.to_string(),
+            email: format!("{}@example.com", user_id),
+            role,
+            exp: i64::MAX,
+            iat: 0,
+        });
+        req
+    }
+
+    async fn body_json(resp: HttpResponse) -> serde_json::Value {
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    async fn create(state: &web::Data<Arc<AppState>>, name: &str) -> String {
+        let resp = create_team(
+            state.clone(),
+            web::Json(CreateTeamRequest { name: name.to_string(), description: None }),
+            request_as("admin", UserRole::Admin),
+        ).await.unwrap();
+        body_json(resp).await["data"]["id"].as_str().unwrap().to_string()
+    }
+
+    async fn add(state: &web::Data<Arc<AppState>>, team_id: &str, actor: HttpRequest, user_id: &str, role: &str) -> ApiResult<HttpResponse> {
+        add_team_member(
+            state.clone(),
+            web::Path::from(team_id.to_string()),
+            web::Json(AddTeamMemberRequest { user_id: user_id.to_string(), role: Some(role.to_string()) }),
+            actor,
+        ).await
+    }
+
+    #[actix_web::test]
+    async fn test_team_crud_and_membership_rules() {
+        let state = test_app_state().await;
+        let team_id = create(&state, "Analytical Chemistry").await;
+
+        // Имя уникально без учёта регистра
+        let duplicate = create_team(
+            state.clone(),
+            web::Json(CreateTeamRequest { name: "analytical chemistry".to_string(), description: None }),
+            request_as("admin", UserRole::Admin),
+        ).await.unwrap_err();
+        assert!(matches!(duplicate, ApiError::BadRequest(_)));
+
+        // Администратор назначает лида, лид добавляет участника, участник - никого
+        add(&state, &team_id, request_as("admin", UserRole::Admin), "lead", "lead").await.unwrap();
+        add(&state, &team_id, request_as("lead", UserRole::Researcher), "member", "member").await.unwrap();
+        let err = add(&state, &team_id, request_as("member", UserRole::Researcher), "outsider", "member").await.unwrap_err();
+        assert!(matches!(err, ApiError::Forbidden(_)));
+        assert!(is_team_member(&state.db_pool, &team_id, "member").await.unwrap());
+        assert!(!is_team_member(&state.db_pool, &team_id, "outsider").await.unwrap());
+
+        let resp = get_team(state.clone(), web::Path::from(team_id.clone())).await.unwrap();
+        let json = body_json(resp).await;
+        assert_eq!(json["data"]["members_count"], 2);
+        assert_eq!(json["data"]["members"][0]["username"], "lead");
+
+        remove_team_member(
+            state.clone(),
+            web::Path::from((team_id.clone(), "member".to_string())),
+            request_as("lead", UserRole::Researcher),
+        ).await.unwrap();
+        assert!(!is_team_member(&state.db_pool, &team_id, "member").await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_delete_team_releases_owned_entities_and_expands_names() {
+        let state = test_app_state().await;
+        let team_id = create(&state, "Synthesis").await;
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, team_id, created_at, updated_at) \
+             VALUES ('r1', 'Acetone', 'active', ?, datetime('now'), datetime('now')), \
+                    ('r2', 'Methanol', 'active', NULL, datetime('now'), datetime('now'))"
+        )
+            .bind(&team_id)
+            .execute(&state.db_pool)
+            .await
+            .unwrap();
+
+        let mut reagents: Vec<Reagent> = sqlx::query_as("SELECT * FROM reagents WHERE id IN ('r1', 'r2') ORDER BY id")
+            .fetch_all(&state.db_pool)
+            .await
+            .unwrap();
+        expand_team_names(&state.db_pool, &mut reagents).await.unwrap();
+        assert_eq!(reagents[0].team_name.as_deref(), Some("Synthesis"));
+        assert_eq!(reagents[1].team_name, None);
+
+        delete_team(state.clone(), web::Path::from(team_id.clone()), request_as("admin", UserRole::Admin))
+            .await
+            .unwrap();
+        let (owner,): (Option<String>,) = sqlx::query_as("SELECT team_id FROM reagents WHERE id = 'r1'")
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(owner, None);
+    }
+}
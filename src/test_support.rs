@@ -0,0 +1,336 @@
+// src/test_support.rs
+//! Тестовый стенд для хендлеров: приложение actix с теми же маршрутами и middleware,
+//! что и сервер (`configure_api`), поверх in-memory SQLite с миграциями и
+//! детерминированным набором фикстур.
+//!
+//! ```ignore
+//! let app = TestApp::new().await;
+//! let (status, body) = app.post(UserRole::Researcher, "/reagents", json!({ "name": "Toluene" })).await;
+//! ```
+//!
+//! Пути запросов относительны `/api/v1`. Каждый вызов идёт с JWT пользователя фикстуры
+//! нужной роли, так что проверяется и таблица прав (`access_control::ROUTE_PERMISSIONS`).
+
+use actix_web::http::{Method, StatusCode};
+use actix_web::{test, web, App};
+use serde_json::Value;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+use crate::auth::{AuthService, User, UserRole};
+use crate::query_log::{install_statement_capture, StatementCapture};
+use crate::AppState;
+
+const TEST_JWT_SECRET: &str = "test-support-jwt-secret-with-enough-length";
+
+/// Идентификаторы фикстур
+pub mod fixtures {
+    pub const ADMIN_ID: &str = "fx-admin";
+    pub const RESEARCHER_ID: &str = "fx-researcher";
+    pub const VIEWER_ID: &str = "fx-viewer";
+
+    /// Этанол: одна доступная партия 1000 ml
+    pub const ETHANOL_ID: &str = "fx-reagent-ethanol";
+    pub const ETHANOL_BATCH_ID: &str = "fx-batch-ethanol-1";
+    /// Хлорид натрия: одна доступная партия 500 g
+    pub const NACL_ID: &str = "fx-reagent-nacl";
+    pub const NACL_BATCH_ID: &str = "fx-batch-nacl-1";
+
+    pub const CENTRIFUGE_ID: &str = "fx-equipment-centrifuge";
+    pub const ROOM_ID: &str = "fx-room-lab-1";
+    /// Запланированный эксперимент без реагентов, автор - исследователь
+    pub const EXPERIMENT_ID: &str = "fx-experiment-titration";
+}
+
+const FIXTURES: &[&str] = &[
+    "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) VALUES \
+     ('fx-admin', 'fx_admin', 'fx-admin@example.com', 'x', 'admin', datetime('now'), datetime('now')), \
+     ('fx-researcher', 'fx_researcher', 'fx-researcher@example.com', 'x', 'researcher', datetime('now'), datetime('now')), \
+     ('fx-viewer', 'fx_viewer', 'fx-viewer@example.com', 'x', 'viewer', datetime('now'), datetime('now'))",
+    "INSERT INTO reagents (id, name, formula, status, created_by, created_at, updated_at) VALUES \
+     ('fx-reagent-ethanol', 'Ethanol', 'C2H5OH', 'active', 'fx-admin', datetime('now'), datetime('now')), \
+     ('fx-reagent-nacl', 'Sodium chloride', 'NaCl', 'active', 'fx-admin', datetime('now'), datetime('now'))",
+    // REAL-литералы, как при создании через API: целые в SQLite не читаются как f64
+    "INSERT INTO batches (id, reagent_id, batch_number, quantity, original_quantity, reserved_quantity, unit, status, \
+     received_date, created_by, created_at, updated_at) VALUES \
+     ('fx-batch-ethanol-1', 'fx-reagent-ethanol', 'ETH-001', 1000.0, 1000.0, 0.0, 'ml', 'available', \
+      datetime('now'), 'fx-admin', datetime('now'), datetime('now')), \
+     ('fx-batch-nacl-1', 'fx-reagent-nacl', 'NACL-001', 500.0, 500.0, 0.0, 'g', 'available', \
+      datetime('now'), 'fx-admin', datetime('now'), datetime('now'))",
+    "INSERT INTO equipment (id, name, type_, quantity, status, created_at, updated_at) VALUES \
+     ('fx-equipment-centrifuge', 'Centrifuge', 'equipment', 1, 'available', datetime('now'), datetime('now'))",
+    "INSERT INTO rooms (id, name, capacity, status, created_at, updated_at) VALUES \
+     ('fx-room-lab-1', 'Lab 1', 20, 'available', datetime('now'), datetime('now'))",
+    "INSERT INTO experiments (id, title, experiment_date, start_date, status, experiment_type, room_id, \
+     created_by, created_at, updated_at) VALUES \
+     ('fx-experiment-titration', 'Titration', datetime('now'), datetime('now'), 'planned', 'research', \
+      'fx-room-lab-1', 'fx-researcher', datetime('now'), datetime('now'))",
+];
+
+pub struct TestApp {
+    pub pool: SqlitePool,
+    /// SQL, выполненный через `pool` (включая миграции и фикстуры - сбросьте `take()` перед проверкой)
+    pub statements: Arc<StatementCapture>,
+    state: web::Data<Arc<AppState>>,
+    auth_service: web::Data<Arc<AuthService>>,
+    maintenance_mode: web::Data<crate::maintenance_mode::MaintenanceModeStore>,
+}
+
+impl TestApp {
+    /// Пустая in-memory БД с миграциями и фикстурами
+    pub async fn new() -> Self {
+        Self::with_config(crate::config::Config::default()).await
+    }
+
+    /// То же, что `new`, с заданной конфигурацией (таймауты, лимиты и т.п.)
+    pub async fn with_config(config: crate::config::Config) -> Self {
+        let statements = Arc::new(StatementCapture::default());
+        let capture = statements.clone();
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .after_connect(move |conn, _meta| {
+                let capture = capture.clone();
+                Box::pin(async move { install_statement_capture(conn, capture).await })
+            })
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        for sql in FIXTURES {
+            sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+
+        Self {
+            state: web::Data::new(Arc::new(AppState {
+                db_pool: pool.clone(),
+                config,
+                read_replica: None,
+                reference_cache: Default::default(),
+            })),
+            auth_service: web::Data::new(Arc::new(AuthService::new(TEST_JWT_SECRET))),
+            maintenance_mode: web::Data::new(Default::default()),
+            pool,
+            statements,
+        }
+    }
+
+    pub fn state(&self) -> web::Data<Arc<AppState>> {
+        self.state.clone()
+    }
+
+    pub fn auth_service(&self) -> web::Data<Arc<AuthService>> {
+        self.auth_service.clone()
+    }
+
+    pub fn user_id(role: UserRole) -> &'static str {
+        match role {
+            UserRole::Admin => fixtures::ADMIN_ID,
+            UserRole::Researcher => fixtures::RESEARCHER_ID,
+            UserRole::Viewer => fixtures::VIEWER_ID,
+        }
+    }
+
+    /// Bearer-токен пользователя фикстуры с этой ролью
+    pub async fn token(&self, role: UserRole) -> String {
+        let user: User = sqlx::query_as("SELECT * FROM users WHERE id = ?")
+            .bind(Self::user_id(role))
+            .fetch_one(&self.pool)
+            .await
+            .unwrap();
+        self.auth_service.generate_token(&user).unwrap()
+    }
+
+    /// Запрос к `/api/v1{path}` от имени роли; тело ответа разбирается как JSON (Null, если пусто)
+    pub async fn request(&self, role: UserRole, method: Method, path: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(self.state.clone())
+                .app_data(self.auth_service.clone())
+                .app_data(self.maintenance_mode.clone())
+                .configure(crate::configure_api),
+        ).await;
+
+        let mut req = test::TestRequest::default()
+            .method(method)
+            .uri(&format!("{}{}", crate::access_control::API_PREFIX, path))
+            .insert_header(("Authorization", format!("Bearer {}", self.token(role).await)));
+        if let Some(body) = body {
+            req = req.set_json(body);
+        }
+
+        // Отказы middleware (401/403) приходят ошибкой сервиса, а не ответом
+        let resp = match test::try_call_service(&app, req.to_request()).await {
+            Ok(resp) => resp.into_parts().1,
+            Err(err) => err.error_response().map_into_boxed_body(),
+        };
+        let status = resp.status();
+        let bytes = actix_web::body::to_bytes(resp.into_body()).await.unwrap_or_default();
+        let json = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+        };
+        (status, json)
+    }
+
+    pub async fn get(&self, role: UserRole, path: &str) -> (StatusCode, Value) {
+        self.request(role, Method::GET, path, None).await
+    }
+
+    pub async fn post(&self, role: UserRole, path: &str, body: Value) -> (StatusCode, Value) {
+        self.request(role, Method::POST, path, Some(body)).await
+    }
+
+    pub async fn put(&self, role: UserRole, path: &str, body: Value) -> (StatusCode, Value) {
+        self.request(role, Method::PUT, path, Some(body)).await
+    }
+
+    pub async fn delete(&self, role: UserRole, path: &str) -> (StatusCode, Value) {
+        self.request(role, Method::DELETE, path, None).await
+    }
+
+    /// Текущий остаток и резерв партии
+    pub async fn batch_stock(&self, batch_id: &str) -> (f64, f64) {
+        sqlx::query_as("SELECT quantity, reserved_quantity FROM batches WHERE id = ?")
+            .bind(batch_id)
+            .fetch_one(&self.pool)
+            .await
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::*;
+    use super::*;
+    use serde_json::json;
+
+    #[actix_web::test]
+    async fn test_fixtures_are_visible_through_the_api() {
+        let app = TestApp::new().await;
+
+        let (status, body) = app.get(UserRole::Viewer, &format!("/reagents/{}", ETHANOL_ID)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["name"], "Ethanol");
+
+        let (status, body) = app.get(UserRole::Viewer, &format!("/experiments/{}", EXPERIMENT_ID)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        for path in [format!("/equipment/{}", CENTRIFUGE_ID), format!("/rooms/{}", ROOM_ID)] {
+            let (status, body) = app.get(UserRole::Viewer, &path).await;
+            assert_eq!(status, StatusCode::OK, "{}: {}", path, body);
+        }
+        assert_eq!(app.batch_stock(NACL_BATCH_ID).await, (500.0, 0.0));
+    }
+
+    #[actix_web::test]
+    async fn test_requests_go_through_auth_and_route_permissions() {
+        let app = TestApp::new().await;
+
+        let (status, _) = app.post(UserRole::Viewer, "/reagents", json!({ "name": "Toluene" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let unauthenticated = test::init_service(
+            App::new()
+                .app_data(app.state())
+                .app_data(app.auth_service.clone())
+                .configure(crate::configure_api),
+        ).await;
+        let status = match test::try_call_service(
+            &unauthenticated,
+            test::TestRequest::get().uri("/api/v1/reagents").to_request(),
+        ).await {
+            Ok(resp) => resp.status(),
+            Err(err) => err.as_response_error().status_code(),
+        };
+        assert!(matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN), "{}", status);
+    }
+
+    #[actix_web::test]
+    async fn test_reagent_lifecycle() {
+        let app = TestApp::new().await;
+
+        let (status, body) = app.post(UserRole::Researcher, "/reagents", json!({
+            "name": "Toluene",
+            "formula": "C7H8",
+            "cas_number": "108-88-3",
+        })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        let id = body["data"]["id"].as_str().unwrap().to_string();
+
+        let (status, body) = app.put(UserRole::Researcher, &format!("/reagents/{}", id), json!({
+            "storage_conditions": "Flammables cabinet",
+        })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+
+        let (status, body) = app.get(UserRole::Viewer, &format!("/reagents/{}", id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["storage_conditions"], "Flammables cabinet");
+
+        let (status, _) = app.delete(UserRole::Viewer, &format!("/reagents/{}", id)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn test_batch_lifecycle_create_use_and_overdraw() {
+        let app = TestApp::new().await;
+
+        let (status, body) = app.post(UserRole::Researcher, &format!("/reagents/{}/batches", NACL_ID), json!({
+            "batch_number": "NACL-002",
+            "quantity": 250.0,
+            "unit": "g",
+        })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        let batch_id = body["data"]["id"].as_str().unwrap().to_string();
+
+        let use_path = format!("/reagents/{}/batches/{}/use", NACL_ID, batch_id);
+        let (status, body) = app.post(UserRole::Researcher, &use_path, json!({
+            "quantity_used": 100.0,
+            "purpose": "Buffer prep",
+        })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        assert_eq!(app.batch_stock(&batch_id).await.0, 150.0);
+
+        let (status, _) = app.post(UserRole::Researcher, &use_path, json!({ "quantity_used": 1000.0 })).await;
+        assert!(status.is_client_error());
+        assert_eq!(app.batch_stock(&batch_id).await.0, 150.0);
+
+        let (status, _) = app.get(UserRole::Viewer, &format!("/reagents/{}/batches/{}/usage", NACL_ID, batch_id)).await;
+        assert_eq!(status, StatusCode::OK);
+        let usage: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM usage_logs WHERE batch_id = ?")
+            .bind(&batch_id)
+            .fetch_one(&app.pool)
+            .await
+            .unwrap();
+        assert_eq!(usage, 1);
+    }
+
+    #[actix_web::test]
+    async fn test_experiment_lifecycle_reserve_complete_consumes_stock() {
+        let app = TestApp::new().await;
+        let base = format!("/experiments/{}", EXPERIMENT_ID);
+
+        let (status, body) = app.post(UserRole::Researcher, &format!("{}/reagents", base), json!({
+            "batch_id": ETHANOL_BATCH_ID,
+            "quantity_used": 200.0,
+        })).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        assert_eq!(app.batch_stock(ETHANOL_BATCH_ID).await, (1000.0, 200.0));
+
+        // Больше доступного (1000 - 200) не резервируется
+        let (status, _) = app.post(UserRole::Researcher, &format!("{}/reagents", base), json!({
+            "batch_id": ETHANOL_BATCH_ID,
+            "quantity_used": 900.0,
+        })).await;
+        assert!(status.is_client_error());
+
+        let (status, body) = app.post(UserRole::Researcher, &format!("{}/start", base), json!({})).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+        let (status, body) = app.post(UserRole::Researcher, &format!("{}/complete", base), json!({})).await;
+        assert!(status.is_success(), "{}: {}", status, body);
+
+        assert_eq!(app.batch_stock(ETHANOL_BATCH_ID).await, (800.0, 0.0));
+        let (_, body) = app.get(UserRole::Viewer, &base).await;
+        assert_eq!(body["data"]["status"], "completed");
+    }
+}
@@ -0,0 +1,319 @@
+// src/user_identity.rs
+//! Имя пользователя и email учётной записи.
+//!
+//! - значения обрезаются (trim) и проверяются: имя - по `auth.username` конфигурации,
+//!   email - упрощённой проверкой RFC 5321/5322 (`is_valid_email`);
+//! - регистр сохраняется, но сравнение везде без учёта регистра (`COLLATE NOCASE`):
+//!   вход, проверка занятости и уникальные индексы idx_users_username_nocase / idx_users_email_nocase;
+//! - при запуске пробелы по краям сохранённых значений убираются, индексы создаются
+//!   (`normalize_stored_identities`); совпадения без учёта регистра, мешающие индексу,
+//!   выводятся в GET /admin/normalizations/user-identity и остаются на разбор администратору.
+
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use std::sync::Arc;
+
+use crate::config::UsernamePolicyConfig;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::AppState;
+
+/// Поле учётной записи, уникальное без учёта регистра: (колонка, уникальный индекс)
+const IDENTITY_FIELDS: [(&str, &str); 2] = [
+    ("username", "idx_users_username_nocase"),
+    ("email", "idx_users_email_nocase"),
+];
+
+const MAX_EMAIL_LENGTH: usize = 255;
+const MAX_EMAIL_LOCAL_LENGTH: usize = 64;
+
+/// Обрезанное имя пользователя, если оно соответствует политике
+pub fn normalize_username(raw: &str, policy: &UsernamePolicyConfig) -> ApiResult<String> {
+    let username = raw.trim();
+    let length = username.chars().count();
+    if length < policy.min_length || length > policy.max_length {
+        return Err(ApiError::ValidationError(format!(
+            "Username must be {}-{} characters", policy.min_length, policy.max_length
+        )));
+    }
+    if !username.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err(ApiError::ValidationError("Username must start with a letter or digit".to_string()));
+    }
+    if let Some(c) = username.chars().find(|c| !c.is_ascii_alphanumeric() && !policy.allowed_symbols.contains(*c)) {
+        return Err(ApiError::ValidationError(format!(
+            "Username contains '{}'; allowed are Latin letters, digits and '{}'", c, policy.allowed_symbols
+        )));
+    }
+    Ok(username.to_string())
+}
+
+/// Обрезанный email, если он проходит `is_valid_email`
+pub fn normalize_email(raw: &str) -> ApiResult<String> {
+    let email = raw.trim();
+    if !is_valid_email(email) {
+        return Err(ApiError::ValidationError("Invalid email format".to_string()));
+    }
+    Ok(email.to_string())
+}
+
+/// `local@domain`: без пробелов и кавычек, local до 64 символов без точек по краям и подряд,
+/// domain - минимум две метки из букв, цифр и дефисов (не по краям метки)
+pub fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else { return false };
+    if email.len() > MAX_EMAIL_LENGTH || local.is_empty() || local.len() > MAX_EMAIL_LOCAL_LENGTH {
+        return false;
+    }
+    let local_ok = local.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c))
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..");
+    let labels: Vec<&str> = domain.split('.').collect();
+    let domain_ok = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        });
+    local_ok && domain_ok
+}
+
+/// Имя и email свободны (без учёта регистра); `exclude_user_id` - изменяемая учётная запись.
+/// Ошибка называет занятое поле: USERNAME_TAKEN или EMAIL_TAKEN
+pub async fn ensure_available(
+    conn: &mut SqliteConnection,
+    username: Option<&str>,
+    email: Option<&str>,
+    exclude_user_id: Option<&str>,
+) -> ApiResult<()> {
+    if let Some(username) = username {
+        if is_taken(conn, "username", username, exclude_user_id).await? {
+            return Err(ApiError::username_taken(username));
+        }
+    }
+    if let Some(email) = email {
+        if is_taken(conn, "email", email, exclude_user_id).await? {
+            return Err(ApiError::email_taken(email));
+        }
+    }
+    Ok(())
+}
+
+async fn is_taken(
+    conn: &mut SqliteConnection,
+    column: &str,
+    value: &str,
+    exclude_user_id: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let sql = format!("SELECT EXISTS(SELECT 1 FROM users WHERE {column} = ? COLLATE NOCASE AND id != ?)");
+    sqlx::query_scalar(&sql)
+        .bind(value)
+        .bind(exclude_user_id.unwrap_or(""))
+        .fetch_one(conn)
+        .await
+}
+
+// ==================== STORED VALUES ====================
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct IdentityCollision {
+    /// username | email
+    pub field: &'static str,
+    /// Значение в нижнем регистре, общее для учётных записей
+    pub value: String,
+    pub users: Vec<CollidingUser>,
+}
+
+#[derive(Debug, Serialize, PartialEq, sqlx::FromRow)]
+pub struct CollidingUser {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub is_active: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserIdentityNormalizations {
+    /// Уникальные индексы без учёта регистра, которые уже созданы
+    pub indexes: Vec<&'static str>,
+    /// Совпадения, из-за которых индекс не создан: переименуйте, объедините или
+    /// анонимизируйте лишние учётные записи
+    pub collisions: Vec<IdentityCollision>,
+}
+
+/// Учётные записи, совпадающие по полю без учёта регистра
+pub async fn find_collisions(conn: &mut SqliteConnection) -> Result<Vec<IdentityCollision>, sqlx::Error> {
+    let mut collisions = Vec::new();
+    for (field, _) in IDENTITY_FIELDS {
+        let sql = format!(
+            "SELECT LOWER({field}) FROM users GROUP BY {field} COLLATE NOCASE HAVING COUNT(*) > 1 ORDER BY 1"
+        );
+        let values: Vec<String> = sqlx::query_scalar(&sql).fetch_all(&mut *conn).await?;
+        for value in values {
+            let sql = format!(
+                "SELECT id, username, email, is_active FROM users WHERE {field} = ? COLLATE NOCASE ORDER BY created_at, id"
+            );
+            let users = sqlx::query_as(&sql).bind(&value).fetch_all(&mut *conn).await?;
+            collisions.push(IdentityCollision { field, value, users });
+        }
+    }
+    Ok(collisions)
+}
+
+/// Миграция старых записей (вызывается из run_migrations): пробелы по краям убираются там,
+/// где это не создаёт совпадения; индекс поля создаётся, когда совпадений нет
+pub async fn normalize_stored_identities(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    for (field, _) in IDENTITY_FIELDS {
+        let trimmed = sqlx::query(&format!(
+            "UPDATE users SET {field} = TRIM({field}) WHERE {field} != TRIM({field}) \
+             AND NOT EXISTS (SELECT 1 FROM users o WHERE o.id != users.id AND o.{field} = TRIM(users.{field}) COLLATE NOCASE)"
+        ))
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+        if trimmed > 0 {
+            log::info!("Trimmed whitespace from {} stored {}(s)", trimmed, field);
+        }
+    }
+
+    let collisions = find_collisions(&mut conn).await?;
+    for (field, index) in IDENTITY_FIELDS {
+        let blocking: Vec<&IdentityCollision> = collisions.iter().filter(|c| c.field == field).collect();
+        if blocking.is_empty() {
+            sqlx::query(&format!("CREATE UNIQUE INDEX IF NOT EXISTS {index} ON users({field} COLLATE NOCASE)"))
+                .execute(&mut *conn)
+                .await?;
+            continue;
+        }
+        for collision in &blocking {
+            let ids: Vec<&str> = collision.users.iter().map(|u| u.id.as_str()).collect();
+            log::warn!("Users {} share the {} '{}' (case-insensitive)", ids.join(", "), field, collision.value);
+        }
+        log::warn!(
+            "{} case-insensitive {} collision(s): {} not created; resolve them via /admin/normalizations/user-identity",
+            blocking.len(), field, index
+        );
+    }
+    Ok(())
+}
+
+pub async fn user_identity_normalizations(pool: &SqlitePool) -> ApiResult<UserIdentityNormalizations> {
+    let mut conn = pool.acquire().await?;
+    let existing: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'index' AND tbl_name = 'users'"
+    )
+        .fetch_all(&mut *conn)
+        .await?;
+    let indexes = IDENTITY_FIELDS.iter()
+        .map(|(_, index)| *index)
+        .filter(|index| existing.iter().any(|name| name == index))
+        .collect();
+    let collisions = find_collisions(&mut conn).await?;
+    Ok(UserIdentityNormalizations { indexes, collisions })
+}
+
+/// GET /admin/normalizations/user-identity
+pub async fn get_user_identity_normalizations(app_state: web::Data<Arc<AppState>>) -> ApiResult<HttpResponse> {
+    let normalizations = user_identity_normalizations(&app_state.db_pool).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(normalizations)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+    use crate::error::{EMAIL_TAKEN, USERNAME_TAKEN};
+    use crate::test_support::TestApp;
+    use actix_web::http::StatusCode;
+    use serde_json::json;
+
+    #[test]
+    fn test_username_and_email_formats() {
+        let policy = UsernamePolicyConfig::default();
+        assert_eq!(normalize_username("  j.doe-2 ", &policy).unwrap(), "j.doe-2");
+        for bad in ["ab", "-jdoe", "j doe", "jdoe@lab", "jöran"] {
+            assert!(normalize_username(bad, &policy).is_err(), "{}", bad);
+        }
+        let strict = UsernamePolicyConfig { min_length: 5, max_length: 8, allowed_symbols: "_".to_string() };
+        assert!(normalize_username("j_doe", &strict).is_ok());
+        assert!(normalize_username("j.doe", &strict).is_err());
+        assert!(normalize_username("jdoe_12345", &strict).is_err());
+
+        assert_eq!(normalize_email(" Jane.Doe+lab@Example.org ").unwrap(), "Jane.Doe+lab@Example.org");
+        for bad in ["jane", "jane@", "@example.org", "jane@localhost", "jane..doe@example.org",
+                    "jane@exa_mple.org", "jane@-example.org", "jane doe@example.org", "a@b@example.org"] {
+            assert!(!is_valid_email(bad), "{}", bad);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_usernames_and_emails_are_unique_regardless_of_case() {
+        let app = TestApp::new().await;
+        let (status, body) = app.post(UserRole::Admin, "/auth/users", json!({
+            "username": "  Jane.Doe ", "email": " Jane@Example.org", "password": "Secret123", "role": "viewer",
+        })).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        assert_eq!(body["data"]["user"]["username"], "Jane.Doe");
+        assert_eq!(body["data"]["user"]["email"], "Jane@Example.org");
+        let jane_id = body["data"]["user"]["id"].as_str().unwrap().to_string();
+
+        let (status, body) = app.post(UserRole::Admin, "/auth/users", json!({
+            "username": "jane.doe", "email": "other@example.org", "password": "Secret123", "role": "viewer",
+        })).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], USERNAME_TAKEN);
+        let (status, body) = app.post(UserRole::Admin, "/auth/users", json!({
+            "username": "john", "email": "JANE@example.ORG", "password": "Secret123", "role": "viewer",
+        })).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], EMAIL_TAKEN);
+
+        // Своё имя в другом регистре - не конфликт; чужой email - конфликт
+        let (status, body) = app.put(UserRole::Admin, &format!("/auth/users/{}", jane_id), json!({ "username": "JANE.DOE" })).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let (status, body) = app.put(UserRole::Admin, &format!("/auth/users/{}", jane_id), json!({ "email": "FX-Viewer@example.com" })).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], EMAIL_TAKEN);
+
+        // Вход ищет учётную запись так же
+        let user = crate::auth::User::find_by_username(&app.pool, " jane.doe ").await.unwrap();
+        assert_eq!(user.id, jane_id);
+    }
+
+    #[actix_web::test]
+    async fn test_stored_collisions_are_reported_and_block_the_index() {
+        let app = TestApp::new().await;
+        let report = user_identity_normalizations(&app.pool).await.unwrap();
+        assert_eq!(report.indexes, ["idx_users_username_nocase", "idx_users_email_nocase"]);
+        assert!(report.collisions.is_empty());
+
+        // База до появления индекса: совпадение по регистру и пробелы по краям
+        sqlx::query("DROP INDEX idx_users_username_nocase").execute(&app.pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, role, created_at, updated_at) VALUES \
+             ('u-bob-1', 'bob', 'bob@example.com', 'x', 'viewer', datetime('now'), datetime('now')), \
+             ('u-bob-2', 'Bob', ' bob2@example.com ', 'x', 'viewer', datetime('now'), datetime('now'))"
+        ).execute(&app.pool).await.unwrap();
+        normalize_stored_identities(&app.pool).await.unwrap();
+
+        let email: String = sqlx::query_scalar("SELECT email FROM users WHERE id = 'u-bob-2'")
+            .fetch_one(&app.pool).await.unwrap();
+        assert_eq!(email, "bob2@example.com");
+        let (status, body) = app.get(UserRole::Admin, "/admin/normalizations/user-identity").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["data"]["indexes"], json!(["idx_users_email_nocase"]));
+        assert_eq!(body["data"]["collisions"][0]["field"], "username");
+        assert_eq!(body["data"]["collisions"][0]["value"], "bob");
+        assert_eq!(body["data"]["collisions"][0]["users"].as_array().unwrap().len(), 2);
+        let (status, _) = app.get(UserRole::Researcher, "/admin/normalizations/user-identity").await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // После переименования индекс создаётся при следующем запуске
+        sqlx::query("UPDATE users SET username = 'bob2' WHERE id = 'u-bob-2'").execute(&app.pool).await.unwrap();
+        normalize_stored_identities(&app.pool).await.unwrap();
+        assert_eq!(user_identity_normalizations(&app.pool).await.unwrap().indexes.len(), 2);
+    }
+}
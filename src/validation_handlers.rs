@@ -0,0 +1,255 @@
+// src/validation_handlers.rs
+//! Проверка форм "на лету": POST /validate/{entity}?id=
+//!
+//! Кандидатный payload проходит те же проверки, что и настоящее создание
+//! (derive-правила, CustomValidate, уникальность, пересечения бронирований помещений),
+//! но ничего не записывает. Сами проверки живут рядом с обработчиками
+//! (`validate_*_request`) и вызываются из create-путей, поэтому разойтись не могут.
+//!
+//! Ответ всегда 200 с `{valid, errors, warnings}` (поле -> сообщения);
+//! `?id=` - редактируемая запись, она исключается из проверок уникальности и пересечений.
+//! Для партии id реагента передаётся в payload как `reagent_id`.
+
+use actix_web::{web, HttpResponse};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::ApiResponse;
+use crate::models::{
+    CreateBatchRequest, CreateEquipmentRequest, CreateExperimentRequest, CreateReagentRequest, CreateRoomRequest,
+};
+use crate::validator::ValidationResult;
+use crate::{batch_handlers, equipment_handlers, experiment_handlers, reagent_handlers, room_handlers};
+use crate::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormEntity {
+    Reagent,
+    Batch,
+    Equipment,
+    Experiment,
+    Room,
+}
+
+impl FormEntity {
+    fn parse(value: &str) -> ApiResult<Self> {
+        match value {
+            "reagent" => Ok(FormEntity::Reagent),
+            "batch" => Ok(FormEntity::Batch),
+            "equipment" => Ok(FormEntity::Equipment),
+            "experiment" => Ok(FormEntity::Experiment),
+            "room" => Ok(FormEntity::Room),
+            other => Err(ApiError::bad_request(&format!(
+                "Unsupported entity '{}'. Valid: reagent, batch, equipment, experiment, room", other
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateFormQuery {
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FormValidationResponse {
+    pub valid: bool,
+    #[serde(flatten)]
+    pub result: ValidationResult,
+}
+
+/// Разбор payload в запрос создания; ошибки типов и пропущенные поля - в формате полей
+fn parse_payload<T: DeserializeOwned>(payload: &serde_json::Value) -> Result<T, ValidationResult> {
+    serde_json::from_value(payload.clone()).map_err(|e| {
+        let message = e.to_string();
+        let mut result = ValidationResult::new();
+        match message.strip_prefix("missing field `").and_then(|rest| rest.split('`').next()) {
+            Some(field) => result.add_error(field, "This field is required"),
+            None => result.add_error("body", message),
+        }
+        result
+    })
+}
+
+pub async fn validate_entity(
+    pool: &sqlx::SqlitePool,
+    entity: FormEntity,
+    payload: &serde_json::Value,
+    existing_id: Option<&str>,
+) -> ApiResult<ValidationResult> {
+    let result = match entity {
+        FormEntity::Reagent => match parse_payload::<CreateReagentRequest>(payload) {
+            Ok(reagent) => reagent_handlers::validate_reagent_request(pool, &reagent, existing_id).await?,
+            Err(result) => result,
+        },
+        FormEntity::Batch => {
+            let reagent_id = payload.get("reagent_id").and_then(|v| v.as_str()).filter(|id| !id.is_empty());
+            match (reagent_id, parse_payload::<CreateBatchRequest>(payload)) {
+                (Some(reagent_id), Ok(batch)) => {
+                    batch_handlers::validate_batch_request(pool, reagent_id, &batch, existing_id).await?
+                }
+                (reagent_id, parsed) => {
+                    let mut result = parsed.err().unwrap_or_default();
+                    if reagent_id.is_none() {
+                        result.add_error("reagent_id", "This field is required");
+                    }
+                    result
+                }
+            }
+        }
+        FormEntity::Equipment => match parse_payload::<CreateEquipmentRequest>(payload) {
+            Ok(mut equipment) => {
+                equipment.take_legacy_type();
+                equipment_handlers::validate_equipment_request(pool, &equipment, existing_id).await?
+            }
+            Err(result) => result,
+        },
+        FormEntity::Experiment => match parse_payload::<CreateExperimentRequest>(payload) {
+            Ok(experiment) => experiment_handlers::validate_experiment_request(pool, &experiment, existing_id).await?,
+            Err(result) => result,
+        },
+        FormEntity::Room => match parse_payload::<CreateRoomRequest>(payload) {
+            Ok(room) => room_handlers::validate_room_request(pool, &room, existing_id).await?,
+            Err(result) => result,
+        },
+    };
+    Ok(result)
+}
+
+pub async fn validate_form(
+    app_state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<ValidateFormQuery>,
+    payload: web::Json<serde_json::Value>,
+) -> ApiResult<HttpResponse> {
+    let entity = FormEntity::parse(&path.into_inner())?;
+    let existing_id = query.id.as_deref().filter(|id| !id.is_empty());
+
+    let result = validate_entity(&app_state.db_pool, entity, &payload, existing_id).await?;
+    Ok(HttpResponse::Ok().json(ApiResponse::success(FormValidationResponse {
+        valid: result.is_valid(),
+        result,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::SqlitePool;
+
+    async fn setup() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        crate::db::run_migrations(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO reagents (id, name, status, created_at, updated_at)
+             VALUES ('r-1', 'Acetone', 'active', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO rooms (id, name, status, created_at, updated_at)
+             VALUES ('room-1', 'Lab 101', 'available', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO experiments (id, title, experiment_date, start_date, end_date, status, room_id, created_at, updated_at)
+             VALUES ('e-1', 'Titration', '2030-03-01T09:00:00+00:00', '2030-03-01T09:00:00+00:00',
+                     '2030-03-01T11:00:00+00:00', 'planned', 'room-1', datetime('now'), datetime('now'))"
+        )
+            .execute(&pool).await.unwrap();
+        pool
+    }
+
+    #[actix_web::test]
+    async fn test_reagent_form_reports_fields_without_writing() {
+        let pool = setup().await;
+        let payload = json!({ "name": "Acetone", "cas_number": "67-64-2" });
+        let result = validate_entity(&pool, FormEntity::Reagent, &payload, None).await.unwrap();
+        assert_eq!(result.errors["cas_number"].len(), 1);
+        assert_eq!(result.errors["name"], vec!["Reagent with this name already exists"]);
+
+        // Тот же реагент при редактировании не конфликтует сам с собой; регистр - только предупреждение
+        let payload = json!({ "name": "acetone", "cas_number": "67-64-1" });
+        let result = validate_entity(&pool, FormEntity::Reagent, &payload, Some("r-2")).await.unwrap();
+        assert!(result.is_valid(), "{:?}", result.errors);
+        assert_eq!(result.warnings["name"].len(), 1);
+
+        let result = validate_entity(&pool, FormEntity::Reagent, &json!({ "formula": "H2O" }), None).await.unwrap();
+        assert_eq!(result.errors["name"], vec!["This field is required"]);
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM reagents").fetch_one(&pool).await.unwrap();
+        assert_eq!(count, 1);
+        assert!(FormEntity::parse("user").is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_experiment_form_checks_room_conflicts_and_educational_bounds() {
+        let pool = setup().await;
+        let overlapping = json!({
+            "title": "Chromatography", "experiment_type": "research", "room_id": "room-1",
+            "start_date": "2030-03-01T10:00:00Z", "end_date": "2030-03-01T12:00:00Z"
+        });
+        let result = validate_entity(&pool, FormEntity::Experiment, &overlapping, None).await.unwrap();
+        assert!(result.errors["room_id"][0].contains("Titration"), "{:?}", result.errors);
+        // Перенос самого эксперимента на соседнее время - не конфликт
+        let result = validate_entity(&pool, FormEntity::Experiment, &overlapping, Some("e-1")).await.unwrap();
+        assert!(result.is_valid(), "{:?}", result.errors);
+
+        let back_to_back = json!({
+            "title": "Lab class", "experiment_type": "educational", "room_id": "room-1",
+            "start_date": "2030-03-01T11:00:00Z", "end_date": "2030-03-01T11:10:00Z"
+        });
+        let result = validate_entity(&pool, FormEntity::Experiment, &back_to_back, None).await.unwrap();
+        assert!(!result.errors.contains_key("room_id"));
+        assert_eq!(result.errors["end_date"], vec!["Educational experiment must be at least 15 minutes"]);
+
+        // Вне часов работы помещения (по умолчанию пн-пт 08-18)
+        let evening = json!({
+            "title": "Night run", "experiment_type": "research", "room_id": "room-1",
+            "start_date": "2030-03-01T17:00:00Z", "end_date": "2030-03-01T19:00:00Z"
+        });
+        let result = validate_entity(&pool, FormEntity::Experiment, &evening, None).await.unwrap();
+        assert_eq!(
+            result.errors["end_date"],
+            vec!["Room 'Lab 101' is closed at 2030-03-01 19:00 UTC (opening hours: Mon-Fri 08:00-18:00 UTC)"]
+        );
+
+        // Путь создания использует те же проверки
+        let request: CreateExperimentRequest = serde_json::from_value(overlapping).unwrap();
+        let app_state = web::Data::new(Arc::new(AppState {
+            db_pool: pool.clone(),
+            config: crate::config::Config::default(),
+            read_replica: None,
+            reference_cache: Default::default(),
+        }));
+        let err = experiment_handlers::create_experiment(app_state, web::Json(request), "tester".to_string())
+            .await.unwrap_err();
+        assert!(matches!(err, ApiError::ValidationError(ref m) if m.contains("room_id")), "{:?}", err);
+    }
+
+    #[actix_web::test]
+    async fn test_batch_and_room_forms() {
+        let pool = setup().await;
+        let batch = json!({ "reagent_id": "r-1", "batch_number": "B-1", "quantity": -1.0, "unit": "ml", "location_id": "nowhere" });
+        let result = validate_entity(&pool, FormEntity::Batch, &batch, None).await.unwrap();
+        assert!(result.errors.contains_key("quantity"));
+        assert_eq!(result.errors["location_id"], vec!["Location not found"]);
+
+        let result = validate_entity(&pool, FormEntity::Batch, &json!({ "batch_number": "B-1" }), None).await.unwrap();
+        assert!(result.errors.contains_key("reagent_id"));
+
+        let room = json!({ "name": "lab 101", "opening_time": "18:00", "closing_time": "08:00" });
+        let result = validate_entity(&pool, FormEntity::Room, &room, None).await.unwrap();
+        assert_eq!(result.errors["name"], vec!["Room with this name already exists"]);
+        assert_eq!(result.errors["closing_time"], vec!["closing_time must be later than opening_time"]);
+        let result = validate_entity(&pool, FormEntity::Room, &json!({ "name": "lab 101" }), Some("room-1")).await.unwrap();
+        assert!(result.is_valid(), "{:?}", result.errors);
+    }
+}
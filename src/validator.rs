@@ -1,11 +1,13 @@
 // src/validator.rs - Centralized validation module
 use std::collections::HashMap;
+use std::str::FromStr;
 use serde::{Serialize, Deserialize};
 use regex::Regex;
 use lazy_static::lazy_static;
 use chrono::{DateTime, Utc};
-use crate::error::ApiError;
+use crate::error::{ApiError, ApiResult};
 use crate::models::*;
+use crate::query_builders::EquipmentType;
 
 lazy_static! {
     static ref CAS_REGEX: Regex = Regex::new(r"^\d{2,7}-\d{2}-\d$").unwrap();
@@ -26,6 +28,22 @@ impl ValidationResult {
         Self::default()
     }
 
+    /// Ошибки derive-правил `validator::Validate` в формате поле -> сообщения
+    pub fn from_validate<T: validator::Validate>(value: &T) -> Self {
+        let mut result = Self::new();
+        if let Err(errors) = value.validate() {
+            for (field, field_errors) in errors.field_errors() {
+                for error in field_errors {
+                    let message = error.message.as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| error.code.to_string());
+                    result.add_error(field, message);
+                }
+            }
+        }
+        result
+    }
+
     pub fn is_valid(&self) -> bool {
         self.errors.is_empty()
     }
@@ -53,6 +71,16 @@ impl ValidationResult {
         }
     }
 
+    /// Ошибки вложенного объекта с префиксом поля: `initial_batch.quantity`
+    pub fn merge_prefixed(&mut self, prefix: &str, other: ValidationResult) {
+        for (field, errors) in other.errors {
+            self.errors.entry(format!("{}.{}", prefix, field)).or_default().extend(errors);
+        }
+        for (field, warnings) in other.warnings {
+            self.warnings.entry(format!("{}.{}", prefix, field)).or_default().extend(warnings);
+        }
+    }
+
     pub fn to_api_error(&self) -> ApiError {
         let message = self.errors
             .iter()
@@ -62,6 +90,15 @@ impl ValidationResult {
 
         ApiError::ValidationError(message)
     }
+
+    /// Для путей create/update: ошибки превращаются в 422, предупреждения не мешают
+    pub fn ensure_valid(self) -> ApiResult<Self> {
+        if self.is_valid() {
+            Ok(self)
+        } else {
+            Err(self.to_api_error())
+        }
+    }
 }
 
 // ==================== FIELD VALIDATORS ====================
@@ -349,6 +386,22 @@ impl UnitConverter {
 
         Ok(result)
     }
+
+    /// Множитель мольной единицы к молям (mol, mmol, μmol); None - единица не мольная
+    pub fn molar_factor(unit: &str) -> Option<f64> {
+        match unit {
+            "mol" => Some(1.0),
+            "mmol" => Some(0.001),
+            "μmol" | "umol" => Some(0.000001),
+            _ => None,
+        }
+    }
+
+    /// Количество в базовой единице своего типа (g, mL); None - единица не пересчитывается
+    pub fn to_base(&self, quantity: f64, unit: &str) -> Option<(f64, &'static str)> {
+        self.conversions.get(unit)
+            .map(|factor| (quantity * factor.to_base, factor.base_unit))
+    }
 }
 
 // ==================== CUSTOM VALIDATION ====================
@@ -357,32 +410,143 @@ pub trait CustomValidate {
     fn custom_validate(&self) -> ValidationResult;
 }
 
+/// CAS и формула реагента - одинаково для создания и обновления
+fn validate_reagent_identity(cas_number: Option<&str>, formula: Option<&str>) -> ValidationResult {
+    let mut result = ValidationResult::new();
+
+    // Пустые строки из форм означают "не задано"
+    if let Some(cas) = cas_number.map(str::trim).filter(|c| !c.is_empty()) {
+        if let Err(e) = FieldValidator::cas_number(cas) {
+            result.add_error("cas_number", e);
+        }
+    }
+
+    if let Some(formula) = formula.map(str::trim).filter(|f| !f.is_empty()) {
+        if let Err(e) = FieldValidator::chemical_formula(formula) {
+            result.add_error("formula", e);
+        }
+    }
+
+    result
+}
+
+/// Порог согласования задаётся вместе с единицей (0 при обновлении - снять порог)
+fn validate_approval_threshold(threshold: Option<f64>, unit: Option<&str>) -> ValidationResult {
+    let mut result = ValidationResult::new();
+    if threshold.is_some_and(|t| t > 0.0) && unit.map(str::trim).filter(|u| !u.is_empty()).is_none() {
+        result.add_error("approval_threshold_unit", "Unit is required when an approval threshold is set");
+    }
+    result
+}
+
+fn validate_expiry_policy(policy: Option<&str>) -> ValidationResult {
+    let mut result = ValidationResult::new();
+    if let Some(policy) = policy {
+        if !crate::models::reagent::EXPIRY_POLICIES.contains(&policy) {
+            result.add_error("expiry_policy", "Expiry policy must be one of: strict, advisory, none");
+        }
+    }
+    result
+}
+
 impl CustomValidate for CreateReagentRequest {
+    fn custom_validate(&self) -> ValidationResult {
+        let mut result = validate_reagent_identity(self.cas_number.as_deref(), self.formula.as_deref());
+        result.merge(validate_approval_threshold(self.approval_threshold, self.approval_threshold_unit.as_deref()));
+        result.merge(validate_expiry_policy(self.expiry_policy.as_deref()));
+        result
+    }
+}
+
+impl CustomValidate for UpdateReagentRequest {
+    fn custom_validate(&self) -> ValidationResult {
+        let mut result = validate_reagent_identity(
+            self.cas_number.as_ref().and_then(|v| v.as_deref()),
+            self.formula.as_ref().and_then(|v| v.as_deref()),
+        );
+        result.merge(validate_approval_threshold(self.approval_threshold.flatten(), self.approval_threshold_unit.as_deref()));
+        result.merge(validate_expiry_policy(self.expiry_policy.as_deref()));
+        result
+    }
+}
+
+/// Число тар задаётся вместе с их размером, и count × size совпадает с остатком
+/// в пределах CONTAINER_FILL_TOLERANCE (создание партии и пересчёт тар при обновлении)
+pub fn validate_container_fill(quantity: f64, container_size: Option<f64>, container_count: Option<i64>) -> ValidationResult {
+    let mut result = ValidationResult::new();
+    let Some(count) = container_count else {
+        return result;
+    };
+    let Some(size) = container_size.filter(|s| *s > 0.0) else {
+        result.add_error("container_count", "Container size (pack_size) is required when a container count is set");
+        return result;
+    };
+    let expected = count as f64 * size;
+    if (expected - quantity).abs() > expected * CONTAINER_FILL_TOLERANCE + 1e-9 {
+        result.add_error("container_count", format!(
+            "{} container(s) of {} hold {}, which does not match the quantity {}",
+            count, size, expected, quantity
+        ));
+    }
+    result
+}
+
+impl CustomValidate for CreateBatchRequest {
     fn custom_validate(&self) -> ValidationResult {
         let mut result = ValidationResult::new();
 
-        if let Some(ref cas) = self.cas_number {
-            if let Err(e) = FieldValidator::cas_number(cas) {
-                result.add_error("cas_number", e);
-            }
+        // Срок годности проверяет validate_batch_request: он зависит от expiry_policy реагента
+        result.merge(validate_container_fill(self.quantity, self.pack_size, self.container_count));
+
+        result
+    }
+}
+
+impl CustomValidate for CreateEquipmentRequest {
+    fn custom_validate(&self) -> ValidationResult {
+        let mut result = ValidationResult::new();
+
+        if self.name.trim().is_empty() {
+            result.add_error("name", "Name cannot be empty");
         }
 
-        if let Some(ref formula) = self.formula {
-            if let Err(e) = FieldValidator::chemical_formula(formula) {
-                result.add_error("formula", e);
-            }
+        if self.quantity < 1 {
+            result.add_error("quantity", "Quantity must be at least 1");
+        }
+
+        if EquipmentType::from_str(&self.type_).is_err() {
+            result.add_error("type", format!(
+                "Invalid type: {}. Valid: instrument, glassware, safety, storage, consumable, other",
+                self.type_
+            ));
         }
 
         result
     }
 }
 
-impl CustomValidate for CreateBatchRequest {
+impl CustomValidate for CreateExperimentRequest {
     fn custom_validate(&self) -> ValidationResult {
         let mut result = ValidationResult::new();
 
-        // Проверка срока годности
-        result.merge(FieldValidator::expiry_date(self.expiry_date.as_ref(), 30));
+        if let Some(status) = self.status.as_deref() {
+            if !CREATE_EXPERIMENT_STATUSES.contains(&status) {
+                result.add_error("status", format!(
+                    "Invalid status: {}. Valid: {}", status, CREATE_EXPERIMENT_STATUSES.join(", ")
+                ));
+            }
+        }
+        if self.is_draft() {
+            return result;
+        }
+
+        if let Err((field, message)) = educational_schedule(
+            self.experiment_type.as_deref(),
+            self.start_date,
+            self.end_date,
+        ) {
+            result.add_error(field, message);
+        }
 
         result
     }
@@ -0,0 +1,259 @@
+// src/work_queue.rs
+//! Очереди тяжёлых операций импорта/экспорта.
+//!
+//! Разбор Excel (calamine) занимает ядро CPU целиком на десятки секунд; два одновременных
+//! импорта по 50k строк съедают все ядра и обычные запросы API начинают ждать. Каждая
+//! очередь - семафор с настраиваемым числом одновременных операций (`[work_queues]`):
+//! - `xlsx_parse` - разбор файлов импорта (по умолчанию 1), выполняется в `spawn_blocking`;
+//! - `export` - экспорты (по умолчанию 2).
+//!
+//! Лишние запросы ждут своей очереди (семафор tokio выдаёт разрешения по порядку); позиция,
+//! с которой запрос встал в очередь, и время ожидания возвращаются в заголовках
+//! `X-Queue-Position` / `X-Queue-Wait-Ms`. Если ожидающих уже `max_queued` - 429 с Retry-After.
+//! Глубина очередей и счётчики - в /metrics.
+
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::HttpResponse;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::config::WorkQueueConfig;
+use crate::error::{ApiError, ApiResult};
+
+pub const QUEUE_POSITION_HEADER: &str = "x-queue-position";
+pub const QUEUE_WAIT_HEADER: &str = "x-queue-wait-ms";
+
+lazy_static::lazy_static! {
+    static ref XLSX_PARSE_QUEUE: WorkQueue = {
+        let defaults = WorkQueueConfig::default();
+        WorkQueue::new("xlsx_parse", defaults.xlsx_parse_concurrency, defaults.max_queued, defaults.retry_after_secs)
+    };
+    static ref EXPORT_QUEUE: WorkQueue = {
+        let defaults = WorkQueueConfig::default();
+        WorkQueue::new("export", defaults.export_concurrency, defaults.max_queued, defaults.retry_after_secs)
+    };
+}
+
+/// Разбор файлов импорта (xlsx/csv)
+pub fn xlsx_parse_queue() -> &'static WorkQueue {
+    &XLSX_PARSE_QUEUE
+}
+
+/// Экспорты (JSON-выгрузки, архив, файлы отчётов)
+pub fn export_queue() -> &'static WorkQueue {
+    &EXPORT_QUEUE
+}
+
+/// Применение `[work_queues]` при старте
+pub fn configure(config: &WorkQueueConfig) {
+    xlsx_parse_queue().configure(config.xlsx_parse_concurrency, config.max_queued, config.retry_after_secs);
+    export_queue().configure(config.export_concurrency, config.max_queued, config.retry_after_secs);
+}
+
+pub struct WorkQueue {
+    name: &'static str,
+    semaphore: Semaphore,
+    concurrency: AtomicUsize,
+    max_queued: AtomicUsize,
+    retry_after_secs: AtomicU64,
+    queued: AtomicUsize,
+    processing: AtomicUsize,
+    completed: AtomicU64,
+    rejected: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct WorkQueueStats {
+    pub queue: &'static str,
+    pub concurrency: usize,
+    pub max_queued: usize,
+    /// Ждут разрешения
+    pub queued: usize,
+    /// Выполняются сейчас
+    pub processing: usize,
+    pub completed_total: u64,
+    /// Отклонены с 429 (очередь заполнена)
+    pub rejected_total: u64,
+}
+
+/// Место в очереди; при отмене запроса (клиент отключился) освобождается
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Разрешение на выполнение; держится до конца операции
+pub struct WorkTicket {
+    queue: &'static WorkQueue,
+    _permit: SemaphorePermit<'static>,
+    /// 0 - операция началась сразу, иначе позиция при постановке в очередь
+    pub position: usize,
+    pub waited: Duration,
+}
+
+impl Drop for WorkTicket {
+    fn drop(&mut self) {
+        self.queue.processing.fetch_sub(1, Ordering::SeqCst);
+        self.queue.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl WorkTicket {
+    /// Заголовки с позицией в очереди и временем ожидания
+    pub fn annotate(&self, mut response: HttpResponse) -> HttpResponse {
+        let headers = response.headers_mut();
+        headers.insert(HeaderName::from_static(QUEUE_POSITION_HEADER), HeaderValue::from(self.position));
+        headers.insert(HeaderName::from_static(QUEUE_WAIT_HEADER), HeaderValue::from(self.waited.as_millis() as u64));
+        response
+    }
+}
+
+impl WorkQueue {
+    pub fn new(name: &'static str, concurrency: usize, max_queued: usize, retry_after_secs: u64) -> Self {
+        let concurrency = concurrency.max(1);
+        Self {
+            name,
+            semaphore: Semaphore::new(concurrency),
+            concurrency: AtomicUsize::new(concurrency),
+            max_queued: AtomicUsize::new(max_queued),
+            retry_after_secs: AtomicU64::new(retry_after_secs),
+            queued: AtomicUsize::new(0),
+            processing: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn configure(&self, concurrency: usize, max_queued: usize, retry_after_secs: u64) {
+        let concurrency = concurrency.max(1);
+        let current = self.concurrency.swap(concurrency, Ordering::SeqCst);
+        if concurrency > current {
+            self.semaphore.add_permits(concurrency - current);
+        } else {
+            self.semaphore.forget_permits(current - concurrency);
+        }
+        self.max_queued.store(max_queued, Ordering::SeqCst);
+        self.retry_after_secs.store(retry_after_secs, Ordering::SeqCst);
+    }
+
+    /// Разрешение сразу, ожидание в очереди или 429, если ожидающих уже max_queued
+    pub async fn acquire(&'static self) -> ApiResult<WorkTicket> {
+        let started = Instant::now();
+        if let Ok(permit) = self.semaphore.try_acquire() {
+            return Ok(self.start(permit, 0, started));
+        }
+
+        let position = self.queued.fetch_add(1, Ordering::SeqCst) + 1;
+        let slot = QueueSlot(&self.queued);
+        let max_queued = self.max_queued.load(Ordering::SeqCst);
+        if position > max_queued {
+            drop(slot);
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(ApiError::TooManyRequests {
+                message: format!(
+                    "Too many {} operations in progress ({} waiting); retry later",
+                    self.name, max_queued
+                ),
+                retry_after_secs: self.retry_after_secs.load(Ordering::SeqCst),
+            });
+        }
+
+        let permit = self.semaphore.acquire().await
+            .map_err(|e| ApiError::InternalServerError(format!("Work queue {} closed: {}", self.name, e)))?;
+        drop(slot);
+        if position > 1 || started.elapsed() > Duration::from_secs(1) {
+            log::info!("{} operation waited {:.1?} in queue (position {})", self.name, started.elapsed(), position);
+        }
+        Ok(self.start(permit, position, started))
+    }
+
+    /// CPU-тяжёлая работа в `spawn_blocking` под разрешением очереди
+    pub async fn run_blocking<F, T>(&'static self, work: F) -> ApiResult<(T, WorkTicket)>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let ticket = self.acquire().await?;
+        let result = tokio::task::spawn_blocking(work)
+            .await
+            .map_err(|e| ApiError::InternalServerError(format!("{} task failed: {}", self.name, e)))?;
+        Ok((result, ticket))
+    }
+
+    fn start(&'static self, permit: SemaphorePermit<'static>, position: usize, started: Instant) -> WorkTicket {
+        self.processing.fetch_add(1, Ordering::SeqCst);
+        WorkTicket { queue: self, _permit: permit, position, waited: started.elapsed() }
+    }
+
+    pub fn stats(&self) -> WorkQueueStats {
+        WorkQueueStats {
+            queue: self.name,
+            concurrency: self.concurrency.load(Ordering::SeqCst),
+            max_queued: self.max_queued.load(Ordering::SeqCst),
+            queued: self.queued.load(Ordering::SeqCst),
+            processing: self.processing.load(Ordering::SeqCst),
+            completed_total: self.completed.load(Ordering::Relaxed),
+            rejected_total: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Состояние всех очередей для /metrics
+pub fn all_stats() -> Vec<WorkQueueStats> {
+    vec![xlsx_parse_queue().stats(), export_queue().stats()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+
+    #[actix_web::test]
+    async fn test_excess_requests_queue_in_order_then_get_429() {
+        let queue: &'static WorkQueue = Box::leak(Box::new(WorkQueue::new("test", 1, 1, 7)));
+
+        let first = queue.acquire().await.unwrap();
+        assert_eq!(first.position, 0);
+
+        let waiting = actix_web::rt::spawn(queue.acquire());
+        while queue.stats().queued == 0 {
+            actix_web::rt::task::yield_now().await;
+        }
+
+        // Очередь заполнена - 429 с Retry-After
+        let err = queue.acquire().await.err().unwrap();
+        let response = err.error_response();
+        assert_eq!(response.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "7");
+
+        drop(first);
+        let second = waiting.await.unwrap().unwrap();
+        assert_eq!(second.position, 1);
+        let response = second.annotate(HttpResponse::Ok().finish());
+        assert_eq!(response.headers().get(QUEUE_POSITION_HEADER).unwrap(), "1");
+        assert!(response.headers().contains_key(QUEUE_WAIT_HEADER));
+
+        assert_eq!(
+            queue.stats(),
+            WorkQueueStats {
+                queue: "test",
+                concurrency: 1,
+                max_queued: 1,
+                queued: 0,
+                processing: 1,
+                completed_total: 1,
+                rejected_total: 1,
+            }
+        );
+
+        // Увеличение лимита при настройке сразу пропускает ещё одну операцию
+        queue.configure(2, 1, 7);
+        let (value, third) = queue.run_blocking(|| 6 * 7).await.unwrap();
+        assert_eq!((value, third.position), (42, 0));
+    }
+}